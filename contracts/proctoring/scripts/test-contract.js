@@ -202,7 +202,7 @@ async function main() {
   console.log("=".repeat(60));
 
   try {
-    const tx7 = await contract.closeRoom(roomId, 0); // 0 = ProctorLeft
+    const tx7 = await contract.closeRoom(roomId, 0, ""); // 0 = ProctorLeft, no manifest
     console.log("Transaction sent:", tx7.hash);
     const receipt7 = await tx7.wait();
     console.log("Transaction confirmed in block:", receipt7.blockNumber);
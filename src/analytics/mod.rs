@@ -0,0 +1,595 @@
+//! Daily room lifecycle aggregates.
+//!
+//! The history is the `room_manifest.json` every closed room leaves in its
+//! recording directory, so only rooms closed while recording was enabled are
+//! counted. A room counts towards the local date it opened on, in
+//! `ANALYTICS_TIMEZONE`, however long it ran past midnight. Each run
+//! recomputes the last `ANALYTICS_LOOKBACK_DAYS` dates from scratch and
+//! replaces their rows, so re-running never double counts and rooms still
+//! open at the previous run are picked up by the next one. Rooms in a tenant
+//! namespace also count towards that tenant's rows, stored in the namespace.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::env;
+use crate::health::alert::{alerter, Alert};
+use crate::recording::{finalize, tenant, RoomManifest, MANIFEST_FILE, MEDIA_GAP_ACTIVITY};
+use crate::sfu::{LocalDate, Timezone, TaskSupervisor};
+
+/// Daily rows, keyed by date, stored in the recording output directory
+pub const ANALYTICS_FILE: &str = "analytics_daily.json";
+
+const DEFAULT_RUN_AT: &str = "01:00";
+const DEFAULT_LOOKBACK_DAYS: u32 = 2;
+
+/// How often the scheduler compares the local time against `ANALYTICS_RUN_AT`
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Aggregates for the rooms that opened on one local date
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailySummary {
+    /// `YYYY-MM-DD` in `timezone`
+    pub date: String,
+    pub timezone: String,
+    pub rooms_held: u32,
+    /// `None` on days without rooms
+    pub avg_session_secs: Option<f64>,
+    pub avg_students_per_room: Option<f64>,
+    pub recording_hours: f64,
+    pub recordings: u32,
+    /// Recordings that reached IPFS
+    pub recordings_uploaded: u32,
+    /// `recordings_uploaded / recordings`, `None` on days without recordings
+    pub upload_success_rate: Option<f64>,
+    /// Suspicious activity reports, media gaps excluded
+    pub incidents: u32,
+    /// Students times the length of the session they joined
+    pub student_hours: f64,
+    pub incidents_per_100_student_hours: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct DayTotals {
+    rooms: u32,
+    session_secs: u64,
+    students: u32,
+    student_secs: u64,
+    recording_secs: u64,
+    recordings: u32,
+    uploaded: u32,
+    incidents: u32,
+}
+
+impl DayTotals {
+    fn add(&mut self, manifest: &RoomManifest) {
+        let session_secs = manifest.closed_at.saturating_sub(opened_at(manifest)) / 1000;
+        let students = manifest.integrity.len() as u32;
+
+        self.rooms += 1;
+        self.session_secs += session_secs;
+        self.students += students;
+        self.student_secs += students as u64 * session_secs;
+        for recording in &manifest.recordings {
+            self.recordings += 1;
+            self.recording_secs += recording.duration_secs;
+            if recording.cid.is_some() {
+                self.uploaded += 1;
+            }
+        }
+        self.incidents += manifest
+            .incidents
+            .iter()
+            .filter(|incident| incident.activity_type != MEDIA_GAP_ACTIVITY)
+            .map(|incident| incident.count)
+            .sum::<u32>();
+    }
+
+    fn summary(&self, date: LocalDate, timezone: &str) -> DailySummary {
+        let per_room = |total: f64| (self.rooms > 0).then(|| round2(total / self.rooms as f64));
+        let student_hours = self.student_secs as f64 / 3600.0;
+
+        DailySummary {
+            date: date.to_string(),
+            timezone: timezone.to_string(),
+            rooms_held: self.rooms,
+            avg_session_secs: per_room(self.session_secs as f64),
+            avg_students_per_room: per_room(self.students as f64),
+            recording_hours: round2(self.recording_secs as f64 / 3600.0),
+            recordings: self.recordings,
+            recordings_uploaded: self.uploaded,
+            upload_success_rate: (self.recordings > 0).then(|| round2(self.uploaded as f64 / self.recordings as f64)),
+            incidents: self.incidents,
+            student_hours: round2(student_hours),
+            incidents_per_100_student_hours: (self.student_secs > 0)
+                .then(|| round2(self.incidents as f64 * 100.0 / student_hours)),
+        }
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Unix milliseconds the room opened. Manifests written before `opened_at`
+/// existed fall back to the first recording start, then to the close.
+fn opened_at(manifest: &RoomManifest) -> u64 {
+    manifest
+        .opened_at
+        .or_else(|| manifest.recordings.iter().filter_map(|r| r.started_at).min())
+        .unwrap_or(manifest.closed_at)
+}
+
+/// One summary per date from `from` to `to` inclusive, days without rooms included
+pub fn aggregate(
+    manifests: impl IntoIterator<Item = RoomManifest>,
+    timezone: &Timezone,
+    from: LocalDate,
+    to: LocalDate,
+) -> Vec<DailySummary> {
+    let mut days: BTreeMap<LocalDate, DayTotals> = BTreeMap::new();
+    let mut date = from;
+    while date <= to {
+        days.insert(date, DayTotals::default());
+        date = date.next();
+    }
+
+    for manifest in manifests {
+        let (date, _) = timezone.local_time((opened_at(&manifest) / 1000) as i64);
+        if let Some(totals) = days.get_mut(&date) {
+            totals.add(&manifest);
+        }
+    }
+
+    days.iter()
+        .map(|(date, totals)| totals.summary(*date, timezone.name()))
+        .collect()
+}
+
+/// Every readable manifest under `output_dir`, one room directory each, with
+/// the tenant namespace it was found in
+fn read_manifests(output_dir: &Path) -> io::Result<Vec<(Option<String>, RoomManifest)>> {
+    let room_dirs = match tenant::room_dirs(output_dir) {
+        Ok(room_dirs) => room_dirs,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut manifests = Vec::new();
+    for (tenant, room_dir) in room_dirs {
+        let path = room_dir.join(MANIFEST_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Failed to read room manifest for analytics");
+                continue;
+            }
+        };
+        match serde_json::from_slice(&contents) {
+            Ok(manifest) => manifests.push((tenant, manifest)),
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Skipping unreadable room manifest"),
+        }
+    }
+    Ok(manifests)
+}
+
+/// Builds the daily rows on a schedule and serves them back
+pub struct DailyAnalytics {
+    output_dir: PathBuf,
+    timezone: Timezone,
+    /// Seconds after local midnight the daily run starts
+    run_at: u32,
+    lookback_days: u32,
+    webhook: bool,
+    /// Whether the daily run is scheduled, rather than only serving stored rows
+    scheduled: bool,
+}
+
+impl DailyAnalytics {
+    pub fn new(output_dir: impl Into<PathBuf>, timezone: Timezone) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            timezone,
+            run_at: parse_time_of_day(DEFAULT_RUN_AT).unwrap_or_default(),
+            lookback_days: DEFAULT_LOOKBACK_DAYS,
+            webhook: false,
+            scheduled: false,
+        }
+    }
+
+    /// `run_at` is seconds after local midnight
+    pub fn with_run_at(mut self, run_at: u32) -> Self {
+        self.run_at = run_at;
+        self
+    }
+
+    pub fn with_lookback_days(mut self, days: u32) -> Self {
+        self.lookback_days = days.max(1);
+        self
+    }
+
+    /// Posts new and changed rows to the alert webhook as `analytics.daily`
+    pub fn with_webhook(mut self, webhook: bool) -> Self {
+        self.webhook = webhook;
+        self
+    }
+
+    /// Reads the schedule from environment; stored rows are served either way,
+    /// the daily run is only scheduled with `ANALYTICS_ENABLED=true`
+    ///
+    /// Optional environment variables:
+    /// - `ANALYTICS_RUN_AT`: Local `HH:MM` of the daily run (default: 01:00)
+    /// - `ANALYTICS_TIMEZONE`: IANA timezone dates are taken in (default: UTC)
+    /// - `ANALYTICS_LOOKBACK_DAYS`: Past dates recomputed by each run (default: 2)
+    /// - `ANALYTICS_WEBHOOK`: Post rows through `ALERT_WEBHOOK_URL` (default: false)
+    pub fn from_env() -> Result<Self, String> {
+        let output_dir = env::get_string("RECORDING_OUTPUT_DIR").unwrap_or_else(|| "./recordings".to_string());
+        let timezone = match env::get_string("ANALYTICS_TIMEZONE") {
+            Some(name) => Timezone::load(&name).map_err(|e| e.message())?,
+            None => Timezone::utc(),
+        };
+        let run_at = env::get_string("ANALYTICS_RUN_AT").unwrap_or_else(|| DEFAULT_RUN_AT.to_string());
+        let run_at = parse_time_of_day(&run_at)
+            .ok_or_else(|| format!("Invalid ANALYTICS_RUN_AT '{}', expected HH:MM", run_at))?;
+
+        Ok(Self {
+            scheduled: env::get_bool("ANALYTICS_ENABLED", false),
+            ..Self::new(output_dir, timezone)
+                .with_run_at(run_at)
+                .with_lookback_days(env::get_parsed("ANALYTICS_LOOKBACK_DAYS").unwrap_or(DEFAULT_LOOKBACK_DAYS))
+                .with_webhook(env::get_bool("ANALYTICS_WEBHOOK", false))
+        })
+    }
+
+    pub fn scheduled(&self) -> bool {
+        self.scheduled
+    }
+
+    pub fn timezone(&self) -> &Timezone {
+        &self.timezone
+    }
+
+    /// Rows across every room in the output directory, or a tenant's own
+    /// rows kept in its namespace, so deleting the tenant takes them along
+    fn store_path(&self, tenant: Option<&str>) -> PathBuf {
+        match tenant {
+            Some(tenant) => self.output_dir.join(tenant).join(ANALYTICS_FILE),
+            None => self.output_dir.join(ANALYTICS_FILE),
+        }
+    }
+
+    /// Recomputes the `lookback_days` dates before `today` and stores them,
+    /// for all rooms and for each tenant's rooms. Returns the all-room rows
+    /// that are new or differ from what was stored.
+    pub fn run(&self, today: LocalDate) -> io::Result<Vec<DailySummary>> {
+        let from = today.minus_days(self.lookback_days as i64);
+        let to = today.minus_days(1);
+        let manifests = read_manifests(&self.output_dir)?;
+
+        let mut by_tenant: BTreeMap<String, Vec<RoomManifest>> = BTreeMap::new();
+        for (tenant, manifest) in &manifests {
+            if let Some(tenant) = tenant {
+                by_tenant.entry(tenant.clone()).or_default().push(manifest.clone());
+            }
+        }
+        for (tenant, manifests) in by_tenant {
+            let rows = aggregate(manifests, &self.timezone, from, to);
+            self.store(Some(&tenant), rows)?;
+        }
+
+        let rows = aggregate(manifests.into_iter().map(|(_, manifest)| manifest), &self.timezone, from, to);
+        self.store(None, rows)
+    }
+
+    /// Merges `rows` into the stored ones, returning those that changed
+    fn store(&self, tenant: Option<&str>, rows: Vec<DailySummary>) -> io::Result<Vec<DailySummary>> {
+        let path = self.store_path(tenant);
+        let mut stored = load(&path)?;
+        let changed: Vec<DailySummary> = rows
+            .into_iter()
+            .filter(|row| stored.get(&row.date) != Some(row))
+            .collect();
+        if changed.is_empty() {
+            return Ok(changed);
+        }
+
+        for row in &changed {
+            stored.insert(row.date.clone(), row.clone());
+        }
+        std::fs::create_dir_all(&self.output_dir)?;
+        finalize::write_atomic(&path, &serde_json::to_vec_pretty(&stored)?)?;
+        Ok(changed)
+    }
+
+    /// Stored rows from `from` to `to` inclusive, oldest first; a tenant's
+    /// own rows when one is given
+    pub fn rows(&self, tenant: Option<&str>, from: Option<LocalDate>, to: Option<LocalDate>) -> io::Result<Vec<DailySummary>> {
+        Ok(load(&self.store_path(tenant))?
+            .into_values()
+            .filter(|row| {
+                LocalDate::parse(&row.date)
+                    .is_some_and(|date| from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to))
+            })
+            .collect())
+    }
+
+    /// Checks the local time every minute and runs once a day after `run_at`,
+    /// including at startup when the server starts later in the day
+    pub fn spawn(self: Arc<Self>, tasks: &TaskSupervisor) {
+        tasks.spawn("daily_analytics", move |cancel| async move {
+            let mut last_run: Option<LocalDate> = None;
+            let mut tick = tokio::time::interval(CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = cancel.cancelled() => break,
+                }
+
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or_default();
+                let (today, secs_of_day) = self.timezone.local_time(now);
+                if secs_of_day < self.run_at || last_run == Some(today) {
+                    continue;
+                }
+                last_run = Some(today);
+
+                let analytics = self.clone();
+                match tokio::task::spawn_blocking(move || analytics.run(today)).await {
+                    Ok(Ok(changed)) => {
+                        tracing::info!(date = %today, changed = changed.len(), "Daily analytics updated");
+                        if self.webhook {
+                            for row in changed {
+                                publish(&row);
+                            }
+                        }
+                    }
+                    Ok(Err(e)) => tracing::error!(error = %e, "Daily analytics run failed"),
+                    Err(e) => tracing::error!(error = %e, "Daily analytics task panicked"),
+                }
+            }
+        });
+    }
+}
+
+fn publish(row: &DailySummary) {
+    let details = match serde_json::to_value(row) {
+        Ok(details) => details,
+        Err(e) => {
+            tracing::warn!(date = %row.date, error = %e, "Failed to serialize daily analytics");
+            return;
+        }
+    };
+    alerter().deliver(Alert::new(
+        "analytics.daily",
+        format!("Room analytics for {}", row.date),
+        details,
+    ));
+}
+
+/// `HH:MM` as seconds after midnight
+fn parse_time_of_day(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.split_once(':')?;
+    let hours: u32 = hours.parse().ok().filter(|&h| h < 24)?;
+    let minutes: u32 = minutes.parse().ok().filter(|&m| m < 60)?;
+    Some(hours * 3600 + minutes * 60)
+}
+
+fn load(path: &Path) -> io::Result<BTreeMap<String, DailySummary>> {
+    match std::fs::read(path) {
+        Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // 2024-05-01 00:00:00 UTC
+    const MAY_1: u64 = 1_714_521_600_000;
+    const HOUR_MS: u64 = 3_600_000;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-analytics-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn date(s: &str) -> LocalDate {
+        LocalDate::parse(s).unwrap()
+    }
+
+    /// Writes a room manifest the way `publish_manifest` leaves it on disk
+    fn seed_room(dir: &Path, room_id: &str, mut manifest: serde_json::Value) {
+        let room_dir = dir.join(room_id);
+        std::fs::create_dir_all(&room_dir).unwrap();
+        manifest["version"] = json!(1);
+        manifest["room_id"] = json!(room_id);
+        manifest["complete"] = json!(true);
+        manifest["pending_uploads"] = json!(0);
+        for key in ["recordings", "incidents"] {
+            if manifest.get(key).is_none() {
+                manifest[key] = json!([]);
+            }
+        }
+        std::fs::write(room_dir.join(MANIFEST_FILE), serde_json::to_vec(&manifest).unwrap()).unwrap();
+    }
+
+    fn recording(peer_id: &str, duration_secs: u64, cid: Option<&str>) -> serde_json::Value {
+        json!({
+            "peer_id": peer_id,
+            "participant_wallet": null,
+            "file": format!("{}.webm", peer_id),
+            "cid": cid,
+            "sha256": null,
+            "started_at": null,
+            "stopped_at": MAY_1,
+            "duration_secs": duration_secs,
+            "bytes_written": 1024,
+        })
+    }
+
+    fn students(peer_ids: &[&str]) -> serde_json::Value {
+        peer_ids
+            .iter()
+            .map(|peer_id| json!({ "peer_id": peer_id, "participant_wallet": null, "score": 10000, "penalties": [] }))
+            .collect()
+    }
+
+    fn incident(peer_id: &str, activity_type: &str, count: u32) -> serde_json::Value {
+        json!({
+            "peer_id": peer_id,
+            "participant_wallet": null,
+            "activity_type": activity_type,
+            "count": count,
+            "first_at": MAY_1,
+            "last_at": MAY_1,
+        })
+    }
+
+    /// Two rooms on May 1st, one of them running past midnight, and one on May 2nd
+    fn seed_history(dir: &Path) {
+        // 09:00-11:00, two students, one upload failed
+        seed_room(dir, "100001", json!({
+            "opened_at": MAY_1 + 9 * HOUR_MS,
+            "closed_at": MAY_1 + 11 * HOUR_MS,
+            "recordings": [recording("proctor", 7200, Some("QmA")), recording("s1", 3600, None)],
+            "incidents": [incident("s1", "tab_switch", 3), incident("s1", MEDIA_GAP_ACTIVITY, 5)],
+            "integrity": students(&["s1", "s2"]),
+        }));
+        // 23:00 on the 1st to 01:00 on the 2nd, counted on the 1st
+        seed_room(dir, "100002", json!({
+            "opened_at": MAY_1 + 23 * HOUR_MS,
+            "closed_at": MAY_1 + 25 * HOUR_MS,
+            "recordings": [recording("proctor", 7200, Some("QmB"))],
+            "incidents": [incident("s3", "phone_detected", 1)],
+            "integrity": students(&["s3", "s4", "s5", "s6"]),
+        }));
+        // A manifest from before opened_at existed, dated by its first recording
+        let mut legacy = recording("proctor", 1800, Some("QmC"));
+        legacy["started_at"] = json!(MAY_1 + 24 * HOUR_MS + 10 * HOUR_MS);
+        seed_room(dir, "100003", json!({
+            "closed_at": MAY_1 + 24 * HOUR_MS + 10 * HOUR_MS + HOUR_MS / 2,
+            "recordings": [legacy],
+        }));
+    }
+
+    #[test]
+    fn test_rooms_attributed_to_start_date() {
+        let dir = test_dir("aggregate");
+        seed_history(&dir);
+
+        let manifests = read_manifests(&dir).unwrap().into_iter().map(|(_, manifest)| manifest);
+        let rows = aggregate(manifests, &Timezone::utc(), date("2024-04-30"), date("2024-05-02"));
+        assert_eq!(rows.iter().map(|r| r.date.as_str()).collect::<Vec<_>>(), ["2024-04-30", "2024-05-01", "2024-05-02"]);
+
+        let empty = &rows[0];
+        assert_eq!(empty.rooms_held, 0);
+        assert_eq!(empty.avg_session_secs, None);
+        assert_eq!(empty.upload_success_rate, None);
+        assert_eq!(empty.incidents_per_100_student_hours, None);
+
+        let may_1 = &rows[1];
+        assert_eq!(may_1.timezone, "UTC");
+        assert_eq!(may_1.rooms_held, 2);
+        assert_eq!(may_1.avg_session_secs, Some(7200.0));
+        assert_eq!(may_1.avg_students_per_room, Some(3.0));
+        assert_eq!(may_1.recording_hours, 5.0);
+        assert_eq!((may_1.recordings, may_1.recordings_uploaded), (3, 2));
+        assert_eq!(may_1.upload_success_rate, Some(0.67));
+        // Media gaps are not incidents; 4 reports over 6 students x 2 hours
+        assert_eq!(may_1.incidents, 4);
+        assert_eq!(may_1.student_hours, 12.0);
+        assert_eq!(may_1.incidents_per_100_student_hours, Some(33.33));
+
+        let may_2 = &rows[2];
+        assert_eq!(may_2.rooms_held, 1);
+        assert_eq!(may_2.avg_session_secs, Some(1800.0));
+        assert_eq!(may_2.upload_success_rate, Some(1.0));
+        assert_eq!(may_2.student_hours, 0.0);
+        assert_eq!(may_2.incidents_per_100_student_hours, None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rerun_is_idempotent() {
+        let dir = test_dir("rerun");
+        seed_history(&dir);
+        let analytics = DailyAnalytics::new(&dir, Timezone::utc()).with_lookback_days(3);
+        // Run on the 3rd covers April 30th to May 2nd
+        let today = date("2024-05-03");
+
+        let first = analytics.run(today).unwrap();
+        assert_eq!(first.len(), 3);
+        let stored = std::fs::read(dir.join(ANALYTICS_FILE)).unwrap();
+
+        assert!(analytics.run(today).unwrap().is_empty());
+        assert_eq!(std::fs::read(dir.join(ANALYTICS_FILE)).unwrap(), stored);
+        assert_eq!(analytics.rows(None, None, None).unwrap(), first);
+
+        // A room that closes after the run changes only its own day
+        seed_room(&dir, "100004", json!({
+            "opened_at": MAY_1 + 24 * HOUR_MS + 20 * HOUR_MS,
+            "closed_at": MAY_1 + 24 * HOUR_MS + 21 * HOUR_MS,
+            "integrity": students(&["s7"]),
+        }));
+        let changed = analytics.run(today).unwrap();
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].date, "2024-05-02");
+        assert_eq!(changed[0].rooms_held, 2);
+
+        let may = analytics.rows(None, Some(date("2024-05-01")), Some(date("2024-05-31"))).unwrap();
+        assert_eq!(may.iter().map(|r| r.rooms_held).collect::<Vec<_>>(), [2, 2]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_tenant_rows_cover_only_their_rooms() {
+        let dir = test_dir("tenants");
+        seed_history(&dir);
+        for (tenant, room_id, students_in_room) in [("uni-a", "200001", 2), ("uni-b", "300001", 3)] {
+            let namespace = tenant::create_tenant_dir(&dir, tenant).unwrap();
+            let peer_ids: Vec<String> = (0..students_in_room).map(|i| format!("{}-s{}", tenant, i)).collect();
+            seed_room(&namespace, room_id, json!({
+                "opened_at": MAY_1 + 10 * HOUR_MS,
+                "closed_at": MAY_1 + 11 * HOUR_MS,
+                "integrity": students(&peer_ids.iter().map(String::as_str).collect::<Vec<_>>()),
+            }));
+        }
+        let analytics = DailyAnalytics::new(&dir, Timezone::utc()).with_lookback_days(1);
+        analytics.run(date("2024-05-02")).unwrap();
+
+        let held = |tenant| analytics.rows(tenant, None, None).unwrap().iter().map(|r| r.rooms_held).collect::<Vec<_>>();
+        // Tenant rooms count towards the all-room rows too
+        assert_eq!(held(None), [4]);
+        assert_eq!(held(Some("uni-a")), [1]);
+        assert_eq!(held(Some("uni-b")), [1]);
+        assert_eq!(analytics.rows(Some("uni-b"), None, None).unwrap()[0].avg_students_per_room, Some(3.0));
+        assert!(dir.join("uni-a").join(ANALYTICS_FILE).is_file());
+        // A tenant without rooms has no rows
+        assert!(held(Some("uni-c")).is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(parse_time_of_day("01:00"), Some(3600));
+        assert_eq!(parse_time_of_day("23:59"), Some(86_340));
+        for invalid in ["24:00", "12:60", "1200", "", "ab:cd"] {
+            assert_eq!(parse_time_of_day(invalid), None, "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! CORS for the REST routes, so the proctor and student UIs can call them
+//! from their own origins. Origins come from `CORS_ALLOWED_ORIGINS`; without
+//! it no CORS headers are sent and responses are unchanged.
+//!
+//! The WebSocket route is left out: browsers do not apply CORS to the
+//! upgrade. So is the LTI launch, which the platform's form posts from an
+//! origin of its own.
+
+use std::time::Duration;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+
+use crate::config::env;
+
+/// How long browsers may cache a preflight answer
+const CORS_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// `PUT` is used by the admin log level and negotiation tuning routes
+const ALLOWED_METHODS: &[&str] = &["GET", "POST", "PUT", "DELETE"];
+
+/// Request headers the routes read: admin and tenant tokens, and JSON bodies
+const ALLOWED_HEADERS: &[&str] = &["authorization", "content-type"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    /// `*`: every origin
+    Any,
+    /// `scheme://host[:port]`, as browsers send them in `Origin`
+    List(Vec<String>),
+}
+
+impl AllowedOrigins {
+    /// Reads `CORS_ALLOWED_ORIGINS`, `None` when it is unset or has no valid entry
+    pub fn from_env() -> Option<Self> {
+        let (origins, warnings) = Self::parse(&env::get_list("CORS_ALLOWED_ORIGINS"));
+        for warning in warnings {
+            tracing::warn!("{}", warning);
+        }
+        origins
+    }
+
+    /// Origins from the entries of the list, skipping malformed ones
+    fn parse(entries: &[String]) -> (Option<Self>, Vec<String>) {
+        if entries.iter().any(|entry| entry == "*") {
+            return (Some(Self::Any), Vec::new());
+        }
+
+        let mut warnings = Vec::new();
+        let mut origins = Vec::new();
+        for entry in entries {
+            // A trailing slash is a common slip; browsers never send one
+            let origin = entry.trim_end_matches('/');
+            if is_origin(origin) {
+                origins.push(origin.to_string());
+            } else {
+                warnings.push(format!(
+                    "CORS_ALLOWED_ORIGINS entry {} is not an origin like https://exam.example.com, skipping it",
+                    entry
+                ));
+            }
+        }
+        ((!origins.is_empty()).then_some(Self::List(origins)), warnings)
+    }
+
+    fn builder(&self) -> warp::cors::Builder {
+        let cors = warp::cors()
+            .allow_methods(ALLOWED_METHODS.iter().copied())
+            .allow_headers(ALLOWED_HEADERS.iter().copied())
+            .max_age(CORS_MAX_AGE);
+        match self {
+            Self::Any => cors.allow_any_origin(),
+            Self::List(origins) => cors.allow_origins(origins.iter().map(String::as_str)),
+        }
+    }
+}
+
+/// `scheme://host[:port]` with nothing after it, which warp accepts as an
+/// allowed origin
+fn is_origin(value: &str) -> bool {
+    let Some((scheme, authority)) = value.split_once("://") else {
+        return false;
+    };
+    let scheme_ok = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    // A bracketed IPv6 host has colons of its own
+    let port_sep = authority.rfind(':').filter(|&i| i > authority.rfind(']').unwrap_or(0));
+    let (host, port) = match port_sep {
+        Some(i) => (&authority[..i], Some(&authority[i + 1..])),
+        None => (authority, None),
+    };
+    let bracketed = host.starts_with('[') && host.ends_with(']');
+    let host_ok = !host.is_empty()
+        && host.chars().all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '.' | '-') || (bracketed && matches!(c, '[' | ']' | ':'))
+        });
+    let port_ok = match port {
+        Some(port) => port.parse::<u16>().is_ok(),
+        None => true,
+    };
+    scheme_ok && host_ok && port_ok
+}
+
+/// `routes` answering CORS requests from `origins`, and preflight `OPTIONS`
+/// for them. Requests from other origins are refused with 403 and carry no
+/// `Access-Control-Allow-Origin`. Without `origins` the routes are unchanged.
+pub fn with_cors<F, R>(routes: F, origins: Option<&AllowedOrigins>) -> BoxedFilter<(Box<dyn Reply>,)>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    match origins {
+        Some(origins) => routes.with(origins.builder()).map(boxed_reply).boxed(),
+        None => routes.map(boxed_reply).boxed(),
+    }
+}
+
+fn boxed_reply<R: Reply + 'static>(reply: R) -> Box<dyn Reply> {
+    Box::new(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::SfuServer;
+    use std::sync::Arc;
+    use warp::http::{header, StatusCode};
+
+    const EXAM_UI: &str = "https://exam.example.com";
+
+    fn origins(entries: &[&str]) -> Option<AllowedOrigins> {
+        let entries: Vec<String> = entries.iter().map(|entry| entry.to_string()).collect();
+        AllowedOrigins::parse(&entries).0
+    }
+
+    fn routes(origins: Option<&AllowedOrigins>) -> BoxedFilter<(Box<dyn Reply>,)> {
+        with_cors(crate::api::sfu_routes::sfu_liveness_check(), origins)
+    }
+
+    #[test]
+    fn test_parse_origins() {
+        assert_eq!(
+            origins(&["https://exam.example.com/", "http://localhost:3000", "exam.example.com", "https://a.com/path"]),
+            Some(AllowedOrigins::List(vec![EXAM_UI.to_string(), "http://localhost:3000".to_string()]))
+        );
+        let (_, warnings) = AllowedOrigins::parse(&["exam.example.com".to_string()]);
+        assert_eq!(warnings.len(), 1);
+
+        assert_eq!(origins(&[EXAM_UI, "*"]), Some(AllowedOrigins::Any));
+        assert_eq!(origins(&[]), None);
+        assert_eq!(origins(&["not an origin"]), None);
+        assert!(!is_origin("https://exam.example.com:port"));
+        assert!(is_origin("http://[::1]:8080"));
+        assert!(!is_origin("http://a:b:8080"));
+    }
+
+    #[tokio::test]
+    async fn test_allow_origin_only_for_configured_origins() {
+        let origins = origins(&[EXAM_UI, "http://localhost:3000"]);
+        let route = routes(origins.as_ref());
+
+        let allowed = warp::test::request().path("/sfu/liveness").header("origin", EXAM_UI).reply(&route).await;
+        assert_eq!(allowed.status(), StatusCode::OK);
+        assert_eq!(allowed.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], EXAM_UI);
+
+        let other = warp::test::request()
+            .path("/sfu/liveness")
+            .header("origin", "https://elsewhere.example.com")
+            .reply(&route)
+            .await;
+        assert_eq!(other.status(), StatusCode::FORBIDDEN);
+        assert!(other.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        // Not a CORS request at all, as from the CLI
+        let direct = warp::test::request().path("/sfu/liveness").reply(&route).await;
+        assert_eq!(direct.status(), StatusCode::OK);
+        assert!(direct.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_requests() {
+        let origins = origins(&[EXAM_UI]);
+        let route = routes(origins.as_ref());
+        let preflight = |origin: &'static str, method: &'static str| {
+            warp::test::request()
+                .method("OPTIONS")
+                .path("/sfu/liveness")
+                .header("origin", origin)
+                .header("access-control-request-method", method)
+                .header("access-control-request-headers", "authorization")
+        };
+
+        let ok = preflight(EXAM_UI, "DELETE").reply(&route).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+        assert_eq!(ok.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], EXAM_UI);
+        assert_eq!(ok.headers()[header::ACCESS_CONTROL_MAX_AGE], "3600");
+        let methods = ok.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().to_string();
+        for method in ALLOWED_METHODS {
+            assert!(methods.contains(method), "{}", methods);
+        }
+
+        let wrong_method = preflight(EXAM_UI, "PATCH").reply(&route).await;
+        assert_eq!(wrong_method.status(), StatusCode::FORBIDDEN);
+
+        let wrong_origin = preflight("https://elsewhere.example.com", "GET").reply(&route).await;
+        assert_eq!(wrong_origin.status(), StatusCode::FORBIDDEN);
+        assert!(wrong_origin.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_preflight_for_log_level_put() {
+        let origins = origins(&[EXAM_UI]);
+        let route = with_cors(crate::api::sfu_routes::sfu_log_level_endpoint(), origins.as_ref());
+
+        let response = warp::test::request()
+            .method("OPTIONS")
+            .path("/sfu/admin/log-level")
+            .header("origin", EXAM_UI)
+            .header("access-control-request-method", "PUT")
+            .header("access-control-request-headers", "authorization, content-type")
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], EXAM_UI);
+        let methods = response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap();
+        assert!(methods.contains("PUT"), "{}", methods);
+    }
+
+    #[tokio::test]
+    async fn test_any_origin_and_unconfigured() {
+        let any = routes(Some(&AllowedOrigins::Any));
+        let response = warp::test::request()
+            .path("/sfu/liveness")
+            .header("origin", "https://anywhere.example.com")
+            .reply(&any)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_some());
+
+        // Unset: no CORS handling, every origin gets the plain response
+        let plain = routes(None);
+        let response = warp::test::request().path("/sfu/liveness").header("origin", EXAM_UI).reply(&plain).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_route_is_unaffected() {
+        let server = Arc::new(SfuServer::new());
+        let origins = origins(&[EXAM_UI]);
+        let route = crate::api::sfu_routes::sfu_websocket_route(server.clone()).or(routes(origins.as_ref()));
+
+        // An origin the REST routes refuse can still open the signaling socket
+        let ws = warp::test::ws()
+            .path("/sfu")
+            .header("origin", "https://elsewhere.example.com")
+            .handshake(route)
+            .await;
+        assert!(ws.is_ok());
+        drop(ws);
+        assert!(server.shutdown().await.is_clean());
+    }
+}
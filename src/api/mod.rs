@@ -1,2 +1,3 @@
+pub mod cors;
 pub mod sfu_websocket;
 pub mod sfu_routes;
\ No newline at end of file
@@ -1,125 +1,1153 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use warp::Filter;
 
-use crate::sfu::SfuServer;
-use crate::substrate::EventQueue;
+use crate::analytics::DailyAnalytics;
+use crate::chaos::{self, ChaosInjector, ChaosRequest};
+use crate::config::env;
+use crate::health;
+use crate::logging::{self, LogLevelRequest, LogLevelState, LogLevels};
+use crate::lti::{self, LtiError};
+use crate::metrics;
+use crate::recording::downloads::{self, DownloadError, CHUNK_SHA256_HEADER};
+use crate::recording::tenant::{self, TenantError, TenantScope};
+use crate::recording::transcript::{self, CallbackError, CallbackOutcome, TranscriptPayload};
+use crate::recording::{read_view_events, VIEW_EVENTS_FILE};
+use crate::sfu::{ice_selftest, rtcp, LocalDate};
+use crate::sfu::{
+    RecipeQuery, RejectReason, RenegotiationTuning, RenegotiationTuningUpdate, RetryPolicy, Roster, SfuServer,
+    WebRtcEngineConfig,
+};
 use super::sfu_websocket;
 
 
-/// Creates the SFU WebSocket route with optional blockchain integration
-pub fn sfu_websocket_route_with_queue(
-    event_queue: Option<EventQueue>,
+/// Creates the SFU WebSocket route for a server whose background tasks are
+/// already started, so the caller can shut them down once serving ends
+pub fn sfu_websocket_route(
+    sfu_server: Arc<SfuServer>,
 ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    let mut sfu_server = SfuServer::new();
-
-    // Set up blockchain event queue if available
-    if let Some(queue) = event_queue {
-        sfu_server.set_event_queue(queue);
-        tracing::info!("SFU server configured with blockchain integration");
-    }
-
-    let sfu_server = Arc::new(sfu_server);
-    sfu_server.clone().start_track_processing();
+    let settings = sfu_websocket::WebSocketSettings::from_env();
 
     warp::path("sfu")
         .and(warp::ws())
         .and(with_sfu_server(sfu_server))
-        .map(|ws: warp::ws::Ws, sfu_server: Arc<SfuServer>| {
+        .map(move |ws: warp::ws::Ws, sfu_server: Arc<SfuServer>| {
             ws.on_upgrade(move |websocket| {
-                sfu_websocket::handle_sfu_websocket(websocket, sfu_server)
+                sfu_websocket::handle_sfu_websocket(websocket, sfu_server, settings)
             })
         })
 }
 
-/// Creates the SFU WebSocket route without blockchain integration
-pub fn sfu_websocket_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    sfu_websocket_route_with_queue(None)
-}
+/// Readiness probe: healthy once startup checks pass and the listener is bound,
+/// unavailable again once graceful shutdown begins or while a required
+/// dependency is down. Optional dependencies that are down only mark it degraded.
+pub fn sfu_health_check(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let retry_policy = RetryPolicy::from_env();
 
-pub fn sfu_health_check() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
     warp::path("sfu")
         .and(warp::path("health"))
+        .and(warp::path::end())
         .and(warp::get())
-        .map(|| {
-            warp::reply::json(&serde_json::json!({
-                "status": "healthy",
-                "service": "SFU Server",
-                "version": "1.0.0"
-            }))
+        .and(with_sfu_server(sfu_server))
+        .and_then(move |sfu_server: Arc<SfuServer>| {
+            let retry_policy = retry_policy.clone();
+            async move {
+                let dependencies = sfu_server.check_dependencies().await;
+                let degraded = dependencies.degraded();
+                let ready = health::monitor().is_ready() && !dependencies.has_failure();
+                let (status, label) = if !ready {
+                    (warp::http::StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+                } else if !degraded.is_empty() {
+                    (warp::http::StatusCode::OK, "degraded")
+                } else {
+                    (warp::http::StatusCode::OK, "healthy")
+                };
+
+                let reply = warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "status": label,
+                        "service": "SFU Server",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "instance_id": env::get_string("INSTANCE_ID"),
+                        "uptime_secs": sfu_server.uptime().as_secs(),
+                        "room_count": sfu_server.room_count().await,
+                        "peer_count": sfu_server.peer_count().await,
+                        "recording_count": sfu_server.recording_count().await,
+                        "dependencies": dependencies,
+                        "degraded": degraded,
+                        "pending_students": {
+                            "current": metrics::metrics().pending_students.get(),
+                            "expired_total": metrics::metrics().pending_students_expired_total.get(),
+                        },
+                        "ice_selftest": ice_selftest::selftest().last_report(),
+                    })),
+                    status,
+                );
+                Ok::<_, warp::Rejection>(with_retry_after(reply, &retry_policy, RejectReason::ServerDraining))
+            }
         })
 }
 
-pub fn sfu_config_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-    warp::path("sfu")
-        .and(warp::path("config"))
+/// Liveness probe: fails when the runtime or a critical background task stops making progress
+pub fn sfu_liveness_check() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let retry_policy = RetryPolicy::from_env();
+
+    warp::path!("sfu" / "health" / "live")
         .and(warp::get())
-        .map(|| {
-            use std::env;
-
-            // Check blockchain configuration (without exposing private key)
-            let blockchain_enabled = env::var("ASSET_HUB_ENABLED")
-                .map(|v| v.to_lowercase() == "true")
-                .unwrap_or(false);
-
-            let blockchain_config = if blockchain_enabled {
-                serde_json::json!({
-                    "enabled": true,
-                    "rpc_url": env::var("ASSET_HUB_RPC_URL").ok(),
-                    "contract_address": env::var("ASSET_HUB_CONTRACT_ADDRESS").ok(),
-                    "gas_limit": env::var("ASSET_HUB_GAS_LIMIT").ok(),
-                    "submission_timeout_secs": env::var("ASSET_HUB_SUBMISSION_TIMEOUT_SECS").ok(),
-                    "retry_count": env::var("ASSET_HUB_RETRY_COUNT").ok(),
-                })
+        .map(move || {
+            let report = health::monitor().liveness();
+            let status = if report.alive {
+                warp::http::StatusCode::OK
             } else {
-                serde_json::json!({
-                    "enabled": false
-                })
+                warp::http::StatusCode::SERVICE_UNAVAILABLE
+            };
+
+            let reply = warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({
+                    "status": if report.alive { "alive" } else { "stalled" },
+                    "tasks": report.tasks,
+                })),
+                status,
+            );
+            with_retry_after(reply, &retry_policy, RejectReason::CapacityExceeded)
+        })
+}
+
+/// Adds a `Retry-After` header to 429/503 replies, using the same policy as signaling rejections
+fn with_retry_after(reply: impl warp::Reply, policy: &RetryPolicy, reason: RejectReason) -> warp::reply::Response {
+    let mut response = reply.into_response();
+    let status = response.status();
+
+    if status == warp::http::StatusCode::TOO_MANY_REQUESTS || status == warp::http::StatusCode::SERVICE_UNAVAILABLE {
+        let secs = policy.retry_after_secs(reason, 1.0);
+        response
+            .headers_mut()
+            .insert(warp::http::header::RETRY_AFTER, warp::http::HeaderValue::from(secs));
+    }
+
+    response
+}
+
+/// Serves a room's proctor view event stream, parsed from its recording directory.
+/// Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set,
+/// or a tenant token, which limits the lookup to that tenant's rooms.
+pub fn sfu_view_events_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "history" / "rooms" / String / "view-events")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|room_id: String, authorization: Option<String>| {
+            let Some(scope) = authorize_scope(authorization.as_deref()) else {
+                return invalid_admin_token();
             };
+            // Room IDs are generated server-side; reject anything that could escape the output dir
+            if room_id.is_empty() || !room_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "Invalid room ID" })),
+                    warp::http::StatusCode::BAD_REQUEST,
+                );
+            }
 
-            // Check recording configuration
-            let recording_enabled = env::var("RECORDING_ENABLED")
-                .map(|v| v.to_lowercase() == "true")
-                .unwrap_or(false);
+            let output_dir = env::get_string("RECORDING_OUTPUT_DIR").unwrap_or_else(|| "./recordings".to_string());
+            let room_dir = tenant::locate_room(std::path::Path::new(&output_dir), scope.tenant(), &room_id);
 
-            let recording_config = if recording_enabled {
-                serde_json::json!({
-                    "enabled": true,
-                    "output_dir": env::var("RECORDING_OUTPUT_DIR").ok(),
-                    "format": env::var("RECORDING_FORMAT").unwrap_or_else(|_| "webm".to_string()),
-                })
-            } else {
-                serde_json::json!({
-                    "enabled": false
+            match room_dir.map_or_else(
+                || Err(std::io::ErrorKind::NotFound.into()),
+                |dir| read_view_events(&dir.join(VIEW_EVENTS_FILE)),
+            ) {
+                Ok(events) => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "room_id": room_id,
+                        "events": events,
+                    })),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "No view events for room" })),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+                Err(e) => {
+                    tracing::error!(room_id = %room_id, error = %e, "Failed to read view events");
+                    warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "error": "Failed to read view events" })),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                }
+            }
+        })
+}
+
+/// Receive-side RTCP statistics per publisher: loss, jitter and the REMB last sent
+pub fn sfu_stats_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "stats")
+        .and(warp::get())
+        .map(|| warp::reply::json(&rtcp::feedback().snapshot()))
+}
+
+/// Process metrics in the Prometheus text exposition format
+pub fn sfu_metrics_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "metrics")
+        .and(warp::get())
+        .map(|| {
+            warp::reply::with_header(
+                metrics::metrics().render_prometheus(),
+                "content-type",
+                "text/plain; version=0.0.4",
+            )
+        })
+}
+
+/// Maximum accepted transcript callback body
+const TRANSCRIPT_MAX_BODY_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Receives transcripts from the ASR service. The callback URL carries a signed
+/// token, so only the service that was handed the job can post results.
+pub fn sfu_transcript_callback_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "recordings" / String / String / "transcript")
+        .and(warp::post())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(warp::body::content_length_limit(TRANSCRIPT_MAX_BODY_BYTES))
+        .and(warp::body::json())
+        .map(|room_id: String, file: String, query: HashMap<String, String>, payload: TranscriptPayload| {
+            let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+            let Some(service) = transcript::service() else {
+                return reply(
+                    serde_json::json!({ "error": "Transcription is not configured" }),
+                    warp::http::StatusCode::NOT_FOUND,
+                );
+            };
+            let token = query.get("token").map(String::as_str).unwrap_or_default();
+
+            match service.accept_callback(&room_id, &file, token, payload) {
+                Ok(CallbackOutcome::Stored(_)) => reply(
+                    serde_json::json!({ "status": "stored" }),
+                    warp::http::StatusCode::CREATED,
+                ),
+                Ok(CallbackOutcome::AlreadyStored(_)) => reply(
+                    serde_json::json!({ "status": "already_stored" }),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(CallbackError::InvalidPath) => reply(
+                    serde_json::json!({ "error": "Invalid room ID or file name" }),
+                    warp::http::StatusCode::BAD_REQUEST,
+                ),
+                Err(CallbackError::InvalidToken) => reply(
+                    serde_json::json!({ "error": "Invalid callback token" }),
+                    warp::http::StatusCode::UNAUTHORIZED,
+                ),
+                Err(CallbackError::RecordingNotFound) => reply(
+                    serde_json::json!({ "error": "Recording not found" }),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+                Err(CallbackError::Storage(e)) => {
+                    tracing::error!(room_id = %room_id, file = %file, error = %e, "Failed to store transcript");
+                    reply(
+                        serde_json::json!({ "error": "Failed to store transcript" }),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                }
+            }
+        })
+}
+
+/// Resumable recording downloads: `GET /sfu/recordings/{room_id}` lists the
+/// room's recordings as `recording` or `finalized`, `GET .../manifest` returns
+/// the file's size, SHA-256 and the hash of every fixed-size chunk,
+/// `GET .../chunk/{n}` returns one chunk with its hash in `X-Chunk-Sha256`.
+/// Recordings still being written answer 409. Requires
+/// `Authorization: Bearer $ADMIN_API_TOKEN`, or a tenant token, which only
+/// reaches that tenant's rooms; without `ADMIN_API_TOKEN` only tenant tokens do.
+pub fn sfu_recording_download_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    use warp::Reply;
+
+    let list = warp::path!("sfu" / "recordings" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|room_id: String, authorization: Option<String>| async move {
+            let scope = match authorize_recordings(authorization.as_deref()) {
+                Ok(scope) => scope,
+                Err(reply) => return Ok::<_, warp::Rejection>(reply),
+            };
+
+            Ok(match downloads::service().list(scope.tenant(), &room_id).await {
+                Ok(recordings) => warp::reply::json(&serde_json::json!({
+                    "room_id": room_id,
+                    "recordings": recordings,
+                }))
+                .into_response(),
+                Err(e) => download_error_reply(&room_id, "", e),
+            })
+        });
+
+    let manifest = warp::path!("sfu" / "recordings" / String / String / "manifest")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|room_id: String, file: String, authorization: Option<String>| async move {
+            let scope = match authorize_recordings(authorization.as_deref()) {
+                Ok(scope) => scope,
+                Err(reply) => return Ok::<_, warp::Rejection>(reply),
+            };
+
+            Ok(match downloads::service().manifest(scope.tenant(), &room_id, &file).await {
+                Ok(manifest) => warp::reply::json(&manifest).into_response(),
+                Err(e) => download_error_reply(&room_id, &file, e),
+            })
+        });
+
+    let chunk = warp::path!("sfu" / "recordings" / String / String / "chunk" / usize)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|room_id: String, file: String, index: usize, authorization: Option<String>| async move {
+            let scope = match authorize_recordings(authorization.as_deref()) {
+                Ok(scope) => scope,
+                Err(reply) => return Ok::<_, warp::Rejection>(reply),
+            };
+
+            Ok(match downloads::service().chunk(scope.tenant(), &room_id, &file, index).await {
+                Ok(chunk) => warp::reply::with_header(chunk.bytes, CHUNK_SHA256_HEADER, chunk.sha256).into_response(),
+                Err(e) => download_error_reply(&room_id, &file, e),
+            })
+        });
+
+    list.or(manifest).unify().or(chunk).unify()
+}
+
+fn invalid_admin_token() -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "error": "Invalid admin token" })),
+        warp::http::StatusCode::UNAUTHORIZED,
+    )
+}
+
+fn download_error_reply(room_id: &str, file: &str, error: DownloadError) -> warp::reply::Response {
+    use warp::Reply;
+
+    let (error, status) = match error {
+        DownloadError::InvalidPath => ("Invalid room ID or file name", warp::http::StatusCode::BAD_REQUEST),
+        DownloadError::RecordingNotFound => ("Recording not found", warp::http::StatusCode::NOT_FOUND),
+        DownloadError::RecordingInProgress => ("Recording is still being written", warp::http::StatusCode::CONFLICT),
+        DownloadError::ChunkOutOfRange => ("Chunk out of range", warp::http::StatusCode::RANGE_NOT_SATISFIABLE),
+        DownloadError::Storage(e) => {
+            tracing::error!(room_id = %room_id, file = %file, error = %e, "Failed to read recording for download");
+            ("Failed to read recording", warp::http::StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    };
+    warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": error })), status).into_response()
+}
+
+/// Runs the STUN/TURN reachability self-test on demand and returns its report.
+/// Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set.
+pub fn sfu_ice_selftest_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "admin" / "ice-selftest")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|authorization: Option<String>| async move {
+            if !authorize_admin(authorization.as_deref()) {
+                return Ok::<_, warp::Rejection>(warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "Invalid admin token" })),
+                    warp::http::StatusCode::UNAUTHORIZED,
+                ));
+            }
+
+            Ok(match ice_selftest::selftest().run().await {
+                Ok(report) => warp::reply::with_status(warp::reply::json(&report), warp::http::StatusCode::OK),
+                Err(ice_selftest::SelfTestRunning) => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "ICE self-test already running" })),
+                    warp::http::StatusCode::CONFLICT,
+                ),
+            })
+        })
+}
+
+/// Checks the bearer token of admin routes against `ADMIN_API_TOKEN`; open when unset
+fn authorize_admin(authorization: Option<&str>) -> bool {
+    let Some(expected) = env::get_string("ADMIN_API_TOKEN") else {
+        return true;
+    };
+    bearer_matches(authorization, &expected)
+}
+
+/// Constant-time comparison of a bearer token against the expected value
+fn bearer_matches(authorization: Option<&str>, expected: &str) -> bool {
+    let token = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .as_bytes();
+    let expected = expected.as_bytes();
+    expected.len() == token.len()
+        && expected.iter().zip(token).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Scope of routes tenants may use: a valid tenant token sees only its
+/// tenant, otherwise the admin check applies and sees everything
+fn authorize_scope(authorization: Option<&str>) -> Option<TenantScope> {
+    match tenant_token_scope(authorization) {
+        Some(tenant) => Some(TenantScope::Tenant(tenant)),
+        None => authorize_admin(authorization).then_some(TenantScope::All),
+    }
+}
+
+/// Scope of recording downloads. Unlike [`authorize_scope`] this is closed
+/// while `ADMIN_API_TOKEN` is unset, since recordings are exam footage
+fn authorize_recordings(authorization: Option<&str>) -> Result<TenantScope, warp::reply::Response> {
+    match tenant_token_scope(authorization) {
+        Some(tenant) => Ok(TenantScope::Tenant(tenant)),
+        None => admin_recordings_scope(authorization, env::get_string("ADMIN_API_TOKEN").as_deref()),
+    }
+}
+
+fn admin_recordings_scope(authorization: Option<&str>, admin_token: Option<&str>) -> Result<TenantScope, warp::reply::Response> {
+    use warp::Reply;
+
+    require_admin_token(authorization, admin_token)
+        .map(|()| TenantScope::All)
+        .map_err(|reply| reply.into_response())
+}
+
+/// Admin check of routes that reach every tenant's data: unlike
+/// [`authorize_admin`], refused with `403` while `ADMIN_API_TOKEN` is unset
+fn require_admin_token(
+    authorization: Option<&str>,
+    admin_token: Option<&str>,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    match admin_token {
+        Some(expected) if bearer_matches(authorization, expected) => Ok(()),
+        Some(_) => Err(invalid_admin_token()),
+        None => Err(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": "This route requires ADMIN_API_TOKEN to be set" })),
+            warp::http::StatusCode::FORBIDDEN,
+        )),
+    }
+}
+
+/// The tenant of a bearer token issued by `POST /sfu/admin/tenants/{tenant}/tokens`
+fn tenant_token_scope(authorization: Option<&str>) -> Option<String> {
+    let token = authorization.and_then(|value| value.strip_prefix("Bearer "))?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    tenant::tokens()?.verify(token, now)
+}
+
+/// Maximum accepted roster upload body
+const ROSTER_MAX_BODY_BYTES: u64 = 512 * 1024;
+
+/// Server state without a WebSocket: `GET /sfu/rooms` lists every room with
+/// its proctor, student count, creation time and recording peers, and
+/// `GET /sfu/rooms/{room_id}` adds each peer's role, name, track count and
+/// connection state. Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that
+/// variable is set, or a tenant token, which only sees that tenant's rooms.
+pub fn sfu_rooms_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let list = warp::path!("sfu" / "rooms")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server.clone()))
+        .and_then(|authorization: Option<String>, sfu_server: Arc<SfuServer>| async move {
+            let Some(scope) = authorize_scope(authorization.as_deref()) else {
+                return Ok::<_, warp::Rejection>(invalid_admin_token());
+            };
+            let mut rooms = sfu_server.room_overviews().await;
+            rooms.retain(|room| scope.allows(room.tenant.as_deref()));
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "rooms": rooms })),
+                warp::http::StatusCode::OK,
+            ))
+        });
+
+    let detail = warp::path!("sfu" / "rooms" / String)
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server))
+        .and_then(|room_id: String, authorization: Option<String>, sfu_server: Arc<SfuServer>| async move {
+            let Some(scope) = authorize_scope(authorization.as_deref()) else {
+                return Ok::<_, warp::Rejection>(invalid_admin_token());
+            };
+            let detail = sfu_server.room_detail(&room_id).await.filter(|detail| scope.allows(detail.room.tenant.as_deref()));
+            Ok(match detail {
+                Some(detail) => warp::reply::with_status(warp::reply::json(&detail), warp::http::StatusCode::OK),
+                None => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "Room not found" })),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+            })
+        });
+
+    list.or(detail).unify()
+}
+
+/// Uploads the roster of pre-registered students for a room, as a JSON array
+/// of `{peer_id, wallet_address, name}` or a CSV with the same columns.
+/// Requires `Authorization: Bearer <proctor_token>` from the room's `RoomCreated`.
+pub fn sfu_roster_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "rooms" / String / "roster")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::header::optional::<String>("content-type"))
+        .and(warp::body::content_length_limit(ROSTER_MAX_BODY_BYTES))
+        .and(warp::body::bytes())
+        .and(with_sfu_server(sfu_server))
+        .and_then(|room_id: String, authorization: Option<String>, content_type: Option<String>, body: bytes::Bytes, sfu_server: Arc<SfuServer>| async move {
+            let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+            if let Err(rejected) = authorize_proctor(&sfu_server, &room_id, authorization.as_deref()).await {
+                return Ok::<_, warp::Rejection>(rejected);
+            }
+
+            let Ok(body) = std::str::from_utf8(&body) else {
+                return Ok(reply(
+                    serde_json::json!({ "error": "Roster must be UTF-8" }),
+                    warp::http::StatusCode::BAD_REQUEST,
+                ));
+            };
+            let roster = match Roster::parse(body, content_type.as_deref()) {
+                Ok(roster) => roster,
+                Err(e) => {
+                    tracing::warn!(room_id = %room_id, error = %e, "Rejected roster upload");
+                    return Ok(reply(
+                        serde_json::json!({ "error": e.to_string() }),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ));
+                }
+            };
+
+            let entries = roster.len();
+            Ok(match sfu_server.set_room_roster(&room_id, roster).await {
+                Ok(()) => reply(
+                    serde_json::json!({ "room_id": room_id, "entries": entries }),
+                    warp::http::StatusCode::OK,
+                ),
+                // The room closed between the token check and the upload
+                Err(_) => reply(
+                    serde_json::json!({ "error": "Room not found" }),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+            })
+        })
+}
+
+/// Checks the bearer token of a room's proctor routes against the room's `proctor_token`
+async fn authorize_proctor(
+    sfu_server: &SfuServer,
+    room_id: &str,
+    authorization: Option<&str>,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+    let Some(expected) = sfu_server.get_proctor_token(room_id).await else {
+        return Err(reply(
+            serde_json::json!({ "error": "Room not found" }),
+            warp::http::StatusCode::NOT_FOUND,
+        ));
+    };
+    if !bearer_matches(authorization, &expected) {
+        return Err(reply(
+            serde_json::json!({ "error": "Invalid proctor token" }),
+            warp::http::StatusCode::UNAUTHORIZED,
+        ));
+    }
+    Ok(())
+}
+
+/// Integrity scores of a room's students: `GET /sfu/rooms/{room_id}/integrity`
+/// ranks them lowest first, `GET /sfu/rooms/{room_id}/peers/{peer_id}/integrity`
+/// shows how one score was reached. Requires the room's proctor token.
+pub fn sfu_integrity_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let room = warp::path!("sfu" / "rooms" / String / "integrity")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server.clone()))
+        .and_then(|room_id: String, authorization: Option<String>, sfu_server: Arc<SfuServer>| async move {
+            if let Err(rejected) = authorize_proctor(&sfu_server, &room_id, authorization.as_deref()).await {
+                return Ok::<_, warp::Rejection>(rejected);
+            }
+
+            let students: Vec<_> = sfu_server
+                .integrity_scores(&room_id)
+                .await
+                .into_iter()
+                .map(|(peer_id, scored)| serde_json::json!({ "peer_id": peer_id, "score": scored.score }))
+                .collect();
+            Ok(warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({ "room_id": room_id, "students": students })),
+                warp::http::StatusCode::OK,
+            ))
+        });
+
+    let peer = warp::path!("sfu" / "rooms" / String / "peers" / String / "integrity")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server))
+        .and_then(|room_id: String, peer_id: String, authorization: Option<String>, sfu_server: Arc<SfuServer>| async move {
+            if let Err(rejected) = authorize_proctor(&sfu_server, &room_id, authorization.as_deref()).await {
+                return Ok::<_, warp::Rejection>(rejected);
+            }
+
+            Ok(match sfu_server.integrity_score(&room_id, &peer_id).await {
+                Some(scored) => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "room_id": room_id,
+                        "peer_id": peer_id,
+                        "score": scored.score,
+                        "penalties": scored.penalties,
+                    })),
+                    warp::http::StatusCode::OK,
+                ),
+                None => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "Student not found in room" })),
+                    warp::http::StatusCode::NOT_FOUND,
+                ),
+            })
+        });
+
+    room.or(peer)
+}
+
+/// Diagnostics bundle of one peer's connection:
+/// `GET /sfu/rooms/{room_id}/peers/{peer_id}/diagnostics`, with `full_sdp=true`
+/// to include the full local and remote SDPs. Requires
+/// `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set, or the
+/// room's proctor token.
+pub fn sfu_diagnostics_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "rooms" / String / "peers" / String / "diagnostics")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_sfu_server(sfu_server))
+        .and_then(
+            |room_id: String, peer_id: String, authorization: Option<String>, query: HashMap<String, String>, sfu_server: Arc<SfuServer>| async move {
+                if !authorize_admin(authorization.as_deref()) {
+                    if let Err(rejected) = authorize_proctor(&sfu_server, &room_id, authorization.as_deref()).await {
+                        return Ok::<_, warp::Rejection>(rejected);
+                    }
+                }
+                let full_sdp = query.get("full_sdp").is_some_and(|value| value == "true" || value == "1");
+
+                Ok(match sfu_server.peer_diagnostics(&room_id, &peer_id, full_sdp).await {
+                    Some(bundle) => warp::reply::with_status(warp::reply::json(&bundle), warp::http::StatusCode::OK),
+                    None => warp::reply::with_status(
+                        warp::reply::json(&serde_json::json!({ "error": "Peer not found in room" })),
+                        warp::http::StatusCode::NOT_FOUND,
+                    ),
                 })
+            },
+        )
+}
+
+/// Daily room aggregates stored by the nightly analytics run:
+/// `GET /sfu/analytics/daily?from=YYYY-MM-DD&to=YYYY-MM-DD`, both bounds
+/// optional and inclusive, plus `tenant=` for one tenant's rows. Requires
+/// `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set, or a
+/// tenant token, which always gets its own tenant's rows.
+pub fn sfu_analytics_endpoint(
+    analytics: Arc<DailyAnalytics>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "analytics" / "daily")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .map(move |authorization: Option<String>, query: HashMap<String, String>| {
+            let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+            let Some(scope) = authorize_scope(authorization.as_deref()) else {
+                return invalid_admin_token();
+            };
+            let tenant = match &scope {
+                TenantScope::Tenant(tenant) => Some(tenant.clone()),
+                TenantScope::All => match tenant::parse_tenant(query.get("tenant").map(String::as_str)) {
+                    Ok(tenant) => tenant,
+                    Err(e) => {
+                        return reply(
+                            serde_json::json!({ "error": e.message(), "code": e.code() }),
+                            warp::http::StatusCode::BAD_REQUEST,
+                        );
+                    }
+                },
+            };
+
+            let mut bounds = [None, None];
+            for (bound, name) in bounds.iter_mut().zip(["from", "to"]) {
+                let Some(value) = query.get(name).filter(|value| !value.is_empty()) else {
+                    continue;
+                };
+                match LocalDate::parse(value) {
+                    Some(date) => *bound = Some(date),
+                    None => {
+                        return reply(
+                            serde_json::json!({ "error": format!("Invalid {} date, expected YYYY-MM-DD", name) }),
+                            warp::http::StatusCode::BAD_REQUEST,
+                        );
+                    }
+                }
+            }
+
+            match analytics.rows(tenant.as_deref(), bounds[0], bounds[1]) {
+                Ok(days) => reply(
+                    serde_json::json!({ "timezone": analytics.timezone().name(), "days": days }),
+                    warp::http::StatusCode::OK,
+                ),
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to read daily analytics");
+                    reply(
+                        serde_json::json!({ "error": "Failed to read daily analytics" }),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                }
+            }
+        })
+}
+
+/// Tenant administration. `POST /sfu/admin/tenants/{tenant}/tokens` issues an
+/// access token that limits room, recording and analytics routes to the
+/// tenant (404 unless `TENANT_TOKEN_SECRET` is set).
+/// `DELETE /sfu/admin/tenants/{tenant}/data?confirm={tenant}` deletes the
+/// tenant's recordings, sidecars, manifests and analytics rows and unpins its
+/// uploads from the local IPFS node; `?dry_run=true` only reports what would go.
+/// Both require `Authorization: Bearer $ADMIN_API_TOKEN` and are refused with
+/// `403` while it is unset, since a tenant token reaches the tenant's recordings.
+pub fn sfu_tenant_admin_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let token = warp::path!("sfu" / "admin" / "tenants" / String / "tokens")
+        .and(warp::post())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(|tenant_name: String, authorization: Option<String>| {
+            let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+            if let Err(refused) = require_admin_token(authorization.as_deref(), env::get_string("ADMIN_API_TOKEN").as_deref()) {
+                return refused;
+            }
+            if !tenant::is_valid_tenant(&tenant_name) {
+                let e = TenantError::Invalid(tenant_name);
+                return reply(serde_json::json!({ "error": e.message(), "code": e.code() }), warp::http::StatusCode::BAD_REQUEST);
+            }
+            let Some(tokens) = tenant::tokens() else {
+                return reply(
+                    serde_json::json!({ "error": "Tenant tokens are not configured" }),
+                    warp::http::StatusCode::NOT_FOUND,
+                );
             };
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let (token, expires_at) = tokens.issue(&tenant_name, now);
+            reply(
+                serde_json::json!({ "tenant": tenant_name, "token": token, "expires_at": expires_at }),
+                warp::http::StatusCode::OK,
+            )
+        });
+
+    let delete = warp::path!("sfu" / "admin" / "tenants" / String / "data")
+        .and(warp::delete())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<HashMap<String, String>>())
+        .and(with_sfu_server(sfu_server))
+        .and_then(
+            |tenant_name: String, authorization: Option<String>, query: HashMap<String, String>, sfu_server: Arc<SfuServer>| async move {
+                let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
 
-            // Check IPFS configuration
-            let ipfs_enabled = env::var("IPFS_ENABLED")
-                .map(|v| v.to_lowercase() == "true")
-                .unwrap_or(false);
+                if let Err(refused) = require_admin_token(authorization.as_deref(), env::get_string("ADMIN_API_TOKEN").as_deref()) {
+                    return Ok::<_, warp::Rejection>(refused);
+                }
+                let Some(dry_run) = tenant_deletion_dry_run(&tenant_name, &query) else {
+                    return Ok(reply(
+                        serde_json::json!({ "error": "Deleting tenant data needs confirm set to the tenant name" }),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ));
+                };
 
-            let ipfs_config = if ipfs_enabled {
-                serde_json::json!({
-                    "enabled": true,
-                    "api_url": env::var("IPFS_API_URL").ok(),
-                    "gateway_url": env::var("IPFS_GATEWAY_URL").ok(),
+                Ok(match sfu_server.delete_tenant_data(&tenant_name, dry_run).await {
+                    Ok(sweep) => reply(serde_json::to_value(&sweep).unwrap_or_default(), warp::http::StatusCode::OK),
+                    Err(e) => {
+                        let status = match e {
+                            TenantError::Invalid(_) => warp::http::StatusCode::BAD_REQUEST,
+                            TenantError::RoomsOpen(_) => warp::http::StatusCode::CONFLICT,
+                            TenantError::Storage(_) => {
+                                tracing::error!(tenant = %tenant_name, error = %e.message(), "Failed to delete tenant data");
+                                warp::http::StatusCode::INTERNAL_SERVER_ERROR
+                            }
+                        };
+                        reply(serde_json::json!({ "error": e.message(), "code": e.code() }), status)
+                    }
                 })
-            } else {
-                serde_json::json!({
-                    "enabled": false
+            },
+        );
+
+    token.or(delete).unify()
+}
+
+/// Whether a tenant deletion is a dry run; `None` when a real one lacks
+/// `confirm` set to the tenant name
+fn tenant_deletion_dry_run(tenant_name: &str, query: &HashMap<String, String>) -> Option<bool> {
+    let dry_run = query.get("dry_run").is_some_and(|value| value == "true" || value == "1");
+    (dry_run || query.get("confirm").map(String::as_str) == Some(tenant_name)).then_some(dry_run)
+}
+
+/// Connection recipe for one role and client kind:
+/// `GET /sfu/recipe?role=proctor|student&client=web|native`, `client` defaulting to `web`
+pub fn sfu_recipe_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // Read the way the WebSocket route reads it, so the two cannot disagree
+    let keepalive = sfu_websocket::WebSocketSettings::from_env().keepalive();
+
+    warp::path!("sfu" / "recipe")
+        .and(warp::get())
+        .and(warp::query::<RecipeQuery>())
+        .and(with_sfu_server(sfu_server))
+        .map(move |query: RecipeQuery, sfu_server: Arc<SfuServer>| {
+            warp::reply::json(&sfu_server.connection_recipe(query.role, query.client, keepalive))
+        })
+}
+
+/// Largest LTI launch form accepted; an id_token is a few kilobytes
+const LTI_LAUNCH_MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// LTI 1.3 launch: the platform posts the signed `id_token` as a form field.
+/// Replies with where to send the user, or `{error, code}` when the launch is refused.
+pub fn sfu_lti_launch_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // Read at startup with the other settings
+    let service = lti::service();
+
+    warp::path!("sfu" / "lti" / "launch")
+        .and(warp::post())
+        .and(warp::body::content_length_limit(LTI_LAUNCH_MAX_BODY_BYTES))
+        .and(warp::body::form())
+        .and(with_sfu_server(sfu_server))
+        .and_then(move |form: HashMap<String, String>, sfu_server: Arc<SfuServer>| {
+            let service = service.clone();
+            async move {
+                let reply = |body: serde_json::Value, status| warp::reply::with_status(warp::reply::json(&body), status);
+
+                let Some(service) = service else {
+                    return Ok::<_, warp::Rejection>(reply(
+                        serde_json::json!({ "error": "LTI launches are not configured", "code": "lti_disabled" }),
+                        warp::http::StatusCode::NOT_FOUND,
+                    ));
+                };
+                let Some(id_token) = form.get("id_token") else {
+                    return Ok(reply(
+                        serde_json::json!({ "error": "Missing id_token", "code": "malformed_token" }),
+                        warp::http::StatusCode::BAD_REQUEST,
+                    ));
+                };
+
+                Ok(match service.launch(&sfu_server, id_token).await {
+                    Ok(outcome) => reply(serde_json::json!(outcome), warp::http::StatusCode::OK),
+                    Err(e) => {
+                        tracing::warn!(code = e.code(), error = %e, "Refused LTI launch");
+                        reply(serde_json::json!({ "error": e.to_string(), "code": e.code() }), lti_error_status(&e))
+                    }
                 })
-            };
+            }
+        })
+}
+
+fn lti_error_status(error: &LtiError) -> warp::http::StatusCode {
+    match error {
+        LtiError::MalformedToken(_)
+        | LtiError::MissingClaim(_)
+        | LtiError::UnsupportedMessage(_)
+        | LtiError::InvalidTenant(_) => {
+            warp::http::StatusCode::BAD_REQUEST
+        }
+        LtiError::TokenExpired
+        | LtiError::InvalidSignature
+        | LtiError::InvalidIssuer
+        | LtiError::InvalidAudience
+        | LtiError::UnknownKey(_)
+        | LtiError::InvalidNonce
+        | LtiError::InvalidDeployment(_) => warp::http::StatusCode::UNAUTHORIZED,
+        LtiError::UnsupportedRole => warp::http::StatusCode::FORBIDDEN,
+        LtiError::RoomNotOpen | LtiError::RoomInUse => warp::http::StatusCode::CONFLICT,
+        LtiError::KeysetUnavailable(_) => warp::http::StatusCode::BAD_GATEWAY,
+        LtiError::RoomCreateFailed(_) => warp::http::StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+/// Per-module log level overrides: `GET` shows the active filter, `PUT` with
+/// `{target, level}` sets or (with a `null` level) clears an override.
+/// Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set.
+pub fn sfu_log_level_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let base = warp::path!("sfu" / "admin" / "log-level")
+        .and(warp::header::optional::<String>("authorization"));
+
+    let reply = |result: Result<LogLevelState, (warp::http::StatusCode, String)>| match result {
+        Ok(state) => warp::reply::with_status(warp::reply::json(&state), warp::http::StatusCode::OK),
+        Err((status, error)) => warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": error })), status),
+    };
+
+    let get = base
+        .and(warp::get())
+        .map(move |authorization: Option<String>| {
+            reply(log_levels(authorization.as_deref()).map(LogLevels::current))
+        });
+
+    let put = base
+        .and(warp::put())
+        .and(warp::body::json())
+        .map(move |authorization: Option<String>, request: LogLevelRequest| {
+            reply(log_levels(authorization.as_deref()).and_then(|levels| {
+                levels.apply(request).map_err(|e| (warp::http::StatusCode::BAD_REQUEST, e))
+            }))
+        });
+
+    get.or(put).unify()
+}
+
+fn log_levels(authorization: Option<&str>) -> Result<&'static LogLevels, (warp::http::StatusCode, String)> {
+    if !authorize_admin(authorization) {
+        return Err((warp::http::StatusCode::UNAUTHORIZED, "Invalid admin token".to_string()));
+    }
+    logging::levels().ok_or_else(|| {
+        (warp::http::StatusCode::SERVICE_UNAVAILABLE, "Log level control is not initialized".to_string())
+    })
+}
+
+/// Renegotiation tuning: `GET` shows the settings in force, `PUT` with any of
+/// `{debounce_ms, max_batch_ms, max_tracks_per_offer, retry_backoff_ms, answer_timeout_ms}`
+/// changes them on the live instance until the next restart. Settings whose
+/// debounce or batch window exceed the answer timeout are refused with `400`.
+/// Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set.
+pub fn sfu_negotiation_tuning_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let base = warp::path!("sfu" / "admin" / "negotiation-tuning")
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server));
+
+    let reply = |result: Result<RenegotiationTuning, (warp::http::StatusCode, String)>| match result {
+        Ok(tuning) => warp::reply::with_status(warp::reply::json(&tuning), warp::http::StatusCode::OK),
+        Err((status, error)) => warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": error })), status),
+    };
+
+    let get = base
+        .clone()
+        .and(warp::get())
+        .map(move |authorization: Option<String>, sfu_server: Arc<SfuServer>| {
+            reply(admin_only(authorization.as_deref()).map(|()| sfu_server.renegotiation().tuning()))
+        });
+
+    let put = base
+        .and(warp::put())
+        .and(warp::body::json())
+        .map(move |authorization: Option<String>, sfu_server: Arc<SfuServer>, update: RenegotiationTuningUpdate| {
+            reply(admin_only(authorization.as_deref()).and_then(|()| {
+                sfu_server
+                    .renegotiation()
+                    .update(&update)
+                    .map_err(|e| (warp::http::StatusCode::BAD_REQUEST, e))
+            }))
+        });
+
+    get.or(put).unify()
+}
+
+/// Renegotiation stats per room, with the tuning they were produced under:
+/// offers sent per connection, track changes per offer, offer to answer time
+/// and retries. Requires `Authorization: Bearer $ADMIN_API_TOKEN` when that variable is set.
+pub fn sfu_negotiation_stats_endpoint(
+    sfu_server: Arc<SfuServer>,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("sfu" / "admin" / "negotiation-stats")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(with_sfu_server(sfu_server))
+        .map(|authorization: Option<String>, sfu_server: Arc<SfuServer>| match admin_only(authorization.as_deref()) {
+            Ok(()) => warp::reply::with_status(
+                warp::reply::json(&sfu_server.renegotiation().snapshot()),
+                warp::http::StatusCode::OK,
+            ),
+            Err((status, error)) => warp::reply::with_status(warp::reply::json(&serde_json::json!({ "error": error })), status),
+        })
+}
 
-            let config = serde_json::json!({
-                "SFU_WEBSOCKET_URL": env::var("SFU_WEBSOCKET_URL").ok(),
-                "STUN_SERVER_URL": env::var("STUN_SERVER_URL").ok(),
-                "PROCTOR_UI_URL": env::var("PROCTOR_UI_URL").ok(),
-                "STUDENT_UI_URL": env::var("STUDENT_UI_URL").ok(),
-                "blockchain": blockchain_config,
-                "recording": recording_config,
-                "ipfs": ipfs_config,
-            });
+fn admin_only(authorization: Option<&str>) -> Result<(), (warp::http::StatusCode, String)> {
+    if !authorize_admin(authorization) {
+        return Err((warp::http::StatusCode::UNAUTHORIZED, "Invalid admin token".to_string()));
+    }
+    Ok(())
+}
 
+/// Failure injection admin API: register (POST), list (GET) and cancel
+/// (DELETE /{id}) chaos directives. Responds 404 unless `CHAOS_ENABLED=true`.
+pub fn sfu_chaos_admin_endpoint() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let base = warp::path!("sfu" / "admin" / "chaos" / ..)
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(|authorization: Option<String>| async move {
+            authorize_chaos_admin(authorization.as_deref()).map_err(|(status, error)| {
+                warp::reject::custom(ChaosAdminRejection { status, error })
+            })
+        });
+
+    let create = base.clone()
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::body::json())
+        .map(|injector: Arc<ChaosInjector>, request: ChaosRequest| {
+            match injector.add(request, std::time::Instant::now()) {
+                Ok(directive) => warp::reply::with_status(
+                    warp::reply::json(&directive),
+                    warp::http::StatusCode::CREATED,
+                ),
+                Err(e) => warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": e.message() })),
+                    warp::http::StatusCode::BAD_REQUEST,
+                ),
+            }
+        });
+
+    let list = base.clone()
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|injector: Arc<ChaosInjector>| {
+            warp::reply::with_status(
+                warp::reply::json(&serde_json::json!({
+                    "directives": injector.list(std::time::Instant::now()),
+                })),
+                warp::http::StatusCode::OK,
+            )
+        });
+
+    let cancel = base
+        .and(warp::path!(u64))
+        .and(warp::delete())
+        .map(|injector: Arc<ChaosInjector>, id: u64| {
+            if injector.cancel(id) {
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "status": "cancelled", "id": id })),
+                    warp::http::StatusCode::OK,
+                )
+            } else {
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({ "error": "Chaos directive not found" })),
+                    warp::http::StatusCode::NOT_FOUND,
+                )
+            }
+        });
+
+    create.or(list).unify().or(cancel).unify().recover(recover_chaos_admin)
+}
+
+#[derive(Debug)]
+struct ChaosAdminRejection {
+    status: warp::http::StatusCode,
+    error: &'static str,
+}
+
+impl warp::reject::Reject for ChaosAdminRejection {}
+
+fn authorize_chaos_admin(authorization: Option<&str>) -> Result<Arc<ChaosInjector>, (warp::http::StatusCode, &'static str)> {
+    let injector = chaos::injector()
+        .ok_or((warp::http::StatusCode::NOT_FOUND, "Failure injection is not enabled"))?;
+    let token = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !injector.authorize(token) {
+        return Err((warp::http::StatusCode::UNAUTHORIZED, "Invalid admin token"));
+    }
+    Ok(injector)
+}
+
+async fn recover_chaos_admin(rejection: warp::Rejection) -> Result<warp::reply::Response, warp::Rejection> {
+    use warp::Reply;
+
+    match rejection.find::<ChaosAdminRejection>() {
+        Some(ChaosAdminRejection { status, error }) => Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": error })),
+            *status,
+        ).into_response()),
+        None => Err(rejection),
+    }
+}
+
+/// Effective configuration. Settings are resolved once when the route is
+/// built; the environment report is recomputed on every request.
+/// `websocket_url` is where clients connect, `wss://` when the listener has TLS.
+pub fn sfu_config_endpoint(websocket_url: String) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // Check blockchain configuration (without exposing private key)
+    let blockchain_config = if env::get_bool("ASSET_HUB_ENABLED", false) {
+        serde_json::json!({
+            "enabled": true,
+            "rpc_url": env::get_string("ASSET_HUB_RPC_URL"),
+            "contract_address": env::get_string("ASSET_HUB_CONTRACT_ADDRESS"),
+            "gas_limit": env::get_string("ASSET_HUB_GAS_LIMIT"),
+            "submission_timeout_secs": env::get_string("ASSET_HUB_SUBMISSION_TIMEOUT_SECS"),
+            "retry_count": env::get_string("ASSET_HUB_RETRY_COUNT"),
+        })
+    } else {
+        serde_json::json!({
+            "enabled": false
+        })
+    };
+
+    // Check recording configuration
+    let recording_config = if env::get_bool("RECORDING_ENABLED", false) {
+        serde_json::json!({
+            "enabled": true,
+            "output_dir": env::get_string("RECORDING_OUTPUT_DIR"),
+            "format": env::get_string("RECORDING_FORMAT").unwrap_or_else(|| "webm".to_string()),
+        })
+    } else {
+        serde_json::json!({
+            "enabled": false
+        })
+    };
+
+    // Check IPFS configuration
+    let ipfs_config = if env::get_bool("IPFS_ENABLED", false) {
+        serde_json::json!({
+            "enabled": true,
+            "backend": env::get_string("IPFS_BACKEND").unwrap_or_else(|| "local".to_string()),
+            "api_url": env::get_string("IPFS_API_URL"),
+            "gateway_url": env::get_string("IPFS_GATEWAY_URL"),
+        })
+    } else {
+        serde_json::json!({
+            "enabled": false
+        })
+    };
+
+    // Where ICE traffic is expected, for checking firewall and NAT setup from a client
+    let webrtc_config = match WebRtcEngineConfig::from_env() {
+        Ok(engine) => serde_json::json!({
+            "udp_port_range": engine.udp_port_range.map(|(min, max)| serde_json::json!({ "min": min, "max": max })),
+            "udp_mux_port": engine.udp_mux_port,
+            "public_ips": engine.public_ips,
+        }),
+        Err(e) => serde_json::json!({ "error": e.to_string() }),
+    };
+
+    let config = serde_json::json!({
+        "SFU_WEBSOCKET_URL": websocket_url,
+        "STUN_SERVER_URL": env::get_string("STUN_SERVER_URLS").or_else(|| env::get_string("STUN_SERVER_URL")),
+        "PROCTOR_UI_URL": env::get_string("PROCTOR_UI_URL"),
+        "STUDENT_UI_URL": env::get_string("STUDENT_UI_URL"),
+        "blockchain": blockchain_config,
+        "recording": recording_config,
+        "ipfs": ipfs_config,
+        "webrtc": webrtc_config,
+    });
+
+    warp::path("sfu")
+        .and(warp::path("config"))
+        .and(warp::get())
+        .map(move || {
+            let mut config = config.clone();
+            config["environment"] = serde_json::json!(env::report());
             warp::reply::json(&config)
         })
 }
@@ -128,4 +1156,230 @@ fn with_sfu_server(
     sfu_server: Arc<SfuServer>,
 ) -> impl Filter<Extract = (Arc<SfuServer>,), Error = std::convert::Infallible> + Clone {
     warp::any().map(move || sfu_server.clone())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::RoomLocale;
+
+    #[tokio::test]
+    async fn test_health_reports_counts_and_dependencies() {
+        let server = Arc::new(SfuServer::new());
+        server
+            .create_room("proctor_health".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let route = sfu_health_check(server.clone());
+
+        // Not ready until main binds the listener
+        let response = warp::test::request().method("GET").path("/sfu/health").reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert!(response.headers().contains_key("retry-after"));
+
+        health::monitor().set_ready(true);
+        let response = warp::test::request().method("GET").path("/sfu/health").reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["room_count"], 1);
+        assert_eq!(body["peer_count"], 1);
+        assert_eq!(body["recording_count"], 0);
+        assert_eq!(body["dependencies"]["asset_hub"]["status"], "disabled");
+        assert_eq!(body["dependencies"]["gstreamer"]["status"], "up");
+        assert!(body["uptime_secs"].is_u64());
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_rooms_endpoint_lists_rooms_and_peers() {
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_rooms".to_string(), Some("Dr. Smith".to_string()), None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        server.add_peer("proctor_rooms".to_string(), room_id.clone(), tx).await.unwrap();
+        let route = sfu_rooms_endpoint(server.clone());
+
+        let response = warp::test::request().method("GET").path("/sfu/rooms").reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let room = body["rooms"].as_array().unwrap().iter().find(|room| room["room_id"] == room_id.as_str()).unwrap();
+        assert_eq!(room["proctor_id"], "proctor_rooms");
+        assert_eq!(room["proctor_name"], "Dr. Smith");
+        assert_eq!(room["student_count"], 0);
+        assert_eq!(room["recording_peers"], serde_json::json!([]));
+        assert!(room["created_at"].is_u64());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path(&format!("/sfu/rooms/{}", room_id))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["room_id"], room_id.as_str());
+        let proctor = &body["peers"][0];
+        assert_eq!(proctor["peer_id"], "proctor_rooms");
+        assert_eq!(proctor["role"], "proctor");
+        assert_eq!(proctor["track_count"], 0);
+        assert!(proctor["connection_state"].is_string());
+        assert_eq!(proctor["recording"], false);
+        assert_eq!(proctor["e2ee"], false);
+
+        let response = warp::test::request().method("GET").path("/sfu/rooms/000000").reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::NOT_FOUND);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_bundle_for_connected_peer() {
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_diag".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        server.add_peer("proctor_diag".to_string(), room_id.clone(), tx).await.unwrap();
+        let route = sfu_diagnostics_endpoint(server.clone());
+        let path = format!("/sfu/rooms/{}/peers/proctor_diag/diagnostics", room_id);
+
+        let response = warp::test::request().method("GET").path(&path).reply(&route).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["peer_id"], "proctor_diag");
+        assert_eq!(body["role"], "proctor");
+        // The offer is out, so the local description is summarized but not included
+        let transport = &body["transport"];
+        assert!(!transport["local_sdp"]["media"].as_array().unwrap().is_empty());
+        assert!(transport.get("local_sdp_full").is_none());
+        assert!(transport["candidate_pairs"].is_array());
+        assert!(body["logs"].is_array());
+        assert!(body["recording"].is_null());
+
+        let response = warp::test::request()
+            .method("GET")
+            .path(&format!("{}?full_sdp=true", path))
+            .reply(&route)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(body["transport"]["local_sdp_full"].as_str().unwrap().starts_with("v=0"));
+
+        let response = warp::test::request()
+            .method("GET")
+            .path(&format!("/sfu/rooms/{}/peers/nobody/diagnostics", room_id))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::NOT_FOUND);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_deletion_needs_confirmation() {
+        let query = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+        };
+        assert_eq!(tenant_deletion_dry_run("uni-a", &query(&[])), None);
+        assert_eq!(tenant_deletion_dry_run("uni-a", &query(&[("confirm", "uni-b")])), None);
+        assert_eq!(tenant_deletion_dry_run("uni-a", &query(&[("confirm", "uni-a")])), Some(false));
+        assert_eq!(tenant_deletion_dry_run("uni-a", &query(&[("dry_run", "true")])), Some(true));
+
+        let server = SfuServer::new();
+        assert_eq!(
+            server.delete_tenant_data("Uni.A", false).await.unwrap_err(),
+            TenantError::Invalid("Uni.A".to_string())
+        );
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_admin_closed_without_admin_token() {
+        use warp::Reply;
+
+        // No test sets ADMIN_API_TOKEN, so both routes stay closed here
+        let server = Arc::new(SfuServer::new());
+        let route = sfu_tenant_admin_endpoint(server.clone());
+
+        let token = warp::test::request()
+            .method("POST")
+            .path("/sfu/admin/tenants/uni-a/tokens")
+            .reply(&route)
+            .await;
+        assert_eq!(token.status(), warp::http::StatusCode::FORBIDDEN);
+
+        let delete = warp::test::request()
+            .method("DELETE")
+            .path("/sfu/admin/tenants/uni-a/data?confirm=uni-a")
+            .header("authorization", "Bearer anything")
+            .reply(&route)
+            .await;
+        assert_eq!(delete.status(), warp::http::StatusCode::FORBIDDEN);
+
+        assert_eq!(
+            require_admin_token(Some("Bearer wrong"), Some("secret")).unwrap_err().into_response().status(),
+            warp::http::StatusCode::UNAUTHORIZED
+        );
+        assert!(require_admin_token(Some("Bearer secret"), Some("secret")).is_ok());
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[test]
+    fn test_recording_downloads_closed_without_admin_token() {
+        let status = |result: Result<TenantScope, warp::reply::Response>| result.map_err(|reply| reply.status());
+
+        assert_eq!(status(admin_recordings_scope(None, None)), Err(warp::http::StatusCode::FORBIDDEN));
+        assert_eq!(status(admin_recordings_scope(Some("Bearer anything"), None)), Err(warp::http::StatusCode::FORBIDDEN));
+        assert_eq!(status(admin_recordings_scope(Some("Bearer wrong"), Some("secret"))), Err(warp::http::StatusCode::UNAUTHORIZED));
+        assert_eq!(status(admin_recordings_scope(Some("Bearer secret"), Some("secret"))), Ok(TenantScope::All));
+    }
+
+    #[tokio::test]
+    async fn test_recording_download_route_refuses_without_admin_token() {
+        // No test sets ADMIN_API_TOKEN, so the downloads stay closed here
+        let response = warp::test::request()
+            .path("/sfu/recordings/room_closed")
+            .reply(&sfu_recording_download_endpoint())
+            .await;
+        assert_eq!(response.status(), warp::http::StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_exports_load() {
+        let server = SfuServer::new();
+        server
+            .create_room("proctor_metrics".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+
+        let response = warp::test::request().method("GET").path("/sfu/metrics").reply(&sfu_metrics_endpoint()).await;
+        assert_eq!(response.status(), warp::http::StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/plain; version=0.0.4");
+
+        let text = String::from_utf8(response.body().to_vec()).unwrap();
+        for name in [
+            "sfu_active_rooms",
+            "sfu_peers{role=\"proctor\"}",
+            "sfu_peers{role=\"student\"}",
+            "sfu_active_recordings",
+            "sfu_rtp_packets_forwarded_total",
+            "sfu_rtp_bytes_forwarded_total",
+            "sfu_renegotiations_total",
+            "sfu_chain_events_queued_total",
+            "sfu_chain_events_processed_total",
+            "sfu_chain_events_failed_total",
+            "sfu_ipfs_uploads_total",
+            "sfu_ipfs_uploads_failed_total",
+            "sfu_rooms_created_total",
+        ] {
+            assert!(text.contains(&format!("\n{} ", name)), "{} missing from scrape", name);
+        }
+        // Other tests share the process-wide registry, so values are not compared
+        assert!(text.contains("# TYPE sfu_active_rooms gauge\n"));
+
+        assert!(server.shutdown().await.is_clean());
+    }
+}
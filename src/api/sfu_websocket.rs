@@ -1,73 +1,536 @@
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tokio::time::Interval;
 use warp::ws::{Message, WebSocket};
-use futures::{SinkExt, StreamExt};
+use futures::{SinkExt, Stream, StreamExt};
+use tracing::Instrument;
 
-use crate::sfu::{SfuServer, SfuSignalingHandler, SfuMessage};
+use crate::chaos::{self, ChaosTarget, Fault};
+use crate::config::env;
+use crate::diagnostics::{self, Direction};
+use crate::sfu::{Keepalive, PeerKey, SfuServer, SfuSignalingHandler, SfuMessage};
+
+/// Default interval between server-initiated WebSocket pings
+const DEFAULT_PING_INTERVAL_SECS: u64 = 30;
+
+/// Default time a ping may go unanswered, in ping intervals
+const DEFAULT_PING_TIMEOUT_INTERVALS: u32 = 2;
+
+/// Default number of unsupported frames tolerated before a connection is closed
+const DEFAULT_MAX_UNEXPECTED_FRAMES: u32 = 10;
+
+/// Close code for "unsupported data" (RFC 6455 section 7.4.1)
+const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+
+/// Keepalive and frame-type policy applied to every signaling connection
+#[derive(Debug, Clone, Copy)]
+pub struct WebSocketSettings {
+    /// Zero disables server-initiated pings and the idle timeout
+    pub ping_interval: Duration,
+    /// How long a ping may go without any frame from the client before the
+    /// connection is closed
+    pub ping_timeout: Duration,
+    pub max_unexpected_frames: u32,
+}
+
+impl WebSocketSettings {
+    pub fn from_env() -> Self {
+        let ping_interval = env::get_duration_secs(
+            "SFU_WS_PING_INTERVAL_SECS",
+            Duration::from_secs(DEFAULT_PING_INTERVAL_SECS),
+        );
+        let default_timeout = ping_interval * DEFAULT_PING_TIMEOUT_INTERVALS;
+        let ping_timeout = Some(env::get_duration_secs("SFU_WS_PING_TIMEOUT_SECS", default_timeout))
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(default_timeout);
+        let max_unexpected_frames = env::get_parsed("SFU_WS_MAX_UNEXPECTED_FRAMES")
+            .unwrap_or(DEFAULT_MAX_UNEXPECTED_FRAMES);
+
+        Self {
+            ping_interval,
+            ping_timeout,
+            max_unexpected_frames,
+        }
+    }
+
+    /// Longest a client can stay silent before it is closed: the wait for the
+    /// next ping plus the time to answer it
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        if self.ping_interval.is_zero() {
+            None
+        } else {
+            Some(self.ping_interval + self.ping_timeout)
+        }
+    }
+
+    /// The keepalive clients are told about in their connection recipe
+    pub fn keepalive(&self) -> Keepalive {
+        Keepalive {
+            ping_interval: self.ping_interval,
+            idle_timeout: self.idle_timeout(),
+            max_unexpected_frames: self.max_unexpected_frames,
+        }
+    }
+}
+
+/// What the connection loop should do with an inbound frame
+#[derive(Debug)]
+enum FrameAction {
+    /// Text frame to decode as a signaling message
+    Dispatch(String),
+    /// Frame handled at the transport level; send this reply to the client
+    Reply(Message),
+    /// Nothing further to do
+    Ignore,
+    /// Close the connection, sending this frame first if present
+    Close(Option<Message>),
+}
+
+/// Per-connection frame bookkeeping: liveness from any inbound frame and
+/// a budget for frame types the signaling protocol doesn't use
+#[derive(Debug)]
+struct FrameGuard {
+    last_seen: Instant,
+    /// When the oldest ping still waiting for an answer was sent
+    unanswered_ping: Option<Instant>,
+    unexpected_frames: u32,
+    max_unexpected_frames: u32,
+}
+
+impl FrameGuard {
+    fn new(max_unexpected_frames: u32, now: Instant) -> Self {
+        Self {
+            last_seen: now,
+            unanswered_ping: None,
+            unexpected_frames: 0,
+            max_unexpected_frames,
+        }
+    }
+
+    fn process(&mut self, message: Message, now: Instant) -> FrameAction {
+        // Any frame proves the client is there, not only the pong itself
+        self.last_seen = now;
+        self.unanswered_ping = None;
+
+        if message.is_close() {
+            return FrameAction::Close(None);
+        }
+        if message.is_ping() {
+            return FrameAction::Reply(Message::pong(message.into_bytes()));
+        }
+        if message.is_pong() {
+            return FrameAction::Ignore;
+        }
+        if let Ok(text) = message.to_str() {
+            return FrameAction::Dispatch(text.to_string());
+        }
+
+        // No binary signaling encoding is negotiated, so binary frames are unsupported
+        self.unexpected_frames += 1;
+        if self.unexpected_frames > self.max_unexpected_frames {
+            return FrameAction::Close(Some(Message::close_with(
+                CLOSE_UNSUPPORTED_DATA,
+                "too many unsupported frames",
+            )));
+        }
+
+        let error = serde_json::json!({
+            "type": "error",
+            "code": "unsupported_frame",
+            "message": "Binary frames are not supported; send signaling messages as JSON text frames",
+        });
+        FrameAction::Reply(Message::text(error.to_string()))
+    }
+
+    fn ping_sent(&mut self, now: Instant) {
+        self.unanswered_ping.get_or_insert(now);
+    }
+
+    /// When the connection times out unless the client answers, `None` with no ping outstanding
+    fn pong_deadline(&self, ping_timeout: Duration) -> Option<Instant> {
+        self.unanswered_ping.map(|sent| sent + ping_timeout)
+    }
+
+    fn silent_for(&self, now: Instant) -> Duration {
+        now.duration_since(self.last_seen)
+    }
+
+    fn is_idle(&self, now: Instant, ping_timeout: Duration) -> bool {
+        self.pong_deadline(ping_timeout).is_some_and(|deadline| now >= deadline)
+    }
+}
+
+/// Waits until `deadline`, or forever without one
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(tokio::time::Instant::from_std(deadline)).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next ping tick, or forever if pings are disabled
+async fn next_ping(timer: &mut Option<Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
 
 pub async fn handle_sfu_websocket(
     websocket: WebSocket,
     sfu_server: Arc<SfuServer>,
+    settings: WebSocketSettings,
 ) {
-    tracing::info!("New SFU WebSocket connection established");
+    let connection_id = diagnostics::next_connection_id();
+    // Everything logged on behalf of this connection carries its ID, which
+    // also keys the log lines kept for diagnostics bundles
+    let span = tracing::info_span!("ws", connection_id);
+    tracing::info!(connection_id, "New SFU WebSocket connection established");
 
-    let (mut ws_sender, mut ws_receiver) = websocket.split();
+    let (mut ws_sender, ws_receiver) = websocket.split();
     let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
 
-    // Create signaling handler
-    let mut signaling_handler = SfuSignalingHandler::new(sfu_server, tx);
+    // Room the connection belongs to, so room-scoped chaos directives can match sends
+    let (room_tx, room_rx) = watch::channel(None::<String>);
 
-    // Spawn task to send messages to client
-    let sender_task = tokio::spawn(async move {
+    // Spawn task to send messages to client, including pongs and heartbeat pings
+    let mut sender_task = tokio::spawn(async move {
         while let Some(message) = rx.recv().await {
+            let is_close = message.is_close();
+            if let Ok(text) = message.to_str() {
+                diagnostics::record_message(connection_id, Direction::Outbound, text);
+            }
+            if message.is_text() {
+                if let Some(injector) = chaos::injector() {
+                    let fault = injector.roll(ChaosTarget::WsSend, room_rx.borrow().as_deref(), Instant::now());
+                    match fault {
+                        Some(Fault::Fail { directive_id }) => {
+                            tracing::debug!(directive_id, "Dropping WebSocket message (chaos)");
+                            continue;
+                        }
+                        Some(Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+                        None => {}
+                    }
+                }
+            }
             if let Err(e) = ws_sender.send(message).await {
                 tracing::error!(error = %e, "Failed to send WebSocket message");
                 break;
             }
+            if is_close {
+                break;
+            }
         }
+    }.instrument(span.clone()));
+
+    serve_connection(ws_receiver, tx, sfu_server, settings, room_tx, connection_id)
+        .instrument(span)
+        .await;
+
+    // Let queued frames (e.g. a close frame) flush before tearing down the sender
+    if tokio::time::timeout(Duration::from_secs(1), &mut sender_task).await.is_err() {
+        sender_task.abort();
+    }
+    tracing::info!(connection_id, "SFU WebSocket connection closed");
+}
+
+/// Reads frames until the client leaves, misses a ping or breaks the protocol,
+/// then removes it from its room. Outbound frames go through `tx`.
+async fn serve_connection<S, E>(
+    mut ws_receiver: S,
+    tx: mpsc::UnboundedSender<Message>,
+    sfu_server: Arc<SfuServer>,
+    settings: WebSocketSettings,
+    room_tx: watch::Sender<Option<String>>,
+    connection_id: u64,
+) where
+    S: Stream<Item = Result<Message, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut signaling_handler = SfuSignalingHandler::new(sfu_server, tx.clone());
+
+    let mut guard = FrameGuard::new(settings.max_unexpected_frames, Instant::now());
+    let mut ping_timer = settings.idle_timeout().map(|_| {
+        // The first ping goes out one interval after connecting, not immediately
+        let start = tokio::time::Instant::now() + settings.ping_interval;
+        let mut timer = tokio::time::interval_at(start, settings.ping_interval);
+        timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        timer
     });
 
-    while let Some(result) = ws_receiver.next().await {
-        match result {
-            Ok(message) => {
-                if let Err(e) = handle_websocket_message(&mut signaling_handler, message).await {
-                    tracing::error!(error = %e, "Error handling WebSocket message");
-                    break;
+    loop {
+        tokio::select! {
+            result = ws_receiver.next() => {
+                let Some(result) = result else { break };
+                match result {
+                    Ok(message) => {
+                        match guard.process(message, Instant::now()) {
+                            FrameAction::Dispatch(text) => {
+                                diagnostics::record_message(connection_id, Direction::Inbound, &text);
+                                if let Err(e) = handle_websocket_message(&mut signaling_handler, &text).await {
+                                    tracing::error!(error = %e, "Error handling WebSocket message");
+                                    break;
+                                }
+                                let moved = room_tx.send_if_modified(|room| {
+                                    let current = signaling_handler.room_id();
+                                    if room.as_deref() == current {
+                                        return false;
+                                    }
+                                    *room = current.map(str::to_string);
+                                    true
+                                });
+                                if let (true, Some(room_id), Some(peer_id)) = (moved, signaling_handler.room_id(), signaling_handler.peer_id()) {
+                                    diagnostics::bind_peer(PeerKey::new(room_id, peer_id), connection_id);
+                                }
+                            }
+                            FrameAction::Reply(reply) => {
+                                let _ = tx.send(reply);
+                            }
+                            FrameAction::Ignore => {}
+                            FrameAction::Close(frame) => {
+                                if let Some(frame) = frame {
+                                    tracing::warn!(
+                                        unexpected_frames = guard.unexpected_frames,
+                                        "Closing WebSocket after too many unsupported frames"
+                                    );
+                                    let _ = tx.send(frame);
+                                }
+                                break;
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::error!(error = %e, "WebSocket error");
+                        break;
+                    }
                 }
             }
-            Err(e) => {
-                tracing::error!(error = %e, "WebSocket error");
-                break;
+            _ = next_ping(&mut ping_timer) => {
+                guard.ping_sent(Instant::now());
+                let _ = tx.send(Message::ping(Vec::new()));
+            }
+            _ = sleep_until(guard.pong_deadline(settings.ping_timeout)) => {
+                let now = Instant::now();
+                if guard.is_idle(now, settings.ping_timeout) {
+                    tracing::warn!(
+                        room_id = ?signaling_handler.room_id(),
+                        silent_ms = guard.silent_for(now).as_millis() as u64,
+                        "No answer to WebSocket ping, closing connection"
+                    );
+                    let _ = tx.send(Message::close());
+                    break;
+                }
             }
         }
     }
 
-
     signaling_handler.cleanup().await;
-    sender_task.abort();
-    tracing::info!("SFU WebSocket connection closed");
 }
 
 async fn handle_websocket_message(
     signaling_handler: &mut SfuSignalingHandler,
-    message: Message,
+    text: &str,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    if let Ok(text) = message.to_str() {
-        tracing::debug!("Received SFU message: {}", text);
+    tracing::debug!("Received SFU message: {}", text);
+
+    match serde_json::from_str::<SfuMessage>(text) {
+        Ok(sfu_message) => {
+            signaling_handler.handle_message(sfu_message).await;
+        }
+        Err(e) => {
+            tracing::debug!(raw_message = %text, "Failed to parse SFU message");
+            signaling_handler.handle_parse_error(&e).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply_json(action: FrameAction) -> serde_json::Value {
+        match action {
+            FrameAction::Reply(message) => serde_json::from_str(message.to_str().unwrap()).unwrap(),
+            other => panic!("expected a reply, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ping_is_answered_with_pong() {
+        let mut guard = FrameGuard::new(10, Instant::now());
+        match guard.process(Message::ping(vec![1, 2, 3]), Instant::now()) {
+            FrameAction::Reply(reply) => {
+                assert!(reply.is_pong());
+                assert_eq!(reply.as_bytes(), &[1, 2, 3]);
+            }
+            other => panic!("expected pong, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pong_refreshes_liveness() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut guard = FrameGuard::new(10, start);
+        // Silence alone is fine until a ping goes unanswered
+        assert!(!guard.is_idle(start + timeout * 10, timeout));
+
+        guard.ping_sent(start);
+        // A later ping doesn't extend the deadline of the first
+        guard.ping_sent(start + Duration::from_secs(30));
+        assert!(!guard.is_idle(start + Duration::from_secs(59), timeout));
+        assert!(guard.is_idle(start + timeout, timeout));
+
+        assert!(matches!(guard.process(Message::pong(Vec::new()), start + Duration::from_secs(50)), FrameAction::Ignore));
+        assert!(guard.pong_deadline(timeout).is_none());
+        assert!(!guard.is_idle(start + Duration::from_secs(150), timeout));
+        assert_eq!(guard.silent_for(start + Duration::from_secs(150)), Duration::from_secs(100));
+    }
 
-        match serde_json::from_str::<SfuMessage>(text) {
-            Ok(sfu_message) => {
-                signaling_handler.handle_message(sfu_message).await;
+    #[test]
+    fn test_any_frame_answers_ping() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(60);
+        let mut guard = FrameGuard::new(10, start);
+        guard.ping_sent(start);
+
+        guard.process(Message::text(r#"{"type":"Leave","peer_id":"p1"}"#), start + Duration::from_secs(10));
+        assert!(!guard.is_idle(start + timeout, timeout));
+    }
+
+    #[tokio::test]
+    async fn test_missed_pong_closes_and_cleans_up() {
+        let server = Arc::new(SfuServer::new());
+        let settings = WebSocketSettings {
+            ping_interval: Duration::from_millis(50),
+            ping_timeout: Duration::from_millis(100),
+            max_unexpected_frames: DEFAULT_MAX_UNEXPECTED_FRAMES,
+        };
+        // The client creates a room, then never answers a ping; it stays connected
+        let (inbound, receiver) = futures::channel::mpsc::unbounded::<Result<Message, std::io::Error>>();
+        inbound
+            .unbounded_send(Ok(Message::text(r#"{"type":"CreateRoom","peer_id":"proctor_silent","name":null,"wallet_address":null}"#)))
+            .unwrap();
+        let (tx, mut outbound) = mpsc::unbounded_channel();
+        let (room_tx, _room_rx) = watch::channel(None);
+
+        tokio::time::timeout(Duration::from_secs(5), serve_connection(receiver, tx, server.clone(), settings, room_tx, 1))
+            .await
+            .expect("connection should close after the missed pong");
+
+        let mut frames = Vec::new();
+        while let Ok(frame) = outbound.try_recv() {
+            frames.push(frame);
+        }
+        assert!(frames.iter().any(|frame| frame.to_str().is_ok_and(|text| text.contains("RoomCreated"))));
+        let ping = frames.iter().position(Message::is_ping).expect("a ping was sent");
+        assert!(frames[ping..].iter().any(Message::is_close));
+
+        // Cleanup removed the proctor, which closes the room
+        assert_eq!(server.room_count().await, 0);
+        assert!(server.shutdown().await.is_clean());
+        drop(inbound);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_and_unsupported_messages_get_error_replies() {
+        let server = Arc::new(SfuServer::new());
+        let (inbound, receiver) = futures::channel::mpsc::unbounded::<Result<Message, std::io::Error>>();
+        for text in [
+            "not json",
+            r#"{"type":"NoSuchMessage"}"#,
+            r#"{"type":"RecordingStarted","room_id":"123456","peer_id":"student_1"}"#,
+        ] {
+            inbound.unbounded_send(Ok(Message::text(text))).unwrap();
+        }
+        drop(inbound);
+        let settings = WebSocketSettings {
+            ping_interval: Duration::ZERO,
+            ping_timeout: Duration::ZERO,
+            max_unexpected_frames: DEFAULT_MAX_UNEXPECTED_FRAMES,
+        };
+        let (tx, mut outbound) = mpsc::unbounded_channel();
+        let (room_tx, _room_rx) = watch::channel(None);
+
+        tokio::time::timeout(Duration::from_secs(5), serve_connection(receiver, tx, server.clone(), settings, room_tx, 1))
+        .await
+        .expect("connection should end with the client stream");
+
+        let mut replies = Vec::new();
+        while let Ok(frame) = outbound.try_recv() {
+            if let Ok(text) = frame.to_str() {
+                replies.push(serde_json::from_str::<serde_json::Value>(text).unwrap());
             }
-            Err(e) => {
-                tracing::error!(
-                    error = %e,
-                    raw_message = %text,
-                    "Failed to parse SFU message"
-                );
+        }
+        assert_eq!(replies.len(), 3);
+        assert!(replies.iter().all(|reply| reply["type"] == "error"));
+        assert_eq!(replies[0]["code"], "invalid_message");
+        assert!(replies[0]["detail"].as_str().unwrap().contains("expected"));
+        assert_eq!(replies[1]["code"], "invalid_message");
+        assert!(replies[1]["detail"].as_str().unwrap().contains("NoSuchMessage"));
+        assert_eq!(replies[2]["code"], "unsupported_message");
+        assert_eq!(replies[2]["detail"], "RecordingStarted");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[test]
+    fn test_text_is_dispatched() {
+        let mut guard = FrameGuard::new(10, Instant::now());
+        match guard.process(Message::text(r#"{"type":"Leave","peer_id":"p1"}"#), Instant::now()) {
+            FrameAction::Dispatch(text) => assert!(text.contains("Leave")),
+            other => panic!("expected dispatch, got {:?}", other),
+        }
+        assert_eq!(guard.unexpected_frames, 0);
+    }
+
+    #[test]
+    fn test_binary_gets_structured_error() {
+        let mut guard = FrameGuard::new(10, Instant::now());
+        let reply = reply_json(guard.process(Message::binary(vec![0x81, 0xa4]), Instant::now()));
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["code"], "unsupported_frame");
+    }
+
+    #[test]
+    fn test_binary_only_client_is_closed_after_limit() {
+        let mut guard = FrameGuard::new(2, Instant::now());
+        for _ in 0..2 {
+            assert!(matches!(guard.process(Message::binary(vec![0]), Instant::now()), FrameAction::Reply(_)));
+        }
+
+        match guard.process(Message::binary(vec![0]), Instant::now()) {
+            FrameAction::Close(Some(frame)) => {
+                assert_eq!(frame.close_frame().map(|(code, _)| code), Some(CLOSE_UNSUPPORTED_DATA));
             }
+            other => panic!("expected close, got {:?}", other),
         }
     }
 
-    Ok(())
-}
\ No newline at end of file
+    #[test]
+    fn test_close_frame_ends_connection() {
+        let mut guard = FrameGuard::new(10, Instant::now());
+        assert!(matches!(guard.process(Message::close(), Instant::now()), FrameAction::Close(None)));
+    }
+
+    #[test]
+    fn test_zero_ping_interval_disables_idle_timeout() {
+        let settings = WebSocketSettings {
+            ping_interval: Duration::ZERO,
+            ping_timeout: Duration::from_secs(60),
+            max_unexpected_frames: DEFAULT_MAX_UNEXPECTED_FRAMES,
+        };
+        assert!(settings.idle_timeout().is_none());
+
+        let settings = WebSocketSettings {
+            ping_interval: Duration::from_secs(30),
+            ..settings
+        };
+        assert_eq!(settings.idle_timeout(), Some(Duration::from_secs(90)));
+    }
+}
@@ -3,18 +3,107 @@
 
 use clap::{Parser, Subcommand};
 use colored::*;
+use ethers::contract::abigen;
+use ethers::providers::{Http, Provider};
+use ethers::types::Address;
 use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::io::{self, Write};
+use std::collections::{HashMap, VecDeque};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use tokio::time::{sleep, timeout, Duration};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{self, handshake::client::Response, Message};
+use tokio_tungstenite::{connect_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream};
 use urlencoding;
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{MediaEngine, MIME_TYPE_VP8};
+use webrtc::api::APIBuilder;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::interceptor::registry::Registry;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtp::header::Header;
+use webrtc::rtp::packet::Packet;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+use webrtc::track::track_local::TrackLocalWriter;
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// The dump format the server records into when its GStreamer pipeline fails
+#[allow(dead_code)]
+#[path = "../recording/rtpdump.rs"]
+mod rtpdump;
+
+use rtpdump::{DumpCodec, DumpReader, DumpRecord, DumpTrack};
+
+// Read-only view of the proctoring contract used by `chain` commands
+abigen!(
+    ProctoringReader,
+    r#"[
+        function getRoomManifest(string roomId) external view returns (string)
+    ]"#
+);
+
+/// Gateway used when the server config doesn't expose one (matches the server default)
+const DEFAULT_IPFS_GATEWAY_URL: &str = "http://127.0.0.1:8080/ipfs";
+
+/// Maximum times to retry after the server rejects with a retry hint
+const MAX_RETRY_ATTEMPTS: u32 = 3;
+
+/// Response header carrying a recording chunk's SHA-256 (matches the server)
+const CHUNK_SHA256_HEADER: &str = "x-chunk-sha256";
+
+/// Synthetic publisher frame rate (one RTP packet per frame)
+const PUBLISH_FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// RTP timestamp step per frame at the 90 kHz video clock
+const PUBLISH_TIMESTAMP_STEP: u32 = 3000;
+
+/// Payload size of each synthetic video packet
+const PUBLISH_PAYLOAD_SIZE: usize = 1000;
+
+/// How long a validation step waits for the reply it expects
+const STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Joining a student can wait seconds for the proctor's tracks before the offer
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Set by `validate --json`: progress goes to stderr so stdout carries only the report
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--tls`: the server is reached over `https://` and `wss://`
+static TLS: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--insecure`: the server's certificate is not verified
+static INSECURE: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for validation progress, moved to stderr under `--json`
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if JSON_OUTPUT.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "sfu-cli")]
 #[command(about = "SFU Server CLI Validation Tool", long_about = None)]
 struct Cli {
-    /// Server URL (default: ws://127.0.0.1:8080)
+    /// Server address (default: 127.0.0.1:8080)
     #[arg(short, long, default_value = "127.0.0.1:8080")]
     server: String,
 
@@ -22,6 +111,14 @@ struct Cli {
     #[arg(short, long, default_value = "http://localhost:5001")]
     ipfs: String,
 
+    /// Connect to the server over TLS (https:// and wss://)
+    #[arg(long)]
+    tls: bool,
+
+    /// Skip verifying the server's certificate, for self-signed ones in development
+    #[arg(long, requires = "tls")]
+    insecure: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -67,6 +164,91 @@ enum Commands {
         name: Option<String>,
     },
 
+    /// Show in-progress and completed recordings for a room
+    RecordingStatus {
+        /// Room ID to query
+        #[arg(short, long)]
+        room_id: String,
+    },
+
+    /// Download a recording chunk by chunk, verifying each chunk and the whole file
+    Download {
+        /// Room ID the recording belongs to
+        #[arg(long)]
+        room: String,
+
+        /// Recording file name, e.g. student1_1700000000.webm
+        #[arg(long)]
+        file: String,
+
+        /// Where to save it (default: the file name in the current directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Keep the verified chunks of an interrupted download and fetch only the rest
+        #[arg(long)]
+        resume: bool,
+
+        /// Admin API token (default: $ADMIN_API_TOKEN)
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Save a peer's diagnostics bundle: transport, tracks, recording and recent logs
+    Diagnostics {
+        /// Room ID the peer is in
+        #[arg(long)]
+        room: String,
+
+        /// Peer ID to diagnose
+        #[arg(long)]
+        peer: String,
+
+        /// Where to write the bundle
+        #[arg(long, default_value = "bundle.json")]
+        out: PathBuf,
+
+        /// Include the full local and remote SDPs
+        #[arg(long)]
+        full_sdp: bool,
+
+        /// Admin API token or the room's proctor token (default: $ADMIN_API_TOKEN)
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Rebuild a webm from an .rtpdump recorded while the server's pipeline was unusable
+    ConvertRtpdump {
+        /// The .rtpdump file
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Where to write the webm (default: the input with a .webm extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Publish a synthetic video track as proctor and report receive stats
+    Publish {
+        /// Proctor peer ID
+        #[arg(short, long)]
+        peer_id: String,
+
+        /// How long to publish, in seconds
+        #[arg(short, long, default_value_t = 10)]
+        duration_secs: u64,
+
+        /// Drop every Nth RTP packet before it reaches the SFU, to simulate loss
+        #[arg(long)]
+        drop_every: Option<u16>,
+    },
+
+    /// Look up what the SFU recorded on-chain
+    Chain {
+        #[command(subcommand)]
+        command: ChainCommands,
+    },
+
     /// Run automated validation scenarios
     Validate {
         /// Run all validation tests
@@ -76,15 +258,76 @@ enum Commands {
         /// Test specific scenario
         #[arg(short, long)]
         scenario: Option<String>,
+
+        /// Print per-step results as JSON on stdout
+        #[arg(long)]
+        json: bool,
     },
 
     /// Interactive mode - send custom messages
-    Interactive,
+    Interactive {
+        /// Run a script instead of reading stdin: each line is a JSON message to
+        /// send or `expect <type> [timeout_secs]`; `${field}` in a message is
+        /// replaced by that field of the last expected message
+        #[arg(long)]
+        script: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ChainCommands {
+    /// Fetch the recordings manifest pinned at room close and pretty-print it
+    Manifest {
+        /// Room ID to look up
+        #[arg(long)]
+        room: String,
+
+        /// IPFS gateway to resolve the CID through (default: from server config)
+        #[arg(long)]
+        gateway: Option<String>,
+    },
+}
+
+/// `http://` or, under `--tls`, `https://` base URL of the server
+fn http_base(server: &str) -> String {
+    let scheme = if TLS.load(Ordering::Relaxed) { "https" } else { "http" };
+    format!("{}://{}", scheme, server)
+}
+
+/// URL of the signaling endpoint, `wss://` under `--tls`
+fn ws_url(server: &str) -> String {
+    let scheme = if TLS.load(Ordering::Relaxed) { "wss" } else { "ws" };
+    format!("{}://{}/sfu", scheme, server)
+}
+
+/// Client for the server's HTTP routes, honoring `--insecure`
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(INSECURE.load(Ordering::Relaxed))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Opens a WebSocket to `url`, without checking the certificate under `--insecure`
+async fn connect(url: &str) -> Result<(WsStream, Response), tungstenite::Error> {
+    let connector = if INSECURE.load(Ordering::Relaxed) {
+        let tls = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()
+            .map_err(|e| tungstenite::Error::Tls(e.into()))?;
+        Some(Connector::NativeTls(tls))
+    } else {
+        None
+    };
+    connect_async_tls_with_config(url, None, false, connector).await
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    TLS.store(cli.tls, Ordering::Relaxed);
+    INSECURE.store(cli.insecure, Ordering::Relaxed);
 
     match &cli.command {
         Commands::Health => {
@@ -106,7 +349,40 @@ async fn main() {
         } => {
             join_room(&cli.server, room_id, peer_id, name.as_deref()).await;
         }
-        Commands::Validate { all, scenario } => {
+        Commands::RecordingStatus { room_id } => {
+            recording_status(&cli.server, room_id).await;
+        }
+        Commands::Download { room, file, output, resume, token } => {
+            let output = output.clone().unwrap_or_else(|| PathBuf::from(file));
+            let token = token
+                .clone()
+                .or_else(|| std::env::var("ADMIN_API_TOKEN").ok().filter(|t| !t.is_empty()));
+            if !download_recording(&cli.server, room, file, &output, *resume, token.as_deref()).await {
+                std::process::exit(1);
+            }
+        }
+        Commands::Diagnostics { room, peer, out, full_sdp, token } => {
+            let token = token
+                .clone()
+                .or_else(|| std::env::var("ADMIN_API_TOKEN").ok().filter(|t| !t.is_empty()));
+            if !save_diagnostics(&cli.server, room, peer, out, *full_sdp, token.as_deref()).await {
+                std::process::exit(1);
+            }
+        }
+        Commands::ConvertRtpdump { input, output } => {
+            let output = output.clone().unwrap_or_else(|| input.with_extension("webm"));
+            if !convert_rtpdump(input, &output) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Publish { peer_id, duration_secs, drop_every } => {
+            publish(&cli.server, peer_id, Duration::from_secs(*duration_secs), *drop_every).await;
+        }
+        Commands::Chain { command: ChainCommands::Manifest { room, gateway } } => {
+            chain_manifest(&cli.server, room, gateway.as_deref()).await;
+        }
+        Commands::Validate { all, scenario, json } => {
+            JSON_OUTPUT.store(*json, Ordering::Relaxed);
             if *all {
                 run_all_validations(&cli.server, &cli.ipfs).await;
             } else if let Some(s) = scenario {
@@ -116,7 +392,12 @@ async fn main() {
                 list_scenarios();
             }
         }
-        Commands::Interactive => {
+        Commands::Interactive { script: Some(script) } => {
+            if !run_script(&cli.server, script).await {
+                std::process::exit(1);
+            }
+        }
+        Commands::Interactive { script: None } => {
             interactive_mode(&cli.server).await;
         }
     }
@@ -125,8 +406,8 @@ async fn main() {
 async fn check_health(server: &str) {
     println!("{}", "Checking server health...".cyan());
 
-    let url = format!("http://{}/sfu/health", server);
-    let client = reqwest::Client::new();
+    let url = format!("{}/sfu/health", http_base(server));
+    let client = http_client();
 
     match client.get(&url).send().await {
         Ok(resp) => {
@@ -153,8 +434,8 @@ async fn check_health(server: &str) {
 async fn check_config(server: &str) {
     println!("{}", "Fetching server configuration...".cyan());
 
-    let url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     match client.get(&url).send().await {
         Ok(resp) => {
@@ -178,9 +459,9 @@ async fn check_config(server: &str) {
 async fn test_connection(server: &str) {
     println!("{}", "Testing WebSocket connection...".cyan());
 
-    let url = format!("ws://{}/sfu", server);
+    let url = ws_url(server);
 
-    match connect_async(&url).await {
+    match connect(&url).await {
         Ok((ws_stream, _)) => {
             println!("{} WebSocket connection established", "✓".green());
             println!("  URL: {}", url);
@@ -200,63 +481,30 @@ async fn create_room(server: &str, peer_id: &str, name: Option<&str>, keep_alive
         println!("  Name: {}", n);
     }
 
-    let url = format!("ws://{}/sfu", server);
-
-    match connect_async(&url).await {
-        Ok((ws_stream, _)) => {
-            let (mut write, mut read) = ws_stream.split();
-
-            // Send CreateRoom message
-            let msg = json!({
-                "type": "CreateRoom",
-                "peer_id": peer_id,
-                "name": name,
-            });
+    let msg = json!({
+        "type": "CreateRoom",
+        "peer_id": peer_id,
+        "name": name,
+    });
 
-            if write.send(Message::Text(msg.to_string())).await.is_err() {
-                println!("{} Failed to send CreateRoom message", "✗".red());
-                return;
-            }
+    match send_with_retry(server, &msg, Duration::from_secs(5)).await {
+        Ok((ws_stream, response)) => {
+            let (_write, mut read) = ws_stream.split();
 
             println!("{} CreateRoom message sent", "✓".green());
-            println!("Waiting for response...");
 
-            // Wait for RoomCreated response
-            let room_id = match timeout(Duration::from_secs(5), read.next()).await {
-                Ok(Some(Ok(Message::Text(text)))) => {
-                    if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if response["type"] == "RoomCreated" {
-                            let room_id = response["room_id"].as_str().unwrap_or("unknown").to_string();
-                            println!("{} Room created successfully!", "✓".green());
-                            println!("\n{}", "═".repeat(50).green());
-                            println!("{} {}", "Room ID:".bold(), room_id.green().bold());
-                            println!("{}", "═".repeat(50).green());
-                            Some(room_id)
-                        } else {
-                            println!("{} Unexpected response: {}", "✗".yellow(), response["type"]);
-                            println!("{}", text);
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                }
-                Ok(Some(Ok(msg))) => {
-                    println!("{} Unexpected message type: {:?}", "✗".yellow(), msg);
-                    None
-                }
-                Ok(Some(Err(e))) => {
-                    println!("{} Error receiving message: {}", "✗".red(), e);
-                    None
-                }
-                Ok(None) => {
-                    println!("{} Connection closed by server", "✗".red());
-                    None
-                }
-                Err(_) => {
-                    println!("{} Timeout waiting for response", "✗".red());
-                    None
-                }
+            // Check RoomCreated response
+            let room_id = if response["type"] == "RoomCreated" {
+                let room_id = response["room_id"].as_str().unwrap_or("unknown").to_string();
+                println!("{} Room created successfully!", "✓".green());
+                println!("\n{}", "═".repeat(50).green());
+                println!("{} {}", "Room ID:".bold(), room_id.green().bold());
+                println!("{}", "═".repeat(50).green());
+                Some(room_id)
+            } else {
+                println!("{} Unexpected response: {}", "✗".yellow(), response["type"]);
+                println!("{}", response);
+                None
             };
 
             if keep_alive && room_id.is_some() {
@@ -298,7 +546,7 @@ async fn create_room(server: &str, peer_id: &str, name: Option<&str>, keep_alive
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            println!("{} {}", "✗".red(), e);
         }
     }
 }
@@ -311,566 +559,1491 @@ async fn join_room(server: &str, room_id: &str, peer_id: &str, name: Option<&str
         println!("  Name: {}", n);
     }
 
-    let url = format!("ws://{}/sfu", server);
-
-    match connect_async(&url).await {
-        Ok((ws_stream, _)) => {
-            let (mut write, mut read) = ws_stream.split();
-
-            // Send JoinRequest message
-            let msg = json!({
-                "type": "JoinRequest",
-                "room_id": room_id,
-                "peer_id": peer_id,
-                "name": name,
-                "role": "student",
-            });
-
-            if write.send(Message::Text(msg.to_string())).await.is_err() {
-                println!("{} Failed to send JoinRequest message", "✗".red());
-                return;
-            }
+    // Send JoinRequest message
+    let msg = json!({
+        "type": "JoinRequest",
+        "room_id": room_id,
+        "peer_id": peer_id,
+        "name": name,
+        "role": "student",
+    });
 
+    match send_with_retry(server, &msg, Duration::from_secs(5)).await {
+        Ok((_ws_stream, response)) => {
             println!("{} JoinRequest message sent", "✓".green());
-            println!("Waiting for response...");
 
-            // Wait for response
-            match timeout(Duration::from_secs(5), read.next()).await {
-                Ok(Some(Ok(Message::Text(text)))) => {
-                    if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        match response["type"].as_str() {
-                            Some("join_request_sent") => {
-                                println!("{} Join request sent to proctor", "✓".green());
-                                println!("  Waiting for proctor approval...");
-                            }
-                            Some("error") => {
-                                println!("{} Error: {}", "✗".red(), response["message"]);
-                            }
-                            _ => {
-                                println!("Response: {}", text);
-                            }
-                        }
-                    }
-                }
-                Ok(Some(Ok(msg))) => {
-                    println!("Received: {:?}", msg);
-                }
-                Ok(Some(Err(e))) => {
-                    println!("{} Error: {}", "✗".red(), e);
+            match response["type"].as_str() {
+                Some("join_request_sent") => {
+                    println!("{} Join request sent to proctor", "✓".green());
+                    println!("  Waiting for proctor approval...");
                 }
-                Ok(None) => {
-                    println!("{} Connection closed", "✗".red());
+                Some("error") => {
+                    println!("{} Error: {}", "✗".red(), response["message"]);
                 }
-                Err(_) => {
-                    println!("{} Timeout", "✗".red());
+                _ => {
+                    println!("Response: {}", response);
                 }
             }
         }
         Err(e) => {
-            println!("{} Cannot connect: {}", "✗".red(), e);
+            println!("{} {}", "✗".red(), e);
         }
     }
 }
 
-fn list_scenarios() {
-    println!("\n{}", "Available Validation Scenarios:".bold());
-    println!("\n{}", "SFU Server:".bold().cyan());
-    println!("  {} - Basic WebSocket connection test", "connection".cyan());
-    println!("  {} - Room creation flow", "create-room".cyan());
-    println!("  {} - Student join flow", "join-room".cyan());
-    println!("  {} - Multiple students joining", "multi-student".cyan());
-    println!("  {} - Invalid room join (error handling)", "invalid-room".cyan());
-    println!("\n{}", "Blockchain (Asset Hub EVM):".bold().cyan());
-    println!("  {} - Check blockchain config from server", "blockchain-status".cyan());
-    println!("  {} - Test RPC endpoint connectivity", "blockchain-rpc".cyan());
-    println!("  {} - Validate contract address format", "blockchain-contract".cyan());
-    println!("  {} - Test contract read functions", "blockchain-functions".cyan());
-    println!("\n{}", "Recording:".bold().cyan());
-    println!("  {} - Check recording config from server", "recording-status".cyan());
-    println!("\n{}", "IPFS:".bold().cyan());
-    println!("  {} - Check IPFS node connectivity", "ipfs-health".cyan());
-    println!("  {} - Upload test file to IPFS", "ipfs-upload".cyan());
-    println!("  {} - Verify MFS (Mutable File System)", "ipfs-mfs".cyan());
-    println!("\nExample: sfu-cli validate --scenario connection");
-    println!("Example: sfu-cli validate --scenario blockchain-status");
-    println!("Example: sfu-cli validate --scenario blockchain-functions");
+async fn recording_status(server: &str, room_id: &str) {
+    println!("{}", "Fetching recording status...".cyan());
+    println!("  Room ID: {}", room_id);
+
+    let msg = json!({
+        "type": "GetRecordingStatus",
+        "room_id": room_id,
+    });
+
+    let response = match send_with_retry(server, &msg, Duration::from_secs(5)).await {
+        Ok((_ws_stream, response)) => response,
+        Err(e) => {
+            println!("{} {}", "✗".red(), e);
+            return;
+        }
+    };
+
+    if response["type"] != "RecordingStatus" {
+        println!("{} Unexpected response: {}", "✗".red(), response);
+        return;
+    }
+    print_session_metadata(&response["metadata"]);
+
+    let recordings = response["recordings"].as_array().cloned().unwrap_or_default();
+    println!("\n{}", "In progress:".bold());
+    if recordings.is_empty() {
+        println!("  (none)");
+    }
+    for rec in &recordings {
+        let paused = if rec["paused"].as_bool().unwrap_or(false) { " (paused)".yellow().to_string() } else { String::new() };
+        println!(
+            "  {} {}{} - {}s, {}, {} segment(s), {}",
+            "●".red(),
+            rec["peer_id"].as_str().unwrap_or("?"),
+            paused,
+            rec["elapsed_secs"].as_u64().unwrap_or(0),
+            format_bytes(rec["bytes_written"].as_u64().unwrap_or(0)),
+            rec["segments"].as_u64().unwrap_or(0),
+            rec["content"].as_str().unwrap_or("unknown"),
+        );
+    }
+
+    let completed = response["completed"].as_array().cloned().unwrap_or_default();
+    println!("\n{}", "Completed:".bold());
+    if completed.is_empty() {
+        println!("  (none)");
+    }
+    for rec in &completed {
+        println!(
+            "  {} {} - {} ({}s, {}){}",
+            "✓".green(),
+            rec["peer_id"].as_str().unwrap_or("?"),
+            rec["file"].as_str().unwrap_or("?"),
+            rec["duration_secs"].as_u64().unwrap_or(0),
+            format_bytes(rec["bytes_written"].as_u64().unwrap_or(0)),
+            rec["cid"].as_str().map(|cid| format!(" cid {}", cid)).unwrap_or_default(),
+        );
+    }
 }
 
-async fn run_scenario(server: &str, ipfs_url: &str, scenario: &str) {
-    println!("\n{} {}", "Running scenario:".bold(), scenario.cyan());
-    println!("{}", "─".repeat(60));
+/// Prints the proctor-set exam title, course code and notes, when present
+fn print_session_metadata(metadata: &serde_json::Value) {
+    for (label, field) in [("Course", "course_code"), ("Exam", "exam_name"), ("Notes", "notes")] {
+        if let Some(value) = metadata[field].as_str() {
+            println!("  {}: {}", label, value);
+        }
+    }
+}
+
+/// Reads the manifest CID for a room from the contract, resolves it through the
+/// IPFS gateway and prints the manifest
+async fn chain_manifest(server: &str, room_id: &str, gateway: Option<&str>) {
+    println!("{}", "Fetching room manifest...".cyan());
+    println!("  Room ID: {}", room_id);
+
+    let config_url = format!("{}/sfu/config", http_base(server));
+    let config = match http_client().get(&config_url).send().await {
+        Ok(response) => response.json::<serde_json::Value>().await.unwrap_or_default(),
+        Err(e) => {
+            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            return;
+        }
+    };
+
+    let blockchain = &config["blockchain"];
+    if !blockchain["enabled"].as_bool().unwrap_or(false) {
+        println!("{} Blockchain is disabled on this server", "✗".red());
+        return;
+    }
+    let (Some(rpc_url), Some(contract_address)) = (
+        blockchain["rpc_url"].as_str(),
+        blockchain["contract_address"].as_str(),
+    ) else {
+        println!("{} Server config is missing the RPC URL or contract address", "✗".red());
+        return;
+    };
 
-    let result = match scenario {
-        "connection" => validate_connection(server).await,
-        "create-room" => validate_create_room(server).await,
-        "join-room" => validate_join_room(server).await,
-        "multi-student" => validate_multi_student(server).await,
-        "invalid-room" => validate_invalid_room(server).await,
-        "blockchain-status" => validate_blockchain_status(server).await,
-        "blockchain-rpc" => validate_blockchain_rpc(server).await,
-        "blockchain-contract" => validate_blockchain_contract(server).await,
-        "blockchain-functions" => validate_blockchain_functions(server).await,
-        "recording-status" => validate_recording_status(server).await,
-        "ipfs-health" => validate_ipfs_health(ipfs_url).await,
-        "ipfs-upload" => validate_ipfs_upload(ipfs_url).await,
-        "ipfs-mfs" => validate_ipfs_mfs(ipfs_url).await,
-        _ => {
-            println!("{} Unknown scenario: {}", "✗".red(), scenario);
-            list_scenarios();
+    let provider = match Provider::<Http>::try_from(rpc_url) {
+        Ok(provider) => provider,
+        Err(e) => {
+            println!("{} Invalid RPC URL {}: {}", "✗".red(), rpc_url, e);
+            return;
+        }
+    };
+    let address: Address = match contract_address.parse() {
+        Ok(address) => address,
+        Err(e) => {
+            println!("{} Invalid contract address {}: {}", "✗".red(), contract_address, e);
             return;
         }
     };
 
-    if result {
-        println!("\n{} Scenario passed", "✓".green().bold());
+    let contract = ProctoringReader::new(address, Arc::new(provider));
+    let cid = match contract.get_room_manifest(room_id.to_string()).call().await {
+        Ok(cid) => cid,
+        Err(e) => {
+            println!("{} Contract call failed: {}", "✗".red(), e);
+            return;
+        }
+    };
+    if cid.is_empty() {
+        println!("{} No manifest recorded for this room", "○".yellow());
+        return;
+    }
+    println!("  Manifest CID: {}", cid);
+
+    let gateway = gateway
+        .or_else(|| config["ipfs"]["gateway_url"].as_str())
+        .unwrap_or(DEFAULT_IPFS_GATEWAY_URL);
+    let url = format!("{}/{}", gateway.trim_end_matches('/'), cid);
+    let manifest = match reqwest::get(&url).await {
+        Ok(response) if response.status().is_success() => match response.json::<serde_json::Value>().await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                println!("{} Manifest at {} is not valid JSON: {}", "✗".red(), url, e);
+                return;
+            }
+        },
+        Ok(response) => {
+            println!("{} Gateway returned {} for {}", "✗".red(), response.status(), url);
+            return;
+        }
+        Err(e) => {
+            println!("{} Cannot reach gateway {}: {}", "✗".red(), url, e);
+            return;
+        }
+    };
+
+    print_session_metadata(&manifest["metadata"]);
+    if manifest["complete"].as_bool().unwrap_or(false) {
+        println!("{} Manifest is complete", "✓".green());
     } else {
-        println!("\n{} Scenario failed", "✗".red().bold());
+        println!(
+            "{} Partial manifest: {} upload(s) were still running at room close",
+            "○".yellow(),
+            manifest["pending_uploads"].as_u64().unwrap_or(0)
+        );
+    }
+
+    let recordings = manifest["recordings"].as_array().cloned().unwrap_or_default();
+    println!("\n{}", "Recordings:".bold());
+    if recordings.is_empty() {
+        println!("  (none)");
+    }
+    for rec in &recordings {
+        println!(
+            "  {} {} - {}s, {} wallet {} cid {}",
+            "●".cyan(),
+            rec["peer_id"].as_str().unwrap_or("?"),
+            rec["duration_secs"].as_u64().unwrap_or(0),
+            format_bytes(rec["bytes_written"].as_u64().unwrap_or(0)),
+            rec["participant_wallet"].as_str().unwrap_or("-"),
+            rec["cid"].as_str().unwrap_or("-"),
+        );
+        if let Some(sha256) = rec["sha256"].as_str() {
+            println!("      sha256 {}", sha256);
+        }
+        let gap_secs = rec["gap_secs"].as_f64().unwrap_or(0.0);
+        if gap_secs > 0.0 {
+            println!("      {} {:.1}s of media gaps", "!".yellow(), gap_secs);
+        }
+    }
+
+    let incidents = manifest["incidents"].as_array().cloned().unwrap_or_default();
+    println!("\n{}", "Incidents:".bold());
+    if incidents.is_empty() {
+        println!("  (none)");
+    }
+    for incident in &incidents {
+        println!(
+            "  {} {} {} x{}",
+            "!".yellow(),
+            incident["peer_id"].as_str().unwrap_or("?"),
+            incident["activity_type"].as_str().unwrap_or("?"),
+            incident["count"].as_u64().unwrap_or(0),
+        );
     }
+
+    println!("\n{}", "Manifest:".bold());
+    println!("{}", serde_json::to_string_pretty(&manifest).unwrap_or_default());
 }
 
-async fn run_all_validations(server: &str, ipfs_url: &str) {
-    println!("\n{}", "Running All Validation Tests".bold().green());
-    println!("{}\n", "═".repeat(60).green());
-
-    let sfu_scenarios = vec![
-        "connection",
-        "create-room",
-        "join-room",
-        "multi-student",
-        "invalid-room",
-    ];
-
-    let blockchain_scenarios = vec![
-        "blockchain-status",
-        "blockchain-rpc",
-        "blockchain-contract",
-        "blockchain-functions",
-    ];
-
-    let recording_scenarios = vec![
-        "recording-status",
-    ];
-
-    let ipfs_scenarios = vec![
-        "ipfs-health",
-        "ipfs-upload",
-        "ipfs-mfs",
-    ];
-
-    let mut passed = 0;
-    let mut failed = 0;
-    let mut skipped = 0;
-
-    // Run SFU scenarios
-    println!("{}", "SFU Server Tests".bold().cyan());
-    for scenario in sfu_scenarios {
-        println!("\n{} Testing: {}", "▶".cyan(), scenario.bold());
-        println!("{}", "─".repeat(60));
-
-        let result = match scenario {
-            "connection" => validate_connection(server).await,
-            "create-room" => validate_create_room(server).await,
-            "join-room" => validate_join_room(server).await,
-            "multi-student" => validate_multi_student(server).await,
-            "invalid-room" => validate_invalid_room(server).await,
-            _ => false,
-        };
+/// Chunk hashes of a recording, as served by `GET /sfu/recordings/{room}/{file}/manifest`
+#[derive(Debug, Deserialize)]
+struct ChunkManifest {
+    size: u64,
+    sha256: String,
+    chunk_size: u64,
+    chunks: Vec<String>,
+}
 
-        if result {
-            passed += 1;
-        } else {
-            failed += 1;
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Where a download is assembled until its full hash checks out
+fn partial_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+fn with_token(request: reqwest::RequestBuilder, token: Option<&str>) -> reqwest::RequestBuilder {
+    match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    }
+}
+
+/// Fetches a peer's diagnostics bundle and writes it to `out` as pretty JSON.
+/// Returns false when the bundle could not be fetched or saved.
+async fn save_diagnostics(server: &str, room_id: &str, peer_id: &str, out: &Path, full_sdp: bool, token: Option<&str>) -> bool {
+    println!("{}", "Fetching diagnostics bundle...".cyan());
+    println!("  Room ID: {}", room_id);
+    println!("  Peer ID: {}", peer_id);
+
+    let url = format!(
+        "{}/sfu/rooms/{}/peers/{}/diagnostics?full_sdp={}",
+        http_base(server),
+        urlencoding::encode(room_id),
+        urlencoding::encode(peer_id),
+        full_sdp
+    );
+    let bundle = match with_token(http_client().get(&url), token).send().await {
+        Ok(response) if response.status().is_success() => match response.json::<serde_json::Value>().await {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                println!("{} Invalid diagnostics bundle: {}", "✗".red(), e);
+                return false;
+            }
+        },
+        Ok(response) => {
+            println!("{} Diagnostics request failed: {}", "✗".red(), response.status());
+            return false;
         }
+        Err(e) => {
+            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            return false;
+        }
+    };
 
-        sleep(Duration::from_millis(500)).await;
+    let text = serde_json::to_string_pretty(&bundle).unwrap_or_default();
+    if let Err(e) = std::fs::write(out, text) {
+        println!("{} Cannot write {}: {}", "✗".red(), out.display(), e);
+        return false;
     }
 
-    // Run Blockchain scenarios
-    println!("\n{}", "Blockchain (Asset Hub EVM) Tests".bold().cyan());
-    for scenario in blockchain_scenarios {
-        println!("\n{} Testing: {}", "▶".cyan(), scenario.bold());
-        println!("{}", "─".repeat(60));
+    let transport = &bundle["transport"];
+    println!("  Connection state: {}", transport["connection_state"].as_str().unwrap_or("no peer connection"));
+    println!("  Selected pair: {}", transport["selected_candidate_pair"].as_str().unwrap_or("none"));
+    println!("  Tracks: {}", bundle["tracks"].as_array().map_or(0, Vec::len));
+    println!(
+        "  Log lines: {}, signaling messages: {}",
+        bundle["logs"].as_array().map_or(0, Vec::len),
+        bundle["transcript"].as_array().map_or(0, Vec::len)
+    );
+    println!("{} Saved to {}", "✓".green(), out.display());
+    true
+}
 
-        let result = match scenario {
-            "blockchain-status" => validate_blockchain_status(server).await,
-            "blockchain-rpc" => validate_blockchain_rpc(server).await,
-            "blockchain-contract" => validate_blockchain_contract(server).await,
-            "blockchain-functions" => validate_blockchain_functions(server).await,
-            _ => false,
-        };
+/// Downloads a recording chunk by chunk into `{output}.part`, checking every
+/// chunk against the manifest and the whole file before renaming it to
+/// `output`. With `resume`, chunks of an earlier attempt that still match
+/// their hash are kept. Returns false when the download could not be verified.
+async fn download_recording(
+    server: &str,
+    room_id: &str,
+    file: &str,
+    output: &Path,
+    resume: bool,
+    token: Option<&str>,
+) -> bool {
+    println!("{}", "Downloading recording...".cyan());
+    println!("  Room ID: {}", room_id);
+    println!("  File: {}", file);
+
+    let client = http_client();
+    let base = format!(
+        "{}/sfu/recordings/{}/{}",
+        http_base(server),
+        urlencoding::encode(room_id),
+        urlencoding::encode(file)
+    );
 
-        if result {
-            passed += 1;
-        } else {
-            // Check if blockchain is just disabled (not a failure)
-            if scenario == "blockchain-status" {
-                skipped += 1;
-            } else {
-                failed += 1;
+    let manifest = match with_token(client.get(format!("{}/manifest", base)), token).send().await {
+        Ok(response) if response.status().is_success() => match response.json::<ChunkManifest>().await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                println!("{} Invalid chunk manifest: {}", "✗".red(), e);
+                return false;
             }
+        },
+        Ok(response) => {
+            println!("{} Manifest request failed: {}", "✗".red(), response.status());
+            return false;
+        }
+        Err(e) => {
+            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            return false;
         }
+    };
+    println!("  Size: {} in {} chunk(s)", format_bytes(manifest.size), manifest.chunks.len());
 
-        sleep(Duration::from_millis(500)).await;
+    let part_path = partial_path(output);
+    if !resume {
+        let _ = std::fs::remove_file(&part_path);
     }
+    let mut part = match std::fs::OpenOptions::new().read(true).write(true).create(true).open(&part_path) {
+        Ok(part) => part,
+        Err(e) => {
+            println!("{} Cannot open {}: {}", "✗".red(), part_path.display(), e);
+            return false;
+        }
+    };
 
-    // Run Recording scenarios
-    println!("\n{}", "Recording Tests".bold().cyan());
-    for scenario in recording_scenarios {
-        println!("\n{} Testing: {}", "▶".cyan(), scenario.bold());
-        println!("{}", "─".repeat(60));
+    let mut reused = 0;
+    for (index, expected) in manifest.chunks.iter().enumerate() {
+        let offset = index as u64 * manifest.chunk_size;
+        let len = manifest.chunk_size.min(manifest.size.saturating_sub(offset));
+        if resume && read_at(&mut part, offset, len).is_some_and(|bytes| sha256_hex(&bytes) == *expected) {
+            reused += 1;
+            continue;
+        }
 
-        let result = match scenario {
-            "recording-status" => validate_recording_status(server).await,
-            _ => false,
+        let Some(bytes) = fetch_chunk(&client, &base, index, expected, token).await else {
+            println!(
+                "{} Chunk {} could not be verified; rerun with --resume to continue",
+                "✗".red(),
+                index
+            );
+            return false;
         };
+        if let Err(e) = part.seek(SeekFrom::Start(offset)).and_then(|_| part.write_all(&bytes)) {
+            println!("{} Cannot write {}: {}", "✗".red(), part_path.display(), e);
+            return false;
+        }
+    }
 
-        if result {
-            passed += 1;
-        } else {
-            skipped += 1;
+    let digest = part
+        .set_len(manifest.size)
+        .and_then(|_| part.sync_all())
+        .and_then(|_| file_sha256(&mut part));
+    drop(part);
+    match digest {
+        Ok(digest) if digest == manifest.sha256 => {}
+        Ok(digest) => {
+            // Every chunk matched, so the manifest itself is inconsistent: start over next time
+            println!("{} SHA-256 mismatch: expected {}, got {}", "✗".red(), manifest.sha256, digest);
+            let _ = std::fs::remove_file(&part_path);
+            return false;
         }
+        Err(e) => {
+            println!("{} Cannot verify {}: {}", "✗".red(), part_path.display(), e);
+            return false;
+        }
+    }
 
-        sleep(Duration::from_millis(500)).await;
+    if let Err(e) = std::fs::rename(&part_path, output) {
+        println!("{} Cannot move download to {}: {}", "✗".red(), output.display(), e);
+        return false;
     }
+    println!(
+        "{} Saved {} ({} chunk(s) fetched, {} reused)",
+        "✓".green(),
+        output.display(),
+        manifest.chunks.len() - reused,
+        reused
+    );
+    println!("  sha256 {}", manifest.sha256);
+    true
+}
 
-    // Run IPFS scenarios
-    println!("\n{}", "IPFS Tests".bold().cyan());
-    for scenario in ipfs_scenarios {
-        println!("\n{} Testing: {}", "▶".cyan(), scenario.bold());
-        println!("{}", "─".repeat(60));
+/// Bytes at `offset` of a partial download, `None` when it ends before them
+fn read_at(file: &mut std::fs::File, offset: u64, len: u64) -> Option<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut bytes = vec![0u8; len as usize];
+    file.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+fn file_sha256(file: &mut std::fs::File) -> io::Result<String> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new();
+    io::copy(file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
 
-        let result = match scenario {
-            "ipfs-health" => validate_ipfs_health(ipfs_url).await,
-            "ipfs-upload" => validate_ipfs_upload(ipfs_url).await,
-            "ipfs-mfs" => validate_ipfs_mfs(ipfs_url).await,
-            _ => false,
+/// Fetches one chunk, retrying when the transfer fails or the bytes don't
+/// match the manifest
+async fn fetch_chunk(
+    client: &reqwest::Client,
+    base: &str,
+    index: usize,
+    expected: &str,
+    token: Option<&str>,
+) -> Option<Vec<u8>> {
+    for attempt in 1..=MAX_RETRY_ATTEMPTS {
+        let request = with_token(client.get(format!("{}/chunk/{}", base, index)), token);
+        let problem = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                let served_hash = response
+                    .headers()
+                    .get(CHUNK_SHA256_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(String::from);
+                match response.bytes().await {
+                    Ok(_) if served_hash.as_deref() != Some(expected) => {
+                        "server hash differs from the manifest".to_string()
+                    }
+                    Ok(bytes) if sha256_hex(&bytes) == expected => return Some(bytes.to_vec()),
+                    Ok(_) => "received bytes don't match the chunk hash".to_string(),
+                    Err(e) => e.to_string(),
+                }
+            }
+            Ok(response) => format!("server returned {}", response.status()),
+            Err(e) => e.to_string(),
         };
+        println!(
+            "  {} Chunk {} attempt {}/{}: {}",
+            "!".yellow(),
+            index,
+            attempt,
+            MAX_RETRY_ATTEMPTS,
+            problem
+        );
+    }
+    None
+}
 
-        if result {
-            passed += 1;
-        } else {
-            failed += 1;
+/// Branches of the server's recording pipeline, which a dump is replayed through
+const REPLAY_VIDEO_BRANCH: &str = "appsrc name=video_src ! rtpvp8depay ! mux.";
+const REPLAY_AUDIO_BRANCH: &str = "appsrc name=audio_src ! rtpopusdepay ! mux.";
+
+/// What was replayed from a dump
+#[derive(Default)]
+struct ReplaySummary {
+    video_packets: u64,
+    audio_packets: u64,
+    /// The dump ended mid-record, as when the server died while writing it
+    truncated: bool,
+}
+
+/// Replays an RTP dump through the recording pipeline into `{output}.part`
+/// and renames it to `output` once the muxer has finished. Returns false
+/// when the dump could not be converted.
+fn convert_rtpdump(input: &Path, output: &Path) -> bool {
+    println!("{}", "Converting RTP dump...".cyan());
+    println!("  Input: {}", input.display());
+
+    let part_path = partial_path(output);
+    let summary = match replay_rtpdump(input, &part_path) {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = std::fs::remove_file(&part_path);
+            println!("{} {}", "✗".red(), e);
+            return false;
         }
+    };
+    if let Err(e) = std::fs::rename(&part_path, output) {
+        println!("{} Cannot move the webm to {}: {}", "✗".red(), output.display(), e);
+        return false;
+    }
 
-        sleep(Duration::from_millis(500)).await;
+    println!(
+        "{} Saved {} ({} video and {} audio packets)",
+        "✓".green(),
+        output.display(),
+        summary.video_packets,
+        summary.audio_packets
+    );
+    if summary.truncated {
+        println!("  {} The dump ends mid-packet; converted up to its last complete one", "!".yellow());
     }
+    true
+}
 
-    println!("\n{}", "═".repeat(60).green());
-    println!("{}", "Validation Summary".bold());
-    println!("{}", "═".repeat(60).green());
-    println!("  {} Passed: {}", "✓".green(), passed.to_string().green());
-    println!("  {} Failed: {}", "✗".red(), failed.to_string().red());
-    if skipped > 0 {
-        println!("  {} Skipped (disabled features): {}", "○".yellow(), skipped.to_string().yellow());
+fn open_rtpdump(input: &Path) -> Result<DumpReader<io::BufReader<std::fs::File>>, String> {
+    let file = std::fs::File::open(input).map_err(|e| format!("Cannot open {}: {}", input.display(), e))?;
+    DumpReader::new(io::BufReader::new(file)).map_err(|e| format!("Cannot read {}: {}", input.display(), e))
+}
+
+/// Appsrc caps for a dumped track, refusing codecs the pipeline cannot depayload
+fn replay_caps(track: DumpTrack, codec: &DumpCodec) -> Result<gstreamer::Caps, String> {
+    let (media, encoding) = match track {
+        DumpTrack::Video => ("video", "VP8"),
+        DumpTrack::Audio => ("audio", "OPUS"),
+    };
+    if !codec.encoding_name.eq_ignore_ascii_case(encoding) {
+        return Err(format!(
+            "Cannot convert {} codec {}, only {} is supported",
+            media, codec.encoding_name, encoding
+        ));
     }
-    println!("  Total: {}", passed + failed + skipped);
+    Ok(gstreamer::Caps::builder("application/x-rtp")
+        .field("media", media)
+        .field("encoding-name", encoding)
+        .field("clock-rate", codec.clock_rate as i32)
+        .field("payload", codec.payload_type as i32)
+        .build())
+}
 
-    if failed == 0 {
-        println!("\n{}", "All validations passed! 🎉".green().bold());
-    } else {
-        println!("\n{}", "Some validations failed. Check output above.".yellow());
+fn replay_rtpdump(input: &Path, output: &Path) -> Result<ReplaySummary, String> {
+    use gstreamer as gst;
+    use gstreamer::prelude::*;
+    use gstreamer_app::AppSrc;
+
+    gst::init().map_err(|e| format!("GStreamer init failed: {}", e))?;
+
+    // Tracks without packets get no branch, so the muxer doesn't wait for them
+    let mut reader = open_rtpdump(input)?;
+    let (mut has_video, mut has_audio) = (false, false);
+    while let Some(record) = reader.next_record().map_err(|e| format!("Cannot read {}: {}", input.display(), e))? {
+        match record {
+            DumpRecord::Rtp { track: DumpTrack::Video, .. } => has_video = true,
+            DumpRecord::Rtp { track: DumpTrack::Audio, .. } => has_audio = true,
+            DumpRecord::Codec { .. } => {}
+        }
+    }
+    if !has_video && !has_audio {
+        return Err(format!("{} holds no packets", input.display()));
     }
+
+    let mut reader = open_rtpdump(input)?;
+    let header = reader.header().clone();
+    println!("  Room ID: {}", header.room_id);
+    println!("  Peer ID: {}", header.peer_id);
+
+    let branches = [(has_video, REPLAY_VIDEO_BRANCH), (has_audio, REPLAY_AUDIO_BRANCH)];
+    let mut launch: Vec<&str> = branches.iter().filter(|(used, _)| *used).map(|(_, branch)| *branch).collect();
+    launch.push("webmmux name=mux ! filesink name=sink");
+    let pipeline = gst::parse::launch(&launch.join(" "))
+        .map_err(|e| format!("Cannot build the recording pipeline: {}", e))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| "Cannot build the recording pipeline".to_string())?;
+    let location = output.to_str().ok_or_else(|| format!("{} is not valid UTF-8", output.display()))?;
+    pipeline.by_name("sink").ok_or("Pipeline has no filesink")?.set_property("location", location);
+
+    // Replayed as fast as the pipeline takes it, timestamped from the dump
+    let appsrc = |name: &str, track: DumpTrack| -> Result<Option<AppSrc>, String> {
+        let Some(src) = pipeline.by_name(name).and_then(|e| e.downcast::<AppSrc>().ok()) else {
+            return Ok(None);
+        };
+        src.set_format(gst::Format::Time);
+        src.set_caps(Some(&replay_caps(track, header.codec(track))?));
+        Ok(Some(src))
+    };
+    let video_src = appsrc("video_src", DumpTrack::Video)?;
+    let audio_src = appsrc("audio_src", DumpTrack::Audio)?;
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| format!("Cannot start the recording pipeline: {}", e))?;
+
+    let mut summary = ReplaySummary::default();
+    let mut pushed = Ok(());
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                pushed = Err(format!("Cannot read {}: {}", input.display(), e));
+                break;
+            }
+        };
+        let (track, src) = match &record {
+            DumpRecord::Rtp { track: DumpTrack::Video, .. } | DumpRecord::Codec { track: DumpTrack::Video, .. } => {
+                (DumpTrack::Video, video_src.as_ref())
+            }
+            _ => (DumpTrack::Audio, audio_src.as_ref()),
+        };
+        let Some(src) = src else { continue };
+        match record {
+            DumpRecord::Rtp { offset, packet, .. } => {
+                let mut buffer = gst::Buffer::from_mut_slice(packet);
+                if let Some(buffer) = buffer.get_mut() {
+                    buffer.set_pts(gst::ClockTime::from_nseconds(offset.as_nanos() as u64));
+                }
+                if let Err(e) = src.push_buffer(buffer) {
+                    pushed = Err(format!("Pipeline refused a packet: {}", e));
+                    break;
+                }
+                match track {
+                    DumpTrack::Video => summary.video_packets += 1,
+                    DumpTrack::Audio => summary.audio_packets += 1,
+                }
+            }
+            DumpRecord::Codec { codec, .. } => match replay_caps(track, &codec) {
+                Ok(caps) => src.set_caps(Some(&caps)),
+                Err(e) => {
+                    pushed = Err(e);
+                    break;
+                }
+            },
+        }
+    }
+    summary.truncated = reader.truncated();
+
+    for src in video_src.iter().chain(audio_src.iter()) {
+        let _ = src.end_of_stream();
+    }
+
+    // The webm is complete once EOS reaches the end of the pipeline
+    let mut finished = Err("Pipeline stopped before finishing the webm".to_string());
+    if pushed.is_ok() {
+        if let Some(bus) = pipeline.bus() {
+            for msg in bus.iter_timed(gst::ClockTime::NONE) {
+                match msg.view() {
+                    gst::MessageView::Eos(_) => {
+                        finished = Ok(());
+                        break;
+                    }
+                    gst::MessageView::Error(err) => {
+                        finished = Err(format!("Pipeline error: {}", err.error()));
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    let _ = pipeline.set_state(gst::State::Null);
+
+    pushed.and(finished).map(|_| summary)
 }
 
-async fn validate_connection(server: &str) -> bool {
-    let url = format!("ws://{}/sfu", server);
+/// Publishes a synthetic VP8 track into a fresh room, dropping every Nth packet
+/// when asked, then prints the loss the SFU observed for it
+async fn publish(server: &str, peer_id: &str, duration: Duration, drop_every: Option<u16>) {
+    println!("{}", "Publishing synthetic video...".cyan());
+    println!("  Proctor ID: {}", peer_id);
+    if let Some(n) = drop_every {
+        println!("  Dropping every {}th packet (~{:.1}% loss)", n, 100.0 / n.max(1) as f64);
+    }
 
-    match connect_async(&url).await {
-        Ok((ws_stream, _)) => {
-            println!("{} WebSocket connection successful", "✓".green());
-            drop(ws_stream);
-            true
+    let msg = json!({
+        "type": "CreateRoom",
+        "peer_id": peer_id,
+        "name": "sfu-cli publisher",
+    });
+
+    let (ws_stream, response) = match send_with_retry(server, &msg, Duration::from_secs(5)).await {
+        Ok(result) => result,
+        Err(e) => {
+            println!("{} {}", "✗".red(), e);
+            return;
         }
+    };
+    if response["type"] != "RoomCreated" {
+        println!("{} Unexpected response: {}", "✗".red(), response);
+        return;
+    }
+    println!("{} Room created: {}", "✓".green(), response["room_id"].as_str().unwrap_or("unknown"));
+
+    let (mut write, mut read) = ws_stream.split();
+    let (signal_tx, mut signal_rx) = mpsc::unbounded_channel::<String>();
+
+    let peer_connection = match create_publisher_connection(peer_id, signal_tx.clone()).await {
+        Ok(pc) => pc,
         Err(e) => {
-            println!("{} Connection failed: {}", "✗".red(), e);
-            false
+            println!("{} Failed to create peer connection: {}", "✗".red(), e);
+            return;
+        }
+    };
+    let connected = Arc::new(AtomicBool::new(false));
+    let connected_flag = connected.clone();
+    peer_connection.on_peer_connection_state_change(Box::new(move |state| {
+        if state == RTCPeerConnectionState::Connected {
+            println!("{} Peer connection established", "✓".green());
+        }
+        connected_flag.store(state == RTCPeerConnectionState::Connected, Ordering::Relaxed);
+        Box::pin(async {})
+    }));
+
+    let track = Arc::new(TrackLocalStaticRTP::new(
+        RTCRtpCodecCapability {
+            mime_type: MIME_TYPE_VP8.to_string(),
+            clock_rate: 90000,
+            ..Default::default()
+        },
+        "video".to_string(),
+        peer_id.to_string(),
+    ));
+
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut frame_timer = tokio::time::interval(PUBLISH_FRAME_INTERVAL);
+    let mut sequence_number: u16 = rand::random();
+    let mut timestamp: u32 = rand::random();
+    let mut sent = 0u64;
+    let mut dropped = 0u64;
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => break,
+            incoming = read.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        println!("{} Connection error: {}", "✗".red(), e);
+                        break;
+                    }
+                    None => {
+                        println!("{} Connection closed", "✗".yellow());
+                        break;
+                    }
+                };
+                let Ok(message) = serde_json::from_str::<serde_json::Value>(&text) else { continue };
+                if let Err(e) = handle_publisher_signal(&peer_connection, &track, peer_id, &message, &signal_tx).await {
+                    println!("{} Signaling failed: {}", "✗".red(), e);
+                    break;
+                }
+            }
+            outgoing = signal_rx.recv() => {
+                let Some(outgoing) = outgoing else { break };
+                if write.send(Message::Text(outgoing)).await.is_err() {
+                    break;
+                }
+            }
+            _ = frame_timer.tick(), if connected.load(Ordering::Relaxed) => {
+                sequence_number = sequence_number.wrapping_add(1);
+                timestamp = timestamp.wrapping_add(PUBLISH_TIMESTAMP_STEP);
+                if drop_every.is_some_and(|n| n > 0 && (sent + dropped + 1) % n as u64 == 0) {
+                    dropped += 1;
+                    continue;
+                }
+                if track.write_rtp(&synthetic_vp8_packet(sequence_number, timestamp)).await.is_ok() {
+                    sent += 1;
+                }
+            }
+        }
+    }
+
+    println!("  Sent {} packets, dropped {}", sent, dropped);
+    print_publisher_stats(server, peer_id).await;
+    let _ = peer_connection.close().await;
+}
+
+async fn create_publisher_connection(
+    peer_id: &str,
+    signal_tx: mpsc::UnboundedSender<String>,
+) -> Result<Arc<RTCPeerConnection>, webrtc::Error> {
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs()?;
+    let registry = register_default_interceptors(Registry::new(), &mut media_engine)?;
+    let api = APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .build();
+
+    let peer_connection = Arc::new(api.new_peer_connection(RTCConfiguration::default()).await?);
+
+    let peer_id = peer_id.to_string();
+    peer_connection.on_ice_candidate(Box::new(move |candidate| {
+        if let Some(Ok(init)) = candidate.map(|c| c.to_json()) {
+            let _ = signal_tx.send(json!({
+                "type": "IceCandidate",
+                "peer_id": peer_id,
+                "candidate": init.candidate,
+                "sdp_mid": init.sdp_mid,
+                "sdp_mline_index": init.sdp_mline_index,
+            }).to_string());
+        }
+        Box::pin(async {})
+    }));
+
+    Ok(peer_connection)
+}
+
+/// Answers the SFU's offer with the synthetic track and applies its ICE candidates
+async fn handle_publisher_signal(
+    peer_connection: &Arc<RTCPeerConnection>,
+    track: &Arc<TrackLocalStaticRTP>,
+    peer_id: &str,
+    message: &serde_json::Value,
+    signal_tx: &mpsc::UnboundedSender<String>,
+) -> Result<(), webrtc::Error> {
+    match message["type"].as_str() {
+        Some("offer") => {
+            let sdp = message["sdp"].as_str().unwrap_or_default().to_string();
+            peer_connection.set_remote_description(RTCSessionDescription::offer(sdp)?).await?;
+
+            if peer_connection.get_senders().await.is_empty() {
+                let rtp_sender = peer_connection.add_track(track.clone()).await?;
+                tokio::spawn(async move {
+                    while let Ok((packets, _)) = rtp_sender.read_rtcp().await {
+                        for packet in packets {
+                            let packet = packet.as_any();
+                            if let Some(rr) = packet.downcast_ref::<ReceiverReport>() {
+                                for report in &rr.reports {
+                                    println!(
+                                        "  {} RR fraction_lost={:.3} total_lost={}",
+                                        "◀".green(),
+                                        report.fraction_lost as f64 / 256.0,
+                                        report.total_lost
+                                    );
+                                }
+                            } else if let Some(remb) = packet.downcast_ref::<ReceiverEstimatedMaximumBitrate>() {
+                                println!("  {} REMB {} kbps", "◀".green(), (remb.bitrate / 1000.0) as u64);
+                            }
+                        }
+                    }
+                });
+            }
+
+            let answer = peer_connection.create_answer(None).await?;
+            peer_connection.set_local_description(answer.clone()).await?;
+            let _ = signal_tx.send(json!({
+                "type": "Answer",
+                "peer_id": peer_id,
+                "sdp": answer.sdp,
+            }).to_string());
+        }
+        Some("IceCandidate") => {
+            let candidate = RTCIceCandidateInit {
+                candidate: message["candidate"].as_str().unwrap_or_default().to_string(),
+                sdp_mid: message["sdp_mid"].as_str().map(String::from),
+                sdp_mline_index: message["sdp_mline_index"].as_u64().map(|i| i as u16),
+                username_fragment: None,
+            };
+            peer_connection.add_ice_candidate(candidate).await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A VP8 packet the SFU can forward: payload descriptor with the start bit, then filler
+fn synthetic_vp8_packet(sequence_number: u16, timestamp: u32) -> Packet {
+    let mut payload = vec![0u8; PUBLISH_PAYLOAD_SIZE];
+    payload[0] = 0x10;
+    Packet {
+        header: Header {
+            version: 2,
+            marker: true,
+            sequence_number,
+            timestamp,
+            ..Default::default()
+        },
+        payload: payload.into(),
+    }
+}
+
+async fn print_publisher_stats(server: &str, peer_id: &str) {
+    let url = format!("{}/sfu/stats", http_base(server));
+    let body = match http_client().get(&url).send().await {
+        Ok(resp) => resp.json::<serde_json::Value>().await.unwrap_or_default(),
+        Err(e) => {
+            println!("{} Cannot fetch stats: {}", "✗".red(), e);
+            return;
+        }
+    };
+
+    println!("\n{} (report interval {} ms)", "SFU receive stats:".bold(), body["report_interval_ms"]);
+    let publisher = body["publishers"]
+        .as_array()
+        .and_then(|publishers| publishers.iter().find(|p| p["peer_id"] == peer_id));
+    let Some(publisher) = publisher else {
+        println!("  (no stats for {})", peer_id);
+        return;
+    };
+    for track in publisher["tracks"].as_array().cloned().unwrap_or_default() {
+        println!(
+            "  {} {} - loss {:.1}%, {} lost, jitter {:.1} ms, REMB {}",
+            "●".cyan(),
+            track["kind"].as_str().unwrap_or("?"),
+            track["fraction_lost"].as_f64().unwrap_or(0.0) * 100.0,
+            track["packets_lost"],
+            track["jitter_ms"].as_f64().unwrap_or(0.0),
+            track["remb_bitrate_bps"].as_u64().map(|b| format!("{} kbps", b / 1000)).unwrap_or_else(|| "-".to_string()),
+        );
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= MB {
+        format!("{:.1} MB", bytes as f64 / MB)
+    } else {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    }
+}
+
+/// Returns the server's retry hint if `response` is a shed/reject error
+fn retry_hint(response: &serde_json::Value) -> Option<(Duration, Option<String>)> {
+    if response["type"] != "error" {
+        return None;
+    }
+    let secs = response["retry_after_secs"].as_u64()?;
+    let alternate = response["alternate_server"].as_str().map(String::from);
+    Some((Duration::from_secs(secs), alternate))
+}
+
+/// Connects, sends `msg` and waits for the first text reply.
+///
+/// If the server rejects with `retry_after_secs`, waits that long and tries again
+/// (against `alternate_server` when provided) instead of retrying immediately.
+async fn send_with_retry(
+    server: &str,
+    msg: &serde_json::Value,
+    wait: Duration,
+) -> Result<(WsStream, serde_json::Value), String> {
+    let mut url = ws_url(server);
+
+    for attempt in 0..=MAX_RETRY_ATTEMPTS {
+        let (mut ws, _) = connect(&url)
+            .await
+            .map_err(|e| format!("Cannot connect to server: {}", e))?;
+
+        ws.send(Message::Text(msg.to_string()))
+            .await
+            .map_err(|e| format!("Failed to send message: {}", e))?;
+
+        let response = match timeout(wait, ws.next()).await {
+            Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<serde_json::Value>(&text)
+                .map_err(|e| format!("Failed to parse response: {}", e))?,
+            Ok(Some(Ok(other))) => return Err(format!("Unexpected message type: {:?}", other)),
+            Ok(Some(Err(e))) => return Err(format!("Error receiving message: {}", e)),
+            Ok(None) => return Err("Connection closed by server".to_string()),
+            Err(_) => return Err("Timeout waiting for response".to_string()),
+        };
+
+        match retry_hint(&response) {
+            Some((delay, alternate)) if attempt < MAX_RETRY_ATTEMPTS => {
+                say!(
+                    "{} Server rejected request ({}), retrying in {}s",
+                    "⚠".yellow(),
+                    response["code"].as_str().unwrap_or("error"),
+                    delay.as_secs()
+                );
+                if let Some(alternate) = alternate {
+                    say!("  Using alternate server: {}", alternate);
+                    url = alternate;
+                }
+                sleep(delay).await;
+            }
+            _ => return Ok((ws, response)),
+        }
+    }
+
+    unreachable!("loop returns on the final attempt")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+struct StepResult {
+    step: String,
+    passed: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    detail: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ScenarioResult {
+    scenario: String,
+    outcome: Outcome,
+    steps: Vec<StepResult>,
+}
+
+/// Steps of one scenario, printed as they complete
+#[derive(Default)]
+struct ScenarioRun {
+    steps: Vec<StepResult>,
+}
+
+impl ScenarioRun {
+    /// Records a step from its result, handing back the value on success so
+    /// the scenario can stop at the first failed step
+    fn check<T>(&mut self, step: &str, result: Result<T, String>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                say!("  {} {}", "✓".green(), step);
+                self.push(step, true, String::new());
+                Some(value)
+            }
+            Err(e) => {
+                say!("  {} {}: {}", "✗".red(), step, e);
+                self.push(step, false, e);
+                None
+            }
+        }
+    }
+
+    fn push(&mut self, step: &str, passed: bool, detail: String) {
+        self.steps.push(StepResult {
+            step: step.to_string(),
+            passed,
+            detail,
+        });
+    }
+
+    fn finish(self, scenario: &str) -> ScenarioResult {
+        let passed = !self.steps.is_empty() && self.steps.iter().all(|step| step.passed);
+        let outcome = if passed {
+            Outcome::Passed
+        } else if skipped_when_failing(scenario) {
+            Outcome::Skipped
+        } else {
+            Outcome::Failed
+        };
+        ScenarioResult {
+            scenario: scenario.to_string(),
+            outcome,
+            steps: self.steps,
         }
     }
 }
 
-async fn validate_create_room(server: &str) -> bool {
-    let url = format!("ws://{}/sfu", server);
+/// A signaling connection that asserts on what the server sends back
+struct SignalingClient {
+    ws: WsStream,
+    /// Messages received while waiting for another type, kept for later expectations
+    backlog: VecDeque<serde_json::Value>,
+}
 
-    match connect_async(&url).await {
-        Ok((ws_stream, _)) => {
-            let (mut write, mut read) = ws_stream.split();
+impl SignalingClient {
+    async fn connect(server: &str) -> Result<Self, String> {
+        let url = ws_url(server);
+        let (ws, _) = connect(&url)
+            .await
+            .map_err(|e| format!("Cannot connect to server: {}", e))?;
+        Ok(Self { ws, backlog: VecDeque::new() })
+    }
 
-            let msg = json!({
-                "type": "CreateRoom",
-                "peer_id": "validator_proctor",
-                "name": "Validator",
-            });
+    /// Connects and sends `msg`, honoring retry hints; the first reply is
+    /// left for the next `expect`
+    async fn open(server: &str, msg: &serde_json::Value) -> Result<Self, String> {
+        let (ws, response) = send_with_retry(server, msg, STEP_TIMEOUT).await?;
+        Ok(Self { ws, backlog: VecDeque::from([response]) })
+    }
 
-            if write.send(Message::Text(msg.to_string())).await.is_err() {
-                println!("{} Failed to send message", "✗".red());
-                return false;
-            }
+    async fn send(&mut self, msg: &serde_json::Value) -> Result<(), String> {
+        self.ws
+            .send(Message::Text(msg.to_string()))
+            .await
+            .map_err(|e| format!("Failed to send message: {}", e))
+    }
 
-            match timeout(Duration::from_secs(3), read.next()).await {
-                Ok(Some(Ok(Message::Text(text)))) => {
-                    if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if response["type"] == "RoomCreated" {
-                            println!("{} Room created: {}", "✓".green(), response["room_id"]);
-                            return true;
-                        }
-                    }
-                    println!("{} Unexpected response", "✗".yellow());
-                    false
-                }
-                _ => {
-                    println!("{} No response received", "✗".red());
-                    false
-                }
+    async fn expect(&mut self, expected: &str, wait: Duration) -> Result<serde_json::Value, String> {
+        self.expect_where(expected, wait, |_| true).await
+    }
+
+    /// Waits for a message of type `expected` that satisfies `filter`. Other
+    /// messages are kept for later; a server error fails the expectation
+    /// unless an error is what is expected.
+    async fn expect_where(
+        &mut self,
+        expected: &str,
+        wait: Duration,
+        filter: impl Fn(&serde_json::Value) -> bool,
+    ) -> Result<serde_json::Value, String> {
+        let matches = |message: &serde_json::Value| message["type"] == expected && filter(message);
+        let unexpected_error = |message: &serde_json::Value| expected != "error" && message["type"] == "error";
+
+        if let Some(index) = self.backlog.iter().position(matches) {
+            return Ok(self.backlog.remove(index).unwrap_or_default());
+        }
+        if let Some(index) = self.backlog.iter().position(unexpected_error) {
+            return Err(server_error(&self.backlog.remove(index).unwrap_or_default()));
+        }
+
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            let message = match tokio::time::timeout_at(deadline, self.ws.next()).await {
+                Ok(Some(Ok(Message::Text(text)))) => serde_json::from_str::<serde_json::Value>(&text)
+                    .map_err(|e| format!("Failed to parse message: {}", e))?,
+                Ok(Some(Ok(_))) => continue,
+                Ok(Some(Err(e))) => return Err(format!("Error receiving message: {}", e)),
+                Ok(None) => return Err(format!("Connection closed while waiting for {}", expected)),
+                Err(_) => return Err(format!("No {} within {}s", expected, wait.as_secs())),
+            };
+
+            if matches(&message) {
+                return Ok(message);
             }
-        }
-        Err(e) => {
-            println!("{} Connection failed: {}", "✗".red(), e);
-            false
+            if unexpected_error(&message) {
+                return Err(server_error(&message));
+            }
+            self.backlog.push_back(message);
         }
     }
 }
 
-async fn validate_join_room(server: &str) -> bool {
-    println!("  Step 1: Creating room (proctor connects)...");
-
-    let url = format!("ws://{}/sfu", server);
-
-    // Connect proctor and keep connection alive
-    let proctor_conn = match connect_async(&url).await {
-        Ok((ws_stream, _)) => ws_stream,
-        Err(e) => {
-            println!("{} Proctor connection failed: {}", "✗".red(), e);
-            return false;
-        }
-    };
+fn server_error(message: &serde_json::Value) -> String {
+    format!(
+        "Server error ({}): {}",
+        message["code"].as_str().unwrap_or("no code"),
+        message["message"].as_str().unwrap_or_default()
+    )
+}
 
-    let (mut proctor_write, mut proctor_read) = proctor_conn.split();
+fn list_scenarios() {
+    say!("\n{}", "Available Validation Scenarios:".bold());
+    say!("\n{}", "SFU Server:".bold().cyan());
+    say!("  {} - Basic WebSocket connection test", "connection".cyan());
+    say!("  {} - Room creation flow", "create-room".cyan());
+    say!("  {} - Student join flow", "join-room".cyan());
+    say!("  {} - Multiple students joining", "multi-student".cyan());
+    say!("  {} - Invalid room join (error handling)", "invalid-room".cyan());
+    say!("\n{}", "Blockchain (Asset Hub EVM):".bold().cyan());
+    say!("  {} - Check blockchain config from server", "blockchain-status".cyan());
+    say!("  {} - Test RPC endpoint connectivity", "blockchain-rpc".cyan());
+    say!("  {} - Validate contract address format", "blockchain-contract".cyan());
+    say!("  {} - Test contract read functions", "blockchain-functions".cyan());
+    say!("\n{}", "Recording:".bold().cyan());
+    say!("  {} - Check recording config from server", "recording-status".cyan());
+    say!("\n{}", "IPFS:".bold().cyan());
+    say!("  {} - Check IPFS node connectivity", "ipfs-health".cyan());
+    say!("  {} - Upload test file to IPFS", "ipfs-upload".cyan());
+    say!("  {} - Verify MFS (Mutable File System)", "ipfs-mfs".cyan());
+    say!("\nExample: sfu-cli validate --scenario connection");
+    say!("Example: sfu-cli validate --scenario blockchain-status");
+    say!("Example: sfu-cli validate --scenario blockchain-functions");
+}
 
-    // Create room
-    let msg = json!({
-        "type": "CreateRoom",
-        "peer_id": "test_proctor_join",
-        "name": "Test Proctor",
-    });
+/// Scenarios run by `validate --all`, by group
+const SCENARIO_GROUPS: &[(&str, &[&str])] = &[
+    ("SFU Server Tests", &["connection", "create-room", "join-room", "multi-student", "invalid-room"]),
+    (
+        "Blockchain (Asset Hub EVM) Tests",
+        &["blockchain-status", "blockchain-rpc", "blockchain-contract", "blockchain-functions"],
+    ),
+    ("Recording Tests", &["recording-status"]),
+    ("IPFS Tests", &["ipfs-health", "ipfs-upload", "ipfs-mfs"]),
+];
+
+/// Scenarios that fail when their feature is disabled; that counts as skipped
+fn skipped_when_failing(scenario: &str) -> bool {
+    matches!(scenario, "blockchain-status" | "recording-status")
+}
 
-    if proctor_write.send(Message::Text(msg.to_string())).await.is_err() {
-        println!("{} Failed to send CreateRoom message", "✗".red());
-        return false;
-    }
+/// Runs one scenario; `None` for an unknown name
+async fn execute_scenario(server: &str, ipfs_url: &str, scenario: &str) -> Option<ScenarioResult> {
+    let mut run = ScenarioRun::default();
 
-    let room_id = match timeout(Duration::from_secs(3), proctor_read.next()).await {
-        Ok(Some(Ok(Message::Text(text)))) => {
-            if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                if response["type"] == "RoomCreated" {
-                    response["room_id"].as_str().map(String::from)
-                } else {
-                    println!("{} Unexpected response: {}", "✗".yellow(), text);
-                    None
-                }
-            } else {
-                None
-            }
+    // Scenarios not yet broken into steps report as a single step
+    let single_step = match scenario {
+        "connection" => {
+            validate_connection(server, &mut run).await;
+            None
         }
-        _ => {
-            println!("{} No response received for CreateRoom", "✗".red());
+        "create-room" => {
+            validate_create_room(server, &mut run).await;
             None
         }
-    };
-
-    let room_id = match room_id {
-        Some(id) => {
-            println!("  {} Room created: {}", "✓".green(), id);
-            id
+        "join-room" => {
+            validate_join_room(server, &mut run).await;
+            None
         }
-        None => {
-            println!("{} Failed to create room", "✗".red());
-            return false;
+        "multi-student" => {
+            validate_multi_student(server, &mut run).await;
+            None
         }
-    };
-
-    // Step 2: Student joins while proctor is still connected
-    println!("  Step 2: Student joining room...");
-
-    let student_conn = match connect_async(&url).await {
-        Ok((ws_stream, _)) => ws_stream,
-        Err(e) => {
-            println!("{} Student connection failed: {}", "✗".red(), e);
-            return false;
+        "invalid-room" => {
+            validate_invalid_room(server, &mut run).await;
+            None
         }
+        "blockchain-status" => Some(validate_blockchain_status(server).await),
+        "blockchain-rpc" => Some(validate_blockchain_rpc(server).await),
+        "blockchain-contract" => Some(validate_blockchain_contract(server).await),
+        "blockchain-functions" => Some(validate_blockchain_functions(server).await),
+        "recording-status" => Some(validate_recording_status(server).await),
+        "ipfs-health" => Some(validate_ipfs_health(ipfs_url).await),
+        "ipfs-upload" => Some(validate_ipfs_upload(ipfs_url).await),
+        "ipfs-mfs" => Some(validate_ipfs_mfs(ipfs_url).await),
+        _ => return None,
     };
+    if let Some(passed) = single_step {
+        run.push(scenario, passed, String::new());
+    }
 
-    let (mut student_write, mut student_read) = student_conn.split();
+    Some(run.finish(scenario))
+}
 
-    let msg = json!({
-        "type": "JoinRequest",
-        "room_id": room_id,
-        "peer_id": "test_student_join",
-        "name": "Test Student",
-        "role": "student",
-    });
+/// Runs `--scenario`; exits non-zero unless it passed or was skipped
+async fn run_scenario(server: &str, ipfs_url: &str, scenario: &str) {
+    say!("\n{} {}", "Running scenario:".bold(), scenario.cyan());
+    say!("{}", "─".repeat(60));
 
-    if student_write.send(Message::Text(msg.to_string())).await.is_err() {
-        println!("{} Failed to send join request", "✗".red());
-        return false;
+    let Some(result) = execute_scenario(server, ipfs_url, scenario).await else {
+        say!("{} Unknown scenario: {}", "✗".red(), scenario);
+        list_scenarios();
+        std::process::exit(2);
+    };
+
+    match result.outcome {
+        Outcome::Passed => say!("\n{} Scenario passed", "✓".green().bold()),
+        Outcome::Skipped => say!("\n{} Scenario skipped (feature disabled)", "○".yellow().bold()),
+        Outcome::Failed => say!("\n{} Scenario failed", "✗".red().bold()),
     }
 
-    let result = match timeout(Duration::from_secs(3), student_read.next()).await {
-        Ok(Some(Ok(Message::Text(text)))) => {
-            if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                if response["type"] == "join_request_sent" {
-                    println!("{} Join request sent successfully", "✓".green());
-                    true
-                } else {
-                    println!("{} Unexpected response: {}", "✗".yellow(), text);
-                    false
-                }
-            } else {
-                println!("{} Failed to parse response", "✗".red());
-                false
+    finish_validation(vec![result]);
+}
+
+async fn run_all_validations(server: &str, ipfs_url: &str) {
+    say!("\n{}", "Running All Validation Tests".bold().green());
+    say!("{}\n", "═".repeat(60).green());
+
+    let mut results = Vec::new();
+    for (group, scenarios) in SCENARIO_GROUPS {
+        say!("\n{}", group.bold().cyan());
+        for scenario in *scenarios {
+            say!("\n{} Testing: {}", "▶".cyan(), scenario.bold());
+            say!("{}", "─".repeat(60));
+
+            if let Some(result) = execute_scenario(server, ipfs_url, scenario).await {
+                results.push(result);
             }
+            sleep(Duration::from_millis(500)).await;
         }
-        _ => {
-            println!("{} No response received", "✗".red());
-            false
-        }
-    };
-
-    // Connections are dropped here, cleaning up proctor and student
-    result
-}
+    }
 
-async fn validate_multi_student(server: &str) -> bool {
-    println!("  Creating room for multi-student test...");
+    let count = |outcome| results.iter().filter(|result| result.outcome == outcome).count();
+    let (passed, failed, skipped) = (count(Outcome::Passed), count(Outcome::Failed), count(Outcome::Skipped));
 
-    let url = format!("ws://{}/sfu", server);
+    say!("\n{}", "═".repeat(60).green());
+    say!("{}", "Validation Summary".bold());
+    say!("{}", "═".repeat(60).green());
+    say!("  {} Passed: {}", "✓".green(), passed.to_string().green());
+    say!("  {} Failed: {}", "✗".red(), failed.to_string().red());
+    if skipped > 0 {
+        say!("  {} Skipped (disabled features): {}", "○".yellow(), skipped.to_string().yellow());
+    }
+    say!("  Total: {}", results.len());
 
-    // Connect proctor and keep connection alive
-    let proctor_conn = match connect_async(&url).await {
-        Ok((ws_stream, _)) => ws_stream,
-        Err(e) => {
-            println!("{} Proctor connection failed: {}", "✗".red(), e);
-            return false;
-        }
-    };
+    if failed == 0 {
+        say!("\n{}", "All validations passed! 🎉".green().bold());
+    } else {
+        say!("\n{}", "Some validations failed. Check output above.".yellow());
+    }
 
-    let (mut proctor_write, mut proctor_read) = proctor_conn.split();
+    finish_validation(results);
+}
 
-    // Create room
-    let msg = json!({
-        "type": "CreateRoom",
-        "peer_id": "proctor_multi",
-        "name": "Multi Test",
-    });
+/// Prints the JSON report under `--json` and exits non-zero if anything failed
+fn finish_validation(results: Vec<ScenarioResult>) {
+    let failed = results.iter().any(|result| result.outcome == Outcome::Failed);
+
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let count = |outcome| results.iter().filter(|result| result.outcome == outcome).count();
+        let report = json!({
+            "passed": count(Outcome::Passed),
+            "failed": count(Outcome::Failed),
+            "skipped": count(Outcome::Skipped),
+            "scenarios": results,
+        });
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    }
 
-    if proctor_write.send(Message::Text(msg.to_string())).await.is_err() {
-        println!("{} Failed to send CreateRoom message", "✗".red());
-        return false;
+    if failed {
+        std::process::exit(1);
     }
+}
 
-    let room_id = match timeout(Duration::from_secs(3), proctor_read.next()).await {
-        Ok(Some(Ok(Message::Text(text)))) => {
-            if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                response.get("room_id")
-                    .and_then(|v| v.as_str())
-                    .map(String::from)
-            } else {
-                None
-            }
-        }
-        _ => None,
+async fn validate_connection(server: &str, run: &mut ScenarioRun) {
+    let url = ws_url(server);
+    run.check(
+        "Open WebSocket connection",
+        connect(&url).await.map(drop).map_err(|e| format!("Connection failed: {}", e)),
+    );
+}
+
+async fn validate_create_room(server: &str, run: &mut ScenarioRun) {
+    // The proctor connection is dropped at the end, closing the room
+    let _ = open_room(server, run, "validator_proctor").await;
+}
+
+async fn validate_join_room(server: &str, run: &mut ScenarioRun) {
+    let Some((mut proctor, room_id)) = open_room(server, run, "test_proctor_join").await else {
+        return;
     };
+    let student_id = "test_student_join";
 
-    let room_id = match room_id {
-        Some(id) => {
-            println!("  {} Room created: {}", "✓".green(), id);
-            id
-        }
-        None => {
-            println!("{} Failed to create room", "✗".red());
-            return false;
-        }
+    let Some(mut student) = request_to_join(server, run, &room_id, student_id).await else {
+        return;
     };
+    if !approve_student(run, &mut proctor, &room_id, "test_proctor_join", student_id).await {
+        return;
+    }
+    if run
+        .check("Student receives join_approved", student.expect("join_approved", STEP_TIMEOUT).await)
+        .is_none()
+    {
+        return;
+    }
 
-    // Try to add 3 students while proctor stays connected
-    let mut success_count = 0;
+    let join = json!({
+        "type": "Join",
+        "room_id": room_id,
+        "peer_id": student_id,
+        "name": "Test Student",
+        "role": "student",
+    });
+    if run.check("Student sends Join", student.send(&join).await).is_none() {
+        return;
+    }
+    let offer = student
+        .expect("Offer", JOIN_TIMEOUT)
+        .await
+        .and_then(|offer| match offer["sdp"].as_str() {
+            Some(sdp) if sdp.starts_with("v=0") => Ok(()),
+            _ => Err(format!("Offer without an SDP: {}", offer)),
+        });
+    if run.check("Student receives SFU Offer", offer).is_none() {
+        return;
+    }
+    run.check("Student receives join_success", student.expect("join_success", JOIN_TIMEOUT).await);
 
-    for i in 1..=3 {
-        println!("  Connecting student {}...", i);
+    // Connections are dropped here, cleaning up proctor and student
+}
 
-        if let Ok((ws_stream, _)) = connect_async(&url).await {
-            let (mut write, mut read) = ws_stream.split();
+async fn validate_multi_student(server: &str, run: &mut ScenarioRun) {
+    let Some((mut proctor, room_id)) = open_room(server, run, "proctor_multi").await else {
+        return;
+    };
 
-            let msg = json!({
-                "type": "JoinRequest",
-                "room_id": room_id,
-                "peer_id": format!("student_multi_{}", i),
-                "name": format!("Student {}", i),
-                "role": "student",
-            });
+    let mut students = Vec::new();
+    for i in 1..=3 {
+        let student_id = format!("student_multi_{}", i);
+        let Some(student) = request_to_join(server, run, &room_id, &student_id).await else {
+            return;
+        };
+        students.push((student_id, student));
+    }
 
-            if write.send(Message::Text(msg.to_string())).await.is_ok() {
-                // Wait for response to verify join request was processed
-                if let Ok(Some(Ok(Message::Text(text)))) =
-                    timeout(Duration::from_secs(2), read.next()).await
-                {
-                    if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
-                        if response["type"] == "join_request_sent" {
-                            success_count += 1;
-                            println!("    {} Student {} join request sent", "✓".green(), i);
-                        }
-                    }
-                }
-            }
+    // Requests may reach the proctor in any order; each is matched by peer ID
+    for (student_id, _) in &students {
+        if !approve_student(run, &mut proctor, &room_id, "proctor_multi", student_id).await {
+            return;
         }
-
-        sleep(Duration::from_millis(100)).await;
     }
-
-    // Proctor connection is dropped here, cleaning up the room
-
-    if success_count == 3 {
-        println!("{} All 3 students join requests sent successfully", "✓".green());
-        true
-    } else {
-        println!(
-            "{} Only {} out of 3 students sent join requests",
-            "✗".red(),
-            success_count
+    for (student_id, student) in &mut students {
+        run.check(
+            &format!("{} receives join_approved", student_id),
+            student.expect("join_approved", STEP_TIMEOUT).await,
         );
-        false
     }
+
+    // The proctor connection is dropped here, cleaning up the room
 }
 
-async fn validate_invalid_room(server: &str) -> bool {
-    println!("  Attempting to join non-existent room...");
+async fn validate_invalid_room(server: &str, run: &mut ScenarioRun) {
+    let msg = join_request_message("999999", "invalid_test");
+    let Some(mut student) = run.check("Send JoinRequest for a non-existent room", SignalingClient::open(server, &msg).await) else {
+        return;
+    };
 
-    let url = format!("ws://{}/sfu", server);
+    let error = student
+        .expect("error", STEP_TIMEOUT)
+        .await
+        .and_then(|error| match error["code"].as_str() {
+            Some("room_not_found") => Ok(()),
+            code => Err(format!("Expected code room_not_found, got {:?}: {}", code, error)),
+        });
+    run.check("Receive room_not_found error", error);
+}
 
-    match connect_async(&url).await {
-        Ok((ws_stream, _)) => {
-            let (mut write, _) = ws_stream.split();
+fn join_request_message(room_id: &str, student_id: &str) -> serde_json::Value {
+    json!({
+        "type": "JoinRequest",
+        "room_id": room_id,
+        "peer_id": student_id,
+        "name": format!("Student {}", student_id),
+        "role": "student",
+    })
+}
 
-            let msg = json!({
-                "type": "JoinRequest",
-                "room_id": "999999",
-                "peer_id": "invalid_test",
-                "name": "Invalid Test",
-                "role": "student",
-            });
+/// Creates a room, keeping the proctor connection open
+async fn open_room(server: &str, run: &mut ScenarioRun, proctor_id: &str) -> Option<(SignalingClient, String)> {
+    let msg = json!({
+        "type": "CreateRoom",
+        "peer_id": proctor_id,
+        "name": "Validator",
+    });
+    let mut proctor = run.check("Proctor sends CreateRoom", SignalingClient::open(server, &msg).await)?;
 
-            if write.send(Message::Text(msg.to_string())).await.is_ok() {
-                println!("{} Request sent (server should handle gracefully)", "✓".green());
-                true
-            } else {
-                println!("{} Failed to send request", "✗".red());
-                false
-            }
-        }
-        Err(e) => {
-            println!("{} Connection failed: {}", "✗".red(), e);
-            false
-        }
+    let room_id = proctor
+        .expect("RoomCreated", STEP_TIMEOUT)
+        .await
+        .and_then(|created| {
+            created["room_id"]
+                .as_str()
+                .map(String::from)
+                .ok_or_else(|| format!("RoomCreated without a room_id: {}", created))
+        });
+    let room_id = run.check("Proctor receives RoomCreated", room_id)?;
+    say!("    Room: {}", room_id);
+    Some((proctor, room_id))
+}
+
+/// Sends a student's JoinRequest and waits for the server to accept it
+async fn request_to_join(server: &str, run: &mut ScenarioRun, room_id: &str, student_id: &str) -> Option<SignalingClient> {
+    let msg = join_request_message(room_id, student_id);
+    let mut student = run.check(
+        &format!("{} sends JoinRequest", student_id),
+        SignalingClient::open(server, &msg).await,
+    )?;
+    run.check(
+        &format!("{} receives join_request_sent", student_id),
+        student.expect("join_request_sent", STEP_TIMEOUT).await,
+    )?;
+    Some(student)
+}
+
+/// Waits for the student's request on the proctor socket and approves it
+async fn approve_student(
+    run: &mut ScenarioRun,
+    proctor: &mut SignalingClient,
+    room_id: &str,
+    proctor_id: &str,
+    student_id: &str,
+) -> bool {
+    let forwarded = proctor
+        .expect_where("JoinRequest", STEP_TIMEOUT, |request| request["peer_id"] == student_id)
+        .await;
+    if run.check(&format!("Proctor receives JoinRequest from {}", student_id), forwarded).is_none() {
+        return false;
     }
+
+    let approval = json!({
+        "type": "JoinResponse",
+        "room_id": room_id,
+        "peer_id": proctor_id,
+        "approved": true,
+        "requester_peer_id": student_id,
+    });
+    run.check(&format!("Proctor approves {}", student_id), proctor.send(&approval).await).is_some()
 }
 
 // ============================================================================
@@ -878,10 +2051,10 @@ async fn validate_invalid_room(server: &str) -> bool {
 // ============================================================================
 
 async fn validate_blockchain_status(server: &str) -> bool {
-    println!("  Checking blockchain configuration...");
+    say!("  Checking blockchain configuration...");
 
-    let url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     match client.get(&url).send().await {
         Ok(response) => {
@@ -891,50 +2064,50 @@ async fn validate_blockchain_status(server: &str) -> bool {
                     let enabled = blockchain["enabled"].as_bool().unwrap_or(false);
 
                     if !enabled {
-                        println!("{} Blockchain integration is disabled", "○".yellow());
-                        println!("  Set ASSET_HUB_ENABLED=true to enable");
+                        say!("{} Blockchain integration is disabled", "○".yellow());
+                        say!("  Set ASSET_HUB_ENABLED=true to enable");
                         return false;
                     }
 
-                    println!("{} Blockchain integration is enabled", "✓".green());
+                    say!("{} Blockchain integration is enabled", "✓".green());
                     if let Some(rpc_url) = blockchain["rpc_url"].as_str() {
-                        println!("  RPC URL: {}", rpc_url);
+                        say!("  RPC URL: {}", rpc_url);
                     }
                     if let Some(contract) = blockchain["contract_address"].as_str() {
-                        println!("  Contract: {}", contract);
+                        say!("  Contract: {}", contract);
                     }
                     if let Some(gas_limit) = blockchain["gas_limit"].as_str() {
-                        println!("  Gas Limit: {}", gas_limit);
+                        say!("  Gas Limit: {}", gas_limit);
                     }
                     return true;
                 }
-                println!("{} Could not parse config response", "✗".red());
+                say!("{} Could not parse config response", "✗".red());
                 false
             } else {
-                println!("{} Config endpoint returned error: {}", "✗".red(), response.status());
+                say!("{} Config endpoint returned error: {}", "✗".red(), response.status());
                 false
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            say!("{} Cannot connect to server: {}", "✗".red(), e);
             false
         }
     }
 }
 
 async fn validate_blockchain_rpc(server: &str) -> bool {
-    println!("  Testing blockchain RPC connectivity...");
+    say!("  Testing blockchain RPC connectivity...");
 
     // First get the RPC URL from server config
-    let config_url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let config_url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     let rpc_url = match client.get(&config_url).send().await {
         Ok(response) => {
             if let Ok(body) = response.json::<serde_json::Value>().await {
                 let blockchain = &body["blockchain"];
                 if !blockchain["enabled"].as_bool().unwrap_or(false) {
-                    println!("{} Blockchain is disabled, skipping RPC test", "○".yellow());
+                    say!("{} Blockchain is disabled, skipping RPC test", "○".yellow());
                     return false;
                 }
                 blockchain["rpc_url"].as_str().map(String::from)
@@ -948,7 +2121,7 @@ async fn validate_blockchain_rpc(server: &str) -> bool {
     let rpc_url = match rpc_url {
         Some(url) => url,
         None => {
-            println!("{} Could not get RPC URL from server config", "✗".red());
+            say!("{} Could not get RPC URL from server config", "✗".red());
             return false;
         }
     };
@@ -975,9 +2148,9 @@ async fn validate_blockchain_rpc(server: &str) -> bool {
                         // Parse chain ID from hex
                         let chain_id = u64::from_str_radix(result.trim_start_matches("0x"), 16)
                             .unwrap_or(0);
-                        println!("{} RPC endpoint is accessible", "✓".green());
-                        println!("  URL: {}", rpc_url);
-                        println!("  Chain ID: {} (0x{:x})", chain_id, chain_id);
+                        say!("{} RPC endpoint is accessible", "✓".green());
+                        say!("  URL: {}", rpc_url);
+                        say!("  Chain ID: {} (0x{:x})", chain_id, chain_id);
 
                         // Identify known networks
                         let network_name = match chain_id {
@@ -987,37 +2160,37 @@ async fn validate_blockchain_rpc(server: &str) -> bool {
                             420420422 => "Paseo Asset Hub",
                             _ => "Unknown network",
                         };
-                        println!("  Network: {}", network_name);
+                        say!("  Network: {}", network_name);
                         return true;
                     }
                 }
-                println!("{} RPC responded but couldn't parse chain ID", "✗".yellow());
+                say!("{} RPC responded but couldn't parse chain ID", "✗".yellow());
                 false
             } else {
-                println!("{} RPC returned error: {}", "✗".red(), response.status());
+                say!("{} RPC returned error: {}", "✗".red(), response.status());
                 false
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to RPC: {}", "✗".red(), e);
-            println!("  URL: {}", rpc_url);
+            say!("{} Cannot connect to RPC: {}", "✗".red(), e);
+            say!("  URL: {}", rpc_url);
             false
         }
     }
 }
 
 async fn validate_blockchain_contract(server: &str) -> bool {
-    println!("  Validating contract address format...");
+    say!("  Validating contract address format...");
 
-    let config_url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let config_url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     match client.get(&config_url).send().await {
         Ok(response) => {
             if let Ok(body) = response.json::<serde_json::Value>().await {
                 let blockchain = &body["blockchain"];
                 if !blockchain["enabled"].as_bool().unwrap_or(false) {
-                    println!("{} Blockchain is disabled, skipping contract validation", "○".yellow());
+                    say!("{} Blockchain is disabled, skipping contract validation", "○".yellow());
                     return false;
                 }
 
@@ -1028,8 +2201,8 @@ async fn validate_blockchain_contract(server: &str) -> bool {
                         && contract_address[2..].chars().all(|c| c.is_ascii_hexdigit());
 
                     if is_valid {
-                        println!("{} Contract address is valid", "✓".green());
-                        println!("  Address: {}", contract_address);
+                        say!("{} Contract address is valid", "✓".green());
+                        say!("  Address: {}", contract_address);
 
                         // Get RPC URL to check if contract has code
                         if let Some(rpc_url) = blockchain["rpc_url"].as_str() {
@@ -1050,10 +2223,10 @@ async fn validate_blockchain_contract(server: &str) -> bool {
                                 if let Ok(rpc_body) = rpc_response.json::<serde_json::Value>().await {
                                     if let Some(code) = rpc_body["result"].as_str() {
                                         if code != "0x" && code.len() > 2 {
-                                            println!("{} Contract has deployed code", "✓".green());
-                                            println!("  Code size: {} bytes", (code.len() - 2) / 2);
+                                            say!("{} Contract has deployed code", "✓".green());
+                                            say!("  Code size: {} bytes", (code.len() - 2) / 2);
                                         } else {
-                                            println!("{} No code at contract address (not deployed?)", "✗".yellow());
+                                            say!("{} No code at contract address (not deployed?)", "✗".yellow());
                                         }
                                     }
                                 }
@@ -1061,40 +2234,40 @@ async fn validate_blockchain_contract(server: &str) -> bool {
                         }
                         return true;
                     } else {
-                        println!("{} Invalid contract address format", "✗".red());
-                        println!("  Address: {}", contract_address);
-                        println!("  Expected: 0x followed by 40 hex characters");
+                        say!("{} Invalid contract address format", "✗".red());
+                        say!("  Address: {}", contract_address);
+                        say!("  Expected: 0x followed by 40 hex characters");
                         return false;
                     }
                 } else {
-                    println!("{} No contract address configured", "✗".red());
+                    say!("{} No contract address configured", "✗".red());
                     false
                 }
             } else {
-                println!("{} Could not parse config response", "✗".red());
+                say!("{} Could not parse config response", "✗".red());
                 false
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            say!("{} Cannot connect to server: {}", "✗".red(), e);
             false
         }
     }
 }
 
 async fn validate_blockchain_functions(server: &str) -> bool {
-    println!("  Testing blockchain flow via WebSocket...");
+    say!("  Testing blockchain flow via WebSocket...");
 
     // First check if blockchain is enabled
-    let config_url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let config_url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     let (rpc_url, contract_address) = match client.get(&config_url).send().await {
         Ok(response) => {
             if let Ok(body) = response.json::<serde_json::Value>().await {
                 let blockchain = &body["blockchain"];
                 if !blockchain["enabled"].as_bool().unwrap_or(false) {
-                    println!("{} Blockchain is disabled, skipping flow test", "○".yellow());
+                    say!("{} Blockchain is disabled, skipping flow test", "○".yellow());
                     return false;
                 }
                 let rpc = blockchain["rpc_url"].as_str().map(String::from);
@@ -1102,17 +2275,17 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                 match (rpc, contract) {
                     (Some(r), Some(c)) => (r, c),
                     _ => {
-                        println!("{} Missing RPC URL or contract address", "✗".red());
+                        say!("{} Missing RPC URL or contract address", "✗".red());
                         return false;
                     }
                 }
             } else {
-                println!("{} Could not parse config", "✗".red());
+                say!("{} Could not parse config", "✗".red());
                 return false;
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            say!("{} Cannot connect to server: {}", "✗".red(), e);
             return false;
         }
     };
@@ -1120,12 +2293,12 @@ async fn validate_blockchain_functions(server: &str) -> bool {
     // Use a known test wallet address for validation
     let test_wallet = "0xD1dcb600264d02933796f01b87A76e6A980Ea6e1";
 
-    println!("\n  Step 1: Creating room with wallet address via WebSocket...");
-    println!("    Wallet: {}", test_wallet);
+    say!("\n  Step 1: Creating room with wallet address via WebSocket...");
+    say!("    Wallet: {}", test_wallet);
 
-    let url = format!("ws://{}/sfu", server);
+    let url = ws_url(server);
 
-    match connect_async(&url).await {
+    match connect(&url).await {
         Ok((ws_stream, _)) => {
             let (mut write, mut read) = ws_stream.split();
 
@@ -1138,7 +2311,7 @@ async fn validate_blockchain_functions(server: &str) -> bool {
             });
 
             if write.send(Message::Text(msg.to_string())).await.is_err() {
-                println!("  {} Failed to send CreateRoom message", "✗".red());
+                say!("  {} Failed to send CreateRoom message", "✗".red());
                 return false;
             }
 
@@ -1148,11 +2321,11 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                     if let Ok(response) = serde_json::from_str::<serde_json::Value>(&text) {
                         if response["type"] == "RoomCreated" {
                             let rid = response["room_id"].as_str().unwrap_or("").to_string();
-                            println!("  {} Room created: {}", "✓".green(), rid);
-                            println!("    (Server emitted ChainEvent::RoomCreated to blockchain queue)");
+                            say!("  {} Room created: {}", "✓".green(), rid);
+                            say!("    (Server emitted ChainEvent::RoomCreated to blockchain queue)");
                             Some(rid)
                         } else {
-                            println!("  {} Unexpected response: {}", "✗".yellow(), response["type"]);
+                            say!("  {} Unexpected response: {}", "✗".yellow(), response["type"]);
                             None
                         }
                     } else {
@@ -1160,7 +2333,7 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                     }
                 }
                 _ => {
-                    println!("  {} No response received", "✗".red());
+                    say!("  {} No response received", "✗".red());
                     None
                 }
             };
@@ -1170,18 +2343,18 @@ async fn validate_blockchain_functions(server: &str) -> bool {
             }
 
             // Give blockchain queue time to process
-            println!("\n  Step 2: Waiting for blockchain transaction to be submitted...");
-            println!("    (Transactions are queued and submitted asynchronously)");
+            say!("\n  Step 2: Waiting for blockchain transaction to be submitted...");
+            say!("    (Transactions are queued and submitted asynchronously)");
             sleep(Duration::from_secs(3)).await;
 
             // Verify on-chain by querying contract read functions
-            println!("\n  Step 3: Verifying contract read functions...");
+            say!("\n  Step 3: Verifying contract read functions...");
 
             let mut all_passed = true;
 
             // Test 1: getTotalExamResults()
             // Function selector: keccak256("getTotalExamResults()")[:4] = 0x3c445589
-            println!("\n    Testing getTotalExamResults()...");
+            say!("\n    Testing getTotalExamResults()...");
             let call_data = "0x3c445589";
 
             let payload = serde_json::json!({
@@ -1207,20 +2380,20 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                             if result.len() >= 2 {
                                 let total = u64::from_str_radix(result.trim_start_matches("0x"), 16)
                                     .unwrap_or(0);
-                                println!("    {} getTotalExamResults() = {}", "✓".green(), total);
+                                say!("    {} getTotalExamResults() = {}", "✓".green(), total);
                             } else {
-                                println!("    {} getTotalExamResults() returned empty", "✗".yellow());
+                                say!("    {} getTotalExamResults() returned empty", "✗".yellow());
                                 all_passed = false;
                             }
                         } else if let Some(error) = body["error"].as_object() {
                             let msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown");
-                            println!("    {} getTotalExamResults() failed: {}", "✗".red(), msg);
+                            say!("    {} getTotalExamResults() failed: {}", "✗".red(), msg);
                             all_passed = false;
                         }
                     }
                 }
                 Err(e) => {
-                    println!("    {} RPC call failed: {}", "✗".red(), e);
+                    say!("    {} RPC call failed: {}", "✗".red(), e);
                     all_passed = false;
                 }
             }
@@ -1232,7 +2405,7 @@ async fn validate_blockchain_functions(server: &str) -> bool {
             let padded_address = format!("{:0>64}", wallet_no_prefix.to_lowercase());
             let call_data = format!("0xd0fb2626{}", padded_address);
 
-            println!("\n    Testing getParticipantExamResultIds({})...", test_wallet);
+            say!("\n    Testing getParticipantExamResultIds({})...", test_wallet);
 
             let payload = serde_json::json!({
                 "jsonrpc": "2.0",
@@ -1262,23 +2435,23 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                                     // Get array length at offset 64-128 (second 32-byte word)
                                     let len_hex = &data[64..128];
                                     let array_len = u64::from_str_radix(len_hex, 16).unwrap_or(0);
-                                    println!("    {} getParticipantExamResultIds() returned {} result(s)", "✓".green(), array_len);
+                                    say!("    {} getParticipantExamResultIds() returned {} result(s)", "✓".green(), array_len);
                                 } else {
-                                    println!("    {} getParticipantExamResultIds() returned empty array", "✓".green());
+                                    say!("    {} getParticipantExamResultIds() returned empty array", "✓".green());
                                 }
                             } else {
-                                println!("    {} getParticipantExamResultIds() returned empty", "✗".yellow());
+                                say!("    {} getParticipantExamResultIds() returned empty", "✗".yellow());
                                 all_passed = false;
                             }
                         } else if let Some(error) = body["error"].as_object() {
                             let msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown");
-                            println!("    {} getParticipantExamResultIds() failed: {}", "✗".red(), msg);
+                            say!("    {} getParticipantExamResultIds() failed: {}", "✗".red(), msg);
                             all_passed = false;
                         }
                     }
                 }
                 Err(e) => {
-                    println!("    {} RPC call failed: {}", "✗".red(), e);
+                    say!("    {} RPC call failed: {}", "✗".red(), e);
                     all_passed = false;
                 }
             }
@@ -1301,7 +2474,7 @@ async fn validate_blockchain_functions(server: &str) -> bool {
             );
             let call_data = format!("0xad10faf4{}", encoded_string);
 
-            println!("\n    Testing getEventCount(\"{}\")...", room_id);
+            say!("\n    Testing getEventCount(\"{}\")...", room_id);
 
             let payload = serde_json::json!({
                 "jsonrpc": "2.0",
@@ -1326,33 +2499,33 @@ async fn validate_blockchain_functions(server: &str) -> bool {
                             if result.len() >= 2 {
                                 let count = u64::from_str_radix(result.trim_start_matches("0x"), 16)
                                     .unwrap_or(0);
-                                println!("    {} getEventCount() = {}", "✓".green(), count);
+                                say!("    {} getEventCount() = {}", "✓".green(), count);
                             } else {
-                                println!("    {} getEventCount() returned empty", "✗".yellow());
+                                say!("    {} getEventCount() returned empty", "✗".yellow());
                             }
                         } else if let Some(error) = body["error"].as_object() {
                             let msg = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown");
                             // This might fail if the room hasn't been recorded on-chain yet
-                            println!("    {} getEventCount() failed: {} (room may not be on-chain yet)", "○".yellow(), msg);
+                            say!("    {} getEventCount() failed: {} (room may not be on-chain yet)", "○".yellow(), msg);
                         }
                     }
                 }
                 Err(e) => {
-                    println!("    {} RPC call failed: {}", "✗".red(), e);
+                    say!("    {} RPC call failed: {}", "✗".red(), e);
                 }
             }
 
             if all_passed {
-                println!("\n{} Blockchain flow test completed successfully", "✓".green());
+                say!("\n{} Blockchain flow test completed successfully", "✓".green());
             } else {
-                println!("\n{} Blockchain flow test completed with some failures", "✗".yellow());
+                say!("\n{} Blockchain flow test completed with some failures", "✗".yellow());
             }
-            println!("  The CreateRoom with wallet triggered the on-chain recording flow.");
-            println!("  Check server logs for transaction details.");
+            say!("  The CreateRoom with wallet triggered the on-chain recording flow.");
+            say!("  Check server logs for transaction details.");
             all_passed
         }
         Err(e) => {
-            println!("{} WebSocket connection failed: {}", "✗".red(), e);
+            say!("{} WebSocket connection failed: {}", "✗".red(), e);
             false
         }
     }
@@ -1363,10 +2536,10 @@ async fn validate_blockchain_functions(server: &str) -> bool {
 // ============================================================================
 
 async fn validate_recording_status(server: &str) -> bool {
-    println!("  Fetching recording configuration from server...");
+    say!("  Fetching recording configuration from server...");
 
-    let url = format!("http://{}/sfu/config", server);
-    let client = reqwest::Client::new();
+    let url = format!("{}/sfu/config", http_base(server));
+    let client = http_client();
 
     match client.get(&url).send().await {
         Ok(response) => {
@@ -1376,41 +2549,41 @@ async fn validate_recording_status(server: &str) -> bool {
                     let enabled = recording["enabled"].as_bool().unwrap_or(false);
 
                     if enabled {
-                        println!("{} Recording is enabled", "✓".green());
+                        say!("{} Recording is enabled", "✓".green());
                         if let Some(output_dir) = recording["output_dir"].as_str() {
-                            println!("  Output directory: {}", output_dir);
+                            say!("  Output directory: {}", output_dir);
                         }
                         if let Some(format) = recording["format"].as_str() {
-                            println!("  Format: {}", format);
+                            say!("  Format: {}", format);
                         }
 
                         // Also check IPFS config since recordings are stored in IPFS
                         let ipfs = &body["ipfs"];
                         if ipfs["enabled"].as_bool().unwrap_or(false) {
-                            println!("{} IPFS storage is enabled for recordings", "✓".green());
+                            say!("{} IPFS storage is enabled for recordings", "✓".green());
                             if let Some(api_url) = ipfs["api_url"].as_str() {
-                                println!("  IPFS API: {}", api_url);
+                                say!("  IPFS API: {}", api_url);
                             }
                         } else {
-                            println!("{} IPFS is disabled (recordings may not be stored)", "○".yellow());
+                            say!("{} IPFS is disabled (recordings may not be stored)", "○".yellow());
                         }
 
                         return true;
                     } else {
-                        println!("{} Recording is disabled", "○".yellow());
-                        println!("  Set RECORDING_ENABLED=true to enable");
+                        say!("{} Recording is disabled", "○".yellow());
+                        say!("  Set RECORDING_ENABLED=true to enable");
                         return false;
                     }
                 }
-                println!("{} Could not parse config response", "✗".red());
+                say!("{} Could not parse config response", "✗".red());
                 false
             } else {
-                println!("{} Config endpoint returned error: {}", "✗".red(), response.status());
+                say!("{} Config endpoint returned error: {}", "✗".red(), response.status());
                 false
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to server: {}", "✗".red(), e);
+            say!("{} Cannot connect to server: {}", "✗".red(), e);
             false
         }
     }
@@ -1421,7 +2594,7 @@ async fn validate_recording_status(server: &str) -> bool {
 // ============================================================================
 
 async fn validate_ipfs_health(ipfs_url: &str) -> bool {
-    println!("  Checking IPFS node connectivity...");
+    say!("  Checking IPFS node connectivity...");
 
     let client = reqwest::Client::new();
     let version_url = format!("{}/api/v0/version", ipfs_url);
@@ -1431,27 +2604,27 @@ async fn validate_ipfs_health(ipfs_url: &str) -> bool {
             if response.status().is_success() {
                 if let Ok(body) = response.json::<serde_json::Value>().await {
                     let version = body["Version"].as_str().unwrap_or("unknown");
-                    println!("{} IPFS node is accessible", "✓".green());
-                    println!("  Version: {}", version);
+                    say!("{} IPFS node is accessible", "✓".green());
+                    say!("  Version: {}", version);
                     return true;
                 }
-                println!("{} IPFS node responded but couldn't parse version", "✓".green());
+                say!("{} IPFS node responded but couldn't parse version", "✓".green());
                 true
             } else {
-                println!("{} IPFS API returned error: {}", "✗".red(), response.status());
+                say!("{} IPFS API returned error: {}", "✗".red(), response.status());
                 false
             }
         }
         Err(e) => {
-            println!("{} Cannot connect to IPFS: {}", "✗".red(), e);
-            println!("  Make sure IPFS is running at {}", ipfs_url);
+            say!("{} Cannot connect to IPFS: {}", "✗".red(), e);
+            say!("  Make sure IPFS is running at {}", ipfs_url);
             false
         }
     }
 }
 
 async fn validate_ipfs_upload(ipfs_url: &str) -> bool {
-    println!("  Testing IPFS file upload...");
+    say!("  Testing IPFS file upload...");
 
     let client = reqwest::Client::new();
     let add_url = format!("{}/api/v0/add", ipfs_url);
@@ -1477,28 +2650,28 @@ async fn validate_ipfs_upload(ipfs_url: &str) -> bool {
                 if let Ok(body) = response.json::<serde_json::Value>().await {
                     let hash = body["Hash"].as_str().unwrap_or("unknown");
                     let size = body["Size"].as_str().unwrap_or("unknown");
-                    println!("{} File uploaded successfully", "✓".green());
-                    println!("  CID: {}", hash);
-                    println!("  Size: {} bytes", size);
+                    say!("{} File uploaded successfully", "✓".green());
+                    say!("  CID: {}", hash);
+                    say!("  Size: {} bytes", size);
                     return true;
                 }
-                println!("{} Upload succeeded but couldn't parse response", "✗".yellow());
+                say!("{} Upload succeeded but couldn't parse response", "✗".yellow());
                 false
             } else {
                 let error = response.text().await.unwrap_or_default();
-                println!("{} Upload failed: {}", "✗".red(), error);
+                say!("{} Upload failed: {}", "✗".red(), error);
                 false
             }
         }
         Err(e) => {
-            println!("{} Upload request failed: {}", "✗".red(), e);
+            say!("{} Upload request failed: {}", "✗".red(), e);
             false
         }
     }
 }
 
 async fn validate_ipfs_mfs(ipfs_url: &str) -> bool {
-    println!("  Testing IPFS MFS (Mutable File System)...");
+    say!("  Testing IPFS MFS (Mutable File System)...");
 
     let client = reqwest::Client::new();
 
@@ -1514,14 +2687,14 @@ async fn validate_ipfs_mfs(ipfs_url: &str) -> bool {
             if !response.status().is_success() {
                 let error = response.text().await.unwrap_or_default();
                 if !error.contains("already has entry") && !error.is_empty() {
-                    println!("{} Failed to create MFS directory: {}", "✗".red(), error);
+                    say!("{} Failed to create MFS directory: {}", "✗".red(), error);
                     return false;
                 }
             }
-            println!("  {} Created test directory: {}", "✓".green(), test_dir);
+            say!("  {} Created test directory: {}", "✓".green(), test_dir);
         }
         Err(e) => {
-            println!("{} MFS mkdir request failed: {}", "✗".red(), e);
+            say!("{} MFS mkdir request failed: {}", "✗".red(), e);
             return false;
         }
     }
@@ -1534,28 +2707,28 @@ async fn validate_ipfs_mfs(ipfs_url: &str) -> bool {
                 if let Ok(body) = response.json::<serde_json::Value>().await {
                     let entries = body["Entries"].as_array();
                     let count = entries.map(|e| e.len()).unwrap_or(0);
-                    println!("  {} MFS listing successful ({} entries in root)", "✓".green(), count);
+                    say!("  {} MFS listing successful ({} entries in root)", "✓".green(), count);
 
                     if let Some(entries) = entries {
                         let has_recordings = entries.iter().any(|e| {
                             e["Name"].as_str() == Some("recordings")
                         });
                         if has_recordings {
-                            println!("  {} Found /recordings directory", "✓".green());
+                            say!("  {} Found /recordings directory", "✓".green());
                         }
                     }
                     return true;
                 }
-                println!("{} MFS listing succeeded but couldn't parse response", "✗".yellow());
+                say!("{} MFS listing succeeded but couldn't parse response", "✗".yellow());
                 false
             } else {
                 let error = response.text().await.unwrap_or_default();
-                println!("{} MFS listing failed: {}", "✗".red(), error);
+                say!("{} MFS listing failed: {}", "✗".red(), error);
                 false
             }
         }
         Err(e) => {
-            println!("{} MFS ls request failed: {}", "✗".red(), e);
+            say!("{} MFS ls request failed: {}", "✗".red(), e);
             false
         }
     }
@@ -1566,9 +2739,9 @@ async fn interactive_mode(server: &str) {
     println!("{}", "═".repeat(60).green());
     println!("Type {} for help, {} to quit\n", "help".cyan(), "quit".cyan());
 
-    let url = format!("ws://{}/sfu", server);
+    let url = ws_url(server);
 
-    match connect_async(&url).await {
+    match connect(&url).await {
         Ok((ws_stream, _)) => {
             println!("{} Connected to server", "✓".green());
 
@@ -1630,6 +2803,90 @@ async fn interactive_mode(server: &str) {
     }
 }
 
+/// Runs an interactive-mode script on one connection, stopping at the first
+/// failed expectation. Lines starting with `#` are comments.
+async fn run_script(server: &str, path: &Path) -> bool {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            println!("{} Cannot read {}: {}", "✗".red(), path.display(), e);
+            return false;
+        }
+    };
+    let mut client = match SignalingClient::connect(server).await {
+        Ok(client) => client,
+        Err(e) => {
+            println!("{} {}", "✗".red(), e);
+            return false;
+        }
+    };
+
+    // Fields of expected messages, for `${field}` in later lines
+    let mut fields: HashMap<String, String> = HashMap::new();
+
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_no = index + 1;
+
+        if let Some(expectation) = line.strip_prefix("expect ") {
+            let mut parts = expectation.split_whitespace();
+            let expected = parts.next().unwrap_or_default();
+            let wait = match parts.next().map(str::parse::<u64>) {
+                None => STEP_TIMEOUT,
+                Some(Ok(secs)) => Duration::from_secs(secs),
+                Some(Err(_)) => {
+                    println!("{} Line {}: timeout must be whole seconds", "✗".red(), line_no);
+                    return false;
+                }
+            };
+
+            match client.expect(expected, wait).await {
+                Ok(message) => {
+                    println!("{} Line {}: received {}", "✓".green(), line_no, expected);
+                    if let Some(object) = message.as_object() {
+                        for (key, value) in object {
+                            let value = match value {
+                                serde_json::Value::String(s) => s.clone(),
+                                serde_json::Value::Number(n) => n.to_string(),
+                                serde_json::Value::Bool(b) => b.to_string(),
+                                _ => continue,
+                            };
+                            fields.insert(key.clone(), value);
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("{} Line {}: expected {}: {}", "✗".red(), line_no, expected, e);
+                    return false;
+                }
+            }
+            continue;
+        }
+
+        let line = fields
+            .iter()
+            .fold(line.to_string(), |line, (key, value)| line.replace(&format!("${{{}}}", key), value));
+        let message = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                println!("{} Line {}: invalid JSON: {}", "✗".red(), line_no, e);
+                return false;
+            }
+        };
+        if let Err(e) = client.send(&message).await {
+            println!("{} Line {}: {}", "✗".red(), line_no, e);
+            return false;
+        }
+        println!("{} Line {}: sent {}", "►".cyan(), line_no, message["type"].as_str().unwrap_or("message"));
+    }
+
+    println!("{} Script completed", "✓".green().bold());
+    true
+}
+
 fn print_interactive_help() {
     println!("\n{}", "Interactive Mode Commands".bold());
     println!("{}", "─".repeat(60));
@@ -1652,5 +2909,128 @@ fn print_interactive_help() {
     println!(r#"  {{"type":"IceCandidate","peer_id":"student1","candidate":"candidate:...","sdp_mid":"0","sdp_mline_index":0}}"#);
 
     println!("\n{}: quit, exit", "Commands".bold());
+    println!("\n{}", "Scripts (sfu-cli interactive --script FILE):".bold());
+    println!("  One JSON message per line, or {} to wait for a reply.", "expect <type> [timeout_secs]".cyan());
+    println!("  {} is replaced by that field of the last expected message.", "${field}".cyan());
     println!();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use warp::Filter;
+
+    const CHUNK_SIZE: usize = 16;
+    const FILE: &str = "peer_1_100.webm";
+
+    /// Recording served by a stand-in for the server's download endpoints
+    struct MockRecording {
+        data: Vec<u8>,
+        sha256: String,
+        /// Chunks whose next transfer arrives with a flipped byte
+        corrupt_once: Mutex<HashSet<usize>>,
+        served: Mutex<Vec<usize>>,
+    }
+
+    impl MockRecording {
+        fn new(len: usize) -> Arc<Self> {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 % 251) as u8).collect();
+            Arc::new(Self {
+                sha256: sha256_hex(&data),
+                data,
+                corrupt_once: Mutex::new(HashSet::new()),
+                served: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn chunk(&self, index: usize) -> &[u8] {
+            self.data.chunks(CHUNK_SIZE).nth(index).unwrap()
+        }
+    }
+
+    async fn serve(recording: Arc<MockRecording>) -> String {
+        let listed = recording.clone();
+        let manifest = warp::path!("sfu" / "recordings" / String / String / "manifest").map(move |_: String, _: String| {
+            warp::reply::json(&json!({
+                "file": FILE,
+                "size": listed.data.len(),
+                "sha256": listed.sha256,
+                "chunk_size": CHUNK_SIZE,
+                "chunks": listed.data.chunks(CHUNK_SIZE).map(sha256_hex).collect::<Vec<_>>(),
+            }))
+        });
+        let chunk = warp::path!("sfu" / "recordings" / String / String / "chunk" / usize).map(
+            move |_: String, _: String, index: usize| {
+                recording.served.lock().unwrap().push(index);
+                let mut bytes = recording.chunk(index).to_vec();
+                if recording.corrupt_once.lock().unwrap().remove(&index) {
+                    bytes[0] ^= 0xff;
+                }
+                warp::reply::with_header(bytes, CHUNK_SHA256_HEADER, sha256_hex(recording.chunk(index)))
+            },
+        );
+
+        let (addr, server) = warp::serve(manifest.or(chunk)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr.to_string()
+    }
+
+    fn temp_output(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-cli-download-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(FILE)
+    }
+
+    #[tokio::test]
+    async fn test_resume_refetches_chunks_corrupted_mid_download() {
+        let recording = MockRecording::new(150);
+        let server = serve(recording.clone()).await;
+        let output = temp_output("resume");
+
+        // An earlier attempt stopped after 5 chunks, and chunk 2 got corrupted on disk
+        let mut partial = recording.data[..5 * CHUNK_SIZE].to_vec();
+        partial[2 * CHUNK_SIZE + 3] ^= 0xff;
+        std::fs::write(partial_path(&output), partial).unwrap();
+
+        assert!(download_recording(&server, "room-1", FILE, &output, true, None).await);
+        assert_eq!(std::fs::read(&output).unwrap(), recording.data);
+        assert_eq!(*recording.served.lock().unwrap(), vec![2, 5, 6, 7, 8, 9]);
+        assert!(!partial_path(&output).exists());
+
+        let _ = std::fs::remove_dir_all(output.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_corrupted_in_transit_is_fetched_again() {
+        let recording = MockRecording::new(100);
+        recording.corrupt_once.lock().unwrap().insert(3);
+        let server = serve(recording.clone()).await;
+        let output = temp_output("transit");
+
+        // Without --resume a leftover partial download is not trusted
+        std::fs::write(partial_path(&output), &recording.data).unwrap();
+
+        assert!(download_recording(&server, "room-1", FILE, &output, false, None).await);
+        assert_eq!(std::fs::read(&output).unwrap(), recording.data);
+        assert_eq!(*recording.served.lock().unwrap(), vec![0, 1, 2, 3, 3, 4, 5, 6]);
+
+        let _ = std::fs::remove_dir_all(output.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_full_hash_mismatch_fails_the_download() {
+        let mut recording = MockRecording::new(100);
+        Arc::get_mut(&mut recording).unwrap().sha256 = sha256_hex(b"something else");
+        let server = serve(recording.clone()).await;
+        let output = temp_output("mismatch");
+
+        assert!(!download_recording(&server, "room-1", FILE, &output, false, None).await);
+        assert!(!output.exists());
+        assert!(!partial_path(&output).exists());
+
+        let _ = std::fs::remove_dir_all(output.parent().unwrap());
+    }
+}
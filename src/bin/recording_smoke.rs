@@ -0,0 +1,418 @@
+// Recording smoke test
+// Records media generated by GStreamer through the server's recording pipeline,
+// then probes the files it wrote. Exits non-zero with a report on any failure.
+
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use colored::*;
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app::AppSink;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tokio::time::{sleep_until, Duration, Instant};
+
+// The recording pipeline and what it is built from, as compiled into the server
+#[allow(dead_code)]
+#[path = "../error.rs"]
+mod error;
+
+mod config {
+    #[allow(dead_code)]
+    #[path = "../../config/env.rs"]
+    pub mod env;
+}
+
+#[allow(dead_code)]
+mod recording {
+    #[path = "../../recording/chapters.rs"]
+    mod chapters;
+    #[path = "../../recording/clock.rs"]
+    mod clock;
+    #[path = "../../recording/codec.rs"]
+    pub mod codec;
+    #[path = "../../recording/finalize.rs"]
+    mod finalize;
+    #[path = "../../recording/gaps.rs"]
+    pub mod gaps;
+    #[path = "../../recording/keyframes.rs"]
+    mod keyframes;
+    #[path = "../../recording/permissions.rs"]
+    mod permissions;
+    #[path = "../../recording/pipeline.rs"]
+    pub mod pipeline;
+    #[path = "../../recording/rtpdump.rs"]
+    pub mod rtpdump;
+    #[path = "../../recording/state.rs"]
+    mod state;
+    #[path = "../../recording/status.rs"]
+    mod status;
+    #[path = "../../recording/tenant.rs"]
+    mod tenant;
+    #[path = "../../recording/timeline.rs"]
+    mod timeline;
+    #[path = "../../recording/view_events.rs"]
+    mod view_events;
+}
+
+use recording::codec::RecordingCodecs;
+use recording::gaps::MediaKind;
+use recording::pipeline::RecordingPipeline;
+use recording::rtpdump::{DumpReader, DumpRecord, DumpTrack};
+
+const PEER_ID: &str = "smoke_student";
+const VIDEO_FPS: u64 = 30;
+/// Opus frames of 20ms
+const AUDIO_FRAMES_PER_SEC: u64 = 50;
+/// Elements the generators need on top of the recording pipeline's
+const GENERATOR_ELEMENTS: &[&str] = &["videotestsrc", "vp8enc", "rtpvp8pay", "audiotestsrc", "opusenc", "rtpopuspay", "appsink"];
+const PROBE_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Parser)]
+#[command(name = "recording-smoke")]
+#[command(about = "Record generated media through the recording pipeline and check the result", long_about = None)]
+struct Cli {
+    /// Seconds of media fed to each recording
+    #[arg(long, default_value_t = 3)]
+    secs: u64,
+
+    /// Profile to exercise; repeat for several (default: all)
+    #[arg(long, value_enum)]
+    profile: Vec<Profile>,
+
+    /// Directory to record into (default: a new directory under the system temp dir)
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Keep the recordings after a passing run
+    #[arg(long)]
+    keep: bool,
+}
+
+/// Kinds of recording the server can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Profile {
+    /// VP8 and Opus written as sent into a webm
+    Webm,
+    /// VP8 and Opus re-encoded into a webm, as with `RECORDING_TRANSCODE`
+    Transcoded,
+    /// Packets written as received, the fallback when the pipeline is unusable
+    Rtpdump,
+}
+
+impl Profile {
+    const ALL: [Profile; 3] = [Profile::Webm, Profile::Transcoded, Profile::Rtpdump];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Profile::Webm => "webm",
+            Profile::Transcoded => "transcoded",
+            Profile::Rtpdump => "rtpdump",
+        }
+    }
+}
+
+/// One generated RTP packet, at its offset from the start of the media
+struct GeneratedPacket {
+    offset: Duration,
+    kind: MediaKind,
+    data: Bytes,
+}
+
+/// Packets a recording accepted
+#[derive(Default)]
+struct Fed {
+    video: u64,
+    audio: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+    let profiles = if cli.profile.is_empty() { Profile::ALL.to_vec() } else { cli.profile.clone() };
+    let duration = Duration::from_secs(cli.secs.max(1));
+    let output_dir = cli
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| std::env::temp_dir().join(format!("recording-smoke-{}", std::process::id())));
+
+    println!("{}", "Recording smoke test".cyan());
+    println!("  GStreamer: {}", gst::version_string());
+    println!("  Output: {}", output_dir.display());
+    println!("  Media: {}s of VP8 and Opus", duration.as_secs());
+
+    if let Err(e) = check_environment() {
+        println!("{} {}", "✗".red(), e);
+        std::process::exit(1);
+    }
+
+    let media = match generate_media(duration) {
+        Ok(media) => media,
+        Err(e) => {
+            println!("{} {}", "✗".red(), e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = 0;
+    for profile in profiles {
+        match run_profile(profile, &media, &output_dir, duration).await {
+            Ok(details) => {
+                println!("{} {}", "✓".green(), profile.name());
+                for detail in details {
+                    println!("    {}", detail);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{} {}: {}", "✗".red(), profile.name(), e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        println!("{} {} profile(s) failed; recordings kept in {}", "✗".red(), failed, output_dir.display());
+        std::process::exit(1);
+    }
+    if !cli.keep && cli.output_dir.is_none() {
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+}
+
+/// The recording pipeline's elements and the generators' are all installed
+fn check_environment() -> Result<(), String> {
+    RecordingPipeline::verify_environment().map_err(|e| e.to_string())?;
+    let missing: Vec<&str> = GENERATOR_ELEMENTS
+        .iter()
+        .copied()
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Missing GStreamer elements for the generators: {}", missing.join(", ")));
+    }
+    Ok(())
+}
+
+/// VP8 and Opus RTP for `duration`, interleaved in the order a publisher would send it
+fn generate_media(duration: Duration) -> Result<Vec<GeneratedPacket>, String> {
+    let mut media = generate_rtp(MediaKind::Video, duration)?;
+    media.extend(generate_rtp(MediaKind::Audio, duration)?);
+    media.sort_by_key(|packet| packet.offset);
+    Ok(media)
+}
+
+/// Encodes and payloads a test pattern or tone with the payload types the
+/// WebRTC engine offers, collecting the packets from an appsink
+fn generate_rtp(kind: MediaKind, duration: Duration) -> Result<Vec<GeneratedPacket>, String> {
+    let codecs = RecordingCodecs::default();
+    let secs = duration.as_secs();
+    let description = match kind {
+        MediaKind::Video => format!(
+            "videotestsrc num-buffers={} ! video/x-raw,width=320,height=240,framerate={}/1 \
+             ! vp8enc deadline=1 keyframe-max-dist={} ! rtpvp8pay pt={} ! appsink name=rtp sync=false",
+            secs * VIDEO_FPS,
+            VIDEO_FPS,
+            VIDEO_FPS,
+            codecs.video.payload_type
+        ),
+        MediaKind::Audio => format!(
+            "audiotestsrc num-buffers={} samplesperbuffer=960 ! audio/x-raw,rate=48000,channels=2 \
+             ! opusenc ! rtpopuspay pt={} ! appsink name=rtp sync=false",
+            secs * AUDIO_FRAMES_PER_SEC,
+            codecs.audio.payload_type
+        ),
+    };
+    let pipeline = gst::parse::launch(&description)
+        .map_err(|e| format!("Cannot build the {} generator: {}", kind.as_str(), e))?
+        .downcast::<gst::Pipeline>()
+        .map_err(|_| format!("Cannot build the {} generator", kind.as_str()))?;
+    let sink = pipeline
+        .by_name("rtp")
+        .and_then(|e| e.downcast::<AppSink>().ok())
+        .ok_or_else(|| format!("The {} generator has no appsink", kind.as_str()))?;
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| format!("Cannot start the {} generator: {}", kind.as_str(), e))?;
+
+    let mut packets = Vec::new();
+    // Fails once the generator reaches EOS, or on an error the bus reports below
+    while let Ok(sample) = sink.pull_sample() {
+        let Some(buffer) = sample.buffer() else { continue };
+        let offset = buffer.pts().map(|pts| Duration::from_nanos(pts.nseconds())).unwrap_or_default();
+        let map = buffer
+            .map_readable()
+            .map_err(|e| format!("Cannot read a generated {} packet: {}", kind.as_str(), e))?;
+        packets.push(GeneratedPacket { offset, kind, data: Bytes::copy_from_slice(map.as_slice()) });
+    }
+
+    let error = pipeline.bus().and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+    let _ = pipeline.set_state(gst::State::Null);
+    if let Some(message) = error {
+        if let gst::MessageView::Error(err) = message.view() {
+            return Err(format!("The {} generator failed: {}", kind.as_str(), err.error()));
+        }
+    }
+    if packets.is_empty() {
+        return Err(format!("The {} generator produced no packets", kind.as_str()));
+    }
+    Ok(packets)
+}
+
+/// Records `media` with one profile and probes the file it leaves
+async fn run_profile(
+    profile: Profile,
+    media: &[GeneratedPacket],
+    output_dir: &Path,
+    duration: Duration,
+) -> Result<Vec<String>, String> {
+    let codecs = RecordingCodecs::default();
+    let room_id = format!("smoke_{}", profile.name());
+    let output_dir = output_dir.to_str().ok_or_else(|| format!("{} is not valid UTF-8", output_dir.display()))?;
+    let pipeline = match profile {
+        Profile::Webm => RecordingPipeline::new(&room_id, PEER_ID, output_dir, &codecs),
+        Profile::Transcoded => RecordingPipeline::transcoding(&room_id, PEER_ID, output_dir, &codecs),
+        Profile::Rtpdump => RecordingPipeline::rtp_dump(&room_id, PEER_ID, output_dir, &codecs),
+    }
+    .map_err(|e| format!("Cannot build the recording: {}", e))?;
+    pipeline.start().await.map_err(|e| format!("Cannot start the recording: {}", e))?;
+
+    // Stopped whatever the feed did, so a refused packet still leaves a file to look at
+    let fed = feed(&pipeline, media).await;
+    let stopped = pipeline.stop().await;
+    let fed = fed?;
+    let path = stopped.map_err(|e| format!("Cannot stop the recording: {}", e))?;
+
+    if pipeline.part_path().exists() {
+        return Err(format!("{} was left behind", pipeline.part_path().display()));
+    }
+    let size = std::fs::metadata(&path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?
+        .len();
+    if size == 0 {
+        return Err(format!("{} is empty", path.display()));
+    }
+
+    let mut details = vec![
+        format!("File: {} ({} bytes)", path.display(), size),
+        format!("Fed: {} video and {} audio packets", fed.video, fed.audio),
+    ];
+    details.extend(match profile {
+        Profile::Webm | Profile::Transcoded => probe_webm(&path, duration)?,
+        Profile::Rtpdump => probe_rtpdump(&path, &fed)?,
+    });
+    Ok(details)
+}
+
+/// Pushes every packet at its offset from now, as a live publisher would;
+/// the pipeline places each track from the arrival of its first packet
+async fn feed(pipeline: &RecordingPipeline, media: &[GeneratedPacket]) -> Result<Fed, String> {
+    let start = Instant::now();
+    let mut fed = Fed::default();
+    for packet in media {
+        sleep_until(start + packet.offset).await;
+        let pushed = match packet.kind {
+            MediaKind::Video => pipeline.push_video_rtp(packet.data.clone()),
+            MediaKind::Audio => pipeline.push_audio_rtp(packet.data.clone()),
+        };
+        pushed.map_err(|e| {
+            format!(
+                "Recording refused a {} packet at {:.2}s: {}",
+                packet.kind.as_str(),
+                packet.offset.as_secs_f64(),
+                e
+            )
+        })?;
+        match packet.kind {
+            MediaKind::Video => fed.video += 1,
+            MediaKind::Audio => fed.audio += 1,
+        }
+    }
+    Ok(fed)
+}
+
+/// The webm plays for about as long as it was fed and holds both streams
+fn probe_webm(path: &Path, duration: Duration) -> Result<Vec<String>, String> {
+    use gstreamer_pbutils::Discoverer;
+
+    let path = std::fs::canonicalize(path).map_err(|e| format!("Cannot resolve {}: {}", path.display(), e))?;
+    let uri = gst::glib::filename_to_uri(path.as_path(), None).map_err(|e| format!("Cannot probe {}: {}", path.display(), e))?;
+    let discoverer = Discoverer::new(gst::ClockTime::from_seconds(PROBE_TIMEOUT_SECS))
+        .map_err(|e| format!("Cannot create the discoverer: {}", e))?;
+    let info = discoverer
+        .discover_uri(&uri)
+        .map_err(|e| format!("Cannot probe {}: {}", path.display(), e))?;
+
+    let probed = info.duration().map(|d| Duration::from_nanos(d.nseconds())).unwrap_or_default();
+    let video_streams = info.video_streams().len();
+    let audio_streams = info.audio_streams().len();
+    let details = vec![
+        format!("Duration: {:.2}s", probed.as_secs_f64()),
+        format!("Streams: {} video, {} audio", video_streams, audio_streams),
+    ];
+
+    let mut problems = Vec::new();
+    if video_streams == 0 {
+        problems.push("no video stream".to_string());
+    }
+    if audio_streams == 0 {
+        problems.push("no audio stream".to_string());
+    }
+    if probed < duration.mul_f64(0.8) || probed > duration + Duration::from_secs(2) {
+        problems.push(format!(
+            "duration {:.2}s for {}s of media",
+            probed.as_secs_f64(),
+            duration.as_secs()
+        ));
+    }
+    if !problems.is_empty() {
+        return Err(format!("{} has {} ({})", path.display(), problems.join(", "), details.join("; ")));
+    }
+    Ok(details)
+}
+
+/// The dump reads back whole, with every packet that was fed
+fn probe_rtpdump(path: &Path, fed: &Fed) -> Result<Vec<String>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut reader = DumpReader::new(BufReader::new(file)).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+    let mut read = Fed::default();
+    let mut last_offset = Duration::ZERO;
+    while let Some(record) = reader.next_record().map_err(|e| format!("Cannot read {}: {}", path.display(), e))? {
+        if let DumpRecord::Rtp { track, offset, .. } = record {
+            match track {
+                DumpTrack::Video => read.video += 1,
+                DumpTrack::Audio => read.audio += 1,
+            }
+            last_offset = last_offset.max(offset);
+        }
+    }
+    if reader.truncated() {
+        return Err(format!("{} ends mid-record", path.display()));
+    }
+    if read.video != fed.video || read.audio != fed.audio {
+        return Err(format!(
+            "{} holds {} video and {} audio packets, {} and {} were fed",
+            path.display(),
+            read.video,
+            read.audio,
+            fed.video,
+            fed.audio
+        ));
+    }
+    let header = reader.header();
+    Ok(vec![
+        format!(
+            "Codecs: {}/{} and {}/{}",
+            header.video.encoding_name, header.video.payload_type, header.audio.encoding_name, header.audio.payload_type
+        ),
+        format!("Last packet at {:.2}s", last_offset.as_secs_f64()),
+    ])
+}
@@ -0,0 +1,470 @@
+//! Failure injection for chaos testing in staging.
+//!
+//! Directives are registered through the admin API and consulted at a few
+//! choke points (IPFS upload, contract sends, recording start, WebSocket
+//! sends). Each matching call rolls against the directive's probability and
+//! either fails with the subsystem's own error or is delayed. Directives
+//! expire on their own; nothing here is active unless `CHAOS_ENABLED=true`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::env;
+use crate::error::SfuError;
+
+/// Delay applied by `delay` directives that don't set `delay_ms`
+const DEFAULT_DELAY_MS: u64 = 2000;
+
+/// Upper bound on how long a single directive may stay active
+const DEFAULT_MAX_DURATION_SECS: u64 = 3600;
+
+/// Subsystem a directive applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChaosTarget {
+    Ipfs,
+    Chain,
+    Recording,
+    WsSend,
+}
+
+/// What happens to a call that a directive hits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChaosMode {
+    Fail,
+    Delay,
+}
+
+/// Body of `POST /sfu/admin/chaos`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChaosRequest {
+    pub target: ChaosTarget,
+    pub mode: ChaosMode,
+    pub probability: f64,
+    pub duration_secs: u64,
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    /// Restrict the directive to calls made on behalf of this room
+    #[serde(default)]
+    pub room_id: Option<String>,
+}
+
+/// An active failure injection rule
+#[derive(Debug, Clone, Serialize)]
+pub struct ChaosDirective {
+    pub id: u64,
+    pub target: ChaosTarget,
+    pub mode: ChaosMode,
+    pub probability: f64,
+    pub delay_ms: u64,
+    pub room_id: Option<String>,
+    /// Unix time in milliseconds when the directive was registered
+    pub created_at: u64,
+    /// Unix time in milliseconds when the directive stops applying
+    pub expires_at: u64,
+    /// Number of calls the directive has failed or delayed so far
+    pub triggered: u64,
+    #[serde(skip)]
+    deadline: Instant,
+}
+
+impl ChaosDirective {
+    /// Calls without room context only match directives that aren't room-scoped
+    fn matches(&self, target: ChaosTarget, room_id: Option<&str>) -> bool {
+        self.target == target
+            && match self.room_id.as_deref() {
+                Some(scoped) => room_id == Some(scoped),
+                None => true,
+            }
+    }
+}
+
+/// Outcome for a call that a directive hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Fail { directive_id: u64 },
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChaosError {
+    InvalidProbability,
+    InvalidDuration { max_secs: u64 },
+    InvalidRoomId,
+    /// Contract sends carry no room, so chain directives can't be room-scoped
+    RoomScopeUnsupported,
+}
+
+impl ChaosError {
+    pub fn message(&self) -> String {
+        match self {
+            ChaosError::InvalidProbability => "probability must be between 0 and 1".to_string(),
+            ChaosError::InvalidDuration { max_secs } => {
+                format!("duration_secs must be between 1 and {}", max_secs)
+            }
+            ChaosError::InvalidRoomId => "room_id must not be empty".to_string(),
+            ChaosError::RoomScopeUnsupported => "chain directives cannot be scoped to a room".to_string(),
+        }
+    }
+}
+
+/// Admin settings for failure injection
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    pub admin_token: String,
+    pub max_duration: Duration,
+}
+
+impl ChaosConfig {
+    /// Returns None unless `CHAOS_ENABLED=true` and `CHAOS_ADMIN_TOKEN` is set
+    pub fn from_env() -> Option<Self> {
+        if !env::get_bool("CHAOS_ENABLED", false) {
+            return None;
+        }
+
+        let Some(admin_token) = env::get_string("CHAOS_ADMIN_TOKEN") else {
+            tracing::warn!("CHAOS_ENABLED is set but CHAOS_ADMIN_TOKEN is not, failure injection stays disabled");
+            return None;
+        };
+
+        let max_duration_secs = env::get_parsed::<u64>("CHAOS_MAX_DURATION_SECS")
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_MAX_DURATION_SECS);
+
+        Some(Self {
+            admin_token,
+            max_duration: Duration::from_secs(max_duration_secs),
+        })
+    }
+}
+
+/// Registry of active directives
+pub struct ChaosInjector {
+    config: ChaosConfig,
+    directives: Mutex<Vec<ChaosDirective>>,
+    next_id: Mutex<u64>,
+    rng: Mutex<StdRng>,
+}
+
+static INJECTOR: OnceLock<Option<Arc<ChaosInjector>>> = OnceLock::new();
+
+/// Process-wide injector, or None when failure injection is disabled
+pub fn injector() -> Option<Arc<ChaosInjector>> {
+    INJECTOR
+        .get_or_init(|| {
+            let config = ChaosConfig::from_env()?;
+            tracing::warn!("Failure injection enabled, do not run this configuration in production");
+            Some(Arc::new(ChaosInjector::new(config)))
+        })
+        .clone()
+}
+
+/// Consults active directives for a call about to be made. Delays are served
+/// here; failures come back as the target subsystem's own error.
+pub async fn check(target: ChaosTarget, room_id: Option<&str>) -> Result<(), SfuError> {
+    let Some(injector) = injector() else {
+        return Ok(());
+    };
+
+    match injector.roll(target, room_id, Instant::now()) {
+        None => Ok(()),
+        Some(Fault::Delay(delay)) => {
+            tokio::time::sleep(delay).await;
+            Ok(())
+        }
+        Some(Fault::Fail { directive_id }) => Err(injected_error(target, directive_id)),
+    }
+}
+
+fn injected_error(target: ChaosTarget, directive_id: u64) -> SfuError {
+    let reason = format!("Injected failure (chaos directive {})", directive_id);
+    match target {
+        ChaosTarget::Ipfs => SfuError::IpfsUploadFailed(reason),
+        ChaosTarget::Chain => SfuError::ContractCallFailed(reason),
+        ChaosTarget::Recording => SfuError::Internal(reason),
+        ChaosTarget::WsSend => SfuError::NetworkError(reason),
+    }
+}
+
+impl ChaosInjector {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self::with_rng(config, StdRng::from_entropy())
+    }
+
+    fn with_rng(config: ChaosConfig, rng: StdRng) -> Self {
+        Self {
+            config,
+            directives: Mutex::new(Vec::new()),
+            next_id: Mutex::new(1),
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Constant-time comparison against the configured admin token
+    pub fn authorize(&self, token: &str) -> bool {
+        let expected = self.config.admin_token.as_bytes();
+        let token = token.as_bytes();
+        expected.len() == token.len()
+            && expected.iter().zip(token).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    pub fn add(&self, request: ChaosRequest, now: Instant) -> Result<ChaosDirective, ChaosError> {
+        if !(0.0..=1.0).contains(&request.probability) {
+            return Err(ChaosError::InvalidProbability);
+        }
+        let max_secs = self.config.max_duration.as_secs();
+        if request.duration_secs == 0 || request.duration_secs > max_secs {
+            return Err(ChaosError::InvalidDuration { max_secs });
+        }
+        if request.room_id.as_deref().is_some_and(str::is_empty) {
+            return Err(ChaosError::InvalidRoomId);
+        }
+        if request.target == ChaosTarget::Chain && request.room_id.is_some() {
+            return Err(ChaosError::RoomScopeUnsupported);
+        }
+
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            let id = *next_id;
+            *next_id += 1;
+            id
+        };
+        let created_at = unix_ms();
+        let directive = ChaosDirective {
+            id,
+            target: request.target,
+            mode: request.mode,
+            probability: request.probability,
+            delay_ms: request.delay_ms.unwrap_or(DEFAULT_DELAY_MS),
+            room_id: request.room_id,
+            created_at,
+            expires_at: created_at + request.duration_secs * 1000,
+            triggered: 0,
+            deadline: now + Duration::from_secs(request.duration_secs),
+        };
+
+        tracing::warn!(
+            directive_id = id,
+            target = ?directive.target,
+            mode = ?directive.mode,
+            probability = directive.probability,
+            duration_secs = request.duration_secs,
+            room_id = ?directive.room_id,
+            "Chaos directive registered"
+        );
+        self.directives.lock().unwrap().push(directive.clone());
+        Ok(directive)
+    }
+
+    /// Active directives, dropping any that expired
+    pub fn list(&self, now: Instant) -> Vec<ChaosDirective> {
+        let mut directives = self.directives.lock().unwrap();
+        directives.retain(|d| d.deadline > now);
+        directives.clone()
+    }
+
+    /// Removes a directive before it expires. Returns false if it was unknown.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut directives = self.directives.lock().unwrap();
+        let before = directives.len();
+        directives.retain(|d| d.id != id);
+        let removed = directives.len() != before;
+        if removed {
+            tracing::info!(directive_id = id, "Chaos directive cancelled");
+        }
+        removed
+    }
+
+    /// Rolls every matching directive in registration order; the first hit wins
+    pub fn roll(&self, target: ChaosTarget, room_id: Option<&str>, now: Instant) -> Option<Fault> {
+        let mut directives = self.directives.lock().unwrap();
+        directives.retain(|d| d.deadline > now);
+
+        let mut rng = self.rng.lock().unwrap();
+        for directive in directives.iter_mut().filter(|d| d.matches(target, room_id)) {
+            if rng.gen::<f64>() >= directive.probability {
+                continue;
+            }
+            directive.triggered += 1;
+            tracing::debug!(
+                directive_id = directive.id,
+                target = ?target,
+                room_id = ?room_id,
+                "Chaos directive triggered"
+            );
+            return Some(match directive.mode {
+                ChaosMode::Fail => Fault::Fail { directive_id: directive.id },
+                ChaosMode::Delay => Fault::Delay(Duration::from_millis(directive.delay_ms)),
+            });
+        }
+        None
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_injector() -> ChaosInjector {
+        let config = ChaosConfig {
+            admin_token: "secret".to_string(),
+            max_duration: Duration::from_secs(DEFAULT_MAX_DURATION_SECS),
+        };
+        ChaosInjector::with_rng(config, StdRng::seed_from_u64(7))
+    }
+
+    fn request(target: ChaosTarget, mode: ChaosMode, probability: f64) -> ChaosRequest {
+        ChaosRequest {
+            target,
+            mode,
+            probability,
+            duration_secs: 60,
+            delay_ms: None,
+            room_id: None,
+        }
+    }
+
+    #[test]
+    fn test_triggers_at_configured_probability() {
+        let injector = test_injector();
+        let now = Instant::now();
+        injector.add(request(ChaosTarget::Ipfs, ChaosMode::Fail, 0.25), now).unwrap();
+
+        let rolls = 10_000;
+        let hits = (0..rolls)
+            .filter(|_| injector.roll(ChaosTarget::Ipfs, None, now).is_some())
+            .count();
+        let rate = hits as f64 / rolls as f64;
+        assert!((rate - 0.25).abs() < 0.02, "hit rate {} too far from 0.25", rate);
+        assert_eq!(injector.list(now)[0].triggered, hits as u64);
+    }
+
+    #[test]
+    fn test_probability_bounds() {
+        let injector = test_injector();
+        let now = Instant::now();
+        injector.add(request(ChaosTarget::Chain, ChaosMode::Fail, 1.0), now).unwrap();
+        injector.add(request(ChaosTarget::Recording, ChaosMode::Fail, 0.0), now).unwrap();
+
+        for _ in 0..100 {
+            assert_eq!(
+                injector.roll(ChaosTarget::Chain, None, now),
+                Some(Fault::Fail { directive_id: 1 })
+            );
+            assert_eq!(injector.roll(ChaosTarget::Recording, None, now), None);
+        }
+        // Untargeted subsystems are never affected
+        assert_eq!(injector.roll(ChaosTarget::WsSend, None, now), None);
+    }
+
+    #[test]
+    fn test_directives_auto_expire() {
+        let injector = test_injector();
+        let now = Instant::now();
+        injector.add(request(ChaosTarget::WsSend, ChaosMode::Fail, 1.0), now).unwrap();
+
+        assert!(injector.roll(ChaosTarget::WsSend, None, now + Duration::from_secs(59)).is_some());
+        assert!(injector.roll(ChaosTarget::WsSend, None, now + Duration::from_secs(60)).is_none());
+        assert!(injector.list(now + Duration::from_secs(60)).is_empty());
+    }
+
+    #[test]
+    fn test_delay_mode() {
+        let injector = test_injector();
+        let now = Instant::now();
+        let mut req = request(ChaosTarget::Ipfs, ChaosMode::Delay, 1.0);
+        req.delay_ms = Some(250);
+        injector.add(req, now).unwrap();
+        injector.add(request(ChaosTarget::Chain, ChaosMode::Delay, 1.0), now).unwrap();
+
+        assert_eq!(
+            injector.roll(ChaosTarget::Ipfs, None, now),
+            Some(Fault::Delay(Duration::from_millis(250)))
+        );
+        assert_eq!(
+            injector.roll(ChaosTarget::Chain, None, now),
+            Some(Fault::Delay(Duration::from_millis(DEFAULT_DELAY_MS)))
+        );
+    }
+
+    #[test]
+    fn test_room_scoped_directive() {
+        let injector = test_injector();
+        let now = Instant::now();
+        let mut req = request(ChaosTarget::Recording, ChaosMode::Fail, 1.0);
+        req.room_id = Some("room-a".to_string());
+        injector.add(req, now).unwrap();
+
+        assert!(injector.roll(ChaosTarget::Recording, Some("room-a"), now).is_some());
+        assert!(injector.roll(ChaosTarget::Recording, Some("room-b"), now).is_none());
+        assert!(injector.roll(ChaosTarget::Recording, None, now).is_none());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let injector = test_injector();
+        let now = Instant::now();
+        let directive = injector.add(request(ChaosTarget::Ipfs, ChaosMode::Fail, 1.0), now).unwrap();
+
+        assert!(injector.cancel(directive.id));
+        assert!(!injector.cancel(directive.id));
+        assert!(injector.roll(ChaosTarget::Ipfs, None, now).is_none());
+    }
+
+    #[test]
+    fn test_rejects_invalid_requests() {
+        let injector = test_injector();
+        let now = Instant::now();
+
+        let err = injector.add(request(ChaosTarget::Ipfs, ChaosMode::Fail, 1.5), now).unwrap_err();
+        assert_eq!(err, ChaosError::InvalidProbability);
+
+        let mut req = request(ChaosTarget::Ipfs, ChaosMode::Fail, 0.5);
+        req.duration_secs = 0;
+        assert!(matches!(injector.add(req, now), Err(ChaosError::InvalidDuration { .. })));
+
+        let mut req = request(ChaosTarget::Ipfs, ChaosMode::Fail, 0.5);
+        req.duration_secs = DEFAULT_MAX_DURATION_SECS + 1;
+        assert!(matches!(injector.add(req, now), Err(ChaosError::InvalidDuration { .. })));
+
+        let mut req = request(ChaosTarget::Ipfs, ChaosMode::Fail, 0.5);
+        req.room_id = Some(String::new());
+        assert_eq!(injector.add(req, now).unwrap_err(), ChaosError::InvalidRoomId);
+
+        let mut req = request(ChaosTarget::Chain, ChaosMode::Fail, 0.5);
+        req.room_id = Some("room-a".to_string());
+        assert_eq!(injector.add(req, now).unwrap_err(), ChaosError::RoomScopeUnsupported);
+    }
+
+    #[test]
+    fn test_authorize() {
+        let injector = test_injector();
+        assert!(injector.authorize("secret"));
+        assert!(!injector.authorize("secreT"));
+        assert!(!injector.authorize("secret2"));
+        assert!(!injector.authorize(""));
+    }
+
+    #[test]
+    fn test_request_deserialization() {
+        let req: ChaosRequest = serde_json::from_str(
+            r#"{"target":"ws_send","mode":"delay","probability":0.5,"duration_secs":30,"room_id":"r1"}"#,
+        )
+        .unwrap();
+        assert_eq!(req.target, ChaosTarget::WsSend);
+        assert_eq!(req.mode, ChaosMode::Delay);
+        assert_eq!(req.room_id.as_deref(), Some("r1"));
+        assert!(req.delay_ms.is_none());
+    }
+}
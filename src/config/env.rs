@@ -0,0 +1,495 @@
+//! Typed environment variable accessors.
+//!
+//! Every read is recorded, so startup can report which variables were set,
+//! which fell back to their defaults, which failed to parse, and which
+//! prefixed variables were set but never read.
+//!
+//! Values from a configuration file sit underneath the environment: they are
+//! used only for variables the environment leaves unset or empty.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+/// Prefixes owned by this server; a variable with one of these that nothing
+/// reads is most likely misspelled
+const CHECKED_PREFIXES: &[&str] = &["SFU_", "ASSET_HUB_", "IPFS_", "LTI_"];
+
+/// `(current, older)` names of renamed variables. The older name is still
+/// read when the current one is unset, with a deprecation warning.
+const ALIASES: &[(&str, &str)] = &[
+    ("SFU_PUBLIC_IP", "WEBRTC_PUBLIC_IP"),
+    ("SFU_UDP_PORT_MIN", "WEBRTC_UDP_PORT_MIN"),
+    ("SFU_UDP_PORT_MAX", "WEBRTC_UDP_PORT_MAX"),
+];
+
+#[cfg(test)]
+use tests::TEST_ALIASES;
+#[cfg(not(test))]
+const TEST_ALIASES: &[(&str, &str)] = &[];
+
+fn alias_of(name: &str) -> Option<&'static str> {
+    ALIASES
+        .iter()
+        .chain(TEST_ALIASES)
+        .find(|(current, _)| *current == name)
+        .map(|(_, older)| *older)
+}
+
+/// How a single variable was resolved. Values are never recorded, so the
+/// report is safe to expose even though it covers keys and credentials.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvRead {
+    pub name: String,
+    /// Unset, empty or invalid, so the caller's default applies
+    pub defaulted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvReport {
+    /// Every variable read so far, by name
+    pub variables: Vec<EnvRead>,
+    /// Set with a checked prefix but never read: a typo, or a setting for a
+    /// feature that is disabled
+    pub unread: Vec<String>,
+}
+
+impl EnvReport {
+    pub fn warnings(&self) -> impl Iterator<Item = &EnvRead> {
+        self.variables.iter().filter(|read| read.warning.is_some())
+    }
+}
+
+static READS: OnceLock<Mutex<BTreeMap<String, EnvRead>>> = OnceLock::new();
+
+fn reads() -> &'static Mutex<BTreeMap<String, EnvRead>> {
+    READS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+fn record(name: &str, defaulted: bool, warning: Option<String>) {
+    let read = EnvRead {
+        name: name.to_string(),
+        defaulted,
+        warning,
+    };
+    let mut reads = reads().lock().unwrap();
+    // The older name of a renamed variable is reported like the current one
+    if let Some(older) = alias_of(name) {
+        let read = EnvRead {
+            name: older.to_string(),
+            warning: None,
+            ..read.clone()
+        };
+        reads.insert(older.to_string(), read);
+    }
+    let previous = reads.insert(name.to_string(), read.clone());
+    drop(reads);
+
+    // Settings read once per connection would otherwise repeat the warning
+    if previous.as_ref() != Some(&read) {
+        if let Some(ref warning) = read.warning {
+            tracing::warn!(variable = name, "{}", warning);
+        }
+    }
+}
+
+static FILE_VALUES: OnceLock<RwLock<BTreeMap<String, String>>> = OnceLock::new();
+
+fn file_values() -> &'static RwLock<BTreeMap<String, String>> {
+    FILE_VALUES.get_or_init(|| RwLock::new(BTreeMap::new()))
+}
+
+/// Adds values from a configuration file, by variable name, replacing any
+/// earlier file value of the same variable
+pub fn set_file_values(values: BTreeMap<String, String>) {
+    file_values().write().unwrap().extend(values);
+}
+
+/// Variables set in the environment or the file, for settings found by
+/// name rather than read one by one, such as the numbered TURN servers
+pub fn names() -> BTreeSet<String> {
+    let mut names: BTreeSet<String> = std::env::vars_os().filter_map(|(name, _)| name.into_string().ok()).collect();
+    names.extend(file_values().read().unwrap().keys().cloned());
+    names
+}
+
+static DEPRECATED: OnceLock<Mutex<BTreeSet<&'static str>>> = OnceLock::new();
+
+/// Warns about an older name the first time it is used
+fn warn_deprecated(older: &'static str, current: &str) {
+    if DEPRECATED.get_or_init(Default::default).lock().unwrap().insert(older) {
+        tracing::warn!(variable = older, replacement = current, "{} is deprecated, set {} instead", older, current);
+    }
+}
+
+/// Trimmed value of the environment variable, `None` when unset or empty
+fn from_env(name: &str) -> Result<Option<String>, String> {
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(value.trim().to_string()).filter(|value| !value.is_empty())),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(_)) => Err(format!("{} is not valid UTF-8, using the default", name)),
+    }
+}
+
+/// Trimmed value, treating empty as unset and falling back to the file. A
+/// renamed variable is looked up under its older name as well, in the
+/// environment before the file. A non-UTF-8 value is a warning.
+fn raw(name: &str) -> Result<Option<String>, String> {
+    let older = alias_of(name);
+    if let Some(value) = from_env(name)? {
+        return Ok(Some(value));
+    }
+    if let Some(older) = older {
+        if let Some(value) = from_env(older)? {
+            warn_deprecated(older, name);
+            return Ok(Some(value));
+        }
+    }
+
+    let file_values = file_values().read().unwrap();
+    let from_file = |name: &str| {
+        let value = file_values.get(name)?.trim();
+        (!value.is_empty()).then(|| value.to_string())
+    };
+    if let Some(value) = from_file(name) {
+        return Ok(Some(value));
+    }
+    let value = older.and_then(|older| from_file(older).map(|value| (older, value)));
+    Ok(value.map(|(older, value)| {
+        warn_deprecated(older, name);
+        value
+    }))
+}
+
+/// Reads a string, `None` when unset or empty
+pub fn get_string(name: &str) -> Option<String> {
+    match raw(name) {
+        Ok(value) => {
+            record(name, value.is_none(), None);
+            value
+        }
+        Err(warning) => {
+            record(name, true, Some(warning));
+            None
+        }
+    }
+}
+
+/// Reads and parses a value, `None` when unset, empty or invalid. Invalid
+/// values are reported rather than silently replaced by the default.
+pub fn get_parsed<T: FromStr>(name: &str) -> Option<T> {
+    let value = match raw(name) {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            record(name, true, None);
+            return None;
+        }
+        Err(warning) => {
+            record(name, true, Some(warning));
+            return None;
+        }
+    };
+
+    match value.parse() {
+        Ok(parsed) => {
+            record(name, false, None);
+            Some(parsed)
+        }
+        Err(_) => {
+            record(
+                name,
+                true,
+                Some(format!("{} has an invalid value, expected {}; using the default", name, type_label::<T>())),
+            );
+            None
+        }
+    }
+}
+
+/// Reads a flag: `1`/`true`/`yes` or `0`/`false`/`no`, case-insensitive
+pub fn get_bool(name: &str, default: bool) -> bool {
+    let value = match raw(name) {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            record(name, true, None);
+            return default;
+        }
+        Err(warning) => {
+            record(name, true, Some(warning));
+            return default;
+        }
+    };
+
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => {
+            record(name, false, None);
+            true
+        }
+        "0" | "false" | "no" => {
+            record(name, false, None);
+            false
+        }
+        _ => {
+            record(
+                name,
+                true,
+                Some(format!(
+                    "{} is not a boolean, expected true/false, yes/no or 1/0; using the default ({})",
+                    name, default
+                )),
+            );
+            default
+        }
+    }
+}
+
+/// Reads a whole number of seconds
+pub fn get_duration_secs(name: &str, default: Duration) -> Duration {
+    get_parsed::<u64>(name).map(Duration::from_secs).unwrap_or(default)
+}
+
+/// Reads a comma-separated list, dropping blank entries
+pub fn get_list(name: &str) -> Vec<String> {
+    get_string(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// What has been read so far, plus checked-prefix variables nothing has read
+pub fn report() -> EnvReport {
+    let reads = reads().lock().unwrap();
+    let mut unread: Vec<String> = std::env::vars_os()
+        .filter_map(|(name, _)| name.into_string().ok())
+        .filter(|name| CHECKED_PREFIXES.iter().any(|prefix| name.starts_with(prefix)))
+        .filter(|name| !reads.contains_key(name))
+        .collect();
+    unread.sort();
+
+    EnvReport {
+        variables: reads.values().cloned().collect(),
+        unread,
+    }
+}
+
+/// Logs the startup summary of the environment read so far
+pub fn log_report() {
+    let report = report();
+    let defaulted = report.variables.iter().filter(|read| read.defaulted).count();
+
+    tracing::info!(
+        variables = report.variables.len(),
+        defaulted = defaulted,
+        set = report.variables.len() - defaulted,
+        warnings = report.warnings().count(),
+        "Environment configuration loaded"
+    );
+    for name in &report.unread {
+        tracing::warn!(variable = %name, "Environment variable is set but never read; check for a typo");
+    }
+}
+
+fn type_label<T>() -> &'static str {
+    match std::any::type_name::<T>() {
+        "u8" | "u16" | "u32" | "u64" | "usize" => "a non-negative integer",
+        "i8" | "i16" | "i32" | "i64" | "isize" => "an integer",
+        "f32" | "f64" => "a number",
+        other => other.rsplit("::").next().unwrap_or(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test uses its own variable names, since the environment and the
+    // report are shared by every test in the process
+
+    /// A renamed variable only tests set, since setting a real one would
+    /// reach every server the test process builds
+    pub(super) const TEST_ALIASES: &[(&str, &str)] = &[("SFU_TEST_ALIASED_PORT", "WEBRTC_TEST_ALIASED_PORT")];
+
+    fn read(name: &str) -> Option<EnvRead> {
+        reads().lock().unwrap().get(name).cloned()
+    }
+
+    #[test]
+    fn test_get_parsed_reports_invalid_values() {
+        std::env::set_var("SFU_TEST_PARSED_OK", " 8080 ");
+        std::env::set_var("SFU_TEST_PARSED_BAD", "80eighty");
+        std::env::set_var("SFU_TEST_PARSED_NEGATIVE", "-5");
+        std::env::set_var("SFU_TEST_PARSED_EMPTY", "");
+        std::env::remove_var("SFU_TEST_PARSED_UNSET");
+
+        assert_eq!(get_parsed::<u16>("SFU_TEST_PARSED_OK"), Some(8080));
+        assert!(!read("SFU_TEST_PARSED_OK").unwrap().defaulted);
+
+        assert_eq!(get_parsed::<u16>("SFU_TEST_PARSED_BAD"), None);
+        let bad = read("SFU_TEST_PARSED_BAD").unwrap();
+        assert!(bad.defaulted);
+        // The offending value itself is kept out of the report
+        assert!(bad.warning.as_deref().unwrap().contains("non-negative integer"));
+        assert!(!bad.warning.unwrap().contains("80eighty"));
+
+        assert_eq!(get_parsed::<u64>("SFU_TEST_PARSED_NEGATIVE"), None);
+        assert_eq!(get_parsed::<i64>("SFU_TEST_PARSED_NEGATIVE"), Some(-5));
+
+        // Empty and unset both default without a warning
+        assert_eq!(get_parsed::<f64>("SFU_TEST_PARSED_EMPTY"), None);
+        assert_eq!(get_parsed::<f64>("SFU_TEST_PARSED_UNSET"), None);
+        let unset = read("SFU_TEST_PARSED_UNSET").unwrap();
+        assert!(unset.defaulted && unset.warning.is_none());
+    }
+
+    #[test]
+    fn test_get_bool_accepts_common_spellings() {
+        for (value, expected) in [("1", true), ("TRUE", true), ("Yes", true), ("0", false), ("false", false), ("NO", false)] {
+            std::env::set_var("SFU_TEST_BOOL", value);
+            assert_eq!(get_bool("SFU_TEST_BOOL", !expected), expected, "{}", value);
+        }
+
+        std::env::set_var("SFU_TEST_BOOL_BAD", "enabled");
+        assert!(get_bool("SFU_TEST_BOOL_BAD", true));
+        assert!(!get_bool("SFU_TEST_BOOL_BAD", false));
+        assert!(read("SFU_TEST_BOOL_BAD").unwrap().warning.is_some());
+
+        std::env::remove_var("SFU_TEST_BOOL_UNSET");
+        assert!(get_bool("SFU_TEST_BOOL_UNSET", true));
+        assert!(read("SFU_TEST_BOOL_UNSET").unwrap().warning.is_none());
+    }
+
+    #[test]
+    fn test_get_duration_secs() {
+        let default = Duration::from_secs(30);
+
+        std::env::set_var("SFU_TEST_DURATION", "90");
+        assert_eq!(get_duration_secs("SFU_TEST_DURATION", default), Duration::from_secs(90));
+
+        // Zero is a valid duration; callers decide what it means
+        std::env::set_var("SFU_TEST_DURATION_ZERO", "0");
+        assert_eq!(get_duration_secs("SFU_TEST_DURATION_ZERO", default), Duration::ZERO);
+
+        std::env::set_var("SFU_TEST_DURATION_BAD", "1.5");
+        assert_eq!(get_duration_secs("SFU_TEST_DURATION_BAD", default), default);
+        assert!(read("SFU_TEST_DURATION_BAD").unwrap().warning.is_some());
+    }
+
+    #[test]
+    fn test_get_list_drops_blank_entries() {
+        std::env::set_var("SFU_TEST_LIST", " a, b ,,c ,");
+        assert_eq!(get_list("SFU_TEST_LIST"), vec!["a", "b", "c"]);
+
+        std::env::set_var("SFU_TEST_LIST_BLANK", " , ");
+        assert!(get_list("SFU_TEST_LIST_BLANK").is_empty());
+
+        std::env::remove_var("SFU_TEST_LIST_UNSET");
+        assert!(get_list("SFU_TEST_LIST_UNSET").is_empty());
+        assert!(read("SFU_TEST_LIST_UNSET").unwrap().defaulted);
+    }
+
+    #[test]
+    fn test_report_lists_unread_prefixed_variables() {
+        std::env::set_var("SFU_TEST_REPORT_READ", "1");
+        std::env::set_var("SFU_TEST_REPORT_TYPO", "1");
+        std::env::set_var("IPFS_TEST_REPORT_TYPO", "1");
+        std::env::set_var("OTHER_TEST_REPORT_UNREAD", "1");
+        get_bool("SFU_TEST_REPORT_READ", false);
+
+        let report = report();
+        assert!(report.unread.contains(&"SFU_TEST_REPORT_TYPO".to_string()));
+        assert!(report.unread.contains(&"IPFS_TEST_REPORT_TYPO".to_string()));
+        assert!(!report.unread.contains(&"SFU_TEST_REPORT_READ".to_string()));
+        assert!(!report.unread.contains(&"OTHER_TEST_REPORT_UNREAD".to_string()));
+        assert!(report.variables.iter().any(|read| read.name == "SFU_TEST_REPORT_READ"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert!(json["variables"].as_array().unwrap().iter().all(|v| v.get("value").is_none()));
+    }
+
+    fn set_file(values: &[(&str, &str)]) {
+        set_file_values(values.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect());
+    }
+
+    #[test]
+    fn test_file_only_values_are_read() {
+        std::env::remove_var("SFU_TEST_FILE_ONLY_PORT");
+        std::env::remove_var("SFU_TEST_FILE_ONLY_FLAG");
+        std::env::remove_var("SFU_TEST_FILE_ONLY_LIST");
+        set_file(&[
+            ("SFU_TEST_FILE_ONLY_PORT", "9000"),
+            ("SFU_TEST_FILE_ONLY_FLAG", "false"),
+            ("SFU_TEST_FILE_ONLY_LIST", "vp8,opus"),
+        ]);
+
+        assert_eq!(get_parsed::<u16>("SFU_TEST_FILE_ONLY_PORT"), Some(9000));
+        assert!(!get_bool("SFU_TEST_FILE_ONLY_FLAG", true));
+        assert_eq!(get_list("SFU_TEST_FILE_ONLY_LIST"), vec!["vp8", "opus"]);
+        assert!(!read("SFU_TEST_FILE_ONLY_PORT").unwrap().defaulted);
+        assert!(names().contains("SFU_TEST_FILE_ONLY_LIST"));
+    }
+
+    #[test]
+    fn test_env_only_values_are_read() {
+        std::env::set_var("SFU_TEST_ENV_ONLY_HOST", "10.0.0.1");
+        std::env::remove_var("SFU_TEST_ENV_ONLY_UNSET");
+
+        assert_eq!(get_string("SFU_TEST_ENV_ONLY_HOST").as_deref(), Some("10.0.0.1"));
+        // Neither set: the caller's default
+        assert_eq!(get_parsed::<u16>("SFU_TEST_ENV_ONLY_UNSET"), None);
+        assert!(read("SFU_TEST_ENV_ONLY_UNSET").unwrap().defaulted);
+    }
+
+    #[test]
+    fn test_env_takes_precedence_over_file() {
+        std::env::set_var("SFU_TEST_MIXED_PORT", "7000");
+        std::env::set_var("SFU_TEST_MIXED_EMPTY", " ");
+        std::env::remove_var("SFU_TEST_MIXED_DIR");
+        set_file(&[
+            ("SFU_TEST_MIXED_PORT", "9000"),
+            ("SFU_TEST_MIXED_EMPTY", "from-file"),
+            ("SFU_TEST_MIXED_DIR", "/srv/recordings"),
+        ]);
+
+        assert_eq!(get_parsed::<u16>("SFU_TEST_MIXED_PORT"), Some(7000));
+        // Empty counts as unset, so the file fills it in
+        assert_eq!(get_string("SFU_TEST_MIXED_EMPTY").as_deref(), Some("from-file"));
+        assert_eq!(get_string("SFU_TEST_MIXED_DIR").as_deref(), Some("/srv/recordings"));
+
+        // An invalid environment value is not replaced by the file's
+        std::env::set_var("SFU_TEST_MIXED_PORT", "seventy");
+        assert_eq!(get_parsed::<u16>("SFU_TEST_MIXED_PORT"), None);
+        assert!(read("SFU_TEST_MIXED_PORT").unwrap().warning.is_some());
+    }
+
+    #[test]
+    fn test_older_names_are_read_when_current_is_unset() {
+        let (current, older) = TEST_ALIASES[0];
+        std::env::remove_var(current);
+        std::env::set_var(older, "5000");
+        assert_eq!(get_parsed::<u16>(current), Some(5000));
+        // Both names are reported, so the older one is never listed as unread
+        assert!(!read(current).unwrap().defaulted);
+        assert!(!read(older).unwrap().defaulted);
+
+        std::env::set_var(current, "6000");
+        assert_eq!(get_parsed::<u16>(current), Some(6000));
+
+        // The older name in the environment still wins over the file
+        std::env::remove_var(current);
+        set_file(&[(current, "7000")]);
+        assert_eq!(get_parsed::<u16>(current), Some(5000));
+
+        std::env::remove_var(older);
+        assert_eq!(get_parsed::<u16>(current), Some(7000));
+    }
+}
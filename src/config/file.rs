@@ -0,0 +1,607 @@
+//! TOML configuration file, given with `--config` or `SFU_CONFIG_FILE`.
+//!
+//! Each setting maps to the environment variable of the same meaning, so
+//! `[recording] output_dir` is `RECORDING_OUTPUT_DIR`. The file only fills in
+//! variables the environment leaves unset; see [`super::env::set_file_values`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigFileError {
+    #[error("Failed to read {}: {}", .0.display(), .1)]
+    Read(PathBuf, std::io::Error),
+
+    #[error("{} is not valid TOML: {}", .0.display(), .1)]
+    Parse(PathBuf, toml::de::Error),
+
+    #[error("{} has {} invalid setting(s): {}", .path.display(), .errors.len(), .errors.join("; "))]
+    Invalid { path: PathBuf, errors: Vec<String> },
+}
+
+/// What a setting accepts
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    String,
+    Bool,
+    /// Non-negative integer up to `max`
+    Integer { max: u64 },
+    /// Integer or float, not negative
+    Number,
+    /// Array of strings, or a single comma-separated string
+    List,
+}
+
+const INTEGER: Kind = Kind::Integer { max: u64::MAX };
+const PORT: Kind = Kind::Integer { max: u16::MAX as u64 };
+
+/// `(key, variable, kind)` of each setting
+type Settings = &'static [(&'static str, &'static str, Kind)];
+
+/// The settings of every section
+const SECTIONS: &[(&str, Settings)] = &[
+    (
+        "server",
+        &[
+            ("host", "SERVER_HOST", Kind::String),
+            ("port", "SERVER_PORT", PORT),
+            ("websocket_url", "SFU_WEBSOCKET_URL", Kind::String),
+            ("tls_cert_path", "TLS_CERT_PATH", Kind::String),
+            ("tls_key_path", "TLS_KEY_PATH", Kind::String),
+            ("cors_allowed_origins", "CORS_ALLOWED_ORIGINS", Kind::List),
+            ("admin_api_token", "ADMIN_API_TOKEN", Kind::String),
+            ("log_filter", "RUST_LOG", Kind::String),
+            ("student_ui_url", "STUDENT_UI_URL", Kind::String),
+            ("proctor_ui_url", "PROCTOR_UI_URL", Kind::String),
+            ("max_rooms_per_proctor", "MAX_ROOMS_PER_PROCTOR", INTEGER),
+            ("max_pending_students", "MAX_PENDING_STUDENTS", INTEGER),
+            ("max_pending_ice_candidates", "MAX_PENDING_ICE_CANDIDATES", INTEGER),
+            ("pending_student_ttl_secs", "PENDING_STUDENT_TTL_SECS", INTEGER),
+            ("max_sdp_bytes", "MAX_SDP_BYTES", INTEGER),
+            ("pli_min_interval_ms", "PLI_MIN_INTERVAL_MS", INTEGER),
+            ("slow_handler_warn_ms", "SLOW_HANDLER_WARN_MS", INTEGER),
+            ("close_room_min_recording_secs", "CLOSE_ROOM_MIN_RECORDING_SECS", INTEGER),
+            ("shutdown_timeout_secs", "SHUTDOWN_TIMEOUT_SECS", INTEGER),
+            ("task_shutdown_timeout_secs", "TASK_SHUTDOWN_TIMEOUT_SECS", INTEGER),
+        ],
+    ),
+    (
+        "sfu",
+        &[
+            ("max_peers", "SFU_MAX_PEERS", INTEGER),
+            ("max_room_peers", "SFU_MAX_ROOM_PEERS", INTEGER),
+            ("signaling_rate_limit", "SFU_SIGNALING_RATE_LIMIT", INTEGER),
+            ("alternate_server", "SFU_ALTERNATE_SERVER", Kind::String),
+            ("retry_base_secs", "SFU_RETRY_BASE_SECS", INTEGER),
+            ("retry_max_secs", "SFU_RETRY_MAX_SECS", INTEGER),
+            ("disconnect_grace_secs", "SFU_DISCONNECT_GRACE_SECS", INTEGER),
+            ("ws_ping_interval_secs", "SFU_WS_PING_INTERVAL_SECS", INTEGER),
+            ("ws_ping_timeout_secs", "SFU_WS_PING_TIMEOUT_SECS", INTEGER),
+            ("ws_max_unexpected_frames", "SFU_WS_MAX_UNEXPECTED_FRAMES", INTEGER),
+            ("diag_log_lines", "SFU_DIAG_LOG_LINES", INTEGER),
+            ("diag_transcript_messages", "SFU_DIAG_TRANSCRIPT_MESSAGES", INTEGER),
+            ("diag_max_connections", "SFU_DIAG_MAX_CONNECTIONS", INTEGER),
+        ],
+    ),
+    (
+        "instance",
+        &[
+            ("id", "INSTANCE_ID", Kind::String),
+            ("public_url", "INSTANCE_PUBLIC_URL", Kind::String),
+        ],
+    ),
+    (
+        "room",
+        &[
+            ("registry_dir", "ROOM_REGISTRY_DIR", Kind::String),
+            ("state_history", "ROOM_STATE_HISTORY", INTEGER),
+            ("event_log_limit", "ROOM_EVENT_LOG_LIMIT", INTEGER),
+            ("manifest_upload_wait_secs", "ROOM_MANIFEST_UPLOAD_WAIT_SECS", INTEGER),
+        ],
+    ),
+    (
+        "join",
+        &[
+            ("escalation_secs", "JOIN_ESCALATION_SECS", INTEGER),
+            ("escalation_alert", "JOIN_ESCALATION_ALERT", Kind::Bool),
+        ],
+    ),
+    (
+        "renegotiation",
+        &[
+            ("debounce_ms", "RENEGOTIATION_DEBOUNCE_MS", INTEGER),
+            ("max_batch_ms", "RENEGOTIATION_MAX_BATCH_MS", INTEGER),
+            ("max_tracks_per_offer", "RENEGOTIATION_MAX_TRACKS_PER_OFFER", INTEGER),
+            ("answer_timeout_ms", "RENEGOTIATION_ANSWER_TIMEOUT_MS", INTEGER),
+            ("retry_backoff_ms", "RENEGOTIATION_RETRY_BACKOFF_MS", INTEGER),
+        ],
+    ),
+    (
+        "rtcp",
+        &[
+            ("remb_enabled", "RTCP_REMB_ENABLED", Kind::Bool),
+            ("remb_max_bitrate_bps", "RTCP_REMB_MAX_BITRATE_BPS", INTEGER),
+            ("report_interval_ms", "RTCP_REPORT_INTERVAL_MS", INTEGER),
+        ],
+    ),
+    (
+        "log",
+        &[
+            ("first_packets", "LOG_FIRST_PACKETS", INTEGER),
+            ("track_summary_secs", "LOG_TRACK_SUMMARY_SECS", INTEGER),
+        ],
+    ),
+    (
+        "recording",
+        &[
+            ("enabled", "RECORDING_ENABLED", Kind::Bool),
+            ("output_dir", "RECORDING_OUTPUT_DIR", Kind::String),
+            ("format", "RECORDING_FORMAT", Kind::String),
+            ("transcode", "RECORDING_TRANSCODE", Kind::Bool),
+            ("storage", "RECORDING_STORAGE", Kind::String),
+            ("min_free_bytes", "RECORDING_MIN_FREE_BYTES", INTEGER),
+            ("max_duration_secs", "RECORDING_MAX_DURATION_SECS", INTEGER),
+            ("retention_hours", "RECORDING_RETENTION_HOURS", INTEGER),
+            ("delete_after_upload", "RECORDING_DELETE_AFTER_UPLOAD", Kind::Bool),
+            ("fallback_rtp", "RECORDING_FALLBACK_RTP", Kind::Bool),
+            ("dir_mode", "RECORDING_DIR_MODE", Kind::String),
+            ("allow_lax_perms", "RECORDING_ALLOW_LAX_PERMS", Kind::Bool),
+            ("hash_workers", "RECORDING_HASH_WORKERS", INTEGER),
+            ("download_chunk_bytes", "RECORDING_DOWNLOAD_CHUNK_BYTES", INTEGER),
+            ("keyframe_interval_secs", "RECORDING_KEYFRAME_INTERVAL_SECS", INTEGER),
+            ("gap_incident_secs", "RECORDING_GAP_INCIDENT_SECS", INTEGER),
+        ],
+    ),
+    (
+        "ipfs",
+        &[
+            ("enabled", "IPFS_ENABLED", Kind::Bool),
+            ("backend", "IPFS_BACKEND", Kind::String),
+            ("api_url", "IPFS_API_URL", Kind::String),
+            ("gateway_url", "IPFS_GATEWAY_URL", Kind::String),
+            ("service_token", "IPFS_SERVICE_TOKEN", Kind::String),
+            ("auto_pin", "IPFS_AUTO_PIN", Kind::Bool),
+            ("upload_timeout_secs", "IPFS_UPLOAD_TIMEOUT_SECS", INTEGER),
+            ("upload_retries", "IPFS_UPLOAD_RETRIES", INTEGER),
+            ("upload_max_mbps", "IPFS_UPLOAD_MAX_MBPS", Kind::Number),
+            ("upload_adaptive_media_mbps", "IPFS_UPLOAD_ADAPTIVE_MEDIA_MBPS", Kind::Number),
+            ("upload_quiet_hours", "IPFS_UPLOAD_QUIET_HOURS", Kind::String),
+        ],
+    ),
+    (
+        "s3",
+        &[
+            ("endpoint", "S3_ENDPOINT", Kind::String),
+            ("bucket", "S3_BUCKET", Kind::String),
+            ("access_key", "S3_ACCESS_KEY", Kind::String),
+            ("secret_key", "S3_SECRET_KEY", Kind::String),
+            ("region", "S3_REGION", Kind::String),
+            ("part_size_mb", "S3_PART_SIZE_MB", INTEGER),
+            ("upload_timeout_secs", "S3_UPLOAD_TIMEOUT_SECS", INTEGER),
+        ],
+    ),
+    (
+        "webrtc",
+        &[
+            ("stun_server_urls", "STUN_SERVER_URLS", Kind::List),
+            ("turn_server_url", "TURN_SERVER_URL", Kind::List),
+            ("turn_username", "TURN_USERNAME", Kind::String),
+            ("turn_credential", "TURN_CREDENTIAL", Kind::String),
+            ("ice_transport_policy", "ICE_TRANSPORT_POLICY", Kind::String),
+            ("codecs", "WEBRTC_CODECS", Kind::List),
+            ("header_extensions", "WEBRTC_HEADER_EXTENSIONS", Kind::List),
+            ("nack", "WEBRTC_NACK", Kind::Bool),
+            ("twcc", "WEBRTC_TWCC", Kind::Bool),
+            ("public_ip", "SFU_PUBLIC_IP", Kind::List),
+            ("udp_port_min", "SFU_UDP_PORT_MIN", PORT),
+            ("udp_port_max", "SFU_UDP_PORT_MAX", PORT),
+            ("udp_mux_port", "WEBRTC_UDP_MUX_PORT", PORT),
+        ],
+    ),
+    (
+        "asset_hub",
+        &[
+            ("enabled", "ASSET_HUB_ENABLED", Kind::Bool),
+            ("rpc_url", "ASSET_HUB_RPC_URL", Kind::String),
+            ("private_key", "ASSET_HUB_PRIVATE_KEY", Kind::String),
+            ("contract_address", "ASSET_HUB_CONTRACT_ADDRESS", Kind::String),
+            ("submission_timeout_secs", "ASSET_HUB_SUBMISSION_TIMEOUT_SECS", INTEGER),
+            ("retry_count", "ASSET_HUB_RETRY_COUNT", INTEGER),
+            ("gas_limit", "ASSET_HUB_GAS_LIMIT", INTEGER),
+            ("gas_margin_percent", "ASSET_HUB_GAS_MARGIN_PERCENT", INTEGER),
+            ("queue_path", "ASSET_HUB_QUEUE_PATH", Kind::String),
+        ],
+    ),
+    (
+        "asr",
+        &[
+            ("webhook_url", "ASR_WEBHOOK_URL", Kind::String),
+            ("webhook_token", "ASR_WEBHOOK_TOKEN", Kind::String),
+            ("callback_base_url", "ASR_CALLBACK_BASE_URL", Kind::String),
+            ("callback_secret", "ASR_CALLBACK_SECRET", Kind::String),
+            ("job_timeout_secs", "ASR_JOB_TIMEOUT_SECS", INTEGER),
+            ("submit_attempts", "ASR_SUBMIT_ATTEMPTS", INTEGER),
+        ],
+    ),
+    ("integrity", &[("weights", "INTEGRITY_WEIGHTS", Kind::List)]),
+    (
+        "lti",
+        &[
+            ("issuer", "LTI_ISSUER", Kind::String),
+            ("client_id", "LTI_CLIENT_ID", Kind::String),
+            ("jwks_url", "LTI_JWKS_URL", Kind::String),
+            ("deployment_ids", "LTI_DEPLOYMENT_IDS", Kind::List),
+            ("invite_secret", "LTI_INVITE_SECRET", Kind::String),
+            ("invite_ttl_secs", "LTI_INVITE_TTL_SECS", INTEGER),
+        ],
+    ),
+    (
+        "tenant",
+        &[
+            ("token_secret", "TENANT_TOKEN_SECRET", Kind::String),
+            ("token_ttl_secs", "TENANT_TOKEN_TTL_SECS", INTEGER),
+        ],
+    ),
+    (
+        "analytics",
+        &[
+            ("enabled", "ANALYTICS_ENABLED", Kind::Bool),
+            ("run_at", "ANALYTICS_RUN_AT", Kind::String),
+            ("timezone", "ANALYTICS_TIMEZONE", Kind::String),
+            ("lookback_days", "ANALYTICS_LOOKBACK_DAYS", INTEGER),
+            ("webhook", "ANALYTICS_WEBHOOK", Kind::Bool),
+        ],
+    ),
+    (
+        "alert",
+        &[
+            ("webhook_url", "ALERT_WEBHOOK_URL", Kind::String),
+            ("webhook_token", "ALERT_WEBHOOK_TOKEN", Kind::String),
+        ],
+    ),
+    (
+        "metrics",
+        &[
+            ("persist", "METRICS_PERSIST", Kind::Bool),
+            ("state_file", "METRICS_STATE_FILE", Kind::String),
+            ("flush_interval_secs", "METRICS_FLUSH_INTERVAL_SECS", INTEGER),
+        ],
+    ),
+    (
+        "ice_selftest",
+        &[
+            ("on_startup", "ICE_SELFTEST_ON_STARTUP", Kind::Bool),
+            ("timeout_secs", "ICE_SELFTEST_TIMEOUT_SECS", INTEGER),
+        ],
+    ),
+    (
+        "chaos",
+        &[
+            ("enabled", "CHAOS_ENABLED", Kind::Bool),
+            ("admin_token", "CHAOS_ADMIN_TOKEN", Kind::String),
+            ("max_duration_secs", "CHAOS_MAX_DURATION_SECS", INTEGER),
+        ],
+    ),
+];
+
+/// `[[webrtc.turn_servers]]` entries, each taking the settings of a numbered
+/// TURN server: the first is `TURN_SERVER_URL_1`, `TURN_USERNAME_1` and
+/// `TURN_CREDENTIAL_1`, and so on
+const TURN_SERVER_KEYS: Settings = &[
+    ("url", "TURN_SERVER_URL", Kind::List),
+    ("username", "TURN_USERNAME", Kind::String),
+    ("credential", "TURN_CREDENTIAL", Kind::String),
+];
+
+/// Reads and validates the file, returning its settings by variable name
+pub fn read(path: &Path) -> Result<BTreeMap<String, String>, ConfigFileError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ConfigFileError::Read(path.to_path_buf(), e))?;
+    let table: toml::Table = toml::from_str(&text).map_err(|e| ConfigFileError::Parse(path.to_path_buf(), e))?;
+    settings(&table).map_err(|errors| ConfigFileError::Invalid {
+        path: path.to_path_buf(),
+        errors,
+    })
+}
+
+/// Settings of a parsed file by variable name, or every problem with it
+fn settings(table: &toml::Table) -> Result<BTreeMap<String, String>, Vec<String>> {
+    let mut values = BTreeMap::new();
+    let mut errors = Vec::new();
+
+    for (section, value) in table {
+        let Some((_, keys)) = SECTIONS.iter().find(|(name, _)| name == section) else {
+            errors.push(match value {
+                toml::Value::Table(_) => format!("unknown section [{}]", section),
+                _ => format!("{}: settings belong in a section such as [server]", section),
+            });
+            continue;
+        };
+        let toml::Value::Table(settings) = value else {
+            errors.push(format!("{}: expected a [{}] section", section, section));
+            continue;
+        };
+
+        for (key, value) in settings {
+            if section == "webrtc" && key == "turn_servers" {
+                turn_servers(value, &mut values, &mut errors);
+                continue;
+            }
+            let path = format!("{}.{}", section, key);
+            insert(keys, &path, key, value, "", &mut values, &mut errors);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(values)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Adds the setting `key` of `keys` to `values` under its variable plus
+/// `suffix`, or reports it as `path`
+fn insert(
+    keys: Settings,
+    path: &str,
+    key: &str,
+    value: &toml::Value,
+    suffix: &str,
+    values: &mut BTreeMap<String, String>,
+    errors: &mut Vec<String>,
+) {
+    let Some(&(_, variable, kind)) = keys.iter().find(|(name, _, _)| *name == key) else {
+        errors.push(format!("{}: unknown setting", path));
+        return;
+    };
+    match convert(value, kind) {
+        Some(converted) => {
+            values.insert(format!("{}{}", variable, suffix), converted);
+        }
+        None => errors.push(format!("{}: expected {}", path, expected(kind))),
+    }
+}
+
+/// Numbers the `[[webrtc.turn_servers]]` entries from 1, in file order
+fn turn_servers(value: &toml::Value, values: &mut BTreeMap<String, String>, errors: &mut Vec<String>) {
+    let Some(servers) = value.as_array() else {
+        errors.push("webrtc.turn_servers: expected an array of tables".to_string());
+        return;
+    };
+    for (number, server) in (1..).zip(servers) {
+        let Some(server) = server.as_table() else {
+            errors.push(format!("webrtc.turn_servers[{}]: expected a table", number));
+            continue;
+        };
+        for (key, value) in server {
+            let path = format!("webrtc.turn_servers[{}].{}", number, key);
+            let suffix = format!("_{}", number);
+            insert(TURN_SERVER_KEYS, &path, key, value, &suffix, values, errors);
+        }
+    }
+}
+
+/// The value as its variable would hold it, `None` when `kind` rejects it
+fn convert(value: &toml::Value, kind: Kind) -> Option<String> {
+    match (kind, value) {
+        (Kind::String, toml::Value::String(s)) => Some(s.clone()),
+        (Kind::Bool, toml::Value::Boolean(b)) => Some(b.to_string()),
+        (Kind::Integer { max }, toml::Value::Integer(i)) => {
+            u64::try_from(*i).ok().filter(|&i| i <= max).map(|i| i.to_string())
+        }
+        (Kind::Number, toml::Value::Integer(i)) if *i >= 0 => Some(i.to_string()),
+        (Kind::Number, toml::Value::Float(f)) if f.is_finite() && *f >= 0.0 => Some(f.to_string()),
+        (Kind::List, toml::Value::String(s)) => Some(s.clone()),
+        (Kind::List, toml::Value::Array(items)) => items
+            .iter()
+            .map(toml::Value::as_str)
+            .collect::<Option<Vec<_>>>()
+            .map(|items| items.join(",")),
+        _ => None,
+    }
+}
+
+fn expected(kind: Kind) -> String {
+    match kind {
+        Kind::String => "a string".to_string(),
+        Kind::Bool => "true or false".to_string(),
+        Kind::Integer { max: u64::MAX } => "a non-negative integer".to_string(),
+        Kind::Integer { max } => format!("an integer from 0 to {}", max),
+        Kind::Number => "a non-negative number".to_string(),
+        Kind::List => "a string or an array of strings".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn parse(text: &str) -> Result<BTreeMap<String, String>, Vec<String>> {
+        settings(&toml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn test_settings_map_to_variables() {
+        let values = parse(
+            r#"
+            [server]
+            port = 9000
+
+            [recording]
+            enabled = false
+            output_dir = "/var/lib/sfu/recordings"
+
+            [ipfs]
+            upload_max_mbps = 2.5
+
+            [webrtc]
+            stun_server_urls = ["stun:a.example.com:3478", "stun:b.example.com:3478"]
+            public_ip = "203.0.113.7"
+
+            [[webrtc.turn_servers]]
+            url = ["turn:a.example.com:3478", "turns:a.example.com:5349"]
+            username = "sfu"
+            credential = "secret"
+
+            [[webrtc.turn_servers]]
+            url = "turn:b.example.com:3478"
+
+            [sfu]
+            max_peers = 200
+
+            [asset_hub]
+            gas_margin_percent = 20
+            "#,
+        )
+        .unwrap();
+
+        let expected = [
+            ("ASSET_HUB_GAS_MARGIN_PERCENT", "20"),
+            ("IPFS_UPLOAD_MAX_MBPS", "2.5"),
+            ("RECORDING_ENABLED", "false"),
+            ("RECORDING_OUTPUT_DIR", "/var/lib/sfu/recordings"),
+            ("SERVER_PORT", "9000"),
+            ("SFU_MAX_PEERS", "200"),
+            ("SFU_PUBLIC_IP", "203.0.113.7"),
+            ("STUN_SERVER_URLS", "stun:a.example.com:3478,stun:b.example.com:3478"),
+            ("TURN_CREDENTIAL_1", "secret"),
+            ("TURN_SERVER_URL_1", "turn:a.example.com:3478,turns:a.example.com:5349"),
+            ("TURN_SERVER_URL_2", "turn:b.example.com:3478"),
+            ("TURN_USERNAME_1", "sfu"),
+        ];
+        assert_eq!(
+            values,
+            expected.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>()
+        );
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_every_invalid_setting_is_reported() {
+        let errors = parse(
+            r#"
+            port = 8080
+
+            [server]
+            port = 70000
+            hots = "0.0.0.0"
+
+            [recording]
+            enabled = "yes"
+            min_free_bytes = -1
+
+            [ipfs]
+            upload_max_mbps = -2.0
+
+            [webrtc]
+            codecs = ["vp8", 9]
+
+            [[webrtc.turn_servers]]
+            url = "turn:a.example.com:3478"
+            secret = "hunter2"
+
+            [database]
+            url = "postgres://"
+            "#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "unknown section [database]",
+                "ipfs.upload_max_mbps: expected a non-negative number",
+                "port: settings belong in a section such as [server]",
+                "recording.enabled: expected true or false",
+                "recording.min_free_bytes: expected a non-negative integer",
+                "server.hots: unknown setting",
+                "server.port: expected an integer from 0 to 65535",
+                "webrtc.codecs: expected a string or an array of strings",
+                "webrtc.turn_servers[1].secret: unknown setting",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_reports_the_file() {
+        let dir = std::env::temp_dir().join(format!("sfu-config-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let missing = read(&dir.join("missing.toml")).unwrap_err();
+        assert!(matches!(missing, ConfigFileError::Read(..)));
+
+        let broken = dir.join("broken.toml");
+        std::fs::write(&broken, "[server\nport = 1").unwrap();
+        assert!(matches!(read(&broken).unwrap_err(), ConfigFileError::Parse(..)));
+
+        let invalid = dir.join("invalid.toml");
+        std::fs::write(&invalid, "[server]\nport = \"http\"\n[recording]\nenabled = 1\n").unwrap();
+        let message = read(&invalid).unwrap_err().to_string();
+        assert!(message.contains("2 invalid setting(s)"), "{}", message);
+        assert!(message.contains("server.port") && message.contains("recording.enabled"), "{}", message);
+
+        let valid = dir.join("valid.toml");
+        std::fs::write(&valid, "[server]\nhost = \"127.0.0.1\"\n").unwrap();
+        assert_eq!(read(&valid).unwrap().get("SERVER_HOST").map(String::as_str), Some("127.0.0.1"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Read outside the configuration: the file's own path, the older name of
+    /// `STUN_SERVER_URLS`, the ones systemd sets and compile-time ones
+    const NOT_SETTINGS: &[&str] = &[
+        "SFU_CONFIG_FILE",
+        "STUN_SERVER_URL",
+        "NOTIFY_SOCKET",
+        "WATCHDOG_PID",
+        "WATCHDOG_USEC",
+        "CARGO_PKG_VERSION",
+    ];
+
+    /// Uppercase string literals passed as a first argument, which is how
+    /// variables are read: `env::get_parsed("SFU_MAX_PEERS")`, or through a
+    /// helper as in `limit("SFU_MAX_PEERS")`
+    fn variables_read(code: &str) -> impl Iterator<Item = &str> {
+        code.split('(').skip(1).filter_map(|call| {
+            let literal = call.trim_start().strip_prefix('"')?;
+            let name = &literal[..literal.find('"')?];
+            let is_variable = name.starts_with(|c: char| c.is_ascii_uppercase())
+                && name.contains('_')
+                && !name.ends_with('_')
+                && name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            is_variable.then_some(name)
+        })
+    }
+
+    #[test]
+    fn test_every_variable_read_has_a_setting() {
+        let settable: BTreeSet<&str> = SECTIONS
+            .iter()
+            .flat_map(|(_, keys)| keys.iter().map(|(_, variable, _)| *variable))
+            .collect();
+
+        let mut missing = BTreeSet::new();
+        let mut paths = vec![PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"))];
+        while let Some(path) = paths.pop() {
+            if path.is_dir() {
+                // The CLI reads its own environment, not the server's
+                if !path.ends_with("bin") {
+                    paths.extend(std::fs::read_dir(&path).unwrap().map(|entry| entry.unwrap().path()));
+                }
+                continue;
+            }
+            if path.extension().and_then(|extension| extension.to_str()) != Some("rs") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            let code = text.split("#[cfg(test)]\nmod tests").next().unwrap_or_default();
+            for name in variables_read(code) {
+                if !settable.contains(name) && !NOT_SETTINGS.contains(&name) {
+                    missing.insert(format!("{} ({})", name, path.display()));
+                }
+            }
+        }
+        assert!(missing.is_empty(), "not settable from the configuration file: {:?}", missing);
+        assert!(settable.contains("SFU_MAX_PEERS"));
+    }
+}
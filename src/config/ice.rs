@@ -0,0 +1,249 @@
+//! STUN/TURN servers and ICE policy for the SFU's peer connections, read
+//! once at startup and shared by every connection.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+use super::env;
+
+/// Used when no STUN server is configured
+const DEFAULT_STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+
+/// Which candidates the SFU's peer connections gather
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IceTransportPolicy {
+    #[default]
+    All,
+    /// TURN candidates only, for deployments that must force traffic through TURN
+    Relay,
+}
+
+impl FromStr for IceTransportPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "all" => Ok(Self::All),
+            "relay" => Ok(Self::Relay),
+            _ => Err(format!("unknown ICE transport policy {}, expected all or relay", value)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WebRTCConfig {
+    pub stun_servers: Vec<String>,
+    pub turn_servers: Vec<TurnServer>,
+    pub ice_transport_policy: IceTransportPolicy,
+}
+
+#[derive(Clone)]
+pub struct TurnServer {
+    pub urls: Vec<String>,
+    pub username: String,
+    pub credential: String,
+}
+
+impl Default for WebRTCConfig {
+    fn default() -> Self {
+        Self {
+            stun_servers: vec![DEFAULT_STUN_SERVER.to_string()],
+            turn_servers: Vec::new(),
+            ice_transport_policy: IceTransportPolicy::All,
+        }
+    }
+}
+
+impl WebRTCConfig {
+    /// Reads `STUN_SERVER_URLS`, the TURN servers and `ICE_TRANSPORT_POLICY`.
+    /// Malformed entries are skipped and logged as warnings.
+    pub fn from_env() -> Self {
+        let (config, warnings) = Self::parse(env::get_string, env::names().into_iter());
+        for warning in warnings {
+            tracing::warn!("{}", warning);
+        }
+        config
+    }
+
+    /// Builds the config from the settings `get` returns. `names` are the
+    /// variables that are set, for finding the numbered TURN servers.
+    fn parse(get: impl Fn(&str) -> Option<String>, names: impl Iterator<Item = String>) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+
+        // STUN_SERVER_URL is the older name; it took a list as well
+        let (stun_setting, stun_urls) = match get("STUN_SERVER_URLS") {
+            Some(urls) => ("STUN_SERVER_URLS", urls),
+            None => ("STUN_SERVER_URL", get("STUN_SERVER_URL").unwrap_or_default()),
+        };
+        let mut stun_servers = valid_urls(stun_setting, &stun_urls, &["stun:", "stuns:"], &mut warnings);
+        if stun_servers.is_empty() {
+            stun_servers.push(DEFAULT_STUN_SERVER.to_string());
+        }
+
+        // The unnumbered TURN_SERVER_URL first, then TURN_SERVER_URL_1, _2, ... in order
+        let mut numbers = BTreeSet::new();
+        for name in names {
+            let Some(suffix) = name.strip_prefix("TURN_SERVER_URL_") else { continue };
+            match suffix.parse::<u32>() {
+                Ok(number) => {
+                    numbers.insert(number);
+                }
+                Err(_) => warnings.push(format!("{} is not a numbered TURN server setting, ignoring it", name)),
+            }
+        }
+        let suffixes = std::iter::once(String::new()).chain(numbers.into_iter().map(|number| format!("_{}", number)));
+
+        let mut turn_servers = Vec::new();
+        for suffix in suffixes {
+            let url_setting = format!("TURN_SERVER_URL{}", suffix);
+            let Some(urls) = get(&url_setting) else { continue };
+            let urls = valid_urls(&url_setting, &urls, &["turn:", "turns:"], &mut warnings);
+            let username = get(&format!("TURN_USERNAME{}", suffix));
+            let credential = get(&format!("TURN_CREDENTIAL{}", suffix));
+            match (username, credential) {
+                _ if urls.is_empty() => {
+                    warnings.push(format!("{} has no valid TURN URL, skipping the server", url_setting))
+                }
+                (Some(username), Some(credential)) => turn_servers.push(TurnServer { urls, username, credential }),
+                _ => warnings.push(format!(
+                    "{} needs TURN_USERNAME{} and TURN_CREDENTIAL{}, skipping the server",
+                    url_setting, suffix, suffix
+                )),
+            }
+        }
+
+        let ice_transport_policy = match get("ICE_TRANSPORT_POLICY") {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                warnings.push(format!("ICE_TRANSPORT_POLICY has an invalid value: {}; using all", e));
+                IceTransportPolicy::All
+            }),
+            None => IceTransportPolicy::All,
+        };
+        if ice_transport_policy == IceTransportPolicy::Relay && turn_servers.is_empty() {
+            warnings.push("ICE_TRANSPORT_POLICY is relay but no TURN server is configured, so peers cannot connect".to_string());
+        }
+
+        let config = Self {
+            stun_servers,
+            turn_servers,
+            ice_transport_policy,
+        };
+        (config, warnings)
+    }
+}
+
+/// Entries of a comma-separated list that start with one of `schemes`;
+/// the rest are skipped with a warning
+fn valid_urls(setting: &str, list: &str, schemes: &[&str], warnings: &mut Vec<String>) -> Vec<String> {
+    let mut urls = Vec::new();
+    for url in list.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+        let valid = schemes
+            .iter()
+            .any(|scheme| url.strip_prefix(scheme).is_some_and(|rest| !rest.is_empty()));
+        if valid {
+            urls.push(url.to_string());
+        } else {
+            warnings.push(format!("{} entry {} is not a {} URL, skipping it", setting, url, schemes.join(" or ")));
+        }
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(vars: &[(&str, &str)]) -> (WebRTCConfig, Vec<String>) {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        WebRTCConfig::parse(|name| vars.get(name).cloned(), vars.keys().cloned())
+    }
+
+    #[test]
+    fn test_defaults_to_public_stun_only() {
+        let (config, warnings) = parse(&[]);
+        assert_eq!(config.stun_servers, vec![DEFAULT_STUN_SERVER]);
+        assert!(config.turn_servers.is_empty());
+        assert_eq!(config.ice_transport_policy, IceTransportPolicy::All);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_stun_list_skips_malformed_entries() {
+        let (config, warnings) = parse(&[
+            ("STUN_SERVER_URLS", "stun:a.example.com:3478, http://b.example.com ,,stuns:c.example.com:5349,stun:"),
+            ("STUN_SERVER_URL", "stun:ignored.example.com"),
+        ]);
+        assert_eq!(config.stun_servers, vec!["stun:a.example.com:3478", "stuns:c.example.com:5349"]);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("http://b.example.com"));
+
+        // The older single-server name still works
+        let (config, _) = parse(&[("STUN_SERVER_URL", "stun:legacy.example.com:3478")]);
+        assert_eq!(config.stun_servers, vec!["stun:legacy.example.com:3478"]);
+    }
+
+    #[test]
+    fn test_numbered_turn_servers_in_order() {
+        let (config, warnings) = parse(&[
+            ("TURN_SERVER_URL", "turn:main.example.com:3478"),
+            ("TURN_USERNAME", "main"),
+            ("TURN_CREDENTIAL", "main-secret"),
+            ("TURN_SERVER_URL_10", "turns:ten.example.com:5349"),
+            ("TURN_USERNAME_10", "ten"),
+            ("TURN_CREDENTIAL_10", "ten-secret"),
+            ("TURN_SERVER_URL_2", "turn:two.example.com:3478?transport=udp,turn:two.example.com:3478?transport=tcp"),
+            ("TURN_USERNAME_2", "two"),
+            ("TURN_CREDENTIAL_2", "two-secret"),
+        ]);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+        let usernames: Vec<&str> = config.turn_servers.iter().map(|server| server.username.as_str()).collect();
+        assert_eq!(usernames, vec!["main", "two", "ten"]);
+        assert_eq!(config.turn_servers[1].urls.len(), 2);
+        assert_eq!(config.turn_servers[2].credential, "ten-secret");
+    }
+
+    #[test]
+    fn test_malformed_turn_servers_are_skipped_with_warnings() {
+        let (config, warnings) = parse(&[
+            // No credential
+            ("TURN_SERVER_URL_1", "turn:one.example.com:3478"),
+            ("TURN_USERNAME_1", "one"),
+            // No URL with a TURN scheme
+            ("TURN_SERVER_URL_2", "stun:two.example.com:3478"),
+            ("TURN_USERNAME_2", "two"),
+            ("TURN_CREDENTIAL_2", "two-secret"),
+            // Not numbered
+            ("TURN_SERVER_URL_BACKUP", "turn:backup.example.com:3478"),
+            ("TURN_SERVER_URL_3", "turn:three.example.com:3478"),
+            ("TURN_USERNAME_3", "three"),
+            ("TURN_CREDENTIAL_3", "three-secret"),
+        ]);
+        assert_eq!(config.turn_servers.len(), 1);
+        assert_eq!(config.turn_servers[0].username, "three");
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert!(warnings.iter().any(|w| w.contains("TURN_SERVER_URL_BACKUP")));
+        assert!(warnings.iter().any(|w| w.contains("needs TURN_USERNAME_1 and TURN_CREDENTIAL_1")));
+    }
+
+    #[test]
+    fn test_ice_transport_policy() {
+        let turn = [
+            ("TURN_SERVER_URL", "turn:turn.example.com:3478"),
+            ("TURN_USERNAME", "user"),
+            ("TURN_CREDENTIAL", "secret"),
+        ];
+        let (config, warnings) = parse(&[turn[0], turn[1], turn[2], ("ICE_TRANSPORT_POLICY", "RELAY")]);
+        assert_eq!(config.ice_transport_policy, IceTransportPolicy::Relay);
+        assert!(warnings.is_empty());
+
+        // Relay without TURN is kept, but warned about
+        let (config, warnings) = parse(&[("ICE_TRANSPORT_POLICY", "relay")]);
+        assert_eq!(config.ice_transport_policy, IceTransportPolicy::Relay);
+        assert_eq!(warnings.len(), 1);
+
+        let (config, warnings) = parse(&[("ICE_TRANSPORT_POLICY", "turn-only")]);
+        assert_eq!(config.ice_transport_policy, IceTransportPolicy::All);
+        assert_eq!(warnings.len(), 1);
+    }
+}
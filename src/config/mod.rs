@@ -1,19 +1,107 @@
-use std::env;
+pub mod env;
+mod file;
+mod ice;
+
 use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub use file::ConfigFileError;
+pub use ice::{IceTransportPolicy, WebRTCConfig};
+
+use crate::recording::store::StorageConfig;
+use crate::recording::{DEFAULT_IPFS_UPLOAD_RETRIES, DEFAULT_KEYFRAME_INTERVAL_SECS, DEFAULT_RECORDING_GAP_INCIDENT_SECS};
+use crate::sfu::SfuConfig;
+use crate::substrate::AssetHubConfig;
+use crate::tls::TlsConfig;
 
 pub struct Config {
     pub server: ServerConfig,
     pub recording: RecordingConfig,
+    /// Shared by every peer connection
+    pub webrtc: WebRTCConfig,
+    /// Where recordings are uploaded: IPFS, S3 or nowhere
+    pub storage: StorageConfig,
+    /// On-chain event recording; `None` when it is disabled or incomplete
+    pub asset_hub: Option<AssetHubConfig>,
+    /// Admission, signaling, RTCP and shutdown settings of the SFU
+    pub sfu: SfuConfig,
 }
 
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    /// HTTPS and WSS instead of HTTP and WS when configured
+    pub tls: TlsConfig,
 }
 
+/// Free space below which recordings are refused and stopped, when not configured
+pub const DEFAULT_RECORDING_MIN_FREE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
 pub struct RecordingConfig {
     pub enabled: bool,
     pub output_dir: String,
+    /// Re-encode VP8 and Opus instead of recording them as sent
+    pub transcode: bool,
+    /// Free space the output directory must keep; recordings are refused
+    /// and stopped below it (0 disables)
+    pub min_free_bytes: u64,
+    /// Recordings running longer are stopped (None = no limit)
+    pub max_duration: Option<Duration>,
+    /// Local files older than this are deleted (None = kept forever)
+    pub retention: Option<Duration>,
+    /// Delete a recording's local file once its upload is pinned on IPFS
+    pub delete_after_upload: bool,
+    /// Interval between keyframe requests to recorded publishers (zero disables)
+    pub keyframe_interval: Duration,
+    /// How long a recorded track may go without media before it is a gap (zero disables)
+    pub gap_threshold: Duration,
+    /// Write an RTP dump when the pipeline cannot be built or started
+    pub rtp_fallback: bool,
+    /// Retries of a failed upload before it is reported as failed
+    pub upload_retries: u32,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            output_dir: "./recordings".to_string(),
+            transcode: false,
+            min_free_bytes: DEFAULT_RECORDING_MIN_FREE_BYTES,
+            max_duration: None,
+            retention: None,
+            delete_after_upload: false,
+            keyframe_interval: Duration::from_secs(DEFAULT_KEYFRAME_INTERVAL_SECS),
+            gap_threshold: Duration::from_secs(DEFAULT_RECORDING_GAP_INCIDENT_SECS),
+            rtp_fallback: false,
+            upload_retries: DEFAULT_IPFS_UPLOAD_RETRIES,
+        }
+    }
+}
+
+impl RecordingConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            enabled: env::get_bool("RECORDING_ENABLED", defaults.enabled),
+            output_dir: env::get_string("RECORDING_OUTPUT_DIR").unwrap_or(defaults.output_dir),
+            transcode: env::get_bool("RECORDING_TRANSCODE", defaults.transcode),
+            min_free_bytes: env::get_parsed("RECORDING_MIN_FREE_BYTES").unwrap_or(defaults.min_free_bytes),
+            max_duration: env::get_parsed("RECORDING_MAX_DURATION_SECS")
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            retention: env::get_parsed::<u64>("RECORDING_RETENTION_HOURS")
+                .filter(|&hours| hours > 0)
+                .map(|hours| Duration::from_secs(hours.saturating_mul(3600))),
+            delete_after_upload: env::get_bool("RECORDING_DELETE_AFTER_UPLOAD", defaults.delete_after_upload),
+            keyframe_interval: env::get_duration_secs("RECORDING_KEYFRAME_INTERVAL_SECS", defaults.keyframe_interval),
+            gap_threshold: env::get_duration_secs("RECORDING_GAP_INCIDENT_SECS", defaults.gap_threshold),
+            rtp_fallback: env::get_bool("RECORDING_FALLBACK_RTP", defaults.rtp_fallback),
+            upload_retries: env::get_parsed("IPFS_UPLOAD_RETRIES").unwrap_or(defaults.upload_retries),
+        }
+    }
 }
 
 impl Config {
@@ -22,23 +110,24 @@ impl Config {
 
         Self {
             server: ServerConfig {
-                host: env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
-                port: env::var("SERVER_PORT")
-                    .unwrap_or_else(|_| "8080".to_string())
-                    .parse()
-                    .expect("Invalid SERVER_PORT"),
-            },
-            recording: RecordingConfig {
-                enabled: env::var("RECORDING_ENABLED")
-                    .unwrap_or_else(|_| "true".to_string())
-                    .parse()
-                    .unwrap_or(true),
-                output_dir: env::var("RECORDING_OUTPUT_DIR")
-                    .unwrap_or_else(|_| "./recordings".to_string()),
+                host: env::get_string("SERVER_HOST").unwrap_or_else(|| "0.0.0.0".to_string()),
+                port: env::get_parsed("SERVER_PORT").unwrap_or(8080),
+                tls: TlsConfig::from_env(),
             },
+            recording: RecordingConfig::from_env(),
+            webrtc: WebRTCConfig::from_env(),
+            storage: StorageConfig::from_env(),
+            asset_hub: AssetHubConfig::from_env(),
+            sfu: SfuConfig::from_env(),
         }
     }
 
+    /// Where clients reach the signaling endpoint, as `GET /sfu/config`
+    /// advertises it
+    pub fn websocket_url(&self) -> String {
+        advertised_websocket_url(env::get_string("SFU_WEBSOCKET_URL"), self.server.port, self.server.tls.enabled())
+    }
+
     pub fn bind_address(&self) -> ([u8; 4], u16) {
         let ip_addr = self.parse_host_to_ipv4();
         (ip_addr.octets(), self.server.port)
@@ -74,25 +163,51 @@ impl Config {
     }
 }
 
+/// Reads the file given on the command line, else the one named by
+/// `SFU_CONFIG_FILE`, and returns its path. A variable that is set takes
+/// precedence over the file's value for it, and every invalid setting in the
+/// file is reported at once. Called before logging starts, since the file can
+/// set `RUST_LOG` as well.
+pub fn load_file(cli_path: Option<&Path>) -> Result<Option<PathBuf>, ConfigFileError> {
+    // SFU_CONFIG_FILE may itself come from .env
+    dotenv::dotenv().ok();
+
+    let path = cli_path.map(Path::to_path_buf).or_else(|| env::get_string("SFU_CONFIG_FILE").map(PathBuf::from));
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    env::set_file_values(file::read(&path)?);
+    Ok(Some(path))
+}
+
+/// `SFU_WEBSOCKET_URL`, else this listener on localhost. A TLS listener only
+/// answers `wss://`, so a configured `ws://` URL is upgraded.
+fn advertised_websocket_url(configured: Option<String>, port: u16, tls: bool) -> String {
+    let scheme = if tls { "wss" } else { "ws" };
+    let url = configured.unwrap_or_else(|| format!("{}://localhost:{}/sfu", scheme, port));
+    match url.strip_prefix("ws://") {
+        Some(rest) if tls => format!("wss://{}", rest),
+        _ => url,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn default_recording_config() -> RecordingConfig {
-        RecordingConfig {
-            enabled: true,
-            output_dir: "./recordings".to_string(),
-        }
-    }
-
     #[test]
     fn test_parse_localhost() {
         let config = Config {
             server: ServerConfig {
                 host: "localhost".to_string(),
                 port: 8080,
+                tls: TlsConfig::default(),
             },
-            recording: default_recording_config(),
+            recording: RecordingConfig::default(),
+            webrtc: WebRTCConfig::default(),
+            storage: StorageConfig::default(),
+            asset_hub: None,
+            sfu: SfuConfig::default(),
         };
 
         let addr = config.bind_address();
@@ -105,8 +220,12 @@ mod tests {
             server: ServerConfig {
                 host: "192.168.1.1".to_string(),
                 port: 3000,
+                tls: TlsConfig::default(),
             },
-            recording: default_recording_config(),
+            recording: RecordingConfig::default(),
+            webrtc: WebRTCConfig::default(),
+            storage: StorageConfig::default(),
+            asset_hub: None,
         };
 
         let addr = config.bind_address();
@@ -119,8 +238,12 @@ mod tests {
             server: ServerConfig {
                 host: "0.0.0.0".to_string(),
                 port: 8080,
+                tls: TlsConfig::default(),
             },
-            recording: default_recording_config(),
+            recording: RecordingConfig::default(),
+            webrtc: WebRTCConfig::default(),
+            storage: StorageConfig::default(),
+            asset_hub: None,
         };
 
         let addr = config.bind_address();
@@ -133,8 +256,12 @@ mod tests {
             server: ServerConfig {
                 host: "".to_string(),
                 port: 8080,
+                tls: TlsConfig::default(),
             },
-            recording: default_recording_config(),
+            recording: RecordingConfig::default(),
+            webrtc: WebRTCConfig::default(),
+            storage: StorageConfig::default(),
+            asset_hub: None,
         };
 
         let addr = config.bind_address();
@@ -147,11 +274,27 @@ mod tests {
             server: ServerConfig {
                 host: "invalid-hostname".to_string(),
                 port: 9000,
+                tls: TlsConfig::default(),
             },
-            recording: default_recording_config(),
+            recording: RecordingConfig::default(),
+            webrtc: WebRTCConfig::default(),
+            storage: StorageConfig::default(),
+            asset_hub: None,
         };
 
         let addr = config.bind_address();
         assert_eq!(addr, ([0, 0, 0, 0], 9000));
     }
+
+    #[test]
+    fn test_advertised_websocket_url_follows_tls() {
+        assert_eq!(advertised_websocket_url(None, 8080, false), "ws://localhost:8080/sfu");
+        assert_eq!(advertised_websocket_url(None, 8443, true), "wss://localhost:8443/sfu");
+
+        let configured = Some("ws://sfu.example.com/sfu".to_string());
+        assert_eq!(advertised_websocket_url(configured.clone(), 8080, false), "ws://sfu.example.com/sfu");
+        assert_eq!(advertised_websocket_url(configured, 8443, true), "wss://sfu.example.com/sfu");
+        let secure = Some("wss://sfu.example.com/sfu".to_string());
+        assert_eq!(advertised_websocket_url(secure, 8443, true), "wss://sfu.example.com/sfu");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,432 @@
+//! In-memory capture of what each signaling connection logged and exchanged.
+//!
+//! A tracing layer copies every event emitted inside a connection's `ws` span
+//! (or carrying a `connection_id` field) into that connection's ring buffer,
+//! next to a redacted transcript of its signaling messages. Memory is bounded
+//! three ways: lines per connection, connections kept (the least recently
+//! written one is evicted), and bytes per line.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::redact::redact_message;
+use super::unix_ms;
+use crate::config::env;
+use crate::sfu::PeerKey;
+
+/// Default log lines kept per connection
+pub const DEFAULT_LOG_LINES: usize = 200;
+
+/// Default signaling messages kept per connection
+pub const DEFAULT_TRANSCRIPT_MESSAGES: usize = 100;
+
+/// Default number of connections with a capture
+pub const DEFAULT_MAX_CONNECTIONS: usize = 256;
+
+/// Longest log line or transcript message kept; the rest is cut off
+pub const MAX_LINE_BYTES: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureSettings {
+    pub log_lines: usize,
+    pub transcript_messages: usize,
+    pub max_connections: usize,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> Self {
+        Self {
+            log_lines: DEFAULT_LOG_LINES,
+            transcript_messages: DEFAULT_TRANSCRIPT_MESSAGES,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl CaptureSettings {
+    pub fn from_env() -> Self {
+        let positive = |name: &str, default: usize| env::get_parsed(name).filter(|n: &usize| *n > 0).unwrap_or(default);
+        Self {
+            log_lines: positive("SFU_DIAG_LOG_LINES", DEFAULT_LOG_LINES),
+            transcript_messages: positive("SFU_DIAG_TRANSCRIPT_MESSAGES", DEFAULT_TRANSCRIPT_MESSAGES),
+            max_connections: positive("SFU_DIAG_MAX_CONNECTIONS", DEFAULT_MAX_CONNECTIONS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogLine {
+    /// Unix time in milliseconds
+    pub at: u64,
+    pub level: &'static str,
+    pub target: &'static str,
+    /// Message followed by the event's fields as `name=value`
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// Sent by the client
+    Inbound,
+    /// Sent by the server
+    Outbound,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TranscriptEntry {
+    /// Unix time in milliseconds
+    pub at: u64,
+    pub direction: Direction,
+    /// The message with credentials, names and SDP bodies redacted; a string
+    /// when it was cut off
+    pub message: serde_json::Value,
+}
+
+/// What was captured for one connection, oldest first
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConnectionCapture {
+    pub logs: Vec<LogLine>,
+    /// Lines pushed out of the ring buffer by newer ones
+    pub dropped_log_lines: u64,
+    pub transcript: Vec<TranscriptEntry>,
+    pub dropped_messages: u64,
+}
+
+#[derive(Debug, Default)]
+struct Capture {
+    lines: VecDeque<LogLine>,
+    dropped_lines: u64,
+    /// Redacted message text, parsed back when a bundle is built
+    transcript: VecDeque<(u64, Direction, String)>,
+    dropped_messages: u64,
+    /// Write counter value of the latest write, for eviction
+    touched: u64,
+}
+
+#[derive(Debug, Default)]
+struct Captures {
+    connections: HashMap<u64, Capture>,
+    peers: HashMap<PeerKey, u64>,
+    writes: u64,
+}
+
+impl Captures {
+    fn touch(&mut self, connection_id: u64, max_connections: usize) -> &mut Capture {
+        self.writes += 1;
+        if !self.connections.contains_key(&connection_id) && self.connections.len() >= max_connections {
+            let oldest = self.connections.iter().min_by_key(|(_, capture)| capture.touched).map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                self.connections.remove(&oldest);
+                self.peers.retain(|_, id| *id != oldest);
+            }
+        }
+        let capture = self.connections.entry(connection_id).or_default();
+        capture.touched = self.writes;
+        capture
+    }
+}
+
+/// Per-connection log and transcript ring buffers
+pub struct ConnectionLogs {
+    settings: CaptureSettings,
+    captures: Mutex<Captures>,
+}
+
+impl ConnectionLogs {
+    pub fn new(settings: CaptureSettings) -> Self {
+        Self {
+            settings,
+            captures: Mutex::new(Captures::default()),
+        }
+    }
+
+    pub fn record_line(&self, connection_id: u64, mut line: LogLine) {
+        truncate(&mut line.message);
+        let mut captures = self.captures.lock().unwrap();
+        let capture = captures.touch(connection_id, self.settings.max_connections);
+        if capture.lines.len() >= self.settings.log_lines {
+            capture.lines.pop_front();
+            capture.dropped_lines += 1;
+        }
+        capture.lines.push_back(line);
+    }
+
+    /// Adds a signaling message to the transcript, redacted before it is stored
+    pub fn record_message(&self, connection_id: u64, direction: Direction, text: &str) {
+        let mut message = redact_message(text);
+        truncate(&mut message);
+        let mut captures = self.captures.lock().unwrap();
+        let capture = captures.touch(connection_id, self.settings.max_connections);
+        if capture.transcript.len() >= self.settings.transcript_messages {
+            capture.transcript.pop_front();
+            capture.dropped_messages += 1;
+        }
+        capture.transcript.push_back((unix_ms(), direction, message));
+    }
+
+    /// Remembers which connection carries `peer`'s signaling, replacing an
+    /// earlier one after a reconnect
+    pub fn bind(&self, peer: PeerKey, connection_id: u64) {
+        let mut captures = self.captures.lock().unwrap();
+        if captures.connections.contains_key(&connection_id) {
+            captures.peers.insert(peer, connection_id);
+        }
+    }
+
+    pub fn connection_for(&self, peer: &PeerKey) -> Option<u64> {
+        self.captures.lock().unwrap().peers.get(peer).copied()
+    }
+
+    pub fn snapshot(&self, connection_id: u64) -> Option<ConnectionCapture> {
+        let captures = self.captures.lock().unwrap();
+        let capture = captures.connections.get(&connection_id)?;
+        Some(ConnectionCapture {
+            logs: capture.lines.iter().cloned().collect(),
+            dropped_log_lines: capture.dropped_lines,
+            transcript: capture
+                .transcript
+                .iter()
+                .map(|(at, direction, message)| TranscriptEntry {
+                    at: *at,
+                    direction: *direction,
+                    message: serde_json::from_str(message).unwrap_or_else(|_| serde_json::Value::String(message.clone())),
+                })
+                .collect(),
+            dropped_messages: capture.dropped_messages,
+        })
+    }
+}
+
+static LOGS: OnceLock<ConnectionLogs> = OnceLock::new();
+
+/// Creates the process-wide buffers from the environment. Called before the
+/// subscriber is installed, since reading settings may itself log.
+pub fn init_from_env() -> &'static ConnectionLogs {
+    LOGS.get_or_init(|| ConnectionLogs::new(CaptureSettings::from_env()))
+}
+
+/// The process-wide buffers; `None` until `init_from_env` has run
+pub fn logs() -> Option<&'static ConnectionLogs> {
+    LOGS.get()
+}
+
+/// Connection a span belongs to, stored in its extensions
+struct SpanConnection(u64);
+
+/// Copies events of connection-scoped spans into `ConnectionLogs`
+pub struct CaptureLayer {
+    logs: &'static ConnectionLogs,
+}
+
+impl CaptureLayer {
+    pub fn new(logs: &'static ConnectionLogs) -> Self {
+        Self { logs }
+    }
+}
+
+impl<S> Layer<S> for CaptureLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = ConnectionIdVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(connection_id), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SpanConnection(connection_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut visitor = LineVisitor::default();
+        event.record(&mut visitor);
+        let connection_id = visitor.connection_id.or_else(|| {
+            ctx.event_scope(event)?
+                .find_map(|span| span.extensions().get::<SpanConnection>().map(|connection| connection.0))
+        });
+        let Some(connection_id) = connection_id else { return };
+
+        let metadata = event.metadata();
+        self.logs.record_line(
+            connection_id,
+            LogLine {
+                at: unix_ms(),
+                level: metadata.level().as_str(),
+                target: metadata.target(),
+                message: visitor.text,
+            },
+        );
+    }
+}
+
+struct ConnectionIdVisitor(Option<u64>);
+
+impl Visit for ConnectionIdVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "connection_id" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "connection_id" {
+            self.0 = u64::try_from(value).ok();
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+#[derive(Default)]
+struct LineVisitor {
+    connection_id: Option<u64>,
+    text: String,
+}
+
+impl LineVisitor {
+    fn push(&mut self, field: &Field, value: std::fmt::Arguments<'_>) {
+        if field.name() == "message" {
+            // The message leads, whatever order the fields came in
+            self.text.insert_str(0, &format!("{}{}", value, if self.text.is_empty() { "" } else { " " }));
+        } else {
+            if !self.text.is_empty() {
+                self.text.push(' ');
+            }
+            let _ = write!(self.text, "{}={}", field.name(), value);
+        }
+    }
+}
+
+impl Visit for LineVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "connection_id" {
+            self.connection_id = Some(value);
+            return;
+        }
+        self.push(field, format_args!("{}", value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "connection_id" {
+            self.connection_id = u64::try_from(value).ok();
+            return;
+        }
+        self.push(field, format_args!("{}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, format_args!("{}", value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, format_args!("{:?}", value));
+    }
+}
+
+/// Cuts `text` to `MAX_LINE_BYTES` on a character boundary
+fn truncate(text: &mut String) {
+    if text.len() <= MAX_LINE_BYTES {
+        return;
+    }
+    let mut end = MAX_LINE_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.truncate(end);
+    text.push('…');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn line(message: &str) -> LogLine {
+        LogLine {
+            at: 0,
+            level: "INFO",
+            target: "test",
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_keeps_newest_lines() {
+        let logs = ConnectionLogs::new(CaptureSettings {
+            log_lines: 3,
+            transcript_messages: 2,
+            max_connections: 4,
+        });
+        for n in 0..10 {
+            logs.record_line(1, line(&format!("line {}", n)));
+        }
+        for n in 0..5 {
+            logs.record_message(1, Direction::Inbound, &format!(r#"{{"type":"Ping","n":{}}}"#, n));
+        }
+
+        let capture = logs.snapshot(1).unwrap();
+        let messages: Vec<&str> = capture.logs.iter().map(|line| line.message.as_str()).collect();
+        assert_eq!(messages, vec!["line 7", "line 8", "line 9"]);
+        assert_eq!(capture.dropped_log_lines, 7);
+        assert_eq!(capture.transcript.len(), 2);
+        assert_eq!(capture.transcript[1].message["n"], 4);
+        assert_eq!(capture.dropped_messages, 3);
+    }
+
+    #[test]
+    fn test_connections_and_line_size_are_bounded() {
+        let logs = ConnectionLogs::new(CaptureSettings {
+            log_lines: 10,
+            transcript_messages: 10,
+            max_connections: 2,
+        });
+        logs.record_line(1, line("first"));
+        logs.record_line(2, line("second"));
+        logs.bind(PeerKey::new("room_1", "student_1"), 1);
+        // Connection 1 is written again, so connection 2 is the one evicted
+        logs.record_line(1, line(&"x".repeat(MAX_LINE_BYTES * 4)));
+        logs.record_line(3, line("third"));
+
+        assert!(logs.snapshot(2).is_none());
+        assert!(logs.snapshot(3).is_some());
+        let long = &logs.snapshot(1).unwrap().logs[1].message;
+        assert!(long.len() <= MAX_LINE_BYTES + '…'.len_utf8());
+
+        // Evicting a connection forgets the peer bound to it
+        assert_eq!(logs.connection_for(&PeerKey::new("room_1", "student_1")), Some(1));
+        logs.record_line(4, line("fourth"));
+        logs.record_line(5, line("fifth"));
+        assert_eq!(logs.connection_for(&PeerKey::new("room_1", "student_1")), None);
+        let kept: Vec<u64> = (1..=5).filter(|id| logs.snapshot(*id).is_some()).collect();
+        assert_eq!(kept, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_layer_captures_events_in_connection_span() {
+        let logs: &'static ConnectionLogs = Box::leak(Box::new(ConnectionLogs::new(CaptureSettings::default())));
+        let subscriber = tracing_subscriber::registry().with(CaptureLayer::new(logs));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("ws", connection_id = 7u64);
+            span.in_scope(|| {
+                tracing::info!(peer_id = "student_1", "Joined room");
+            });
+            tracing::info!("Outside any connection");
+            tracing::warn!(connection_id = 8u64, "Tagged explicitly");
+        });
+
+        let capture = logs.snapshot(7).unwrap();
+        assert_eq!(capture.logs.len(), 1);
+        assert_eq!(capture.logs[0].message, "Joined room peer_id=student_1");
+        assert_eq!(capture.logs[0].level, "INFO");
+        assert_eq!(logs.snapshot(8).unwrap().logs[0].level, "WARN");
+        assert_eq!(logs.snapshot(8).unwrap().logs[0].message, "Tagged explicitly");
+    }
+}
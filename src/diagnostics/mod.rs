@@ -0,0 +1,199 @@
+//! Per-connection diagnostics for support: everything known about one peer's
+//! transport gathered into a single JSON bundle, served by
+//! `GET /sfu/rooms/{room_id}/peers/{peer_id}/diagnostics`.
+//!
+//! Most of the bundle is read from live state when it is requested. What has
+//! to be kept as it happens is kept here: the last log lines and signaling
+//! messages of every connection (`capture`), and the state changes of each
+//! peer connection (`StateHistory`).
+
+mod capture;
+mod redact;
+mod sdp;
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::stats::StatsReportType;
+
+use crate::recording::{MediaGap, RecordingDetail};
+use crate::sfu::rtcp::TrackReceiveStats;
+use crate::sfu::{PeerKey, PeerRole};
+
+pub use capture::{init_from_env, logs, CaptureLayer, ConnectionCapture, Direction};
+pub use sdp::{summarize_sdp, SdpSummary};
+
+/// State changes kept per peer connection
+const STATE_HISTORY_LIMIT: usize = 64;
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifies a signaling connection in logs and captures, unique per process
+pub fn next_connection_id() -> u64 {
+    NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Adds a signaling message to the connection's transcript, if capture is on
+pub fn record_message(connection_id: u64, direction: Direction, text: &str) {
+    if let Some(logs) = logs() {
+        logs.record_message(connection_id, direction, text);
+    }
+}
+
+/// Associates a peer with the connection its signaling arrives on
+pub fn bind_peer(peer: PeerKey, connection_id: u64) {
+    if let Some(logs) = logs() {
+        logs.bind(peer, connection_id);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateChange {
+    /// Unix time in milliseconds
+    pub at: u64,
+    /// `ice`, `ice_gathering` or `peer_connection`
+    pub component: &'static str,
+    pub state: String,
+}
+
+/// The most recent state changes of one peer connection
+#[derive(Debug, Default)]
+pub struct StateHistory {
+    changes: Mutex<VecDeque<StateChange>>,
+}
+
+impl StateHistory {
+    pub fn record(&self, component: &'static str, state: impl ToString) {
+        let mut changes = self.changes.lock().unwrap();
+        if changes.len() >= STATE_HISTORY_LIMIT {
+            changes.pop_front();
+        }
+        changes.push_back(StateChange {
+            at: unix_ms(),
+            component,
+            state: state.to_string(),
+        });
+    }
+
+    pub fn changes(&self) -> Vec<StateChange> {
+        self.changes.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Negotiated transport of a peer connection
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransportDiagnostics {
+    pub connection_state: String,
+    pub ice_connection_state: String,
+    pub ice_gathering_state: String,
+    pub signaling_state: String,
+    pub local_sdp: Option<SdpSummary>,
+    pub remote_sdp: Option<SdpSummary>,
+    /// Full descriptions, only when asked for with `full_sdp=true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local_sdp_full: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_sdp_full: Option<String>,
+    pub selected_candidate_pair: Option<String>,
+    /// WebRTC stats reports of the candidate pairs and candidates
+    pub candidate_pairs: Vec<serde_json::Value>,
+    pub local_candidates: Vec<serde_json::Value>,
+    pub remote_candidates: Vec<serde_json::Value>,
+    pub state_history: Vec<StateChange>,
+}
+
+impl TransportDiagnostics {
+    /// Reads the current state, descriptions and ICE stats of `pc`
+    pub async fn collect(pc: &RTCPeerConnection, history: &StateHistory, full_sdp: bool) -> Self {
+        // Pending descriptions included, so a negotiation that stalled shows what it stalled on
+        let local = pc.local_description().await.map(|description| description.sdp);
+        let remote = pc.remote_description().await.map(|description| description.sdp);
+        let selected = pc.dtls_transport().ice_transport().get_selected_candidate_pair().await;
+
+        let mut transport = Self {
+            connection_state: pc.connection_state().to_string(),
+            ice_connection_state: pc.ice_connection_state().to_string(),
+            ice_gathering_state: pc.ice_gathering_state().to_string(),
+            signaling_state: pc.signaling_state().to_string(),
+            local_sdp: local.as_deref().map(summarize_sdp),
+            remote_sdp: remote.as_deref().map(summarize_sdp),
+            selected_candidate_pair: selected.map(|pair| pair.to_string()),
+            state_history: history.changes(),
+            ..Default::default()
+        };
+        if full_sdp {
+            transport.local_sdp_full = local;
+            transport.remote_sdp_full = remote;
+        }
+
+        let mut reports: Vec<_> = pc.get_stats().await.reports.into_iter().collect();
+        reports.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, report) in reports {
+            let list = match report {
+                StatsReportType::CandidatePair(_) => &mut transport.candidate_pairs,
+                StatsReportType::LocalCandidate(_) => &mut transport.local_candidates,
+                StatsReportType::RemoteCandidate(_) => &mut transport.remote_candidates,
+                _ => continue,
+            };
+            if let Ok(value) = serde_json::to_value(&report) {
+                list.push(value);
+            }
+        }
+        transport
+    }
+}
+
+/// The peer's recording, while one is in progress
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingDiagnostics {
+    #[serde(flatten)]
+    pub detail: RecordingDetail,
+    /// Gaps closed so far
+    pub gaps: Vec<MediaGap>,
+}
+
+/// Everything support needs to look into one peer's connection
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsBundle {
+    pub room_id: String,
+    pub peer_id: String,
+    /// Missing once the peer has left the room
+    pub role: Option<PeerRole>,
+    /// Unix time in milliseconds
+    pub generated_at: u64,
+    /// Signaling connection the capture below belongs to
+    pub connection_id: Option<u64>,
+    /// Missing when the peer has no peer connection, e.g. while waiting for approval
+    pub transport: Option<TransportDiagnostics>,
+    /// Receive and forwarding stats of the tracks the peer publishes
+    pub tracks: Vec<TrackReceiveStats>,
+    pub recording: Option<RecordingDiagnostics>,
+    #[serde(flatten)]
+    pub capture: ConnectionCapture,
+}
+
+fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_history_is_bounded() {
+        let history = StateHistory::default();
+        for n in 0..STATE_HISTORY_LIMIT + 5 {
+            history.record("ice", n);
+        }
+        let changes = history.changes();
+        assert_eq!(changes.len(), STATE_HISTORY_LIMIT);
+        assert_eq!(changes[0].state, "5");
+        assert_eq!(changes.last().unwrap().component, "ice");
+    }
+}
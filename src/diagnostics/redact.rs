@@ -0,0 +1,84 @@
+use serde_json::Value;
+
+/// Fields whose values identify a person or grant access
+const REDACTED_FIELDS: &[&str] = &[
+    "name",
+    "exam_name",
+    "wallet_address",
+    "proctor_token",
+    "invite_token",
+    "token",
+    "signature",
+    "text",
+];
+
+/// A signaling message as it goes into a diagnostics transcript: tokens,
+/// names, wallets and free text are replaced, SDP bodies are reduced to their
+/// size and ICE candidates to their type. Text that isn't JSON is reduced to
+/// its size.
+pub fn redact_message(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => Value::String(format!("[{} bytes, not JSON]", text.len())).to_string(),
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                match (key.as_str(), &*field) {
+                    (_, Value::Null) => {}
+                    ("sdp", Value::String(sdp)) => *field = Value::String(format!("[sdp, {} bytes]", sdp.len())),
+                    ("candidate", Value::String(candidate)) => {
+                        let kind = candidate_type(candidate).unwrap_or("unknown");
+                        *field = Value::String(format!("[candidate typ {}]", kind));
+                    }
+                    (key, _) if REDACTED_FIELDS.contains(&key) => *field = Value::String("[redacted]".to_string()),
+                    _ => redact(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// The `typ` of an ICE candidate line, which is all a transcript keeps of it
+fn candidate_type(candidate: &str) -> Option<&str> {
+    let mut parts = candidate.split_whitespace();
+    parts.find(|part| *part == "typ")?;
+    parts.next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credentials_and_sdp_are_redacted() {
+        let message = r#"{"type":"RoomCreated","room_id":"123456","proctor_token":"secret","peers":[{"peer_id":"s1","name":"Ada","wallet_address":"0xabc"}],"sdp":"v=0\r\no=- 1 1 IN IP4 0.0.0.0\r\n","timezone":null}"#;
+        let redacted: Value = serde_json::from_str(&redact_message(message)).unwrap();
+
+        assert_eq!(redacted["room_id"], "123456");
+        assert_eq!(redacted["proctor_token"], "[redacted]");
+        assert_eq!(redacted["peers"][0]["peer_id"], "s1");
+        assert_eq!(redacted["peers"][0]["name"], "[redacted]");
+        assert_eq!(redacted["peers"][0]["wallet_address"], "[redacted]");
+        assert_eq!(redacted["sdp"], "[sdp, 29 bytes]");
+        assert!(redacted["timezone"].is_null());
+    }
+
+    #[test]
+    fn test_candidates_keep_only_their_type() {
+        let message = r#"{"type":"IceCandidate","peer_id":"sfu","candidate":"candidate:1 1 udp 2130706431 192.0.2.10 50000 typ host","sdp_mid":"0"}"#;
+        let redacted: Value = serde_json::from_str(&redact_message(message)).unwrap();
+        assert_eq!(redacted["candidate"], "[candidate typ host]");
+        assert_eq!(redacted["sdp_mid"], "0");
+
+        assert_eq!(redact_message("not json"), r#""[8 bytes, not JSON]""#);
+    }
+}
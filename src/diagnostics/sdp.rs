@@ -0,0 +1,133 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// What was negotiated in one session description, without addresses or keys
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SdpSummary {
+    /// Size of the full description
+    pub bytes: usize,
+    /// `a=group:BUNDLE` mids
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bundle: Vec<String>,
+    pub ice_lite: bool,
+    pub media: Vec<MediaSummary>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MediaSummary {
+    /// `audio`, `video` or `application`
+    pub kind: String,
+    pub mid: Option<String>,
+    /// `sendrecv`, `sendonly`, `recvonly` or `inactive`
+    pub direction: Option<String>,
+    /// DTLS role from `a=setup`
+    pub setup: Option<String>,
+    /// `payload_type name/clock[/channels]` in the order offered
+    pub codecs: Vec<String>,
+    /// Candidates in the description, by type
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub candidates: BTreeMap<String, usize>,
+    /// Port 0: the section was rejected or stopped
+    pub rejected: bool,
+}
+
+const DIRECTIONS: &[&str] = &["sendrecv", "sendonly", "recvonly", "inactive"];
+
+/// Summarizes an SDP for a diagnostics bundle
+pub fn summarize_sdp(sdp: &str) -> SdpSummary {
+    let mut summary = SdpSummary {
+        bytes: sdp.len(),
+        ..Default::default()
+    };
+    for line in sdp.lines().map(str::trim_end) {
+        if let Some(media) = line.strip_prefix("m=") {
+            let mut fields = media.split_whitespace();
+            summary.media.push(MediaSummary {
+                kind: fields.next().unwrap_or_default().to_string(),
+                rejected: fields.next() == Some("0"),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(attribute) = line.strip_prefix("a=") else { continue };
+        let (name, value) = attribute.split_once(':').unwrap_or((attribute, ""));
+
+        let Some(media) = summary.media.last_mut() else {
+            match name {
+                "group" => {
+                    if let Some(mids) = value.strip_prefix("BUNDLE") {
+                        summary.bundle = mids.split_whitespace().map(str::to_string).collect();
+                    }
+                }
+                "ice-lite" => summary.ice_lite = true,
+                _ => {}
+            }
+            continue;
+        };
+        match name {
+            "mid" => media.mid = Some(value.to_string()),
+            "setup" => media.setup = Some(value.to_string()),
+            "rtpmap" => media.codecs.push(value.to_string()),
+            "candidate" => {
+                let mut parts = value.split_whitespace();
+                if parts.any(|part| part == "typ") {
+                    if let Some(kind) = parts.next() {
+                        *media.candidates.entry(kind.to_string()).or_default() += 1;
+                    }
+                }
+            }
+            direction if DIRECTIONS.contains(&direction) => media.direction = Some(direction.to_string()),
+            _ => {}
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_lists_media_codecs_and_candidates() {
+        let sdp = "v=0\r\n\
+            o=- 4215 2 IN IP4 127.0.0.1\r\n\
+            s=-\r\n\
+            t=0 0\r\n\
+            a=group:BUNDLE 0 1\r\n\
+            m=video 9 UDP/TLS/RTP/SAVPF 96 98\r\n\
+            c=IN IP4 0.0.0.0\r\n\
+            a=setup:actpass\r\n\
+            a=mid:0\r\n\
+            a=sendrecv\r\n\
+            a=rtpmap:96 VP8/90000\r\n\
+            a=rtpmap:98 VP9/90000\r\n\
+            a=candidate:1 1 udp 2130706431 192.0.2.10 50000 typ host\r\n\
+            a=candidate:2 1 udp 1694498815 198.51.100.7 50000 typ srflx raddr 0.0.0.0 rport 50000\r\n\
+            a=candidate:3 1 udp 2130706431 192.0.2.11 50001 typ host\r\n\
+            m=audio 0 UDP/TLS/RTP/SAVPF 111\r\n\
+            a=mid:1\r\n\
+            a=inactive\r\n\
+            a=rtpmap:111 opus/48000/2\r\n";
+
+        let summary = summarize_sdp(sdp);
+        assert_eq!(summary.bytes, sdp.len());
+        assert_eq!(summary.bundle, vec!["0", "1"]);
+        assert!(!summary.ice_lite);
+        assert_eq!(summary.media.len(), 2);
+
+        let video = &summary.media[0];
+        assert_eq!(video.kind, "video");
+        assert_eq!(video.mid.as_deref(), Some("0"));
+        assert_eq!(video.direction.as_deref(), Some("sendrecv"));
+        assert_eq!(video.setup.as_deref(), Some("actpass"));
+        assert_eq!(video.codecs, vec!["96 VP8/90000", "98 VP9/90000"]);
+        assert_eq!(video.candidates.get("host"), Some(&2));
+        assert_eq!(video.candidates.get("srflx"), Some(&1));
+        assert!(!video.rejected);
+
+        let audio = &summary.media[1];
+        assert_eq!(audio.codecs, vec!["111 opus/48000/2"]);
+        assert_eq!(audio.direction.as_deref(), Some("inactive"));
+        assert!(audio.rejected);
+    }
+}
@@ -106,13 +106,34 @@ pub enum SfuError {
     #[error("ICE connection failed for peer {0}")]
     IceConnectionFailed(String),
 
+    /// Recording errors
+    #[error("Recording failed: {0}")]
+    RecordingFailed(String),
+
+    #[error("Invalid recording state: {0}")]
+    InvalidRecordingState(String),
+
+    #[error("Recording disabled: {0}")]
+    RecordingDisabled(String),
+
     /// IPFS errors
     #[error("IPFS upload failed: {0}")]
     IpfsUploadFailed(String),
 
+    #[error("IPFS pin failed: {0}")]
+    IpfsPinFailed(String),
+
     #[error("IPFS node not reachable")]
     IpfsNodeUnavailable,
 
+    /// S3 errors
+    #[error("S3 request failed: {0}")]
+    S3RequestFailed(String),
+
+    /// Transcript (ASR webhook) errors
+    #[error("Transcript job failed: {0}")]
+    TranscriptFailed(String),
+
     /// Substrate/Aleph Zero errors
     #[error("Failed to connect to Substrate node: {0}")]
     SubstrateConnection(String),
@@ -0,0 +1,120 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::env;
+
+/// Request timeout for a single alert webhook delivery
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Operational alert raised by the server
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    /// Stable identifier, e.g. `ice_turn_unreachable`
+    pub kind: &'static str,
+    pub message: String,
+    pub details: serde_json::Value,
+    pub instance_id: Option<String>,
+    /// Unix time in milliseconds
+    pub raised_at: u64,
+}
+
+impl Alert {
+    pub fn new(kind: &'static str, message: impl Into<String>, details: serde_json::Value) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            details,
+            instance_id: env::get_string("INSTANCE_ID"),
+            raised_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Destination for alerts: always the error log, plus a webhook when configured
+pub struct Alerter {
+    webhook_url: Option<String>,
+    webhook_token: Option<String>,
+    client: reqwest::Client,
+}
+
+static ALERTER: OnceLock<Alerter> = OnceLock::new();
+
+/// Process-wide alerter, configured from `ALERT_WEBHOOK_URL` and `ALERT_WEBHOOK_TOKEN`
+pub fn alerter() -> &'static Alerter {
+    ALERTER.get_or_init(Alerter::from_env)
+}
+
+impl Alerter {
+    pub fn from_env() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            webhook_url: env::get_string("ALERT_WEBHOOK_URL"),
+            webhook_token: env::get_string("ALERT_WEBHOOK_TOKEN"),
+            client,
+        }
+    }
+
+    /// Logs the alert and posts it to the webhook in the background
+    pub fn raise(&self, alert: Alert) {
+        tracing::error!(
+            alert = alert.kind,
+            details = %alert.details,
+            "{}",
+            alert.message
+        );
+        self.deliver(alert);
+    }
+
+    /// Posts a routine event to the webhook in the background, without
+    /// logging it as an error
+    pub fn deliver(&self, alert: Alert) {
+        let Some(url) = self.webhook_url.clone() else {
+            return;
+        };
+        let mut request = self.client.post(url).json(&alert);
+        if let Some(ref token) = self.webhook_token {
+            request = request.bearer_auth(token);
+        }
+
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    tracing::debug!(alert = alert.kind, "Delivered alert to webhook");
+                }
+                Ok(response) => {
+                    tracing::warn!(alert = alert.kind, status = %response.status(), "Alert webhook rejected alert");
+                }
+                Err(e) => {
+                    tracing::warn!(alert = alert.kind, error = %e, "Failed to deliver alert to webhook");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_serialization() {
+        let alert = Alert::new(
+            "ice_turn_unreachable",
+            "TURN server produced no relay candidates",
+            serde_json::json!({ "url": "turn:turn.example.com:3478" }),
+        );
+        let json = serde_json::to_value(&alert).unwrap();
+
+        assert_eq!(json["kind"], "ice_turn_unreachable");
+        assert_eq!(json["details"]["url"], "turn:turn.example.com:3478");
+        assert!(json["raised_at"].as_u64().unwrap() > 0);
+    }
+}
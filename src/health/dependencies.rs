@@ -0,0 +1,112 @@
+//! Status of the external services the server depends on, reported by the readiness route
+
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+
+/// Bound on each probe, so a hung service cannot stall the health route
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyState {
+    Up,
+    Down,
+    /// Not configured, so not checked
+    Disabled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyStatus {
+    pub status: DependencyState,
+    /// A required dependency that is down makes the instance unready
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    pub fn disabled() -> Self {
+        Self {
+            status: DependencyState::Disabled,
+            required: false,
+            error: None,
+        }
+    }
+
+    pub fn from_result(required: bool, result: Result<(), String>) -> Self {
+        let (status, error) = match result {
+            Ok(()) => (DependencyState::Up, None),
+            Err(e) => (DependencyState::Down, Some(e)),
+        };
+        Self { status, required, error }
+    }
+
+    pub fn is_down(&self) -> bool {
+        self.status == DependencyState::Down
+    }
+}
+
+/// Runs `check` within `PROBE_TIMEOUT`
+pub async fn probe(required: bool, check: impl Future<Output = Result<(), String>>) -> DependencyStatus {
+    let result = tokio::time::timeout(PROBE_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("No response within {}s", PROBE_TIMEOUT.as_secs())));
+    DependencyStatus::from_result(required, result)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Dependencies {
+    pub ipfs: DependencyStatus,
+    pub asset_hub: DependencyStatus,
+    pub gstreamer: DependencyStatus,
+}
+
+impl Dependencies {
+    fn all(&self) -> [(&'static str, &DependencyStatus); 3] {
+        [
+            ("ipfs", &self.ipfs),
+            ("asset_hub", &self.asset_hub),
+            ("gstreamer", &self.gstreamer),
+        ]
+    }
+
+    /// Whether a required dependency is down
+    pub fn has_failure(&self) -> bool {
+        self.all().iter().any(|(_, dependency)| dependency.required && dependency.is_down())
+    }
+
+    /// Optional dependencies that are down; the server keeps serving without them
+    pub fn degraded(&self) -> Vec<&'static str> {
+        self.all()
+            .iter()
+            .filter(|(_, dependency)| !dependency.required && dependency.is_down())
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_required_failures_and_degraded_dependencies() {
+        let dependencies = Dependencies {
+            ipfs: DependencyStatus::from_result(false, Err("connection refused".to_string())),
+            asset_hub: DependencyStatus::disabled(),
+            gstreamer: DependencyStatus::from_result(true, Ok(())),
+        };
+        assert!(!dependencies.has_failure());
+        assert_eq!(dependencies.degraded(), vec!["ipfs"]);
+
+        let hung = probe(true, std::future::pending()).await;
+        let dependencies = Dependencies { asset_hub: hung, ..dependencies };
+        assert!(dependencies.has_failure());
+        assert_eq!(dependencies.degraded(), vec!["ipfs"]);
+        assert_eq!(
+            serde_json::to_value(&dependencies.asset_hub).unwrap(),
+            serde_json::json!({ "status": "down", "required": true, "error": "No response within 2s" })
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often idle background tasks should beat their heartbeat
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress marker for a single background task.
+///
+/// The task calls `beat()` whenever it makes progress (including idle ticks);
+/// the task is considered stalled once `max_silence` passes without a beat.
+pub struct Heartbeat {
+    name: &'static str,
+    max_silence: Duration,
+    epoch: Instant,
+    last_beat_ms: AtomicU64,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        self.beat_at(Instant::now());
+    }
+
+    pub fn beat_at(&self, now: Instant) {
+        let ms = now.saturating_duration_since(self.epoch).as_millis() as u64;
+        self.last_beat_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Time since the last beat
+    pub fn silence(&self, now: Instant) -> Duration {
+        let last = self.epoch + Duration::from_millis(self.last_beat_ms.load(Ordering::Relaxed));
+        now.saturating_duration_since(last)
+    }
+
+    pub fn is_stalled(&self, now: Instant) -> bool {
+        self.silence(now) > self.max_silence
+    }
+}
+
+/// Liveness of a single task as reported by the health endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskLiveness {
+    pub name: &'static str,
+    pub silent_ms: u64,
+    pub max_silence_ms: u64,
+    pub stalled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LivenessReport {
+    pub alive: bool,
+    pub tasks: Vec<TaskLiveness>,
+}
+
+/// Tracks readiness of the process and heartbeats of its critical background tasks
+pub struct HealthMonitor {
+    epoch: Instant,
+    tasks: Mutex<Vec<Arc<Heartbeat>>>,
+    ready: AtomicBool,
+    draining: AtomicBool,
+}
+
+impl HealthMonitor {
+    pub fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            tasks: Mutex::new(Vec::new()),
+            ready: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    /// Registers a background task; the returned heartbeat starts out fresh
+    pub fn register(&self, name: &'static str, max_silence: Duration) -> Arc<Heartbeat> {
+        let heartbeat = Arc::new(Heartbeat {
+            name,
+            max_silence,
+            epoch: self.epoch,
+            last_beat_ms: AtomicU64::new(0),
+        });
+        heartbeat.beat();
+
+        self.tasks.lock().unwrap().push(heartbeat.clone());
+        tracing::debug!(task = name, max_silence_secs = max_silence.as_secs(), "Registered heartbeat");
+        heartbeat
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    /// Marks the process as shutting down: no longer ready, and new work is shed
+    pub fn begin_draining(&self) {
+        self.ready.store(false, Ordering::Relaxed);
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn liveness(&self) -> LivenessReport {
+        self.liveness_at(Instant::now())
+    }
+
+    pub fn liveness_at(&self, now: Instant) -> LivenessReport {
+        let tasks: Vec<TaskLiveness> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|hb| TaskLiveness {
+                name: hb.name,
+                silent_ms: hb.silence(now).as_millis() as u64,
+                max_silence_ms: hb.max_silence.as_millis() as u64,
+                stalled: hb.is_stalled(now),
+            })
+            .collect();
+
+        LivenessReport {
+            alive: tasks.iter().all(|t| !t.stalled),
+            tasks,
+        }
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresh_task_is_alive() {
+        let monitor = HealthMonitor::new();
+        monitor.register("track_processor", Duration::from_secs(30));
+
+        let report = monitor.liveness();
+        assert!(report.alive);
+        assert_eq!(report.tasks.len(), 1);
+        assert_eq!(report.tasks[0].name, "track_processor");
+    }
+
+    #[test]
+    fn test_silent_task_is_stalled() {
+        let monitor = HealthMonitor::new();
+        let heartbeat = monitor.register("chain_processor", Duration::from_secs(30));
+        let start = Instant::now();
+        heartbeat.beat_at(start);
+
+        assert!(monitor.liveness_at(start + Duration::from_secs(29)).alive);
+
+        let report = monitor.liveness_at(start + Duration::from_secs(31));
+        assert!(!report.alive);
+        assert!(report.tasks[0].stalled);
+    }
+
+    #[test]
+    fn test_beat_recovers_liveness() {
+        let monitor = HealthMonitor::new();
+        let heartbeat = monitor.register("runtime", Duration::from_secs(10));
+        let start = Instant::now();
+        heartbeat.beat_at(start);
+
+        let later = start + Duration::from_secs(20);
+        assert!(!monitor.liveness_at(later).alive);
+
+        heartbeat.beat_at(later);
+        assert!(monitor.liveness_at(later).alive);
+    }
+
+    #[test]
+    fn test_one_stalled_task_fails_liveness() {
+        let monitor = HealthMonitor::new();
+        let fast = monitor.register("runtime", Duration::from_secs(10));
+        let slow = monitor.register("chain_processor", Duration::from_secs(600));
+        let start = Instant::now();
+        fast.beat_at(start);
+        slow.beat_at(start);
+
+        let report = monitor.liveness_at(start + Duration::from_secs(60));
+        assert!(!report.alive);
+        assert_eq!(report.tasks.iter().filter(|t| t.stalled).count(), 1);
+    }
+
+    #[test]
+    fn test_readiness_flag() {
+        let monitor = HealthMonitor::new();
+        assert!(!monitor.is_ready());
+        monitor.set_ready(true);
+        assert!(monitor.is_ready());
+    }
+
+    #[test]
+    fn test_draining_clears_readiness() {
+        let monitor = HealthMonitor::new();
+        monitor.set_ready(true);
+        assert!(!monitor.is_draining());
+
+        monitor.begin_draining();
+        assert!(!monitor.is_ready());
+        assert!(monitor.is_draining());
+    }
+}
@@ -0,0 +1,84 @@
+//! Process health: readiness, dependency status, background task heartbeats, alerts and systemd integration
+
+pub mod alert;
+mod dependencies;
+mod heartbeat;
+pub mod systemd;
+
+pub use dependencies::{probe, Dependencies, DependencyStatus};
+pub use heartbeat::{HealthMonitor, Heartbeat, HEARTBEAT_INTERVAL};
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Maximum silence tolerated from the runtime heartbeat task
+const RUNTIME_MAX_SILENCE: Duration = Duration::from_secs(10);
+
+/// Watchdog ping interval when systemd has not requested one (liveness logging only)
+const DEFAULT_WATCHDOG_INTERVAL: Duration = Duration::from_secs(10);
+
+static MONITOR: OnceLock<HealthMonitor> = OnceLock::new();
+
+/// Process-wide health monitor shared by background tasks and the health routes
+pub fn monitor() -> &'static HealthMonitor {
+    MONITOR.get_or_init(HealthMonitor::new)
+}
+
+/// Spawns a tokio task that beats once a second, proving the runtime still schedules work
+pub fn spawn_runtime_heartbeat() {
+    let heartbeat = monitor().register("runtime", RUNTIME_MAX_SILENCE);
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tick.tick().await;
+            heartbeat.beat();
+        }
+    });
+}
+
+/// Starts the watchdog on a dedicated OS thread so a wedged tokio runtime cannot
+/// keep it alive. Sends `WATCHDOG=1` while every heartbeat is fresh and stops
+/// pinging (letting systemd restart the service) once any task stalls.
+pub fn spawn_watchdog() {
+    let timeout = systemd::watchdog_timeout();
+    let interval = timeout.map(|t| t / 2).unwrap_or(DEFAULT_WATCHDOG_INTERVAL);
+
+    tracing::info!(
+        systemd_watchdog = timeout.is_some(),
+        interval_ms = interval.as_millis() as u64,
+        "Starting liveness watchdog"
+    );
+
+    let spawned = std::thread::Builder::new()
+        .name("sfu-watchdog".to_string())
+        .spawn(move || {
+            let mut was_alive = true;
+            loop {
+                std::thread::sleep(interval);
+
+                let report = monitor().liveness();
+                if report.alive {
+                    if timeout.is_some() {
+                        systemd::notify("WATCHDOG=1");
+                    }
+                    if !was_alive {
+                        tracing::info!("All background tasks are making progress again");
+                    }
+                } else {
+                    let stalled: Vec<&str> = report
+                        .tasks
+                        .iter()
+                        .filter(|t| t.stalled)
+                        .map(|t| t.name)
+                        .collect();
+                    tracing::error!(stalled = ?stalled, "Background tasks stalled, withholding watchdog ping");
+                }
+                was_alive = report.alive;
+            }
+        });
+
+    if let Err(e) = spawned {
+        tracing::error!(error = %e, "Failed to start watchdog thread");
+    }
+}
@@ -0,0 +1,114 @@
+//! Minimal sd_notify(3) client.
+//!
+//! Activated only when systemd sets `NOTIFY_SOCKET`; every call is a no-op otherwise,
+//! so the server behaves the same when run outside a `Type=notify` unit.
+
+use std::env;
+use std::io;
+use std::time::Duration;
+
+/// Sends a state string such as `READY=1` to the service manager.
+/// Returns false if not running under systemd or the send failed.
+pub fn notify(state: &str) -> bool {
+    let Ok(socket_path) = env::var("NOTIFY_SOCKET") else {
+        return false;
+    };
+
+    match send(&socket_path, state) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!(error = %e, state = %state, "Failed to notify systemd");
+            false
+        }
+    }
+}
+
+/// Watchdog interval requested by systemd (`WatchdogSec=`), if any
+pub fn watchdog_timeout() -> Option<Duration> {
+    parse_watchdog(
+        env::var("WATCHDOG_USEC").ok().as_deref(),
+        env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    )
+}
+
+fn parse_watchdog(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    // WATCHDOG_PID, when set, must name this process
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    match usec?.parse::<u64>().ok()? {
+        0 => None,
+        usec => Some(Duration::from_micros(usec)),
+    }
+}
+
+#[cfg(unix)]
+fn send(socket_path: &str, state: &str) -> io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+
+    if let Some(name) = socket_path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract notify sockets are only supported on Linux",
+            ));
+        }
+    }
+
+    socket.send_to(state.as_bytes(), socket_path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket_path: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "sd_notify requires unix sockets"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_watchdog() {
+        assert_eq!(parse_watchdog(Some("30000000"), None, 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog(Some("30000000"), Some("42"), 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog(Some("abc"), None, 42), None);
+        assert_eq!(parse_watchdog(None, None, 42), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_to_path_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::temp_dir().join(format!("sfu-notify-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send(path.to_str().unwrap(), "READY=1").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
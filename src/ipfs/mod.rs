@@ -1,48 +1,170 @@
+mod throttle;
+
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use reqwest::multipart::{Form, Part};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tokio::fs::File;
-use tokio::io::AsyncReadExt;
 
+use crate::chaos::{self, ChaosTarget};
+use crate::config::env;
 use crate::error::{Result, SfuError};
+use crate::metrics;
+
+pub use throttle::{QuietHours, ThrottleStatus, UploadProgress, UploadThrottle};
 
 const DEFAULT_IPFS_API_URL: &str = "http://127.0.0.1:5001";
 const DEFAULT_IPFS_GATEWAY_URL: &str = "http://127.0.0.1:8080/ipfs";
 
+/// Where uploads go: the operator's own node or a remote pinning service
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageBackend {
+    /// Kubo-compatible node API
+    #[default]
+    Local,
+    /// Pinata `pinFileToIPFS`
+    Pinata,
+    /// web3.storage `upload`
+    Web3Storage,
+}
+
+impl StorageBackend {
+    /// Parses `IPFS_BACKEND`: `local`, `pinata` or `web3storage`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "local" => Some(Self::Local),
+            "pinata" => Some(Self::Pinata),
+            "web3storage" | "web3.storage" => Some(Self::Web3Storage),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Pinata => "pinata",
+            Self::Web3Storage => "web3storage",
+        }
+    }
+
+    /// Pinning services keep everything uploaded to them pinned, and need a token
+    pub fn is_pinning_service(&self) -> bool {
+        *self != Self::Local
+    }
+
+    fn default_api_url(&self) -> &'static str {
+        match self {
+            Self::Local => DEFAULT_IPFS_API_URL,
+            Self::Pinata => "https://api.pinata.cloud",
+            Self::Web3Storage => "https://api.web3.storage",
+        }
+    }
+
+    fn upload_path(&self) -> &'static str {
+        match self {
+            Self::Local => "/api/v0/add",
+            Self::Pinata => "/pinning/pinFileToIPFS",
+            Self::Web3Storage => "/upload",
+        }
+    }
+
+    /// CID and size from an upload response. Each backend names the CID
+    /// differently; web3.storage reports no size, so `len` is used.
+    fn parse_upload_response(&self, body: &str, len: u64) -> Result<(String, u64)> {
+        let parsed = match self {
+            Self::Local => serde_json::from_str::<IpfsAddResponse>(body)
+                .map(|add| (add.hash, add.size.parse().unwrap_or(0))),
+            Self::Pinata => serde_json::from_str::<PinataPinResponse>(body)
+                .map(|pin| (pin.ipfs_hash, pin.pin_size)),
+            Self::Web3Storage => serde_json::from_str::<Web3StorageUploadResponse>(body)
+                .map(|upload| (upload.cid, len)),
+        };
+        parsed.map_err(|e| SfuError::IpfsUploadFailed(format!("Failed to parse response: {}", e)))
+    }
+
+    fn default_gateway_url(&self) -> &'static str {
+        match self {
+            Self::Local => DEFAULT_IPFS_GATEWAY_URL,
+            Self::Pinata => "https://gateway.pinata.cloud/ipfs",
+            Self::Web3Storage => "https://w3s.link/ipfs",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct IpfsConfig {
     pub enabled: bool,
+    pub backend: StorageBackend,
+    /// Bearer token for the pinning service; unused by the local backend
+    pub service_token: Option<String>,
     pub api_url: String,
     pub gateway_url: String,
     pub upload_timeout_secs: u64,
+    /// Global upload bandwidth cap in megabits per second (None = unlimited)
+    pub upload_max_mbps: Option<f64>,
+    /// Live media throughput above which the upload cap is halved
+    pub upload_adaptive_media_mbps: Option<f64>,
+    /// UTC hours during which the full upload cap is always allowed
+    pub upload_quiet_hours: Option<QuietHours>,
+    /// Pin every upload so the node's garbage collector keeps it
+    pub auto_pin: bool,
 }
 
 impl IpfsConfig {
     pub fn from_env() -> Option<Self> {
-        let enabled = std::env::var("IPFS_ENABLED")
-            .unwrap_or_else(|_| "false".to_string())
-            .parse()
-            .unwrap_or(false);
+        let enabled = env::get_bool("IPFS_ENABLED", false);
 
         if !enabled {
             return None;
         }
 
-        let api_url = std::env::var("IPFS_API_URL")
-            .unwrap_or_else(|_| DEFAULT_IPFS_API_URL.to_string());
-        let gateway_url = std::env::var("IPFS_GATEWAY_URL")
-            .unwrap_or_else(|_| DEFAULT_IPFS_GATEWAY_URL.to_string());
-        let upload_timeout_secs = std::env::var("IPFS_UPLOAD_TIMEOUT_SECS")
-            .unwrap_or_else(|_| "300".to_string())
-            .parse()
-            .unwrap_or(300);
+        let backend = env::get_string("IPFS_BACKEND")
+            .and_then(|v| {
+                let parsed = StorageBackend::parse(&v);
+                if parsed.is_none() {
+                    tracing::warn!(value = %v, "Invalid IPFS_BACKEND, expected local, pinata or web3storage");
+                }
+                parsed
+            })
+            .unwrap_or_default();
+        let service_token = env::get_string("IPFS_SERVICE_TOKEN");
+        if backend.is_pinning_service() && service_token.is_none() {
+            tracing::error!(backend = backend.as_str(), "IPFS_BACKEND needs IPFS_SERVICE_TOKEN, IPFS uploads disabled");
+            return None;
+        }
+
+        let api_url = env::get_string("IPFS_API_URL")
+            .unwrap_or_else(|| backend.default_api_url().to_string());
+        let gateway_url = env::get_string("IPFS_GATEWAY_URL")
+            .unwrap_or_else(|| backend.default_gateway_url().to_string());
+        let upload_timeout_secs = env::get_parsed("IPFS_UPLOAD_TIMEOUT_SECS").unwrap_or(300);
+        let upload_max_mbps = env::get_parsed("IPFS_UPLOAD_MAX_MBPS")
+            .filter(|v: &f64| *v > 0.0);
+        let upload_adaptive_media_mbps = env::get_parsed("IPFS_UPLOAD_ADAPTIVE_MEDIA_MBPS")
+            .filter(|v: &f64| *v > 0.0);
+        let upload_quiet_hours = env::get_string("IPFS_UPLOAD_QUIET_HOURS")
+            .and_then(|v| {
+                let parsed = QuietHours::parse(&v);
+                if parsed.is_none() {
+                    tracing::warn!(value = %v, "Invalid IPFS_UPLOAD_QUIET_HOURS, expected e.g. 22-6");
+                }
+                parsed
+            });
+        let auto_pin = env::get_bool("IPFS_AUTO_PIN", false);
 
         Some(Self {
             enabled,
+            backend,
+            service_token,
             api_url,
             gateway_url,
             upload_timeout_secs,
+            upload_max_mbps,
+            upload_adaptive_media_mbps,
+            upload_quiet_hours,
+            auto_pin,
         })
     }
 }
@@ -58,17 +180,50 @@ pub struct IpfsAddResponse {
     pub size: String,
 }
 
+/// Response from IPFS pin/add API
+#[derive(Debug, Clone, Deserialize)]
+struct IpfsPinAddResponse {
+    #[serde(rename = "Pins", default)]
+    pins: Vec<String>,
+}
+
+/// Response from IPFS pin/ls API
+#[derive(Debug, Clone, Deserialize)]
+struct IpfsPinLsResponse {
+    #[serde(rename = "Keys", default)]
+    keys: HashMap<String, serde_json::Value>,
+}
+
+/// Response from Pinata's pinFileToIPFS API
+#[derive(Debug, Clone, Deserialize)]
+struct PinataPinResponse {
+    #[serde(rename = "IpfsHash")]
+    ipfs_hash: String,
+    #[serde(rename = "PinSize", default)]
+    pin_size: u64,
+}
+
+/// Response from web3.storage's upload API, which reports no size
+#[derive(Debug, Clone, Deserialize)]
+struct Web3StorageUploadResponse {
+    cid: String,
+}
+
 /// Result of uploading a file to IPFS
 #[derive(Debug, Clone)]
 pub struct IpfsUploadResult {
     pub cid: String,
     pub gateway_url: String,
     pub size: u64,
+    /// Pinned after the add; false when auto-pinning is off or the pin failed.
+    /// Always true for pinning services.
+    pub pinned: bool,
 }
 
 pub struct IpfsClient {
     config: IpfsConfig,
     client: reqwest::Client,
+    throttle: Arc<UploadThrottle>,
 }
 
 impl IpfsClient {
@@ -78,15 +233,37 @@ impl IpfsClient {
             .build()
             .map_err(|e| SfuError::Internal(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self { config, client })
+        let throttle = Arc::new(UploadThrottle::new(
+            config.upload_max_mbps,
+            config.upload_adaptive_media_mbps,
+            config.upload_quiet_hours,
+        ));
+        if throttle.is_limited() {
+            tracing::info!(
+                max_mbps = ?config.upload_max_mbps,
+                adaptive_media_mbps = ?config.upload_adaptive_media_mbps,
+                quiet_hours = ?config.upload_quiet_hours,
+                "IPFS upload throttling enabled"
+            );
+            throttle.spawn_adaptive();
+        }
+
+        Ok(Self { config, client, throttle })
+    }
+
+    /// Shared upload throttle, also fed with live media byte counts
+    pub fn throttle(&self) -> &Arc<UploadThrottle> {
+        &self.throttle
     }
 
-    /// Upload a file to IPFS and return the CID
+    /// Upload a file to IPFS and return the CID. A tenant is added to the
+    /// pin metadata of pinning services and to the MFS path on a local node.
     pub async fn upload_file(
         &self,
         file_path: &Path,
         room_id: &str,
         peer_id: &str,
+        tenant: Option<&str>,
     ) -> Result<IpfsUploadResult> {
         let file_name = file_path
             .file_name()
@@ -94,35 +271,93 @@ impl IpfsClient {
             .unwrap_or("recording.webm")
             .to_string();
 
-        // Read file contents
-        let mut file = File::open(file_path).await.map_err(|e| {
+        chaos::check(ChaosTarget::Ipfs, Some(room_id)).await?;
+
+        // Stream file contents through the shared bandwidth throttle
+        let file = File::open(file_path).await.map_err(|e| {
             SfuError::Internal(format!("Failed to open file for upload: {}", e))
         })?;
 
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer).await.map_err(|e| {
+        let file_len = file.metadata().await.map_err(|e| {
             SfuError::Internal(format!("Failed to read file for upload: {}", e))
-        })?;
+        })?.len();
+
+        let (upload_id, body_stream) = throttle::throttled_stream(file, self.throttle.clone(), &file_name, file_len);
+
+        let uploaded = self
+            .send_upload(reqwest::Body::wrap_stream(body_stream), file_len, &file_name, tenant)
+            .await;
+        let progress = self.throttle.finish_upload(upload_id);
+        let (cid, size) = match uploaded {
+            Ok(uploaded) => uploaded,
+            Err(e) => {
+                metrics::metrics().ipfs_uploads_failed_total.inc();
+                return Err(e);
+            }
+        };
 
-        // Create multipart form
-        let file_part = Part::bytes(buffer)
-            .file_name(file_name.clone());
+        metrics::metrics().ipfs_uploads_total.inc();
+        let gateway_url = format!("{}/{}", self.config.gateway_url, cid);
+        let pinned = self.auto_pin(&cid).await;
+
+        // Copy file to MFS so it shows up in the node's Web UI
+        if self.config.backend == StorageBackend::Local {
+            if let Err(e) = self.copy_to_mfs(&cid, tenant, room_id, &file_name).await {
+                tracing::warn!(
+                    cid = %cid,
+                    error = %e,
+                    "Failed to copy file to MFS (file is still accessible via CID)"
+                );
+            }
+        }
 
-        let form = Form::new()
-            .part("file", file_part);
+        tracing::info!(
+            cid = %cid,
+            size = size,
+            room_id = %room_id,
+            peer_id = %peer_id,
+            tenant = ?tenant,
+            file_name = %file_name,
+            backend = self.config.backend.as_str(),
+            throughput_mbps = ?progress.map(|p| p.throughput_mbps),
+            "Successfully uploaded recording to IPFS"
+        );
 
-        // IPFS API endpoint for adding files
-        let add_url = format!("{}/api/v0/add", self.config.api_url);
+        Ok(IpfsUploadResult {
+            cid,
+            gateway_url,
+            size,
+            pinned,
+        })
+    }
 
-        // Send request
-        let response = self.client
-            .post(&add_url)
-            .multipart(form)
-            .send()
-            .await
-            .map_err(|e| {
-                SfuError::IpfsUploadFailed(format!("Request failed: {}", e))
-            })?;
+    /// Posts `body` to the configured backend and returns the CID and size it reports
+    async fn send_upload(&self, body: reqwest::Body, len: u64, file_name: &str, tenant: Option<&str>) -> Result<(String, u64)> {
+        let url = format!("{}{}", self.config.api_url, self.config.backend.upload_path());
+        let request = if self.config.backend == StorageBackend::Web3Storage {
+            // web3.storage takes the raw file, named in a header
+            self.client
+                .post(&url)
+                .header("X-Name", urlencoding::encode(file_name).into_owned())
+                .header(reqwest::header::CONTENT_LENGTH, len)
+                .body(body)
+        } else {
+            let file_part = Part::stream_with_length(body, len).file_name(file_name.to_string());
+            let mut form = Form::new().part("file", file_part);
+            if let (StorageBackend::Pinata, Some(tenant)) = (self.config.backend, tenant) {
+                let metadata = serde_json::json!({ "name": file_name, "keyvalues": { "tenant": tenant } });
+                form = form.text("pinataMetadata", metadata.to_string());
+            }
+            self.client.post(&url).multipart(form)
+        };
+        let request = match &self.config.service_token {
+            Some(token) if self.config.backend.is_pinning_service() => request.bearer_auth(token),
+            _ => request,
+        };
+
+        let response = request.send().await.map_err(|e| {
+            SfuError::IpfsUploadFailed(format!("Request failed: {}", e))
+        })?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -133,43 +368,111 @@ impl IpfsClient {
             )));
         }
 
-        let ipfs_response: IpfsAddResponse = response.json().await.map_err(|e| {
-            SfuError::IpfsUploadFailed(format!("Failed to parse response: {}", e))
+        let body = response.text().await.map_err(|e| {
+            SfuError::IpfsUploadFailed(format!("Failed to read response: {}", e))
         })?;
+        self.config.backend.parse_upload_response(&body, len)
+    }
 
-        let cid = &ipfs_response.hash;
-        let gateway_url = format!("{}/{}", self.config.gateway_url, cid);
-        let size: u64 = ipfs_response.size.parse().unwrap_or(0);
-
-        // Copy file to MFS so it shows up in the Web UI
-        if let Err(e) = self.copy_to_mfs(cid, room_id, &file_name).await {
-            tracing::warn!(
-                cid = %cid,
-                error = %e,
-                "Failed to copy file to MFS (file is still accessible via CID)"
-            );
+    /// Pins `cid` when `IPFS_AUTO_PIN` is set. A failed pin is logged and the
+    /// upload still counts: the CID is valid, only at risk of garbage collection.
+    async fn auto_pin(&self, cid: &str) -> bool {
+        if self.config.backend.is_pinning_service() {
+            return true;
         }
+        if !self.config.auto_pin {
+            return false;
+        }
+        match self.pin_file(cid).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::warn!(cid = %cid, error = %e, "Failed to pin upload, it may be garbage-collected");
+                false
+            }
+        }
+    }
 
-        tracing::info!(
-            cid = %cid,
-            size = size,
-            room_id = %room_id,
-            peer_id = %peer_id,
-            file_name = %file_name,
-            "Successfully uploaded recording to IPFS"
-        );
+    /// Pin `cid` recursively on the node
+    pub async fn pin_file(&self, cid: &str) -> Result<()> {
+        let pin_url = format!("{}/api/v0/pin/add?arg={}", self.config.api_url, urlencoding::encode(cid));
+        let response = self.client.post(&pin_url).send().await.map_err(|e| {
+            SfuError::IpfsPinFailed(format!("Request failed: {}", e))
+        })?;
 
-        Ok(IpfsUploadResult {
-            cid: cid.clone(),
-            gateway_url,
-            size,
-        })
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(SfuError::IpfsPinFailed(format!("Pin failed with status {}: {}", status, error_text)));
+        }
+
+        let pinned: IpfsPinAddResponse = response.json().await.map_err(|e| {
+            SfuError::IpfsPinFailed(format!("Failed to parse response: {}", e))
+        })?;
+        if !pinned.pins.iter().any(|pin| pin == cid) {
+            return Err(SfuError::IpfsPinFailed(format!("Node did not report {} as pinned", cid)));
+        }
+
+        tracing::debug!(cid = %cid, "Pinned on IPFS");
+        Ok(())
+    }
+
+    /// Unpin `cid` on the node, so its blocks can be garbage-collected.
+    /// Returns false when it was not pinned. Pinning services are not supported.
+    pub async fn unpin_file(&self, cid: &str) -> Result<bool> {
+        if self.config.backend.is_pinning_service() {
+            return Err(SfuError::IpfsPinFailed(format!(
+                "Unpinning from {} is not supported",
+                self.config.backend.as_str()
+            )));
+        }
+        let rm_url = format!("{}/api/v0/pin/rm?arg={}", self.config.api_url, urlencoding::encode(cid));
+        let response = self.client.post(&rm_url).send().await.map_err(|e| {
+            SfuError::IpfsPinFailed(format!("Request failed: {}", e))
+        })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            if error_text.contains("not pinned") {
+                return Ok(false);
+            }
+            return Err(SfuError::IpfsPinFailed(format!("Unpin failed with status {}: {}", status, error_text)));
+        }
+
+        tracing::debug!(cid = %cid, "Unpinned on IPFS");
+        Ok(true)
+    }
+
+    /// Whether `cid` is pinned on the node, directly or through a parent
+    pub async fn is_pinned(&self, cid: &str) -> Result<bool> {
+        let ls_url = format!("{}/api/v0/pin/ls?arg={}", self.config.api_url, urlencoding::encode(cid));
+        let response = self.client.post(&ls_url).send().await.map_err(|e| {
+            SfuError::IpfsPinFailed(format!("Request failed: {}", e))
+        })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            // The node answers an error rather than an empty list for unpinned CIDs
+            if error_text.contains("not pinned") {
+                return Ok(false);
+            }
+            return Err(SfuError::IpfsPinFailed(format!("Pin check failed with status {}: {}", status, error_text)));
+        }
+
+        let listed: IpfsPinLsResponse = response.json().await.map_err(|e| {
+            SfuError::IpfsPinFailed(format!("Failed to parse response: {}", e))
+        })?;
+        Ok(listed.keys.contains_key(cid))
     }
 
     /// Copy a file to MFS (Mutable File System) so it appears in the Web UI
-    async fn copy_to_mfs(&self, cid: &str, room_id: &str, file_name: &str) -> Result<()> {
-        // Create the directory structure: /recordings/{room_id}/
-        let mfs_dir = format!("/recordings/{}", room_id);
+    async fn copy_to_mfs(&self, cid: &str, tenant: Option<&str>, room_id: &str, file_name: &str) -> Result<()> {
+        // Create the directory structure: /recordings/{room_id}/ or /recordings/{tenant}/{room_id}/
+        let mfs_dir = match tenant {
+            Some(tenant) => format!("/recordings/{}/{}", tenant, room_id),
+            None => format!("/recordings/{}", room_id),
+        };
         let mkdir_url = format!(
             "{}/api/v0/files/mkdir?arg={}&parents=true",
             self.config.api_url,
@@ -179,7 +482,7 @@ impl IpfsClient {
         // Create directory (ignore error if already exists)
         let _ = self.client.post(&mkdir_url).send().await;
 
-        // Copy file from IPFS to MFS: /recordings/{room_id}/{file_name}
+        // Copy file from IPFS to MFS: {mfs_dir}/{file_name}
         let mfs_path = format!("{}/{}", mfs_dir, file_name);
         let cp_url = format!(
             "{}/api/v0/files/cp?arg=/ipfs/{}&arg={}",
@@ -209,8 +512,16 @@ impl IpfsClient {
         Ok(())
     }
 
-    /// Check if IPFS node is reachable
+    /// Check if IPFS node is reachable. A pinning service has no unauthenticated
+    /// status route, so any answer short of a server error counts.
     pub async fn health_check(&self) -> Result<bool> {
+        if self.config.backend.is_pinning_service() {
+            return match self.client.get(&self.config.api_url).send().await {
+                Ok(response) => Ok(!response.status().is_server_error()),
+                Err(_) => Ok(false),
+            };
+        }
+
         let version_url = format!("{}/api/v0/version", self.config.api_url);
 
         match self.client.post(&version_url).send().await {
@@ -231,44 +542,20 @@ impl IpfsClient {
     pub async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<IpfsUploadResult> {
         let name = file_name.unwrap_or("upload.bin").to_string();
 
-        let file_part = Part::bytes(data.to_vec())
-            .file_name(name.clone());
-
-        let form = Form::new()
-            .part("file", file_part);
-
-        let add_url = format!("{}/api/v0/add", self.config.api_url);
-
-        let response = self.client
-            .post(&add_url)
-            .multipart(form)
-            .send()
-            .await
-            .map_err(|e| {
-                SfuError::IpfsUploadFailed(format!("Request failed: {}", e))
-            })?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(SfuError::IpfsUploadFailed(format!(
-                "Upload failed with status {}: {}",
-                status, error_text
-            )));
-        }
+        self.throttle.acquire(data.len() as u64).await;
 
-        let ipfs_response: IpfsAddResponse = response.json().await.map_err(|e| {
-            SfuError::IpfsUploadFailed(format!("Failed to parse response: {}", e))
-        })?;
+        let (cid, size) = self
+            .send_upload(reqwest::Body::from(data.to_vec()), data.len() as u64, &name, None)
+            .await?;
 
-        let cid = ipfs_response.hash;
         let gateway_url = format!("{}/{}", self.config.gateway_url, cid);
-        let size: u64 = ipfs_response.size.parse().unwrap_or(0);
+        let pinned = self.auto_pin(&cid).await;
 
         Ok(IpfsUploadResult {
             cid,
             gateway_url,
             size,
+            pinned,
         })
     }
 }
@@ -276,6 +563,213 @@ impl IpfsClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    /// Kubo-like API that records pins; `pin/add` fails when `pin_fails`
+    fn mock_ipfs(pin_fails: bool) -> (IpfsConfig, Arc<Mutex<Vec<String>>>) {
+        let pins = Arc::new(Mutex::new(Vec::new()));
+
+        let add = warp::path!("api" / "v0" / "add")
+            .and(warp::body::bytes())
+            .map(|_body: bytes::Bytes| warp::reply::json(&json!({"Name": "test.webm", "Hash": "QmMock", "Size": "4"})));
+        let files = warp::path!("api" / "v0" / "files" / String).map(|_command: String| warp::reply());
+        let pin_add = {
+            let pins = pins.clone();
+            warp::path!("api" / "v0" / "pin" / "add")
+                .and(warp::query::<HashMap<String, String>>())
+                .map(move |query: HashMap<String, String>| {
+                    let cid = query.get("arg").cloned().unwrap_or_default();
+                    if pin_fails {
+                        let error = json!({"Message": "pin: context deadline exceeded", "Code": 0, "Type": "error"});
+                        return warp::reply::with_status(warp::reply::json(&error), StatusCode::INTERNAL_SERVER_ERROR);
+                    }
+                    pins.lock().unwrap().push(cid.clone());
+                    warp::reply::with_status(warp::reply::json(&json!({"Pins": [cid]})), StatusCode::OK)
+                })
+        };
+        let pin_ls = {
+            let pins = pins.clone();
+            warp::path!("api" / "v0" / "pin" / "ls")
+                .and(warp::query::<HashMap<String, String>>())
+                .map(move |query: HashMap<String, String>| {
+                    let cid = query.get("arg").cloned().unwrap_or_default();
+                    if pins.lock().unwrap().contains(&cid) {
+                        let keys = json!({"Keys": {cid: {"Type": "recursive"}}});
+                        return warp::reply::with_status(warp::reply::json(&keys), StatusCode::OK);
+                    }
+                    let error = json!({"Message": format!("path '{}' is not pinned", cid), "Code": 0, "Type": "error"});
+                    warp::reply::with_status(warp::reply::json(&error), StatusCode::INTERNAL_SERVER_ERROR)
+                })
+        };
+
+        let pin_rm = {
+            let pins = pins.clone();
+            warp::path!("api" / "v0" / "pin" / "rm")
+                .and(warp::query::<HashMap<String, String>>())
+                .map(move |query: HashMap<String, String>| {
+                    let cid = query.get("arg").cloned().unwrap_or_default();
+                    let mut pins = pins.lock().unwrap();
+                    if pins.contains(&cid) {
+                        pins.retain(|pin| *pin != cid);
+                        return warp::reply::with_status(warp::reply::json(&json!({"Pins": [cid]})), StatusCode::OK);
+                    }
+                    let error = json!({"Message": "not pinned or pinned indirectly", "Code": 0, "Type": "error"});
+                    warp::reply::with_status(warp::reply::json(&error), StatusCode::INTERNAL_SERVER_ERROR)
+                })
+        };
+
+        let route = warp::post().and(add.or(files).or(pin_add).or(pin_ls).or(pin_rm));
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let config = IpfsConfig {
+            enabled: true,
+            backend: StorageBackend::Local,
+            service_token: None,
+            api_url: format!("http://{}", addr),
+            gateway_url: format!("http://{}/ipfs", addr),
+            upload_timeout_secs: 5,
+            upload_max_mbps: None,
+            upload_adaptive_media_mbps: None,
+            upload_quiet_hours: None,
+            auto_pin: true,
+        };
+        (config, pins)
+    }
+
+    /// Pinata- and web3.storage-like upload endpoints that require `Bearer secret`
+    fn mock_pinning_service(backend: StorageBackend) -> IpfsConfig {
+        let authorized = warp::header::exact("authorization", "Bearer secret");
+        let pinata = warp::path!("pinning" / "pinFileToIPFS")
+            .and(authorized.clone())
+            .and(warp::body::bytes())
+            .map(|body: bytes::Bytes| {
+                assert!(String::from_utf8_lossy(&body).contains(r#""keyvalues":{"tenant":"uni-a"}"#));
+                warp::reply::json(&json!({"IpfsHash": "QmPinata", "PinSize": 4, "Timestamp": "2026-10-15T09:00:00.000Z"}))
+            });
+        let web3storage = warp::path!("upload")
+            .and(authorized)
+            .and(warp::header::<String>("x-name"))
+            .and(warp::body::bytes())
+            .map(|name: String, body: bytes::Bytes| {
+                assert_eq!(name, "peer_1.webm");
+                assert_eq!(&body[..], b"webm");
+                warp::reply::json(&json!({"cid": "bafyweb3"}))
+            });
+
+        let (addr, server) = warp::serve(warp::post().and(pinata.or(web3storage))).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        IpfsConfig {
+            enabled: true,
+            backend,
+            service_token: Some("secret".to_string()),
+            api_url: format!("http://{}", addr),
+            gateway_url: "https://gateway.example/ipfs".to_string(),
+            upload_timeout_secs: 5,
+            upload_max_mbps: None,
+            upload_adaptive_media_mbps: None,
+            upload_quiet_hours: None,
+            auto_pin: false,
+        }
+    }
+
+    fn temp_recording(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("sfu-ipfs-{}-{}.webm", name, std::process::id()));
+        std::fs::write(&path, b"webm").unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_pin_and_verify() {
+        let (config, pins) = mock_ipfs(false);
+        let client = IpfsClient::new(config).unwrap();
+
+        assert!(!client.is_pinned("QmOther").await.unwrap());
+        client.pin_file("QmOther").await.unwrap();
+        assert!(client.is_pinned("QmOther").await.unwrap());
+        assert_eq!(*pins.lock().unwrap(), vec!["QmOther".to_string()]);
+
+        assert!(client.unpin_file("QmOther").await.unwrap());
+        assert!(!client.is_pinned("QmOther").await.unwrap());
+        assert!(!client.unpin_file("QmOther").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_upload_auto_pins() {
+        let recording = temp_recording("autopin");
+        let (config, pins) = mock_ipfs(false);
+        let client = IpfsClient::new(config.clone()).unwrap();
+
+        let result = client.upload_file(&recording, "room-1", "peer_1", None).await.unwrap();
+        assert_eq!(result.cid, "QmMock");
+        assert!(result.pinned);
+        assert!(client.is_pinned("QmMock").await.unwrap());
+
+        // Without IPFS_AUTO_PIN the upload is left unpinned
+        pins.lock().unwrap().clear();
+        let client = IpfsClient::new(IpfsConfig { auto_pin: false, ..config }).unwrap();
+        let result = client.upload_file(&recording, "room-1", "peer_1", None).await.unwrap();
+        assert!(!result.pinned);
+        assert!(pins.lock().unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[tokio::test]
+    async fn test_failed_pin_keeps_the_upload() {
+        let recording = temp_recording("pinfail");
+        let (config, _pins) = mock_ipfs(true);
+        let client = IpfsClient::new(config).unwrap();
+
+        let result = client.upload_file(&recording, "room-1", "peer_1", None).await.unwrap();
+        assert_eq!(result.cid, "QmMock");
+        assert!(!result.pinned);
+        assert!(matches!(client.pin_file("QmMock").await, Err(SfuError::IpfsPinFailed(_))));
+        assert!(!client.is_pinned("QmMock").await.unwrap());
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[tokio::test]
+    async fn test_upload_to_pinning_services() {
+        let recording = temp_recording("pinning");
+        let config = mock_pinning_service(StorageBackend::Pinata);
+
+        let result = IpfsClient::new(config.clone()).unwrap().upload_file(&recording, "room-1", "peer_1", Some("uni-a")).await.unwrap();
+        assert_eq!(result.cid, "QmPinata");
+        assert_eq!(result.gateway_url, "https://gateway.example/ipfs/QmPinata");
+        assert_eq!(result.size, 4);
+        assert!(result.pinned);
+
+        let web3storage = IpfsClient::new(IpfsConfig { backend: StorageBackend::Web3Storage, ..config.clone() }).unwrap();
+        let result = web3storage.upload_bytes(b"webm", Some("peer_1.webm")).await.unwrap();
+        assert_eq!(result.cid, "bafyweb3");
+        assert_eq!(result.size, 4);
+        assert!(result.pinned);
+
+        let unauthorized = IpfsClient::new(IpfsConfig { service_token: Some("wrong".to_string()), ..config }).unwrap();
+        assert!(matches!(
+            unauthorized.upload_file(&recording, "room-1", "peer_1", None).await,
+            Err(SfuError::IpfsUploadFailed(_))
+        ));
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[test]
+    fn test_storage_backend_parse() {
+        assert_eq!(StorageBackend::parse("local"), Some(StorageBackend::Local));
+        assert_eq!(StorageBackend::parse(" Pinata "), Some(StorageBackend::Pinata));
+        assert_eq!(StorageBackend::parse("web3storage"), Some(StorageBackend::Web3Storage));
+        assert_eq!(StorageBackend::parse("web3.storage"), Some(StorageBackend::Web3Storage));
+        assert_eq!(StorageBackend::parse("s3"), None);
+        assert!(!StorageBackend::Local.is_pinning_service());
+        assert!(StorageBackend::Web3Storage.is_pinning_service());
+    }
 
     #[test]
     fn test_ipfs_config_disabled_by_default() {
@@ -299,4 +793,35 @@ mod tests {
         assert_eq!(response.name, "test.webm");
         assert_eq!(response.size, "12345");
     }
+
+    #[test]
+    fn test_pinata_response_deserialize() {
+        let json = r#"{
+            "IpfsHash": "QmPinata123",
+            "PinSize": 12345,
+            "Timestamp": "2026-10-15T09:00:00.000Z"
+        }"#;
+
+        let (cid, size) = StorageBackend::Pinata.parse_upload_response(json, 0).unwrap();
+        assert_eq!(cid, "QmPinata123");
+        assert_eq!(size, 12345);
+
+        // The local node's field names are not accepted
+        let local = r#"{"Name": "test.webm", "Hash": "QmTest", "Size": "4"}"#;
+        assert!(matches!(
+            StorageBackend::Pinata.parse_upload_response(local, 0),
+            Err(SfuError::IpfsUploadFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_web3storage_response_deserialize() {
+        let json = r#"{"cid": "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi"}"#;
+
+        let (cid, size) = StorageBackend::Web3Storage.parse_upload_response(json, 12345).unwrap();
+        assert_eq!(cid, "bafybeigdyrzt5sfp7udm7hu76uh7y26nf3efuylqabf3oclgtqy55fbzdi");
+        // No size in the response, the uploaded length is used
+        assert_eq!(size, 12345);
+        assert!(StorageBackend::Web3Storage.parse_upload_response(r#"{"IpfsHash": "Qm"}"#, 0).is_err());
+    }
 }
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use futures::Stream;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::Instant;
+
+/// Size of each chunk read from disk and charged against the bucket
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How often the adaptive task samples media throughput
+const ADAPTIVE_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Fraction of the configured cap used while live media is busy
+const ADAPTIVE_REDUCTION_FACTOR: f64 = 0.5;
+
+/// Converts megabits per second to bytes per second
+pub fn mbps_to_bytes_per_sec(mbps: f64) -> f64 {
+    mbps * 1_000_000.0 / 8.0
+}
+
+/// Token bucket over bytes with a one second burst capacity.
+///
+/// Reservations may drive the bucket negative; the returned wait is the time
+/// until the debt is repaid, which keeps concurrent uploads from starving.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(bytes_per_sec: f64, now: Instant) -> Self {
+        Self {
+            rate: bytes_per_sec,
+            capacity: bytes_per_sec,
+            tokens: bytes_per_sec,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Change the fill rate, keeping accumulated credit within the new capacity
+    pub fn set_rate(&mut self, bytes_per_sec: f64, now: Instant) {
+        self.refill(now);
+        self.rate = bytes_per_sec;
+        self.capacity = bytes_per_sec;
+        self.tokens = self.tokens.min(self.capacity);
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Reserve `bytes` and return how long the caller must wait before sending them
+    pub fn reserve(&mut self, bytes: u64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 || self.rate <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Hour range in UTC during which the full upload cap is always allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct QuietHours {
+    pub start_hour: u8,
+    pub end_hour: u8,
+}
+
+impl QuietHours {
+    /// Parses "22-6" style ranges; the range may wrap past midnight
+    pub fn parse(value: &str) -> Option<Self> {
+        let (start, end) = value.trim().split_once('-')?;
+        let start_hour: u8 = start.trim().parse().ok()?;
+        let end_hour: u8 = end.trim().parse().ok()?;
+        if start_hour > 23 || end_hour > 23 {
+            return None;
+        }
+        Some(Self { start_hour, end_hour })
+    }
+
+    pub fn contains(&self, hour: u8) -> bool {
+        if self.start_hour <= self.end_hour {
+            hour >= self.start_hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+
+    fn contains_now(&self) -> bool {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.contains(((secs / 3600) % 24) as u8)
+    }
+}
+
+/// Progress of a single in-flight upload
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub file_name: String,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+    pub elapsed_secs: f64,
+    pub throughput_mbps: f64,
+}
+
+struct ActiveUpload {
+    file_name: String,
+    bytes_sent: u64,
+    total_bytes: u64,
+    started_at: Instant,
+}
+
+/// Snapshot of the throttle for status endpoints and metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct ThrottleStatus {
+    pub max_mbps: Option<f64>,
+    pub current_mbps: Option<f64>,
+    pub reduced: bool,
+    pub media_mbps: f64,
+    pub quiet_hours: Option<QuietHours>,
+    pub active_uploads: Vec<UploadProgress>,
+}
+
+/// Global upload rate limiter shared by every IPFS upload
+pub struct UploadThrottle {
+    /// Configured cap in bytes/s, None means unlimited
+    max_rate: Option<f64>,
+    /// Media throughput in bytes/s above which the cap is reduced
+    adaptive_threshold: Option<f64>,
+    quiet_hours: Option<QuietHours>,
+    bucket: Mutex<Option<TokenBucket>>,
+    reduced: AtomicBool,
+    media_bytes: AtomicU64,
+    /// Last sampled media throughput in bytes/s
+    media_rate: AtomicU64,
+    uploads: Mutex<HashMap<u64, ActiveUpload>>,
+    next_upload_id: AtomicU64,
+}
+
+impl UploadThrottle {
+    pub fn new(max_mbps: Option<f64>, adaptive_threshold_mbps: Option<f64>, quiet_hours: Option<QuietHours>) -> Self {
+        let max_rate = max_mbps.filter(|m| *m > 0.0).map(mbps_to_bytes_per_sec);
+        Self {
+            max_rate,
+            adaptive_threshold: adaptive_threshold_mbps.filter(|m| *m > 0.0).map(mbps_to_bytes_per_sec),
+            quiet_hours,
+            bucket: Mutex::new(max_rate.map(|rate| TokenBucket::new(rate, Instant::now()))),
+            reduced: AtomicBool::new(false),
+            media_bytes: AtomicU64::new(0),
+            media_rate: AtomicU64::new(0),
+            uploads: Mutex::new(HashMap::new()),
+            next_upload_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Throttle that never waits
+    pub fn unlimited() -> Self {
+        Self::new(None, None, None)
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.max_rate.is_some()
+    }
+
+    /// Wait until `bytes` may be sent without exceeding the cap
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = match self.bucket.lock() {
+            Ok(mut bucket) => bucket
+                .as_mut()
+                .map(|b| b.reserve(bytes, Instant::now()))
+                .unwrap_or(Duration::ZERO),
+            Err(_) => Duration::ZERO,
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Account bytes of live media forwarded by the SFU
+    pub fn record_media_bytes(&self, bytes: u64) {
+        self.media_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Recompute the cap from media throughput sampled over `elapsed`
+    fn adapt(&self, elapsed: Duration, quiet: bool) {
+        let (Some(max_rate), Some(threshold)) = (self.max_rate, self.adaptive_threshold) else {
+            return;
+        };
+
+        let media_bytes = self.media_bytes.swap(0, Ordering::Relaxed);
+        let media_rate = media_bytes as f64 / elapsed.as_secs_f64().max(0.001);
+        self.media_rate.store(media_rate as u64, Ordering::Relaxed);
+
+        let reduce = !quiet && media_rate > threshold;
+        let was_reduced = self.reduced.swap(reduce, Ordering::Relaxed);
+        if reduce == was_reduced {
+            return;
+        }
+
+        let new_rate = if reduce { max_rate * ADAPTIVE_REDUCTION_FACTOR } else { max_rate };
+        if let Ok(mut bucket) = self.bucket.lock() {
+            if let Some(b) = bucket.as_mut() {
+                b.set_rate(new_rate, Instant::now());
+            }
+        }
+
+        tracing::info!(
+            reduced = reduce,
+            quiet_hours = quiet,
+            media_mbps = media_rate * 8.0 / 1_000_000.0,
+            upload_cap_mbps = new_rate * 8.0 / 1_000_000.0,
+            "Adjusted IPFS upload bandwidth cap"
+        );
+    }
+
+    /// Spawns the background task that adapts the cap to live media load
+    pub fn spawn_adaptive(self: &Arc<Self>) {
+        if self.max_rate.is_none() || self.adaptive_threshold.is_none() {
+            return;
+        }
+
+        let throttle = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ADAPTIVE_SAMPLE_INTERVAL);
+            let mut last = Instant::now();
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                let quiet = throttle.quiet_hours.map(|q| q.contains_now()).unwrap_or(false);
+                throttle.adapt(now.duration_since(last), quiet);
+                last = now;
+            }
+        });
+    }
+
+    fn begin_upload(&self, file_name: &str, total_bytes: u64) -> u64 {
+        let id = self.next_upload_id.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut uploads) = self.uploads.lock() {
+            uploads.insert(id, ActiveUpload {
+                file_name: file_name.to_string(),
+                bytes_sent: 0,
+                total_bytes,
+                started_at: Instant::now(),
+            });
+        }
+        id
+    }
+
+    fn record_progress(&self, upload_id: u64, bytes: u64) {
+        if let Ok(mut uploads) = self.uploads.lock() {
+            if let Some(upload) = uploads.get_mut(&upload_id) {
+                upload.bytes_sent += bytes;
+            }
+        }
+    }
+
+    /// Stop tracking an upload and return its final progress
+    pub fn finish_upload(&self, upload_id: u64) -> Option<UploadProgress> {
+        let upload = self.uploads.lock().ok()?.remove(&upload_id)?;
+        Some(Self::progress(&upload))
+    }
+
+    fn progress(upload: &ActiveUpload) -> UploadProgress {
+        let elapsed_secs = upload.started_at.elapsed().as_secs_f64();
+        let throughput_mbps = if elapsed_secs > 0.0 {
+            upload.bytes_sent as f64 * 8.0 / 1_000_000.0 / elapsed_secs
+        } else {
+            0.0
+        };
+        UploadProgress {
+            file_name: upload.file_name.clone(),
+            bytes_sent: upload.bytes_sent,
+            total_bytes: upload.total_bytes,
+            elapsed_secs,
+            throughput_mbps,
+        }
+    }
+
+    pub fn status(&self) -> ThrottleStatus {
+        let current_mbps = self
+            .bucket
+            .lock()
+            .ok()
+            .and_then(|b| b.as_ref().map(|b| b.rate() * 8.0 / 1_000_000.0));
+        let active_uploads = self
+            .uploads
+            .lock()
+            .map(|uploads| uploads.values().map(Self::progress).collect())
+            .unwrap_or_default();
+
+        ThrottleStatus {
+            max_mbps: self.max_rate.map(|r| r * 8.0 / 1_000_000.0),
+            current_mbps,
+            reduced: self.reduced.load(Ordering::Relaxed),
+            media_mbps: self.media_rate.load(Ordering::Relaxed) as f64 * 8.0 / 1_000_000.0,
+            quiet_hours: self.quiet_hours,
+            active_uploads,
+        }
+    }
+}
+
+/// Wraps a reader into a chunked body stream paced by the shared throttle.
+///
+/// Returns the upload id used for progress tracking alongside the stream;
+/// pass it to `UploadThrottle::finish_upload` once the request completes.
+pub fn throttled_stream<R>(
+    reader: R,
+    throttle: Arc<UploadThrottle>,
+    file_name: &str,
+    total_bytes: u64,
+) -> (u64, impl Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let upload_id = throttle.begin_upload(file_name, total_bytes);
+
+    let stream = futures::stream::unfold((reader, throttle), move |(mut reader, throttle)| async move {
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        match reader.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                throttle.acquire(n as u64).await;
+                throttle.record_progress(upload_id, n as u64);
+                Some((Ok(chunk), (reader, throttle)))
+            }
+            Err(e) => Some((Err(e), (reader, throttle))),
+        }
+    });
+
+    (upload_id, stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_mbps_conversion() {
+        assert_eq!(mbps_to_bytes_per_sec(8.0), 1_000_000.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_allows_initial_burst() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1000.0, now);
+        assert_eq!(bucket.reserve(1000, now), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_wait_for_debt() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1000.0, now);
+        bucket.reserve(1000, now);
+        // 500 bytes of debt at 1000 B/s is half a second
+        assert_eq!(bucket.reserve(500, now), Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000.0, start);
+        bucket.reserve(1000, start);
+        let later = start + Duration::from_millis(250);
+        assert_eq!(bucket.reserve(250, later), Duration::ZERO);
+        assert_eq!(bucket.reserve(100, later), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refill_capped_at_capacity() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(1000.0, start);
+        let later = start + Duration::from_secs(60);
+        // A long idle period only accrues one second of burst
+        assert_eq!(bucket.reserve(1000, later), Duration::ZERO);
+        assert_eq!(bucket.reserve(1000, later), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_set_rate_clamps_tokens() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(1000.0, now);
+        bucket.set_rate(500.0, now);
+        assert_eq!(bucket.rate(), 500.0);
+        assert_eq!(bucket.reserve(500, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(500, now), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_quiet_hours_parse_and_wrap() {
+        let quiet = QuietHours::parse("22-6").unwrap();
+        assert!(quiet.contains(23));
+        assert!(quiet.contains(2));
+        assert!(!quiet.contains(6));
+        assert!(!quiet.contains(12));
+
+        let daytime = QuietHours::parse("9-17").unwrap();
+        assert!(daytime.contains(9));
+        assert!(!daytime.contains(17));
+
+        assert!(QuietHours::parse("25-3").is_none());
+        assert!(QuietHours::parse("nonsense").is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_paced_to_cap() {
+        // 1 Mbps = 125_000 B/s; 500_000 bytes with a one second burst takes 3 seconds
+        let throttle = Arc::new(UploadThrottle::new(Some(1.0), None, None));
+        let data = vec![7u8; 500_000];
+        let (upload_id, stream) = throttled_stream(std::io::Cursor::new(data.clone()), throttle.clone(), "test.webm", 500_000);
+
+        let start = Instant::now();
+        let chunks: Vec<Vec<u8>> = stream.map(|c| c.unwrap()).collect().await;
+        let elapsed = start.elapsed();
+
+        let sink: Vec<u8> = chunks.concat();
+        assert_eq!(sink, data);
+        assert!(elapsed >= Duration::from_millis(2900), "elapsed {:?}", elapsed);
+        assert!(elapsed <= Duration::from_millis(3600), "elapsed {:?}", elapsed);
+
+        let progress = throttle.finish_upload(upload_id).unwrap();
+        assert_eq!(progress.bytes_sent, 500_000);
+        assert!(throttle.status().active_uploads.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_streams_share_cap() {
+        let throttle = Arc::new(UploadThrottle::new(Some(1.0), None, None));
+        let (_, a) = throttled_stream(std::io::Cursor::new(vec![0u8; 250_000]), throttle.clone(), "a", 250_000);
+        let (_, b) = throttled_stream(std::io::Cursor::new(vec![0u8; 250_000]), throttle.clone(), "b", 250_000);
+
+        let start = Instant::now();
+        let (a, b) = tokio::join!(a.collect::<Vec<_>>(), b.collect::<Vec<_>>());
+        assert_eq!(a.len() + b.len(), 8);
+        // Same 500_000 total bytes as a single stream, so the same 3 seconds
+        assert!(start.elapsed() >= Duration::from_millis(2900));
+        assert_eq!(throttle.status().active_uploads.len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unlimited_stream_does_not_wait() {
+        let throttle = Arc::new(UploadThrottle::unlimited());
+        let (_, stream) = throttled_stream(std::io::Cursor::new(vec![1u8; 1_000_000]), throttle, "big", 1_000_000);
+        let start = Instant::now();
+        let _: Vec<_> = stream.collect().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_reduces_and_restores_cap() {
+        let throttle = UploadThrottle::new(Some(8.0), Some(80.0), None);
+
+        // 100 Mbps of media over one second exceeds the 80 Mbps threshold
+        throttle.record_media_bytes(12_500_000);
+        throttle.adapt(Duration::from_secs(1), false);
+        let status = throttle.status();
+        assert!(status.reduced);
+        assert_eq!(status.current_mbps, Some(4.0));
+
+        // Quiet hours restore the full cap regardless of media load
+        throttle.record_media_bytes(12_500_000);
+        throttle.adapt(Duration::from_secs(1), true);
+        let status = throttle.status();
+        assert!(!status.reduced);
+        assert_eq!(status.current_mbps, Some(8.0));
+    }
+}
@@ -0,0 +1,208 @@
+//! Tracing setup with a filter that can be changed while the server runs.
+//!
+//! The base filter comes from `RUST_LOG`. Support can raise (or lower) the
+//! level of a single module on a live instance through
+//! `PUT /sfu/admin/log-level`; overrides are layered on top of the base
+//! filter and last until they are cleared or the process restarts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::config::env;
+use crate::diagnostics;
+
+/// Filter used when `RUST_LOG` is unset
+const DEFAULT_FILTER: &str = "info";
+
+/// Body of `PUT /sfu/admin/log-level`; a `null` level removes the override
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogLevelRequest {
+    /// Module path, e.g. `sfu_server::sfu::connection`
+    pub target: String,
+    pub level: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogLevelState {
+    /// Filter currently applied, in `RUST_LOG` syntax
+    pub filter: String,
+    pub overrides: BTreeMap<String, String>,
+}
+
+/// Base filter plus per-target overrides, applied through a reload handle
+pub struct LogLevels {
+    base: String,
+    overrides: Mutex<BTreeMap<String, LevelFilter>>,
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+static LEVELS: OnceLock<LogLevels> = OnceLock::new();
+
+/// Installs the global subscriber. Must be called once, before anything logs.
+pub fn init() {
+    let base = env::get_string("RUST_LOG").unwrap_or_else(|| DEFAULT_FILTER.to_string());
+    let filter = EnvFilter::try_new(&base).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+    let connection_logs = diagnostics::init_from_env();
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(diagnostics::CaptureLayer::new(connection_logs))
+        .init();
+
+    let _ = LEVELS.set(LogLevels::new(base, handle));
+}
+
+/// Runtime log level control; `None` until `init` has run
+pub fn levels() -> Option<&'static LogLevels> {
+    LEVELS.get()
+}
+
+impl LogLevels {
+    pub fn new(base: String, handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        Self {
+            base,
+            overrides: Mutex::new(BTreeMap::new()),
+            handle,
+        }
+    }
+
+    /// Sets or clears the override for one target and reloads the filter
+    pub fn apply(&self, request: LogLevelRequest) -> Result<LogLevelState, String> {
+        let target = request.target.trim();
+        if target.is_empty() || !target.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':') {
+            return Err("target must be a module path such as sfu_server::sfu::connection".to_string());
+        }
+        let level = request
+            .level
+            .map(|level| level.parse::<LevelFilter>().map_err(|_| format!("invalid level: {}", level)))
+            .transpose()?;
+
+        let mut overrides = self.overrides.lock().unwrap();
+        let previous = match level {
+            Some(level) => overrides.insert(target.to_string(), level),
+            None => overrides.remove(target),
+        };
+
+        let directives = Self::directives(&self.base, &overrides);
+        let reloaded = EnvFilter::try_new(&directives)
+            .map_err(|e| e.to_string())
+            .and_then(|filter| self.handle.reload(filter).map_err(|e| e.to_string()));
+        if let Err(e) = reloaded {
+            // Keep the override set in step with the filter actually applied
+            match previous {
+                Some(previous) => overrides.insert(target.to_string(), previous),
+                None => overrides.remove(target),
+            };
+            return Err(format!("failed to apply log filter: {}", e));
+        }
+
+        tracing::info!(target_module = %target, level = ?level, filter = %directives, "Log level override changed");
+        Ok(Self::state(directives, &overrides))
+    }
+
+    pub fn current(&self) -> LogLevelState {
+        let overrides = self.overrides.lock().unwrap();
+        Self::state(Self::directives(&self.base, &overrides), &overrides)
+    }
+
+    fn state(filter: String, overrides: &BTreeMap<String, LevelFilter>) -> LogLevelState {
+        LogLevelState {
+            filter,
+            overrides: overrides
+                .iter()
+                .map(|(target, level)| (target.clone(), level.to_string().to_lowercase()))
+                .collect(),
+        }
+    }
+
+    /// Base directives with overridden targets replaced by the override
+    fn directives(base: &str, overrides: &BTreeMap<String, LevelFilter>) -> String {
+        base.split(',')
+            .map(str::trim)
+            .filter(|directive| !directive.is_empty())
+            .filter(|directive| {
+                let target = directive.split('=').next().unwrap_or_default();
+                !overrides.contains_key(target)
+            })
+            .map(str::to_string)
+            .chain(
+                overrides
+                    .iter()
+                    .map(|(target, level)| format!("{}={}", target, level.to_string().to_lowercase())),
+            )
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(target: &str, level: Option<&str>) -> LogLevelRequest {
+        LogLevelRequest {
+            target: target.to_string(),
+            level: level.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_override_reloads_filter() {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let subscriber = tracing_subscriber::registry().with(layer);
+        let levels = LogLevels::new("info,hyper=warn".to_string(), handle);
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(!tracing::enabled!(target: "sfu_server::sfu::connection", tracing::Level::DEBUG));
+
+            let state = levels.apply(request("sfu_server::sfu::connection", Some("DEBUG"))).unwrap();
+            assert_eq!(state.filter, "info,hyper=warn,sfu_server::sfu::connection=debug");
+            assert_eq!(state.overrides.get("sfu_server::sfu::connection").map(String::as_str), Some("debug"));
+
+            assert!(tracing::enabled!(target: "sfu_server::sfu::connection", tracing::Level::DEBUG));
+            // Other modules keep the base level
+            assert!(!tracing::enabled!(target: "sfu_server::sfu::server", tracing::Level::DEBUG));
+
+            let state = levels.apply(request("sfu_server::sfu::connection", None)).unwrap();
+            assert_eq!(state.filter, "info,hyper=warn");
+            assert!(state.overrides.is_empty());
+            assert!(!tracing::enabled!(target: "sfu_server::sfu::connection", tracing::Level::DEBUG));
+        });
+    }
+
+    #[test]
+    fn test_override_replaces_base_directive_for_target() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let levels = LogLevels::new("info,hyper=warn".to_string(), handle);
+
+        let state = levels.apply(request("hyper", Some("trace"))).unwrap();
+        assert_eq!(state.filter, "info,hyper=trace");
+        assert_eq!(levels.current(), state);
+    }
+
+    #[test]
+    fn test_invalid_requests_leave_filter_unchanged() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let levels = LogLevels::new("info".to_string(), handle);
+
+        assert!(levels.apply(request("sfu_server::sfu", Some("loud"))).is_err());
+        assert!(levels.apply(request("sfu_server=debug", Some("debug"))).is_err());
+        assert!(levels.apply(request("  ", Some("debug"))).is_err());
+        assert_eq!(levels.current().filter, "info");
+    }
+
+    #[test]
+    fn test_reload_fails_once_subscriber_is_gone() {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let levels = LogLevels::new("info".to_string(), handle);
+        drop(layer);
+
+        assert!(levels.apply(request("sfu_server::sfu", Some("debug"))).is_err());
+        assert!(levels.current().overrides.is_empty());
+    }
+}
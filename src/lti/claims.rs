@@ -0,0 +1,149 @@
+//! The parts of an LTI 1.3 resource link launch this server uses
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// `message_type` of a resource link launch, the only one handled
+pub const RESOURCE_LINK_REQUEST: &str = "LtiResourceLinkRequest";
+
+pub const LTI_VERSION: &str = "1.3.0";
+
+const MEMBERSHIP: &str = "http://purl.imsglobal.org/vocab/lis/v2/membership";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LaunchClaims {
+    pub iss: String,
+    /// The platform's ID for the user, stable across launches
+    pub sub: String,
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Unix seconds
+    pub exp: u64,
+    /// Client the token was issued to, when it has more than one audience
+    #[serde(default)]
+    pub azp: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/message_type")]
+    pub message_type: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/version")]
+    pub version: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/deployment_id")]
+    pub deployment_id: String,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/roles", default)]
+    pub roles: Vec<String>,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/resource_link", default)]
+    pub resource_link: Option<ResourceLink>,
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/context", default)]
+    pub context: Option<LaunchContext>,
+    /// Custom parameters the platform adds to the placement
+    #[serde(rename = "https://purl.imsglobal.org/spec/lti/claim/custom", default)]
+    pub custom: HashMap<String, serde_json::Value>,
+}
+
+/// The placement the user launched from, e.g. one exam in a course
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceLink {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// The course the launch happened in
+#[derive(Debug, Clone, Deserialize)]
+pub struct LaunchContext {
+    /// Short course code, e.g. `CS101`
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// What a launch makes of the user in the room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchRole {
+    Proctor,
+    Student,
+}
+
+impl LaunchClaims {
+    /// Storage namespace from the `tenant` custom parameter, so each
+    /// institution's platform keeps its rooms apart
+    pub fn tenant(&self) -> Option<&str> {
+        self.custom.get("tenant").and_then(|tenant| tenant.as_str())
+    }
+
+    /// Instructors (including sub-roles such as teaching assistants) proctor;
+    /// learners join as students. Instructor wins when a user has both.
+    pub fn launch_role(&self) -> Option<LaunchRole> {
+        let has = |role: &str| {
+            self.roles.iter().any(|claimed| {
+                claimed == role
+                    || *claimed == format!("{}#{}", MEMBERSHIP, role)
+                    || claimed.starts_with(&format!("{}/{}#", MEMBERSHIP, role))
+            })
+        };
+        if has("Instructor") {
+            Some(LaunchRole::Proctor)
+        } else if has("Learner") {
+            Some(LaunchRole::Student)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn claims(roles: &[&str]) -> LaunchClaims {
+        serde_json::from_value(json!({
+            "iss": "https://lms.example.edu",
+            "sub": "user-1",
+            "exp": 1_760_000_000u64,
+            "https://purl.imsglobal.org/spec/lti/claim/message_type": RESOURCE_LINK_REQUEST,
+            "https://purl.imsglobal.org/spec/lti/claim/version": LTI_VERSION,
+            "https://purl.imsglobal.org/spec/lti/claim/deployment_id": "deployment-1",
+            "https://purl.imsglobal.org/spec/lti/claim/roles": roles,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_launch_role_from_lis_roles() {
+        let instructor = "http://purl.imsglobal.org/vocab/lis/v2/membership#Instructor";
+        let assistant = "http://purl.imsglobal.org/vocab/lis/v2/membership/Instructor#TeachingAssistant";
+        let learner = "http://purl.imsglobal.org/vocab/lis/v2/membership#Learner";
+        let admin = "http://purl.imsglobal.org/vocab/lis/v2/institution/person#Administrator";
+
+        assert_eq!(claims(&[instructor]).launch_role(), Some(LaunchRole::Proctor));
+        assert_eq!(claims(&[assistant]).launch_role(), Some(LaunchRole::Proctor));
+        assert_eq!(claims(&[learner, instructor]).launch_role(), Some(LaunchRole::Proctor));
+        assert_eq!(claims(&[learner]).launch_role(), Some(LaunchRole::Student));
+        assert_eq!(claims(&["Learner"]).launch_role(), Some(LaunchRole::Student));
+        assert_eq!(claims(&[admin]).launch_role(), None);
+        assert_eq!(claims(&[]).launch_role(), None);
+        // Not a sub-role of Instructor, just a similar prefix
+        assert_eq!(claims(&["http://purl.imsglobal.org/vocab/lis/v2/membership#InstructorX"]).launch_role(), None);
+    }
+
+    #[test]
+    fn test_tenant_from_custom_claim() {
+        assert_eq!(claims(&[]).tenant(), None);
+
+        let claims: LaunchClaims = serde_json::from_value(json!({
+            "iss": "https://lms.example.edu",
+            "sub": "user-1",
+            "exp": 1_760_000_000u64,
+            "https://purl.imsglobal.org/spec/lti/claim/message_type": RESOURCE_LINK_REQUEST,
+            "https://purl.imsglobal.org/spec/lti/claim/version": LTI_VERSION,
+            "https://purl.imsglobal.org/spec/lti/claim/deployment_id": "deployment-1",
+            "https://purl.imsglobal.org/spec/lti/claim/custom": { "tenant": "uni-a", "exam": 3 },
+        }))
+        .unwrap();
+        assert_eq!(claims.tenant(), Some("uni-a"));
+    }
+}
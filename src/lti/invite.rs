@@ -0,0 +1,76 @@
+//! Invite tokens handed to students by an LTI launch. A token binds one LTI
+//! user's peer ID to one room until it expires, formatted as
+//! `{expires_at}.{hex HMAC-SHA256}` so it fits in a join URL.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub struct InviteSigner {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl InviteSigner {
+    pub fn new(secret: Vec<u8>, ttl: Duration) -> Self {
+        Self { secret, ttl }
+    }
+
+    fn sign(&self, room_id: &str, peer_id: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(room_id.as_bytes());
+        mac.update(b"/");
+        mac.update(peer_id.as_bytes());
+        mac.update(b"/");
+        mac.update(expires_at.to_string().as_bytes());
+        mac
+    }
+
+    /// Token for `peer_id` to join `room_id`, and when it expires (Unix seconds)
+    pub fn issue(&self, room_id: &str, peer_id: &str, now: u64) -> (String, u64) {
+        let expires_at = now + self.ttl.as_secs();
+        let signature = hex::encode(self.sign(room_id, peer_id, expires_at).finalize().into_bytes());
+        (format!("{}.{}", expires_at, signature), expires_at)
+    }
+
+    pub fn verify(&self, room_id: &str, peer_id: &str, token: &str, now: u64) -> bool {
+        let Some((expires_at, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let (Ok(expires_at), Ok(signature)) = (expires_at.parse::<u64>(), hex::decode(signature)) else {
+            return false;
+        };
+        now < expires_at && self.sign(room_id, peer_id, expires_at).verify_slice(&signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_760_000_000;
+
+    #[test]
+    fn test_invite_binds_room_and_peer_until_expiry() {
+        let signer = InviteSigner::new(b"secret".to_vec(), Duration::from_secs(600));
+        let (token, expires_at) = signer.issue("123456", "lti-1", NOW);
+        assert_eq!(expires_at, NOW + 600);
+
+        assert!(signer.verify("123456", "lti-1", &token, NOW));
+        assert!(signer.verify("123456", "lti-1", &token, NOW + 599));
+        assert!(!signer.verify("123456", "lti-1", &token, NOW + 600));
+        assert!(!signer.verify("123456", "lti-2", &token, NOW));
+        assert!(!signer.verify("654321", "lti-1", &token, NOW));
+
+        // Pushing the expiry out breaks the signature
+        let (_, signature) = token.split_once('.').unwrap();
+        assert!(!signer.verify("123456", "lti-1", &format!("{}.{}", NOW + 6000, signature), NOW + 700));
+
+        let other = InviteSigner::new(b"other".to_vec(), Duration::from_secs(600));
+        assert!(!other.verify("123456", "lti-1", &token, NOW));
+        assert!(!signer.verify("123456", "lti-1", "garbage", NOW));
+        assert!(!signer.verify("123456", "lti-1", "1.zz", 0));
+    }
+}
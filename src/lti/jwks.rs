@@ -0,0 +1,81 @@
+//! The platform's signing keys, fetched from its JWKS URL and cached. An
+//! unknown `kid` triggers a refetch, so key rotation on the platform side is
+//! picked up without a restart.
+
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::DecodingKey;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::LtiError;
+
+/// Request timeout for the JWKS fetch
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimum time between refetches, so tokens with made-up key IDs cannot
+/// make this server hammer the platform
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: Instant,
+}
+
+pub struct PlatformKeys {
+    jwks_url: String,
+    client: reqwest::Client,
+    cache: Mutex<Option<CachedKeys>>,
+}
+
+impl PlatformKeys {
+    pub fn new(jwks_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            jwks_url,
+            client,
+            cache: Mutex::new(None),
+        }
+    }
+
+    async fn fetch(&self) -> Result<JwkSet, LtiError> {
+        let response = self
+            .client
+            .get(&self.jwks_url)
+            .send()
+            .await
+            .map_err(|e| LtiError::KeysetUnavailable(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(LtiError::KeysetUnavailable(format!("JWKS URL returned {}", response.status())));
+        }
+        response
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| LtiError::KeysetUnavailable(format!("Invalid JWKS: {}", e)))
+    }
+
+    /// Verification key for `kid`, refetching the key set when it is not cached
+    pub async fn key(&self, kid: &str) -> Result<DecodingKey, LtiError> {
+        let mut cache = self.cache.lock().await;
+
+        let stale = match cache.as_ref() {
+            Some(cached) if cached.keys.find(kid).is_some() => false,
+            Some(cached) => cached.fetched_at.elapsed() >= MIN_REFETCH_INTERVAL,
+            None => true,
+        };
+        if stale {
+            let keys = self.fetch().await?;
+            tracing::info!(url = %self.jwks_url, keys = keys.keys.len(), "Fetched LTI platform keys");
+            *cache = Some(CachedKeys { keys, fetched_at: Instant::now() });
+        }
+
+        let jwk = cache
+            .as_ref()
+            .and_then(|cached| cached.keys.find(kid))
+            .ok_or_else(|| LtiError::UnknownKey(kid.to_string()))?;
+        DecodingKey::from_jwk(jwk).map_err(|e| LtiError::UnknownKey(format!("{}: {}", kid, e)))
+    }
+}
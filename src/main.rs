@@ -4,36 +4,101 @@ mod api;
 mod error;
 mod recording;
 mod ipfs;
+mod s3;
 mod substrate;
+mod health;
+mod metrics;
+mod chaos;
+mod logging;
+mod lti;
+mod analytics;
+mod diagnostics;
+mod tls;
 
+use clap::Parser;
 use warp::Filter;
 use config::Config;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+#[derive(Parser)]
+#[command(name = "sfu-server")]
+#[command(about = "WebRTC SFU with recording for proctored exams", long_about = None)]
+struct Cli {
+    /// TOML configuration file; environment variables override its values
+    /// (default: SFU_CONFIG_FILE)
+    #[arg(long, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+}
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
+    // Read first, so the file can set the log filter too
+    let config_file = config::load_file(cli.config.as_deref());
+
     // Initialize tracing subscriber with environment filter
     // Set RUST_LOG environment variable to control log levels
     // Example: RUST_LOG=info,sfu_server=debug
-    tracing_subscriber::registry()
-        .with(
-            EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info".into())
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    logging::init();
 
     tracing::info!("Starting SFU server");
 
+    match config_file {
+        Ok(Some(path)) => tracing::info!(path = %path.display(), "Read configuration file"),
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "Invalid configuration file");
+            health::systemd::notify(&format!("STATUS=Invalid configuration file: {}", e));
+            std::process::exit(1);
+        }
+    }
     let config = Config::from_env();
     tracing::info!(
         host = %config.server.host,
         port = config.server.port,
+        tls = config.server.tls.enabled(),
         "Server configuration loaded"
     );
 
+    let tls_identity = match config.server.tls.load() {
+        Ok(identity) => identity,
+        Err(e) => {
+            tracing::error!(error = %e, "Invalid TLS configuration");
+            health::systemd::notify(&format!("STATUS=Invalid TLS configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    health::spawn_runtime_heartbeat();
+
+    let counter_persistence = metrics::CounterPersistence::from_env().map(std::sync::Arc::new);
+    if let Some(ref persistence) = counter_persistence {
+        persistence.restore(metrics::metrics());
+        persistence.clone().spawn_flush(metrics::metrics());
+    }
+
+    let mut builder = sfu::SfuServer::builder()
+        .config(&config)
+        .admission(std::sync::Arc::new(sfu::PendingAdmissions::from_env()));
+    if let Some(transcripts) = recording::transcript::service() {
+        builder = builder.transcripts(transcripts);
+    }
+    let built = sfu::WebRtcEngineConfig::from_env().and_then(|engine_config| builder.engine_config(engine_config).build());
+    let mut sfu_server = match built {
+        Ok(server) => server,
+        Err(e) => {
+            tracing::error!(error = %e, "Invalid WebRTC engine configuration");
+            health::systemd::notify(&format!("STATUS=Invalid WebRTC engine configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+
     // Initialize Asset Hub EVM blockchain integration if configured
-    let event_queue = match substrate::init_from_env().await {
+    let chain = match config.asset_hub.clone() {
+        Some(asset_hub) => substrate::init(asset_hub, sfu_server.tasks()).await,
+        None => None,
+    };
+    let event_queue = match chain {
         Some((_client, queue)) => {
             tracing::info!("Asset Hub EVM blockchain integration enabled");
             Some(queue)
@@ -44,13 +109,156 @@ async fn main() {
         }
     };
 
-    let routes = api::sfu_routes::sfu_websocket_route_with_queue(event_queue)
-        .or(api::sfu_routes::sfu_health_check())
-        .or(api::sfu_routes::sfu_config_endpoint());
+    if let Err(e) = startup_checks(&config, event_queue.is_some()) {
+        tracing::error!(error = %e, "Startup checks failed");
+        health::systemd::notify(&format!("STATUS=Startup checks failed: {}", e));
+        std::process::exit(1);
+    }
+
+    if let Some(queue) = event_queue {
+        sfu_server.set_event_queue(queue);
+        tracing::info!("SFU server configured with blockchain integration");
+    }
+    let sfu_server = std::sync::Arc::new(sfu_server);
+    sfu_server.start_background_tasks();
+    sfu::ice_selftest::spawn_startup_selftest(sfu_server.tasks(), &config.webrtc);
+    recording::retention::spawn(sfu_server.tasks(), &config.recording, sfu_server.recording_manager());
+
+    let daily_analytics = match analytics::DailyAnalytics::from_env() {
+        Ok(daily) => std::sync::Arc::new(daily),
+        Err(e) => {
+            tracing::error!(error = %e, "Invalid analytics configuration");
+            health::systemd::notify(&format!("STATUS=Invalid analytics configuration: {}", e));
+            std::process::exit(1);
+        }
+    };
+    if daily_analytics.scheduled() {
+        daily_analytics.clone().spawn(sfu_server.tasks());
+    }
+
+    let websocket_url = config.websocket_url();
+    let rest_routes = api::sfu_routes::sfu_liveness_check()
+        .or(api::sfu_routes::sfu_health_check(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_view_events_endpoint())
+        .or(api::sfu_routes::sfu_stats_endpoint())
+        .or(api::sfu_routes::sfu_metrics_endpoint())
+        .or(api::sfu_routes::sfu_transcript_callback_endpoint())
+        .or(api::sfu_routes::sfu_recording_download_endpoint())
+        .or(api::sfu_routes::sfu_chaos_admin_endpoint())
+        .or(api::sfu_routes::sfu_ice_selftest_endpoint())
+        .or(api::sfu_routes::sfu_log_level_endpoint())
+        .or(api::sfu_routes::sfu_negotiation_tuning_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_negotiation_stats_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_rooms_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_tenant_admin_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_roster_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_integrity_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_diagnostics_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_recipe_endpoint(sfu_server.clone()))
+        .or(api::sfu_routes::sfu_analytics_endpoint(daily_analytics))
+        .or(api::sfu_routes::sfu_config_endpoint(websocket_url.clone()));
+    // The LTI launch is a form post from the platform's origin, so it stays
+    // outside CORS like the WebSocket upgrade
+    let cors_origins = api::cors::AllowedOrigins::from_env();
+    let routes = api::sfu_routes::sfu_websocket_route(sfu_server.clone())
+        .or(api::sfu_routes::sfu_lti_launch_endpoint(sfu_server.clone()))
+        .or(api::cors::with_cors(rest_routes, cors_origins.as_ref()));
+
+    // Every subsystem and route has read its settings by now
+    config::env::log_report();
 
     tracing::info!("Starting server on {}:{}", config.server.host, config.server.port);
 
-    warp::serve(routes)
-        .run(config.bind_address())
-        .await;
-}
\ No newline at end of file
+    let (addr, server) = match tls::bind(routes, config.bind_address(), tls_identity.as_ref(), shutdown_signal()) {
+        Ok(bound) => bound,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to bind server listener");
+            std::process::exit(1);
+        }
+    };
+
+    health::monitor().set_ready(true);
+    health::spawn_watchdog();
+    if health::systemd::notify("READY=1") {
+        tracing::info!("Notified systemd of readiness");
+    }
+    tracing::info!(address = %addr, websocket_url = %websocket_url, "Server listening");
+
+    server.await;
+
+    // The listener is closed; tell connected peers, finish recordings, uploads
+    // and chain events, then stop the tasks that still touch shared state
+    sfu_server.shutdown().await;
+
+    if let Some(persistence) = counter_persistence {
+        match persistence.flush(metrics::metrics()) {
+            Ok(()) => tracing::info!(path = %persistence.path().display(), "Flushed metric counters"),
+            Err(e) => tracing::error!(error = %e, "Failed to flush metric counters on shutdown"),
+        }
+    }
+}
+
+/// Verifies the subsystems the server depends on before reporting readiness
+fn startup_checks(config: &Config, chain_connected: bool) -> Result<(), String> {
+    if config.recording.enabled {
+        recording::RecordingPipeline::verify_environment().map_err(|e| e.to_string())?;
+        if config.recording.transcode {
+            recording::RecordingPipeline::verify_transcoding().map_err(|e| e.to_string())?;
+        }
+
+        let output_dir = std::path::Path::new(&config.recording.output_dir);
+        let report = recording::permissions::policy()
+            .harden(output_dir)
+            .map_err(|e| format!("Failed to secure {}: {}", output_dir.display(), e))?;
+        if report.lax_allowed > 0 {
+            tracing::warn!(
+                paths = report.lax_allowed,
+                "Recording paths have broader permissions than RECORDING_DIR_MODE, allowed by RECORDING_ALLOW_LAX_PERMS"
+            );
+        }
+        if report.dirs_fixed + report.files_fixed > 0 {
+            tracing::info!(dirs = report.dirs_fixed, files = report.files_fixed, "Tightened recording permissions");
+        }
+    }
+
+    sfu::RenegotiationTuning::from_env().map_err(|e| format!("Invalid renegotiation tuning: {}", e))?;
+
+    // Configured but failed to connect; an unconfigured chain is simply disabled
+    if config.asset_hub.is_some() && !chain_connected {
+        return Err("Asset Hub client is configured but failed to initialize".to_string());
+    }
+
+    Ok(())
+}
+
+/// Resolves on SIGINT or SIGTERM and marks the server as stopping
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to install SIGTERM handler");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+
+    tracing::info!("Shutdown signal received, stopping server");
+    health::monitor().begin_draining();
+    health::systemd::notify("STOPPING=1");
+}
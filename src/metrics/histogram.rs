@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the latency buckets. Observations above the
+/// last bound only land in the implicit `+Inf` bucket.
+pub const LATENCY_BUCKETS_SECS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Fixed-bucket latency histogram
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Per-bucket (non-cumulative) counts; the extra slot is `+Inf`
+    buckets: [AtomicU64; LATENCY_BUCKETS_SECS.len() + 1],
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let index = LATENCY_BUCKETS_SECS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_SECS.len());
+        self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum_secs(&self) -> f64 {
+        self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+    }
+
+    /// Cumulative counts per bucket bound, as Prometheus exposes them.
+    /// The last entry is the `+Inf` bucket (`None`).
+    pub fn cumulative(&self) -> Vec<(Option<f64>, u64)> {
+        let mut total = 0;
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, bucket)| {
+                total += bucket.load(Ordering::Relaxed);
+                (LATENCY_BUCKETS_SECS.get(i).copied(), total)
+            })
+            .collect()
+    }
+
+    /// Estimated quantile in seconds, interpolating linearly within the bucket
+    /// it falls in. Observations past the last bound report that bound.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let cumulative = self.cumulative();
+        let total = cumulative.last().map(|(_, n)| *n).unwrap_or(0);
+        if total == 0 {
+            return None;
+        }
+
+        let rank = q.clamp(0.0, 1.0) * total as f64;
+        let mut lower_bound = 0.0;
+        let mut lower_count = 0;
+        for (bound, count) in cumulative {
+            let Some(upper_bound) = bound else {
+                return Some(lower_bound);
+            };
+            if count as f64 >= rank && count > lower_count {
+                let fraction = (rank - lower_count as f64) / (count - lower_count) as f64;
+                return Some(lower_bound + (upper_bound - lower_bound) * fraction.clamp(0.0, 1.0));
+            }
+            lower_bound = upper_bound;
+            lower_count = count;
+        }
+        Some(lower_bound)
+    }
+}
+
+/// Histograms keyed by a single label value, created on first observation
+#[derive(Debug, Default)]
+pub struct LabeledHistogram {
+    histograms: RwLock<BTreeMap<&'static str, Arc<Histogram>>>,
+}
+
+impl LabeledHistogram {
+    pub fn observe(&self, label: &'static str, elapsed: Duration) {
+        let existing = self.histograms.read().unwrap().get(label).cloned();
+        let histogram = match existing {
+            Some(histogram) => histogram,
+            None => self.histograms.write().unwrap().entry(label).or_default().clone(),
+        };
+        histogram.observe(elapsed);
+    }
+
+    /// Every label seen so far with its histogram, sorted by label
+    pub fn entries(&self) -> Vec<(&'static str, Arc<Histogram>)> {
+        self.histograms
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(label, histogram)| (*label, histogram.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observations_land_in_buckets() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(30));
+        histogram.observe(Duration::from_secs(10));
+
+        assert_eq!(histogram.count(), 3);
+        assert!((histogram.sum_secs() - 10.0305).abs() < 1e-9);
+
+        let cumulative = histogram.cumulative();
+        assert_eq!(cumulative[0], (Some(0.001), 1));
+        assert_eq!(cumulative[5], (Some(0.05), 2));
+        assert_eq!(cumulative[11], (Some(5.0), 2));
+        assert_eq!(cumulative[12], (None, 3));
+    }
+
+    #[test]
+    fn test_quantile_interpolates_within_bucket() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.quantile(0.95), None);
+
+        // 95 fast observations in (0, 1ms], 5 slow ones in (250ms, 500ms]
+        for _ in 0..95 {
+            histogram.observe(Duration::from_micros(800));
+        }
+        for _ in 0..5 {
+            histogram.observe(Duration::from_millis(400));
+        }
+
+        let p50 = histogram.quantile(0.5).unwrap();
+        assert!(p50 > 0.0 && p50 <= 0.001);
+        let p95 = histogram.quantile(0.95).unwrap();
+        assert!((p95 - 0.001).abs() < 1e-9);
+        let p99 = histogram.quantile(0.99).unwrap();
+        assert!(p99 > 0.25 && p99 <= 0.5);
+    }
+
+    #[test]
+    fn test_quantile_past_last_bound_reports_last_bound() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_secs(30));
+        assert_eq!(histogram.quantile(0.95), Some(5.0));
+    }
+
+    #[test]
+    fn test_labeled_histograms_are_independent() {
+        let labeled = LabeledHistogram::default();
+        labeled.observe("Answer", Duration::from_millis(2));
+        labeled.observe("Answer", Duration::from_millis(3));
+        labeled.observe("Join", Duration::from_secs(1));
+
+        let counts: Vec<_> = labeled
+            .entries()
+            .into_iter()
+            .map(|(label, histogram)| (label, histogram.count()))
+            .collect();
+        assert_eq!(counts, vec![("Answer", 2), ("Join", 1)]);
+    }
+}
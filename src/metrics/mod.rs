@@ -0,0 +1,285 @@
+//! Process-wide counters for business metrics
+
+mod histogram;
+mod persist;
+
+pub use histogram::{Histogram, LabeledHistogram};
+pub use persist::CounterPersistence;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+/// Monotonic counter
+#[derive(Debug, Default)]
+pub struct Counter {
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Point-in-time value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge {
+    value: AtomicU64,
+}
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Process-wide metrics. Only the counters listed in `counters()` are persisted.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub recordings_completed_total: Counter,
+    /// Successful uploads
+    pub ipfs_uploads_total: Counter,
+    pub ipfs_uploads_failed_total: Counter,
+    pub chain_events_queued_total: Counter,
+    /// Events submitted successfully
+    pub chain_events_processed_total: Counter,
+    pub chain_events_failed_total: Counter,
+    pub rooms_created_total: Counter,
+    pub pending_students_expired_total: Counter,
+    /// RTP packets written to subscribers, one per subscriber copy
+    pub rtp_packets_forwarded_total: Counter,
+    /// Payload bytes of those packets
+    pub rtp_bytes_forwarded_total: Counter,
+    /// Renegotiation offers sent to peers
+    pub renegotiations_total: Counter,
+    /// Track changes carried by those offers
+    pub renegotiation_tracks_total: Counter,
+    /// Offers postponed because the peer still held an unanswered one
+    pub renegotiation_retries_total: Counter,
+    pub renegotiation_answer_timeouts_total: Counter,
+    /// Recordings written as raw RTP dumps because the pipeline failed
+    pub recording_rtp_fallbacks_total: Counter,
+    /// Join requests currently waiting for a proctor decision
+    pub pending_students: Gauge,
+    pub active_rooms: Gauge,
+    pub active_recordings: Gauge,
+    /// Peers currently in a room, by role
+    pub proctors: Gauge,
+    pub students: Gauge,
+    /// Time spent in the signaling handler, labeled by message type
+    pub signaling_handler_latency: LabeledHistogram,
+    /// Renegotiation offer to answer
+    pub renegotiation_duration: Histogram,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn counters(&self) -> [(&'static str, &Counter); 8] {
+        [
+            ("recordings_completed_total", &self.recordings_completed_total),
+            ("ipfs_uploads_total", &self.ipfs_uploads_total),
+            ("ipfs_uploads_failed_total", &self.ipfs_uploads_failed_total),
+            ("chain_events_queued_total", &self.chain_events_queued_total),
+            ("chain_events_processed_total", &self.chain_events_processed_total),
+            ("chain_events_failed_total", &self.chain_events_failed_total),
+            ("rooms_created_total", &self.rooms_created_total),
+            ("pending_students_expired_total", &self.pending_students_expired_total),
+        ]
+    }
+
+    /// Counters that restart from zero with the process
+    fn live_counters(&self) -> [(&'static str, &Counter); 7] {
+        [
+            ("rtp_packets_forwarded_total", &self.rtp_packets_forwarded_total),
+            ("rtp_bytes_forwarded_total", &self.rtp_bytes_forwarded_total),
+            ("renegotiations_total", &self.renegotiations_total),
+            ("renegotiation_tracks_total", &self.renegotiation_tracks_total),
+            ("renegotiation_retries_total", &self.renegotiation_retries_total),
+            ("renegotiation_answer_timeouts_total", &self.renegotiation_answer_timeouts_total),
+            ("recording_rtp_fallbacks_total", &self.recording_rtp_fallbacks_total),
+        ]
+    }
+
+    fn gauges(&self) -> [(&'static str, &Gauge); 3] {
+        [
+            ("pending_students", &self.pending_students),
+            ("active_rooms", &self.active_rooms),
+            ("active_recordings", &self.active_recordings),
+        ]
+    }
+
+    /// Current value of every persisted counter, keyed by metric name
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counters()
+            .iter()
+            .map(|(name, counter)| (name.to_string(), counter.get()))
+            .collect()
+    }
+
+    /// Adds persisted totals onto the live counters.
+    ///
+    /// Adding (rather than overwriting) keeps any increments that happened
+    /// between process start and the restore. Unknown names are ignored.
+    pub fn restore(&self, totals: &BTreeMap<String, u64>) {
+        for (name, counter) in self.counters() {
+            if let Some(&value) = totals.get(name) {
+                counter.inc_by(value);
+            }
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        for (name, counter) in self.counters().into_iter().chain(self.live_counters()) {
+            let _ = writeln!(out, "# TYPE sfu_{} counter", name);
+            let _ = writeln!(out, "sfu_{} {}", name, counter.get());
+        }
+
+        for (name, gauge) in self.gauges() {
+            let _ = writeln!(out, "# TYPE sfu_{} gauge", name);
+            let _ = writeln!(out, "sfu_{} {}", name, gauge.get());
+        }
+
+        let _ = writeln!(out, "# HELP sfu_peers Peers currently in a room");
+        let _ = writeln!(out, "# TYPE sfu_peers gauge");
+        let _ = writeln!(out, "sfu_peers{{role=\"proctor\"}} {}", self.proctors.get());
+        let _ = writeln!(out, "sfu_peers{{role=\"student\"}} {}", self.students.get());
+
+        let entries = self.signaling_handler_latency.entries();
+        let _ = writeln!(out, "# HELP sfu_signaling_handler_duration_seconds Time spent handling one signaling message");
+        let _ = writeln!(out, "# TYPE sfu_signaling_handler_duration_seconds histogram");
+        for (message_type, histogram) in &entries {
+            for (bound, count) in histogram.cumulative() {
+                let le = bound.map_or_else(|| "+Inf".to_string(), |b| b.to_string());
+                let _ = writeln!(
+                    out,
+                    "sfu_signaling_handler_duration_seconds_bucket{{message_type=\"{}\",le=\"{}\"}} {}",
+                    message_type, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "sfu_signaling_handler_duration_seconds_sum{{message_type=\"{}\"}} {}",
+                message_type,
+                histogram.sum_secs()
+            );
+            let _ = writeln!(
+                out,
+                "sfu_signaling_handler_duration_seconds_count{{message_type=\"{}\"}} {}",
+                message_type,
+                histogram.count()
+            );
+        }
+
+        let _ = writeln!(out, "# HELP sfu_signaling_handler_p95_seconds Estimated 95th percentile of signaling handler time");
+        let _ = writeln!(out, "# TYPE sfu_signaling_handler_p95_seconds gauge");
+        for (message_type, histogram) in &entries {
+            if let Some(p95) = histogram.quantile(0.95) {
+                let _ = writeln!(
+                    out,
+                    "sfu_signaling_handler_p95_seconds{{message_type=\"{}\"}} {}",
+                    message_type, p95
+                );
+            }
+        }
+
+        let _ = writeln!(out, "# HELP sfu_renegotiation_duration_seconds Time from a renegotiation offer to its answer");
+        let _ = writeln!(out, "# TYPE sfu_renegotiation_duration_seconds histogram");
+        for (bound, count) in self.renegotiation_duration.cumulative() {
+            let le = bound.map_or_else(|| "+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(out, "sfu_renegotiation_duration_seconds_bucket{{le=\"{}\"}} {}", le, count);
+        }
+        let _ = writeln!(out, "sfu_renegotiation_duration_seconds_sum {}", self.renegotiation_duration.sum_secs());
+        let _ = writeln!(out, "sfu_renegotiation_duration_seconds_count {}", self.renegotiation_duration.count());
+
+        out
+    }
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Process-wide metrics registry
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let metrics = Metrics::new();
+        metrics.rooms_created_total.inc();
+        metrics.ipfs_uploads_total.inc_by(3);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["rooms_created_total"], 1);
+        assert_eq!(snapshot["ipfs_uploads_total"], 3);
+        assert_eq!(snapshot["recordings_completed_total"], 0);
+
+        let restored = Metrics::new();
+        restored.restore(&snapshot);
+        assert_eq!(restored.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn test_restore_keeps_increments_made_before_restore() {
+        let metrics = Metrics::new();
+        metrics.recordings_completed_total.inc();
+
+        let mut totals = BTreeMap::new();
+        totals.insert("recordings_completed_total".to_string(), 41);
+        totals.insert("unknown_total".to_string(), 7);
+        metrics.restore(&totals);
+
+        assert_eq!(metrics.recordings_completed_total.get(), 42);
+    }
+
+    #[test]
+    fn test_live_counters_are_not_persisted() {
+        let metrics = Metrics::new();
+        metrics.rtp_packets_forwarded_total.inc();
+        metrics.chain_events_failed_total.inc();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["chain_events_failed_total"], 1);
+        assert!(!snapshot.contains_key("rtp_packets_forwarded_total"));
+    }
+
+    #[test]
+    fn test_render_prometheus_includes_handler_latency() {
+        let metrics = Metrics::new();
+        metrics.rooms_created_total.inc();
+        metrics
+            .signaling_handler_latency
+            .observe("IceCandidate", std::time::Duration::from_millis(3));
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("sfu_rooms_created_total 1\n"));
+        assert!(text.contains("sfu_signaling_handler_duration_seconds_bucket{message_type=\"IceCandidate\",le=\"0.0025\"} 0\n"));
+        assert!(text.contains("sfu_signaling_handler_duration_seconds_bucket{message_type=\"IceCandidate\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("sfu_signaling_handler_duration_seconds_bucket{message_type=\"IceCandidate\",le=\"+Inf\"} 1\n"));
+        assert!(text.contains("sfu_signaling_handler_duration_seconds_count{message_type=\"IceCandidate\"} 1\n"));
+        assert!(text.contains("sfu_signaling_handler_p95_seconds{message_type=\"IceCandidate\"} "));
+    }
+}
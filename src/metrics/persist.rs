@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::Metrics;
+use crate::config::env;
+
+/// Default interval between periodic counter flushes
+const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 60;
+
+const DEFAULT_STATE_FILE: &str = "./metrics_state.json";
+
+/// Persists counter totals to a JSON state file so they survive restarts
+pub struct CounterPersistence {
+    path: PathBuf,
+    flush_interval: Duration,
+}
+
+impl CounterPersistence {
+    pub fn new(path: impl Into<PathBuf>, flush_interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            flush_interval,
+        }
+    }
+
+    /// Builds persistence from environment, or `None` unless `METRICS_PERSIST=true`
+    ///
+    /// Optional environment variables:
+    /// - `METRICS_STATE_FILE`: State file path (default: ./metrics_state.json)
+    /// - `METRICS_FLUSH_INTERVAL_SECS`: Periodic flush interval (default: 60)
+    pub fn from_env() -> Option<Self> {
+        if !env::get_bool("METRICS_PERSIST", false) {
+            return None;
+        }
+
+        let path = env::get_string("METRICS_STATE_FILE").unwrap_or_else(|| DEFAULT_STATE_FILE.to_string());
+        let flush_secs = env::get_parsed::<u64>("METRICS_FLUSH_INTERVAL_SECS")
+            .filter(|&secs| secs > 0)
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_SECS);
+
+        Some(Self::new(path, Duration::from_secs(flush_secs)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads persisted totals into `metrics`. A missing or corrupt state file
+    /// starts the counters from zero.
+    pub fn restore(&self, metrics: &Metrics) {
+        let totals = load(&self.path);
+        metrics.restore(&totals);
+        tracing::info!(path = %self.path.display(), counters = ?totals, "Restored persisted metric counters");
+    }
+
+    /// Writes current totals to the state file
+    pub fn flush(&self, metrics: &Metrics) -> io::Result<()> {
+        save(&self.path, &metrics.snapshot())
+    }
+
+    /// Spawns a task that flushes `metrics` every `flush_interval`
+    pub fn spawn_flush(self: std::sync::Arc<Self>, metrics: &'static Metrics) {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(self.flush_interval);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                if let Err(e) = self.flush(metrics) {
+                    tracing::warn!(path = %self.path.display(), error = %e, "Failed to flush metric counters");
+                }
+            }
+        });
+    }
+}
+
+fn load(path: &Path) -> BTreeMap<String, u64> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            tracing::info!(path = %path.display(), "No metric state file, starting counters from zero");
+            return BTreeMap::new();
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to read metric state file, starting counters from zero");
+            return BTreeMap::new();
+        }
+    };
+
+    match serde_json::from_str(&contents) {
+        Ok(totals) => totals,
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "Corrupt metric state file, starting counters from zero");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Writes via a temp file and rename so a crash mid-write never leaves a truncated state file
+fn save(path: &Path, totals: &BTreeMap<String, u64>) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_vec_pretty(totals)?)?;
+    std::fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn temp_state_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sfu-metrics-{}-{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_save_restore_round_trip() {
+        let path = temp_state_file("roundtrip");
+        let persistence = CounterPersistence::new(&path, Duration::from_secs(60));
+
+        let metrics = Metrics::new();
+        metrics.rooms_created_total.inc_by(5);
+        metrics.chain_events_processed_total.inc_by(12);
+        persistence.flush(&metrics).unwrap();
+
+        let restarted = Metrics::new();
+        persistence.restore(&restarted);
+        assert_eq!(restarted.rooms_created_total.get(), 5);
+        assert_eq!(restarted.chain_events_processed_total.get(), 12);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_missing_file_starts_from_zero() {
+        let path = temp_state_file("missing");
+        let metrics = Metrics::new();
+        CounterPersistence::new(&path, Duration::from_secs(60)).restore(&metrics);
+        assert!(metrics.snapshot().values().all(|&v| v == 0));
+    }
+
+    #[test]
+    fn test_corrupt_file_starts_from_zero() {
+        let path = temp_state_file("corrupt");
+        std::fs::write(&path, b"{\"rooms_created_total\": 3,").unwrap();
+
+        let metrics = Metrics::new();
+        CounterPersistence::new(&path, Duration::from_secs(60)).restore(&metrics);
+        assert_eq!(metrics.rooms_created_total.get(), 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_concurrent_increments_during_flush() {
+        let path = temp_state_file("concurrent");
+        let persistence = Arc::new(CounterPersistence::new(&path, Duration::from_secs(60)));
+        let metrics: &'static Metrics = Box::leak(Box::new(Metrics::new()));
+
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.recordings_completed_total.inc();
+                    }
+                })
+            })
+            .collect();
+
+        // Flush repeatedly while writers are running; every intermediate file must parse
+        for _ in 0..20 {
+            persistence.flush(metrics).unwrap();
+            let partial = load(&path);
+            assert!(partial["recordings_completed_total"] <= 4000);
+        }
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        persistence.flush(metrics).unwrap();
+
+        let restarted = Metrics::new();
+        persistence.restore(&restarted);
+        assert_eq!(restarted.recordings_completed_total.get(), 4000);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
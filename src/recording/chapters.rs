@@ -0,0 +1,291 @@
+//! WebVTT chapters for a finalized recording, written as
+//! `{peer_id}_{timestamp}.chapters.vtt` so reviewers can jump to joins, ID
+//! verification, incidents and the exam clock. Chapters come from the room's
+//! view events that fall within the recording: the proctor's recording gets
+//! every peer's, a student's its own plus the room-wide exam clock.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::view_events::{ViewEvent, ViewEventKind};
+
+/// Title of the chapter covering the recording up to its first event
+const OPENING_TITLE: &str = "Recording started";
+
+/// Chapters file of a recording, e.g. `peer_123.webm` -> `peer_123.chapters.vtt`
+pub fn chapters_path(recording: &Path) -> PathBuf {
+    recording.with_extension("chapters.vtt")
+}
+
+/// Wall-clock span of one recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingWindow {
+    /// Unix time in milliseconds
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// Milliseconds into the recording
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub title: String,
+}
+
+/// Chapters of `peer_id`'s recording, in order and covering it end to end.
+/// Empty when no event falls within `window` or the timeline has no session start.
+pub fn chapters(events: &[ViewEvent], peer_id: &str, window: RecordingWindow) -> Vec<Chapter> {
+    let Some(session) = events.iter().find(|event| event.event == ViewEventKind::SessionStarted) else {
+        return Vec::new();
+    };
+    let Some(session_started_at_ms) = session.details["started_at_ms"].as_u64() else {
+        return Vec::new();
+    };
+    let is_proctor = session.peer_id == peer_id;
+
+    // Milliseconds into the recording of a point in the session, if it is within it
+    let locate = |offset_secs: f64| {
+        let at_ms = session_started_at_ms + (offset_secs.max(0.0) * 1000.0).round() as u64;
+        at_ms
+            .checked_sub(window.started_at_ms)
+            .filter(|position| *position < window.duration_ms)
+    };
+
+    let mut marks: Vec<(u64, String)> = Vec::new();
+    for event in events {
+        let room_wide = event.event == ViewEventKind::ExamClock;
+        if !(is_proctor || room_wide || event.peer_id == peer_id) {
+            continue;
+        }
+        for (offset_secs, title) in titles(event, is_proctor) {
+            if let Some(position) = locate(offset_secs) {
+                marks.push((position, title));
+            }
+        }
+    }
+    if marks.is_empty() {
+        return Vec::new();
+    }
+    marks.sort_by_key(|(position, _)| *position);
+
+    // Events in the same millisecond share one chapter
+    let mut merged: Vec<(u64, String)> = Vec::new();
+    for (position, title) in marks {
+        match merged.last_mut() {
+            Some((last, titles)) if *last == position => {
+                titles.push_str(" / ");
+                titles.push_str(&title);
+            }
+            _ => merged.push((position, title)),
+        }
+    }
+    if merged[0].0 > 0 {
+        merged.insert(0, (0, OPENING_TITLE.to_string()));
+    }
+
+    let ends: Vec<u64> = merged
+        .iter()
+        .skip(1)
+        .map(|(position, _)| *position)
+        .chain(std::iter::once(window.duration_ms))
+        .collect();
+    merged
+        .into_iter()
+        .zip(ends)
+        .map(|((start_ms, title), end_ms)| Chapter { start_ms, end_ms, title })
+        .collect()
+}
+
+/// Chapter titles an event contributes, with their session offsets. Names
+/// the peer only in the proctor's recording, where several peers appear.
+fn titles(event: &ViewEvent, name_peer: bool) -> Vec<(f64, String)> {
+    let who = |what: &str| if name_peer { format!("{} {}", event.peer_id, what) } else { capitalize(what) };
+    let detail = |key: &str| event.details[key].as_str().unwrap_or("unknown").replace('_', " ");
+
+    match event.event {
+        ViewEventKind::Joined => vec![(event.offset_secs, who("joined"))],
+        ViewEventKind::Unsubscribed => vec![(event.offset_secs, who("left"))],
+        ViewEventKind::IdVerification => {
+            vec![(event.offset_secs, who(&format!("ID verification {}", detail("status"))))]
+        }
+        ViewEventKind::Incident => vec![(event.offset_secs, who(&format!("incident: {}", detail("activity_type"))))],
+        ViewEventKind::ExamClock => {
+            let mut titles = vec![(event.offset_secs, "Exam started".to_string())];
+            if let Some(duration_secs) = event.details["duration_secs"].as_u64() {
+                titles.push((event.offset_secs + duration_secs as f64, "Exam ended".to_string()));
+            }
+            titles
+        }
+        ViewEventKind::SessionStarted
+        | ViewEventKind::Subscribed
+        | ViewEventKind::MediaState
+        | ViewEventKind::SessionMetadata
+        | ViewEventKind::JoinEscalation => Vec::new(),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// `HH:MM:SS.mmm`
+fn timestamp(ms: u64) -> String {
+    format!("{:02}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// Cue text on one line, with the characters WebVTT reserves escaped
+fn cue_text(title: &str) -> String {
+    title
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+/// Renders chapters as a WebVTT file, one numbered cue per chapter
+pub fn to_webvtt(chapters: &[Chapter]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for (index, chapter) in chapters.iter().enumerate() {
+        let _ = write!(
+            vtt,
+            "\n{}\n{} --> {}\n{}\n",
+            index + 1,
+            timestamp(chapter.start_ms),
+            timestamp(chapter.end_ms),
+            cue_text(&chapter.title)
+        );
+    }
+    vtt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const SESSION_START_MS: u64 = 1_700_000_000_000;
+
+    fn event(offset_secs: f64, kind: ViewEventKind, peer_id: &str, details: serde_json::Value) -> ViewEvent {
+        ViewEvent {
+            offset_secs,
+            event: kind,
+            peer_id: peer_id.to_string(),
+            details,
+        }
+    }
+
+    /// A session whose student is recorded from 100 s to 400 s in
+    fn timeline() -> Vec<ViewEvent> {
+        vec![
+            event(0.0, ViewEventKind::SessionStarted, "proctor_1", json!({ "started_at_ms": SESSION_START_MS })),
+            event(95.0, ViewEventKind::Joined, "student_1", json!({ "role": "student" })),
+            // Exactly at the recording start
+            event(100.0, ViewEventKind::IdVerification, "student_1", json!({ "status": "pending" })),
+            event(100.0, ViewEventKind::Subscribed, "student_1", json!({})),
+            event(150.0, ViewEventKind::ExamClock, "proctor_1", json!({ "duration_secs": 200 })),
+            event(180.5, ViewEventKind::IdVerification, "student_1", json!({ "status": "valid" })),
+            event(200.0, ViewEventKind::Joined, "student_2", json!({ "role": "student" })),
+            event(250.25, ViewEventKind::Incident, "student_1", json!({ "activity_type": "tab_switch" })),
+            event(250.25, ViewEventKind::Incident, "student_1", json!({ "activity_type": "window_blur" })),
+            event(300.0, ViewEventKind::Incident, "student_2", json!({ "activity_type": "<script>" })),
+            // Exactly at the recording end, so outside it
+            event(400.0, ViewEventKind::Unsubscribed, "student_1", json!({ "reason": "left" })),
+        ]
+    }
+
+    fn window() -> RecordingWindow {
+        RecordingWindow {
+            started_at_ms: SESSION_START_MS + 100_000,
+            duration_ms: 300_000,
+        }
+    }
+
+    fn chapter(start_ms: u64, end_ms: u64, title: &str) -> Chapter {
+        Chapter { start_ms, end_ms, title: title.to_string() }
+    }
+
+    #[test]
+    fn test_student_chapters() {
+        assert_eq!(
+            chapters(&timeline(), "student_1", window()),
+            vec![
+                chapter(0, 50_000, "ID verification pending"),
+                chapter(50_000, 80_500, "Exam started"),
+                chapter(80_500, 150_250, "ID verification valid"),
+                chapter(150_250, 250_000, "Incident: tab switch / Incident: window blur"),
+                // The exam clock ends at 350 s in the session, within the recording
+                chapter(250_000, 300_000, "Exam ended"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proctor_chapters_name_every_peer() {
+        let window = RecordingWindow { started_at_ms: SESSION_START_MS, duration_ms: 500_000 };
+        let titles: Vec<String> = chapters(&timeline(), "proctor_1", window)
+            .into_iter()
+            .map(|chapter| chapter.title)
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Recording started",
+                "student_1 joined",
+                "student_1 ID verification pending",
+                "Exam started",
+                "student_1 ID verification valid",
+                "student_2 joined",
+                "student_1 incident: tab switch / student_1 incident: window blur",
+                "student_2 incident: <script>",
+                "Exam ended",
+                "student_1 left",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recording_boundaries() {
+        // Starts after everything: no event falls inside
+        let late = RecordingWindow { started_at_ms: SESSION_START_MS + 600_000, duration_ms: 60_000 };
+        assert!(chapters(&timeline(), "student_1", late).is_empty());
+
+        // Started before the session: events are shifted, an opening chapter covers the lead-in
+        let early = RecordingWindow { started_at_ms: SESSION_START_MS - 5_000, duration_ms: 102_000 };
+        assert_eq!(
+            chapters(&timeline(), "student_1", early),
+            vec![chapter(0, 100_000, "Recording started"), chapter(100_000, 102_000, "Joined")]
+        );
+
+        // Without a session start the offsets cannot be placed
+        assert!(chapters(&timeline()[1..], "student_1", window()).is_empty());
+    }
+
+    #[test]
+    fn test_webvtt_rendering() {
+        let window = RecordingWindow { started_at_ms: SESSION_START_MS + 290_000, duration_ms: 3_723_456 };
+        let vtt = to_webvtt(&chapters(&timeline(), "proctor_1", window));
+        assert_eq!(
+            vtt,
+            "WEBVTT\n\
+             \n1\n00:00:00.000 --> 00:00:10.000\nRecording started\n\
+             \n2\n00:00:10.000 --> 00:01:00.000\nstudent_2 incident: &lt;script&gt;\n\
+             \n3\n00:01:00.000 --> 00:01:50.000\nExam ended\n\
+             \n4\n00:01:50.000 --> 01:02:03.456\nstudent_1 left\n"
+        );
+        assert_eq!(to_webvtt(&[]), "WEBVTT\n");
+        assert_eq!(cue_text("a\nb --> c"), "a b --&gt; c");
+    }
+
+    #[test]
+    fn test_chapters_path() {
+        assert_eq!(
+            chapters_path(Path::new("/recordings/room-1/peer_1_100.webm")),
+            Path::new("/recordings/room-1/peer_1_100.chapters.vtt")
+        );
+    }
+}
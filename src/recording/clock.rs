@@ -0,0 +1,57 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Monotonic clock anchored at the start of a room session.
+///
+/// Offsets are measured on `Instant` so they stay consistent with media
+/// timing even if the wall clock is adjusted mid-session.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionClock {
+    started: Instant,
+    started_at: SystemTime,
+}
+
+impl SessionClock {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+        }
+    }
+
+    /// Seconds elapsed since the session started
+    pub fn offset_secs(&self) -> f64 {
+        self.offset_secs_at(Instant::now())
+    }
+
+    pub fn offset_secs_at(&self, now: Instant) -> f64 {
+        now.saturating_duration_since(self.started).as_secs_f64()
+    }
+
+    /// Wall-clock session start in milliseconds since the Unix epoch
+    pub fn started_at_ms(&self) -> u64 {
+        self.started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_offset_from_start() {
+        let clock = SessionClock::start();
+        let later = Instant::now() + Duration::from_millis(2500);
+        let offset = clock.offset_secs_at(later);
+        assert!((2.5..2.6).contains(&offset));
+    }
+
+    #[test]
+    fn test_offset_never_negative() {
+        let clock = SessionClock::start();
+        assert_eq!(clock.offset_secs_at(clock.started - Duration::from_secs(1)), 0.0);
+    }
+}
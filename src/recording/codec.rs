@@ -0,0 +1,189 @@
+use gstreamer as gst;
+use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecParameters;
+
+use crate::error::SfuError;
+use super::finalize::{MATROSKA_EXTENSION, WEBM_EXTENSION};
+use super::gaps::MediaKind;
+
+/// Encodings the pipeline can depayload for each kind of track
+const VIDEO_ENCODINGS: &[&str] = &["VP8", "VP9", "AV1", "H264"];
+const AUDIO_ENCODINGS: &[&str] = &["OPUS"];
+
+/// RTP parameters of a recorded track, as negotiated with the publisher
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtpCodec {
+    /// Upper-case RTP encoding name, e.g. `VP8`
+    pub encoding_name: String,
+    pub payload_type: u8,
+    pub clock_rate: u32,
+}
+
+impl RtpCodec {
+    /// Codec from a mime type such as `video/VP8`
+    pub fn from_mime_type(mime_type: &str, payload_type: u8, clock_rate: u32) -> Self {
+        let subtype = mime_type.split_once('/').map_or(mime_type, |(_, subtype)| subtype);
+        Self {
+            encoding_name: subtype.to_ascii_uppercase(),
+            payload_type,
+            clock_rate,
+        }
+    }
+
+    /// Codec a remote track was negotiated with
+    pub fn from_parameters(parameters: &RTCRtpCodecParameters) -> Self {
+        Self::from_mime_type(
+            &parameters.capability.mime_type,
+            parameters.payload_type,
+            parameters.capability.clock_rate,
+        )
+    }
+
+    pub fn is_h264(&self) -> bool {
+        self.encoding_name.eq_ignore_ascii_case("H264")
+    }
+
+    /// `application/x-rtp` caps for the pipeline's appsrc. Encodings the
+    /// pipeline has no depayloader for are refused rather than recorded empty.
+    pub fn caps(&self, kind: MediaKind) -> Result<gst::Caps, SfuError> {
+        let supported = match kind {
+            MediaKind::Video => VIDEO_ENCODINGS,
+            MediaKind::Audio => AUDIO_ENCODINGS,
+        };
+        let Some(encoding_name) = supported.iter().find(|name| self.encoding_name.eq_ignore_ascii_case(name)) else {
+            return Err(SfuError::RecordingFailed(format!(
+                "Cannot record {} codec {}, only {} is supported",
+                kind.as_str(),
+                self.encoding_name,
+                supported.join(" or ")
+            )));
+        };
+
+        Ok(gst::Caps::builder("application/x-rtp")
+            .field("media", kind.as_str())
+            .field("encoding-name", *encoding_name)
+            .field("clock-rate", self.clock_rate as i32)
+            .field("payload", self.payload_type as i32)
+            .build())
+    }
+}
+
+/// Codecs a recording's video and audio branches expect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingCodecs {
+    pub video: RtpCodec,
+    pub audio: RtpCodec,
+}
+
+impl Default for RecordingCodecs {
+    /// The payload types the default WebRTC engine offers
+    fn default() -> Self {
+        Self {
+            video: RtpCodec::from_mime_type("video/VP8", 96, 90000),
+            audio: RtpCodec::from_mime_type("audio/opus", 111, 48000),
+        }
+    }
+}
+
+impl RecordingCodecs {
+    pub fn get(&self, kind: MediaKind) -> &RtpCodec {
+        match kind {
+            MediaKind::Video => &self.video,
+            MediaKind::Audio => &self.audio,
+        }
+    }
+
+    pub fn set(&mut self, kind: MediaKind, codec: RtpCodec) {
+        match kind {
+            MediaKind::Video => self.video = codec,
+            MediaKind::Audio => self.audio = codec,
+        }
+    }
+
+    /// Extension of the file these codecs are recorded into: WebM for VP8,
+    /// VP9 and AV1, Matroska for H.264, which WebM cannot carry
+    pub fn extension(&self) -> &'static str {
+        if self.video.is_h264() {
+            MATROSKA_EXTENSION
+        } else {
+            WEBM_EXTENSION
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+
+    #[test]
+    fn test_caps_carry_negotiated_payload_type() {
+        gst::init().unwrap();
+
+        let video = RtpCodec::from_mime_type("video/vp8", 120, 90000);
+        assert_eq!(
+            video.caps(MediaKind::Video).unwrap().to_string(),
+            "application/x-rtp, media=(string)video, encoding-name=(string)VP8, clock-rate=(int)90000, payload=(int)120"
+        );
+
+        let audio = RtpCodec::from_mime_type("audio/opus", 109, 48000);
+        assert_eq!(
+            audio.caps(MediaKind::Audio).unwrap().to_string(),
+            "application/x-rtp, media=(string)audio, encoding-name=(string)OPUS, clock-rate=(int)48000, payload=(int)109"
+        );
+    }
+
+    #[test]
+    fn test_h264_recorded_into_matroska() {
+        gst::init().unwrap();
+
+        let h264 = RtpCodec::from_mime_type("video/h264", 102, 90000);
+        assert_eq!(
+            h264.caps(MediaKind::Video).unwrap().to_string(),
+            "application/x-rtp, media=(string)video, encoding-name=(string)H264, clock-rate=(int)90000, payload=(int)102"
+        );
+
+        assert_eq!(RecordingCodecs::default().extension(), "webm");
+        let codecs = RecordingCodecs {
+            video: h264,
+            ..RecordingCodecs::default()
+        };
+        assert_eq!(codecs.extension(), "mkv");
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/AV1", 41, 90000),
+            ..RecordingCodecs::default()
+        };
+        assert_eq!(codecs.extension(), "webm");
+    }
+
+    #[test]
+    fn test_unsupported_encoding_names_the_codec() {
+        gst::init().unwrap();
+
+        let h265 = RtpCodec::from_mime_type("video/H265", 100, 90000);
+        let err = h265.caps(MediaKind::Video).unwrap_err();
+        assert!(matches!(err, SfuError::RecordingFailed(_)));
+        assert!(err.to_string().contains("H265"), "{}", err);
+
+        // Opus on the video branch is just as unusable
+        let opus = RtpCodec::from_mime_type("audio/opus", 111, 48000);
+        assert!(opus.caps(MediaKind::Video).is_err());
+    }
+
+    #[test]
+    fn test_codec_from_track_parameters() {
+        let parameters = RTCRtpCodecParameters {
+            capability: RTCRtpCodecCapability {
+                mime_type: "video/VP8".to_string(),
+                clock_rate: 90000,
+                channels: 0,
+                sdp_fmtp_line: String::new(),
+                rtcp_feedback: vec![],
+            },
+            payload_type: 97,
+            ..Default::default()
+        };
+
+        assert_eq!(RtpCodec::from_parameters(&parameters), RtpCodec::from_mime_type("video/VP8", 97, 90000));
+        assert_eq!(RecordingCodecs::default().get(MediaKind::Audio).payload_type, 111);
+    }
+}
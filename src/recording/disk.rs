@@ -0,0 +1,47 @@
+//! Free space where recordings are written. The trait is what
+//! `RecordingManager` checks against `RECORDING_MIN_FREE_BYTES`, so tests
+//! can stand in for a filling disk.
+
+use std::path::Path;
+
+pub trait DiskSpace: Send + Sync {
+    /// Bytes available to the server on the filesystem holding `path`
+    fn available_bytes(&self, path: &Path) -> std::io::Result<u64>;
+}
+
+/// Asks the filesystem, through `statvfs` on Unix
+pub struct FsDiskSpace;
+
+impl DiskSpace for FsDiskSpace {
+    fn available_bytes(&self, path: &Path) -> std::io::Result<u64> {
+        fs2::available_space(path)
+    }
+}
+
+#[cfg(test)]
+pub use fixed::FixedDiskSpace;
+
+#[cfg(test)]
+mod fixed {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Reports whatever free space it was last set to
+    pub struct FixedDiskSpace(AtomicU64);
+
+    impl FixedDiskSpace {
+        pub fn new(bytes: u64) -> Self {
+            Self(AtomicU64::new(bytes))
+        }
+
+        pub fn set(&self, bytes: u64) {
+            self.0.store(bytes, Ordering::Relaxed);
+        }
+    }
+
+    impl DiskSpace for FixedDiskSpace {
+        fn available_bytes(&self, _path: &Path) -> std::io::Result<u64> {
+            Ok(self.0.load(Ordering::Relaxed))
+        }
+    }
+}
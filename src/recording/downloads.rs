@@ -0,0 +1,514 @@
+//! Chunked, resumable recording downloads.
+//!
+//! A recording is served as fixed-size chunks, each with its own SHA-256, so a
+//! client on a flaky link can verify what it already has and fetch only the
+//! rest. Hashes are computed on first request in the bounded hashing pool and
+//! cached next to the recording (`{peer_id}_{timestamp}.chunks.json`) until the
+//! file changes. Recordings still being written (`.part`) are listed but
+//! never served.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::Semaphore;
+
+use crate::config::env;
+use super::finalize::{is_part, list_recordings, part_path, RecordingFile};
+use super::permissions;
+use super::tenant;
+use super::transcript::is_safe_component;
+
+/// Chunk size when `RECORDING_DOWNLOAD_CHUNK_BYTES` is unset
+pub const DEFAULT_DOWNLOAD_CHUNK_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Hashing jobs that may run at once when `RECORDING_HASH_WORKERS` is unset
+pub const DEFAULT_HASH_WORKERS: usize = 2;
+
+/// Response header carrying the SHA-256 of a served chunk
+pub const CHUNK_SHA256_HEADER: &str = "x-chunk-sha256";
+
+const READ_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Size, hash and chunk hashes of a recording
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+    pub chunk_size: u64,
+    /// SHA-256 of every chunk in order; the last one may be shorter
+    pub chunks: Vec<String>,
+    /// Modification time of the recording the hashes were computed from, ms since epoch
+    pub modified_at_ms: u64,
+}
+
+impl ChunkManifest {
+    /// Byte offset and length of a chunk, `None` past the end of the file
+    pub fn chunk_range(&self, index: usize) -> Option<(u64, u64)> {
+        if index >= self.chunks.len() {
+            return None;
+        }
+        let offset = index as u64 * self.chunk_size;
+        Some((offset, self.chunk_size.min(self.size - offset)))
+    }
+}
+
+/// Chunk hash sidecar of a recording, e.g. `peer_123.webm` -> `peer_123.chunks.json`
+pub fn chunks_path(recording: &Path) -> PathBuf {
+    recording.with_extension("chunks.json")
+}
+
+fn modified_ms(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Hashes a recording in one pass: the whole file and every `chunk_size` chunk
+pub fn hash_chunks(recording: &Path, chunk_size: u64) -> io::Result<ChunkManifest> {
+    let mut file = File::open(recording)?;
+    let modified_at_ms = modified_ms(&file.metadata()?);
+    let mut whole = Sha256::new();
+    let mut chunks = Vec::new();
+    let mut size = 0u64;
+    let mut buf = vec![0u8; READ_BUFFER_BYTES.min(chunk_size as usize)];
+
+    loop {
+        let mut chunk = Sha256::new();
+        let mut chunk_len = 0u64;
+        while chunk_len < chunk_size {
+            let want = buf.len().min((chunk_size - chunk_len) as usize);
+            let n = file.read(&mut buf[..want])?;
+            if n == 0 {
+                break;
+            }
+            whole.update(&buf[..n]);
+            chunk.update(&buf[..n]);
+            chunk_len += n as u64;
+        }
+        if chunk_len == 0 {
+            break;
+        }
+        size += chunk_len;
+        chunks.push(hex::encode(chunk.finalize()));
+        if chunk_len < chunk_size {
+            break;
+        }
+    }
+
+    Ok(ChunkManifest {
+        file: recording
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        size,
+        sha256: hex::encode(whole.finalize()),
+        chunk_size,
+        chunks,
+        modified_at_ms,
+    })
+}
+
+/// The cached manifest of a recording, hashing it and refreshing the sidecar
+/// when the file changed since or was never hashed with this chunk size
+pub fn load_or_hash(recording: &Path, chunk_size: u64) -> io::Result<ChunkManifest> {
+    let metadata = std::fs::metadata(recording)?;
+    let sidecar = chunks_path(recording);
+
+    let cached = std::fs::read(&sidecar)
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<ChunkManifest>(&contents).ok())
+        .filter(|cached| {
+            cached.size == metadata.len()
+                && cached.modified_at_ms == modified_ms(&metadata)
+                && cached.chunk_size == chunk_size
+        });
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let manifest = hash_chunks(recording, chunk_size)?;
+    let tmp_path = sidecar.with_extension("tmp");
+    let written = serde_json::to_vec(&manifest)
+        .map_err(io::Error::from)
+        .and_then(|contents| permissions::write_private(&tmp_path, &contents))
+        .and_then(|_| std::fs::rename(&tmp_path, &sidecar));
+    if let Err(e) = written {
+        tracing::warn!(recording = %recording.display(), error = %e, "Failed to cache chunk hashes");
+    }
+    Ok(manifest)
+}
+
+/// Reads one chunk of a recording
+pub fn read_chunk(recording: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(recording)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "recording shrank while reading a chunk"));
+    }
+    Ok(bytes)
+}
+
+/// Runs hashing work on blocking threads, at most `workers` jobs at a time,
+/// so hashing multi-GB recordings can't take over the blocking pool
+pub struct HashWorkers {
+    permits: Arc<Semaphore>,
+}
+
+impl HashWorkers {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(workers.max(1))),
+        }
+    }
+
+    /// Waits for a free worker, then runs `work` on it. The worker stays taken
+    /// until `work` returns, even if the caller stops waiting.
+    pub async fn run<T, F>(&self, work: F) -> io::Result<T>
+    where
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            work()
+        })
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+    }
+}
+
+static HASH_WORKERS: OnceLock<Arc<HashWorkers>> = OnceLock::new();
+
+/// Process-wide hashing pool sized by `RECORDING_HASH_WORKERS`
+pub fn hash_workers() -> Arc<HashWorkers> {
+    HASH_WORKERS
+        .get_or_init(|| {
+            let workers = env::get_parsed::<usize>("RECORDING_HASH_WORKERS")
+                .filter(|&n| n > 0)
+                .unwrap_or(DEFAULT_HASH_WORKERS);
+            Arc::new(HashWorkers::new(workers))
+        })
+        .clone()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadError {
+    InvalidPath,
+    RecordingNotFound,
+    /// Still being written, or orphaned by a crash and not yet repaired
+    RecordingInProgress,
+    ChunkOutOfRange,
+    Storage(String),
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => DownloadError::RecordingNotFound,
+            _ => DownloadError::Storage(e.to_string()),
+        }
+    }
+}
+
+/// One chunk of a recording and its hash from the manifest
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub sha256: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Serves recordings under the output directory as verifiable chunks
+pub struct RecordingDownloads {
+    output_dir: PathBuf,
+    chunk_size: u64,
+    workers: Arc<HashWorkers>,
+    /// Serializes hashing per recording, so concurrent first requests hash it once
+    hashing: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+static DOWNLOADS: OnceLock<RecordingDownloads> = OnceLock::new();
+
+/// Process-wide downloads over `RECORDING_OUTPUT_DIR`
+pub fn service() -> &'static RecordingDownloads {
+    DOWNLOADS.get_or_init(RecordingDownloads::from_env)
+}
+
+impl RecordingDownloads {
+    /// Reads `RECORDING_OUTPUT_DIR` and `RECORDING_DOWNLOAD_CHUNK_BYTES`, from
+    /// the environment or the config file
+    fn from_env() -> Self {
+        let output_dir = env::get_string("RECORDING_OUTPUT_DIR").unwrap_or_else(|| "./recordings".to_string());
+        let chunk_size = env::get_parsed::<u64>("RECORDING_DOWNLOAD_CHUNK_BYTES")
+            .filter(|&n| n > 0)
+            .unwrap_or(DEFAULT_DOWNLOAD_CHUNK_BYTES);
+        Self::new(output_dir, chunk_size, hash_workers())
+    }
+
+    pub fn new(output_dir: impl Into<PathBuf>, chunk_size: u64, workers: Arc<HashWorkers>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            chunk_size: chunk_size.max(1),
+            workers,
+            hashing: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Room directory, in `tenant`'s namespace only when one is given
+    fn room_dir(&self, tenant: Option<&str>, room_id: &str) -> Result<PathBuf, DownloadError> {
+        if !is_safe_component(room_id) {
+            return Err(DownloadError::InvalidPath);
+        }
+        tenant::locate_room(&self.output_dir, tenant, room_id).ok_or(DownloadError::RecordingNotFound)
+    }
+
+    fn recording_path(&self, tenant: Option<&str>, room_id: &str, file: &str) -> Result<PathBuf, DownloadError> {
+        if !is_safe_component(file) {
+            return Err(DownloadError::InvalidPath);
+        }
+        let recording = self.room_dir(tenant, room_id)?.join(file);
+        if is_part(&recording) || part_path(&recording).exists() {
+            return Err(DownloadError::RecordingInProgress);
+        }
+        if !recording.is_file() {
+            return Err(DownloadError::RecordingNotFound);
+        }
+        Ok(recording)
+    }
+
+    /// Recordings of a room, finalized or still being written. With a
+    /// `tenant`, rooms outside its namespace are not found.
+    pub async fn list(&self, tenant: Option<&str>, room_id: &str) -> Result<Vec<RecordingFile>, DownloadError> {
+        let room_dir = self.room_dir(tenant, room_id)?;
+        tokio::task::spawn_blocking(move || list_recordings(&room_dir))
+            .await
+            .map_err(|e| DownloadError::Storage(e.to_string()))?
+            .map_err(DownloadError::from)
+    }
+
+    /// Chunk manifest of a recording, hashed on first request and cached after
+    pub async fn manifest(&self, tenant: Option<&str>, room_id: &str, file: &str) -> Result<ChunkManifest, DownloadError> {
+        let recording = self.recording_path(tenant, room_id, file)?;
+        let lock = self.hashing.lock().unwrap().entry(recording.clone()).or_default().clone();
+        let _hashing = lock.lock().await;
+
+        let chunk_size = self.chunk_size;
+        Ok(self.workers.run(move || load_or_hash(&recording, chunk_size)).await?)
+    }
+
+    pub async fn chunk(&self, tenant: Option<&str>, room_id: &str, file: &str, index: usize) -> Result<Chunk, DownloadError> {
+        let manifest = self.manifest(tenant, room_id, file).await?;
+        let (offset, len) = manifest.chunk_range(index).ok_or(DownloadError::ChunkOutOfRange)?;
+        let recording = self.recording_path(tenant, room_id, file)?;
+
+        let bytes = tokio::task::spawn_blocking(move || read_chunk(&recording, offset, len))
+            .await
+            .map_err(|e| DownloadError::Storage(e.to_string()))??;
+        Ok(Chunk {
+            sha256: manifest.chunks[index].clone(),
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-downloads-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("room-1")).unwrap();
+        dir
+    }
+
+    fn contents(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    fn sha256(bytes: &[u8]) -> String {
+        hex::encode(Sha256::digest(bytes))
+    }
+
+    #[test]
+    fn test_chunk_size_from_config_file() {
+        // Set by no test in the environment, so the file value is what is read
+        env::set_file_values([("RECORDING_DOWNLOAD_CHUNK_BYTES".to_string(), "4096".to_string())].into());
+        assert_eq!(RecordingDownloads::from_env().chunk_size, 4096);
+    }
+
+    #[test]
+    fn test_manifest_hashes_every_chunk_and_the_whole_file() {
+        let dir = temp_output_dir("hash");
+        let recording = dir.join("room-1").join("peer_1_100.webm");
+        let data = contents(100);
+        std::fs::write(&recording, &data).unwrap();
+
+        let manifest = hash_chunks(&recording, 32).unwrap();
+        assert_eq!(manifest.file, "peer_1_100.webm");
+        assert_eq!(manifest.size, 100);
+        assert_eq!(manifest.sha256, sha256(&data));
+        let expected: Vec<_> = data.chunks(32).map(sha256).collect();
+        assert_eq!(manifest.chunks, expected);
+        assert_eq!(manifest.chunk_range(3), Some((96, 4)));
+        assert_eq!(manifest.chunk_range(4), None);
+
+        // Exact multiples don't end in an empty chunk
+        std::fs::write(&recording, &data[..64]).unwrap();
+        assert_eq!(hash_chunks(&recording, 32).unwrap().chunks.len(), 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sidecar_is_reused_until_the_recording_changes() {
+        let dir = temp_output_dir("sidecar");
+        let recording = dir.join("room-1").join("peer_1_100.webm");
+        std::fs::write(&recording, contents(100)).unwrap();
+
+        let first = load_or_hash(&recording, 32).unwrap();
+        assert!(chunks_path(&recording).is_file());
+
+        // A doctored sidecar is served as-is, proving nothing was rehashed
+        let mut doctored = first.clone();
+        doctored.sha256 = "cached".to_string();
+        std::fs::write(chunks_path(&recording), serde_json::to_vec(&doctored).unwrap()).unwrap();
+        assert_eq!(load_or_hash(&recording, 32).unwrap().sha256, "cached");
+
+        // Another chunk size or a changed file is rehashed
+        assert_eq!(load_or_hash(&recording, 16).unwrap().sha256, first.sha256);
+        std::fs::write(&recording, contents(120)).unwrap();
+        let changed = load_or_hash(&recording, 16).unwrap();
+        assert_eq!(changed.size, 120);
+        assert_eq!(changed.sha256, sha256(&contents(120)));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_are_served_with_their_hash() {
+        let dir = temp_output_dir("serve");
+        let data = contents(100);
+        std::fs::write(dir.join("room-1").join("peer_1_100.webm"), &data).unwrap();
+        let downloads = RecordingDownloads::new(&dir, 32, Arc::new(HashWorkers::new(1)));
+
+        let manifest = downloads.manifest(None, "room-1", "peer_1_100.webm").await.unwrap();
+        for (index, expected) in data.chunks(32).enumerate() {
+            let chunk = downloads.chunk(None, "room-1", "peer_1_100.webm", index).await.unwrap();
+            assert_eq!(chunk.bytes, expected);
+            assert_eq!(chunk.sha256, manifest.chunks[index]);
+        }
+
+        assert_eq!(
+            downloads.chunk(None, "room-1", "peer_1_100.webm", 4).await.unwrap_err(),
+            DownloadError::ChunkOutOfRange
+        );
+        assert_eq!(
+            downloads.manifest(None, "room-1", "missing.webm").await.unwrap_err(),
+            DownloadError::RecordingNotFound
+        );
+        assert_eq!(
+            downloads.manifest(None, "..", "peer_1_100.webm").await.unwrap_err(),
+            DownloadError::InvalidPath
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_in_progress_recordings_are_listed_but_not_served() {
+        let dir = temp_output_dir("in-progress");
+        std::fs::write(dir.join("room-1").join("peer_1_100.webm"), contents(10)).unwrap();
+        std::fs::write(dir.join("room-1").join("peer_2_100.webm.part"), contents(20)).unwrap();
+        let downloads = RecordingDownloads::new(&dir, 32, Arc::new(HashWorkers::new(1)));
+
+        let listed = downloads.list(None, "room-1").await.unwrap();
+        let states: Vec<_> = listed.iter().map(|f| (f.file.as_str(), serde_json::to_value(f.state).unwrap())).collect();
+        assert_eq!(
+            states,
+            vec![("peer_1_100.webm", serde_json::json!("finalized")), ("peer_2_100.webm", serde_json::json!("recording"))]
+        );
+
+        for file in ["peer_2_100.webm", "peer_2_100.webm.part"] {
+            assert_eq!(downloads.manifest(None, "room-1", file).await.unwrap_err(), DownloadError::RecordingInProgress);
+        }
+        assert!(downloads.manifest(None, "room-1", "peer_1_100.webm").await.is_ok());
+        assert_eq!(downloads.list(None, "missing").await.unwrap_err(), DownloadError::RecordingNotFound);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_scope_limits_listings() {
+        let dir = temp_output_dir("tenants");
+        for (tenant, room_id) in [("uni-a", "200002"), ("uni-b", "300003")] {
+            let room_dir = tenant::create_tenant_dir(&dir, tenant).unwrap().join(room_id);
+            std::fs::create_dir_all(&room_dir).unwrap();
+            std::fs::write(room_dir.join("peer_1_100.webm"), contents(10)).unwrap();
+        }
+        std::fs::write(dir.join("room-1").join("peer_1_100.webm"), contents(10)).unwrap();
+        let downloads = RecordingDownloads::new(&dir, 32, Arc::new(HashWorkers::new(1)));
+
+        // The admin scope finds rooms in any namespace
+        assert_eq!(downloads.list(None, "200002").await.unwrap().len(), 1);
+        assert_eq!(downloads.list(None, "room-1").await.unwrap().len(), 1);
+
+        assert_eq!(downloads.list(Some("uni-a"), "200002").await.unwrap().len(), 1);
+        assert!(downloads.manifest(Some("uni-a"), "200002", "peer_1_100.webm").await.is_ok());
+        for room_id in ["300003", "room-1"] {
+            assert_eq!(downloads.list(Some("uni-a"), room_id).await.unwrap_err(), DownloadError::RecordingNotFound);
+            assert_eq!(
+                downloads.chunk(Some("uni-a"), room_id, "peer_1_100.webm", 0).await.unwrap_err(),
+                DownloadError::RecordingNotFound
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_hash_workers_bound_concurrent_jobs() {
+        let workers = Arc::new(HashWorkers::new(2));
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let jobs: Vec<_> = (0..6)
+            .map(|_| {
+                let (workers, running, peak) = (workers.clone(), running.clone(), peak.clone());
+                tokio::spawn(async move {
+                    workers
+                        .run(move || {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(now, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(20));
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            Ok(())
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for job in jobs {
+            job.await.unwrap().unwrap();
+        }
+
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+}
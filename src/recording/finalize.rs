@@ -0,0 +1,213 @@
+//! Recordings are written as `{peer_id}_{timestamp}.webm.part` (`.mkv.part`
+//! for H.264 video) and renamed to their final name only once GStreamer has
+//! finalized them, so any `.webm` or `.mkv` file in the output directory is
+//! complete. A `.part` file is either still
+//! being recorded or was left behind by a crash and is repaired on startup.
+
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::chapters::chapters_path;
+use super::permissions;
+use super::tenant;
+
+/// Extension appended to a recording while it is being written
+pub const PART_EXTENSION: &str = "part";
+
+/// Extension of finalized VP8 recordings
+pub const WEBM_EXTENSION: &str = "webm";
+
+/// Extension of finalized H.264 recordings
+pub const MATROSKA_EXTENSION: &str = "mkv";
+
+/// Extension appended to the remuxed copy of an orphan while it is being repaired
+const REPAIR_EXTENSION: &str = "repair";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// In-progress name of a recording, e.g. `peer_123.webm` -> `peer_123.webm.part`
+pub fn part_path(recording: &Path) -> PathBuf {
+    with_suffix(recording, PART_EXTENSION)
+}
+
+/// File a repaired orphan is remuxed into before taking the final name
+pub fn repair_path(recording: &Path) -> PathBuf {
+    with_suffix(recording, REPAIR_EXTENSION)
+}
+
+pub fn is_part(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == PART_EXTENSION)
+}
+
+/// Whether `path` is the remuxed copy of an orphan still being repaired
+pub fn is_repair(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == REPAIR_EXTENSION)
+}
+
+/// Final name of an in-progress recording, `None` for anything else
+pub fn final_path(part: &Path) -> Option<PathBuf> {
+    let recording = part.with_extension("");
+    (is_part(part) && is_recording(&recording)).then_some(recording)
+}
+
+fn is_recording(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == WEBM_EXTENSION || ext == MATROSKA_EXTENSION)
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it into
+/// place, so readers see either the old file or the whole new one
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = with_suffix(path, "tmp");
+    let written = permissions::private_file()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path)
+        .and_then(|mut file| file.write_all(contents).and_then(|_| file.sync_all()))
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingFileState {
+    /// Still being written, or orphaned and awaiting repair; not downloadable
+    Recording,
+    Finalized,
+}
+
+/// A recording in a room's output directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecordingFile {
+    /// Final file name, also for recordings still in progress
+    pub file: String,
+    pub state: RecordingFileState,
+    pub size: u64,
+    /// WebVTT chapters file next to the recording, once written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chapters: Option<String>,
+}
+
+/// Recordings in `room_dir` sorted by name, in-progress ones included
+pub fn list_recordings(room_dir: &Path) -> io::Result<Vec<RecordingFile>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(room_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let (recording, state) = match final_path(&path) {
+            Some(recording) => (recording, RecordingFileState::Recording),
+            None if is_recording(&path) => (path, RecordingFileState::Finalized),
+            None => continue,
+        };
+        let Some(file) = recording.file_name() else { continue };
+        let chapters = chapters_path(&recording);
+        files.push(RecordingFile {
+            file: file.to_string_lossy().to_string(),
+            state,
+            size: entry.metadata()?.len(),
+            chapters: chapters
+                .file_name()
+                .filter(|_| chapters.is_file())
+                .map(|name| name.to_string_lossy().to_string()),
+        });
+    }
+    files.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(files)
+}
+
+/// `.part` recordings under every room directory of `output_dir`, tenant
+/// namespaces included. Only meaningful before any recording starts, when
+/// none of them has a writer.
+pub fn find_orphans(output_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut orphans = Vec::new();
+    for (_, room_dir) in tenant::room_dirs(output_dir)? {
+        for entry in std::fs::read_dir(room_dir)? {
+            let path = entry?.path();
+            if final_path(&path).is_some() {
+                orphans.push(path);
+            }
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_room_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-finalize-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("room-1")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_part_names_round_trip() {
+        let recording = Path::new("/recordings/room-1/peer_1_100.webm");
+        let part = part_path(recording);
+        assert_eq!(part, Path::new("/recordings/room-1/peer_1_100.webm.part"));
+        assert!(is_part(&part));
+        assert_eq!(final_path(&part).as_deref(), Some(recording));
+
+        assert!(!is_part(recording));
+        assert_eq!(final_path(recording), None);
+        assert_eq!(final_path(Path::new("/recordings/room-1/view_events.jsonl.part")), None);
+
+        // H.264 recordings are Matroska
+        let mkv = Path::new("/recordings/room-1/peer_1_100.mkv");
+        assert_eq!(final_path(&part_path(mkv)).as_deref(), Some(mkv));
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_without_leftovers() {
+        let dir = temp_room_dir("atomic");
+        let path = dir.join("room-1").join("peer_1_100.meta.json");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        let names: Vec<_> = std::fs::read_dir(dir.join("room-1")).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![OsString::from("peer_1_100.meta.json")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_listing_marks_part_files_in_progress() {
+        let dir = temp_room_dir("listing");
+        let room_dir = dir.join("room-1");
+        std::fs::write(room_dir.join("peer_1_100.webm"), [0u8; 10]).unwrap();
+        std::fs::write(room_dir.join("peer_2_100.webm.part"), [0u8; 4]).unwrap();
+        std::fs::write(room_dir.join("peer_1_100.meta.json"), b"{}").unwrap();
+        std::fs::write(room_dir.join("peer_1_100.webm.repair"), [0u8; 2]).unwrap();
+        std::fs::write(room_dir.join("peer_1_100.chapters.vtt"), b"WEBVTT\n").unwrap();
+
+        assert_eq!(
+            list_recordings(&room_dir).unwrap(),
+            vec![
+                RecordingFile {
+                    file: "peer_1_100.webm".to_string(),
+                    state: RecordingFileState::Finalized,
+                    size: 10,
+                    chapters: Some("peer_1_100.chapters.vtt".to_string()),
+                },
+                RecordingFile { file: "peer_2_100.webm".to_string(), state: RecordingFileState::Recording, size: 4, chapters: None },
+            ]
+        );
+        assert_eq!(find_orphans(&dir).unwrap(), vec![room_dir.join("peer_2_100.webm.part")]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use super::clock::SessionClock;
+
+/// Default silence on a recorded track before it is reported as a gap
+pub const DEFAULT_RECORDING_GAP_INCIDENT_SECS: u64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Audio,
+    Video,
+}
+
+impl MediaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Audio => "audio",
+            Self::Video => "video",
+        }
+    }
+}
+
+/// A stretch of a recording where a live track delivered no media, as a
+/// line of the recording's `.gaps.jsonl` sidecar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaGap {
+    /// Seconds from the start of the recording
+    pub start_offset: f64,
+    pub end_offset: f64,
+    pub kind: MediaKind,
+}
+
+impl MediaGap {
+    pub fn duration_secs(&self) -> f64 {
+        self.end_offset - self.start_offset
+    }
+}
+
+/// Change in a recording's gaps since the last poll
+#[derive(Debug, Clone, PartialEq)]
+pub enum GapEvent {
+    /// A track has been silent for longer than the threshold
+    Opened { kind: MediaKind, start_offset: f64 },
+    /// Media resumed, the publisher muted the track, or the recording stopped
+    Closed(MediaGap),
+}
+
+#[derive(Debug)]
+struct TrackState {
+    kind: MediaKind,
+    /// Last packet, or when the silence timer was last reset
+    last_media: Instant,
+    /// Publisher deliberately turned the track off, so silence is expected
+    muted: bool,
+    open_since: Option<Instant>,
+}
+
+/// Detects arrival gaps on the tracks of one recording.
+///
+/// Packets only reset a timer; gaps are opened by `poll` and closed by the
+/// next packet, a mute or `finish`, so the RTP path never does more than
+/// update an `Instant`.
+#[derive(Debug)]
+pub struct GapTracker {
+    clock: SessionClock,
+    threshold: Duration,
+    tracks: Vec<TrackState>,
+    gaps: Vec<MediaGap>,
+    events: Vec<GapEvent>,
+}
+
+impl GapTracker {
+    /// Tracks `kinds` from `now`, so a track that never delivers counts from the start
+    pub fn new(clock: SessionClock, threshold: Duration, kinds: &[MediaKind], now: Instant) -> Self {
+        Self {
+            clock,
+            threshold,
+            tracks: kinds
+                .iter()
+                .map(|kind| TrackState {
+                    kind: *kind,
+                    last_media: now,
+                    muted: false,
+                    open_since: None,
+                })
+                .collect(),
+            gaps: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn on_media(&mut self, kind: MediaKind, now: Instant) {
+        if let Some(track) = self.tracks.iter_mut().find(|t| t.kind == kind) {
+            track.last_media = now;
+            if let Some(since) = track.open_since.take() {
+                Self::close(&self.clock, &mut self.gaps, &mut self.events, kind, since, now);
+            }
+        }
+    }
+
+    /// Muting closes an open gap at `now`; unmuting restarts the silence timer
+    pub fn set_muted(&mut self, kind: MediaKind, muted: bool, now: Instant) {
+        if let Some(track) = self.tracks.iter_mut().find(|t| t.kind == kind) {
+            if track.muted == muted {
+                return;
+            }
+            track.muted = muted;
+            track.last_media = now;
+            if let Some(since) = track.open_since.take() {
+                Self::close(&self.clock, &mut self.gaps, &mut self.events, kind, since, now);
+            }
+        }
+    }
+
+    /// Closes open gaps at `now` and restarts every silence timer, for a
+    /// recording that pauses or resumes and so expects no media in between
+    pub fn restart_timers(&mut self, now: Instant) {
+        for track in &mut self.tracks {
+            track.last_media = now;
+            if let Some(since) = track.open_since.take() {
+                Self::close(&self.clock, &mut self.gaps, &mut self.events, track.kind, since, now);
+            }
+        }
+    }
+
+    /// Whether the publisher has `kind` turned off
+    pub fn is_muted(&self, kind: MediaKind) -> bool {
+        self.tracks.iter().any(|t| t.kind == kind && t.muted)
+    }
+
+    /// Opens gaps for tracks silent past the threshold and drains pending events
+    pub fn poll(&mut self, now: Instant) -> Vec<GapEvent> {
+        for track in &mut self.tracks {
+            if track.muted || track.open_since.is_some() {
+                continue;
+            }
+            if now.saturating_duration_since(track.last_media) > self.threshold {
+                // The gap starts where media stopped, not where it was noticed
+                track.open_since = Some(track.last_media);
+                self.events.push(GapEvent::Opened {
+                    kind: track.kind,
+                    start_offset: self.clock.offset_secs_at(track.last_media),
+                });
+            }
+        }
+        std::mem::take(&mut self.events)
+    }
+
+    /// Closes gaps still open when the recording stops and drains pending events.
+    /// Nothing is tracked afterwards.
+    pub fn finish(&mut self, now: Instant) -> Vec<GapEvent> {
+        let mut events = self.poll(now);
+        for track in std::mem::take(&mut self.tracks) {
+            if let Some(since) = track.open_since {
+                Self::close(&self.clock, &mut self.gaps, &mut events, track.kind, since, now);
+            }
+        }
+        events
+    }
+
+    /// Gaps closed so far, oldest first
+    pub fn gaps(&self) -> &[MediaGap] {
+        &self.gaps
+    }
+
+    pub fn total_gap_secs(&self) -> f64 {
+        self.gaps.iter().map(MediaGap::duration_secs).sum()
+    }
+
+    fn close(
+        clock: &SessionClock,
+        gaps: &mut Vec<MediaGap>,
+        events: &mut Vec<GapEvent>,
+        kind: MediaKind,
+        since: Instant,
+        now: Instant,
+    ) {
+        let gap = MediaGap {
+            start_offset: clock.offset_secs_at(since),
+            end_offset: clock.offset_secs_at(now),
+            kind,
+        };
+        gaps.push(gap.clone());
+        events.push(GapEvent::Closed(gap));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLD: Duration = Duration::from_secs(15);
+
+    fn tracker() -> (GapTracker, SessionClock, Instant) {
+        let clock = SessionClock::start();
+        let t0 = Instant::now();
+        (GapTracker::new(clock, THRESHOLD, &[MediaKind::Audio, MediaKind::Video], t0), clock, t0)
+    }
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn test_gap_opens_after_threshold_and_closes_on_resume() {
+        let (mut gaps, clock, t0) = tracker();
+        gaps.on_media(MediaKind::Audio, t0 + secs(5));
+        gaps.on_media(MediaKind::Video, t0 + secs(5));
+
+        // Silence up to the threshold is not a gap yet
+        gaps.on_media(MediaKind::Audio, t0 + secs(20));
+        assert!(gaps.poll(t0 + secs(20)).is_empty());
+
+        let opened = gaps.poll(t0 + secs(21));
+        assert_eq!(
+            opened,
+            vec![GapEvent::Opened { kind: MediaKind::Video, start_offset: clock.offset_secs_at(t0 + secs(5)) }]
+        );
+        // An open gap is only reported once
+        gaps.on_media(MediaKind::Audio, t0 + secs(30));
+        assert!(gaps.poll(t0 + secs(30)).is_empty());
+
+        gaps.on_media(MediaKind::Video, t0 + secs(40));
+        let closed = gaps.poll(t0 + secs(40));
+        let expected = MediaGap {
+            start_offset: clock.offset_secs_at(t0 + secs(5)),
+            end_offset: clock.offset_secs_at(t0 + secs(40)),
+            kind: MediaKind::Video,
+        };
+        assert_eq!(closed, vec![GapEvent::Closed(expected.clone())]);
+        assert!((gaps.total_gap_secs() - expected.duration_secs()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_muted_track_is_not_a_gap() {
+        let (mut gaps, _, t0) = tracker();
+        gaps.on_media(MediaKind::Audio, t0 + secs(1));
+        gaps.set_muted(MediaKind::Audio, true, t0 + secs(2));
+
+        for s in [10, 30, 60] {
+            gaps.on_media(MediaKind::Video, t0 + secs(s));
+            assert!(gaps.poll(t0 + secs(s)).is_empty());
+        }
+
+        // The silence timer restarts from the unmute, not the last packet
+        gaps.set_muted(MediaKind::Audio, false, t0 + secs(60));
+        gaps.on_media(MediaKind::Video, t0 + secs(75));
+        assert!(gaps.poll(t0 + secs(75)).is_empty());
+        gaps.on_media(MediaKind::Video, t0 + secs(76));
+        assert!(matches!(
+            gaps.poll(t0 + secs(76)).as_slice(),
+            [GapEvent::Opened { kind: MediaKind::Audio, .. }]
+        ));
+        assert_eq!(gaps.total_gap_secs(), 0.0);
+    }
+
+    #[test]
+    fn test_mute_during_gap_ends_it() {
+        let (mut gaps, clock, t0) = tracker();
+        gaps.set_muted(MediaKind::Audio, true, t0);
+        assert_eq!(gaps.poll(t0 + secs(16)).len(), 1);
+
+        gaps.set_muted(MediaKind::Video, true, t0 + secs(25));
+        let events = gaps.poll(t0 + secs(50));
+        assert_eq!(
+            events,
+            vec![GapEvent::Closed(MediaGap {
+                start_offset: clock.offset_secs_at(t0),
+                end_offset: clock.offset_secs_at(t0 + secs(25)),
+                kind: MediaKind::Video,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_finish_closes_gaps_that_never_resumed() {
+        let (mut gaps, clock, t0) = tracker();
+        gaps.on_media(MediaKind::Audio, t0 + secs(10));
+        gaps.on_media(MediaKind::Video, t0 + secs(10));
+
+        // Stopped before anyone polled: the gap is still opened and closed
+        let events = gaps.finish(t0 + secs(40));
+        assert_eq!(events.len(), 4);
+        assert!(events.contains(&GapEvent::Closed(MediaGap {
+            start_offset: clock.offset_secs_at(t0 + secs(10)),
+            end_offset: clock.offset_secs_at(t0 + secs(40)),
+            kind: MediaKind::Audio,
+        })));
+        assert!((gaps.total_gap_secs() - 60.0).abs() < 1e-6);
+        assert!(gaps.finish(t0 + secs(41)).is_empty());
+    }
+
+    #[test]
+    fn test_gap_serialization() {
+        let gap = MediaGap { start_offset: 12.5, end_offset: 30.0, kind: MediaKind::Audio };
+        let json = serde_json::to_value(&gap).unwrap();
+        assert_eq!(json, serde_json::json!({ "start_offset": 12.5, "end_offset": 30.0, "kind": "audio" }));
+    }
+}
@@ -0,0 +1,287 @@
+//! Per-student exam integrity score.
+//!
+//! A score is in basis points: 10000 means nothing in the session called for a
+//! closer look. Every input subtracts `count × weight` basis points, and the
+//! score never drops below 0. Weights are basis points per occurrence:
+//!
+//! | Key | Applied |
+//! |-----|---------|
+//! | `incident.<activity_type>` | per reported suspicious activity of that type |
+//! | `incident.default` | per report of a type without its own weight |
+//! | `media_gap` | per gap in the student's recorded media |
+//! | `rejoin` | per time the student's connection was lost |
+//! | `verification.<status>` | once, for the latest ID verification status (`valid`, `invalid`, `pending`, `skipped`, or `none` when never verified) |
+//!
+//! `INTEGRITY_WEIGHTS` overrides any of them as `key=weight` pairs, e.g.
+//! `INTEGRITY_WEIGHTS=incident.tab_switch=100,rejoin=0`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use crate::config::env;
+
+/// Score of a session with nothing to review
+pub const MAX_INTEGRITY_SCORE: u32 = 10_000;
+
+/// Activity type of the incident raised when a recorded track stops delivering media
+pub const MEDIA_GAP_ACTIVITY: &str = "media_gap";
+
+const DEFAULT_INCIDENT_WEIGHTS: &[(&str, u32)] = &[
+    ("unauthorized_person", 2000),
+    ("multiple_devices", 1500),
+    ("screen_share", 1000),
+    ("audio_anomaly", 500),
+    ("tab_switch", 300),
+    ("window_blur", 200),
+];
+const DEFAULT_INCIDENT_WEIGHT: u32 = 500;
+const DEFAULT_MEDIA_GAP_WEIGHT: u32 = 300;
+const DEFAULT_REJOIN_WEIGHT: u32 = 200;
+const DEFAULT_VERIFICATION_WEIGHTS: &[(&str, u32)] = &[
+    ("valid", 0),
+    ("invalid", 3000),
+    ("pending", 0),
+    ("skipped", 500),
+    ("none", 0),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityWeights {
+    pub incidents: BTreeMap<String, u32>,
+    pub default_incident: u32,
+    pub media_gap: u32,
+    pub rejoin: u32,
+    /// By verification status, `none` for never verified
+    pub verification: BTreeMap<String, u32>,
+}
+
+impl Default for IntegrityWeights {
+    fn default() -> Self {
+        let table = |entries: &[(&str, u32)]| {
+            entries.iter().map(|(key, weight)| (key.to_string(), *weight)).collect()
+        };
+        Self {
+            incidents: table(DEFAULT_INCIDENT_WEIGHTS),
+            default_incident: DEFAULT_INCIDENT_WEIGHT,
+            media_gap: DEFAULT_MEDIA_GAP_WEIGHT,
+            rejoin: DEFAULT_REJOIN_WEIGHT,
+            verification: table(DEFAULT_VERIFICATION_WEIGHTS),
+        }
+    }
+}
+
+impl IntegrityWeights {
+    /// Defaults with the overrides in `INTEGRITY_WEIGHTS`; invalid entries are
+    /// reported and skipped
+    pub fn from_env() -> Self {
+        let mut weights = Self::default();
+        for entry in env::get_list("INTEGRITY_WEIGHTS") {
+            if let Err(e) = weights.apply(&entry) {
+                tracing::warn!(entry = %entry, "Ignoring INTEGRITY_WEIGHTS entry: {}", e);
+            }
+        }
+        weights
+    }
+
+    /// Applies one `key=weight` override
+    pub fn apply(&mut self, entry: &str) -> Result<(), String> {
+        let (key, weight) = entry.split_once('=').ok_or("expected key=weight")?;
+        let weight: u32 = weight
+            .trim()
+            .parse()
+            .map_err(|_| format!("weight {} is not a whole number of basis points", weight.trim()))?;
+        let key = key.trim().to_ascii_lowercase();
+
+        match key.split_once('.') {
+            Some(("incident", "default")) => self.default_incident = weight,
+            Some(("incident", activity_type)) if !activity_type.is_empty() => {
+                self.incidents.insert(activity_type.to_string(), weight);
+            }
+            Some(("verification", status)) if self.verification.contains_key(status) => {
+                self.verification.insert(status.to_string(), weight);
+            }
+            None if key == MEDIA_GAP_ACTIVITY => self.media_gap = weight,
+            None if key == "rejoin" => self.rejoin = weight,
+            _ => return Err(format!("unknown key {}", key)),
+        }
+        Ok(())
+    }
+
+    pub fn incident(&self, activity_type: &str) -> u32 {
+        self.incidents.get(activity_type).copied().unwrap_or(self.default_incident)
+    }
+
+    /// Weight of a verification status; statuses without one count as `pending`
+    pub fn verification(&self, status: Option<&str>) -> u32 {
+        let status = status.unwrap_or("none");
+        self.verification
+            .get(status)
+            .or_else(|| self.verification.get("pending"))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+static WEIGHTS: OnceLock<IntegrityWeights> = OnceLock::new();
+
+/// Weights read from the environment once per process
+pub fn weights() -> &'static IntegrityWeights {
+    WEIGHTS.get_or_init(IntegrityWeights::from_env)
+}
+
+/// What a student's score is computed from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityInputs {
+    /// Suspicious activity reports by type, media gaps excluded
+    pub incidents: BTreeMap<String, u32>,
+    pub media_gaps: u32,
+    pub rejoins: u32,
+    /// Latest ID verification status, `None` when never verified
+    pub verification: Option<String>,
+}
+
+/// One input's share of the deductions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Penalty {
+    /// `incident.<type>`, `media_gap`, `rejoin` or `verification.<status>`
+    pub input: String,
+    pub count: u32,
+    /// Basis points per occurrence
+    pub weight: u32,
+    /// `count × weight`
+    pub points: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntegrityScore {
+    /// Basis points, 10000 = nothing to review
+    pub score: u32,
+    /// Every input that occurred, including those weighted zero
+    pub penalties: Vec<Penalty>,
+}
+
+/// Scores a student. Pure: the same inputs and weights always give the same result.
+pub fn score(inputs: &IntegrityInputs, weights: &IntegrityWeights) -> IntegrityScore {
+    let penalty = |input: String, count: u32, weight: u32| Penalty {
+        input,
+        count,
+        weight,
+        points: count.saturating_mul(weight),
+    };
+
+    let mut penalties: Vec<Penalty> = inputs
+        .incidents
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .map(|(activity_type, count)| {
+            penalty(format!("incident.{}", activity_type), *count, weights.incident(activity_type))
+        })
+        .collect();
+    if inputs.media_gaps > 0 {
+        penalties.push(penalty(MEDIA_GAP_ACTIVITY.to_string(), inputs.media_gaps, weights.media_gap));
+    }
+    if inputs.rejoins > 0 {
+        penalties.push(penalty("rejoin".to_string(), inputs.rejoins, weights.rejoin));
+    }
+    let status = inputs.verification.as_deref();
+    penalties.push(penalty(
+        format!("verification.{}", status.unwrap_or("none")),
+        1,
+        weights.verification(status),
+    ));
+
+    let deducted = penalties.iter().fold(0u32, |total, p| total.saturating_add(p.points));
+    IntegrityScore {
+        score: MAX_INTEGRITY_SCORE.saturating_sub(deducted),
+        penalties,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(incidents: &[(&str, u32)], media_gaps: u32, rejoins: u32, verification: Option<&str>) -> IntegrityInputs {
+        IntegrityInputs {
+            incidents: incidents.iter().map(|(t, n)| (t.to_string(), *n)).collect(),
+            media_gaps,
+            rejoins,
+            verification: verification.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_clean_session_scores_full_marks() {
+        let result = score(&IntegrityInputs::default(), &IntegrityWeights::default());
+        assert_eq!(result.score, MAX_INTEGRITY_SCORE);
+        assert_eq!(result.penalties.len(), 1);
+        assert_eq!(result.penalties[0].input, "verification.none");
+        assert_eq!(result.penalties[0].points, 0);
+    }
+
+    #[test]
+    fn test_each_input_deducts_count_times_weight() {
+        let result = score(
+            &inputs(&[("tab_switch", 3), ("unknown_thing", 1)], 2, 1, Some("skipped")),
+            &IntegrityWeights::default(),
+        );
+
+        let points: Vec<_> = result.penalties.iter().map(|p| (p.input.as_str(), p.count, p.points)).collect();
+        assert_eq!(
+            points,
+            vec![
+                ("incident.tab_switch", 3, 900),
+                ("incident.unknown_thing", 1, DEFAULT_INCIDENT_WEIGHT),
+                ("media_gap", 2, 600),
+                ("rejoin", 1, 200),
+                ("verification.skipped", 1, 500),
+            ]
+        );
+        assert_eq!(result.score, 10_000 - 900 - 500 - 600 - 200 - 500);
+    }
+
+    #[test]
+    fn test_score_floors_at_zero() {
+        let result = score(&inputs(&[("unauthorized_person", 50)], 0, 0, Some("invalid")), &IntegrityWeights::default());
+        assert_eq!(result.score, 0);
+
+        let result = score(&inputs(&[("tab_switch", u32::MAX)], u32::MAX, 0, None), &IntegrityWeights::default());
+        assert_eq!(result.score, 0);
+    }
+
+    #[test]
+    fn test_more_incidents_never_raise_the_score() {
+        let weights = IntegrityWeights::default();
+        let mut previous = MAX_INTEGRITY_SCORE;
+        for count in 0..40 {
+            let current = score(&inputs(&[("window_blur", count)], count / 2, count / 4, None), &weights).score;
+            assert!(current <= previous);
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn test_overrides_replace_defaults() {
+        let mut weights = IntegrityWeights::default();
+        weights.apply("incident.tab_switch=100").unwrap();
+        weights.apply("incident.default = 50").unwrap();
+        weights.apply("REJOIN=0").unwrap();
+        weights.apply("media_gap=1000").unwrap();
+        weights.apply("verification.none=700").unwrap();
+
+        assert_eq!(weights.incident("tab_switch"), 100);
+        assert_eq!(weights.incident("other"), 50);
+        assert_eq!(weights.rejoin, 0);
+        assert_eq!(weights.media_gap, 1000);
+        assert_eq!(weights.verification(None), 700);
+        // Unlisted statuses are treated as pending
+        assert_eq!(weights.verification(Some("expired")), weights.verification(Some("pending")));
+
+        assert!(weights.apply("rejoin").is_err());
+        assert!(weights.apply("rejoin=-5").is_err());
+        assert!(weights.apply("verification.maybe=10").is_err());
+        assert!(weights.apply("gaps=10").is_err());
+        assert_eq!(weights.rejoin, 0);
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Observed keyframe cadence for a single recording, kept for diagnostics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KeyframeStats {
+    pub keyframe_count: u64,
+    pub pli_requests: u64,
+    pub min_interval_ms: Option<u64>,
+    pub max_interval_ms: Option<u64>,
+    pub avg_interval_ms: Option<u64>,
+    #[serde(skip)]
+    last_keyframe: Option<Instant>,
+    #[serde(skip)]
+    total_interval_ms: u64,
+}
+
+/// Equal when the reported figures are; the running state is not kept
+/// across serialization
+impl PartialEq for KeyframeStats {
+    fn eq(&self, other: &Self) -> bool {
+        (self.keyframe_count, self.pli_requests, self.min_interval_ms, self.max_interval_ms, self.avg_interval_ms)
+            == (other.keyframe_count, other.pli_requests, other.min_interval_ms, other.max_interval_ms, other.avg_interval_ms)
+    }
+}
+
+impl KeyframeStats {
+    /// Record a keyframe observed at `now`
+    pub fn record_keyframe(&mut self, now: Instant) {
+        if let Some(last) = self.last_keyframe {
+            let interval_ms = now.duration_since(last).as_millis() as u64;
+            self.total_interval_ms += interval_ms;
+            self.min_interval_ms = Some(self.min_interval_ms.map_or(interval_ms, |m| m.min(interval_ms)));
+            self.max_interval_ms = Some(self.max_interval_ms.map_or(interval_ms, |m| m.max(interval_ms)));
+            // keyframe_count is at least 1 here, so there are keyframe_count intervals after this one
+            self.avg_interval_ms = Some(self.total_interval_ms / self.keyframe_count);
+        }
+        self.keyframe_count += 1;
+        self.last_keyframe = Some(now);
+    }
+
+    /// Record that the SFU asked the publisher for a keyframe
+    pub fn record_pli_request(&mut self) {
+        self.pli_requests += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_empty_stats() {
+        let stats = KeyframeStats::default();
+        assert_eq!(stats.keyframe_count, 0);
+        assert!(stats.avg_interval_ms.is_none());
+    }
+
+    #[test]
+    fn test_interval_statistics() {
+        let mut stats = KeyframeStats::default();
+        let start = Instant::now();
+        stats.record_keyframe(start);
+        stats.record_keyframe(start + Duration::from_millis(2000));
+        stats.record_keyframe(start + Duration::from_millis(6000));
+
+        assert_eq!(stats.keyframe_count, 3);
+        assert_eq!(stats.min_interval_ms, Some(2000));
+        assert_eq!(stats.max_interval_ms, Some(4000));
+        assert_eq!(stats.avg_interval_ms, Some(3000));
+    }
+
+    #[test]
+    fn test_serialization_skips_internal_fields() {
+        let mut stats = KeyframeStats::default();
+        stats.record_keyframe(Instant::now());
+        stats.record_pli_request();
+
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(json.contains("\"keyframe_count\":1"));
+        assert!(json.contains("\"pli_requests\":1"));
+        assert!(!json.contains("last_keyframe"));
+    }
+}
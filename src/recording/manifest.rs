@@ -0,0 +1,548 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::integrity::{self, IntegrityInputs, IntegrityWeights, Penalty, MEDIA_GAP_ACTIVITY};
+use super::metadata::SessionMetadata;
+use super::status::CompletedRecording;
+
+/// Layout version of the manifest document
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Name the manifest is uploaded under
+pub const MANIFEST_FILE: &str = "room_manifest.json";
+
+/// Participants and flagged activity of a room, kept until its manifest is built
+#[derive(Debug, Default, Clone)]
+pub struct RoomSession {
+    /// peer_id -> wallet address, for everyone who joined with one
+    wallets: HashMap<String, String>,
+    /// (peer_id, activity_type) -> running summary
+    incidents: BTreeMap<(String, String), IncidentSummary>,
+    /// Latest metadata the proctor set for the room
+    metadata: SessionMetadata,
+    /// Everyone who left, in the order they left
+    departures: Vec<Departure>,
+    /// Students who joined, whether or not they are still in the room
+    students: BTreeSet<String>,
+    /// peer_id -> latest ID verification status
+    verifications: HashMap<String, String>,
+    /// Steps taken on join requests the proctor left unanswered, in order
+    join_escalations: Vec<JoinEscalationRecord>,
+    /// peer_id -> Unix time in milliseconds it first reported end-to-end encrypted media
+    e2ee: BTreeMap<String, u64>,
+    /// Unix time in milliseconds when the room was created
+    opened_at: Option<u64>,
+    /// Storage namespace the room was created in
+    tenant: Option<String>,
+}
+
+impl RoomSession {
+    pub fn record_opened(&mut self, at_ms: u64) {
+        self.opened_at = Some(at_ms);
+    }
+
+    pub fn set_tenant(&mut self, tenant: Option<String>) {
+        self.tenant = tenant;
+    }
+
+    pub fn record_wallet(&mut self, peer_id: &str, wallet: String) {
+        self.wallets.insert(peer_id.to_string(), wallet);
+    }
+
+    pub fn record_incident(&mut self, peer_id: &str, activity_type: &str, at_ms: u64) {
+        self.incidents
+            .entry((peer_id.to_string(), activity_type.to_string()))
+            .and_modify(|incident| {
+                incident.count += 1;
+                incident.last_at = at_ms;
+            })
+            .or_insert_with(|| IncidentSummary {
+                peer_id: peer_id.to_string(),
+                participant_wallet: None,
+                activity_type: activity_type.to_string(),
+                count: 1,
+                first_at: at_ms,
+                last_at: at_ms,
+            });
+    }
+
+    /// `cause` is why the peer left, e.g. `kicked` or `connection_lost`
+    pub fn record_departure(&mut self, peer_id: &str, cause: &str, at_ms: u64) {
+        self.departures.push(Departure {
+            peer_id: peer_id.to_string(),
+            participant_wallet: None,
+            cause: cause.to_string(),
+            left_at: at_ms,
+        });
+    }
+
+    /// `action` is the escalation step, e.g. `reminded` or `auto_denied`,
+    /// under the room's `policy`
+    pub fn record_join_escalation(&mut self, peer_id: &str, action: &str, policy: &str, waited_secs: u64, at_ms: u64) {
+        self.join_escalations.push(JoinEscalationRecord {
+            peer_id: peer_id.to_string(),
+            participant_wallet: None,
+            action: action.to_string(),
+            policy: policy.to_string(),
+            waited_secs,
+            at: at_ms,
+        });
+    }
+
+    /// Keeps the first report; the peer's media is not recorded from then on
+    pub fn record_e2ee(&mut self, peer_id: &str, at_ms: u64) {
+        self.e2ee.entry(peer_id.to_string()).or_insert(at_ms);
+    }
+
+    pub fn record_student(&mut self, peer_id: &str) {
+        self.students.insert(peer_id.to_string());
+    }
+
+    pub fn record_verification(&mut self, peer_id: &str, status: &str) {
+        self.verifications.insert(peer_id.to_string(), status.to_string());
+    }
+
+    pub fn is_student(&self, peer_id: &str) -> bool {
+        self.students.contains(peer_id)
+    }
+
+    /// Whether the proctor has recorded a result for the student's ID check,
+    /// `pending` not counting as one
+    pub fn is_verified(&self, peer_id: &str) -> bool {
+        self.verifications
+            .get(peer_id)
+            .is_some_and(|status| status != "pending")
+    }
+
+    /// Students in peer_id order
+    pub fn students(&self) -> impl Iterator<Item = &str> {
+        self.students.iter().map(String::as_str)
+    }
+
+    /// What this session contributes to a student's integrity score
+    pub fn integrity_inputs(&self, peer_id: &str) -> IntegrityInputs {
+        let mut inputs = IntegrityInputs {
+            verification: self.verifications.get(peer_id).cloned(),
+            ..Default::default()
+        };
+        for incident in self.incidents.values().filter(|incident| incident.peer_id == peer_id) {
+            if incident.activity_type == MEDIA_GAP_ACTIVITY {
+                inputs.media_gaps += incident.count;
+            } else {
+                inputs.incidents.insert(incident.activity_type.clone(), incident.count);
+            }
+        }
+        inputs.rejoins = self
+            .departures
+            .iter()
+            .filter(|departure| departure.peer_id == peer_id && departure.cause == "connection_lost")
+            .count() as u32;
+        inputs
+    }
+
+    pub fn set_metadata(&mut self, metadata: SessionMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    fn wallet(&self, peer_id: &str) -> Option<String> {
+        self.wallets.get(peer_id).cloned()
+    }
+}
+
+/// Suspicious activity of one kind reported for one participant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncidentSummary {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    pub activity_type: String,
+    pub count: u32,
+    /// Unix time in milliseconds of the first and last report
+    pub first_at: u64,
+    pub last_at: u64,
+}
+
+/// A student's integrity score when the room closed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StudentIntegrity {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    /// Basis points, 10000 = nothing to review
+    pub score: u32,
+    pub penalties: Vec<Penalty>,
+}
+
+/// A participant leaving the room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Departure {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    pub cause: String,
+    /// Unix time in milliseconds
+    pub left_at: u64,
+}
+
+/// An escalation step on a join request the proctor did not answer in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JoinEscalationRecord {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    pub action: String,
+    pub policy: String,
+    /// Seconds the request had been waiting
+    pub waited_secs: u64,
+    /// Unix time in milliseconds
+    pub at: u64,
+}
+
+/// A participant whose media was end-to-end encrypted, so it was forwarded
+/// but not recorded
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct E2eeParticipant {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    /// Unix time in milliseconds of the first report; nothing was recorded after it
+    pub reported_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestRecording {
+    pub peer_id: String,
+    pub participant_wallet: Option<String>,
+    pub file: String,
+    pub cid: Option<String>,
+    /// SHA-256 of the local file, hex encoded
+    pub sha256: Option<String>,
+    pub started_at: Option<u64>,
+    pub stopped_at: u64,
+    pub duration_secs: u64,
+    pub bytes_written: u64,
+    /// Seconds in which a live track delivered no media
+    #[serde(default)]
+    pub gap_secs: f64,
+    /// File this recording continues after a restart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// File the recording continued in after a restart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    /// Room `previous` belongs to, for a student transferred from another room
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_room: Option<String>,
+    /// Room `next` belongs to, for a student transferred to another room
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_room: Option<String>,
+}
+
+/// Canonical record of a closed room, uploaded to IPFS and referenced on-chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomManifest {
+    pub version: u32,
+    pub room_id: String,
+    /// Storage namespace of the room, absent for rooms created without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Exam title, course code and notes the proctor attached to the session
+    #[serde(default, skip_serializing_if = "SessionMetadata::is_empty")]
+    pub metadata: SessionMetadata,
+    /// Unix time in milliseconds when the room was created; absent from
+    /// manifests written before it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opened_at: Option<u64>,
+    /// Unix time in milliseconds when the manifest was built
+    pub closed_at: u64,
+    /// False when the close flow stopped waiting with uploads still running
+    pub complete: bool,
+    pub pending_uploads: usize,
+    pub recordings: Vec<ManifestRecording>,
+    pub incidents: Vec<IncidentSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub departures: Vec<Departure>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub join_escalations: Vec<JoinEscalationRecord>,
+    /// Participants with end-to-end encrypted media, in peer_id order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub e2ee: Vec<E2eeParticipant>,
+    /// Final integrity score of every student, lowest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub integrity: Vec<StudentIntegrity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view_events_cid: Option<String>,
+}
+
+impl RoomManifest {
+    pub fn build(
+        room_id: &str,
+        closed_at: u64,
+        completed: &[CompletedRecording],
+        session: &RoomSession,
+        pending_uploads: usize,
+        view_events_cid: Option<String>,
+        weights: &IntegrityWeights,
+    ) -> Self {
+        let recordings = completed
+            .iter()
+            .map(|recording| ManifestRecording {
+                peer_id: recording.peer_id.clone(),
+                participant_wallet: session.wallet(&recording.peer_id),
+                file: recording.file.clone(),
+                cid: recording.cid.clone(),
+                sha256: recording.sha256.clone(),
+                started_at: recording.started_at,
+                stopped_at: recording.stopped_at,
+                duration_secs: recording.duration_secs,
+                bytes_written: recording.bytes_written,
+                gap_secs: recording.gap_secs,
+                previous: recording.previous.clone(),
+                next: recording.next.clone(),
+                previous_room: recording.previous_room.clone(),
+                next_room: recording.next_room.clone(),
+            })
+            .collect();
+
+        let incidents = session
+            .incidents
+            .values()
+            .map(|incident| IncidentSummary {
+                participant_wallet: session.wallet(&incident.peer_id),
+                ..incident.clone()
+            })
+            .collect();
+
+        let departures = session
+            .departures
+            .iter()
+            .map(|departure| Departure {
+                participant_wallet: session.wallet(&departure.peer_id),
+                ..departure.clone()
+            })
+            .collect();
+
+        let join_escalations = session
+            .join_escalations
+            .iter()
+            .map(|escalation| JoinEscalationRecord {
+                participant_wallet: session.wallet(&escalation.peer_id),
+                ..escalation.clone()
+            })
+            .collect();
+
+        let e2ee = session
+            .e2ee
+            .iter()
+            .map(|(peer_id, reported_at)| E2eeParticipant {
+                peer_id: peer_id.clone(),
+                participant_wallet: session.wallet(peer_id),
+                reported_at: *reported_at,
+            })
+            .collect();
+
+        let mut integrity: Vec<StudentIntegrity> = session
+            .students()
+            .map(|peer_id| {
+                let scored = integrity::score(&session.integrity_inputs(peer_id), weights);
+                StudentIntegrity {
+                    peer_id: peer_id.to_string(),
+                    participant_wallet: session.wallet(peer_id),
+                    score: scored.score,
+                    penalties: scored.penalties,
+                }
+            })
+            .collect();
+        integrity.sort_by_key(|student| student.score);
+
+        Self {
+            version: MANIFEST_VERSION,
+            room_id: room_id.to_string(),
+            tenant: session.tenant.clone(),
+            metadata: session.metadata.clone(),
+            opened_at: session.opened_at,
+            closed_at,
+            complete: pending_uploads == 0,
+            pending_uploads,
+            recordings,
+            incidents,
+            departures,
+            join_escalations,
+            e2ee,
+            integrity,
+            view_events_cid,
+        }
+    }
+}
+
+/// SHA-256 of a file as lowercase hex, read in chunks so large recordings
+/// are never held in memory
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completed(peer_id: &str, cid: Option<&str>) -> CompletedRecording {
+        CompletedRecording {
+            peer_id: peer_id.to_string(),
+            file: format!("{}_1700000000.webm", peer_id),
+            started_at: Some(1_700_000_000_000),
+            stopped_at: 1_700_000_060_000,
+            started_at_local: None,
+            stopped_at_local: None,
+            duration_secs: 60,
+            bytes_written: 4096,
+            cid: cid.map(String::from),
+            sha256: Some("ab".repeat(32)),
+            gap_secs: 0.0,
+            previous: None,
+            next: None,
+            previous_room: None,
+            next_room: None,
+            stop_reason: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_joins_wallets_and_incidents() {
+        let mut session = RoomSession::default();
+        session.record_wallet("student_1", "0x1111111111111111111111111111111111111111".to_string());
+        session.record_incident("student_1", "tab_switch", 1_700_000_010_000);
+        session.record_incident("student_1", "tab_switch", 1_700_000_020_000);
+        session.record_incident("student_2", "window_blur", 1_700_000_030_000);
+        session.record_departure("student_2", "connection_lost", 1_700_000_040_000);
+        session.record_departure("student_1", "room_closed", 1_700_000_090_000);
+        session.record_student("student_1");
+        session.record_student("student_2");
+        session.record_verification("student_1", "valid");
+        session.record_join_escalation("student_1", "reminded", "auto_approve", 60, 1_700_000_005_000);
+        session.record_e2ee("student_1", 1_700_000_006_000);
+        session.record_e2ee("student_1", 1_700_000_007_000);
+        session.set_metadata(SessionMetadata::sanitized(
+            Some("Midterm".to_string()),
+            Some("CS101".to_string()),
+            None,
+        ));
+
+        let manifest = RoomManifest::build(
+            "123456",
+            1_700_000_100_000,
+            &[
+                CompletedRecording { gap_secs: 12.5, ..completed("student_1", Some("QmOne")) },
+                completed("student_2", None),
+            ],
+            &session,
+            0,
+            Some("QmViews".to_string()),
+            &IntegrityWeights::default(),
+        );
+
+        assert!(manifest.complete);
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        assert_eq!(manifest.recordings.len(), 2);
+        assert_eq!(
+            manifest.recordings[0].participant_wallet.as_deref(),
+            Some("0x1111111111111111111111111111111111111111")
+        );
+        assert_eq!(manifest.recordings[0].cid.as_deref(), Some("QmOne"));
+        assert_eq!(manifest.recordings[0].gap_secs, 12.5);
+        assert!(manifest.recordings[1].participant_wallet.is_none());
+
+        assert_eq!(manifest.incidents.len(), 2);
+        let tab_switch = &manifest.incidents[0];
+        assert_eq!((tab_switch.peer_id.as_str(), tab_switch.activity_type.as_str()), ("student_1", "tab_switch"));
+        assert_eq!(tab_switch.count, 2);
+        assert_eq!((tab_switch.first_at, tab_switch.last_at), (1_700_000_010_000, 1_700_000_020_000));
+        assert!(tab_switch.participant_wallet.is_some());
+
+        let causes: Vec<_> = manifest.departures.iter().map(|d| (d.peer_id.as_str(), d.cause.as_str())).collect();
+        assert_eq!(causes, vec![("student_2", "connection_lost"), ("student_1", "room_closed")]);
+        assert!(manifest.departures[1].participant_wallet.is_some());
+
+        assert_eq!(manifest.join_escalations.len(), 1);
+        assert_eq!(manifest.join_escalations[0].action, "reminded");
+        assert!(manifest.join_escalations[0].participant_wallet.is_some());
+
+        assert_eq!(manifest.e2ee.len(), 1);
+        assert_eq!((manifest.e2ee[0].peer_id.as_str(), manifest.e2ee[0].reported_at), ("student_1", 1_700_000_006_000));
+        assert!(manifest.e2ee[0].participant_wallet.is_some());
+
+        // Lowest score first: two tab switches outweigh a blur and a dropped connection
+        let scores: Vec<_> = manifest.integrity.iter().map(|s| (s.peer_id.as_str(), s.score)).collect();
+        assert_eq!(scores, vec![("student_1", 10_000 - 600), ("student_2", 10_000 - 200 - 200)]);
+        assert!(manifest.integrity[0].participant_wallet.is_some());
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["metadata"], serde_json::json!({ "exam_name": "Midterm", "course_code": "CS101" }));
+    }
+
+    #[test]
+    fn test_integrity_inputs_separate_gaps_and_rejoins() {
+        let mut session = RoomSession::default();
+        session.record_student("student_1");
+        session.record_incident("student_1", "tab_switch", 1);
+        session.record_incident("student_1", MEDIA_GAP_ACTIVITY, 2);
+        session.record_incident("student_1", MEDIA_GAP_ACTIVITY, 3);
+        session.record_incident("student_2", "tab_switch", 4);
+        session.record_departure("student_1", "connection_lost", 5);
+        session.record_departure("student_1", "connection_lost", 6);
+        session.record_departure("student_1", "left", 7);
+        session.record_verification("student_1", "pending");
+        session.record_verification("student_1", "invalid");
+
+        let inputs = session.integrity_inputs("student_1");
+        assert_eq!(inputs.incidents, BTreeMap::from([("tab_switch".to_string(), 1)]));
+        assert_eq!(inputs.media_gaps, 2);
+        assert_eq!(inputs.rejoins, 2);
+        assert_eq!(inputs.verification.as_deref(), Some("invalid"));
+        assert!(session.is_verified("student_1"));
+        session.record_verification("student_2", "pending");
+        assert!(!session.is_verified("student_2"));
+        assert!(!session.is_verified("nobody"));
+
+        assert!(session.is_student("student_1"));
+        assert!(!session.is_student("student_2"));
+        assert_eq!(session.integrity_inputs("nobody"), IntegrityInputs::default());
+    }
+
+    #[test]
+    fn test_partial_manifest_flagged_incomplete() {
+        let manifest = RoomManifest::build("123456", 0, &[], &RoomSession::default(), 2, None, &IntegrityWeights::default());
+        assert!(!manifest.complete);
+        assert_eq!(manifest.pending_uploads, 2);
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["complete"], false);
+        assert!(json.get("view_events_cid").is_none());
+        assert!(json.get("metadata").is_none());
+        assert!(json.get("departures").is_none());
+        assert!(json.get("join_escalations").is_none());
+        assert!(json.get("e2ee").is_none());
+        assert!(json.get("integrity").is_none());
+    }
+
+    #[test]
+    fn test_file_sha256() {
+        let path = std::env::temp_dir().join(format!("manifest_sha_{}.bin", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let digest = file_sha256(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(digest, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+}
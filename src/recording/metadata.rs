@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+/// Longest exam title kept, in characters
+pub const MAX_EXAM_NAME_CHARS: usize = 120;
+
+/// Longest course code kept, in characters
+pub const MAX_COURSE_CODE_CHARS: usize = 32;
+
+/// Longest proctor notes kept, in characters
+pub const MAX_NOTES_CHARS: usize = 2000;
+
+/// Human-facing description of a session, set by the proctor while the room is open
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exam_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub course_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+impl SessionMetadata {
+    /// Trims each field, drops control characters (notes keep line breaks) and
+    /// truncates to the per-field cap. Fields left empty become `None`.
+    pub fn sanitized(exam_name: Option<String>, course_code: Option<String>, notes: Option<String>) -> Self {
+        Self {
+            exam_name: sanitize_field(exam_name, MAX_EXAM_NAME_CHARS, false),
+            course_code: sanitize_field(course_code, MAX_COURSE_CODE_CHARS, false),
+            notes: sanitize_field(notes, MAX_NOTES_CHARS, true),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exam_name.is_none() && self.course_code.is_none() && self.notes.is_none()
+    }
+
+    /// Title for exam records, e.g. `CS101: Midterm`
+    pub fn exam_title(&self) -> Option<String> {
+        match (&self.course_code, &self.exam_name) {
+            (Some(course), Some(exam)) => Some(format!("{}: {}", course, exam)),
+            (None, Some(exam)) => Some(exam.clone()),
+            (Some(course), None) => Some(course.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+fn sanitize_field(value: Option<String>, max_chars: usize, multiline: bool) -> Option<String> {
+    let cleaned: String = value?
+        .chars()
+        .map(|c| if c == '\t' || (c == '\n' && !multiline) { ' ' } else { c })
+        .filter(|c| !c.is_control() || (multiline && *c == '\n'))
+        .collect();
+    let trimmed: String = cleaned.trim().chars().take(max_chars).collect();
+    let trimmed = trimmed.trim_end();
+    (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitized_strips_controls_and_caps_length() {
+        let metadata = SessionMetadata::sanitized(
+            Some("  Midterm\u{0007}\nExam  ".to_string()),
+            Some("CS101".repeat(20)),
+            Some("Line one\r\nLine two\u{0000}".to_string()),
+        );
+
+        assert_eq!(metadata.exam_name.as_deref(), Some("Midterm Exam"));
+        assert_eq!(metadata.course_code.as_ref().map(|c| c.chars().count()), Some(MAX_COURSE_CODE_CHARS));
+        assert_eq!(metadata.notes.as_deref(), Some("Line one\nLine two"));
+
+        let long_notes = SessionMetadata::sanitized(None, None, Some("é".repeat(MAX_NOTES_CHARS + 50)));
+        assert_eq!(long_notes.notes.unwrap().chars().count(), MAX_NOTES_CHARS);
+    }
+
+    #[test]
+    fn test_blank_fields_become_none() {
+        let metadata = SessionMetadata::sanitized(Some("   ".to_string()), Some("\u{0001}".to_string()), None);
+        assert!(metadata.is_empty());
+        assert_eq!(serde_json::to_value(&metadata).unwrap(), serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_exam_title() {
+        let both = SessionMetadata::sanitized(Some("Midterm".to_string()), Some("CS101".to_string()), None);
+        assert_eq!(both.exam_title().as_deref(), Some("CS101: Midterm"));
+
+        let exam_only = SessionMetadata::sanitized(Some("Midterm".to_string()), None, Some("notes".to_string()));
+        assert_eq!(exam_only.exam_title().as_deref(), Some("Midterm"));
+
+        assert_eq!(SessionMetadata::default().exam_title(), None);
+    }
+}
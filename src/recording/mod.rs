@@ -1,7 +1,44 @@
+mod chapters;
+mod clock;
+mod codec;
+mod disk;
+pub mod downloads;
+pub mod finalize;
+mod gaps;
+pub mod integrity;
+mod keyframes;
+mod manifest;
+mod metadata;
+pub mod permissions;
 mod pipeline;
 mod recorder;
+pub mod retention;
+// The reader is only used by `sfu-cli convert-rtpdump` and tests
+#[allow(dead_code)]
+mod rtpdump;
+mod sidecar;
 mod state;
+mod status;
+pub mod store;
+pub mod tenant;
+mod timeline;
+pub mod transcript;
+mod view_events;
 
+pub use codec::{RecordingCodecs, RtpCodec};
+pub use gaps::{GapEvent, MediaGap, MediaKind, DEFAULT_RECORDING_GAP_INCIDENT_SECS};
+pub use integrity::{IntegrityScore, MEDIA_GAP_ACTIVITY};
+pub use keyframes::KeyframeStats;
+pub use manifest::{RoomManifest, RoomSession, MANIFEST_FILE};
+pub use metadata::SessionMetadata;
 pub use pipeline::RecordingPipeline;
-pub use recorder::{RecordingManager, RecordingResult};
+pub use recorder::{RecordingFailure, RecordingLimit, RecordingManager, RecordingRestart, RecordingResult, RecordingUpload, DEFAULT_IPFS_UPLOAD_RETRIES, DEFAULT_KEYFRAME_INTERVAL_SECS};
+pub use sidecar::RecordingSidecar;
 pub use state::RecordingState;
+pub use status::{CompletedRecording, RecordingContent, RecordingDetail};
+pub use store::RecordingStore;
+#[cfg(test)]
+pub use store::{MockStore, StoreCall};
+#[cfg(test)]
+pub(crate) use recorder::tests::{encoded_rtp, recording_config};
+pub use view_events::{read_view_events, ViewEventKind, VIEW_EVENTS_FILE};
@@ -0,0 +1,327 @@
+//! Room directories hold exam footage, so they are private to the user running
+//! the server: created `RECORDING_DIR_MODE` (0700 by default), with every file
+//! in them created without group or other access beyond that mode (0600 by
+//! default). A directory with broader permissions is refused for recording
+//! unless `RECORDING_ALLOW_LAX_PERMS=true`. On platforms without Unix
+//! permissions none of this is enforced and a note is logged once.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::env;
+
+/// Default mode of room directories
+pub const DEFAULT_RECORDING_DIR_MODE: u32 = 0o700;
+
+/// How room directories and the files in them are protected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomDirPolicy {
+    pub dir_mode: u32,
+    /// Record into directories with broader permissions than `dir_mode`
+    pub allow_lax: bool,
+}
+
+impl Default for RoomDirPolicy {
+    fn default() -> Self {
+        Self {
+            dir_mode: DEFAULT_RECORDING_DIR_MODE,
+            allow_lax: false,
+        }
+    }
+}
+
+/// Parses an octal mode such as `0700` or `750`. The owner must keep full
+/// access, or the server could not write its own recordings.
+fn parse_mode(value: &str) -> Option<u32> {
+    let digits = value.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777 && mode & 0o700 == 0o700)
+}
+
+static POLICY: OnceLock<RoomDirPolicy> = OnceLock::new();
+
+/// Policy read from `RECORDING_DIR_MODE` and `RECORDING_ALLOW_LAX_PERMS` once per process
+pub fn policy() -> RoomDirPolicy {
+    *POLICY.get_or_init(|| {
+        let dir_mode = env::get_string("RECORDING_DIR_MODE")
+            .and_then(|value| {
+                let parsed = parse_mode(&value);
+                if parsed.is_none() {
+                    tracing::warn!(value = %value, "Invalid RECORDING_DIR_MODE, expected an octal mode with owner rwx such as 0700");
+                }
+                parsed
+            })
+            .unwrap_or(DEFAULT_RECORDING_DIR_MODE);
+        RoomDirPolicy {
+            dir_mode,
+            allow_lax: env::get_bool("RECORDING_ALLOW_LAX_PERMS", false),
+        }
+    })
+}
+
+/// Directories and files `harden` had to tighten
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HardenReport {
+    pub dirs_fixed: usize,
+    pub files_fixed: usize,
+    /// Broader than the policy but left alone because lax permissions are allowed
+    pub lax_allowed: usize,
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::fs::{DirBuilder, OpenOptions, Permissions};
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+    use std::path::Path;
+
+    use super::super::tenant;
+    use super::{HardenReport, RoomDirPolicy};
+
+    fn mode_of(path: &Path) -> io::Result<u32> {
+        Ok(std::fs::metadata(path)?.permissions().mode() & 0o777)
+    }
+
+    impl RoomDirPolicy {
+        pub fn file_mode(&self) -> u32 {
+            self.dir_mode & 0o666
+        }
+
+        /// Creates `dir` and any missing parents with the policy's mode, then
+        /// refuses it if it already existed with broader permissions
+        pub fn create_room_dir(&self, dir: &Path) -> io::Result<()> {
+            DirBuilder::new().recursive(true).mode(self.dir_mode).create(dir)?;
+            self.check_dir(dir)
+        }
+
+        pub fn check_dir(&self, dir: &Path) -> io::Result<()> {
+            let mode = mode_of(dir)?;
+            if mode & !self.dir_mode == 0 {
+                return Ok(());
+            }
+            if self.allow_lax {
+                tracing::warn!(dir = %dir.display(), mode = %format!("{:o}", mode), "Recording into a directory with lax permissions");
+                return Ok(());
+            }
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} has mode {:o}, broader than {:o}; fix it or set RECORDING_ALLOW_LAX_PERMS=true",
+                    dir.display(),
+                    mode,
+                    self.dir_mode
+                ),
+            ))
+        }
+
+        pub fn file_options(&self) -> OpenOptions {
+            let mut options = OpenOptions::new();
+            options.mode(self.file_mode());
+            options
+        }
+
+        /// Tightens `path` to `mode` if it is broader; returns whether it changed
+        fn tighten(&self, path: &Path, mode: u32, report: &mut HardenReport) -> io::Result<bool> {
+            let current = mode_of(path)?;
+            if current & !mode == 0 {
+                return Ok(false);
+            }
+            if self.allow_lax {
+                report.lax_allowed += 1;
+                return Ok(false);
+            }
+            std::fs::set_permissions(path, Permissions::from_mode(current & mode))?;
+            tracing::info!(path = %path.display(), from = %format!("{:o}", current), to = %format!("{:o}", current & mode), "Tightened recording permissions");
+            Ok(true)
+        }
+
+        pub fn set_file_mode(&self, path: &Path) -> io::Result<()> {
+            self.tighten(path, self.file_mode(), &mut HardenReport::default()).map(|_| ())
+        }
+
+        /// Tightens the output directory, every tenant namespace and room
+        /// directory in it and the files they contain to the policy. With
+        /// lax permissions allowed, only counts what is broader.
+        pub fn harden(&self, output_dir: &Path) -> io::Result<HardenReport> {
+            let mut report = HardenReport::default();
+            if !output_dir.is_dir() {
+                return Ok(report);
+            }
+            if self.tighten(output_dir, self.dir_mode, &mut report)? {
+                report.dirs_fixed += 1;
+            }
+            let namespaces = tenant::tenants(output_dir)?.into_iter().map(|tenant| output_dir.join(tenant));
+            let rooms = tenant::room_dirs(output_dir)?.into_iter().map(|(_, room)| room);
+            for dir in namespaces.chain(rooms) {
+                if self.tighten(&dir, self.dir_mode, &mut report)? {
+                    report.dirs_fixed += 1;
+                }
+                for entry in std::fs::read_dir(dir)? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() && self.tighten(&entry.path(), self.file_mode(), &mut report)? {
+                        report.files_fixed += 1;
+                    }
+                }
+            }
+            Ok(report)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Once;
+
+    use super::{HardenReport, RoomDirPolicy};
+
+    fn note_unsupported() {
+        static NOTE: Once = Once::new();
+        NOTE.call_once(|| {
+            tracing::info!("Recording directory permissions are not enforced on this platform");
+        });
+    }
+
+    impl RoomDirPolicy {
+        pub fn create_room_dir(&self, dir: &Path) -> io::Result<()> {
+            note_unsupported();
+            std::fs::create_dir_all(dir)
+        }
+
+        pub fn check_dir(&self, _dir: &Path) -> io::Result<()> {
+            note_unsupported();
+            Ok(())
+        }
+
+        pub fn file_options(&self) -> OpenOptions {
+            OpenOptions::new()
+        }
+
+        pub fn set_file_mode(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        pub fn harden(&self, _output_dir: &Path) -> io::Result<HardenReport> {
+            note_unsupported();
+            Ok(HardenReport::default())
+        }
+    }
+}
+
+/// Creates a room directory (and missing parents) under the process policy
+pub fn create_room_dir(dir: &Path) -> io::Result<()> {
+    policy().create_room_dir(dir)
+}
+
+/// Options for creating a file in a room directory with the policy's file mode
+pub fn private_file() -> OpenOptions {
+    policy().file_options()
+}
+
+/// `std::fs::write` for files in room directories
+pub fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+    private_file()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+}
+
+/// Tightens a file written by something else, e.g. GStreamer, to the policy's file mode
+pub fn set_private(path: &Path) -> io::Result<()> {
+    policy().set_file_mode(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-perms-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn mode(path: &Path) -> u32 {
+        std::fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("0700"), Some(0o700));
+        assert_eq!(parse_mode("750"), Some(0o750));
+        assert_eq!(parse_mode("0o770"), Some(0o770));
+        assert_eq!(parse_mode("0500"), None);
+        assert_eq!(parse_mode("0800"), None);
+        assert_eq!(parse_mode("10700"), None);
+    }
+
+    #[test]
+    fn test_created_dirs_and_files_are_private() {
+        let output = temp_dir("create");
+        let room = output.join("room-1");
+        let policy = RoomDirPolicy::default();
+        policy.create_room_dir(&room).unwrap();
+        assert_eq!(mode(&output), 0o700);
+        assert_eq!(mode(&room), 0o700);
+
+        let file = room.join("peer_1_100.meta.json");
+        write_private(&file, b"{}").unwrap();
+        assert_eq!(mode(&file), 0o600);
+
+        let _ = std::fs::remove_dir_all(&output);
+    }
+
+    #[test]
+    fn test_lax_dir_refused_unless_allowed() {
+        let output = temp_dir("lax");
+        let room = output.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        std::fs::set_permissions(&room, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let strict = RoomDirPolicy::default();
+        let err = strict.create_room_dir(&room).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let lax = RoomDirPolicy { allow_lax: true, ..strict };
+        lax.create_room_dir(&room).unwrap();
+        let group = RoomDirPolicy { dir_mode: 0o755, ..strict };
+        group.check_dir(&room).unwrap();
+
+        let _ = std::fs::remove_dir_all(&output);
+    }
+
+    #[test]
+    fn test_harden_tightens_existing_tree() {
+        let output = temp_dir("harden");
+        let room = output.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        let recording = room.join("peer_1_100.webm");
+        std::fs::write(&recording, b"webm").unwrap();
+        std::fs::set_permissions(&recording, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::fs::set_permissions(&room, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let lax = RoomDirPolicy { allow_lax: true, ..RoomDirPolicy::default() };
+        assert_eq!(lax.harden(&output).unwrap(), HardenReport { lax_allowed: 3, ..Default::default() });
+        assert_eq!(mode(&room), 0o755);
+
+        let report = RoomDirPolicy::default().harden(&output).unwrap();
+        assert_eq!(report, HardenReport { dirs_fixed: 2, files_fixed: 1, lax_allowed: 0 });
+        assert_eq!(mode(&output), 0o700);
+        assert_eq!(mode(&room), 0o700);
+        assert_eq!(mode(&recording), 0o600);
+        assert_eq!(RoomDirPolicy::default().harden(&output).unwrap(), HardenReport::default());
+
+        let _ = std::fs::remove_dir_all(&output);
+    }
+}
@@ -1,185 +1,364 @@
+use bytes::Bytes;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::Mutex;
 
 use crate::error::SfuError;
-use super::state::RecordingState;
+use super::clock::SessionClock;
+use super::codec::{RecordingCodecs, RtpCodec};
+use super::finalize::{final_path, part_path, MATROSKA_EXTENSION, WEBM_EXTENSION};
+use super::gaps::{GapEvent, GapTracker, MediaGap, MediaKind};
+use super::keyframes::KeyframeStats;
+use super::permissions;
+use super::rtpdump::{DumpCodec, DumpHeader, DumpTrack, DumpWriter, DUMP_EXTENSION};
+use super::state::{RecordingState, RecordingTransition};
+use super::status::{RecordingContent, RecordingDetail};
+use super::timeline::{RtpHeader, RtpTimeline};
+
+/// GStreamer elements the recording pipeline is built from
+const REQUIRED_ELEMENTS: &[&str] = &[
+    "appsrc",
+    "rtpvp8depay",
+    "rtpopusdepay",
+    "webmmux",
+    "filesink",
+    // Repairing recordings left behind by a crash
+    "filesrc",
+    "matroskademux",
+];
+
+/// Result of the one GStreamer initialization, shared by every pipeline and the health route
+static GST_INIT: std::sync::OnceLock<Result<(), String>> = std::sync::OnceLock::new();
+
+/// How long the remux of an orphaned recording may take before it is abandoned
+const REPAIR_TIMEOUT_SECS: u64 = 300;
+
+/// How long the muxer holds media back for a track that has not sent
+/// anything, e.g. from a student without a microphone, before it goes on
+/// without it
+const MISSING_TRACK_GRACE: Duration = Duration::from_secs(5);
+
+/// Time a recording spent paused
+#[derive(Debug, Default)]
+struct PauseClock {
+    /// Start of the pause in progress
+    since: Option<Instant>,
+    /// Length of the pauses that ended
+    total: Duration,
+}
+
+impl PauseClock {
+    /// Time spent paused up to `now`, counting a pause still in progress
+    fn paused_at(&self, now: Instant) -> Duration {
+        self.total + self.since.map(|since| now.saturating_duration_since(since)).unwrap_or_default()
+    }
+}
+
+/// Where a recording's media goes
+enum Sink {
+    /// Written into a webm (or an mkv for H.264) by GStreamer
+    Gstreamer {
+        pipeline: gst::Pipeline,
+        /// Re-encode VP8 and Opus, for branches built later
+        transcode: bool,
+        video: std::sync::Mutex<Branch>,
+        audio: std::sync::Mutex<Branch>,
+        /// Every track has a branch or was ended, so nothing is held back any more
+        settled: AtomicBool,
+    },
+    /// Written as received into an RTP dump, for when the pipeline is unusable
+    RtpDump(std::sync::Mutex<RtpDumpSink>),
+}
+
+/// A track's way into the muxer
+enum Branch {
+    /// The muxer pad is requested up front, so the muxer waits for the
+    /// track; the elements in front of it are built by its first packet
+    Pending(gst::Pad),
+    Linked {
+        appsrc: gst_app::AppSrc,
+        /// Places the track's packets by their RTP timestamps
+        timeline: RtpTimeline,
+    },
+    /// Ended without media, so the muxer could go on without the track
+    Ended,
+}
+
+struct RtpDumpSink {
+    room_id: String,
+    peer_id: String,
+    /// Opened by `start`, taken by `stop`
+    writer: Option<DumpWriter<BufWriter<File>>>,
+}
+
+fn dump_track(kind: MediaKind) -> DumpTrack {
+    match kind {
+        MediaKind::Video => DumpTrack::Video,
+        MediaKind::Audio => DumpTrack::Audio,
+    }
+}
+
+/// Buffer for the appsrc stamped with `pts`, wrapping the marshalled packet
+/// without another copy
+fn rtp_buffer(data: Bytes, pts: Duration) -> gst::Buffer {
+    let mut buffer = gst::Buffer::from_slice(data);
+    buffer.make_mut().set_pts(gst::ClockTime::from_nseconds(pts.as_nanos() as u64));
+    buffer
+}
+
+/// Branches that decode and re-encode VP8 and Opus, for `RECORDING_TRANSCODE`
+const VP8_TRANSCODING: &[&str] = &["rtpvp8depay", "vp8dec", "videoconvert", "vp8enc"];
+const OPUS_TRANSCODING: &[&str] = &["rtpopusdepay", "opusdec", "audioconvert", "opusenc"];
+
+/// Elements between a track's appsrc and the muxer when it is recorded as
+/// sent. Hosts that only record VP8 and Opus can do without the others, so
+/// they are checked when a pipeline needs them.
+fn passthrough_elements(codec: &RtpCodec) -> Option<&'static [&'static str]> {
+    match codec.encoding_name.to_ascii_uppercase().as_str() {
+        "VP8" => Some(&["rtpvp8depay"]),
+        "OPUS" => Some(&["rtpopusdepay"]),
+        "H264" => Some(&["rtph264depay", "h264parse"]),
+        "VP9" => Some(&["rtpvp9depay"]),
+        "AV1" => Some(&["rtpav1depay", "av1parse"]),
+        _ => None,
+    }
+}
+
+/// Elements of a track's branch; only VP8 and Opus are ever re-encoded
+fn branch_elements(codec: &RtpCodec, transcode: bool) -> Option<&'static [&'static str]> {
+    match codec.encoding_name.to_ascii_uppercase().as_str() {
+        "VP8" if transcode => Some(VP8_TRANSCODING),
+        "OPUS" if transcode => Some(OPUS_TRANSCODING),
+        _ => passthrough_elements(codec),
+    }
+}
+
+fn missing_elements<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    names
+        .into_iter()
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .collect()
+}
+
+fn make_element(factory: &str) -> Result<gst::Element, SfuError> {
+    let element = gst::ElementFactory::make(factory);
+    let element = match factory {
+        "vp8enc" => element.property("deadline", 1i64).property("cpu-used", 4i32),
+        _ => element,
+    };
+    element
+        .build()
+        .map_err(|e| SfuError::Internal(format!("Failed to create {}: {}", factory, e)))
+}
+
+/// Builds a track's branch in front of its muxer pad in a pipeline that may
+/// already be playing: appsrc -> depayloader (and parser) -> mux, or when
+/// transcoding appsrc -> depayloader -> decoder -> converter -> encoder -> mux
+fn link_branch(
+    pipeline: &gst::Pipeline,
+    kind: MediaKind,
+    codec: &RtpCodec,
+    transcode: bool,
+    mux_pad: &gst::Pad,
+) -> Result<gst_app::AppSrc, SfuError> {
+    let caps = codec.caps(kind)?;
+    let names = branch_elements(codec, transcode)
+        .ok_or_else(|| SfuError::RecordingFailed(format!("Cannot record {}", codec.encoding_name)))?;
+
+    let appsrc = gst::ElementFactory::make("appsrc")
+        .name(format!("{}_src", kind.as_str()))
+        .build()
+        .map_err(|e| SfuError::Internal(format!("Failed to create {} appsrc: {}", kind.as_str(), e)))?
+        .dynamic_cast::<gst_app::AppSrc>()
+        .map_err(|_| SfuError::Internal("Failed to cast to AppSrc".into()))?;
+    // Buffers come stamped from their RTP timestamps, not on arrival
+    appsrc.set_format(gst::Format::Time);
+    appsrc.set_is_live(true);
+    appsrc.set_do_timestamp(false);
+    appsrc.set_caps(Some(&caps));
+    let elements = names.iter().map(|&name| make_element(name)).collect::<Result<Vec<_>, _>>()?;
+
+    pipeline.add(&appsrc)
+        .and_then(|_| pipeline.add_many(&elements))
+        .map_err(|e| SfuError::Internal(format!("Failed to add {} elements: {}", kind.as_str(), e)))?;
+    gst::Element::link_many(std::iter::once(appsrc.upcast_ref::<gst::Element>()).chain(&elements))
+        .map_err(|e| SfuError::Internal(format!("Failed to link {} elements: {}", kind.as_str(), e)))?;
+    let branch_src = elements.last().and_then(|element| element.static_pad("src"))
+        .ok_or_else(|| SfuError::Internal(format!("Failed to get {} branch src pad", kind.as_str())))?;
+    branch_src.link(mux_pad)
+        .map_err(|e| SfuError::Internal(format!("Failed to link {} to mux: {}", kind.as_str(), e)))?;
+
+    // Downstream first, so nothing pushes into an element that isn't ready
+    for element in elements.iter().rev().chain([appsrc.upcast_ref::<gst::Element>()]) {
+        element.sync_state_with_parent()
+            .map_err(|e| SfuError::Internal(format!("Failed to start {} branch: {}", kind.as_str(), e)))?;
+    }
+    Ok(appsrc)
+}
+
+/// Muxer writing the container a recording's name, final or `.part`, calls for
+fn muxer_for(recording: &Path) -> &'static str {
+    let recording = final_path(recording).unwrap_or_else(|| recording.to_path_buf());
+    muxer_for_extension(&recording.extension().unwrap_or_default().to_string_lossy())
+}
+
+fn muxer_for_extension(extension: &str) -> &'static str {
+    if extension == MATROSKA_EXTENSION {
+        "matroskamux"
+    } else {
+        "webmmux"
+    }
+}
+
+impl From<&RtpCodec> for DumpCodec {
+    fn from(codec: &RtpCodec) -> Self {
+        Self {
+            encoding_name: codec.encoding_name.clone(),
+            payload_type: codec.payload_type,
+            clock_rate: codec.clock_rate,
+        }
+    }
+}
 
 pub struct RecordingPipeline {
-    pipeline: gst::Pipeline,
-    video_appsrc: Option<gst_app::AppSrc>,
-    audio_appsrc: Option<gst_app::AppSrc>,
+    sink: Sink,
+    /// Final name, which only exists once the recording has been finalized
     output_path: PathBuf,
+    /// `{output_path}.part`, what GStreamer writes to while recording
+    part_path: PathBuf,
     state: Arc<Mutex<RecordingState>>,
+    keyframe_stats: std::sync::Mutex<KeyframeStats>,
+    /// Anchors elapsed time and gap offsets at the moment the pipeline started
+    started: std::sync::OnceLock<SessionClock>,
+    /// When `stop` was called, so the duration no longer grows afterwards
+    stopped: std::sync::OnceLock<Instant>,
+    /// Time spent paused, which the recording's running time leaves out
+    pause: std::sync::Mutex<PauseClock>,
+    /// Silence on a live track before it counts as a gap (zero disables)
+    gap_threshold: Duration,
+    gaps: std::sync::Mutex<Option<GapTracker>>,
+    /// Codecs the tracks were last negotiated with, which a restart reuses
+    codecs: std::sync::Mutex<RecordingCodecs>,
+    /// File this recording continues after a restart
+    previous: Option<String>,
+    /// Room `previous` was recorded in, when the peer was transferred from it
+    previous_room: Option<String>,
+    /// First error the pipeline posted, set from the thread that hit it
+    failure: Arc<std::sync::OnceLock<String>>,
 }
 
 impl RecordingPipeline {
-    pub fn new(room_id: &str, peer_id: &str, output_dir: &str) -> Result<Self, SfuError> {
-        gst::init().map_err(|e| SfuError::Internal(format!("GStreamer init failed: {}", e)))?;
+    /// Initializes GStreamer on first use; later calls return the cached outcome
+    pub fn init_gstreamer() -> Result<(), SfuError> {
+        GST_INIT
+            .get_or_init(|| gst::init().map_err(|e| e.to_string()))
+            .clone()
+            .map_err(|e| SfuError::Internal(format!("GStreamer init failed: {}", e)))
+    }
 
-        // Create nested directory structure: recordings/{room_id}/
-        let room_dir = PathBuf::from(output_dir).join(room_id);
-        std::fs::create_dir_all(&room_dir)
-            .map_err(|e| SfuError::Internal(format!("Failed to create recording directory: {}", e)))?;
+    /// Verifies GStreamer initializes and every element the pipeline needs is installed
+    pub fn verify_environment() -> Result<(), SfuError> {
+        Self::init_gstreamer()?;
 
-        // Generate timestamp for unique filename per session
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_millis())
-            .unwrap_or(0);
-
-        // Output file: recordings/{room_id}/{peer_id}_{timestamp}.webm
-        let output_path = room_dir.join(format!("{}_{}.webm", peer_id, timestamp));
+        let missing = missing_elements(REQUIRED_ELEMENTS.iter().copied());
 
-        let pipeline = gst::Pipeline::new();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(SfuError::Internal(format!(
+                "Missing GStreamer elements: {}",
+                missing.join(", ")
+            )))
+        }
+    }
 
-        // Video branch: appsrc -> rtpvp8depay -> vp8dec -> vp8enc -> webmmux
-        let video_appsrc = gst::ElementFactory::make("appsrc")
-            .name("video_src")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create video appsrc: {}", e)))?;
-
-        let video_appsrc = video_appsrc
-            .dynamic_cast::<gst_app::AppSrc>()
-            .map_err(|_| SfuError::Internal("Failed to cast to AppSrc".into()))?;
-
-        // Configure video appsrc for RTP VP8
-        video_appsrc.set_format(gst::Format::Time);
-        video_appsrc.set_is_live(true);
-        video_appsrc.set_do_timestamp(true);
-
-        let video_caps = gst::Caps::builder("application/x-rtp")
-            .field("media", "video")
-            .field("encoding-name", "VP8")
-            .field("clock-rate", 90000i32)
-            .field("payload", 96i32)
-            .build();
-        video_appsrc.set_caps(Some(&video_caps));
-
-        let rtpvp8depay = gst::ElementFactory::make("rtpvp8depay")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create rtpvp8depay: {}", e)))?;
+    /// Verifies the decoders and encoders `RECORDING_TRANSCODE` needs are installed
+    pub fn verify_transcoding() -> Result<(), SfuError> {
+        Self::init_gstreamer()?;
 
-        let vp8dec = gst::ElementFactory::make("vp8dec")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create vp8dec: {}", e)))?;
+        let missing = missing_elements(VP8_TRANSCODING.iter().chain(OPUS_TRANSCODING).copied());
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(SfuError::Internal(format!(
+                "Missing GStreamer elements for transcoding: {}",
+                missing.join(", ")
+            )))
+        }
+    }
 
-        let videoconvert = gst::ElementFactory::make("videoconvert")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create videoconvert: {}", e)))?;
+    /// Builds the pipeline for the payload types in `codecs`, refusing
+    /// encodings it cannot depayload. Media is written as sent: VP8, VP9 and
+    /// AV1 into a webm, H.264 into an mkv, with Opus audio. Each track's
+    /// branch is only built by its first packet, so a peer without a camera
+    /// or microphone gets a file with just the other track.
+    pub fn new(room_id: &str, peer_id: &str, output_dir: &str, codecs: &RecordingCodecs) -> Result<Self, SfuError> {
+        Self::build(room_id, peer_id, output_dir, codecs, false)
+    }
 
-        let vp8enc = gst::ElementFactory::make("vp8enc")
-            .property("deadline", 1i64)
-            .property("cpu-used", 4i32)
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create vp8enc: {}", e)))?;
+    /// Like `new`, but VP8 and Opus are decoded and re-encoded, which costs
+    /// about a core per recording. Other video is still kept as sent.
+    pub fn transcoding(room_id: &str, peer_id: &str, output_dir: &str, codecs: &RecordingCodecs) -> Result<Self, SfuError> {
+        Self::build(room_id, peer_id, output_dir, codecs, true)
+    }
 
-        // Audio branch: appsrc -> rtpopusdepay -> opusdec -> opusenc -> webmmux
-        let audio_appsrc = gst::ElementFactory::make("appsrc")
-            .name("audio_src")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create audio appsrc: {}", e)))?;
-
-        let audio_appsrc = audio_appsrc
-            .dynamic_cast::<gst_app::AppSrc>()
-            .map_err(|_| SfuError::Internal("Failed to cast to AppSrc".into()))?;
-
-        // Configure audio appsrc for RTP Opus
-        audio_appsrc.set_format(gst::Format::Time);
-        audio_appsrc.set_is_live(true);
-        audio_appsrc.set_do_timestamp(true);
-
-        let audio_caps = gst::Caps::builder("application/x-rtp")
-            .field("media", "audio")
-            .field("encoding-name", "OPUS")
-            .field("clock-rate", 48000i32)
-            .field("payload", 111i32)
-            .build();
-        audio_appsrc.set_caps(Some(&audio_caps));
-
-        let rtpopusdepay = gst::ElementFactory::make("rtpopusdepay")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create rtpopusdepay: {}", e)))?;
+    fn build(room_id: &str, peer_id: &str, output_dir: &str, codecs: &RecordingCodecs, transcode: bool) -> Result<Self, SfuError> {
+        Self::init_gstreamer()?;
 
-        let opusdec = gst::ElementFactory::make("opusdec")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create opusdec: {}", e)))?;
+        // Refused up front, although the branches are only built by the first packets
+        codecs.video.caps(MediaKind::Video)?;
+        codecs.audio.caps(MediaKind::Audio)?;
+        let branch = |codec: &RtpCodec| {
+            branch_elements(codec, transcode)
+                .ok_or_else(|| SfuError::RecordingFailed(format!("Cannot record {}", codec.encoding_name)))
+        };
+        let video_branch = branch(&codecs.video)?;
+        let audio_branch = branch(&codecs.audio)?;
+        let muxer = muxer_for_extension(codecs.extension());
+        let missing = missing_elements(video_branch.iter().chain(audio_branch).copied().chain([muxer]));
+        if !missing.is_empty() {
+            return Err(SfuError::RecordingFailed(format!(
+                "Cannot record {} video and {} audio, missing GStreamer elements: {}",
+                codecs.video.encoding_name,
+                codecs.audio.encoding_name,
+                missing.join(", ")
+            )));
+        }
 
-        let audioconvert = gst::ElementFactory::make("audioconvert")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create audioconvert: {}", e)))?;
+        let output_path = Self::recording_path(room_id, peer_id, output_dir, codecs.extension())?;
+        let part_path = part_path(&output_path);
 
-        let opusenc = gst::ElementFactory::make("opusenc")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create opusenc: {}", e)))?;
+        let pipeline = gst::Pipeline::new();
 
-        // Muxer and sink
-        let webmmux = gst::ElementFactory::make("webmmux")
-            .build()
-            .map_err(|e| SfuError::Internal(format!("Failed to create webmmux: {}", e)))?;
+        // Muxer and sink; the sink doesn't wait to preroll, as the branches
+        // feeding the muxer only come with the first packets
+        let mux = make_element(muxer)?;
 
         let filesink = gst::ElementFactory::make("filesink")
-            .property("location", output_path.to_str().unwrap())
+            .property("location", part_path.to_str().unwrap())
+            .property("async", false)
             .build()
             .map_err(|e| SfuError::Internal(format!("Failed to create filesink: {}", e)))?;
 
-        // Add all elements to pipeline
-        pipeline.add_many([
-            video_appsrc.upcast_ref(),
-            &rtpvp8depay,
-            &vp8dec,
-            &videoconvert,
-            &vp8enc,
-            audio_appsrc.upcast_ref(),
-            &rtpopusdepay,
-            &opusdec,
-            &audioconvert,
-            &opusenc,
-            &webmmux,
-            &filesink,
-        ]).map_err(|e| SfuError::Internal(format!("Failed to add elements: {}", e)))?;
-
-        // Link video branch
-        gst::Element::link_many([
-            video_appsrc.upcast_ref(),
-            &rtpvp8depay,
-            &vp8dec,
-            &videoconvert,
-            &vp8enc,
-        ]).map_err(|e| SfuError::Internal(format!("Failed to link video elements: {}", e)))?;
-
-        // Link audio branch
-        gst::Element::link_many([
-            audio_appsrc.upcast_ref(),
-            &rtpopusdepay,
-            &opusdec,
-            &audioconvert,
-            &opusenc,
-        ]).map_err(|e| SfuError::Internal(format!("Failed to link audio elements: {}", e)))?;
-
-        // Link to muxer using request pads
-        let video_pad = webmmux.request_pad_simple("video_%u")
-            .ok_or_else(|| SfuError::Internal("Failed to get video pad".into()))?;
-        let vp8enc_src = vp8enc.static_pad("src")
-            .ok_or_else(|| SfuError::Internal("Failed to get vp8enc src pad".into()))?;
-        vp8enc_src.link(&video_pad)
-            .map_err(|e| SfuError::Internal(format!("Failed to link video to mux: {}", e)))?;
-
-        let audio_pad = webmmux.request_pad_simple("audio_%u")
-            .ok_or_else(|| SfuError::Internal("Failed to get audio pad".into()))?;
-        let opusenc_src = opusenc.static_pad("src")
-            .ok_or_else(|| SfuError::Internal("Failed to get opusenc src pad".into()))?;
-        opusenc_src.link(&audio_pad)
-            .map_err(|e| SfuError::Internal(format!("Failed to link audio to mux: {}", e)))?;
-
-        // Link muxer to filesink
-        webmmux.link(&filesink)
+        pipeline.add_many([&mux, &filesink])
+            .map_err(|e| SfuError::Internal(format!("Failed to add elements: {}", e)))?;
+        mux.link(&filesink)
             .map_err(|e| SfuError::Internal(format!("Failed to link mux to sink: {}", e)))?;
 
+        let request_pad = |kind: MediaKind| {
+            mux.request_pad_simple(&format!("{}_%u", kind.as_str()))
+                .ok_or_else(|| SfuError::Internal(format!("Failed to get {} pad", kind.as_str())))
+        };
+        let video_pad = request_pad(MediaKind::Video)?;
+        let audio_pad = request_pad(MediaKind::Audio)?;
+
         tracing::info!(
             room_id = %room_id,
             peer_id = %peer_id,
@@ -187,84 +366,906 @@ impl RecordingPipeline {
             "Created recording pipeline"
         );
 
-        Ok(Self {
+        let sink = Sink::Gstreamer {
             pipeline,
-            video_appsrc: Some(video_appsrc),
-            audio_appsrc: Some(audio_appsrc),
+            transcode,
+            video: std::sync::Mutex::new(Branch::Pending(video_pad)),
+            audio: std::sync::Mutex::new(Branch::Pending(audio_pad)),
+            settled: AtomicBool::new(false),
+        };
+        Ok(Self::with_sink(sink, output_path, part_path, codecs))
+    }
+
+    /// Records the packets as received into `{peer_id}_{timestamp}.rtpdump`,
+    /// without GStreamer, for hosts where the pipeline cannot be built or started
+    pub fn rtp_dump(room_id: &str, peer_id: &str, output_dir: &str, codecs: &RecordingCodecs) -> Result<Self, SfuError> {
+        let output_path = Self::recording_path(room_id, peer_id, output_dir, DUMP_EXTENSION)?;
+        let part_path = part_path(&output_path);
+
+        tracing::info!(
+            room_id = %room_id,
+            peer_id = %peer_id,
+            output_path = %output_path.display(),
+            "Created RTP dump recording"
+        );
+
+        let sink = Sink::RtpDump(std::sync::Mutex::new(RtpDumpSink {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            writer: None,
+        }));
+        Ok(Self::with_sink(sink, output_path, part_path, codecs))
+    }
+
+    /// `recordings/{room_id}/{peer_id}_{timestamp}.{extension}`, creating the room directory
+    fn recording_path(room_id: &str, peer_id: &str, output_dir: &str, extension: &str) -> Result<PathBuf, SfuError> {
+        let room_dir = PathBuf::from(output_dir).join(room_id);
+        permissions::create_room_dir(&room_dir)
+            .map_err(|e| SfuError::RecordingFailed(format!("Refusing recording directory: {}", e)))?;
+
+        // Generate timestamp for unique filename per session
+        let mut timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+
+        // A restart opens the next file while the previous one is still being written,
+        // maybe in another container, and the sidecars only differ from it in the extension
+        loop {
+            let path = room_dir.join(format!("{}_{}.{}", peer_id, timestamp, extension));
+            let taken = [WEBM_EXTENSION, MATROSKA_EXTENSION, DUMP_EXTENSION].iter().any(|extension| {
+                let path = path.with_extension(extension);
+                path.exists() || part_path(&path).exists()
+            });
+            if !taken {
+                return Ok(path);
+            }
+            timestamp += 1;
+        }
+    }
+
+    fn with_sink(sink: Sink, output_path: PathBuf, part_path: PathBuf, codecs: &RecordingCodecs) -> Self {
+        Self {
+            sink,
             output_path,
+            part_path,
             state: Arc::new(Mutex::new(RecordingState::Idle)),
-        })
+            keyframe_stats: std::sync::Mutex::new(KeyframeStats::default()),
+            started: std::sync::OnceLock::new(),
+            stopped: std::sync::OnceLock::new(),
+            pause: std::sync::Mutex::new(PauseClock::default()),
+            gap_threshold: Duration::ZERO,
+            gaps: std::sync::Mutex::new(None),
+            codecs: std::sync::Mutex::new(codecs.clone()),
+            previous: None,
+            previous_room: None,
+            failure: Arc::new(std::sync::OnceLock::new()),
+        }
+    }
+
+    /// Whether packets go to an RTP dump instead of the GStreamer pipeline
+    fn is_rtp_dump(&self) -> bool {
+        matches!(self.sink, Sink::RtpDump(_))
+    }
+
+    fn branch(&self, kind: MediaKind) -> Option<&std::sync::Mutex<Branch>> {
+        match &self.sink {
+            Sink::Gstreamer { video, audio, .. } => match kind {
+                MediaKind::Video => Some(video),
+                MediaKind::Audio => Some(audio),
+            },
+            Sink::RtpDump(_) => None,
+        }
+    }
+
+    fn appsrc(&self, kind: MediaKind) -> Option<gst_app::AppSrc> {
+        match &*self.branch(kind)?.lock().unwrap() {
+            Branch::Linked { appsrc, .. } => Some(appsrc.clone()),
+            Branch::Pending(_) | Branch::Ended => None,
+        }
+    }
+
+    /// The appsrc of `kind` and the running time of the packet with `header`
+    fn stamp(&self, kind: MediaKind, header: &RtpHeader, arrival: Duration) -> Option<(gst_app::AppSrc, Duration)> {
+        match &mut *self.branch(kind)?.lock().unwrap() {
+            Branch::Linked { appsrc, timeline } => Some((appsrc.clone(), timeline.pts(header, arrival))),
+            Branch::Pending(_) | Branch::Ended => None,
+        }
+    }
+
+    /// Whether the recording has, or still waits for, a branch for `kind`; a dump takes both
+    fn has_track(&self, kind: MediaKind) -> bool {
+        self.branch(kind)
+            .is_none_or(|branch| !matches!(*branch.lock().unwrap(), Branch::Ended))
+    }
+
+    /// Builds the branch for `kind` when its first packet arrives. Returns
+    /// false when the muxer already went on without the track, so its media
+    /// needs a new recording; a dump always takes it.
+    pub fn ensure_branch(&self, kind: MediaKind) -> Result<bool, SfuError> {
+        let Sink::Gstreamer { pipeline, transcode, .. } = &self.sink else { return Ok(true) };
+        let Some(branch) = self.branch(kind) else { return Ok(true) };
+        let mut branch = branch.lock().unwrap();
+        let mux_pad = match &*branch {
+            Branch::Pending(pad) => pad.clone(),
+            Branch::Linked { .. } => return Ok(true),
+            Branch::Ended => return Ok(false),
+        };
+
+        let codec = self.codecs.lock().unwrap().get(kind).clone();
+        *branch = Branch::Linked {
+            appsrc: link_branch(pipeline, kind, &codec, *transcode, &mux_pad)?,
+            timeline: RtpTimeline::new(codec.clock_rate),
+        };
+        tracing::debug!(path = %self.output_path.display(), track = kind.as_str(), "Built recording branch");
+        Ok(true)
+    }
+
+    /// Ends the muxer pads of tracks without a branch, so the muxer writes
+    /// the others. Only `all` at stop; otherwise once the grace has passed.
+    fn end_missing_tracks(&self, all: bool) {
+        let Sink::Gstreamer { settled, .. } = &self.sink else { return };
+        if settled.load(Ordering::Relaxed) || (!all && self.offset() < MISSING_TRACK_GRACE) {
+            return;
+        }
+        for kind in [MediaKind::Video, MediaKind::Audio] {
+            let Some(branch) = self.branch(kind) else { continue };
+            let mut branch = branch.lock().unwrap();
+            if let Branch::Pending(pad) = &*branch {
+                pad.send_event(gst::event::Eos::new());
+                if !all {
+                    tracing::info!(path = %self.output_path.display(), track = kind.as_str(), "Recording without a track that sent nothing");
+                }
+                *branch = Branch::Ended;
+            }
+        }
+        settled.store(true, Ordering::Relaxed);
+    }
+
+    /// Running time of the recording, which dump records are stamped with
+    fn offset(&self) -> Duration {
+        self.running_at(Instant::now())
+    }
+
+    /// Time from the start of the recording to `now`, less the time spent paused
+    fn running_at(&self, now: Instant) -> Duration {
+        self.started
+            .get()
+            .map(|clock| {
+                Duration::from_secs_f64(clock.offset_secs_at(now)).saturating_sub(self.pause.lock().unwrap().paused_at(now))
+            })
+            .unwrap_or_default()
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause.lock().unwrap().since.is_some()
+    }
+
+    /// Report tracks that deliver nothing for longer than `threshold` (zero disables)
+    pub fn with_gap_threshold(mut self, threshold: Duration) -> Self {
+        self.gap_threshold = threshold;
+        self
+    }
+
+    /// Link the recording to the file it continues after a restart
+    pub fn with_previous(mut self, file: String) -> Self {
+        self.previous = Some(file);
+        self
+    }
+
+    /// Mark `previous` as recorded in another room, which the peer was transferred from
+    pub fn with_previous_room(mut self, room_id: String) -> Self {
+        self.previous_room = Some(room_id);
+        self
     }
 
     pub async fn start(&self) -> Result<(), SfuError> {
         let mut state = self.state.lock().await;
-        if *state != RecordingState::Idle {
+        let Ok(started) = state.apply(RecordingTransition::Start) else {
             return Err(SfuError::Internal("Recording already started".into()));
-        }
+        };
 
-        self.pipeline.set_state(gst::State::Playing)
-            .map_err(|e| SfuError::Internal(format!("Failed to start pipeline: {}", e)))?;
+        // Created private up front; filesink truncates it but keeps the mode
+        let file = permissions::private_file()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.part_path)
+            .map_err(|e| SfuError::RecordingFailed(format!("Failed to create {}: {}", self.part_path.display(), e)))?;
 
-        *state = RecordingState::Recording;
+        let clock = SessionClock::start();
+        match &self.sink {
+            Sink::Gstreamer { pipeline, .. } => {
+                pipeline.set_state(gst::State::Playing)
+                    .map_err(|e| SfuError::Internal(format!("Failed to start pipeline: {}", e)))?;
+            }
+            Sink::RtpDump(dump) => {
+                let mut dump = dump.lock().unwrap();
+                let codecs = self.codecs.lock().unwrap().clone();
+                let header = DumpHeader {
+                    room_id: dump.room_id.clone(),
+                    peer_id: dump.peer_id.clone(),
+                    started_at_ms: clock.started_at_ms(),
+                    video: DumpCodec::from(&codecs.video),
+                    audio: DumpCodec::from(&codecs.audio),
+                };
+                let writer = DumpWriter::new(BufWriter::new(file), &header)
+                    .map_err(|e| SfuError::RecordingFailed(format!("Failed to write {}: {}", self.part_path.display(), e)))?;
+                dump.writer = Some(writer);
+            }
+        }
+
+        *state = started;
+        let _ = self.started.set(clock);
+        if !self.gap_threshold.is_zero() {
+            let kinds: Vec<MediaKind> = [MediaKind::Video, MediaKind::Audio]
+                .into_iter()
+                .filter(|kind| self.has_track(*kind))
+                .collect();
+            *self.gaps.lock().unwrap() = Some(GapTracker::new(clock, self.gap_threshold, &kinds, Instant::now()));
+        }
         tracing::info!("Recording started: {:?}", self.output_path);
         Ok(())
     }
 
+    /// Calls `notify` with the first error the pipeline posts, such as a write
+    /// to a full disk. From then on the recording reports `RecordingState::Error`
+    /// and drops its media until `fail` tears it down. A dump has no bus.
+    pub fn watch_errors(&self, notify: impl Fn(String) + Send + Sync + 'static) {
+        let Sink::Gstreamer { pipeline, .. } = &self.sink else { return };
+        let Some(bus) = pipeline.bus() else { return };
+        let failure = self.failure.clone();
+        // Runs on the posting thread and passes every message on, so `stop` still finds its EOS
+        bus.set_sync_handler(move |_, message| {
+            if let gst::MessageView::Error(err) = message.view() {
+                let source = err.src().map(|src| src.name().to_string()).unwrap_or_default();
+                let error = format!("{}: {}", source, err.error());
+                if failure.set(error.clone()).is_ok() {
+                    notify(error);
+                }
+            }
+            gst::BusSyncReply::Pass
+        });
+    }
+
+    /// The error the pipeline failed with, if it has
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.get().map(String::as_str)
+    }
+
+    /// Tears down a pipeline that posted an error. What was written stays
+    /// `.part`, to be repaired on the next startup.
+    pub async fn fail(&self) {
+        let mut state = self.state.lock().await;
+        if let Sink::Gstreamer { pipeline, .. } = &self.sink {
+            let _ = pipeline.set_state(gst::State::Null);
+        }
+        let _ = self.stopped.set(Instant::now());
+        let error = self.failure().unwrap_or("Recording failed").to_string();
+        tracing::error!(path = %self.part_path.display(), error = %error, "Recording pipeline failed");
+        *state = RecordingState::Error(error);
+    }
+
+    /// Stops taking media until `resume`. The pipeline keeps running, so the
+    /// file goes on where it left off instead of being split.
+    pub async fn pause(&self) -> Result<(), SfuError> {
+        let mut state = self.state.lock().await;
+        if let Some(error) = self.failure() {
+            return Err(SfuError::RecordingFailed(error.to_string()));
+        }
+        *state = state.apply(RecordingTransition::Pause)?;
+
+        let now = Instant::now();
+        self.pause.lock().unwrap().since = Some(now);
+        // Silence is expected from here on; gaps still open end at the pause
+        if let Some(gaps) = self.gaps.lock().unwrap().as_mut() {
+            gaps.restart_timers(now);
+        }
+        tracing::info!(path = %self.part_path.display(), "Recording paused");
+        Ok(())
+    }
+
+    /// Takes media again after `pause`. Media resumes at the running time the
+    /// pause left off at, so the file has no frozen stretch for the break.
+    pub async fn resume(&self) -> Result<(), SfuError> {
+        let mut state = self.state.lock().await;
+        if let Some(error) = self.failure() {
+            return Err(SfuError::RecordingFailed(error.to_string()));
+        }
+        let resumed = state.apply(RecordingTransition::Resume)?;
+
+        // The publisher's RTP timestamps ran on through the pause; each track
+        // is placed again from its next packet, before any packet gets through
+        for kind in [MediaKind::Video, MediaKind::Audio] {
+            if let Some(branch) = self.branch(kind) {
+                if let Branch::Linked { timeline, .. } = &mut *branch.lock().unwrap() {
+                    timeline.reanchor();
+                }
+            }
+        }
+        let now = Instant::now();
+        let paused_for = {
+            let mut pause = self.pause.lock().unwrap();
+            let paused_for = pause.since.take().map(|since| now.saturating_duration_since(since)).unwrap_or_default();
+            pause.total += paused_for;
+            paused_for
+        };
+        if let Some(gaps) = self.gaps.lock().unwrap().as_mut() {
+            gaps.restart_timers(now);
+        }
+        *state = resumed;
+        tracing::info!(path = %self.part_path.display(), paused_secs = paused_for.as_secs_f64(), "Recording resumed");
+        Ok(())
+    }
+
     pub async fn stop(&self) -> Result<PathBuf, SfuError> {
         let mut state = self.state.lock().await;
-        if *state != RecordingState::Recording {
+        if state.apply(RecordingTransition::Stop).is_err() {
             return Err(SfuError::Internal("Recording not in progress".into()));
         }
 
         *state = RecordingState::Stopping;
+        let _ = self.stopped.set(Instant::now());
 
-        // Send EOS to appsrcs
-        if let Some(ref video_src) = self.video_appsrc {
-            let _ = video_src.end_of_stream();
+        let finalized = match &self.sink {
+            Sink::Gstreamer { pipeline, .. } => {
+                // EOS through the branches that were built; tracks that never sent anything are just ended
+                self.end_missing_tracks(true);
+                for kind in [MediaKind::Video, MediaKind::Audio] {
+                    if let Some(appsrc) = self.appsrc(kind) {
+                        let _ = appsrc.end_of_stream();
+                    }
+                }
+
+                // Wait for EOS on bus; without it the muxer never wrote the file's index.
+                // A pipeline that failed never sends it.
+                let bus = pipeline.bus().unwrap();
+                let mut finalized = false;
+                if self.failure().is_none() {
+                    for msg in bus.iter_timed(gst::ClockTime::from_seconds(5)) {
+                        match msg.view() {
+                            gst::MessageView::Eos(_) => {
+                                finalized = true;
+                                break;
+                            }
+                            gst::MessageView::Error(_) => break,
+                            _ => {}
+                        }
+                    }
+                }
+
+                pipeline.set_state(gst::State::Null)
+                    .map_err(|e| SfuError::Internal(format!("Failed to stop pipeline: {}", e)))?;
+                finalized
+            }
+            Sink::RtpDump(dump) => {
+                let Some(writer) = dump.lock().unwrap().writer.take() else {
+                    return Err(SfuError::Internal("RTP dump was never opened".into()));
+                };
+                let flushed = writer
+                    .into_inner()
+                    .into_inner()
+                    .map_err(|e| e.into_error())
+                    .and_then(|file| file.sync_all());
+                if let Err(ref e) = flushed {
+                    tracing::warn!(path = %self.part_path.display(), error = %e, "Failed to flush RTP dump");
+                }
+                flushed.is_ok()
+            }
+        };
+
+        *state = match self.failure() {
+            Some(error) => RecordingState::Error(error.to_string()),
+            None => RecordingState::Stopped,
+        };
+        let events = self.gaps.lock().unwrap().as_mut().map(|gaps| gaps.finish(Instant::now()));
+        self.append_gaps(&events.unwrap_or_default());
+
+        // Left as .part, so it is repaired on the next startup instead of served half-written;
+        // a dump reads fine up to its last complete record and is converted by hand
+        if let Some(error) = self.failure() {
+            return Err(SfuError::RecordingFailed(format!(
+                "Recording failed with {}, left at {}",
+                error,
+                self.part_path.display()
+            )));
         }
-        if let Some(ref audio_src) = self.audio_appsrc {
-            let _ = audio_src.end_of_stream();
+        if !finalized {
+            return Err(SfuError::RecordingFailed(format!(
+                "Recording did not finalize, left at {}",
+                self.part_path.display()
+            )));
         }
+        std::fs::rename(&self.part_path, &self.output_path)
+            .map_err(|e| SfuError::RecordingFailed(format!("Failed to finalize {}: {}", self.part_path.display(), e)))?;
+        tracing::info!("Recording stopped: {:?}", self.output_path);
+        Ok(self.output_path.clone())
+    }
+
+    /// Remuxes a recording whose writer died before finalizing it into `output`,
+    /// keeping whatever complete clusters `source` holds
+    pub fn remux(source: &Path, output: &Path) -> Result<(), SfuError> {
+        Self::init_gstreamer()?;
+
+        let make = |factory: &str| {
+            gst::ElementFactory::make(factory)
+                .build()
+                .map_err(|e| SfuError::Internal(format!("Failed to create {}: {}", factory, e)))
+        };
+        let filesrc = make("filesrc")?;
+        filesrc.set_property("location", source.to_str().unwrap_or_default());
+        let demux = make("matroskademux")?;
+        let mux = make(muxer_for(source))?;
+        let filesink = make("filesink")?;
+        filesink.set_property("location", output.to_str().unwrap_or_default());
+
+        let pipeline = gst::Pipeline::new();
+        pipeline.add_many([&filesrc, &demux, &mux, &filesink])
+            .map_err(|e| SfuError::Internal(format!("Failed to add elements: {}", e)))?;
+        filesrc.link(&demux)
+            .map_err(|e| SfuError::Internal(format!("Failed to link source to demuxer: {}", e)))?;
+        mux.link(&filesink)
+            .map_err(|e| SfuError::Internal(format!("Failed to link mux to sink: {}", e)))?;
 
-        // Wait for EOS on bus
-        let bus = self.pipeline.bus().unwrap();
-        for msg in bus.iter_timed(gst::ClockTime::from_seconds(5)) {
-            if let gst::MessageView::Eos(_) = msg.view() {
-                break;
+        // The demuxer exposes one pad per track it finds, named like the muxer's templates
+        let mux = mux.downgrade();
+        demux.connect_pad_added(move |_, pad| {
+            let Some(mux) = mux.upgrade() else { return };
+            let name = pad.name();
+            let template = if name.starts_with("video") {
+                "video_%u"
+            } else if name.starts_with("audio") {
+                "audio_%u"
+            } else {
+                return;
+            };
+            if let Some(sink) = mux.request_pad_simple(template) {
+                if let Err(e) = pad.link(&sink) {
+                    tracing::warn!(pad = %name, error = ?e, "Failed to link track while repairing recording");
+                }
+            }
+        });
+
+        pipeline.set_state(gst::State::Playing)
+            .map_err(|e| SfuError::Internal(format!("Failed to start repair pipeline: {}", e)))?;
+
+        let bus = pipeline.bus().unwrap();
+        let mut result = Err(SfuError::RecordingFailed(format!("Repair of {} timed out", source.display())));
+        for msg in bus.iter_timed(gst::ClockTime::from_seconds(REPAIR_TIMEOUT_SECS)) {
+            match msg.view() {
+                gst::MessageView::Eos(_) => {
+                    result = Ok(());
+                    break;
+                }
+                gst::MessageView::Error(err) => {
+                    result = Err(SfuError::RecordingFailed(format!(
+                        "Failed to repair {}: {}",
+                        source.display(),
+                        err.error()
+                    )));
+                    break;
+                }
+                _ => {}
             }
         }
 
-        self.pipeline.set_state(gst::State::Null)
-            .map_err(|e| SfuError::Internal(format!("Failed to stop pipeline: {}", e)))?;
+        let _ = pipeline.set_state(gst::State::Null);
+        result
+    }
 
-        *state = RecordingState::Stopped;
-        tracing::info!("Recording stopped: {:?}", self.output_path);
-        Ok(self.output_path.clone())
+    pub fn push_video_rtp(&self, data: Bytes) -> Result<(), SfuError> {
+        self.push_rtp(MediaKind::Video, data)
+    }
+
+    pub fn push_audio_rtp(&self, data: Bytes) -> Result<(), SfuError> {
+        self.push_rtp(MediaKind::Audio, data)
     }
 
-    pub fn push_video_rtp(&self, data: &[u8]) -> Result<(), SfuError> {
-        if let Some(ref appsrc) = self.video_appsrc {
-            let buffer = gst::Buffer::from_slice(data.to_vec());
-            appsrc.push_buffer(buffer)
-                .map_err(|e| SfuError::Internal(format!("Failed to push video: {}", e)))?;
+    fn push_rtp(&self, kind: MediaKind, data: Bytes) -> Result<(), SfuError> {
+        // Nothing from a break makes it into the file
+        if self.is_paused() {
+            return Ok(());
         }
+        match &self.sink {
+            Sink::Gstreamer { .. } => {
+                // A pipeline that failed takes nothing more; the manager tears it down
+                if self.failure().is_some() {
+                    return Ok(());
+                }
+                // A track the muxer went on without is dropped; the manager restarts the recording for it
+                if !self.ensure_branch(kind)? {
+                    return Ok(());
+                }
+                let header = RtpHeader::parse(&data)
+                    .ok_or_else(|| SfuError::Internal(format!("Cannot record {}: not an RTP packet", kind.as_str())))?;
+                let arrival = self.offset();
+                let Some((appsrc, pts)) = self.stamp(kind, &header, arrival) else { return Ok(()) };
+                appsrc.push_buffer(rtp_buffer(data, pts))
+                    .map_err(|e| SfuError::Internal(format!("Failed to push {}: {}", kind.as_str(), e)))?;
+                self.end_missing_tracks(false);
+            }
+            Sink::RtpDump(dump) => {
+                // Packets racing a stop find the writer gone and are dropped
+                let offset = self.offset();
+                let mut dump = dump.lock().unwrap();
+                let Some(writer) = dump.writer.as_mut() else { return Ok(()) };
+                writer.write_rtp(dump_track(kind), offset, &data)
+                    .map_err(|e| SfuError::RecordingFailed(format!("Failed to dump {}: {}", kind.as_str(), e)))?;
+            }
+        }
+        self.note_media(kind);
         Ok(())
     }
 
-    pub fn push_audio_rtp(&self, data: &[u8]) -> Result<(), SfuError> {
-        if let Some(ref appsrc) = self.audio_appsrc {
-            let buffer = gst::Buffer::from_slice(data.to_vec());
-            appsrc.push_buffer(buffer)
-                .map_err(|e| SfuError::Internal(format!("Failed to push audio: {}", e)))?;
+    /// Switches a branch to the codec its track was actually negotiated with,
+    /// so packets are not rejected by the depayloader for a payload type mismatch.
+    /// A dump records the switch for the conversion, whatever the codec.
+    ///
+    /// A branch that has not been built yet is built for the new codec.
+    ///
+    /// Returns true when the codec needs another depayloader, e.g. H.264 on a
+    /// branch built for VP8. The branch is then left alone and only the codec
+    /// is kept, for the new pipeline the caller has to restart into.
+    pub fn set_track_codec(&self, kind: MediaKind, codec: &RtpCodec) -> Result<bool, SfuError> {
+        if let Sink::RtpDump(dump) = &self.sink {
+            let offset = self.offset();
+            let mut dump = dump.lock().unwrap();
+            let mut codecs = self.codecs.lock().unwrap();
+            if codecs.get(kind) == codec {
+                return Ok(false);
+            }
+            codecs.set(kind, codec.clone());
+            if let Some(writer) = dump.writer.as_mut() {
+                writer.write_codec(dump_track(kind), offset, &DumpCodec::from(codec))
+                    .map_err(|e| SfuError::RecordingFailed(format!("Failed to dump {} codec: {}", kind.as_str(), e)))?;
+            }
+            return Ok(false);
+        }
+
+        let caps = codec.caps(kind)?;
+        // Locked in the order `ensure_branch` locks them, so a branch being built gets one codec or the other
+        let branch = self.branch(kind).map(|branch| branch.lock().unwrap());
+        let mut codecs = self.codecs.lock().unwrap();
+        let rebuild = !codecs.get(kind).encoding_name.eq_ignore_ascii_case(&codec.encoding_name);
+        if !rebuild {
+            if let Some(Branch::Linked { appsrc, .. }) = branch.as_deref() {
+                if appsrc.caps().as_ref() != Some(&caps) {
+                    appsrc.set_caps(Some(&caps));
+                }
+            }
+        }
+        codecs.set(kind, codec.clone());
+        Ok(rebuild)
+    }
+
+    /// Codecs the tracks were last negotiated with
+    pub fn codecs(&self) -> RecordingCodecs {
+        self.codecs.lock().unwrap().clone()
+    }
+
+    /// File this recording continues after a restart
+    pub fn previous(&self) -> Option<&str> {
+        self.previous.as_deref()
+    }
+
+    /// Room of `previous`, when it is not this recording's room
+    pub fn previous_room(&self) -> Option<&str> {
+        self.previous_room.as_deref()
+    }
+
+    fn note_media(&self, kind: MediaKind) {
+        if let Some(gaps) = self.gaps.lock().unwrap().as_mut() {
+            gaps.on_media(kind, Instant::now());
+        }
+    }
+
+    /// Record that the publisher turned a track off or back on, so the
+    /// silence in between is not reported as a gap
+    pub fn set_track_muted(&self, kind: MediaKind, muted: bool) {
+        if let Some(gaps) = self.gaps.lock().unwrap().as_mut() {
+            gaps.set_muted(kind, muted, Instant::now());
+        }
+    }
+
+    /// Whether the publisher has `kind` turned off
+    pub fn is_track_muted(&self, kind: MediaKind) -> bool {
+        self.gaps.lock().unwrap().as_ref().is_some_and(|gaps| gaps.is_muted(kind))
+    }
+
+    /// Check for tracks that went silent, appending gaps that ended to the
+    /// sidecar. A paused recording has no gaps.
+    pub fn poll_gaps(&self, now: Instant) -> Vec<GapEvent> {
+        if self.is_paused() {
+            return Vec::new();
+        }
+        let events = match self.gaps.lock().unwrap().as_mut() {
+            Some(gaps) => gaps.poll(now),
+            None => return Vec::new(),
+        };
+        self.append_gaps(&events);
+        events
+    }
+
+    /// Total seconds of gaps closed so far
+    pub fn gap_secs(&self) -> f64 {
+        self.gaps.lock().unwrap().as_ref().map(GapTracker::total_gap_secs).unwrap_or(0.0)
+    }
+
+    /// Gaps closed so far
+    pub fn closed_gaps(&self) -> Vec<MediaGap> {
+        self.gaps.lock().unwrap().as_ref().map(|gaps| gaps.gaps().to_vec()).unwrap_or_default()
+    }
+
+    /// Gap sidecar written next to the recording: `{peer_id}_{timestamp}.gaps.jsonl`
+    pub fn gaps_path(&self) -> PathBuf {
+        self.output_path.with_extension("gaps.jsonl")
+    }
+
+    fn append_gaps(&self, events: &[GapEvent]) {
+        let mut lines = String::new();
+        for event in events {
+            if let GapEvent::Closed(gap) = event {
+                if let Ok(line) = serde_json::to_string(gap) {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+            }
+        }
+        if lines.is_empty() {
+            return;
+        }
+
+        let result = permissions::private_file()
+            .create(true)
+            .append(true)
+            .open(self.gaps_path())
+            .and_then(|mut file| file.write_all(lines.as_bytes()));
+        if let Err(e) = result {
+            tracing::warn!(path = %self.gaps_path().display(), error = %e, "Failed to write recording gap sidecar");
+        }
+    }
+
+    pub fn record_keyframe(&self) {
+        if let Ok(mut stats) = self.keyframe_stats.lock() {
+            stats.record_keyframe(std::time::Instant::now());
         }
-        Ok(())
     }
 
+    pub fn record_pli_request(&self) {
+        if let Ok(mut stats) = self.keyframe_stats.lock() {
+            stats.record_pli_request();
+        }
+    }
+
+    pub fn keyframe_stats(&self) -> KeyframeStats {
+        self.keyframe_stats.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// The state, which is `Error` as soon as the pipeline posts one, before it is torn down
     pub async fn get_state(&self) -> RecordingState {
-        self.state.lock().await.clone()
+        let state = self.state.lock().await.clone();
+        match (state, self.failure()) {
+            (RecordingState::Recording | RecordingState::Paused, Some(error)) => RecordingState::Error(error.to_string()),
+            (state, _) => state,
+        }
     }
 
     pub fn output_path(&self) -> &PathBuf {
         &self.output_path
     }
+
+    /// Unix time in milliseconds when the pipeline started, if it has
+    pub fn started_at_ms(&self) -> Option<u64> {
+        self.started.get().map(SessionClock::started_at_ms)
+    }
+
+    /// Time recorded so far, or between start and stop once stopped, not
+    /// counting time spent paused
+    pub fn elapsed(&self) -> Duration {
+        self.running_at(self.stopped.get().copied().unwrap_or_else(Instant::now))
+    }
+
+    /// Tears the pipeline down without EOS, as if the process died mid-recording
+    #[cfg(test)]
+    pub(crate) fn kill(&self) {
+        match &self.sink {
+            Sink::Gstreamer { pipeline, .. } => {
+                let _ = pipeline.set_state(gst::State::Null);
+            }
+            Sink::RtpDump(dump) => {
+                // Closed without finalizing, so the dump stays `.part`
+                dump.lock().unwrap().writer.take();
+            }
+        }
+    }
+
+    /// File GStreamer writes to until the recording is finalized
+    pub fn part_path(&self) -> &PathBuf {
+        &self.part_path
+    }
+
+    /// Current size of the output file, sampled from the filesystem
+    pub fn bytes_written(&self) -> u64 {
+        std::fs::metadata(&self.part_path)
+            .or_else(|_| std::fs::metadata(&self.output_path))
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
+    pub fn content(&self) -> RecordingContent {
+        RecordingContent::from_tracks(self.has_track(MediaKind::Video), self.has_track(MediaKind::Audio))
+            .unwrap_or(RecordingContent::AudioVideo)
+    }
+
+    pub async fn detail(&self, peer_id: &str) -> RecordingDetail {
+        let state = self.get_state().await;
+        RecordingDetail {
+            peer_id: peer_id.to_string(),
+            paused: state == RecordingState::Paused,
+            state,
+            started_at: self.started_at_ms(),
+            started_at_local: None,
+            elapsed_secs: self.elapsed().as_secs(),
+            bytes_written: self.bytes_written(),
+            segments: 1,
+            content: self.content(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sfu-pipeline-{}-{}", name, std::process::id()))
+    }
+
+    fn appsrc_caps(appsrc: Option<gst_app::AppSrc>) -> String {
+        appsrc.and_then(|src| src.caps()).map(|caps| caps.to_string()).unwrap_or_default()
+    }
+
+    fn element_factories(pipeline: &RecordingPipeline) -> Vec<String> {
+        let Sink::Gstreamer { pipeline, .. } = &pipeline.sink else { return Vec::new() };
+        pipeline
+            .iterate_elements()
+            .into_iter()
+            .filter_map(|element| element.ok()?.factory().map(|factory| factory.name().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_pipeline_uses_negotiated_payload_types() {
+        // Needs the GStreamer plugins the recorder is built from
+        if RecordingPipeline::verify_environment().is_err() {
+            return;
+        }
+
+        let dir = output_dir("payload-types");
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/VP8", 120, 90000),
+            audio: RtpCodec::from_mime_type("audio/opus", 111, 48000),
+        };
+        let pipeline = RecordingPipeline::new("room", "peer", dir.to_str().unwrap(), &codecs).unwrap();
+
+        // The audio branch isn't built until its first packet, and then for the codec negotiated by then
+        assert!(!pipeline.set_track_codec(MediaKind::Audio, &RtpCodec::from_mime_type("audio/opus", 109, 48000)).unwrap());
+        assert!(pipeline.appsrc(MediaKind::Audio).is_none());
+        assert!(pipeline.ensure_branch(MediaKind::Video).unwrap() && pipeline.ensure_branch(MediaKind::Audio).unwrap());
+        assert_eq!(
+            appsrc_caps(pipeline.appsrc(MediaKind::Video)),
+            "application/x-rtp, media=(string)video, encoding-name=(string)VP8, clock-rate=(int)90000, payload=(int)120"
+        );
+        assert_eq!(
+            appsrc_caps(pipeline.appsrc(MediaKind::Audio)),
+            "application/x-rtp, media=(string)audio, encoding-name=(string)OPUS, clock-rate=(int)48000, payload=(int)109"
+        );
+
+        // A track that turns up with yet another payload type moves its branch over
+        assert!(!pipeline.set_track_codec(MediaKind::Video, &RtpCodec::from_mime_type("video/VP8", 97, 90000)).unwrap());
+        assert!(appsrc_caps(pipeline.appsrc(MediaKind::Video)).ends_with("payload=(int)97"));
+
+        let h265 = RtpCodec::from_mime_type("video/H265", 100, 90000);
+        assert!(matches!(pipeline.set_track_codec(MediaKind::Video, &h265), Err(SfuError::RecordingFailed(_))));
+        assert!(appsrc_caps(pipeline.appsrc(MediaKind::Video)).ends_with("payload=(int)97"));
+
+        // H.264 needs another branch, which only a new pipeline has
+        let h264 = RtpCodec::from_mime_type("video/H264", 102, 90000);
+        assert!(pipeline.set_track_codec(MediaKind::Video, &h264).unwrap());
+        assert!(appsrc_caps(pipeline.appsrc(MediaKind::Video)).ends_with("payload=(int)97"));
+        assert_eq!(pipeline.codecs().video, h264);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_transcoding_pipeline_reencodes_vp8_and_opus() {
+        if RecordingPipeline::verify_environment().is_err() || RecordingPipeline::verify_transcoding().is_err() {
+            return;
+        }
+
+        let dir = output_dir("transcoding");
+        let pipeline = RecordingPipeline::transcoding("room", "peer", dir.to_str().unwrap(), &RecordingCodecs::default()).unwrap();
+        assert!(pipeline.ensure_branch(MediaKind::Video).unwrap() && pipeline.ensure_branch(MediaKind::Audio).unwrap());
+        let factories = element_factories(&pipeline);
+        for element in ["vp8dec", "vp8enc", "opusdec", "opusenc"] {
+            assert!(factories.iter().any(|name| name == element), "{:?}", factories);
+        }
+        assert_eq!(pipeline.output_path().extension().unwrap(), "webm");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_passthrough_pipelines_depayload_the_track_codec() {
+        if RecordingPipeline::verify_environment().is_err() {
+            return;
+        }
+
+        let cases = [
+            (RtpCodec::from_mime_type("video/VP8", 96, 90000), "rtpvp8depay", "webm"),
+            (RtpCodec::from_mime_type("video/H264", 102, 90000), "rtph264depay", "mkv"),
+            (RtpCodec::from_mime_type("video/VP9", 98, 90000), "rtpvp9depay", "webm"),
+            (RtpCodec::from_mime_type("video/AV1", 41, 90000), "rtpav1depay", "webm"),
+        ];
+        for (video, depayloader, extension) in cases {
+            let dir = output_dir(&format!("passthrough-{}", extension));
+            let codecs = RecordingCodecs {
+                video: video.clone(),
+                ..RecordingCodecs::default()
+            };
+            let needed = passthrough_elements(&video).unwrap().iter().copied().chain([muxer_for_extension(extension)]);
+            if !missing_elements(needed).is_empty() {
+                // Without the plugins the pipeline is refused by name
+                let err = RecordingPipeline::new("room", "peer", dir.to_str().unwrap(), &codecs).err().unwrap();
+                assert!(err.to_string().contains(&video.encoding_name), "{}", err);
+                continue;
+            }
+
+            let pipeline = RecordingPipeline::new("room", "peer", dir.to_str().unwrap(), &codecs).unwrap();
+            assert_eq!(pipeline.output_path().extension().unwrap(), extension);
+            assert!(pipeline.ensure_branch(MediaKind::Video).unwrap());
+            assert!(appsrc_caps(pipeline.appsrc(MediaKind::Video)).contains(&format!("encoding-name=(string){}", video.encoding_name)));
+            let factories = element_factories(&pipeline);
+            assert!(factories.iter().any(|name| name == depayloader), "{:?}", factories);
+            for encoder in ["vp8enc", "opusenc"] {
+                assert!(!factories.iter().any(|name| name == encoder), "{:?}", factories);
+            }
+
+            std::fs::remove_dir_all(&dir).ok();
+        }
+
+        assert_eq!(muxer_for(Path::new("peer_1.mkv.part")), "matroskamux");
+        assert_eq!(muxer_for(Path::new("peer_1.webm.part")), "webmmux");
+    }
+
+    #[test]
+    fn test_rtp_buffer_shares_the_packet_memory() {
+        gst::init().unwrap();
+
+        let data = Bytes::from(vec![0x80, 0x60, 0x00, 0x07, 0xaa, 0xbb]);
+        let ptr = data.as_ptr();
+        let buffer = rtp_buffer(data, Duration::from_millis(20));
+
+        assert_eq!(buffer.pts(), Some(gst::ClockTime::from_mseconds(20)));
+        let map = buffer.map_readable().unwrap();
+        assert_eq!(map.as_slice(), &[0x80, 0x60, 0x00, 0x07, 0xaa, 0xbb]);
+        // The appsrc reads the marshalled packet in place
+        assert_eq!(map.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_unsupported_codec_refused_before_any_file_is_created() {
+        gst::init().unwrap();
+
+        let dir = output_dir("unsupported");
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 100, 90000),
+            ..RecordingCodecs::default()
+        };
+
+        let err = RecordingPipeline::new("room", "peer", dir.to_str().unwrap(), &codecs).err().unwrap();
+        assert!(matches!(err, SfuError::RecordingFailed(ref msg) if msg.contains("H265")), "{}", err);
+        assert!(!dir.exists());
+    }
 }
@@ -1,53 +1,445 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex, Notify, RwLock};
+use webrtc::rtp::packet::Packet;
+use webrtc::util::Marshal;
 
+use crate::chaos::{self, ChaosTarget};
+use crate::config::RecordingConfig;
 use crate::error::SfuError;
-use crate::ipfs::IpfsClient;
+use crate::ipfs::IpfsUploadResult;
+use crate::metrics;
+use crate::sfu::PeerRole;
+use super::chapters::{self, chapters_path, RecordingWindow};
+use super::disk::{DiskSpace, FsDiskSpace};
+use super::downloads::hash_workers;
+use super::finalize::{self, write_atomic};
+use super::integrity;
+use super::keyframes::KeyframeStats;
+use super::manifest::{file_sha256, RoomManifest, RoomSession, MANIFEST_FILE};
+use super::metadata::SessionMetadata;
+use super::permissions;
 use super::pipeline::RecordingPipeline;
+use super::sidecar::{self, RecordingSidecar};
 use super::state::RecordingState;
+use super::tenant;
+use super::status::{CompletedRecording, RecordingDetail};
+use super::store::RecordingStore;
+use super::clock::SessionClock;
+use super::codec::{RecordingCodecs, RtpCodec};
+use super::gaps::{GapEvent, MediaGap, MediaKind};
+use super::transcript::TranscriptService;
+use super::view_events::{read_view_events, ViewEventKind, ViewEventLog, ViewEventsResult, VIEW_EVENTS_FILE};
 
 /// Key for identifying a recording: (room_id, peer_id)
 pub type RecordingKey = (String, String);
 
-/// Result of stopping a recording, including optional IPFS upload info
+/// Result of stopping a recording. The IPFS upload happens afterwards in the
+/// background, so `cid` is `None` here and arrives with the `RecordingUpload`.
 #[derive(Debug, Clone)]
 pub struct RecordingResult {
     pub file_path: PathBuf,
+    /// Size of the finalized file
+    pub file_size_bytes: u64,
+    /// Time between the pipeline starting and being stopped
+    pub duration_secs: u64,
+    /// SHA-256 of the finalized file, hex encoded; `None` when it could not be read
+    pub sha256: Option<String>,
     pub cid: Option<String>,
     pub ipfs_gateway_url: Option<String>,
+    /// The recording was queued for upload; a `RecordingUpload` follows
+    pub upload_pending: bool,
+    /// Observed keyframe cadence while the recording was active
+    pub keyframe_stats: KeyframeStats,
+}
+
+/// Result of restarting a recording: the file that was finalized and the one
+/// the recording continues in
+#[derive(Debug, Clone)]
+pub struct RecordingRestart {
+    pub stopped: RecordingResult,
+    pub file_path: PathBuf,
+}
+
+/// A recording whose pipeline posted an error and was torn down. What it
+/// wrote is left at `file_path`, to be repaired on the next startup.
+#[derive(Debug, Clone)]
+pub struct RecordingFailure {
+    pub room_id: String,
+    pub peer_id: String,
+    pub file_path: PathBuf,
+    pub error: String,
+}
+
+/// Who a recording is of, for its sidecar
+#[derive(Debug, Clone)]
+struct RecordedPeer {
+    name: Option<String>,
+    role: PeerRole,
+}
+
+/// An error a pipeline posted, on its way to `next_failure`
+struct PipelineError {
+    key: RecordingKey,
+    /// Tells the pipeline apart from one a restart put in its place
+    output_path: PathBuf,
+    error: String,
+}
+
+/// A limit a running recording went past, for which the watchdog stops it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingLimit {
+    /// Ran for longer than `RECORDING_MAX_DURATION_SECS`
+    MaxDuration(Duration),
+    /// Free space in the output directory fell below `RECORDING_MIN_FREE_BYTES`
+    LowDiskSpace { available: u64, min_free: u64 },
+}
+
+impl std::fmt::Display for RecordingLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MaxDuration(limit) => write!(f, "Recording reached the maximum duration of {}s", limit.as_secs()),
+            Self::LowDiskSpace { available, min_free } => write!(
+                f,
+                "Only {} bytes free for recordings, below the minimum of {}",
+                available, min_free
+            ),
+        }
+    }
+}
+
+/// `stop_reason` of a recording finalized by a restart
+const STOP_REASON_RESTARTED: &str = "restarted";
+
+/// `stop_reason` of a recording finalized because its peer moved to another room
+const STOP_REASON_TRANSFERRED: &str = "transferred";
+
+/// Default number of times a failed recording upload is retried
+pub const DEFAULT_IPFS_UPLOAD_RETRIES: u32 = 3;
+
+/// Wait before the first retry of a failed upload, doubled for each one after
+const DEFAULT_UPLOAD_RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+/// A finalized recording waiting for the upload worker
+struct UploadJob {
+    room_id: String,
+    peer_id: String,
+    tenant: Option<String>,
+    file_path: PathBuf,
+    duration_secs: u64,
+    /// Keeps the recording counted as pending until the upload settles
+    _in_flight: InFlightGuard,
+}
+
+/// How the background upload of a stopped recording ended. The recording
+/// counts as pending for the room's manifest until this is dropped.
+pub struct RecordingUpload {
+    pub room_id: String,
+    pub peer_id: String,
+    pub file_path: PathBuf,
+    pub duration_secs: u64,
+    /// Attempts made, retries included
+    pub attempts: u32,
+    /// The uploaded file, or the last error once retries ran out
+    pub result: Result<IpfsUploadResult, String>,
+    _in_flight: InFlightGuard,
+}
+
+/// Serialize an RTP packet into a single buffer that the pipeline takes ownership of
+fn push_to(pipeline: &RecordingPipeline, kind: MediaKind, data: bytes::Bytes) -> Result<(), SfuError> {
+    match kind {
+        MediaKind::Video => pipeline.push_video_rtp(data),
+        MediaKind::Audio => pipeline.push_audio_rtp(data),
+    }
+}
+
+fn marshal_packet(packet: &Packet) -> Result<bytes::Bytes, SfuError> {
+    packet
+        .marshal()
+        .map_err(|e| SfuError::Internal(format!("Failed to marshal RTP packet: {}", e)))
+}
+
+/// Writes `{peer_id}_{timestamp}.chapters.vtt` next to a finalized recording
+/// from the room's view events. Returns its file name, or `None` when nothing
+/// happened during the recording or the chapters could not be written.
+fn write_chapters(
+    recording: &std::path::Path,
+    view_events: &std::path::Path,
+    peer_id: &str,
+    window: RecordingWindow,
+) -> Option<String> {
+    let events = match read_view_events(view_events) {
+        Ok(events) => events,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(path = %view_events.display(), error = %e, "Failed to read view events for chapters");
+            }
+            return None;
+        }
+    };
+    let chapters = chapters::chapters(&events, peer_id, window);
+    if chapters.is_empty() {
+        return None;
+    }
+
+    let path = chapters_path(recording);
+    if let Err(e) = write_atomic(&path, chapters::to_webvtt(&chapters).as_bytes()) {
+        tracing::warn!(path = %path.display(), error = %e, "Failed to write recording chapters");
+        return None;
+    }
+    path.file_name().map(|name| name.to_string_lossy().to_string())
+}
+
+/// Removes a recording's local file after its upload. An unpinned upload can
+/// be garbage collected by the node, so its file is kept.
+fn delete_uploaded(room_id: &str, peer_id: &str, file_path: &std::path::Path, uploaded: &IpfsUploadResult) {
+    if !uploaded.pinned {
+        tracing::warn!(
+            room_id = %room_id,
+            peer_id = %peer_id,
+            cid = %uploaded.cid,
+            "Keeping local recording, its upload is not pinned"
+        );
+        return;
+    }
+    match std::fs::remove_file(file_path) {
+        Ok(()) => tracing::info!(
+            room_id = %room_id,
+            peer_id = %peer_id,
+            file = %file_path.display(),
+            freed_bytes = uploaded.size,
+            "Deleted local recording after upload"
+        ),
+        Err(e) => tracing::warn!(file = %file_path.display(), error = %e, "Failed to delete uploaded recording"),
+    }
+}
+
+/// Default interval between SFU-initiated keyframe requests for recorded publishers
+pub const DEFAULT_KEYFRAME_INTERVAL_SECS: u64 = 10;
+
+/// Recordings per room that have left the active set but are still being
+/// finalized and uploaded
+#[derive(Default)]
+struct InFlightUploads {
+    rooms: std::sync::Mutex<HashMap<String, usize>>,
+    done: Notify,
+}
+
+impl InFlightUploads {
+    fn start(self: &Arc<Self>, room_id: &str) -> InFlightGuard {
+        *self.rooms.lock().unwrap().entry(room_id.to_string()).or_default() += 1;
+        InFlightGuard {
+            uploads: self.clone(),
+            room_id: room_id.to_string(),
+        }
+    }
+
+    fn count(&self, room_id: &str) -> usize {
+        self.rooms.lock().unwrap().get(room_id).copied().unwrap_or(0)
+    }
+
+    fn total(&self) -> usize {
+        self.rooms.lock().unwrap().values().sum()
+    }
+}
+
+struct InFlightGuard {
+    uploads: Arc<InFlightUploads>,
+    room_id: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut rooms = self.uploads.rooms.lock().unwrap();
+        if let Some(count) = rooms.get_mut(&self.room_id) {
+            *count -= 1;
+            if *count == 0 {
+                rooms.remove(&self.room_id);
+            }
+        }
+        drop(rooms);
+        self.uploads.done.notify_waiters();
+    }
 }
 
 pub struct RecordingManager {
     recordings: Arc<RwLock<HashMap<RecordingKey, Arc<RecordingPipeline>>>>,
     output_dir: String,
-    ipfs_client: Option<Arc<IpfsClient>>,
+    /// Where stopped recordings, view events and manifests are uploaded (None = kept locally)
+    store: Option<Arc<dyn RecordingStore>>,
     enabled: bool,
+    /// Interval for automatic PLI requests to recorded publishers (zero disables)
+    keyframe_interval: Duration,
+    /// Silence on a recorded track before it becomes a gap incident (zero disables)
+    gap_threshold: Duration,
+    /// Record raw RTP dumps when the GStreamer pipeline cannot be built or started
+    rtp_fallback: bool,
+    /// Re-encode VP8 and Opus instead of writing them as sent
+    transcode: bool,
+    /// Free space the output directory must keep (0 disables the guard)
+    min_free_bytes: u64,
+    /// Recordings running longer are stopped by the watchdog
+    max_duration: Option<Duration>,
+    disk: Arc<dyn DiskSpace>,
+    /// Peers whose media is end-to-end encrypted, which is forwarded but never recorded
+    e2ee_peers: Arc<RwLock<HashSet<RecordingKey>>>,
+    /// room_id -> storage namespace, for rooms created for a tenant. Kept
+    /// until the room's manifest is written, so late files land beside the rest.
+    room_tenants: std::sync::RwLock<HashMap<String, String>>,
+    /// Name and role of each peer, written into its recordings' sidecars
+    peers: std::sync::RwLock<HashMap<RecordingKey, RecordedPeer>>,
+    /// Per-room proctor view event streams, keyed by room_id
+    view_logs: Arc<RwLock<HashMap<String, ViewEventLog>>>,
+    /// ASR webhook for transcribing uploaded recordings (None = disabled)
+    transcripts: Option<Arc<TranscriptService>>,
+    /// Recordings already stopped in each room, newest last
+    completed: Arc<RwLock<HashMap<String, Vec<CompletedRecording>>>>,
+    in_flight: Arc<InFlightUploads>,
+    /// Proctor-set session metadata per room, copied into sidecars and transcript jobs
+    session_metadata: Arc<RwLock<HashMap<String, SessionMetadata>>>,
+    /// Finalized recordings waiting for IPFS, drained by `next_upload`
+    upload_queue: mpsc::UnboundedSender<UploadJob>,
+    upload_jobs: Mutex<mpsc::UnboundedReceiver<UploadJob>>,
+    upload_retries: u32,
+    upload_retry_backoff: Duration,
+    /// Remove local files once their upload is pinned
+    delete_after_upload: bool,
+    /// Errors posted by recording pipelines, drained by `next_failure`
+    pipeline_errors: mpsc::UnboundedSender<PipelineError>,
+    failed_pipelines: Mutex<mpsc::UnboundedReceiver<PipelineError>>,
 }
 
 impl RecordingManager {
-    pub fn new(output_dir: &str, ipfs_client: Option<Arc<IpfsClient>>, enabled: bool) -> Self {
+    pub fn new(config: &RecordingConfig, store: Option<Arc<dyn RecordingStore>>) -> Self {
+        let output_dir = config.output_dir.as_str();
         // Create output directory if it doesn't exist (only if enabled)
-        if enabled {
-            std::fs::create_dir_all(output_dir).ok();
+        if config.enabled {
+            if let Err(e) = permissions::create_room_dir(std::path::Path::new(output_dir)) {
+                tracing::warn!(output_dir = %output_dir, error = %e, "Recording output directory is not usable");
+            }
         }
+        let (upload_queue, upload_jobs) = mpsc::unbounded_channel();
+        let (pipeline_errors, failed_pipelines) = mpsc::unbounded_channel();
 
         Self {
             recordings: Arc::new(RwLock::new(HashMap::new())),
             output_dir: output_dir.to_string(),
-            ipfs_client,
-            enabled,
+            store,
+            enabled: config.enabled,
+            keyframe_interval: config.keyframe_interval,
+            gap_threshold: config.gap_threshold,
+            rtp_fallback: config.rtp_fallback,
+            transcode: config.transcode,
+            min_free_bytes: config.min_free_bytes,
+            max_duration: config.max_duration,
+            disk: Arc::new(FsDiskSpace),
+            e2ee_peers: Arc::new(RwLock::new(HashSet::new())),
+            room_tenants: std::sync::RwLock::new(HashMap::new()),
+            peers: std::sync::RwLock::new(HashMap::new()),
+            view_logs: Arc::new(RwLock::new(HashMap::new())),
+            transcripts: None,
+            completed: Arc::new(RwLock::new(HashMap::new())),
+            in_flight: Arc::new(InFlightUploads::default()),
+            session_metadata: Arc::new(RwLock::new(HashMap::new())),
+            upload_queue,
+            upload_jobs: Mutex::new(upload_jobs),
+            upload_retries: config.upload_retries,
+            upload_retry_backoff: DEFAULT_UPLOAD_RETRY_BACKOFF,
+            delete_after_upload: config.delete_after_upload,
+            pipeline_errors,
+            failed_pipelines: Mutex::new(failed_pipelines),
         }
     }
 
+    /// Set how often a failed upload is retried before it is reported as failed
+    pub fn with_upload_retries(mut self, retries: u32) -> Self {
+        self.upload_retries = retries;
+        self
+    }
+
+    /// Submit uploaded recordings to an ASR service for transcription
+    pub fn with_transcripts(mut self, transcripts: Option<Arc<TranscriptService>>) -> Self {
+        self.transcripts = transcripts;
+        self
+    }
+
+    /// Set the automatic keyframe request interval (zero disables)
+    pub fn with_keyframe_interval(mut self, interval: Duration) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+
     /// Check if recording is enabled
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
 
-    /// Start recording for a specific peer in a room
-    pub async fn start_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
+    /// Whether stopped recordings are uploaded to IPFS
+    pub fn uploads_to_ipfs(&self) -> bool {
+        self.store.is_some()
+    }
+
+    pub fn store(&self) -> Option<&Arc<dyn RecordingStore>> {
+        self.store.as_ref()
+    }
+
+    /// Recordings currently in progress, across all rooms
+    pub async fn active_count(&self) -> usize {
+        self.recordings.read().await.len()
+    }
+
+    /// Final paths of the recordings in progress, whose files retention keeps
+    pub async fn active_output_paths(&self) -> Vec<PathBuf> {
+        self.recordings.read().await.values().map(|pipeline| pipeline.output_path().clone()).collect()
+    }
+
+    /// Interval between automatic keyframe requests for recorded publishers
+    pub fn keyframe_interval(&self) -> Duration {
+        self.keyframe_interval
+    }
+
+    /// Set how long a recorded track may go without media before it is a gap (zero disables)
+    pub fn with_gap_threshold(mut self, threshold: Duration) -> Self {
+        self.gap_threshold = threshold;
+        self
+    }
+
+    /// Record raw RTP dumps instead of nothing when the pipeline fails
+    pub fn with_rtp_fallback(mut self, enabled: bool) -> Self {
+        self.rtp_fallback = enabled;
+        self
+    }
+
+    /// Measure free space through `disk` instead of the filesystem
+    #[cfg(test)]
+    pub fn with_disk_space(mut self, disk: Arc<dyn DiskSpace>) -> Self {
+        self.disk = disk;
+        self
+    }
+
+    /// Free space left for recordings, `None` when the guard is off or the
+    /// space cannot be read
+    fn low_disk_space(&self) -> Option<RecordingLimit> {
+        if self.min_free_bytes == 0 {
+            return None;
+        }
+        match self.disk.available_bytes(std::path::Path::new(&self.output_dir)) {
+            Ok(available) if available < self.min_free_bytes => Some(RecordingLimit::LowDiskSpace {
+                available,
+                min_free: self.min_free_bytes,
+            }),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!(output_dir = %self.output_dir, error = %e, "Cannot read free space for recordings");
+                None
+            }
+        }
+    }
+
+    /// Start recording for a specific peer in a room, expecting its tracks in `codecs`
+    pub async fn start_recording(&self, room_id: &str, peer_id: &str, codecs: &RecordingCodecs) -> Result<(), SfuError> {
         // Skip if recording is disabled
         if !self.enabled {
             tracing::debug!(
@@ -58,8 +450,15 @@ impl RecordingManager {
             return Ok(());
         }
 
-        let mut recordings = self.recordings.write().await;
         let key = (room_id.to_string(), peer_id.to_string());
+        if self.e2ee_peers.read().await.contains(&key) {
+            return Err(SfuError::RecordingFailed(format!(
+                "Media of peer {} is end-to-end encrypted and cannot be recorded",
+                peer_id
+            )));
+        }
+
+        let mut recordings = self.recordings.write().await;
 
         if recordings.contains_key(&key) {
             return Err(SfuError::Internal(format!(
@@ -68,10 +467,14 @@ impl RecordingManager {
             )));
         }
 
-        let pipeline = RecordingPipeline::new(room_id, peer_id, &self.output_dir)?;
-        pipeline.start().await?;
+        chaos::check(ChaosTarget::Recording, Some(room_id)).await?;
+        if let Some(limit) = self.low_disk_space() {
+            return Err(SfuError::RecordingFailed(format!("{}; not starting the recording", limit)));
+        }
 
+        let pipeline = self.open_recording(room_id, peer_id, codecs).await?;
         recordings.insert(key, Arc::new(pipeline));
+        metrics::metrics().active_recordings.set(recordings.len() as u64);
         tracing::info!(
             room_id = %room_id,
             peer_id = %peer_id,
@@ -80,6 +483,90 @@ impl RecordingManager {
         Ok(())
     }
 
+    /// Starts the GStreamer pipeline, or an RTP dump when it fails and the fallback is on
+    async fn open_recording(&self, room_id: &str, peer_id: &str, codecs: &RecordingCodecs) -> Result<RecordingPipeline, SfuError> {
+        match self.start_pipeline(room_id, peer_id, codecs).await {
+            Ok(pipeline) => Ok(pipeline),
+            Err(e) if self.rtp_fallback => {
+                tracing::warn!(
+                    room_id = %room_id,
+                    peer_id = %peer_id,
+                    error = %e,
+                    "Recording pipeline unavailable, recording raw RTP instead"
+                );
+                let dump = RecordingPipeline::rtp_dump(room_id, peer_id, &self.namespace_dir(room_id), codecs)?
+                    .with_gap_threshold(self.gap_threshold);
+                dump.start().await?;
+                metrics::metrics().recording_rtp_fallbacks_total.inc();
+                Ok(dump)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn start_pipeline(&self, room_id: &str, peer_id: &str, codecs: &RecordingCodecs) -> Result<RecordingPipeline, SfuError> {
+        let output_dir = self.namespace_dir(room_id);
+        let pipeline = if self.transcode {
+            RecordingPipeline::transcoding(room_id, peer_id, &output_dir, codecs)?
+        } else {
+            RecordingPipeline::new(room_id, peer_id, &output_dir, codecs)?
+        }
+        .with_gap_threshold(self.gap_threshold);
+        self.watch_errors(room_id, peer_id, &pipeline);
+        if let Err(e) = pipeline.start().await {
+            // Nothing was recorded, so don't leave it to be repaired as an orphan
+            let _ = std::fs::remove_file(pipeline.part_path());
+            return Err(e);
+        }
+        Ok(pipeline)
+    }
+
+    /// Queues the first error `pipeline` posts for `next_failure`
+    fn watch_errors(&self, room_id: &str, peer_id: &str, pipeline: &RecordingPipeline) {
+        let errors = self.pipeline_errors.clone();
+        let key = (room_id.to_string(), peer_id.to_string());
+        let output_path = pipeline.output_path().clone();
+        pipeline.watch_errors(move |error| {
+            let _ = errors.send(PipelineError {
+                key: key.clone(),
+                output_path: output_path.clone(),
+                error,
+            });
+        });
+    }
+
+    /// Waits for the next recording whose pipeline failed, such as on a full
+    /// disk, tears it down and takes it out of the active recordings. Errors
+    /// of pipelines already stopped or restarted are skipped.
+    pub async fn next_failure(&self) -> Option<RecordingFailure> {
+        loop {
+            let PipelineError { key, output_path, error } = self.failed_pipelines.lock().await.recv().await?;
+            let mut recordings = self.recordings.write().await;
+            let pipeline = match recordings.get(&key) {
+                Some(pipeline) if *pipeline.output_path() == output_path => pipeline.clone(),
+                _ => continue,
+            };
+            recordings.remove(&key);
+            metrics::metrics().active_recordings.set(recordings.len() as u64);
+            drop(recordings);
+
+            pipeline.fail().await;
+            let (room_id, peer_id) = key;
+            tracing::error!(
+                room_id = %room_id,
+                peer_id = %peer_id,
+                error = %error,
+                "Recording failed, stopped recording for peer"
+            );
+            return Some(RecordingFailure {
+                room_id,
+                peer_id,
+                file_path: pipeline.part_path().clone(),
+                error,
+            });
+        }
+    }
+
     /// Stop recording for a specific peer in a room
     pub async fn stop_recording(&self, room_id: &str, peer_id: &str) -> Result<RecordingResult, SfuError> {
         let mut recordings = self.recordings.write().await;
@@ -91,139 +578,861 @@ impl RecordingManager {
                 peer_id, room_id
             ))
         })?;
+        metrics::metrics().active_recordings.set(recordings.len() as u64);
+        // Counted before the lock is released so a room close never misses it
+        let in_flight = self.in_flight.start(room_id);
+        drop(recordings);
+
+        self.finish_recording(room_id, peer_id, &pipeline, in_flight, None, None).await
+    }
+
+    /// Pause a peer's recording: its media is dropped until it is resumed,
+    /// and the file carries on from where it paused
+    pub async fn pause_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
+        self.find_recording(room_id, peer_id).await?.pause().await
+    }
+
+    /// Resume a paused recording
+    pub async fn resume_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
+        self.find_recording(room_id, peer_id).await?.resume().await
+    }
+
+    async fn find_recording(&self, room_id: &str, peer_id: &str) -> Result<Arc<RecordingPipeline>, SfuError> {
+        let key = (room_id.to_string(), peer_id.to_string());
+        self.recordings.read().await.get(&key).cloned().ok_or_else(|| {
+            SfuError::Internal(format!(
+                "No recording found for peer {} in room {}",
+                peer_id, room_id
+            ))
+        })
+    }
+
+    /// Finalizes a recording for the peer with a new file, so the exam goes on
+    /// in a clean recording. The new pipeline, reusing the codecs the tracks
+    /// were negotiated with, is started before the old one is taken out, and
+    /// packets go to it from the moment it is swapped in. Both files are
+    /// linked through `previous` and `next` in their summaries and sidecars.
+    pub async fn restart_recording(&self, room_id: &str, peer_id: &str) -> Result<RecordingRestart, SfuError> {
+        self.continue_recording(room_id, peer_id, room_id).await
+    }
+
+    /// Restart for a peer moving from `room_id` to `to_room_id`: the new file
+    /// is opened under the destination's directory and recorded as part of
+    /// that room, and the two files name each other's room in their summaries
+    /// and sidecars. On failure the recording carries on in `room_id`.
+    pub async fn transfer_recording(&self, room_id: &str, to_room_id: &str, peer_id: &str) -> Result<RecordingRestart, SfuError> {
+        self.continue_recording(room_id, peer_id, to_room_id).await
+    }
+
+    async fn continue_recording(&self, room_id: &str, peer_id: &str, to_room_id: &str) -> Result<RecordingRestart, SfuError> {
+        let key = (room_id.to_string(), peer_id.to_string());
+        let next_key = (to_room_id.to_string(), peer_id.to_string());
+        let moving = room_id != to_room_id;
+        let current = self.recordings.read().await.get(&key).cloned().ok_or_else(|| {
+            SfuError::Internal(format!(
+                "No recording found for peer {} in room {}",
+                peer_id, room_id
+            ))
+        })?;
+
+        chaos::check(ChaosTarget::Recording, Some(to_room_id)).await?;
+
+        let previous = current
+            .output_path()
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut next = self
+            .open_recording(to_room_id, peer_id, &current.codecs())
+            .await?
+            .with_previous(previous);
+        if moving {
+            next = next.with_previous_room(room_id.to_string());
+        }
+        for kind in [MediaKind::Video, MediaKind::Audio] {
+            if current.is_track_muted(kind) {
+                next.set_track_muted(kind, true);
+            }
+        }
+        let next = Arc::new(next);
+
+        let mut recordings = self.recordings.write().await;
+        let replaced = recordings.get(&key).is_some_and(|pipeline| Arc::ptr_eq(pipeline, &current));
+        if !replaced || (moving && recordings.contains_key(&next_key)) {
+            // Stopped while the new pipeline was starting, or the destination
+            // already records the peer; either way it has nothing to continue
+            drop(recordings);
+            if next.stop().await.is_ok() {
+                let _ = std::fs::remove_file(next.output_path());
+            }
+            return Err(SfuError::Internal(format!(
+                "Recording for peer {} in room {} stopped during the restart",
+                peer_id, room_id
+            )));
+        }
+        if moving {
+            recordings.remove(&key);
+            let mut peers = self.peers.write().unwrap();
+            if let Some(peer) = peers.get(&key).cloned() {
+                peers.insert(next_key.clone(), peer);
+            }
+        }
+        recordings.insert(next_key, next.clone());
+        let in_flight = self.in_flight.start(room_id);
+        drop(recordings);
+
+        tracing::info!(
+            room_id = %room_id,
+            to_room_id = %to_room_id,
+            peer_id = %peer_id,
+            file = %next.output_path().display(),
+            "Restarted recording for peer"
+        );
+        let next_file = next
+            .output_path()
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string());
+        let next_room = moving.then_some(to_room_id);
+        let stopped = self
+            .finish_recording(room_id, peer_id, &current, in_flight, next_file.as_deref(), next_room)
+            .await?;
+        Ok(RecordingRestart {
+            stopped,
+            file_path: next.output_path().clone(),
+        })
+    }
 
+    /// Stops a recording already taken out of the active set, then records and
+    /// queues it. `next` is the file a restart continues the recording in, in
+    /// `next_room` when the peer was transferred.
+    async fn finish_recording(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        pipeline: &RecordingPipeline,
+        in_flight: InFlightGuard,
+        next: Option<&str>,
+        next_room: Option<&str>,
+    ) -> Result<RecordingResult, SfuError> {
         let output_path = pipeline.stop().await?;
+        metrics::metrics().recordings_completed_total.inc();
+        let keyframe_stats = pipeline.keyframe_stats();
         tracing::info!(
             room_id = %room_id,
             peer_id = %peer_id,
             file = %output_path.display(),
+            keyframes = keyframe_stats.keyframe_count,
+            avg_keyframe_interval_ms = ?keyframe_stats.avg_interval_ms,
             "Stopped recording for peer"
         );
 
-        // Upload to IPFS if configured
-        let (cid, ipfs_gateway_url) = if let Some(ref client) = self.ipfs_client {
-            match client.upload_file(&output_path, room_id, peer_id).await {
-                Ok(result) => {
+        let sha256 = self.record_completed(room_id, peer_id, pipeline, &output_path, next, next_room).await;
+        let duration_secs = pipeline.elapsed().as_secs();
+        let upload_pending = self.queue_upload(room_id, peer_id, &output_path, duration_secs, in_flight);
+
+        Ok(RecordingResult {
+            file_path: output_path,
+            file_size_bytes: pipeline.bytes_written(),
+            duration_secs,
+            sha256,
+            cid: None,
+            ipfs_gateway_url: None,
+            upload_pending,
+            keyframe_stats,
+        })
+    }
+
+    /// Stop all recordings in a room (used when room closes)
+    pub async fn stop_all_recordings_in_room(&self, room_id: &str) -> Vec<(String, RecordingResult)> {
+        let mut stopped = Vec::new();
+
+        // Take this room's recordings out under the lock, then finalize them without it
+        let pipelines: Vec<(String, Arc<RecordingPipeline>, InFlightGuard)> = {
+            let mut recordings = self.recordings.write().await;
+            let keys_to_remove: Vec<RecordingKey> = recordings
+                .keys()
+                .filter(|(rid, _)| rid == room_id)
+                .cloned()
+                .collect();
+            let removed = keys_to_remove
+                .into_iter()
+                .filter_map(|key| {
+                    let pipeline = recordings.remove(&key)?;
+                    Some((key.1, pipeline, self.in_flight.start(room_id)))
+                })
+                .collect();
+            metrics::metrics().active_recordings.set(recordings.len() as u64);
+            removed
+        };
+
+        for (peer_id, pipeline, in_flight) in pipelines {
+            match pipeline.stop().await {
+                Ok(output_path) => {
+                    metrics::metrics().recordings_completed_total.inc();
                     tracing::info!(
                         room_id = %room_id,
                         peer_id = %peer_id,
-                        cid = %result.cid,
-                        "Uploaded recording to IPFS"
+                        file = %output_path.display(),
+                        "Stopped recording for peer (room cleanup)"
                     );
-                    (Some(result.cid), Some(result.gateway_url))
+
+                    let sha256 = self.record_completed(room_id, &peer_id, &pipeline, &output_path, None, None).await;
+                    let duration_secs = pipeline.elapsed().as_secs();
+                    let upload_pending = self.queue_upload(room_id, &peer_id, &output_path, duration_secs, in_flight);
+
+                    stopped.push((peer_id, RecordingResult {
+                        file_path: output_path,
+                        file_size_bytes: pipeline.bytes_written(),
+                        duration_secs,
+                        sha256,
+                        cid: None,
+                        ipfs_gateway_url: None,
+                        upload_pending,
+                        keyframe_stats: pipeline.keyframe_stats(),
+                    }));
                 }
                 Err(e) => {
                     tracing::error!(
                         room_id = %room_id,
                         peer_id = %peer_id,
                         error = %e,
-                        "Failed to upload recording to IPFS, continuing with local file only"
+                        "Failed to stop recording during room cleanup"
                     );
-                    (None, None)
                 }
             }
-        } else {
-            (None, None)
+        }
+
+        stopped
+    }
+
+    /// Hashes the finalized file on a hash worker, off the recordings lock,
+    /// then writes its sidecar and keeps its summary. Returns the hash.
+    async fn record_completed(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        pipeline: &RecordingPipeline,
+        output_path: &std::path::Path,
+        next: Option<&str>,
+        next_room: Option<&str>,
+    ) -> Option<String> {
+        let stopped_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+
+        let path = output_path.to_path_buf();
+        let sha256 = match hash_workers().run(move || file_sha256(&path)).await {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                tracing::warn!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to hash recording");
+                None
+            }
         };
 
-        Ok(RecordingResult {
-            file_path: output_path,
-            cid,
-            ipfs_gateway_url,
+        let summary = CompletedRecording {
+            peer_id: peer_id.to_string(),
+            file: output_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            started_at: pipeline.started_at_ms(),
+            stopped_at,
+            started_at_local: None,
+            stopped_at_local: None,
+            duration_secs: pipeline.elapsed().as_secs(),
+            bytes_written: pipeline.bytes_written(),
+            cid: None,
+            sha256,
+            gap_secs: pipeline.gap_secs(),
+            previous: pipeline.previous().map(String::from),
+            next: next.map(String::from),
+            previous_room: pipeline.previous_room().map(String::from),
+            next_room: next_room.map(String::from),
+            stop_reason: next.map(|_| {
+                let reason = if next_room.is_some() { STOP_REASON_TRANSFERRED } else { STOP_REASON_RESTARTED };
+                reason.to_string()
+            }),
+        };
+
+        let chapters = pipeline.started_at_ms().and_then(|started_at_ms| {
+            let window = RecordingWindow {
+                started_at_ms,
+                duration_ms: pipeline.elapsed().as_millis() as u64,
+            };
+            write_chapters(output_path, &self.room_dir(room_id).join(VIEW_EVENTS_FILE), peer_id, window)
+        });
+
+        let peer = self.peers.read().unwrap().get(&(room_id.to_string(), peer_id.to_string())).cloned();
+        let codecs = pipeline.codecs();
+        let sidecar = RecordingSidecar {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            peer_name: peer.as_ref().and_then(|peer| peer.name.clone()),
+            role: peer.map(|peer| peer.role),
+            tenant: self.room_tenant(room_id),
+            file: summary.file.clone(),
+            started_at: summary.started_at,
+            stopped_at,
+            duration_secs: summary.duration_secs,
+            size: std::fs::metadata(output_path).map_or(summary.bytes_written, |m| m.len()),
+            video_codec: codecs.video.encoding_name,
+            audio_codec: codecs.audio.encoding_name,
+            sha256: summary.sha256.clone(),
+            cid: None,
+            metadata: self.session_metadata(room_id).await,
+            chapters,
+            previous: summary.previous.clone(),
+            previous_room: summary.previous_room.clone(),
+            next: summary.next.clone(),
+            next_room: summary.next_room.clone(),
+            stop_reason: summary.stop_reason.clone(),
+            keyframes: pipeline.keyframe_stats(),
+        };
+        if let Err(e) = sidecar.write(output_path) {
+            tracing::warn!(file = %output_path.display(), error = %e, "Failed to write recording metadata sidecar");
+        }
+
+        let sha256 = summary.sha256.clone();
+        self.completed
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .push(summary);
+        sha256
+    }
+
+    /// Hands a finalized recording to the upload worker when IPFS is
+    /// configured. Returns whether it was queued.
+    fn queue_upload(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        file_path: &std::path::Path,
+        duration_secs: u64,
+        in_flight: InFlightGuard,
+    ) -> bool {
+        if self.store.is_none() {
+            return false;
+        }
+        let job = UploadJob {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            tenant: self.room_tenant(room_id),
+            file_path: file_path.to_path_buf(),
+            duration_secs,
+            _in_flight: in_flight,
+        };
+        self.upload_queue.send(job).is_ok()
+    }
+
+    /// Waits for the next queued recording and uploads it, retrying failures
+    /// up to the configured number of times. Recordings are uploaded one at a
+    /// time per caller.
+    pub async fn next_upload(&self) -> Option<RecordingUpload> {
+        let job = self.upload_jobs.lock().await.recv().await?;
+        let store = self.store.clone()?;
+        let UploadJob { room_id, peer_id, tenant, file_path, duration_secs, _in_flight: in_flight } = job;
+
+        let mut attempts = 0;
+        let mut backoff = self.upload_retry_backoff;
+        let result = loop {
+            attempts += 1;
+            match store.upload_file(&file_path, &room_id, &peer_id, tenant.as_deref()).await {
+                Ok(uploaded) => break Ok(uploaded),
+                Err(e) if attempts > self.upload_retries => break Err(e.to_string()),
+                Err(e) => {
+                    tracing::warn!(
+                        room_id = %room_id,
+                        peer_id = %peer_id,
+                        attempt = attempts,
+                        retry_in_secs = backoff.as_secs_f64(),
+                        error = %e,
+                        "Failed to upload recording to IPFS, retrying"
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        };
+
+        match &result {
+            Ok(uploaded) => {
+                tracing::info!(
+                    room_id = %room_id,
+                    peer_id = %peer_id,
+                    cid = %uploaded.cid,
+                    pinned = uploaded.pinned,
+                    attempts = attempts,
+                    "Uploaded recording to IPFS"
+                );
+                self.set_completed_cid(&room_id, &file_path, &uploaded.cid).await;
+                self.request_transcript(&room_id, &file_path, &uploaded.gateway_url);
+                if self.delete_after_upload {
+                    delete_uploaded(&room_id, &peer_id, &file_path, uploaded);
+                }
+            }
+            Err(e) => {
+                tracing::error!(
+                    room_id = %room_id,
+                    peer_id = %peer_id,
+                    attempts = attempts,
+                    error = %e,
+                    "Failed to upload recording to IPFS, keeping the local file only"
+                );
+            }
+        }
+
+        Some(RecordingUpload {
+            room_id,
+            peer_id,
+            file_path,
+            duration_secs,
+            attempts,
+            result,
+            _in_flight: in_flight,
         })
     }
 
-    /// Stop all recordings in a room (used when room closes)
-    pub async fn stop_all_recordings_in_room(&self, room_id: &str) -> Vec<(String, RecordingResult)> {
-        let mut recordings = self.recordings.write().await;
-        let mut stopped = Vec::new();
+    /// Attaches the CID of a finished upload to the room's completed recording
+    /// and to its sidecar
+    async fn set_completed_cid(&self, room_id: &str, file_path: &std::path::Path, cid: &str) {
+        let Some(file) = file_path.file_name().map(|n| n.to_string_lossy().to_string()) else {
+            return;
+        };
+        let mut completed = self.completed.write().await;
+        if let Some(summary) = completed
+            .get_mut(room_id)
+            .and_then(|recordings| recordings.iter_mut().find(|recording| recording.file == file))
+        {
+            summary.cid = Some(cid.to_string());
+        }
+        drop(completed);
 
-        // Find all recordings for this room
-        let keys_to_remove: Vec<RecordingKey> = recordings
-            .keys()
-            .filter(|(rid, _)| rid == room_id)
-            .cloned()
-            .collect();
+        // Repaired orphans have no sidecar to update
+        if let Err(e) = sidecar::set_cid(file_path, cid) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(file = %file_path.display(), error = %e, "Failed to record CID in recording sidecar");
+            }
+        }
+    }
+
+    /// `.part` recordings a crash left behind. Only call this before any
+    /// recording starts, while no `.part` file has a live writer.
+    pub fn orphaned_recordings(&self) -> Vec<PathBuf> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        finalize::find_orphans(std::path::Path::new(&self.output_dir)).unwrap_or_else(|e| {
+            tracing::warn!(output_dir = %self.output_dir, error = %e, "Failed to scan for orphaned recordings");
+            Vec::new()
+        })
+    }
+
+    /// Repairs orphaned recordings: each is remuxed into a new file that then
+    /// takes the final name. Returns the recordings that were recovered; ones
+    /// that can't be repaired stay `.part` for an operator to look at.
+    pub async fn repair_orphans(&self, orphans: Vec<PathBuf>) -> Vec<PathBuf> {
+        let mut recovered = Vec::new();
+        for part in orphans {
+            let Some(recording) = finalize::final_path(&part) else { continue };
+            tracing::warn!(file = %part.display(), "Repairing recording that was never finalized");
 
-        for key in keys_to_remove {
-            let peer_id = key.1.clone();
-            if let Some(pipeline) = recordings.remove(&key) {
-                match pipeline.stop().await {
-                    Ok(output_path) => {
-                        tracing::info!(
-                            room_id = %room_id,
-                            peer_id = %peer_id,
-                            file = %output_path.display(),
-                            "Stopped recording for peer (room cleanup)"
-                        );
-
-                        // Upload to IPFS if configured
-                        let (cid, ipfs_gateway_url) = if let Some(ref client) = self.ipfs_client {
-                            match client.upload_file(&output_path, room_id, &peer_id).await {
-                                Ok(result) => {
-                                    tracing::info!(
-                                        room_id = %room_id,
-                                        peer_id = %peer_id,
-                                        cid = %result.cid,
-                                        "Uploaded recording to IPFS (room cleanup)"
-                                    );
-                                    (Some(result.cid), Some(result.gateway_url))
-                                }
-                                Err(e) => {
-                                    tracing::error!(
-                                        room_id = %room_id,
-                                        peer_id = %peer_id,
-                                        error = %e,
-                                        "Failed to upload recording to IPFS during room cleanup"
-                                    );
-                                    (None, None)
-                                }
-                            }
-                        } else {
-                            (None, None)
-                        };
-
-                        stopped.push((peer_id, RecordingResult {
-                            file_path: output_path,
-                            cid,
-                            ipfs_gateway_url,
-                        }));
+            let (source, output) = (part.clone(), recording.clone());
+            let repaired = tokio::task::spawn_blocking(move || {
+                let repair = finalize::repair_path(&output);
+                let result = RecordingPipeline::remux(&source, &repair)
+                    .and_then(|_| {
+                        std::fs::rename(&repair, &output)
+                            .map_err(|e| SfuError::RecordingFailed(format!("Failed to finalize {}: {}", output.display(), e)))
+                    });
+                match result {
+                    Ok(()) => {
+                        let _ = std::fs::remove_file(&source);
+                        Ok(())
                     }
                     Err(e) => {
-                        tracing::error!(
-                            room_id = %room_id,
-                            peer_id = %peer_id,
-                            error = %e,
-                            "Failed to stop recording during room cleanup"
-                        );
+                        let _ = std::fs::remove_file(&repair);
+                        Err(e)
                     }
                 }
+            })
+            .await
+            .map_err(|e| SfuError::Internal(e.to_string()))
+            .and_then(|result| result);
+
+            match repaired {
+                Ok(()) => {
+                    // The remuxed copy was written by GStreamer with default permissions
+                    if let Err(e) = permissions::set_private(&recording) {
+                        tracing::warn!(file = %recording.display(), error = %e, "Failed to restrict recovered recording");
+                    }
+                    tracing::info!(file = %recording.display(), "Recovered orphaned recording");
+                    recovered.push(recording);
+                }
+                Err(e) => {
+                    tracing::error!(file = %part.display(), error = %e, "Failed to repair orphaned recording");
+                }
             }
         }
+        recovered
+    }
 
-        stopped
+    /// Per-recording detail for every in-progress recording in a room
+    pub async fn recording_details(&self, room_id: &str) -> Vec<RecordingDetail> {
+        let recordings = self.recordings.read().await;
+        let mut details = Vec::new();
+        for ((rid, peer_id), pipeline) in recordings.iter() {
+            if rid == room_id {
+                details.push(pipeline.detail(peer_id).await);
+            }
+        }
+        details.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        details
+    }
+
+    /// Detail and closed gaps of a peer's in-progress recording
+    pub async fn peer_recording(&self, room_id: &str, peer_id: &str) -> Option<(RecordingDetail, Vec<MediaGap>)> {
+        let recordings = self.recordings.read().await;
+        let pipeline = recordings.get(&(room_id.to_string(), peer_id.to_string()))?;
+        Some((pipeline.detail(peer_id).await, pipeline.closed_gaps()))
+    }
+
+    /// Recordings that already stopped in a room
+    pub async fn completed_recordings(&self, room_id: &str) -> Vec<CompletedRecording> {
+        self.completed
+            .read()
+            .await
+            .get(room_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Drop the completed recording summaries for a closed room
+    pub async fn forget_completed(&self, room_id: &str) {
+        self.completed.write().await.remove(room_id);
+        self.session_metadata.write().await.remove(room_id);
+        self.room_tenants.write().unwrap().remove(room_id);
+        self.peers.write().unwrap().retain(|(room, _), _| room != room_id);
+    }
+
+    /// Name and role written into the sidecars of the peer's recordings
+    pub fn set_peer(&self, room_id: &str, peer_id: &str, name: Option<String>, role: PeerRole) {
+        self.peers
+            .write()
+            .unwrap()
+            .insert((room_id.to_string(), peer_id.to_string()), RecordedPeer { name, role });
+    }
+
+    /// Sidecar of the peer's most recently stopped recording in the room
+    pub fn get_recording_metadata(&self, room_id: &str, peer_id: &str) -> Result<Option<RecordingSidecar>, SfuError> {
+        match sidecar::latest(&self.room_dir(room_id), peer_id) {
+            Ok(latest) => Ok(latest),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SfuError::Internal(format!("Failed to read recording metadata: {}", e))),
+        }
+    }
+
+    /// Replace the metadata attached to recordings finalized in this room from now on
+    pub async fn set_session_metadata(&self, room_id: &str, metadata: SessionMetadata) {
+        self.session_metadata.write().await.insert(room_id.to_string(), metadata);
+    }
+
+    pub async fn session_metadata(&self, room_id: &str) -> SessionMetadata {
+        self.session_metadata.read().await.get(room_id).cloned().unwrap_or_default()
+    }
+
+    /// Recordings in a room that are stopping or uploading right now
+    pub fn pending_uploads(&self, room_id: &str) -> usize {
+        self.in_flight.count(room_id)
+    }
+
+    /// Wait until no recording in the room is still finalizing or uploading,
+    /// or until `limit` passes. Returns how many were still pending.
+    pub async fn wait_for_uploads(&self, room_id: &str, limit: Duration) -> usize {
+        self.wait_until_uploaded(limit, || self.pending_uploads(room_id)).await
+    }
+
+    /// `wait_for_uploads` across every room, for shutdown
+    pub async fn wait_for_all_uploads(&self, limit: Duration) -> usize {
+        self.wait_until_uploaded(limit, || self.in_flight.total()).await
+    }
+
+    async fn wait_until_uploaded(&self, limit: Duration, pending: impl Fn() -> usize) -> usize {
+        let deadline = tokio::time::Instant::now() + limit;
+        loop {
+            // Registered before the check so a completion in between isn't missed
+            let done = self.in_flight.done.notified();
+            if pending() == 0 {
+                return 0;
+            }
+            if tokio::time::timeout_at(deadline, done).await.is_err() {
+                return pending();
+            }
+        }
+    }
+
+    /// Build the room's manifest once its uploads finish (or `upload_wait` runs
+    /// out), save it next to the recordings and upload it to IPFS.
+    /// Returns the manifest and its CID, if it was uploaded.
+    pub async fn publish_manifest(
+        &self,
+        room_id: &str,
+        session: &RoomSession,
+        upload_wait: Duration,
+        view_events_cid: Option<String>,
+    ) -> (RoomManifest, Option<String>) {
+        let pending = self.wait_for_uploads(room_id, upload_wait).await;
+        if pending > 0 {
+            tracing::warn!(
+                room_id = %room_id,
+                pending_uploads = pending,
+                wait_secs = upload_wait.as_secs(),
+                "Recordings still uploading, building a partial manifest"
+            );
+        }
+
+        let closed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let completed = self.completed_recordings(room_id).await;
+        let manifest = RoomManifest::build(
+            room_id,
+            closed_at,
+            &completed,
+            session,
+            pending,
+            view_events_cid,
+            integrity::weights(),
+        );
+
+        let json = match serde_json::to_vec_pretty(&manifest) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!(room_id = %room_id, error = %e, "Failed to serialize room manifest");
+                return (manifest, None);
+            }
+        };
+
+        if self.enabled {
+            let path = self.room_dir(room_id).join(MANIFEST_FILE);
+            if let Err(e) = permissions::create_room_dir(&self.room_dir(room_id)).and_then(|_| permissions::write_private(&path, &json)) {
+                tracing::warn!(room_id = %room_id, error = %e, "Failed to save room manifest locally");
+            }
+        }
+
+        let cid = if let Some(ref store) = self.store {
+            match store.upload_bytes(&json, Some(MANIFEST_FILE)).await {
+                Ok(result) => {
+                    tracing::info!(
+                        room_id = %room_id,
+                        cid = %result.cid,
+                        recordings = manifest.recordings.len(),
+                        complete = manifest.complete,
+                        "Uploaded room manifest to IPFS"
+                    );
+                    Some(result.cid)
+                }
+                Err(e) => {
+                    tracing::error!(room_id = %room_id, error = %e, "Failed to upload room manifest to IPFS");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        (manifest, cid)
+    }
+
+    /// Queue a transcription job for an uploaded recording; the gateway URL is the download link
+    fn request_transcript(&self, room_id: &str, recording: &std::path::Path, download_url: &str) {
+        let Some(transcripts) = self.transcripts.clone() else {
+            return;
+        };
+
+        let room_id = room_id.to_string();
+        let recording = recording.to_path_buf();
+        let download_url = download_url.to_string();
+        let session_metadata = self.session_metadata.clone();
+        tokio::spawn(async move {
+            let metadata = session_metadata.read().await.get(&room_id).cloned().unwrap_or_default();
+            if let Err(e) = transcripts.submit(&room_id, &recording, &download_url, &metadata).await {
+                tracing::error!(
+                    room_id = %room_id,
+                    file = %recording.display(),
+                    error = %e,
+                    "Failed to submit recording for transcription"
+                );
+            }
+        });
+    }
+
+    /// Push a video RTP packet to a specific peer's recording.
+    /// The packet is only serialized when the peer is actually being recorded.
+    pub async fn push_video_rtp(&self, room_id: &str, peer_id: &str, packet: &Packet) -> Result<(), SfuError> {
+        self.push_rtp(room_id, peer_id, MediaKind::Video, packet).await
+    }
+
+    /// Push an audio RTP packet to a specific peer's recording.
+    /// The packet is only serialized when the peer is actually being recorded.
+    pub async fn push_audio_rtp(&self, room_id: &str, peer_id: &str, packet: &Packet) -> Result<(), SfuError> {
+        self.push_rtp(room_id, peer_id, MediaKind::Audio, packet).await
+    }
+
+    /// A track whose first packet comes after the recording went on without
+    /// it, such as a microphone turned on well after joining, continues the
+    /// recording in a new file that has a branch for it
+    async fn push_rtp(&self, room_id: &str, peer_id: &str, kind: MediaKind, packet: &Packet) -> Result<(), SfuError> {
+        let key = (room_id.to_string(), peer_id.to_string());
+        let recordings = self.recordings.read().await;
+        let Some(pipeline) = recordings.get(&key) else { return Ok(()) };
+        if pipeline.ensure_branch(kind)? {
+            return push_to(pipeline, kind, marshal_packet(packet)?);
+        }
+        drop(recordings);
+
+        tracing::info!(
+            room_id = %room_id,
+            peer_id = %peer_id,
+            track = kind.as_str(),
+            "Track arrived after its recording went on without it"
+        );
+        self.restart_recording(room_id, peer_id).await?;
+        match self.recordings.read().await.get(&key) {
+            Some(pipeline) => push_to(pipeline, kind, marshal_packet(packet)?),
+            None => Ok(()),
+        }
     }
 
-    /// Push video RTP data for a specific peer's recording
-    pub async fn push_video_rtp(&self, room_id: &str, peer_id: &str, data: &[u8]) -> Result<(), SfuError> {
+    /// Points a peer's recording at the codec one of its tracks was negotiated with.
+    /// A codec the pipeline has no branch for, such as H.264 from a Safari
+    /// publisher when VP8 was expected, continues the recording in a new file
+    /// built for it. Peers that are not being recorded are left alone.
+    pub async fn set_track_codec(&self, room_id: &str, peer_id: &str, kind: MediaKind, codec: &RtpCodec) -> Result<(), SfuError> {
         let recordings = self.recordings.read().await;
         let key = (room_id.to_string(), peer_id.to_string());
 
-        if let Some(pipeline) = recordings.get(&key) {
-            pipeline.push_video_rtp(data)?;
+        let rebuild = match recordings.get(&key) {
+            Some(pipeline) => pipeline.set_track_codec(kind, codec)?,
+            None => false,
+        };
+        drop(recordings);
+        if rebuild {
+            tracing::info!(
+                room_id = %room_id,
+                peer_id = %peer_id,
+                codec = %codec.encoding_name,
+                "Track codec needs a new recording pipeline"
+            );
+            self.restart_recording(room_id, peer_id).await?;
         }
         Ok(())
     }
 
-    /// Push audio RTP data for a specific peer's recording
-    pub async fn push_audio_rtp(&self, room_id: &str, peer_id: &str, data: &[u8]) -> Result<(), SfuError> {
+    /// Check every active recording for tracks that went silent or resumed.
+    /// Returns `(room_id, peer_id, event)` for each change since the last sweep.
+    pub async fn sweep_media_gaps(&self, now: std::time::Instant) -> Vec<(String, String, GapEvent)> {
+        let recordings = self.recordings.read().await;
+        recordings
+            .iter()
+            .flat_map(|((room_id, peer_id), pipeline)| {
+                pipeline
+                    .poll_gaps(now)
+                    .into_iter()
+                    .map(move |event| (room_id.clone(), peer_id.clone(), event))
+            })
+            .collect()
+    }
+
+    /// Recordings past a limit, for the watchdog to stop: every recording while
+    /// free space is below `RECORDING_MIN_FREE_BYTES`, and those that ran
+    /// longer than `RECORDING_MAX_DURATION_SECS`
+    pub async fn recordings_over_limits(&self) -> Vec<(String, String, RecordingLimit)> {
+        let recordings = self.recordings.read().await;
+        if recordings.is_empty() {
+            return Vec::new();
+        }
+        let low_disk_space = self.low_disk_space();
+        recordings
+            .iter()
+            .filter_map(|((room_id, peer_id), pipeline)| {
+                let limit = low_disk_space.or_else(|| {
+                    self.max_duration
+                        .filter(|max| pipeline.elapsed() > *max)
+                        .map(RecordingLimit::MaxDuration)
+                })?;
+                Some((room_id.clone(), peer_id.clone(), limit))
+            })
+            .collect()
+    }
+
+    /// Apply a publisher's reported camera and microphone state to its recording
+    pub async fn set_media_state(&self, room_id: &str, peer_id: &str, has_video: bool, has_audio: bool) {
         let recordings = self.recordings.read().await;
         let key = (room_id.to_string(), peer_id.to_string());
 
         if let Some(pipeline) = recordings.get(&key) {
-            pipeline.push_audio_rtp(data)?;
+            pipeline.set_track_muted(MediaKind::Video, !has_video);
+            pipeline.set_track_muted(MediaKind::Audio, !has_audio);
         }
-        Ok(())
+    }
+
+    /// Mark a peer's media as end-to-end encrypted, so it is not recorded
+    /// until it is marked otherwise. A recording already running is left to the caller.
+    pub async fn set_e2ee(&self, room_id: &str, peer_id: &str, e2ee: bool) {
+        let key = (room_id.to_string(), peer_id.to_string());
+        let mut peers = self.e2ee_peers.write().await;
+        if e2ee {
+            peers.insert(key);
+        } else {
+            peers.remove(&key);
+        }
+    }
+
+    /// Whether the peer's media is marked as end-to-end encrypted
+    pub async fn is_e2ee(&self, room_id: &str, peer_id: &str) -> bool {
+        self.e2ee_peers
+            .read()
+            .await
+            .contains(&(room_id.to_string(), peer_id.to_string()))
+    }
+
+    /// Account forwarded live media so IPFS uploads can back off under load
+    pub fn account_media_bytes(&self, bytes: u64) {
+        if let Some(ref store) = self.store {
+            store.record_media_bytes(bytes);
+        }
+    }
+
+    /// Record a keyframe observed from a recorded publisher
+    pub async fn record_keyframe(&self, room_id: &str, peer_id: &str) {
+        let recordings = self.recordings.read().await;
+        let key = (room_id.to_string(), peer_id.to_string());
+
+        if let Some(pipeline) = recordings.get(&key) {
+            pipeline.record_keyframe();
+        }
+    }
+
+    /// Record that a keyframe was requested from a recorded publisher
+    pub async fn record_keyframe_request(&self, room_id: &str, peer_id: &str) {
+        let recordings = self.recordings.read().await;
+        let key = (room_id.to_string(), peer_id.to_string());
+
+        if let Some(pipeline) = recordings.get(&key) {
+            pipeline.record_pli_request();
+        }
+    }
+
+    /// Check if a peer's recording exists and is currently capturing media
+    pub async fn is_actively_recording(&self, room_id: &str, peer_id: &str) -> bool {
+        matches!(
+            self.get_recording_state(room_id, peer_id).await,
+            Some(RecordingState::Recording)
+        )
     }
 
     /// Get the recording state for a specific peer
@@ -231,34 +1440,148 @@ impl RecordingManager {
         let recordings = self.recordings.read().await;
         let key = (room_id.to_string(), peer_id.to_string());
 
-        if let Some(pipeline) = recordings.get(&key) {
-            Some(pipeline.get_state().await)
-        } else {
-            None
+        if let Some(pipeline) = recordings.get(&key) {
+            Some(pipeline.get_state().await)
+        } else {
+            None
+        }
+    }
+
+    /// Check if a specific peer is being recorded
+    pub async fn is_recording(&self, room_id: &str, peer_id: &str) -> bool {
+        let recordings = self.recordings.read().await;
+        let key = (room_id.to_string(), peer_id.to_string());
+        recordings.contains_key(&key)
+    }
+
+    /// Check if any recording exists in a room
+    pub async fn is_room_recording(&self, room_id: &str) -> bool {
+        let recordings = self.recordings.read().await;
+        recordings.keys().any(|(rid, _)| rid == room_id)
+    }
+
+    /// Rooms with at least one recording not yet stopped
+    pub async fn recording_rooms(&self) -> Vec<String> {
+        let recordings = self.recordings.read().await;
+        let mut rooms: Vec<String> = recordings.keys().map(|(rid, _)| rid.clone()).collect();
+        rooms.sort_unstable();
+        rooms.dedup();
+        rooms
+    }
+
+    /// Get all peer IDs being recorded in a room
+    pub async fn get_recording_peers(&self, room_id: &str) -> Vec<String> {
+        let recordings = self.recordings.read().await;
+        recordings
+            .keys()
+            .filter(|(rid, _)| rid == room_id)
+            .map(|(_, pid)| pid.clone())
+            .collect()
+    }
+
+    /// Keeps the room's files in `tenant`'s namespace, `{output_dir}/{tenant}/{room_id}/`.
+    /// Set before anything is written for the room.
+    pub fn set_room_tenant(&self, room_id: &str, tenant: Option<String>) {
+        let Some(tenant) = tenant else {
+            self.room_tenants.write().unwrap().remove(room_id);
+            return;
+        };
+        if self.enabled {
+            if let Err(e) = tenant::create_tenant_dir(std::path::Path::new(&self.output_dir), &tenant) {
+                tracing::warn!(tenant = %tenant, error = %e, "Tenant directory is not usable");
+            }
+        }
+        self.room_tenants.write().unwrap().insert(room_id.to_string(), tenant);
+    }
+
+    pub fn room_tenant(&self, room_id: &str) -> Option<String> {
+        self.room_tenants.read().unwrap().get(room_id).cloned()
+    }
+
+    /// Rooms created for `tenant` that have not finished closing
+    pub fn tenant_rooms(&self, tenant: &str) -> Vec<String> {
+        let mut rooms: Vec<String> = self
+            .room_tenants
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, t)| *t == tenant)
+            .map(|(room_id, _)| room_id.clone())
+            .collect();
+        rooms.sort();
+        rooms
+    }
+
+    pub fn output_dir(&self) -> &str {
+        &self.output_dir
+    }
+
+    /// Directory the room's directory sits in: the tenant namespace, or the output directory
+    fn namespace_dir(&self, room_id: &str) -> String {
+        match self.room_tenant(room_id) {
+            Some(tenant) => PathBuf::from(&self.output_dir).join(tenant).to_string_lossy().to_string(),
+            None => self.output_dir.clone(),
+        }
+    }
+
+    /// Directory holding a room's recordings and metadata
+    pub fn room_dir(&self, room_id: &str) -> PathBuf {
+        PathBuf::from(self.namespace_dir(room_id)).join(room_id)
+    }
+
+    /// Start the view event stream for a room, anchored to the session start
+    pub async fn open_view_log(&self, room_id: &str, proctor_id: &str) {
+        if !self.enabled {
+            return;
+        }
+
+        match ViewEventLog::create(&self.room_dir(room_id), proctor_id, SessionClock::start()) {
+            Ok(log) => {
+                self.view_logs.write().await.insert(room_id.to_string(), log);
+            }
+            Err(e) => {
+                tracing::error!(room_id = %room_id, error = %e, "Failed to create view event log");
+            }
         }
     }
 
-    /// Check if a specific peer is being recorded
-    pub async fn is_recording(&self, room_id: &str, peer_id: &str) -> bool {
-        let recordings = self.recordings.read().await;
-        let key = (room_id.to_string(), peer_id.to_string());
-        recordings.contains_key(&key)
+    /// Append a view event to the room's stream (no-op if the room has none)
+    pub async fn record_view_event(
+        &self,
+        room_id: &str,
+        event: ViewEventKind,
+        peer_id: &str,
+        details: serde_json::Value,
+    ) {
+        let logs = self.view_logs.read().await;
+        if let Some(log) = logs.get(room_id) {
+            if let Err(e) = log.append(event, peer_id, details) {
+                tracing::warn!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to append view event");
+            }
+        }
     }
 
-    /// Check if any recording exists in a room
-    pub async fn is_room_recording(&self, room_id: &str) -> bool {
-        let recordings = self.recordings.read().await;
-        recordings.keys().any(|(rid, _)| rid == room_id)
-    }
+    /// Close the room's view event stream and upload it to IPFS if configured
+    pub async fn close_view_log(&self, room_id: &str) -> Option<ViewEventsResult> {
+        let log = self.view_logs.write().await.remove(room_id)?;
+        let file_path = log.path().to_path_buf();
 
-    /// Get all peer IDs being recorded in a room
-    pub async fn get_recording_peers(&self, room_id: &str) -> Vec<String> {
-        let recordings = self.recordings.read().await;
-        recordings
-            .keys()
-            .filter(|(rid, _)| rid == room_id)
-            .map(|(_, pid)| pid.clone())
-            .collect()
+        let cid = if let Some(ref store) = self.store {
+            match store.upload_file(&file_path, room_id, "view_events", self.room_tenant(room_id).as_deref()).await {
+                Ok(result) => {
+                    tracing::info!(room_id = %room_id, cid = %result.cid, "Uploaded view events to IPFS");
+                    Some(result.cid)
+                }
+                Err(e) => {
+                    tracing::error!(room_id = %room_id, error = %e, "Failed to upload view events to IPFS");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Some(ViewEventsResult { file_path, cid })
     }
 
     /// Cleanup a specific peer's recording (stop if active)
@@ -289,15 +1612,21 @@ impl RecordingManager {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::recording::{MockStore, StoreCall};
 
     #[test]
     fn test_recording_result_debug() {
         let result = RecordingResult {
             file_path: PathBuf::from("/tmp/test.webm"),
+            file_size_bytes: 1024,
+            duration_secs: 60,
+            sha256: None,
             cid: Some("QmTest123".to_string()),
             ipfs_gateway_url: Some("http://localhost:8080/ipfs/QmTest123".to_string()),
+            upload_pending: false,
+            keyframe_stats: KeyframeStats::default(),
         };
         let debug_str = format!("{:?}", result);
         assert!(debug_str.contains("test.webm"));
@@ -308,21 +1637,33 @@ mod tests {
     fn test_recording_result_clone() {
         let result = RecordingResult {
             file_path: PathBuf::from("/tmp/test.webm"),
+            file_size_bytes: 1024,
+            duration_secs: 60,
+            sha256: None,
             cid: Some("QmTest123".to_string()),
             ipfs_gateway_url: Some("http://localhost:8080/ipfs/QmTest123".to_string()),
+            upload_pending: false,
+            keyframe_stats: KeyframeStats::default(),
         };
         let cloned = result.clone();
         assert_eq!(result.file_path, cloned.file_path);
         assert_eq!(result.cid, cloned.cid);
         assert_eq!(result.ipfs_gateway_url, cloned.ipfs_gateway_url);
+        assert_eq!(result.file_size_bytes, cloned.file_size_bytes);
+        assert_eq!(result.duration_secs, cloned.duration_secs);
     }
 
     #[test]
     fn test_recording_result_without_ipfs() {
         let result = RecordingResult {
             file_path: PathBuf::from("/tmp/test.webm"),
+            file_size_bytes: 1024,
+            duration_secs: 60,
+            sha256: None,
             cid: None,
             ipfs_gateway_url: None,
+            upload_pending: false,
+            keyframe_stats: KeyframeStats::default(),
         };
         assert!(result.cid.is_none());
         assert!(result.ipfs_gateway_url.is_none());
@@ -335,59 +1676,188 @@ mod tests {
         assert_eq!(key.1, "peer1");
     }
 
+    #[tokio::test]
+    async fn test_wait_for_uploads_until_in_flight_done() {
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        assert_eq!(manager.wait_for_uploads("room1", Duration::from_secs(5)).await, 0);
+
+        let guard = manager.in_flight.start("room1");
+        let _other_room = manager.in_flight.start("room2");
+        assert_eq!(manager.pending_uploads("room1"), 1);
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert_eq!(manager.wait_for_uploads("room1", Duration::from_secs(5)).await, 0);
+        assert_eq!(manager.pending_uploads("room2"), 1);
+        assert_eq!(manager.wait_for_all_uploads(Duration::from_millis(20)).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_uploads_is_bounded() {
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        let _first = manager.in_flight.start("room1");
+        let _second = manager.in_flight.start("room1");
+
+        let started = std::time::Instant::now();
+        assert_eq!(manager.wait_for_uploads("room1", Duration::from_millis(50)).await, 2);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_upload_is_retried_then_reported() {
+        let without_ipfs = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        let in_flight = without_ipfs.in_flight.start("room1");
+        assert!(!without_ipfs.queue_upload("room1", "peer1", std::path::Path::new("/tmp/a.webm"), 60, in_flight));
+        assert_eq!(without_ipfs.pending_uploads("room1"), 0);
+
+        let store = Arc::new(MockStore::new());
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), Some(store.clone())).with_upload_retries(2);
+
+        let in_flight = manager.in_flight.start("room1");
+        let missing = std::path::Path::new("/tmp/test_recordings/missing_upload.webm");
+        assert!(manager.queue_upload("room1", "peer1", missing, 60, in_flight));
+        assert_eq!(manager.pending_uploads("room1"), 1);
+
+        let started = tokio::time::Instant::now();
+        let upload = manager.next_upload().await.unwrap();
+        assert_eq!(upload.attempts, 3);
+        assert_eq!(upload.duration_secs, 60);
+        assert!(upload.result.is_err());
+        // Backoff doubles after each failed attempt
+        assert_eq!(started.elapsed(), DEFAULT_UPLOAD_RETRY_BACKOFF * 3);
+        let attempt = StoreCall::File {
+            room_id: "room1".to_string(),
+            peer_id: "peer1".to_string(),
+            file_name: "missing_upload.webm".to_string(),
+        };
+        assert_eq!(store.calls(), vec![attempt; 3]);
+        // Still pending until whoever reports the upload is done with it
+        assert_eq!(manager.pending_uploads("room1"), 1);
+        drop(upload);
+        assert_eq!(manager.pending_uploads("room1"), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_upload_succeeds_after_transient_failures() {
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-retry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join("peer1_1700000000.webm");
+        std::fs::write(&file_path, b"webm").unwrap();
+
+        let store = Arc::new(MockStore::new());
+        store.fail_next(2);
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), false), Some(store.clone())).with_upload_retries(2);
+        let in_flight = manager.in_flight.start("room1");
+        assert!(manager.queue_upload("room1", "peer1", &file_path, 30, in_flight));
+
+        let upload = manager.next_upload().await.unwrap();
+        assert_eq!(upload.attempts, 3);
+        let uploaded = upload.result.as_ref().unwrap();
+        assert_eq!(uploaded.cid, "bafymock3");
+        assert_eq!(uploaded.size, 4);
+        assert_eq!(store.calls().len(), 3);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_local_file_is_deleted_once_uploaded() {
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-delete-uploaded-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (kept, deleted) = (dir.join("peer1_1700000000.webm"), dir.join("peer2_1700000000.webm"));
+        std::fs::write(&kept, b"webm").unwrap();
+        std::fs::write(&deleted, b"webm").unwrap();
+
+        let store = Arc::new(MockStore::new());
+        let config = RecordingConfig {
+            delete_after_upload: true,
+            ..recording_config(dir.to_str().unwrap(), false)
+        };
+        let manager = RecordingManager::new(&config, Some(store.clone())).with_upload_retries(0);
+
+        // A failed upload keeps the only copy
+        store.fail_next(1);
+        assert!(manager.queue_upload("room1", "peer1", &kept, 30, manager.in_flight.start("room1")));
+        assert!(manager.next_upload().await.unwrap().result.is_err());
+        assert!(kept.exists());
+
+        assert!(manager.queue_upload("room1", "peer2", &deleted, 30, manager.in_flight.start("room1")));
+        assert!(manager.next_upload().await.unwrap().result.unwrap().pinned);
+        assert!(!deleted.exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[tokio::test]
     async fn test_recording_manager_disabled() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         assert!(!manager.is_enabled());
     }
 
     #[tokio::test]
     async fn test_recording_manager_enabled() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, true);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", true), None);
         assert!(manager.is_enabled());
     }
 
     #[tokio::test]
     async fn test_start_recording_when_disabled() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
 
         // Starting recording when disabled should succeed silently
-        let result = manager.start_recording("room1", "peer1").await;
+        let result = manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await;
         assert!(result.is_ok());
 
         // Should not actually create a recording
         assert!(!manager.is_recording("room1", "peer1").await);
     }
 
+    #[tokio::test]
+    async fn test_keyframe_interval_default_and_override() {
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        assert_eq!(manager.keyframe_interval(), Duration::from_secs(DEFAULT_KEYFRAME_INTERVAL_SECS));
+
+        let manager = manager.with_keyframe_interval(Duration::ZERO);
+        assert!(manager.keyframe_interval().is_zero());
+    }
+
+    #[tokio::test]
+    async fn test_is_actively_recording_no_recordings() {
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        assert!(!manager.is_actively_recording("room1", "peer1").await);
+    }
+
     #[tokio::test]
     async fn test_is_recording_no_recordings() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         assert!(!manager.is_recording("room1", "peer1").await);
     }
 
     #[tokio::test]
     async fn test_is_room_recording_no_recordings() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         assert!(!manager.is_room_recording("room1").await);
     }
 
     #[tokio::test]
     async fn test_get_recording_peers_empty() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         let peers = manager.get_recording_peers("room1").await;
         assert!(peers.is_empty());
     }
 
     #[tokio::test]
     async fn test_get_recording_state_none() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         let state = manager.get_recording_state("room1", "peer1").await;
         assert!(state.is_none());
     }
 
     #[tokio::test]
     async fn test_stop_recording_not_found() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, true);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", true), None);
         let result = manager.stop_recording("room1", "peer1").await;
         assert!(result.is_err());
 
@@ -398,34 +1868,722 @@ mod tests {
 
     #[tokio::test]
     async fn test_stop_all_recordings_empty_room() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         let stopped = manager.stop_all_recordings_in_room("room1").await;
         assert!(stopped.is_empty());
     }
 
     #[tokio::test]
     async fn test_cleanup_peer_no_recording() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         // Should not panic when cleaning up a non-existent recording
         manager.cleanup_peer("room1", "peer1").await;
     }
 
+    #[test]
+    fn test_marshal_packet_writes_header_and_payload() {
+        let packet = Packet {
+            header: webrtc::rtp::header::Header {
+                version: 2,
+                sequence_number: 7,
+                ..Default::default()
+            },
+            payload: bytes::Bytes::from_static(&[0xaa, 0xbb, 0xcc]),
+        };
+
+        let data = marshal_packet(&packet).unwrap();
+        assert_eq!(data.len(), 12 + 3);
+        assert_eq!(&data[12..], &[0xaa, 0xbb, 0xcc]);
+    }
+
+    #[tokio::test]
+    async fn test_recording_status_empty_room() {
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
+        assert!(manager.recording_details("room1").await.is_empty());
+        assert!(manager.completed_recordings("room1").await.is_empty());
+
+        manager.forget_completed("room1").await;
+        assert!(manager.completed_recordings("room1").await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_cleanup_room_empty() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
         // Should not panic when cleaning up an empty room
         manager.cleanup_room("room1").await;
     }
 
     #[tokio::test]
     async fn test_push_rtp_no_recording() {
-        let manager = RecordingManager::new("/tmp/test_recordings", None, false);
+        let manager = RecordingManager::new(&recording_config("/tmp/test_recordings", false), None);
 
         // Pushing RTP to non-existent recording should succeed silently
-        let video_result = manager.push_video_rtp("room1", "peer1", &[0, 1, 2, 3]).await;
+        let packet = Packet {
+            payload: bytes::Bytes::from_static(&[0, 1, 2, 3]),
+            ..Default::default()
+        };
+        let video_result = manager.push_video_rtp("room1", "peer1", &packet).await;
         assert!(video_result.is_ok());
 
-        let audio_result = manager.push_audio_rtp("room1", "peer1", &[0, 1, 2, 3]).await;
+        let audio_result = manager.push_audio_rtp("room1", "peer1", &packet).await;
         assert!(audio_result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_failed_pipeline_falls_back_to_rtp_dump() {
+        use crate::recording::rtpdump::{DumpReader, DumpRecord, DumpTrack};
+        use webrtc::rtp::header::Header;
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-fallback-{}", std::process::id()));
+        // H.265 has no depayloader in the pipeline, so building it fails on any host
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 98, 90000),
+            ..RecordingCodecs::default()
+        };
+
+        let without = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+        assert!(without.start_recording("room1", "peer1", &codecs).await.is_err());
+        assert!(!without.is_recording("room1", "peer1").await);
+
+        let store = Arc::new(MockStore::new());
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), Some(store.clone())).with_rtp_fallback(true);
+        manager.start_recording("room1", "peer1", &codecs).await.unwrap();
+        assert!(manager.is_actively_recording("room1", "peer1").await);
+
+        let packet = |payload_type: u8, sequence_number: u16| Packet {
+            header: Header { version: 2, payload_type, sequence_number, ..Default::default() },
+            payload: bytes::Bytes::from(vec![sequence_number as u8; 100]),
+        };
+        for i in 0..3 {
+            manager.push_video_rtp("room1", "peer1", &packet(98, i)).await.unwrap();
+        }
+        let opus = RtpCodec::from_mime_type("audio/opus", 109, 48000);
+        manager.set_track_codec("room1", "peer1", MediaKind::Audio, &opus).await.unwrap();
+        manager.push_audio_rtp("room1", "peer1", &packet(109, 7)).await.unwrap();
+
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        let name = result.file_path.file_name().unwrap().to_str().unwrap().to_string();
+        assert!(name.starts_with("peer1_") && name.ends_with(".rtpdump"), "{}", name);
+        assert!(!finalize::part_path(&result.file_path).exists());
+        assert_eq!(result.file_size_bytes, std::fs::metadata(&result.file_path).unwrap().len());
+        assert!(result.upload_pending);
+
+        let mut reader = DumpReader::new(std::fs::File::open(&result.file_path).unwrap()).unwrap();
+        assert_eq!(reader.header().peer_id, "peer1");
+        assert_eq!(reader.header().video.encoding_name, "H265");
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record);
+        }
+        assert_eq!(records.len(), 5);
+        for (record, i) in records[..3].iter().zip(0..) {
+            assert!(matches!(
+                record,
+                DumpRecord::Rtp { track: DumpTrack::Video, packet: bytes, .. } if *bytes == marshal_packet(&packet(98, i)).unwrap()
+            ));
+        }
+        assert!(matches!(&records[3], DumpRecord::Codec { track: DumpTrack::Audio, codec, .. } if codec.payload_type == 109));
+        assert!(matches!(&records[4], DumpRecord::Rtp { track: DumpTrack::Audio, .. }));
+
+        // Completed and uploaded like any other recording
+        assert_eq!(manager.completed_recordings("room1").await[0].file, name);
+        let upload = manager.next_upload().await.unwrap();
+        assert!(upload.result.is_ok());
+        assert_eq!(
+            store.calls(),
+            vec![StoreCall::File { room_id: "room1".to_string(), peer_id: "peer1".to_string(), file_name: name }]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_paused_recording_drops_media_and_resumes_seamlessly() {
+        use crate::recording::rtpdump::{DumpReader, DumpRecord};
+        use webrtc::rtp::header::Header;
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-pause-{}", std::process::id()));
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 98, 90000),
+            ..RecordingCodecs::default()
+        };
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true);
+        assert!(manager.pause_recording("room1", "peer1").await.is_err());
+        manager.start_recording("room1", "peer1", &codecs).await.unwrap();
+
+        let packet = |sequence_number: u16| Packet {
+            header: Header { version: 2, payload_type: 98, sequence_number, ..Default::default() },
+            payload: bytes::Bytes::from(vec![sequence_number as u8; 100]),
+        };
+        // Resuming a recording that isn't paused is refused
+        assert!(matches!(
+            manager.resume_recording("room1", "peer1").await,
+            Err(SfuError::InvalidRecordingState(_))
+        ));
+        manager.push_video_rtp("room1", "peer1", &packet(1)).await.unwrap();
+
+        manager.pause_recording("room1", "peer1").await.unwrap();
+        assert_eq!(manager.get_recording_state("room1", "peer1").await, Some(RecordingState::Paused));
+        assert!(!manager.is_actively_recording("room1", "peer1").await);
+        assert!(matches!(
+            manager.pause_recording("room1", "peer1").await,
+            Err(SfuError::InvalidRecordingState(_))
+        ));
+        manager.push_video_rtp("room1", "peer1", &packet(2)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let details = manager.recording_details("room1").await;
+        assert!(details[0].paused);
+
+        manager.resume_recording("room1", "peer1").await.unwrap();
+        assert!(manager.is_actively_recording("room1", "peer1").await);
+        manager.push_video_rtp("room1", "peer1", &packet(3)).await.unwrap();
+
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        let mut reader = DumpReader::new(std::fs::File::open(&result.file_path).unwrap()).unwrap();
+        let mut offsets = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            if let DumpRecord::Rtp { offset, packet, .. } = record {
+                offsets.push((offset, packet[2..4].to_vec()));
+            }
+        }
+        // The packet sent while paused is gone, and the pause left no hole
+        let sequence_numbers: Vec<_> = offsets.iter().map(|(_, seq)| u16::from_be_bytes([seq[0], seq[1]])).collect();
+        assert_eq!(sequence_numbers, vec![1, 3]);
+        assert!(offsets[1].0 - offsets[0].0 < Duration::from_millis(200), "{:?}", offsets);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_e2ee_peer_is_not_recorded() {
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-e2ee-{}", std::process::id()));
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 98, 90000),
+            ..RecordingCodecs::default()
+        };
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true);
+
+        manager.set_e2ee("room1", "peer1", true).await;
+        assert!(manager.is_e2ee("room1", "peer1").await);
+        assert!(matches!(
+            manager.start_recording("room1", "peer1", &codecs).await,
+            Err(SfuError::RecordingFailed(_))
+        ));
+        assert!(!manager.is_recording("room1", "peer1").await);
+
+        // Only that peer in that room is skipped
+        assert!(!manager.is_e2ee("room2", "peer1").await);
+        manager.start_recording("room1", "peer2", &codecs).await.unwrap();
+        manager.start_recording("room2", "peer1", &codecs).await.unwrap();
+
+        manager.set_e2ee("room1", "peer1", false).await;
+        manager.start_recording("room1", "peer1", &codecs).await.unwrap();
+        assert_eq!(manager.active_count().await, 3);
+
+        manager.cleanup_room("room1").await;
+        manager.cleanup_room("room2").await;
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_h264_track_continues_recording_in_mkv() {
+        let h264_elements = ["rtph264depay", "h264parse", "matroskamux"];
+        if RecordingPipeline::verify_environment().is_err()
+            || h264_elements.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+        {
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-h264-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+
+        // Another VP8 payload type keeps the pipeline
+        let vp8 = RtpCodec::from_mime_type("video/VP8", 97, 90000);
+        manager.set_track_codec("room1", "peer1", MediaKind::Video, &vp8).await.unwrap();
+        assert!(manager.completed_recordings("room1").await.is_empty());
+
+        // A Safari publisher negotiates H.264, which the VP8 pipeline cannot take
+        let h264 = RtpCodec::from_mime_type("video/H264", 102, 90000);
+        manager.set_track_codec("room1", "peer1", MediaKind::Video, &h264).await.unwrap();
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        assert_eq!(result.file_path.extension().unwrap(), "mkv");
+
+        let completed = manager.completed_recordings("room1").await;
+        assert_eq!(completed.len(), 2);
+        assert!(completed[0].file.ends_with(".webm"), "{}", completed[0].file);
+        assert_eq!(completed[1].previous.as_deref(), Some(completed[0].file.as_str()));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_recordings_are_held_to_disk_space_and_duration_limits() {
+        use crate::recording::disk::FixedDiskSpace;
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-limits-{}", std::process::id()));
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 98, 90000),
+            ..RecordingCodecs::default()
+        };
+        let disk = Arc::new(FixedDiskSpace::new(1000));
+        let config = RecordingConfig {
+            min_free_bytes: 5000,
+            max_duration: Some(Duration::from_millis(200)),
+            ..recording_config(dir.to_str().unwrap(), true)
+        };
+        let manager = RecordingManager::new(&config, None).with_rtp_fallback(true).with_disk_space(disk.clone());
+
+        // Refused before anything is written
+        let refused = manager.start_recording("room1", "peer1", &codecs).await.unwrap_err();
+        assert!(refused.to_string().contains("Only 1000 bytes free"), "{}", refused);
+        assert!(!manager.is_recording("room1", "peer1").await);
+
+        disk.set(10_000);
+        manager.start_recording("room1", "peer1", &codecs).await.unwrap();
+        manager.start_recording("room1", "peer2", &codecs).await.unwrap();
+        assert!(manager.recordings_over_limits().await.is_empty());
+
+        // Every recording once the disk fills up
+        disk.set(4000);
+        let mut over = manager.recordings_over_limits().await;
+        over.sort_by(|a, b| a.1.cmp(&b.1));
+        let low = RecordingLimit::LowDiskSpace { available: 4000, min_free: 5000 };
+        assert_eq!(
+            over,
+            vec![("room1".to_string(), "peer1".to_string(), low), ("room1".to_string(), "peer2".to_string(), low)]
+        );
+
+        // Only those that ran past the maximum duration
+        disk.set(10_000);
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        manager.start_recording("room1", "peer3", &codecs).await.unwrap();
+        let mut over = manager.recordings_over_limits().await;
+        over.sort_by(|a, b| a.1.cmp(&b.1));
+        let peers: Vec<_> = over.iter().map(|(_, peer_id, limit)| (peer_id.as_str(), *limit)).collect();
+        let max = RecordingLimit::MaxDuration(Duration::from_millis(200));
+        assert_eq!(peers, vec![("peer1", max), ("peer2", max)]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_restart_continues_recording_in_linked_file() {
+        use crate::recording::rtpdump::{DumpReader, DumpRecord, DumpTrack};
+        use webrtc::rtp::header::Header;
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-restart-{}", std::process::id()));
+        // Recorded as RTP dumps, whose records show which file each packet went to
+        let codecs = RecordingCodecs {
+            video: RtpCodec::from_mime_type("video/H265", 98, 90000),
+            ..RecordingCodecs::default()
+        };
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true);
+        assert!(manager.restart_recording("room1", "peer1").await.is_err());
+
+        manager.start_recording("room1", "peer1", &codecs).await.unwrap();
+        let opus = RtpCodec::from_mime_type("audio/opus", 109, 48000);
+        manager.set_track_codec("room1", "peer1", MediaKind::Audio, &opus).await.unwrap();
+        let packet = |payload_type: u8, sequence_number: u16| Packet {
+            header: Header { version: 2, payload_type, sequence_number, ..Default::default() },
+            payload: bytes::Bytes::from(vec![sequence_number as u8; 100]),
+        };
+        for i in 0..2 {
+            manager.push_video_rtp("room1", "peer1", &packet(98, i)).await.unwrap();
+        }
+
+        let restart = manager.restart_recording("room1", "peer1").await.unwrap();
+        assert!(manager.is_actively_recording("room1", "peer1").await);
+        assert_eq!(manager.active_count().await, 1);
+        for i in 2..5 {
+            manager.push_video_rtp("room1", "peer1", &packet(98, i)).await.unwrap();
+        }
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        assert_eq!(result.file_path, restart.file_path);
+
+        let rtp_records = |path: &std::path::Path| {
+            let mut reader = DumpReader::new(std::fs::File::open(path).unwrap()).unwrap();
+            let header = reader.header().clone();
+            let mut sequence_numbers = Vec::new();
+            while let Some(record) = reader.next_record().unwrap() {
+                if let DumpRecord::Rtp { track: DumpTrack::Video, packet, .. } = record {
+                    sequence_numbers.push(packet[3]);
+                }
+            }
+            (header, sequence_numbers)
+        };
+        let (_, before) = rtp_records(&restart.stopped.file_path);
+        assert_eq!(before, vec![0, 1]);
+        // The new file starts with the codecs negotiated during the first one
+        let (header, after) = rtp_records(&restart.file_path);
+        assert_eq!((header.video.payload_type, header.audio.payload_type), (98, 109));
+        assert_eq!(after, vec![2, 3, 4]);
+
+        let file_name = |path: &std::path::Path| path.file_name().unwrap().to_str().unwrap().to_string();
+        let (first, second) = (file_name(&restart.stopped.file_path), file_name(&restart.file_path));
+        let completed = manager.completed_recordings("room1").await;
+        assert_eq!(completed.len(), 2);
+        assert_eq!((completed[0].file.as_str(), completed[0].previous.as_deref()), (first.as_str(), None));
+        assert_eq!(completed[0].next.as_deref(), Some(second.as_str()));
+        assert_eq!(completed[0].stop_reason.as_deref(), Some(STOP_REASON_RESTARTED));
+        assert_eq!(completed[1].previous.as_deref(), Some(first.as_str()));
+        assert_eq!((completed[1].next.as_deref(), completed[1].stop_reason.as_deref()), (None, None));
+
+        let sidecar = |path: &std::path::Path| -> serde_json::Value {
+            serde_json::from_slice(&std::fs::read(path.with_extension("meta.json")).unwrap()).unwrap()
+        };
+        let stopped = sidecar(&restart.stopped.file_path);
+        assert_eq!((stopped["next"].as_str(), stopped["stop_reason"].as_str()), (Some(second.as_str()), Some("restarted")));
+        assert!(stopped.get("previous").is_none());
+        let continued = sidecar(&restart.file_path);
+        assert_eq!(continued["previous"].as_str(), Some(first.as_str()));
+        assert!(continued.get("next").is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_transfer_moves_recording_to_destination_room() {
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-transfer-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true);
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+
+        let transfer = manager.transfer_recording("room1", "room2", "peer1").await.unwrap();
+        assert!(!manager.is_recording("room1", "peer1").await);
+        assert!(manager.is_actively_recording("room2", "peer1").await);
+        assert_eq!(manager.active_count().await, 1);
+        assert!(transfer.file_path.starts_with(manager.room_dir("room2")));
+        assert!(transfer.stopped.file_path.starts_with(manager.room_dir("room1")));
+        manager.stop_recording("room2", "peer1").await.unwrap();
+
+        let file_name = |path: &std::path::Path| path.file_name().unwrap().to_str().unwrap().to_string();
+        let (first, second) = (file_name(&transfer.stopped.file_path), file_name(&transfer.file_path));
+        let stopped = &manager.completed_recordings("room1").await[0];
+        assert_eq!((stopped.next.as_deref(), stopped.next_room.as_deref()), (Some(second.as_str()), Some("room2")));
+        assert_eq!(stopped.stop_reason.as_deref(), Some(STOP_REASON_TRANSFERRED));
+        let continued = &manager.completed_recordings("room2").await[0];
+        assert_eq!((continued.previous.as_deref(), continued.previous_room.as_deref()), (Some(first.as_str()), Some("room1")));
+
+        let sidecar = |path: &std::path::Path| -> serde_json::Value {
+            serde_json::from_slice(&std::fs::read(path.with_extension("meta.json")).unwrap()).unwrap()
+        };
+        let stopped = sidecar(&transfer.stopped.file_path);
+        assert_eq!((stopped["room_id"].as_str(), stopped["next_room"].as_str()), (Some("room1"), Some("room2")));
+        assert_eq!(stopped["stop_reason"].as_str(), Some("transferred"));
+        let continued = sidecar(&transfer.file_path);
+        assert_eq!((continued["room_id"].as_str(), continued["previous_room"].as_str()), (Some("room2"), Some("room1")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_every_recording_gets_a_metadata_sidecar() {
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-sidecar-{}", std::process::id()));
+        let store = Arc::new(MockStore::new());
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), Some(store))
+            .with_rtp_fallback(true)
+            .with_upload_retries(0);
+        assert!(manager.get_recording_metadata("room1", "peer1").unwrap().is_none());
+
+        manager.set_peer("room1", "peer1", Some("Ada".to_string()), PeerRole::Student);
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+        let stopped = manager.stop_recording("room1", "peer1").await.unwrap();
+
+        let sidecar = manager.get_recording_metadata("room1", "peer1").unwrap().unwrap();
+        assert_eq!((sidecar.room_id.as_str(), sidecar.peer_name.as_deref(), sidecar.role), ("room1", Some("Ada"), Some(PeerRole::Student)));
+        assert_eq!((sidecar.video_codec.as_str(), sidecar.audio_codec.as_str()), ("VP8", "OPUS"));
+        assert_eq!(sidecar.size, std::fs::metadata(&stopped.file_path).unwrap().len());
+        assert_eq!(sidecar.sha256, manager.completed_recordings("room1").await[0].sha256);
+        assert!(sidecar.started_at.is_some_and(|started_at| started_at <= sidecar.stopped_at));
+        assert_eq!(sidecar.cid, None);
+
+        // The CID lands in the sidecar once the upload finishes
+        let uploaded = manager.next_upload().await.unwrap().result.unwrap();
+        let sidecar = manager.get_recording_metadata("room1", "peer1").unwrap().unwrap();
+        assert_eq!(sidecar.cid, Some(uploaded.cid));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_stopped_recording_carries_its_file_hash() {
+        use sha2::{Digest, Sha256};
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-sha256-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true);
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+        let stopped = manager.stop_recording("room1", "peer1").await.unwrap();
+
+        let expected = hex::encode(Sha256::digest(std::fs::read(&stopped.file_path).unwrap()));
+        assert_eq!(stopped.sha256.as_deref(), Some(expected.as_str()));
+        assert_eq!(expected.len(), 64);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    /// Recording into `output_dir` with the defaults, but without the disk space guard
+    pub(crate) fn recording_config(output_dir: &str, enabled: bool) -> RecordingConfig {
+        RecordingConfig {
+            enabled,
+            output_dir: output_dir.to_string(),
+            min_free_bytes: 0,
+            ..Default::default()
+        }
+    }
+
+    /// RTP packets GStreamer encodes and payloads from `source`, a launch line
+    /// ending in a payloader
+    pub(crate) fn encoded_rtp(source: &str) -> Vec<Packet> {
+        use gstreamer as gst;
+        use gstreamer::prelude::*;
+        use webrtc::util::Unmarshal;
+
+        let pipeline = gst::parse::launch(&format!("{} ! appsink name=sink sync=false", source))
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap();
+        let sink = pipeline
+            .by_name("sink")
+            .unwrap()
+            .downcast::<gstreamer_app::AppSink>()
+            .unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        let mut packets = Vec::new();
+        while let Ok(sample) = sink.pull_sample() {
+            let buffer = sample.buffer().unwrap().map_readable().unwrap();
+            let mut data = buffer.as_slice();
+            packets.push(Packet::unmarshal(&mut data).unwrap());
+        }
+        pipeline.set_state(gst::State::Null).unwrap();
+        packets
+    }
+
+    #[tokio::test]
+    async fn test_pushed_rtp_grows_recording_file() {
+        // Needs the recorder's GStreamer plugins plus the test sources and payloaders
+        let generators = ["videotestsrc", "audiotestsrc", "vp8enc", "opusenc", "rtpvp8pay", "rtpopuspay", "appsink"];
+        if RecordingPipeline::verify_environment().is_err()
+            || generators.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+        {
+            return;
+        }
+
+        let video = encoded_rtp(
+            "videotestsrc num-buffers=60 ! video/x-raw,width=320,height=240,framerate=30/1 \
+             ! vp8enc deadline=1 ! rtpvp8pay pt=96",
+        );
+        let audio = encoded_rtp("audiotestsrc num-buffers=100 ! audio/x-raw,rate=48000 ! opusenc ! rtpopuspay pt=111");
+        assert!(!video.is_empty() && !audio.is_empty());
+
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-growth-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+        let pipeline = manager.recordings.read().await.get(&("room1".to_string(), "peer1".to_string())).cloned().unwrap();
+
+        // Paced roughly like live media, so the pipeline timestamps them apart
+        let mut sizes = vec![pipeline.bytes_written()];
+        let rounds = video.len().max(audio.len());
+        for i in 0..rounds {
+            if let Some(packet) = video.get(i) {
+                manager.push_video_rtp("room1", "peer1", packet).await.unwrap();
+            }
+            if let Some(packet) = audio.get(i) {
+                manager.push_audio_rtp("room1", "peer1", packet).await.unwrap();
+            }
+            if i % 10 == 9 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                sizes.push(pipeline.bytes_written());
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        sizes.push(pipeline.bytes_written());
+        assert!(sizes.windows(2).any(|pair| pair[1] > pair[0]), "file never grew: {:?}", sizes);
+
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        assert!(result.file_path.starts_with(dir.join("room1")));
+        let name = result.file_path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("peer1_") && name.ends_with(".webm"), "{}", name);
+        assert!(std::fs::metadata(&result.file_path).unwrap().len() >= *sizes.last().unwrap());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_recordings_hold_the_tracks_that_sent_media() {
+        use gstreamer_pbutils::prelude::*;
+
+        let generators = ["videotestsrc", "audiotestsrc", "vp8enc", "opusenc", "rtpvp8pay", "rtpopuspay", "appsink"];
+        if RecordingPipeline::verify_environment().is_err()
+            || generators.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+        {
+            return;
+        }
+
+        let video = encoded_rtp(
+            "videotestsrc num-buffers=60 ! video/x-raw,width=320,height=240,framerate=30/1 \
+             ! vp8enc deadline=1 ! rtpvp8pay pt=96",
+        );
+        let audio = encoded_rtp("audiotestsrc num-buffers=100 ! audio/x-raw,rate=48000 ! opusenc ! rtpopuspay pt=111");
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-tracks-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+        let discoverer = gstreamer_pbutils::Discoverer::new(gstreamer::ClockTime::from_seconds(10)).unwrap();
+        let caps_name = |caps: Option<gstreamer::Caps>| {
+            caps.and_then(|caps| caps.structure(0).map(|structure| structure.name().to_string()))
+        };
+
+        // A student without a microphone, one without a camera, and one with both
+        for (peer_id, has_video, has_audio) in [("video_only", true, false), ("audio_only", false, true), ("both", true, true)] {
+            manager.start_recording("room1", peer_id, &RecordingCodecs::default()).await.unwrap();
+            for i in 0..video.len().max(audio.len()) {
+                if let Some(packet) = video.get(i).filter(|_| has_video) {
+                    manager.push_video_rtp("room1", peer_id, packet).await.unwrap();
+                }
+                if let Some(packet) = audio.get(i).filter(|_| has_audio) {
+                    manager.push_audio_rtp("room1", peer_id, packet).await.unwrap();
+                }
+                if i % 10 == 9 {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            }
+            let result = manager.stop_recording("room1", peer_id).await.unwrap();
+
+            // What a browser would be handed: a webm holding the VP8 and Opus as sent
+            let path = std::fs::canonicalize(&result.file_path).unwrap();
+            let uri = gstreamer::glib::filename_to_uri(&path, None).unwrap();
+            let info = discoverer.discover_uri(&uri).unwrap();
+            let container = info.stream_info().and_then(|stream| caps_name(stream.caps())).unwrap_or_default();
+            assert!(container.ends_with("/webm"), "{}: {}", peer_id, container);
+            let video_streams: Vec<_> = info.video_streams().iter().map(|stream| caps_name(stream.caps())).collect();
+            let audio_streams: Vec<_> = info.audio_streams().iter().map(|stream| caps_name(stream.caps())).collect();
+            assert_eq!(video_streams, if has_video { vec![Some("video/x-vp8".to_string())] } else { vec![] }, "{}", peer_id);
+            assert_eq!(audio_streams, if has_audio { vec![Some("audio/x-opus".to_string())] } else { vec![] }, "{}", peer_id);
+            assert!(info.duration().is_some_and(|duration| duration > gstreamer::ClockTime::ZERO), "{}", peer_id);
+        }
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pipeline_error_ends_the_recording() {
+        let generators = ["videotestsrc", "audiotestsrc", "vp8enc", "opusenc", "rtpvp8pay", "rtpopuspay", "appsink"];
+        if RecordingPipeline::verify_environment().is_err()
+            || generators.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+            || !std::path::Path::new("/dev/full").exists()
+        {
+            return;
+        }
+
+        // Enough media to get past the file sink's buffer
+        let video = encoded_rtp(
+            "videotestsrc num-buffers=60 pattern=snow ! video/x-raw,width=320,height=240,framerate=30/1 \
+             ! vp8enc deadline=1 target-bitrate=2000000 ! rtpvp8pay pt=96",
+        );
+        let audio = encoded_rtp("audiotestsrc num-buffers=60 ! audio/x-raw,rate=48000 ! opusenc ! rtpopuspay pt=111");
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-error-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+
+        // Every write fails, as on a full disk
+        let pipeline = RecordingPipeline::new("room1", "peer1", &manager.namespace_dir("room1"), &RecordingCodecs::default()).unwrap();
+        std::os::unix::fs::symlink("/dev/full", pipeline.part_path()).unwrap();
+        manager.watch_errors("room1", "peer1", &pipeline);
+        pipeline.start().await.unwrap();
+        let key = ("room1".to_string(), "peer1".to_string());
+        manager.recordings.write().await.insert(key, Arc::new(pipeline));
+
+        for (v, a) in video.iter().zip(&audio) {
+            manager.push_video_rtp("room1", "peer1", v).await.unwrap();
+            manager.push_audio_rtp("room1", "peer1", a).await.unwrap();
+        }
+
+        // Reported while the recording is still listed, instead of stalling until it is stopped
+        let error = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(RecordingState::Error(error)) = manager.get_recording_state("room1", "peer1").await {
+                    break error;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("the write error never reached the recording state");
+
+        let failure = tokio::time::timeout(Duration::from_secs(5), manager.next_failure()).await.unwrap().unwrap();
+        assert_eq!((failure.room_id.as_str(), failure.peer_id.as_str()), ("room1", "peer1"));
+        assert_eq!(failure.error, error);
+        assert!(!manager.is_recording("room1", "peer1").await);
+        assert!(manager.stop_recording("room1", "peer1").await.is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_final_name_only_exists_once_finalized() {
+        let generators = ["videotestsrc", "audiotestsrc", "vp8enc", "opusenc", "rtpvp8pay", "rtpopuspay", "appsink"];
+        if RecordingPipeline::verify_environment().is_err()
+            || generators.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+        {
+            return;
+        }
+
+        let video = encoded_rtp(
+            "videotestsrc num-buffers=60 ! video/x-raw,width=320,height=240,framerate=30/1 \
+             ! vp8enc deadline=1 ! rtpvp8pay pt=96",
+        );
+        let audio = encoded_rtp("audiotestsrc num-buffers=60 ! audio/x-raw,rate=48000 ! opusenc ! rtpopuspay pt=111");
+        let dir = std::env::temp_dir().join(format!("sfu-recorder-finalize-{}", std::process::id()));
+        let manager = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+
+        // Recordings killed without EOS after different amounts of media
+        for cutoff in [0, 15, 60] {
+            let peer_id = format!("killed{}", cutoff);
+            manager.start_recording("room1", &peer_id, &RecordingCodecs::default()).await.unwrap();
+            let key = ("room1".to_string(), peer_id.clone());
+            let pipeline = manager.recordings.read().await.get(&key).cloned().unwrap();
+            let (recording, part) = (pipeline.output_path().clone(), pipeline.part_path().clone());
+
+            for i in 0..cutoff {
+                manager.push_video_rtp("room1", &peer_id, &video[i % video.len()]).await.unwrap();
+                manager.push_audio_rtp("room1", &peer_id, &audio[i % audio.len()]).await.unwrap();
+                if i % 10 == 9 {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    assert!(!recording.exists(), "{} visible while recording", recording.display());
+                }
+            }
+            assert!(part.is_file());
+
+            pipeline.kill();
+            manager.recordings.write().await.remove(&key);
+            assert!(!recording.exists(), "{} visible after a crash", recording.display());
+        }
+
+        // After a restart every orphan is either repaired under its final name or left as .part
+        let restarted = RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None);
+        let orphans = restarted.orphaned_recordings();
+        assert_eq!(orphans.len(), 3);
+        let recovered = restarted.repair_orphans(orphans.clone()).await;
+        for part in &orphans {
+            let recording = finalize::final_path(part).unwrap();
+            assert_ne!(recording.exists(), part.exists(), "{}", part.display());
+            assert_eq!(recovered.contains(&recording), recording.exists());
+            assert!(!finalize::repair_path(&recording).exists());
+        }
+
+        // A recording stopped normally is renamed once EOS has been written
+        let started = std::time::Instant::now();
+        manager.start_recording("room1", "peer1", &RecordingCodecs::default()).await.unwrap();
+        for (v, a) in video.iter().zip(&audio) {
+            manager.push_video_rtp("room1", "peer1", v).await.unwrap();
+            manager.push_audio_rtp("room1", "peer1", a).await.unwrap();
+        }
+        let result = manager.stop_recording("room1", "peer1").await.unwrap();
+        assert!(result.file_path.is_file());
+        assert!(!finalize::part_path(&result.file_path).exists());
+        assert_eq!(result.file_size_bytes, std::fs::metadata(&result.file_path).unwrap().len());
+        assert!(result.file_size_bytes > 0);
+        assert!(result.duration_secs <= started.elapsed().as_secs());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
@@ -0,0 +1,257 @@
+//! Local retention of recordings. With `RECORDING_RETENTION_HOURS` set, a
+//! background task sweeps the output directory and deletes files that were
+//! last written before the retention window, other than those of recordings
+//! still in progress, orphans awaiting repair and recordings not uploaded
+//! yet. Uploaded copies on IPFS are not touched.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use super::finalize::{is_part, is_repair, list_recordings, RecordingFileState};
+use super::sidecar::{sidecar_path, RecordingSidecar};
+use super::tenant::room_dirs;
+use super::RecordingManager;
+use crate::config::RecordingConfig;
+use crate::sfu::TaskSupervisor;
+
+/// How often the output directory is swept
+const RETENTION_SWEEP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// What one sweep deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionSweep {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Deletes files in the room directories under `output_dir` last modified
+/// before `cutoff`, keeping every file of the recordings in `active`: the
+/// `.part` being written and the sidecars sharing its name. `.part` and
+/// `.repair` files are kept for startup repair to adopt, and with `uploads`
+/// set so is every file of a recording whose sidecar has no CID yet, as the
+/// local copy is then the only one. Room directories left empty are removed.
+pub fn sweep(output_dir: &Path, cutoff: SystemTime, active: &[PathBuf], uploads: bool) -> io::Result<RetentionSweep> {
+    let mut sweep = RetentionSweep::default();
+    for (_, room_dir) in room_dirs(output_dir)? {
+        let mut kept = active.to_vec();
+        if uploads {
+            kept.extend(awaiting_upload(&room_dir)?);
+        }
+        for entry in std::fs::read_dir(&room_dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let path = entry.path();
+            if !metadata.is_file() || metadata.modified()? >= cutoff {
+                continue;
+            }
+            if is_part(&path) || is_repair(&path) || kept.iter().any(|recording| belongs_to(&path, recording)) {
+                continue;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    sweep.files += 1;
+                    sweep.bytes += metadata.len();
+                }
+                Err(e) => tracing::warn!(file = %path.display(), error = %e, "Failed to delete expired recording file"),
+            }
+        }
+        // Fails while anything is left in it, which is the point
+        let _ = std::fs::remove_dir(&room_dir);
+    }
+    Ok(sweep)
+}
+
+/// Finalized recordings in `room_dir` whose sidecar has no CID yet: queued,
+/// uploading, or failed to upload
+fn awaiting_upload(room_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(list_recordings(room_dir)?
+        .into_iter()
+        .filter(|recording| recording.state == RecordingFileState::Finalized)
+        .map(|recording| room_dir.join(recording.file))
+        .filter(|recording| {
+            RecordingSidecar::read(&sidecar_path(recording)).is_ok_and(|sidecar| sidecar.cid.is_none())
+        })
+        .collect())
+}
+
+/// Whether `path` is `recording` or a file named after it, such as its
+/// `.part`, `.meta.json` or `.chapters.vtt`
+fn belongs_to(path: &Path, recording: &Path) -> bool {
+    let (Some(name), Some(stem)) = (path.file_name(), recording.file_stem()) else {
+        return false;
+    };
+    path.parent() == recording.parent()
+        && name
+            .to_string_lossy()
+            .strip_prefix(stem.to_string_lossy().as_ref())
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Sweeps `config.output_dir` every 15 minutes when a retention window is
+/// configured
+pub fn spawn(tasks: &TaskSupervisor, config: &RecordingConfig, recordings: Arc<RecordingManager>) {
+    let Some(retention) = config.retention else {
+        return;
+    };
+    let output_dir = PathBuf::from(&config.output_dir);
+    tracing::info!(
+        output_dir = %output_dir.display(),
+        retention_hours = retention.as_secs() / 3600,
+        "Deleting local recordings past their retention window"
+    );
+
+    tasks.spawn("recording_retention", move |cancel| async move {
+        let mut tick = tokio::time::interval(RETENTION_SWEEP_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {}
+                _ = cancel.cancelled() => break,
+            }
+            let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+                continue;
+            };
+            let active = recordings.active_output_paths().await;
+            let uploads = recordings.uploads_to_ipfs();
+            let dir = output_dir.clone();
+            match tokio::task::spawn_blocking(move || sweep(&dir, cutoff, &active, uploads)).await {
+                Ok(Ok(swept)) if swept.files > 0 => {
+                    tracing::info!(files = swept.files, freed_bytes = swept.bytes, "Deleted expired local recordings")
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::warn!(output_dir = %output_dir.display(), error = %e, "Failed to sweep recordings"),
+                Err(e) => tracing::error!(error = %e, "Recording retention sweep panicked"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::tenant::{create_tenant_dir, TENANT_MARKER};
+
+    fn temp_output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-retention-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes `len` bytes to `path`, last modified `age` ago
+    fn write_aged(path: &Path, len: usize, age: Duration) {
+        std::fs::write(path, vec![0u8; len]).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 3600);
+
+    #[test]
+    fn test_sweep_deletes_files_past_the_cutoff() {
+        let dir = temp_output_dir("expired");
+        let room = dir.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        write_aged(&room.join("peer_1_100.webm"), 1000, 3 * DAY);
+        write_aged(&room.join("peer_1_100.meta.json"), 24, 3 * DAY);
+        write_aged(&room.join("peer_2_200.webm"), 500, Duration::from_secs(60));
+
+        let swept = sweep(&dir, SystemTime::now() - DAY, &[], false).unwrap();
+        assert_eq!(swept, RetentionSweep { files: 2, bytes: 1024 });
+        let mut left: Vec<_> = std::fs::read_dir(&room).unwrap().map(|e| e.unwrap().file_name()).collect();
+        left.sort();
+        assert_eq!(left, vec!["peer_2_200.webm"]);
+
+        // Nothing left to expire
+        assert_eq!(sweep(&dir, SystemTime::now() - DAY, &[], false).unwrap(), RetentionSweep::default());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sweep_keeps_active_recordings() {
+        let dir = temp_output_dir("active");
+        let room = dir.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        // A stalled recording that hasn't written in days is still in progress
+        write_aged(&room.join("peer_1_100.webm.part"), 1000, 3 * DAY);
+        write_aged(&room.join("peer_1_1000.webm"), 10, 3 * DAY);
+
+        let active = [room.join("peer_1_100.webm")];
+        let swept = sweep(&dir, SystemTime::now() - DAY, &active, false).unwrap();
+        assert_eq!(swept, RetentionSweep { files: 1, bytes: 10 });
+        assert!(room.join("peer_1_100.webm.part").exists());
+        assert!(!room.join("peer_1_1000.webm").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sweep_covers_tenant_namespaces_and_removes_empty_rooms() {
+        let dir = temp_output_dir("tenants");
+        let namespace = create_tenant_dir(&dir, "acme").unwrap();
+        let room = namespace.join("room-2");
+        std::fs::create_dir_all(&room).unwrap();
+        write_aged(&room.join("peer_3_300.webm"), 100, 2 * DAY);
+        write_aged(&room.join("room_manifest.json"), 20, 2 * DAY);
+
+        let swept = sweep(&dir, SystemTime::now() - DAY, &[], false).unwrap();
+        assert_eq!(swept, RetentionSweep { files: 2, bytes: 120 });
+        assert!(!room.exists());
+        // The namespace itself stays
+        assert!(namespace.join(TENANT_MARKER).is_file());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sweep_keeps_orphans_for_repair() {
+        let dir = temp_output_dir("orphans");
+        let room = dir.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        // Left behind by a crash, with no recording writing to it
+        write_aged(&room.join("peer_1_100.webm.part"), 1000, 3 * DAY);
+        write_aged(&room.join("peer_2_200.webm.repair"), 100, 3 * DAY);
+
+        assert_eq!(sweep(&dir, SystemTime::now() - DAY, &[], false).unwrap(), RetentionSweep::default());
+        assert!(room.join("peer_1_100.webm.part").exists());
+        assert!(room.join("peer_2_200.webm.repair").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_sweep_keeps_recordings_not_uploaded_yet() {
+        let dir = temp_output_dir("uploads");
+        let room = dir.join("room-1");
+        std::fs::create_dir_all(&room).unwrap();
+        let sidecar = |file: &str, cid: Option<&str>| {
+            serde_json::json!({ "room_id": "room-1", "peer_id": "peer_1", "file": file, "cid": cid }).to_string()
+        };
+        write_aged(&room.join("peer_1_100.webm"), 1000, 3 * DAY);
+        std::fs::write(room.join("peer_1_100.meta.json"), sidecar("peer_1_100.webm", None)).unwrap();
+        write_aged(&room.join("peer_1_200.webm"), 500, 3 * DAY);
+        std::fs::write(room.join("peer_1_200.meta.json"), sidecar("peer_1_200.webm", Some("bafy123"))).unwrap();
+        for file in ["peer_1_100.meta.json", "peer_1_200.meta.json"] {
+            let file = std::fs::File::options().write(true).open(room.join(file)).unwrap();
+            file.set_modified(SystemTime::now() - 3 * DAY).unwrap();
+        }
+
+        let swept = sweep(&dir, SystemTime::now() - DAY, &[], true).unwrap();
+        assert_eq!(swept.files, 2);
+        assert!(room.join("peer_1_100.webm").exists());
+        assert!(room.join("peer_1_100.meta.json").exists());
+        assert!(!room.join("peer_1_200.webm").exists());
+
+        // Kept only while uploads are configured; otherwise the local copy is all there is to expire
+        assert_eq!(sweep(&dir, SystemTime::now() - DAY, &[], false).unwrap().files, 2);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_belongs_to_matches_whole_names() {
+        let recording = Path::new("/recordings/room-1/peer_1_100.webm");
+        assert!(belongs_to(Path::new("/recordings/room-1/peer_1_100.webm"), recording));
+        assert!(belongs_to(Path::new("/recordings/room-1/peer_1_100.webm.part"), recording));
+        assert!(belongs_to(Path::new("/recordings/room-1/peer_1_100.chapters.vtt"), recording));
+        assert!(!belongs_to(Path::new("/recordings/room-1/peer_1_1000.webm"), recording));
+        assert!(!belongs_to(Path::new("/recordings/room-2/peer_1_100.webm"), recording));
+    }
+}
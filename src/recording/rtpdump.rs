@@ -0,0 +1,335 @@
+//! Raw RTP dumps, written instead of a webm when the GStreamer pipeline
+//! cannot be built or started, so the media can be reconstructed offline.
+//!
+//! A dump is a magic line, a length-prefixed JSON `DumpHeader`, then one
+//! record per packet: a record type byte, the offset from the start of the
+//! recording in milliseconds (u32), the payload length (u16) and the payload.
+//! Integers are big-endian, as in rtpdump. A codec record carries the JSON
+//! `DumpCodec` a track switched to. A dump cut short by a crash reads up to
+//! its last complete record.
+//!
+//! This file has no dependencies on the rest of the crate; `sfu-cli` includes
+//! it to read dumps back.
+
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// First line of every dump
+pub const DUMP_MAGIC: &[u8] = b"#!sfu-rtpdump 1.0\n";
+
+/// Extension of finalized dumps
+pub const DUMP_EXTENSION: &str = "rtpdump";
+
+/// Larger headers are refused as corrupt
+const MAX_HEADER_BYTES: u32 = 64 * 1024;
+
+const RECORD_VIDEO_RTP: u8 = 0;
+const RECORD_AUDIO_RTP: u8 = 1;
+const RECORD_VIDEO_CODEC: u8 = 2;
+const RECORD_AUDIO_CODEC: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DumpTrack {
+    Video,
+    Audio,
+}
+
+/// RTP parameters of a dumped track
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpCodec {
+    pub encoding_name: String,
+    pub payload_type: u8,
+    pub clock_rate: u32,
+}
+
+/// Describes the recording a dump was written for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpHeader {
+    pub room_id: String,
+    pub peer_id: String,
+    /// Unix time in milliseconds that record offsets count from
+    pub started_at_ms: u64,
+    /// Codecs the tracks were expected with; codec records override them
+    pub video: DumpCodec,
+    pub audio: DumpCodec,
+}
+
+impl DumpHeader {
+    pub fn codec(&self, track: DumpTrack) -> &DumpCodec {
+        match track {
+            DumpTrack::Video => &self.video,
+            DumpTrack::Audio => &self.audio,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DumpRecord {
+    /// A marshalled RTP packet as it arrived from the publisher
+    Rtp { track: DumpTrack, offset: Duration, packet: Vec<u8> },
+    /// The track was negotiated with a different codec from here on
+    Codec { track: DumpTrack, offset: Duration, codec: DumpCodec },
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub struct DumpWriter<W: Write> {
+    out: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> DumpWriter<W> {
+    /// Writes the magic line and `header` to `out`
+    pub fn new(mut out: W, header: &DumpHeader) -> io::Result<Self> {
+        let json = serde_json::to_vec(header)?;
+        out.write_all(DUMP_MAGIC)?;
+        out.write_all(&(json.len() as u32).to_be_bytes())?;
+        out.write_all(&json)?;
+        Ok(Self {
+            out,
+            bytes_written: (DUMP_MAGIC.len() + 4 + json.len()) as u64,
+        })
+    }
+
+    pub fn write_rtp(&mut self, track: DumpTrack, offset: Duration, packet: &[u8]) -> io::Result<()> {
+        let kind = match track {
+            DumpTrack::Video => RECORD_VIDEO_RTP,
+            DumpTrack::Audio => RECORD_AUDIO_RTP,
+        };
+        self.write_record(kind, offset, packet)
+    }
+
+    pub fn write_codec(&mut self, track: DumpTrack, offset: Duration, codec: &DumpCodec) -> io::Result<()> {
+        let kind = match track {
+            DumpTrack::Video => RECORD_VIDEO_CODEC,
+            DumpTrack::Audio => RECORD_AUDIO_CODEC,
+        };
+        let json = serde_json::to_vec(codec)?;
+        self.write_record(kind, offset, &json)
+    }
+
+    fn write_record(&mut self, kind: u8, offset: Duration, payload: &[u8]) -> io::Result<()> {
+        let len = u16::try_from(payload.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} byte record does not fit a dump", payload.len()))
+        })?;
+        let offset_ms = u32::try_from(offset.as_millis()).unwrap_or(u32::MAX);
+
+        let mut prefix = [0u8; 7];
+        prefix[0] = kind;
+        prefix[1..5].copy_from_slice(&offset_ms.to_be_bytes());
+        prefix[5..7].copy_from_slice(&len.to_be_bytes());
+        self.out.write_all(&prefix)?;
+        self.out.write_all(payload)?;
+        self.bytes_written += (prefix.len() + payload.len()) as u64;
+        Ok(())
+    }
+
+    /// Bytes handed to the output so far, header included
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+pub struct DumpReader<R: Read> {
+    input: R,
+    header: DumpHeader,
+    truncated: bool,
+}
+
+impl<R: Read> DumpReader<R> {
+    /// Checks the magic line and reads the header
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = vec![0u8; DUMP_MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if magic != DUMP_MAGIC {
+            return Err(invalid("Not an sfu-rtpdump file"));
+        }
+
+        let mut len = [0u8; 4];
+        input.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if len > MAX_HEADER_BYTES {
+            return Err(invalid(format!("Dump header of {} bytes is too large", len)));
+        }
+        let mut json = vec![0u8; len as usize];
+        input.read_exact(&mut json)?;
+        let header = serde_json::from_slice(&json).map_err(|e| invalid(format!("Invalid dump header: {}", e)))?;
+
+        Ok(Self { input, header, truncated: false })
+    }
+
+    pub fn header(&self) -> &DumpHeader {
+        &self.header
+    }
+
+    /// Whether the dump ended in the middle of a record
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The next record, or `None` at the end of the dump
+    pub fn next_record(&mut self) -> io::Result<Option<DumpRecord>> {
+        let mut prefix = [0u8; 7];
+        match read_full(&mut self.input, &mut prefix)? {
+            0 => return Ok(None),
+            n if n < prefix.len() => {
+                self.truncated = true;
+                return Ok(None);
+            }
+            _ => {}
+        }
+        let kind = prefix[0];
+        let offset = Duration::from_millis(u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as u64);
+        let len = u16::from_be_bytes([prefix[5], prefix[6]]) as usize;
+
+        let mut payload = vec![0u8; len];
+        if read_full(&mut self.input, &mut payload)? < len {
+            self.truncated = true;
+            return Ok(None);
+        }
+
+        let record = match kind {
+            RECORD_VIDEO_RTP | RECORD_AUDIO_RTP => DumpRecord::Rtp {
+                track: if kind == RECORD_VIDEO_RTP { DumpTrack::Video } else { DumpTrack::Audio },
+                offset,
+                packet: payload,
+            },
+            RECORD_VIDEO_CODEC | RECORD_AUDIO_CODEC => DumpRecord::Codec {
+                track: if kind == RECORD_VIDEO_CODEC { DumpTrack::Video } else { DumpTrack::Audio },
+                offset,
+                codec: serde_json::from_slice(&payload).map_err(|e| invalid(format!("Invalid codec record: {}", e)))?,
+            },
+            other => return Err(invalid(format!("Unknown dump record type {}", other))),
+        };
+        Ok(Some(record))
+    }
+}
+
+/// Reads until `buf` is full or the input ends, returning how much was read
+fn read_full(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn header() -> DumpHeader {
+        DumpHeader {
+            room_id: "room-1".to_string(),
+            peer_id: "student1".to_string(),
+            started_at_ms: 1_700_000_000_000,
+            video: DumpCodec { encoding_name: "VP8".to_string(), payload_type: 96, clock_rate: 90000 },
+            audio: DumpCodec { encoding_name: "OPUS".to_string(), payload_type: 111, clock_rate: 48000 },
+        }
+    }
+
+    /// A minimal RTP packet: version 2, the given payload type, sequence number and payload
+    fn synthetic_packet(payload_type: u8, sequence: u16, payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0x80, payload_type & 0x7f];
+        packet.extend_from_slice(&sequence.to_be_bytes());
+        packet.extend_from_slice(&(sequence as u32 * 3000).to_be_bytes());
+        packet.extend_from_slice(&0x1234_5678u32.to_be_bytes());
+        packet.extend((0..payload_len).map(|i| (i % 251) as u8));
+        packet
+    }
+
+    fn read_all(bytes: &[u8]) -> (DumpHeader, Vec<DumpRecord>, bool) {
+        let mut reader = DumpReader::new(Cursor::new(bytes)).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record);
+        }
+        (reader.header().clone(), records, reader.truncated())
+    }
+
+    #[test]
+    fn test_round_trip_preserves_packets_and_codecs() {
+        let mut expected = Vec::new();
+        for i in 0..50u16 {
+            expected.push(DumpRecord::Rtp {
+                track: DumpTrack::Video,
+                offset: Duration::from_millis(i as u64 * 33),
+                packet: synthetic_packet(96, i, 1000 + i as usize),
+            });
+            if i % 2 == 0 {
+                expected.push(DumpRecord::Rtp {
+                    track: DumpTrack::Audio,
+                    offset: Duration::from_millis(i as u64 * 33 + 5),
+                    packet: synthetic_packet(111, i / 2, 80),
+                });
+            }
+            if i == 20 {
+                expected.push(DumpRecord::Codec {
+                    track: DumpTrack::Video,
+                    offset: Duration::from_millis(660),
+                    codec: DumpCodec { encoding_name: "VP8".to_string(), payload_type: 120, clock_rate: 90000 },
+                });
+            }
+        }
+
+        let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+        for record in &expected {
+            match record {
+                DumpRecord::Rtp { track, offset, packet } => writer.write_rtp(*track, *offset, packet).unwrap(),
+                DumpRecord::Codec { track, offset, codec } => writer.write_codec(*track, *offset, codec).unwrap(),
+            }
+        }
+        let written = writer.bytes_written();
+        let bytes = writer.into_inner();
+        assert_eq!(written, bytes.len() as u64);
+        assert!(bytes.starts_with(DUMP_MAGIC));
+
+        let (read_header, records, truncated) = read_all(&bytes);
+        assert_eq!(read_header, header());
+        assert_eq!(records, expected);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_truncated_dump_reads_complete_records() {
+        let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+        for i in 0..3u16 {
+            writer.write_rtp(DumpTrack::Audio, Duration::from_millis(i as u64 * 20), &synthetic_packet(111, i, 60)).unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        // Cut inside the last payload, then inside the last record's prefix
+        for cut in [bytes.len() - 10, bytes.len() - 60 - 12 - 4] {
+            let (_, records, truncated) = read_all(&bytes[..cut]);
+            assert_eq!(records.len(), 2);
+            assert!(truncated);
+        }
+    }
+
+    #[test]
+    fn test_rejects_foreign_files_and_oversized_packets() {
+        assert!(DumpReader::new(Cursor::new(b"#!rtpplay1.0 127.0.0.1/5000\n".to_vec())).is_err());
+
+        let mut writer = DumpWriter::new(Vec::new(), &header()).unwrap();
+        let err = writer.write_rtp(DumpTrack::Video, Duration::ZERO, &vec![0u8; 70_000]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,201 @@
+//! `{peer_id}_{timestamp}.meta.json`, written next to every finalized
+//! recording so a reviewer holding only the file knows whose it is, when it
+//! ran and how to check it. The CID is filled in once the upload finishes.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::finalize::write_atomic;
+use super::keyframes::KeyframeStats;
+use super::metadata::SessionMetadata;
+use crate::sfu::PeerRole;
+
+/// Sidecar of a recording, e.g. `peer_123.webm` -> `peer_123.meta.json`
+pub fn sidecar_path(recording: &Path) -> PathBuf {
+    recording.with_extension("meta.json")
+}
+
+/// Everything known about a finalized recording. Sidecars written before a
+/// field existed read it as its default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingSidecar {
+    pub room_id: String,
+    pub peer_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<PeerRole>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub file: String,
+    /// Unix time in milliseconds when the pipeline started
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    /// Unix time in milliseconds when the recording was finalized
+    #[serde(default)]
+    pub stopped_at: u64,
+    #[serde(default)]
+    pub duration_secs: u64,
+    /// Size of the finalized file
+    #[serde(default)]
+    pub size: u64,
+    /// RTP encoding names, e.g. `VP8` and `OPUS`
+    #[serde(default)]
+    pub video_codec: String,
+    #[serde(default)]
+    pub audio_codec: String,
+    /// SHA-256 of the finalized file, hex encoded
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Set once the recording is uploaded to IPFS
+    #[serde(default)]
+    pub cid: Option<String>,
+    #[serde(default)]
+    pub metadata: SessionMetadata,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapters: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_room: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_room: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    /// Keyframes seen in the video and the intervals between them
+    #[serde(default)]
+    pub keyframes: KeyframeStats,
+}
+
+impl RecordingSidecar {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        serde_json::from_slice(&bytes).map_err(io::Error::from)
+    }
+
+    /// Writes the sidecar of `recording`, through a temporary file
+    pub fn write(&self, recording: &Path) -> io::Result<()> {
+        let bytes = serde_json::to_vec_pretty(self).map_err(io::Error::from)?;
+        write_atomic(&sidecar_path(recording), &bytes)
+    }
+}
+
+/// Records the CID of an uploaded recording in its sidecar
+pub fn set_cid(recording: &Path, cid: &str) -> io::Result<()> {
+    let mut sidecar = RecordingSidecar::read(&sidecar_path(recording))?;
+    sidecar.cid = Some(cid.to_string());
+    sidecar.write(recording)
+}
+
+/// Sidecar of the peer's most recently stopped recording in `room_dir`
+pub fn latest(room_dir: &Path, peer_id: &str) -> io::Result<Option<RecordingSidecar>> {
+    let prefix = format!("{}_", peer_id);
+    let mut latest: Option<RecordingSidecar> = None;
+    for entry in std::fs::read_dir(room_dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        // `{peer_id}_{timestamp}.meta.json`, and not another peer whose ID starts with this one's
+        let is_peers = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".meta.json"))
+            .is_some_and(|timestamp| !timestamp.is_empty() && timestamp.chars().all(|c| c.is_ascii_digit()));
+        if !is_peers {
+            continue;
+        }
+        let sidecar = RecordingSidecar::read(&room_dir.join(name.as_ref()))?;
+        if !latest.as_ref().is_some_and(|newest| newest.stopped_at >= sidecar.stopped_at) {
+            latest = Some(sidecar);
+        }
+    }
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sidecar(peer_id: &str, file: &str, stopped_at: u64) -> RecordingSidecar {
+        RecordingSidecar {
+            room_id: "room-1".to_string(),
+            peer_id: peer_id.to_string(),
+            peer_name: Some("Ada".to_string()),
+            role: Some(PeerRole::Student),
+            tenant: None,
+            file: file.to_string(),
+            started_at: Some(stopped_at - 60_000),
+            stopped_at,
+            duration_secs: 60,
+            size: 4096,
+            video_codec: "VP8".to_string(),
+            audio_codec: "OPUS".to_string(),
+            sha256: Some("ab".repeat(32)),
+            cid: None,
+            metadata: SessionMetadata::default(),
+            chapters: None,
+            previous: None,
+            previous_room: None,
+            next: None,
+            next_room: None,
+            stop_reason: None,
+            keyframes: KeyframeStats::default(),
+        }
+    }
+
+    #[test]
+    fn test_sidecar_round_trips() {
+        let mut full = sidecar("peer_1", "peer_1_100.webm", 1_700_000_060_000);
+        full.tenant = Some("acme".to_string());
+        full.cid = Some("bafy123".to_string());
+        full.metadata = SessionMetadata::sanitized(Some("Midterm".to_string()), Some("CS101".to_string()), None);
+        full.next = Some("peer_1_200.mkv".to_string());
+        full.stop_reason = Some("restarted".to_string());
+        let start = std::time::Instant::now();
+        for offset_ms in [0, 2000, 6000] {
+            full.keyframes.record_keyframe(start + std::time::Duration::from_millis(offset_ms));
+        }
+        let json = serde_json::to_string(&full).unwrap();
+        assert_eq!(serde_json::from_str::<RecordingSidecar>(&json).unwrap(), full);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["role"], "student");
+        assert!(value.get("previous").is_none());
+        assert_eq!(value["keyframes"]["keyframe_count"], 3);
+        assert_eq!(value["keyframes"]["min_interval_ms"], 2000);
+        assert_eq!(value["keyframes"]["avg_interval_ms"], 3000);
+        assert_eq!(value["keyframes"]["max_interval_ms"], 4000);
+    }
+
+    #[test]
+    fn test_older_sidecars_still_parse() {
+        let json = r#"{"room_id":"room-1","peer_id":"peer_1","file":"peer_1_100.webm","metadata":{"exam_name":"Midterm"}}"#;
+        let sidecar: RecordingSidecar = serde_json::from_str(json).unwrap();
+        assert_eq!(sidecar.metadata.exam_name.as_deref(), Some("Midterm"));
+        assert_eq!((sidecar.stopped_at, sidecar.cid, sidecar.role), (0, None, None));
+        assert_eq!(sidecar.keyframes, KeyframeStats::default());
+    }
+
+    #[test]
+    fn test_latest_sidecar_and_cid_update() {
+        let dir = std::env::temp_dir().join(format!("sfu-sidecar-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (peer_id, file, stopped_at) in [
+            ("peer_1", "peer_1_100.webm", 2_000),
+            ("peer_1", "peer_1_200.webm", 3_000),
+            ("peer_1_2", "peer_1_2_300.webm", 9_000),
+        ] {
+            sidecar(peer_id, file, stopped_at).write(&dir.join(file)).unwrap();
+        }
+
+        let newest = latest(&dir, "peer_1").unwrap().unwrap();
+        assert_eq!(newest.file, "peer_1_200.webm");
+        assert!(latest(&dir, "peer_3").unwrap().is_none());
+
+        set_cid(&dir.join("peer_1_200.webm"), "bafy456").unwrap();
+        assert_eq!(latest(&dir, "peer_1").unwrap().unwrap().cid.as_deref(), Some("bafy456"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
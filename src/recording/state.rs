@@ -1,9 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::SfuError;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RecordingState {
     Idle,
     Recording,
+    /// Started, but dropping media until resumed; the file goes on where it left off
+    Paused,
     Stopping,
     Stopped,
     Error(String),
@@ -15,6 +19,44 @@ impl Default for RecordingState {
     }
 }
 
+/// What can be asked of a recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingTransition {
+    Start,
+    Pause,
+    Resume,
+    Stop,
+}
+
+impl RecordingTransition {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+impl RecordingState {
+    /// The state `transition` leads to, or why a recording in this state can't make it
+    pub fn apply(&self, transition: RecordingTransition) -> Result<Self, SfuError> {
+        use RecordingTransition::*;
+        match (self, transition) {
+            (Self::Idle, Start) => Ok(Self::Recording),
+            (Self::Recording, Pause) => Ok(Self::Paused),
+            (Self::Paused, Resume) => Ok(Self::Recording),
+            (Self::Recording | Self::Paused, Stop) => Ok(Self::Stopping),
+            (state, transition) => Err(SfuError::InvalidRecordingState(format!(
+                "cannot {} a recording that is {:?}",
+                transition.as_str(),
+                state
+            ))),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,4 +112,58 @@ mod tests {
         let recording_state: RecordingState = serde_json::from_str(recording_json).unwrap();
         assert_eq!(recording_state, RecordingState::Recording);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_legal_transitions() {
+        use RecordingTransition::*;
+        let legal = [
+            (RecordingState::Idle, Start, RecordingState::Recording),
+            (RecordingState::Recording, Pause, RecordingState::Paused),
+            (RecordingState::Paused, Resume, RecordingState::Recording),
+            (RecordingState::Recording, Stop, RecordingState::Stopping),
+            (RecordingState::Paused, Stop, RecordingState::Stopping),
+        ];
+        for (from, transition, to) in legal {
+            assert_eq!(from.apply(transition).unwrap(), to, "{:?} {:?}", from, transition);
+        }
+    }
+
+    #[test]
+    fn test_illegal_transitions() {
+        use RecordingTransition::*;
+        let states = [
+            RecordingState::Idle,
+            RecordingState::Recording,
+            RecordingState::Paused,
+            RecordingState::Stopping,
+            RecordingState::Stopped,
+            RecordingState::Error("disk full".to_string()),
+        ];
+        let legal = |state: &RecordingState, transition| {
+            matches!(
+                (state, transition),
+                (RecordingState::Idle, Start)
+                    | (RecordingState::Recording, Pause)
+                    | (RecordingState::Paused, Resume)
+                    | (RecordingState::Recording | RecordingState::Paused, Stop)
+            )
+        };
+        let mut refused = 0;
+        for state in &states {
+            for transition in [Start, Pause, Resume, Stop] {
+                if legal(state, transition) {
+                    continue;
+                }
+                let error = state.apply(transition).unwrap_err();
+                assert!(matches!(error, SfuError::InvalidRecordingState(_)), "{:?} {:?}", state, transition);
+                refused += 1;
+            }
+        }
+        // Six states times four transitions, five of them legal
+        assert_eq!(refused, 19);
+
+        // The two the signaling messages run into
+        assert!(RecordingState::Idle.apply(Pause).unwrap_err().to_string().contains("cannot pause a recording that is Idle"));
+        assert!(RecordingState::Recording.apply(Resume).unwrap_err().to_string().contains("cannot resume a recording that is Recording"));
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use super::state::RecordingState;
+
+/// Which media a recording captures
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingContent {
+    AudioVideo,
+    Video,
+    Audio,
+}
+
+impl RecordingContent {
+    pub fn from_tracks(has_video: bool, has_audio: bool) -> Option<Self> {
+        match (has_video, has_audio) {
+            (true, true) => Some(Self::AudioVideo),
+            (true, false) => Some(Self::Video),
+            (false, true) => Some(Self::Audio),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Live view of an in-progress recording, as reported by `RecordingStatus`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingDetail {
+    pub peer_id: String,
+    pub state: RecordingState,
+    /// Unix time in milliseconds when the pipeline started
+    pub started_at: Option<u64>,
+    /// `started_at` in the room's timezone, when the room has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at_local: Option<String>,
+    pub elapsed_secs: u64,
+    pub paused: bool,
+    /// Size of the output file when the status was taken
+    pub bytes_written: u64,
+    pub segments: u32,
+    pub content: RecordingContent,
+}
+
+/// Summary of a recording that already stopped in this room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompletedRecording {
+    pub peer_id: String,
+    pub file: String,
+    pub started_at: Option<u64>,
+    /// Unix time in milliseconds when the recording was finalized
+    pub stopped_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at_local: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped_at_local: Option<String>,
+    pub duration_secs: u64,
+    pub bytes_written: u64,
+    pub cid: Option<String>,
+    /// SHA-256 of the finalized file, hex encoded
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Seconds of media gaps on live tracks, as listed in the `.gaps.jsonl` sidecar
+    #[serde(default)]
+    pub gap_secs: f64,
+    /// File this recording continues, when it was opened by a restart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+    /// File the recording continued in, when it was stopped by a restart
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    /// Room `previous` was recorded in, when the student was transferred here from it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_room: Option<String>,
+    /// Room `next` is recorded in, when the student was transferred there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_room: Option<String>,
+    /// Why the recording stopped, when not at the proctor's or room's request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detail_serialization_shape() {
+        let detail = RecordingDetail {
+            peer_id: "student_1".to_string(),
+            state: RecordingState::Recording,
+            started_at: Some(1_700_000_000_000),
+            started_at_local: None,
+            elapsed_secs: 42,
+            paused: false,
+            bytes_written: 1024,
+            segments: 1,
+            content: RecordingContent::AudioVideo,
+        };
+
+        let json = serde_json::to_value(&detail).unwrap();
+        assert_eq!(json["peer_id"], "student_1");
+        assert_eq!(json["state"], "Recording");
+        assert_eq!(json["started_at"], 1_700_000_000_000u64);
+        assert_eq!(json["elapsed_secs"], 42);
+        assert_eq!(json["paused"], false);
+        assert_eq!(json["bytes_written"], 1024);
+        assert_eq!(json["segments"], 1);
+        assert_eq!(json["content"], "audio_video");
+        assert!(json.get("started_at_local").is_none());
+
+        let parsed: RecordingDetail = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, detail);
+    }
+
+    #[test]
+    fn test_completed_round_trip() {
+        let completed = CompletedRecording {
+            peer_id: "student_1".to_string(),
+            file: "student_1_1700000000.webm".to_string(),
+            started_at: Some(1_700_000_000_000),
+            stopped_at: 1_700_000_060_000,
+            started_at_local: None,
+            stopped_at_local: Some("2023-11-14 23:14:20 CET (UTC+01:00)".to_string()),
+            duration_secs: 60,
+            bytes_written: 4096,
+            cid: None,
+            sha256: None,
+            gap_secs: 18.5,
+            previous: None,
+            next: Some("student_1_1700000060.webm".to_string()),
+            previous_room: None,
+            next_room: None,
+            stop_reason: Some("restarted".to_string()),
+        };
+
+        let json = serde_json::to_string(&completed).unwrap();
+        assert!(json.contains("\"cid\":null"));
+        assert!(json.contains("stopped_at_local"));
+        assert!(!json.contains("previous"));
+        let parsed: CompletedRecording = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, completed);
+    }
+
+    #[test]
+    fn test_content_from_tracks() {
+        assert_eq!(RecordingContent::from_tracks(true, true), Some(RecordingContent::AudioVideo));
+        assert_eq!(RecordingContent::from_tracks(false, true), Some(RecordingContent::Audio));
+        assert_eq!(RecordingContent::from_tracks(false, false), None);
+    }
+}
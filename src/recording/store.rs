@@ -0,0 +1,296 @@
+//! Where finished recordings, view event streams and room manifests are
+//! published: IPFS or an S3-compatible bucket, chosen by `RECORDING_STORAGE`.
+//! The trait is what `RecordingManager` depends on, so tests can stand in
+//! for either.
+
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::config::env;
+use crate::error::{Result, SfuError};
+use crate::ipfs::{IpfsClient, IpfsConfig, IpfsUploadResult};
+use crate::s3::{S3Config, S3Store, StoredObject};
+
+/// Backend selected by `RECORDING_STORAGE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StorageKind {
+    /// IPFS, configured by the `IPFS_*` settings and still off unless `IPFS_ENABLED` is set
+    #[default]
+    Ipfs,
+    /// S3 or MinIO, configured by the `S3_*` settings
+    S3,
+    /// Recordings are only kept locally
+    None,
+}
+
+impl StorageKind {
+    /// Parses `RECORDING_STORAGE`: `ipfs`, `s3` or `none`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ipfs" => Some(Self::Ipfs),
+            "s3" => Some(Self::S3),
+            "none" => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        env::get_string("RECORDING_STORAGE")
+            .and_then(|v| {
+                let parsed = Self::parse(&v);
+                if parsed.is_none() {
+                    tracing::warn!(value = %v, "Invalid RECORDING_STORAGE, expected ipfs, s3 or none");
+                }
+                parsed
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Where recordings are uploaded: the backend `RECORDING_STORAGE` selects
+/// and its settings. Only the selected backend's settings are read.
+#[derive(Debug, Clone, Default)]
+pub struct StorageConfig {
+    pub kind: StorageKind,
+    /// `None` unless IPFS is selected, enabled and configured
+    pub ipfs: Option<IpfsConfig>,
+    /// `None` unless S3 is selected and configured
+    pub s3: Option<S3Config>,
+}
+
+impl StorageConfig {
+    pub fn from_env() -> Self {
+        let kind = StorageKind::from_env();
+        Self {
+            kind,
+            ipfs: if kind == StorageKind::Ipfs { IpfsConfig::from_env() } else { None },
+            s3: if kind == StorageKind::S3 { S3Config::from_env() } else { None },
+        }
+    }
+}
+
+/// The store `config` selects; `None` when uploads are off or the backend
+/// is not configured
+pub fn from_config(config: &StorageConfig) -> Option<Arc<dyn RecordingStore>> {
+    match config.kind {
+        StorageKind::Ipfs => match IpfsClient::new(config.ipfs.clone()?) {
+            Ok(client) => {
+                tracing::info!("IPFS client initialized");
+                Some(Arc::new(client) as Arc<dyn RecordingStore>)
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to initialize IPFS client");
+                None
+            }
+        },
+        StorageKind::S3 => match S3Store::new(config.s3.clone()?) {
+            Ok(store) => {
+                tracing::info!(location = %store.location(), "S3 recording storage initialized");
+                Some(Arc::new(store) as Arc<dyn RecordingStore>)
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to initialize S3 recording storage");
+                None
+            }
+        },
+        StorageKind::None => {
+            tracing::info!("Recording uploads disabled by RECORDING_STORAGE=none");
+            None
+        }
+    }
+}
+
+#[async_trait]
+pub trait RecordingStore: Send + Sync {
+    /// Uploads a file written for `peer_id` in `room_id`, tagged with the room's tenant if it has one
+    async fn upload_file(&self, file_path: &Path, room_id: &str, peer_id: &str, tenant: Option<&str>) -> Result<IpfsUploadResult>;
+
+    async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<IpfsUploadResult>;
+
+    /// Drops `cid` from the store's pin set; false when it was not pinned
+    async fn unpin(&self, cid: &str) -> Result<bool>;
+
+    /// Whether the store is reachable; `Err` only when the check itself could not run
+    async fn health_check(&self) -> Result<bool>;
+
+    /// Where uploads go, for logs and health reports
+    fn location(&self) -> &str;
+
+    /// Live media forwarded by the SFU, for stores that back off under load
+    fn record_media_bytes(&self, _bytes: u64) {}
+}
+
+#[async_trait]
+impl RecordingStore for IpfsClient {
+    async fn upload_file(&self, file_path: &Path, room_id: &str, peer_id: &str, tenant: Option<&str>) -> Result<IpfsUploadResult> {
+        IpfsClient::upload_file(self, file_path, room_id, peer_id, tenant).await
+    }
+
+    async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<IpfsUploadResult> {
+        IpfsClient::upload_bytes(self, data, file_name).await
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<bool> {
+        IpfsClient::unpin_file(self, cid).await
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        IpfsClient::health_check(self).await
+    }
+
+    fn location(&self) -> &str {
+        self.api_url()
+    }
+
+    fn record_media_bytes(&self, bytes: u64) {
+        self.throttle().record_media_bytes(bytes);
+    }
+}
+
+/// An S3 upload as the rest of the server sees one: the ETag stands in for
+/// the CID and the object URL for the gateway URL. Objects in a bucket are
+/// kept until deleted, so they count as pinned.
+impl From<StoredObject> for IpfsUploadResult {
+    fn from(object: StoredObject) -> Self {
+        IpfsUploadResult {
+            cid: object.etag,
+            gateway_url: object.url,
+            size: object.size,
+            pinned: true,
+        }
+    }
+}
+
+#[async_trait]
+impl RecordingStore for S3Store {
+    async fn upload_file(&self, file_path: &Path, room_id: &str, peer_id: &str, tenant: Option<&str>) -> Result<IpfsUploadResult> {
+        S3Store::upload_file(self, file_path, room_id, peer_id, tenant).await.map(IpfsUploadResult::from)
+    }
+
+    async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<IpfsUploadResult> {
+        S3Store::upload_bytes(self, data, file_name).await.map(IpfsUploadResult::from)
+    }
+
+    /// An ETag doesn't name the object it belongs to, so objects are removed from the bucket itself
+    async fn unpin(&self, cid: &str) -> Result<bool> {
+        Err(SfuError::S3RequestFailed(format!("Cannot delete {} by ETag, remove it from the bucket", cid)))
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        S3Store::health_check(self).await
+    }
+
+    fn location(&self) -> &str {
+        S3Store::location(self)
+    }
+}
+
+#[cfg(test)]
+pub use mock::{MockStore, StoreCall};
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// An upload as `MockStore` saw it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum StoreCall {
+        File { room_id: String, peer_id: String, file_name: String },
+        Bytes { file_name: String },
+        Unpin { cid: String },
+    }
+
+    /// In-memory store that records every upload in order. Uploads succeed
+    /// with CIDs `bafymock1`, `bafymock2`, ... unless the file is missing or
+    /// a failure was requested with `fail_next`.
+    #[derive(Default)]
+    pub struct MockStore {
+        calls: Mutex<Vec<StoreCall>>,
+        failures: Mutex<u32>,
+    }
+
+    impl MockStore {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Makes the next `count` uploads fail; they are still recorded
+        pub fn fail_next(&self, count: u32) {
+            *self.failures.lock().unwrap() = count;
+        }
+
+        pub fn calls(&self) -> Vec<StoreCall> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn record(&self, call: StoreCall, size: u64) -> Result<IpfsUploadResult> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(call);
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(SfuError::IpfsUploadFailed("Injected upload failure".to_string()));
+            }
+            let cid = format!("bafymock{}", calls.len());
+            Ok(IpfsUploadResult {
+                gateway_url: format!("mock://ipfs/{}", cid),
+                cid,
+                size,
+                pinned: true,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl RecordingStore for MockStore {
+        async fn upload_file(&self, file_path: &Path, room_id: &str, peer_id: &str, _tenant: Option<&str>) -> Result<IpfsUploadResult> {
+            let call = StoreCall::File {
+                room_id: room_id.to_string(),
+                peer_id: peer_id.to_string(),
+                file_name: file_path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string(),
+            };
+            match std::fs::metadata(file_path) {
+                Ok(metadata) => self.record(call, metadata.len()),
+                Err(e) => {
+                    self.calls.lock().unwrap().push(call);
+                    Err(SfuError::IpfsUploadFailed(format!("Failed to open {}: {}", file_path.display(), e)))
+                }
+            }
+        }
+
+        async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<IpfsUploadResult> {
+            let call = StoreCall::Bytes { file_name: file_name.unwrap_or("upload.bin").to_string() };
+            self.record(call, data.len() as u64)
+        }
+
+        /// Every CID counts as pinned, as uploads to pinning services do
+        async fn unpin(&self, cid: &str) -> Result<bool> {
+            self.calls.lock().unwrap().push(StoreCall::Unpin { cid: cid.to_string() });
+            Ok(true)
+        }
+
+        async fn health_check(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn location(&self) -> &str {
+            "mock://ipfs"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_kind_parse() {
+        assert_eq!(StorageKind::parse("ipfs"), Some(StorageKind::Ipfs));
+        assert_eq!(StorageKind::parse(" S3 "), Some(StorageKind::S3));
+        assert_eq!(StorageKind::parse("none"), Some(StorageKind::None));
+        assert_eq!(StorageKind::parse("gcs"), None);
+        assert_eq!(StorageKind::default(), StorageKind::Ipfs);
+    }
+}
@@ -0,0 +1,462 @@
+//! Per-tenant storage namespaces for deployments hosting several institutions.
+//!
+//! A room created for a tenant keeps its directory under
+//! `{output_dir}/{tenant}/{room_id}/`; rooms created without one stay in
+//! `{output_dir}/{room_id}/`. A namespace directory is told apart from a room
+//! directory by the `.tenant` marker written when it is first used, since
+//! prefixed room IDs can look like tenant names.
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use super::finalize::list_recordings;
+use super::permissions;
+use crate::config::env;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Marks a directory under the output directory as a tenant namespace
+pub const TENANT_MARKER: &str = ".tenant";
+
+/// Deletion requests, one JSON object per line, in the output directory
+pub const TENANT_AUDIT_FILE: &str = "tenant_audit.jsonl";
+
+/// Longest tenant name accepted, in characters
+pub const MAX_TENANT_CHARS: usize = 64;
+
+/// Default lifetime of a tenant access token
+const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Manifest the close flow leaves in each room directory; read here as plain
+/// JSON so a sweep does not depend on the manifest layout
+const MANIFEST_FILE: &str = "room_manifest.json";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TenantError {
+    Invalid(String),
+    /// Rooms of the tenant are still open and writing into its namespace
+    RoomsOpen(usize),
+    Storage(String),
+}
+
+impl TenantError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TenantError::Invalid(_) => "invalid_tenant",
+            TenantError::RoomsOpen(_) => "tenant_rooms_open",
+            TenantError::Storage(_) => "tenant_storage_failed",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            TenantError::Invalid(name) => format!(
+                "Invalid tenant '{}', expected up to {} lowercase letters, digits, '-' or '_' starting with a letter",
+                name, MAX_TENANT_CHARS
+            ),
+            TenantError::RoomsOpen(count) => format!("Tenant still has {} open room(s); close them first", count),
+            TenantError::Storage(e) => format!("Failed to access tenant storage: {}", e),
+        }
+    }
+}
+
+/// Validates an optional tenant from a room creation request; empty means none
+pub fn parse_tenant(tenant: Option<&str>) -> Result<Option<String>, TenantError> {
+    let Some(tenant) = tenant.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    if is_valid_tenant(tenant) {
+        Ok(Some(tenant.to_string()))
+    } else {
+        Err(TenantError::Invalid(tenant.to_string()))
+    }
+}
+
+/// Names that are safe as a single path component and as a token field
+pub fn is_valid_tenant(tenant: &str) -> bool {
+    tenant.len() <= MAX_TENANT_CHARS
+        && tenant.starts_with(|c: char| c.is_ascii_lowercase())
+        && tenant.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Creates the tenant's namespace under `output_dir`, marking it as one
+pub fn create_tenant_dir(output_dir: &Path, tenant: &str) -> io::Result<PathBuf> {
+    let dir = output_dir.join(tenant);
+    permissions::create_room_dir(&dir)?;
+    let marker = dir.join(TENANT_MARKER);
+    if !marker.is_file() {
+        permissions::write_private(&marker, tenant.as_bytes())?;
+    }
+    Ok(dir)
+}
+
+fn is_tenant_dir(dir: &Path) -> bool {
+    dir.join(TENANT_MARKER).is_file()
+}
+
+/// Tenants with a namespace under `output_dir`, sorted
+pub fn tenants(output_dir: &Path) -> io::Result<Vec<String>> {
+    let mut tenants = Vec::new();
+    for entry in std::fs::read_dir(output_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && is_tenant_dir(&entry.path()) {
+            tenants.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    tenants.sort();
+    Ok(tenants)
+}
+
+/// Every room directory under `output_dir` with the tenant it belongs to,
+/// untenanted rooms first
+pub fn room_dirs(output_dir: &Path) -> io::Result<Vec<(Option<String>, PathBuf)>> {
+    let mut rooms = Vec::new();
+    for entry in std::fs::read_dir(output_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if !is_tenant_dir(&path) {
+            rooms.push((None, path));
+            continue;
+        }
+        let tenant = entry.file_name().to_string_lossy().to_string();
+        for room in std::fs::read_dir(&path)? {
+            let room = room?;
+            if room.file_type()?.is_dir() {
+                rooms.push((Some(tenant.clone()), room.path()));
+            }
+        }
+    }
+    rooms.sort();
+    Ok(rooms)
+}
+
+/// Directory of `room_id`: inside `tenant`'s namespace when one is given,
+/// otherwise the untenanted directory or the first namespace holding the
+/// room. `None` when there is no such room.
+pub fn locate_room(output_dir: &Path, tenant: Option<&str>, room_id: &str) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = match tenant {
+        Some(tenant) => vec![output_dir.join(tenant).join(room_id)],
+        None => std::iter::once(output_dir.join(room_id))
+            .chain(tenants(output_dir).unwrap_or_default().into_iter().map(|tenant| output_dir.join(tenant).join(room_id)))
+            .collect(),
+    };
+    candidates.into_iter().find(|dir| dir.is_dir() && !is_tenant_dir(dir))
+}
+
+/// Who a listing is for: the admin token sees every namespace, a tenant token only its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantScope {
+    All,
+    Tenant(String),
+}
+
+impl TenantScope {
+    /// The tenant listings are restricted to, `None` for the admin scope
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            TenantScope::All => None,
+            TenantScope::Tenant(tenant) => Some(tenant),
+        }
+    }
+
+    /// Whether a resource in `tenant`'s namespace (`None` = untenanted) is visible
+    pub fn allows(&self, tenant: Option<&str>) -> bool {
+        match self {
+            TenantScope::All => true,
+            TenantScope::Tenant(scope) => tenant == Some(scope.as_str()),
+        }
+    }
+}
+
+/// Access tokens scoped to one tenant, formatted as
+/// `{tenant}.{expires_at}.{hex HMAC-SHA256}`
+pub struct TenantTokens {
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+static TOKENS: OnceLock<Option<TenantTokens>> = OnceLock::new();
+
+/// Tenant tokens keyed by `TENANT_TOKEN_SECRET`; `None` when it is unset,
+/// leaving the admin token as the only way to list resources
+pub fn tokens() -> Option<&'static TenantTokens> {
+    TOKENS
+        .get_or_init(|| {
+            let secret = env::get_string("TENANT_TOKEN_SECRET")?;
+            Some(TenantTokens::new(
+                secret.into_bytes(),
+                env::get_duration_secs("TENANT_TOKEN_TTL_SECS", DEFAULT_TOKEN_TTL),
+            ))
+        })
+        .as_ref()
+}
+
+impl TenantTokens {
+    pub fn new(secret: Vec<u8>, ttl: Duration) -> Self {
+        Self { secret, ttl }
+    }
+
+    fn sign(&self, tenant: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(tenant.as_bytes());
+        mac.update(b"/");
+        mac.update(expires_at.to_string().as_bytes());
+        mac
+    }
+
+    /// Token for `tenant`, and when it expires (Unix seconds)
+    pub fn issue(&self, tenant: &str, now: u64) -> (String, u64) {
+        let expires_at = now + self.ttl.as_secs();
+        let signature = hex::encode(self.sign(tenant, expires_at).finalize().into_bytes());
+        (format!("{}.{}.{}", tenant, expires_at, signature), expires_at)
+    }
+
+    /// The tenant a token was issued for, if it verifies and has not expired
+    pub fn verify(&self, token: &str, now: u64) -> Option<String> {
+        let mut parts = token.splitn(3, '.');
+        let (tenant, expires_at, signature) = (parts.next()?, parts.next()?, parts.next()?);
+        let expires_at = expires_at.parse::<u64>().ok()?;
+        let signature = hex::decode(signature).ok()?;
+        (is_valid_tenant(tenant) && now < expires_at && self.sign(tenant, expires_at).verify_slice(&signature).is_ok())
+            .then(|| tenant.to_string())
+    }
+}
+
+/// What deleting a tenant's data removes, or would remove on a dry run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TenantSweep {
+    pub tenant: String,
+    pub dry_run: bool,
+    pub rooms: Vec<String>,
+    /// Recordings, finalized or left in progress
+    pub recordings: usize,
+    /// Every file in the namespace: recordings, sidecars, view events, manifests
+    pub files: usize,
+    pub bytes: u64,
+    /// Uploads the tenant's manifests reference, to drop from the local pin set
+    pub cids: Vec<String>,
+    /// CIDs unpinned from the local node; empty on a dry run
+    pub unpinned: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unpin_failed: Vec<String>,
+}
+
+/// Inventories `tenant`'s namespace and, unless `dry_run`, removes it. Pins
+/// are the caller's to drop, from the returned `cids`.
+pub fn sweep(output_dir: &Path, tenant: &str, dry_run: bool) -> io::Result<TenantSweep> {
+    let mut sweep = TenantSweep {
+        tenant: tenant.to_string(),
+        dry_run,
+        ..Default::default()
+    };
+    let dir = output_dir.join(tenant);
+    if !is_tenant_dir(&dir) {
+        return Ok(sweep);
+    }
+
+    for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            sweep.files += 1;
+            sweep.bytes += entry.metadata()?.len();
+            continue;
+        }
+        let room_dir = entry.path();
+        sweep.rooms.push(entry.file_name().to_string_lossy().to_string());
+        sweep.recordings += list_recordings(&room_dir)?.len();
+        for file in std::fs::read_dir(&room_dir)? {
+            let file = file?;
+            if file.file_type()?.is_file() {
+                sweep.files += 1;
+                sweep.bytes += file.metadata()?.len();
+            }
+        }
+        sweep.cids.extend(manifest_cids(&room_dir.join(MANIFEST_FILE)));
+    }
+    sweep.rooms.sort();
+    sweep.cids.sort();
+    sweep.cids.dedup();
+
+    if !dry_run {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    Ok(sweep)
+}
+
+/// CIDs of the recordings and view events a room manifest lists
+fn manifest_cids(path: &Path) -> Vec<String> {
+    let manifest: serde_json::Value = match std::fs::read(path) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_default(),
+        Err(_) => return Vec::new(),
+    };
+    let recordings = manifest["recordings"].as_array().into_iter().flatten().map(|recording| &recording["cid"]);
+    recordings
+        .chain(std::iter::once(&manifest["view_events_cid"]))
+        .filter_map(|cid| cid.as_str().map(String::from))
+        .collect()
+}
+
+/// Appends one entry to the output directory's tenant audit log
+pub fn append_audit(output_dir: &Path, entry: &serde_json::Value) -> io::Result<()> {
+    permissions::create_room_dir(output_dir)?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    permissions::private_file()
+        .create(true)
+        .append(true)
+        .open(output_dir.join(TENANT_AUDIT_FILE))?
+        .write_all(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_760_000_000;
+
+    fn temp_output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-tenant-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// `{tenant}/{room_id}` with one recording, its sidecar and a manifest naming `cid`
+    fn seed_room(output_dir: &Path, tenant: Option<&str>, room_id: &str, cid: &str) -> PathBuf {
+        let base = match tenant {
+            Some(tenant) => create_tenant_dir(output_dir, tenant).unwrap(),
+            None => output_dir.to_path_buf(),
+        };
+        let room_dir = base.join(room_id);
+        std::fs::create_dir_all(&room_dir).unwrap();
+        std::fs::write(room_dir.join("peer_1_100.webm"), [0u8; 10]).unwrap();
+        std::fs::write(room_dir.join("peer_1_100.meta.json"), b"{}").unwrap();
+        let manifest = serde_json::json!({
+            "room_id": room_id,
+            "recordings": [{ "peer_id": "peer_1", "cid": cid }, { "peer_id": "peer_2", "cid": null }],
+            "view_events_cid": format!("{}-events", cid),
+        });
+        std::fs::write(room_dir.join(MANIFEST_FILE), serde_json::to_vec(&manifest).unwrap()).unwrap();
+        room_dir
+    }
+
+    #[test]
+    fn test_tenant_names() {
+        assert_eq!(parse_tenant(None), Ok(None));
+        assert_eq!(parse_tenant(Some("  ")), Ok(None));
+        assert_eq!(parse_tenant(Some(" uni-a ")), Ok(Some("uni-a".to_string())));
+        assert!(is_valid_tenant("college_2"));
+
+        for invalid in ["Uni", "2uni", "../etc", "uni.a", "uni/a", &"a".repeat(MAX_TENANT_CHARS + 1)] {
+            assert_eq!(parse_tenant(Some(invalid)).unwrap_err().code(), "invalid_tenant", "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_listings_are_isolated_per_tenant() {
+        let dir = temp_output_dir("listing");
+        let plain = seed_room(&dir, None, "100001", "bafyplain");
+        let a = seed_room(&dir, Some("uni-a"), "200002", "bafya");
+        let b = seed_room(&dir, Some("uni-b"), "300003", "bafyb");
+        // Prefixed room IDs can look like tenant names; without a marker they stay rooms
+        let prefixed = seed_room(&dir, None, "sfu-400004", "bafyprefixed");
+
+        assert_eq!(tenants(&dir).unwrap(), vec!["uni-a", "uni-b"]);
+        assert_eq!(
+            room_dirs(&dir).unwrap(),
+            vec![
+                (None, plain.clone()),
+                (None, prefixed),
+                (Some("uni-a".to_string()), a.clone()),
+                (Some("uni-b".to_string()), b.clone()),
+            ]
+        );
+
+        assert_eq!(locate_room(&dir, None, "200002"), Some(a));
+        assert_eq!(locate_room(&dir, None, "100001"), Some(plain));
+        assert_eq!(locate_room(&dir, Some("uni-b"), "300003"), Some(b));
+        // A scoped lookup never leaves the tenant's namespace
+        assert_eq!(locate_room(&dir, Some("uni-b"), "200002"), None);
+        assert_eq!(locate_room(&dir, Some("uni-b"), "100001"), None);
+        // Nor does an unscoped one take a namespace for a room
+        assert_eq!(locate_room(&dir, None, "uni-a"), None);
+
+        let scope = TenantScope::Tenant("uni-a".to_string());
+        assert!(scope.allows(Some("uni-a")));
+        assert!(!scope.allows(Some("uni-b")));
+        assert!(!scope.allows(None));
+        assert!(TenantScope::All.allows(None));
+    }
+
+    #[test]
+    fn test_sweep_removes_only_the_tenant_namespace() {
+        let dir = temp_output_dir("sweep");
+        seed_room(&dir, Some("uni-a"), "200002", "bafya");
+        seed_room(&dir, Some("uni-a"), "200003", "bafya2");
+        let b = seed_room(&dir, Some("uni-b"), "300003", "bafyb");
+        let plain = seed_room(&dir, None, "100001", "bafyplain");
+
+        let dry_run = sweep(&dir, "uni-a", true).unwrap();
+        assert!(dry_run.dry_run);
+        assert_eq!(dry_run.rooms, vec!["200002", "200003"]);
+        assert_eq!(dry_run.recordings, 2);
+        // Two rooms of recording, sidecar and manifest, plus the marker
+        assert_eq!(dry_run.files, 7);
+        assert_eq!(dry_run.cids, vec!["bafya", "bafya-events", "bafya2", "bafya2-events"]);
+        assert!(dir.join("uni-a").join("200002").join("peer_1_100.webm").is_file());
+
+        let deleted = sweep(&dir, "uni-a", false).unwrap();
+        assert_eq!(TenantSweep { dry_run: true, ..deleted.clone() }, dry_run);
+        assert!(!dir.join("uni-a").exists());
+        assert!(b.join("peer_1_100.webm").is_file());
+        assert!(plain.join("peer_1_100.webm").is_file());
+        assert_eq!(tenants(&dir).unwrap(), vec!["uni-b"]);
+
+        // Nothing left to sweep, and an unknown tenant is not an error
+        assert_eq!(sweep(&dir, "uni-a", false).unwrap().files, 0);
+        assert!(sweep(&dir, "uni-c", true).unwrap().rooms.is_empty());
+    }
+
+    #[test]
+    fn test_audit_appends_lines() {
+        let dir = temp_output_dir("audit");
+        append_audit(&dir, &serde_json::json!({ "tenant": "uni-a", "dry_run": true })).unwrap();
+        append_audit(&dir, &serde_json::json!({ "tenant": "uni-a", "dry_run": false })).unwrap();
+
+        let log = std::fs::read_to_string(dir.join(TENANT_AUDIT_FILE)).unwrap();
+        let entries: Vec<serde_json::Value> = log.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1]["dry_run"], false);
+        // The audit log is a file, never mistaken for a room
+        assert!(room_dirs(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tokens_bind_tenant_until_expiry() {
+        let tokens = TenantTokens::new(b"secret".to_vec(), Duration::from_secs(600));
+        let (token, expires_at) = tokens.issue("uni-a", NOW);
+        assert_eq!(expires_at, NOW + 600);
+        assert!(token.starts_with("uni-a."));
+
+        assert_eq!(tokens.verify(&token, NOW), Some("uni-a".to_string()));
+        assert_eq!(tokens.verify(&token, NOW + 600), None);
+
+        // Swapping the tenant or pushing the expiry out breaks the signature
+        let signature = token.rsplit('.').next().unwrap();
+        assert_eq!(tokens.verify(&format!("uni-b.{}.{}", expires_at, signature), NOW), None);
+        assert_eq!(tokens.verify(&format!("uni-a.{}.{}", expires_at + 6000, signature), NOW), None);
+
+        let other = TenantTokens::new(b"other".to_vec(), Duration::from_secs(600));
+        assert_eq!(other.verify(&token, NOW), None);
+        assert_eq!(tokens.verify("garbage", NOW), None);
+        assert_eq!(tokens.verify("uni-a.1.zz", 0), None);
+    }
+}
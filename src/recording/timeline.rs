@@ -0,0 +1,180 @@
+//! Presentation times of recorded RTP packets, taken from the packets' own
+//! RTP timestamps instead of the moment they arrived, so network jitter does
+//! not turn into drift between the tracks of a long recording.
+
+use std::time::Duration;
+
+/// Fixed part of an RTP header, which holds everything read here
+const RTP_HEADER_LEN: usize = 12;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// The fields of an RTP header a packet's place in the recording depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtpHeader {
+    pub sequence_number: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+}
+
+impl RtpHeader {
+    /// Reads the header of a marshalled RTP packet; `None` when it isn't one
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < RTP_HEADER_LEN || packet[0] >> 6 != 2 {
+            return None;
+        }
+        Some(Self {
+            sequence_number: u16::from_be_bytes([packet[2], packet[3]]),
+            timestamp: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+            ssrc: u32::from_be_bytes([packet[8], packet[9], packet[10], packet[11]]),
+        })
+    }
+}
+
+/// Maps one track's RTP timestamps onto the recording's running time.
+///
+/// The first packet is placed at the time it arrived, measured from the start
+/// of the recording, so both tracks start near zero and in step with each
+/// other. Every later packet is placed by how far its timestamp is from the
+/// first one, unwrapped past 32 bits.
+#[derive(Debug)]
+pub struct RtpTimeline {
+    clock_rate: u32,
+    anchor: Option<Anchor>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    ssrc: u32,
+    /// Running time of the first packet
+    at: Duration,
+    /// Ticks from the first packet to the newest one in sequence order
+    elapsed: i64,
+    timestamp: u32,
+    sequence_number: u16,
+}
+
+impl RtpTimeline {
+    pub fn new(clock_rate: u32) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            anchor: None,
+        }
+    }
+
+    /// Running time of the packet with `header`, which arrived `arrival` after
+    /// the recording started
+    pub fn pts(&mut self, header: &RtpHeader, arrival: Duration) -> Duration {
+        let mut anchor = match self.anchor {
+            Some(anchor) if anchor.ssrc == header.ssrc => anchor,
+            // The first packet, or a new source that starts its timestamps over
+            _ => Anchor {
+                ssrc: header.ssrc,
+                at: arrival,
+                elapsed: 0,
+                timestamp: header.timestamp,
+                sequence_number: header.sequence_number,
+            },
+        };
+
+        // A wrapping distance, so a timestamp that went past 2^32 still comes after the newest
+        let elapsed = anchor.elapsed + header.timestamp.wrapping_sub(anchor.timestamp) as i32 as i64;
+        // Only a newer packet moves the reference; a reordered one is placed relative to it
+        if (header.sequence_number.wrapping_sub(anchor.sequence_number) as i16) > 0 {
+            anchor.elapsed = elapsed;
+            anchor.timestamp = header.timestamp;
+            anchor.sequence_number = header.sequence_number;
+        }
+        self.anchor = Some(anchor);
+
+        let nanos = anchor.at.as_nanos() as i128 + elapsed as i128 * NANOS_PER_SEC / self.clock_rate as i128;
+        Duration::from_nanos(nanos.max(0) as u64)
+    }
+
+    /// Places the next packet at its arrival again, like the first one, for
+    /// a track whose timestamps ran on through time the recording leaves out
+    pub fn reanchor(&mut self) {
+        self.anchor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(sequence_number: u16, timestamp: u32) -> RtpHeader {
+        RtpHeader {
+            sequence_number,
+            timestamp,
+            ssrc: 1,
+        }
+    }
+
+    #[test]
+    fn test_parse_reads_the_fixed_header() {
+        let packet = [0x80, 96, 0x12, 0x34, 0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 7, 0xff];
+        let header = RtpHeader::parse(&packet).unwrap();
+        assert_eq!(header, RtpHeader { sequence_number: 0x1234, timestamp: 0xdeadbeef, ssrc: 7 });
+
+        assert!(RtpHeader::parse(&packet[..11]).is_none());
+        // Version 1
+        assert!(RtpHeader::parse(&[0x40; 12]).is_none());
+    }
+
+    #[test]
+    fn test_timestamps_convert_at_the_clock_rate() {
+        let mut video = RtpTimeline::new(90000);
+        let start = Duration::from_millis(200);
+        assert_eq!(video.pts(&header(10, 1_000_000), start), start);
+        // Arrival jitter doesn't move a packet, its timestamp does
+        assert_eq!(video.pts(&header(11, 1_003_000), Duration::from_millis(290)), Duration::from_millis(233) + Duration::from_nanos(333_333));
+        assert_eq!(video.pts(&header(12, 1_090_000), Duration::from_millis(1150)), Duration::from_millis(1200));
+
+        let mut audio = RtpTimeline::new(48000);
+        audio.pts(&header(500, 7), Duration::from_millis(180));
+        assert_eq!(audio.pts(&header(501, 7 + 960), Duration::from_millis(260)), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_timestamps_unwrap_past_32_bits() {
+        let mut audio = RtpTimeline::new(48000);
+        let start = Duration::from_secs(1);
+        audio.pts(&header(65535, u32::MAX - 479), start);
+        // The sequence number wraps along with the timestamp
+        assert_eq!(audio.pts(&header(0, 480), Duration::from_secs(5)), start + Duration::from_millis(20));
+        assert_eq!(audio.pts(&header(1, 1440), Duration::from_secs(5)), start + Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_reordered_packets_keep_their_place() {
+        let mut video = RtpTimeline::new(90000);
+        video.pts(&header(1, 0), Duration::ZERO);
+        assert_eq!(video.pts(&header(3, 6000), Duration::ZERO), Duration::from_nanos(66_666_666));
+        assert_eq!(video.pts(&header(2, 3000), Duration::ZERO), Duration::from_nanos(33_333_333));
+        assert_eq!(video.pts(&header(4, 9000), Duration::ZERO), Duration::from_millis(100));
+
+        // Before the first packet, clamped to the start of the recording
+        assert_eq!(video.pts(&header(0, u32::MAX - 8999), Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_reanchor_skips_the_time_left_out() {
+        let mut video = RtpTimeline::new(90000);
+        video.pts(&header(1, 0), Duration::ZERO);
+        assert_eq!(video.pts(&header(2, 90_000), Duration::from_secs(1)), Duration::from_secs(1));
+
+        // Paused for a minute: the timestamps moved on by 60s, the recording by none
+        video.reanchor();
+        let resumed = Duration::from_millis(1033);
+        assert_eq!(video.pts(&header(3, 90_000 + 60 * 90_000), resumed), resumed);
+        assert_eq!(video.pts(&header(4, 93_000 + 60 * 90_000), resumed), resumed + Duration::from_nanos(33_333_333));
+    }
+
+    #[test]
+    fn test_new_source_starts_from_its_arrival() {
+        let mut video = RtpTimeline::new(90000);
+        video.pts(&header(1, 0), Duration::ZERO);
+        let replaced = RtpHeader { sequence_number: 9000, timestamp: 123_456, ssrc: 2 };
+        assert_eq!(video.pts(&replaced, Duration::from_secs(30)), Duration::from_secs(30));
+    }
+}
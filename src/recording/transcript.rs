@@ -0,0 +1,561 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::env;
+use crate::error::SfuError;
+use super::metadata::SessionMetadata;
+use super::permissions;
+use super::tenant;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Default time an ASR job may take before it is considered lost
+const DEFAULT_JOB_TIMEOUT_SECS: u64 = 3600;
+
+/// Default number of attempts to submit a job to the ASR webhook
+const DEFAULT_SUBMIT_ATTEMPTS: u32 = 3;
+
+/// First retry delay for webhook submissions; doubles on each attempt
+const DEFAULT_RETRY_BASE: Duration = Duration::from_secs(2);
+
+/// Request timeout for a single webhook submission
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often pending jobs are checked for timeouts
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct TranscriptConfig {
+    pub webhook_url: String,
+    pub webhook_token: Option<String>,
+    /// Public base URL of this server, used to build callback URLs
+    pub callback_base_url: String,
+    /// Key for signing callback tokens
+    pub callback_secret: Vec<u8>,
+    pub job_timeout: Duration,
+    pub submit_attempts: u32,
+    pub retry_base: Duration,
+}
+
+impl TranscriptConfig {
+    /// Returns None unless `ASR_WEBHOOK_URL` is set
+    pub fn from_env() -> Option<Self> {
+        let webhook_url = env::get_string("ASR_WEBHOOK_URL")?;
+        let webhook_token = env::get_string("ASR_WEBHOOK_TOKEN");
+
+        let callback_base_url = env::get_string("ASR_CALLBACK_BASE_URL").unwrap_or_else(|| {
+            let port = env::get_parsed::<u16>("SERVER_PORT").unwrap_or(8080);
+            format!("http://localhost:{}", port)
+        });
+
+        let callback_secret = match env::get_string("ASR_CALLBACK_SECRET") {
+            Some(secret) => secret.into_bytes(),
+            None => {
+                tracing::warn!("ASR_CALLBACK_SECRET not set, callbacks for jobs submitted before a restart will be rejected");
+                rand::thread_rng().gen::<[u8; 32]>().to_vec()
+            }
+        };
+
+        let job_timeout = env::get_duration_secs("ASR_JOB_TIMEOUT_SECS", Duration::from_secs(DEFAULT_JOB_TIMEOUT_SECS));
+        let submit_attempts = env::get_parsed::<u32>("ASR_SUBMIT_ATTEMPTS")
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_SUBMIT_ATTEMPTS);
+
+        Some(Self {
+            webhook_url,
+            webhook_token,
+            callback_base_url: callback_base_url.trim_end_matches('/').to_string(),
+            callback_secret,
+            job_timeout,
+            submit_attempts,
+            retry_base: DEFAULT_RETRY_BASE,
+        })
+    }
+}
+
+/// Job posted to the ASR webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptJobRequest {
+    pub room_id: String,
+    pub file: String,
+    pub download_url: String,
+    pub callback_url: String,
+    /// Exam title, course code and notes the proctor attached to the session
+    #[serde(default, skip_serializing_if = "SessionMetadata::is_empty")]
+    pub metadata: SessionMetadata,
+}
+
+/// Body the ASR service posts back to the callback URL
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum TranscriptPayload {
+    Json { transcript: serde_json::Value },
+    Srt { transcript: String },
+}
+
+/// Result of accepting a callback
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackOutcome {
+    Stored(PathBuf),
+    /// A transcript was already stored for this recording; the retry is ignored
+    AlreadyStored(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackError {
+    InvalidPath,
+    InvalidToken,
+    RecordingNotFound,
+    Storage(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JobStatus {
+    Pending,
+    Completed,
+}
+
+#[derive(Debug, Clone)]
+struct TranscriptJob {
+    submitted_at: Instant,
+    status: JobStatus,
+}
+
+/// Submits finished recordings to an external ASR service and stores the
+/// transcripts it calls back with next to the recording
+pub struct TranscriptService {
+    config: TranscriptConfig,
+    output_dir: PathBuf,
+    client: reqwest::Client,
+    /// Submitted jobs keyed by (room_id, file name)
+    jobs: Mutex<HashMap<(String, String), TranscriptJob>>,
+}
+
+static SERVICE: OnceLock<Option<Arc<TranscriptService>>> = OnceLock::new();
+
+/// Process-wide transcript service, or None when `ASR_WEBHOOK_URL` is unset
+pub fn service() -> Option<Arc<TranscriptService>> {
+    SERVICE
+        .get_or_init(|| {
+            let config = TranscriptConfig::from_env()?;
+            let output_dir = env::get_string("RECORDING_OUTPUT_DIR").unwrap_or_else(|| "./recordings".to_string());
+            let service = Arc::new(TranscriptService::new(config, output_dir));
+            service.clone().spawn_timeout_sweeper();
+            Some(service)
+        })
+        .clone()
+}
+
+impl TranscriptService {
+    pub fn new(config: TranscriptConfig, output_dir: impl Into<PathBuf>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(SUBMIT_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            config,
+            output_dir: output_dir.into(),
+            client,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn sign(&self, room_id: &str, file: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.config.callback_secret).expect("HMAC accepts any key length");
+        mac.update(room_id.as_bytes());
+        mac.update(b"/");
+        mac.update(file.as_bytes());
+        mac
+    }
+
+    /// Token embedded in the callback URL, binding it to one recording
+    pub fn callback_token(&self, room_id: &str, file: &str) -> String {
+        hex::encode(self.sign(room_id, file).finalize().into_bytes())
+    }
+
+    fn verify_token(&self, room_id: &str, file: &str, token: &str) -> bool {
+        match hex::decode(token) {
+            Ok(bytes) => self.sign(room_id, file).verify_slice(&bytes).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn callback_url(&self, room_id: &str, file: &str) -> String {
+        format!(
+            "{}/sfu/recordings/{}/{}/transcript?token={}",
+            self.config.callback_base_url,
+            urlencoding::encode(room_id),
+            urlencoding::encode(file),
+            self.callback_token(room_id, file)
+        )
+    }
+
+    /// Post a transcription job for a finished recording, retrying failed submissions
+    pub async fn submit(
+        &self,
+        room_id: &str,
+        recording: &Path,
+        download_url: &str,
+        metadata: &SessionMetadata,
+    ) -> Result<(), SfuError> {
+        let file = recording
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| SfuError::TranscriptFailed(format!("Invalid recording path {}", recording.display())))?
+            .to_string();
+
+        let job = TranscriptJobRequest {
+            room_id: room_id.to_string(),
+            file: file.clone(),
+            download_url: download_url.to_string(),
+            callback_url: self.callback_url(room_id, &file),
+            metadata: metadata.clone(),
+        };
+
+        let mut last_error = String::new();
+        for attempt in 0..self.config.submit_attempts {
+            if attempt > 0 {
+                tokio::time::sleep(self.config.retry_base * 2u32.saturating_pow(attempt - 1)).await;
+            }
+
+            let mut request = self.client.post(&self.config.webhook_url).json(&job);
+            if let Some(ref token) = self.config.webhook_token {
+                request = request.bearer_auth(token);
+            }
+
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    self.jobs.lock().unwrap().insert(
+                        (room_id.to_string(), file.clone()),
+                        TranscriptJob {
+                            submitted_at: Instant::now(),
+                            status: JobStatus::Pending,
+                        },
+                    );
+                    tracing::info!(room_id = %room_id, file = %file, attempt = attempt + 1, "Submitted transcript job");
+                    return Ok(());
+                }
+                Ok(response) if response.status().is_client_error() => {
+                    // The job itself was rejected, retrying won't help
+                    return Err(SfuError::TranscriptFailed(format!(
+                        "ASR webhook rejected job with status {}",
+                        response.status()
+                    )));
+                }
+                Ok(response) => last_error = format!("status {}", response.status()),
+                Err(e) => last_error = e.to_string(),
+            }
+
+            tracing::warn!(
+                room_id = %room_id,
+                file = %file,
+                attempt = attempt + 1,
+                error = %last_error,
+                "Transcript job submission failed"
+            );
+        }
+
+        Err(SfuError::TranscriptFailed(format!(
+            "ASR webhook unavailable after {} attempts: {}",
+            self.config.submit_attempts, last_error
+        )))
+    }
+
+    /// Store a transcript posted back by the ASR service. Repeated callbacks
+    /// for the same recording are acknowledged without rewriting the file.
+    pub fn accept_callback(
+        &self,
+        room_id: &str,
+        file: &str,
+        token: &str,
+        payload: TranscriptPayload,
+    ) -> Result<CallbackOutcome, CallbackError> {
+        if !is_safe_component(room_id) || !is_safe_component(file) {
+            return Err(CallbackError::InvalidPath);
+        }
+        if !self.verify_token(room_id, file, token) {
+            return Err(CallbackError::InvalidToken);
+        }
+
+        let recording = tenant::locate_room(&self.output_dir, None, room_id)
+            .map(|room_dir| room_dir.join(file))
+            .filter(|recording| recording.is_file())
+            .ok_or(CallbackError::RecordingNotFound)?;
+
+        if let Some(existing) = find_transcript(&recording) {
+            self.mark_completed(room_id, file);
+            return Ok(CallbackOutcome::AlreadyStored(existing));
+        }
+
+        let (path, contents) = match payload {
+            TranscriptPayload::Json { transcript } => (
+                transcript_path(&recording, "json"),
+                serde_json::to_vec_pretty(&transcript).map_err(|e| CallbackError::Storage(e.to_string()))?,
+            ),
+            TranscriptPayload::Srt { transcript } => (transcript_path(&recording, "srt"), transcript.into_bytes()),
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        permissions::write_private(&tmp_path, &contents)
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .map_err(|e| CallbackError::Storage(e.to_string()))?;
+
+        self.mark_completed(room_id, file);
+        tracing::info!(room_id = %room_id, file = %file, transcript = %path.display(), "Stored recording transcript");
+        Ok(CallbackOutcome::Stored(path))
+    }
+
+    fn mark_completed(&self, room_id: &str, file: &str) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&(room_id.to_string(), file.to_string())) {
+            job.status = JobStatus::Completed;
+        }
+    }
+
+    /// Drop jobs that finished or never called back within the timeout, returning the timed out ones
+    pub fn expire_jobs_at(&self, now: Instant) -> Vec<(String, String)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let mut timed_out = Vec::new();
+
+        jobs.retain(|key, job| match job.status {
+            JobStatus::Completed => false,
+            JobStatus::Pending if now.duration_since(job.submitted_at) >= self.config.job_timeout => {
+                timed_out.push(key.clone());
+                false
+            }
+            JobStatus::Pending => true,
+        });
+
+        timed_out
+    }
+
+    pub fn pending_jobs(&self) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|job| job.status == JobStatus::Pending)
+            .count()
+    }
+
+    fn spawn_timeout_sweeper(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                for (room_id, file) in self.expire_jobs_at(Instant::now()) {
+                    tracing::warn!(
+                        room_id = %room_id,
+                        file = %file,
+                        timeout_secs = self.config.job_timeout.as_secs(),
+                        "Transcript job timed out without a callback"
+                    );
+                }
+                tracing::debug!(pending = self.pending_jobs(), "Swept transcript jobs");
+            }
+        });
+    }
+}
+
+/// Transcript stored next to a recording, e.g. `peer_123.webm` -> `peer_123.transcript.json`
+pub fn transcript_path(recording: &Path, extension: &str) -> PathBuf {
+    recording.with_extension(format!("transcript.{}", extension))
+}
+
+/// Existing transcript for a recording, if one has been stored
+pub fn find_transcript(recording: &Path) -> Option<PathBuf> {
+    ["json", "srt"]
+        .into_iter()
+        .map(|ext| transcript_path(recording, ext))
+        .find(|path| path.is_file())
+}
+
+pub(super) fn is_safe_component(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('.')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use warp::Filter;
+
+    fn config(webhook_url: String) -> TranscriptConfig {
+        TranscriptConfig {
+            webhook_url,
+            webhook_token: Some("asr-token".to_string()),
+            callback_base_url: "https://sfu.example.com".to_string(),
+            callback_secret: b"test-secret".to_vec(),
+            job_timeout: Duration::from_secs(60),
+            submit_attempts: 3,
+            retry_base: Duration::from_millis(10),
+        }
+    }
+
+    /// Mock ASR service that fails the first `failures` submissions with 503
+    async fn mock_asr(failures: usize) -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<TranscriptJobRequest>>>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let route = {
+            let hits = hits.clone();
+            let received = received.clone();
+            warp::post()
+                .and(warp::header::<String>("authorization"))
+                .and(warp::body::json())
+                .map(move |auth: String, job: TranscriptJobRequest| {
+                    assert_eq!(auth, "Bearer asr-token");
+                    let n = hits.fetch_add(1, Ordering::SeqCst);
+                    if n < failures {
+                        return warp::http::StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    received.lock().unwrap().push(job);
+                    warp::http::StatusCode::ACCEPTED
+                })
+        };
+
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}/jobs", addr), hits, received)
+    }
+
+    fn temp_output_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-transcripts-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn recording_in(dir: &Path) -> PathBuf {
+        let room_dir = dir.join("room-1");
+        std::fs::create_dir_all(&room_dir).unwrap();
+        let recording = room_dir.join("peer_1_100.webm");
+        std::fs::write(&recording, b"webm").unwrap();
+        recording
+    }
+
+    #[test]
+    fn test_unconfigured_is_none() {
+        std::env::remove_var("ASR_WEBHOOK_URL");
+        assert!(TranscriptConfig::from_env().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_submit_success() {
+        let dir = temp_output_dir("success");
+        let (url, hits, received) = mock_asr(0).await;
+        let service = TranscriptService::new(config(url), &dir);
+        let recording = recording_in(&dir);
+
+        let metadata = SessionMetadata::sanitized(Some("Midterm".to_string()), Some("CS101".to_string()), None);
+        service.submit("room-1", &recording, "https://gw.example.com/ipfs/Qm1", &metadata).await.unwrap();
+
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        let job = received.lock().unwrap()[0].clone();
+        assert_eq!(job.file, "peer_1_100.webm");
+        assert_eq!(job.download_url, "https://gw.example.com/ipfs/Qm1");
+        assert_eq!(job.metadata, metadata);
+        assert!(job.callback_url.starts_with("https://sfu.example.com/sfu/recordings/room-1/peer_1_100.webm/transcript?token="));
+        assert_eq!(service.pending_jobs(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_submit_retries_server_errors() {
+        let dir = temp_output_dir("retry");
+        let (url, hits, _) = mock_asr(2).await;
+        let service = TranscriptService::new(config(url), &dir);
+
+        service.submit("room-1", &recording_in(&dir), "https://gw/ipfs/Qm1", &SessionMetadata::default()).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_submit_gives_up_after_attempts() {
+        let dir = temp_output_dir("give-up");
+        let (url, hits, _) = mock_asr(usize::MAX).await;
+        let service = TranscriptService::new(config(url), &dir);
+
+        let result = service.submit("room-1", &recording_in(&dir), "https://gw/ipfs/Qm1", &SessionMetadata::default()).await;
+        assert!(matches!(result, Err(SfuError::TranscriptFailed(_))));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(service.pending_jobs(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_callback_stores_transcript_idempotently() {
+        let dir = temp_output_dir("callback");
+        let (url, _, _) = mock_asr(0).await;
+        let service = TranscriptService::new(config(url), &dir);
+        let recording = recording_in(&dir);
+        service.submit("room-1", &recording, "https://gw/ipfs/Qm1", &SessionMetadata::default()).await.unwrap();
+
+        let token = service.callback_token("room-1", "peer_1_100.webm");
+        let payload = || TranscriptPayload::Json {
+            transcript: serde_json::json!({ "segments": [{ "start": 0.0, "text": "hello" }] }),
+        };
+
+        let first = service.accept_callback("room-1", "peer_1_100.webm", &token, payload()).unwrap();
+        let expected = dir.join("room-1").join("peer_1_100.transcript.json");
+        assert_eq!(first, CallbackOutcome::Stored(expected.clone()));
+        assert_eq!(find_transcript(&recording), Some(expected.clone()));
+
+        // ASR retries the callback
+        let second = service.accept_callback("room-1", "peer_1_100.webm", &token, payload()).unwrap();
+        assert_eq!(second, CallbackOutcome::AlreadyStored(expected));
+        assert_eq!(service.pending_jobs(), 0);
+    }
+
+    #[test]
+    fn test_callback_rejects_bad_token_and_paths() {
+        let dir = temp_output_dir("reject");
+        let service = TranscriptService::new(config("http://unused".to_string()), &dir);
+        recording_in(&dir);
+        let srt = || TranscriptPayload::Srt { transcript: "1\n00:00:00,000 --> 00:00:01,000\nhi\n".to_string() };
+
+        let other_token = service.callback_token("room-1", "other.webm");
+        assert_eq!(
+            service.accept_callback("room-1", "peer_1_100.webm", &other_token, srt()),
+            Err(CallbackError::InvalidToken)
+        );
+        assert_eq!(
+            service.accept_callback("room-1", "peer_1_100.webm", "not-hex", srt()),
+            Err(CallbackError::InvalidToken)
+        );
+        assert_eq!(
+            service.accept_callback("room-1", "..", &service.callback_token("room-1", ".."), srt()),
+            Err(CallbackError::InvalidPath)
+        );
+        assert_eq!(
+            service.accept_callback("room-1", "missing.webm", &service.callback_token("room-1", "missing.webm"), srt()),
+            Err(CallbackError::RecordingNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jobs_without_callback_time_out() {
+        let dir = temp_output_dir("timeout");
+        let (url, _, _) = mock_asr(0).await;
+        let service = TranscriptService::new(config(url), &dir);
+        service.submit("room-1", &recording_in(&dir), "https://gw/ipfs/Qm1", &SessionMetadata::default()).await.unwrap();
+
+        assert!(service.expire_jobs_at(Instant::now()).is_empty());
+        let timed_out = service.expire_jobs_at(Instant::now() + Duration::from_secs(61));
+        assert_eq!(timed_out, vec![("room-1".to_string(), "peer_1_100.webm".to_string())]);
+        assert_eq!(service.pending_jobs(), 0);
+    }
+
+    #[test]
+    fn test_payload_formats() {
+        let json: TranscriptPayload = serde_json::from_str(r#"{"format":"json","transcript":{"text":"hi"}}"#).unwrap();
+        assert!(matches!(json, TranscriptPayload::Json { .. }));
+        let srt: TranscriptPayload = serde_json::from_str(r#"{"format":"srt","transcript":"1\n"}"#).unwrap();
+        assert!(matches!(srt, TranscriptPayload::Srt { .. }));
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::clock::SessionClock;
+use super::permissions;
+
+/// File name of the per-room view event stream inside the room's recording directory
+pub const VIEW_EVENTS_FILE: &str = "room_view_events.jsonl";
+
+/// Change in what the proctor could see at a point in the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewEventKind {
+    /// Session clock started; details carry the wall-clock anchor
+    SessionStarted,
+    /// A peer's track was forwarded to a subscriber
+    Subscribed,
+    /// A peer's tracks stopped being forwarded (peer left or was removed)
+    Unsubscribed,
+    /// A peer reported which of its camera and microphone are live
+    MediaState,
+    /// The proctor changed the session's exam title, course code or notes
+    SessionMetadata,
+    /// A join request the proctor left unanswered was escalated; details
+    /// carry the step taken, the room's policy and how long it had waited
+    JoinEscalation,
+    /// A peer joined the room; details carry its role
+    Joined,
+    /// A student's ID verification status changed; details carry the status
+    IdVerification,
+    /// Suspicious activity was reported for a student; details carry its type
+    Incident,
+    /// The proctor started the exam clock; details carry its duration
+    ExamClock,
+}
+
+/// A single line of `room_view_events.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ViewEvent {
+    pub offset_secs: f64,
+    pub event: ViewEventKind,
+    pub peer_id: String,
+    #[serde(default)]
+    pub details: serde_json::Value,
+}
+
+/// Closed view event stream, with IPFS info if it was uploaded
+#[derive(Debug, Clone)]
+pub struct ViewEventsResult {
+    pub file_path: PathBuf,
+    pub cid: Option<String>,
+}
+
+/// Append-only view event stream for one room
+pub struct ViewEventLog {
+    path: PathBuf,
+    clock: SessionClock,
+}
+
+impl ViewEventLog {
+    /// Creates the log in `room_dir`, writing the session start marker
+    pub fn create(room_dir: &Path, proctor_id: &str, clock: SessionClock) -> io::Result<Self> {
+        permissions::create_room_dir(room_dir)?;
+
+        let log = Self {
+            path: room_dir.join(VIEW_EVENTS_FILE),
+            clock,
+        };
+        log.append(
+            ViewEventKind::SessionStarted,
+            proctor_id,
+            serde_json::json!({ "started_at_ms": clock.started_at_ms() }),
+        )?;
+        Ok(log)
+    }
+
+    pub fn append(&self, event: ViewEventKind, peer_id: &str, details: serde_json::Value) -> io::Result<()> {
+        let entry = ViewEvent {
+            offset_secs: self.clock.offset_secs(),
+            event,
+            peer_id: peer_id.to_string(),
+            details,
+        };
+
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = permissions::private_file().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Reads a view event stream, skipping lines that fail to parse
+pub fn read_view_events(path: &Path) -> io::Result<Vec<ViewEvent>> {
+    let contents = std::fs::read_to_string(path)?;
+
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Skipping malformed view event");
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_room_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-view-events-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_append_and_read_round_trip() {
+        let dir = temp_room_dir("roundtrip");
+        let log = ViewEventLog::create(&dir, "proctor_1", SessionClock::start()).unwrap();
+        log.append(
+            ViewEventKind::MediaState,
+            "student_1",
+            serde_json::json!({ "has_video": true, "has_audio": false }),
+        )
+        .unwrap();
+
+        let events = read_view_events(log.path()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, ViewEventKind::SessionStarted);
+        assert_eq!(events[1].event, ViewEventKind::MediaState);
+        assert_eq!(events[1].peer_id, "student_1");
+        assert_eq!(events[1].details["has_audio"], false);
+        assert!(events[1].offset_secs >= events[0].offset_secs);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_read_skips_malformed_lines() {
+        let dir = temp_room_dir("malformed");
+        let log = ViewEventLog::create(&dir, "proctor_1", SessionClock::start()).unwrap();
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(log.path()).unwrap();
+        file.write_all(b"{truncated\n").unwrap();
+        log.append(ViewEventKind::Unsubscribed, "student_1", serde_json::Value::Null).unwrap();
+
+        let events = read_view_events(log.path()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].event, ViewEventKind::Unsubscribed);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_event_serialization_format() {
+        let event = ViewEvent {
+            offset_secs: 12.5,
+            event: ViewEventKind::Subscribed,
+            peer_id: "student_1".to_string(),
+            details: serde_json::json!({ "subscriber": "proctor_1" }),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert!(json.contains("\"event\":\"subscribed\""));
+        assert!(json.contains("\"offset_secs\":12.5"));
+    }
+}
@@ -0,0 +1,528 @@
+//! S3-compatible object storage (AWS S3, MinIO) for deployments that can't
+//! run IPFS. Requests are signed with AWS Signature Version 4 and addressed
+//! path-style, `{endpoint}/{bucket}/{key}`, which both accept. Files larger
+//! than one part go up as a multipart upload, so a long recording is never
+//! held in memory whole.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
+
+use crate::config::env;
+use crate::error::{Result, SfuError};
+use crate::sfu::Timezone;
+
+const DEFAULT_S3_REGION: &str = "us-east-1";
+const DEFAULT_PART_SIZE_MB: u64 = 8;
+/// S3 refuses parts smaller than this, other than the last
+const MIN_PART_SIZE_MB: u64 = 5;
+const DEFAULT_UPLOAD_TIMEOUT_SECS: u64 = 300;
+
+/// Payloads are streamed, so their hash is not part of the signature
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const SIGNED_HEADERS: &str = "host;x-amz-content-sha256;x-amz-date";
+
+#[derive(Debug, Clone)]
+pub struct S3Config {
+    /// Base URL of the S3 API, e.g. `https://s3.eu-west-1.amazonaws.com` or `http://minio:9000`
+    pub endpoint: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub region: String,
+    /// Size of each part of a multipart upload; smaller files are sent in one request
+    pub part_size: u64,
+    pub upload_timeout_secs: u64,
+}
+
+impl S3Config {
+    /// `None` when any of `S3_ENDPOINT`, `S3_BUCKET`, `S3_ACCESS_KEY` or `S3_SECRET_KEY` is unset
+    pub fn from_env() -> Option<Self> {
+        let required = ["S3_ENDPOINT", "S3_BUCKET", "S3_ACCESS_KEY", "S3_SECRET_KEY"];
+        let missing: Vec<_> = required.iter().filter(|name| env::get_string(name).is_none()).collect();
+        if !missing.is_empty() {
+            tracing::error!(missing = ?missing, "RECORDING_STORAGE=s3 is missing settings, uploads disabled");
+            return None;
+        }
+
+        let part_size_mb = env::get_parsed("S3_PART_SIZE_MB").unwrap_or(DEFAULT_PART_SIZE_MB);
+        if part_size_mb < MIN_PART_SIZE_MB {
+            tracing::warn!(value = part_size_mb, min = MIN_PART_SIZE_MB, "S3_PART_SIZE_MB is below the S3 minimum, using the minimum");
+        }
+
+        Some(Self {
+            endpoint: env::get_string("S3_ENDPOINT")?.trim_end_matches('/').to_string(),
+            bucket: env::get_string("S3_BUCKET")?,
+            access_key: env::get_string("S3_ACCESS_KEY")?,
+            secret_key: env::get_string("S3_SECRET_KEY")?,
+            region: env::get_string("S3_REGION").unwrap_or_else(|| DEFAULT_S3_REGION.to_string()),
+            part_size: part_size_mb.max(MIN_PART_SIZE_MB) * 1024 * 1024,
+            upload_timeout_secs: env::get_parsed("S3_UPLOAD_TIMEOUT_SECS").unwrap_or(DEFAULT_UPLOAD_TIMEOUT_SECS),
+        })
+    }
+}
+
+/// An object written to the bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub url: String,
+    /// Entity tag S3 reports for the object, without its quotes
+    pub etag: String,
+    pub size: u64,
+}
+
+pub struct S3Store {
+    config: S3Config,
+    client: reqwest::Client,
+    /// `Host` as signed, with the port when the endpoint has one
+    host: String,
+    location: String,
+}
+
+impl S3Store {
+    pub fn new(config: S3Config) -> Result<Self> {
+        let endpoint = reqwest::Url::parse(&config.endpoint)
+            .map_err(|e| SfuError::InvalidConfiguration(format!("Invalid S3_ENDPOINT {}: {}", config.endpoint, e)))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(SfuError::InvalidConfiguration(format!("S3_ENDPOINT {} has no host", config.endpoint))),
+        };
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.upload_timeout_secs))
+            .build()
+            .map_err(|e| SfuError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+        let location = format!("{}/{}", config.endpoint, config.bucket);
+
+        Ok(Self { config, client, host, location })
+    }
+
+    /// Where uploads go, `{endpoint}/{bucket}`
+    pub fn location(&self) -> &str {
+        &self.location
+    }
+
+    /// Uploads a recording to `{tenant}/{room_id}/{file name}`, or
+    /// `{room_id}/{file name}` without a tenant
+    pub async fn upload_file(&self, file_path: &Path, room_id: &str, peer_id: &str, tenant: Option<&str>) -> Result<StoredObject> {
+        let file_name = file_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("recording.webm");
+        let key = match tenant {
+            Some(tenant) => format!("{}/{}/{}", tenant, room_id, file_name),
+            None => format!("{}/{}", room_id, file_name),
+        };
+
+        let mut file = tokio::fs::File::open(file_path)
+            .await
+            .map_err(|e| SfuError::Internal(format!("Failed to open file for upload: {}", e)))?;
+        let len = file
+            .metadata()
+            .await
+            .map_err(|e| SfuError::Internal(format!("Failed to read file for upload: {}", e)))?
+            .len();
+
+        let etag = if len <= self.config.part_size {
+            let mut data = Vec::with_capacity(len as usize);
+            file.read_to_end(&mut data)
+                .await
+                .map_err(|e| SfuError::Internal(format!("Failed to read file for upload: {}", e)))?;
+            self.put_object(&key, data).await?
+        } else {
+            self.multipart_upload(&key, &mut file).await?
+        };
+
+        tracing::info!(
+            key = %key,
+            size = len,
+            room_id = %room_id,
+            peer_id = %peer_id,
+            bucket = %self.config.bucket,
+            "Successfully uploaded recording to S3"
+        );
+        Ok(self.stored(&key, etag, len))
+    }
+
+    /// Uploads `data` under `uploads/{sha256}/{file_name}`, so equal
+    /// uploads share a key and different ones never collide
+    pub async fn upload_bytes(&self, data: &[u8], file_name: Option<&str>) -> Result<StoredObject> {
+        let key = format!("uploads/{}/{}", hex::encode(Sha256::digest(data)), file_name.unwrap_or("upload.bin"));
+        let etag = self.put_object(&key, data.to_vec()).await?;
+        Ok(self.stored(&key, etag, data.len() as u64))
+    }
+
+    /// Whether the bucket answers a signed `HEAD`
+    pub async fn health_check(&self) -> Result<bool> {
+        match self.request(reqwest::Method::HEAD, "", &[]).send().await {
+            Ok(response) => Ok(response.status().is_success()),
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn stored(&self, key: &str, etag: String, size: u64) -> StoredObject {
+        StoredObject {
+            url: format!("{}/{}", self.location, encode_key(key)),
+            etag,
+            size,
+        }
+    }
+
+    async fn put_object(&self, key: &str, data: Vec<u8>) -> Result<String> {
+        let response = self.send(self.request(reqwest::Method::PUT, key, &[]).body(data), "Upload").await?;
+        response_etag(&response)
+    }
+
+    /// Creates the upload, sends the file a part at a time and completes it.
+    /// A failed part aborts the upload so the bucket doesn't keep its parts.
+    async fn multipart_upload(&self, key: &str, file: &mut tokio::fs::File) -> Result<String> {
+        let created = self.request(reqwest::Method::POST, key, &[("uploads", String::new())]);
+        let body = self.send(created, "Creating multipart upload").await?.text().await.unwrap_or_default();
+        let upload_id = xml_value(&body, "UploadId")
+            .ok_or_else(|| SfuError::S3RequestFailed(format!("No UploadId in response: {}", body)))?;
+
+        match self.upload_parts(key, &upload_id, file).await {
+            Ok(etag) => Ok(etag),
+            Err(e) => {
+                let abort = self.request(reqwest::Method::DELETE, key, &[("uploadId", upload_id.clone())]);
+                if let Err(abort_error) = self.send(abort, "Aborting multipart upload").await {
+                    tracing::warn!(key = %key, upload_id = %upload_id, error = %abort_error, "Failed to abort S3 multipart upload");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(&self, key: &str, upload_id: &str, file: &mut tokio::fs::File) -> Result<String> {
+        let mut parts = Vec::new();
+        loop {
+            let mut part = Vec::with_capacity(self.config.part_size as usize);
+            (&mut *file)
+                .take(self.config.part_size)
+                .read_to_end(&mut part)
+                .await
+                .map_err(|e| SfuError::Internal(format!("Failed to read file for upload: {}", e)))?;
+            if part.is_empty() {
+                break;
+            }
+            let part_number = parts.len() + 1;
+            let query = [("partNumber", part_number.to_string()), ("uploadId", upload_id.to_string())];
+            let response = self
+                .send(self.request(reqwest::Method::PUT, key, &query).body(part), "Uploading part")
+                .await?;
+            parts.push(response_etag(&response)?);
+        }
+
+        let completion: String = parts
+            .iter()
+            .enumerate()
+            .map(|(i, etag)| format!("<Part><PartNumber>{}</PartNumber><ETag>\"{}\"</ETag></Part>", i + 1, etag))
+            .collect();
+        let completion = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", completion);
+        let completed = self
+            .request(reqwest::Method::POST, key, &[("uploadId", upload_id.to_string())])
+            .body(completion);
+        let body = self.send(completed, "Completing multipart upload").await?.text().await.unwrap_or_default();
+        // S3 can answer 200 with an error in the body
+        if let Some(code) = xml_value(&body, "Code") {
+            return Err(SfuError::S3RequestFailed(format!("Completing multipart upload failed: {}", code)));
+        }
+        xml_value(&body, "ETag")
+            .map(|etag| etag.replace("&quot;", "").trim_matches('"').to_string())
+            .ok_or_else(|| SfuError::S3RequestFailed(format!("No ETag in response: {}", body)))
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, action: &str) -> Result<reqwest::Response> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| SfuError::S3RequestFailed(format!("{} failed: {}", action, e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(SfuError::S3RequestFailed(format!("{} failed with status {}: {}", action, status, error_text)));
+        }
+        Ok(response)
+    }
+
+    /// A signed request for `key` in the bucket, or the bucket itself when `key` is empty
+    fn request(&self, method: reqwest::Method, key: &str, query: &[(&str, String)]) -> reqwest::RequestBuilder {
+        let path = if key.is_empty() {
+            format!("/{}", self.config.bucket)
+        } else {
+            format!("/{}/{}", self.config.bucket, encode_key(key))
+        };
+        let query = canonical_query(query);
+        let amz_date = amz_date(SystemTime::now());
+        let authorization = self.authorization(method.as_str(), &path, &query, &amz_date);
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.config.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.config.endpoint, path, query)
+        };
+        self.client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header(reqwest::header::AUTHORIZATION, authorization)
+    }
+
+    /// `Authorization` header of a Signature Version 4 request
+    fn authorization(&self, method: &str, path: &str, query: &str, amz_date: &str) -> String {
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, query, self.host, UNSIGNED_PAYLOAD, amz_date, SIGNED_HEADERS, UNSIGNED_PAYLOAD
+        );
+        let date = &amz_date[..8];
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = signing_key(&self.config.secret_key, date, &self.config.region, "s3");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.config.access_key, scope, SIGNED_HEADERS, signature
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Key the string to sign is signed with, derived from the secret for one day, region and service
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// `YYYYMMDD'T'HHMMSS'Z'` in UTC
+fn amz_date(at: SystemTime) -> String {
+    let unix_secs = at.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+    let (date, secs_of_day) = Timezone::utc().local_time(unix_secs);
+    format!(
+        "{}T{:02}{:02}{:02}Z",
+        date.to_string().replace('-', ""),
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Percent-encodes each segment of an object key, keeping the `/` between them
+fn encode_key(key: &str) -> String {
+    key.split('/').map(|segment| urlencoding::encode(segment).into_owned()).collect::<Vec<_>>().join("/")
+}
+
+/// Query parameters sorted and encoded as Signature Version 4 expects them
+fn canonical_query(query: &[(&str, String)]) -> String {
+    let mut pairs: Vec<_> = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", urlencoding::encode(name), urlencoding::encode(value)))
+        .collect();
+    pairs.sort();
+    pairs.join("&")
+}
+
+fn response_etag(response: &reqwest::Response) -> Result<String> {
+    response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.trim_matches('"').to_string())
+        .ok_or_else(|| SfuError::S3RequestFailed("No ETag in response".to_string()))
+}
+
+/// Text of the first `<tag>` in an S3 XML response
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = start + body[start..].find(&format!("</{}>", tag))?;
+    Some(body[start..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use warp::http::{Method, Response, StatusCode};
+    use warp::Filter;
+
+    /// A request as the mock S3 endpoint saw it
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum S3Call {
+        Put { key: String, len: usize },
+        Create { key: String },
+        Part { number: u32, len: usize },
+        Complete { etags: Vec<String> },
+        Abort,
+        Head,
+    }
+
+    /// Path-style S3 endpoint for bucket `recordings` that records every
+    /// request. Refuses requests without a signature, and part
+    /// `fail_part` when it is set.
+    fn mock_s3(fail_part: Option<u32>) -> (S3Config, Arc<Mutex<Vec<S3Call>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let route = warp::method()
+            .and(warp::path("recordings"))
+            .and(warp::path::tail())
+            .and(warp::query::<HashMap<String, String>>())
+            .and(warp::header::optional::<String>("authorization"))
+            .and(warp::body::bytes())
+            .map(move |method: Method, tail: warp::path::Tail, query: HashMap<String, String>, auth: Option<String>, body: bytes::Bytes| {
+                if !auth.is_some_and(|auth| auth.starts_with("AWS4-HMAC-SHA256 Credential=minio/")) {
+                    return Response::builder().status(StatusCode::FORBIDDEN).body(String::new()).unwrap();
+                }
+                let key = tail.as_str().to_string();
+                let mut calls = recorded.lock().unwrap();
+                let ok = Response::builder().status(StatusCode::OK);
+                match (method, query.get("uploadId"), query.get("partNumber")) {
+                    (Method::HEAD, _, _) => {
+                        calls.push(S3Call::Head);
+                        ok.body(String::new()).unwrap()
+                    }
+                    (Method::PUT, None, _) => {
+                        calls.push(S3Call::Put { key, len: body.len() });
+                        ok.header("ETag", "\"single\"").body(String::new()).unwrap()
+                    }
+                    (Method::POST, None, _) if query.contains_key("uploads") => {
+                        calls.push(S3Call::Create { key });
+                        ok.body("<InitiateMultipartUploadResult><UploadId>upload-1</UploadId></InitiateMultipartUploadResult>".to_string())
+                            .unwrap()
+                    }
+                    (Method::PUT, Some(_), Some(number)) => {
+                        let number: u32 = number.parse().unwrap();
+                        calls.push(S3Call::Part { number, len: body.len() });
+                        if fail_part == Some(number) {
+                            return Response::builder().status(StatusCode::SERVICE_UNAVAILABLE).body("SlowDown".to_string()).unwrap();
+                        }
+                        ok.header("ETag", format!("\"part-{}\"", number)).body(String::new()).unwrap()
+                    }
+                    (Method::POST, Some(upload_id), _) => {
+                        assert_eq!(upload_id, "upload-1");
+                        let body = String::from_utf8_lossy(&body);
+                        let etags = body.split("<ETag>").skip(1).map(|rest| rest[..rest.find("</ETag>").unwrap()].to_string()).collect();
+                        calls.push(S3Call::Complete { etags });
+                        ok.body("<CompleteMultipartUploadResult><ETag>&quot;whole-3&quot;</ETag></CompleteMultipartUploadResult>".to_string())
+                            .unwrap()
+                    }
+                    (Method::DELETE, Some(_), _) => {
+                        calls.push(S3Call::Abort);
+                        Response::builder().status(StatusCode::NO_CONTENT).body(String::new()).unwrap()
+                    }
+                    _ => Response::builder().status(StatusCode::BAD_REQUEST).body(String::new()).unwrap(),
+                }
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let config = S3Config {
+            endpoint: format!("http://{}", addr),
+            bucket: "recordings".to_string(),
+            access_key: "minio".to_string(),
+            secret_key: "minio-secret".to_string(),
+            region: DEFAULT_S3_REGION.to_string(),
+            part_size: 4,
+            upload_timeout_secs: 5,
+        };
+        (config, calls)
+    }
+
+    fn temp_recording(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("sfu-s3-{}-{}.webm", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_large_file_uploads_in_parts() {
+        let recording = temp_recording("multipart", b"0123456789");
+        let (config, calls) = mock_s3(None);
+        let store = S3Store::new(config).unwrap();
+
+        let stored = store.upload_file(&recording, "room-1", "peer_1", Some("uni-a")).await.unwrap();
+        let key = format!("uni-a/room-1/{}", recording.file_name().unwrap().to_str().unwrap());
+        assert_eq!(stored.url, format!("{}/{}", store.location(), key));
+        assert_eq!((stored.etag.as_str(), stored.size), ("whole-3", 10));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                S3Call::Create { key },
+                S3Call::Part { number: 1, len: 4 },
+                S3Call::Part { number: 2, len: 4 },
+                S3Call::Part { number: 3, len: 2 },
+                S3Call::Complete { etags: vec!["\"part-1\"".to_string(), "\"part-2\"".to_string(), "\"part-3\"".to_string()] },
+            ]
+        );
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[tokio::test]
+    async fn test_failed_part_aborts_the_upload() {
+        let recording = temp_recording("abort", b"0123456789");
+        let (config, calls) = mock_s3(Some(2));
+        let store = S3Store::new(config).unwrap();
+
+        let result = store.upload_file(&recording, "room-1", "peer_1", None).await;
+        assert!(matches!(result, Err(SfuError::S3RequestFailed(_))), "{:?}", result);
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls[1..], [S3Call::Part { number: 1, len: 4 }, S3Call::Part { number: 2, len: 4 }, S3Call::Abort]);
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[tokio::test]
+    async fn test_small_uploads_use_a_single_put() {
+        let recording = temp_recording("single", b"webm");
+        let (config, calls) = mock_s3(None);
+        let store = S3Store::new(config.clone()).unwrap();
+
+        let stored = store.upload_file(&recording, "room-1", "peer_1", None).await.unwrap();
+        assert_eq!((stored.etag.as_str(), stored.size), ("single", 4));
+        let manifest = store.upload_bytes(b"{}", Some("room_manifest.json")).await.unwrap();
+        let uploads = format!("{}/uploads/", store.location());
+        assert!(manifest.url.starts_with(&uploads) && manifest.url.ends_with("/room_manifest.json"), "{}", manifest.url);
+        assert!(store.health_check().await.unwrap());
+        assert_eq!(calls.lock().unwrap().len(), 3);
+
+        let unsigned = S3Store::new(S3Config { access_key: "other".to_string(), ..config }).unwrap();
+        assert!(matches!(unsigned.upload_bytes(b"{}", None).await, Err(SfuError::S3RequestFailed(_))));
+        assert!(!unsigned.health_check().await.unwrap());
+
+        let _ = std::fs::remove_file(&recording);
+    }
+
+    #[test]
+    fn test_signing_key_matches_the_aws_example() {
+        let key = signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20120215", "us-east-1", "iam");
+        assert_eq!(hex::encode(key), "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d");
+    }
+
+    #[test]
+    fn test_request_encoding() {
+        assert_eq!(amz_date(UNIX_EPOCH + Duration::from_secs(1_329_305_402)), "20120215T113002Z");
+        assert_eq!(encode_key("uni-a/room 1/peer_1.webm"), "uni-a/room%201/peer_1.webm");
+        assert_eq!(
+            canonical_query(&[("uploadId", "a/b".to_string()), ("partNumber", "2".to_string())]),
+            "partNumber=2&uploadId=a%2Fb"
+        );
+        assert_eq!(canonical_query(&[("uploads", String::new())]), "uploads=");
+        assert_eq!(xml_value("<a><UploadId>x1</UploadId></a>", "UploadId").as_deref(), Some("x1"));
+        assert_eq!(xml_value("<a></a>", "UploadId"), None);
+    }
+}
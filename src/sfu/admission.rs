@@ -0,0 +1,531 @@
+use rand::Rng;
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::SendError;
+use warp::ws::Message;
+
+use super::pending::{EscalatedRequest, IceBufferError, PendingIceCandidate, PendingLimitReached, PendingStudent, PendingStudents};
+use crate::config::env;
+use crate::metrics;
+
+/// Why the server refused to take on work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    CapacityExceeded,
+    ServerDraining,
+    RateLimited,
+    RoomFull,
+}
+
+impl RejectReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::CapacityExceeded => "capacity_exceeded",
+            RejectReason::ServerDraining => "server_draining",
+            RejectReason::RateLimited => "rate_limited",
+            RejectReason::RoomFull => "room_full",
+        }
+    }
+}
+
+/// Structured rejection sent to clients, telling them when (and where) to retry
+#[derive(Debug, Clone, Serialize)]
+pub struct Rejection {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub code: &'static str,
+    pub message: String,
+    pub retry_after_secs: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alternate_server: Option<String>,
+}
+
+/// Default delay suggested at zero utilization
+const DEFAULT_RETRY_BASE_SECS: u64 = 1;
+
+/// Default upper bound on suggested delays
+const DEFAULT_RETRY_MAX_SECS: u64 = 120;
+
+/// Doublings of the base delay between idle and fully utilized
+const UTILIZATION_DOUBLINGS: f64 = 6.0;
+
+/// Shared policy for every shed/reject path, so clients see consistent backoff hints.
+///
+/// The suggested delay grows exponentially with utilization
+/// (`base * 2^(6 * utilization)`, capped at `max`) and is spread by ±25% jitter
+/// so rejected clients do not retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    base: Duration,
+    max: Duration,
+    alternate_server: Option<String>,
+}
+
+impl RetryPolicy {
+    pub fn new(base: Duration, max: Duration, alternate_server: Option<String>) -> Self {
+        Self {
+            base,
+            max: max.max(base),
+            alternate_server,
+        }
+    }
+
+    /// Reads `SFU_RETRY_BASE_SECS`, `SFU_RETRY_MAX_SECS` and `SFU_ALTERNATE_SERVER`
+    pub fn from_env() -> Self {
+        Self::new(
+            env::get_duration_secs("SFU_RETRY_BASE_SECS", Duration::from_secs(DEFAULT_RETRY_BASE_SECS)),
+            env::get_duration_secs("SFU_RETRY_MAX_SECS", Duration::from_secs(DEFAULT_RETRY_MAX_SECS)),
+            env::get_string("SFU_ALTERNATE_SERVER"),
+        )
+    }
+
+    /// Suggested delay for `utilization` (0.0 = idle, 1.0 = at the limit) and
+    /// `jitter` in [0, 1). Draining always suggests the maximum.
+    pub fn retry_after_secs_with_jitter(&self, reason: RejectReason, utilization: f64, jitter: f64) -> u64 {
+        let utilization = match reason {
+            RejectReason::ServerDraining => 1.0,
+            _ if utilization.is_finite() => utilization.clamp(0.0, 1.0),
+            _ => 1.0,
+        };
+
+        let delay = self.base.as_secs_f64() * 2f64.powf(UTILIZATION_DOUBLINGS * utilization);
+        let delay = delay.min(self.max.as_secs_f64());
+        let jittered = delay * (0.75 + 0.5 * jitter.clamp(0.0, 1.0));
+
+        (jittered.ceil() as u64).clamp(1, self.max.as_secs().max(1))
+    }
+
+    pub fn retry_after_secs(&self, reason: RejectReason, utilization: f64) -> u64 {
+        let jitter = rand::thread_rng().gen::<f64>();
+        self.retry_after_secs_with_jitter(reason, utilization, jitter)
+    }
+
+    pub fn reject(&self, reason: RejectReason, utilization: f64, message: impl Into<String>) -> Rejection {
+        Rejection {
+            kind: "error",
+            code: reason.code(),
+            message: message.into(),
+            retry_after_secs: self.retry_after_secs(reason, utilization),
+            alternate_server: self.alternate_server.clone(),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(DEFAULT_RETRY_BASE_SECS),
+            Duration::from_secs(DEFAULT_RETRY_MAX_SECS),
+            None,
+        )
+    }
+}
+
+/// Optional admission limits; `None` means unlimited
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionLimits {
+    /// Maximum peers (connected plus pending approval) on this instance
+    pub max_peers: Option<usize>,
+    /// Maximum peers in a single room, proctor included
+    pub max_room_peers: Option<usize>,
+    /// Maximum signaling messages per second per connection
+    pub max_messages_per_sec: Option<u32>,
+    /// Maximum rooms one proctor may run at once
+    pub max_rooms_per_proctor: Option<usize>,
+}
+
+impl AdmissionLimits {
+    /// Reads `SFU_MAX_PEERS`, `SFU_MAX_ROOM_PEERS`, `SFU_SIGNALING_RATE_LIMIT`
+    /// and `MAX_ROOMS_PER_PROCTOR`
+    pub fn from_env() -> Self {
+        fn limit<T: std::str::FromStr + PartialOrd + Default>(name: &str) -> Option<T> {
+            env::get_parsed(name).filter(|v| *v > T::default())
+        }
+
+        Self {
+            max_peers: limit("SFU_MAX_PEERS"),
+            max_room_peers: limit("SFU_MAX_ROOM_PEERS"),
+            max_messages_per_sec: limit("SFU_SIGNALING_RATE_LIMIT"),
+            max_rooms_per_proctor: limit("MAX_ROOMS_PER_PROCTOR"),
+        }
+    }
+}
+
+/// Fixed one-second window message counter for a single connection
+pub struct MessageRateLimiter {
+    limit: Option<u32>,
+    window_start: Instant,
+    count: u32,
+}
+
+impl MessageRateLimiter {
+    pub fn new(limit: Option<u32>) -> Self {
+        Self {
+            limit,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Counts a message at `now`; returns the window utilization if over the limit
+    pub fn check(&mut self, now: Instant) -> Result<(), f64> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+
+        self.count += 1;
+        if self.count > limit {
+            Err(self.count as f64 / limit as f64)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Join requests awaiting the proctor: recording them, answering them and
+/// settling them once the student connects
+pub trait AdmissionService: Send + Sync {
+    fn pending_count(&self) -> usize;
+
+    fn is_pending(&self, room_id: &str, peer_id: &str) -> bool;
+
+    /// Students whose request for `room_id` the proctor has not answered
+    fn unanswered(&self, room_id: &str) -> Vec<String>;
+
+    /// Records a join request, refused once `MAX_PENDING_STUDENTS` are waiting
+    fn request_join(&self, room_id: &str, peer_id: &str, student: PendingStudent) -> Result<(), PendingLimitReached>;
+
+    /// Wallet the student sent with its request for `room_id`
+    fn pending_wallet(&self, room_id: &str, peer_id: &str) -> Option<String>;
+
+    /// Delivers the proctor's decision to a student pending for `room_id`;
+    /// `None` when there is no such request. A denial ends the request, an
+    /// approval stops its escalation.
+    fn answer(&self, room_id: &str, peer_id: &str, approved: bool, message: Message) -> Option<Result<(), SendError<Message>>>;
+
+    /// Ends the request of a student now joining `room_id`, returning the ICE
+    /// candidates it buffered. Candidates from a request for another room are dropped.
+    fn settle(&self, room_id: &str, peer_id: &str) -> Vec<PendingIceCandidate>;
+
+    /// Drops the student's request for `room_id`, leaving one for any other room
+    fn withdraw(&self, room_id: &str, peer_id: &str);
+
+    /// Buffers a candidate from a student still awaiting approval for `room_id`
+    fn buffer_ice_candidate(&self, room_id: &str, peer_id: &str, candidate: PendingIceCandidate) -> Result<usize, IceBufferError>;
+
+    /// Takes the next escalation step for requests unanswered for `interval`
+    fn escalate(&self, now: Instant, interval: Duration) -> Vec<EscalatedRequest>;
+
+    /// Removes requests older than `ttl` as (room_id, peer_id, student)
+    fn expire(&self, now: Instant) -> Vec<(String, String, PendingStudent)>;
+
+    fn ttl(&self) -> Duration;
+}
+
+/// `AdmissionService` over `PendingStudents`, keeping the pending gauge current
+pub struct PendingAdmissions {
+    pending: Mutex<PendingStudents>,
+}
+
+impl Default for PendingAdmissions {
+    fn default() -> Self {
+        Self::new(PendingStudents::default())
+    }
+}
+
+impl PendingAdmissions {
+    pub fn new(pending: PendingStudents) -> Self {
+        Self {
+            pending: Mutex::new(pending),
+        }
+    }
+
+    /// Reads `MAX_PENDING_STUDENTS`, `PENDING_STUDENT_TTL_SECS` and `MAX_PENDING_ICE_CANDIDATES`
+    pub fn from_env() -> Self {
+        Self::new(PendingStudents::from_env())
+    }
+
+    fn update<T>(&self, f: impl FnOnce(&mut PendingStudents) -> T) -> T {
+        let mut pending = self.pending.lock().unwrap();
+        let result = f(&mut pending);
+        metrics::metrics().pending_students.set(pending.len() as u64);
+        result
+    }
+}
+
+impl AdmissionService for PendingAdmissions {
+    fn pending_count(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    fn is_pending(&self, room_id: &str, peer_id: &str) -> bool {
+        self.pending.lock().unwrap().get(room_id, peer_id).is_some()
+    }
+
+    fn unanswered(&self, room_id: &str) -> Vec<String> {
+        self.pending.lock().unwrap().unanswered(room_id)
+    }
+
+    fn request_join(&self, room_id: &str, peer_id: &str, student: PendingStudent) -> Result<(), PendingLimitReached> {
+        self.update(|pending| pending.insert(room_id, peer_id, student))
+    }
+
+    fn pending_wallet(&self, room_id: &str, peer_id: &str) -> Option<String> {
+        self.pending
+            .lock()
+            .unwrap()
+            .get(room_id, peer_id)
+            .and_then(|student| student.wallet_address.clone())
+    }
+
+    fn answer(&self, room_id: &str, peer_id: &str, approved: bool, message: Message) -> Option<Result<(), SendError<Message>>> {
+        self.update(|pending| {
+            let result = pending.get(room_id, peer_id)?.sender.send(message);
+            if approved {
+                pending.decide(room_id, peer_id);
+            } else {
+                // A denied request is over; its buffered candidates go with it
+                pending.remove(peer_id);
+            }
+            Some(result)
+        })
+    }
+
+    fn settle(&self, room_id: &str, peer_id: &str) -> Vec<PendingIceCandidate> {
+        self.update(|pending| {
+            let same_room = pending.get(room_id, peer_id).is_some();
+            let student = pending.remove(peer_id);
+            student.filter(|_| same_room).map(|s| s.ice_candidates).unwrap_or_default()
+        })
+    }
+
+    fn withdraw(&self, room_id: &str, peer_id: &str) {
+        self.update(|pending| {
+            if pending.get(room_id, peer_id).is_some() {
+                pending.remove(peer_id);
+            }
+        });
+    }
+
+    fn buffer_ice_candidate(&self, room_id: &str, peer_id: &str, candidate: PendingIceCandidate) -> Result<usize, IceBufferError> {
+        self.pending.lock().unwrap().buffer_ice_candidate(room_id, peer_id, candidate)
+    }
+
+    fn escalate(&self, now: Instant, interval: Duration) -> Vec<EscalatedRequest> {
+        self.update(|pending| pending.escalate(now, interval))
+    }
+
+    fn expire(&self, now: Instant) -> Vec<(String, String, PendingStudent)> {
+        self.update(|pending| pending.expire(now))
+    }
+
+    fn ttl(&self) -> Duration {
+        self.pending.lock().unwrap().ttl()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::escalation::{EscalationAction, EscalationPolicy, EscalationStage};
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(Duration::from_secs(1), Duration::from_secs(120), None)
+    }
+
+    #[test]
+    fn test_backoff_curve_without_jitter() {
+        let policy = policy();
+        // jitter 0.5 is the midpoint, i.e. no spread
+        let at = |u: f64| policy.retry_after_secs_with_jitter(RejectReason::CapacityExceeded, u, 0.5);
+
+        assert_eq!(at(0.0), 1);
+        assert_eq!(at(0.5), 8);
+        assert_eq!(at(0.75), 23);
+        assert_eq!(at(1.0), 64);
+    }
+
+    #[test]
+    fn test_backoff_is_monotonic_in_utilization() {
+        let policy = policy();
+        let mut previous = 0;
+        for step in 0..=20 {
+            let secs = policy.retry_after_secs_with_jitter(RejectReason::RoomFull, step as f64 / 20.0, 0.5);
+            assert!(secs >= previous, "delay decreased at step {}", step);
+            previous = secs;
+        }
+    }
+
+    #[test]
+    fn test_jitter_bounds() {
+        let policy = policy();
+        let low = policy.retry_after_secs_with_jitter(RejectReason::CapacityExceeded, 1.0, 0.0);
+        let high = policy.retry_after_secs_with_jitter(RejectReason::CapacityExceeded, 1.0, 0.999);
+        assert_eq!(low, 48);
+        assert_eq!(high, 80);
+
+        for _ in 0..100 {
+            let secs = policy.retry_after_secs(RejectReason::CapacityExceeded, 1.0);
+            assert!((48..=80).contains(&secs));
+        }
+    }
+
+    #[test]
+    fn test_backoff_capped_at_max() {
+        let policy = RetryPolicy::new(Duration::from_secs(5), Duration::from_secs(30), None);
+        assert_eq!(policy.retry_after_secs_with_jitter(RejectReason::CapacityExceeded, 1.0, 0.999), 30);
+        // Utilization above 1.0 or invalid values are treated as fully loaded
+        assert_eq!(policy.retry_after_secs_with_jitter(RejectReason::RateLimited, 3.0, 0.0), 23);
+        assert_eq!(policy.retry_after_secs_with_jitter(RejectReason::RateLimited, f64::NAN, 0.0), 23);
+    }
+
+    #[test]
+    fn test_draining_ignores_utilization() {
+        let policy = policy();
+        assert_eq!(
+            policy.retry_after_secs_with_jitter(RejectReason::ServerDraining, 0.0, 0.5),
+            policy.retry_after_secs_with_jitter(RejectReason::CapacityExceeded, 1.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn test_rejection_serialization() {
+        let with_alternate = RetryPolicy::new(
+            Duration::from_secs(1),
+            Duration::from_secs(120),
+            Some("wss://sfu-2.example.com/sfu".to_string()),
+        );
+        let rejection = with_alternate.reject(RejectReason::RoomFull, 1.0, "Room 123456 is full");
+
+        let json: serde_json::Value = serde_json::to_value(&rejection).unwrap();
+        assert_eq!(json["type"], "error");
+        assert_eq!(json["code"], "room_full");
+        assert_eq!(json["alternate_server"], "wss://sfu-2.example.com/sfu");
+        assert!(json["retry_after_secs"].as_u64().unwrap() >= 1);
+
+        let without_alternate = policy().reject(RejectReason::RateLimited, 0.0, "slow down");
+        let json = serde_json::to_string(&without_alternate).unwrap();
+        assert!(!json.contains("alternate_server"));
+    }
+
+    #[test]
+    fn test_rate_limiter_window() {
+        let mut limiter = MessageRateLimiter::new(Some(2));
+        let start = Instant::now();
+        assert!(limiter.check(start).is_ok());
+        assert!(limiter.check(start).is_ok());
+        assert_eq!(limiter.check(start), Err(1.5));
+
+        // New window resets the count
+        assert!(limiter.check(start + Duration::from_secs(1)).is_ok());
+    }
+
+    fn pending_student() -> (PendingStudent, tokio::sync::mpsc::UnboundedReceiver<Message>) {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let student = PendingStudent {
+            sender,
+            wallet_address: Some("0x1111111111111111111111111111111111111111".to_string()),
+            name: None,
+            role: "student".to_string(),
+            requested_at: Instant::now(),
+            ice_candidates: Vec::new(),
+            escalation: EscalationPolicy::AutoDeny,
+            stage: EscalationStage::Waiting,
+        };
+        (student, receiver)
+    }
+
+    fn candidate() -> PendingIceCandidate {
+        PendingIceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host".to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_mline_index: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_answers_reach_only_requests_for_the_room() {
+        let admissions = PendingAdmissions::new(PendingStudents::new(10, Duration::from_secs(60)));
+        let (student, mut receiver) = pending_student();
+        admissions.request_join("room-a", "s1", student).unwrap();
+        assert!(admissions.pending_wallet("room-a", "s1").is_some());
+        assert!(admissions.pending_wallet("room-b", "s1").is_none());
+
+        assert!(admissions.answer("room-b", "s1", true, Message::text("approved")).is_none());
+        assert!(admissions.answer("room-a", "s1", true, Message::text("approved")).unwrap().is_ok());
+        assert_eq!(receiver.try_recv().unwrap().to_str().unwrap(), "approved");
+        // Approval keeps the request until the student connects
+        assert!(admissions.is_pending("room-a", "s1"));
+        assert!(!admissions.is_pending("room-b", "s1"));
+
+        assert!(admissions.answer("room-a", "s1", false, Message::text("denied")).is_some());
+        assert!(!admissions.is_pending("room-a", "s1"));
+        assert_eq!(admissions.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_settle_hands_over_candidates_for_the_same_room() {
+        let admissions = PendingAdmissions::new(PendingStudents::new(10, Duration::from_secs(60)));
+        admissions.request_join("room-a", "s1", pending_student().0).unwrap();
+        admissions.buffer_ice_candidate("room-a", "s1", candidate()).unwrap();
+        assert_eq!(admissions.settle("room-a", "s1"), vec![candidate()]);
+        assert!(!admissions.is_pending("room-a", "s1"));
+
+        admissions.request_join("room-a", "s2", pending_student().0).unwrap();
+        admissions.buffer_ice_candidate("room-a", "s2", candidate()).unwrap();
+        assert!(admissions.settle("room-b", "s2").is_empty());
+        assert!(!admissions.is_pending("room-a", "s2"));
+    }
+
+    #[test]
+    fn test_withdraw_only_touches_the_room_asked_about() {
+        let admissions = PendingAdmissions::new(PendingStudents::new(10, Duration::from_secs(60)));
+        admissions.request_join("room-b", "s1", pending_student().0).unwrap();
+
+        // A connection that asked about another room closing leaves this request alone
+        admissions.withdraw("room-a", "s1");
+        assert!(admissions.is_pending("room-b", "s1"));
+        assert_eq!(
+            admissions.buffer_ice_candidate("room-a", "s1", candidate()),
+            Err(IceBufferError::NotPending)
+        );
+
+        admissions.withdraw("room-b", "s1");
+        assert_eq!(admissions.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_approval_stops_escalation() {
+        let admissions = PendingAdmissions::new(PendingStudents::new(10, Duration::from_secs(600)));
+        let interval = Duration::from_secs(60);
+        admissions.request_join("room-a", "s1", pending_student().0).unwrap();
+        admissions.request_join("room-a", "s2", pending_student().0).unwrap();
+
+        assert!(admissions.answer("room-a", "s1", true, Message::text("approved")).is_some());
+        let later = Instant::now() + interval * 2;
+        let escalated = admissions.escalate(later, interval);
+        assert_eq!(escalated.len(), 1);
+        assert_eq!(escalated[0].peer_id, "s2");
+
+        // The fallback denial ends the request, so the proctor's late answer finds nothing
+        assert_eq!(admissions.escalate(later, interval)[0].action, EscalationAction::AutoDenied);
+        assert!(admissions.answer("room-a", "s2", true, Message::text("approved")).is_none());
+        assert!(admissions.is_pending("room-a", "s1"));
+        assert_eq!(admissions.pending_count(), 1);
+    }
+
+    #[test]
+    fn test_rate_limiter_unlimited() {
+        let mut limiter = MessageRateLimiter::new(None);
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.check(now).is_ok());
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+use crate::config::env;
+use crate::error::{Result, SfuError};
+
+/// Identity of this SFU instance when several run behind one hostname
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub instance_id: String,
+    /// WebSocket URL that reaches this instance directly, used in redirects
+    pub public_url: Option<String>,
+}
+
+impl InstanceInfo {
+    /// Reads `INSTANCE_ID` and `INSTANCE_PUBLIC_URL`; `None` when no instance ID is set
+    pub fn from_env() -> Option<Self> {
+        let instance_id = env::get_string("INSTANCE_ID")?;
+        Some(Self {
+            instance_id,
+            public_url: env::get_string("INSTANCE_PUBLIC_URL"),
+        })
+    }
+
+    /// Opaque routing key embedded in room IDs created by this instance.
+    ///
+    /// Four hex digits of an FNV-1a hash, so a routing layer can map a room ID
+    /// prefix to an instance without exposing the instance ID itself.
+    pub fn routing_key(&self) -> String {
+        let hash = self
+            .instance_id
+            .bytes()
+            .fold(0x811c9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x01000193));
+        format!("{:04x}", hash & 0xffff)
+    }
+}
+
+/// Extracts the routing key from a room ID of the form `{key}-{digits}`
+pub fn routing_key_of(room_id: &str) -> Option<&str> {
+    let (key, _) = room_id.split_once('-')?;
+    (key.len() == 4 && key.chars().all(|c| c.is_ascii_hexdigit())).then_some(key)
+}
+
+/// Shared room → instance mapping consulted when a room is not found locally
+pub trait RoomRegistry: Send + Sync {
+    fn register(&self, room_id: &str, instance: &InstanceInfo) -> Result<()>;
+    fn deregister(&self, room_id: &str) -> Result<()>;
+    fn lookup(&self, room_id: &str) -> Result<Option<InstanceInfo>>;
+}
+
+/// Registry local to this process; used when no shared registry is configured
+#[derive(Default)]
+pub struct MemoryRoomRegistry {
+    rooms: RwLock<HashMap<String, InstanceInfo>>,
+}
+
+impl RoomRegistry for MemoryRoomRegistry {
+    fn register(&self, room_id: &str, instance: &InstanceInfo) -> Result<()> {
+        self.rooms.write().unwrap().insert(room_id.to_string(), instance.clone());
+        Ok(())
+    }
+
+    fn deregister(&self, room_id: &str) -> Result<()> {
+        self.rooms.write().unwrap().remove(room_id);
+        Ok(())
+    }
+
+    fn lookup(&self, room_id: &str) -> Result<Option<InstanceInfo>> {
+        Ok(self.rooms.read().unwrap().get(room_id).cloned())
+    }
+}
+
+/// Registry stored as one JSON file per room in a directory on a shared volume
+pub struct FileRoomRegistry {
+    dir: PathBuf,
+}
+
+impl FileRoomRegistry {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| SfuError::Internal(format!("Failed to create room registry directory: {}", e)))?;
+        Ok(Self { dir })
+    }
+
+    fn entry_path(&self, room_id: &str) -> Result<PathBuf> {
+        if room_id.is_empty() || !room_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(SfuError::Internal(format!("Invalid room ID for registry: {}", room_id)));
+        }
+        Ok(self.dir.join(format!("{}.json", room_id)))
+    }
+}
+
+impl RoomRegistry for FileRoomRegistry {
+    fn register(&self, room_id: &str, instance: &InstanceInfo) -> Result<()> {
+        let path = self.entry_path(room_id)?;
+        let tmp_path = path.with_extension("json.tmp");
+        let contents = serde_json::to_vec(instance)?;
+
+        std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, &path))
+            .map_err(|e| SfuError::Internal(format!("Failed to register room: {}", e)))
+    }
+
+    fn deregister(&self, room_id: &str) -> Result<()> {
+        match std::fs::remove_file(self.entry_path(room_id)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(SfuError::Internal(format!("Failed to deregister room: {}", e))),
+        }
+    }
+
+    fn lookup(&self, room_id: &str) -> Result<Option<InstanceInfo>> {
+        match std::fs::read(self.entry_path(room_id)?) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SfuError::Internal(format!("Failed to read room registry: {}", e))),
+        }
+    }
+}
+
+/// Where a room lives relative to this instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomLocation {
+    Local,
+    Remote(InstanceInfo),
+    Unknown,
+}
+
+/// Binds this instance's identity to the shared room registry
+#[derive(Clone)]
+pub struct RoomAffinity {
+    instance: Option<InstanceInfo>,
+    registry: Arc<dyn RoomRegistry>,
+}
+
+impl Default for RoomAffinity {
+    fn default() -> Self {
+        Self::new(None, Arc::new(MemoryRoomRegistry::default()))
+    }
+}
+
+impl RoomAffinity {
+    pub fn new(instance: Option<InstanceInfo>, registry: Arc<dyn RoomRegistry>) -> Self {
+        Self { instance, registry }
+    }
+
+    /// Reads instance identity and `ROOM_REGISTRY_DIR` (shared volume) from environment
+    pub fn from_env() -> Self {
+        let instance = InstanceInfo::from_env();
+
+        let registry: Arc<dyn RoomRegistry> = match env::get_string("ROOM_REGISTRY_DIR") {
+            Some(dir) => match FileRoomRegistry::new(&dir) {
+                Ok(registry) => {
+                    tracing::info!(dir = %dir, "Using shared room registry");
+                    Arc::new(registry)
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to open shared room registry, using local registry");
+                    Arc::new(MemoryRoomRegistry::default())
+                }
+            },
+            None => Arc::new(MemoryRoomRegistry::default()),
+        };
+
+        if let Some(ref instance) = instance {
+            tracing::info!(
+                instance_id = %instance.instance_id,
+                routing_key = %instance.routing_key(),
+                "Instance identity configured"
+            );
+        }
+
+        Self::new(instance, registry)
+    }
+
+    pub fn instance(&self) -> Option<&InstanceInfo> {
+        self.instance.as_ref()
+    }
+
+    /// Prefix for new room IDs, if this instance has an identity
+    pub fn room_id_prefix(&self) -> Option<String> {
+        self.instance.as_ref().map(|i| i.routing_key())
+    }
+
+    pub fn on_room_created(&self, room_id: &str) {
+        let Some(ref instance) = self.instance else {
+            return;
+        };
+        if let Err(e) = self.registry.register(room_id, instance) {
+            tracing::error!(room_id = %room_id, error = %e, "Failed to register room in registry");
+        }
+    }
+
+    pub fn on_room_closed(&self, room_id: &str) {
+        if self.instance.is_none() {
+            return;
+        }
+        if let Err(e) = self.registry.deregister(room_id) {
+            tracing::error!(room_id = %room_id, error = %e, "Failed to deregister room from registry");
+        }
+    }
+
+    /// Resolves a room that may be hosted by another instance
+    pub fn locate(&self, room_id: &str, exists_locally: bool) -> RoomLocation {
+        if exists_locally {
+            return RoomLocation::Local;
+        }
+
+        match self.registry.lookup(room_id) {
+            Ok(Some(owner)) if Some(&owner) != self.instance.as_ref() => RoomLocation::Remote(owner),
+            Ok(_) => {
+                let own_key = self.room_id_prefix();
+                if let Some(key) = routing_key_of(room_id).filter(|key| Some(*key) != own_key.as_deref()) {
+                    tracing::warn!(
+                        room_id = %room_id,
+                        routing_key = %key,
+                        "Room belongs to another instance but is not in the registry"
+                    );
+                }
+                RoomLocation::Unknown
+            }
+            Err(e) => {
+                tracing::warn!(room_id = %room_id, error = %e, "Room registry lookup failed");
+                RoomLocation::Unknown
+            }
+        }
+    }
+}
+
+/// Structured `wrong_instance` error telling the client where the room lives
+pub fn wrong_instance_error(room_id: &str, owner: &InstanceInfo) -> serde_json::Value {
+    serde_json::json!({
+        "type": "error",
+        "code": "wrong_instance",
+        "message": format!("Room {} is hosted by instance {}", room_id, owner.instance_id),
+        "correct_instance": owner.instance_id,
+        "redirect_url": owner.public_url,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Registry that records calls and serves preset lookups
+    #[derive(Default)]
+    struct MockRegistry {
+        calls: Mutex<Vec<String>>,
+        entries: Mutex<HashMap<String, InstanceInfo>>,
+        fail: bool,
+    }
+
+    impl RoomRegistry for MockRegistry {
+        fn register(&self, room_id: &str, instance: &InstanceInfo) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("register:{}", room_id));
+            if self.fail {
+                return Err(SfuError::Internal("registry down".to_string()));
+            }
+            self.entries.lock().unwrap().insert(room_id.to_string(), instance.clone());
+            Ok(())
+        }
+
+        fn deregister(&self, room_id: &str) -> Result<()> {
+            self.calls.lock().unwrap().push(format!("deregister:{}", room_id));
+            self.entries.lock().unwrap().remove(room_id);
+            Ok(())
+        }
+
+        fn lookup(&self, room_id: &str) -> Result<Option<InstanceInfo>> {
+            if self.fail {
+                return Err(SfuError::Internal("registry down".to_string()));
+            }
+            Ok(self.entries.lock().unwrap().get(room_id).cloned())
+        }
+    }
+
+    fn instance(id: &str) -> InstanceInfo {
+        InstanceInfo {
+            instance_id: id.to_string(),
+            public_url: Some(format!("wss://{}.example.com/sfu", id)),
+        }
+    }
+
+    #[test]
+    fn test_routing_key_is_stable_and_parseable() {
+        let a = instance("sfu-a");
+        assert_eq!(a.routing_key(), a.routing_key());
+        assert_ne!(a.routing_key(), instance("sfu-b").routing_key());
+
+        let room_id = format!("{}-123456", a.routing_key());
+        assert_eq!(routing_key_of(&room_id), Some(a.routing_key().as_str()));
+        assert_eq!(routing_key_of("123456"), None);
+        assert_eq!(routing_key_of("zzzz-123456"), None);
+    }
+
+    #[test]
+    fn test_register_on_create_and_deregister_on_close() {
+        let registry = Arc::new(MockRegistry::default());
+        let affinity = RoomAffinity::new(Some(instance("sfu-a")), registry.clone());
+
+        affinity.on_room_created("ab12-100001");
+        assert_eq!(registry.lookup("ab12-100001").unwrap(), Some(instance("sfu-a")));
+
+        affinity.on_room_closed("ab12-100001");
+        assert_eq!(registry.lookup("ab12-100001").unwrap(), None);
+        assert_eq!(
+            *registry.calls.lock().unwrap(),
+            vec!["register:ab12-100001", "deregister:ab12-100001"]
+        );
+    }
+
+    #[test]
+    fn test_no_registration_without_instance_identity() {
+        let registry = Arc::new(MockRegistry::default());
+        let affinity = RoomAffinity::new(None, registry.clone());
+
+        affinity.on_room_created("100001");
+        affinity.on_room_closed("100001");
+        assert!(registry.calls.lock().unwrap().is_empty());
+        assert_eq!(affinity.room_id_prefix(), None);
+    }
+
+    #[test]
+    fn test_locate_foreign_room() {
+        let registry = Arc::new(MockRegistry::default());
+        registry.register("cd34-200002", &instance("sfu-b")).unwrap();
+        let affinity = RoomAffinity::new(Some(instance("sfu-a")), registry);
+
+        assert_eq!(affinity.locate("cd34-200002", false), RoomLocation::Remote(instance("sfu-b")));
+        assert_eq!(affinity.locate("cd34-200002", true), RoomLocation::Local);
+        assert_eq!(affinity.locate("ef56-300003", false), RoomLocation::Unknown);
+    }
+
+    #[test]
+    fn test_locate_own_stale_entry_is_unknown() {
+        let registry = Arc::new(MockRegistry::default());
+        registry.register("ab12-100001", &instance("sfu-a")).unwrap();
+        let affinity = RoomAffinity::new(Some(instance("sfu-a")), registry);
+
+        // Registered to us but gone locally: not a redirect loop
+        assert_eq!(affinity.locate("ab12-100001", false), RoomLocation::Unknown);
+    }
+
+    #[test]
+    fn test_registry_failure_is_not_fatal() {
+        let registry = Arc::new(MockRegistry { fail: true, ..Default::default() });
+        let affinity = RoomAffinity::new(Some(instance("sfu-a")), registry);
+
+        affinity.on_room_created("ab12-100001");
+        assert_eq!(affinity.locate("ab12-100001", false), RoomLocation::Unknown);
+    }
+
+    #[test]
+    fn test_wrong_instance_error() {
+        let error = wrong_instance_error("cd34-200002", &instance("sfu-b"));
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["code"], "wrong_instance");
+        assert_eq!(error["correct_instance"], "sfu-b");
+        assert_eq!(error["redirect_url"], "wss://sfu-b.example.com/sfu");
+    }
+
+    #[test]
+    fn test_file_registry_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sfu-room-registry-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let registry = FileRoomRegistry::new(&dir).unwrap();
+
+        registry.register("ab12-100001", &instance("sfu-a")).unwrap();
+        assert_eq!(registry.lookup("ab12-100001").unwrap(), Some(instance("sfu-a")));
+
+        registry.deregister("ab12-100001").unwrap();
+        assert_eq!(registry.lookup("ab12-100001").unwrap(), None);
+        // Deregistering twice is harmless
+        registry.deregister("ab12-100001").unwrap();
+
+        assert!(registry.lookup("../etc/passwd").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::recording::RecordingDetail;
+
+/// Default age a recording needs before an unforced CloseRoom may stop it
+pub const DEFAULT_CLOSE_ROOM_MIN_RECORDING_SECS: u64 = 60;
+
+/// What closing a room would do right now, shown to the proctor before they confirm
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClosePreview {
+    /// Students still in the room, in join order
+    pub connected_students: Vec<String>,
+    /// Recordings the close would stop
+    pub active_recordings: Vec<RecordingDetail>,
+    /// Recordings already stopped that are still finalizing or uploading
+    pub pending_uploads: usize,
+    /// Connected students with no ID verification result yet
+    pub unverified_students: Vec<String>,
+    /// Students whose join request the proctor has not answered
+    pub unanswered_join_requests: Vec<String>,
+}
+
+/// Why an unforced CloseRoom was refused
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum CloseBlocker {
+    /// The recording started less than the minimum age ago
+    RecentRecording { peer_id: String, elapsed_secs: u64 },
+    UnverifiedStudent { peer_id: String },
+}
+
+impl ClosePreview {
+    /// What keeps the room from closing without `force`; empty when nothing does
+    pub fn blockers(&self, min_recording_age: Duration) -> Vec<CloseBlocker> {
+        let recent = self
+            .active_recordings
+            .iter()
+            .filter(|recording| recording.elapsed_secs < min_recording_age.as_secs())
+            .map(|recording| CloseBlocker::RecentRecording {
+                peer_id: recording.peer_id.clone(),
+                elapsed_secs: recording.elapsed_secs,
+            });
+        let unverified = self
+            .unverified_students
+            .iter()
+            .map(|peer_id| CloseBlocker::UnverifiedStudent { peer_id: peer_id.clone() });
+        recent.chain(unverified).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{RecordingContent, RecordingState};
+
+    fn recording(peer_id: &str, elapsed_secs: u64) -> RecordingDetail {
+        RecordingDetail {
+            peer_id: peer_id.to_string(),
+            state: RecordingState::Recording,
+            started_at: None,
+            started_at_local: None,
+            elapsed_secs,
+            paused: false,
+            bytes_written: 0,
+            segments: 1,
+            content: RecordingContent::AudioVideo,
+        }
+    }
+
+    #[test]
+    fn test_blockers() {
+        let min_age = Duration::from_secs(DEFAULT_CLOSE_ROOM_MIN_RECORDING_SECS);
+        let mut preview = ClosePreview {
+            connected_students: vec!["student_1".to_string(), "student_2".to_string()],
+            active_recordings: vec![recording("student_1", 600), recording("student_2", 59)],
+            pending_uploads: 1,
+            unverified_students: vec!["student_2".to_string()],
+            unanswered_join_requests: vec!["student_3".to_string()],
+        };
+        assert_eq!(
+            preview.blockers(min_age),
+            vec![
+                CloseBlocker::RecentRecording { peer_id: "student_2".to_string(), elapsed_secs: 59 },
+                CloseBlocker::UnverifiedStudent { peer_id: "student_2".to_string() },
+            ]
+        );
+        assert_eq!(preview.blockers(Duration::ZERO).len(), 1);
+
+        // Uploads and unanswered requests are shown but never block
+        preview.active_recordings.pop();
+        preview.unverified_students.clear();
+        assert!(preview.blockers(min_age).is_empty());
+    }
+
+    #[test]
+    fn test_blocker_serialization_shape() {
+        let json = serde_json::to_value(CloseBlocker::RecentRecording {
+            peer_id: "student_1".to_string(),
+            elapsed_secs: 12,
+        })
+        .unwrap();
+        assert_eq!(json["reason"], "recent_recording");
+        assert_eq!(json["peer_id"], "student_1");
+        assert_eq!(json["elapsed_secs"], 12);
+    }
+}
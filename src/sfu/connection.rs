@@ -1,48 +1,82 @@
 use std::sync::Arc;
 use tokio::sync::mpsc;
+use tracing::Instrument;
 use warp::ws::Message;
 use webrtc::api::API;
-use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
 use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtcp::payload_feedbacks::receiver_estimated_maximum_bitrate::ReceiverEstimatedMaximumBitrate;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
 use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
 use webrtc::track::track_local::TrackLocalWriter;
-use webrtc::util::Marshal;
 
+use super::keyframe::{is_vp8_keyframe, PliDecision, RecordingKeyframeScheduler};
+use super::log_sampling::{self, TrackLogSampler};
+use super::room::PeerKey;
+use super::rtcp::{self, ReceiveStats, RembEstimator, TrackReceiveStats};
 use super::track_manager::TrackManager;
-use super::webrtc_utils::get_ice_servers;
-use crate::recording::RecordingManager;
-
+use super::webrtc_utils::rtc_configuration;
+use crate::config::WebRTCConfig;
+use crate::diagnostics::StateHistory;
+use crate::metrics;
+use crate::recording::{MediaKind, RecordingManager, RtpCodec};
+
+
+/// Size of the per-track RTP read buffer, enough for one MTU-sized packet
+const RTP_READ_BUFFER_SIZE: usize = 1500;
+
+/// New tracks as (publisher, track_id)
+pub type TrackNotificationSender = mpsc::UnboundedSender<(PeerKey, String)>;
+
+/// A peer connection changed state
+#[derive(Debug, Clone)]
+pub struct PeerStateChange {
+    pub peer: PeerKey,
+    pub state: RTCPeerConnectionState,
+    /// The connection that changed, so a report about one the peer has
+    /// since replaced is not applied to the new one
+    pub connection: std::sync::Weak<RTCPeerConnection>,
+}
 
-pub type TrackNotificationSender = mpsc::UnboundedSender<(String, String)>;
+pub type PeerStateSender = mpsc::UnboundedSender<PeerStateChange>;
 
 pub struct SfuConnection {
     pub peer_id: String,
     pub peer_connection: Arc<RTCPeerConnection>,
     pub sender: mpsc::UnboundedSender<Message>,
     pub room_id: Option<String>,
+    /// ICE and connection state changes, for diagnostics bundles
+    pub state_history: Arc<StateHistory>,
+    /// Held from the signaling state check until the local description is
+    /// set, so a renegotiation and a client offer cannot interleave
+    pub negotiation: tokio::sync::Mutex<()>,
 }
 
 impl SfuConnection {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         peer_id: String,
         room_id: String,
         sender: mpsc::UnboundedSender<Message>,
         api: &Arc<API>,
+        webrtc_config: &WebRTCConfig,
         track_manager: Arc<TrackManager>,
         track_notification_sender: Option<TrackNotificationSender>,
+        peer_state_sender: Option<PeerStateSender>,
         recording_manager: Option<Arc<RecordingManager>>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let config = RTCConfiguration {
-            ice_servers: get_ice_servers(&Default::default()),
-            ..Default::default()
-        };
-
-        let peer_connection = Arc::new(api.new_peer_connection(config).await?);
+        let peer_connection = Arc::new(api.new_peer_connection(rtc_configuration(webrtc_config)).await?);
 
         peer_connection.add_transceiver_from_kind(RTPCodecType::Video, None).await?;
         peer_connection.add_transceiver_from_kind(RTPCodecType::Audio, None).await?;
 
+        // Callbacks run on WebRTC's tasks; the span ties what they log to the
+        // signaling connection that created this peer connection
+        let span = tracing::Span::current();
+        let state_history = Arc::new(StateHistory::default());
 
         let peer_id_clone = peer_id.clone();
         let room_id_clone = room_id.clone();
@@ -50,8 +84,10 @@ impl SfuConnection {
         let pc_clone = peer_connection.clone();
         let notification_sender = track_notification_sender.clone();
         let recording_manager_clone = recording_manager.clone();
+        let track_span = span.clone();
 
         peer_connection.on_track(Box::new(move |track, _receiver, _transceiver| {
+            let span = track_span.clone();
             let peer_id = peer_id_clone.clone();
             let room_id = room_id_clone.clone();
             let track_manager = track_manager_clone.clone();
@@ -77,7 +113,8 @@ impl SfuConnection {
                     "SFU received track from peer"
                 );
 
-                track_manager.add_track(track_id.clone(), peer_id.clone(), track.clone()).await;
+                let source = PeerKey::new(room_id.clone(), peer_id.clone());
+                track_manager.add_track(track_id.clone(), &source, track.clone()).await;
 
                 Self::start_track_forwarding(
                     track,
@@ -90,7 +127,7 @@ impl SfuConnection {
                 ).await;
 
                 if let Some(tx) = sender {
-                    if let Err(_) = tx.send((peer_id.clone(), track_id.clone())) {
+                    if let Err(_) = tx.send((source, track_id.clone())) {
                         tracing::error!(
                             peer_id = %peer_id,
                             track_id = %track_id,
@@ -98,17 +135,22 @@ impl SfuConnection {
                         );
                     }
                 }
-            })
+            }.instrument(span))
         }));
 
         let sender_clone = sender.clone();
         let peer_id_for_ice = peer_id.clone();
+        // Candidates are logged individually only at trace level; debug gets the count
+        let candidates_sent = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let candidate_span = span.clone();
         peer_connection.on_ice_candidate(Box::new(move |candidate| {
+            let span = candidate_span.clone();
             let sender = sender_clone.clone();
             let peer_id = peer_id_for_ice.clone();
+            let candidates_sent = candidates_sent.clone();
             Box::pin(async move {
                 if let Some(candidate) = candidate {
-                    tracing::debug!(peer_id = %peer_id, "Generating ICE candidate for peer");
+                    tracing::trace!(peer_id = %peer_id, "Generating ICE candidate for peer");
                     if let Ok(candidate_json) = candidate.to_json() {
                         let ice_message = serde_json::json!({
                             "type": "IceCandidate",
@@ -119,30 +161,56 @@ impl SfuConnection {
                         });
 
                         if let Ok(msg_str) = serde_json::to_string(&ice_message) {
-                            tracing::debug!(peer_id = %peer_id, "Sending ICE candidate to peer");
+                            tracing::trace!(peer_id = %peer_id, "Sending ICE candidate to peer");
+                            candidates_sent.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                             let _ = sender.send(Message::text(msg_str));
                         }
                     }
                 } else {
-                    tracing::info!(peer_id = %peer_id, "ICE gathering complete for peer");
+                    tracing::info!(
+                        peer_id = %peer_id,
+                        candidates = candidates_sent.swap(0, std::sync::atomic::Ordering::Relaxed),
+                        "ICE gathering complete for peer"
+                    );
                 }
-            })
+            }.instrument(span))
         }));
 
         let peer_id_clone = peer_id.clone();
+        let (ice_span, ice_history) = (span.clone(), state_history.clone());
         peer_connection.on_ice_connection_state_change(Box::new(move |state| {
             let peer_id = peer_id_clone.clone();
+            ice_history.record("ice", state);
             Box::pin(async move {
                 tracing::info!(peer_id = %peer_id, ?state, "ICE connection state changed");
-            })
+            }.instrument(ice_span.clone()))
+        }));
+
+        let peer = PeerKey::new(room_id.clone(), peer_id.clone());
+        // Weak, so the callback doesn't keep its own connection alive
+        let connection = Arc::downgrade(&peer_connection);
+        let (pc_span, pc_history) = (span.clone(), state_history.clone());
+        peer_connection.on_peer_connection_state_change(Box::new(move |state| {
+            pc_history.record("peer_connection", state);
+            pc_span.in_scope(|| tracing::info!(peer = %peer, ?state, "Peer connection state changed"));
+            if let Some(ref state_sender) = peer_state_sender {
+                let _ = state_sender.send(PeerStateChange {
+                    peer: peer.clone(),
+                    state,
+                    connection: connection.clone(),
+                });
+            }
+            Box::pin(async {})
         }));
 
         let peer_id_clone = peer_id.clone();
+        let gathering_history = state_history.clone();
         peer_connection.on_ice_gathering_state_change(Box::new(move |state| {
             let peer_id = peer_id_clone.clone();
+            gathering_history.record("ice_gathering", state);
             Box::pin(async move {
                 tracing::debug!(peer_id = %peer_id, ?state, "ICE gathering state changed");
-            })
+            }.instrument(span.clone()))
         }));
 
         Ok(Self {
@@ -150,6 +218,8 @@ impl SfuConnection {
             peer_connection,
             sender,
             room_id: Some(room_id),
+            state_history,
+            negotiation: tokio::sync::Mutex::new(()),
         })
     }
 
@@ -167,12 +237,41 @@ impl SfuConnection {
         let tid = track_id.clone();
 
         let is_video = remote_track.kind() == webrtc::rtp_transceiver::rtp_codec::RTPCodecType::Video;
+        let is_vp8 = remote_track.codec().capability.mime_type.eq_ignore_ascii_case("video/VP8");
+
+        // Recordings are set up before negotiation; bring them in line with what was negotiated
+        if let Some(ref recorder) = recording_manager {
+            let kind = if is_video { MediaKind::Video } else { MediaKind::Audio };
+            let codec = RtpCodec::from_parameters(&remote_track.codec());
+            if let Err(e) = recorder.set_track_codec(&room_id, &source_peer_id, kind, &codec).await {
+                tracing::error!(
+                    track_id = %track_id,
+                    peer_id = %source_peer_id,
+                    error = %e,
+                    "Track cannot be recorded"
+                );
+            }
+        }
 
         tokio::spawn(async move {
-            let mut rtp_buf = vec![0u8; 1500];
-            let mut packet_count = 0u64;
+            let mut rtp_buf = vec![0u8; RTP_READ_BUFFER_SIZE];
+            let mut log_sampler = TrackLogSampler::new(log_sampling::settings(), std::time::Instant::now());
             let mut last_pli_time = std::time::Instant::now();
             let pli_interval = std::time::Duration::from_secs(3);
+            let recording_keyframe_interval = recording_manager
+                .as_ref()
+                .map(|r| r.keyframe_interval())
+                .unwrap_or_default();
+            let mut keyframe_scheduler = RecordingKeyframeScheduler::new(recording_keyframe_interval);
+            let metrics = metrics::metrics();
+
+            let feedback = rtcp::feedback();
+            let report_interval = feedback.settings().report_interval;
+            let send_remb = is_video && feedback.settings().remb_enabled;
+            let kind = if is_video { "video" } else { "audio" };
+            let mut receive_stats = ReceiveStats::new(track.codec().capability.clock_rate, std::time::Instant::now());
+            let mut remb = RembEstimator::new(feedback.settings().remb_max_bitrate_bps);
+            let mut last_report_time = std::time::Instant::now();
 
             // Send initial PLI to request keyframe for video tracks
             if track.kind() == RTPCodecType::Video {
@@ -194,17 +293,66 @@ impl SfuConnection {
             loop {
                 match track.read(&mut rtp_buf).await {
                     Ok((rtp_packet, _)) => {
-                        packet_count += 1;
+                        let log_packet = log_sampler.on_packet(rtp_packet.payload.len());
+
+                        let arrival = std::time::Instant::now();
+                        let forwarded_track = track_manager.get_track(&room_id, &tid).await;
+                        // Encrypted payloads only look like VP8, so keyframes cannot be told apart
+                        let e2ee = forwarded_track.as_ref().is_some_and(|t| t.e2ee);
+                        let keyframe = is_vp8 && !e2ee && is_vp8_keyframe(&rtp_packet.payload);
+                        if keyframe {
+                            feedback.on_keyframe(&room_id, &tid, arrival);
+                        }
+                        receive_stats.record(
+                            rtp_packet.header.sequence_number,
+                            rtp_packet.header.timestamp,
+                            rtp_packet.payload.len(),
+                            arrival,
+                        );
+                        if arrival.duration_since(last_report_time) >= report_interval {
+                            last_report_time = arrival;
+                            let report = receive_stats.report(arrival);
+                            let remb_bitrate = if send_remb {
+                                let bitrate = remb.update(report.fraction_lost);
+                                if let Err(e) = Self::send_remb(&pc, track.ssrc(), bitrate).await {
+                                    tracing::debug!(track_id = %tid, error = %e, "Failed to send REMB");
+                                }
+                                Some(bitrate)
+                            } else {
+                                None
+                            };
+                            if report.fraction_lost > 0.0 {
+                                tracing::debug!(
+                                    track_id = %tid,
+                                    fraction_lost = report.fraction_lost,
+                                    remb_bitrate = ?remb_bitrate,
+                                    "Publisher loss observed"
+                                );
+                            }
+                            let mut stats = TrackReceiveStats::from_report(&tid, kind, track.ssrc(), &report, remb_bitrate);
+                            stats.forward_drops = log_sampler.total_drops();
+                            feedback.update(&source_peer_id, &room_id, stats);
+                        }
 
-                        if packet_count <= 5 {
+                        if log_packet {
                             tracing::debug!(
                                 track_id = %tid,
-                                packet_count = packet_count,
+                                packet_count = log_sampler.total_packets(),
                                 "Forwarding packet for track"
                             );
                         }
+                        if let Some(summary) = log_sampler.poll(arrival) {
+                            tracing::debug!(
+                                track_id = %tid,
+                                packets = summary.packets,
+                                bytes = summary.bytes,
+                                drops = summary.drops,
+                                interval_secs = summary.interval.as_secs_f64(),
+                                "Track forwarding summary"
+                            );
+                        }
 
-                        if let Some(forwarded_track) = track_manager.get_track(&tid).await {
+                        if let Some(forwarded_track) = forwarded_track {
                             let has_subscribers = forwarded_track.local_tracks.iter()
                                 .any(|(target_peer_id, _)| target_peer_id != &source_peer_id);
 
@@ -222,15 +370,29 @@ impl SfuConnection {
                                 }
                             }
 
+                            if let Some(ref recorder) = recording_manager {
+                                let fanout = forwarded_track.local_tracks.len() as u64;
+                                recorder.account_media_bytes(rtp_packet.payload.len() as u64 * fanout);
+                            }
+
+                            // The payload is a shared `Bytes` handle, so each subscriber write
+                            // only bumps a refcount while rewriting the header for its binding
                             for (target_peer_id, local_track) in &forwarded_track.local_tracks {
                                 if target_peer_id != &source_peer_id {
-                                    if let Err(e) = local_track.write_rtp(&rtp_packet).await {
-                                        if packet_count <= 5 {
-                                            tracing::warn!(
-                                                target_peer_id = %target_peer_id,
-                                                error = %e,
-                                                "Failed to forward RTP to peer"
-                                            );
+                                    match local_track.write_rtp(&rtp_packet).await {
+                                        Ok(_) => {
+                                            metrics.rtp_packets_forwarded_total.inc();
+                                            metrics.rtp_bytes_forwarded_total.inc_by(rtp_packet.payload.len() as u64);
+                                        }
+                                        Err(e) => {
+                                            log_sampler.on_drop();
+                                            if log_sampler.is_early() {
+                                                tracing::warn!(
+                                                    target_peer_id = %target_peer_id,
+                                                    error = %e,
+                                                    "Failed to forward RTP to peer"
+                                                );
+                                            }
                                         }
                                     }
                                 }
@@ -239,11 +401,39 @@ impl SfuConnection {
 
                         // Push to recording pipeline for this specific peer
                         if let Some(ref recorder) = recording_manager {
-                            let rtp_data = rtp_packet.marshal().unwrap_or_default();
+                            if is_video && keyframe_scheduler.is_enabled() {
+                                let now = std::time::Instant::now();
+                                if keyframe {
+                                    keyframe_scheduler.on_keyframe(now);
+                                    recorder.record_keyframe(&room_id, &source_peer_id).await;
+                                }
+
+                                // Bound the GOP length of the recording by asking for a keyframe
+                                // when the publisher's encoder hasn't produced one recently
+                                if keyframe_scheduler.is_due(now) {
+                                    let active = recorder.is_actively_recording(&room_id, &source_peer_id).await;
+                                    if keyframe_scheduler.should_request(now, active) {
+                                        if let Err(e) = Self::send_pli(&pc, track.ssrc()).await {
+                                            tracing::warn!(
+                                                track_id = %tid,
+                                                error = %e,
+                                                "Failed to send recording keyframe request"
+                                            );
+                                        } else {
+                                            recorder.record_keyframe_request(&room_id, &source_peer_id).await;
+                                            tracing::debug!(
+                                                track_id = %tid,
+                                                "Sent PLI to bound recording keyframe interval"
+                                            );
+                                        }
+                                    }
+                                }
+                            }
+
                             if is_video {
-                                let _ = recorder.push_video_rtp(&room_id, &source_peer_id, &rtp_data).await;
+                                let _ = recorder.push_video_rtp(&room_id, &source_peer_id, &rtp_packet).await;
                             } else {
-                                let _ = recorder.push_audio_rtp(&room_id, &source_peer_id, &rtp_data).await;
+                                let _ = recorder.push_audio_rtp(&room_id, &source_peer_id, &rtp_packet).await;
                             }
                         }
                     }
@@ -258,12 +448,13 @@ impl SfuConnection {
                 }
             }
 
+            feedback.remove(&room_id, &tid);
             tracing::info!(
                 track_id = %tid,
-                packet_count = packet_count,
+                packet_count = log_sampler.total_packets(),
                 "Stopped forwarding track"
             );
-        });
+        }.in_current_span());
     }
 
     /// Send PLI (Picture Loss Indication) to request a keyframe
@@ -284,40 +475,164 @@ impl SfuConnection {
         Ok(())
     }
 
+    /// Asks the publisher of `track_id` for a keyframe on behalf of `subscriber`,
+    /// unless the track's PLI limiter coalesces the request or a recent
+    /// keyframe already answers it
+    pub async fn request_keyframe(
+        publisher: &Arc<RTCPeerConnection>,
+        media_ssrc: u32,
+        room_id: &str,
+        track_id: &str,
+        subscriber: &str,
+    ) -> Result<PliDecision, Box<dyn std::error::Error + Send + Sync>> {
+        let decision = rtcp::feedback().request_keyframe(room_id, track_id, subscriber, std::time::Instant::now());
+        if decision == PliDecision::Forward {
+            Self::send_pli(publisher, media_ssrc).await?;
+        }
+        Ok(decision)
+    }
+
+    /// Asks the publisher of `track_id` for a keyframe for a subscription that
+    /// was just attached. Only a PLI that is still waiting for its keyframe
+    /// answers it; a keyframe sent before the subscription does not.
+    pub async fn request_subscription_keyframe(
+        publisher: &Arc<RTCPeerConnection>,
+        media_ssrc: u32,
+        room_id: &str,
+        track_id: &str,
+        subscriber: &str,
+    ) -> Result<PliDecision, Box<dyn std::error::Error + Send + Sync>> {
+        let decision = rtcp::feedback().request_subscription_keyframe(room_id, track_id, subscriber, std::time::Instant::now());
+        if decision == PliDecision::Forward {
+            Self::send_pli(publisher, media_ssrc).await?;
+        }
+        Ok(decision)
+    }
+
+    /// Relays what a subscriber sends back for a forwarded video track to its
+    /// publisher: keyframe requests (PLI or FIR) through the track's limiter,
+    /// and NACKs through its relay, rewritten to the publisher's SSRC. Stops
+    /// once the sender is removed or either connection goes away.
+    pub fn forward_subscriber_feedback(
+        sender: Arc<RTCRtpSender>,
+        publisher: &Arc<RTCPeerConnection>,
+        media_ssrc: u32,
+        room_id: String,
+        track_id: String,
+        subscriber: String,
+    ) {
+        // Weak, so a subscriber that never sends RTCP doesn't keep a departed publisher's connection alive
+        let publisher = Arc::downgrade(publisher);
+        tokio::spawn(async move {
+            while let Ok((packets, _)) = sender.read_rtcp().await {
+                let mut keyframe_requested = false;
+                let mut nacks = Vec::new();
+                for packet in &packets {
+                    let packet = packet.as_any();
+                    if packet.is::<PictureLossIndication>() || packet.is::<FullIntraRequest>() {
+                        keyframe_requested = true;
+                    } else if let Some(nack) = packet.downcast_ref::<TransportLayerNack>() {
+                        nacks.push(nack.clone());
+                    }
+                }
+                if !keyframe_requested && nacks.is_empty() {
+                    continue;
+                }
+                let Some(publisher) = publisher.upgrade() else {
+                    break;
+                };
+
+                let now = std::time::Instant::now();
+                for nack in &nacks {
+                    let Some(relayed) = rtcp::feedback().relay_nack(&room_id, &track_id, nack, media_ssrc, now) else {
+                        continue;
+                    };
+                    if let Err(e) = publisher.write_rtcp(&[Box::new(relayed)]).await {
+                        tracing::debug!(track_id = %track_id, error = %e, "Failed to relay NACK to publisher");
+                    }
+                }
+
+                if !keyframe_requested {
+                    continue;
+                }
+                match Self::request_keyframe(&publisher, media_ssrc, &room_id, &track_id, &subscriber).await {
+                    Ok(PliDecision::Suppressed) => {
+                        tracing::debug!(track_id = %track_id, subscriber = %subscriber, "Suppressed keyframe request from subscriber");
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        tracing::warn!(track_id = %track_id, error = %e, "Failed to forward keyframe request");
+                    }
+                }
+            }
+        });
+    }
+
+    /// Send REMB (Receiver Estimated Maximum Bitrate) so the publisher's encoder adapts to loss
+    async fn send_remb(
+        peer_connection: &Arc<RTCPeerConnection>,
+        media_ssrc: u32,
+        bitrate_bps: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let remb = ReceiverEstimatedMaximumBitrate {
+            sender_ssrc: 0,
+            bitrate: bitrate_bps as f32,
+            ssrcs: vec![media_ssrc],
+        };
+
+        peer_connection
+            .write_rtcp(&[Box::new(remb)])
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)?;
+
+        Ok(())
+    }
+
     pub async fn add_existing_tracks(
         &self,
         track_manager: Arc<TrackManager>,
         existing_track_ids: Vec<String>,
-        source_connections: &std::collections::HashMap<String, Arc<SfuConnection>>,
+        source_connections: &std::collections::HashMap<PeerKey, Arc<SfuConnection>>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key = self.key();
         for track_id in existing_track_ids {
             if let Some((local_track, is_new, is_video, ssrc, source_peer_id)) = track_manager
-                .create_local_track_for_peer(&track_id, &self.peer_id)
+                .create_local_track_for_peer(&track_id, &key)
                 .await
             {
-                self.peer_connection.add_track(local_track).await?;
+                let rtp_sender = self.peer_connection.add_track(local_track).await?;
                 tracing::info!(
                     track_id = %track_id,
                     peer_id = %self.peer_id,
                     "Added existing track to peer"
                 );
 
-                // Send PLI for new video track subscriptions to get immediate keyframe
-                if is_new && is_video {
-                    if let Some(source_conn) = source_connections.get(&source_peer_id) {
-                        if let Err(e) = Self::send_pli(&source_conn.peer_connection, ssrc).await {
-                            tracing::warn!(
-                                track_id = %track_id,
-                                error = %e,
-                                "Failed to send PLI for new subscriber"
-                            );
-                        } else {
-                            tracing::info!(
+                let source = PeerKey::new(key.room_id.clone(), source_peer_id.clone());
+                if let Some(source_conn) = source_connections.get(&source).filter(|_| is_video) {
+                    Self::forward_subscriber_feedback(
+                        rtp_sender,
+                        &source_conn.peer_connection,
+                        ssrc,
+                        key.room_id.clone(),
+                        track_id.clone(),
+                        self.peer_id.clone(),
+                    );
+
+                    // Ask for a keyframe for the new subscription
+                    if is_new {
+                        match Self::request_subscription_keyframe(&source_conn.peer_connection, ssrc, &key.room_id, &track_id, &self.peer_id).await {
+                            Ok(decision) => tracing::info!(
                                 track_id = %track_id,
                                 target_peer_id = %self.peer_id,
                                 source_peer_id = %source_peer_id,
-                                "Sent PLI for new subscriber keyframe request"
-                            );
+                                ?decision,
+                                "Requested keyframe for new subscriber"
+                            ),
+                            Err(e) => tracing::warn!(
+                                track_id = %track_id,
+                                error = %e,
+                                "Failed to send PLI for new subscriber"
+                            ),
                         }
                     }
                 }
@@ -326,6 +641,11 @@ impl SfuConnection {
         Ok(())
     }
 
+    /// Registry key of this connection: one per room the peer is in
+    pub fn key(&self) -> PeerKey {
+        PeerKey::new(self.room_id.clone().unwrap_or_default(), self.peer_id.clone())
+    }
+
     pub async fn send_message(&self, message: Message) -> Result<(), mpsc::error::SendError<Message>> {
         self.sender.send(message)
     }
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use super::connection::SfuConnection;
+use super::pending::PendingIceCandidate;
+use super::room::PeerKey;
+
+/// Cap on signaling messages held for one peer while its connection is set up
+const MAX_HELD_SIGNALS: usize = 64;
+
+/// Setup still running after this long is considered failed; its marker no
+/// longer holds messages or blocks a new setup
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Signaling from a peer whose connection is still being set up, replayed
+/// once the setup completes
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeldSignal {
+    Answer { sdp: String },
+    IceCandidate(PendingIceCandidate),
+}
+
+/// Why a signaling message was not held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldError {
+    /// The peer has no connection setup in progress
+    NotSettingUp,
+    /// `max` messages are already held for the peer
+    Full { max: usize },
+}
+
+/// Live peer connections by (room_id, peer_id); a proctor in two rooms has two
+pub trait ConnectionRegistry: Send + Sync {
+    fn get(&self, key: &PeerKey) -> Option<Arc<SfuConnection>>;
+
+    fn contains(&self, key: &PeerKey) -> bool;
+
+    fn insert(&self, key: PeerKey, connection: Arc<SfuConnection>);
+
+    /// Removes the connection, and ends a setup still in progress for it
+    fn remove(&self, key: &PeerKey) -> Option<Arc<SfuConnection>>;
+
+    fn count(&self) -> usize;
+
+    /// Every connection at this moment, for work that awaits between peers
+    fn snapshot(&self) -> HashMap<PeerKey, Arc<SfuConnection>>;
+
+    /// Marks `key` as setting up. False when it is already connected or
+    /// another setup for it is in progress.
+    fn begin_setup(&self, key: &PeerKey) -> bool;
+
+    /// True between `begin_setup` and `finish_setup`, until the setup times out
+    fn is_setting_up(&self, key: &PeerKey) -> bool;
+
+    /// Holds a message for a peer whose setup is in progress
+    fn hold(&self, key: &PeerKey, signal: HeldSignal) -> Result<usize, HoldError>;
+
+    /// Ends the setup and returns the messages held meanwhile, oldest first
+    fn finish_setup(&self, key: &PeerKey) -> Vec<HeldSignal>;
+}
+
+struct Setup {
+    started_at: Instant,
+    held: Vec<HeldSignal>,
+}
+
+/// In-memory registry. The locks are never held across an await; callers that
+/// walk every connection take a snapshot.
+pub struct PeerConnections {
+    connections: RwLock<HashMap<PeerKey, Arc<SfuConnection>>>,
+    /// Peers between `begin_setup` and `finish_setup`
+    setups: Mutex<HashMap<PeerKey, Setup>>,
+    max_held: usize,
+    setup_timeout: Duration,
+}
+
+impl Default for PeerConnections {
+    fn default() -> Self {
+        Self {
+            connections: RwLock::new(HashMap::new()),
+            setups: Mutex::new(HashMap::new()),
+            max_held: MAX_HELD_SIGNALS,
+            setup_timeout: SETUP_TIMEOUT,
+        }
+    }
+}
+
+impl PeerConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ConnectionRegistry for PeerConnections {
+    fn get(&self, key: &PeerKey) -> Option<Arc<SfuConnection>> {
+        self.connections.read().unwrap().get(key).cloned()
+    }
+
+    fn contains(&self, key: &PeerKey) -> bool {
+        self.connections.read().unwrap().contains_key(key)
+    }
+
+    fn insert(&self, key: PeerKey, connection: Arc<SfuConnection>) {
+        self.connections.write().unwrap().insert(key, connection);
+    }
+
+    fn remove(&self, key: &PeerKey) -> Option<Arc<SfuConnection>> {
+        self.setups.lock().unwrap().remove(key);
+        self.connections.write().unwrap().remove(key)
+    }
+
+    fn count(&self) -> usize {
+        self.connections.read().unwrap().len()
+    }
+
+    fn snapshot(&self) -> HashMap<PeerKey, Arc<SfuConnection>> {
+        self.connections.read().unwrap().clone()
+    }
+
+    fn begin_setup(&self, key: &PeerKey) -> bool {
+        let mut setups = self.setups.lock().unwrap();
+        if self.connections.read().unwrap().contains_key(key) {
+            return false;
+        }
+        if setups.get(key).is_some_and(|setup| setup.started_at.elapsed() < self.setup_timeout) {
+            return false;
+        }
+        setups.insert(key.clone(), Setup { started_at: Instant::now(), held: Vec::new() });
+        true
+    }
+
+    fn is_setting_up(&self, key: &PeerKey) -> bool {
+        self.setups
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|setup| setup.started_at.elapsed() < self.setup_timeout)
+    }
+
+    fn hold(&self, key: &PeerKey, signal: HeldSignal) -> Result<usize, HoldError> {
+        let mut setups = self.setups.lock().unwrap();
+        let Some(setup) = setups.get_mut(key) else {
+            return Err(HoldError::NotSettingUp);
+        };
+        if setup.started_at.elapsed() >= self.setup_timeout {
+            tracing::warn!(peer = %key, held = setup.held.len(), "Connection setup timed out, dropping held signaling");
+            setups.remove(key);
+            return Err(HoldError::NotSettingUp);
+        }
+        if setup.held.len() >= self.max_held {
+            return Err(HoldError::Full { max: self.max_held });
+        }
+        setup.held.push(signal);
+        Ok(setup.held.len())
+    }
+
+    fn finish_setup(&self, key: &PeerKey) -> Vec<HeldSignal> {
+        self.setups
+            .lock()
+            .unwrap()
+            .remove(key)
+            .map(|setup| setup.held)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::track_manager::TrackManager;
+    use crate::config::WebRTCConfig;
+    use crate::sfu::webrtc_utils::{api_factory, WebRtcEngineConfig};
+    use tokio::sync::mpsc;
+
+    async fn connection(room_id: &str, peer_id: &str) -> Arc<SfuConnection> {
+        let api = api_factory().build(&WebRtcEngineConfig::default()).unwrap();
+        let (sender, _receiver) = mpsc::unbounded_channel();
+        Arc::new(
+            SfuConnection::new(
+                peer_id.to_string(),
+                room_id.to_string(),
+                sender,
+                &api,
+                &WebRTCConfig::default(),
+                Arc::new(TrackManager::new()),
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_registry_tracks_connections_by_peer() {
+        let registry = PeerConnections::new();
+        let proctor = PeerKey::new("123456", "proctor");
+        let student = PeerKey::new("123456", "student");
+        registry.insert(proctor.clone(), connection("123456", "proctor").await);
+        registry.insert(student.clone(), connection("123456", "student").await);
+
+        assert_eq!(registry.count(), 2);
+        assert!(registry.contains(&student));
+        assert_eq!(registry.get(&proctor).unwrap().peer_id, "proctor");
+
+        // A snapshot is unaffected by later changes
+        let snapshot = registry.snapshot();
+        let removed = registry.remove(&student).unwrap();
+        assert_eq!(removed.peer_id, "student");
+        assert!(!registry.contains(&student));
+        assert!(registry.remove(&student).is_none());
+        assert_eq!(snapshot.len(), 2);
+
+        for connection in snapshot.into_values() {
+            connection.close().await;
+        }
+    }
+
+    fn answer(n: usize) -> HeldSignal {
+        HeldSignal::Answer { sdp: format!("v=0 {}", n) }
+    }
+
+    #[tokio::test]
+    async fn test_signaling_held_during_setup() {
+        let registry = PeerConnections { max_held: 3, ..PeerConnections::new() };
+        let student = PeerKey::new("123456", "student");
+
+        // Nothing is held for a peer that is not setting up
+        assert_eq!(registry.hold(&student, answer(0)), Err(HoldError::NotSettingUp));
+
+        assert!(registry.begin_setup(&student));
+        assert!(!registry.begin_setup(&student));
+        let candidate = HeldSignal::IceCandidate(PendingIceCandidate {
+            candidate: "candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host".to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_mline_index: Some(0),
+        });
+        assert_eq!(registry.hold(&student, candidate.clone()), Ok(1));
+        assert_eq!(registry.hold(&student, answer(1)), Ok(2));
+
+        // The connection is visible before the setup completes; messages are still held
+        registry.insert(student.clone(), connection("123456", "student").await);
+        assert_eq!(registry.hold(&student, answer(2)), Ok(3));
+        assert_eq!(registry.hold(&student, answer(3)), Err(HoldError::Full { max: 3 }));
+
+        assert_eq!(registry.finish_setup(&student), vec![candidate, answer(1), answer(2)]);
+        assert_eq!(registry.hold(&student, answer(4)), Err(HoldError::NotSettingUp));
+        assert!(registry.finish_setup(&student).is_empty());
+
+        // Connected peers cannot start another setup until they leave
+        assert!(!registry.begin_setup(&student));
+        registry.remove(&student).unwrap().close().await;
+        assert!(registry.begin_setup(&student));
+        assert!(registry.remove(&student).is_none());
+        assert_eq!(registry.hold(&student, answer(5)), Err(HoldError::NotSettingUp));
+    }
+
+    #[test]
+    fn test_stale_setup_stops_holding() {
+        let registry = PeerConnections { setup_timeout: Duration::ZERO, ..PeerConnections::new() };
+        let student = PeerKey::new("123456", "student");
+
+        assert!(registry.begin_setup(&student));
+        assert_eq!(registry.hold(&student, answer(0)), Err(HoldError::NotSettingUp));
+        // A timed-out setup does not block the next one
+        assert!(registry.begin_setup(&student));
+        assert!(registry.begin_setup(&student));
+    }
+
+    #[tokio::test]
+    async fn test_same_peer_in_two_rooms_keeps_both_connections() {
+        let registry = PeerConnections::new();
+        let room_a = PeerKey::new("111111", "ta");
+        let room_b = PeerKey::new("222222", "ta");
+        registry.insert(room_a.clone(), connection("111111", "ta").await);
+        registry.insert(room_b.clone(), connection("222222", "ta").await);
+
+        assert_eq!(registry.count(), 2);
+        assert_eq!(registry.get(&room_b).unwrap().room_id.as_deref(), Some("222222"));
+
+        registry.remove(&room_a).unwrap().close().await;
+        assert!(registry.contains(&room_b));
+        registry.remove(&room_b).unwrap().close().await;
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::config::env;
+
+/// Default time a join request waits for the proctor before it is escalated
+const DEFAULT_JOIN_ESCALATION_SECS: u64 = 60;
+
+/// What happens to a join request the proctor still has not answered one
+/// interval after being reminded. Chosen by the proctor at room creation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscalationPolicy {
+    /// Remind the proctor, then keep the student waiting
+    #[default]
+    RemindOnly,
+    AutoApprove,
+    AutoDeny,
+}
+
+impl EscalationPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EscalationPolicy::RemindOnly => "remind_only",
+            EscalationPolicy::AutoApprove => "auto_approve",
+            EscalationPolicy::AutoDeny => "auto_deny",
+        }
+    }
+}
+
+/// How far an unanswered join request has been escalated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EscalationStage {
+    #[default]
+    Waiting,
+    Reminded,
+    /// Answered by the proctor or by the fallback; nothing further is due
+    Decided,
+}
+
+/// Escalation step a join request reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscalationAction {
+    /// The JoinRequest was sent to the proctor again
+    Reminded,
+    AutoApproved,
+    AutoDenied,
+    /// The fallback is `remind_only`; the request waits for the proctor or its TTL
+    KeptWaiting,
+}
+
+impl EscalationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EscalationAction::Reminded => "reminded",
+            EscalationAction::AutoApproved => "auto_approved",
+            EscalationAction::AutoDenied => "auto_denied",
+            EscalationAction::KeptWaiting => "kept_waiting",
+        }
+    }
+
+    /// Action taken once the reminder went unanswered too
+    pub fn fallback(policy: EscalationPolicy) -> Self {
+        match policy {
+            EscalationPolicy::RemindOnly => EscalationAction::KeptWaiting,
+            EscalationPolicy::AutoApprove => EscalationAction::AutoApproved,
+            EscalationPolicy::AutoDeny => EscalationAction::AutoDenied,
+        }
+    }
+}
+
+/// Instance-wide escalation timing; each room picks its own fallback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinEscalation {
+    /// Wait before the reminder, and again before the fallback. `None` disables escalation.
+    pub interval: Option<Duration>,
+    /// Also raise an operational alert with each reminder
+    pub alert: bool,
+}
+
+impl JoinEscalation {
+    /// Reads `JOIN_ESCALATION_SECS` (0 disables) and `JOIN_ESCALATION_ALERT`
+    pub fn from_env() -> Self {
+        let secs = env::get_parsed("JOIN_ESCALATION_SECS").unwrap_or(DEFAULT_JOIN_ESCALATION_SECS);
+        Self {
+            interval: Some(Duration::from_secs(secs)).filter(|interval| !interval.is_zero()),
+            alert: env::get_bool("JOIN_ESCALATION_ALERT", false),
+        }
+    }
+}
+
+impl Default for JoinEscalation {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(DEFAULT_JOIN_ESCALATION_SECS)),
+            alert: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_names_round_trip() {
+        for policy in [EscalationPolicy::RemindOnly, EscalationPolicy::AutoApprove, EscalationPolicy::AutoDeny] {
+            let json = serde_json::to_string(&policy).unwrap();
+            assert_eq!(json, format!("\"{}\"", policy.as_str()));
+            assert_eq!(serde_json::from_str::<EscalationPolicy>(&json).unwrap(), policy);
+        }
+        assert!(serde_json::from_str::<EscalationPolicy>("\"auto_maybe\"").is_err());
+    }
+
+    #[test]
+    fn test_fallback_follows_policy() {
+        assert_eq!(EscalationAction::fallback(EscalationPolicy::RemindOnly), EscalationAction::KeptWaiting);
+        assert_eq!(EscalationAction::fallback(EscalationPolicy::AutoApprove), EscalationAction::AutoApproved);
+        assert_eq!(EscalationAction::fallback(EscalationPolicy::AutoDeny), EscalationAction::AutoDenied);
+    }
+}
@@ -0,0 +1,94 @@
+use serde::Serialize;
+
+/// ICE candidate type, as carried in the `typ` field of a candidate line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum CandidateType {
+    #[serde(rename = "host")]
+    Host,
+    #[serde(rename = "srflx")]
+    ServerReflexive,
+    #[serde(rename = "prflx")]
+    PeerReflexive,
+    #[serde(rename = "relay")]
+    Relay,
+}
+
+impl CandidateType {
+    /// Classifies a candidate attribute, with or without its `a=` SDP prefix
+    pub fn parse(candidate: &str) -> Option<Self> {
+        let mut fields = candidate.split_whitespace();
+        if !fields.next()?.trim_start_matches("a=").starts_with("candidate:") {
+            return None;
+        }
+        fields.find(|field| *field == "typ")?;
+        match fields.next()? {
+            "host" => Some(Self::Host),
+            "srflx" => Some(Self::ServerReflexive),
+            "prflx" => Some(Self::PeerReflexive),
+            "relay" => Some(Self::Relay),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Host => "host",
+            Self::ServerReflexive => "srflx",
+            Self::PeerReflexive => "prflx",
+            Self::Relay => "relay",
+        }
+    }
+}
+
+/// Types of the candidates listed in an SDP blob, in order of appearance
+pub fn sdp_candidate_types(sdp: &str) -> Vec<CandidateType> {
+    sdp.lines()
+        .filter(|line| line.starts_with("a=candidate:"))
+        .filter_map(CandidateType::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_candidate_types() {
+        let cases = [
+            ("candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host", Some(CandidateType::Host)),
+            (
+                "candidate:2 1 udp 1686052607 203.0.113.7 61000 typ srflx raddr 192.0.2.1 rport 54400",
+                Some(CandidateType::ServerReflexive),
+            ),
+            ("a=candidate:3 1 udp 41885439 198.51.100.9 3478 typ relay raddr 0.0.0.0 rport 0", Some(CandidateType::Relay)),
+            ("candidate:4 1 udp 1845501695 203.0.113.8 50000 typ prflx", Some(CandidateType::PeerReflexive)),
+            ("candidate:5 1 tcp 1518280447 192.0.2.1 9 typ host tcptype active", Some(CandidateType::Host)),
+        ];
+        for (candidate, expected) in cases {
+            assert_eq!(CandidateType::parse(candidate), expected, "{}", candidate);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_candidates() {
+        assert_eq!(CandidateType::parse(""), None);
+        assert_eq!(CandidateType::parse("candidate:1 1 udp 2122260223 192.0.2.1 54400"), None);
+        assert_eq!(CandidateType::parse("candidate:1 1 udp 2122260223 192.0.2.1 54400 typ"), None);
+        assert_eq!(CandidateType::parse("candidate:1 1 udp 2122260223 192.0.2.1 54400 typ bogus"), None);
+        assert_eq!(CandidateType::parse("a=mid:0 typ host"), None);
+    }
+
+    #[test]
+    fn test_sdp_candidate_types() {
+        let sdp = "v=0\r\n\
+                   m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\n\
+                   a=candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host\r\n\
+                   a=candidate:2 1 udp 1686052607 203.0.113.7 61000 typ srflx raddr 192.0.2.1 rport 54400\r\n\
+                   a=end-of-candidates\r\n";
+        assert_eq!(
+            sdp_candidate_types(sdp),
+            vec![CandidateType::Host, CandidateType::ServerReflexive]
+        );
+        assert_eq!(CandidateType::Relay.as_str(), "relay");
+    }
+}
@@ -0,0 +1,333 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use webrtc::api::API;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+
+use super::ice::{sdp_candidate_types, CandidateType};
+use super::supervisor::TaskSupervisor;
+use super::webrtc_utils::{api_factory, get_ice_servers, WebRtcEngineConfig};
+use crate::config::{env, WebRTCConfig};
+use crate::health::alert::{alerter, Alert};
+
+/// Default hard limit on one self-test run, across all servers
+const DEFAULT_ICE_SELFTEST_TIMEOUT_SECS: u64 = 10;
+
+/// Extra time allowed for tearing down peer connections after gathering stops
+const TEARDOWN_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IceServerKind {
+    Stun,
+    Turn,
+}
+
+impl IceServerKind {
+    fn from_url(url: &str) -> Self {
+        if url.starts_with("turn:") || url.starts_with("turns:") {
+            Self::Turn
+        } else {
+            Self::Stun
+        }
+    }
+
+    /// Candidate type that can only come from a working server of this kind
+    fn proves_reachable(self) -> CandidateType {
+        match self {
+            Self::Stun => CandidateType::ServerReflexive,
+            Self::Turn => CandidateType::Relay,
+        }
+    }
+}
+
+/// Outcome of gathering through a single configured ICE server URL
+#[derive(Debug, Clone, Serialize)]
+pub struct IceServerResult {
+    pub url: String,
+    pub kind: IceServerKind,
+    /// Candidates gathered, by type
+    pub candidates: BTreeMap<CandidateType, usize>,
+    pub gathering_ms: u64,
+    pub reachable: bool,
+    pub error: Option<String>,
+}
+
+impl IceServerResult {
+    fn evaluate(url: &str, candidates: &[CandidateType], elapsed: Duration, error: Option<String>) -> Self {
+        let kind = IceServerKind::from_url(url);
+        let mut counts = BTreeMap::new();
+        for candidate in candidates {
+            *counts.entry(*candidate).or_insert(0) += 1;
+        }
+
+        let reachable = counts.contains_key(&kind.proves_reachable());
+        let error = match (reachable, error) {
+            (true, _) => None,
+            (false, Some(error)) => Some(error),
+            (false, None) => Some(format!("no {} candidates gathered", kind.proves_reachable().as_str())),
+        };
+
+        Self {
+            url: url.to_string(),
+            kind,
+            candidates: counts,
+            gathering_ms: elapsed.as_millis() as u64,
+            reachable,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IceSelfTestReport {
+    /// Unix time in milliseconds when the run started
+    pub ran_at: u64,
+    pub duration_ms: u64,
+    pub timeout_ms: u64,
+    /// Candidate types produced by any server
+    pub candidate_types: Vec<CandidateType>,
+    pub servers: Vec<IceServerResult>,
+    /// Every configured server produced the candidate type that proves it works
+    pub healthy: bool,
+}
+
+impl IceSelfTestReport {
+    fn new(ran_at: u64, duration: Duration, timeout: Duration, servers: Vec<IceServerResult>) -> Self {
+        let mut candidate_types: Vec<CandidateType> = servers
+            .iter()
+            .flat_map(|server| server.candidates.keys().copied())
+            .collect();
+        candidate_types.sort();
+        candidate_types.dedup();
+
+        Self {
+            ran_at,
+            duration_ms: duration.as_millis() as u64,
+            timeout_ms: timeout.as_millis() as u64,
+            candidate_types,
+            healthy: servers.iter().all(|server| server.reachable),
+            servers,
+        }
+    }
+}
+
+/// A self-test is already gathering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestRunning;
+
+/// Checks that the configured STUN/TURN servers actually produce candidates by
+/// gathering through a throwaway peer connection per server
+pub struct IceSelfTest {
+    api: Arc<API>,
+    ice_servers: Vec<RTCIceServer>,
+    timeout: Duration,
+    running: tokio::sync::Mutex<()>,
+    last: RwLock<Option<IceSelfTestReport>>,
+}
+
+static SELFTEST: OnceLock<IceSelfTest> = OnceLock::new();
+
+/// Process-wide self-test, shared by the startup run, the admin route and the health check
+pub fn selftest() -> &'static IceSelfTest {
+    SELFTEST.get_or_init(|| IceSelfTest::new(&WebRTCConfig::from_env()))
+}
+
+/// Sets up the self-test for the servers in `webrtc` and runs it once in the
+/// background unless `ICE_SELFTEST_ON_STARTUP=false`; startup never waits for it
+pub fn spawn_startup_selftest(tasks: &TaskSupervisor, webrtc: &WebRTCConfig) {
+    SELFTEST.get_or_init(|| IceSelfTest::new(webrtc));
+    if !env::get_bool("ICE_SELFTEST_ON_STARTUP", true) {
+        return;
+    }
+
+    tasks.spawn("ice_selftest", |cancel| async move {
+        tokio::select! {
+            _ = selftest().run() => {}
+            _ = cancel.cancelled() => {}
+        }
+    });
+}
+
+impl IceSelfTest {
+    /// Tests the servers in `webrtc`; reads `ICE_SELFTEST_TIMEOUT_SECS`
+    pub fn new(webrtc: &WebRTCConfig) -> Self {
+        let timeout_secs = env::get_parsed::<u64>("ICE_SELFTEST_TIMEOUT_SECS")
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_ICE_SELFTEST_TIMEOUT_SECS);
+
+        // Shares the server's engine when the config matches; a config the server
+        // would reject still leaves the self-test usable
+        let engine_config = WebRtcEngineConfig::from_env().unwrap_or_default();
+        let api = api_factory()
+            .build(&engine_config)
+            .or_else(|_| api_factory().build(&WebRtcEngineConfig::default()))
+            .expect("default WebRTC engine config is valid");
+
+        Self {
+            api,
+            ice_servers: get_ice_servers(webrtc),
+            timeout: Duration::from_secs(timeout_secs),
+            running: tokio::sync::Mutex::new(()),
+            last: RwLock::new(None),
+        }
+    }
+
+    pub fn last_report(&self) -> Option<IceSelfTestReport> {
+        self.last.read().unwrap().clone()
+    }
+
+    /// Gathers through every configured server concurrently, bounded by the timeout.
+    /// Unreachable TURN servers raise an alert.
+    pub async fn run(&self) -> Result<IceSelfTestReport, SelfTestRunning> {
+        let _running = self.running.try_lock().map_err(|_| SelfTestRunning)?;
+
+        let ran_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + self.timeout;
+
+        let servers = self
+            .ice_servers
+            .iter()
+            .cloned()
+            .flat_map(|server| {
+                server.urls.clone().into_iter().map(move |url| RTCIceServer {
+                    urls: vec![url],
+                    ..server.clone()
+                })
+            });
+        let results = futures::future::join_all(servers.map(|server| self.gather(server, deadline))).await;
+        let report = IceSelfTestReport::new(ran_at, started.elapsed(), self.timeout, results);
+
+        for server in &report.servers {
+            match (server.kind, server.reachable) {
+                (_, true) => {}
+                (IceServerKind::Turn, false) => alerter().raise(Alert::new(
+                    "ice_turn_unreachable",
+                    format!("TURN server {} failed the ICE self-test", server.url),
+                    serde_json::json!({ "url": server.url, "error": server.error }),
+                )),
+                (IceServerKind::Stun, false) => {
+                    tracing::warn!(url = %server.url, error = ?server.error, "STUN server failed the ICE self-test");
+                }
+            }
+        }
+
+        tracing::info!(
+            healthy = report.healthy,
+            duration_ms = report.duration_ms,
+            candidate_types = ?report.candidate_types.iter().map(CandidateType::as_str).collect::<Vec<_>>(),
+            "ICE self-test finished"
+        );
+
+        *self.last.write().unwrap() = Some(report.clone());
+        Ok(report)
+    }
+
+    async fn gather(&self, server: RTCIceServer, deadline: tokio::time::Instant) -> IceServerResult {
+        let url = server.urls.first().cloned().unwrap_or_default();
+        let started = Instant::now();
+
+        let gathering = async {
+            let config = RTCConfiguration {
+                ice_servers: vec![server],
+                ..Default::default()
+            };
+            let peer_connection = self.api.new_peer_connection(config).await?;
+
+            // A data channel is enough to give the offer a section to gather for
+            peer_connection.create_data_channel("ice-selftest", None).await?;
+            let offer = peer_connection.create_offer(None).await?;
+            let mut gathering_complete = peer_connection.gathering_complete_promise().await;
+            peer_connection.set_local_description(offer).await?;
+
+            let timed_out = tokio::time::timeout_at(deadline, gathering_complete.recv()).await.is_err();
+            let candidates = peer_connection
+                .local_description()
+                .await
+                .map(|description| sdp_candidate_types(&description.sdp))
+                .unwrap_or_default();
+            let elapsed = started.elapsed();
+
+            let _ = peer_connection.close().await;
+            Ok::<_, webrtc::Error>((candidates, elapsed, timed_out))
+        };
+
+        match tokio::time::timeout_at(deadline + TEARDOWN_GRACE, gathering).await {
+            Ok(Ok((candidates, elapsed, timed_out))) => {
+                let error = timed_out.then(|| "gathering did not complete before the timeout".to_string());
+                IceServerResult::evaluate(&url, &candidates, elapsed, error)
+            }
+            Ok(Err(e)) => IceServerResult::evaluate(&url, &[], started.elapsed(), Some(e.to_string())),
+            Err(_) => IceServerResult::evaluate(
+                &url,
+                &[],
+                started.elapsed(),
+                Some("peer connection setup did not finish before the timeout".to_string()),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_reachable_only_with_its_candidate_type() {
+        use CandidateType::*;
+
+        let stun = IceServerResult::evaluate("stun:stun.example.com:19302", &[Host, ServerReflexive], Duration::from_millis(40), None);
+        assert!(stun.reachable);
+        assert_eq!(stun.kind, IceServerKind::Stun);
+        assert!(stun.error.is_none());
+
+        // Host candidates alone say nothing about the TURN server
+        let turn = IceServerResult::evaluate("turn:turn.example.com:3478", &[Host, Host], Duration::from_millis(40), None);
+        assert!(!turn.reachable);
+        assert_eq!(turn.kind, IceServerKind::Turn);
+        assert_eq!(turn.candidates.get(&Host), Some(&2));
+        assert_eq!(turn.error.as_deref(), Some("no relay candidates gathered"));
+
+        let turns = IceServerResult::evaluate("turns:turn.example.com:5349", &[Host, Relay], Duration::from_millis(40), None);
+        assert!(turns.reachable);
+    }
+
+    #[test]
+    fn test_gathering_error_reported_when_unreachable() {
+        let result = IceServerResult::evaluate(
+            "turn:turn.example.com:3478",
+            &[CandidateType::Host],
+            Duration::from_secs(10),
+            Some("gathering did not complete before the timeout".to_string()),
+        );
+        assert!(!result.reachable);
+        assert_eq!(result.error.as_deref(), Some("gathering did not complete before the timeout"));
+        assert_eq!(result.gathering_ms, 10_000);
+    }
+
+    #[test]
+    fn test_report_combines_servers() {
+        use CandidateType::*;
+
+        let servers = vec![
+            IceServerResult::evaluate("stun:stun.example.com:19302", &[Host, ServerReflexive], Duration::from_millis(40), None),
+            IceServerResult::evaluate("turn:turn.example.com:3478", &[Host], Duration::from_millis(90), None),
+        ];
+        let report = IceSelfTestReport::new(0, Duration::from_millis(95), Duration::from_secs(10), servers);
+
+        assert!(!report.healthy);
+        assert_eq!(report.candidate_types, vec![Host, ServerReflexive]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["candidate_types"], serde_json::json!(["host", "srflx"]));
+        assert_eq!(json["servers"][0]["candidates"]["srflx"], 1);
+        assert_eq!(json["servers"][1]["kind"], "turn");
+        assert_eq!(json["servers"][1]["reachable"], false);
+    }
+}
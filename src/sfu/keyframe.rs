@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Default minimum time between PLIs forwarded to a publisher for one track
+pub const DEFAULT_PLI_MIN_INTERVAL_MS: u64 = 1000;
+
+/// Returns true if the RTP payload starts a VP8 keyframe.
+///
+/// Parses the VP8 payload descriptor (RFC 7741 section 4.2) and inspects the
+/// P bit of the VP8 payload header. Only the first packet of partition 0
+/// carries the payload header, so any other packet returns false.
+pub fn is_vp8_keyframe(payload: &[u8]) -> bool {
+    let Some(&first) = payload.first() else {
+        return false;
+    };
+
+    let extended = first & 0x80 != 0;
+    let start_of_partition = first & 0x10 != 0;
+    let partition_id = first & 0x07;
+
+    if !start_of_partition || partition_id != 0 {
+        return false;
+    }
+
+    let mut offset = 1;
+
+    if extended {
+        let Some(&ext) = payload.get(offset) else {
+            return false;
+        };
+        offset += 1;
+
+        let has_picture_id = ext & 0x80 != 0;
+        let has_tl0_pic_idx = ext & 0x40 != 0;
+        let has_tid = ext & 0x20 != 0;
+        let has_key_idx = ext & 0x10 != 0;
+
+        if has_picture_id {
+            let Some(&pid) = payload.get(offset) else {
+                return false;
+            };
+            // M bit set means a 15-bit picture ID
+            offset += if pid & 0x80 != 0 { 2 } else { 1 };
+        }
+        if has_tl0_pic_idx {
+            offset += 1;
+        }
+        if has_tid || has_key_idx {
+            offset += 1;
+        }
+    }
+
+    match payload.get(offset) {
+        // P bit (inverse key frame flag) is 0 for keyframes
+        Some(&header) => header & 0x01 == 0,
+        None => false,
+    }
+}
+
+/// Decides when to ask a recorded publisher for a keyframe.
+///
+/// A PLI is due once `interval` has passed since both the last keyframe seen
+/// from the publisher and the last PLI we sent for the recording.
+pub struct RecordingKeyframeScheduler {
+    interval: Duration,
+    last_keyframe: Option<Instant>,
+    last_request: Option<Instant>,
+}
+
+impl RecordingKeyframeScheduler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_keyframe: None,
+            last_request: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.interval.is_zero()
+    }
+
+    /// Note that the publisher produced a keyframe
+    pub fn on_keyframe(&mut self, now: Instant) {
+        self.last_keyframe = Some(now);
+    }
+
+    /// Returns true if the interval has elapsed and a PLI may be sent.
+    /// Callers should check `recording_active` only when this returns true.
+    pub fn is_due(&self, now: Instant) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+
+        let elapsed_since = |t: Option<Instant>| t.map(|t| now.duration_since(t) >= self.interval).unwrap_or(true);
+        elapsed_since(self.last_keyframe) && elapsed_since(self.last_request)
+    }
+
+    /// Returns true and marks the request as sent if a PLI should go out now.
+    /// Never fires while the recording is inactive (paused or stopped).
+    pub fn should_request(&mut self, now: Instant, recording_active: bool) -> bool {
+        if !recording_active || !self.is_due(now) {
+            return false;
+        }
+        self.last_request = Some(now);
+        true
+    }
+}
+
+/// What became of one subscriber keyframe request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PliDecision {
+    /// Sent on to the publisher as a PLI
+    Forward,
+    /// The publisher produced a keyframe within the window; nothing to ask for
+    SatisfiedByKeyframe,
+    /// Coalesced into the PLI already forwarded within the window
+    Suppressed,
+}
+
+/// Coalesces keyframe requests from every subscriber of one published track
+/// into at most one PLI to the publisher per `min_interval`, so a subscriber
+/// that keeps asking can't make the publisher's encoder emit keyframes back
+/// to back.
+#[derive(Debug, Clone)]
+pub struct PliLimiter {
+    min_interval: Duration,
+    last_forwarded: Option<Instant>,
+    last_keyframe: Option<Instant>,
+    forwarded: u64,
+    /// Requests suppressed per subscriber peer ID
+    suppressed: BTreeMap<String, u64>,
+}
+
+impl PliLimiter {
+    /// A zero `min_interval` forwards every request
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_forwarded: None,
+            last_keyframe: None,
+            forwarded: 0,
+            suppressed: BTreeMap::new(),
+        }
+    }
+
+    /// Note that the publisher produced a keyframe
+    pub fn on_keyframe(&mut self, now: Instant) {
+        self.last_keyframe = Some(now);
+    }
+
+    /// Decides what to do with a keyframe request from `subscriber`, marking
+    /// the PLI as sent when it is to be forwarded
+    pub fn on_request(&mut self, subscriber: &str, now: Instant) -> PliDecision {
+        let within_window = |t: Option<Instant>| t.is_some_and(|t| now.saturating_duration_since(t) < self.min_interval);
+
+        if within_window(self.last_keyframe) {
+            PliDecision::SatisfiedByKeyframe
+        } else if within_window(self.last_forwarded) {
+            *self.suppressed.entry(subscriber.to_string()).or_default() += 1;
+            PliDecision::Suppressed
+        } else {
+            self.last_forwarded = Some(now);
+            self.forwarded += 1;
+            PliDecision::Forward
+        }
+    }
+
+    /// Decides what to do with the keyframe request of a subscription that
+    /// was just attached. A keyframe the publisher sent before the subscriber
+    /// existed never reached it, so only a PLI still waiting for its keyframe
+    /// answers the request; otherwise it is forwarded regardless of the window.
+    pub fn on_subscribe(&mut self, subscriber: &str, now: Instant) -> PliDecision {
+        let awaiting_keyframe = self.last_forwarded.is_some_and(|forwarded| {
+            now.saturating_duration_since(forwarded) < self.min_interval
+                && self.last_keyframe.is_none_or(|keyframe| keyframe < forwarded)
+        });
+
+        if awaiting_keyframe {
+            *self.suppressed.entry(subscriber.to_string()).or_default() += 1;
+            PliDecision::Suppressed
+        } else {
+            self.last_forwarded = Some(now);
+            self.forwarded += 1;
+            PliDecision::Forward
+        }
+    }
+
+    /// PLIs forwarded to the publisher so far
+    pub fn forwarded(&self) -> u64 {
+        self.forwarded
+    }
+
+    pub fn suppressed(&self) -> &BTreeMap<String, u64> {
+        &self.suppressed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Chrome keyframe: X=1 S=1 PID=0, I=1 with 15-bit picture ID, then frame tag with P=0
+    // followed by the keyframe start code 9d 01 2a
+    const VP8_KEYFRAME: [u8; 10] = [0x90, 0x80, 0x80, 0x2a, 0x50, 0x42, 0x00, 0x9d, 0x01, 0x2a];
+
+    // Same descriptor, frame tag with P=1 (interframe)
+    const VP8_INTERFRAME: [u8; 7] = [0x90, 0x80, 0x80, 0x2b, 0x31, 0x0b, 0x00];
+
+    // Continuation packet of the keyframe (S=0)
+    const VP8_CONTINUATION: [u8; 6] = [0x80, 0x80, 0x80, 0x2a, 0x50, 0x42];
+
+    #[test]
+    fn test_detects_keyframe() {
+        assert!(is_vp8_keyframe(&VP8_KEYFRAME));
+    }
+
+    #[test]
+    fn test_interframe_is_not_keyframe() {
+        assert!(!is_vp8_keyframe(&VP8_INTERFRAME));
+    }
+
+    #[test]
+    fn test_continuation_packet_is_not_keyframe() {
+        assert!(!is_vp8_keyframe(&VP8_CONTINUATION));
+    }
+
+    #[test]
+    fn test_keyframe_without_extension() {
+        // Firefox-style minimal descriptor: X=0 S=1 PID=0
+        assert!(is_vp8_keyframe(&[0x10, 0x50, 0x42, 0x00, 0x9d, 0x01, 0x2a]));
+        assert!(!is_vp8_keyframe(&[0x10, 0x31, 0x0b, 0x00]));
+    }
+
+    #[test]
+    fn test_keyframe_with_7bit_picture_id_and_tl0() {
+        // X=1 S=1, I=1 L=1 T=1, 7-bit picture ID, TL0PICIDX, TID byte
+        assert!(is_vp8_keyframe(&[0x90, 0xe0, 0x05, 0x11, 0x20, 0x50, 0x42, 0x00, 0x9d]));
+    }
+
+    #[test]
+    fn test_non_zero_partition_is_not_keyframe() {
+        assert!(!is_vp8_keyframe(&[0x11, 0x50, 0x42]));
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        assert!(!is_vp8_keyframe(&[]));
+        assert!(!is_vp8_keyframe(&[0x90]));
+        assert!(!is_vp8_keyframe(&[0x90, 0x80, 0x80]));
+    }
+
+    #[test]
+    fn test_scheduler_disabled_with_zero_interval() {
+        let mut scheduler = RecordingKeyframeScheduler::new(Duration::ZERO);
+        assert!(!scheduler.should_request(Instant::now(), true));
+    }
+
+    #[test]
+    fn test_scheduler_does_not_fire_when_paused() {
+        let mut scheduler = RecordingKeyframeScheduler::new(Duration::from_secs(10));
+        let now = Instant::now();
+        assert!(!scheduler.should_request(now, false));
+        assert!(scheduler.should_request(now, true));
+    }
+
+    #[test]
+    fn test_scheduler_rate_limits_requests() {
+        let mut scheduler = RecordingKeyframeScheduler::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(scheduler.should_request(start, true));
+        assert!(!scheduler.should_request(start + Duration::from_secs(5), true));
+        assert!(scheduler.should_request(start + Duration::from_secs(10), true));
+    }
+
+    #[test]
+    fn test_pli_limiter_coalesces_requests_within_window() {
+        let mut limiter = PliLimiter::new(Duration::from_millis(1000));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(limiter.on_request("proctor", at(0)), PliDecision::Forward);
+        assert_eq!(limiter.on_request("proctor", at(100)), PliDecision::Suppressed);
+        assert_eq!(limiter.on_request("proctor", at(200)), PliDecision::Suppressed);
+        assert_eq!(limiter.on_request("observer", at(999)), PliDecision::Suppressed);
+        assert_eq!(limiter.on_request("observer", at(1000)), PliDecision::Forward);
+
+        assert_eq!(limiter.forwarded(), 2);
+        let suppressed: Vec<_> = limiter.suppressed().iter().map(|(peer, n)| (peer.as_str(), *n)).collect();
+        assert_eq!(suppressed, vec![("observer", 1), ("proctor", 2)]);
+    }
+
+    #[test]
+    fn test_pli_limiter_satisfied_by_recent_keyframe() {
+        let mut limiter = PliLimiter::new(Duration::from_millis(1000));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(limiter.on_request("proctor", at(0)), PliDecision::Forward);
+        limiter.on_keyframe(at(150));
+        assert_eq!(limiter.on_request("proctor", at(300)), PliDecision::SatisfiedByKeyframe);
+        assert_eq!(limiter.on_request("proctor", at(1100)), PliDecision::SatisfiedByKeyframe);
+        // Satisfied requests are not held against the subscriber
+        assert!(limiter.suppressed().is_empty());
+
+        assert_eq!(limiter.on_request("proctor", at(1150)), PliDecision::Forward);
+        assert_eq!(limiter.forwarded(), 2);
+    }
+
+    #[test]
+    fn test_pli_limiter_new_subscriber_ignores_earlier_keyframe() {
+        let mut limiter = PliLimiter::new(Duration::from_millis(1000));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(limiter.on_subscribe("proctor", at(0)), PliDecision::Forward);
+        // Joins before the keyframe arrives share the PLI already sent
+        assert_eq!(limiter.on_subscribe("observer", at(50)), PliDecision::Suppressed);
+        limiter.on_keyframe(at(150));
+        // The keyframe went out before this subscriber was attached
+        assert_eq!(limiter.on_subscribe("late_student", at(300)), PliDecision::Forward);
+        // A repeat request from an existing subscriber is still answered by it
+        assert_eq!(limiter.on_request("proctor", at(400)), PliDecision::SatisfiedByKeyframe);
+
+        assert_eq!(limiter.forwarded(), 2);
+        assert_eq!(limiter.suppressed().get("observer"), Some(&1));
+    }
+
+    #[test]
+    fn test_pli_limiter_disabled_with_zero_interval() {
+        let mut limiter = PliLimiter::new(Duration::ZERO);
+        let now = Instant::now();
+        limiter.on_keyframe(now);
+        for _ in 0..3 {
+            assert_eq!(limiter.on_request("proctor", now), PliDecision::Forward);
+        }
+        assert_eq!(limiter.forwarded(), 3);
+    }
+
+    #[test]
+    fn test_scheduler_suppressed_by_recent_keyframe() {
+        let mut scheduler = RecordingKeyframeScheduler::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let after_keyframe = start + Duration::from_secs(2);
+        scheduler.on_keyframe(start);
+        assert!(!scheduler.should_request(after_keyframe, true));
+        assert!(scheduler.should_request(start + Duration::from_secs(10), true));
+    }
+}
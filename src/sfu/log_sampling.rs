@@ -0,0 +1,194 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::config::env;
+
+/// Default number of packets per track logged individually at debug level
+const DEFAULT_LOG_FIRST_PACKETS: u64 = 5;
+
+/// Default interval between per-track forwarding summaries
+const DEFAULT_LOG_TRACK_SUMMARY_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LogSamplingSettings {
+    /// Packets at the start of each track logged one by one
+    pub first_packets: u64,
+    /// Zero disables the periodic summary
+    pub summary_interval: Duration,
+}
+
+impl LogSamplingSettings {
+    /// Reads `LOG_FIRST_PACKETS` and `LOG_TRACK_SUMMARY_SECS`
+    pub fn from_env() -> Self {
+        Self {
+            first_packets: env::get_parsed("LOG_FIRST_PACKETS").unwrap_or(DEFAULT_LOG_FIRST_PACKETS),
+            summary_interval: env::get_duration_secs(
+                "LOG_TRACK_SUMMARY_SECS",
+                Duration::from_secs(DEFAULT_LOG_TRACK_SUMMARY_SECS),
+            ),
+        }
+    }
+}
+
+static SETTINGS: OnceLock<LogSamplingSettings> = OnceLock::new();
+
+pub fn settings() -> LogSamplingSettings {
+    *SETTINGS.get_or_init(LogSamplingSettings::from_env)
+}
+
+/// Forwarding activity of one track over a summary interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackSummary {
+    pub packets: u64,
+    pub bytes: u64,
+    /// Subscriber writes that failed
+    pub drops: u64,
+    pub interval: Duration,
+}
+
+/// Decides what the forwarding loop of one track logs: the first few packets
+/// individually, then one summary per interval instead of a line per packet
+#[derive(Debug)]
+pub struct TrackLogSampler {
+    settings: LogSamplingSettings,
+    total_packets: u64,
+    window_start: Instant,
+    packets: u64,
+    bytes: u64,
+    drops: u64,
+    total_drops: u64,
+}
+
+impl TrackLogSampler {
+    pub fn new(settings: LogSamplingSettings, now: Instant) -> Self {
+        Self {
+            settings,
+            total_packets: 0,
+            window_start: now,
+            packets: 0,
+            bytes: 0,
+            drops: 0,
+            total_drops: 0,
+        }
+    }
+
+    /// Counts a packet; true while it is among the first packets of the track
+    pub fn on_packet(&mut self, bytes: usize) -> bool {
+        self.total_packets += 1;
+        self.packets += 1;
+        self.bytes += bytes as u64;
+        self.is_early()
+    }
+
+    pub fn on_drop(&mut self) {
+        self.drops += 1;
+        self.total_drops += 1;
+    }
+
+    /// Still within the first packets, where every event is worth logging
+    pub fn is_early(&self) -> bool {
+        self.total_packets <= self.settings.first_packets
+    }
+
+    pub fn total_packets(&self) -> u64 {
+        self.total_packets
+    }
+
+    /// Subscriber writes that failed since the track started
+    pub fn total_drops(&self) -> u64 {
+        self.total_drops
+    }
+
+    /// Summary of the interval that just ended, once it has elapsed
+    pub fn poll(&mut self, now: Instant) -> Option<TrackSummary> {
+        if self.settings.summary_interval.is_zero() {
+            return None;
+        }
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed < self.settings.summary_interval {
+            return None;
+        }
+
+        let summary = TrackSummary {
+            packets: self.packets,
+            bytes: self.bytes,
+            drops: self.drops,
+            interval: elapsed,
+        };
+        self.window_start = now;
+        self.packets = 0;
+        self.bytes = 0;
+        self.drops = 0;
+        Some(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_sampler(first_packets: u64, summary_secs: u64) -> (TrackLogSampler, Instant) {
+        let t0 = Instant::now();
+        let settings = LogSamplingSettings {
+            first_packets,
+            summary_interval: Duration::from_secs(summary_secs),
+        };
+        (TrackLogSampler::new(settings, t0), t0)
+    }
+
+    #[test]
+    fn test_first_packets_threshold() {
+        let (mut sampler, _) = new_sampler(3, 30);
+        let logged: Vec<bool> = (0..5).map(|_| sampler.on_packet(1200)).collect();
+        assert_eq!(logged, vec![true, true, true, false, false]);
+
+        let (mut silent, _) = new_sampler(0, 30);
+        assert!(!silent.on_packet(1200));
+    }
+
+    #[test]
+    fn test_summary_cadence() {
+        let (mut sampler, t0) = new_sampler(5, 10);
+        let secs = Duration::from_secs;
+
+        // 50 packets per second for 25 seconds, one drop every 100 packets
+        let mut summaries = Vec::new();
+        for packet in 0..1250u64 {
+            let now = t0 + Duration::from_millis(packet * 20);
+            sampler.on_packet(1000);
+            if packet % 100 == 99 {
+                sampler.on_drop();
+            }
+            if let Some(summary) = sampler.poll(now) {
+                summaries.push((now, summary));
+            }
+        }
+
+        assert_eq!(summaries.len(), 2);
+        let (at, first) = summaries[0];
+        assert_eq!(at, t0 + secs(10));
+        assert_eq!(first.packets, 501);
+        assert_eq!(first.bytes, 501_000);
+        assert_eq!(first.drops, 5);
+        assert_eq!(first.interval, secs(10));
+
+        // The next window starts where the last summary was emitted
+        let (at, second) = summaries[1];
+        assert_eq!(at, t0 + secs(20));
+        assert_eq!(second.packets, 500);
+        assert_eq!(sampler.total_packets(), 1250);
+        // Unlike the per-window count, the total survives each summary
+        assert_eq!(sampler.total_drops(), 12);
+
+        // Nothing until a full interval has passed again
+        assert!(sampler.poll(t0 + secs(29)).is_none());
+        assert_eq!(sampler.poll(t0 + secs(31)).map(|s| s.interval), Some(secs(11)));
+    }
+
+    #[test]
+    fn test_zero_interval_disables_summary() {
+        let (mut sampler, t0) = new_sampler(5, 0);
+        sampler.on_packet(1000);
+        assert!(sampler.poll(t0 + Duration::from_secs(3600)).is_none());
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::room::PeerKey;
+
+/// Which publishers have media to forward, per room they publish in
+pub trait MediaRoutingService: Send + Sync {
+    /// Counts a track received from `peer`, returning how many it has published
+    fn track_published(&self, peer: &PeerKey) -> usize;
+
+    fn published_tracks(&self, peer: &PeerKey) -> usize;
+
+    /// Whether students joining now will receive the publisher's media right away
+    fn is_ready(&self, peer: &PeerKey) -> bool {
+        self.published_tracks(peer) >= 1
+    }
+
+    /// Drops the counts of a departed peer, so a rejoin starts from nothing
+    fn forget(&self, peer: &PeerKey);
+}
+
+#[derive(Default)]
+pub struct TrackReadiness {
+    published: Mutex<HashMap<PeerKey, usize>>,
+}
+
+impl TrackReadiness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MediaRoutingService for TrackReadiness {
+    fn track_published(&self, peer: &PeerKey) -> usize {
+        let mut published = self.published.lock().unwrap();
+        let count = published.entry(peer.clone()).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    fn published_tracks(&self, peer: &PeerKey) -> usize {
+        self.published.lock().unwrap().get(peer).copied().unwrap_or(0)
+    }
+
+    fn forget(&self, peer: &PeerKey) {
+        self.published.lock().unwrap().remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publisher_ready_after_first_track_until_it_leaves() {
+        let readiness = TrackReadiness::new();
+        let proctor = PeerKey::new("123456", "proctor");
+        assert!(!readiness.is_ready(&proctor));
+
+        assert_eq!(readiness.track_published(&proctor), 1);
+        assert_eq!(readiness.track_published(&proctor), 2);
+        assert!(readiness.is_ready(&proctor));
+        assert!(!readiness.is_ready(&PeerKey::new("123456", "student")));
+
+        readiness.forget(&proctor);
+        assert!(!readiness.is_ready(&proctor));
+        assert_eq!(readiness.published_tracks(&proctor), 0);
+    }
+
+    #[test]
+    fn test_readiness_is_per_room() {
+        let readiness = TrackReadiness::new();
+        let room_a = PeerKey::new("111111", "ta");
+        let room_b = PeerKey::new("222222", "ta");
+
+        readiness.track_published(&room_a);
+        assert!(readiness.is_ready(&room_a));
+        assert!(!readiness.is_ready(&room_b));
+    }
+}
@@ -1,8 +1,43 @@
+mod admission;
+mod affinity;
+mod closing;
 pub mod connection;
+mod connections;
+mod escalation;
+mod ice;
+pub mod ice_selftest;
+mod keyframe;
+mod log_sampling;
+mod media_routing;
+mod negotiation;
+mod overview;
+mod pending;
+mod recipe;
+mod renegotiation;
 mod server;
 mod room;
+mod room_state;
+mod roster;
+mod sdp;
+mod state_log;
+pub mod rtcp;
 mod track_manager;
 mod signaling;
+mod supervisor;
+mod timezone;
+mod transfer;
 mod webrtc_utils;
-pub use server::SfuServer;
-pub use signaling::{SfuSignalingHandler, SfuMessage};
\ No newline at end of file
+pub use admission::{AdmissionService, PendingAdmissions, RejectReason, RetryPolicy};
+pub use connections::{ConnectionRegistry, PeerConnections};
+pub use media_routing::{MediaRoutingService, TrackReadiness};
+pub use negotiation::{NegotiationService, Negotiations};
+pub use overview::{PeerOverview, RoomDetail, RoomOverview};
+pub use recipe::{Keepalive, RecipeQuery};
+pub use renegotiation::{RenegotiationControl, RenegotiationSnapshot, RenegotiationTuning, RenegotiationTuningUpdate};
+pub use room::{PeerKey, PeerRole};
+pub use roster::Roster;
+pub use server::{SfuConfig, SfuServer, SfuServerBuilder};
+pub use signaling::{SfuSignalingHandler, SfuMessage};
+pub use supervisor::TaskSupervisor;
+pub use timezone::{LocalDate, RoomLocale, Timezone};
+pub use webrtc_utils::{ApiFactory, CodecConfig, EngineConfigError, FeedbackConfig, WebRtcEngineConfig};
\ No newline at end of file
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use warp::ws::Message;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::signaling_state::RTCSignalingState;
+
+use super::connection::SfuConnection;
+use super::pending::PendingIceCandidate;
+use super::room::PeerKey;
+
+/// Retries of a renegotiation that found the signaling state busy
+pub const MAX_RENEGOTIATION_RETRIES: u32 = 3;
+
+/// Renegotiation batching, and ICE candidates held until a peer's remote
+/// description is set. Each room a peer is in has its own connection, so
+/// state is kept per (room_id, peer_id).
+pub trait NegotiationService: Send + Sync {
+    /// Asks for a renegotiation of `peer`. True when none was pending, so the
+    /// caller schedules one; requests until it starts join that batch.
+    fn request_renegotiation(&self, peer: &PeerKey) -> bool;
+
+    /// Requests in the peer's open batch, 0 when none is open
+    fn batched_renegotiations(&self, peer: &PeerKey) -> u32;
+
+    /// Closes the batch as its renegotiation starts, returning how many
+    /// requests it held
+    fn start_renegotiation(&self, peer: &PeerKey) -> u32;
+
+    /// Opens the candidate queue of a joining peer, with candidates it sent
+    /// before approval ahead of any already queued
+    fn open_ice_queue(&self, peer: &PeerKey, early: Vec<PendingIceCandidate>);
+
+    /// Queues a candidate, opening the peer's queue if needed
+    fn queue_ice_candidate(&self, peer: &PeerKey, candidate: PendingIceCandidate);
+
+    /// Queues a candidate only if the peer's queue is open
+    fn queue_if_open(&self, peer: &PeerKey, candidate: PendingIceCandidate) -> bool;
+
+    /// Candidates queued for `peer`, `None` once its queue is closed
+    fn queued_ice_candidates(&self, peer: &PeerKey) -> Option<usize>;
+
+    /// Closes the peer's queue and returns what it held
+    fn take_ice_candidates(&self, peer: &PeerKey) -> Vec<PendingIceCandidate>;
+
+    /// Drops the queue and any pending renegotiation of a departed peer
+    fn forget(&self, peer: &PeerKey);
+}
+
+#[derive(Default)]
+struct NegotiationState {
+    /// Peers with a renegotiation scheduled but not yet started, with the
+    /// requests batched into it
+    renegotiations: HashMap<PeerKey, u32>,
+    ice_queues: HashMap<PeerKey, Vec<PendingIceCandidate>>,
+}
+
+#[derive(Default)]
+pub struct Negotiations {
+    state: Mutex<NegotiationState>,
+}
+
+impl Negotiations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NegotiationService for Negotiations {
+    fn request_renegotiation(&self, peer: &PeerKey) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let batched = state.renegotiations.entry(peer.clone()).or_default();
+        *batched += 1;
+        *batched == 1
+    }
+
+    fn batched_renegotiations(&self, peer: &PeerKey) -> u32 {
+        self.state.lock().unwrap().renegotiations.get(peer).copied().unwrap_or(0)
+    }
+
+    fn start_renegotiation(&self, peer: &PeerKey) -> u32 {
+        self.state.lock().unwrap().renegotiations.remove(peer).unwrap_or(0)
+    }
+
+    fn open_ice_queue(&self, peer: &PeerKey, early: Vec<PendingIceCandidate>) {
+        let mut state = self.state.lock().unwrap();
+        let queue = state.ice_queues.entry(peer.clone()).or_default();
+        queue.splice(0..0, early);
+    }
+
+    fn queue_ice_candidate(&self, peer: &PeerKey, candidate: PendingIceCandidate) {
+        self.state
+            .lock()
+            .unwrap()
+            .ice_queues
+            .entry(peer.clone())
+            .or_default()
+            .push(candidate);
+    }
+
+    fn queue_if_open(&self, peer: &PeerKey, candidate: PendingIceCandidate) -> bool {
+        match self.state.lock().unwrap().ice_queues.get_mut(peer) {
+            Some(queue) => {
+                queue.push(candidate);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn queued_ice_candidates(&self, peer: &PeerKey) -> Option<usize> {
+        self.state.lock().unwrap().ice_queues.get(peer).map(Vec::len)
+    }
+
+    fn take_ice_candidates(&self, peer: &PeerKey) -> Vec<PendingIceCandidate> {
+        self.state.lock().unwrap().ice_queues.remove(peer).unwrap_or_default()
+    }
+
+    fn forget(&self, peer: &PeerKey) {
+        let mut state = self.state.lock().unwrap();
+        if state.ice_queues.remove(peer).is_some() {
+            tracing::debug!(peer = %peer, "Removed pending ICE candidates");
+        }
+        if state.renegotiations.remove(peer).is_some() {
+            tracing::debug!(peer = %peer, "Removed pending renegotiation");
+        }
+    }
+}
+
+/// Sends the peer a fresh offer as the SFU
+pub async fn send_offer(connection: &SfuConnection) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let _negotiating = connection.negotiation.lock().await;
+    let offer = connection.peer_connection.create_offer(None).await?;
+    connection.peer_connection.set_local_description(offer.clone()).await?;
+
+    let offer_message = serde_json::to_string(&serde_json::json!({
+        "type": "offer",
+        "sdp": offer.sdp,
+        "peer_id": "sfu"
+    }))?;
+
+    connection.send_message(Message::text(offer_message)).await?;
+    tracing::info!(peer_id = %connection.peer_id, "Sent SFU offer to peer");
+    Ok(())
+}
+
+/// What became of one renegotiation attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenegotiationOutcome {
+    Sent,
+    /// The peer has not answered an earlier offer yet; worth retrying
+    Busy,
+    Failed,
+}
+
+/// Sends a batched renegotiation offer if the peer's signaling state allows one
+pub async fn renegotiate(connection: &SfuConnection, retry_count: u32) -> RenegotiationOutcome {
+    let target_peer_id = connection.peer_id.as_str();
+    let negotiating = connection.negotiation.lock().await;
+    let signaling_state = connection.peer_connection.signaling_state();
+    tracing::debug!(
+        target_peer_id = %target_peer_id,
+        ?signaling_state,
+        retry_count = retry_count,
+        "Checking signaling state for renegotiation"
+    );
+
+    if signaling_state != RTCSignalingState::Stable {
+        return RenegotiationOutcome::Busy;
+    }
+
+    tracing::info!(
+        target_peer_id = %target_peer_id,
+        retry_count = retry_count,
+        "Creating batched renegotiation offer"
+    );
+
+    let offer = match connection.peer_connection.create_offer(None).await {
+        Ok(offer) => offer,
+        Err(e) => {
+            tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to create renegotiation offer");
+            return RenegotiationOutcome::Failed;
+        }
+    };
+
+    if let Err(e) = connection.peer_connection.set_local_description(offer.clone()).await {
+        tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to set local description");
+        return RenegotiationOutcome::Failed;
+    }
+    drop(negotiating);
+    tracing::debug!(target_peer_id = %target_peer_id, "Set local description");
+
+    let renegotiate_message = match serde_json::to_string(&serde_json::json!({
+        "type": "renegotiate",
+        "sdp": offer.sdp
+    })) {
+        Ok(msg) => msg,
+        Err(e) => {
+            tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to serialize renegotiation message");
+            return RenegotiationOutcome::Failed;
+        }
+    };
+
+    if let Err(e) = connection.send_message(Message::text(renegotiate_message)).await {
+        tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to send renegotiation offer");
+        return RenegotiationOutcome::Failed;
+    }
+    tracing::info!(
+        target_peer_id = %target_peer_id,
+        retry_count = retry_count,
+        "Sent renegotiation offer"
+    );
+    RenegotiationOutcome::Sent
+}
+
+/// Why an offer a client sent was not answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientOfferError {
+    /// The peer has no connection in the room
+    NoConnection,
+    /// The SFU's own offer is still waiting for the peer's answer
+    Glare,
+    InvalidSdp(String),
+    Failed(String),
+}
+
+impl ClientOfferError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientOfferError::NoConnection => "no_connection",
+            ClientOfferError::Glare => "glare",
+            ClientOfferError::InvalidSdp(_) => "invalid_sdp",
+            ClientOfferError::Failed(_) => "negotiation_failed",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ClientOfferError::NoConnection => "No media connection to renegotiate".to_string(),
+            ClientOfferError::Glare => {
+                "The SFU has an offer outstanding; answer it, then resend your offer".to_string()
+            }
+            ClientOfferError::InvalidSdp(e) => format!("Failed to parse offer SDP: {}", e),
+            ClientOfferError::Failed(e) => format!("Failed to answer offer: {}", e),
+        }
+    }
+}
+
+/// Answers an offer the peer sent, e.g. after adding a screen share track.
+/// The SFU is the impolite side of glare: while its own offer is
+/// unanswered the peer's is refused, and the peer resends it after
+/// answering ours.
+pub async fn answer_client_offer(connection: &SfuConnection, sdp: &str) -> Result<(), ClientOfferError> {
+    let negotiating = connection.negotiation.lock().await;
+    let signaling_state = connection.peer_connection.signaling_state();
+    if signaling_state != RTCSignalingState::Stable {
+        tracing::info!(
+            peer_id = %connection.peer_id,
+            ?signaling_state,
+            "Refusing client offer while SFU offer is outstanding"
+        );
+        return Err(ClientOfferError::Glare);
+    }
+
+    let offer = RTCSessionDescription::offer(sdp.to_string())
+        .map_err(|e| ClientOfferError::InvalidSdp(e.to_string()))?;
+    connection
+        .peer_connection
+        .set_remote_description(offer)
+        .await
+        .map_err(remote_offer_error)?;
+
+    let answer = connection
+        .peer_connection
+        .create_answer(None)
+        .await
+        .map_err(|e| ClientOfferError::Failed(e.to_string()))?;
+    connection
+        .peer_connection
+        .set_local_description(answer.clone())
+        .await
+        .map_err(|e| ClientOfferError::Failed(e.to_string()))?;
+    drop(negotiating);
+
+    let answer_message = serde_json::json!({
+        "type": "answer",
+        "sdp": answer.sdp,
+        "peer_id": "sfu"
+    });
+    connection
+        .send_message(Message::text(answer_message.to_string()))
+        .await
+        .map_err(|e| ClientOfferError::Failed(e.to_string()))?;
+    tracing::info!(peer_id = %connection.peer_id, "Answered client offer");
+    Ok(())
+}
+
+/// A remote offer the signaling state would not take is glare, whatever its SDP
+fn remote_offer_error(e: webrtc::Error) -> ClientOfferError {
+    match e {
+        webrtc::Error::ErrSignalingStateProposedTransitionInvalid { .. } => ClientOfferError::Glare,
+        e => ClientOfferError::InvalidSdp(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(n: u16) -> PendingIceCandidate {
+        PendingIceCandidate {
+            candidate: format!("candidate:{} 1 udp 2122260223 192.0.2.1 54400 typ host", n),
+            sdp_mid: Some("0".to_string()),
+            sdp_mline_index: Some(n),
+        }
+    }
+
+    #[test]
+    fn test_renegotiation_requests_batch_until_started() {
+        let negotiations = Negotiations::new();
+        let student_1 = PeerKey::new("123456", "student_1");
+
+        // The first track schedules; the rest ride along
+        assert!(negotiations.request_renegotiation(&student_1));
+        assert!(!negotiations.request_renegotiation(&student_1));
+        assert!(!negotiations.request_renegotiation(&student_1));
+
+        // Batches are per peer
+        assert!(negotiations.request_renegotiation(&PeerKey::new("123456", "student_2")));
+
+        // Once the offer goes out, the next track starts a new batch
+        assert_eq!(negotiations.batched_renegotiations(&student_1), 3);
+        assert_eq!(negotiations.start_renegotiation(&student_1), 3);
+        assert_eq!(negotiations.batched_renegotiations(&student_1), 0);
+        assert!(negotiations.request_renegotiation(&student_1));
+
+        negotiations.forget(&student_1);
+        assert!(negotiations.request_renegotiation(&student_1));
+    }
+
+    #[test]
+    fn test_early_candidates_go_ahead_of_queued_ones() {
+        let negotiations = Negotiations::new();
+        let student_1 = PeerKey::new("123456", "student_1");
+
+        // Nothing is queued for a peer that is not joining
+        assert!(!negotiations.queue_if_open(&student_1, candidate(0)));
+        assert_eq!(negotiations.queued_ice_candidates(&student_1), None);
+
+        negotiations.open_ice_queue(&student_1, Vec::new());
+        assert_eq!(negotiations.queued_ice_candidates(&student_1), Some(0));
+        assert!(negotiations.queue_if_open(&student_1, candidate(2)));
+
+        // Reopening keeps what was queued, behind the earlier candidates
+        negotiations.open_ice_queue(&student_1, vec![candidate(0), candidate(1)]);
+        negotiations.queue_ice_candidate(&student_1, candidate(3));
+
+        let flushed = negotiations.take_ice_candidates(&student_1);
+        assert_eq!(flushed, vec![candidate(0), candidate(1), candidate(2), candidate(3)]);
+        assert_eq!(negotiations.queued_ice_candidates(&student_1), None);
+        assert!(negotiations.take_ice_candidates(&student_1).is_empty());
+    }
+
+    #[test]
+    fn test_queues_are_per_room() {
+        let negotiations = Negotiations::new();
+        let room_a = PeerKey::new("111111", "ta");
+        let room_b = PeerKey::new("222222", "ta");
+
+        negotiations.open_ice_queue(&room_a, vec![candidate(0)]);
+        assert!(!negotiations.queue_if_open(&room_b, candidate(1)));
+        assert!(negotiations.request_renegotiation(&room_a));
+        assert!(negotiations.request_renegotiation(&room_b));
+
+        negotiations.forget(&room_b);
+        assert_eq!(negotiations.queued_ice_candidates(&room_a), Some(1));
+        assert!(!negotiations.request_renegotiation(&room_a));
+    }
+
+    #[test]
+    fn test_signaling_state_conflict_is_glare() {
+        use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+
+        let conflict = webrtc::Error::ErrSignalingStateProposedTransitionInvalid {
+            from: RTCSignalingState::HaveLocalOffer,
+            applying: RTCSdpType::Offer,
+            is_local: false,
+        };
+        assert_eq!(remote_offer_error(conflict), ClientOfferError::Glare);
+        assert_eq!(remote_offer_error(webrtc::Error::ErrSessionDescriptionNoFingerprint).code(), "invalid_sdp");
+    }
+
+    #[test]
+    fn test_forget_drops_queue() {
+        let negotiations = Negotiations::new();
+        let student_1 = PeerKey::new("123456", "student_1");
+        negotiations.queue_ice_candidate(&student_1, candidate(0));
+        negotiations.forget(&student_1);
+        assert_eq!(negotiations.queued_ice_candidates(&student_1), None);
+    }
+}
@@ -0,0 +1,45 @@
+//! What the room admin routes report about rooms and the peers in them
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::room::{PeerRole, RoomSummary};
+
+/// A room with the peers whose media is being recorded, for `GET /sfu/rooms`
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomOverview {
+    #[serde(flatten)]
+    pub room: RoomSummary,
+    /// Storage namespace the room was created for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub recording_peers: Vec<String>,
+    /// Integrity score of every student who joined, by peer ID
+    pub integrity_scores: BTreeMap<String, u32>,
+}
+
+/// One peer of a room, for `GET /sfu/rooms/{room_id}`
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerOverview {
+    pub peer_id: String,
+    pub role: PeerRole,
+    pub name: Option<String>,
+    /// Tracks the peer publishes in the room
+    pub track_count: usize,
+    /// WebRTC connection state; `None` until the connection is set up
+    pub connection_state: Option<String>,
+    pub recording: bool,
+    /// The peer reported end-to-end encrypted media, which is not recorded
+    pub e2ee: bool,
+    /// Students only, in basis points
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity_score: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomDetail {
+    #[serde(flatten)]
+    pub room: RoomOverview,
+    /// The proctor, then students in join order
+    pub peers: Vec<PeerOverview>,
+}
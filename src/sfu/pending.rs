@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use warp::ws::Message;
+
+use crate::config::env;
+
+use super::escalation::{EscalationAction, EscalationPolicy, EscalationStage};
+
+/// Default cap on join requests awaiting a proctor decision across all rooms
+const DEFAULT_MAX_PENDING_STUDENTS: usize = 1000;
+
+/// Default time a join request may wait for the proctor before it expires
+const DEFAULT_PENDING_STUDENT_TTL_SECS: u64 = 300;
+
+/// Default cap on ICE candidates buffered for one student before approval
+const DEFAULT_MAX_PENDING_ICE_CANDIDATES: usize = 32;
+
+/// ICE candidate received before it can be added to a peer connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingIceCandidate {
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    pub sdp_mline_index: Option<u16>,
+}
+
+/// Student waiting for the proctor to approve or deny their join request
+pub struct PendingStudent {
+    pub sender: mpsc::UnboundedSender<Message>,
+    pub wallet_address: Option<String>,
+    /// Name and role from the request, repeated when the proctor is reminded
+    pub name: Option<String>,
+    pub role: String,
+    pub requested_at: Instant,
+    /// Candidates trickled before the student had a connection, oldest first
+    pub ice_candidates: Vec<PendingIceCandidate>,
+    /// Fallback of the room the request was made for
+    pub escalation: EscalationPolicy,
+    pub stage: EscalationStage,
+}
+
+/// Join request that reached a step of its room's escalation policy
+#[derive(Debug, Clone)]
+pub struct EscalatedRequest {
+    pub room_id: String,
+    pub peer_id: String,
+    pub action: EscalationAction,
+    pub policy: EscalationPolicy,
+    pub name: Option<String>,
+    pub role: String,
+    pub wallet_address: Option<String>,
+    pub sender: mpsc::UnboundedSender<Message>,
+    /// Time since the request was made
+    pub waited: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingLimitReached {
+    pub max: usize,
+}
+
+/// Why an early ICE candidate was not buffered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IceBufferError {
+    /// The peer has no pending join request for the room
+    NotPending,
+    /// The student already has `max` candidates buffered
+    Full { max: usize },
+}
+
+/// Join requests grouped by the room they were made for.
+///
+/// A student is pending in at most one room; asking about another room
+/// replaces the earlier request.
+pub struct PendingStudents {
+    rooms: HashMap<String, HashMap<String, PendingStudent>>,
+    /// peer_id -> room_id of its pending request
+    peer_rooms: HashMap<String, String>,
+    max: usize,
+    ttl: Duration,
+    max_ice_candidates: usize,
+}
+
+impl Default for PendingStudents {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PENDING_STUDENTS, Duration::from_secs(DEFAULT_PENDING_STUDENT_TTL_SECS))
+    }
+}
+
+impl PendingStudents {
+    pub fn new(max: usize, ttl: Duration) -> Self {
+        Self {
+            rooms: HashMap::new(),
+            peer_rooms: HashMap::new(),
+            max,
+            ttl,
+            max_ice_candidates: DEFAULT_MAX_PENDING_ICE_CANDIDATES,
+        }
+    }
+
+    pub fn with_max_ice_candidates(mut self, max_ice_candidates: usize) -> Self {
+        self.max_ice_candidates = max_ice_candidates;
+        self
+    }
+
+    /// Reads `MAX_PENDING_STUDENTS`, `PENDING_STUDENT_TTL_SECS` and `MAX_PENDING_ICE_CANDIDATES`
+    pub fn from_env() -> Self {
+        let max = env::get_parsed::<usize>("MAX_PENDING_STUDENTS").unwrap_or(DEFAULT_MAX_PENDING_STUDENTS);
+        let ttl_secs = env::get_parsed::<u64>("PENDING_STUDENT_TTL_SECS")
+            .filter(|secs| *secs > 0)
+            .unwrap_or(DEFAULT_PENDING_STUDENT_TTL_SECS);
+        let max_ice_candidates =
+            env::get_parsed::<usize>("MAX_PENDING_ICE_CANDIDATES").unwrap_or(DEFAULT_MAX_PENDING_ICE_CANDIDATES);
+
+        Self::new(max, Duration::from_secs(ttl_secs)).with_max_ice_candidates(max_ice_candidates)
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn len(&self) -> usize {
+        self.peer_rooms.len()
+    }
+
+    pub fn contains(&self, peer_id: &str) -> bool {
+        self.peer_rooms.contains_key(peer_id)
+    }
+
+    /// Records a join request, refusing new students once the cap is reached.
+    /// A repeated request from the same student refreshes its timestamp and
+    /// keeps candidates buffered for the same room.
+    pub fn insert(&mut self, room_id: &str, peer_id: &str, mut student: PendingStudent) -> Result<(), PendingLimitReached> {
+        if !self.contains(peer_id) && self.len() >= self.max {
+            return Err(PendingLimitReached { max: self.max });
+        }
+
+        let same_room = self.peer_rooms.get(peer_id).map(String::as_str) == Some(room_id);
+        if let Some(previous) = self.remove(peer_id) {
+            if same_room {
+                let mut candidates = previous.ice_candidates;
+                candidates.append(&mut student.ice_candidates);
+                candidates.truncate(self.max_ice_candidates);
+                student.ice_candidates = candidates;
+            }
+        }
+        self.rooms
+            .entry(room_id.to_string())
+            .or_default()
+            .insert(peer_id.to_string(), student);
+        self.peer_rooms.insert(peer_id.to_string(), room_id.to_string());
+        Ok(())
+    }
+
+    /// Looks up a request only within the room it was made for
+    pub fn get(&self, room_id: &str, peer_id: &str) -> Option<&PendingStudent> {
+        self.rooms.get(room_id).and_then(|room| room.get(peer_id))
+    }
+
+    /// Marks the request for `room_id` as answered, so no escalation step fires for it
+    pub fn decide(&mut self, room_id: &str, peer_id: &str) -> bool {
+        match self.rooms.get_mut(room_id).and_then(|room| room.get_mut(peer_id)) {
+            Some(student) => {
+                student.stage = EscalationStage::Decided;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Students in `room_id` still waiting on the proctor, in peer_id order.
+    /// Approved students who have not joined yet are not included.
+    pub fn unanswered(&self, room_id: &str) -> Vec<String> {
+        let mut peer_ids: Vec<String> = self
+            .rooms
+            .get(room_id)
+            .into_iter()
+            .flatten()
+            .filter(|(_, student)| student.stage != EscalationStage::Decided)
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect();
+        peer_ids.sort();
+        peer_ids
+    }
+
+    /// Buffers a candidate trickled by a student still awaiting approval for `room_id`
+    pub fn buffer_ice_candidate(
+        &mut self,
+        room_id: &str,
+        peer_id: &str,
+        candidate: PendingIceCandidate,
+    ) -> Result<usize, IceBufferError> {
+        let max = self.max_ice_candidates;
+        let student = self
+            .rooms
+            .get_mut(room_id)
+            .and_then(|room| room.get_mut(peer_id))
+            .ok_or(IceBufferError::NotPending)?;
+
+        if student.ice_candidates.len() >= max {
+            return Err(IceBufferError::Full { max });
+        }
+        student.ice_candidates.push(candidate);
+        Ok(student.ice_candidates.len())
+    }
+
+    pub fn remove(&mut self, peer_id: &str) -> Option<PendingStudent> {
+        let room_id = self.peer_rooms.remove(peer_id)?;
+        let room = self.rooms.get_mut(&room_id)?;
+        let student = room.remove(peer_id);
+        if room.is_empty() {
+            self.rooms.remove(&room_id);
+        }
+        student
+    }
+
+    /// Moves requests unanswered for `interval` on to a reminder, and those
+    /// still unanswered one more `interval` later on to their room's fallback.
+    /// A request takes at most one step per call, so the proctor is always
+    /// reminded before the fallback applies. Auto-denied requests are removed.
+    pub fn escalate(&mut self, now: Instant, interval: Duration) -> Vec<EscalatedRequest> {
+        let mut escalated = Vec::new();
+        for (room_id, room) in self.rooms.iter_mut() {
+            for (peer_id, student) in room.iter_mut() {
+                let waited = now.saturating_duration_since(student.requested_at);
+                let action = match student.stage {
+                    EscalationStage::Waiting if waited >= interval => EscalationAction::Reminded,
+                    EscalationStage::Reminded if waited >= interval * 2 => EscalationAction::fallback(student.escalation),
+                    _ => continue,
+                };
+                student.stage = match action {
+                    EscalationAction::Reminded => EscalationStage::Reminded,
+                    _ => EscalationStage::Decided,
+                };
+                escalated.push(EscalatedRequest {
+                    room_id: room_id.clone(),
+                    peer_id: peer_id.clone(),
+                    action,
+                    policy: student.escalation,
+                    name: student.name.clone(),
+                    role: student.role.clone(),
+                    wallet_address: student.wallet_address.clone(),
+                    sender: student.sender.clone(),
+                    waited,
+                });
+            }
+        }
+
+        for request in &escalated {
+            if request.action == EscalationAction::AutoDenied {
+                self.remove(&request.peer_id);
+            }
+        }
+        escalated
+    }
+
+    /// Removes and returns every request older than the TTL as (room_id, peer_id, student)
+    pub fn expire(&mut self, now: Instant) -> Vec<(String, String, PendingStudent)> {
+        let ttl = self.ttl;
+        let expired: Vec<(String, String)> = self
+            .rooms
+            .iter()
+            .flat_map(|(room_id, room)| {
+                room.iter()
+                    .filter(move |(_, student)| now.saturating_duration_since(student.requested_at) >= ttl)
+                    .map(move |(peer_id, _)| (room_id.clone(), peer_id.clone()))
+            })
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|(room_id, peer_id)| {
+                let student = self.remove(&peer_id)?;
+                Some((room_id, peer_id, student))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn student(requested_at: Instant) -> (PendingStudent, mpsc::UnboundedReceiver<Message>) {
+        escalating_student(requested_at, EscalationPolicy::RemindOnly)
+    }
+
+    fn escalating_student(
+        requested_at: Instant,
+        escalation: EscalationPolicy,
+    ) -> (PendingStudent, mpsc::UnboundedReceiver<Message>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let student = PendingStudent {
+            sender,
+            wallet_address: None,
+            name: None,
+            role: "student".to_string(),
+            requested_at,
+            ice_candidates: Vec::new(),
+            escalation,
+            stage: EscalationStage::Waiting,
+        };
+        (student, receiver)
+    }
+
+    fn actions(escalated: &[EscalatedRequest]) -> Vec<(&str, EscalationAction)> {
+        let mut actions: Vec<_> = escalated.iter().map(|e| (e.peer_id.as_str(), e.action)).collect();
+        actions.sort_by_key(|(peer_id, _)| *peer_id);
+        actions
+    }
+
+    #[test]
+    fn test_escalation_reminds_then_applies_fallback() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut pending = PendingStudents::new(10, Duration::from_secs(600));
+        pending.insert("room-a", "approve", escalating_student(now, EscalationPolicy::AutoApprove).0).unwrap();
+        pending.insert("room-a", "deny", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+        pending.insert("room-a", "wait", escalating_student(now, EscalationPolicy::RemindOnly).0).unwrap();
+
+        assert!(pending.escalate(now + Duration::from_secs(59), interval).is_empty());
+
+        let reminded = pending.escalate(now + interval, interval);
+        assert_eq!(
+            actions(&reminded),
+            vec![
+                ("approve", EscalationAction::Reminded),
+                ("deny", EscalationAction::Reminded),
+                ("wait", EscalationAction::Reminded),
+            ]
+        );
+        // Each step fires once
+        assert!(pending.escalate(now + interval + Duration::from_secs(30), interval).is_empty());
+
+        let fallen_back = pending.escalate(now + interval * 2, interval);
+        assert_eq!(
+            actions(&fallen_back),
+            vec![
+                ("approve", EscalationAction::AutoApproved),
+                ("deny", EscalationAction::AutoDenied),
+                ("wait", EscalationAction::KeptWaiting),
+            ]
+        );
+        assert!(fallen_back.iter().all(|e| e.waited == interval * 2));
+        assert!(pending.escalate(now + interval * 10, interval).is_empty());
+
+        // Denied requests are over; the others wait to be joined or to expire
+        assert!(!pending.contains("deny"));
+        assert!(pending.contains("approve"));
+        assert!(pending.contains("wait"));
+    }
+
+    #[test]
+    fn test_late_sweep_still_reminds_first() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut pending = PendingStudents::new(10, Duration::from_secs(600));
+        pending.insert("room-a", "s1", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+
+        let late = now + interval * 5;
+        assert_eq!(actions(&pending.escalate(late, interval)), vec![("s1", EscalationAction::Reminded)]);
+        assert_eq!(actions(&pending.escalate(late, interval)), vec![("s1", EscalationAction::AutoDenied)]);
+    }
+
+    #[test]
+    fn test_answered_or_withdrawn_requests_do_not_escalate() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut pending = PendingStudents::new(10, Duration::from_secs(600));
+        pending.insert("room-a", "answered", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+        pending.insert("room-a", "gone", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+        pending.insert("room-a", "reminded", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+
+        // The proctor answers one before anything is due, and another after its reminder
+        assert!(pending.decide("room-a", "answered"));
+        assert!(!pending.decide("room-b", "gone"));
+        pending.remove("gone");
+        assert_eq!(actions(&pending.escalate(now + interval, interval)), vec![("reminded", EscalationAction::Reminded)]);
+        assert!(pending.decide("room-a", "reminded"));
+
+        assert!(pending.escalate(now + interval * 2, interval).is_empty());
+        assert!(pending.contains("answered"));
+        assert!(pending.contains("reminded"));
+    }
+
+    #[test]
+    fn test_unanswered_excludes_decided_and_other_rooms() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(600));
+        pending.insert("room-a", "waiting_2", student(now).0).unwrap();
+        pending.insert("room-a", "waiting_1", student(now).0).unwrap();
+        pending.insert("room-a", "approved", student(now).0).unwrap();
+        pending.insert("room-b", "elsewhere", student(now).0).unwrap();
+        assert!(pending.decide("room-a", "approved"));
+
+        assert_eq!(pending.unanswered("room-a"), vec!["waiting_1", "waiting_2"]);
+        assert!(pending.unanswered("room-c").is_empty());
+    }
+
+    #[test]
+    fn test_repeated_request_restarts_escalation() {
+        let now = Instant::now();
+        let interval = Duration::from_secs(60);
+        let mut pending = PendingStudents::new(10, Duration::from_secs(600));
+        pending.insert("room-a", "s1", escalating_student(now, EscalationPolicy::AutoDeny).0).unwrap();
+        pending.escalate(now + interval, interval);
+
+        pending.insert("room-a", "s1", escalating_student(now + interval, EscalationPolicy::AutoDeny).0).unwrap();
+        assert!(pending.escalate(now + interval * 2 - Duration::from_secs(1), interval).is_empty());
+        assert_eq!(pending.get("room-a", "s1").unwrap().stage, EscalationStage::Waiting);
+    }
+
+    #[test]
+    fn test_cap_rejects_new_students() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(2, Duration::from_secs(60));
+
+        assert!(pending.insert("room-a", "s1", student(now).0).is_ok());
+        assert!(pending.insert("room-b", "s2", student(now).0).is_ok());
+        assert_eq!(
+            pending.insert("room-a", "s3", student(now).0),
+            Err(PendingLimitReached { max: 2 })
+        );
+
+        // A student already counted may re-request, even at the cap
+        assert!(pending.insert("room-a", "s2", student(now).0).is_ok());
+        assert_eq!(pending.len(), 2);
+
+        pending.remove("s1");
+        assert!(pending.insert("room-a", "s3", student(now).0).is_ok());
+    }
+
+    #[test]
+    fn test_expire_after_ttl() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(60));
+        pending.insert("room-a", "old", student(now).0).unwrap();
+        pending.insert("room-a", "new", student(now + Duration::from_secs(30)).0).unwrap();
+
+        assert!(pending.expire(now + Duration::from_secs(59)).is_empty());
+
+        let expired = pending.expire(now + Duration::from_secs(60));
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].0.as_str(), expired[0].1.as_str()), ("room-a", "old"));
+        assert!(!pending.contains("old"));
+        assert!(pending.contains("new"));
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_lookup_is_room_scoped() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(60));
+        pending.insert("room-a", "s1", student(now).0).unwrap();
+
+        assert!(pending.get("room-a", "s1").is_some());
+        assert!(pending.get("room-b", "s1").is_none());
+
+        // Asking about another room replaces the earlier request
+        pending.insert("room-b", "s1", student(now).0).unwrap();
+        assert!(pending.get("room-a", "s1").is_none());
+        assert!(pending.get("room-b", "s1").is_some());
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_cleans_up_empty_rooms() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(60));
+        pending.insert("room-a", "s1", student(now).0).unwrap();
+
+        assert!(pending.remove("s1").is_some());
+        assert!(pending.remove("s1").is_none());
+        assert!(pending.rooms.is_empty());
+        assert_eq!(pending.len(), 0);
+    }
+
+    fn candidate(n: u16) -> PendingIceCandidate {
+        PendingIceCandidate {
+            candidate: format!("candidate:{} 1 udp 2122260223 192.0.2.1 {} typ host", n, 50000 + n),
+            sdp_mid: Some("0".to_string()),
+            sdp_mline_index: Some(0),
+        }
+    }
+
+    #[test]
+    fn test_ice_candidates_buffered_up_to_cap() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(60)).with_max_ice_candidates(2);
+        assert_eq!(pending.buffer_ice_candidate("room-a", "s1", candidate(1)), Err(IceBufferError::NotPending));
+
+        pending.insert("room-a", "s1", student(now).0).unwrap();
+        assert_eq!(pending.buffer_ice_candidate("room-a", "s1", candidate(1)), Ok(1));
+        assert_eq!(pending.buffer_ice_candidate("room-a", "s1", candidate(2)), Ok(2));
+        assert_eq!(pending.buffer_ice_candidate("room-a", "s1", candidate(3)), Err(IceBufferError::Full { max: 2 }));
+
+        let student = pending.remove("s1").unwrap();
+        assert_eq!(student.ice_candidates, vec![candidate(1), candidate(2)]);
+    }
+
+    #[test]
+    fn test_ice_candidates_follow_the_request() {
+        let now = Instant::now();
+        let mut pending = PendingStudents::new(10, Duration::from_secs(60));
+        pending.insert("room-a", "s1", student(now).0).unwrap();
+        pending.buffer_ice_candidate("room-a", "s1", candidate(1)).unwrap();
+
+        // Re-asking for the same room keeps the buffer
+        pending.insert("room-a", "s1", student(now).0).unwrap();
+        assert_eq!(pending.get("room-a", "s1").unwrap().ice_candidates.len(), 1);
+
+        // Asking about another room starts over
+        pending.insert("room-b", "s1", student(now).0).unwrap();
+        assert!(pending.get("room-b", "s1").unwrap().ice_candidates.is_empty());
+        assert_eq!(pending.buffer_ice_candidate("room-a", "s1", candidate(2)), Err(IceBufferError::NotPending));
+
+        // Expiry takes the buffer with the request
+        pending.buffer_ice_candidate("room-b", "s1", candidate(2)).unwrap();
+        let expired = pending.expire(now + Duration::from_secs(60));
+        assert_eq!(expired[0].2.ice_candidates, vec![candidate(2)]);
+        assert_eq!(pending.buffer_ice_candidate("room-b", "s1", candidate(3)), Err(IceBufferError::NotPending));
+    }
+}
@@ -0,0 +1,450 @@
+//! Connection recipe served by `GET /sfu/recipe`: everything a proctor or
+//! student client needs to connect to this deployment, built from the settings
+//! the server actually runs with so clients need not hard-code them.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+
+use super::webrtc_utils::CodecConfig;
+
+/// Version of the recipe document, bumped when a field changes meaning or goes away
+pub const RECIPE_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipeRole {
+    Proctor,
+    Student,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+    /// Browsers answer WebSocket pings but cannot send their own
+    #[default]
+    Web,
+    Native,
+}
+
+/// Query of `GET /sfu/recipe`; `client` defaults to `web`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RecipeQuery {
+    pub role: RecipeRole,
+    #[serde(default)]
+    pub client: ClientKind,
+}
+
+/// WebSocket keepalive applied to signaling connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Zero when the server sends no pings
+    pub ping_interval: Duration,
+    pub idle_timeout: Option<Duration>,
+    pub max_unexpected_frames: u32,
+}
+
+/// What a client may turn on or should expect from this deployment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeFeatures {
+    /// Students are asked for `RecordingConsent` when this is on
+    pub recording: bool,
+    /// Stopped recordings are announced again with `RecordingUploaded`
+    pub ipfs_uploads: bool,
+    pub blockchain: bool,
+    /// Joins may be answered with a `wrong_instance` redirect
+    pub room_affinity: bool,
+}
+
+/// Effective settings of a running server that shape how clients connect
+#[derive(Debug, Clone)]
+pub struct DeploymentProfile {
+    pub ice_servers: Vec<RTCIceServer>,
+    /// In order of preference
+    pub codecs: Vec<CodecConfig>,
+    pub max_sdp_bytes: usize,
+    pub max_messages_per_sec: Option<u32>,
+    pub max_room_peers: Option<usize>,
+    pub keepalive: Keepalive,
+    pub features: RecipeFeatures,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeIceServer {
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+impl From<&RTCIceServer> for RecipeIceServer {
+    fn from(server: &RTCIceServer) -> Self {
+        let non_empty = |value: &String| (!value.is_empty()).then(|| value.clone());
+        Self {
+            urls: server.urls.clone(),
+            username: non_empty(&server.username),
+            credential: non_empty(&server.credential),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+/// One message of the connection sequence, by its `type`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeStep {
+    pub direction: Direction,
+    pub message: String,
+    /// Messages that end the sequence instead of this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// Sent as many times as needed, e.g. one `IceCandidate` per candidate
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub repeated: bool,
+}
+
+impl RecipeStep {
+    fn send(message: &str) -> Self {
+        Self {
+            direction: Direction::Send,
+            message: message.to_string(),
+            alternatives: Vec::new(),
+            optional: false,
+            repeated: false,
+        }
+    }
+
+    fn receive(message: &str) -> Self {
+        Self { direction: Direction::Receive, ..Self::send(message) }
+    }
+
+    fn or(mut self, alternatives: &[&str]) -> Self {
+        self.alternatives = alternatives.iter().map(|message| message.to_string()).collect();
+        self
+    }
+
+    fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeCodec {
+    pub mime_type: String,
+    pub clock_rate: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub channels: u16,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sdp_fmtp_line: String,
+    pub payload_type: u8,
+}
+
+fn is_zero(value: &u16) -> bool {
+    *value == 0
+}
+
+impl From<&CodecConfig> for RecipeCodec {
+    fn from(codec: &CodecConfig) -> Self {
+        Self {
+            mime_type: codec.mime_type.clone(),
+            clock_rate: codec.clock_rate,
+            channels: codec.channels,
+            sdp_fmtp_line: codec.sdp_fmtp_line.clone(),
+            payload_type: codec.payload_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeLimits {
+    pub max_sdp_bytes: usize,
+    /// `None` when unlimited
+    pub max_messages_per_sec: Option<u32>,
+    /// Proctor included; `None` when unlimited
+    pub max_room_peers: Option<usize>,
+    /// Binary frames tolerated before the connection is closed
+    pub max_unexpected_frames: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecipeHeartbeat {
+    /// `None` when the server sends no pings
+    pub server_ping_interval_secs: Option<u64>,
+    /// Connections silent this long are closed; any inbound frame counts
+    pub idle_timeout_secs: Option<u64>,
+    /// How often the client should ping on its own, `None` when it need not
+    pub client_ping_interval_secs: Option<u64>,
+}
+
+/// Machine-readable connection instructions for one role and client kind
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionRecipe {
+    pub schema_version: u32,
+    pub role: RecipeRole,
+    pub client: ClientKind,
+    pub ice_servers: Vec<RecipeIceServer>,
+    pub sequence: Vec<RecipeStep>,
+    /// In order of preference
+    pub codecs: Vec<RecipeCodec>,
+    pub limits: RecipeLimits,
+    pub heartbeat: RecipeHeartbeat,
+    pub features: RecipeFeatures,
+}
+
+impl ConnectionRecipe {
+    pub fn build(profile: &DeploymentProfile, role: RecipeRole, client: ClientKind) -> Self {
+        let keepalive = profile.keepalive;
+        let server_ping_interval_secs = (!keepalive.ping_interval.is_zero()).then_some(keepalive.ping_interval.as_secs());
+
+        Self {
+            schema_version: RECIPE_SCHEMA_VERSION,
+            role,
+            client,
+            ice_servers: profile.ice_servers.iter().map(RecipeIceServer::from).collect(),
+            sequence: sequence(role, &profile.features),
+            codecs: profile.codecs.iter().map(RecipeCodec::from).collect(),
+            limits: RecipeLimits {
+                max_sdp_bytes: profile.max_sdp_bytes,
+                max_messages_per_sec: profile.max_messages_per_sec,
+                max_room_peers: profile.max_room_peers,
+                max_unexpected_frames: keepalive.max_unexpected_frames,
+            },
+            heartbeat: RecipeHeartbeat {
+                server_ping_interval_secs,
+                idle_timeout_secs: keepalive.idle_timeout.map(|timeout| timeout.as_secs()),
+                // A native client pings too, so a dead server is noticed from its side
+                client_ping_interval_secs: match client {
+                    ClientKind::Web => None,
+                    ClientKind::Native => server_ping_interval_secs,
+                },
+            },
+            features: profile.features,
+        }
+    }
+}
+
+/// Messages a client of `role` exchanges to get media flowing, in order
+fn sequence(role: RecipeRole, features: &RecipeFeatures) -> Vec<RecipeStep> {
+    let mut steps = match role {
+        RecipeRole::Proctor => vec![
+            RecipeStep::send("CreateRoom"),
+            RecipeStep::receive("RoomCreated"),
+        ],
+        RecipeRole::Student => vec![
+            RecipeStep::send("JoinRequest"),
+            RecipeStep::receive("join_request_sent"),
+            RecipeStep::receive("join_approved").or(&["join_denied", "join_request_expired"]),
+            RecipeStep::send("Join"),
+        ],
+    };
+
+    steps.extend([
+        RecipeStep::receive("StateSync"),
+        RecipeStep::receive("RoomState"),
+        RecipeStep::receive("Offer"),
+        RecipeStep::send("Answer"),
+        RecipeStep::send("IceCandidate").repeated(),
+    ]);
+    if role == RecipeRole::Student {
+        steps.push(RecipeStep::receive("join_success"));
+        if features.recording {
+            steps.push(RecipeStep::send("RecordingConsent").optional());
+        }
+    }
+    steps.push(RecipeStep::send("MediaReady"));
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn profile() -> DeploymentProfile {
+        DeploymentProfile {
+            ice_servers: vec![RTCIceServer {
+                urls: vec!["stun:stun.l.google.com:19302".to_string()],
+                ..Default::default()
+            }],
+            codecs: vec![CodecConfig::named("vp8").unwrap(), CodecConfig::named("opus").unwrap()],
+            max_sdp_bytes: 64 * 1024,
+            max_messages_per_sec: None,
+            max_room_peers: None,
+            keepalive: Keepalive {
+                ping_interval: Duration::from_secs(30),
+                idle_timeout: Some(Duration::from_secs(90)),
+                max_unexpected_frames: 10,
+            },
+            features: RecipeFeatures::default(),
+        }
+    }
+
+    #[test]
+    fn test_student_web_recipe_snapshot() {
+        let recipe = ConnectionRecipe::build(&profile(), RecipeRole::Student, ClientKind::Web);
+        assert_eq!(
+            serde_json::to_value(&recipe).unwrap(),
+            json!({
+                "schema_version": 1,
+                "role": "student",
+                "client": "web",
+                "ice_servers": [{ "urls": ["stun:stun.l.google.com:19302"] }],
+                "sequence": [
+                    { "direction": "send", "message": "JoinRequest" },
+                    { "direction": "receive", "message": "join_request_sent" },
+                    { "direction": "receive", "message": "join_approved", "alternatives": ["join_denied", "join_request_expired"] },
+                    { "direction": "send", "message": "Join" },
+                    { "direction": "receive", "message": "StateSync" },
+                    { "direction": "receive", "message": "RoomState" },
+                    { "direction": "receive", "message": "Offer" },
+                    { "direction": "send", "message": "Answer" },
+                    { "direction": "send", "message": "IceCandidate", "repeated": true },
+                    { "direction": "receive", "message": "join_success" },
+                    { "direction": "send", "message": "MediaReady" },
+                ],
+                "codecs": [
+                    { "mime_type": "video/VP8", "clock_rate": 90000, "payload_type": 96 },
+                    { "mime_type": "audio/opus", "clock_rate": 48000, "channels": 2, "sdp_fmtp_line": "minptime=10;useinbandfec=1", "payload_type": 111 },
+                ],
+                "limits": {
+                    "max_sdp_bytes": 65536,
+                    "max_messages_per_sec": null,
+                    "max_room_peers": null,
+                    "max_unexpected_frames": 10,
+                },
+                "heartbeat": {
+                    "server_ping_interval_secs": 30,
+                    "idle_timeout_secs": 90,
+                    "client_ping_interval_secs": null,
+                },
+                "features": {
+                    "recording": false,
+                    "ipfs_uploads": false,
+                    "blockchain": false,
+                    "room_affinity": false,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_proctor_native_recipe_snapshot() {
+        let mut profile = profile();
+        profile.ice_servers.push(RTCIceServer {
+            urls: vec!["turn:turn.example.com:3478".to_string(), "turns:turn.example.com:5349".to_string()],
+            username: "sfu".to_string(),
+            credential: "secret".to_string(),
+            ..Default::default()
+        });
+        profile.codecs = vec![CodecConfig::named("h264").unwrap(), CodecConfig::named("opus").unwrap()];
+        profile.max_messages_per_sec = Some(50);
+        profile.max_room_peers = Some(31);
+        profile.features = RecipeFeatures {
+            recording: true,
+            ipfs_uploads: true,
+            blockchain: true,
+            room_affinity: true,
+        };
+
+        let recipe = ConnectionRecipe::build(&profile, RecipeRole::Proctor, ClientKind::Native);
+        assert_eq!(
+            serde_json::to_value(&recipe).unwrap(),
+            json!({
+                "schema_version": 1,
+                "role": "proctor",
+                "client": "native",
+                "ice_servers": [
+                    { "urls": ["stun:stun.l.google.com:19302"] },
+                    {
+                        "urls": ["turn:turn.example.com:3478", "turns:turn.example.com:5349"],
+                        "username": "sfu",
+                        "credential": "secret",
+                    },
+                ],
+                "sequence": [
+                    { "direction": "send", "message": "CreateRoom" },
+                    { "direction": "receive", "message": "RoomCreated" },
+                    { "direction": "receive", "message": "StateSync" },
+                    { "direction": "receive", "message": "RoomState" },
+                    { "direction": "receive", "message": "Offer" },
+                    { "direction": "send", "message": "Answer" },
+                    { "direction": "send", "message": "IceCandidate", "repeated": true },
+                    { "direction": "send", "message": "MediaReady" },
+                ],
+                "codecs": [
+                    {
+                        "mime_type": "video/H264",
+                        "clock_rate": 90000,
+                        "sdp_fmtp_line": "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+                        "payload_type": 102,
+                    },
+                    { "mime_type": "audio/opus", "clock_rate": 48000, "channels": 2, "sdp_fmtp_line": "minptime=10;useinbandfec=1", "payload_type": 111 },
+                ],
+                "limits": {
+                    "max_sdp_bytes": 65536,
+                    "max_messages_per_sec": 50,
+                    "max_room_peers": 31,
+                    "max_unexpected_frames": 10,
+                },
+                "heartbeat": {
+                    "server_ping_interval_secs": 30,
+                    "idle_timeout_secs": 90,
+                    "client_ping_interval_secs": 30,
+                },
+                "features": {
+                    "recording": true,
+                    "ipfs_uploads": true,
+                    "blockchain": true,
+                    "room_affinity": true,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_consent_step_follows_recording() {
+        let mut profile = profile();
+        profile.features.recording = true;
+        profile.keepalive = Keepalive {
+            ping_interval: Duration::ZERO,
+            idle_timeout: None,
+            max_unexpected_frames: 10,
+        };
+
+        let recipe = ConnectionRecipe::build(&profile, RecipeRole::Student, ClientKind::Native);
+        let consent = recipe.sequence.iter().find(|step| step.message == "RecordingConsent").unwrap();
+        assert!(consent.optional);
+        assert_eq!(consent.direction, Direction::Send);
+        // Without server pings there is nothing for a native client to match
+        assert_eq!(
+            recipe.heartbeat,
+            RecipeHeartbeat {
+                server_ping_interval_secs: None,
+                idle_timeout_secs: None,
+                client_ping_interval_secs: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_parsing() {
+        let query: RecipeQuery = serde_json::from_value(json!({ "role": "proctor" })).unwrap();
+        assert_eq!((query.role, query.client), (RecipeRole::Proctor, ClientKind::Web));
+        assert!(serde_json::from_value::<RecipeQuery>(json!({ "role": "admin" })).is_err());
+        assert!(serde_json::from_value::<RecipeQuery>(json!({ "role": "student", "client": "tv" })).is_err());
+    }
+}
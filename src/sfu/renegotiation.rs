@@ -0,0 +1,398 @@
+//! Renegotiation tuning, adjustable on a live instance through the admin API,
+//! and the per-peer offer statistics that show what a setting does
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use super::room::PeerKey;
+use crate::config::env;
+use crate::metrics;
+
+const DEFAULT_DEBOUNCE_MS: u64 = 150;
+
+/// Equal to the debounce, so by default an offer goes out a fixed delay after the first change
+const DEFAULT_MAX_BATCH_MS: u64 = 150;
+
+const DEFAULT_RETRY_BACKOFF_MS: u64 = 200;
+
+const DEFAULT_ANSWER_TIMEOUT_MS: u64 = 10_000;
+
+/// How track changes are batched into renegotiation offers. Durations are in
+/// milliseconds, as the admin API shows and accepts them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenegotiationTuning {
+    /// Quiet period after the latest track change before the offer goes out
+    pub debounce_ms: u64,
+    /// Longest a batch stays open after its first change, however busy the room
+    pub max_batch_ms: u64,
+    /// Track changes that send the offer without waiting out the debounce (0 = no limit)
+    pub max_tracks_per_offer: u32,
+    /// Delay before the first retry when the peer still holds an unanswered
+    /// offer; doubles with each retry
+    pub retry_backoff_ms: u64,
+    /// An offer unanswered for this long counts as timed out
+    pub answer_timeout_ms: u64,
+}
+
+/// Body of `PUT /sfu/admin/negotiation-tuning`; fields left out keep their value
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenegotiationTuningUpdate {
+    pub debounce_ms: Option<u64>,
+    pub max_batch_ms: Option<u64>,
+    pub max_tracks_per_offer: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub answer_timeout_ms: Option<u64>,
+}
+
+impl RenegotiationTuning {
+    /// Reads `RENEGOTIATION_DEBOUNCE_MS`, `RENEGOTIATION_MAX_BATCH_MS`,
+    /// `RENEGOTIATION_MAX_TRACKS_PER_OFFER`, `RENEGOTIATION_RETRY_BACKOFF_MS`
+    /// and `RENEGOTIATION_ANSWER_TIMEOUT_MS`
+    pub fn from_env() -> Result<Self, String> {
+        let defaults = Self::default();
+        let tuning = Self {
+            debounce_ms: env::get_parsed("RENEGOTIATION_DEBOUNCE_MS").unwrap_or(defaults.debounce_ms),
+            max_batch_ms: env::get_parsed("RENEGOTIATION_MAX_BATCH_MS").unwrap_or(defaults.max_batch_ms),
+            max_tracks_per_offer: env::get_parsed("RENEGOTIATION_MAX_TRACKS_PER_OFFER")
+                .unwrap_or(defaults.max_tracks_per_offer),
+            retry_backoff_ms: env::get_parsed("RENEGOTIATION_RETRY_BACKOFF_MS").unwrap_or(defaults.retry_backoff_ms),
+            answer_timeout_ms: env::get_parsed("RENEGOTIATION_ANSWER_TIMEOUT_MS")
+                .unwrap_or(defaults.answer_timeout_ms),
+        };
+        tuning.validate()?;
+        Ok(tuning)
+    }
+
+    /// Rejects settings that would hold offers back longer than peers are
+    /// given to answer them
+    pub fn validate(&self) -> Result<(), String> {
+        if self.answer_timeout_ms == 0 {
+            return Err("answer_timeout_ms must be positive".to_string());
+        }
+        if self.debounce_ms > self.answer_timeout_ms {
+            return Err(format!(
+                "debounce_ms ({}) exceeds answer_timeout_ms ({})",
+                self.debounce_ms, self.answer_timeout_ms
+            ));
+        }
+        if self.max_batch_ms < self.debounce_ms {
+            return Err(format!(
+                "max_batch_ms ({}) is shorter than debounce_ms ({})",
+                self.max_batch_ms, self.debounce_ms
+            ));
+        }
+        if self.max_batch_ms > self.answer_timeout_ms {
+            return Err(format!(
+                "max_batch_ms ({}) exceeds answer_timeout_ms ({})",
+                self.max_batch_ms, self.answer_timeout_ms
+            ));
+        }
+        Ok(())
+    }
+
+    /// These settings with `update` applied, if the result is valid
+    pub fn updated(&self, update: &RenegotiationTuningUpdate) -> Result<Self, String> {
+        let tuning = Self {
+            debounce_ms: update.debounce_ms.unwrap_or(self.debounce_ms),
+            max_batch_ms: update.max_batch_ms.unwrap_or(self.max_batch_ms),
+            max_tracks_per_offer: update.max_tracks_per_offer.unwrap_or(self.max_tracks_per_offer),
+            retry_backoff_ms: update.retry_backoff_ms.unwrap_or(self.retry_backoff_ms),
+            answer_timeout_ms: update.answer_timeout_ms.unwrap_or(self.answer_timeout_ms),
+        };
+        tuning.validate()?;
+        Ok(tuning)
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+
+    pub fn max_batch(&self) -> Duration {
+        Duration::from_millis(self.max_batch_ms)
+    }
+
+    /// Delay before retry number `retry` (0-based)
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.retry_backoff_ms.saturating_mul(2_u64.saturating_pow(retry)))
+    }
+
+    pub fn answer_timeout(&self) -> Duration {
+        Duration::from_millis(self.answer_timeout_ms)
+    }
+
+    /// Whether a batch holding `tracks` changes should go out now
+    pub fn batch_full(&self, tracks: u32) -> bool {
+        self.max_tracks_per_offer > 0 && tracks >= self.max_tracks_per_offer
+    }
+}
+
+impl Default for RenegotiationTuning {
+    fn default() -> Self {
+        Self {
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            max_batch_ms: DEFAULT_MAX_BATCH_MS,
+            max_tracks_per_offer: 0,
+            retry_backoff_ms: DEFAULT_RETRY_BACKOFF_MS,
+            answer_timeout_ms: DEFAULT_ANSWER_TIMEOUT_MS,
+        }
+    }
+}
+
+/// Renegotiation activity of one peer connection
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PeerRenegotiationStats {
+    pub offers_sent: u64,
+    /// Track changes carried by those offers
+    pub tracks_offered: u64,
+    pub answers: u64,
+    /// Offer to answer, summed over answered offers
+    pub answer_time_ms_total: u64,
+    pub last_answer_ms: Option<u64>,
+    /// Offers postponed because the peer was still answering an earlier one
+    pub retries: u64,
+    pub answer_timeouts: u64,
+    /// An offer is out and not yet answered
+    pub awaiting_answer: bool,
+}
+
+/// Renegotiation activity of a room, from its peers' stats
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RoomRenegotiationStats {
+    pub offers_sent: u64,
+    pub tracks_per_offer: f64,
+    pub mean_answer_ms: Option<u64>,
+    pub retries: u64,
+    pub answer_timeouts: u64,
+    pub peers: BTreeMap<String, PeerRenegotiationStats>,
+}
+
+/// Body of `GET /sfu/admin/negotiation-stats`
+#[derive(Debug, Clone, Serialize)]
+pub struct RenegotiationSnapshot {
+    pub tuning: RenegotiationTuning,
+    pub rooms: BTreeMap<String, RoomRenegotiationStats>,
+}
+
+#[derive(Default)]
+struct PeerState {
+    stats: PeerRenegotiationStats,
+    /// When the unanswered offer was sent
+    offer_sent_at: Option<Instant>,
+}
+
+/// Live tuning shared by a server's renegotiation tasks, and what they did
+pub struct RenegotiationControl {
+    tuning: RwLock<RenegotiationTuning>,
+    peers: Mutex<HashMap<PeerKey, PeerState>>,
+    /// Wakes the task of an open batch that filled up
+    flushes: Mutex<HashMap<PeerKey, Arc<Notify>>>,
+}
+
+impl RenegotiationControl {
+    pub fn new(tuning: RenegotiationTuning) -> Self {
+        Self {
+            tuning: RwLock::new(tuning),
+            peers: Mutex::new(HashMap::new()),
+            flushes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn tuning(&self) -> RenegotiationTuning {
+        *self.tuning.read().unwrap()
+    }
+
+    /// Applies `update`, returning the new settings. Batches already open keep
+    /// running under the settings they read last.
+    pub fn update(&self, update: &RenegotiationTuningUpdate) -> Result<RenegotiationTuning, String> {
+        let mut tuning = self.tuning.write().unwrap();
+        let updated = tuning.updated(update)?;
+        *tuning = updated;
+        tracing::info!(tuning = ?updated, "Updated renegotiation tuning");
+        Ok(updated)
+    }
+
+    /// Registers the batch just opened for `peer`, returning what wakes its task early
+    pub fn open_batch(&self, peer: &PeerKey) -> Arc<Notify> {
+        let flush = Arc::new(Notify::new());
+        self.flushes.lock().unwrap().insert(peer.clone(), flush.clone());
+        flush
+    }
+
+    /// Sends the open batch of `peer` without waiting out the debounce
+    pub fn flush_batch(&self, peer: &PeerKey) {
+        if let Some(flush) = self.flushes.lock().unwrap().get(peer) {
+            flush.notify_one();
+        }
+    }
+
+    /// Unregisters a batch as its offer is prepared, unless a newer one replaced it
+    pub fn close_batch(&self, peer: &PeerKey, flush: &Arc<Notify>) {
+        let mut flushes = self.flushes.lock().unwrap();
+        if flushes.get(peer).is_some_and(|current| Arc::ptr_eq(current, flush)) {
+            flushes.remove(peer);
+        }
+    }
+
+    /// Counts an offer carrying `tracks` changes, returning when it was sent
+    pub fn offer_sent(&self, peer: &PeerKey, tracks: u32) -> Instant {
+        let now = Instant::now();
+        let mut peers = self.peers.lock().unwrap();
+        let state = peers.entry(peer.clone()).or_default();
+        state.stats.offers_sent += 1;
+        state.stats.tracks_offered += u64::from(tracks);
+        state.stats.awaiting_answer = true;
+        state.offer_sent_at = Some(now);
+        metrics::metrics().renegotiations_total.inc();
+        metrics::metrics().renegotiation_tracks_total.inc_by(u64::from(tracks));
+        now
+    }
+
+    /// Records the answer to the outstanding offer, returning how long it took.
+    /// `None` when no renegotiation offer was waiting, as for the initial offer.
+    pub fn answered(&self, peer: &PeerKey) -> Option<Duration> {
+        let mut peers = self.peers.lock().unwrap();
+        let state = peers.get_mut(peer)?;
+        let elapsed = state.offer_sent_at.take()?.elapsed();
+        state.stats.answers += 1;
+        state.stats.answer_time_ms_total += elapsed.as_millis() as u64;
+        state.stats.last_answer_ms = Some(elapsed.as_millis() as u64);
+        state.stats.awaiting_answer = false;
+        metrics::metrics().renegotiation_duration.observe(elapsed);
+        Some(elapsed)
+    }
+
+    /// Counts the offer sent at `sent_at` as timed out if it is still unanswered
+    pub fn check_answer(&self, peer: &PeerKey, sent_at: Instant) -> bool {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(state) = peers.get_mut(peer).filter(|state| state.offer_sent_at == Some(sent_at)) else {
+            return false;
+        };
+        state.stats.answer_timeouts += 1;
+        metrics::metrics().renegotiation_answer_timeouts_total.inc();
+        true
+    }
+
+    pub fn retried(&self, peer: &PeerKey) {
+        self.peers.lock().unwrap().entry(peer.clone()).or_default().stats.retries += 1;
+        metrics::metrics().renegotiation_retries_total.inc();
+    }
+
+    /// Drops the stats of a departed peer
+    pub fn forget(&self, peer: &PeerKey) {
+        self.peers.lock().unwrap().remove(peer);
+        self.flushes.lock().unwrap().remove(peer);
+    }
+
+    pub fn peer_stats(&self, peer: &PeerKey) -> Option<PeerRenegotiationStats> {
+        self.peers.lock().unwrap().get(peer).map(|state| state.stats.clone())
+    }
+
+    /// Current tuning and the stats of every room with a connected peer
+    pub fn snapshot(&self) -> RenegotiationSnapshot {
+        let mut rooms: BTreeMap<String, RoomRenegotiationStats> = BTreeMap::new();
+        for (peer, state) in self.peers.lock().unwrap().iter() {
+            rooms
+                .entry(peer.room_id.clone())
+                .or_default()
+                .peers
+                .insert(peer.peer_id.clone(), state.stats.clone());
+        }
+
+        for room in rooms.values_mut() {
+            let mut tracks = 0;
+            let mut answers = 0;
+            let mut answer_time_ms = 0;
+            for stats in room.peers.values() {
+                room.offers_sent += stats.offers_sent;
+                room.retries += stats.retries;
+                room.answer_timeouts += stats.answer_timeouts;
+                tracks += stats.tracks_offered;
+                answers += stats.answers;
+                answer_time_ms += stats.answer_time_ms_total;
+            }
+            if room.offers_sent > 0 {
+                room.tracks_per_offer = tracks as f64 / room.offers_sent as f64;
+            }
+            room.mean_answer_ms = (answers > 0).then(|| answer_time_ms / answers);
+        }
+
+        RenegotiationSnapshot { tuning: self.tuning(), rooms }
+    }
+}
+
+impl Default for RenegotiationControl {
+    fn default() -> Self {
+        Self::new(RenegotiationTuning::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validation_rejects_debounce_past_answer_timeout() {
+        assert!(RenegotiationTuning::default().validate().is_ok());
+
+        let tuning = RenegotiationTuning::default();
+        let err = tuning
+            .updated(&RenegotiationTuningUpdate { debounce_ms: Some(20_000), max_batch_ms: Some(20_000), ..Default::default() })
+            .unwrap_err();
+        assert_eq!(err, "debounce_ms (20000) exceeds answer_timeout_ms (10000)");
+
+        let err = tuning
+            .updated(&RenegotiationTuningUpdate { debounce_ms: Some(500), ..Default::default() })
+            .unwrap_err();
+        assert_eq!(err, "max_batch_ms (150) is shorter than debounce_ms (500)");
+
+        let control = RenegotiationControl::default();
+        assert!(control
+            .update(&RenegotiationTuningUpdate { answer_timeout_ms: Some(100), ..Default::default() })
+            .is_err());
+        assert_eq!(control.tuning(), tuning);
+
+        let updated = control
+            .update(&RenegotiationTuningUpdate { debounce_ms: Some(400), max_batch_ms: Some(2000), ..Default::default() })
+            .unwrap();
+        assert_eq!(updated.debounce(), Duration::from_millis(400));
+        assert_eq!(updated.retry_delay(2), Duration::from_millis(800));
+        assert_eq!(control.tuning(), updated);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_snapshot_groups_peers_by_room() {
+        let control = RenegotiationControl::default();
+        let proctor = PeerKey::new("123456", "proctor");
+        let student = PeerKey::new("123456", "student_1");
+
+        let sent_at = control.offer_sent(&proctor, 3);
+        tokio::time::advance(Duration::from_millis(40)).await;
+        assert_eq!(control.answered(&proctor), Some(Duration::from_millis(40)));
+        assert_eq!(control.answered(&proctor), None);
+        assert!(!control.check_answer(&proctor, sent_at));
+
+        let sent_at = control.offer_sent(&proctor, 1);
+        control.retried(&student);
+        assert!(control.check_answer(&proctor, sent_at));
+        control.offer_sent(&PeerKey::new("654321", "proctor"), 2);
+
+        let snapshot = control.snapshot();
+        let room = &snapshot.rooms["123456"];
+        assert_eq!(room.offers_sent, 2);
+        assert_eq!(room.tracks_per_offer, 2.0);
+        assert_eq!(room.mean_answer_ms, Some(40));
+        assert_eq!(room.retries, 1);
+        assert_eq!(room.answer_timeouts, 1);
+        assert!(room.peers["proctor"].awaiting_answer);
+        assert_eq!(snapshot.rooms["654321"].offers_sent, 1);
+
+        control.forget(&student);
+        control.forget(&proctor);
+        assert!(!control.snapshot().rooms.contains_key("123456"));
+    }
+}
@@ -1,21 +1,103 @@
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use super::escalation::EscalationPolicy;
+use super::roster::{Roster, RosterEntry};
+use super::state_log::{RoomEvent, RoomEventLog, StateSync};
+use super::timezone::RoomLocale;
+use crate::metrics;
+use crate::recording::SessionMetadata;
+
+/// Random IDs tried before creating a room gives up
+const ROOM_ID_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PeerRole {
     Proctor,
     Student,
 }
 
+/// A peer within one room. The same peer ID can be in several rooms at once
+/// (a proctor running parallel sessions), so per-peer state is keyed by both.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerKey {
+    pub room_id: String,
+    pub peer_id: String,
+}
+
+impl PeerKey {
+    pub fn new(room_id: impl Into<String>, peer_id: impl Into<String>) -> Self {
+        Self {
+            room_id: room_id.into(),
+            peer_id: peer_id.into(),
+        }
+    }
+}
+
+impl fmt::Display for PeerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.room_id, self.peer_id)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Peer {
     pub id: String,
     pub role: PeerRole,
     pub room_id: String,
     pub name: Option<String>,
+    /// Joined under an entry of the room roster, so the wallet came from the institution
+    pub pre_registered: bool,
+}
+
+/// Why a peer left its room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectCause {
+    /// The peer sent Leave
+    Left,
+    /// The proctor kicked the peer
+    Kicked,
+    /// The WebSocket failed, closed without Leave, or stopped answering pings
+    ConnectionLost,
+    /// The proctor's departure closed the room
+    RoomClosed,
+    /// A proctor moved the student to another room
+    Transferred,
+}
+
+impl DisconnectCause {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisconnectCause::Left => "left",
+            DisconnectCause::Kicked => "kicked",
+            DisconnectCause::ConnectionLost => "connection_lost",
+            DisconnectCause::RoomClosed => "room_closed",
+            DisconnectCause::Transferred => "transferred",
+        }
+    }
+}
+
+/// A peer removed from its room, and why
+#[derive(Debug, Clone)]
+pub struct DepartedPeer {
+    pub id: String,
+    pub room_id: String,
+    pub role: PeerRole,
+    pub name: Option<String>,
+    pub cause: DisconnectCause,
+    /// Students removed with the room when a proctor departs
+    pub displaced: Vec<DepartedPeer>,
+}
+
+impl DepartedPeer {
+    pub fn key(&self) -> PeerKey {
+        PeerKey::new(self.room_id.clone(), self.id.clone())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -24,36 +106,125 @@ pub struct Room {
     pub proctor_id: String,
     pub students: Vec<String>,
     pub created_at: std::time::SystemTime,
+    /// Timezone and locale for human-facing timestamps
+    pub locale: RoomLocale,
+    /// Exam title, course code and notes set by the proctor
+    pub metadata: SessionMetadata,
+    /// Bearer token for the proctor's room-scoped HTTP routes
+    pub proctor_token: String,
+    /// Students pre-registered by the institution
+    pub roster: Roster,
+    /// What happens to join requests the proctor leaves unanswered
+    pub escalation: EscalationPolicy,
+    /// Whether peers are recorded; off for a practice room created without recording
+    pub recording_enabled: bool,
+    /// State changes delivered to the room, replayed as `StateSync` on (re)join
+    pub events: RoomEventLog,
+}
+
+/// A room as the admin routes list it
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomSummary {
+    pub room_id: String,
+    pub proctor_id: String,
+    pub proctor_name: Option<String>,
+    pub student_count: usize,
+    /// Unix time in milliseconds
+    pub created_at: u64,
+}
+
+impl RoomSummary {
+    fn of(room: &Room) -> Self {
+        Self {
+            room_id: room.id.clone(),
+            proctor_id: room.proctor_id.clone(),
+            proctor_name: None,
+            student_count: room.students.len(),
+            created_at: room
+                .created_at
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+        }
+    }
 }
 
 pub struct RoomManager {
     rooms: Arc<RwLock<HashMap<String, Room>>>,
-    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    peers: Arc<RwLock<HashMap<PeerKey, Peer>>>,
+    /// Proctor ID -> rooms it runs, in creation order
+    owners: Arc<RwLock<HashMap<String, Vec<String>>>>,
+    /// Instance routing key prepended to generated room IDs
+    id_prefix: Option<String>,
+    /// Rooms one proctor may run at once; `None` means unlimited
+    max_rooms_per_proctor: Option<usize>,
 }
 
 impl RoomManager {
     pub fn new() -> Arc<Self> {
+        Self::with_limits(None, None)
+    }
+
+    /// Room IDs become `{prefix}-{6 digits}` so a routing layer can find the owning instance
+    pub fn with_id_prefix(id_prefix: String) -> Arc<Self> {
+        Self::with_limits(Some(id_prefix), None)
+    }
+
+    pub fn with_limits(id_prefix: Option<String>, max_rooms_per_proctor: Option<usize>) -> Arc<Self> {
         Arc::new(Self {
             rooms: Arc::new(RwLock::new(HashMap::new())),
             peers: Arc::new(RwLock::new(HashMap::new())),
+            owners: Arc::new(RwLock::new(HashMap::new())),
+            id_prefix,
+            max_rooms_per_proctor,
         })
     }
 
     /// Generate a random room ID
-    fn generate_room_id() -> String {
+    fn generate_room_id(&self) -> String {
         let mut rng = rand::thread_rng();
-        format!("{:06}", rng.gen_range(100000..999999))
+        let digits = format!("{:06}", rng.gen_range(100000..999999));
+        match self.id_prefix {
+            Some(ref prefix) => format!("{}-{}", prefix, digits),
+            None => digits,
+        }
     }
 
-    /// Create a new room with a proctor
-    pub async fn create_room(&self, proctor_id: String, proctor_name: Option<String>) -> Result<String, String> {
-        let room_id = Self::generate_room_id();
+    /// Create a new room with a proctor; `locale` controls human-facing timestamps.
+    /// A proctor may run several rooms, up to `max_rooms_per_proctor`.
+    pub async fn create_room(
+        &self,
+        proctor_id: String,
+        proctor_name: Option<String>,
+        locale: RoomLocale,
+    ) -> Result<String, String> {
+        let mut rooms = self.rooms.write().await;
+        let mut peers = self.peers.write().await;
+        let mut owners = self.owners.write().await;
+
+        let owned = owners.get(&proctor_id).map_or(0, Vec::len);
+        if let Some(max) = self.max_rooms_per_proctor {
+            if owned >= max {
+                return Err(format!("Proctor {} already runs {} rooms, the most allowed", proctor_id, max));
+            }
+        }
+
+        // Drawn under the lock, so a free ID stays free until the room is inserted
+        let room_id = unused_room_id(|| self.generate_room_id(), |id| rooms.contains_key(id))
+            .ok_or_else(|| "No free room ID found, please try again".to_string())?;
 
         let room = Room {
             id: room_id.clone(),
             proctor_id: proctor_id.clone(),
             students: Vec::new(),
             created_at: std::time::SystemTime::now(),
+            locale,
+            metadata: SessionMetadata::default(),
+            proctor_token: hex::encode(rand::thread_rng().gen::<[u8; 32]>()),
+            roster: Roster::default(),
+            escalation: EscalationPolicy::default(),
+            recording_enabled: true,
+            events: RoomEventLog::default(),
         };
 
         let peer = Peer {
@@ -61,24 +232,20 @@ impl RoomManager {
             role: PeerRole::Proctor,
             room_id: room_id.clone(),
             name: proctor_name,
+            pre_registered: false,
         };
 
-        let mut rooms = self.rooms.write().await;
-        let mut peers = self.peers.write().await;
-
-        // Check if room ID already exists (unlikely but possible)
-        if rooms.contains_key(&room_id) {
-            return Err("Room ID collision, please try again".to_string());
-        }
-
         rooms.insert(room_id.clone(), room);
-        peers.insert(proctor_id, peer);
+        peers.insert(PeerKey::new(room_id.clone(), proctor_id.clone()), peer);
+        owners.entry(proctor_id).or_default().push(room_id.clone());
+        publish_counts(&rooms, &peers);
 
-        tracing::info!(room_id = %room_id, "Room created by proctor");
+        tracing::info!(room_id = %room_id, rooms_owned = owned + 1, "Room created by proctor");
         Ok(room_id)
     }
 
-    /// Join an existing room as a student
+    /// Join an existing room as a student. Students on the roster get its
+    /// display name when they did not send one.
     pub async fn join_room(&self, room_id: String, student_id: String, student_name: Option<String>) -> Result<(), String> {
         let mut rooms = self.rooms.write().await;
         let mut peers = self.peers.write().await;
@@ -93,73 +260,228 @@ impl RoomManager {
 
         room.students.push(student_id.clone());
 
+        let roster_entry = room.roster.get(&student_id);
         let peer = Peer {
             id: student_id.clone(),
             role: PeerRole::Student,
             room_id: room_id.clone(),
-            name: student_name,
+            name: student_name.or_else(|| roster_entry.and_then(|entry| entry.name.clone())),
+            pre_registered: roster_entry.is_some(),
         };
 
-        peers.insert(student_id.clone(), peer);
+        peers.insert(PeerKey::new(room_id.clone(), student_id.clone()), peer);
+        publish_counts(&rooms, &peers);
 
         tracing::info!(student_id = %student_id, room_id = %room_id, "Student joined room");
         Ok(())
     }
 
+    pub async fn room_count(&self) -> usize {
+        self.rooms.read().await.len()
+    }
+
+    pub async fn peer_count(&self) -> usize {
+        self.peers.read().await.len()
+    }
+
+    /// Every room, oldest first
+    pub async fn room_summaries(&self) -> Vec<RoomSummary> {
+        let mut summaries: Vec<RoomSummary> = self.rooms.read().await.values().map(RoomSummary::of).collect();
+        self.add_proctor_names(&mut summaries).await;
+        summaries.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.room_id.cmp(&b.room_id)));
+        summaries
+    }
+
+    pub async fn room_summary(&self, room_id: &str) -> Option<RoomSummary> {
+        let mut summary = RoomSummary::of(self.rooms.read().await.get(room_id)?);
+        self.add_proctor_names(std::slice::from_mut(&mut summary)).await;
+        Some(summary)
+    }
+
+    /// Fills in proctor names. Called without the rooms lock held, since
+    /// `remove_peer` takes the peers lock first.
+    async fn add_proctor_names(&self, summaries: &mut [RoomSummary]) {
+        let peers = self.peers.read().await;
+        for summary in summaries {
+            summary.proctor_name = peers
+                .get(&PeerKey::new(summary.room_id.as_str(), summary.proctor_id.as_str()))
+                .and_then(|proctor| proctor.name.clone());
+        }
+    }
+
     /// Get peer information
-    pub async fn get_peer(&self, peer_id: &str) -> Option<Peer> {
+    pub async fn get_peer(&self, key: &PeerKey) -> Option<Peer> {
         let peers = self.peers.read().await;
-        peers.get(peer_id).cloned()
+        peers.get(key).cloned()
+    }
+
+    /// Rooms the proctor runs, in the order it created them
+    pub async fn rooms_owned_by(&self, proctor_id: &str) -> Vec<String> {
+        let owners = self.owners.read().await;
+        owners.get(proctor_id).cloned().unwrap_or_default()
     }
 
     /// Get room information
+    pub async fn get_room_locale(&self, room_id: &str) -> RoomLocale {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).map(|r| r.locale.clone()).unwrap_or_default()
+    }
+
+    /// Replace a room's metadata; the last write wins
+    pub async fn set_room_metadata(&self, room_id: &str, metadata: SessionMetadata) -> Result<(), String> {
+        let mut rooms = self.rooms.write().await;
+        let room = rooms.get_mut(room_id)
+            .ok_or_else(|| format!("Room {} does not exist", room_id))?;
+        room.metadata = metadata;
+        Ok(())
+    }
+
+    pub async fn get_proctor_token(&self, room_id: &str) -> Option<String> {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).map(|r| r.proctor_token.clone())
+    }
+
+    /// Replace a room's roster; students already in the room keep their binding
+    pub async fn set_roster(&self, room_id: &str, roster: Roster) -> Result<(), String> {
+        let mut rooms = self.rooms.write().await;
+        let room = rooms.get_mut(room_id)
+            .ok_or_else(|| format!("Room {} does not exist", room_id))?;
+        room.roster = roster;
+        Ok(())
+    }
+
+    pub async fn set_escalation_policy(&self, room_id: &str, policy: EscalationPolicy) -> Result<(), String> {
+        let mut rooms = self.rooms.write().await;
+        let room = rooms.get_mut(room_id)
+            .ok_or_else(|| format!("Room {} does not exist", room_id))?;
+        room.escalation = policy;
+        Ok(())
+    }
+
+    pub async fn get_escalation_policy(&self, room_id: &str) -> EscalationPolicy {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).map(|r| r.escalation).unwrap_or_default()
+    }
+
+    pub async fn set_recording_enabled(&self, room_id: &str, enabled: bool) -> Result<(), String> {
+        let mut rooms = self.rooms.write().await;
+        let room = rooms.get_mut(room_id)
+            .ok_or_else(|| format!("Room {} does not exist", room_id))?;
+        room.recording_enabled = enabled;
+        Ok(())
+    }
+
+    /// Whether the room records its peers; rooms not found do, as before rooms could opt out
+    pub async fn is_recording_enabled(&self, room_id: &str) -> bool {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).is_none_or(|r| r.recording_enabled)
+    }
+
+    pub async fn get_roster_entry(&self, room_id: &str, peer_id: &str) -> Option<RosterEntry> {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).and_then(|r| r.roster.get(peer_id).cloned())
+    }
+
     pub async fn get_room(&self, room_id: &str) -> Option<Room> {
         let rooms = self.rooms.read().await;
         rooms.get(room_id).cloned()
     }
 
-    /// Remove a peer from their room
-    /// Returns (room_id, role, name) if peer was found
-    pub async fn remove_peer(&self, peer_id: &str) -> Option<(String, PeerRole, Option<String>)> {
+    /// Remove a peer from their room for `cause`. A departing proctor closes the
+    /// room, and its students are returned as displaced with `RoomClosed`.
+    pub async fn remove_peer(&self, key: &PeerKey, cause: DisconnectCause) -> Option<DepartedPeer> {
         let mut peers = self.peers.write().await;
-
-        if let Some(peer) = peers.remove(peer_id) {
-            let mut rooms = self.rooms.write().await;
-
-            if let Some(room) = rooms.get_mut(&peer.room_id) {
-                match peer.role {
-                    PeerRole::Proctor => {
-                        // If proctor leaves, remove the entire room
-                        tracing::info!(room_id = %peer.room_id, "Proctor left, closing room");
-                        rooms.remove(&peer.room_id);
-
-                        // Remove all students from this room
-                        let students_to_remove: Vec<String> = peers
-                            .iter()
-                            .filter(|(_, p)| p.room_id == peer.room_id)
-                            .map(|(id, _)| id.clone())
-                            .collect();
-
-                        for student_id in students_to_remove {
-                            peers.remove(&student_id);
+        let peer = peers.remove(key)?;
+        let mut rooms = self.rooms.write().await;
+        let mut displaced = Vec::new();
+
+        if let Some(room) = rooms.get_mut(&peer.room_id) {
+            match peer.role {
+                PeerRole::Proctor => {
+                    // If proctor leaves, remove the entire room
+                    tracing::info!(room_id = %peer.room_id, cause = cause.as_str(), "Proctor left, closing room");
+                    let room = rooms.remove(&peer.room_id).expect("room was just found");
+
+                    // The proctor's other rooms carry on
+                    let mut owners = self.owners.write().await;
+                    if let Some(owned) = owners.get_mut(&peer.id) {
+                        owned.retain(|room_id| room_id != &peer.room_id);
+                        if owned.is_empty() {
+                            owners.remove(&peer.id);
+                        }
+                    }
+
+                    // Remove all students from this room, in join order
+                    for student_id in &room.students {
+                        if let Some(student) = peers.remove(&PeerKey::new(room.id.clone(), student_id.clone())) {
+                            displaced.push(DepartedPeer {
+                                id: student.id,
+                                room_id: student.room_id,
+                                role: student.role,
+                                name: student.name,
+                                cause: DisconnectCause::RoomClosed,
+                                displaced: Vec::new(),
+                            });
                         }
-                    },
-                    PeerRole::Student => {
-                        // Remove student from room's student list
-                        room.students.retain(|id| id != peer_id);
-                        tracing::info!(
-                            student_id = %peer_id,
-                            room_id = %peer.room_id,
-                            "Student left room"
-                        );
-                    },
-                }
+                    }
+                },
+                PeerRole::Student => {
+                    // Remove student from room's student list
+                    room.students.retain(|id| id != &key.peer_id);
+                    tracing::info!(
+                        student_id = %key.peer_id,
+                        room_id = %peer.room_id,
+                        cause = cause.as_str(),
+                        "Student left room"
+                    );
+                },
             }
+        }
+        publish_counts(&rooms, &peers);
+
+        Some(DepartedPeer {
+            id: peer.id,
+            room_id: peer.room_id,
+            role: peer.role,
+            name: peer.name,
+            cause,
+            displaced,
+        })
+    }
 
-            return Some((peer.room_id, peer.role, peer.name));
+    /// Moves a student from their room to `to_room_id` in one step, keeping
+    /// their name. Refused when the destination does not exist or already has
+    /// the peer, leaving the student where they were.
+    pub async fn move_student(&self, key: &PeerKey, to_room_id: &str) -> Result<(), String> {
+        let mut peers = self.peers.write().await;
+        let mut rooms = self.rooms.write().await;
+
+        let to_key = PeerKey::new(to_room_id, key.peer_id.as_str());
+        if !matches!(peers.get(key), Some(peer) if matches!(peer.role, PeerRole::Student)) {
+            return Err(format!("Student {} is not in room {}", key.peer_id, key.room_id));
+        }
+        if peers.contains_key(&to_key) {
+            return Err(format!("Peer {} is already in room {}", key.peer_id, to_room_id));
+        }
+        let pre_registered = rooms
+            .get(to_room_id)
+            .ok_or_else(|| format!("Room {} does not exist", to_room_id))?
+            .roster
+            .get(&key.peer_id)
+            .is_some();
+
+        let mut peer = peers.remove(key).expect("student was just found");
+        if let Some(room) = rooms.get_mut(&key.room_id) {
+            room.students.retain(|id| id != &key.peer_id);
         }
+        let destination = rooms.get_mut(to_room_id).expect("room was just found");
+        destination.students.push(key.peer_id.clone());
+        peer.room_id = to_room_id.to_string();
+        peer.pre_registered = pre_registered;
+        peers.insert(to_key, peer);
 
-        None
+        tracing::info!(student_id = %key.peer_id, from_room_id = %key.room_id, to_room_id = %to_room_id, "Student moved to another room");
+        Ok(())
     }
 
     /// Get all peers in a room
@@ -171,6 +493,29 @@ impl RoomManager {
             .collect()
     }
 
+    /// Peers of a room in the order they joined: the proctor, then students.
+    /// A student who leaves and rejoins moves to the end.
+    pub async fn join_order(&self, room_id: &str) -> Vec<String> {
+        let rooms = self.rooms.read().await;
+        rooms
+            .get(room_id)
+            .map(|room| std::iter::once(room.proctor_id.clone()).chain(room.students.iter().cloned()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Logs a state change for `peer_id`, or for the whole room when `None`.
+    /// Returns its sequence number, `None` if the room does not exist.
+    pub async fn record_event(&self, room_id: &str, peer_id: Option<&str>, event: RoomEvent) -> Option<u64> {
+        let mut rooms = self.rooms.write().await;
+        rooms.get_mut(room_id).map(|room| room.events.append(peer_id, event))
+    }
+
+    /// What `peer_id` has been told in the room, rebuilt from its event log
+    pub async fn state_sync(&self, room_id: &str, peer_id: &str) -> Option<StateSync> {
+        let rooms = self.rooms.read().await;
+        rooms.get(room_id).map(|room| room.events.snapshot(peer_id))
+    }
+
     /// Check if a room exists
     pub async fn room_exists(&self, room_id: &str) -> bool {
         let rooms = self.rooms.read().await;
@@ -184,19 +529,19 @@ impl RoomManager {
     }
 
     /// Check who should receive video from whom based on roles
-    pub async fn should_forward_track(&self, from_peer_id: &str, to_peer_id: &str) -> bool {
-        if from_peer_id == to_peer_id {
+    pub async fn should_forward_track(&self, from: &PeerKey, to: &PeerKey) -> bool {
+        if from == to {
             return false; // Don't forward to self
         }
 
         let peers = self.peers.read().await;
 
-        let from_peer = match peers.get(from_peer_id) {
+        let from_peer = match peers.get(from) {
             Some(p) => p,
             None => return false,
         };
 
-        let to_peer = match peers.get(to_peer_id) {
+        let to_peer = match peers.get(to) {
             Some(p) => p,
             None => return false,
         };
@@ -215,6 +560,20 @@ impl RoomManager {
     }
 }
 
+/// Updates the room and peer gauges; called with both maps locked after a change
+fn publish_counts(rooms: &HashMap<String, Room>, peers: &HashMap<PeerKey, Peer>) {
+    let proctors = peers.values().filter(|peer| matches!(peer.role, PeerRole::Proctor)).count();
+    let metrics = metrics::metrics();
+    metrics.active_rooms.set(rooms.len() as u64);
+    metrics.proctors.set(proctors as u64);
+    metrics.students.set((peers.len() - proctors) as u64);
+}
+
+/// First of up to `ROOM_ID_ATTEMPTS` candidates that is not `taken`
+fn unused_room_id(mut candidate: impl FnMut() -> String, taken: impl Fn(&str) -> bool) -> Option<String> {
+    (0..ROOM_ID_ATTEMPTS).map(|_| candidate()).find(|id| !taken(id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,7 +584,7 @@ mod tests {
         let proctor_id = "proctor_123".to_string();
         let proctor_name = Some("Dr. Smith".to_string());
 
-        let result = room_manager.create_room(proctor_id.clone(), proctor_name).await;
+        let result = room_manager.create_room(proctor_id.clone(), proctor_name, RoomLocale::default()).await;
         assert!(result.is_ok());
 
         let room_id = result.unwrap();
@@ -235,20 +594,30 @@ mod tests {
         assert!(room_manager.room_exists(&room_id).await);
 
         // Verify proctor is registered
-        let peer = room_manager.get_peer(&proctor_id).await;
+        let peer = room_manager.get_peer(&PeerKey::new(room_id.clone(), proctor_id.clone())).await;
         assert!(peer.is_some());
         let peer = peer.unwrap();
         assert_eq!(peer.id, proctor_id);
         matches!(peer.role, PeerRole::Proctor);
     }
 
+    #[tokio::test]
+    async fn test_room_id_with_instance_prefix() {
+        let room_manager = RoomManager::with_id_prefix("ab12".to_string());
+        let room_id = room_manager.create_room("proctor_123".to_string(), None, RoomLocale::default()).await.unwrap();
+
+        assert!(room_id.starts_with("ab12-"));
+        assert_eq!(room_id.len(), 11);
+        assert!(room_manager.room_exists(&room_id).await);
+    }
+
     #[tokio::test]
     async fn test_join_room() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
 
         // Create room first
-        let room_id = room_manager.create_room(proctor_id, None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id, None, RoomLocale::default()).await.unwrap();
 
         // Join as student
         let student_id = "student_456".to_string();
@@ -256,13 +625,56 @@ mod tests {
         assert!(result.is_ok());
 
         // Verify student is in room
-        let peer = room_manager.get_peer(&student_id).await;
+        let peer = room_manager.get_peer(&PeerKey::new(room_id.clone(), student_id)).await;
         assert!(peer.is_some());
         let peer = peer.unwrap();
         matches!(peer.role, PeerRole::Student);
         assert_eq!(peer.room_id, room_id);
     }
 
+    #[tokio::test]
+    async fn test_duplicate_join_is_ignored() {
+        let room_manager = RoomManager::new();
+        let room_id = room_manager.create_room("proctor_123".to_string(), None, RoomLocale::default()).await.unwrap();
+
+        room_manager.join_room(room_id.clone(), "student_1".to_string(), Some("First".to_string())).await.unwrap();
+        room_manager.join_room(room_id.clone(), "student_1".to_string(), Some("Second".to_string())).await.unwrap();
+
+        assert_eq!(room_manager.get_room(&room_id).await.unwrap().students, vec!["student_1".to_string()]);
+        let peer = room_manager.get_peer(&PeerKey::new(room_id.clone(), "student_1")).await.unwrap();
+        assert_eq!(peer.name.as_deref(), Some("First"));
+    }
+
+    #[tokio::test]
+    async fn test_remove_unknown_peer() {
+        let room_manager = RoomManager::new();
+        let room_id = room_manager.create_room("proctor_123".to_string(), None, RoomLocale::default()).await.unwrap();
+
+        assert!(room_manager.remove_peer(&PeerKey::new(room_id.clone(), "nobody"), DisconnectCause::Left).await.is_none());
+        assert!(room_manager.remove_peer(&PeerKey::new("999999", "proctor_123"), DisconnectCause::Left).await.is_none());
+        assert!(room_manager.room_exists(&room_id).await);
+    }
+
+    #[test]
+    fn test_room_id_collision_draws_again() {
+        let taken = ["111111", "222222"];
+        let mut candidates = ["111111", "222222", "333333"].into_iter().map(String::from);
+        let id = unused_room_id(|| candidates.next().unwrap(), |id| taken.contains(&id));
+        assert_eq!(id.as_deref(), Some("333333"));
+
+        // Every draw colliding gives up rather than looping forever
+        let mut draws = 0;
+        let id = unused_room_id(
+            || {
+                draws += 1;
+                "111111".to_string()
+            },
+            |id| taken.contains(&id),
+        );
+        assert!(id.is_none());
+        assert_eq!(draws, ROOM_ID_ATTEMPTS);
+    }
+
     #[tokio::test]
     async fn test_join_nonexistent_room() {
         let room_manager = RoomManager::new();
@@ -273,24 +685,52 @@ mod tests {
         assert!(result.unwrap_err().contains("does not exist"));
     }
 
+    #[tokio::test]
+    async fn test_join_with_roster() {
+        let room_manager = RoomManager::new();
+        let room_id = room_manager.create_room("proctor_123".to_string(), None, RoomLocale::default()).await.unwrap();
+        let csv = "student_1,0x1111111111111111111111111111111111111111,Jane Doe";
+        room_manager.set_roster(&room_id, Roster::parse(csv, None).unwrap()).await.unwrap();
+
+        // On the roster: name pre-filled, marked pre-registered
+        room_manager.join_room(room_id.clone(), "student_1".to_string(), None).await.unwrap();
+        let peer = room_manager.get_peer(&PeerKey::new(room_id.clone(), "student_1")).await.unwrap();
+        assert!(peer.pre_registered);
+        assert_eq!(peer.name.as_deref(), Some("Jane Doe"));
+        assert!(room_manager.get_roster_entry(&room_id, "student_1").await.is_some());
+
+        // Not on the roster: joins as before
+        room_manager.join_room(room_id.clone(), "student_2".to_string(), Some("Walk-in".to_string())).await.unwrap();
+        let peer = room_manager.get_peer(&PeerKey::new(room_id.clone(), "student_2")).await.unwrap();
+        assert!(!peer.pre_registered);
+        assert_eq!(peer.name.as_deref(), Some("Walk-in"));
+        assert!(room_manager.get_roster_entry(&room_id, "student_2").await.is_none());
+
+        assert_eq!(room_manager.get_proctor_token(&room_id).await.map(|t| t.len()), Some(64));
+        assert!(room_manager.set_roster("999999", Roster::default()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_remove_student() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id, None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id, None, RoomLocale::default()).await.unwrap();
 
         let student_id = "student_456".to_string();
         room_manager.join_room(room_id.clone(), student_id.clone(), None).await.unwrap();
 
         // Remove student
-        let result = room_manager.remove_peer(&student_id).await;
+        let student = PeerKey::new(room_id.clone(), student_id);
+        let result = room_manager.remove_peer(&student, DisconnectCause::Left).await;
         assert!(result.is_some());
-        let (removed_room_id, role, _wallet) = result.unwrap();
-        assert_eq!(removed_room_id, room_id);
-        matches!(role, PeerRole::Student);
+        let departed = result.unwrap();
+        assert_eq!(departed.room_id, room_id);
+        assert!(matches!(departed.role, PeerRole::Student));
+        assert_eq!(departed.cause, DisconnectCause::Left);
+        assert!(departed.displaced.is_empty());
 
         // Verify student is removed
-        let peer = room_manager.get_peer(&student_id).await;
+        let peer = room_manager.get_peer(&student).await;
         assert!(peer.is_none());
 
         // Room should still exist
@@ -301,28 +741,66 @@ mod tests {
     async fn test_remove_proctor_closes_room() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id.clone(), None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id.clone(), None, RoomLocale::default()).await.unwrap();
 
         let student_id = "student_456".to_string();
         room_manager.join_room(room_id.clone(), student_id.clone(), None).await.unwrap();
 
         // Remove proctor
-        let result = room_manager.remove_peer(&proctor_id).await;
+        let result = room_manager
+            .remove_peer(&PeerKey::new(room_id.clone(), proctor_id), DisconnectCause::ConnectionLost)
+            .await;
         assert!(result.is_some());
+        let departed = result.unwrap();
+        assert_eq!(departed.cause, DisconnectCause::ConnectionLost);
+
+        // The student leaves with the room, whatever took the proctor away
+        assert_eq!(departed.displaced.len(), 1);
+        assert_eq!(departed.displaced[0].id, student_id);
+        assert_eq!(departed.displaced[0].cause, DisconnectCause::RoomClosed);
 
         // Room should be closed
         assert!(!room_manager.room_exists(&room_id).await);
 
         // All students should be removed
-        let student_peer = room_manager.get_peer(&student_id).await;
+        let student_peer = room_manager.get_peer(&PeerKey::new(room_id, student_id)).await;
         assert!(student_peer.is_none());
     }
 
+    #[tokio::test]
+    async fn test_move_student_between_rooms() {
+        let room_manager = RoomManager::new();
+        let room1 = room_manager.create_room("proctor_1".to_string(), None, RoomLocale::default()).await.unwrap();
+        let room2 = room_manager.create_room("proctor_2".to_string(), None, RoomLocale::default()).await.unwrap();
+        room_manager.join_room(room1.clone(), "student_1".to_string(), Some("Ada".to_string())).await.unwrap();
+
+        let student = PeerKey::new(room1.clone(), "student_1");
+        room_manager.move_student(&student, &room2).await.unwrap();
+        assert!(room_manager.get_peer(&student).await.is_none());
+        assert_eq!(room_manager.join_order(&room1).await, vec!["proctor_1"]);
+        assert_eq!(room_manager.join_order(&room2).await, vec!["proctor_2", "student_1"]);
+
+        let moved = room_manager.get_peer(&PeerKey::new(room2.clone(), "student_1")).await.unwrap();
+        assert_eq!((moved.room_id.as_str(), moved.name.as_deref()), (room2.as_str(), Some("Ada")));
+        // Forwarding follows the destination room
+        assert!(
+            room_manager
+                .should_forward_track(&PeerKey::new(room2.clone(), "student_1"), &PeerKey::new(room2.clone(), "proctor_2"))
+                .await
+        );
+
+        // Refused moves leave the student where they are
+        let moved = PeerKey::new(room2.clone(), "student_1");
+        assert!(room_manager.move_student(&moved, "missing").await.is_err());
+        assert!(room_manager.move_student(&PeerKey::new(room2.clone(), "proctor_2"), &room1).await.is_err());
+        assert!(room_manager.get_peer(&moved).await.is_some());
+    }
+
     #[tokio::test]
     async fn test_get_room_peers() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id, None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id, None, RoomLocale::default()).await.unwrap();
 
         let student1 = "student_1".to_string();
         let student2 = "student_2".to_string();
@@ -337,13 +815,15 @@ mod tests {
     async fn test_should_forward_track_proctor_to_all() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id.clone(), None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id.clone(), None, RoomLocale::default()).await.unwrap();
 
         let student_id = "student_456".to_string();
-        room_manager.join_room(room_id, student_id.clone(), None).await.unwrap();
+        room_manager.join_room(room_id.clone(), student_id.clone(), None).await.unwrap();
 
         // Proctor's video should be forwarded to student
-        let should_forward = room_manager.should_forward_track(&proctor_id, &student_id).await;
+        let should_forward = room_manager
+            .should_forward_track(&PeerKey::new(room_id.clone(), proctor_id), &PeerKey::new(room_id, student_id))
+            .await;
         assert!(should_forward);
     }
 
@@ -351,13 +831,15 @@ mod tests {
     async fn test_should_forward_track_student_to_proctor() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id.clone(), None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id.clone(), None, RoomLocale::default()).await.unwrap();
 
         let student_id = "student_456".to_string();
-        room_manager.join_room(room_id, student_id.clone(), None).await.unwrap();
+        room_manager.join_room(room_id.clone(), student_id.clone(), None).await.unwrap();
 
         // Student's video should be forwarded to proctor
-        let should_forward = room_manager.should_forward_track(&student_id, &proctor_id).await;
+        let should_forward = room_manager
+            .should_forward_track(&PeerKey::new(room_id.clone(), student_id), &PeerKey::new(room_id, proctor_id))
+            .await;
         assert!(should_forward);
     }
 
@@ -365,15 +847,17 @@ mod tests {
     async fn test_should_not_forward_track_student_to_student() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        let room_id = room_manager.create_room(proctor_id, None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id, None, RoomLocale::default()).await.unwrap();
 
         let student1 = "student_1".to_string();
         let student2 = "student_2".to_string();
         room_manager.join_room(room_id.clone(), student1.clone(), None).await.unwrap();
-        room_manager.join_room(room_id, student2.clone(), None).await.unwrap();
+        room_manager.join_room(room_id.clone(), student2.clone(), None).await.unwrap();
 
         // Students should not see each other
-        let should_forward = room_manager.should_forward_track(&student1, &student2).await;
+        let should_forward = room_manager
+            .should_forward_track(&PeerKey::new(room_id.clone(), student1), &PeerKey::new(room_id, student2))
+            .await;
         assert!(!should_forward);
     }
 
@@ -381,10 +865,11 @@ mod tests {
     async fn test_should_not_forward_to_self() {
         let room_manager = RoomManager::new();
         let proctor_id = "proctor_123".to_string();
-        room_manager.create_room(proctor_id.clone(), None).await.unwrap();
+        let room_id = room_manager.create_room(proctor_id.clone(), None, RoomLocale::default()).await.unwrap();
 
         // Should not forward to self
-        let should_forward = room_manager.should_forward_track(&proctor_id, &proctor_id).await;
+        let proctor = PeerKey::new(room_id, proctor_id);
+        let should_forward = room_manager.should_forward_track(&proctor, &proctor).await;
         assert!(!should_forward);
     }
 
@@ -394,16 +879,97 @@ mod tests {
         let proctor1 = "proctor_1".to_string();
         let proctor2 = "proctor_2".to_string();
 
-        let room1 = room_manager.create_room(proctor1.clone(), None).await.unwrap();
-        let room2 = room_manager.create_room(proctor2.clone(), None).await.unwrap();
+        let room1 = room_manager.create_room(proctor1.clone(), None, RoomLocale::default()).await.unwrap();
+        let room2 = room_manager.create_room(proctor2.clone(), None, RoomLocale::default()).await.unwrap();
 
         let student1 = "student_1".to_string();
         let student2 = "student_2".to_string();
-        room_manager.join_room(room1, student1.clone(), None).await.unwrap();
-        room_manager.join_room(room2, student2.clone(), None).await.unwrap();
+        room_manager.join_room(room1.clone(), student1.clone(), None).await.unwrap();
+        room_manager.join_room(room2.clone(), student2.clone(), None).await.unwrap();
 
         // Should not forward tracks across different rooms
-        let should_forward = room_manager.should_forward_track(&student1, &student2).await;
+        let should_forward = room_manager
+            .should_forward_track(&PeerKey::new(room1, student1), &PeerKey::new(room2, student2))
+            .await;
         assert!(!should_forward);
     }
+
+    #[tokio::test]
+    async fn test_proctor_runs_several_rooms() {
+        let room_manager = RoomManager::new();
+        let room_a = room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.unwrap();
+        let room_b = room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.unwrap();
+        assert_eq!(room_manager.rooms_owned_by("ta").await, vec![room_a.clone(), room_b.clone()]);
+
+        // The same student ID may sit in both groups
+        room_manager.join_room(room_a.clone(), "student_1".to_string(), None).await.unwrap();
+        room_manager.join_room(room_b.clone(), "student_1".to_string(), None).await.unwrap();
+
+        let ta_a = PeerKey::new(room_a.clone(), "ta");
+        let ta_b = PeerKey::new(room_b.clone(), "ta");
+        assert!(room_manager.should_forward_track(&PeerKey::new(room_a.clone(), "student_1"), &ta_a).await);
+        assert!(!room_manager.should_forward_track(&PeerKey::new(room_a.clone(), "student_1"), &ta_b).await);
+
+        // Closing one room leaves the other running
+        let departed = room_manager.remove_peer(&ta_a, DisconnectCause::Left).await.unwrap();
+        assert_eq!(departed.displaced.len(), 1);
+        assert!(!room_manager.room_exists(&room_a).await);
+        assert!(room_manager.get_peer(&ta_b).await.is_some());
+        assert!(room_manager.get_peer(&PeerKey::new(room_b.clone(), "student_1")).await.is_some());
+        assert_eq!(room_manager.rooms_owned_by("ta").await, vec![room_b.clone()]);
+
+        room_manager.remove_peer(&ta_b, DisconnectCause::Left).await.unwrap();
+        assert!(room_manager.rooms_owned_by("ta").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rooms_per_proctor_capped() {
+        let room_manager = RoomManager::with_limits(None, Some(2));
+        let room_a = room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.unwrap();
+        room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.unwrap();
+
+        let err = room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.unwrap_err();
+        assert!(err.contains("2 rooms"), "{}", err);
+        // The cap is per proctor
+        assert!(room_manager.create_room("other".to_string(), None, RoomLocale::default()).await.is_ok());
+
+        // Closing a room frees a slot
+        room_manager.remove_peer(&PeerKey::new(room_a, "ta"), DisconnectCause::Left).await.unwrap();
+        assert!(room_manager.create_room("ta".to_string(), None, RoomLocale::default()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_room_summaries() {
+        let room_manager = RoomManager::new();
+        let room_a = room_manager.create_room("ta".to_string(), Some("Dr. Smith".to_string()), RoomLocale::default()).await.unwrap();
+        let room_b = room_manager.create_room("other".to_string(), None, RoomLocale::default()).await.unwrap();
+        room_manager.join_room(room_a.clone(), "student_1".to_string(), None).await.unwrap();
+        room_manager.join_room(room_a.clone(), "student_2".to_string(), None).await.unwrap();
+
+        let summaries = room_manager.room_summaries().await;
+        assert_eq!(summaries.len(), 2);
+        let summary_a = summaries.iter().find(|s| s.room_id == room_a).unwrap();
+        assert_eq!(summary_a.proctor_id, "ta");
+        assert_eq!(summary_a.proctor_name.as_deref(), Some("Dr. Smith"));
+        assert_eq!(summary_a.student_count, 2);
+        assert!(summary_a.created_at > 0);
+
+        let summary_b = room_manager.room_summary(&room_b).await.unwrap();
+        assert_eq!(summary_b.proctor_name, None);
+        assert_eq!(summary_b.student_count, 0);
+        assert!(room_manager.room_summary("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_room_keeps_locale() {
+        let room_manager = RoomManager::new();
+        let locale = RoomLocale {
+            timezone: None,
+            locale: Some("fr-FR".to_string()),
+        };
+        let room_id = room_manager.create_room("proctor_123".to_string(), None, locale).await.unwrap();
+
+        assert_eq!(room_manager.get_room_locale(&room_id).await.locale.as_deref(), Some("fr-FR"));
+        assert!(room_manager.get_room_locale("missing").await.locale.is_none());
+    }
 }
\ No newline at end of file
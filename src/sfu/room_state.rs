@@ -0,0 +1,434 @@
+//! Revisioned `RoomState` streams. Every track order sent to a peer gets the
+//! next revision; peers that negotiated `room_state_delta` in `Hello` are sent
+//! only the changes from the order they were sent before, and can ask for the
+//! ones they missed while they are still in the bounded history.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::env;
+use super::room::PeerKey;
+use super::track_manager::{TrackContent, TrackOrderEntry};
+
+/// `Hello` capability for receiving `RoomStateDelta` instead of full `RoomState`
+pub const ROOM_STATE_DELTA: &str = "room_state_delta";
+
+/// Default number of deltas kept per peer for resyncing clients
+pub const DEFAULT_ROOM_STATE_HISTORY: usize = 32;
+
+static ROOM_STATE_HISTORY: OnceLock<usize> = OnceLock::new();
+
+/// History bound read from `ROOM_STATE_HISTORY` once per process
+pub fn room_state_history() -> usize {
+    *ROOM_STATE_HISTORY.get_or_init(|| {
+        env::get_parsed("ROOM_STATE_HISTORY")
+            .filter(|limit| *limit > 0)
+            .unwrap_or(DEFAULT_ROOM_STATE_HISTORY)
+    })
+}
+
+/// Fields of a track that changed; unset fields kept their value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackUpdate {
+    pub track_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_peer_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<TrackContent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e2ee: Option<bool>,
+}
+
+impl TrackUpdate {
+    fn between(old: &TrackOrderEntry, new: &TrackOrderEntry) -> Self {
+        let changed = |old: &String, new: &String| (old != new).then(|| new.clone());
+        Self {
+            track_id: new.track_id.clone(),
+            source_peer_id: changed(&old.source_peer_id, &new.source_peer_id),
+            stream_id: changed(&old.stream_id, &new.stream_id),
+            kind: changed(&old.kind, &new.kind),
+            content: (old.content != new.content).then_some(new.content),
+            e2ee: (old.e2ee != new.e2ee).then_some(new.e2ee),
+        }
+    }
+
+    fn apply_to(&self, track: &mut TrackOrderEntry) {
+        if let Some(source_peer_id) = &self.source_peer_id {
+            track.source_peer_id = source_peer_id.clone();
+        }
+        if let Some(stream_id) = &self.stream_id {
+            track.stream_id = stream_id.clone();
+        }
+        if let Some(kind) = &self.kind {
+            track.kind = kind.clone();
+        }
+        if let Some(content) = self.content {
+            track.content = content;
+        }
+        if let Some(e2ee) = self.e2ee {
+            track.e2ee = e2ee;
+        }
+    }
+}
+
+/// One step from a revision's track order to the next, applied in order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TrackChange {
+    Remove { track_id: String },
+    Update(TrackUpdate),
+    /// Inserted at `index` of the order as it stands after the earlier changes
+    Add { index: usize, track: TrackOrderEntry },
+    /// The complete order by track ID, when tracks that stayed moved
+    Reorder { track_ids: Vec<String> },
+}
+
+/// Changes that turn `previous` into `current`
+pub fn diff(previous: &[TrackOrderEntry], current: &[TrackOrderEntry]) -> Vec<TrackChange> {
+    let kept: HashSet<&str> = current.iter().map(|t| t.track_id.as_str()).collect();
+    let before: HashMap<&str, &TrackOrderEntry> = previous.iter().map(|t| (t.track_id.as_str(), t)).collect();
+
+    let mut changes: Vec<TrackChange> = previous
+        .iter()
+        .filter(|t| !kept.contains(t.track_id.as_str()))
+        .map(|t| TrackChange::Remove { track_id: t.track_id.clone() })
+        .collect();
+    let mut added = Vec::new();
+    for (index, track) in current.iter().enumerate() {
+        match before.get(track.track_id.as_str()) {
+            None => added.push(TrackChange::Add { index, track: track.clone() }),
+            Some(old) if *old != track => changes.push(TrackChange::Update(TrackUpdate::between(old, track))),
+            Some(_) => {}
+        }
+    }
+    // Ascending indexes land each track where it ends up if the others kept their order
+    changes.extend(added);
+
+    let mut applied = previous.to_vec();
+    if apply(&mut applied, &changes).is_err() || applied != current {
+        changes.push(TrackChange::Reorder {
+            track_ids: current.iter().map(|t| t.track_id.clone()).collect(),
+        });
+    }
+    changes
+}
+
+/// Applies `changes` to `tracks` as a client does. Fails on a change that
+/// does not fit the order, which means the client missed a revision.
+pub fn apply(tracks: &mut Vec<TrackOrderEntry>, changes: &[TrackChange]) -> Result<(), String> {
+    let position = |tracks: &[TrackOrderEntry], track_id: &str| {
+        tracks
+            .iter()
+            .position(|t| t.track_id == track_id)
+            .ok_or_else(|| format!("Unknown track {}", track_id))
+    };
+    for change in changes {
+        match change {
+            TrackChange::Remove { track_id } => {
+                let index = position(tracks, track_id)?;
+                tracks.remove(index);
+            }
+            TrackChange::Update(update) => {
+                let index = position(tracks, &update.track_id)?;
+                update.apply_to(&mut tracks[index]);
+            }
+            TrackChange::Add { index, track } => {
+                if *index > tracks.len() {
+                    return Err(format!("Track {} added past the end", track.track_id));
+                }
+                tracks.insert(*index, track.clone());
+            }
+            TrackChange::Reorder { track_ids } => {
+                if track_ids.len() != tracks.len() {
+                    return Err("Reorder does not list every track".to_string());
+                }
+                let mut remaining = std::mem::take(tracks);
+                for track_id in track_ids {
+                    let index = position(&remaining, track_id)?;
+                    tracks.push(remaining.swap_remove(index));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What to send a peer for its latest track order
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomStateUpdate {
+    Full { revision: u64, track_order: Vec<TrackOrderEntry> },
+    Delta { revision: u64, changes: Vec<TrackChange> },
+    /// A delta peer already has this order
+    Unchanged,
+}
+
+#[derive(Debug, Default)]
+struct RoomStateStream {
+    deltas: bool,
+    revision: u64,
+    /// Order as of `revision`, once one was sent
+    current: Option<Vec<TrackOrderEntry>>,
+    /// Changes that led to each retained revision, oldest first
+    history: VecDeque<(u64, Vec<TrackChange>)>,
+}
+
+/// Revision and delta history of the `RoomState` sent to each peer
+pub struct RoomStateStreams {
+    streams: Mutex<HashMap<PeerKey, RoomStateStream>>,
+    history_limit: usize,
+}
+
+impl Default for RoomStateStreams {
+    fn default() -> Self {
+        Self::new(room_state_history())
+    }
+}
+
+impl RoomStateStreams {
+    pub fn new(history_limit: usize) -> Self {
+        Self {
+            streams: Mutex::new(HashMap::new()),
+            history_limit,
+        }
+    }
+
+    /// Sends `peer` deltas after the next full state
+    pub fn enable_deltas(&self, peer: &PeerKey) {
+        self.streams.lock().unwrap().entry(peer.clone()).or_default().deltas = true;
+    }
+
+    /// Records `track_order` as sent to `peer`: a delta from the last order for
+    /// peers that take them, the full order for everyone else
+    pub fn update(&self, peer: &PeerKey, track_order: Vec<TrackOrderEntry>) -> RoomStateUpdate {
+        let mut streams = self.streams.lock().unwrap();
+        let stream = streams.entry(peer.clone()).or_default();
+        let changes = match &stream.current {
+            Some(current) if stream.deltas => {
+                if *current == track_order {
+                    return RoomStateUpdate::Unchanged;
+                }
+                diff(current, &track_order)
+            }
+            _ => return Self::reset(stream, track_order),
+        };
+
+        stream.revision += 1;
+        stream.history.push_back((stream.revision, changes.clone()));
+        while stream.history.len() > self.history_limit {
+            stream.history.pop_front();
+        }
+        stream.current = Some(track_order);
+        RoomStateUpdate::Delta { revision: stream.revision, changes }
+    }
+
+    /// Deltas that bring a peer at `since_revision` up to date, or `None` when
+    /// they are no longer retained and the peer needs the full order
+    pub fn since(&self, peer: &PeerKey, since_revision: u64) -> Option<Vec<RoomStateUpdate>> {
+        let streams = self.streams.lock().unwrap();
+        let stream = streams.get(peer).filter(|stream| stream.deltas && stream.current.is_some())?;
+        if since_revision > stream.revision {
+            return None;
+        }
+        let oldest_base = stream.history.front().map(|(revision, _)| revision - 1).unwrap_or(stream.revision);
+        if since_revision < oldest_base {
+            return None;
+        }
+        Some(
+            stream
+                .history
+                .iter()
+                .filter(|(revision, _)| *revision > since_revision)
+                .map(|(revision, changes)| RoomStateUpdate::Delta { revision: *revision, changes: changes.clone() })
+                .collect(),
+        )
+    }
+
+    /// Records `track_order` as sent in full, starting the history over
+    pub fn resync(&self, peer: &PeerKey, track_order: Vec<TrackOrderEntry>) -> RoomStateUpdate {
+        let mut streams = self.streams.lock().unwrap();
+        Self::reset(streams.entry(peer.clone()).or_default(), track_order)
+    }
+
+    fn reset(stream: &mut RoomStateStream, track_order: Vec<TrackOrderEntry>) -> RoomStateUpdate {
+        stream.revision += 1;
+        stream.history.clear();
+        stream.current = Some(track_order.clone());
+        RoomStateUpdate::Full { revision: stream.revision, track_order }
+    }
+
+    pub fn forget(&self, peer: &PeerKey) {
+        self.streams.lock().unwrap().remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    fn track(track_id: &str, source_peer_id: &str, kind: &str) -> TrackOrderEntry {
+        TrackOrderEntry {
+            track_id: track_id.to_string(),
+            source_peer_id: source_peer_id.to_string(),
+            stream_id: format!("stream_{}", source_peer_id),
+            kind: kind.to_string(),
+            content: TrackContent::Camera,
+            e2ee: false,
+        }
+    }
+
+    /// A random subset of up to 40 tracks in random order, with random field values
+    fn random_order(rng: &mut StdRng) -> Vec<TrackOrderEntry> {
+        let mut tracks: Vec<TrackOrderEntry> = (0..40)
+            .filter(|_| rng.gen_bool(0.6))
+            .map(|i| {
+                let peer = format!("student_{}", rng.gen_range(0..5));
+                let mut track = track(&format!("t{}", i), &peer, if rng.gen_bool(0.5) { "video" } else { "audio" });
+                if rng.gen_bool(0.2) {
+                    track.content = TrackContent::Screen;
+                }
+                if rng.gen_bool(0.1) {
+                    track.e2ee = true;
+                }
+                if rng.gen_bool(0.1) {
+                    track.stream_id = format!("stream_{}", rng.gen::<u8>());
+                }
+                track
+            })
+            .collect();
+        if rng.gen_bool(0.5) {
+            tracks.shuffle(rng);
+        }
+        tracks
+    }
+
+    #[test]
+    fn test_applying_diff_reproduces_current_order() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut client = Vec::new();
+        for _ in 0..2000 {
+            let current = random_order(&mut rng);
+            let changes = diff(&client, &current);
+            apply(&mut client, &changes).unwrap();
+            assert_eq!(client, current);
+
+            // Survives the JSON the client actually receives
+            let wire: Vec<TrackChange> = serde_json::from_str(&serde_json::to_string(&changes).unwrap()).unwrap();
+            assert_eq!(wire, changes);
+        }
+    }
+
+    #[test]
+    fn test_field_change_is_sent_alone() {
+        let before = vec![track("a", "student_1", "video"), track("b", "student_1", "audio")];
+        let mut after = before.clone();
+        after[0].content = TrackContent::Screen;
+
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes,
+            vec![TrackChange::Update(TrackUpdate {
+                track_id: "a".to_string(),
+                content: Some(TrackContent::Screen),
+                ..TrackUpdate::default()
+            })]
+        );
+        assert_eq!(
+            serde_json::to_value(&changes[0]).unwrap(),
+            serde_json::json!({ "op": "update", "track_id": "a", "content": "screen" })
+        );
+        assert!(diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_changes_for_another_order_fail_to_apply() {
+        let first = vec![track("a", "student_1", "video")];
+        let second = vec![track("a", "student_1", "video"), track("b", "student_2", "video")];
+        let third = vec![track("b", "student_2", "video")];
+
+        // Removing or placing tracks the client never had
+        assert!(apply(&mut Vec::new(), &diff(&second, &third)).is_err());
+        assert!(apply(&mut Vec::new(), &diff(&first, &second)).is_err());
+        assert!(apply(&mut first.clone(), &diff(&first, &second)).is_ok());
+    }
+
+    #[test]
+    fn test_deltas_follow_a_full_state() {
+        let streams = RoomStateStreams::new(4);
+        let peer = PeerKey::new("room1", "student_1");
+        let order = vec![track("a", "proctor", "video")];
+
+        // Without the capability every order goes out in full
+        assert!(matches!(streams.update(&peer, order.clone()), RoomStateUpdate::Full { revision: 1, .. }));
+        assert!(matches!(streams.update(&peer, order.clone()), RoomStateUpdate::Full { revision: 2, .. }));
+
+        streams.enable_deltas(&peer);
+        assert_eq!(streams.update(&peer, order.clone()), RoomStateUpdate::Unchanged);
+        let mut grown = order.clone();
+        grown.push(track("b", "proctor", "audio"));
+        match streams.update(&peer, grown.clone()) {
+            RoomStateUpdate::Delta { revision, changes } => {
+                assert_eq!(revision, 3);
+                assert!(matches!(&changes[..], [TrackChange::Add { index: 1, .. }]));
+            }
+            other => panic!("expected a delta, got {:?}", other),
+        }
+
+        streams.forget(&peer);
+        assert!(matches!(streams.update(&peer, grown), RoomStateUpdate::Full { revision: 1, .. }));
+    }
+
+    #[test]
+    fn test_resync_within_and_beyond_history() {
+        let streams = RoomStateStreams::new(3);
+        let peer = PeerKey::new("room1", "student_1");
+        streams.enable_deltas(&peer);
+
+        let mut client = match streams.update(&peer, Vec::new()) {
+            RoomStateUpdate::Full { revision: 1, track_order } => track_order,
+            other => panic!("expected the full state first, got {:?}", other),
+        };
+        let mut orders = Vec::new();
+        for i in 0..5 {
+            let mut order = orders.last().cloned().unwrap_or_default();
+            order.push(track(&format!("t{}", i), "proctor", "video"));
+            streams.update(&peer, order.clone());
+            orders.push(order);
+        }
+        // Revisions 2..=6 were sent; only the last three deltas are kept
+        assert!(streams.since(&peer, 1).is_none());
+        assert!(streams.since(&peer, 2).is_none());
+        assert!(streams.since(&peer, 7).is_none());
+        assert_eq!(streams.since(&peer, 6), Some(Vec::new()));
+
+        client.clone_from(&orders[1]);
+        let missed = streams.since(&peer, 3).unwrap();
+        let revisions: Vec<u64> = missed
+            .iter()
+            .map(|update| match update {
+                RoomStateUpdate::Delta { revision, changes } => {
+                    apply(&mut client, changes).unwrap();
+                    *revision
+                }
+                other => panic!("expected deltas, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(revisions, vec![4, 5, 6]);
+        assert_eq!(&client, orders.last().unwrap());
+
+        // A full resync restarts the history at the next revision
+        match streams.resync(&peer, client.clone()) {
+            RoomStateUpdate::Full { revision, .. } => assert_eq!(revision, 7),
+            other => panic!("expected the full state, got {:?}", other),
+        }
+        assert_eq!(streams.since(&peer, 7), Some(Vec::new()));
+        assert!(streams.since(&peer, 6).is_none());
+    }
+}
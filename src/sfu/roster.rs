@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::substrate::{parse_address, Address};
+
+/// Largest roster accepted for one room
+pub const MAX_ROSTER_ENTRIES: usize = 2000;
+
+/// Longest display name kept from a roster, in characters
+const MAX_ROSTER_NAME_CHARS: usize = 100;
+
+/// A student registered by the institution ahead of the session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RosterEntry {
+    pub peer_id: String,
+    pub wallet: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// One row of a JSON roster upload
+#[derive(Debug, Deserialize)]
+struct RosterRow {
+    peer_id: String,
+    wallet_address: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Why a roster upload was refused; nothing is stored when any row is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosterError {
+    /// The body is not a JSON array of entries or a CSV table
+    Malformed(String),
+    /// Row numbers are 1-based and count the CSV header when there is one
+    MissingPeerId { row: usize },
+    InvalidAddress { row: usize },
+    DuplicatePeer(String),
+    DuplicateWallet(Address),
+    TooLarge { max: usize },
+}
+
+impl fmt::Display for RosterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RosterError::Malformed(reason) => write!(f, "Malformed roster: {}", reason),
+            RosterError::MissingPeerId { row } => write!(f, "Row {} has no peer_id", row),
+            RosterError::InvalidAddress { row } => write!(f, "Row {} has an invalid wallet address", row),
+            RosterError::DuplicatePeer(peer_id) => write!(f, "Peer {} appears more than once", peer_id),
+            RosterError::DuplicateWallet(wallet) => write!(f, "Wallet {:?} is assigned to more than one peer", wallet),
+            RosterError::TooLarge { max } => write!(f, "Roster has more than {} entries", max),
+        }
+    }
+}
+
+/// Pre-registered students of a room, keyed by peer ID
+#[derive(Debug, Clone, Default)]
+pub struct Roster {
+    entries: HashMap<String, RosterEntry>,
+}
+
+impl Roster {
+    /// Parses a roster upload. JSON is an array of
+    /// `{peer_id, wallet_address, name}` objects; CSV has the columns
+    /// `peer_id,wallet_address,name` with an optional header row. Without a
+    /// CSV or JSON content type, a body starting with `[` is read as JSON.
+    pub fn parse(body: &str, content_type: Option<&str>) -> Result<Self, RosterError> {
+        let is_csv = match content_type {
+            Some(ct) if ct.contains("csv") => true,
+            Some(ct) if ct.contains("json") => false,
+            _ => !body.trim_start().starts_with('['),
+        };
+
+        let rows = if is_csv {
+            parse_csv(body)?
+        } else {
+            serde_json::from_str::<Vec<RosterRow>>(body)
+                .map_err(|e| RosterError::Malformed(e.to_string()))?
+                .into_iter()
+                .enumerate()
+                .map(|(index, row)| (index + 1, row))
+                .collect()
+        };
+
+        Self::from_rows(rows)
+    }
+
+    fn from_rows(rows: Vec<(usize, RosterRow)>) -> Result<Self, RosterError> {
+        if rows.len() > MAX_ROSTER_ENTRIES {
+            return Err(RosterError::TooLarge { max: MAX_ROSTER_ENTRIES });
+        }
+
+        let mut entries = HashMap::with_capacity(rows.len());
+        let mut wallets = HashSet::with_capacity(rows.len());
+        for (row, raw) in rows {
+            let peer_id = raw.peer_id.trim().to_string();
+            if peer_id.is_empty() {
+                return Err(RosterError::MissingPeerId { row });
+            }
+            let wallet = parse_address(raw.wallet_address.trim())
+                .filter(|wallet| !wallet.is_zero())
+                .ok_or(RosterError::InvalidAddress { row })?;
+            if !wallets.insert(wallet) {
+                return Err(RosterError::DuplicateWallet(wallet));
+            }
+            let name = raw
+                .name
+                .map(|name| name.trim().chars().take(MAX_ROSTER_NAME_CHARS).collect::<String>())
+                .filter(|name| !name.is_empty());
+
+            let entry = RosterEntry { peer_id: peer_id.clone(), wallet, name };
+            if entries.insert(peer_id.clone(), entry).is_some() {
+                return Err(RosterError::DuplicatePeer(peer_id));
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn get(&self, peer_id: &str) -> Option<&RosterEntry> {
+        self.entries.get(peer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Rows of a CSV roster. The name is the rest of the line after the second
+/// comma, so names containing commas work without quoting.
+fn parse_csv(body: &str) -> Result<Vec<(usize, RosterRow)>, RosterError> {
+    let mut rows = Vec::new();
+    let mut first = true;
+    for (index, line) in body.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, ',').map(|field| field.trim().trim_matches('"'));
+        let peer_id = fields.next().unwrap_or_default();
+        if std::mem::take(&mut first) && peer_id.eq_ignore_ascii_case("peer_id") {
+            continue;
+        }
+        let Some(wallet_address) = fields.next() else {
+            return Err(RosterError::Malformed(format!(
+                "row {} needs at least peer_id and wallet_address",
+                index + 1
+            )));
+        };
+        rows.push((
+            index + 1,
+            RosterRow {
+                peer_id: peer_id.to_string(),
+                wallet_address: wallet_address.to_string(),
+                name: fields.next().map(str::to_string),
+            },
+        ));
+
+        if rows.len() > MAX_ROSTER_ENTRIES {
+            return Err(RosterError::TooLarge { max: MAX_ROSTER_ENTRIES });
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLET_A: &str = "0x1111111111111111111111111111111111111111";
+    const WALLET_B: &str = "0x2222222222222222222222222222222222222222";
+
+    #[test]
+    fn test_parse_csv_and_json() {
+        let csv = format!(
+            "peer_id,wallet_address,name\n\nstudent_1,{},\"Doe, Jane\"\nstudent_2, {} \n",
+            WALLET_A, WALLET_B
+        );
+        let roster = Roster::parse(&csv, Some("text/csv")).unwrap();
+        assert_eq!(roster.len(), 2);
+        let jane = roster.get("student_1").unwrap();
+        assert_eq!(jane.wallet, parse_address(WALLET_A).unwrap());
+        assert_eq!(jane.name.as_deref(), Some("Doe, Jane"));
+        assert_eq!(roster.get("student_2").unwrap().name, None);
+        assert!(roster.get("student_3").is_none());
+
+        let json = format!(
+            r#"[{{"peer_id":"student_1","wallet_address":"{}","name":"Jane"}},{{"peer_id":"student_2","wallet_address":"{}"}}]"#,
+            WALLET_A, WALLET_B
+        );
+        let roster = Roster::parse(&json, Some("application/json")).unwrap();
+        assert_eq!(roster.get("student_1").unwrap().name.as_deref(), Some("Jane"));
+        assert!(roster.get("student_2").is_some());
+
+        // Without a content type the body decides
+        assert_eq!(Roster::parse(&json, None).unwrap().len(), 2);
+        assert_eq!(Roster::parse(&format!("student_1,{}", WALLET_A), None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_rows() {
+        let bad_address = format!("student_1,{}\nstudent_2,0x1234", WALLET_A);
+        assert_eq!(Roster::parse(&bad_address, None).unwrap_err(), RosterError::InvalidAddress { row: 2 });
+
+        let zero = "student_1,0x0000000000000000000000000000000000000000";
+        assert_eq!(Roster::parse(zero, None).unwrap_err(), RosterError::InvalidAddress { row: 1 });
+
+        assert!(matches!(Roster::parse("student_1", None), Err(RosterError::Malformed(_))));
+        assert!(matches!(Roster::parse("[{\"peer_id\":1}]", None), Err(RosterError::Malformed(_))));
+        assert_eq!(
+            Roster::parse(&format!(",{}", WALLET_A), None).unwrap_err(),
+            RosterError::MissingPeerId { row: 1 }
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_duplicates_and_oversized_rosters() {
+        let duplicate_peer = format!("student_1,{}\nstudent_1,{}", WALLET_A, WALLET_B);
+        assert_eq!(
+            Roster::parse(&duplicate_peer, None).unwrap_err(),
+            RosterError::DuplicatePeer("student_1".to_string())
+        );
+
+        // Checksummed and lowercase spellings are the same wallet
+        let duplicate_wallet = format!("student_1,{}\nstudent_2,{}", WALLET_A, WALLET_A.to_uppercase().replace("0X", "0x"));
+        assert!(matches!(Roster::parse(&duplicate_wallet, None), Err(RosterError::DuplicateWallet(_))));
+
+        let oversized: String = (0..=MAX_ROSTER_ENTRIES)
+            .map(|i| format!("student_{},0x{:040x}\n", i, i + 1))
+            .collect();
+        assert_eq!(
+            Roster::parse(&oversized, None).unwrap_err(),
+            RosterError::TooLarge { max: MAX_ROSTER_ENTRIES }
+        );
+    }
+}
@@ -0,0 +1,786 @@
+//! Receive-side RTCP feedback toward publishers.
+//!
+//! Receiver reports come from the interceptor registry at `RTCP_REPORT_INTERVAL_MS`.
+//! On top of that the forwarding loop keeps RFC 3550 reception statistics per
+//! track, turns the observed loss into a REMB estimate for video publishers,
+//! and publishes the latest numbers for the stats endpoint. Keyframe requests
+//! from subscribers go through a per-track `PliLimiter` before reaching the
+//! publisher, and their NACKs through a per-track `NackRelay`. Subscriber
+//! receiver reports are not relayed: they describe the SFU's leg to the
+//! subscriber, and the publisher already gets ours for its own leg.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::{nack_pairs_from_sequence_numbers, TransportLayerNack};
+
+use crate::config::env;
+
+use super::keyframe::{PliDecision, PliLimiter, DEFAULT_PLI_MIN_INTERVAL_MS};
+
+/// Default interval between receiver reports and REMB updates
+pub const DEFAULT_REPORT_INTERVAL_MS: u64 = 1000;
+
+/// Default ceiling for REMB estimates
+const DEFAULT_REMB_MAX_BITRATE_BPS: u64 = 2_500_000;
+
+/// REMB estimates never drop below this, so a lossy burst can't starve a publisher
+const REMB_MIN_BITRATE_BPS: u64 = 100_000;
+
+/// Loss below this lets the estimate grow
+const LOSS_LOW_WATERMARK: f64 = 0.02;
+
+/// Loss above this shrinks the estimate proportionally
+const LOSS_HIGH_WATERMARK: f64 = 0.10;
+
+/// Per-interval growth of the estimate while loss stays low
+const REMB_INCREASE_FACTOR: f64 = 1.05;
+
+/// How long a packet relayed to the publisher in a NACK is not asked for again,
+/// roughly one retransmission round trip
+const NACK_RELAY_WINDOW: Duration = Duration::from_millis(200);
+
+/// Sequence numbers a `NackRelay` remembers, enough for a burst across all subscribers
+const NACK_RELAY_HISTORY: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct RtcpSettings {
+    pub report_interval: Duration,
+    pub remb_enabled: bool,
+    pub remb_max_bitrate_bps: u64,
+    /// Minimum time between subscriber PLIs forwarded for one track (zero disables limiting)
+    pub pli_min_interval: Duration,
+}
+
+impl Default for RtcpSettings {
+    fn default() -> Self {
+        Self {
+            report_interval: Duration::from_millis(DEFAULT_REPORT_INTERVAL_MS),
+            remb_enabled: true,
+            remb_max_bitrate_bps: DEFAULT_REMB_MAX_BITRATE_BPS,
+            pli_min_interval: Duration::from_millis(DEFAULT_PLI_MIN_INTERVAL_MS),
+        }
+    }
+}
+
+impl RtcpSettings {
+    /// Reads `RTCP_REPORT_INTERVAL_MS`, `RTCP_REMB_ENABLED`, `RTCP_REMB_MAX_BITRATE_BPS`
+    /// and `PLI_MIN_INTERVAL_MS`
+    pub fn from_env() -> Self {
+        let report_interval_ms = env::get_parsed::<u64>("RTCP_REPORT_INTERVAL_MS")
+            .filter(|ms| *ms > 0)
+            .unwrap_or(DEFAULT_REPORT_INTERVAL_MS);
+        let remb_enabled = env::get_bool("RTCP_REMB_ENABLED", true);
+        let remb_max_bitrate_bps = env::get_parsed::<u64>("RTCP_REMB_MAX_BITRATE_BPS")
+            .unwrap_or(DEFAULT_REMB_MAX_BITRATE_BPS)
+            .max(REMB_MIN_BITRATE_BPS);
+        let pli_min_interval_ms = env::get_parsed::<u64>("PLI_MIN_INTERVAL_MS").unwrap_or(DEFAULT_PLI_MIN_INTERVAL_MS);
+
+        Self {
+            report_interval: Duration::from_millis(report_interval_ms),
+            remb_enabled,
+            remb_max_bitrate_bps,
+            pli_min_interval: Duration::from_millis(pli_min_interval_ms),
+        }
+    }
+}
+
+/// Reception statistics for one interval, as they would appear in a receiver report
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalReport {
+    pub packets_received: u64,
+    /// Cumulative packets lost since the track started (negative with duplicates)
+    pub packets_lost: i64,
+    /// Fraction of expected packets lost since the previous report
+    pub fraction_lost: f64,
+    pub jitter_ms: f64,
+    /// Payload bitrate observed since the previous report
+    pub bitrate_bps: u64,
+}
+
+/// RFC 3550 (appendix A.3 and A.8) loss and jitter accounting for one RTP stream
+#[derive(Debug)]
+pub struct ReceiveStats {
+    clock_rate: u32,
+    epoch: Instant,
+    base_seq: u32,
+    max_seq: u16,
+    cycles: u32,
+    received: u64,
+    expected_prior: u64,
+    received_prior: u64,
+    /// Interarrival jitter in timestamp units
+    jitter: f64,
+    last_transit: Option<f64>,
+    bytes_since_report: u64,
+    last_report_at: Instant,
+}
+
+impl ReceiveStats {
+    pub fn new(clock_rate: u32, now: Instant) -> Self {
+        Self {
+            clock_rate: clock_rate.max(1),
+            epoch: now,
+            base_seq: 0,
+            max_seq: 0,
+            cycles: 0,
+            received: 0,
+            expected_prior: 0,
+            received_prior: 0,
+            jitter: 0.0,
+            last_transit: None,
+            bytes_since_report: 0,
+            last_report_at: now,
+        }
+    }
+
+    pub fn record(&mut self, sequence_number: u16, timestamp: u32, payload_len: usize, arrival: Instant) {
+        if self.received == 0 {
+            self.base_seq = sequence_number as u32;
+            self.max_seq = sequence_number;
+        } else {
+            let delta = sequence_number.wrapping_sub(self.max_seq);
+            // Small forward steps (including gaps) advance max_seq; anything else is late or a duplicate
+            if delta != 0 && delta < 0x8000 {
+                if sequence_number < self.max_seq {
+                    self.cycles += 1;
+                }
+                self.max_seq = sequence_number;
+            }
+        }
+        self.received += 1;
+        self.bytes_since_report += payload_len as u64;
+
+        let arrival_units = arrival.saturating_duration_since(self.epoch).as_secs_f64() * self.clock_rate as f64;
+        let transit = arrival_units - timestamp as f64;
+        if let Some(last_transit) = self.last_transit {
+            let d = (transit - last_transit).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    fn expected(&self) -> u64 {
+        if self.received == 0 {
+            return 0;
+        }
+        let extended_max = self.cycles as u64 * 65536 + self.max_seq as u64;
+        extended_max + 1 - self.base_seq as u64
+    }
+
+    /// Closes the current interval and returns its statistics
+    pub fn report(&mut self, now: Instant) -> IntervalReport {
+        let expected = self.expected();
+        let expected_interval = expected - self.expected_prior;
+        let received_interval = self.received - self.received_prior;
+        let lost_interval = expected_interval as i64 - received_interval as i64;
+        self.expected_prior = expected;
+        self.received_prior = self.received;
+
+        let fraction_lost = if expected_interval == 0 || lost_interval <= 0 {
+            0.0
+        } else {
+            lost_interval as f64 / expected_interval as f64
+        };
+
+        let elapsed = now.saturating_duration_since(self.last_report_at).as_secs_f64();
+        let bitrate_bps = if elapsed > 0.0 {
+            (self.bytes_since_report as f64 * 8.0 / elapsed) as u64
+        } else {
+            0
+        };
+        self.bytes_since_report = 0;
+        self.last_report_at = now;
+
+        IntervalReport {
+            packets_received: self.received,
+            packets_lost: expected as i64 - self.received as i64,
+            fraction_lost,
+            jitter_ms: self.jitter * 1000.0 / self.clock_rate as f64,
+            bitrate_bps,
+        }
+    }
+}
+
+/// Loss-based bandwidth estimate advertised to video publishers via REMB
+#[derive(Debug)]
+pub struct RembEstimator {
+    estimate_bps: f64,
+    max_bps: f64,
+}
+
+impl RembEstimator {
+    /// Starts at the ceiling, so REMB only caps publishers once loss shows up
+    pub fn new(max_bps: u64) -> Self {
+        Self {
+            estimate_bps: max_bps as f64,
+            max_bps: max_bps as f64,
+        }
+    }
+
+    pub fn update(&mut self, fraction_lost: f64) -> u64 {
+        if fraction_lost > LOSS_HIGH_WATERMARK {
+            self.estimate_bps *= 1.0 - 0.5 * fraction_lost;
+        } else if fraction_lost < LOSS_LOW_WATERMARK {
+            self.estimate_bps *= REMB_INCREASE_FACTOR;
+        }
+        self.estimate_bps = self.estimate_bps.clamp(REMB_MIN_BITRATE_BPS as f64, self.max_bps);
+        self.estimate_bps as u64
+    }
+}
+
+/// Relays subscriber NACKs for one published track to its publisher. The
+/// NACKs are rewritten to the publisher's SSRC, and a packet several
+/// subscribers lost is asked for once per `NACK_RELAY_WINDOW`.
+#[derive(Debug, Default)]
+pub struct NackRelay {
+    /// Sequence numbers asked for, oldest first
+    requested: VecDeque<(u16, Instant)>,
+    relayed: u64,
+    coalesced: u64,
+}
+
+impl NackRelay {
+    /// The NACK to send to the publisher of `media_ssrc` for a subscriber's
+    /// `nack`, without the packets already asked for; None if none are left
+    pub fn relay(&mut self, nack: &TransportLayerNack, media_ssrc: u32, now: Instant) -> Option<TransportLayerNack> {
+        while self
+            .requested
+            .front()
+            .is_some_and(|(_, at)| now.saturating_duration_since(*at) >= NACK_RELAY_WINDOW)
+        {
+            self.requested.pop_front();
+        }
+
+        let mut lost: Vec<u16> = Vec::new();
+        for seq in nack.nacks.iter().flat_map(|pair| pair.packet_list()) {
+            if self.requested.iter().any(|(requested, _)| *requested == seq) {
+                self.coalesced += 1;
+            } else if !lost.contains(&seq) {
+                lost.push(seq);
+            }
+        }
+        if lost.is_empty() {
+            return None;
+        }
+
+        for seq in &lost {
+            if self.requested.len() >= NACK_RELAY_HISTORY {
+                self.requested.pop_front();
+            }
+            self.requested.push_back((*seq, now));
+        }
+        self.relayed += lost.len() as u64;
+        Some(TransportLayerNack {
+            sender_ssrc: 0,
+            media_ssrc,
+            nacks: nack_pairs_from_sequence_numbers(&lost),
+        })
+    }
+
+    /// Lost packets asked of the publisher so far
+    pub fn relayed(&self) -> u64 {
+        self.relayed
+    }
+
+    /// Lost packets left out because another request already covered them
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
+
+/// Latest receive-side statistics for one published track
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackReceiveStats {
+    pub track_id: String,
+    pub kind: &'static str,
+    pub ssrc: u32,
+    pub packets_received: u64,
+    pub packets_lost: i64,
+    /// Loss over the most recent report interval
+    pub fraction_lost: f64,
+    pub jitter_ms: f64,
+    pub bitrate_bps: u64,
+    /// Last REMB sent to the publisher, for video tracks
+    pub remb_bitrate_bps: Option<u64>,
+    /// Unix time in milliseconds of the last report
+    pub reported_at: u64,
+    /// Subscriber PLIs forwarded to the publisher
+    pub plis_forwarded: u64,
+    /// Subscriber PLIs held back by the rate limit, per subscriber peer ID
+    pub plis_suppressed: BTreeMap<String, u64>,
+    /// Lost packets subscribers NACKed that were asked of the publisher
+    pub nacks_relayed: u64,
+    /// Lost packets subscribers NACKed that an earlier relayed NACK covered
+    pub nacks_coalesced: u64,
+    /// Writes to subscribers that failed since the track started
+    pub forward_drops: u64,
+}
+
+impl TrackReceiveStats {
+    pub fn from_report(track_id: &str, kind: &'static str, ssrc: u32, report: &IntervalReport, remb_bitrate_bps: Option<u64>) -> Self {
+        Self {
+            track_id: track_id.to_string(),
+            kind,
+            ssrc,
+            packets_received: report.packets_received,
+            packets_lost: report.packets_lost,
+            fraction_lost: report.fraction_lost,
+            jitter_ms: report.jitter_ms,
+            bitrate_bps: report.bitrate_bps,
+            remb_bitrate_bps,
+            reported_at: unix_ms(),
+            plis_forwarded: 0,
+            plis_suppressed: BTreeMap::new(),
+            nacks_relayed: 0,
+            nacks_coalesced: 0,
+            forward_drops: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PublisherStats {
+    pub peer_id: String,
+    pub room_id: String,
+    pub tracks: Vec<TrackReceiveStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiveStatsSnapshot {
+    pub report_interval_ms: u64,
+    pub remb_enabled: bool,
+    pub pli_min_interval_ms: u64,
+    pub publishers: Vec<PublisherStats>,
+}
+
+/// Shared settings plus the latest report for every track being forwarded
+pub struct ReceiverFeedback {
+    settings: RtcpSettings,
+    /// (room_id, track_id) -> (peer_id, room_id, stats); a peer publishing in
+    /// two rooms repeats its track IDs
+    tracks: Mutex<HashMap<(String, String), (String, String, TrackReceiveStats)>>,
+    /// (room_id, track_id) -> keyframe request limiter of that track
+    keyframe_requests: Mutex<HashMap<(String, String), PliLimiter>>,
+    /// (room_id, track_id) -> NACK relay of that track
+    nack_relays: Mutex<HashMap<(String, String), NackRelay>>,
+}
+
+static FEEDBACK: OnceLock<ReceiverFeedback> = OnceLock::new();
+
+/// Sets up the process-wide feedback state with `settings`. Only the first
+/// call takes effect, since every connection in the process shares the state.
+pub fn init(settings: RtcpSettings) {
+    if FEEDBACK.set(ReceiverFeedback::new(settings)).is_err() {
+        tracing::debug!("RTCP feedback already initialized, keeping its settings");
+    }
+}
+
+/// Process-wide receive feedback state, with the default settings until `init`
+pub fn feedback() -> &'static ReceiverFeedback {
+    FEEDBACK.get_or_init(|| ReceiverFeedback::new(RtcpSettings::default()))
+}
+
+impl ReceiverFeedback {
+    pub fn new(settings: RtcpSettings) -> Self {
+        Self {
+            settings,
+            tracks: Mutex::new(HashMap::new()),
+            keyframe_requests: Mutex::new(HashMap::new()),
+            nack_relays: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn settings(&self) -> &RtcpSettings {
+        &self.settings
+    }
+
+    pub fn update(&self, peer_id: &str, room_id: &str, stats: TrackReceiveStats) {
+        self.tracks
+            .lock()
+            .unwrap()
+            .insert(
+                (room_id.to_string(), stats.track_id.clone()),
+                (peer_id.to_string(), room_id.to_string(), stats),
+            );
+    }
+
+    pub fn remove(&self, room_id: &str, track_id: &str) {
+        let key = (room_id.to_string(), track_id.to_string());
+        self.tracks.lock().unwrap().remove(&key);
+        self.keyframe_requests.lock().unwrap().remove(&key);
+        self.nack_relays.lock().unwrap().remove(&key);
+    }
+
+    /// Passes a keyframe request from `subscriber` through the track's limiter
+    pub fn request_keyframe(&self, room_id: &str, track_id: &str, subscriber: &str, now: Instant) -> PliDecision {
+        self.keyframe_requests
+            .lock()
+            .unwrap()
+            .entry((room_id.to_string(), track_id.to_string()))
+            .or_insert_with(|| PliLimiter::new(self.settings.pli_min_interval))
+            .on_request(subscriber, now)
+    }
+
+    /// Passes the keyframe request of a new subscription to `track_id` through
+    /// the track's limiter; see `PliLimiter::on_subscribe`
+    pub fn request_subscription_keyframe(&self, room_id: &str, track_id: &str, subscriber: &str, now: Instant) -> PliDecision {
+        self.keyframe_requests
+            .lock()
+            .unwrap()
+            .entry((room_id.to_string(), track_id.to_string()))
+            .or_insert_with(|| PliLimiter::new(self.settings.pli_min_interval))
+            .on_subscribe(subscriber, now)
+    }
+
+    /// Passes a subscriber NACK for `track_id` through the track's relay; see
+    /// `NackRelay::relay`
+    pub fn relay_nack(
+        &self,
+        room_id: &str,
+        track_id: &str,
+        nack: &TransportLayerNack,
+        media_ssrc: u32,
+        now: Instant,
+    ) -> Option<TransportLayerNack> {
+        self.nack_relays
+            .lock()
+            .unwrap()
+            .entry((room_id.to_string(), track_id.to_string()))
+            .or_default()
+            .relay(nack, media_ssrc, now)
+    }
+
+    /// Note that the publisher of `track_id` produced a keyframe
+    pub fn on_keyframe(&self, room_id: &str, track_id: &str, now: Instant) {
+        self.keyframe_requests
+            .lock()
+            .unwrap()
+            .entry((room_id.to_string(), track_id.to_string()))
+            .or_insert_with(|| PliLimiter::new(self.settings.pli_min_interval))
+            .on_keyframe(now);
+    }
+
+    pub fn snapshot(&self) -> ReceiveStatsSnapshot {
+        let tracks = self.tracks.lock().unwrap();
+        let keyframe_requests = self.keyframe_requests.lock().unwrap();
+        let nack_relays = self.nack_relays.lock().unwrap();
+        let mut publishers: Vec<PublisherStats> = Vec::new();
+        for (key, (peer_id, room_id, stats)) in tracks.iter() {
+            let mut stats = stats.clone();
+            if let Some(limiter) = keyframe_requests.get(key) {
+                stats.plis_forwarded = limiter.forwarded();
+                stats.plis_suppressed = limiter.suppressed().clone();
+            }
+            if let Some(relay) = nack_relays.get(key) {
+                stats.nacks_relayed = relay.relayed();
+                stats.nacks_coalesced = relay.coalesced();
+            }
+            match publishers.iter_mut().find(|p| &p.peer_id == peer_id && &p.room_id == room_id) {
+                Some(publisher) => publisher.tracks.push(stats),
+                None => publishers.push(PublisherStats {
+                    peer_id: peer_id.clone(),
+                    room_id: room_id.clone(),
+                    tracks: vec![stats],
+                }),
+            }
+        }
+        publishers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id).then_with(|| a.room_id.cmp(&b.room_id)));
+        for publisher in &mut publishers {
+            publisher.tracks.sort_by(|a, b| a.track_id.cmp(&b.track_id));
+        }
+
+        ReceiveStatsSnapshot {
+            report_interval_ms: self.settings.report_interval.as_millis() as u64,
+            remb_enabled: self.settings.remb_enabled,
+            pli_min_interval_ms: self.settings.pli_min_interval.as_millis() as u64,
+            publishers,
+        }
+    }
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIDEO_CLOCK_RATE: u32 = 90_000;
+
+    #[test]
+    fn test_no_loss() {
+        let start = Instant::now();
+        let mut stats = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        for i in 0..100u16 {
+            stats.record(1000 + i, i as u32 * 3000, 1000, start + Duration::from_millis(i as u64 * 33));
+        }
+
+        let report = stats.report(start + Duration::from_secs(1));
+        assert_eq!(report.packets_received, 100);
+        assert_eq!(report.packets_lost, 0);
+        assert_eq!(report.fraction_lost, 0.0);
+        assert_eq!(report.bitrate_bps, 800_000);
+    }
+
+    #[test]
+    fn test_drop_every_nth_packet() {
+        let start = Instant::now();
+        let mut stats = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        // Sequence numbers 0..200 with every 10th missing
+        for seq in (0..200u16).filter(|seq| seq % 10 != 5) {
+            stats.record(seq, seq as u32 * 3000, 1000, start);
+        }
+
+        let report = stats.report(start + Duration::from_secs(1));
+        assert_eq!(report.packets_received, 180);
+        assert_eq!(report.packets_lost, 20);
+        assert!((report.fraction_lost - 0.1).abs() < 1e-9);
+
+        // The next interval only counts its own loss
+        for seq in 200..300u16 {
+            stats.record(seq, seq as u32 * 3000, 1000, start);
+        }
+        let report = stats.report(start + Duration::from_secs(2));
+        assert_eq!(report.fraction_lost, 0.0);
+        assert_eq!(report.packets_lost, 20);
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        let start = Instant::now();
+        let mut stats = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        for i in 0..20u32 {
+            let seq = (65530 + i) as u16;
+            if seq != 2 {
+                stats.record(seq, i * 3000, 100, start);
+            }
+        }
+
+        let report = stats.report(start + Duration::from_secs(1));
+        assert_eq!(report.packets_received, 19);
+        assert_eq!(report.packets_lost, 1);
+    }
+
+    #[test]
+    fn test_duplicates_and_reordering_are_not_loss() {
+        let start = Instant::now();
+        let mut stats = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        for seq in [1u16, 2, 4, 3, 5, 5] {
+            stats.record(seq, seq as u32 * 3000, 100, start);
+        }
+
+        let report = stats.report(start + Duration::from_secs(1));
+        assert_eq!(report.fraction_lost, 0.0);
+        assert_eq!(report.packets_lost, -1);
+    }
+
+    #[test]
+    fn test_jitter_from_uneven_arrival() {
+        let start = Instant::now();
+        let mut stats = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        let mut steady = ReceiveStats::new(VIDEO_CLOCK_RATE, start);
+        for i in 0..50u64 {
+            let ts = i as u32 * 3000;
+            let wobble = if i % 2 == 0 { 0 } else { 15 };
+            stats.record(i as u16, ts, 100, start + Duration::from_millis(i * 33 + wobble));
+            steady.record(i as u16, ts, 100, start + Duration::from_nanos(i * 33_333_333));
+        }
+
+        let jitter_ms = stats.report(start + Duration::from_secs(2)).jitter_ms;
+        assert!(jitter_ms > 5.0, "jitter {} too low", jitter_ms);
+        assert!(steady.report(start + Duration::from_secs(2)).jitter_ms < 0.1);
+    }
+
+    #[test]
+    fn test_remb_backs_off_under_loss() {
+        let mut remb = RembEstimator::new(2_000_000);
+        assert_eq!(remb.update(0.0), 2_000_000);
+
+        let after_loss = remb.update(0.2);
+        assert_eq!(after_loss, 1_800_000);
+
+        // Moderate loss holds the estimate
+        assert_eq!(remb.update(0.05), after_loss);
+
+        // Recovers while loss stays low, but never above the ceiling
+        assert!(remb.update(0.0) > after_loss);
+        for _ in 0..20 {
+            remb.update(0.0);
+        }
+        assert_eq!(remb.update(0.0), 2_000_000);
+
+        for _ in 0..100 {
+            remb.update(0.5);
+        }
+        assert_eq!(remb.update(0.5), REMB_MIN_BITRATE_BPS);
+    }
+
+    #[test]
+    fn test_snapshot_groups_tracks_by_publisher() {
+        let feedback = ReceiverFeedback::new(RtcpSettings {
+            report_interval: Duration::from_millis(500),
+            remb_enabled: true,
+            remb_max_bitrate_bps: DEFAULT_REMB_MAX_BITRATE_BPS,
+            pli_min_interval: Duration::from_millis(DEFAULT_PLI_MIN_INTERVAL_MS),
+        });
+        let stats = |track_id: &str, kind| TrackReceiveStats {
+            track_id: track_id.to_string(),
+            kind,
+            ssrc: 1,
+            packets_received: 10,
+            packets_lost: 1,
+            fraction_lost: 0.1,
+            jitter_ms: 2.0,
+            bitrate_bps: 1000,
+            remb_bitrate_bps: None,
+            reported_at: 0,
+            plis_forwarded: 0,
+            plis_suppressed: BTreeMap::new(),
+            nacks_relayed: 0,
+            nacks_coalesced: 0,
+            forward_drops: 0,
+        };
+        feedback.update("student_1", "room", stats("student_1_video", "video"));
+        feedback.update("student_1", "room", stats("student_1_audio", "audio"));
+        feedback.update("proctor", "room", stats("proctor_video", "video"));
+
+        let snapshot = feedback.snapshot();
+        assert_eq!(snapshot.report_interval_ms, 500);
+        assert_eq!(snapshot.publishers.len(), 2);
+        assert_eq!(snapshot.publishers[1].peer_id, "student_1");
+        assert_eq!(snapshot.publishers[1].tracks.len(), 2);
+
+        feedback.remove("room", "proctor_video");
+        assert_eq!(feedback.snapshot().publishers.len(), 1);
+
+        // The same publisher in another room is reported separately
+        feedback.update("student_1", "other_room", stats("student_1_video", "video"));
+        let snapshot = feedback.snapshot();
+        assert_eq!(snapshot.publishers.len(), 2);
+        assert_eq!(snapshot.publishers[0].room_id, "other_room");
+        assert_eq!(snapshot.publishers[0].tracks.len(), 1);
+        assert_eq!(snapshot.publishers[1].tracks.len(), 2);
+    }
+
+    #[test]
+    fn test_keyframe_requests_limited_per_track() {
+        let feedback = ReceiverFeedback::new(RtcpSettings {
+            report_interval: Duration::from_millis(500),
+            remb_enabled: false,
+            remb_max_bitrate_bps: DEFAULT_REMB_MAX_BITRATE_BPS,
+            pli_min_interval: Duration::from_millis(1000),
+        });
+        let start = Instant::now();
+        let report = ReceiveStats::new(90000, start).report(start);
+        feedback.update("student_1", "room", TrackReceiveStats::from_report("student_1_video", "video", 1, &report, None));
+
+        assert_eq!(feedback.request_keyframe("room", "student_1_video", "proctor", start), PliDecision::Forward);
+        assert_eq!(feedback.request_keyframe("room", "student_1_video", "proctor", start), PliDecision::Suppressed);
+        // Each track has its own window
+        assert_eq!(feedback.request_keyframe("room", "student_2_video", "proctor", start), PliDecision::Forward);
+        feedback.on_keyframe("room", "student_1_video", start + Duration::from_millis(1100));
+        assert_eq!(
+            feedback.request_keyframe("room", "student_1_video", "proctor", start + Duration::from_millis(1200)),
+            PliDecision::SatisfiedByKeyframe
+        );
+
+        let snapshot = feedback.snapshot();
+        let track = &snapshot.publishers[0].tracks[0];
+        assert_eq!(snapshot.pli_min_interval_ms, 1000);
+        assert_eq!(track.plis_forwarded, 1);
+        assert_eq!(track.plis_suppressed.get("proctor"), Some(&1));
+
+        feedback.remove("room", "student_1_video");
+        assert_eq!(feedback.request_keyframe("room", "student_1_video", "proctor", start), PliDecision::Forward);
+    }
+
+    #[test]
+    fn test_second_subscriber_gets_a_pli_after_the_first_keyframe() {
+        let feedback = ReceiverFeedback::new(RtcpSettings {
+            report_interval: Duration::from_millis(500),
+            remb_enabled: false,
+            remb_max_bitrate_bps: DEFAULT_REMB_MAX_BITRATE_BPS,
+            pli_min_interval: Duration::from_millis(1000),
+        });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(
+            feedback.request_subscription_keyframe("room", "proctor_video", "student_1", at(0)),
+            PliDecision::Forward
+        );
+        feedback.on_keyframe("room", "proctor_video", at(100));
+        // A late joiner within the window still needs a keyframe of its own
+        assert_eq!(
+            feedback.request_subscription_keyframe("room", "proctor_video", "student_2", at(400)),
+            PliDecision::Forward
+        );
+        assert_eq!(
+            feedback.request_subscription_keyframe("room", "proctor_video", "student_3", at(450)),
+            PliDecision::Suppressed
+        );
+    }
+
+    fn subscriber_nack(subscriber_ssrc: u32, lost: &[u16]) -> TransportLayerNack {
+        TransportLayerNack {
+            sender_ssrc: 1,
+            media_ssrc: subscriber_ssrc,
+            nacks: nack_pairs_from_sequence_numbers(lost),
+        }
+    }
+
+    fn relayed_packets(nack: &TransportLayerNack) -> Vec<u16> {
+        nack.nacks.iter().flat_map(|pair| pair.packet_list()).collect()
+    }
+
+    #[test]
+    fn test_nack_rewritten_to_publisher_ssrc() {
+        const PUBLISHER_SSRC: u32 = 0xabcd_0001;
+        let mut relay = NackRelay::default();
+        let now = Instant::now();
+
+        // The subscriber names the SSRC the SFU sends it on, not the publisher's
+        let relayed = relay.relay(&subscriber_nack(0x1111, &[100, 101, 105, 140]), PUBLISHER_SSRC, now).unwrap();
+        assert_eq!(relayed.media_ssrc, PUBLISHER_SSRC);
+        assert_eq!(relayed.sender_ssrc, 0);
+        assert_eq!(relayed_packets(&relayed), vec![100, 101, 105, 140]);
+        assert_eq!(relayed.nacks.len(), 2);
+
+        // Across the sequence number wrap
+        let relayed = relay.relay(&subscriber_nack(0x1111, &[65534, 65535, 0, 1]), PUBLISHER_SSRC, now).unwrap();
+        assert_eq!(relayed_packets(&relayed), vec![65534, 65535, 0, 1]);
+    }
+
+    #[test]
+    fn test_nack_relay_coalesces_subscribers() {
+        const PUBLISHER_SSRC: u32 = 42;
+        let mut relay = NackRelay::default();
+        let start = Instant::now();
+
+        // Every subscriber misses the packets lost between the publisher and the SFU
+        let first = relay.relay(&subscriber_nack(0x1111, &[10, 20, 30]), PUBLISHER_SSRC, start).unwrap();
+        assert_eq!(relayed_packets(&first), vec![10, 20, 30]);
+        let second = relay
+            .relay(&subscriber_nack(0x2222, &[20, 30, 31]), PUBLISHER_SSRC, start + Duration::from_millis(20))
+            .unwrap();
+        assert_eq!(relayed_packets(&second), vec![31]);
+        assert!(relay
+            .relay(&subscriber_nack(0x3333, &[10, 31]), PUBLISHER_SSRC, start + Duration::from_millis(40))
+            .is_none());
+
+        // A retransmission that was lost too is asked for again after the window
+        let retry = relay
+            .relay(&subscriber_nack(0x1111, &[10]), PUBLISHER_SSRC, start + NACK_RELAY_WINDOW)
+            .unwrap();
+        assert_eq!(relayed_packets(&retry), vec![10]);
+
+        assert_eq!(relay.relayed(), 5);
+        assert_eq!(relay.coalesced(), 4);
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::OnceLock;
+
+use crate::config::env;
+
+/// Default upper bound on a client SDP, comfortably above a multi-track session
+pub const DEFAULT_MAX_SDP_BYTES: usize = 64 * 1024;
+
+const BOM: char = '\u{feff}';
+
+/// Why a client SDP was refused before reaching the WebRTC stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdpError {
+    /// Lines are run together or separated by stray carriage returns
+    BadLineEndings,
+    TooLarge { size: usize, max: usize },
+    /// The first line is not `v=0`
+    NotSdp,
+}
+
+impl SdpError {
+    pub fn code(&self) -> &'static str {
+        "invalid_sdp"
+    }
+
+    /// Sub-reason sent alongside `invalid_sdp`
+    pub fn reason(&self) -> &'static str {
+        match self {
+            SdpError::BadLineEndings => "bad_line_endings",
+            SdpError::TooLarge { .. } => "too_large",
+            SdpError::NotSdp => "not_sdp",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            SdpError::BadLineEndings => "SDP lines must be separated by CRLF or LF".to_string(),
+            SdpError::TooLarge { size, max } => format!("SDP is {} bytes, the limit is {}", size, max),
+            SdpError::NotSdp => "SDP must start with v=0".to_string(),
+        }
+    }
+}
+
+/// What normalization had to repair; all false for a well-formed SDP
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SdpFixes {
+    pub bom: bool,
+    pub nul: bool,
+    pub line_endings: bool,
+}
+
+impl SdpFixes {
+    pub fn any(&self) -> bool {
+        self.bom || self.nul || self.line_endings
+    }
+}
+
+static MAX_SDP_BYTES: OnceLock<usize> = OnceLock::new();
+
+/// Size budget read from `MAX_SDP_BYTES` once per process
+pub fn max_sdp_bytes() -> usize {
+    *MAX_SDP_BYTES.get_or_init(|| {
+        env::get_parsed("MAX_SDP_BYTES")
+            .filter(|max| *max > 0)
+            .unwrap_or(DEFAULT_MAX_SDP_BYTES)
+    })
+}
+
+/// Repairs the mangling clients are known to send (a UTF-8 BOM, NUL padding,
+/// bare LF or doubled CR line endings) and checks the result looks like SDP.
+/// Returns the SDP with CRLF line endings and what had to be fixed.
+pub fn normalize_sdp(raw: &str, max_bytes: usize) -> Result<(String, SdpFixes), SdpError> {
+    if raw.len() > max_bytes {
+        return Err(SdpError::TooLarge { size: raw.len(), max: max_bytes });
+    }
+
+    let mut fixes = SdpFixes::default();
+    let mut text = raw;
+    if let Some(stripped) = text.strip_prefix(BOM) {
+        fixes.bom = true;
+        text = stripped;
+    }
+    let text = if text.contains('\0') {
+        fixes.nul = true;
+        text.replace('\0', "")
+    } else {
+        text.to_string()
+    };
+
+    let mut lines: Vec<&str> = text.split('\n').map(|line| line.trim_end_matches('\r')).collect();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+
+    match lines.first() {
+        Some(&"v=0") => {}
+        // A whole SDP on one line: its line breaks were lost or escaped
+        Some(first) if first.starts_with("v=0") && lines.len() == 1 => return Err(SdpError::BadLineEndings),
+        _ => return Err(SdpError::NotSdp),
+    }
+    if lines.iter().any(|line| line.contains('\r')) {
+        return Err(SdpError::BadLineEndings);
+    }
+
+    let mut sdp = lines.join("\r\n");
+    sdp.push_str("\r\n");
+    fixes.line_endings = sdp != text;
+    Ok((sdp, fixes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SDP: &str = "v=0\r\no=- 4215775240449105457 2 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\nm=audio 9 UDP/TLS/RTP/SAVPF 111\r\nc=IN IP4 0.0.0.0\r\na=mid:0\r\n";
+
+    fn normalize(raw: &str) -> Result<(String, SdpFixes), SdpError> {
+        normalize_sdp(raw, DEFAULT_MAX_SDP_BYTES)
+    }
+
+    #[test]
+    fn test_well_formed_sdp_is_untouched() {
+        let (sdp, fixes) = normalize(SDP).unwrap();
+        assert_eq!(sdp, SDP);
+        assert!(!fixes.any());
+    }
+
+    #[test]
+    fn test_mangled_sdps_are_repaired() {
+        let corpus = [
+            ("bare LF", SDP.replace("\r\n", "\n"), SdpFixes { line_endings: true, ..Default::default() }),
+            ("mixed endings", SDP.replacen("\r\n", "\n", 3), SdpFixes { line_endings: true, ..Default::default() }),
+            ("doubled CR", SDP.replace("\r\n", "\r\r\n"), SdpFixes { line_endings: true, ..Default::default() }),
+            ("no final newline", SDP.trim_end().to_string(), SdpFixes { line_endings: true, ..Default::default() }),
+            ("trailing blank lines", format!("{}\r\n\r\n", SDP), SdpFixes { line_endings: true, ..Default::default() }),
+            ("BOM", format!("\u{feff}{}", SDP), SdpFixes { bom: true, ..Default::default() }),
+            // The Electron build pads the SDP with NULs after a bare-LF body
+            (
+                "Electron",
+                format!("\u{feff}{}\0\0\0\0", SDP.replace("\r\n", "\n")),
+                SdpFixes { bom: true, nul: true, line_endings: true },
+            ),
+        ];
+
+        for (name, raw, expected) in corpus {
+            let (sdp, fixes) = normalize(&raw).unwrap_or_else(|e| panic!("{} rejected: {:?}", name, e));
+            assert_eq!(sdp, SDP, "{}", name);
+            assert_eq!(fixes, expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_unrepairable_sdps_are_rejected() {
+        let corpus = [
+            ("empty", String::new(), SdpError::NotSdp),
+            ("only NULs", "\0\0\0".to_string(), SdpError::NotSdp),
+            ("JSON", r#"{"type":"answer","sdp":"v=0"}"#.to_string(), SdpError::NotSdp),
+            ("wrong version", SDP.replace("v=0", "v=1"), SdpError::NotSdp),
+            ("leading blank line", format!("\r\n{}", SDP), SdpError::NotSdp),
+            ("escaped newlines", SDP.replace("\r\n", "\\r\\n"), SdpError::BadLineEndings),
+            ("CR only", SDP.replace("\r\n", "\r"), SdpError::BadLineEndings),
+            ("stray CR", SDP.replacen("s=-", "s=-\rt=0 0", 1), SdpError::BadLineEndings),
+        ];
+
+        for (name, raw, expected) in corpus {
+            assert_eq!(normalize(&raw).unwrap_err(), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_size_budget() {
+        assert!(normalize_sdp(SDP, SDP.len()).is_ok());
+        assert_eq!(
+            normalize_sdp(SDP, SDP.len() - 1),
+            Err(SdpError::TooLarge { size: SDP.len(), max: SDP.len() - 1 })
+        );
+        assert_eq!(SdpError::TooLarge { size: 2, max: 1 }.reason(), "too_large");
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
@@ -6,112 +7,752 @@ use tokio::time::sleep;
 use warp::ws::Message;
 use webrtc::api::API;
 use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
-
-use super::connection::{SfuConnection, TrackNotificationSender};
-use super::room::{RoomManager, PeerRole};
-use super::track_manager::TrackManager;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::RTCPeerConnection;
+
+use super::connection::{PeerStateChange, PeerStateSender, SfuConnection, TrackNotificationSender};
+use super::room::{DepartedPeer, DisconnectCause, PeerKey, RoomManager, PeerRole};
+use super::roster::Roster;
+use super::admission::{AdmissionLimits, AdmissionService, PendingAdmissions, RejectReason, Rejection, RetryPolicy};
+use super::affinity::{InstanceInfo, RoomAffinity, RoomLocation};
+use super::closing::{CloseBlocker, ClosePreview, DEFAULT_CLOSE_ROOM_MIN_RECORDING_SECS};
+use super::connections::{ConnectionRegistry, HeldSignal, HoldError, PeerConnections};
+use super::escalation::{EscalationAction, EscalationPolicy, JoinEscalation};
+use super::media_routing::{MediaRoutingService, TrackReadiness};
+use super::negotiation::{self, ClientOfferError, NegotiationService, Negotiations, RenegotiationOutcome, MAX_RENEGOTIATION_RETRIES};
+use super::overview::{PeerOverview, RoomDetail, RoomOverview};
+use super::renegotiation::{RenegotiationControl, RenegotiationTuning};
+use super::rtcp::{self, RtcpSettings};
+use super::room_state::{RoomStateStreams, RoomStateUpdate};
+use super::sdp::max_sdp_bytes;
+use super::recipe::{ClientKind, ConnectionRecipe, DeploymentProfile, Keepalive, RecipeFeatures, RecipeRole};
+use super::pending::{IceBufferError, PendingIceCandidate, PendingStudent};
+use super::track_manager::{order_tracks, stream_id, TrackContent, TrackManager, TrackOrderEntry};
 use super::signaling::SfuMessage;
+use super::state_log::{Announcement, ExamClock, RoomEvent};
+use super::supervisor::{ShutdownReport, TaskSupervisor};
+use super::timezone::RoomLocale;
+use super::transfer::{self, TransferError, Transfers};
+use super::webrtc_utils::{api_factory, get_ice_servers, ApiFactory, EngineConfigError, WebRtcEngineConfig};
+use crate::config::{env, Config, RecordingConfig, WebRTCConfig};
+use crate::diagnostics::{self, DiagnosticsBundle, RecordingDiagnostics, TransportDiagnostics};
 use crate::error::SfuError;
-use crate::recording::{RecordingManager, RecordingResult};
-use crate::ipfs::{IpfsClient, IpfsConfig};
-use crate::substrate::{EventQueue, ChainEvent, Role as ChainRole, LeaveReason as ChainLeaveReason, VerificationStatus as ChainVerificationStatus, SuspiciousActivityType as ChainSuspiciousActivityType, RoomCloseReason as ChainRoomCloseReason, Address, parse_address};
+use crate::health;
+use crate::health::alert::{alerter, Alert};
+use crate::metrics;
+use crate::recording::integrity;
+use crate::recording::store::{self, StorageConfig};
+use crate::recording::tenant::{self, TenantError, TenantSweep};
+use crate::recording::{
+    CompletedRecording, GapEvent, IntegrityScore, RecordingDetail, RecordingFailure, RecordingManager, RecordingPipeline, RecordingRestart,
+    RecordingResult, RecordingSidecar, RecordingStore, RecordingUpload, RoomSession, SessionMetadata, MEDIA_GAP_ACTIVITY,
+    ViewEventKind,
+};
+use crate::recording::transcript::{self, TranscriptService};
+use crate::substrate::{EventQueue, ChainEvent, ChainRecorder, Role as ChainRole, LeaveReason as ChainLeaveReason, VerificationStatus as ChainVerificationStatus, SuspiciousActivityType as ChainSuspiciousActivityType, RoomCloseReason as ChainRoomCloseReason, Address, parse_address};
 
-/// Queued ICE candidate waiting for remote description
-#[derive(Debug, Clone)]
-struct PendingIceCandidate {
-    candidate: String,
-    sdp_mid: Option<String>,
-    sdp_mline_index: Option<u16>,
-}
+/// Longest a single track notification may take before the track processor counts as stalled
+const TRACK_PROCESSOR_MAX_SILENCE: Duration = Duration::from_secs(60);
 
-/// Pending student info including wallet address
-struct PendingStudent {
-    sender: mpsc::UnboundedSender<Message>,
-    wallet_address: Option<String>,
-}
+/// How often unanswered join requests are escalated and expired ones swept
+const PENDING_SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often recorded tracks are checked for media gaps
+const GAP_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often running recordings are checked against the disk space and duration limits
+const RECORDING_LIMIT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often peers whose connection stayed disconnected are looked for
+const PEER_STATE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default time a peer connection may stay disconnected before the peer is removed
+const DEFAULT_DISCONNECT_GRACE_SECS: u64 = 15;
+
+/// Default time a room close waits for recording uploads before building the manifest
+const DEFAULT_MANIFEST_UPLOAD_WAIT_SECS: u64 = 120;
+
+/// Default time shutdown waits for background tasks before aborting them
+const DEFAULT_TASK_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+
+/// Default time shutdown waits for recording uploads and queued chain events
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
 
 /// Stores exam result info for a peer
 #[derive(Debug, Clone)]
 pub struct ExamGrade {
     pub grade: u64,      // Grade in basis points (8500 = 85.00%)
-    pub exam_name: String,
+    /// Exam name the student submitted, if any
+    pub exam_name: Option<String>,
+}
+
+/// Why a proctor's kick was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KickError {
+    /// The target is not in the room
+    PeerNotFound(String),
+    /// The target is the room's proctor
+    Proctor,
+}
+
+impl KickError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            KickError::PeerNotFound(_) => "peer_not_found",
+            KickError::Proctor => "invalid_target",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            KickError::PeerNotFound(peer_id) => format!("Peer {} is not in this room", peer_id),
+            KickError::Proctor => "The proctor cannot be removed from their own room".to_string(),
+        }
+    }
+}
+
+/// Why a proctor's CloseRoom did not close the room
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseRoomError {
+    RoomNotFound(String),
+    /// Unforced close refused; the proctor has to resend with `force`
+    Blocked {
+        blockers: Vec<CloseBlocker>,
+        preview: ClosePreview,
+    },
+}
+
+impl CloseRoomError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            CloseRoomError::RoomNotFound(_) => "room_not_found",
+            CloseRoomError::Blocked { .. } => "close_blocked",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            CloseRoomError::RoomNotFound(room_id) => format!("Room {} does not exist", room_id),
+            CloseRoomError::Blocked { blockers, .. } => {
+                format!("{} check(s) failed; resend CloseRoom with force to close anyway", blockers.len())
+            }
+        }
+    }
+}
+
+/// Builds the on-chain exam result for a departing student. The proctor's session
+/// title wins over the name the student submitted, which wins over the default.
+fn exam_result_event(
+    room_id: &str,
+    participant: Address,
+    exam_grade: Option<&ExamGrade>,
+    metadata: &SessionMetadata,
+) -> ChainEvent {
+    let grade = exam_grade.map(|eg| eg.grade).unwrap_or(0);
+    let exam_name = metadata
+        .exam_title()
+        .or_else(|| exam_grade.and_then(|eg| eg.exam_name.clone()))
+        .unwrap_or_else(|| format!("Exam Session {}", room_id));
+
+    ChainEvent::CreateExamResult {
+        room_id: room_id.to_string(),
+        participant,
+        grade,
+        exam_name,
+    }
+}
+
+/// The message carrying a room state update, if there is anything to send
+fn room_state_message(room_id: &str, update: RoomStateUpdate) -> Option<SfuMessage> {
+    match update {
+        RoomStateUpdate::Full { revision, track_order } => Some(SfuMessage::RoomState {
+            room_id: room_id.to_string(),
+            track_order,
+            revision: Some(revision),
+        }),
+        RoomStateUpdate::Delta { revision, changes } => Some(SfuMessage::RoomStateDelta {
+            room_id: room_id.to_string(),
+            revision,
+            changes,
+        }),
+        RoomStateUpdate::Unchanged => None,
+    }
+}
+
+/// On-chain leave reason for a departure
+fn chain_leave_reason(cause: DisconnectCause) -> ChainLeaveReason {
+    match cause {
+        DisconnectCause::Left => ChainLeaveReason::Normal,
+        DisconnectCause::Kicked => ChainLeaveReason::Kicked,
+        DisconnectCause::ConnectionLost => ChainLeaveReason::Disconnected,
+        DisconnectCause::RoomClosed => ChainLeaveReason::RoomClosed,
+        // The contract has no transfer reason; the event's details name the destination
+        DisconnectCause::Transferred => ChainLeaveReason::Normal,
+    }
+}
+
+/// Sends `message` over a peer's WebSocket while it has no connection registered
+fn send_direct(sender: &mpsc::UnboundedSender<Message>, message: &SfuMessage) {
+    if let Ok(text) = serde_json::to_string(message) {
+        let _ = sender.send(Message::text(text));
+    }
+}
+
+/// Tells the source room's proctor, or a student put back in it, that a transfer did not happen
+fn transfer_failed(from_room_id: &str, to_room_id: &str, target_peer_id: &str, error: &TransferError) -> SfuMessage {
+    SfuMessage::TransferFailed {
+        from_room_id: from_room_id.to_string(),
+        to_room_id: to_room_id.to_string(),
+        target_peer_id: target_peer_id.to_string(),
+        code: error.code().to_string(),
+        message: error.message(),
+    }
 }
 
 pub struct SfuServer {
     api: Arc<API>,
-    connections: Arc<RwLock<HashMap<String, Arc<SfuConnection>>>>,
-    pending_students: Arc<RwLock<HashMap<String, PendingStudent>>>,
-    /// Maps peer_id to wallet address for on-chain event emission
-    peer_wallets: Arc<RwLock<HashMap<String, Address>>>,
-    /// Maps peer_id to their exam grade (set when student submits exam)
-    peer_exam_grades: Arc<RwLock<HashMap<String, ExamGrade>>>,
+    connections: Arc<dyn ConnectionRegistry>,
+    /// Join requests awaiting a proctor decision, scoped to the room they were made for
+    admission: Arc<dyn AdmissionService>,
+    /// Wallet address per (room_id, peer_id) for on-chain event emission
+    peer_wallets: Arc<RwLock<HashMap<PeerKey, Address>>>,
+    /// Exam grade per (room_id, peer_id), set when the student submits the exam
+    peer_exam_grades: Arc<RwLock<HashMap<PeerKey, ExamGrade>>>,
     track_manager: Arc<TrackManager>,
     room_manager: Arc<RoomManager>,
     track_notification_sender: TrackNotificationSender,
-    track_notification_receiver: Arc<RwLock<Option<mpsc::UnboundedReceiver<(String, String)>>>>,
-    peers_with_tracks: Arc<RwLock<HashMap<String, usize>>>,
-    pending_renegotiations: Arc<RwLock<HashMap<String, bool>>>,
-    pending_ice_candidates: Arc<RwLock<HashMap<String, Vec<PendingIceCandidate>>>>,
+    track_notification_receiver: Arc<RwLock<Option<mpsc::UnboundedReceiver<(PeerKey, String)>>>>,
+    peer_state_sender: PeerStateSender,
+    peer_state_receiver: Arc<RwLock<Option<mpsc::UnboundedReceiver<PeerStateChange>>>>,
+    /// How long a peer connection may stay disconnected before the peer is removed
+    disconnect_grace: Duration,
+    /// Peer connections reported disconnected, since when
+    disconnected: std::sync::Mutex<HashMap<PeerKey, (std::time::Instant, std::sync::Weak<RTCPeerConnection>)>>,
+    /// Which publishers have media for subscribers
+    media_routing: Arc<dyn MediaRoutingService>,
+    /// Renegotiation batching and ICE candidates awaiting a remote description
+    negotiation: Arc<dyn NegotiationService>,
+    /// Live renegotiation tuning and per-peer offer stats
+    renegotiation: Arc<RenegotiationControl>,
+    /// Revision and delta history of the `RoomState` sent to each peer
+    room_state: RoomStateStreams,
     recording_manager: Arc<RecordingManager>,
+    /// Wallets, and file hashes, whose on-chain RecordingStopped waits for the upload of this file
+    awaiting_upload: std::sync::Mutex<HashMap<PathBuf, (Address, Option<String>)>>,
+    /// Settings the WebRTC engine was built with; recordings expect its preferred codecs
+    engine_config: WebRtcEngineConfig,
+    /// STUN/TURN servers and ICE policy every peer connection is created with
+    webrtc_config: WebRTCConfig,
     /// Optional blockchain event queue for recording events on-chain
     event_queue: Option<EventQueue>,
+    admission_limits: AdmissionLimits,
+    /// Backoff hints attached to every shed/reject response
+    retry_policy: RetryPolicy,
+    /// When unanswered join requests are escalated; each room picks the fallback
+    join_escalation: JoinEscalation,
+    /// Instance identity and shared room registry for multi-instance deployments
+    affinity: RoomAffinity,
+    /// Wallets and incidents per room, for the manifest built at room close
+    room_sessions: Arc<RwLock<HashMap<String, RoomSession>>>,
+    /// Room transfers awaiting the destination proctor, and where moved students went
+    transfers: Transfers,
+    /// Bound on waiting for uploads before a partial manifest is published
+    manifest_upload_wait: Duration,
+    /// Recordings younger than this keep an unforced CloseRoom from closing the room
+    close_min_recording_age: Duration,
+    /// Background tasks owned by the server, stopped by `shutdown`
+    tasks: TaskSupervisor,
+    task_shutdown_timeout: Duration,
+    /// Bound on waiting for uploads and chain events before the tasks are stopped
+    shutdown_timeout: Duration,
+    started_at: std::time::Instant,
+}
+
+/// Admission, signaling, RTCP and shutdown settings of the server
+#[derive(Clone)]
+pub struct SfuConfig {
+    pub admission_limits: AdmissionLimits,
+    pub retry_policy: RetryPolicy,
+    pub join_escalation: JoinEscalation,
+    /// Instance identity and the room registry shared with other instances
+    pub affinity: RoomAffinity,
+    pub renegotiation: RenegotiationTuning,
+    /// Receiver reports, REMB and PLI forwarding; process-wide, so the first
+    /// server built sets them
+    pub rtcp: RtcpSettings,
+    /// How long a peer whose connection failed may rejoin before it is removed
+    pub disconnect_grace: Duration,
+    /// How long a room close waits for uploads before publishing the manifest
+    pub manifest_upload_wait: Duration,
+    /// Recordings younger than this keep an unforced CloseRoom from closing the room
+    pub close_min_recording_age: Duration,
+    /// Bound on waiting for uploads and chain events at shutdown
+    pub shutdown_timeout: Duration,
+    /// Bound on waiting for background tasks to stop at shutdown
+    pub task_shutdown_timeout: Duration,
+}
+
+impl Default for SfuConfig {
+    fn default() -> Self {
+        Self {
+            admission_limits: AdmissionLimits::default(),
+            retry_policy: RetryPolicy::default(),
+            join_escalation: JoinEscalation::default(),
+            affinity: RoomAffinity::default(),
+            renegotiation: RenegotiationTuning::default(),
+            rtcp: RtcpSettings::default(),
+            disconnect_grace: Duration::from_secs(DEFAULT_DISCONNECT_GRACE_SECS),
+            manifest_upload_wait: Duration::from_secs(DEFAULT_MANIFEST_UPLOAD_WAIT_SECS),
+            close_min_recording_age: Duration::from_secs(DEFAULT_CLOSE_ROOM_MIN_RECORDING_SECS),
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+            task_shutdown_timeout: Duration::from_secs(DEFAULT_TASK_SHUTDOWN_TIMEOUT_SECS),
+        }
+    }
+}
+
+impl SfuConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+
+        // Startup checks refuse invalid settings; this only guards other callers
+        let renegotiation = RenegotiationTuning::from_env().unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Invalid renegotiation tuning, using defaults");
+            RenegotiationTuning::default()
+        });
+
+        Self {
+            admission_limits: AdmissionLimits::from_env(),
+            retry_policy: RetryPolicy::from_env(),
+            join_escalation: JoinEscalation::from_env(),
+            affinity: RoomAffinity::from_env(),
+            renegotiation,
+            rtcp: RtcpSettings::from_env(),
+            disconnect_grace: env::get_duration_secs("SFU_DISCONNECT_GRACE_SECS", defaults.disconnect_grace),
+            manifest_upload_wait: env::get_duration_secs("ROOM_MANIFEST_UPLOAD_WAIT_SECS", defaults.manifest_upload_wait),
+            close_min_recording_age: env::get_duration_secs(
+                "CLOSE_ROOM_MIN_RECORDING_SECS",
+                defaults.close_min_recording_age,
+            ),
+            shutdown_timeout: env::get_duration_secs("SHUTDOWN_TIMEOUT_SECS", defaults.shutdown_timeout),
+            task_shutdown_timeout: env::get_duration_secs("TASK_SHUTDOWN_TIMEOUT_SECS", defaults.task_shutdown_timeout),
+        }
+    }
+}
+
+/// Builds an `SfuServer` from the settings it is given, reading nothing from
+/// the environment. Settings not supplied, directly or through a `Config`,
+/// take their defaults: the default WebRTC engine on the process-wide
+/// `ApiFactory`, no recording store and no transcripts. Services not
+/// supplied get their in-memory implementation.
+#[derive(Default)]
+pub struct SfuServerBuilder {
+    engine_config: Option<WebRtcEngineConfig>,
+    webrtc_config: Option<WebRTCConfig>,
+    api_factory: Option<Arc<ApiFactory>>,
+    connections: Option<Arc<dyn ConnectionRegistry>>,
+    admission: Option<Arc<dyn AdmissionService>>,
+    negotiation: Option<Arc<dyn NegotiationService>>,
+    media_routing: Option<Arc<dyn MediaRoutingService>>,
+    recording_store: Option<Arc<dyn RecordingStore>>,
+    recording_config: Option<RecordingConfig>,
+    storage_config: Option<StorageConfig>,
+    sfu_config: Option<SfuConfig>,
+    transcripts: Option<Arc<TranscriptService>>,
+    chain_recorder: Option<Arc<dyn ChainRecorder>>,
+}
+
+impl SfuServerBuilder {
+    /// Takes the ICE, recording, storage and server settings from `config`,
+    /// as assembled once by `Config::from_env`
+    pub fn config(self, config: &Config) -> Self {
+        self.webrtc_config(config.webrtc.clone())
+            .recording_config(config.recording.clone())
+            .storage_config(config.storage.clone())
+            .sfu_config(config.sfu.clone())
+    }
+
+    pub fn engine_config(mut self, config: WebRtcEngineConfig) -> Self {
+        self.engine_config = Some(config);
+        self
+    }
+
+    /// ICE settings for every peer connection
+    pub fn webrtc_config(mut self, config: WebRTCConfig) -> Self {
+        self.webrtc_config = Some(config);
+        self
+    }
+
+    /// Where and how recordings are written
+    pub fn recording_config(mut self, config: RecordingConfig) -> Self {
+        self.recording_config = Some(config);
+        self
+    }
+
+    /// Builds the API through `factory`, so servers sharing it share engines
+    pub fn api_factory(mut self, factory: Arc<ApiFactory>) -> Self {
+        self.api_factory = Some(factory);
+        self
+    }
+
+    pub fn connections(mut self, connections: Arc<dyn ConnectionRegistry>) -> Self {
+        self.connections = Some(connections);
+        self
+    }
+
+    pub fn admission(mut self, admission: Arc<dyn AdmissionService>) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    pub fn negotiation(mut self, negotiation: Arc<dyn NegotiationService>) -> Self {
+        self.negotiation = Some(negotiation);
+        self
+    }
+
+    pub fn media_routing(mut self, media_routing: Arc<dyn MediaRoutingService>) -> Self {
+        self.media_routing = Some(media_routing);
+        self
+    }
+
+    /// Uploads recordings to the store `config` selects
+    pub fn storage_config(mut self, config: StorageConfig) -> Self {
+        self.storage_config = Some(config);
+        self
+    }
+
+    /// Admission limits, timeouts and the other server settings
+    pub fn sfu_config(mut self, config: SfuConfig) -> Self {
+        self.sfu_config = Some(config);
+        self
+    }
+
+    /// Submits uploaded recordings for transcription through `transcripts`
+    pub fn transcripts(mut self, transcripts: Arc<TranscriptService>) -> Self {
+        self.transcripts = Some(transcripts);
+        self
+    }
+
+    /// Uploads recordings here instead of the store of the storage settings
+    pub fn recording_store(mut self, store: Arc<dyn RecordingStore>) -> Self {
+        self.recording_store = Some(store);
+        self
+    }
+
+    /// Submits chain events through `recorder`; without one the server only
+    /// gets an event queue through `set_event_queue`
+    pub fn chain_recorder(mut self, recorder: Arc<dyn ChainRecorder>) -> Self {
+        self.chain_recorder = Some(recorder);
+        self
+    }
+
+    pub fn build(self) -> Result<SfuServer, EngineConfigError> {
+        let engine_config = self.engine_config.unwrap_or_default();
+        let api = match self.api_factory {
+            Some(factory) => factory.build(&engine_config)?,
+            None => api_factory().build(&engine_config)?,
+        };
+
+        let recording_config = self.recording_config.unwrap_or_default();
+        let recording_store = self.recording_store.or_else(|| self.storage_config.as_ref().and_then(store::from_config));
+        let sfu_config = self.sfu_config.unwrap_or_default();
+        rtcp::init(sfu_config.rtcp.clone());
+
+        let mut server = SfuServer::with_api(api, recording_store, &recording_config, sfu_config, self.transcripts);
+        server.engine_config = engine_config;
+        server.webrtc_config = self.webrtc_config.unwrap_or_default();
+        if let Some(connections) = self.connections {
+            server.connections = connections;
+        }
+        if let Some(admission) = self.admission {
+            server.admission = admission;
+        }
+        if let Some(negotiation) = self.negotiation {
+            server.negotiation = negotiation;
+        }
+        if let Some(media_routing) = self.media_routing {
+            server.media_routing = media_routing;
+        }
+        if let Some(recorder) = self.chain_recorder {
+            server.event_queue = Some(EventQueue::new(recorder, None, &server.tasks));
+        }
+        Ok(server)
+    }
 }
 
 impl SfuServer {
+    /// Server configured from the environment.
+    ///
+    /// Panics if the WebRTC engine settings are invalid; use `builder` to handle that.
     pub fn new() -> Self {
-        use super::webrtc_utils;
-        let api = webrtc_utils::create_webrtc_api();
-
-        let (track_sender, track_receiver) = mpsc::unbounded_channel();
+        let engine_config =
+            WebRtcEngineConfig::from_env().unwrap_or_else(|e| panic!("Invalid WebRTC engine configuration: {}", e));
+        let mut builder = Self::builder()
+            .config(&Config::from_env())
+            .engine_config(engine_config)
+            .admission(Arc::new(PendingAdmissions::from_env()));
+        if let Some(transcripts) = transcript::service() {
+            builder = builder.transcripts(transcripts);
+        }
+        builder
+            .build()
+            .unwrap_or_else(|e| panic!("Invalid WebRTC engine configuration: {}", e))
+    }
 
-        let recording_output_dir = std::env::var("RECORDING_OUTPUT_DIR")
-            .unwrap_or_else(|_| "./recordings".to_string());
+    pub fn builder() -> SfuServerBuilder {
+        SfuServerBuilder::default()
+    }
 
-        let recording_enabled = std::env::var("RECORDING_ENABLED")
-            .unwrap_or_else(|_| "true".to_string())
-            .parse()
-            .unwrap_or(true);
+    fn with_api(
+        api: Arc<API>,
+        recording_store: Option<Arc<dyn RecordingStore>>,
+        recording_config: &RecordingConfig,
+        config: SfuConfig,
+        transcripts: Option<Arc<TranscriptService>>,
+    ) -> Self {
+        let (track_sender, track_receiver) = mpsc::unbounded_channel();
+        let (peer_state_sender, peer_state_receiver) = mpsc::unbounded_channel();
 
-        if recording_enabled {
-            tracing::info!("Recording enabled");
+        if recording_config.enabled {
+            tracing::info!(
+                keyframe_interval_secs = recording_config.keyframe_interval.as_secs(),
+                gap_incident_secs = recording_config.gap_threshold.as_secs(),
+                rtp_fallback = recording_config.rtp_fallback,
+                transcode = recording_config.transcode,
+                min_free_bytes = recording_config.min_free_bytes,
+                max_duration_secs = recording_config.max_duration.map(|max| max.as_secs()),
+                "Recording enabled"
+            );
         } else {
             tracing::info!("Recording disabled");
         }
 
-        // Initialize IPFS client if configured
-        let ipfs_client = IpfsConfig::from_env().and_then(|config| {
-            match IpfsClient::new(config) {
-                Ok(client) => {
-                    tracing::info!("IPFS client initialized");
-                    Some(Arc::new(client))
-                }
-                Err(e) => {
-                    tracing::error!(error = %e, "Failed to initialize IPFS client");
-                    None
-                }
-            }
-        });
-
         let server = Self {
             api,
-            connections: Arc::new(RwLock::new(HashMap::new())),
-            pending_students: Arc::new(RwLock::new(HashMap::new())),
+            connections: Arc::new(PeerConnections::new()),
+            admission: Arc::new(PendingAdmissions::default()),
             peer_wallets: Arc::new(RwLock::new(HashMap::new())),
             peer_exam_grades: Arc::new(RwLock::new(HashMap::new())),
             track_manager: Arc::new(TrackManager::new()),
-            room_manager: RoomManager::new(),
+            room_manager: RoomManager::with_limits(
+                config.affinity.room_id_prefix(),
+                config.admission_limits.max_rooms_per_proctor,
+            ),
             track_notification_sender: track_sender,
             track_notification_receiver: Arc::new(RwLock::new(Some(track_receiver))),
-            peers_with_tracks: Arc::new(RwLock::new(HashMap::new())),
-            pending_renegotiations: Arc::new(RwLock::new(HashMap::new())),
-            pending_ice_candidates: Arc::new(RwLock::new(HashMap::new())),
-            recording_manager: Arc::new(RecordingManager::new(&recording_output_dir, ipfs_client, recording_enabled)),
+            peer_state_sender,
+            peer_state_receiver: Arc::new(RwLock::new(Some(peer_state_receiver))),
+            disconnect_grace: config.disconnect_grace,
+            disconnected: std::sync::Mutex::new(HashMap::new()),
+            media_routing: Arc::new(TrackReadiness::new()),
+            negotiation: Arc::new(Negotiations::new()),
+            renegotiation: Arc::new(RenegotiationControl::new(config.renegotiation)),
+            room_state: RoomStateStreams::default(),
+            recording_manager: Arc::new(
+                RecordingManager::new(recording_config, recording_store).with_transcripts(transcripts),
+            ),
+            awaiting_upload: std::sync::Mutex::new(HashMap::new()),
+            engine_config: WebRtcEngineConfig::default(),
+            webrtc_config: WebRTCConfig::default(),
             event_queue: None,
+            admission_limits: config.admission_limits,
+            retry_policy: config.retry_policy,
+            join_escalation: config.join_escalation,
+            affinity: config.affinity,
+            room_sessions: Arc::new(RwLock::new(HashMap::new())),
+            transfers: Transfers::default(),
+            manifest_upload_wait: config.manifest_upload_wait,
+            close_min_recording_age: config.close_min_recording_age,
+            tasks: TaskSupervisor::new(),
+            task_shutdown_timeout: config.task_shutdown_timeout,
+            shutdown_timeout: config.shutdown_timeout,
+            started_at: std::time::Instant::now(),
         };
 
         server
     }
 
+    async fn room_session_wallet(&self, room_id: &str, peer_id: &str, wallet: Address) {
+        self.room_sessions
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .record_wallet(peer_id, format!("{:?}", wallet));
+    }
+
+    /// Publishes the room's manifest in the background, then records the close
+    /// on-chain with the manifest CID. Waits at most `manifest_upload_wait` for
+    /// recordings that are still uploading.
+    fn close_room_with_manifest(&self, room_id: String, reason: ChainRoomCloseReason, view_events_cid: Option<String>) {
+        let recording_manager = self.recording_manager.clone();
+        let room_sessions = self.room_sessions.clone();
+        let event_queue = self.event_queue.clone();
+        let upload_wait = self.manifest_upload_wait;
+
+        self.tasks.spawn("room_manifest", move |cancel| async move {
+            // Shutdown cuts the upload wait short; the manifest is still published, marked partial
+            tokio::select! {
+                _ = recording_manager.wait_for_uploads(&room_id, upload_wait) => {}
+                _ = cancel.cancelled() => {
+                    tracing::warn!(room_id = %room_id, "Shutting down, publishing manifest without waiting for uploads");
+                }
+            }
+
+            let session = room_sessions.write().await.remove(&room_id).unwrap_or_default();
+            let (manifest, manifest_cid) = recording_manager
+                .publish_manifest(&room_id, &session, Duration::ZERO, view_events_cid)
+                .await;
+            recording_manager.forget_completed(&room_id).await;
+
+            tracing::info!(
+                room_id = %room_id,
+                manifest_cid = ?manifest_cid,
+                recordings = manifest.recordings.len(),
+                complete = manifest.complete,
+                "Room manifest built"
+            );
+
+            if let Some(queue) = event_queue {
+                queue.emit(ChainEvent::RoomClosed {
+                    room_id,
+                    reason,
+                    manifest_cid,
+                });
+            }
+        });
+    }
+
+    /// Supervisor that background work owned by this server is spawned through
+    pub fn tasks(&self) -> &TaskSupervisor {
+        &self.tasks
+    }
+
+    pub fn recording_manager(&self) -> Arc<RecordingManager> {
+        self.recording_manager.clone()
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub async fn room_count(&self) -> usize {
+        self.room_manager.room_count().await
+    }
+
+    /// Peers in a room, proctors included
+    pub async fn peer_count(&self) -> usize {
+        self.room_manager.peer_count().await
+    }
+
+    pub async fn recording_count(&self) -> usize {
+        self.recording_manager.active_count().await
+    }
+
+    /// Probes IPFS and the Asset Hub RPC node, and reports whether GStreamer
+    /// initialized. The chain and GStreamer are required when configured;
+    /// failed uploads are retried, so IPFS is not.
+    pub async fn check_dependencies(&self) -> health::Dependencies {
+        let ipfs = async {
+            let Some(store) = self.recording_manager.store() else {
+                return health::DependencyStatus::disabled();
+            };
+            health::probe(false, async {
+                match store.health_check().await {
+                    Ok(true) => Ok(()),
+                    Ok(false) => Err(format!("{} is unreachable or unhealthy", store.location())),
+                    Err(e) => Err(e.to_string()),
+                }
+            })
+            .await
+        };
+        let asset_hub = async {
+            let Some(ref queue) = self.event_queue else {
+                return health::DependencyStatus::disabled();
+            };
+            health::probe(true, async { queue.recorder().chain_id().await.map(|_| ()).map_err(|e| e.to_string()) }).await
+        };
+        let (ipfs, asset_hub) = tokio::join!(ipfs, asset_hub);
+
+        let gstreamer = if self.recording_manager.is_enabled() {
+            health::DependencyStatus::from_result(true, RecordingPipeline::init_gstreamer().map_err(|e| e.to_string()))
+        } else {
+            health::DependencyStatus::disabled()
+        };
+
+        health::Dependencies { ipfs, asset_hub, gstreamer }
+    }
+
+    /// Starts the long-running tasks the server needs while it accepts peers
+    pub fn start_background_tasks(self: &Arc<Self>) {
+        self.clone().start_track_processing();
+        self.clone().start_pending_student_sweeper();
+        self.clone().start_recording_gap_sweeper();
+        self.clone().start_peer_state_monitor();
+        self.clone().start_recording_recovery();
+        self.clone().start_recording_uploads();
+        self.clone().start_recording_failures();
+        self.clone().start_recording_watchdog();
+    }
+
+    /// Tells every connected peer the server is going away, stops and
+    /// finalizes active recordings, and waits up to `SHUTDOWN_TIMEOUT_SECS`
+    /// for their uploads and the queued chain events. Then cancels every
+    /// background task and waits up to `TASK_SHUTDOWN_TIMEOUT_SECS` for them
+    /// to stop, aborting and reporting any that don't.
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let deadline = tokio::time::Instant::now() + self.shutdown_timeout;
+
+        for peer in self.connections.snapshot().into_keys() {
+            let message = SfuMessage::ServerShutdown { room_id: peer.room_id.clone() };
+            self.send_to_peer(&peer, &message).await;
+        }
+        self.stop_recordings_for_shutdown().await;
+
+        // The upload worker still runs, and emits RecordingStopped as each upload settles
+        let pending = self
+            .recording_manager
+            .wait_for_all_uploads(deadline.saturating_duration_since(tokio::time::Instant::now()))
+            .await;
+        if pending > 0 {
+            tracing::warn!(pending = pending, "Shutting down with recording uploads still in progress");
+        }
+        if let Some(queue) = &self.event_queue {
+            queue.close();
+            let queued = queue.flush(deadline.saturating_duration_since(tokio::time::Instant::now())).await;
+            if queued > 0 {
+                tracing::warn!(queued = queued, "Shutting down with chain events still queued");
+            }
+        }
+
+        let report = self.tasks.shutdown(self.task_shutdown_timeout).await;
+        if report.is_clean() {
+            tracing::info!(stopped = report.stopped, "Background tasks stopped");
+        } else {
+            tracing::warn!(
+                stopped = report.stopped,
+                panicked = ?report.panicked,
+                stragglers = ?report.stragglers,
+                "Background tasks did not shut down cleanly"
+            );
+        }
+        report
+    }
+
+    /// Stops every active recording; `stop_all_recordings` records each stop
+    /// on-chain like a room close does
+    async fn stop_recordings_for_shutdown(&self) {
+        for room_id in self.recording_manager.recording_rooms().await {
+            for (peer_id, result) in self.stop_all_recordings(&room_id).await {
+                tracing::info!(
+                    room_id = %room_id,
+                    peer_id = %peer_id,
+                    file = %result.file_path.display(),
+                    "Recording saved on shutdown"
+                );
+            }
+        }
+    }
+
     /// Sets the blockchain event queue for recording events on-chain
     pub fn set_event_queue(&mut self, queue: EventQueue) {
         self.event_queue = Some(queue);
@@ -125,40 +766,236 @@ impl SfuServer {
         }
     }
 
+    /// Records a stopped recording on-chain, right away when there is no
+    /// upload to wait for and otherwise once the upload settles
+    fn emit_recording_stopped(&self, room_id: &str, wallet: Address, result: &RecordingResult) {
+        if result.upload_pending {
+            self.awaiting_upload
+                .lock()
+                .unwrap()
+                .insert(result.file_path.clone(), (wallet, result.sha256.clone()));
+            return;
+        }
+        self.emit_chain_event(ChainEvent::RecordingStopped {
+            room_id: room_id.to_string(),
+            participant: wallet,
+            duration_secs: result.duration_secs,
+            ipfs_cid: result.cid.clone(),
+            file_hash: result.sha256.clone(),
+        });
+    }
+
+    pub fn admission_limits(&self) -> &AdmissionLimits {
+        &self.admission_limits
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// Fraction of the instance peer limit in use (0.0 when unlimited)
+    pub async fn utilization(&self) -> f64 {
+        match self.admission_limits.max_peers {
+            Some(max) => {
+                let peers = self.connections.count() + self.admission.pending_count();
+                peers as f64 / max as f64
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Decides whether `peer_id` may create or enter a room on this instance.
+    ///
+    /// Every rejection carries a retry hint from the shared `RetryPolicy`.
+    pub async fn check_admission(&self, room_id: Option<&str>, peer_id: &str) -> Result<(), Rejection> {
+        if health::monitor().is_draining() {
+            return Err(self.retry_policy.reject(
+                RejectReason::ServerDraining,
+                1.0,
+                "Server is shutting down",
+            ));
+        }
+
+        if let Some(max) = self.admission_limits.max_peers {
+            // An approved student joining is already counted as pending. Each
+            // room a proctor creates is another connection.
+            let already_counted = room_id.is_some_and(|room_id| {
+                self.connections.contains(&PeerKey::new(room_id, peer_id)) || self.admission.is_pending(room_id, peer_id)
+            });
+            let peers = self.connections.count() + self.admission.pending_count();
+
+            if !already_counted && peers >= max {
+                return Err(self.retry_policy.reject(
+                    RejectReason::CapacityExceeded,
+                    peers as f64 / max as f64,
+                    format!("Server is at capacity ({} peers)", max),
+                ));
+            }
+        }
+
+        if let (Some(room_id), Some(max)) = (room_id, self.admission_limits.max_room_peers) {
+            let room_peers = self.room_manager.get_room_peers(room_id).await;
+            if room_peers.len() >= max && !room_peers.iter().any(|p| p.id == peer_id) {
+                return Err(self.retry_policy.reject(
+                    RejectReason::RoomFull,
+                    self.utilization().await,
+                    format!("Room {} is full ({} peers)", room_id, max),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// This instance's identity, if running as one of several instances
+    pub fn instance(&self) -> Option<&InstanceInfo> {
+        self.affinity.instance()
+    }
+
+    /// How a client of `role` connects to this server, from the same ICE,
+    /// engine, admission and recording settings the server runs with
+    pub fn connection_recipe(&self, role: RecipeRole, client: ClientKind, keepalive: Keepalive) -> ConnectionRecipe {
+        let profile = DeploymentProfile {
+            ice_servers: get_ice_servers(&self.webrtc_config),
+            codecs: self.engine_config.codecs.clone(),
+            max_sdp_bytes: max_sdp_bytes(),
+            max_messages_per_sec: self.admission_limits.max_messages_per_sec,
+            max_room_peers: self.admission_limits.max_room_peers,
+            keepalive,
+            features: RecipeFeatures {
+                recording: self.recording_manager.is_enabled(),
+                ipfs_uploads: self.recording_manager.is_enabled() && self.recording_manager.uploads_to_ipfs(),
+                blockchain: self.event_queue.is_some(),
+                room_affinity: self.instance().is_some(),
+            },
+        };
+        ConnectionRecipe::build(&profile, role, client)
+    }
+
+    /// Returns the owning instance if `room_id` is hosted elsewhere
+    pub async fn find_remote_room(&self, room_id: &str) -> Option<InstanceInfo> {
+        let exists_locally = self.room_manager.room_exists(room_id).await;
+        match self.affinity.locate(room_id, exists_locally) {
+            RoomLocation::Remote(owner) => Some(owner),
+            RoomLocation::Local | RoomLocation::Unknown => None,
+        }
+    }
+
+    /// Records a change in what the proctor can see, if the peer is still in the room
+    pub async fn record_view_event(&self, peer: &PeerKey, event: ViewEventKind, details: serde_json::Value) {
+        if self.room_manager.get_peer(peer).await.is_some() {
+            self.recording_manager
+                .record_view_event(&peer.room_id, event, &peer.peer_id, details)
+                .await;
+        }
+    }
+
+    /// Applies a publisher's camera and microphone state to its recording,
+    /// so deliberately muted tracks are not reported as gaps
+    pub async fn set_media_state(&self, room_id: &str, peer_id: &str, has_video: bool, has_audio: bool) {
+        if self.room_manager.get_peer(&PeerKey::new(room_id, peer_id)).await.is_some() {
+            self.recording_manager
+                .set_media_state(room_id, peer_id, has_video, has_audio)
+                .await;
+        }
+    }
+
     pub fn start_track_processing(self: Arc<Self>) {
         let server = self.clone();
 
-        tokio::spawn(async move {
+        let heartbeat = health::monitor().register("track_processor", TRACK_PROCESSOR_MAX_SILENCE);
+
+        self.tasks.spawn("track_processor", move |cancel| async move {
             let receiver = {
                 let mut receiver_guard = server.track_notification_receiver.write().await;
                 receiver_guard.take()
             };
 
             if let Some(mut rx) = receiver {
-                while let Some((peer_id, track_id)) = rx.recv().await {
-                    if let Err(e) = server.handle_track_received(&peer_id, &track_id).await {
-                        tracing::error!(
-                            peer_id = %peer_id,
-                            track_id = %track_id,
-                            error = %e,
-                            "Error processing track notification"
-                        );
+                let mut tick = tokio::time::interval(health::HEARTBEAT_INTERVAL);
+                loop {
+                    tokio::select! {
+                        notification = rx.recv() => {
+                            let Some((source, track_id)) = notification else {
+                                break;
+                            };
+                            if let Err(e) = server.handle_track_received(&source, &track_id).await {
+                                tracing::error!(
+                                    peer = %source,
+                                    track_id = %track_id,
+                                    error = %e,
+                                    "Error processing track notification"
+                                );
+                            }
+                        }
+                        _ = tick.tick() => {}
+                        _ = cancel.cancelled() => break,
                     }
+                    heartbeat.beat();
                 }
             }
         });
     }
 
 
-    pub async fn create_room(&self, proctor_id: String, proctor_name: Option<String>, wallet_address: Option<String>) -> Result<String, String> {
-        let room_id = self.room_manager.create_room(proctor_id.clone(), proctor_name.clone()).await?;
+    pub async fn create_room(
+        &self,
+        proctor_id: String,
+        proctor_name: Option<String>,
+        wallet_address: Option<String>,
+        locale: RoomLocale,
+    ) -> Result<String, String> {
+        self.create_room_for_tenant(proctor_id, proctor_name, wallet_address, locale, None, None).await
+    }
+
+    /// Creates a room whose recordings, view events and manifest are kept in
+    /// `tenant`'s storage namespace. `None` keeps the untenanted layout.
+    /// `recording_enabled` opts the room out of recording (or back in), in
+    /// place of `RECORDING_ENABLED`; a server with recording disabled records
+    /// no room.
+    pub async fn create_room_for_tenant(
+        &self,
+        proctor_id: String,
+        proctor_name: Option<String>,
+        wallet_address: Option<String>,
+        locale: RoomLocale,
+        tenant: Option<String>,
+        recording_enabled: Option<bool>,
+    ) -> Result<String, String> {
+        let room_id = self
+            .room_manager
+            .create_room(proctor_id.clone(), proctor_name.clone(), locale)
+            .await?;
+        let records = recording_enabled.unwrap_or(self.recording_manager.is_enabled());
+        if records && !self.recording_manager.is_enabled() {
+            tracing::warn!(room_id = %room_id, "Room asked for recording, but recording is disabled on this server");
+        }
+        // Set before anyone joins, so no one is auto-recorded under the default
+        self.room_manager.set_recording_enabled(&room_id, records).await?;
+        metrics::metrics().rooms_created_total.inc();
+        self.affinity.on_room_created(&room_id);
+        self.recording_manager.set_room_tenant(&room_id, tenant.clone());
+        self.recording_manager.set_peer(&room_id, &proctor_id, proctor_name.clone(), PeerRole::Proctor);
+
+        let opened_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        {
+            let mut sessions = self.room_sessions.write().await;
+            let session = sessions.entry(room_id.clone()).or_default();
+            session.record_opened(opened_at);
+            session.set_tenant(tenant);
+        }
 
         // Store wallet address if provided
         let proctor_wallet = wallet_address.as_ref().and_then(|w| parse_address(w));
         if let Some(wallet) = proctor_wallet {
             let mut wallets = self.peer_wallets.write().await;
-            wallets.insert(proctor_id.clone(), wallet);
+            wallets.insert(PeerKey::new(room_id.clone(), proctor_id.clone()), wallet);
             tracing::info!(proctor_id = %proctor_id, wallet = %wallet, "Stored proctor wallet address");
+            drop(wallets);
+            self.room_session_wallet(&room_id, &proctor_id, wallet).await;
 
             // Emit chain event for room creation with wallet address
             self.emit_chain_event(ChainEvent::RoomCreated {
@@ -171,8 +1008,12 @@ impl SfuServer {
             tracing::debug!(proctor_id = %proctor_id, "No wallet address provided for proctor");
         }
 
+        self.recording_manager.open_view_log(&room_id, &proctor_id).await;
+
         // Auto-start recording for the proctor when room is created
-        if let Err(e) = self.recording_manager.start_recording(&room_id, &proctor_id).await {
+        if !self.records_room(&room_id).await {
+            tracing::info!(room_id = %room_id, proctor_id = %proctor_id, "Room is not recorded, not recording proctor");
+        } else if let Err(e) = self.recording_manager.start_recording(&room_id, &proctor_id, &self.engine_config.recording_codecs()).await {
             tracing::error!(
                 room_id = %room_id,
                 proctor_id = %proctor_id,
@@ -185,6 +1026,7 @@ impl SfuServer {
                 proctor_id = %proctor_id,
                 "Auto-started recording for proctor"
             );
+            self.room_manager.record_event(&room_id, Some(&proctor_id), RoomEvent::Recording(true)).await;
 
             // Emit chain event for recording started (only if wallet is available)
             if let Some(wallet) = proctor_wallet {
@@ -215,12 +1057,20 @@ impl SfuServer {
             ChainRole::Student
         };
 
+        // A roster entry is the institution's binding and takes precedence
+        // over whatever wallet the client sent
+        let roster_entry = if role == "student" {
+            self.room_manager.get_roster_entry(&room_id, &peer_id).await
+        } else {
+            None
+        };
+        let name = name.or_else(|| roster_entry.as_ref().and_then(|entry| entry.name.clone()));
+
         // For students, try to get wallet from pending_students if not provided
         let effective_wallet = if wallet_address.is_some() {
             wallet_address
         } else if role == "student" {
-            let pending = self.pending_students.read().await;
-            let wallet = pending.get(&peer_id).and_then(|p| p.wallet_address.clone());
+            let wallet = self.admission.pending_wallet(&room_id, &peer_id);
             if wallet.is_some() {
                 tracing::info!(peer_id = %peer_id, "Retrieved wallet from pending student");
             }
@@ -229,17 +1079,28 @@ impl SfuServer {
             None
         };
 
-        // Clean up pending student entry now that they're joining
-        if role == "student" {
-            self.remove_pending_student(&peer_id).await;
-        }
-
         // Store wallet address if provided
-        let participant_wallet = effective_wallet.as_ref().and_then(|w| parse_address(w));
+        let claimed_wallet = effective_wallet.as_ref().and_then(|w| parse_address(w));
+        let participant_wallet = match roster_entry {
+            Some(entry) => {
+                if claimed_wallet.is_some_and(|claimed| claimed != entry.wallet) {
+                    tracing::warn!(
+                        peer_id = %peer_id,
+                        room_id = %room_id,
+                        "Ignoring client wallet that differs from the roster entry"
+                    );
+                }
+                tracing::info!(peer_id = %peer_id, wallet = %entry.wallet, "Bound pre-registered student to roster wallet");
+                Some(entry.wallet)
+            }
+            None => claimed_wallet,
+        };
         if let Some(wallet) = participant_wallet {
             let mut wallets = self.peer_wallets.write().await;
-            wallets.insert(peer_id.clone(), wallet);
+            wallets.insert(PeerKey::new(room_id.clone(), peer_id.clone()), wallet);
             tracing::info!(peer_id = %peer_id, wallet = %wallet, "Stored participant wallet address");
+            drop(wallets);
+            self.room_session_wallet(&room_id, &peer_id, wallet).await;
         }
 
         if role == "student" {
@@ -267,6 +1128,16 @@ impl SfuServer {
             }
 
             self.room_manager.join_room(room_id.clone(), peer_id.clone(), name.clone()).await?;
+            self.recording_manager.set_peer(&room_id, &peer_id, name.clone(), PeerRole::Student);
+            self.room_sessions
+                .write()
+                .await
+                .entry(room_id.clone())
+                .or_default()
+                .record_student(&peer_id);
+            self.recording_manager
+                .record_view_event(&room_id, ViewEventKind::Joined, &peer_id, serde_json::json!({ "role": "student" }))
+                .await;
 
             // Emit chain event for participant joined (only if wallet is available)
             if let Some(wallet) = participant_wallet {
@@ -275,11 +1146,14 @@ impl SfuServer {
                     participant: wallet,
                     name: name.clone(),
                     role: chain_role,
+                    details: None,
                 });
             }
 
             // Auto-start recording for the student when they join
-            if let Err(e) = self.recording_manager.start_recording(&room_id, &peer_id).await {
+            if !self.records_room(&room_id).await {
+                tracing::debug!(room_id = %room_id, peer_id = %peer_id, "Room is not recorded, not recording student");
+            } else if let Err(e) = self.recording_manager.start_recording(&room_id, &peer_id, &self.engine_config.recording_codecs()).await {
                 tracing::error!(
                     room_id = %room_id,
                     peer_id = %peer_id,
@@ -292,6 +1166,7 @@ impl SfuServer {
                     peer_id = %peer_id,
                     "Auto-started recording for student"
                 );
+                self.room_manager.record_event(&room_id, Some(&peer_id), RoomEvent::Recording(true)).await;
 
                 // Emit chain event for recording started (only if wallet is available)
                 if let Some(wallet) = participant_wallet {
@@ -314,32 +1189,60 @@ impl SfuServer {
         room_id: String,
         sender: mpsc::UnboundedSender<Message>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Check if peer already has an active connection to prevent duplicate joins
-        {
-            let connections = self.connections.read().await;
-            if connections.contains_key(&peer_id) {
-                tracing::warn!(peer_id = %peer_id, "Peer already connected, ignoring duplicate join");
-                return Ok(());
-            }
+        // A peer may be in several rooms, but has one connection in each
+        let key = PeerKey::new(room_id.clone(), peer_id.clone());
+        if !self.connections.begin_setup(&key) {
+            tracing::warn!(peer_id = %peer_id, room_id = %room_id, "Peer already connected to room, ignoring duplicate join");
+            return Ok(());
         }
 
         tracing::info!(peer_id = %peer_id, room_id = %room_id, "Adding peer to SFU");
 
-        // Create SFU connection
-        let connection = Arc::new(
-            SfuConnection::new(
-                peer_id.clone(),
-                room_id.clone(),
-                sender,
-                &self.api,
-                self.track_manager.clone(),
-                Some(self.track_notification_sender.clone()),
-                Some(self.recording_manager.clone()),
+        // Answers and candidates arriving until the offer is out are held, then replayed in order
+        let set_up = self.set_up_connection(&key, sender).await;
+        let held = self.connections.finish_setup(&key);
+        if let Err(e) = set_up {
+            if !held.is_empty() {
+                tracing::debug!(peer = %key, count = held.len(), "Dropping signaling held for failed setup");
+            }
+            return Err(e);
+        }
+        self.replay_held_signals(&key, held).await;
+
+        tracing::info!(peer_id = %peer_id, "Peer added to SFU successfully");
+        Ok(())
+    }
+
+    /// Creates the peer's connection, registers it and sends the first offer
+    async fn set_up_connection(
+        &self,
+        key: &PeerKey,
+        sender: mpsc::UnboundedSender<Message>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let PeerKey { room_id, peer_id } = key.clone();
+
+        // Create SFU connection
+        let connection = Arc::new(
+            SfuConnection::new(
+                peer_id.clone(),
+                room_id.clone(),
+                sender,
+                &self.api,
+                &self.webrtc_config,
+                self.track_manager.clone(),
+                Some(self.track_notification_sender.clone()),
+                Some(self.peer_state_sender.clone()),
+                Some(self.recording_manager.clone()),
             )
                 .await?,
         );
 
-        let existing_tracks = self.get_tracks_for_peer(&peer_id, &room_id).await;
+        let existing_tracks: Vec<String> = self
+            .get_tracks_for_peer(key)
+            .await
+            .into_iter()
+            .map(|track| track.track_id)
+            .collect();
         if !existing_tracks.is_empty() {
             tracing::info!(
                 peer_id = %peer_id,
@@ -347,10 +1250,7 @@ impl SfuServer {
                 "Adding existing tracks to peer"
             );
             // Get current connections for PLI sending
-            let connections = self.connections.read().await;
-            let connections_map: std::collections::HashMap<String, Arc<SfuConnection>> =
-                connections.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-            drop(connections);
+            let connections_map = self.connections.snapshot();
 
             connection
                 .add_existing_tracks(self.track_manager.clone(), existing_tracks, &connections_map)
@@ -359,58 +1259,134 @@ impl SfuServer {
             tracing::debug!(peer_id = %peer_id, "No existing tracks to add to peer");
         }
 
-        {
-            let mut connections = self.connections.write().await;
-            connections.insert(peer_id.clone(), connection.clone());
+        // The join request is settled; candidates it buffered wait for the answer like any other
+        self.queue_early_ice_candidates(key).await;
+
+        // Registered before the offer goes out, so the answer always finds the connection
+        self.connections.insert(key.clone(), connection.clone());
+
+        // First, so a refreshed client rebuilds its UI before any other traffic
+        self.send_state_sync(key).await;
+        // Ahead of the offer, so the client can place tiles as the tracks arrive
+        self.send_room_state(key).await;
+        negotiation::send_offer(&connection).await
+    }
+
+    /// Applies signaling held while the peer's connection was set up
+    async fn replay_held_signals(&self, peer: &PeerKey, held: Vec<HeldSignal>) {
+        if held.is_empty() {
+            return;
+        }
+        tracing::info!(peer = %peer, count = held.len(), "Replaying signaling received during connection setup");
+
+        for signal in held {
+            let replayed = match signal {
+                HeldSignal::Answer { sdp } => self.handle_answer(&peer.room_id, &peer.peer_id, &sdp).await,
+                HeldSignal::IceCandidate(candidate) => {
+                    self.handle_ice_candidate(
+                        &peer.room_id,
+                        &peer.peer_id,
+                        &candidate.candidate,
+                        candidate.sdp_mid,
+                        candidate.sdp_mline_index,
+                    )
+                    .await
+                }
+            };
+            if let Err(e) = replayed {
+                tracing::warn!(peer = %peer, error = %e, "Failed to replay held signaling");
+            }
+        }
+    }
+
+    /// Holds a message from a peer whose connection setup is in progress. True
+    /// when the registry took it (or dropped it for a full buffer), so the
+    /// caller must not apply it now.
+    fn hold_during_setup(&self, peer: &PeerKey, signal: HeldSignal) -> bool {
+        match self.connections.hold(peer, signal) {
+            Ok(count) => {
+                tracing::debug!(peer = %peer, held = count, "Holding signaling until connection setup completes");
+                true
+            }
+            Err(HoldError::Full { max }) => {
+                tracing::warn!(peer = %peer, max = max, "Signaling buffer for connection setup full, dropping message");
+                true
+            }
+            Err(HoldError::NotSettingUp) => false,
+        }
+    }
+
+    /// Ends a student's pending request and moves the ICE candidates it
+    /// buffered into the queue flushed once the remote description is set
+    async fn queue_early_ice_candidates(&self, peer: &PeerKey) {
+        // Candidates gathered while asking about another room belong to another session
+        let candidates = self.admission.settle(&peer.room_id, &peer.peer_id);
+        if !candidates.is_empty() {
+            tracing::info!(
+                peer = %peer,
+                count = candidates.len(),
+                "Replaying ICE candidates received before approval"
+            );
         }
 
-        self.create_and_send_offer(&peer_id).await?;
+        // Opens the queue even with nothing buffered, so candidates arriving
+        // before the connection is registered are queued rather than dropped
+        self.negotiation.open_ice_queue(peer, candidates);
+    }
 
-        tracing::info!(peer_id = %peer_id, "Peer added to SFU successfully");
-        Ok(())
+    /// Removes a peer from `room_id` for `cause`, which decides the `LeaveReason`
+    /// on-chain. Rooms the peer is in besides this one are unaffected.
+    pub async fn remove_peer(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        cause: DisconnectCause,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.remove_peer_closing_as(room_id, peer_id, cause, ChainRoomCloseReason::ProctorLeft).await
     }
 
-    pub async fn remove_peer(&self, peer_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        tracing::info!(peer_id = %peer_id, "Removing peer from SFU");
+    /// `remove_peer`, recording `close_reason` on-chain if the peer is the
+    /// proctor and its departure closes the room
+    async fn remove_peer_closing_as(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        cause: DisconnectCause,
+        close_reason: ChainRoomCloseReason,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let key = PeerKey::new(room_id, peer_id);
+        tracing::info!(peer_id = %peer_id, room_id = %room_id, cause = cause.as_str(), "Removing peer from SFU");
 
         // Remove peer from room manager (this handles room closure if proctor leaves)
-        let room_info = self.room_manager.remove_peer(peer_id).await;
+        let departed = self.room_manager.remove_peer(&key, cause).await;
 
         // Remove connection
-        let connection = {
-            let mut connections = self.connections.write().await;
-            connections.remove(peer_id)
-        };
-
-        if let Some(connection) = connection {
+        if let Some(connection) = self.connections.remove(&key) {
             connection.close().await;
         }
 
-        // Remove tracks from this peer
-        self.track_manager.remove_peer_tracks(peer_id).await;
-
-        // Clean up pending ICE candidates
-        {
-            let mut pending_ice = self.pending_ice_candidates.write().await;
-            if pending_ice.remove(peer_id).is_some() {
-                tracing::debug!(peer_id = %peer_id, "Removed pending ICE candidates");
-            }
-        }
+        // Remove tracks from this peer, and the tracks forwarded to it
+        self.track_manager.remove_peer_tracks(&key).await;
+        self.track_manager.remove_subscriber(&key).await;
+        // A rejoin reports its encryption again in MediaReady
+        self.recording_manager.set_e2ee(room_id, peer_id, false).await;
+        self.media_routing.forget(&key);
 
-        // Clean up pending renegotiations
-        {
-            let mut pending_renego = self.pending_renegotiations.write().await;
-            if pending_renego.remove(peer_id).is_some() {
-                tracing::debug!(peer_id = %peer_id, "Removed pending renegotiation");
-            }
-        }
+        // Clean up pending ICE candidates, renegotiations and transfer requests
+        self.negotiation.forget(&key);
+        self.renegotiation.forget(&key);
+        self.room_state.forget(&key);
+        self.transfers.forget(&key);
 
         // Handle recording cleanup and room closure
-        if let Some((room_id, role, peer_name)) = room_info {
+        if let Some(departed) = departed {
+            self.record_departures(&departed).await;
+            let DepartedPeer { room_id, role, name: peer_name, displaced, .. } = departed;
+
             // Get wallet address for this peer
             let peer_wallet = {
                 let wallets = self.peer_wallets.read().await;
-                wallets.get(peer_id).copied()
+                wallets.get(&key).copied()
             };
 
             if matches!(role, PeerRole::Proctor) {
@@ -428,109 +1404,103 @@ impl SfuServer {
                         peer_id = %stopped_peer_id,
                         file = %result.file_path.display(),
                         cid = ?result.cid,
+                        keyframes = result.keyframe_stats.keyframe_count,
+                        keyframe_requests = result.keyframe_stats.pli_requests,
                         "Recording saved on room close"
                     );
 
                     // Emit chain event for recording stopped (only if wallet available)
                     let stopped_wallet = {
                         let wallets = self.peer_wallets.read().await;
-                        wallets.get(stopped_peer_id).copied()
+                        wallets.get(&PeerKey::new(room_id.as_str(), stopped_peer_id.as_str())).copied()
                     };
                     if let Some(wallet) = stopped_wallet {
-                        self.emit_chain_event(ChainEvent::RecordingStopped {
-                            room_id: room_id.clone(),
-                            participant: wallet,
-                            duration_secs: 0, // Duration not tracked currently
-                            ipfs_cid: result.cid.clone(),
-                        });
+                        self.emit_recording_stopped(&room_id, wallet, result);
                     }
                 }
 
+                let view_events_cid = match self.recording_manager.close_view_log(&room_id).await {
+                    Some(view_events) => {
+                        tracing::info!(
+                            room_id = %room_id,
+                            file = %view_events.file_path.display(),
+                            cid = ?view_events.cid,
+                            "View events saved on room close"
+                        );
+                        view_events.cid
+                    }
+                    None => None,
+                };
+
                 // Emit chain event for proctor leaving (only if wallet available)
                 if let Some(wallet) = peer_wallet {
                     self.emit_chain_event(ChainEvent::ParticipantLeft {
                         room_id: room_id.clone(),
                         participant: wallet,
-                        reason: ChainLeaveReason::Normal,
+                        reason: chain_leave_reason(cause),
+                        details: None,
                     });
                 }
 
-                // Get all student connections to close
-                let students_to_close: Vec<String> = self.room_manager.get_room_peers(&room_id).await
-                    .into_iter()
-                    .filter(|p| p.id != peer_id)
-                    .map(|p| p.id)
-                    .collect();
-
                 // Emit chain events for students being forced to leave
-                for student_id in &students_to_close {
+                for student in &displaced {
                     let student_wallet = {
                         let wallets = self.peer_wallets.read().await;
-                        wallets.get(student_id).copied()
+                        wallets.get(&student.key()).copied()
                     };
                     if let Some(wallet) = student_wallet {
                         self.emit_chain_event(ChainEvent::ParticipantLeft {
                             room_id: room_id.clone(),
                             participant: wallet,
-                            reason: ChainLeaveReason::RoomClosed,
+                            reason: chain_leave_reason(student.cause),
+                            details: None,
                         });
                     }
                 }
 
-                // Emit chain event for room closed
-                self.emit_chain_event(ChainEvent::RoomClosed {
-                    room_id: room_id.clone(),
-                    reason: ChainRoomCloseReason::ProctorLeft,
-                });
+                self.affinity.on_room_closed(&room_id);
+
+                // RoomClosed carries the manifest, so it goes out once uploads settle
+                self.close_room_with_manifest(room_id.clone(), close_reason, view_events_cid);
 
                 // Close all student connections and clean up their wallet mappings
-                for student_id in students_to_close {
-                    self.close_peer_connection(&student_id).await;
-                    let mut wallets = self.peer_wallets.write().await;
-                    wallets.remove(&student_id);
+                for student in displaced {
+                    let student = student.key();
+                    self.close_peer_connection(&student).await;
+                    self.peer_wallets.write().await.remove(&student);
+                    self.peer_exam_grades.write().await.remove(&student);
                 }
             } else {
                 // Student left - get their exam grade (if submitted)
-                let exam_grade = self.get_exam_grade(peer_id).await;
+                let exam_grade = self.get_exam_grade(&room_id, peer_id).await;
 
                 // Stop their recording
                 if let Ok(result) = self.recording_manager.stop_recording(&room_id, peer_id).await {
+                    self.room_manager.record_event(&room_id, Some(peer_id), RoomEvent::Recording(false)).await;
                     // Emit chain events (only if wallet available)
                     if let Some(wallet) = peer_wallet {
-                        // Get grade and exam name from submitted result, or use defaults
-                        let (grade, exam_name) = match &exam_grade {
-                            Some(eg) => (eg.grade, eg.exam_name.clone()),
-                            None => (0, format!("Exam Session {}", room_id)),
-                        };
-
-                        tracing::info!(
-                            peer_id = %peer_id,
-                            grade = grade,
-                            exam_name = %exam_name,
-                            "Creating exam result with grade"
-                        );
+                        let metadata = self.session_metadata(&room_id).await;
+                        let event = exam_result_event(&room_id, wallet, exam_grade.as_ref(), &metadata);
+                        if let ChainEvent::CreateExamResult { grade, ref exam_name, .. } = event {
+                            tracing::info!(
+                                peer_id = %peer_id,
+                                grade = grade,
+                                exam_name = %exam_name,
+                                "Creating exam result with grade"
+                            );
+                        }
 
                         // IMPORTANT: CreateExamResult must be emitted BEFORE RecordingStopped
                         // so the contract can link the recording CID to the exam result
-                        self.emit_chain_event(ChainEvent::CreateExamResult {
-                            room_id: room_id.clone(),
-                            participant: wallet,
-                            grade,
-                            exam_name,
-                        });
+                        self.emit_chain_event(event);
 
-                        // Now emit RecordingStopped - the contract will add the CID to the exam result
-                        self.emit_chain_event(ChainEvent::RecordingStopped {
-                            room_id: room_id.clone(),
-                            participant: wallet,
-                            duration_secs: 0,
-                            ipfs_cid: result.cid.clone(),
-                        });
+                        // Now (or once uploaded) RecordingStopped - the contract will add the CID to the exam result
+                        self.emit_recording_stopped(&room_id, wallet, &result);
                     }
                 }
 
                 // Clean up exam grade
-                self.remove_exam_grade(peer_id).await;
+                self.remove_exam_grade(&room_id, peer_id).await;
 
                 tracing::info!(
                     room_id = %room_id,
@@ -543,107 +1513,181 @@ impl SfuServer {
                     self.emit_chain_event(ChainEvent::ParticipantLeft {
                         room_id: room_id.clone(),
                         participant: wallet,
-                        reason: ChainLeaveReason::Normal,
+                        reason: chain_leave_reason(cause),
+                        details: None,
                     });
                 }
 
+                self.recording_manager
+                    .record_view_event(
+                        &room_id,
+                        ViewEventKind::Unsubscribed,
+                        peer_id,
+                        serde_json::json!({ "reason": cause.as_str() }),
+                    )
+                    .await;
+
                 // Notify proctor about participant leaving
-                self.update_all_connections_for_peer_removal(peer_id, &room_id, peer_name).await?;
+                self.update_all_connections_for_peer_removal(peer_id, &room_id, peer_name, cause).await?;
             }
 
             // Clean up wallet mapping for this peer
             let mut wallets = self.peer_wallets.write().await;
-            wallets.remove(peer_id);
+            wallets.remove(&key);
         }
 
-        tracing::info!(peer_id = %peer_id, "Peer removed from SFU successfully");
+        tracing::info!(peer_id = %peer_id, room_id = %room_id, "Peer removed from SFU successfully");
         Ok(())
     }
 
 
-    async fn close_peer_connection(&self, peer_id: &str) {
-        tracing::info!(peer_id = %peer_id, "Closing peer connection");
-
-        // Remove connection
-        let connection = {
-            let mut connections = self.connections.write().await;
-            connections.remove(peer_id)
-        };
+    /// Adds a departure, and the departures it caused, to the room's session summary
+    async fn record_departures(&self, departed: &DepartedPeer) {
+        let at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        {
+            let mut sessions = self.room_sessions.write().await;
+            let session = sessions.entry(departed.room_id.clone()).or_default();
+            for peer in std::iter::once(departed).chain(&departed.displaced) {
+                session.record_departure(&peer.id, peer.cause.as_str(), at_ms);
+            }
+        }
 
-        if let Some(connection) = connection {
-            connection.close().await;
+        // A lost connection counts against the student if it comes back
+        if departed.cause == DisconnectCause::ConnectionLost {
+            self.refresh_integrity_score(&departed.room_id, &departed.id).await;
         }
+    }
 
-        // Remove tracks from this peer
-        self.track_manager.remove_peer_tracks(peer_id).await;
+    /// Current integrity score of a student in the room, `None` for anyone else
+    pub async fn integrity_score(&self, room_id: &str, peer_id: &str) -> Option<IntegrityScore> {
+        let sessions = self.room_sessions.read().await;
+        let session = sessions.get(room_id).filter(|session| session.is_student(peer_id))?;
+        Some(integrity::score(&session.integrity_inputs(peer_id), integrity::weights()))
+    }
+
+    /// Integrity scores of every student who joined the room, lowest first
+    pub async fn integrity_scores(&self, room_id: &str) -> Vec<(String, IntegrityScore)> {
+        let sessions = self.room_sessions.read().await;
+        let Some(session) = sessions.get(room_id) else {
+            return Vec::new();
+        };
+        let mut scores: Vec<_> = session
+            .students()
+            .map(|peer_id| {
+                let score = integrity::score(&session.integrity_inputs(peer_id), integrity::weights());
+                (peer_id.to_string(), score)
+            })
+            .collect();
+        scores.sort_by_key(|(_, score)| score.score);
+        scores
     }
 
+    /// Score of every student who joined the room, by peer ID
+    async fn integrity_score_table(&self, room_id: &str) -> BTreeMap<String, u32> {
+        self.integrity_scores(room_id).await.into_iter().map(|(peer_id, scored)| (peer_id, scored.score)).collect()
+    }
 
-    async fn create_and_send_offer(&self, peer_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connection = {
-            let connections = self.connections.read().await;
-            connections.get(peer_id).cloned()
+    /// Recomputes a student's score after something that contributes to it
+    /// and tells the room's proctor
+    async fn refresh_integrity_score(&self, room_id: &str, peer_id: &str) {
+        let Some(scored) = self.integrity_score(room_id, peer_id).await else {
+            return;
         };
+        tracing::debug!(room_id = %room_id, peer_id = %peer_id, score = scored.score, "Integrity score updated");
 
-        if let Some(connection) = connection {
-            let offer = connection.peer_connection.create_offer(None).await?;
-            connection.peer_connection.set_local_description(offer.clone()).await?;
+        let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await else {
+            return;
+        };
+        let message = SfuMessage::IntegrityScoreUpdated {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            score: scored.score,
+        };
+        let proctor = PeerKey::new(room_id, proctor_id);
+        if let (Some(connection), Ok(text)) = (self.connections.get(&proctor), serde_json::to_string(&message)) {
+            let _ = connection.send_message(Message::text(text)).await;
+        }
+    }
 
-            let offer_message = serde_json::to_string(&serde_json::json!({
-                "type": "offer",
-                "sdp": offer.sdp,
-                "peer_id": "sfu"
-            }))?;
+    async fn close_peer_connection(&self, peer: &PeerKey) {
+        tracing::info!(peer = %peer, "Closing peer connection");
 
-            connection.send_message(Message::text(offer_message)).await?;
-            tracing::info!(peer_id = %peer_id, "Sent SFU offer to peer");
+        // Remove connection
+        if let Some(connection) = self.connections.remove(peer) {
+            connection.close().await;
         }
 
-        Ok(())
+        // Remove tracks from this peer
+        self.track_manager.remove_peer_tracks(peer).await;
+        self.recording_manager.set_e2ee(&peer.room_id, &peer.peer_id, false).await;
+        self.media_routing.forget(peer);
+        self.negotiation.forget(peer);
+        self.renegotiation.forget(peer);
+        self.room_state.forget(peer);
     }
 
 
     pub async fn handle_answer(
         &self,
+        room_id: &str,
         peer_id: &str,
         sdp: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connection = {
-            let connections = self.connections.read().await;
-            connections.get(peer_id).cloned()
-        };
-
-        if let Some(connection) = connection {
+        let key = PeerKey::new(room_id, peer_id);
+        if self.hold_during_setup(&key, HeldSignal::Answer { sdp: sdp.to_string() }) {
+            return Ok(());
+        }
+        if let Some(connection) = self.connections.get(&key) {
             use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 
             let answer = RTCSessionDescription::answer(sdp.to_string())
                 .map_err(|e| SfuError::InvalidSdp(format!("Failed to parse answer SDP: {}", e)))?;
             connection.peer_connection.set_remote_description(answer).await?;
             tracing::info!(peer_id = %peer_id, "Processed answer from peer");
+            if let Some(elapsed) = self.renegotiation.answered(&key) {
+                tracing::debug!(peer_id = %peer_id, elapsed_ms = elapsed.as_millis() as u64, "Renegotiation answered");
+            }
 
             // Flush any queued ICE candidates now that remote description is set
-            self.flush_pending_ice_candidates(peer_id, &connection).await?;
+            self.flush_pending_ice_candidates(&key, &connection).await?;
 
             tracing::debug!(peer_id = %peer_id, "Waiting for tracks from peer");
+        } else {
+            tracing::debug!(peer_id = %peer_id, room_id = %room_id, "No connection for answer, dropping");
+        }
+
+        Ok(())
+    }
+
+    /// Answers an offer the peer sent to add or change its own media
+    pub async fn handle_client_offer(&self, room_id: &str, peer_id: &str, sdp: &str) -> Result<(), ClientOfferError> {
+        let key = PeerKey::new(room_id, peer_id);
+        // The SFU's first offer is on its way; the peer answers it before offering
+        if self.connections.is_setting_up(&key) {
+            return Err(ClientOfferError::Glare);
         }
+        let connection = self.connections.get(&key).ok_or(ClientOfferError::NoConnection)?;
+        negotiation::answer_client_offer(&connection, sdp).await?;
 
+        if let Err(e) = self.flush_pending_ice_candidates(&key, &connection).await {
+            tracing::warn!(peer_id = %peer_id, error = %e, "Failed to flush ICE candidates after client offer");
+        }
         Ok(())
     }
 
     /// Flush any queued ICE candidates after remote description is set
     async fn flush_pending_ice_candidates(
         &self,
-        peer_id: &str,
+        peer: &PeerKey,
         connection: &Arc<SfuConnection>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let candidates = {
-            let mut pending = self.pending_ice_candidates.write().await;
-            pending.remove(peer_id)
-        };
-
-        if let Some(candidates) = candidates {
+        let candidates = self.negotiation.take_ice_candidates(peer);
+        if !candidates.is_empty() {
             tracing::info!(
-                peer_id = %peer_id,
+                peer = %peer,
                 count = candidates.len(),
                 "Flushing queued ICE candidates"
             );
@@ -658,12 +1702,12 @@ impl SfuServer {
 
                 if let Err(e) = connection.peer_connection.add_ice_candidate(ice_candidate).await {
                     tracing::error!(
-                        peer_id = %peer_id,
+                        peer = %peer,
                         error = %e,
                         "Failed to add queued ICE candidate"
                     );
                 } else {
-                    tracing::debug!(peer_id = %peer_id, "Added queued ICE candidate");
+                    tracing::trace!(peer = %peer, "Added queued ICE candidate");
                 }
             }
         }
@@ -674,81 +1718,234 @@ impl SfuServer {
 
     pub async fn handle_ice_candidate(
         &self,
+        room_id: &str,
         peer_id: &str,
         candidate: &str,
         sdp_mid: Option<String>,
         sdp_mline_index: Option<u16>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connection = {
-            let connections = self.connections.read().await;
-            connections.get(peer_id).cloned()
+        let key = PeerKey::new(room_id, peer_id);
+        let pending = PendingIceCandidate {
+            candidate: candidate.to_string(),
+            sdp_mid,
+            sdp_mline_index,
         };
-
-        if let Some(connection) = connection {
+        if self.hold_during_setup(&key, HeldSignal::IceCandidate(pending.clone())) {
+            return Ok(());
+        }
+        if let Some(connection) = self.connections.get(&key) {
             // Check if remote description is set
             if connection.peer_connection.remote_description().await.is_none() {
-                tracing::debug!(
+                tracing::trace!(
                     peer_id = %peer_id,
                     "Queueing ICE candidate until remote description is set"
                 );
 
                 // Queue the candidate
-                let mut pending = self.pending_ice_candidates.write().await;
-                pending.entry(peer_id.to_string())
-                    .or_insert_with(Vec::new)
-                    .push(PendingIceCandidate {
-                        candidate: candidate.to_string(),
-                        sdp_mid,
-                        sdp_mline_index,
-                    });
+                self.negotiation.queue_ice_candidate(&key, pending);
 
-                tracing::debug!(
+                tracing::trace!(
                     peer_id = %peer_id,
-                    queue_size = pending.get(peer_id).map(|v| v.len()).unwrap_or(0),
+                    queue_size = self.negotiation.queued_ice_candidates(&key).unwrap_or(0),
                     "ICE candidate queued"
                 );
                 return Ok(());
             }
 
-            tracing::debug!(peer_id = %peer_id, "Receiving ICE candidate from peer");
+            tracing::trace!(peer_id = %peer_id, "Receiving ICE candidate from peer");
 
             let ice_candidate = RTCIceCandidateInit {
-                candidate: candidate.to_string(),
-                sdp_mid,
-                sdp_mline_index,
+                candidate: pending.candidate,
+                sdp_mid: pending.sdp_mid,
+                sdp_mline_index: pending.sdp_mline_index,
                 username_fragment: None,
             };
 
             connection.peer_connection.add_ice_candidate(ice_candidate).await?;
-            tracing::debug!(peer_id = %peer_id, "Added ICE candidate from peer");
+            tracing::trace!(peer_id = %peer_id, "Added ICE candidate from peer");
+        } else {
+            self.buffer_early_ice_candidate(&key, pending).await;
         }
 
         Ok(())
     }
 
+    /// Holds a candidate from a peer without a connection yet: a student awaiting
+    /// approval, or one whose connection is still being set up
+    async fn buffer_early_ice_candidate(&self, peer: &PeerKey, candidate: PendingIceCandidate) {
+        match self.admission.buffer_ice_candidate(&peer.room_id, &peer.peer_id, candidate.clone()) {
+            Ok(count) => {
+                tracing::trace!(peer = %peer, buffered = count, "Buffered ICE candidate from pending student");
+            }
+            Err(IceBufferError::Full { max }) => {
+                tracing::warn!(peer = %peer, max = max, "Pending student ICE buffer full, dropping candidate");
+            }
+            Err(IceBufferError::NotPending) => {
+                if self.negotiation.queue_if_open(peer, candidate) {
+                    tracing::trace!(peer = %peer, "Queued ICE candidate for joining peer");
+                } else {
+                    tracing::debug!(peer = %peer, "No connection or pending request for ICE candidate, dropping");
+                }
+            }
+        }
+    }
+
 
-    async fn get_tracks_for_peer(&self, peer_id: &str, room_id: &str) -> Vec<String> {
-        let mut tracks_to_forward = Vec::new();
+    /// Tracks `peer` should receive in its room, in the order from `order_tracks`
+    async fn get_tracks_for_peer(&self, peer: &PeerKey) -> Vec<TrackOrderEntry> {
+        let join_order = self.room_manager.join_order(&peer.room_id).await;
 
-        let room_peers = self.room_manager.get_room_peers(room_id).await;
+        let mut sources = Vec::new();
+        for source_peer_id in &join_order {
+            // Check if this peer's tracks should be forwarded based on roles
+            let source = PeerKey::new(peer.room_id.as_str(), source_peer_id.as_str());
+            if self.room_manager.should_forward_track(&source, peer).await {
+                sources.push(source_peer_id.clone());
+            }
+        }
 
-        let all_tracks = self.track_manager.get_all_track_ids().await;
+        order_tracks(self.track_manager.track_entries(&peer.room_id, &sources).await, &join_order)
+    }
 
-        for track_id in all_tracks {
-            for peer in &room_peers {
-                if track_id.starts_with(&peer.id) && peer.id != *peer_id {
-                    // Check if this track should be forwarded based on roles
-                    if self.room_manager.should_forward_track(&peer.id, peer_id).await {
-                        tracks_to_forward.push(track_id.clone());
-                    }
-                    break;
-                }
+    /// Sends `message` to one peer if it is connected
+    async fn send_to_peer(&self, peer: &PeerKey, message: &SfuMessage) {
+        if let (Some(connection), Ok(text)) = (self.connections.get(peer), serde_json::to_string(message)) {
+            let _ = connection.send_message(Message::text(text)).await;
+        }
+    }
+
+    /// Sends `message` to everyone connected to the room
+    async fn broadcast(&self, room_id: &str, message: &SfuMessage) {
+        let Ok(text) = serde_json::to_string(message) else {
+            return;
+        };
+        for peer_id in self.room_manager.join_order(room_id).await {
+            if let Some(connection) = self.connections.get(&PeerKey::new(room_id, peer_id)) {
+                let _ = connection.send_message(Message::text(text.clone())).await;
+            }
+        }
+    }
+
+    /// Sends the peer everything the room's event log says it has been told
+    async fn send_state_sync(&self, peer: &PeerKey) {
+        if let Some(state) = self.room_manager.state_sync(&peer.room_id, &peer.peer_id).await {
+            let message = SfuMessage::StateSync {
+                room_id: peer.room_id.clone(),
+                state,
+            };
+            self.send_to_peer(peer, &message).await;
+        }
+    }
+
+    /// Sends `peer` the current order of the tracks forwarded to it, as the
+    /// changes since the last one if it negotiated `room_state_delta`
+    async fn send_room_state(&self, peer: &PeerKey) {
+        let Some(connection) = self.connections.get(peer) else {
+            return;
+        };
+        let track_order = self.get_tracks_for_peer(peer).await;
+        let update = self.room_state.update(peer, track_order);
+        if let Some(message) = room_state_message(&peer.room_id, update) {
+            if let Ok(text) = serde_json::to_string(&message) {
+                let _ = connection.send_message(Message::text(text)).await;
+            }
+        }
+    }
+
+    /// Sends `peer` the changes after `since_revision` that it missed, or the
+    /// full order when they are no longer retained
+    pub async fn resend_room_state(&self, peer: &PeerKey, since_revision: Option<u64>) {
+        let Some(connection) = self.connections.get(peer) else {
+            return;
+        };
+        let updates = match since_revision.and_then(|revision| self.room_state.since(peer, revision)) {
+            Some(updates) => updates,
+            None => vec![self.room_state.resync(peer, self.get_tracks_for_peer(peer).await)],
+        };
+        for update in updates {
+            if let Some(Ok(text)) = room_state_message(&peer.room_id, update).map(|m| serde_json::to_string(&m)) {
+                let _ = connection.send_message(Message::text(text)).await;
             }
         }
+    }
+
+    /// Sends `peer` track order deltas from its next `RoomState` on
+    pub fn enable_room_state_deltas(&self, peer: &PeerKey) {
+        self.room_state.enable_deltas(peer);
+    }
 
-        tracks_to_forward
+    /// Records which of a peer's tracks in `room_id` are screen shares and
+    /// refreshes the track order of everyone there receiving them
+    pub async fn set_track_content_hints(&self, room_id: &str, peer_id: &str, hints: HashMap<String, TrackContent>) {
+        let source = PeerKey::new(room_id, peer_id);
+        self.track_manager.set_content_hints(&source, hints).await;
+
+        if self.room_manager.get_peer(&source).await.is_none() {
+            return;
+        }
+        for subscriber in self.room_manager.join_order(room_id).await {
+            let subscriber = PeerKey::new(room_id, subscriber);
+            if self.room_manager.should_forward_track(&source, &subscriber).await {
+                self.send_room_state(&subscriber).await;
+            }
+        }
     }
 
+    /// Applies whether a peer's media is end-to-end encrypted. Encrypted
+    /// tracks are forwarded as usual but flagged in the track order, not
+    /// inspected for keyframes and not recorded: a running recording is
+    /// stopped, the proctor is told why, and the room's manifest lists the
+    /// peer. Turning encryption off again does not restart the recording.
+    pub async fn set_media_e2ee(&self, room_id: &str, peer_id: &str, e2ee: bool) {
+        let source = PeerKey::new(room_id, peer_id);
+        if self.room_manager.get_peer(&source).await.is_none() {
+            return;
+        }
+        self.recording_manager.set_e2ee(room_id, peer_id, e2ee).await;
+        if !self.track_manager.set_e2ee(&source, e2ee).await {
+            return;
+        }
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, e2ee = e2ee, "Peer media encryption changed");
+
+        if e2ee {
+            let at_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default();
+            self.room_sessions
+                .write()
+                .await
+                .entry(room_id.to_string())
+                .or_default()
+                .record_e2ee(peer_id, at_ms);
+
+            if self.recording_manager.is_recording(room_id, peer_id).await {
+                if let Err(e) = self.stop_recording(room_id, peer_id).await {
+                    tracing::error!(
+                        room_id = %room_id,
+                        peer_id = %peer_id,
+                        error = %e,
+                        "Failed to stop recording of end-to-end encrypted media"
+                    );
+                }
+            }
+            if let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await {
+                let message = SfuMessage::RecordingError {
+                    room_id: room_id.to_string(),
+                    peer_id: Some(peer_id.to_string()),
+                    error: "Media is end-to-end encrypted; it is forwarded but not recorded".to_string(),
+                };
+                self.send_to_peer(&PeerKey::new(room_id, proctor_id), &message).await;
+            }
+        }
+
+        for subscriber in self.room_manager.join_order(room_id).await {
+            let subscriber = PeerKey::new(room_id, subscriber);
+            if self.room_manager.should_forward_track(&source, &subscriber).await {
+                self.send_room_state(&subscriber).await;
+            }
+        }
+    }
 
     async fn is_proctor_ready(&self, room_id: &str) -> bool {
         let proctor_id = match self.room_manager.get_room_proctor(room_id).await {
@@ -759,10 +1956,9 @@ impl SfuServer {
             }
         };
 
-        let peers = self.peers_with_tracks.read().await;
-        let track_count = peers.get(&proctor_id).unwrap_or(&0);
-
-        let ready = *track_count >= 1;
+        let proctor = PeerKey::new(room_id, proctor_id.as_str());
+        let track_count = self.media_routing.published_tracks(&proctor);
+        let ready = self.media_routing.is_ready(&proctor);
         tracing::debug!(
             proctor_id = %proctor_id,
             track_count = track_count,
@@ -772,184 +1968,222 @@ impl SfuServer {
         ready
     }
 
-    pub async fn handle_track_received(&self, peer_id: &str, track_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// Forwards a track `source` published to everyone in its room who should
+    /// receive it. The same peer's connections in other rooms are left alone.
+    pub async fn handle_track_received(&self, source: &PeerKey, track_id: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         tracing::info!(
-            peer_id = %peer_id,
+            peer = %source,
             track_id = %track_id,
             "Handling new track from peer"
         );
 
-        {
-            let mut peers = self.peers_with_tracks.write().await;
-            let count = peers.entry(peer_id.to_string()).or_insert(0);
-            *count += 1;
-            tracing::debug!(peer_id = %peer_id, track_count = *count, "Updated peer track count");
-        }
+        let track_count = self.media_routing.track_published(source);
+        tracing::debug!(peer = %source, track_count = track_count, "Updated peer track count");
 
-        let connections = self.connections.read().await;
+        let connections = self.connections.snapshot();
         // Get source connection for sending PLI
-        let source_connection = connections.get(peer_id).cloned();
+        let source_connection = connections.get(source).cloned();
+        let mut subscribers = Vec::new();
 
-        for (target_peer_id, connection) in connections.iter() {
-            if target_peer_id != peer_id {
-                if !self.room_manager.should_forward_track(peer_id, target_peer_id).await {
+        for (target, connection) in connections.iter() {
+            if target.room_id == source.room_id && target != source {
+                if !self.room_manager.should_forward_track(source, target).await {
                     continue;
                 }
+                let target_peer_id = &target.peer_id;
 
                 if let Some((local_track, is_new, is_video, ssrc, _source_peer_id)) = self
                     .track_manager
-                    .create_local_track_for_peer(track_id, target_peer_id)
+                    .create_local_track_for_peer(track_id, target)
                     .await
                 {
-                    connection.peer_connection.add_track(local_track).await?;
+                    let rtp_sender = connection.peer_connection.add_track(local_track).await?;
+                    subscribers.push(target.clone());
                     tracing::info!(
                         track_id = %track_id,
                         target_peer_id = %target_peer_id,
+                        room_id = %target.room_id,
                         "Added track to peer"
                     );
 
-                    // Send PLI for new video track subscriptions to get immediate keyframe
-                    if is_new && is_video {
-                        if let Some(ref src_conn) = source_connection {
-                            if let Err(e) = SfuConnection::send_pli(&src_conn.peer_connection, ssrc).await {
-                                tracing::warn!(
+                    self.record_view_event(source, ViewEventKind::Subscribed, serde_json::json!({
+                        "subscriber": target_peer_id,
+                        "track_id": track_id,
+                        "kind": if is_video { "video" } else { "audio" },
+                    })).await;
+
+                    if let Some(src_conn) = source_connection.as_ref().filter(|_| is_video) {
+                        SfuConnection::forward_subscriber_feedback(
+                            rtp_sender,
+                            &src_conn.peer_connection,
+                            ssrc,
+                            source.room_id.clone(),
+                            track_id.to_string(),
+                            target_peer_id.clone(),
+                        );
+
+                        // Ask for a keyframe for the new subscription
+                        if is_new {
+                            match SfuConnection::request_subscription_keyframe(&src_conn.peer_connection, ssrc, &source.room_id, track_id, target_peer_id).await {
+                                Ok(decision) => tracing::info!(
+                                    track_id = %track_id,
+                                    target_peer_id = %target_peer_id,
+                                    ?decision,
+                                    "Requested keyframe for new subscriber"
+                                ),
+                                Err(e) => tracing::warn!(
                                     track_id = %track_id,
                                     error = %e,
                                     "Failed to send PLI for new subscriber"
-                                );
-                            } else {
-                                tracing::info!(
-                                    track_id = %track_id,
-                                    target_peer_id = %target_peer_id,
-                                    "Sent PLI for new subscriber keyframe request"
-                                );
+                                ),
                             }
                         }
                     }
 
-                    let should_schedule = {
-                        let mut pending = self.pending_renegotiations.write().await;
-                        let is_pending = pending.contains_key(target_peer_id);
-                        pending.insert(target_peer_id.to_string(), true);
-                        !is_pending
-                    };
-
-                    if should_schedule {
-                        tracing::debug!(
-                            target_peer_id = %target_peer_id,
-                            "Scheduling renegotiation in 150ms"
-                        );
-                        let connections_clone = self.connections.clone();
-                        let target_id = target_peer_id.clone();
-                        let pending_clone = self.pending_renegotiations.clone();
-                        tokio::spawn(async move {
-                            sleep(Duration::from_millis(150)).await;
-                            let _ = Self::perform_renegotiation_static(connections_clone, pending_clone, &target_id, 0).await;
-                        });
-                    } else {
-                        tracing::debug!(
-                            target_peer_id = %target_peer_id,
-                            "Renegotiation already scheduled, batching tracks"
-                        );
-                    }
+                    self.schedule_renegotiation(target);
                 }
             }
         }
 
+        for subscriber in subscribers {
+            self.send_room_state(&subscriber).await;
+        }
+
         Ok(())
     }
 
-    async fn perform_renegotiation_static(
-        connections: Arc<RwLock<HashMap<String, Arc<SfuConnection>>>>,
-        pending: Arc<RwLock<HashMap<String, bool>>>,
-        target_peer_id: &str,
-        retry_count: u32,
-    ) {
-        const MAX_RETRIES: u32 = 3;
-        const BASE_RETRY_DELAY_MS: u64 = 200;
+    /// Sends `target` a renegotiation offer once track changes pause for the
+    /// debounce, batching them into one offer. A batch goes out early when it
+    /// reaches the per-offer track limit or has been open for the batch window.
+    fn schedule_renegotiation(&self, target: &PeerKey) {
+        let tuning = self.renegotiation.tuning();
+        if !self.negotiation.request_renegotiation(target) {
+            let batched = self.negotiation.batched_renegotiations(target);
+            tracing::trace!(target = %target, batched, "Renegotiation already scheduled, batching tracks");
+            if tuning.batch_full(batched) {
+                self.renegotiation.flush_batch(target);
+            }
+            return;
+        }
 
-        // Only clear pending flag on first attempt (retry_count == 0)
-        if retry_count == 0 {
-            let mut pending_map = pending.write().await;
-            pending_map.remove(target_peer_id);
+        tracing::trace!(target = %target, debounce_ms = tuning.debounce_ms, "Scheduling renegotiation");
+        let flush = self.renegotiation.open_batch(target);
+        if tuning.batch_full(1) {
+            flush.notify_one();
         }
+        let connections = self.connections.clone();
+        let negotiation = self.negotiation.clone();
+        let renegotiation = self.renegotiation.clone();
+        let target = target.clone();
+        self.tasks.spawn("renegotiation", move |cancel| async move {
+            let opened = tokio::time::Instant::now();
+            let mut batched = 1;
+            loop {
+                // Read on every pass, so a change through the admin API applies to open batches
+                let tuning = renegotiation.tuning();
+                let wait = tuning.debounce().min(tuning.max_batch().saturating_sub(opened.elapsed()));
+                tokio::select! {
+                    _ = sleep(wait) => {}
+                    _ = flush.notified() => break,
+                    _ = cancel.cancelled() => return,
+                }
+                let now_batched = negotiation.batched_renegotiations(&target);
+                if now_batched <= batched || opened.elapsed() >= tuning.max_batch() {
+                    break;
+                }
+                batched = now_batched;
+            }
+            renegotiation.close_batch(&target, &flush);
+            let tracks = negotiation.start_renegotiation(&target);
 
-        let connection = {
-            let connections_map = connections.read().await;
-            connections_map.get(target_peer_id).cloned()
-        };
+            let Some(connection) = connections.get(&target) else {
+                return;
+            };
+            let mut retries = 0;
+            let sent_at = loop {
+                match negotiation::renegotiate(&connection, retries).await {
+                    RenegotiationOutcome::Sent => break renegotiation.offer_sent(&target, tracks),
+                    RenegotiationOutcome::Failed => return,
+                    RenegotiationOutcome::Busy if retries < MAX_RENEGOTIATION_RETRIES => {
+                        let delay = renegotiation.tuning().retry_delay(retries);
+                        tracing::warn!(
+                            target = %target,
+                            retry_count = retries,
+                            retry_delay_ms = delay.as_millis() as u64,
+                            "Signaling state not stable, retrying renegotiation"
+                        );
+                        renegotiation.retried(&target);
+                        retries += 1;
+                        tokio::select! {
+                            _ = sleep(delay) => {}
+                            _ = cancel.cancelled() => return,
+                        }
+                    }
+                    RenegotiationOutcome::Busy => {
+                        tracing::error!(
+                            target = %target,
+                            retry_count = retries,
+                            "Renegotiation failed after {} retries, giving up",
+                            MAX_RENEGOTIATION_RETRIES
+                        );
+                        return;
+                    }
+                }
+            };
 
-        if let Some(connection) = connection {
-            let signaling_state = connection.peer_connection.signaling_state();
-            tracing::debug!(
-                target_peer_id = %target_peer_id,
-                ?signaling_state,
-                retry_count = retry_count,
-                "Checking signaling state for renegotiation"
-            );
+            tokio::select! {
+                _ = sleep(renegotiation.tuning().answer_timeout()) => {}
+                _ = cancel.cancelled() => return,
+            }
+            if renegotiation.check_answer(&target, sent_at) {
+                tracing::warn!(target = %target, "Renegotiation offer not answered in time");
+            }
+        });
+    }
 
-            if signaling_state == webrtc::peer_connection::signaling_state::RTCSignalingState::Stable {
-                tracing::info!(
-                    target_peer_id = %target_peer_id,
-                    retry_count = retry_count,
-                    "Creating batched renegotiation offer"
-                );
+    /// Renegotiation tuning and per-room offer stats, for the admin API
+    pub fn renegotiation(&self) -> &RenegotiationControl {
+        &self.renegotiation
+    }
 
-                let offer = match connection.peer_connection.create_offer(None).await {
-                    Ok(offer) => offer,
-                    Err(e) => {
-                        tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to create renegotiation offer");
-                        return;
-                    }
-                };
+    /// Removes the senders forwarding `departed`'s tracks from everyone left in
+    /// its room, renegotiating each connection that lost one so the client
+    /// drops the stale transceivers
+    async fn remove_forwarded_tracks(&self, departed: &PeerKey) {
+        let departed_stream = stream_id(&departed.peer_id);
+        for (target, connection) in self.connections.snapshot() {
+            if target.room_id != departed.room_id || target == *departed {
+                continue;
+            }
 
-                if let Err(e) = connection.peer_connection.set_local_description(offer.clone()).await {
-                    tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to set local description");
-                    return;
-                }
-                tracing::debug!(target_peer_id = %target_peer_id, "Set local description");
-
-                let renegotiate_message = match serde_json::to_string(&serde_json::json!({
-                    "type": "renegotiate",
-                    "sdp": offer.sdp
-                })) {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to serialize renegotiation message");
-                        return;
-                    }
+            let mut removed = 0;
+            for sender in connection.peer_connection.get_senders().await {
+                let Some(track) = sender.track().await else {
+                    continue;
                 };
-
-                if let Err(e) = connection.send_message(Message::text(renegotiate_message)).await {
-                    tracing::error!(target_peer_id = %target_peer_id, error = %e, "Failed to send renegotiation offer");
-                    return;
+                if track.stream_id() != departed_stream {
+                    continue;
                 }
+                match connection.peer_connection.remove_track(&sender).await {
+                    Ok(()) => removed += 1,
+                    Err(e) => tracing::warn!(
+                        target = %target,
+                        track_id = %track.id(),
+                        error = %e,
+                        "Failed to remove departed peer's track"
+                    ),
+                }
+            }
+
+            if removed > 0 {
                 tracing::info!(
-                    target_peer_id = %target_peer_id,
-                    retry_count = retry_count,
-                    "Sent renegotiation offer"
-                );
-            } else if retry_count < MAX_RETRIES {
-                // Retry with exponential backoff
-                let retry_delay = BASE_RETRY_DELAY_MS * (2_u64.pow(retry_count));
-                tracing::warn!(
-                    target_peer_id = %target_peer_id,
-                    ?signaling_state,
-                    retry_count = retry_count,
-                    retry_delay_ms = retry_delay,
-                    "Signaling state not stable, will retry on next track or manual trigger"
-                );
-                // Note: Retry will happen naturally when next track is added
-                // or connection state changes. The exponential backoff is logged
-                // for monitoring purposes.
-            } else {
-                tracing::error!(
-                    target_peer_id = %target_peer_id,
-                    ?signaling_state,
-                    retry_count = retry_count,
-                    "Renegotiation failed after {} retries, giving up",
-                    MAX_RETRIES
+                    target = %target,
+                    departed_peer_id = %departed.peer_id,
+                    removed = removed,
+                    "Removed departed peer's tracks"
                 );
+                self.schedule_renegotiation(&target);
+                self.send_room_state(&target).await;
             }
         }
     }
@@ -959,6 +2193,7 @@ impl SfuServer {
         removed_peer_id: &str,
         room_id: &str,
         peer_name: Option<String>,
+        cause: DisconnectCause,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         tracing::debug!(
             removed_peer_id = %removed_peer_id,
@@ -966,14 +2201,16 @@ impl SfuServer {
             "Notifying proctor about participant leaving"
         );
 
+        self.remove_forwarded_tracks(&PeerKey::new(room_id, removed_peer_id)).await;
+
         // Notify the proctor that a participant has left
         if let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await {
-            let connections = self.connections.read().await;
-            if let Some(proctor_connection) = connections.get(&proctor_id) {
+            if let Some(proctor_connection) = self.connections.get(&PeerKey::new(room_id, proctor_id)) {
                 let message = SfuMessage::ParticipantLeft {
                     room_id: room_id.to_string(),
                     peer_id: removed_peer_id.to_string(),
                     name: peer_name,
+                    reason: cause.as_str().to_string(),
                 };
 
                 if let Ok(message_str) = serde_json::to_string(&message) {
@@ -1000,18 +2237,43 @@ impl SfuServer {
         student_name: Option<String>,
         role: String,
         wallet_address: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.send_join_request(room_id, student_peer_id, student_name, role, wallet_address, false)
+            .await
+    }
+
+    /// Sends the proctor a student's join request; `reminder` marks a request
+    /// the proctor already received and has not answered
+    async fn send_join_request(
+        &self,
+        room_id: String,
+        student_peer_id: String,
+        student_name: Option<String>,
+        role: String,
+        wallet_address: Option<String>,
+        reminder: bool,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let proctor_peer_id = self.room_manager.get_room_proctor(&room_id).await;
 
+        // Show the proctor the roster's name and wallet for pre-registered students
+        let roster_entry = self.room_manager.get_roster_entry(&room_id, &student_peer_id).await;
+        let pre_registered = roster_entry.is_some();
+        let (student_name, wallet_address) = match roster_entry {
+            Some(entry) => (student_name.or(entry.name), Some(format!("{:?}", entry.wallet))),
+            None => (student_name, wallet_address),
+        };
+
         if let Some(proctor_id) = proctor_peer_id {
-            let connections = self.connections.read().await;
-            if let Some(proctor_connection) = connections.get(&proctor_id) {
+            if let Some(proctor_connection) = self.connections.get(&PeerKey::new(room_id.as_str(), proctor_id)) {
                 let join_request_message = SfuMessage::JoinRequest {
                     room_id,
                     peer_id: student_peer_id,
                     name: student_name,
                     role,
                     wallet_address,
+                    pre_registered,
+                    reminder,
+                    invite_token: None,
                 };
 
                 let message_str = serde_json::to_string(&join_request_message)?;
@@ -1024,112 +2286,649 @@ impl SfuServer {
         Err("Proctor not found for this room".into())
     }
 
+    /// Records a join request for `room_id`, rejecting it once `MAX_PENDING_STUDENTS` are waiting
     pub async fn track_pending_student(
         &self,
+        room_id: &str,
         student_peer_id: String,
+        name: Option<String>,
+        role: String,
         wallet_address: Option<String>,
         sender: mpsc::UnboundedSender<Message>,
-    ) {
-        let mut pending = self.pending_students.write().await;
-        pending.insert(student_peer_id, PendingStudent { sender, wallet_address });
-    }
+    ) -> Result<(), Rejection> {
+        let student = PendingStudent {
+            sender,
+            wallet_address,
+            name,
+            role,
+            requested_at: std::time::Instant::now(),
+            ice_candidates: Vec::new(),
+            escalation: self.room_manager.get_escalation_policy(room_id).await,
+            stage: Default::default(),
+        };
 
+        self.admission.request_join(room_id, &student_peer_id, student).map_err(|limit| {
+            tracing::warn!(
+                peer_id = %student_peer_id,
+                room_id = %room_id,
+                max_pending = limit.max,
+                "Rejecting join request, too many pending students"
+            );
+            self.retry_policy.reject(
+                RejectReason::CapacityExceeded,
+                1.0,
+                format!("Too many pending join requests ({})", limit.max),
+            )
+        })
+    }
 
+    /// Delivers the proctor's decision to a student who asked to join `room_id`.
+    /// Students with a request for a different room are not found.
     pub async fn send_join_response(
         &self,
         room_id: String,
         student_peer_id: String,
         approved: bool,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        {
-            let connections = self.connections.read().await;
-            if let Some(student_connection) = connections.get(&student_peer_id) {
-                let response_message = if approved {
-                    serde_json::json!({
-                        "type": "join_approved",
-                        "room_id": room_id,
-                        "message": "Join request approved! Connecting to room..."
-                    })
-                } else {
-                    serde_json::json!({
-                        "type": "join_denied",
-                        "room_id": room_id,
-                        "message": "Join request denied by proctor"
-                    })
-                };
+        let response_message = if approved {
+            serde_json::json!({
+                "type": "join_approved",
+                "room_id": room_id,
+                "message": "Join request approved! Connecting to room..."
+            })
+        } else {
+            serde_json::json!({
+                "type": "join_denied",
+                "room_id": room_id,
+                "message": "Join request denied by proctor"
+            })
+        };
+        let message_str = serde_json::to_string(&response_message)?;
+
+        if let Some(student_connection) = self.connections.get(&PeerKey::new(room_id.as_str(), student_peer_id.as_str())) {
+            student_connection.send_message(Message::text(message_str)).await?;
+            return Ok(());
+        }
 
-                let message_str = serde_json::to_string(&response_message)?;
-                student_connection.send_message(Message::text(message_str)).await?;
+        // A denied request is over; its buffered candidates go with it
+        match self.admission.answer(&room_id, &student_peer_id, approved, Message::text(message_str)) {
+            Some(sent) => Ok(sent?),
+            None => Err("Student connection not found".into()),
+        }
+    }
 
-                return Ok(());
+    /// Withdraws the student's request for `room_id`, if it is still pending
+    pub async fn remove_pending_student(&self, room_id: &str, student_peer_id: &str) {
+        self.admission.withdraw(room_id, student_peer_id);
+    }
+
+    /// Periodically drops join requests the proctor never answered, telling the student
+    pub fn start_pending_student_sweeper(self: Arc<Self>) {
+        let heartbeat = health::monitor().register("pending_student_sweeper", PENDING_SWEEP_INTERVAL * 4);
+
+        let server = self.clone();
+        self.tasks.spawn("pending_student_sweeper", move |cancel| async move {
+            let mut tick = tokio::time::interval(PENDING_SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = cancel.cancelled() => break,
+                }
+                let now = std::time::Instant::now();
+                server.escalate_pending_students(now).await;
+                server.expire_pending_students(now).await;
+                heartbeat.beat();
+            }
+        });
+    }
+
+    /// Removes peers whose connection failed, or stayed disconnected longer
+    /// than `SFU_DISCONNECT_GRACE_SECS`, as if their WebSocket had dropped
+    pub fn start_peer_state_monitor(self: Arc<Self>) {
+        let heartbeat = health::monitor().register("peer_state_monitor", PEER_STATE_SWEEP_INTERVAL * 10);
+
+        let server = self.clone();
+        self.tasks.spawn("peer_state_monitor", move |cancel| async move {
+            let receiver = server.peer_state_receiver.write().await.take();
+            let Some(mut rx) = receiver else {
+                return;
+            };
+
+            let mut tick = tokio::time::interval(PEER_STATE_SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    change = rx.recv() => {
+                        let Some(change) = change else {
+                            break;
+                        };
+                        server.handle_peer_state_change(change).await;
+                    }
+                    _ = tick.tick() => {
+                        server.expire_disconnected_peers(std::time::Instant::now()).await;
+                    }
+                    _ = cancel.cancelled() => break,
+                }
+                heartbeat.beat();
+            }
+        });
+    }
+
+    /// Whether `connection` is still the one registered for `peer`
+    fn is_current_connection(&self, peer: &PeerKey, connection: &std::sync::Weak<RTCPeerConnection>) -> bool {
+        self.connections
+            .get(peer)
+            .is_some_and(|current| std::ptr::eq(Arc::as_ptr(&current.peer_connection), connection.as_ptr()))
+    }
+
+    async fn handle_peer_state_change(&self, change: PeerStateChange) {
+        let PeerStateChange { peer, state, connection } = change;
+        // The peer rejoined since; the report is about its old connection
+        if !self.is_current_connection(&peer, &connection) {
+            return;
+        }
+
+        match state {
+            RTCPeerConnectionState::Failed => {
+                self.disconnected.lock().unwrap().remove(&peer);
+                tracing::warn!(peer = %peer, "Peer connection failed, removing peer");
+                self.remove_dead_peer(&peer).await;
+            }
+            RTCPeerConnectionState::Disconnected => {
+                self.disconnected
+                    .lock()
+                    .unwrap()
+                    .entry(peer)
+                    .or_insert((std::time::Instant::now(), connection));
+            }
+            _ => {
+                self.disconnected.lock().unwrap().remove(&peer);
             }
         }
+    }
 
+    /// Removes peers whose connection has been disconnected for the whole grace period
+    async fn expire_disconnected_peers(&self, now: std::time::Instant) {
+        let expired: Vec<(PeerKey, std::sync::Weak<RTCPeerConnection>)> = {
+            let mut disconnected = self.disconnected.lock().unwrap();
+            let expired: Vec<_> = disconnected
+                .iter()
+                .filter(|(_, (since, _))| now.saturating_duration_since(*since) >= self.disconnect_grace)
+                .map(|(peer, (_, connection))| (peer.clone(), connection.clone()))
+                .collect();
+            for (peer, _) in &expired {
+                disconnected.remove(peer);
+            }
+            expired
+        };
 
-        let pending = self.pending_students.read().await;
-        if let Some(pending_student) = pending.get(&student_peer_id) {
-            let response_message = if approved {
-                serde_json::json!({
-                    "type": "join_approved",
-                    "room_id": room_id,
-                    "message": "Join request approved! Connecting to room..."
-                })
-            } else {
-                serde_json::json!({
-                    "type": "join_denied",
-                    "room_id": room_id,
-                    "message": "Join request denied by proctor"
-                })
+        for (peer, connection) in expired {
+            if self.is_current_connection(&peer, &connection) {
+                tracing::warn!(
+                    peer = %peer,
+                    grace_secs = self.disconnect_grace.as_secs(),
+                    "Peer connection stayed disconnected, removing peer"
+                );
+                self.remove_dead_peer(&peer).await;
+            }
+        }
+    }
+
+    async fn remove_dead_peer(&self, peer: &PeerKey) {
+        if let Err(e) = self.remove_peer(&peer.room_id, &peer.peer_id, DisconnectCause::ConnectionLost).await {
+            tracing::error!(peer = %peer, error = %e, "Failed to remove peer with a dead connection");
+        }
+    }
+
+    /// Repairs recordings a crash left as `.part`. They are found before the
+    /// server accepts peers, so none of them can be a live recording.
+    pub fn start_recording_recovery(self: Arc<Self>) {
+        let orphans = self.recording_manager.orphaned_recordings();
+        if orphans.is_empty() {
+            return;
+        }
+
+        let recording_manager = self.recording_manager.clone();
+        self.tasks.spawn("recording_recovery", move |_cancel| async move {
+            let total = orphans.len();
+            let recovered = recording_manager.repair_orphans(orphans).await;
+            tracing::info!(recovered = recovered.len(), total = total, "Finished repairing orphaned recordings");
+        });
+    }
+
+    /// Uploads stopped recordings to IPFS in the background and reports each
+    /// one once it is uploaded or its retries run out
+    pub fn start_recording_uploads(self: Arc<Self>) {
+        let server = self.clone();
+        self.tasks.spawn("recording_uploads", move |cancel| async move {
+            loop {
+                tokio::select! {
+                    upload = server.recording_manager.next_upload() => {
+                        let Some(upload) = upload else {
+                            break;
+                        };
+                        server.handle_recording_upload(upload).await;
+                    }
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        });
+    }
+
+    /// Stops recordings whose pipeline failed, such as on a full disk, and
+    /// tells the room's proctor
+    pub fn start_recording_failures(self: Arc<Self>) {
+        let server = self.clone();
+        self.tasks.spawn("recording_failures", move |cancel| async move {
+            loop {
+                tokio::select! {
+                    failure = server.recording_manager.next_failure() => {
+                        let Some(failure) = failure else {
+                            break;
+                        };
+                        server.handle_recording_failure(failure).await;
+                    }
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        });
+    }
+
+    /// Stops recordings that ran past `RECORDING_MAX_DURATION_SECS`, or all
+    /// of them once free space falls below `RECORDING_MIN_FREE_BYTES`
+    pub fn start_recording_watchdog(self: Arc<Self>) {
+        let heartbeat = health::monitor().register("recording_watchdog", RECORDING_LIMIT_INTERVAL * 3);
+
+        let server = self.clone();
+        self.tasks.spawn("recording_watchdog", move |cancel| async move {
+            let mut tick = tokio::time::interval(RECORDING_LIMIT_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = cancel.cancelled() => break,
+                }
+                server.enforce_recording_limits().await;
+                heartbeat.beat();
+            }
+        });
+    }
+
+    async fn enforce_recording_limits(&self) {
+        for (room_id, peer_id, limit) in self.recording_manager.recordings_over_limits().await {
+            tracing::warn!(room_id = %room_id, peer_id = %peer_id, limit = %limit, "Stopping recording over its limit");
+            if let Err(e) = self.stop_recording(&room_id, &peer_id).await {
+                tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to stop recording over its limit");
+            }
+            let Some(proctor_id) = self.room_manager.get_room_proctor(&room_id).await else {
+                continue;
+            };
+            let message = SfuMessage::RecordingError {
+                room_id: room_id.clone(),
+                peer_id: Some(peer_id),
+                error: limit.to_string(),
             };
+            self.send_to_peer(&PeerKey::new(room_id.as_str(), proctor_id), &message).await;
+        }
+    }
+
+    async fn handle_recording_failure(&self, failure: RecordingFailure) {
+        let room_id = failure.room_id.as_str();
+        self.room_manager.record_event(room_id, Some(&failure.peer_id), RoomEvent::Recording(false)).await;
+        let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await else {
+            return;
+        };
+        let message = SfuMessage::RecordingError {
+            room_id: room_id.to_string(),
+            peer_id: Some(failure.peer_id.clone()),
+            error: format!("Recording failed: {}", failure.error),
+        };
+        self.send_to_peer(&PeerKey::new(room_id, proctor_id), &message).await;
+    }
 
-            let message_str = serde_json::to_string(&response_message)?;
-            pending_student.sender.send(Message::text(message_str))?;
+    /// Completes what stopping the recording left for its upload: the on-chain
+    /// RecordingStopped, with the CID when there is one, and telling the proctor.
+    /// The recording is pending for the room's manifest until this returns.
+    async fn handle_recording_upload(&self, upload: RecordingUpload) {
+        let room_id = upload.room_id.as_str();
+        let awaiting = self.awaiting_upload.lock().unwrap().remove(&upload.file_path);
+        if let Some((wallet, file_hash)) = awaiting {
+            self.emit_chain_event(ChainEvent::RecordingStopped {
+                room_id: room_id.to_string(),
+                participant: wallet,
+                duration_secs: upload.duration_secs,
+                ipfs_cid: upload.result.as_ref().ok().map(|uploaded| uploaded.cid.clone()),
+                file_hash,
+            });
+        }
 
-            return Ok(());
+        let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await else {
+            return;
+        };
+        let message = match &upload.result {
+            Ok(uploaded) => SfuMessage::RecordingUploaded {
+                room_id: room_id.to_string(),
+                peer_id: upload.peer_id.clone(),
+                cid: uploaded.cid.clone(),
+                ipfs_gateway_url: uploaded.gateway_url.clone(),
+                storage_url: uploaded.gateway_url.clone(),
+            },
+            Err(e) => SfuMessage::RecordingError {
+                room_id: room_id.to_string(),
+                peer_id: Some(upload.peer_id.clone()),
+                error: format!("IPFS upload failed after {} attempts: {}", upload.attempts, e),
+            },
+        };
+        self.send_to_peer(&PeerKey::new(room_id, proctor_id), &message).await;
+    }
+
+    pub fn start_recording_gap_sweeper(self: Arc<Self>) {
+        let heartbeat = health::monitor().register("recording_gap_sweeper", GAP_SWEEP_INTERVAL * 10);
+
+        let server = self.clone();
+        self.tasks.spawn("recording_gap_sweeper", move |cancel| async move {
+            let mut tick = tokio::time::interval(GAP_SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = cancel.cancelled() => break,
+                }
+                server.report_recording_gaps(std::time::Instant::now()).await;
+                heartbeat.beat();
+            }
+        });
+    }
+
+    /// Turns new recording gaps into incidents and tells the room's proctor
+    /// when a gap opens and when it closes
+    async fn report_recording_gaps(&self, now: std::time::Instant) {
+        for (room_id, peer_id, event) in self.recording_manager.sweep_media_gaps(now).await {
+            let (kind, start_offset, end_offset) = match event {
+                GapEvent::Opened { kind, start_offset } => {
+                    tracing::warn!(
+                        room_id = %room_id,
+                        peer_id = %peer_id,
+                        kind = kind.as_str(),
+                        start_offset = start_offset,
+                        "Recorded track stopped delivering media"
+                    );
+                    self.emit_suspicious_activity(
+                        &room_id,
+                        &peer_id,
+                        MEDIA_GAP_ACTIVITY,
+                        Some(format!("No {} since {:.1}s into the recording", kind.as_str(), start_offset)),
+                    )
+                    .await;
+                    (kind, start_offset, None)
+                }
+                GapEvent::Closed(gap) => {
+                    tracing::info!(
+                        room_id = %room_id,
+                        peer_id = %peer_id,
+                        kind = gap.kind.as_str(),
+                        gap_secs = gap.duration_secs(),
+                        "Recorded track resumed after a media gap"
+                    );
+                    (gap.kind, gap.start_offset, Some(gap.end_offset))
+                }
+            };
+
+            let Some(proctor_id) = self.room_manager.get_room_proctor(&room_id).await else {
+                continue;
+            };
+            let proctor = PeerKey::new(room_id.as_str(), proctor_id);
+            let message = SfuMessage::RecordingGap {
+                room_id,
+                peer_id,
+                kind,
+                start_offset,
+                end_offset,
+            };
+            if let (Some(connection), Ok(message_str)) = (self.connections.get(&proctor), serde_json::to_string(&message)) {
+                let _ = connection.send_message(Message::text(message_str)).await;
+            }
+        }
+    }
+
+    /// Reminds the proctor of join requests left unanswered for `JOIN_ESCALATION_SECS`,
+    /// and one interval later applies the room's fallback. Every step is audited.
+    async fn escalate_pending_students(&self, now: std::time::Instant) {
+        let Some(interval) = self.join_escalation.interval else {
+            return;
+        };
+
+        for request in self.admission.escalate(now, interval) {
+            let waited_secs = request.waited.as_secs();
+            tracing::info!(
+                room_id = %request.room_id,
+                peer_id = %request.peer_id,
+                action = request.action.as_str(),
+                policy = request.policy.as_str(),
+                waited_secs = waited_secs,
+                "Escalating unanswered join request"
+            );
+
+            match request.action {
+                EscalationAction::Reminded => {
+                    if let Err(e) = self
+                        .send_join_request(
+                            request.room_id.clone(),
+                            request.peer_id.clone(),
+                            request.name.clone(),
+                            request.role.clone(),
+                            request.wallet_address.clone(),
+                            true,
+                        )
+                        .await
+                    {
+                        tracing::warn!(room_id = %request.room_id, peer_id = %request.peer_id, error = %e, "Failed to remind proctor of join request");
+                    }
+                    if self.join_escalation.alert {
+                        alerter().raise(Alert::new(
+                            "join_request_unanswered",
+                            format!(
+                                "Join request from {} in room {} unanswered for {}s",
+                                request.peer_id, request.room_id, waited_secs
+                            ),
+                            serde_json::json!({
+                                "room_id": request.room_id,
+                                "peer_id": request.peer_id,
+                                "policy": request.policy.as_str(),
+                                "waited_secs": waited_secs,
+                            }),
+                        ));
+                    }
+                }
+                EscalationAction::AutoApproved | EscalationAction::AutoDenied => {
+                    let (kind, text) = if request.action == EscalationAction::AutoApproved {
+                        ("join_approved", "Join request approved automatically. Connecting to room...")
+                    } else {
+                        ("join_denied", "Join request denied, the proctor did not answer in time")
+                    };
+                    let message = serde_json::json!({
+                        "type": kind,
+                        "room_id": request.room_id,
+                        "message": text,
+                        "automatic": true,
+                    });
+                    let _ = request.sender.send(Message::text(message.to_string()));
+                    self.notify_join_escalated(&request.room_id, &request.peer_id, request.action, waited_secs).await;
+                }
+                EscalationAction::KeptWaiting => {
+                    self.notify_join_escalated(&request.room_id, &request.peer_id, request.action, waited_secs).await;
+                }
+            }
+
+            self.record_join_escalation(&request.room_id, &request.peer_id, request.action, request.policy, waited_secs)
+                .await;
+        }
+    }
+
+    /// Tells the proctor the room's fallback settled a join request it left unanswered
+    async fn notify_join_escalated(&self, room_id: &str, peer_id: &str, action: EscalationAction, waited_secs: u64) {
+        let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await else {
+            return;
+        };
+        let message = SfuMessage::JoinRequestEscalated {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            action: action.as_str().to_string(),
+            waited_secs,
+        };
+        let proctor = PeerKey::new(room_id, proctor_id);
+        if let (Some(connection), Ok(message_str)) = (self.connections.get(&proctor), serde_json::to_string(&message)) {
+            let _ = connection.send_message(Message::text(message_str)).await;
         }
+    }
 
-        Err("Student connection not found".into())
+    /// Adds an escalation step to the room's view event stream and session summary
+    async fn record_join_escalation(
+        &self,
+        room_id: &str,
+        peer_id: &str,
+        action: EscalationAction,
+        policy: EscalationPolicy,
+        waited_secs: u64,
+    ) {
+        let at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        self.room_sessions
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .record_join_escalation(peer_id, action.as_str(), policy.as_str(), waited_secs, at_ms);
+        self.recording_manager
+            .record_view_event(
+                room_id,
+                ViewEventKind::JoinEscalation,
+                peer_id,
+                serde_json::json!({
+                    "action": action.as_str(),
+                    "policy": policy.as_str(),
+                    "waited_secs": waited_secs,
+                }),
+            )
+            .await;
     }
 
+    async fn expire_pending_students(&self, now: std::time::Instant) {
+        let expired = self.admission.expire(now);
+        let ttl = self.admission.ttl();
+        if expired.is_empty() {
+            return;
+        }
 
-    pub async fn remove_pending_student(&self, student_peer_id: &str) {
-        let mut pending = self.pending_students.write().await;
-        pending.remove(student_peer_id);
+        metrics::metrics().pending_students_expired_total.inc_by(expired.len() as u64);
+        for (room_id, peer_id, student) in expired {
+            tracing::info!(
+                peer_id = %peer_id,
+                room_id = %room_id,
+                ttl_secs = ttl.as_secs(),
+                "Join request expired without a proctor decision"
+            );
+            let message = serde_json::json!({
+                "type": "join_request_expired",
+                "room_id": room_id,
+                "message": "Join request timed out waiting for the proctor"
+            });
+            let _ = student.sender.send(Message::text(message.to_string()));
+        }
     }
 
-    /// Store exam grade for a peer (called when student submits exam)
-    pub async fn set_exam_grade(&self, peer_id: &str, grade: u64, exam_name: String) {
+    /// Store exam grade for a peer in a room (called when student submits exam)
+    pub async fn set_exam_grade(&self, room_id: &str, peer_id: &str, grade: u64, exam_name: Option<String>) {
         let mut grades = self.peer_exam_grades.write().await;
-        grades.insert(peer_id.to_string(), ExamGrade { grade, exam_name });
-        tracing::info!(peer_id = %peer_id, grade = grade, "Stored exam grade for peer");
+        grades.insert(PeerKey::new(room_id, peer_id), ExamGrade { grade, exam_name });
+        tracing::info!(peer_id = %peer_id, room_id = %room_id, grade = grade, "Stored exam grade for peer");
     }
 
-    /// Get exam grade for a peer (returns grade in basis points, e.g., 8500 = 85.00%)
-    pub async fn get_exam_grade(&self, peer_id: &str) -> Option<ExamGrade> {
+    /// Get exam grade for a peer in a room (returns grade in basis points, e.g., 8500 = 85.00%)
+    pub async fn get_exam_grade(&self, room_id: &str, peer_id: &str) -> Option<ExamGrade> {
         let grades = self.peer_exam_grades.read().await;
-        grades.get(peer_id).cloned()
+        grades.get(&PeerKey::new(room_id, peer_id)).cloned()
     }
 
-    /// Remove exam grade for a peer
-    pub async fn remove_exam_grade(&self, peer_id: &str) {
+    /// Remove exam grade for a peer in a room
+    pub async fn remove_exam_grade(&self, room_id: &str, peer_id: &str) {
         let mut grades = self.peer_exam_grades.write().await;
-        grades.remove(peer_id);
+        grades.remove(&PeerKey::new(room_id, peer_id));
     }
 
     // Recording methods
+    /// Whether peers of `room_id` are recorded: recording is enabled on the
+    /// server and the room did not opt out
+    async fn records_room(&self, room_id: &str) -> bool {
+        self.recording_manager.is_enabled() && self.room_manager.is_recording_enabled(room_id).await
+    }
+
     pub async fn start_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
         tracing::info!(room_id = %room_id, peer_id = %peer_id, "Starting recording for peer");
-        self.recording_manager.start_recording(room_id, peer_id).await
+        if !self.room_manager.is_recording_enabled(room_id).await {
+            return Err(SfuError::RecordingDisabled(format!("room {} was created without recording", room_id)));
+        }
+        self.recording_manager.start_recording(room_id, peer_id, &self.engine_config.recording_codecs()).await?;
+        self.room_manager.record_event(room_id, Some(peer_id), RoomEvent::Recording(true)).await;
+        if let Some(wallet) = self.peer_wallet(room_id, peer_id).await {
+            self.emit_chain_event(ChainEvent::RecordingStarted {
+                room_id: room_id.to_string(),
+                participant: wallet,
+            });
+        }
+        Ok(())
     }
 
     pub async fn stop_recording(&self, room_id: &str, peer_id: &str) -> Result<RecordingResult, SfuError> {
         tracing::info!(room_id = %room_id, peer_id = %peer_id, "Stopping recording for peer");
-        self.recording_manager.stop_recording(room_id, peer_id).await
+        let result = self.recording_manager.stop_recording(room_id, peer_id).await?;
+        self.room_manager.record_event(room_id, Some(peer_id), RoomEvent::Recording(false)).await;
+        if let Some(wallet) = self.peer_wallet(room_id, peer_id).await {
+            self.emit_recording_stopped(room_id, wallet, &result);
+        }
+        Ok(result)
+    }
+
+    /// Pauses a peer's recording; the file stays open and goes on at resume
+    pub async fn pause_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Pausing recording for peer");
+        self.recording_manager.pause_recording(room_id, peer_id).await
+    }
+
+    pub async fn resume_recording(&self, room_id: &str, peer_id: &str) -> Result<(), SfuError> {
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Resuming recording for peer");
+        self.recording_manager.resume_recording(room_id, peer_id).await
+    }
+
+    /// Continues a peer's recording in a new file; it stays recording throughout.
+    /// On-chain, the finished file is a stopped recording and the new one a started one.
+    pub async fn restart_recording(&self, room_id: &str, peer_id: &str) -> Result<RecordingRestart, SfuError> {
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Restarting recording for peer");
+        let restart = self.recording_manager.restart_recording(room_id, peer_id).await?;
+        if let Some(wallet) = self.peer_wallet(room_id, peer_id).await {
+            self.emit_recording_stopped(room_id, wallet, &restart.stopped);
+            self.emit_chain_event(ChainEvent::RecordingStarted {
+                room_id: room_id.to_string(),
+                participant: wallet,
+            });
+        }
+        Ok(restart)
     }
 
     pub async fn stop_all_recordings(&self, room_id: &str) -> Vec<(String, RecordingResult)> {
         tracing::info!(room_id = %room_id, "Stopping all recordings in room");
-        self.recording_manager.stop_all_recordings_in_room(room_id).await
+        let stopped = self.recording_manager.stop_all_recordings_in_room(room_id).await;
+        for (peer_id, result) in &stopped {
+            self.room_manager.record_event(room_id, Some(peer_id), RoomEvent::Recording(false)).await;
+            if let Some(wallet) = self.peer_wallet(room_id, peer_id).await {
+                self.emit_recording_stopped(room_id, wallet, result);
+            }
+        }
+        stopped
+    }
+
+    /// Wallet the peer joined the room with, if any
+    async fn peer_wallet(&self, room_id: &str, peer_id: &str) -> Option<Address> {
+        self.peer_wallets.read().await.get(&PeerKey::new(room_id, peer_id)).copied()
     }
 
     pub async fn is_peer_recording(&self, room_id: &str, peer_id: &str) -> bool {
@@ -1140,43 +2939,313 @@ impl SfuServer {
         self.recording_manager.get_recording_peers(room_id).await
     }
 
-    pub fn get_recording_manager(&self) -> Arc<RecordingManager> {
-        self.recording_manager.clone()
+    pub async fn get_recording_details(&self, room_id: &str) -> Vec<RecordingDetail> {
+        self.recording_manager.recording_details(room_id).await
     }
 
-    // Chain event emission methods
+    pub async fn get_completed_recordings(&self, room_id: &str) -> Vec<CompletedRecording> {
+        self.recording_manager.completed_recordings(room_id).await
+    }
 
-    /// Emits a participant kicked event to the blockchain
-    pub async fn emit_participant_kicked(
+    /// Sidecar of the peer's most recently stopped recording in the room
+    pub fn get_recording_metadata(&self, room_id: &str, peer_id: &str) -> Result<Option<RecordingSidecar>, SfuError> {
+        self.recording_manager.get_recording_metadata(room_id, peer_id)
+    }
+
+    /// Replaces the room's metadata (last write wins) and propagates it to the
+    /// session summary and to recordings finalized from now on. Each change is
+    /// audited in the room's view event stream.
+    pub async fn set_session_metadata(
         &self,
         room_id: &str,
-        kicked_peer_id: &str,
-        reason: Option<String>,
-    ) {
-        // Get wallet addresses for proctor and kicked participant
-        let wallets = self.peer_wallets.read().await;
-        let proctor_id = self.room_manager.get_room_proctor(room_id).await;
-        let proctor_wallet = proctor_id.as_ref().and_then(|id| wallets.get(id).copied());
-        let kicked_wallet = wallets.get(kicked_peer_id).copied();
+        changed_by: &str,
+        metadata: SessionMetadata,
+    ) -> Result<(), String> {
+        self.room_manager.set_room_metadata(room_id, metadata.clone()).await?;
+        self.room_sessions
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .set_metadata(metadata.clone());
+        self.recording_manager.set_session_metadata(room_id, metadata.clone()).await;
+        self.recording_manager
+            .record_view_event(
+                room_id,
+                ViewEventKind::SessionMetadata,
+                changed_by,
+                serde_json::to_value(&metadata).unwrap_or_default(),
+            )
+            .await;
 
-        if let (Some(proctor), Some(kicked)) = (proctor_wallet, kicked_wallet) {
-            self.emit_chain_event(ChainEvent::ParticipantKicked {
-                room_id: room_id.to_string(),
-                proctor,
-                kicked,
-                reason,
+        tracing::info!(
+            room_id = %room_id,
+            changed_by = %changed_by,
+            exam_name = ?metadata.exam_name,
+            course_code = ?metadata.course_code,
+            "Session metadata updated"
+        );
+        Ok(())
+    }
+
+    pub async fn room_exists(&self, room_id: &str) -> bool {
+        self.room_manager.room_exists(room_id).await
+    }
+
+    /// Every room on this instance with the peers recording in it, oldest first
+    pub async fn room_overviews(&self) -> Vec<RoomOverview> {
+        let mut overviews = Vec::new();
+        for room in self.room_manager.room_summaries().await {
+            let recording_peers = self.recording_manager.get_recording_peers(&room.room_id).await;
+            let tenant = self.recording_manager.room_tenant(&room.room_id);
+            let integrity_scores = self.integrity_score_table(&room.room_id).await;
+            overviews.push(RoomOverview { room, tenant, recording_peers, integrity_scores });
+        }
+        overviews
+    }
+
+    /// The room's peers with their published tracks, connection and recording state
+    pub async fn room_detail(&self, room_id: &str) -> Option<RoomDetail> {
+        let room = self.room_manager.room_summary(room_id).await?;
+        let recording_peers = self.recording_manager.get_recording_peers(room_id).await;
+
+        let join_order = self.room_manager.join_order(room_id).await;
+        let mut members = self.room_manager.get_room_peers(room_id).await;
+        members.sort_by_key(|peer| join_order.iter().position(|id| *id == peer.id).unwrap_or(usize::MAX));
+
+        let mut peers = Vec::with_capacity(members.len());
+        for peer in members {
+            let key = PeerKey::new(room_id, peer.id.as_str());
+            peers.push(PeerOverview {
+                track_count: self.track_manager.count_tracks_for_peer(&key).await,
+                connection_state: self
+                    .connections
+                    .get(&key)
+                    .map(|connection| connection.peer_connection.connection_state().to_string()),
+                recording: recording_peers.contains(&peer.id),
+                e2ee: self.track_manager.is_e2ee(&key).await,
+                integrity_score: self.integrity_score(room_id, &peer.id).await.map(|scored| scored.score),
+                peer_id: peer.id,
+                role: peer.role,
+                name: peer.name,
             });
-        } else {
-            tracing::debug!(
-                room_id = %room_id,
-                kicked_peer_id = %kicked_peer_id,
-                "Cannot emit participant kicked event: wallet addresses not available"
-            );
         }
+
+        Some(RoomDetail {
+            room: RoomOverview {
+                room,
+                tenant: self.recording_manager.room_tenant(room_id),
+                recording_peers,
+                integrity_scores: self.integrity_score_table(room_id).await,
+            },
+            peers,
+        })
     }
 
-    /// Emits an ID verification event to the blockchain
-    pub async fn emit_id_verification(
+    /// Diagnostics bundle of one peer: its negotiated transport, the tracks it
+    /// publishes, its recording, and what its signaling connection logged and
+    /// exchanged. Full SDPs are only included with `full_sdp`. `None` when
+    /// nothing is known about the peer.
+    pub async fn peer_diagnostics(&self, room_id: &str, peer_id: &str, full_sdp: bool) -> Option<DiagnosticsBundle> {
+        let key = PeerKey::new(room_id, peer_id);
+        let peer = self.room_manager.get_peer(&key).await;
+        let connection = self.connections.get(&key);
+        let connection_id = diagnostics::logs().and_then(|logs| logs.connection_for(&key));
+        if peer.is_none() && connection.is_none() && connection_id.is_none() {
+            return None;
+        }
+
+        let transport = match connection {
+            Some(connection) => Some(
+                TransportDiagnostics::collect(&connection.peer_connection, &connection.state_history, full_sdp).await,
+            ),
+            None => None,
+        };
+        let tracks = rtcp::feedback()
+            .snapshot()
+            .publishers
+            .into_iter()
+            .filter(|publisher| publisher.room_id == room_id && publisher.peer_id == peer_id)
+            .flat_map(|publisher| publisher.tracks)
+            .collect();
+        let recording = self
+            .recording_manager
+            .peer_recording(room_id, peer_id)
+            .await
+            .map(|(detail, gaps)| RecordingDiagnostics { detail, gaps });
+        let capture = connection_id
+            .and_then(|id| diagnostics::logs().and_then(|logs| logs.snapshot(id)))
+            .unwrap_or_default();
+
+        Some(DiagnosticsBundle {
+            room_id: room_id.to_string(),
+            peer_id: peer_id.to_string(),
+            role: peer.map(|peer| peer.role),
+            generated_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+            connection_id,
+            transport,
+            tracks,
+            recording,
+            capture,
+        })
+    }
+
+    /// Deletes everything stored in `tenant`'s namespace and unpins the
+    /// uploads its manifests list from the local IPFS node. A dry run only
+    /// reports what would go. Refused while rooms of the tenant are open or
+    /// still closing; every request lands in the tenant audit log
+    pub async fn delete_tenant_data(&self, tenant_name: &str, dry_run: bool) -> Result<TenantSweep, TenantError> {
+        if !tenant::is_valid_tenant(tenant_name) {
+            return Err(TenantError::Invalid(tenant_name.to_string()));
+        }
+        let open = self.recording_manager.tenant_rooms(tenant_name);
+        if !open.is_empty() && !dry_run {
+            return Err(TenantError::RoomsOpen(open.len()));
+        }
+
+        let output_dir = PathBuf::from(self.recording_manager.output_dir());
+        let sweep_dir = output_dir.clone();
+        let sweep_tenant = tenant_name.to_string();
+        let mut sweep = tokio::task::spawn_blocking(move || tenant::sweep(&sweep_dir, &sweep_tenant, dry_run))
+            .await
+            .map_err(|e| TenantError::Storage(e.to_string()))?
+            .map_err(|e| TenantError::Storage(e.to_string()))?;
+
+        if !dry_run {
+            if let Some(store) = self.recording_manager.store() {
+                for cid in sweep.cids.clone() {
+                    match store.unpin(&cid).await {
+                        Ok(true) => sweep.unpinned.push(cid),
+                        Ok(false) => {}
+                        Err(e) => {
+                            tracing::warn!(tenant = %tenant_name, cid = %cid, error = %e, "Failed to unpin tenant upload");
+                            sweep.unpin_failed.push(cid);
+                        }
+                    }
+                }
+            }
+        }
+
+        let at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let mut entry = serde_json::to_value(&sweep).unwrap_or_default();
+        entry["at"] = at.into();
+        let audit_dir = output_dir.clone();
+        let audited = tokio::task::spawn_blocking(move || tenant::append_audit(&audit_dir, &entry)).await;
+        if let Err(e) = audited.map_err(|e| e.to_string()).and_then(|r| r.map_err(|e| e.to_string())) {
+            tracing::error!(tenant = %tenant_name, error = %e, "Failed to write the tenant audit log");
+        }
+
+        tracing::warn!(
+            tenant = %tenant_name,
+            dry_run,
+            rooms = sweep.rooms.len(),
+            files = sweep.files,
+            bytes = sweep.bytes,
+            unpinned = sweep.unpinned.len(),
+            "Tenant data deletion requested"
+        );
+        Ok(sweep)
+    }
+
+    pub async fn get_room_proctor(&self, room_id: &str) -> Option<String> {
+        self.room_manager.get_room_proctor(room_id).await
+    }
+
+    /// Rooms the proctor currently runs on this instance, oldest first
+    pub async fn rooms_owned_by(&self, proctor_id: &str) -> Vec<String> {
+        self.room_manager.rooms_owned_by(proctor_id).await
+    }
+
+    /// Sets what happens to join requests for `room_id` the proctor leaves unanswered.
+    /// Requests already waiting keep the policy they were made under.
+    pub async fn set_escalation_policy(&self, room_id: &str, policy: EscalationPolicy) -> Result<(), String> {
+        self.room_manager.set_escalation_policy(room_id, policy).await
+    }
+
+    /// Token the proctor of `room_id` presents to room-scoped HTTP routes
+    pub async fn get_proctor_token(&self, room_id: &str) -> Option<String> {
+        self.room_manager.get_proctor_token(room_id).await
+    }
+
+    /// Replaces the room's roster. Students who later join with a peer ID on
+    /// it are bound to its wallet; students already in the room are unchanged.
+    pub async fn set_room_roster(&self, room_id: &str, roster: Roster) -> Result<(), String> {
+        let entries = roster.len();
+        let cleared = roster.is_empty();
+        self.room_manager.set_roster(room_id, roster).await?;
+        if cleared {
+            tracing::info!(room_id = %room_id, "Room roster cleared");
+        } else {
+            tracing::info!(room_id = %room_id, entries = entries, "Room roster uploaded");
+        }
+        Ok(())
+    }
+
+    /// Whether the peer joined `room_id` under an entry of its roster
+    pub async fn is_pre_registered(&self, room_id: &str, peer_id: &str) -> bool {
+        self.room_manager
+            .get_peer(&PeerKey::new(room_id, peer_id))
+            .await
+            .is_some_and(|peer| peer.pre_registered)
+    }
+
+    /// Metadata the proctor last set for a room, kept until its manifest is built
+    pub async fn session_metadata(&self, room_id: &str) -> SessionMetadata {
+        self.room_sessions
+            .read()
+            .await
+            .get(room_id)
+            .map(|session| session.metadata().clone())
+            .unwrap_or_default()
+    }
+
+    pub async fn get_room_locale(&self, room_id: &str) -> RoomLocale {
+        self.room_manager.get_room_locale(room_id).await
+    }
+
+    pub fn get_recording_manager(&self) -> Arc<RecordingManager> {
+        self.recording_manager.clone()
+    }
+
+    // Chain event emission methods
+
+    /// Emits a participant kicked event to the blockchain
+    pub async fn emit_participant_kicked(
+        &self,
+        room_id: &str,
+        kicked_peer_id: &str,
+        reason: Option<String>,
+    ) {
+        // Get wallet addresses for proctor and kicked participant
+        let wallets = self.peer_wallets.read().await;
+        let proctor_id = self.room_manager.get_room_proctor(room_id).await;
+        let proctor_wallet = proctor_id.and_then(|id| wallets.get(&PeerKey::new(room_id, id)).copied());
+        let kicked_wallet = wallets.get(&PeerKey::new(room_id, kicked_peer_id)).copied();
+
+        if let (Some(proctor), Some(kicked)) = (proctor_wallet, kicked_wallet) {
+            self.emit_chain_event(ChainEvent::ParticipantKicked {
+                room_id: room_id.to_string(),
+                proctor,
+                kicked,
+                reason,
+            });
+        } else {
+            tracing::debug!(
+                room_id = %room_id,
+                kicked_peer_id = %kicked_peer_id,
+                "Cannot emit participant kicked event: wallet addresses not available"
+            );
+        }
+    }
+
+    /// Emits an ID verification event to the blockchain
+    pub async fn emit_id_verification(
         &self,
         room_id: &str,
         peer_id: &str,
@@ -1191,8 +3260,16 @@ impl SfuServer {
             _ => ChainVerificationStatus::Pending,
         };
 
+        self.room_sessions
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .record_verification(peer_id, &status.to_lowercase());
+        self.refresh_integrity_score(room_id, peer_id).await;
+
         let wallets = self.peer_wallets.read().await;
-        if let Some(wallet) = wallets.get(peer_id).copied() {
+        if let Some(wallet) = wallets.get(&PeerKey::new(room_id, peer_id)).copied() {
             self.emit_chain_event(ChainEvent::IdVerification {
                 room_id: room_id.to_string(),
                 participant: wallet,
@@ -1226,8 +3303,28 @@ impl SfuServer {
             _ => ChainSuspiciousActivityType::Other,
         };
 
+        let at_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        self.room_sessions
+            .write()
+            .await
+            .entry(room_id.to_string())
+            .or_default()
+            .record_incident(peer_id, &activity_type.to_lowercase(), at_ms);
+        self.recording_manager
+            .record_view_event(
+                room_id,
+                ViewEventKind::Incident,
+                peer_id,
+                serde_json::json!({ "activity_type": activity_type.to_lowercase() }),
+            )
+            .await;
+        self.refresh_integrity_score(room_id, peer_id).await;
+
         let wallets = self.peer_wallets.read().await;
-        if let Some(wallet) = wallets.get(peer_id).copied() {
+        if let Some(wallet) = wallets.get(&PeerKey::new(room_id, peer_id)).copied() {
             self.emit_chain_event(ChainEvent::SuspiciousActivity {
                 room_id: room_id.to_string(),
                 participant: wallet,
@@ -1246,14 +3343,468 @@ impl SfuServer {
     // Signaling helper methods
 
     /// Sends a kick notification to a participant
+    /// Removes a student from `room_id` on the proctor's behalf: tells the
+    /// student, records the kick on-chain, then removes them like any departure,
+    /// which stops their recording and closes their connection
+    pub async fn kick_peer(&self, room_id: &str, target_peer_id: &str, reason: Option<String>) -> Result<(), KickError> {
+        match self.room_manager.get_peer(&PeerKey::new(room_id, target_peer_id)).await {
+            None => return Err(KickError::PeerNotFound(target_peer_id.to_string())),
+            Some(peer) if matches!(peer.role, PeerRole::Proctor) => return Err(KickError::Proctor),
+            Some(_) => {}
+        }
+
+        if let Err(e) = self.send_kick_notification(room_id, target_peer_id, reason.clone()).await {
+            tracing::error!(
+                room_id = %room_id,
+                peer_id = %target_peer_id,
+                error = %e,
+                "Failed to send kick notification"
+            );
+        }
+
+        // While the kicked peer's wallet is still known
+        self.emit_participant_kicked(room_id, target_peer_id, reason).await;
+
+        if let Err(e) = self.remove_peer(room_id, target_peer_id, DisconnectCause::Kicked).await {
+            tracing::error!(
+                peer_id = %target_peer_id,
+                error = %e,
+                "Failed to remove kicked peer"
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether `student` can move to `to_room_id`; returns their display name
+    async fn check_transfer(&self, student: &PeerKey, to_room_id: &str) -> Result<Option<String>, TransferError> {
+        if student.room_id == to_room_id {
+            return Err(TransferError::SameRoom);
+        }
+        if !self.room_manager.room_exists(to_room_id).await {
+            return Err(TransferError::RoomNotFound(to_room_id.to_string()));
+        }
+        let peer = match self.room_manager.get_peer(student).await {
+            None => return Err(TransferError::PeerNotFound(student.peer_id.clone())),
+            Some(peer) if matches!(peer.role, PeerRole::Proctor) => {
+                return Err(TransferError::NotStudent(student.peer_id.clone()))
+            }
+            Some(peer) => peer,
+        };
+        if self.room_manager.get_peer(&PeerKey::new(to_room_id, student.peer_id.as_str())).await.is_some() {
+            return Err(TransferError::AlreadyInRoom(to_room_id.to_string()));
+        }
+        Ok(peer.name)
+    }
+
+    /// Moves a student for `proctor_id`, the proctor of `from_room_id`. The move
+    /// happens at once when they also run the destination; otherwise its proctor
+    /// gets a TransferRequest and `Ok(false)` means the move waits for their answer.
+    pub async fn request_transfer(
+        &self,
+        from_room_id: &str,
+        to_room_id: &str,
+        target_peer_id: &str,
+        proctor_id: &str,
+    ) -> Result<bool, TransferError> {
+        let student = PeerKey::new(from_room_id, target_peer_id);
+        let name = self.check_transfer(&student, to_room_id).await?;
+        let destination_proctor = self
+            .room_manager
+            .get_room_proctor(to_room_id)
+            .await
+            .ok_or_else(|| TransferError::RoomNotFound(to_room_id.to_string()))?;
+        if destination_proctor == proctor_id {
+            self.transfer_peer(from_room_id, to_room_id, target_peer_id).await?;
+            return Ok(true);
+        }
+
+        let destination_proctor = PeerKey::new(to_room_id, destination_proctor);
+        if self.connections.get(&destination_proctor).is_none() {
+            return Err(TransferError::ProctorUnavailable(to_room_id.to_string()));
+        }
+        self.transfers.request(&student, to_room_id);
+        tracing::info!(
+            peer_id = %target_peer_id,
+            from_room_id = %from_room_id,
+            to_room_id = %to_room_id,
+            "Transfer waiting for the destination proctor"
+        );
+        let request = SfuMessage::TransferRequest {
+            from_room_id: from_room_id.to_string(),
+            to_room_id: to_room_id.to_string(),
+            target_peer_id: target_peer_id.to_string(),
+            name,
+            requested_by: proctor_id.to_string(),
+        };
+        self.send_to_peer(&destination_proctor, &request).await;
+        Ok(false)
+    }
+
+    /// Applies the destination proctor's answer to a TransferRequest. The source
+    /// room's proctor is told when the transfer was declined or failed.
+    pub async fn answer_transfer(
+        &self,
+        from_room_id: &str,
+        to_room_id: &str,
+        target_peer_id: &str,
+        approved: bool,
+    ) -> Result<(), TransferError> {
+        if !self.transfers.take_request(&PeerKey::new(from_room_id, target_peer_id), to_room_id) {
+            return Err(TransferError::NoRequest);
+        }
+        let result = if approved {
+            self.transfer_peer(from_room_id, to_room_id, target_peer_id).await
+        } else {
+            Err(TransferError::Refused)
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                if let Some(proctor_id) = self.room_manager.get_room_proctor(from_room_id).await {
+                    let failed = transfer_failed(from_room_id, to_room_id, target_peer_id, &e);
+                    self.send_to_peer(&PeerKey::new(from_room_id, proctor_id), &failed).await;
+                }
+                // Declining is an answer, not an error for the proctor who gave it
+                if e == TransferError::Refused {
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Moves a connected student from `from_room_id` to `to_room_id` without
+    /// them reconnecting or asking to join again. Their tracks leave the old
+    /// room's subscribers, room membership moves, and the connection set up in
+    /// the destination is forwarded under that room's policy. The recording
+    /// continues in a file under the destination's directory. A failure at any
+    /// step returns the student to the source room.
+    pub async fn transfer_peer(&self, from_room_id: &str, to_room_id: &str, target_peer_id: &str) -> Result<(), TransferError> {
+        let from = PeerKey::new(from_room_id, target_peer_id);
+        let to = PeerKey::new(to_room_id, target_peer_id);
+        let name = self.check_transfer(&from, to_room_id).await?;
+        let sender = self
+            .connections
+            .get(&from)
+            .map(|connection| connection.sender.clone())
+            .ok_or_else(|| TransferError::PeerNotFound(target_peer_id.to_string()))?;
+        tracing::info!(peer_id = %target_peer_id, from_room_id = %from_room_id, to_room_id = %to_room_id, "Transferring student");
+
+        self.room_manager.move_student(&from, to_room_id).await.map_err(TransferError::Failed)?;
+
+        // Opened in the destination before anything is torn down, so a failure leaves the student as they were
+        let recording = if self.recording_manager.is_recording(from_room_id, target_peer_id).await {
+            match self.recording_manager.transfer_recording(from_room_id, to_room_id, target_peer_id).await {
+                Ok(restart) => Some(restart),
+                Err(e) => {
+                    tracing::warn!(peer = %from, to_room_id = %to_room_id, error = %e, "Transfer failed moving the recording, rolling back");
+                    self.restore_membership(&to, from_room_id).await;
+                    return Err(TransferError::Failed(e.to_string()));
+                }
+            }
+        } else {
+            None
+        };
+
+        // The old connection goes, and the source proctor's copies of its tracks with it
+        self.close_peer_connection(&from).await;
+        self.track_manager.remove_subscriber(&from).await;
+        let _ = self
+            .update_all_connections_for_peer_removal(target_peer_id, from_room_id, name.clone(), DisconnectCause::Transferred)
+            .await;
+        self.rekey_peer_state(&from, &to).await;
+        if recording.is_some() {
+            self.room_manager.record_event(from_room_id, Some(target_peer_id), RoomEvent::Recording(false)).await;
+            self.room_manager.record_event(to_room_id, Some(target_peer_id), RoomEvent::Recording(true)).await;
+        }
+
+        // Ahead of the destination's offer, so the client answers it on a new peer connection
+        let transferred = SfuMessage::PeerTransferred {
+            from_room_id: from_room_id.to_string(),
+            to_room_id: to_room_id.to_string(),
+            peer_id: target_peer_id.to_string(),
+            name: name.clone(),
+        };
+        send_direct(&sender, &transferred);
+        self.transfers.record_move(&from, to_room_id);
+
+        if let Err(e) = self.add_peer(target_peer_id.to_string(), to_room_id.to_string(), sender.clone()).await {
+            tracing::warn!(peer = %to, error = %e, "Transfer failed connecting the student to the destination, rolling back");
+            let error = TransferError::Failed(e.to_string());
+            self.transfers.undo_move(&from);
+            self.close_peer_connection(&to).await;
+            self.rekey_peer_state(&to, &from).await;
+            self.restore_membership(&to, from_room_id).await;
+            if recording.is_some() {
+                self.room_manager.record_event(to_room_id, Some(target_peer_id), RoomEvent::Recording(false)).await;
+                match self.recording_manager.transfer_recording(to_room_id, from_room_id, target_peer_id).await {
+                    Ok(_) => {
+                        self.room_manager.record_event(from_room_id, Some(target_peer_id), RoomEvent::Recording(true)).await;
+                    }
+                    Err(e) => tracing::error!(peer = %from, error = %e, "Could not move the recording back after a failed transfer"),
+                }
+            }
+            send_direct(&sender, &transfer_failed(from_room_id, to_room_id, target_peer_id, &error));
+            if let Err(e) = self.add_peer(target_peer_id.to_string(), from_room_id.to_string(), sender).await {
+                tracing::error!(peer = %from, error = %e, "Could not reconnect the student to the source room");
+            }
+            return Err(error);
+        }
+
+        self.record_transfer(&from, to_room_id, name, recording.as_ref()).await;
+        Ok(())
+    }
+
+    /// Puts a student moved to `moved.room_id` back in `room_id`
+    async fn restore_membership(&self, moved: &PeerKey, room_id: &str) {
+        if let Err(e) = self.room_manager.move_student(moved, room_id).await {
+            tracing::error!(peer = %moved, room_id = %room_id, error = %e, "Could not return the student to the source room");
+        }
+    }
+
+    /// Moves the wallet and exam grade a peer has in one room to another
+    async fn rekey_peer_state(&self, from: &PeerKey, to: &PeerKey) {
+        let mut wallets = self.peer_wallets.write().await;
+        if let Some(wallet) = wallets.remove(from) {
+            wallets.insert(to.clone(), wallet);
+        }
+        drop(wallets);
+        let mut grades = self.peer_exam_grades.write().await;
+        if let Some(grade) = grades.remove(from) {
+            grades.insert(to.clone(), grade);
+        }
+    }
+
+    /// Session summaries, view events, chain events and proctor notices for a
+    /// student who moved from `from` to `to_room_id`
+    async fn record_transfer(&self, from: &PeerKey, to_room_id: &str, name: Option<String>, recording: Option<&RecordingRestart>) {
+        let PeerKey { room_id: from_room_id, peer_id } = from;
+        self.record_departures(&DepartedPeer {
+            id: peer_id.clone(),
+            room_id: from_room_id.clone(),
+            role: PeerRole::Student,
+            name: name.clone(),
+            cause: DisconnectCause::Transferred,
+            displaced: Vec::new(),
+        })
+        .await;
+        self.room_sessions
+            .write()
+            .await
+            .entry(to_room_id.to_string())
+            .or_default()
+            .record_student(peer_id);
+
+        self.recording_manager
+            .record_view_event(
+                from_room_id,
+                ViewEventKind::Unsubscribed,
+                peer_id,
+                serde_json::json!({ "reason": DisconnectCause::Transferred.as_str(), "to_room_id": to_room_id }),
+            )
+            .await;
+        self.recording_manager
+            .record_view_event(
+                to_room_id,
+                ViewEventKind::Joined,
+                peer_id,
+                serde_json::json!({ "role": "student", "from_room_id": from_room_id }),
+            )
+            .await;
+
+        if let Some(wallet) = self.peer_wallet(to_room_id, peer_id).await {
+            self.room_session_wallet(to_room_id, peer_id, wallet).await;
+            if let Some(recording) = recording {
+                self.emit_recording_stopped(from_room_id, wallet, &recording.stopped);
+            }
+            self.emit_chain_event(ChainEvent::ParticipantLeft {
+                room_id: from_room_id.clone(),
+                participant: wallet,
+                reason: chain_leave_reason(DisconnectCause::Transferred),
+                details: Some(transfer::left_details(to_room_id)),
+            });
+            self.emit_chain_event(ChainEvent::ParticipantJoined {
+                room_id: to_room_id.to_string(),
+                participant: wallet,
+                name: name.clone(),
+                role: ChainRole::Student,
+                details: Some(transfer::joined_details(from_room_id)),
+            });
+            if recording.is_some() {
+                self.emit_chain_event(ChainEvent::RecordingStarted {
+                    room_id: to_room_id.to_string(),
+                    participant: wallet,
+                });
+            }
+        }
+
+        let transferred = SfuMessage::PeerTransferred {
+            from_room_id: from_room_id.clone(),
+            to_room_id: to_room_id.to_string(),
+            peer_id: peer_id.clone(),
+            name,
+        };
+        for room_id in [from_room_id.as_str(), to_room_id] {
+            if let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await {
+                self.send_to_peer(&PeerKey::new(room_id, proctor_id), &transferred).await;
+            }
+        }
+        tracing::info!(peer_id = %peer_id, from_room_id = %from_room_id, to_room_id = %to_room_id, "Student transferred");
+    }
+
+    /// Room a transferred peer now lives in, for the signaling connection that
+    /// still knows it by `peer`; `None` if it was not moved
+    pub fn follow_transfer(&self, peer: &PeerKey) -> Option<String> {
+        self.transfers.follow(peer)
+    }
+
+    /// What closing the room would do right now; `None` if the room does not exist
+    pub async fn close_preview(&self, room_id: &str) -> Option<ClosePreview> {
+        let proctor_id = self.room_manager.get_room_proctor(room_id).await?;
+        let connected_students: Vec<String> = self
+            .room_manager
+            .join_order(room_id)
+            .await
+            .into_iter()
+            .filter(|peer_id| *peer_id != proctor_id)
+            .collect();
+        let unverified_students = {
+            let sessions = self.room_sessions.read().await;
+            let session = sessions.get(room_id);
+            connected_students
+                .iter()
+                .filter(|peer_id| !session.is_some_and(|session| session.is_verified(peer_id)))
+                .cloned()
+                .collect()
+        };
+
+        Some(ClosePreview {
+            active_recordings: self.recording_manager.recording_details(room_id).await,
+            pending_uploads: self.recording_manager.pending_uploads(room_id),
+            unanswered_join_requests: self.admission.unanswered(room_id),
+            connected_students,
+            unverified_students,
+        })
+    }
+
+    /// Closes the room at the proctor's request, as if they had left. Unless
+    /// `force` is set, refuses while a recording is younger than
+    /// `close_min_recording_age` or a connected student is unverified.
+    pub async fn close_room(&self, room_id: &str, reason: Option<String>, force: bool) -> Result<(), CloseRoomError> {
+        let not_found = || CloseRoomError::RoomNotFound(room_id.to_string());
+        let proctor_id = self.room_manager.get_room_proctor(room_id).await.ok_or_else(not_found)?;
+        let preview = self.close_preview(room_id).await.ok_or_else(not_found)?;
+        let blockers = preview.blockers(self.close_min_recording_age);
+        if !force && !blockers.is_empty() {
+            return Err(CloseRoomError::Blocked { blockers, preview });
+        }
+
+        tracing::info!(
+            room_id = %room_id,
+            proctor_id = %proctor_id,
+            reason = ?reason,
+            overridden = blockers.len(),
+            recordings = preview.active_recordings.len(),
+            "Proctor closing room"
+        );
+
+        // Everyone hears why before their connection goes away
+        let message = SfuMessage::RoomClosed {
+            room_id: room_id.to_string(),
+            reason,
+        };
+        self.broadcast(room_id, &message).await;
+
+        if let Err(e) = self
+            .remove_peer_closing_as(room_id, &proctor_id, DisconnectCause::Left, ChainRoomCloseReason::SessionCompleted)
+            .await
+        {
+            tracing::error!(room_id = %room_id, error = %e, "Failed to close room");
+        }
+        Ok(())
+    }
+
+    /// Posts an announcement to everyone in the room
+    pub async fn announce(&self, room_id: &str, text: String) {
+        let announcement = Announcement::now(text);
+        let event = RoomEvent::Announcement(announcement.clone());
+        if self.room_manager.record_event(room_id, None, event).await.is_none() {
+            return;
+        }
+        let message = SfuMessage::Announcement {
+            room_id: room_id.to_string(),
+            text: announcement.text,
+            at: announcement.at,
+        };
+        self.broadcast(room_id, &message).await;
+    }
+
+    /// Starts the exam countdown everyone in the room displays, replacing any earlier one
+    pub async fn start_exam_clock(&self, room_id: &str, duration_secs: u64) {
+        let clock = ExamClock::starting_now(duration_secs);
+        if self.room_manager.record_event(room_id, None, RoomEvent::ExamClock(clock)).await.is_none() {
+            return;
+        }
+        if let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await {
+            self.recording_manager
+                .record_view_event(
+                    room_id,
+                    ViewEventKind::ExamClock,
+                    &proctor_id,
+                    serde_json::json!({ "duration_secs": duration_secs }),
+                )
+                .await;
+        }
+        let message = SfuMessage::ExamClock {
+            room_id: room_id.to_string(),
+            started_at: clock.started_at,
+            duration_secs: clock.duration_secs,
+        };
+        self.broadcast(room_id, &message).await;
+    }
+
+    /// Mutes or unmutes a student at the proctor's request; `false` when the
+    /// target is not a student in the room
+    pub async fn force_mute(&self, room_id: &str, peer_id: &str, muted: bool) -> bool {
+        let key = PeerKey::new(room_id, peer_id);
+        match self.room_manager.get_peer(&key).await {
+            Some(peer) if matches!(peer.role, PeerRole::Student) => {}
+            _ => return false,
+        }
+        self.room_manager.record_event(room_id, Some(peer_id), RoomEvent::Muted(muted)).await;
+        let message = SfuMessage::ForcedMute {
+            room_id: room_id.to_string(),
+            muted,
+        };
+        self.send_to_peer(&key, &message).await;
+        true
+    }
+
+    /// Records a student's answer to the recording consent prompt and passes it on to the proctor
+    pub async fn record_consent(&self, room_id: &str, peer_id: &str, given: bool) {
+        if self.room_manager.get_peer(&PeerKey::new(room_id, peer_id)).await.is_none() {
+            return;
+        }
+        self.room_manager.record_event(room_id, Some(peer_id), RoomEvent::Consent(given)).await;
+        if let Some(proctor_id) = self.room_manager.get_room_proctor(room_id).await {
+            let message = SfuMessage::RecordingConsent {
+                room_id: room_id.to_string(),
+                peer_id: peer_id.to_string(),
+                given,
+            };
+            self.send_to_peer(&PeerKey::new(room_id, proctor_id), &message).await;
+        }
+    }
+
     pub async fn send_kick_notification(
         &self,
         room_id: &str,
         peer_id: &str,
         reason: Option<String>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connections = self.connections.read().await;
-        if let Some(connection) = connections.get(peer_id) {
+        if let Some(connection) = self.connections.get(&PeerKey::new(room_id, peer_id)) {
             let message = SfuMessage::ParticipantKicked {
                 room_id: room_id.to_string(),
                 peer_id: peer_id.to_string(),
@@ -1271,8 +3822,7 @@ impl SfuServer {
         room_id: &str,
         peer_id: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connections = self.connections.read().await;
-        if let Some(connection) = connections.get(peer_id) {
+        if let Some(connection) = self.connections.get(&PeerKey::new(room_id, peer_id)) {
             let message = SfuMessage::StartIdVerification {
                 room_id: room_id.to_string(),
                 peer_id: peer_id.to_string(),
@@ -1290,8 +3840,18 @@ impl SfuServer {
         peer_id: &str,
         status: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let connections = self.connections.read().await;
-        if let Some(connection) = connections.get(peer_id) {
+        self.room_manager
+            .record_event(room_id, Some(peer_id), RoomEvent::Verification(status.to_lowercase()))
+            .await;
+        self.recording_manager
+            .record_view_event(
+                room_id,
+                ViewEventKind::IdVerification,
+                peer_id,
+                serde_json::json!({ "status": status.to_lowercase() }),
+            )
+            .await;
+        if let Some(connection) = self.connections.get(&PeerKey::new(room_id, peer_id)) {
             let message = serde_json::json!({
                 "type": "id_verification_status",
                 "room_id": room_id,
@@ -1303,4 +3863,1632 @@ impl SfuServer {
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::recording_config;
+    use crate::sfu::renegotiation::{PeerRenegotiationStats, RenegotiationTuningUpdate};
+    use webrtc::api::media_engine::MediaEngine;
+    use webrtc::api::APIBuilder;
+    use webrtc::peer_connection::configuration::RTCConfiguration;
+    use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+    use webrtc::peer_connection::signaling_state::RTCSignalingState;
+    use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
+    use webrtc::stats::StatsReportType;
+    use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
+
+    const EARLY_CANDIDATE: &str = "candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host";
+
+    /// Offers H.265 video, which the recording pipeline cannot take, so
+    /// recordings fall back to RTP dumps on any host
+    fn rtp_dump_engine_config() -> WebRtcEngineConfig {
+        let h265 = crate::sfu::CodecConfig {
+            mime_type: "video/H265".to_string(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: String::new(),
+            payload_type: 98,
+        };
+        WebRtcEngineConfig {
+            codecs: vec![h265, crate::sfu::CodecConfig::named("opus").unwrap()],
+            ..Default::default()
+        }
+    }
+
+    async fn next_message_of_type(rx: &mut mpsc::UnboundedReceiver<Message>, message_type: &str) -> serde_json::Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+                .await
+                .expect("timed out waiting for message")
+                .expect("channel closed");
+            let message: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+            if message["type"] == message_type {
+                return message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_early_ice_candidates_applied_after_approval() {
+        let server = SfuServer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.track_pending_student("123456", "student_1".to_string(), None, "student".to_string(), None, tx.clone()).await.unwrap();
+
+        let key = PeerKey::new("123456", "student_1");
+
+        // Trickled before the proctor approved; there is no connection yet
+        server
+            .handle_ice_candidate("123456", "student_1", EARLY_CANDIDATE, Some("0".to_string()), Some(0))
+            .await
+            .unwrap();
+        assert!(server.admission.is_pending("123456", "student_1"));
+        assert_eq!(server.negotiation.queued_ice_candidates(&key), None);
+
+        server.add_peer("student_1".to_string(), "123456".to_string(), tx).await.unwrap();
+        assert!(!server.admission.is_pending("123456", "student_1"));
+        assert_eq!(server.negotiation.queued_ice_candidates(&key), Some(1));
+
+        let offer = next_message_of_type(&mut rx, "offer").await;
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let client_api = APIBuilder::new().with_media_engine(media_engine).build();
+        let client = client_api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        client
+            .set_remote_description(RTCSessionDescription::offer(offer["sdp"].as_str().unwrap().to_string()).unwrap())
+            .await
+            .unwrap();
+        let answer = client.create_answer(None).await.unwrap();
+        client.set_local_description(answer.clone()).await.unwrap();
+
+        server.handle_answer("123456", "student_1", &answer.sdp).await.unwrap();
+        assert_eq!(server.negotiation.queued_ice_candidates(&key), None);
+
+        // The ICE agent adds remote candidates in the background
+        let connection = server.connections.get(&key).unwrap();
+        let applied = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let stats = connection.peer_connection.get_stats().await;
+                let found = stats.reports.values().any(|report| {
+                    matches!(report, StatsReportType::RemoteCandidate(candidate) if candidate.ip == "192.0.2.1")
+                });
+                if found {
+                    break;
+                }
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await;
+        assert!(applied.is_ok(), "early candidate was never added to the peer connection");
+
+        client.close().await.unwrap();
+        server.remove_peer("123456", "student_1", DisconnectCause::Left).await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    /// Clients answering the moment the offer arrives race the end of
+    /// `add_peer`; every answer must land, wherever the race falls
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_immediate_answers_never_strand_negotiation() {
+        const ROUNDS: usize = 2000;
+        const CONCURRENT: usize = 32;
+
+        let server = Arc::new(SfuServer::new());
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let client_api = Arc::new(APIBuilder::new().with_media_engine(media_engine).build());
+
+        let mut rounds = tokio::task::JoinSet::new();
+        let mut stuck = 0;
+        for round in 0..ROUNDS {
+            if rounds.len() >= CONCURRENT {
+                stuck += usize::from(rounds.join_next().await.unwrap().unwrap() != RTCSignalingState::Stable);
+            }
+            let server = server.clone();
+            let client_api = client_api.clone();
+            rounds.spawn(async move {
+                let peer_id = format!("student_{}", round);
+                let (tx, mut rx) = mpsc::unbounded_channel();
+                let client = client_api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+
+                let answering = async {
+                    let offer = next_message_of_type(&mut rx, "offer").await;
+                    client
+                        .set_remote_description(RTCSessionDescription::offer(offer["sdp"].as_str().unwrap().to_string()).unwrap())
+                        .await
+                        .unwrap();
+                    let answer = client.create_answer(None).await.unwrap();
+                    client.set_local_description(answer.clone()).await.unwrap();
+                    server.handle_answer("123456", &peer_id, &answer.sdp).await.unwrap();
+                };
+                let (added, ()) = tokio::join!(server.add_peer(peer_id.clone(), "123456".to_string(), tx), answering);
+                added.unwrap();
+
+                let connection = server.connections.get(&PeerKey::new("123456", peer_id.as_str())).unwrap();
+                let state = connection.peer_connection.signaling_state();
+                client.close().await.unwrap();
+                server.remove_peer("123456", &peer_id, DisconnectCause::Left).await.unwrap();
+                state
+            });
+        }
+        while let Some(state) = rounds.join_next().await {
+            stuck += usize::from(state.unwrap() != RTCSignalingState::Stable);
+        }
+
+        assert_eq!(stuck, 0, "negotiations left waiting for their answer");
+        assert_eq!(server.connections.count(), 0);
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_signaling_during_setup_is_replayed() {
+        let server = SfuServer::new();
+        let key = PeerKey::new("123456", "student_1");
+
+        // Held while a setup is in progress instead of being dropped for want of a connection
+        let (tx, _rx) = mpsc::unbounded_channel();
+        assert!(server.connections.begin_setup(&key));
+        server
+            .handle_ice_candidate("123456", "student_1", EARLY_CANDIDATE, Some("0".to_string()), Some(0))
+            .await
+            .unwrap();
+        let held = server.connections.finish_setup(&key);
+        assert_eq!(held.len(), 1);
+
+        // Replayed once the connection exists, it joins the queue flushed by the answer
+        server.add_peer("student_1".to_string(), "123456".to_string(), tx.clone()).await.unwrap();
+        assert_eq!(server.negotiation.queued_ice_candidates(&key), Some(0));
+        server.replay_held_signals(&key, held).await;
+        assert_eq!(server.negotiation.queued_ice_candidates(&key), Some(1));
+
+        // A second join while the first is connected is ignored
+        server.add_peer("student_1".to_string(), "123456".to_string(), tx).await.unwrap();
+        assert_eq!(server.connections.count(), 1);
+
+        server.remove_peer("123456", "student_1", DisconnectCause::Left).await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[test]
+    fn test_disconnect_causes_map_to_leave_reasons() {
+        assert_eq!(chain_leave_reason(DisconnectCause::Left), ChainLeaveReason::Normal);
+        assert_eq!(chain_leave_reason(DisconnectCause::Kicked), ChainLeaveReason::Kicked);
+        assert_eq!(chain_leave_reason(DisconnectCause::ConnectionLost), ChainLeaveReason::Disconnected);
+        assert_eq!(chain_leave_reason(DisconnectCause::RoomClosed), ChainLeaveReason::RoomClosed);
+        assert_eq!(chain_leave_reason(DisconnectCause::Transferred), ChainLeaveReason::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_participant_left_carries_disconnect_cause() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_leave".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_leave".to_string(), room_id.clone(), tx).await.unwrap();
+
+        for (student, cause) in [
+            ("student_left", DisconnectCause::Left),
+            ("student_kicked", DisconnectCause::Kicked),
+            ("student_dropped", DisconnectCause::ConnectionLost),
+        ] {
+            server.room_manager.join_room(room_id.clone(), student.to_string(), None).await.unwrap();
+            server.remove_peer(&room_id, student, cause).await.unwrap();
+
+            let left = next_message_of_type(&mut rx, "ParticipantLeft").await;
+            assert_eq!(left["peer_id"], student);
+            assert_eq!(left["reason"], cause.as_str());
+        }
+
+        server.remove_peer(&room_id, "proctor_leave", DisconnectCause::Left).await.unwrap();
+        assert!(!server.room_exists(&room_id).await);
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    /// Local track as the SFU forwards it from `source_peer_id`
+    fn forwarded_track(source_peer_id: &str, kind: &str) -> Arc<TrackLocalStaticRTP> {
+        let mime_type = if kind == "video" { "video/VP8" } else { "audio/opus" };
+        Arc::new(TrackLocalStaticRTP::new(
+            RTCRtpCodecCapability { mime_type: mime_type.to_string(), ..Default::default() },
+            format!("{}_{}_1", source_peer_id, kind),
+            stream_id(source_peer_id),
+        ))
+    }
+
+    async fn senders_with_tracks(connection: &SfuConnection) -> usize {
+        let mut count = 0;
+        for sender in connection.peer_connection.get_senders().await {
+            if sender.track().await.is_some() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[tokio::test]
+    async fn test_departed_student_tracks_removed_and_renegotiated() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_rm".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_rm".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        for student in ["student_a", "student_b"] {
+            server.room_manager.join_room(room_id.clone(), student.to_string(), None).await.unwrap();
+            let (tx, _rx) = mpsc::unbounded_channel();
+            server.add_peer(student.to_string(), room_id.clone(), tx).await.unwrap();
+        }
+
+        // The proctor receives both students' tracks
+        let proctor = server.connections.get(&PeerKey::new(room_id.as_str(), "proctor_rm")).unwrap();
+        for track in [
+            forwarded_track("student_a", "video"),
+            forwarded_track("student_a", "audio"),
+            forwarded_track("student_b", "video"),
+        ] {
+            proctor.peer_connection.add_track(track).await.unwrap();
+        }
+        assert_eq!(senders_with_tracks(&proctor).await, 3);
+
+        // Settle the proctor's initial offer so a renegotiation can go out
+        let offer = next_message_of_type(&mut proctor_rx, "offer").await;
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let client_api = APIBuilder::new().with_media_engine(media_engine).build();
+        let client = client_api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        client
+            .set_remote_description(RTCSessionDescription::offer(offer["sdp"].as_str().unwrap().to_string()).unwrap())
+            .await
+            .unwrap();
+        let answer = client.create_answer(None).await.unwrap();
+        client.set_local_description(answer.clone()).await.unwrap();
+        server.handle_answer(&room_id, "proctor_rm", &answer.sdp).await.unwrap();
+
+        server.remove_peer(&room_id, "student_a", DisconnectCause::Left).await.unwrap();
+        assert_eq!(senders_with_tracks(&proctor).await, 1);
+
+        let renegotiate = next_message_of_type(&mut proctor_rx, "renegotiate").await;
+        let sdp = renegotiate["sdp"].as_str().unwrap();
+        assert!(!sdp.contains(&stream_id("student_a")));
+        assert!(sdp.contains(&stream_id("student_b")));
+
+        client.close().await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_client_offer_refused_during_glare_then_answered() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_offer".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_offer".to_string(), room_id.clone(), tx).await.unwrap();
+        let key = PeerKey::new(room_id.as_str(), "proctor_offer");
+        let connection = server.connections.get(&key).unwrap();
+
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let client_api = APIBuilder::new().with_media_engine(media_engine).build();
+        let client = client_api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        let screen = Arc::new(TrackLocalStaticRTP::new(
+            RTCRtpCodecCapability { mime_type: "video/VP8".to_string(), ..Default::default() },
+            "proctor_offer_screen_1".to_string(),
+            stream_id("proctor_offer"),
+        ));
+        client.add_track(screen).await.unwrap();
+
+        // The client offers screen share before answering the SFU's offer
+        let offer = next_message_of_type(&mut rx, "offer").await;
+        let early_offer = client.create_offer(None).await.unwrap();
+        assert_eq!(
+            server.handle_client_offer(&room_id, "proctor_offer", &early_offer.sdp).await,
+            Err(ClientOfferError::Glare)
+        );
+        assert_eq!(connection.peer_connection.signaling_state(), RTCSignalingState::HaveLocalOffer);
+
+        // The SFU's offer is unaffected and still completes
+        client
+            .set_remote_description(RTCSessionDescription::offer(offer["sdp"].as_str().unwrap().to_string()).unwrap())
+            .await
+            .unwrap();
+        let answer = client.create_answer(None).await.unwrap();
+        client.set_local_description(answer.clone()).await.unwrap();
+        server.handle_answer(&room_id, "proctor_offer", &answer.sdp).await.unwrap();
+        assert_eq!(connection.peer_connection.signaling_state(), RTCSignalingState::Stable);
+
+        // Once stable, the resent offer is answered
+        let client_offer = client.create_offer(None).await.unwrap();
+        client.set_local_description(client_offer.clone()).await.unwrap();
+        server.handle_client_offer(&room_id, "proctor_offer", &client_offer.sdp).await.unwrap();
+        let reply = next_message_of_type(&mut rx, "answer").await;
+        assert_eq!(reply["peer_id"], "sfu");
+        client
+            .set_remote_description(RTCSessionDescription::answer(reply["sdp"].as_str().unwrap().to_string()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(client.signaling_state(), RTCSignalingState::Stable);
+        assert_eq!(connection.peer_connection.signaling_state(), RTCSignalingState::Stable);
+
+        assert_eq!(
+            server.handle_client_offer(&room_id, "nobody", &client_offer.sdp).await,
+            Err(ClientOfferError::NoConnection)
+        );
+
+        client.close().await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    /// Drives a burst of track changes at a proctor whose client answers every
+    /// offer, under `update`, and returns the proctor's renegotiation stats
+    async fn renegotiations_for_track_burst(update: RenegotiationTuningUpdate) -> PeerRenegotiationStats {
+        const CHANGES: u32 = 12;
+        const SPACING: Duration = Duration::from_millis(40);
+
+        let server = SfuServer::new();
+        server.renegotiation().update(&update).unwrap();
+        let room_id = server
+            .create_room("proctor_burst".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_burst".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        let key = PeerKey::new(room_id.as_str(), "proctor_burst");
+
+        let mut media_engine = MediaEngine::default();
+        media_engine.register_default_codecs().unwrap();
+        let client_api = APIBuilder::new().with_media_engine(media_engine).build();
+        let client = client_api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+
+        // Answers the initial offer and every renegotiation until the server goes quiet
+        let answering = async {
+            while let Ok(Some(message)) = tokio::time::timeout(Duration::from_secs(2), proctor_rx.recv()).await {
+                let message: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+                if message["type"] != "offer" && message["type"] != "renegotiate" {
+                    continue;
+                }
+                let offer = RTCSessionDescription::offer(message["sdp"].as_str().unwrap().to_string()).unwrap();
+                client.set_remote_description(offer).await.unwrap();
+                let answer = client.create_answer(None).await.unwrap();
+                client.set_local_description(answer.clone()).await.unwrap();
+                server.handle_answer(&room_id, "proctor_burst", &answer.sdp).await.unwrap();
+            }
+        };
+        let burst = async {
+            for _ in 0..CHANGES {
+                server.schedule_renegotiation(&key);
+                sleep(SPACING).await;
+            }
+        };
+        tokio::join!(answering, burst);
+
+        let stats = server.renegotiation().peer_stats(&key).unwrap();
+        assert_eq!(stats.tracks_offered, u64::from(CHANGES), "every change reaches the client");
+        assert_eq!(stats.answers, stats.offers_sent);
+
+        client.close().await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+        stats
+    }
+
+    #[tokio::test]
+    async fn test_debounce_setting_changes_offer_count_under_load() {
+        // No debounce: each change goes out on its own while the client keeps up
+        let immediate = renegotiations_for_track_burst(RenegotiationTuningUpdate {
+            debounce_ms: Some(0),
+            max_batch_ms: Some(0),
+            ..Default::default()
+        })
+        .await;
+
+        // A debounce longer than the gap between changes folds the burst into one offer
+        let debounced = renegotiations_for_track_burst(RenegotiationTuningUpdate {
+            debounce_ms: Some(300),
+            max_batch_ms: Some(2000),
+            ..Default::default()
+        })
+        .await;
+
+        assert!(immediate.offers_sent >= 6, "{} offers without a debounce", immediate.offers_sent);
+        assert_eq!(debounced.offers_sent, 1);
+        assert!(debounced.last_answer_ms.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dead_peer_connection_removes_peer() {
+        let mut server = SfuServer::new();
+        server.disconnect_grace = Duration::from_secs(10);
+        let room_id = server
+            .create_room("proctor_dead".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_dead".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        for student in ["student_1", "student_2"] {
+            server.room_manager.join_room(room_id.clone(), student.to_string(), None).await.unwrap();
+            let (tx, _rx) = mpsc::unbounded_channel();
+            server.add_peer(student.to_string(), room_id.clone(), tx).await.unwrap();
+        }
+        let key = |peer_id: &str| PeerKey::new(room_id.as_str(), peer_id);
+        let change = |peer_id: &str, state| PeerStateChange {
+            peer: key(peer_id),
+            state,
+            connection: Arc::downgrade(&server.connections.get(&key(peer_id)).unwrap().peer_connection),
+        };
+
+        // Failed: removed at once, with the proctor told the connection was lost
+        server.handle_peer_state_change(change("student_1", RTCPeerConnectionState::Failed)).await;
+        assert!(!server.connections.contains(&key("student_1")));
+        assert!(server.room_manager.get_peer(&key("student_1")).await.is_none());
+        let left = next_message_of_type(&mut proctor_rx, "ParticipantLeft").await;
+        assert_eq!(left["peer_id"], "student_1");
+        assert_eq!(left["reason"], "connection_lost");
+
+        // Reports about a connection the peer no longer uses are ignored
+        let stale = PeerStateChange {
+            peer: key("student_2"),
+            state: RTCPeerConnectionState::Failed,
+            connection: std::sync::Weak::new(),
+        };
+        server.handle_peer_state_change(stale).await;
+        assert!(server.connections.contains(&key("student_2")));
+
+        // Disconnected: only removed once the grace period passes without recovering
+        let start = std::time::Instant::now();
+        server.handle_peer_state_change(change("student_2", RTCPeerConnectionState::Disconnected)).await;
+        server.handle_peer_state_change(change("student_2", RTCPeerConnectionState::Connected)).await;
+        server.expire_disconnected_peers(start + Duration::from_secs(11)).await;
+        assert!(server.connections.contains(&key("student_2")));
+
+        server.handle_peer_state_change(change("student_2", RTCPeerConnectionState::Disconnected)).await;
+        server.expire_disconnected_peers(std::time::Instant::now() + Duration::from_secs(5)).await;
+        assert!(server.connections.contains(&key("student_2")));
+        server.expire_disconnected_peers(std::time::Instant::now() + Duration::from_secs(11)).await;
+        assert!(!server.connections.contains(&key("student_2")));
+        assert!(server.room_manager.get_peer(&key("student_2")).await.is_none());
+        assert!(server.connections.contains(&key("proctor_dead")));
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_kick_removes_student_and_notifies_them() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_kick".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_kick".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        server.room_manager.join_room(room_id.clone(), "student_1".to_string(), None).await.unwrap();
+        let (student_tx, mut student_rx) = mpsc::unbounded_channel();
+        server.add_peer("student_1".to_string(), room_id.clone(), student_tx).await.unwrap();
+
+        assert_eq!(
+            server.kick_peer(&room_id, "nobody", None).await,
+            Err(KickError::PeerNotFound("nobody".to_string()))
+        );
+        assert_eq!(server.kick_peer(&room_id, "proctor_kick", None).await, Err(KickError::Proctor));
+        assert!(server.room_exists(&room_id).await);
+
+        server.kick_peer(&room_id, "student_1", Some("Phone in view".to_string())).await.unwrap();
+        let kicked = next_message_of_type(&mut student_rx, "ParticipantKicked").await;
+        assert_eq!(kicked["reason"], "Phone in view");
+        let left = next_message_of_type(&mut proctor_rx, "ParticipantLeft").await;
+        assert_eq!(left["reason"], "kicked");
+        assert!(!server.connections.contains(&PeerKey::new(room_id.as_str(), "student_1")));
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_id.as_str(), "student_1")).await.is_none());
+
+        // Kicking twice finds nobody the second time
+        assert!(server.kick_peer(&room_id, "student_1", None).await.is_err());
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_close_room_refused_until_forced() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_close".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_close".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        let mut student_rxs = Vec::new();
+        for student in ["student_1", "student_2"] {
+            server.room_manager.join_room(room_id.clone(), student.to_string(), None).await.unwrap();
+            let (student_tx, student_rx) = mpsc::unbounded_channel();
+            server.add_peer(student.to_string(), room_id.clone(), student_tx).await.unwrap();
+            student_rxs.push(student_rx);
+        }
+        server.emit_id_verification(&room_id, "student_1", "Valid", "proctor_close").await;
+
+        let preview = server.close_preview(&room_id).await.unwrap();
+        assert_eq!(preview.connected_students, vec!["student_1", "student_2"]);
+        assert_eq!(preview.unverified_students, vec!["student_2"]);
+        assert!(preview.unanswered_join_requests.is_empty());
+
+        match server.close_room(&room_id, None, false).await {
+            Err(CloseRoomError::Blocked { blockers, preview: refused }) => {
+                assert!(blockers.contains(&CloseBlocker::UnverifiedStudent { peer_id: "student_2".to_string() }));
+                assert_eq!(refused.connected_students, preview.connected_students);
+            }
+            other => panic!("unforced close went through: {:?}", other),
+        }
+        assert!(server.room_exists(&room_id).await);
+
+        server.close_room(&room_id, Some("Exam over".to_string()), true).await.unwrap();
+        for rx in student_rxs.iter_mut().chain(std::iter::once(&mut proctor_rx)) {
+            let closed = next_message_of_type(rx, "RoomClosed").await;
+            assert_eq!(closed["reason"], "Exam over");
+        }
+        assert!(!server.room_exists(&room_id).await);
+        assert!(!server.connections.contains(&PeerKey::new(room_id.as_str(), "student_2")));
+        assert_eq!(
+            server.close_room(&room_id, None, true).await,
+            Err(CloseRoomError::RoomNotFound(room_id.clone()))
+        );
+        assert!(server.close_preview(&room_id).await.is_none());
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    /// Drops the student's connection and joins again, returning the StateSync
+    /// it is sent first and the new receiver
+    async fn refresh(server: &SfuServer, room_id: &str, peer_id: &str) -> (serde_json::Value, mpsc::UnboundedReceiver<Message>) {
+        server.remove_peer(room_id, peer_id, DisconnectCause::ConnectionLost).await.unwrap();
+        server.room_manager.join_room(room_id.to_string(), peer_id.to_string(), None).await.unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.add_peer(peer_id.to_string(), room_id.to_string(), tx).await.unwrap();
+        let first: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(first["type"], "StateSync");
+        (first, rx)
+    }
+
+    #[tokio::test]
+    async fn test_state_sync_after_refresh_reflects_each_change() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_sync".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_sync".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        server.room_manager.join_room(room_id.clone(), "student_1".to_string(), None).await.unwrap();
+        let (student_tx, _student_rx) = mpsc::unbounded_channel();
+        server.add_peer("student_1".to_string(), room_id.clone(), student_tx).await.unwrap();
+
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["announcements"], serde_json::json!([]));
+        assert_eq!(state["seq"], 0);
+
+        server.announce(&room_id, "Ten minutes left".to_string()).await;
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["announcements"][0]["text"], "Ten minutes left");
+
+        server.send_verification_result(&room_id, "student_1", "Valid").await.unwrap();
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["verification_status"], "valid");
+
+        server.record_consent(&room_id, "student_1", true).await;
+        let consent = next_message_of_type(&mut proctor_rx, "RecordingConsent").await;
+        assert_eq!(consent["peer_id"], "student_1");
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["consent"], true);
+
+        assert!(server.force_mute(&room_id, "student_1", true).await);
+        assert!(!server.force_mute(&room_id, "proctor_sync", true).await);
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["muted"], true);
+
+        server.start_exam_clock(&room_id, 3600).await;
+        let (state, _rx) = refresh(&server, &room_id, "student_1").await;
+        assert_eq!(state["exam_clock"]["duration_secs"], 3600);
+        // Everything before is still there, each kind once
+        assert_eq!(state["announcements"].as_array().unwrap().len(), 1);
+        assert_eq!(state["verification_status"], "valid");
+        assert_eq!(state["consent"], true);
+        assert_eq!(state["muted"], true);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_integrity_score_follows_incidents_and_verification() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_integrity".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_integrity".to_string(), room_id.clone(), tx).await.unwrap();
+        for student in ["student_1", "student_2"] {
+            server.room_sessions.write().await.entry(room_id.clone()).or_default().record_student(student);
+        }
+
+        server.emit_suspicious_activity(&room_id, "student_1", "tab_switch", None).await;
+        let update = next_message_of_type(&mut rx, "IntegrityScoreUpdated").await;
+        assert_eq!(update["peer_id"], "student_1");
+        assert_eq!(update["score"], 9700);
+
+        server.emit_id_verification(&room_id, "student_2", "Invalid", "proctor_integrity").await;
+        let update = next_message_of_type(&mut rx, "IntegrityScoreUpdated").await;
+        assert_eq!(update["peer_id"], "student_2");
+        assert_eq!(update["score"], 7000);
+
+        let ranked: Vec<_> = server
+            .integrity_scores(&room_id)
+            .await
+            .into_iter()
+            .map(|(peer_id, scored)| (peer_id, scored.score))
+            .collect();
+        assert_eq!(ranked, vec![("student_2".to_string(), 7000), ("student_1".to_string(), 9700)]);
+
+        let breakdown = server.integrity_score(&room_id, "student_2").await.unwrap();
+        assert_eq!(breakdown.penalties.last().unwrap().input, "verification.invalid");
+        assert!(server.integrity_score(&room_id, "proctor_integrity").await.is_none());
+        assert!(server.integrity_score("000000", "student_1").await.is_none());
+
+        server.remove_peer(&room_id, "proctor_integrity", DisconnectCause::Left).await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_room_overview_reports_integrity_scores() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_overview".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, _proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_overview".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        let (student_tx, _student_rx) = mpsc::unbounded_channel();
+        server
+            .add_peer_with_role("student_overview".to_string(), room_id.clone(), "student".to_string(), None, None, student_tx)
+            .await
+            .unwrap();
+        server.emit_suspicious_activity(&room_id, "student_overview", "tab_switch", None).await;
+
+        let detail = server.room_detail(&room_id).await.unwrap();
+        let scores: Vec<_> = detail.peers.iter().map(|peer| (peer.peer_id.as_str(), peer.integrity_score)).collect();
+        assert_eq!(scores, vec![("proctor_overview", None), ("student_overview", Some(9700))]);
+        assert_eq!(detail.room.integrity_scores, BTreeMap::from([("student_overview".to_string(), 9700)]));
+
+        let overviews = server.room_overviews().await;
+        let overview = overviews.iter().find(|overview| overview.room.room_id == room_id).unwrap();
+        assert_eq!(overview.integrity_scores, detail.room.integrity_scores);
+        let json = serde_json::to_value(&detail).unwrap();
+        assert!(json["peers"][0].get("integrity_score").is_none());
+        assert_eq!(json["peers"][1]["integrity_score"], 9700);
+
+        server.remove_peer(&room_id, "proctor_overview", DisconnectCause::Left).await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    /// Publisher that counts as ready without any media flowing
+    struct AlwaysReady;
+
+    impl MediaRoutingService for AlwaysReady {
+        fn track_published(&self, _peer: &PeerKey) -> usize {
+            1
+        }
+
+        fn published_tracks(&self, _peer: &PeerKey) -> usize {
+            1
+        }
+
+        fn forget(&self, _peer: &PeerKey) {}
+    }
+
+    #[tokio::test]
+    async fn test_proctor_readiness_comes_from_media_routing() {
+        let server = SfuServer::builder()
+            .engine_config(WebRtcEngineConfig::default())
+            .media_routing(Arc::new(AlwaysReady))
+            .build()
+            .unwrap();
+        let room_id = server
+            .create_room("proctor_ready".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        assert!(server.is_proctor_ready(&room_id).await);
+
+        // Without a proctor there is nobody to be ready
+        assert!(!server.is_proctor_ready("000000").await);
+
+        let server = SfuServer::builder().engine_config(WebRtcEngineConfig::default()).build().unwrap();
+        let room_id = server
+            .create_room("proctor_ready".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        assert!(!server.is_proctor_ready(&room_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_connection_recipe_follows_engine_config() {
+        let engine_config = WebRtcEngineConfig {
+            codecs: ["h264", "vp8", "opus"]
+                .iter()
+                .map(|name| crate::sfu::CodecConfig::named(name).unwrap())
+                .collect(),
+            ..Default::default()
+        };
+        let server = SfuServer::builder().engine_config(engine_config).build().unwrap();
+        let keepalive = Keepalive {
+            ping_interval: Duration::from_secs(15),
+            idle_timeout: Some(Duration::from_secs(45)),
+            max_unexpected_frames: 3,
+        };
+
+        let recipe = server.connection_recipe(RecipeRole::Student, ClientKind::Web, keepalive);
+        let codecs: Vec<&str> = recipe.codecs.iter().map(|codec| codec.mime_type.as_str()).collect();
+        assert_eq!(codecs, vec!["video/H264", "video/VP8", "audio/opus"]);
+        assert_eq!(recipe.limits.max_sdp_bytes, max_sdp_bytes());
+        assert_eq!(recipe.limits.max_unexpected_frames, 3);
+        assert_eq!(recipe.heartbeat.server_ping_interval_secs, Some(15));
+        assert_eq!(recipe.features.recording, server.recording_manager.is_enabled());
+        assert!(!recipe.ice_servers.is_empty());
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_session_metadata_propagates_to_summary_and_exam_result() {
+        let server = SfuServer::new();
+        let room_id = server
+            .create_room("proctor_meta".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+
+        let first = SessionMetadata::sanitized(Some("Quiz".to_string()), None, None);
+        server.set_session_metadata(&room_id, "proctor_meta", first).await.unwrap();
+        let latest = SessionMetadata::sanitized(
+            Some("Midterm".to_string()),
+            Some("CS101".to_string()),
+            Some("Calculators allowed".to_string()),
+        );
+        server.set_session_metadata(&room_id, "proctor_meta", latest.clone()).await.unwrap();
+
+        // Last write wins everywhere the metadata is kept
+        assert_eq!(server.room_manager.get_room(&room_id).await.unwrap().metadata, latest);
+        assert_eq!(server.session_metadata(&room_id).await, latest);
+        assert_eq!(server.recording_manager.session_metadata(&room_id).await, latest);
+
+        let wallet = parse_address("0x1111111111111111111111111111111111111111").unwrap();
+        let submitted = ExamGrade { grade: 8500, exam_name: Some("Student title".to_string()) };
+        match exam_result_event(&room_id, wallet, Some(&submitted), &server.session_metadata(&room_id).await) {
+            ChainEvent::CreateExamResult { grade, exam_name, .. } => {
+                assert_eq!(grade, 8500);
+                assert_eq!(exam_name, "CS101: Midterm");
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        assert!(server.set_session_metadata("000000", "proctor_meta", latest).await.is_err());
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[test]
+    fn test_exam_result_event_falls_back_without_metadata() {
+        let wallet = parse_address("0x1111111111111111111111111111111111111111").unwrap();
+        let submitted = ExamGrade { grade: 7000, exam_name: Some("Final".to_string()) };
+
+        let event = exam_result_event("123456", wallet, Some(&submitted), &SessionMetadata::default());
+        assert!(matches!(event, ChainEvent::CreateExamResult { ref exam_name, .. } if exam_name == "Final"));
+
+        let event = exam_result_event("123456", wallet, None, &SessionMetadata::default());
+        assert!(matches!(
+            event,
+            ChainEvent::CreateExamResult { grade: 0, ref exam_name, .. } if exam_name == "Exam Session 123456"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_denied_student_ice_buffer_dropped() {
+        let server = SfuServer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        server.track_pending_student("123456", "student_1".to_string(), None, "student".to_string(), None, tx).await.unwrap();
+        server
+            .handle_ice_candidate("123456", "student_1", EARLY_CANDIDATE, Some("0".to_string()), Some(0))
+            .await
+            .unwrap();
+
+        server.send_join_response("123456".to_string(), "student_1".to_string(), false).await.unwrap();
+        next_message_of_type(&mut rx, "join_denied").await;
+        assert!(!server.admission.is_pending("123456", "student_1"));
+
+        // Later candidates have nowhere to go and are not kept
+        server
+            .handle_ice_candidate("123456", "student_1", EARLY_CANDIDATE, Some("0".to_string()), Some(0))
+            .await
+            .unwrap();
+        assert_eq!(server.negotiation.queued_ice_candidates(&PeerKey::new("123456", "student_1")), None);
+        assert!(!server.admission.is_pending("123456", "student_1"));
+    }
+
+    /// Room with its proctor connected and one student's request forwarded,
+    /// returning the proctor's and the student's channels
+    async fn room_with_join_request(
+        server: &SfuServer,
+        policy: EscalationPolicy,
+    ) -> (String, mpsc::UnboundedReceiver<Message>, mpsc::UnboundedReceiver<Message>) {
+        let room_id = server
+            .create_room("proctor_esc".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        server.set_escalation_policy(&room_id, policy).await.unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_esc".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+
+        let (student_tx, student_rx) = mpsc::unbounded_channel();
+        server
+            .track_pending_student(&room_id, "student_1".to_string(), Some("Ada".to_string()), "student".to_string(), None, student_tx)
+            .await
+            .unwrap();
+        server
+            .forward_join_request(room_id.clone(), "student_1".to_string(), Some("Ada".to_string()), "student".to_string(), None)
+            .await
+            .unwrap();
+        let request = next_message_of_type(&mut proctor_rx, "JoinRequest").await;
+        assert!(request.get("reminder").is_none());
+        (room_id, proctor_rx, student_rx)
+    }
+
+    fn message_types(rx: &mut mpsc::UnboundedReceiver<Message>) -> Vec<String> {
+        std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|message| serde_json::from_str::<serde_json::Value>(message.to_str().unwrap()).unwrap())
+            .map(|message| message["type"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_join_request_reminds_then_auto_approves() {
+        let server = SfuServer::new();
+        let (room_id, mut proctor_rx, mut student_rx) = room_with_join_request(&server, EscalationPolicy::AutoApprove).await;
+        let requested = std::time::Instant::now();
+
+        server.escalate_pending_students(requested + Duration::from_secs(61)).await;
+        let reminder = next_message_of_type(&mut proctor_rx, "JoinRequest").await;
+        assert_eq!(reminder["reminder"], true);
+        assert_eq!(reminder["peer_id"], "student_1");
+        assert_eq!(reminder["name"], "Ada");
+        assert!(message_types(&mut student_rx).is_empty());
+
+        server.escalate_pending_students(requested + Duration::from_secs(121)).await;
+        let approved = next_message_of_type(&mut student_rx, "join_approved").await;
+        assert_eq!(approved["automatic"], true);
+        let escalated = next_message_of_type(&mut proctor_rx, "JoinRequestEscalated").await;
+        assert_eq!(escalated["action"], "auto_approved");
+        assert_eq!(escalated["room_id"], room_id.as_str());
+
+        // Approved like any other request: it waits for the student to join
+        assert!(server.admission.is_pending(&room_id, "student_1"));
+    }
+
+    #[tokio::test]
+    async fn test_proctor_answer_before_fallback_wins() {
+        let server = SfuServer::new();
+        let (room_id, mut proctor_rx, mut student_rx) = room_with_join_request(&server, EscalationPolicy::AutoDeny).await;
+        let requested = std::time::Instant::now();
+
+        server.escalate_pending_students(requested + Duration::from_secs(61)).await;
+        next_message_of_type(&mut proctor_rx, "JoinRequest").await;
+        server.send_join_response(room_id.clone(), "student_1".to_string(), true).await.unwrap();
+        next_message_of_type(&mut student_rx, "join_approved").await;
+
+        server.escalate_pending_students(requested + Duration::from_secs(121)).await;
+        assert!(!message_types(&mut student_rx).contains(&"join_denied".to_string()));
+        assert!(!message_types(&mut proctor_rx).contains(&"JoinRequestEscalated".to_string()));
+        assert!(server.admission.is_pending(&room_id, "student_1"));
+    }
+
+    #[tokio::test]
+    async fn test_proctor_answer_after_auto_deny_is_rejected() {
+        let server = SfuServer::new();
+        let (room_id, mut proctor_rx, mut student_rx) = room_with_join_request(&server, EscalationPolicy::AutoDeny).await;
+        let requested = std::time::Instant::now();
+
+        server.escalate_pending_students(requested + Duration::from_secs(61)).await;
+        server.escalate_pending_students(requested + Duration::from_secs(121)).await;
+        let denied = next_message_of_type(&mut student_rx, "join_denied").await;
+        assert_eq!(denied["automatic"], true);
+        let escalated = next_message_of_type(&mut proctor_rx, "JoinRequestEscalated").await;
+        assert_eq!(escalated["action"], "auto_denied");
+        assert!(!server.admission.is_pending(&room_id, "student_1"));
+
+        let late = server.send_join_response(room_id, "student_1".to_string(), true).await;
+        assert_eq!(late.unwrap_err().to_string(), "Student connection not found");
+        assert!(message_types(&mut student_rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_withdrawn_request_never_escalates() {
+        let server = SfuServer::new();
+        let (room_id, mut proctor_rx, _student_rx) = room_with_join_request(&server, EscalationPolicy::AutoApprove).await;
+        let requested = std::time::Instant::now();
+
+        server.remove_pending_student(&room_id, "student_1").await;
+        server.escalate_pending_students(requested + Duration::from_secs(61)).await;
+        server.escalate_pending_students(requested + Duration::from_secs(121)).await;
+        let types = message_types(&mut proctor_rx);
+        assert!(!types.contains(&"JoinRequest".to_string()));
+        assert!(!types.contains(&"JoinRequestEscalated".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_one_proctor_runs_two_rooms() {
+        let server = SfuServer::new();
+        let first = server
+            .create_room("proctor_multi".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let second = server
+            .create_room("proctor_multi".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        assert_ne!(first, second);
+        assert_eq!(server.rooms_owned_by("proctor_multi").await, vec![first.clone(), second.clone()]);
+
+        let (first_tx, mut first_rx) = mpsc::unbounded_channel();
+        let (second_tx, mut second_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_multi".to_string(), first.clone(), first_tx).await.unwrap();
+        server.add_peer("proctor_multi".to_string(), second.clone(), second_tx).await.unwrap();
+        assert!(server.connections.contains(&PeerKey::new(first.as_str(), "proctor_multi")));
+        assert!(server.connections.contains(&PeerKey::new(second.as_str(), "proctor_multi")));
+
+        // The same student ID in each room is two separate students
+        for room_id in [&first, &second] {
+            server.room_manager.join_room(room_id.clone(), "student_1".to_string(), None).await.unwrap();
+        }
+        server.remove_peer(&second, "student_1", DisconnectCause::Kicked).await.unwrap();
+        let left = next_message_of_type(&mut second_rx, "ParticipantLeft").await;
+        assert_eq!(left["room_id"], second.as_str());
+        assert_eq!(left["reason"], DisconnectCause::Kicked.as_str());
+        assert!(server.room_manager.get_peer(&PeerKey::new(first.as_str(), "student_1")).await.is_some());
+
+        server.remove_peer(&first, "student_1", DisconnectCause::Left).await.unwrap();
+        let left = next_message_of_type(&mut first_rx, "ParticipantLeft").await;
+        assert_eq!(left["room_id"], first.as_str());
+        assert_eq!(left["reason"], DisconnectCause::Left.as_str());
+
+        // Closing one room leaves the proctor's other session running
+        server.remove_peer(&first, "proctor_multi", DisconnectCause::Left).await.unwrap();
+        assert!(!server.room_exists(&first).await);
+        assert!(server.room_exists(&second).await);
+        assert!(server.connections.contains(&PeerKey::new(second.as_str(), "proctor_multi")));
+        assert_eq!(server.rooms_owned_by("proctor_multi").await, vec![second.clone()]);
+
+        server.remove_peer(&second, "proctor_multi", DisconnectCause::Left).await.unwrap();
+        assert_eq!(server.connections.count(), 0);
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_background_tasks() {
+        let server = Arc::new(SfuServer::new());
+        server.start_background_tasks();
+        tokio::task::yield_now().await;
+
+        let started = std::time::Instant::now();
+        let report = server.shutdown().await;
+        assert!(started.elapsed() < server.task_shutdown_timeout);
+        assert!(report.is_clean(), "unclean shutdown: {:?}", report);
+        assert_eq!(report.stopped, 3);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_notifies_peers_and_stops_recordings() {
+        let dir = std::env::temp_dir().join(format!("sfu-server-shutdown-{}", std::process::id()));
+        let can_record = crate::recording::RecordingPipeline::verify_environment().is_ok();
+        let mut server = SfuServer::new();
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), can_record), None));
+
+        let room_id = server
+            .create_room("proctor_shutdown".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_shutdown".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        server.start_recording(&room_id, "proctor_shutdown").await.unwrap();
+        if can_record {
+            assert_eq!(
+                server.recording_manager.get_recording_state(&room_id, "proctor_shutdown").await,
+                Some(crate::recording::RecordingState::Recording)
+            );
+        }
+
+        assert!(server.shutdown().await.is_clean());
+        let notice = next_message_of_type(&mut proctor_rx, "ServerShutdown").await;
+        assert_eq!(notice["room_id"], room_id.as_str());
+        assert_eq!(server.recording_manager.get_recording_state(&room_id, "proctor_shutdown").await, None);
+        assert!(server.recording_manager.recording_rooms().await.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_chain_events_only_for_peers_with_wallets() {
+        use crate::substrate::MockChain;
+
+        let chain = Arc::new(MockChain::new());
+        let server = SfuServer::builder()
+            .engine_config(WebRtcEngineConfig::default())
+            .chain_recorder(chain.clone())
+            .build()
+            .unwrap();
+        let proctor = Address::from_low_u64_be(1);
+        let student = Address::from_low_u64_be(2);
+
+        let room_id = server
+            .create_room("proctor_chain".to_string(), None, Some(format!("{:?}", proctor)), RoomLocale::default())
+            .await
+            .unwrap();
+        let mut receivers = Vec::new();
+        for (peer_id, wallet) in [("student_wallet", Some(format!("{:?}", student))), ("student_plain", None)] {
+            let (tx, rx) = mpsc::unbounded_channel();
+            receivers.push(rx);
+            server
+                .add_peer_with_role(peer_id.to_string(), room_id.clone(), "student".to_string(), None, wallet, tx)
+                .await
+                .unwrap();
+        }
+        server.remove_peer(&room_id, "student_wallet", DisconnectCause::Kicked).await.unwrap();
+        server.remove_peer(&room_id, "student_plain", DisconnectCause::Left).await.unwrap();
+
+        let participant_events = || -> Vec<ChainEvent> {
+            chain
+                .events()
+                .into_iter()
+                .filter(|event| {
+                    matches!(
+                        event,
+                        ChainEvent::RoomCreated { .. } | ChainEvent::ParticipantJoined { .. } | ChainEvent::ParticipantLeft { .. }
+                    )
+                })
+                .collect()
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !participant_events().iter().any(|event| matches!(event, ChainEvent::ParticipantLeft { .. })) {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the student's departure never reached the chain");
+
+        // The student without a wallet is in the room but leaves no on-chain record
+        assert_eq!(
+            participant_events(),
+            [
+                ChainEvent::RoomCreated { room_id: room_id.clone(), proctor, proctor_name: None },
+                ChainEvent::ParticipantJoined {
+                    room_id: room_id.clone(),
+                    participant: student,
+                    name: None,
+                    role: ChainRole::Student,
+                    details: None,
+                },
+                ChainEvent::ParticipantLeft {
+                    room_id: room_id.clone(),
+                    participant: student,
+                    reason: ChainLeaveReason::Kicked,
+                    details: None,
+                },
+            ]
+        );
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_manual_recording_controls_reach_chain() {
+        use crate::substrate::MockChain;
+        use webrtc::rtp::{header::Header, packet::Packet};
+
+        let engine_config = rtp_dump_engine_config();
+        let chain = Arc::new(MockChain::new());
+        let mut server = SfuServer::builder()
+            .engine_config(engine_config)
+            .chain_recorder(chain.clone())
+            .build()
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("sfu-server-manual-chain-{}", std::process::id()));
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true));
+        let wallet = Address::from_low_u64_be(9);
+
+        let room_id = server
+            .create_room("proctor_manual".to_string(), None, Some(format!("{:?}", wallet)), RoomLocale::default())
+            .await
+            .unwrap();
+        let packet = Packet {
+            header: Header { version: 2, payload_type: 98, ..Default::default() },
+            payload: bytes::Bytes::from(vec![0u8; 100]),
+        };
+        let manager = server.recording_manager.clone();
+
+        manager.push_video_rtp(&room_id, "proctor_manual", &packet).await.unwrap();
+        server.stop_recording(&room_id, "proctor_manual").await.unwrap();
+        server.start_recording(&room_id, "proctor_manual").await.unwrap();
+        manager.push_video_rtp(&room_id, "proctor_manual", &packet).await.unwrap();
+        server.restart_recording(&room_id, "proctor_manual").await.unwrap();
+        manager.push_video_rtp(&room_id, "proctor_manual", &packet).await.unwrap();
+        assert_eq!(server.stop_all_recordings(&room_id).await.len(), 1);
+
+        let recording_events = || -> Vec<ChainEvent> {
+            chain
+                .events()
+                .into_iter()
+                .filter(|event| matches!(event, ChainEvent::RecordingStarted { .. } | ChainEvent::RecordingStopped { .. }))
+                .collect()
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while recording_events().len() < 6 {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the recording controls never reached the chain");
+
+        // Auto-start, stop, start, restart as a stop and a start, then stop all
+        let kinds: Vec<&str> = recording_events()
+            .iter()
+            .map(|event| match event {
+                ChainEvent::RecordingStarted { room_id: event_room, participant } => {
+                    assert_eq!((event_room, *participant), (&room_id, wallet));
+                    "started"
+                }
+                ChainEvent::RecordingStopped { room_id: event_room, participant, .. } => {
+                    assert_eq!((event_room, *participant), (&room_id, wallet));
+                    "stopped"
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(kinds, ["started", "stopped", "started", "stopped", "started", "stopped"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_e2ee_media_is_forwarded_but_not_recorded() {
+        let engine_config = rtp_dump_engine_config();
+        let mut server = SfuServer::builder().engine_config(engine_config).build().unwrap();
+        let dir = std::env::temp_dir().join(format!("sfu-server-e2ee-{}", std::process::id()));
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true));
+
+        let room_id = server
+            .create_room("proctor_e2ee".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_tx, mut proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_e2ee".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        let (student_tx, _student_rx) = mpsc::unbounded_channel();
+        server
+            .add_peer_with_role("student_e2ee".to_string(), room_id.clone(), "student".to_string(), None, None, student_tx)
+            .await
+            .unwrap();
+        assert!(server.is_peer_recording(&room_id, "student_e2ee").await);
+
+        server.set_media_e2ee(&room_id, "student_e2ee", true).await;
+        assert!(!server.is_peer_recording(&room_id, "student_e2ee").await);
+        assert!(server.start_recording(&room_id, "student_e2ee").await.is_err());
+        let error = next_message_of_type(&mut proctor_rx, "RecordingError").await;
+        assert_eq!(error["peer_id"], "student_e2ee");
+        assert!(error["error"].as_str().unwrap().contains("end-to-end encrypted"));
+
+        let e2ee_peers = |detail: RoomDetail| -> Vec<String> {
+            detail.peers.into_iter().filter(|peer| peer.e2ee).map(|peer| peer.peer_id).collect()
+        };
+        assert_eq!(e2ee_peers(server.room_detail(&room_id).await.unwrap()), ["student_e2ee"]);
+        // The proctor's recording is unaffected
+        assert!(server.is_peer_recording(&room_id, "proctor_e2ee").await);
+
+        // Turning it off allows recording again, but only when asked for
+        server.set_media_e2ee(&room_id, "student_e2ee", false).await;
+        assert!(e2ee_peers(server.room_detail(&room_id).await.unwrap()).is_empty());
+        assert!(!server.is_peer_recording(&room_id, "student_e2ee").await);
+        server.start_recording(&room_id, "student_e2ee").await.unwrap();
+
+        server.stop_all_recordings(&room_id).await;
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_servers_built_from_separate_configs() {
+        use crate::config::ServerConfig;
+        use crate::recording::store::StorageKind;
+        use crate::s3::S3Config;
+
+        let config = |enabled: bool, output_dir: &str, storage: StorageConfig| Config {
+            server: ServerConfig { host: "127.0.0.1".to_string(), port: 0, tls: crate::tls::TlsConfig::default() },
+            recording: RecordingConfig { enabled, output_dir: output_dir.to_string(), ..RecordingConfig::default() },
+            webrtc: WebRTCConfig::default(),
+            storage,
+            asset_hub: None,
+            sfu: SfuConfig {
+                shutdown_timeout: Duration::from_secs(if enabled { 5 } else { 7 }),
+                ..SfuConfig::default()
+            },
+        };
+        let s3 = StorageConfig {
+            kind: StorageKind::S3,
+            ipfs: None,
+            s3: Some(S3Config {
+                endpoint: "http://127.0.0.1:9000".to_string(),
+                bucket: "recordings".to_string(),
+                access_key: "minio".to_string(),
+                secret_key: "minio123".to_string(),
+                region: "us-east-1".to_string(),
+                part_size: 5 * 1024 * 1024,
+                upload_timeout_secs: 30,
+            }),
+        };
+        let none = StorageConfig { kind: StorageKind::None, ..StorageConfig::default() };
+
+        // Two servers in one process, each with its own settings and none from the environment
+        let uploading = SfuServer::builder()
+            .engine_config(WebRtcEngineConfig::default())
+            .config(&config(true, "/tmp/sfu-config-a", s3))
+            .build()
+            .unwrap();
+        let local = SfuServer::builder()
+            .engine_config(WebRtcEngineConfig::default())
+            .config(&config(false, "/tmp/sfu-config-b", none))
+            .build()
+            .unwrap();
+
+        assert!(uploading.recording_manager.is_enabled());
+        assert_eq!(uploading.recording_manager.output_dir(), "/tmp/sfu-config-a");
+        assert!(uploading.recording_manager.store().is_some());
+        assert!(!local.recording_manager.is_enabled());
+        assert_eq!(local.recording_manager.output_dir(), "/tmp/sfu-config-b");
+        assert!(local.recording_manager.store().is_none());
+        assert_eq!(uploading.shutdown_timeout, Duration::from_secs(5));
+        assert_eq!(local.shutdown_timeout, Duration::from_secs(7));
+
+        assert!(uploading.shutdown().await.is_clean());
+        assert!(local.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_auto_start_respects_recording_enabled_and_room_opt_out() {
+        let dir = std::env::temp_dir().join(format!("sfu-server-opt-out-{}", std::process::id()));
+        // (RECORDING_ENABLED, CreateRoom.recording_enabled, peers recorded)
+        let cases = [(true, None, true), (true, Some(false), false), (false, None, false)];
+        for (enabled, room_override, recorded) in cases {
+            let mut server = SfuServer::builder().engine_config(rtp_dump_engine_config()).build().unwrap();
+            server.recording_manager = Arc::new(
+                RecordingManager::new(&recording_config(dir.to_str().unwrap(), enabled), None).with_rtp_fallback(true),
+            );
+
+            let room_id = server
+                .create_room_for_tenant("proctor_opt".to_string(), None, None, RoomLocale::default(), None, room_override)
+                .await
+                .unwrap();
+            let (student_tx, _student_rx) = mpsc::unbounded_channel();
+            server
+                .add_peer_with_role("student_opt".to_string(), room_id.clone(), "student".to_string(), None, None, student_tx)
+                .await
+                .unwrap();
+            for peer_id in ["proctor_opt", "student_opt"] {
+                let recording = server.is_peer_recording(&room_id, peer_id).await;
+                assert_eq!(recording, recorded, "{} {:?} {}", enabled, room_override, peer_id);
+            }
+
+            // A room created without recording says so when asked to record anyway
+            if room_override == Some(false) {
+                let error = server.start_recording(&room_id, "student_opt").await.unwrap_err();
+                assert!(matches!(error, SfuError::RecordingDisabled(_)));
+                assert!(error.to_string().contains("created without recording"), "{}", error);
+                assert!(!server.is_peer_recording(&room_id, "student_opt").await);
+            }
+
+            server.stop_all_recordings(&room_id).await;
+            assert!(server.shutdown().await.is_clean());
+        }
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_student_transfer_between_rooms() {
+        use crate::substrate::MockChain;
+
+        let engine_config = rtp_dump_engine_config();
+        let chain = Arc::new(MockChain::new());
+        let mut server = SfuServer::builder()
+            .engine_config(engine_config)
+            .chain_recorder(chain.clone())
+            .build()
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!("sfu-server-transfer-{}", std::process::id()));
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), None).with_rtp_fallback(true));
+        let wallet = Address::from_low_u64_be(21);
+
+        let room_a = server
+            .create_room("proctor_a".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let room_b = server
+            .create_room("proctor_b".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let (proctor_a_tx, mut proctor_a_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_a".to_string(), room_a.clone(), proctor_a_tx).await.unwrap();
+        let (proctor_b_tx, mut proctor_b_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_b".to_string(), room_b.clone(), proctor_b_tx).await.unwrap();
+        let (student_tx, mut student_rx) = mpsc::unbounded_channel();
+        server
+            .add_peer_with_role(
+                "student_t".to_string(),
+                room_a.clone(),
+                "student".to_string(),
+                Some("Ada".to_string()),
+                Some(format!("{:?}", wallet)),
+                student_tx,
+            )
+            .await
+            .unwrap();
+        assert!(server.is_peer_recording(&room_a, "student_t").await);
+
+        // Proctors are not transferred, and a request waits for the destination's proctor
+        assert_eq!(
+            server.request_transfer(&room_a, &room_b, "proctor_a", "proctor_a").await,
+            Err(TransferError::NotStudent("proctor_a".to_string()))
+        );
+        assert_eq!(server.request_transfer(&room_a, &room_b, "student_t", "proctor_a").await, Ok(false));
+        let request = next_message_of_type(&mut proctor_b_rx, "TransferRequest").await;
+        assert_eq!((request["target_peer_id"].as_str(), request["name"].as_str()), (Some("student_t"), Some("Ada")));
+
+        // Declining leaves the student where they were
+        assert_eq!(server.answer_transfer(&room_a, &room_b, "student_t", false).await, Ok(()));
+        let failed = next_message_of_type(&mut proctor_a_rx, "TransferFailed").await;
+        assert_eq!(failed["code"], "transfer_refused");
+        assert_eq!(server.answer_transfer(&room_a, &room_b, "student_t", true).await, Err(TransferError::NoRequest));
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_a.as_str(), "student_t")).await.is_some());
+
+        // Approved, the student moves with their wallet and recording
+        assert_eq!(server.request_transfer(&room_a, &room_b, "student_t", "proctor_a").await, Ok(false));
+        server.answer_transfer(&room_a, &room_b, "student_t", true).await.unwrap();
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_a.as_str(), "student_t")).await.is_none());
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_b.as_str(), "student_t")).await.is_some());
+        assert_eq!(server.peer_wallet(&room_b, "student_t").await, Some(wallet));
+        assert!(!server.is_peer_recording(&room_a, "student_t").await);
+        assert!(server.is_peer_recording(&room_b, "student_t").await);
+        let completed = server.recording_manager.completed_recordings(&room_a).await;
+        let student_recording = completed.iter().find(|recording| recording.peer_id == "student_t").unwrap();
+        assert_eq!(student_recording.next_room.as_deref(), Some(room_b.as_str()));
+
+        let moved = next_message_of_type(&mut student_rx, "PeerTransferred").await;
+        assert_eq!(moved["to_room_id"], room_b.as_str());
+        let left = next_message_of_type(&mut proctor_a_rx, "ParticipantLeft").await;
+        assert_eq!(left["reason"], "transferred");
+        next_message_of_type(&mut proctor_a_rx, "PeerTransferred").await;
+        next_message_of_type(&mut proctor_b_rx, "PeerTransferred").await;
+        // The student's signaling connection follows them once
+        let student = PeerKey::new(room_a.as_str(), "student_t");
+        assert_eq!(server.follow_transfer(&student), Some(room_b.clone()));
+        assert_eq!(server.follow_transfer(&student), None);
+
+        let transfer_events = || -> Vec<ChainEvent> {
+            chain
+                .events()
+                .into_iter()
+                .filter(|event| match event {
+                    ChainEvent::ParticipantLeft { details, .. } | ChainEvent::ParticipantJoined { details, .. } => {
+                        details.is_some()
+                    }
+                    _ => false,
+                })
+                .collect()
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while transfer_events().len() < 2 {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("the transfer never reached the chain");
+        for event in transfer_events() {
+            match event {
+                ChainEvent::ParticipantLeft { room_id, participant, reason, details } => {
+                    assert_eq!((room_id, participant), (room_a.clone(), wallet));
+                    assert!(matches!(reason, ChainLeaveReason::Normal));
+                    assert_eq!(details, Some(transfer::left_details(&room_b)));
+                }
+                ChainEvent::ParticipantJoined { room_id, participant, details, .. } => {
+                    assert_eq!((room_id, participant), (room_b.clone(), wallet));
+                    assert_eq!(details, Some(transfer::joined_details(&room_a)));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        // A failure moving the recording returns the student to the room they were in
+        let room_c = server
+            .create_room("proctor_b".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        server
+            .recording_manager
+            .start_recording(&room_c, "student_t", &crate::recording::RecordingCodecs::default())
+            .await
+            .unwrap();
+        let result = server.request_transfer(&room_b, &room_c, "student_t", "proctor_b").await;
+        assert!(matches!(result, Err(TransferError::Failed(_))));
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_b.as_str(), "student_t")).await.is_some());
+        assert!(server.room_manager.get_peer(&PeerKey::new(room_c.as_str(), "student_t")).await.is_none());
+        assert!(server.is_peer_recording(&room_b, "student_t").await);
+
+        for room_id in [&room_a, &room_b, &room_c] {
+            server.stop_all_recordings(room_id).await;
+        }
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_data_deletion() {
+        use crate::recording::{MockStore, StoreCall};
+
+        let dir = std::env::temp_dir().join(format!("sfu-server-tenant-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = Arc::new(MockStore::new());
+        let mut server = SfuServer::new();
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), false), Some(store.clone())));
+
+        let room_id = server
+            .create_room_for_tenant("proctor_t".to_string(), None, None, RoomLocale::default(), Some("uni-a".to_string()), None)
+            .await
+            .unwrap();
+        let overviews = server.room_overviews().await;
+        assert_eq!(overviews[0].tenant.as_deref(), Some("uni-a"));
+
+        // An earlier room of the tenant, already closed and uploaded
+        let room_dir = tenant::create_tenant_dir(&dir, "uni-a").unwrap().join("654321");
+        std::fs::create_dir_all(&room_dir).unwrap();
+        std::fs::write(room_dir.join("room_manifest.json"), r#"{"recordings":[{"cid":"bafyold"}]}"#).unwrap();
+
+        assert!(matches!(server.delete_tenant_data("uni-a", false).await, Err(TenantError::RoomsOpen(1))));
+        assert!(matches!(server.delete_tenant_data("../etc", true).await, Err(TenantError::Invalid(_))));
+        let preview = server.delete_tenant_data("uni-a", true).await.unwrap();
+        assert_eq!((preview.rooms, preview.cids), (vec!["654321".to_string()], vec!["bafyold".to_string()]));
+        assert!(room_dir.exists() && store.calls().is_empty());
+
+        server.recording_manager.set_room_tenant(&room_id, None);
+        let sweep = server.delete_tenant_data("uni-a", false).await.unwrap();
+        assert_eq!(sweep.unpinned, vec!["bafyold".to_string()]);
+        assert_eq!(store.calls(), vec![StoreCall::Unpin { cid: "bafyold".to_string() }]);
+        assert!(!dir.join("uni-a").exists());
+
+        let audit = std::fs::read_to_string(dir.join(tenant::TENANT_AUDIT_FILE)).unwrap();
+        assert_eq!(audit.lines().count(), 2);
+        assert!(audit.lines().all(|line| line.contains(r#""tenant":"uni-a""#)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_room_lifecycle_against_mock_store_and_chain() {
+        use crate::recording::{MockStore, StoreCall};
+        use crate::substrate::MockChain;
+
+        let store = Arc::new(MockStore::new());
+        let chain = Arc::new(MockChain::new());
+        let mut server = SfuServer::builder()
+            .engine_config(WebRtcEngineConfig::default())
+            .recording_store(store.clone())
+            .chain_recorder(chain.clone())
+            .build()
+            .unwrap();
+        let dependencies = server.check_dependencies().await;
+        assert!(!dependencies.ipfs.is_down() && !dependencies.asset_hub.is_down());
+
+        // Finalizing a recording needs media through the real pipeline
+        let generators = ["videotestsrc", "audiotestsrc", "vp8enc", "opusenc", "rtpvp8pay", "rtpopuspay", "appsink"];
+        if RecordingPipeline::verify_environment().is_err()
+            || generators.iter().any(|name| gstreamer::ElementFactory::find(name).is_none())
+        {
+            return;
+        }
+        let video = crate::recording::encoded_rtp(
+            "videotestsrc num-buffers=30 ! video/x-raw,width=320,height=240,framerate=30/1 \
+             ! vp8enc deadline=1 ! rtpvp8pay pt=96",
+        );
+        let audio = crate::recording::encoded_rtp("audiotestsrc num-buffers=50 ! audio/x-raw,rate=48000 ! opusenc ! rtpopuspay pt=111");
+
+        let dir = std::env::temp_dir().join(format!("sfu-server-lifecycle-{}", std::process::id()));
+        server.recording_manager = Arc::new(RecordingManager::new(&recording_config(dir.to_str().unwrap(), true), Some(store.clone())));
+        let server = Arc::new(server);
+        let wallet = Address::from_low_u64_be(7);
+
+        // Create: the proctor's recording starts with the room
+        let room_id = server
+            .create_room(
+                "proctor_life".to_string(),
+                Some("Dr. Mock".to_string()),
+                Some(format!("{:?}", wallet)),
+                RoomLocale::default(),
+            )
+            .await
+            .unwrap();
+
+        // Join and record
+        let (proctor_tx, _proctor_rx) = mpsc::unbounded_channel();
+        server.add_peer("proctor_life".to_string(), room_id.clone(), proctor_tx).await.unwrap();
+        for (v, a) in video.iter().zip(&audio) {
+            server.recording_manager.push_video_rtp(&room_id, "proctor_life", v).await.unwrap();
+            server.recording_manager.push_audio_rtp(&room_id, "proctor_life", a).await.unwrap();
+        }
+
+        // Stop and close: closing stops the recording, and the upload worker
+        // starts afterwards so the uploads happen in a fixed order
+        server.close_room(&room_id, None, true).await.unwrap();
+        server.clone().start_recording_uploads();
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while !chain.events().iter().any(|event| matches!(event, ChainEvent::RoomClosed { .. })) {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("room close never reached the chain");
+
+        let calls = store.calls();
+        assert_eq!(calls.len(), 3, "{:?}", calls);
+        assert_eq!(
+            calls[0],
+            StoreCall::File {
+                room_id: room_id.clone(),
+                peer_id: "view_events".to_string(),
+                file_name: crate::recording::VIEW_EVENTS_FILE.to_string(),
+            }
+        );
+        match &calls[1] {
+            StoreCall::File { room_id: uploaded_room, peer_id, file_name } => {
+                assert_eq!((uploaded_room, peer_id.as_str()), (&room_id, "proctor_life"));
+                assert!(file_name.starts_with("proctor_life_") && file_name.ends_with(".webm"), "{}", file_name);
+            }
+            other => panic!("expected the recording upload, got {:?}", other),
+        }
+        assert_eq!(calls[2], StoreCall::Bytes { file_name: "room_manifest.json".to_string() });
+
+        let events = chain.events();
+        assert_eq!(events.len(), 5, "{:?}", events);
+        assert_eq!(
+            events[..3],
+            [
+                ChainEvent::RoomCreated {
+                    room_id: room_id.clone(),
+                    proctor: wallet,
+                    proctor_name: Some("Dr. Mock".to_string()),
+                },
+                ChainEvent::RecordingStarted { room_id: room_id.clone(), participant: wallet },
+                ChainEvent::ParticipantLeft {
+                    room_id: room_id.clone(),
+                    participant: wallet,
+                    reason: ChainLeaveReason::Normal,
+                    details: None,
+                },
+            ]
+        );
+        match &events[3] {
+            ChainEvent::RecordingStopped { room_id: stopped_room, participant, ipfs_cid, file_hash, .. } => {
+                assert_eq!((stopped_room, *participant), (&room_id, wallet));
+                assert_eq!(ipfs_cid.as_deref(), Some("bafymock2"));
+                // Hashed when the recording stopped, carried through the upload
+                assert!(file_hash.as_ref().is_some_and(|hash| hash.len() == 64), "{:?}", file_hash);
+            }
+            other => panic!("expected RecordingStopped, got {:?}", other),
+        }
+        assert_eq!(
+            events[4],
+            ChainEvent::RoomClosed {
+                room_id: room_id.clone(),
+                reason: ChainRoomCloseReason::SessionCompleted,
+                manifest_cid: Some("bafymock3".to_string()),
+            }
+        );
+
+        assert!(server.shutdown().await.is_clean());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
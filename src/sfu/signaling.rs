@@ -1,15 +1,48 @@
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use warp::ws::Message;
 
-use super::server::SfuServer;
+use crate::config::env;
+use crate::substrate::parse_address;
+
+use super::admission::{MessageRateLimiter, RejectReason, Rejection};
+use super::escalation::EscalationPolicy;
+use super::room::{DisconnectCause, PeerKey};
+use super::room_state::{TrackChange, ROOM_STATE_DELTA};
+use super::sdp::{max_sdp_bytes, normalize_sdp};
+use super::affinity::wrong_instance_error;
+use super::closing::{CloseBlocker, ClosePreview};
+use super::server::{CloseRoomError, SfuServer};
+use super::state_log::StateSync;
+use super::timezone::RoomLocale;
+use super::track_manager::{TrackContent, TrackOrderEntry};
+use crate::lti;
+use crate::metrics::metrics;
+use crate::recording::{tenant, CompletedRecording, MediaKind, RecordingDetail, RecordingSidecar, SessionMetadata, ViewEventKind};
+
+/// Default handling time above which a signaling message is logged as slow
+const DEFAULT_SLOW_HANDLER_WARN_MS: u64 = 250;
+
+static SLOW_HANDLER_WARN: OnceLock<Duration> = OnceLock::new();
+
+/// Threshold from `SLOW_HANDLER_WARN_MS`, read once
+fn slow_handler_warn() -> Duration {
+    *SLOW_HANDLER_WARN.get_or_init(|| {
+        let ms = env::get_parsed::<u64>("SLOW_HANDLER_WARN_MS").unwrap_or(DEFAULT_SLOW_HANDLER_WARN_MS);
+        Duration::from_millis(ms)
+    })
+}
 
 /// Recording info for stopped recordings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordingInfo {
     pub peer_id: String,
     pub file_path: Option<String>,
+    pub file_size_bytes: u64,
+    pub duration_secs: u64,
     pub cid: Option<String>,
     pub ipfs_gateway_url: Option<String>,
 }
@@ -23,10 +56,35 @@ pub enum SfuMessage {
         name: Option<String>,
         /// Wallet address of the proctor (for on-chain recording)
         wallet_address: Option<String>,
+        /// IANA timezone for human-facing timestamps, e.g. "Europe/Berlin"
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timezone: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        locale: Option<String>,
+        /// What happens to join requests left unanswered after the reminder:
+        /// `remind_only` (default), `auto_approve` or `auto_deny`
+        #[serde(default)]
+        escalation: EscalationPolicy,
+        /// Storage namespace for the room's recordings, view events and manifest
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tenant: Option<String>,
+        /// `false` for a practice room no one is recorded in; defaults to `RECORDING_ENABLED`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recording_enabled: Option<bool>,
     },
 
     RoomCreated {
         room_id: String,
+        /// Instance hosting the room, when several instances share one hostname
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        instance_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timezone: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        locale: Option<String>,
+        /// Bearer token for the proctor's room-scoped HTTP routes, e.g. the roster upload
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        proctor_token: Option<String>,
     },
 
     JoinRequest {
@@ -36,6 +94,17 @@ pub enum SfuMessage {
         role: String,
         /// Wallet address of the participant (for on-chain recording and NFT generation)
         wallet_address: Option<String>,
+        /// Set by the server when forwarding to the proctor: the student is on
+        /// the room roster and `name`/`wallet_address` come from it
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pre_registered: bool,
+        /// Set by the server when it forwards a request the proctor has not
+        /// answered yet a second time
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        reminder: bool,
+        /// Invite from an LTI launch's join URL, required for `lti-` peer IDs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        invite_token: Option<String>,
     },
 
     JoinResponse {
@@ -45,6 +114,15 @@ pub enum SfuMessage {
         requester_peer_id: String,
     },
 
+    /// Sent to proctor when the room's escalation policy settled a join request
+    /// it left unanswered: `auto_approved`, `auto_denied` or `kept_waiting`
+    JoinRequestEscalated {
+        room_id: String,
+        peer_id: String,
+        action: String,
+        waited_secs: u64,
+    },
+
     Join {
         room_id: String,
         peer_id: String,
@@ -82,6 +160,47 @@ pub enum SfuMessage {
         peer_id: String,
         has_video: bool,
         has_audio: bool,
+        /// Publisher track ID (`MediaStreamTrack.id`) -> content, for tracks
+        /// that are not the camera or microphone
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        content_hints: HashMap<String, TrackContent>,
+        /// Set when the client encrypts its media end to end (insertable
+        /// streams / SFrame); it is forwarded but not recorded
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        e2ee: bool,
+    },
+
+    /// Sent to a peer when it joins and whenever the tracks forwarded to it
+    /// change, listing them in the order they are added to its connection
+    RoomState {
+        room_id: String,
+        track_order: Vec<TrackOrderEntry>,
+        /// Numbers every track order sent to the peer, deltas included
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        revision: Option<u64>,
+    },
+
+    /// Sent instead of `RoomState` to peers that negotiated `room_state_delta`,
+    /// once they have had a full one: the changes from `revision - 1`
+    RoomStateDelta {
+        room_id: String,
+        revision: u64,
+        changes: Vec<TrackChange>,
+    },
+
+    /// Sent by a peer that missed a revision. The server answers with the
+    /// deltas after `since_revision`, or a full `RoomState` when they are gone.
+    GetRoomState {
+        room_id: String,
+        #[serde(default)]
+        since_revision: Option<u64>,
+    },
+
+    /// Capability exchange: the client lists what it supports, and the server
+    /// answers with the ones it will use for this connection
+    Hello {
+        #[serde(default)]
+        capabilities: Vec<String>,
     },
 
     // Recording messages
@@ -99,6 +218,25 @@ pub enum SfuMessage {
         room_id: String,
     },
 
+    /// Sent by the proctor to stop taking a peer's media into its recording
+    /// until `ResumeRecording`; the file goes on where it paused
+    PauseRecording {
+        room_id: String,
+        peer_id: String,
+    },
+
+    ResumeRecording {
+        room_id: String,
+        peer_id: String,
+    },
+
+    /// Sent by the proctor to finalize a student's recording and continue it
+    /// in a new file
+    RestartRecording {
+        room_id: String,
+        target_peer_id: String,
+    },
+
     RecordingStarted {
         room_id: String,
         peer_id: String,
@@ -108,8 +246,15 @@ pub enum SfuMessage {
         room_id: String,
         peer_id: String,
         file_path: Option<String>,
+        file_size_bytes: u64,
+        duration_secs: u64,
+        /// SHA-256 of the finalized file, hex encoded, as published on-chain
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
         cid: Option<String>,
         ipfs_gateway_url: Option<String>,
+        /// Where the upload can be fetched, whichever store it went to
+        storage_url: Option<String>,
     },
 
     AllRecordingsStopped {
@@ -117,39 +262,266 @@ pub enum SfuMessage {
         recordings: Vec<RecordingInfo>,
     },
 
+    RecordingPaused {
+        room_id: String,
+        peer_id: String,
+    },
+
+    RecordingResumed {
+        room_id: String,
+        peer_id: String,
+    },
+
+    /// Reply to `RestartRecording`: `stopped` was finalized and the recording
+    /// goes on in `file_path`, which names it as its `previous`
+    RecordingRestarted {
+        room_id: String,
+        peer_id: String,
+        stopped: RecordingInfo,
+        file_path: String,
+    },
+
+    /// Sent to proctor once a stopped recording is on IPFS
+    RecordingUploaded {
+        room_id: String,
+        peer_id: String,
+        /// IPFS CID, or the object's ETag with S3 storage
+        cid: String,
+        ipfs_gateway_url: String,
+        storage_url: String,
+    },
+
     RecordingError {
         room_id: String,
         peer_id: Option<String>,
         error: String,
     },
 
+    /// Sent to proctor when a recorded track stops delivering media, and again
+    /// with `end_offset` once it resumes. Offsets are seconds into the recording.
+    RecordingGap {
+        room_id: String,
+        peer_id: String,
+        kind: MediaKind,
+        start_offset: f64,
+        end_offset: Option<f64>,
+    },
+
     GetRecordingStatus {
         room_id: String,
     },
 
     RecordingStatus {
         room_id: String,
+        /// Deprecated: peer IDs of `recordings`, kept for older clients
         recording_peers: Vec<String>,
+        #[serde(default)]
+        recordings: Vec<RecordingDetail>,
+        /// Peer IDs of `recordings` that are paused
+        #[serde(default)]
+        paused_peers: Vec<String>,
+        #[serde(default)]
+        completed: Vec<CompletedRecording>,
+        /// Exam title, course code and notes the proctor set for the session
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metadata: Option<SessionMetadata>,
+    },
+
+    /// Sent by the proctor for the sidecar of a peer's most recently stopped recording
+    GetRecordingMetadata {
+        room_id: String,
+        peer_id: String,
+    },
+
+    RecordingMetadata {
+        room_id: String,
+        peer_id: String,
+        recording: RecordingSidecar,
+    },
+
+    /// Sent by the proctor to title and annotate the session; each message
+    /// replaces the previous metadata
+    SetSessionMetadata {
+        room_id: String,
+        exam_name: Option<String>,
+        course_code: Option<String>,
+        notes: Option<String>,
+    },
+
+    /// Confirmation sent to the proctor with the metadata as stored, after sanitizing
+    SessionMetadataUpdated {
+        room_id: String,
+        metadata: SessionMetadata,
     },
 
     // Proctor action messages
+    /// Deprecated: `peer_id` is the target. Use `KickPeer`, which names the proctor too.
     KickParticipant {
         room_id: String,
         peer_id: String,
         reason: Option<String>,
     },
 
+    /// Sent by the proctor (`peer_id`) to remove `target_peer_id` from the room
+    KickPeer {
+        room_id: String,
+        peer_id: String,
+        target_peer_id: String,
+        reason: Option<String>,
+    },
+
     ParticipantKicked {
         room_id: String,
         peer_id: String,
         reason: Option<String>,
     },
 
-    /// Sent to proctor when a participant voluntarily leaves the room
+    /// Sent to proctor when a participant leaves the room
     ParticipantLeft {
         room_id: String,
         peer_id: String,
         name: Option<String>,
+        /// `left`, `kicked`, `connection_lost` or `transferred`
+        reason: String,
+    },
+
+    /// Sent by the proctor of `from_room_id` to move a student to `to_room_id`
+    /// without them reconnecting. Needs the destination proctor's approval
+    /// unless the same proctor runs both rooms.
+    TransferPeer {
+        from_room_id: String,
+        to_room_id: String,
+        target_peer_id: String,
+    },
+
+    /// Forwarded to the destination room's proctor to approve a transfer
+    TransferRequest {
+        from_room_id: String,
+        to_room_id: String,
+        target_peer_id: String,
+        name: Option<String>,
+        /// Proctor of the source room
+        requested_by: String,
+    },
+
+    /// Sent by the destination room's proctor to answer a TransferRequest
+    TransferResponse {
+        from_room_id: String,
+        to_room_id: String,
+        target_peer_id: String,
+        approved: bool,
+    },
+
+    /// Sent to the student and both proctors once the student is in the
+    /// destination room. The student's client answers the offer that follows
+    /// on a new peer connection.
+    PeerTransferred {
+        from_room_id: String,
+        to_room_id: String,
+        peer_id: String,
+        name: Option<String>,
+    },
+
+    /// Sent to the source room's proctor when a transfer was declined or failed.
+    /// A student whose transfer was rolled back gets it too, followed by an
+    /// offer for the source room.
+    TransferFailed {
+        from_room_id: String,
+        to_room_id: String,
+        target_peer_id: String,
+        code: String,
+        message: String,
+    },
+
+    /// Sent by the proctor to see what closing the room would do
+    PreviewCloseRoom {
+        room_id: String,
+    },
+
+    ClosePreview {
+        room_id: String,
+        #[serde(flatten)]
+        preview: ClosePreview,
+    },
+
+    /// Sent by the proctor to end the session. Without `force` the room stays
+    /// open if the preview shows a blocker, and `CloseRoomRefused` is returned.
+    CloseRoom {
+        room_id: String,
+        reason: Option<String>,
+        #[serde(default)]
+        force: bool,
+    },
+
+    CloseRoomRefused {
+        room_id: String,
+        blockers: Vec<CloseBlocker>,
+        preview: ClosePreview,
+    },
+
+    /// Sent to everyone in the room when the proctor closes it
+    RoomClosed {
+        room_id: String,
+        reason: Option<String>,
+    },
+
+    /// Sent to every connected peer when the server begins shutting down
+    ServerShutdown {
+        room_id: String,
+    },
+
+    /// Sent by the proctor to post a notice to everyone in the room
+    Announce {
+        room_id: String,
+        text: String,
+    },
+
+    Announcement {
+        room_id: String,
+        text: String,
+        /// Unix time in milliseconds
+        at: u64,
+    },
+
+    /// Sent by the proctor to start the exam countdown
+    StartExamClock {
+        room_id: String,
+        duration_secs: u64,
+    },
+
+    ExamClock {
+        room_id: String,
+        /// Unix time in milliseconds
+        started_at: u64,
+        duration_secs: u64,
+    },
+
+    /// Sent by the proctor to mute or unmute a student
+    ForceMute {
+        room_id: String,
+        target_peer_id: String,
+        muted: bool,
+    },
+
+    /// Sent to the student the proctor muted or unmuted
+    ForcedMute {
+        room_id: String,
+        muted: bool,
+    },
+
+    /// Sent by a student answering the recording consent prompt, and passed on to the proctor
+    RecordingConsent {
+        room_id: String,
+        peer_id: String,
+        given: bool,
+    },
+
+    /// Sent on every (re)join before any other message, with everything the
+    /// peer has been told in the room so far
+    StateSync {
+        room_id: String,
+        #[serde(flatten)]
+        state: StateSync,
     },
 
     // ID verification messages
@@ -179,6 +551,15 @@ pub enum SfuMessage {
         activity_type: String,
     },
 
+    /// Sent to proctor whenever a student's integrity score changes; the
+    /// breakdown is at `GET /sfu/rooms/{room_id}/peers/{peer_id}/integrity`
+    IntegrityScoreUpdated {
+        room_id: String,
+        peer_id: String,
+        /// Basis points, 10000 = nothing to review
+        score: u32,
+    },
+
     /// Sent by student when they complete an exam with their score
     SubmitExamResult {
         room_id: String,
@@ -196,11 +577,175 @@ pub enum SfuMessage {
     },
 }
 
+impl SfuMessage {
+    /// Variant name, matching the `type` tag on the wire
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SfuMessage::CreateRoom { .. } => "CreateRoom",
+            SfuMessage::RoomCreated { .. } => "RoomCreated",
+            SfuMessage::JoinRequest { .. } => "JoinRequest",
+            SfuMessage::JoinResponse { .. } => "JoinResponse",
+            SfuMessage::JoinRequestEscalated { .. } => "JoinRequestEscalated",
+            SfuMessage::Join { .. } => "Join",
+            SfuMessage::Leave { .. } => "Leave",
+            SfuMessage::Offer { .. } => "Offer",
+            SfuMessage::Answer { .. } => "Answer",
+            SfuMessage::IceCandidate { .. } => "IceCandidate",
+            SfuMessage::Renegotiate { .. } => "Renegotiate",
+            SfuMessage::MediaReady { .. } => "MediaReady",
+            SfuMessage::RoomState { .. } => "RoomState",
+            SfuMessage::RoomStateDelta { .. } => "RoomStateDelta",
+            SfuMessage::GetRoomState { .. } => "GetRoomState",
+            SfuMessage::Hello { .. } => "Hello",
+            SfuMessage::StartRecording { .. } => "StartRecording",
+            SfuMessage::StopRecording { .. } => "StopRecording",
+            SfuMessage::StopAllRecordings { .. } => "StopAllRecordings",
+            SfuMessage::PauseRecording { .. } => "PauseRecording",
+            SfuMessage::ResumeRecording { .. } => "ResumeRecording",
+            SfuMessage::RestartRecording { .. } => "RestartRecording",
+            SfuMessage::RecordingStarted { .. } => "RecordingStarted",
+            SfuMessage::RecordingStopped { .. } => "RecordingStopped",
+            SfuMessage::AllRecordingsStopped { .. } => "AllRecordingsStopped",
+            SfuMessage::RecordingPaused { .. } => "RecordingPaused",
+            SfuMessage::RecordingResumed { .. } => "RecordingResumed",
+            SfuMessage::RecordingRestarted { .. } => "RecordingRestarted",
+            SfuMessage::RecordingUploaded { .. } => "RecordingUploaded",
+            SfuMessage::RecordingError { .. } => "RecordingError",
+            SfuMessage::RecordingGap { .. } => "RecordingGap",
+            SfuMessage::GetRecordingStatus { .. } => "GetRecordingStatus",
+            SfuMessage::RecordingStatus { .. } => "RecordingStatus",
+            SfuMessage::GetRecordingMetadata { .. } => "GetRecordingMetadata",
+            SfuMessage::RecordingMetadata { .. } => "RecordingMetadata",
+            SfuMessage::SetSessionMetadata { .. } => "SetSessionMetadata",
+            SfuMessage::SessionMetadataUpdated { .. } => "SessionMetadataUpdated",
+            SfuMessage::KickParticipant { .. } => "KickParticipant",
+            SfuMessage::KickPeer { .. } => "KickPeer",
+            SfuMessage::ParticipantKicked { .. } => "ParticipantKicked",
+            SfuMessage::ParticipantLeft { .. } => "ParticipantLeft",
+            SfuMessage::TransferPeer { .. } => "TransferPeer",
+            SfuMessage::TransferRequest { .. } => "TransferRequest",
+            SfuMessage::TransferResponse { .. } => "TransferResponse",
+            SfuMessage::PeerTransferred { .. } => "PeerTransferred",
+            SfuMessage::TransferFailed { .. } => "TransferFailed",
+            SfuMessage::PreviewCloseRoom { .. } => "PreviewCloseRoom",
+            SfuMessage::ClosePreview { .. } => "ClosePreview",
+            SfuMessage::CloseRoom { .. } => "CloseRoom",
+            SfuMessage::CloseRoomRefused { .. } => "CloseRoomRefused",
+            SfuMessage::RoomClosed { .. } => "RoomClosed",
+            SfuMessage::ServerShutdown { .. } => "ServerShutdown",
+            SfuMessage::Announce { .. } => "Announce",
+            SfuMessage::Announcement { .. } => "Announcement",
+            SfuMessage::StartExamClock { .. } => "StartExamClock",
+            SfuMessage::ExamClock { .. } => "ExamClock",
+            SfuMessage::ForceMute { .. } => "ForceMute",
+            SfuMessage::ForcedMute { .. } => "ForcedMute",
+            SfuMessage::RecordingConsent { .. } => "RecordingConsent",
+            SfuMessage::StateSync { .. } => "StateSync",
+            SfuMessage::StartIdVerification { .. } => "StartIdVerification",
+            SfuMessage::IdVerificationResult { .. } => "IdVerificationResult",
+            SfuMessage::ReportSuspiciousActivity { .. } => "ReportSuspiciousActivity",
+            SfuMessage::SuspiciousActivityReported { .. } => "SuspiciousActivityReported",
+            SfuMessage::IntegrityScoreUpdated { .. } => "IntegrityScoreUpdated",
+            SfuMessage::SubmitExamResult { .. } => "SubmitExamResult",
+            SfuMessage::ExamResultSubmitted { .. } => "ExamResultSubmitted",
+        }
+    }
+
+    /// Peer ID the sender claims as its own identity, if the message carries one.
+    ///
+    /// Fields that name another peer (targets of proctor actions, the requester in
+    /// a JoinResponse) are deliberately not returned here.
+    fn sender_peer_id(&self) -> Option<&str> {
+        match self {
+            SfuMessage::CreateRoom { peer_id, .. }
+            | SfuMessage::JoinRequest { peer_id, .. }
+            | SfuMessage::Join { peer_id, .. }
+            | SfuMessage::JoinResponse { peer_id, .. }
+            | SfuMessage::Leave { peer_id }
+            | SfuMessage::Answer { peer_id, .. }
+            | SfuMessage::IceCandidate { peer_id, .. }
+            | SfuMessage::MediaReady { peer_id, .. }
+            | SfuMessage::KickPeer { peer_id, .. }
+            | SfuMessage::RecordingConsent { peer_id, .. }
+            | SfuMessage::SubmitExamResult { peer_id, .. } => Some(peer_id),
+            _ => None,
+        }
+    }
+
+    /// Whether this message may bind an identity to an unbound connection
+    fn establishes_identity(&self) -> bool {
+        matches!(
+            self,
+            SfuMessage::CreateRoom { .. } | SfuMessage::JoinRequest { .. } | SfuMessage::Join { .. }
+        )
+    }
+}
+
+/// Rejection produced by the identity check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityError {
+    /// The message claims a peer_id other than the one bound to the connection
+    Mismatch { bound: String, claimed: String },
+    /// The message claims a peer_id before the connection established one
+    NotEstablished { claimed: String },
+}
+
+impl IdentityError {
+    pub fn code(&self) -> &'static str {
+        "identity_mismatch"
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            IdentityError::Mismatch { bound, claimed } => format!(
+                "Message peer_id {} does not match connection identity {}",
+                claimed, bound
+            ),
+            IdentityError::NotEstablished { claimed } => format!(
+                "Message peer_id {} sent before identity was established",
+                claimed
+            ),
+        }
+    }
+}
+
+/// Checks that a message's self-identifying peer_id matches the identity bound
+/// to the connection. Returns the identity to bind if the message establishes one.
+pub fn authorize_identity(bound: Option<&str>, message: &SfuMessage) -> Result<Option<String>, IdentityError> {
+    let Some(claimed) = message.sender_peer_id() else {
+        return Ok(None);
+    };
+
+    match bound {
+        Some(bound) if bound == claimed => Ok(None),
+        Some(bound) => Err(IdentityError::Mismatch {
+            bound: bound.to_string(),
+            claimed: claimed.to_string(),
+        }),
+        None if message.establishes_identity() => Ok(Some(claimed.to_string())),
+        None => Err(IdentityError::NotEstablished {
+            claimed: claimed.to_string(),
+        }),
+    }
+}
+
+/// Sends a message from work spawned off the handler, after the handler may be gone
+fn send_json<T: Serialize>(sender: &mpsc::UnboundedSender<Message>, message: &T) {
+    if let Ok(msg_str) = serde_json::to_string(message) {
+        let _ = sender.send(Message::text(msg_str));
+    }
+}
+
 pub struct SfuSignalingHandler {
     sfu_server: Arc<SfuServer>,
     peer_id: Option<String>,
     room_id: Option<String>,
+    /// Whether the connection created or joined `room_id`, rather than only asking to
+    in_session: bool,
+    /// The client negotiated `room_state_delta` in `Hello`
+    room_state_deltas: bool,
     sender: mpsc::UnboundedSender<Message>,
+    rate_limiter: MessageRateLimiter,
 }
 
 impl SfuSignalingHandler {
@@ -208,24 +753,134 @@ impl SfuSignalingHandler {
         sfu_server: Arc<SfuServer>,
         sender: mpsc::UnboundedSender<Message>,
     ) -> Self {
+        let rate_limiter = MessageRateLimiter::new(sfu_server.admission_limits().max_messages_per_sec);
         Self {
             sfu_server,
             peer_id: None,
             room_id: None,
+            in_session: false,
+            room_state_deltas: false,
             sender,
+            rate_limiter,
         }
     }
 
+    /// Room this connection joined or created, once known
+    pub fn room_id(&self) -> Option<&str> {
+        self.room_id.as_deref()
+    }
+
+    /// Peer this connection speaks for, once known
+    pub fn peer_id(&self) -> Option<&str> {
+        self.peer_id.as_deref()
+    }
+
+    /// Handles one message and records how long it took under its message type
     pub async fn handle_message(&mut self, message: SfuMessage) {
+        let kind = message.kind();
+        let started = Instant::now();
+
+        self.dispatch(message).await;
+
+        let elapsed = started.elapsed();
+        metrics().signaling_handler_latency.observe(kind, elapsed);
+        if elapsed >= slow_handler_warn() {
+            tracing::warn!(
+                message_type = kind,
+                peer_id = ?self.peer_id,
+                room_id = ?self.room_id,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow signaling handler"
+            );
+        }
+    }
+
+    async fn dispatch(&mut self, message: SfuMessage) {
+        self.follow_transfer();
+
+        if let Err(utilization) = self.rate_limiter.check(std::time::Instant::now()) {
+            let rejection = self.sfu_server.retry_policy().reject(
+                RejectReason::RateLimited,
+                utilization,
+                "Too many signaling messages",
+            );
+            tracing::warn!(peer_id = ?self.peer_id, retry_after_secs = rejection.retry_after_secs, "Rate limited signaling message");
+            self.send_rejection(&rejection).await;
+            return;
+        }
+
+        if let Some(error) = self.session_conflict(&message) {
+            tracing::warn!(peer_id = ?self.peer_id, room_id = ?self.room_id, "Rejected entering a second room on one connection");
+            self.send_error_with_code("room_session_active", &error).await;
+            return;
+        }
+
+        if let SfuMessage::JoinRequest { room_id, .. } | SfuMessage::Join { room_id, .. } = &message {
+            if let Some(owner) = self.sfu_server.find_remote_room(room_id).await {
+                tracing::info!(
+                    room_id = %room_id,
+                    correct_instance = %owner.instance_id,
+                    "Join for room hosted by another instance"
+                );
+                let error = wrong_instance_error(room_id, &owner);
+                let _ = self.sender.send(Message::text(error.to_string()));
+                return;
+            }
+        }
+
+        let admission = match &message {
+            SfuMessage::CreateRoom { peer_id, .. } => Some((None, peer_id)),
+            SfuMessage::JoinRequest { room_id, peer_id, .. }
+            | SfuMessage::Join { room_id, peer_id, .. } => Some((Some(room_id.as_str()), peer_id)),
+            _ => None,
+        };
+        if let Some((room_id, peer_id)) = admission {
+            if let Err(rejection) = self.sfu_server.check_admission(room_id, peer_id).await {
+                tracing::warn!(
+                    peer_id = %peer_id,
+                    room_id = ?room_id,
+                    code = rejection.code,
+                    retry_after_secs = rejection.retry_after_secs,
+                    "Rejected room entry"
+                );
+                self.send_rejection(&rejection).await;
+                return;
+            }
+        }
+
+        match authorize_identity(self.peer_id.as_deref(), &message) {
+            Ok(Some(peer_id)) => {
+                tracing::debug!(peer_id = %peer_id, "Bound peer identity to connection");
+                self.peer_id = Some(peer_id);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(
+                    bound_peer_id = ?self.peer_id,
+                    error = %e.message(),
+                    "Rejected message with spoofed peer identity"
+                );
+                self.send_error_with_code(e.code(), &e.message()).await;
+                return;
+            }
+        }
+
         match message {
-            SfuMessage::CreateRoom { peer_id, name, wallet_address } => {
-                self.handle_create_room(peer_id, name, wallet_address).await;
+            SfuMessage::Hello { capabilities } => {
+                self.handle_hello(capabilities);
+            }
+            SfuMessage::GetRoomState { room_id, since_revision } => {
+                self.handle_get_room_state(room_id, since_revision).await;
+            }
+            SfuMessage::CreateRoom { peer_id, name, wallet_address, timezone, locale, escalation, tenant, recording_enabled } => {
+                self.handle_create_room(peer_id, name, wallet_address, timezone, locale, escalation, tenant, recording_enabled)
+                    .await;
             }
             SfuMessage::Join { room_id, peer_id, name, role, wallet_address } => {
                 self.handle_join(room_id, peer_id, name, role, wallet_address).await;
             }
-            SfuMessage::JoinRequest { room_id, peer_id, name, role, wallet_address } => {
-                self.handle_join_request(room_id, peer_id, name, role, wallet_address).await;
+            SfuMessage::JoinRequest { room_id, peer_id, name, role, wallet_address, invite_token, .. } => {
+                self.handle_join_request(room_id, peer_id, name, role, wallet_address, invite_token).await;
             }
             SfuMessage::JoinResponse { room_id, peer_id, approved, requester_peer_id } => {
                 self.handle_join_response(room_id, peer_id, approved, requester_peer_id).await;
@@ -236,6 +891,9 @@ impl SfuSignalingHandler {
             SfuMessage::Answer { peer_id, sdp } => {
                 self.handle_answer(peer_id, sdp).await;
             }
+            SfuMessage::Offer { sdp } | SfuMessage::Renegotiate { sdp } => {
+                self.handle_client_offer(sdp).await;
+            }
             SfuMessage::IceCandidate {
                 peer_id,
                 candidate,
@@ -244,8 +902,8 @@ impl SfuSignalingHandler {
             } => {
                 self.handle_ice_candidate(peer_id, candidate, sdp_mid, sdp_mline_index).await;
             }
-            SfuMessage::MediaReady { peer_id, has_video, has_audio } => {
-                self.handle_media_ready(peer_id, has_video, has_audio).await;
+            SfuMessage::MediaReady { peer_id, has_video, has_audio, content_hints, e2ee } => {
+                self.handle_media_ready(peer_id, has_video, has_audio, content_hints, e2ee).await;
             }
             SfuMessage::StartRecording { room_id, peer_id } => {
                 self.handle_start_recording(room_id, peer_id).await;
@@ -256,11 +914,53 @@ impl SfuSignalingHandler {
             SfuMessage::StopAllRecordings { room_id } => {
                 self.handle_stop_all_recordings(room_id).await;
             }
+            SfuMessage::PauseRecording { room_id, peer_id } => {
+                self.handle_pause_recording(room_id, peer_id, true).await;
+            }
+            SfuMessage::ResumeRecording { room_id, peer_id } => {
+                self.handle_pause_recording(room_id, peer_id, false).await;
+            }
+            SfuMessage::RestartRecording { room_id, target_peer_id } => {
+                self.handle_restart_recording(room_id, target_peer_id).await;
+            }
             SfuMessage::GetRecordingStatus { room_id } => {
                 self.handle_get_recording_status(room_id).await;
             }
+            SfuMessage::GetRecordingMetadata { room_id, peer_id } => {
+                self.handle_get_recording_metadata(room_id, peer_id).await;
+            }
+            SfuMessage::SetSessionMetadata { room_id, exam_name, course_code, notes } => {
+                self.handle_set_session_metadata(room_id, exam_name, course_code, notes).await;
+            }
             SfuMessage::KickParticipant { room_id, peer_id, reason } => {
-                self.handle_kick_participant(room_id, peer_id, reason).await;
+                self.handle_kick_peer(room_id, peer_id, reason).await;
+            }
+            SfuMessage::KickPeer { room_id, target_peer_id, reason, .. } => {
+                self.handle_kick_peer(room_id, target_peer_id, reason).await;
+            }
+            SfuMessage::TransferPeer { from_room_id, to_room_id, target_peer_id } => {
+                self.handle_transfer_peer(from_room_id, to_room_id, target_peer_id).await;
+            }
+            SfuMessage::TransferResponse { from_room_id, to_room_id, target_peer_id, approved } => {
+                self.handle_transfer_response(from_room_id, to_room_id, target_peer_id, approved).await;
+            }
+            SfuMessage::PreviewCloseRoom { room_id } => {
+                self.handle_preview_close_room(room_id).await;
+            }
+            SfuMessage::CloseRoom { room_id, reason, force } => {
+                self.handle_close_room(room_id, reason, force).await;
+            }
+            SfuMessage::Announce { room_id, text } => {
+                self.handle_announce(room_id, text).await;
+            }
+            SfuMessage::StartExamClock { room_id, duration_secs } => {
+                self.handle_start_exam_clock(room_id, duration_secs).await;
+            }
+            SfuMessage::ForceMute { room_id, target_peer_id, muted } => {
+                self.handle_force_mute(room_id, target_peer_id, muted).await;
+            }
+            SfuMessage::RecordingConsent { room_id, peer_id, given } => {
+                self.sfu_server.record_consent(&room_id, &peer_id, given).await;
             }
             SfuMessage::StartIdVerification { room_id, peer_id } => {
                 self.handle_start_id_verification(room_id, peer_id).await;
@@ -274,21 +974,160 @@ impl SfuSignalingHandler {
             SfuMessage::SubmitExamResult { room_id, peer_id, score, total, exam_name } => {
                 self.handle_submit_exam_result(room_id, peer_id, score, total, exam_name).await;
             }
+            other => {
+                // Server-to-client messages and ones only ever sent by the SFU
+                let kind = other.kind();
+                tracing::warn!(message_type = kind, peer_id = ?self.peer_id, "Unhandled SFU message type");
+                self.send_error_detail(
+                    "unsupported_message",
+                    &format!("{} messages are not accepted from clients", kind),
+                    kind,
+                )
+                .await;
+            }
+        }
+    }
+
+    /// Answers a text frame that is not a valid signaling message, so the
+    /// client is not left waiting for a reply that never comes
+    pub async fn handle_parse_error(&self, error: &serde_json::Error) {
+        tracing::warn!(peer_id = ?self.peer_id, error = %error, "Rejected malformed SFU message");
+        self.send_error_detail("invalid_message", "Could not parse signaling message", &error.to_string())
+            .await;
+    }
+
+    fn handle_hello(&mut self, capabilities: Vec<String>) {
+        let accepted: Vec<String> = capabilities.into_iter().filter(|c| c == ROOM_STATE_DELTA).collect();
+        tracing::debug!(peer_id = ?self.peer_id, capabilities = ?accepted, "Negotiated client capabilities");
+        self.room_state_deltas = !accepted.is_empty();
+        self.negotiate_room_state();
+        send_json(&self.sender, &SfuMessage::Hello { capabilities: accepted });
+    }
+
+    /// Moves this connection to the room a proctor transferred its peer to, so
+    /// its signaling reaches the connection set up there
+    fn follow_transfer(&mut self) {
+        let (Some(peer_id), Some(room_id)) = (&self.peer_id, &self.room_id) else {
+            return;
+        };
+        if !self.in_session {
+            return;
+        }
+        let Some(to_room_id) = self.sfu_server.follow_transfer(&PeerKey::new(room_id.as_str(), peer_id.as_str())) else {
+            return;
+        };
+        tracing::info!(peer_id = %peer_id, from_room_id = %room_id, to_room_id = %to_room_id, "Connection follows transferred peer");
+        self.room_id = Some(to_room_id);
+        self.negotiate_room_state();
+    }
+
+    /// Has the server send this connection's peer track order deltas, once it
+    /// negotiated them and is in a room
+    fn negotiate_room_state(&self) {
+        if let (true, Some(peer_id), Some(room_id)) = (self.room_state_deltas, &self.peer_id, &self.room_id) {
+            self.sfu_server.enable_room_state_deltas(&PeerKey::new(room_id.as_str(), peer_id.as_str()));
+        }
+    }
+
+    async fn handle_get_room_state(&self, room_id: String, since_revision: Option<u64>) {
+        let peer_id = match (&self.peer_id, &self.room_id) {
+            (Some(peer_id), Some(current)) if self.in_session && *current == room_id => peer_id,
             _ => {
-                tracing::warn!("Unhandled SFU message type");
+                self.send_error_with_code("not_in_room", &format!("This connection is not in room {}", room_id)).await;
+                return;
             }
+        };
+        tracing::debug!(peer_id = %peer_id, room_id = %room_id, since_revision = ?since_revision, "Resending room state");
+        self.sfu_server
+            .resend_room_state(&PeerKey::new(room_id.as_str(), peer_id.as_str()), since_revision)
+            .await;
+    }
+
+    /// A connection carries one room's session. Once it created or joined a
+    /// room, entering another needs a connection of its own; a student still
+    /// waiting for approval may ask about a different room instead.
+    fn session_conflict(&self, message: &SfuMessage) -> Option<String> {
+        let current = self.room_id.as_deref().filter(|_| self.in_session)?;
+        let requested = match message {
+            SfuMessage::CreateRoom { .. } => None,
+            SfuMessage::Join { room_id, .. } | SfuMessage::JoinRequest { room_id, .. } => Some(room_id.as_str()),
+            _ => return None,
+        };
+        if requested == Some(current) {
+            return None;
         }
+        Some(format!(
+            "This connection is already in room {}; open a separate connection for each room",
+            current
+        ))
     }
 
-    async fn handle_create_room(&mut self, peer_id: String, name: Option<String>, wallet_address: Option<String>) {
-        tracing::info!(peer_id = %peer_id, name = ?name, wallet = ?wallet_address, "Proctor creating room");
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_create_room(
+        &mut self,
+        peer_id: String,
+        name: Option<String>,
+        wallet_address: Option<String>,
+        timezone: Option<String>,
+        locale: Option<String>,
+        escalation: EscalationPolicy,
+        tenant: Option<String>,
+        recording_enabled: Option<bool>,
+    ) {
+        tracing::info!(
+            peer_id = %peer_id,
+            name = ?name,
+            wallet = ?wallet_address,
+            timezone = ?timezone,
+            escalation = escalation.as_str(),
+            tenant = ?tenant,
+            recording_enabled = ?recording_enabled,
+            "Proctor creating room"
+        );
 
-        match self.sfu_server.create_room(peer_id.clone(), name, wallet_address).await {
+        if !self.check_wallet(&peer_id, wallet_address.as_deref()).await {
+            return;
+        }
+        let room_locale = match RoomLocale::parse(timezone.as_deref(), locale.as_deref()) {
+            Ok(room_locale) => room_locale,
+            Err(e) => {
+                self.send_error_with_code(e.code(), &e.message()).await;
+                return;
+            }
+        };
+        let tenant = match tenant::parse_tenant(tenant.as_deref()) {
+            Ok(tenant) => tenant,
+            Err(e) => {
+                self.send_error_with_code(e.code(), &e.message()).await;
+                return;
+            }
+        };
+        let timezone = room_locale.timezone_name();
+        let locale = room_locale.locale.clone();
+
+        match self
+            .sfu_server
+            .create_room_for_tenant(peer_id.clone(), name, wallet_address, room_locale, tenant, recording_enabled)
+            .await
+        {
             Ok(room_id) => {
                 self.peer_id = Some(peer_id.clone());
                 self.room_id = Some(room_id.clone());
+                self.in_session = true;
+                self.negotiate_room_state();
+
+                // Set before the room ID is out, so no request arrives under the default
+                if let Err(e) = self.sfu_server.set_escalation_policy(&room_id, escalation).await {
+                    tracing::warn!(room_id = %room_id, error = %e, "Failed to set join escalation policy");
+                }
 
-                let message = SfuMessage::RoomCreated { room_id: room_id.clone() };
+                let message = SfuMessage::RoomCreated {
+                    room_id: room_id.clone(),
+                    instance_id: self.sfu_server.instance().map(|i| i.instance_id.clone()),
+                    timezone,
+                    locale,
+                    proctor_token: self.sfu_server.get_proctor_token(&room_id).await,
+                };
                 if let Ok(msg_str) = serde_json::to_string(&message) {
                     tracing::debug!(room_id = %room_id, "Sending RoomCreated message");
                     let _ = self.sender.send(Message::text(msg_str));
@@ -318,21 +1157,51 @@ impl SfuSignalingHandler {
             "Peer joining room"
         );
 
+        if !self.check_wallet(&peer_id, wallet_address.as_deref()).await {
+            return;
+        }
         self.peer_id = Some(peer_id.clone());
         self.room_id = Some(room_id.clone());
-
-        self.sfu_server.remove_pending_student(&peer_id).await;
-
-        // Add peer to SFU with role and wallet address
-        if let Err(e) = self.sfu_server.add_peer_with_role(peer_id.clone(), room_id, role, name, wallet_address, self.sender.clone()).await {
-            tracing::error!(peer_id = %peer_id, error = %e, "Failed to add peer to SFU");
-            self.send_error(&format!("Failed to join: {}", e)).await;
-        } else {
-            self.send_join_success().await;
-        }
+        self.in_session = true;
+        self.negotiate_room_state();
+
+        // Adding a student can wait seconds for the proctor's tracks, so it runs
+        // off the message loop and ICE/answers for this connection keep flowing
+        let sfu_server = self.sfu_server.clone();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sfu_server
+                .add_peer_with_role(peer_id.clone(), room_id.clone(), role, name, wallet_address, sender.clone())
+                .await
+            {
+                tracing::error!(peer_id = %peer_id, error = %e, "Failed to add peer to SFU");
+                send_json(&sender, &serde_json::json!({
+                    "type": "error",
+                    "message": format!("Failed to join: {}", e)
+                }));
+            } else if sender.is_closed() {
+                // The connection went away while we were waiting; its cleanup already ran
+                tracing::info!(peer_id = %peer_id, "Peer disconnected while joining, removing");
+                let _ = sfu_server.remove_peer(&room_id, &peer_id, DisconnectCause::ConnectionLost).await;
+            } else {
+                send_json(&sender, &serde_json::json!({
+                    "type": "join_success",
+                    "message": "Successfully connected to SFU",
+                    "pre_registered": sfu_server.is_pre_registered(&room_id, &peer_id).await,
+                }));
+            }
+        });
     }
 
-    async fn handle_join_request(&mut self, room_id: String, peer_id: String, name: Option<String>, role: String, wallet_address: Option<String>) {
+    async fn handle_join_request(
+        &mut self,
+        room_id: String,
+        peer_id: String,
+        name: Option<String>,
+        role: String,
+        wallet_address: Option<String>,
+        invite_token: Option<String>,
+    ) {
         tracing::info!(
             peer_id = %peer_id,
             room_id = %room_id,
@@ -341,10 +1210,40 @@ impl SfuSignalingHandler {
             "Student requesting to join room"
         );
 
+        if !self.check_wallet(&peer_id, wallet_address.as_deref()).await {
+            return;
+        }
         self.peer_id = Some(peer_id.clone());
         self.room_id = Some(room_id.clone());
+        self.negotiate_room_state();
 
-        self.sfu_server.track_pending_student(peer_id.clone(), wallet_address.clone(), self.sender.clone()).await;
+        if !self.sfu_server.room_exists(&room_id).await {
+            tracing::info!(peer_id = %peer_id, room_id = %room_id, "Join request for unknown room");
+            self.send_error_with_code("room_not_found", &format!("Room {} does not exist", room_id)).await;
+            return;
+        }
+
+        if let Err(e) = lti::authorize_join(lti::service().as_deref(), &room_id, &peer_id, invite_token.as_deref()) {
+            tracing::info!(peer_id = %peer_id, room_id = %room_id, error = %e, "Join request with invalid invite");
+            self.send_error_with_code("invalid_invite", &e).await;
+            return;
+        }
+
+        if let Err(rejection) = self
+            .sfu_server
+            .track_pending_student(
+                &room_id,
+                peer_id.clone(),
+                name.clone(),
+                role.clone(),
+                wallet_address.clone(),
+                self.sender.clone(),
+            )
+            .await
+        {
+            self.send_rejection(&rejection).await;
+            return;
+        }
 
         // Forward the join request to the proctor (but don't add connection to SFU yet)
         if let Err(e) = self.sfu_server.forward_join_request(room_id, peer_id, name, role, wallet_address).await {
@@ -372,20 +1271,30 @@ impl SfuSignalingHandler {
     }
 
     async fn handle_leave(&mut self, peer_id: String) {
-        tracing::info!(peer_id = %peer_id, "Client leaving");
+        tracing::info!(peer_id = %peer_id, room_id = ?self.room_id, "Client leaving");
 
-        if let Err(e) = self.sfu_server.remove_peer(&peer_id).await {
-            tracing::error!(peer_id = %peer_id, error = %e, "Failed to remove peer from SFU");
+        if let Some(room_id) = &self.room_id {
+            if let Err(e) = self.sfu_server.remove_peer(room_id, &peer_id, DisconnectCause::Left).await {
+                tracing::error!(peer_id = %peer_id, error = %e, "Failed to remove peer from SFU");
+            }
         }
 
         self.peer_id = None;
         self.room_id = None;
+        self.in_session = false;
     }
 
     async fn handle_answer(&self, peer_id: String, sdp: String) {
         tracing::info!(peer_id = %peer_id, "Received answer from client");
 
-        if let Err(e) = self.sfu_server.handle_answer(&peer_id, &sdp).await {
+        let Some(room_id) = &self.room_id else {
+            self.send_error("Failed to process answer: not in a room").await;
+            return;
+        };
+        let Some(sdp) = self.normalize_client_sdp(&peer_id, "answer", &sdp).await else {
+            return;
+        };
+        if let Err(e) = self.sfu_server.handle_answer(room_id, &peer_id, &sdp).await {
             tracing::error!(peer_id = %peer_id, error = %e, "Failed to handle answer");
             self.send_error(&format!("Failed to process answer: {}", e)).await;
         } else {
@@ -393,6 +1302,22 @@ impl SfuSignalingHandler {
         }
     }
 
+    async fn handle_client_offer(&self, sdp: String) {
+        let (Some(room_id), Some(peer_id)) = (&self.room_id, &self.peer_id) else {
+            self.send_error_with_code("not_in_room", "Failed to process offer: not in a room").await;
+            return;
+        };
+        tracing::info!(peer_id = %peer_id, "Received offer from client");
+
+        let Some(sdp) = self.normalize_client_sdp(peer_id, "offer", &sdp).await else {
+            return;
+        };
+        if let Err(e) = self.sfu_server.handle_client_offer(room_id, peer_id, &sdp).await {
+            tracing::warn!(peer_id = %peer_id, code = e.code(), error = %e.message(), "Failed to answer client offer");
+            self.send_error_with_code(e.code(), &e.message()).await;
+        }
+    }
+
     async fn handle_ice_candidate(
         &self,
         peer_id: String,
@@ -400,21 +1325,48 @@ impl SfuSignalingHandler {
         sdp_mid: Option<String>,
         sdp_mline_index: Option<u16>,
     ) {
+        let Some(room_id) = &self.room_id else {
+            tracing::debug!(peer_id = %peer_id, "ICE candidate before any room, dropping");
+            return;
+        };
         if let Err(e) = self.sfu_server
-            .handle_ice_candidate(&peer_id, &candidate, sdp_mid, sdp_mline_index)
+            .handle_ice_candidate(room_id, &peer_id, &candidate, sdp_mid, sdp_mline_index)
             .await
         {
             tracing::error!(peer_id = %peer_id, error = %e, "Failed to handle ICE candidate");
         }
     }
 
-    async fn handle_media_ready(&self, peer_id: String, has_video: bool, has_audio: bool) {
+    async fn handle_media_ready(
+        &self,
+        peer_id: String,
+        has_video: bool,
+        has_audio: bool,
+        content_hints: HashMap<String, TrackContent>,
+        e2ee: bool,
+    ) {
         tracing::info!(
             peer_id = %peer_id,
             has_video = has_video,
             has_audio = has_audio,
+            e2ee = e2ee,
             "Client media ready"
         );
+
+        let Some(room_id) = self.room_id.clone() else {
+            tracing::warn!(peer_id = %peer_id, "Media ready before joining a room, ignoring");
+            return;
+        };
+        self.sfu_server
+            .record_view_event(&PeerKey::new(room_id.as_str(), peer_id.as_str()), ViewEventKind::MediaState, serde_json::json!({
+                "has_video": has_video,
+                "has_audio": has_audio,
+                "e2ee": e2ee,
+            }))
+            .await;
+        self.sfu_server.set_media_state(&room_id, &peer_id, has_video, has_audio).await;
+        self.sfu_server.set_track_content_hints(&room_id, &peer_id, content_hints).await;
+        self.sfu_server.set_media_e2ee(&room_id, &peer_id, e2ee).await;
     }
 
     async fn handle_start_recording(&self, room_id: String, peer_id: String) {
@@ -447,98 +1399,331 @@ impl SfuSignalingHandler {
     async fn handle_stop_recording(&self, room_id: String, peer_id: String) {
         tracing::info!(room_id = %room_id, peer_id = %peer_id, "Stopping recording for peer");
 
-        match self.sfu_server.stop_recording(&room_id, &peer_id).await {
-            Ok(result) => {
-                let message = SfuMessage::RecordingStopped {
+        // Stopping finalizes the file; don't hold up the connection
+        let sfu_server = self.sfu_server.clone();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let message = match sfu_server.stop_recording(&room_id, &peer_id).await {
+                Ok(result) => SfuMessage::RecordingStopped {
                     room_id,
                     peer_id,
                     file_path: Some(result.file_path.to_string_lossy().to_string()),
+                    file_size_bytes: result.file_size_bytes,
+                    duration_secs: result.duration_secs,
+                    sha256: result.sha256,
                     cid: result.cid,
+                    storage_url: result.ipfs_gateway_url.clone(),
                     ipfs_gateway_url: result.ipfs_gateway_url,
-                };
-                if let Ok(msg_str) = serde_json::to_string(&message) {
-                    let _ = self.sender.send(Message::text(msg_str));
+                },
+                Err(e) => {
+                    tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to stop recording");
+                    SfuMessage::RecordingError {
+                        room_id,
+                        peer_id: Some(peer_id),
+                        error: e.to_string(),
+                    }
                 }
-            }
+            };
+            send_json(&sender, &message);
+        });
+    }
+
+    async fn handle_stop_all_recordings(&self, room_id: String) {
+        tracing::info!(room_id = %room_id, "Stopping all recordings in room");
+
+        let sfu_server = self.sfu_server.clone();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let stopped = sfu_server.stop_all_recordings(&room_id).await;
+            let recordings: Vec<RecordingInfo> = stopped
+                .into_iter()
+                .map(|(peer_id, result)| RecordingInfo {
+                    peer_id,
+                    file_path: Some(result.file_path.to_string_lossy().to_string()),
+                    file_size_bytes: result.file_size_bytes,
+                    duration_secs: result.duration_secs,
+                    cid: result.cid,
+                    ipfs_gateway_url: result.ipfs_gateway_url,
+                })
+                .collect();
+
+            send_json(&sender, &SfuMessage::AllRecordingsStopped {
+                room_id,
+                recordings,
+            });
+        });
+    }
+
+    async fn handle_pause_recording(&self, room_id: String, peer_id: String, pause: bool) {
+        if !self.is_room_proctor(&room_id).await {
+            let action = if pause { "pause" } else { "resume" };
+            self.send_error_with_code("not_proctor", &format!("Only the room's proctor can {} recordings", action)).await;
+            return;
+        }
+
+        let result = if pause {
+            self.sfu_server.pause_recording(&room_id, &peer_id).await
+        } else {
+            self.sfu_server.resume_recording(&room_id, &peer_id).await
+        };
+        let message = match result {
+            Ok(()) if pause => SfuMessage::RecordingPaused { room_id, peer_id },
+            Ok(()) => SfuMessage::RecordingResumed { room_id, peer_id },
             Err(e) => {
-                tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to stop recording");
-                let message = SfuMessage::RecordingError {
+                tracing::warn!(room_id = %room_id, peer_id = %peer_id, pause, error = %e, "Failed to pause or resume recording");
+                SfuMessage::RecordingError {
                     room_id,
                     peer_id: Some(peer_id),
                     error: e.to_string(),
-                };
-                if let Ok(msg_str) = serde_json::to_string(&message) {
-                    let _ = self.sender.send(Message::text(msg_str));
                 }
             }
+        };
+        send_json(&self.sender, &message);
+    }
+
+    async fn handle_restart_recording(&self, room_id: String, peer_id: String) {
+        if !self.is_room_proctor(&room_id).await {
+            self.send_error_with_code("not_proctor", "Only the room's proctor can restart recordings").await;
+            return;
+        }
+        tracing::info!(room_id = %room_id, peer_id = %peer_id, "Restarting recording for peer");
+
+        // Finalizing the old file takes a while; don't hold up the connection
+        let sfu_server = self.sfu_server.clone();
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            let message = match sfu_server.restart_recording(&room_id, &peer_id).await {
+                Ok(restart) => SfuMessage::RecordingRestarted {
+                    stopped: RecordingInfo {
+                        peer_id: peer_id.clone(),
+                        file_path: Some(restart.stopped.file_path.to_string_lossy().to_string()),
+                        file_size_bytes: restart.stopped.file_size_bytes,
+                        duration_secs: restart.stopped.duration_secs,
+                        cid: restart.stopped.cid,
+                        ipfs_gateway_url: restart.stopped.ipfs_gateway_url,
+                    },
+                    room_id,
+                    peer_id,
+                    file_path: restart.file_path.to_string_lossy().to_string(),
+                },
+                Err(e) => {
+                    tracing::error!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to restart recording");
+                    SfuMessage::RecordingError {
+                        room_id,
+                        peer_id: Some(peer_id),
+                        error: e.to_string(),
+                    }
+                }
+            };
+            send_json(&sender, &message);
+        });
+    }
+
+    async fn handle_get_recording_status(&self, room_id: String) {
+        tracing::debug!(room_id = %room_id, "Getting recording status");
+
+        let mut recordings = self.sfu_server.get_recording_details(&room_id).await;
+        let mut completed = self.sfu_server.get_completed_recordings(&room_id).await;
+
+        // Stored timestamps stay UTC; add renderings in the room's timezone when it has one
+        let room_locale = self.sfu_server.get_room_locale(&room_id).await;
+        for recording in &mut recordings {
+            recording.started_at_local = recording.started_at.and_then(|ms| room_locale.format_local_ms(ms));
+        }
+        for recording in &mut completed {
+            recording.started_at_local = recording.started_at.and_then(|ms| room_locale.format_local_ms(ms));
+            recording.stopped_at_local = room_locale.format_local_ms(recording.stopped_at);
+        }
+        let metadata = Some(self.sfu_server.session_metadata(&room_id).await).filter(|m| !m.is_empty());
+        let message = SfuMessage::RecordingStatus {
+            recording_peers: recordings.iter().map(|r| r.peer_id.clone()).collect(),
+            paused_peers: recordings.iter().filter(|r| r.paused).map(|r| r.peer_id.clone()).collect(),
+            room_id,
+            recordings,
+            completed,
+            metadata,
+        };
+        if let Ok(msg_str) = serde_json::to_string(&message) {
+            let _ = self.sender.send(Message::text(msg_str));
+        }
+    }
+
+    async fn handle_get_recording_metadata(&self, room_id: String, peer_id: String) {
+        if !self.is_room_proctor(&room_id).await {
+            tracing::warn!(room_id = %room_id, peer_id = ?self.peer_id, target_peer_id = %peer_id, "Rejected recording metadata request from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the room's proctor can read recording metadata").await;
+            return;
+        }
+
+        let recording = match self.sfu_server.get_recording_metadata(&room_id, &peer_id) {
+            Ok(Some(recording)) => recording,
+            Ok(None) => {
+                self.send_error_with_code("recording_not_found", &format!("No stopped recording of {} in this room", peer_id))
+                    .await;
+                return;
+            }
+            Err(e) => {
+                tracing::warn!(room_id = %room_id, peer_id = %peer_id, error = %e, "Failed to read recording metadata");
+                self.send_error_with_code("recording_metadata_failed", &e.to_string()).await;
+                return;
+            }
+        };
+        let message = SfuMessage::RecordingMetadata { room_id, peer_id, recording };
+        if let Ok(msg_str) = serde_json::to_string(&message) {
+            let _ = self.sender.send(Message::text(msg_str));
+        }
+    }
+
+    async fn handle_set_session_metadata(
+        &self,
+        room_id: String,
+        exam_name: Option<String>,
+        course_code: Option<String>,
+        notes: Option<String>,
+    ) {
+        let proctor_id = self.sfu_server.get_room_proctor(&room_id).await;
+        let Some(peer_id) = self.peer_id.clone().filter(|id| proctor_id.as_deref() == Some(id.as_str())) else {
+            tracing::warn!(room_id = %room_id, peer_id = ?self.peer_id, "Rejected session metadata from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the room's proctor can set session metadata").await;
+            return;
+        };
+
+        let metadata = SessionMetadata::sanitized(exam_name, course_code, notes);
+        if let Err(e) = self.sfu_server.set_session_metadata(&room_id, &peer_id, metadata.clone()).await {
+            self.send_error(&e).await;
+            return;
+        }
+
+        let message = SfuMessage::SessionMetadataUpdated { room_id, metadata };
+        if let Ok(msg_str) = serde_json::to_string(&message) {
+            let _ = self.sender.send(Message::text(msg_str));
+        }
+    }
+
+    async fn handle_kick_peer(&self, room_id: String, target_peer_id: String, reason: Option<String>) {
+        let proctor_id = self.sfu_server.get_room_proctor(&room_id).await;
+        let Some(peer_id) = self.peer_id.clone().filter(|id| proctor_id.as_deref() == Some(id.as_str())) else {
+            tracing::warn!(room_id = %room_id, peer_id = ?self.peer_id, target_peer_id = %target_peer_id, "Rejected kick from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the room's proctor can remove participants").await;
+            return;
+        };
+
+        tracing::info!(
+            room_id = %room_id,
+            proctor_id = %peer_id,
+            target_peer_id = %target_peer_id,
+            reason = ?reason,
+            "Proctor kicking participant"
+        );
+
+        if let Err(e) = self.sfu_server.kick_peer(&room_id, &target_peer_id, reason).await {
+            self.send_error_with_code(e.code(), &e.message()).await;
+        }
+    }
+
+    async fn handle_transfer_peer(&self, from_room_id: String, to_room_id: String, target_peer_id: String) {
+        if !self.is_room_proctor(&from_room_id).await {
+            tracing::warn!(room_id = %from_room_id, peer_id = ?self.peer_id, target_peer_id = %target_peer_id, "Rejected transfer from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the room's proctor can transfer its students").await;
+            return;
+        }
+        let proctor_id = self.peer_id.as_deref().unwrap_or_default();
+
+        tracing::info!(
+            from_room_id = %from_room_id,
+            to_room_id = %to_room_id,
+            proctor_id = %proctor_id,
+            target_peer_id = %target_peer_id,
+            "Proctor transferring student"
+        );
+        if let Err(e) = self
+            .sfu_server
+            .request_transfer(&from_room_id, &to_room_id, &target_peer_id, proctor_id)
+            .await
+        {
+            self.send_error_with_code(e.code(), &e.message()).await;
+        }
+    }
+
+    async fn handle_transfer_response(&self, from_room_id: String, to_room_id: String, target_peer_id: String, approved: bool) {
+        if !self.is_room_proctor(&to_room_id).await {
+            tracing::warn!(room_id = %to_room_id, peer_id = ?self.peer_id, "Rejected transfer answer from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the destination room's proctor can answer a transfer").await;
+            return;
+        }
+        if let Err(e) = self
+            .sfu_server
+            .answer_transfer(&from_room_id, &to_room_id, &target_peer_id, approved)
+            .await
+        {
+            self.send_error_with_code(e.code(), &e.message()).await;
         }
     }
 
-    async fn handle_stop_all_recordings(&self, room_id: String) {
-        tracing::info!(room_id = %room_id, "Stopping all recordings in room");
-
-        let stopped = self.sfu_server.stop_all_recordings(&room_id).await;
-        let recordings: Vec<RecordingInfo> = stopped
-            .into_iter()
-            .map(|(peer_id, result)| RecordingInfo {
-                peer_id,
-                file_path: Some(result.file_path.to_string_lossy().to_string()),
-                cid: result.cid,
-                ipfs_gateway_url: result.ipfs_gateway_url,
-            })
-            .collect();
+    /// Whether this connection belongs to the room's proctor
+    async fn is_room_proctor(&self, room_id: &str) -> bool {
+        let proctor_id = self.sfu_server.get_room_proctor(room_id).await;
+        self.peer_id.is_some() && self.peer_id == proctor_id
+    }
 
-        let message = SfuMessage::AllRecordingsStopped {
-            room_id,
-            recordings,
-        };
-        if let Ok(msg_str) = serde_json::to_string(&message) {
-            let _ = self.sender.send(Message::text(msg_str));
+    async fn handle_preview_close_room(&self, room_id: String) {
+        if !self.is_room_proctor(&room_id).await {
+            self.send_error_with_code("not_proctor", "Only the room's proctor can close it").await;
+            return;
+        }
+        match self.sfu_server.close_preview(&room_id).await {
+            Some(preview) => send_json(&self.sender, &SfuMessage::ClosePreview { room_id, preview }),
+            None => {
+                self.send_error_with_code("room_not_found", &format!("Room {} does not exist", room_id)).await;
+            }
         }
     }
 
-    async fn handle_get_recording_status(&self, room_id: String) {
-        tracing::debug!(room_id = %room_id, "Getting recording status");
+    async fn handle_close_room(&self, room_id: String, reason: Option<String>, force: bool) {
+        if !self.is_room_proctor(&room_id).await {
+            tracing::warn!(room_id = %room_id, peer_id = ?self.peer_id, "Rejected room close from non-proctor");
+            self.send_error_with_code("not_proctor", "Only the room's proctor can close it").await;
+            return;
+        }
 
-        let recording_peers = self.sfu_server.get_recording_peers(&room_id).await;
-        let message = SfuMessage::RecordingStatus {
-            room_id,
-            recording_peers,
-        };
-        if let Ok(msg_str) = serde_json::to_string(&message) {
-            let _ = self.sender.send(Message::text(msg_str));
+        match self.sfu_server.close_room(&room_id, reason, force).await {
+            Ok(()) => {}
+            Err(CloseRoomError::Blocked { blockers, preview }) => {
+                tracing::info!(room_id = %room_id, blockers = blockers.len(), "Room close refused without force");
+                send_json(&self.sender, &SfuMessage::CloseRoomRefused { room_id, blockers, preview });
+            }
+            Err(e) => self.send_error_with_code(e.code(), &e.message()).await,
         }
     }
 
-    async fn handle_kick_participant(&self, room_id: String, peer_id: String, reason: Option<String>) {
-        tracing::info!(
-            room_id = %room_id,
-            peer_id = %peer_id,
-            reason = ?reason,
-            "Proctor kicking participant"
-        );
-
-        // Notify the kicked participant
-        if let Err(e) = self.sfu_server.send_kick_notification(&room_id, &peer_id, reason.clone()).await {
-            tracing::error!(
-                room_id = %room_id,
-                peer_id = %peer_id,
-                error = %e,
-                "Failed to send kick notification"
-            );
+    async fn handle_announce(&self, room_id: String, text: String) {
+        if !self.is_room_proctor(&room_id).await {
+            self.send_error_with_code("not_proctor", "Only the room's proctor can post announcements").await;
+            return;
         }
+        self.sfu_server.announce(&room_id, text).await;
+    }
 
-        // Remove the participant from the room
-        if let Err(e) = self.sfu_server.remove_peer(&peer_id).await {
-            tracing::error!(
-                peer_id = %peer_id,
-                error = %e,
-                "Failed to remove kicked peer"
-            );
+    async fn handle_start_exam_clock(&self, room_id: String, duration_secs: u64) {
+        if !self.is_room_proctor(&room_id).await {
+            self.send_error_with_code("not_proctor", "Only the room's proctor can start the exam clock").await;
+            return;
         }
+        self.sfu_server.start_exam_clock(&room_id, duration_secs).await;
+    }
 
-        // Emit chain event for the kick (handled by SfuServer)
-        self.sfu_server.emit_participant_kicked(&room_id, &peer_id, reason).await;
+    async fn handle_force_mute(&self, room_id: String, target_peer_id: String, muted: bool) {
+        if !self.is_room_proctor(&room_id).await {
+            self.send_error_with_code("not_proctor", "Only the room's proctor can mute participants").await;
+            return;
+        }
+        if !self.sfu_server.force_mute(&room_id, &target_peer_id, muted).await {
+            self.send_error_with_code(
+                "peer_not_found",
+                &format!("Student {} is not in this room", target_peer_id),
+            )
+            .await;
+        }
     }
 
     async fn handle_start_id_verification(&self, room_id: String, peer_id: String) {
@@ -632,20 +1817,19 @@ impl SfuSignalingHandler {
             0
         };
 
-        let exam_name = exam_name.unwrap_or_else(|| format!("Exam Session {}", room_id));
-
         tracing::info!(
             room_id = %room_id,
             peer_id = %peer_id,
             score = score,
             total = total,
             grade = grade,
-            exam_name = %exam_name,
+            exam_name = ?exam_name,
             "Student submitted exam result"
         );
 
-        // Store the grade for when the student leaves
-        self.sfu_server.set_exam_grade(&peer_id, grade, exam_name).await;
+        // Store the grade for when the student leaves; the exam name is settled then,
+        // since the proctor's session title takes precedence
+        self.sfu_server.set_exam_grade(&room_id, &peer_id, grade, exam_name).await;
 
         // Send confirmation back to student
         let message = SfuMessage::ExamResultSubmitted {
@@ -659,10 +1843,10 @@ impl SfuSignalingHandler {
         }
     }
 
-    async fn send_join_success(&self) {
+    async fn send_join_request_sent(&self) {
         let message = serde_json::json!({
-            "type": "join_success",
-            "message": "Successfully connected to SFU"
+            "type": "join_request_sent",
+            "message": "Join request sent to proctor. Waiting for approval..."
         });
 
         if let Ok(msg_str) = serde_json::to_string(&message) {
@@ -670,10 +1854,10 @@ impl SfuSignalingHandler {
         }
     }
 
-    async fn send_join_request_sent(&self) {
+    async fn send_error(&self, error: &str) {
         let message = serde_json::json!({
-            "type": "join_request_sent",
-            "message": "Join request sent to proctor. Waiting for approval..."
+            "type": "error",
+            "message": error
         });
 
         if let Ok(msg_str) = serde_json::to_string(&message) {
@@ -681,9 +1865,73 @@ impl SfuSignalingHandler {
         }
     }
 
-    async fn send_error(&self, error: &str) {
+    /// Repairs a client SDP before it reaches the WebRTC stack, or answers
+    /// with `invalid_sdp` and the reason it could not be repaired
+    async fn normalize_client_sdp(&self, peer_id: &str, kind: &str, sdp: &str) -> Option<String> {
+        match normalize_sdp(sdp, max_sdp_bytes()) {
+            Ok((normalized, fixes)) => {
+                if fixes.any() {
+                    tracing::info!(
+                        peer_id = %peer_id,
+                        kind = kind,
+                        bom = fixes.bom,
+                        nul = fixes.nul,
+                        line_endings = fixes.line_endings,
+                        "Normalized malformed client SDP"
+                    );
+                }
+                Some(normalized)
+            }
+            Err(e) => {
+                tracing::warn!(peer_id = %peer_id, kind = kind, reason = e.reason(), "Rejecting client SDP");
+                let message = serde_json::json!({
+                    "type": "error",
+                    "code": e.code(),
+                    "reason": e.reason(),
+                    "message": format!("Failed to process {}: {}", kind, e.message()),
+                });
+                let _ = self.sender.send(Message::text(message.to_string()));
+                None
+            }
+        }
+    }
+
+    /// Refuses a wallet address that does not parse. Peers without one still
+    /// join, only without on-chain records, but a mistyped one is not dropped
+    /// silently. False when the message must not be handled.
+    async fn check_wallet(&self, peer_id: &str, wallet_address: Option<&str>) -> bool {
+        let Some(wallet) = wallet_address.map(str::trim).filter(|w| !w.is_empty()) else {
+            return true;
+        };
+        if parse_address(wallet).is_some() {
+            return true;
+        }
+        tracing::warn!(peer_id = %peer_id, wallet = %wallet, "Rejecting invalid wallet address");
+        self.send_error_with_code("invalid_wallet", &format!("{} is not a valid wallet address", wallet))
+            .await;
+        false
+    }
+
+    async fn send_rejection(&self, rejection: &Rejection) {
+        if let Ok(msg_str) = serde_json::to_string(rejection) {
+            let _ = self.sender.send(Message::text(msg_str));
+        }
+    }
+
+    async fn send_error_detail(&self, code: &str, error: &str, detail: &str) {
+        let message = serde_json::json!({
+            "type": "error",
+            "code": code,
+            "message": error,
+            "detail": detail,
+        });
+        let _ = self.sender.send(Message::text(message.to_string()));
+    }
+
+    async fn send_error_with_code(&self, code: &str, error: &str) {
         let message = serde_json::json!({
             "type": "error",
+            "code": code,
             "message": error
         });
 
@@ -692,10 +1940,13 @@ impl SfuSignalingHandler {
         }
     }
 
+    /// Removes the peer after its WebSocket went away. A peer that sent Leave
+    /// was already removed, so anything left here lost its connection.
     pub async fn cleanup(&mut self) {
-        if let Some(peer_id) = &self.peer_id {
-            let _ = self.sfu_server.remove_peer(peer_id).await;
-            self.sfu_server.remove_pending_student(peer_id).await;
+        self.follow_transfer();
+        if let (Some(peer_id), Some(room_id)) = (&self.peer_id, &self.room_id) {
+            let _ = self.sfu_server.remove_peer(room_id, peer_id, DisconnectCause::ConnectionLost).await;
+            self.sfu_server.remove_pending_student(room_id, peer_id).await;
         }
     }
 }
@@ -710,6 +1961,11 @@ mod tests {
             peer_id: "proctor_123".to_string(),
             name: Some("Dr. Smith".to_string()),
             wallet_address: Some("0x1234567890abcdef1234567890abcdef12345678".to_string()),
+            timezone: None,
+            locale: None,
+            escalation: EscalationPolicy::default(),
+            tenant: None,
+            recording_enabled: None,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
@@ -725,7 +1981,7 @@ mod tests {
         let msg: SfuMessage = serde_json::from_str(json).unwrap();
 
         match msg {
-            SfuMessage::CreateRoom { peer_id, name, wallet_address } => {
+            SfuMessage::CreateRoom { peer_id, name, wallet_address, .. } => {
                 assert_eq!(peer_id, "proctor_123");
                 assert_eq!(name, Some("Dr. Smith".to_string()));
                 assert_eq!(wallet_address, Some("0x1234".to_string()));
@@ -830,13 +2086,63 @@ mod tests {
             peer_id: "peer_123".to_string(),
             has_video: true,
             has_audio: true,
+            content_hints: HashMap::new(),
+            e2ee: false,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("MediaReady"));
+        assert!(!json.contains("e2ee"));
         assert!(json.contains("true"));
     }
 
+    #[test]
+    fn test_media_ready_content_hints_optional() {
+        let msg: SfuMessage = serde_json::from_str(
+            r#"{"type":"MediaReady","peer_id":"peer_123","has_video":true,"has_audio":false}"#,
+        ).unwrap();
+        assert!(matches!(msg, SfuMessage::MediaReady { ref content_hints, e2ee: false, .. } if content_hints.is_empty()));
+
+        let msg: SfuMessage = serde_json::from_str(
+            r#"{"type":"MediaReady","peer_id":"peer_123","has_video":true,"has_audio":true,"content_hints":{"abc":"screen"}}"#,
+        ).unwrap();
+        match msg {
+            SfuMessage::MediaReady { content_hints, .. } => {
+                assert_eq!(content_hints.get("abc"), Some(&TrackContent::Screen));
+            }
+            _ => panic!("Wrong message type"),
+        }
+
+        let msg: SfuMessage = serde_json::from_str(
+            r#"{"type":"MediaReady","peer_id":"peer_123","has_video":true,"has_audio":true,"e2ee":true}"#,
+        ).unwrap();
+        assert!(matches!(msg, SfuMessage::MediaReady { e2ee: true, .. }));
+    }
+
+    #[test]
+    fn test_serialize_room_state() {
+        let msg = SfuMessage::RoomState {
+            room_id: "123456".to_string(),
+            track_order: vec![TrackOrderEntry {
+                track_id: "student_1_video_abc".to_string(),
+                source_peer_id: "student_1".to_string(),
+                stream_id: "student_1_stream".to_string(),
+                kind: "video".to_string(),
+                content: TrackContent::Camera,
+                e2ee: false,
+            }],
+            revision: None,
+        };
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["type"], "RoomState");
+        assert!(json.get("revision").is_none());
+        assert_eq!(json["track_order"][0]["source_peer_id"], "student_1");
+        assert_eq!(json["track_order"][0]["content"], "camera");
+        assert!(json["track_order"][0].get("e2ee").is_none());
+        assert_eq!(msg.kind(), "RoomState");
+    }
+
     #[test]
     fn test_serialize_leave() {
         let msg = SfuMessage::Leave {
@@ -848,14 +2154,788 @@ mod tests {
         assert!(json.contains("peer_123"));
     }
 
+    #[test]
+    fn test_serialize_recording_gap() {
+        let msg = SfuMessage::RecordingGap {
+            room_id: "ABC123".to_string(),
+            peer_id: "student_1".to_string(),
+            kind: MediaKind::Video,
+            start_offset: 42.5,
+            end_offset: None,
+        };
+
+        let json = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["type"], "RecordingGap");
+        assert_eq!(json["kind"], "video");
+        assert_eq!(json["start_offset"], 42.5);
+        assert!(json["end_offset"].is_null());
+        assert_eq!(msg.kind(), "RecordingGap");
+    }
+
+    #[test]
+    fn test_recording_status_detail_shape() {
+        let json = r#"{
+            "type": "RecordingStatus",
+            "room_id": "ABC123",
+            "recording_peers": ["student_1"],
+            "recordings": [{
+                "peer_id": "student_1",
+                "state": "Recording",
+                "started_at": 1700000000000,
+                "elapsed_secs": 12,
+                "paused": false,
+                "bytes_written": 2048,
+                "segments": 1,
+                "content": "audio_video"
+            }],
+            "completed": [{
+                "peer_id": "student_2",
+                "file": "student_2_1700000000.webm",
+                "started_at": 1699999000000,
+                "stopped_at": 1699999060000,
+                "duration_secs": 60,
+                "bytes_written": 4096,
+                "cid": "QmTest"
+            }]
+        }"#;
+
+        let msg: SfuMessage = serde_json::from_str(json).unwrap();
+        match &msg {
+            SfuMessage::RecordingStatus { recording_peers, recordings, completed, .. } => {
+                assert_eq!(recording_peers, &vec!["student_1".to_string()]);
+                assert_eq!(recordings[0].bytes_written, 2048);
+                assert_eq!(recordings[0].elapsed_secs, 12);
+                assert_eq!(completed[0].cid.as_deref(), Some("QmTest"));
+            }
+            _ => panic!("Wrong message type"),
+        }
+
+        let round_trip: serde_json::Value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(round_trip["recordings"][0]["content"], "audio_video");
+        assert_eq!(round_trip["completed"][0]["duration_secs"], 60);
+    }
+
+    #[test]
+    fn test_recording_status_accepts_legacy_payload() {
+        let json = r#"{"type":"RecordingStatus","room_id":"ABC123","recording_peers":["student_1"]}"#;
+        let msg: SfuMessage = serde_json::from_str(json).unwrap();
+        match msg {
+            SfuMessage::RecordingStatus { recording_peers, recordings, paused_peers, completed, .. } => {
+                assert_eq!(recording_peers.len(), 1);
+                assert!(recordings.is_empty());
+                assert!(paused_peers.is_empty());
+                assert!(completed.is_empty());
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[test]
+    fn test_pause_recording_messages() {
+        let json = r#"{"type":"PauseRecording","room_id":"ABC123","peer_id":"student_1"}"#;
+        let msg: SfuMessage = serde_json::from_str(json).unwrap();
+        assert!(matches!(&msg, SfuMessage::PauseRecording { peer_id, .. } if peer_id == "student_1"));
+        assert_eq!(msg.kind(), "PauseRecording");
+
+        let resumed = SfuMessage::RecordingResumed { room_id: "ABC123".to_string(), peer_id: "student_1".to_string() };
+        let json = serde_json::to_value(&resumed).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "RecordingResumed", "room_id": "ABC123", "peer_id": "student_1" }));
+    }
+
+    #[test]
+    fn test_identity_bound_by_establishing_message() {
+        let join = SfuMessage::JoinRequest {
+            room_id: "123456".to_string(),
+            peer_id: "student_1".to_string(),
+            name: None,
+            role: "student".to_string(),
+            wallet_address: None,
+            pre_registered: false,
+            reminder: false,
+            invite_token: None,
+        };
+        // Only the server sets the roster flag, and only when it is true
+        assert!(!serde_json::to_string(&join).unwrap().contains("pre_registered"));
+        assert_eq!(authorize_identity(None, &join), Ok(Some("student_1".to_string())));
+        assert_eq!(authorize_identity(Some("student_1"), &join), Ok(None));
+    }
+
+    #[test]
+    fn test_spoofed_leave_rejected() {
+        let leave = SfuMessage::Leave {
+            peer_id: "victim".to_string(),
+        };
+        let err = authorize_identity(Some("attacker"), &leave).unwrap_err();
+        assert_eq!(err.code(), "identity_mismatch");
+        assert!(matches!(err, IdentityError::Mismatch { .. }));
+
+        // Leave from a connection that never established an identity is rejected too
+        assert!(matches!(
+            authorize_identity(None, &leave),
+            Err(IdentityError::NotEstablished { .. })
+        ));
+    }
+
+    #[test]
+    fn test_spoofed_answer_rejected() {
+        let answer = SfuMessage::Answer {
+            peer_id: "victim".to_string(),
+            sdp: "v=0".to_string(),
+        };
+        assert!(authorize_identity(Some("attacker"), &answer).is_err());
+        assert_eq!(authorize_identity(Some("victim"), &answer), Ok(None));
+    }
+
+    #[test]
+    fn test_rejoin_under_other_identity_rejected() {
+        let join = SfuMessage::Join {
+            room_id: "123456".to_string(),
+            peer_id: "victim".to_string(),
+            name: None,
+            role: "student".to_string(),
+            wallet_address: None,
+        };
+        assert!(authorize_identity(Some("attacker"), &join).is_err());
+    }
+
+    #[test]
+    fn test_proctor_target_fields_allowed() {
+        let kick = SfuMessage::KickParticipant {
+            room_id: "123456".to_string(),
+            peer_id: "student_1".to_string(),
+            reason: None,
+        };
+        assert_eq!(authorize_identity(Some("proctor_1"), &kick), Ok(None));
+
+        let response = SfuMessage::JoinResponse {
+            room_id: "123456".to_string(),
+            peer_id: "proctor_1".to_string(),
+            approved: true,
+            requester_peer_id: "student_1".to_string(),
+        };
+        assert_eq!(authorize_identity(Some("proctor_1"), &response), Ok(None));
+
+        let spoofed_response = SfuMessage::JoinResponse {
+            room_id: "123456".to_string(),
+            peer_id: "proctor_1".to_string(),
+            approved: true,
+            requester_peer_id: "student_1".to_string(),
+        };
+        assert!(authorize_identity(Some("student_2"), &spoofed_response).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handler_rejects_spoofed_leave_and_answer() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+        handler.peer_id = Some("attacker".to_string());
+
+        handler.handle_message(SfuMessage::Leave { peer_id: "victim".to_string() }).await;
+        handler.handle_message(SfuMessage::Answer {
+            peer_id: "victim".to_string(),
+            sdp: "v=0".to_string(),
+        }).await;
+
+        for _ in 0..2 {
+            let reply = rx.recv().await.unwrap();
+            let reply: serde_json::Value = serde_json::from_str(reply.to_str().unwrap()).unwrap();
+            assert_eq!(reply["type"], "error");
+            assert_eq!(reply["code"], "identity_mismatch");
+        }
+
+        // The attacker's identity is untouched by the rejected Leave
+        assert_eq!(handler.peer_id.as_deref(), Some("attacker"));
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_ice_and_answer_not_blocked_by_slow_work() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+        let budget = Duration::from_millis(500);
+
+        // A student joining a room whose proctor never publishes waits ~3s for the
+        // proctor's tracks; that wait must not hold up the rest of the connection
+        let started = Instant::now();
+        handler.handle_message(SfuMessage::Join {
+            room_id: "123456".to_string(),
+            peer_id: "student_1".to_string(),
+            name: None,
+            role: "student".to_string(),
+            wallet_address: None,
+        }).await;
+        handler.handle_message(SfuMessage::StopRecording {
+            room_id: "123456".to_string(),
+            peer_id: "student_1".to_string(),
+        }).await;
+        handler.handle_message(SfuMessage::IceCandidate {
+            peer_id: "student_1".to_string(),
+            candidate: "candidate:1 1 udp 2122260223 192.0.2.1 54400 typ host".to_string(),
+            sdp_mid: Some("0".to_string()),
+            sdp_mline_index: Some(0),
+        }).await;
+        handler.handle_message(SfuMessage::Answer {
+            peer_id: "student_1".to_string(),
+            sdp: "v=0".to_string(),
+        }).await;
+        assert!(started.elapsed() < budget, "handlers took {:?}", started.elapsed());
+
+        // Replies to the later messages arrive while the join is still waiting
+        let mut types = Vec::new();
+        for _ in 0..2 {
+            let reply = tokio::time::timeout(budget, rx.recv()).await.unwrap().unwrap();
+            let reply: serde_json::Value = serde_json::from_str(reply.to_str().unwrap()).unwrap();
+            types.push(reply["type"].as_str().unwrap().to_string());
+        }
+        types.sort();
+        assert_eq!(types, vec!["RecordingError", "error"]);
+
+        let observed: Vec<_> = metrics()
+            .signaling_handler_latency
+            .entries()
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        for kind in ["Join", "StopRecording", "IceCandidate", "Answer"] {
+            assert!(observed.contains(&kind));
+        }
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
     #[test]
     fn test_serialize_room_created() {
         let msg = SfuMessage::RoomCreated {
             room_id: "123456".to_string(),
+            instance_id: None,
+            timezone: None,
+            locale: None,
+            proctor_token: None,
         };
 
         let json = serde_json::to_string(&msg).unwrap();
         assert!(json.contains("RoomCreated"));
         assert!(json.contains("123456"));
+        assert!(!json.contains("instance_id"));
+
+        let msg = SfuMessage::RoomCreated {
+            room_id: "ab12-123456".to_string(),
+            instance_id: Some("sfu-a".to_string()),
+            timezone: Some("Europe/Berlin".to_string()),
+            locale: Some("de-DE".to_string()),
+            proctor_token: Some("abc123".to_string()),
+        };
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains("\"instance_id\":\"sfu-a\""));
+        assert!(json.contains("\"proctor_token\":\"abc123\""));
+        assert!(json.contains("\"timezone\":\"Europe/Berlin\""));
+    }
+
+    #[test]
+    fn test_create_room_locale_fields_optional() {
+        let json = r#"{"type":"CreateRoom","peer_id":"proctor_123","name":null,"wallet_address":null,"timezone":"Asia/Tokyo","locale":"ja-JP"}"#;
+        match serde_json::from_str::<SfuMessage>(json).unwrap() {
+            SfuMessage::CreateRoom { timezone, locale, .. } => {
+                assert_eq!(timezone.as_deref(), Some("Asia/Tokyo"));
+                assert_eq!(locale.as_deref(), Some("ja-JP"));
+            }
+            _ => panic!("Wrong message type"),
+        }
+
+        let json = r#"{"type":"CreateRoom","peer_id":"proctor_123","name":null,"wallet_address":null}"#;
+        match serde_json::from_str::<SfuMessage>(json).unwrap() {
+            SfuMessage::CreateRoom { timezone, locale, .. } => {
+                assert!(timezone.is_none());
+                assert!(locale.is_none());
+            }
+            _ => panic!("Wrong message type"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_room_rejects_unknown_timezone() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        handler
+            .handle_message(SfuMessage::CreateRoom {
+                peer_id: "proctor_123".to_string(),
+                name: None,
+                wallet_address: None,
+                timezone: Some("Mars/Olympus_Mons".to_string()),
+                locale: None,
+                escalation: EscalationPolicy::default(),
+                tenant: None,
+                recording_enabled: None,
+            })
+            .await;
+
+        let reply = rx.recv().await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["code"], "invalid_timezone");
+        assert!(handler.room_id.is_none());
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_create_room_rejects_invalid_tenant() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        handler
+            .handle_message(SfuMessage::CreateRoom {
+                peer_id: "proctor_123".to_string(),
+                name: None,
+                wallet_address: None,
+                timezone: None,
+                locale: None,
+                escalation: EscalationPolicy::default(),
+                tenant: Some("../uni-b".to_string()),
+                recording_enabled: None,
+            })
+            .await;
+
+        let reply = rx.recv().await.unwrap();
+        let reply: serde_json::Value = serde_json::from_str(reply.to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["code"], "invalid_tenant");
+        assert!(handler.room_id.is_none());
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_unrepairable_answer_gets_invalid_sdp() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+        handler.peer_id = Some("student_1".to_string());
+        handler.room_id = Some("123456".to_string());
+
+        for (sdp, reason) in [
+            ("<html>502 Bad Gateway</html>".to_string(), "not_sdp"),
+            ("v=0\\r\\no=- 1 2 IN IP4 127.0.0.1".to_string(), "bad_line_endings"),
+            (format!("v=0\r\n{}", "a=x\r\n".repeat(max_sdp_bytes())), "too_large"),
+        ] {
+            handler
+                .handle_message(SfuMessage::Answer { peer_id: "student_1".to_string(), sdp })
+                .await;
+            let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+            assert_eq!(reply["type"], "error");
+            assert_eq!(reply["code"], "invalid_sdp");
+            assert_eq!(reply["reason"], reason);
+        }
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_set_session_metadata_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_meta".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let set_metadata = || SfuMessage::SetSessionMetadata {
+            room_id: room_id.clone(),
+            exam_name: Some("  Midterm ".to_string()),
+            course_code: Some("CS101".to_string()),
+            notes: None,
+        };
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(set_metadata()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+        assert!(server.session_metadata(&room_id).await.is_empty());
+
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_meta".to_string());
+        proctor.handle_message(set_metadata()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "SessionMetadataUpdated");
+        assert_eq!(reply["metadata"], serde_json::json!({ "exam_name": "Midterm", "course_code": "CS101" }));
+        assert_eq!(server.session_metadata(&room_id).await.exam_title().as_deref(), Some("CS101: Midterm"));
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_get_recording_metadata_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_sidecar".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let get_metadata = || SfuMessage::GetRecordingMetadata {
+            room_id: room_id.clone(),
+            peer_id: "student_1".to_string(),
+        };
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(get_metadata()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+
+        // Nothing stopped yet
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_sidecar".to_string());
+        proctor.handle_message(get_metadata()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "recording_not_found");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_kick_peer_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_kick".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let kick = |sender: &str, target: &str| SfuMessage::KickPeer {
+            room_id: room_id.clone(),
+            peer_id: sender.to_string(),
+            target_peer_id: target.to_string(),
+            reason: None,
+        };
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(kick("student_1", "proctor_kick")).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+
+        // The deprecated message names the target in peer_id and is held to the same rule
+        student
+            .handle_message(SfuMessage::KickParticipant {
+                room_id: room_id.clone(),
+                peer_id: "proctor_kick".to_string(),
+                reason: None,
+            })
+            .await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+        assert!(server.room_exists(&room_id).await);
+
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_kick".to_string());
+        proctor.handle_message(kick("proctor_kick", "student_9")).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "peer_not_found");
+        proctor.handle_message(kick("proctor_kick", "proctor_kick")).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "invalid_target");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_close_room_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_close".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        let forced: SfuMessage =
+            serde_json::from_str(&format!(r#"{{"type":"CloseRoom","room_id":"{}","reason":null,"force":true}}"#, room_id)).unwrap();
+        student.handle_message(forced).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+        assert!(server.room_exists(&room_id).await);
+
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_close".to_string());
+        proctor.handle_message(SfuMessage::PreviewCloseRoom { room_id: room_id.clone() }).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "ClosePreview");
+        assert_eq!(reply["connected_students"], serde_json::json!([]));
+        assert_eq!(reply["pending_uploads"], 0);
+
+        // `force` defaults to false; an empty room has nothing to block the close
+        let unforced: SfuMessage =
+            serde_json::from_str(&format!(r#"{{"type":"CloseRoom","room_id":"{}","reason":null}}"#, room_id)).unwrap();
+        proctor.handle_message(unforced).await;
+        assert!(!server.room_exists(&room_id).await);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_restart_recording_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_restart".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let restart = || SfuMessage::RestartRecording {
+            room_id: room_id.clone(),
+            target_peer_id: "student_1".to_string(),
+        };
+        assert_eq!(restart().kind(), "RestartRecording");
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(restart()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+
+        // Only a recording in progress can be restarted
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_restart".to_string());
+        proctor.handle_message(restart()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "RecordingError");
+        assert_eq!(reply["peer_id"], "student_1");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_pause_recording_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_pause".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let pause = || SfuMessage::PauseRecording {
+            room_id: room_id.clone(),
+            peer_id: "student_1".to_string(),
+        };
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(pause()).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+
+        // Nothing to pause or resume without a recording
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_pause".to_string());
+        proctor.handle_message(pause()).await;
+        proctor
+            .handle_message(SfuMessage::ResumeRecording { room_id: room_id.clone(), peer_id: "student_1".to_string() })
+            .await;
+        for _ in 0..2 {
+            let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+            assert_eq!(reply["type"], "RecordingError");
+            assert_eq!(reply["peer_id"], "student_1");
+        }
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_transfer_peer_proctor_only() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let room_id = server
+            .create_room("proctor_from".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let transfer = |to_room_id: &str| SfuMessage::TransferPeer {
+            from_room_id: room_id.clone(),
+            to_room_id: to_room_id.to_string(),
+            target_peer_id: "student_1".to_string(),
+        };
+        assert_eq!(transfer("654321").kind(), "TransferPeer");
+
+        let mut student = SfuSignalingHandler::new(server.clone(), tx.clone());
+        student.peer_id = Some("student_1".to_string());
+        student.handle_message(transfer("654321")).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+
+        let mut proctor = SfuSignalingHandler::new(server.clone(), tx);
+        proctor.peer_id = Some("proctor_from".to_string());
+        proctor.handle_message(transfer("654321")).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "room_not_found");
+        proctor.handle_message(transfer(&room_id)).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "invalid_transfer");
+
+        // Only the destination's proctor answers, and only a request that was made
+        let response = |to_room_id: &str| SfuMessage::TransferResponse {
+            from_room_id: "654321".to_string(),
+            to_room_id: to_room_id.to_string(),
+            target_peer_id: "student_1".to_string(),
+            approved: true,
+        };
+        student.handle_message(response(&room_id)).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_proctor");
+        proctor.handle_message(response(&room_id)).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "transfer_not_found");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_hello_negotiates_room_state_deltas() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        let hello: SfuMessage =
+            serde_json::from_str(r#"{"type":"Hello","capabilities":["room_state_delta","video_av1"]}"#).unwrap();
+        handler.handle_message(hello).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply, serde_json::json!({ "type": "Hello", "capabilities": ["room_state_delta"] }));
+        assert!(handler.room_state_deltas);
+
+        // Resyncing needs a room this connection is in
+        handler
+            .handle_message(SfuMessage::GetRoomState { room_id: "123456".to_string(), since_revision: Some(3) })
+            .await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_in_room");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_wallet_address_refused() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        let create: SfuMessage =
+            serde_json::from_str(r#"{"type":"CreateRoom","peer_id":"proctor_123","wallet_address":"0x1234"}"#).unwrap();
+        handler.handle_message(create).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "invalid_wallet");
+        assert_eq!(handler.room_id, None);
+        assert_eq!(server.room_count().await, 0);
+
+        let join: SfuMessage = serde_json::from_str(
+            r#"{"type":"JoinRequest","room_id":"123456","peer_id":"student_1","role":"student","wallet_address":"not-a-wallet"}"#,
+        )
+        .unwrap();
+        let mut student = SfuSignalingHandler::new(server.clone(), handler.sender.clone());
+        student.handle_message(join).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "invalid_wallet");
+        assert_eq!(student.room_id, None);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_client_offer_needs_room_and_connection() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        handler.handle_message(SfuMessage::Offer { sdp: "v=0\r\n".to_string() }).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "not_in_room");
+
+        handler.peer_id = Some("student_1".to_string());
+        handler.room_id = Some("123456".to_string());
+        handler.handle_message(SfuMessage::Renegotiate { sdp: "v=0\r\n".to_string() }).await;
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["code"], "no_connection");
+
+        assert!(server.shutdown().await.is_clean());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_join_request_for_unknown_room() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+
+        handler
+            .handle_message(SfuMessage::JoinRequest {
+                room_id: "999999".to_string(),
+                peer_id: "student_1".to_string(),
+                name: None,
+                role: "student".to_string(),
+                wallet_address: None,
+                pre_registered: false,
+                reminder: false,
+                invite_token: None,
+            })
+            .await;
+
+        let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(reply["type"], "error");
+        assert_eq!(reply["code"], "room_not_found");
+
+        assert!(server.shutdown().await.is_clean());
+    }
+
+    #[tokio::test]
+    async fn test_second_room_needs_its_own_connection() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = Arc::new(SfuServer::new());
+        let other_room = server
+            .create_room("proctor_other".to_string(), None, None, RoomLocale::default())
+            .await
+            .unwrap();
+        let mut handler = SfuSignalingHandler::new(server.clone(), tx);
+        let create_room = || SfuMessage::CreateRoom {
+            peer_id: "proctor_multi".to_string(),
+            name: None,
+            wallet_address: None,
+            timezone: None,
+            locale: None,
+            escalation: EscalationPolicy::default(),
+            tenant: None,
+            recording_enabled: None,
+        };
+
+        handler.handle_message(create_room()).await;
+        let created: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(created["type"], "RoomCreated");
+        let room_id = handler.room_id().unwrap().to_string();
+
+        handler.handle_message(create_room()).await;
+        handler
+            .handle_message(SfuMessage::JoinRequest {
+                room_id: other_room.clone(),
+                peer_id: "proctor_multi".to_string(),
+                name: None,
+                role: "student".to_string(),
+                wallet_address: None,
+                pre_registered: false,
+                reminder: false,
+                invite_token: None,
+            })
+            .await;
+        for _ in 0..2 {
+            loop {
+                let reply: serde_json::Value = serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+                if reply["type"] == "error" {
+                    assert_eq!(reply["code"], "room_session_active");
+                    break;
+                }
+            }
+        }
+        assert_eq!(handler.room_id(), Some(room_id.as_str()));
+        assert_eq!(server.rooms_owned_by("proctor_multi").await, vec![room_id]);
+
+        assert!(server.shutdown().await.is_clean());
+    }
+}
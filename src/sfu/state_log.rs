@@ -0,0 +1,258 @@
+//! Ordered log of the state changes a room delivers as one-off messages, kept so
+//! a peer that refreshes mid-exam can be sent a `StateSync` snapshot and rebuild
+//! its UI. Idempotent kinds keep only their latest event per target.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use crate::config::env;
+
+/// Default number of events a room's log keeps
+pub const DEFAULT_ROOM_EVENT_LOG_LIMIT: usize = 256;
+
+static ROOM_EVENT_LOG_LIMIT: OnceLock<usize> = OnceLock::new();
+
+/// Size bound read from `ROOM_EVENT_LOG_LIMIT` once per process
+pub fn room_event_log_limit() -> usize {
+    *ROOM_EVENT_LOG_LIMIT.get_or_init(|| {
+        env::get_parsed("ROOM_EVENT_LOG_LIMIT")
+            .filter(|limit| *limit > 0)
+            .unwrap_or(DEFAULT_ROOM_EVENT_LOG_LIMIT)
+    })
+}
+
+fn unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub text: String,
+    /// Unix time in milliseconds
+    pub at: u64,
+}
+
+impl Announcement {
+    pub fn now(text: String) -> Self {
+        Self { text, at: unix_ms() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExamClock {
+    /// Unix time in milliseconds
+    pub started_at: u64,
+    pub duration_secs: u64,
+}
+
+impl ExamClock {
+    pub fn starting_now(duration_secs: u64) -> Self {
+        Self { started_at: unix_ms(), duration_secs }
+    }
+}
+
+/// A state change delivered to a room, or to one peer in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomEvent {
+    Announcement(Announcement),
+    /// Lowercase ID verification status, e.g. `valid` or `pending`
+    Verification(String),
+    /// Whether the student consented to being recorded
+    Consent(bool),
+    /// Whether the proctor muted the peer
+    Muted(bool),
+    ExamClock(ExamClock),
+    Recording(bool),
+}
+
+impl RoomEvent {
+    /// Whether the latest event of this kind carries the whole state
+    fn is_idempotent(&self) -> bool {
+        !matches!(self, RoomEvent::Announcement(_))
+    }
+
+    fn same_kind(&self, other: &RoomEvent) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LoggedEvent {
+    seq: u64,
+    /// `None` for events addressed to the whole room
+    peer_id: Option<String>,
+    event: RoomEvent,
+}
+
+/// What a peer has been told so far, rebuilt from the room's event log
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSync {
+    /// Oldest first
+    pub announcements: Vec<Announcement>,
+    pub verification_status: Option<String>,
+    /// `None` until the student answers
+    pub consent: Option<bool>,
+    pub muted: bool,
+    pub exam_clock: Option<ExamClock>,
+    /// Whether the peer is being recorded
+    pub recording: bool,
+    /// Sequence number of the latest event included, 0 for none
+    pub seq: u64,
+}
+
+impl StateSync {
+    fn apply(&mut self, event: &RoomEvent) {
+        match event {
+            RoomEvent::Announcement(announcement) => self.announcements.push(announcement.clone()),
+            RoomEvent::Verification(status) => self.verification_status = Some(status.clone()),
+            RoomEvent::Consent(given) => self.consent = Some(*given),
+            RoomEvent::Muted(muted) => self.muted = *muted,
+            RoomEvent::ExamClock(clock) => self.exam_clock = Some(*clock),
+            RoomEvent::Recording(active) => self.recording = *active,
+        }
+    }
+}
+
+/// Events of one room in the order they happened, bounded to `limit`
+#[derive(Debug, Clone)]
+pub struct RoomEventLog {
+    entries: VecDeque<LoggedEvent>,
+    next_seq: u64,
+    limit: usize,
+}
+
+impl Default for RoomEventLog {
+    fn default() -> Self {
+        Self::new(room_event_log_limit())
+    }
+}
+
+impl RoomEventLog {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next_seq: 1,
+            limit,
+        }
+    }
+
+    /// Appends `event` for `peer_id`, or for everyone when `None`, and returns
+    /// its sequence number. An idempotent event replaces the earlier one of its
+    /// kind for the same target. Over the limit, the oldest announcement goes first.
+    pub fn append(&mut self, peer_id: Option<&str>, event: RoomEvent) -> u64 {
+        if event.is_idempotent() {
+            self.entries
+                .retain(|entry| !(entry.peer_id.as_deref() == peer_id && entry.event.same_kind(&event)));
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries.push_back(LoggedEvent {
+            seq,
+            peer_id: peer_id.map(str::to_string),
+            event,
+        });
+
+        while self.entries.len() > self.limit {
+            let oldest = self
+                .entries
+                .iter()
+                .position(|entry| !entry.event.is_idempotent())
+                .unwrap_or(0);
+            self.entries.remove(oldest);
+        }
+        seq
+    }
+
+    /// State `peer_id` should see, folded from the room-wide events and its own
+    pub fn snapshot(&self, peer_id: &str) -> StateSync {
+        let mut state = StateSync::default();
+        for entry in &self.entries {
+            if entry.peer_id.is_none() || entry.peer_id.as_deref() == Some(peer_id) {
+                state.apply(&entry.event);
+                state.seq = entry.seq;
+            }
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcement(text: &str, at: u64) -> RoomEvent {
+        RoomEvent::Announcement(Announcement { text: text.to_string(), at })
+    }
+
+    #[test]
+    fn test_snapshot_follows_each_kind() {
+        let mut log = RoomEventLog::new(16);
+        assert_eq!(log.snapshot("student_1"), StateSync::default());
+
+        log.append(None, announcement("Ten minutes left", 1));
+        log.append(Some("student_1"), RoomEvent::Verification("pending".to_string()));
+        log.append(Some("student_1"), RoomEvent::Consent(true));
+        log.append(Some("student_1"), RoomEvent::Muted(true));
+        log.append(None, RoomEvent::ExamClock(ExamClock { started_at: 2, duration_secs: 3600 }));
+        log.append(Some("student_1"), RoomEvent::Recording(true));
+        log.append(Some("student_1"), RoomEvent::Verification("valid".to_string()));
+        let seq = log.append(Some("student_1"), RoomEvent::Muted(false));
+
+        assert_eq!(
+            log.snapshot("student_1"),
+            StateSync {
+                announcements: vec![Announcement { text: "Ten minutes left".to_string(), at: 1 }],
+                verification_status: Some("valid".to_string()),
+                consent: Some(true),
+                muted: false,
+                exam_clock: Some(ExamClock { started_at: 2, duration_secs: 3600 }),
+                recording: true,
+                seq,
+            }
+        );
+
+        // Another student only sees what was addressed to the whole room
+        let other = log.snapshot("student_2");
+        assert_eq!(other.announcements.len(), 1);
+        assert!(other.exam_clock.is_some());
+        assert_eq!(other.verification_status, None);
+        assert_eq!(other.consent, None);
+        assert!(!other.recording);
+    }
+
+    #[test]
+    fn test_idempotent_kinds_keep_latest_per_target() {
+        let mut log = RoomEventLog::new(16);
+        for muted in [true, false, true] {
+            log.append(Some("student_1"), RoomEvent::Muted(muted));
+            log.append(Some("student_2"), RoomEvent::Muted(!muted));
+        }
+        log.append(None, announcement("first", 1));
+        log.append(None, announcement("second", 2));
+
+        assert_eq!(log.entries.len(), 4);
+        assert!(log.snapshot("student_1").muted);
+        assert!(!log.snapshot("student_2").muted);
+        assert_eq!(log.snapshot("student_1").announcements.len(), 2);
+    }
+
+    #[test]
+    fn test_limit_drops_oldest_announcements_first() {
+        let mut log = RoomEventLog::new(3);
+        log.append(Some("student_1"), RoomEvent::Consent(true));
+        for at in 0..5 {
+            log.append(None, announcement(&format!("notice {}", at), at));
+        }
+
+        let state = log.snapshot("student_1");
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(state.consent, Some(true));
+        let kept: Vec<u64> = state.announcements.iter().map(|a| a.at).collect();
+        assert_eq!(kept, vec![3, 4]);
+    }
+}
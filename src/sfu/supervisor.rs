@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::{Id, JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+
+/// Background tasks of a server, stopped together on shutdown.
+///
+/// Every task receives a child of the shared cancellation token and is
+/// expected to return promptly once it fires. Cloning shares the same set.
+#[derive(Clone, Default)]
+pub struct TaskSupervisor {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cancel: CancellationToken,
+    tasks: Mutex<Tasks>,
+}
+
+#[derive(Default)]
+struct Tasks {
+    set: JoinSet<()>,
+    names: HashMap<Id, &'static str>,
+}
+
+/// Outcome of [`TaskSupervisor::shutdown`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Tasks that returned after cancellation
+    pub stopped: usize,
+    /// Tasks that panicked, whether before or during shutdown
+    pub panicked: Vec<&'static str>,
+    /// Tasks still running at the deadline; these were aborted
+    pub stragglers: Vec<&'static str>,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.panicked.is_empty() && self.stragglers.is_empty()
+    }
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns a named task, handing it the token it should stop on
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let future = task(self.inner.cancel.child_token());
+        let mut tasks = self.inner.tasks.lock().unwrap();
+
+        // Reap tasks that already finished so one-shot work doesn't accumulate
+        while let Some(result) = tasks.set.try_join_next_with_id() {
+            let id = match &result {
+                Ok((id, ())) => *id,
+                Err(e) => e.id(),
+            };
+            let finished = tasks.names.remove(&id).unwrap_or("unknown");
+            if let Err(e) = result {
+                log_failure(finished, &e);
+            }
+        }
+
+        let handle = tasks.set.spawn(future);
+        tasks.names.insert(handle.id(), name);
+    }
+
+    /// Cancels every task and waits up to `timeout` for them to return.
+    /// Anything still running at the deadline is aborted and reported.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.inner.cancel.cancel();
+        let Tasks { mut set, mut names } = std::mem::take(&mut *self.inner.tasks.lock().unwrap());
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut report = ShutdownReport::default();
+        loop {
+            match tokio::time::timeout_at(deadline, set.join_next_with_id()).await {
+                Ok(None) => break,
+                Ok(Some(Ok((id, ())))) => {
+                    names.remove(&id);
+                    report.stopped += 1;
+                }
+                Ok(Some(Err(e))) => {
+                    let name = names.remove(&e.id()).unwrap_or("unknown");
+                    log_failure(name, &e);
+                    if e.is_panic() {
+                        report.panicked.push(name);
+                    }
+                }
+                Err(_) => {
+                    report.stragglers = names.into_values().collect();
+                    report.stragglers.sort_unstable();
+                    set.abort_all();
+                    break;
+                }
+            }
+        }
+
+        if !report.stragglers.is_empty() {
+            tracing::warn!(
+                stragglers = ?report.stragglers,
+                timeout_ms = timeout.as_millis() as u64,
+                "Background tasks did not stop in time and were aborted"
+            );
+        }
+        report
+    }
+}
+
+fn log_failure(name: &str, error: &JoinError) {
+    if error.is_panic() {
+        tracing::error!(task = name, "Background task panicked");
+    } else {
+        tracing::debug!(task = name, "Background task was aborted");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn running(tasks: &TaskSupervisor) -> usize {
+        tasks.inner.tasks.lock().unwrap().set.len()
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_cancellable_tasks() {
+        let tasks = TaskSupervisor::new();
+        for name in ["first", "second"] {
+            tasks.spawn(name, |cancel| async move {
+                cancel.cancelled().await;
+            });
+        }
+        assert_eq!(running(&tasks), 2);
+
+        let report = tasks.shutdown(Duration::from_secs(1)).await;
+        assert!(report.is_clean());
+        assert_eq!(report.stopped, 2);
+        assert!(tasks.inner.cancel.is_cancelled());
+        assert_eq!(running(&tasks), 0);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_reports_stragglers_and_panics() {
+        let tasks = TaskSupervisor::new();
+        tasks.spawn("ignores_cancel", |_cancel| async move {
+            std::future::pending::<()>().await;
+        });
+        tasks.spawn("panics", |_cancel| async move {
+            panic!("boom");
+        });
+        tasks.spawn("well_behaved", |cancel| async move {
+            cancel.cancelled().await;
+        });
+
+        let started = std::time::Instant::now();
+        let report = tasks.shutdown(Duration::from_millis(100)).await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(report.stopped, 1);
+        assert_eq!(report.panicked, vec!["panics"]);
+        assert_eq!(report.stragglers, vec!["ignores_cancel"]);
+    }
+
+    #[tokio::test]
+    async fn test_finished_tasks_are_reaped_on_spawn() {
+        let tasks = TaskSupervisor::new();
+        tasks.spawn("one_shot", |_cancel| async {});
+        tokio::task::yield_now().await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        tasks.spawn("long_lived", |cancel| async move {
+            cancel.cancelled().await;
+        });
+        assert_eq!(running(&tasks), 1);
+        assert!(tasks.shutdown(Duration::from_secs(1)).await.is_clean());
+    }
+}
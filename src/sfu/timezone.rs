@@ -0,0 +1,841 @@
+//! Room timezone and locale settings.
+//!
+//! Timezones are IANA names resolved against the system zoneinfo database
+//! (`TZDIR`, default `/usr/share/zoneinfo`). They only affect human-facing
+//! renderings; everything stored or sent on-chain stays in UTC.
+//!
+//! The TZif and POSIX TZ readers are kept in-tree: rendering needs only the
+//! offsets, abbreviations and footer rule, and chrono-tz would compile a
+//! tzdata snapshot into the binary rather than follow the host's updates.
+//! Zone files are untrusted input to them; malformed data yields `None`.
+
+use std::path::PathBuf;
+
+use crate::config::env;
+
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+
+/// Validation failure for room locale settings, reported to the client at room creation
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocaleError {
+    UnknownTimezone(String),
+    InvalidLocale(String),
+}
+
+impl LocaleError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LocaleError::UnknownTimezone(_) => "invalid_timezone",
+            LocaleError::InvalidLocale(_) => "invalid_locale",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            LocaleError::UnknownTimezone(name) => format!("Unknown timezone '{}', expected an IANA name such as Europe/Berlin", name),
+            LocaleError::InvalidLocale(tag) => format!("Invalid locale '{}', expected a language tag such as de-DE", tag),
+        }
+    }
+}
+
+/// Timezone and locale a room's human-facing timestamps are rendered in
+#[derive(Debug, Clone, Default)]
+pub struct RoomLocale {
+    pub timezone: Option<Timezone>,
+    pub locale: Option<String>,
+}
+
+impl RoomLocale {
+    /// Validate the optional settings from a `CreateRoom` message
+    pub fn parse(timezone: Option<&str>, locale: Option<&str>) -> Result<Self, LocaleError> {
+        let timezone = timezone.filter(|s| !s.is_empty()).map(Timezone::load).transpose()?;
+        let locale = locale.filter(|s| !s.is_empty()).map(validate_locale).transpose()?;
+        Ok(Self { timezone, locale })
+    }
+
+    pub fn timezone_name(&self) -> Option<String> {
+        self.timezone.as_ref().map(|tz| tz.name().to_string())
+    }
+
+    /// Render a Unix millisecond timestamp in the room's timezone, if one is set
+    pub fn format_local_ms(&self, unix_ms: u64) -> Option<String> {
+        self.timezone.as_ref().map(|tz| tz.format((unix_ms / 1000) as i64))
+    }
+}
+
+/// Accepts BCP 47 shaped tags (`en`, `de-DE`, `zh-Hant-TW`) and normalizes `_` to `-`
+pub fn validate_locale(tag: &str) -> Result<String, LocaleError> {
+    let normalized = tag.replace('_', "-");
+    let mut parts = normalized.split('-');
+
+    let language_ok = parts
+        .next()
+        .map(|lang| (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_alphabetic()))
+        .unwrap_or(false);
+    let rest_ok = parts.all(|p| (1..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    if language_ok && rest_ok {
+        Ok(normalized)
+    } else {
+        Err(LocaleError::InvalidLocale(tag.to_string()))
+    }
+}
+
+/// Local time type from the zoneinfo data
+#[derive(Debug, Clone, PartialEq)]
+struct LocalType {
+    utc_offset: i32,
+    is_dst: bool,
+    abbreviation: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Timezone {
+    name: String,
+    /// Transition instants (Unix seconds) and the local type that starts there
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalType>,
+    /// Rule for instants after the last transition
+    footer: Option<PosixRule>,
+}
+
+impl Timezone {
+    /// Look up an IANA timezone in the system zoneinfo database
+    pub fn load(name: &str) -> Result<Self, LocaleError> {
+        let unknown = || LocaleError::UnknownTimezone(name.to_string());
+
+        // Reject anything that isn't a plain relative zone path
+        let valid = !name.is_empty()
+            && !name.starts_with('/')
+            && name.split('/').all(|part| !part.is_empty() && part != "." && part != "..")
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+        if !valid {
+            return Err(unknown());
+        }
+
+        let dir = PathBuf::from(env::get_string("TZDIR").unwrap_or_else(|| DEFAULT_TZDIR.to_string()));
+        let data = std::fs::read(dir.join(name)).map_err(|_| unknown())?;
+        Self::from_tzif(name, &data).ok_or_else(unknown)
+    }
+
+    /// Parse TZif data (RFC 8536), preferring the 64-bit block of version 2+ files
+    pub fn from_tzif(name: &str, data: &[u8]) -> Option<Self> {
+        let header = TzifHeader::parse(data)?;
+        let (header, body, time_size) = if header.version >= b'2' {
+            let v1_len = header.block_len(4);
+            let second = data.get(44 + v1_len..)?;
+            (TzifHeader::parse(second)?, &second[44..], 8)
+        } else {
+            (header, &data[44..], 4)
+        };
+
+        let mut cursor = 0usize;
+        let mut take = |len: usize| -> Option<&[u8]> {
+            let slice = body.get(cursor..cursor + len)?;
+            cursor += len;
+            Some(slice)
+        };
+
+        let times = take(header.timecnt * time_size)?;
+        let indices = take(header.timecnt)?;
+        let type_records = take(header.typecnt * 6)?;
+        let chars = take(header.charcnt)?;
+        take(header.leapcnt * (time_size + 4))?;
+        take(header.isstdcnt)?;
+        take(header.isutcnt)?;
+        let footer_start = cursor;
+
+        let mut types = Vec::with_capacity(header.typecnt);
+        for record in type_records.chunks_exact(6) {
+            let utc_offset = i32::from_be_bytes([record[0], record[1], record[2], record[3]]);
+            let abbr_start = record[5] as usize;
+            let abbr_end = chars.get(abbr_start..)?.iter().position(|&b| b == 0)? + abbr_start;
+            types.push(LocalType {
+                utc_offset,
+                is_dst: record[4] != 0,
+                abbreviation: String::from_utf8_lossy(&chars[abbr_start..abbr_end]).to_string(),
+            });
+        }
+        if types.is_empty() {
+            return None;
+        }
+
+        let mut transitions = Vec::with_capacity(header.timecnt);
+        for (i, &index) in indices.iter().enumerate() {
+            let raw = &times[i * time_size..(i + 1) * time_size];
+            let at = if time_size == 8 {
+                i64::from_be_bytes(raw.try_into().ok()?)
+            } else {
+                i32::from_be_bytes(raw.try_into().ok()?) as i64
+            };
+            if index as usize >= types.len() {
+                return None;
+            }
+            transitions.push((at, index as usize));
+        }
+
+        let footer = if time_size == 8 {
+            body.get(footer_start..)
+                .and_then(|rest| rest.strip_prefix(b"\n"))
+                .and_then(|rest| rest.split(|&b| b == b'\n').next())
+                .and_then(|line| std::str::from_utf8(line).ok())
+                .filter(|line| !line.is_empty())
+                .and_then(PosixRule::parse)
+        } else {
+            None
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            transitions,
+            types,
+            footer,
+        })
+    }
+
+    /// Zone fixed at UTC, for settings where no timezone was configured
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_string(),
+            transitions: Vec::new(),
+            types: vec![LocalType {
+                utc_offset: 0,
+                is_dst: false,
+                abbreviation: "UTC".to_string(),
+            }],
+            footer: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// UTC offset in seconds and zone abbreviation in effect at a Unix instant
+    pub fn offset_at(&self, unix_secs: i64) -> (i32, String) {
+        if let (Some(footer), Some(&(last, _))) = (&self.footer, self.transitions.last()) {
+            if unix_secs >= last {
+                return footer.offset_at(unix_secs);
+            }
+        }
+        if self.transitions.is_empty() {
+            if let Some(ref footer) = self.footer {
+                return footer.offset_at(unix_secs);
+            }
+        }
+
+        let local = match self.transitions.partition_point(|&(at, _)| at <= unix_secs) {
+            // Before the first transition the first standard-time type applies
+            0 => self.types.iter().find(|t| !t.is_dst).unwrap_or(&self.types[0]),
+            n => &self.types[self.transitions[n - 1].1],
+        };
+        (local.utc_offset, local.abbreviation.clone())
+    }
+
+    /// Local calendar date and seconds since local midnight at a Unix instant
+    pub fn local_time(&self, unix_secs: i64) -> (LocalDate, u32) {
+        let local = unix_secs + self.offset_at(unix_secs).0 as i64;
+        (LocalDate(local.div_euclid(86_400)), local.rem_euclid(86_400) as u32)
+    }
+
+    /// Human-facing rendering such as `2024-03-10 03:30:00 EDT (UTC-04:00)`
+    pub fn format(&self, unix_secs: i64) -> String {
+        let (offset, abbreviation) = self.offset_at(unix_secs);
+        let local = unix_secs + offset as i64;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let secs_of_day = local.rem_euclid(86_400);
+
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset_abs = offset.unsigned_abs();
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {} (UTC{}{:02}:{:02})",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day % 3600 / 60,
+            secs_of_day % 60,
+            abbreviation,
+            sign,
+            offset_abs / 3600,
+            offset_abs % 3600 / 60
+        )
+    }
+}
+
+/// Calendar date without a timezone, rendered and parsed as `YYYY-MM-DD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LocalDate(i64);
+
+impl LocalDate {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, '-');
+        let mut field = |len: usize| {
+            parts
+                .next()
+                .filter(|p| p.len() == len && p.chars().all(|c| c.is_ascii_digit()))
+                .and_then(|p| p.parse::<i64>().ok())
+        };
+        let (year, month, day) = (field(4)?, field(2)?, field(2)?);
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(Self(days_from_civil(year, month, day)))
+    }
+
+    pub fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+
+    pub fn minus_days(self, days: i64) -> Self {
+        Self(self.0 - days)
+    }
+}
+
+impl std::fmt::Display for LocalDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_from_days(self.0);
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+struct TzifHeader {
+    version: u8,
+    isutcnt: usize,
+    isstdcnt: usize,
+    leapcnt: usize,
+    timecnt: usize,
+    typecnt: usize,
+    charcnt: usize,
+}
+
+impl TzifHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 44 || &data[..4] != b"TZif" {
+            return None;
+        }
+        let count = |i: usize| u32::from_be_bytes(data[20 + i * 4..24 + i * 4].try_into().unwrap()) as usize;
+        Some(Self {
+            version: data[4],
+            isutcnt: count(0),
+            isstdcnt: count(1),
+            leapcnt: count(2),
+            timecnt: count(3),
+            typecnt: count(4),
+            charcnt: count(5),
+        })
+    }
+
+    fn block_len(&self, time_size: usize) -> usize {
+        self.timecnt * time_size
+            + self.timecnt
+            + self.typecnt * 6
+            + self.charcnt
+            + self.leapcnt * (time_size + 4)
+            + self.isstdcnt
+            + self.isutcnt
+    }
+}
+
+/// Day of year a POSIX TZ rule switches on
+#[derive(Debug, Clone, PartialEq)]
+enum RuleDate {
+    /// `Jn`: day 1-365, February 29 never counted
+    Julian(u16),
+    /// `n`: zero-based day 0-365, counting February 29 in leap years
+    ZeroBased(u16),
+    /// `Mm.w.d`: day `d` (0 = Sunday) of week `w` (5 = last) of month `m`
+    MonthWeekDay { month: u8, week: u8, weekday: u8 },
+}
+
+impl RuleDate {
+    /// Days since the Unix epoch of this date in `year`
+    fn day_in_year(&self, year: i64) -> i64 {
+        let jan1 = days_from_civil(year, 1, 1);
+        match *self {
+            RuleDate::Julian(n) => {
+                let n = n as i64;
+                // Julian days skip Feb 29, so shift by one from March on in leap years
+                jan1 + n - 1 + if is_leap(year) && n >= 60 { 1 } else { 0 }
+            }
+            RuleDate::ZeroBased(n) => jan1 + n as i64,
+            RuleDate::MonthWeekDay { month, week, weekday } => {
+                let first = days_from_civil(year, month as i64, 1);
+                // 1970-01-01 was a Thursday
+                let first_weekday = (first + 4).rem_euclid(7);
+                let mut day = first + (weekday as i64 - first_weekday).rem_euclid(7) + (week as i64 - 1) * 7;
+                let month_len = days_in_month(year, month as i64);
+                while day >= first + month_len {
+                    day -= 7;
+                }
+                day
+            }
+        }
+    }
+}
+
+/// The TZ string at the end of a TZif file, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, Clone, PartialEq)]
+struct PosixRule {
+    std_abbr: String,
+    /// Seconds east of UTC (the POSIX string uses west-positive offsets)
+    std_offset: i32,
+    dst: Option<PosixDst>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PosixDst {
+    abbr: String,
+    offset: i32,
+    start: (RuleDate, i32),
+    end: (RuleDate, i32),
+}
+
+impl PosixRule {
+    fn parse(s: &str) -> Option<Self> {
+        let mut p = PosixParser { s: s.as_bytes(), pos: 0 };
+        let std_abbr = p.abbreviation()?;
+        let std_offset = -p.offset()?;
+
+        if p.done() {
+            return Some(Self { std_abbr, std_offset, dst: None });
+        }
+
+        let dst_abbr = p.abbreviation()?;
+        let dst_offset = if p.peek().map(|c| c != b',').unwrap_or(false) {
+            -p.offset()?
+        } else {
+            std_offset + 3600
+        };
+
+        // Rules are required in TZif footers whenever DST is present
+        p.expect(b',')?;
+        let start = p.rule()?;
+        p.expect(b',')?;
+        let end = p.rule()?;
+        if !p.done() {
+            return None;
+        }
+
+        Some(Self {
+            std_abbr,
+            std_offset,
+            dst: Some(PosixDst {
+                abbr: dst_abbr,
+                offset: dst_offset,
+                start,
+                end,
+            }),
+        })
+    }
+
+    fn offset_at(&self, unix_secs: i64) -> (i32, String) {
+        let Some(ref dst) = self.dst else {
+            return (self.std_offset, self.std_abbr.clone());
+        };
+
+        let (year, _, _) = civil_from_days((unix_secs + self.std_offset as i64).div_euclid(86_400));
+        // Start is expressed in standard time, end in daylight time
+        let start = dst.start.0.day_in_year(year) * 86_400 + dst.start.1 as i64 - self.std_offset as i64;
+        let end = dst.end.0.day_in_year(year) * 86_400 + dst.end.1 as i64 - dst.offset as i64;
+
+        let in_dst = if start < end {
+            unix_secs >= start && unix_secs < end
+        } else {
+            // Southern hemisphere: DST spans the new year
+            unix_secs >= start || unix_secs < end
+        };
+
+        if in_dst {
+            (dst.offset, dst.abbr.clone())
+        } else {
+            (self.std_offset, self.std_abbr.clone())
+        }
+    }
+}
+
+struct PosixParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl PosixParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn done(&self) -> bool {
+        self.pos >= self.s.len()
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        (self.peek()? == c).then(|| self.pos += 1)
+    }
+
+    fn abbreviation(&mut self) -> Option<String> {
+        let start = self.pos;
+        let abbr = if self.peek()? == b'<' {
+            self.pos += 1;
+            while self.peek()? != b'>' {
+                self.pos += 1;
+            }
+            self.pos += 1;
+            &self.s[start + 1..self.pos - 1]
+        } else {
+            while self.peek().map(|c| c.is_ascii_alphabetic()).unwrap_or(false) {
+                self.pos += 1;
+            }
+            &self.s[start..self.pos]
+        };
+        (abbr.len() >= 3).then(|| String::from_utf8_lossy(abbr).to_string())
+    }
+
+    fn number(&mut self) -> Option<i32> {
+        let start = self.pos;
+        while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos]).ok()?.parse().ok()
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, sign as written
+    fn offset(&mut self) -> Option<i32> {
+        let sign = match self.peek()? {
+            b'-' => {
+                self.pos += 1;
+                -1
+            }
+            b'+' => {
+                self.pos += 1;
+                1
+            }
+            _ => 1,
+        };
+        // RFC 8536 allows hours up to 167 in transition times
+        let mut secs = self.number().filter(|h| *h <= 167)? * 3600;
+        if self.peek() == Some(b':') {
+            self.pos += 1;
+            secs += self.number().filter(|m| *m <= 59)? * 60;
+            if self.peek() == Some(b':') {
+                self.pos += 1;
+                secs += self.number().filter(|s| *s <= 59)?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    fn rule(&mut self) -> Option<(RuleDate, i32)> {
+        let date = match self.peek()? {
+            b'M' => {
+                self.pos += 1;
+                let month = self.number()?;
+                self.expect(b'.')?;
+                let week = self.number()?;
+                self.expect(b'.')?;
+                let weekday = self.number()?;
+                if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+                    return None;
+                }
+                RuleDate::MonthWeekDay {
+                    month: month as u8,
+                    week: week as u8,
+                    weekday: weekday as u8,
+                }
+            }
+            b'J' => {
+                self.pos += 1;
+                let n = self.number()?;
+                if !(1..=365).contains(&n) {
+                    return None;
+                }
+                RuleDate::Julian(n as u16)
+            }
+            _ => {
+                let n = self.number()?;
+                if !(0..=365).contains(&n) {
+                    return None;
+                }
+                RuleDate::ZeroBased(n as u16)
+            }
+        };
+
+        // Transition time defaults to 02:00 local
+        let time = if self.peek() == Some(b'/') {
+            self.pos += 1;
+            self.offset()?
+        } else {
+            7200
+        };
+        Some((date, time))
+    }
+}
+
+fn is_leap(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Timezone with no transitions, driven entirely by a POSIX rule
+    fn rule_only(name: &str, rule: &str) -> Timezone {
+        Timezone {
+            name: name.to_string(),
+            transitions: Vec::new(),
+            types: vec![LocalType {
+                utc_offset: 0,
+                is_dst: false,
+                abbreviation: "UTC".to_string(),
+            }],
+            footer: Some(PosixRule::parse(rule).unwrap()),
+        }
+    }
+
+    // 2024-03-10 07:00:00 UTC = 02:00 EST, when US clocks spring forward
+    const US_SPRING_FORWARD_2024: i64 = 1_710_054_000;
+    // 2024-11-03 06:00:00 UTC = 02:00 EDT, when US clocks fall back
+    const US_FALL_BACK_2024: i64 = 1_730_613_600;
+
+    #[test]
+    fn test_civil_date_round_trip() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+
+    #[test]
+    fn test_parse_posix_rules() {
+        let rule = PosixRule::parse("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(rule.std_offset, -5 * 3600);
+        let dst = rule.dst.unwrap();
+        assert_eq!(dst.offset, -4 * 3600);
+        assert_eq!(dst.start.1, 7200);
+
+        let quoted = PosixRule::parse("<+0330>-3:30").unwrap();
+        assert_eq!(quoted.std_abbr, "+0330");
+        assert_eq!(quoted.std_offset, 3 * 3600 + 1800);
+        assert!(quoted.dst.is_none());
+
+        assert!(PosixRule::parse("EST5EDT,M13.2.0,M11.1.0").is_none());
+        assert!(PosixRule::parse("X5").is_none());
+    }
+
+    #[test]
+    fn test_us_dst_boundaries() {
+        let tz = rule_only("America/New_York", "EST5EDT,M3.2.0,M11.1.0");
+
+        assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024 - 1), (-5 * 3600, "EST".to_string()));
+        assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024), (-4 * 3600, "EDT".to_string()));
+        assert_eq!(tz.format(US_SPRING_FORWARD_2024 - 1), "2024-03-10 01:59:59 EST (UTC-05:00)");
+        assert_eq!(tz.format(US_SPRING_FORWARD_2024), "2024-03-10 03:00:00 EDT (UTC-04:00)");
+
+        assert_eq!(tz.offset_at(US_FALL_BACK_2024 - 1).1, "EDT");
+        assert_eq!(tz.offset_at(US_FALL_BACK_2024).1, "EST");
+        // 01:30 local happens twice; the renderings stay unambiguous through the abbreviation
+        assert_eq!(tz.format(US_FALL_BACK_2024 - 1800), "2024-11-03 01:30:00 EDT (UTC-04:00)");
+        assert_eq!(tz.format(US_FALL_BACK_2024 + 1800), "2024-11-03 01:30:00 EST (UTC-05:00)");
+    }
+
+    #[test]
+    fn test_southern_hemisphere_dst() {
+        // Australia/Sydney: DST from first Sunday of October to first Sunday of April
+        let tz = rule_only("Australia/Sydney", "AEST-10AEDT,M10.1.0,M4.1.0/3");
+
+        // 2024-01-15 00:00 UTC is summer in Sydney
+        assert_eq!(tz.offset_at(1_705_276_800), (11 * 3600, "AEDT".to_string()));
+        // 2024-07-15 00:00 UTC is winter
+        assert_eq!(tz.offset_at(1_721_001_600), (10 * 3600, "AEST".to_string()));
+        // 2024-04-07 03:00 AEDT (16:00 UTC on the 6th) clocks go back to 02:00 AEST
+        assert_eq!(tz.offset_at(1_712_419_200 - 1).1, "AEDT");
+        assert_eq!(tz.offset_at(1_712_419_200).1, "AEST");
+    }
+
+    #[test]
+    fn test_local_dates() {
+        let tz = rule_only("America/New_York", "EST5EDT,M3.2.0,M11.1.0");
+        // 2024-03-10 04:30 UTC is still the 9th in New York
+        let (date, secs) = tz.local_time(US_SPRING_FORWARD_2024 - 9000);
+        assert_eq!(date.to_string(), "2024-03-09");
+        assert_eq!(secs, 23 * 3600 + 30 * 60);
+        assert_eq!(Timezone::utc().local_time(US_SPRING_FORWARD_2024 - 9000).0.to_string(), "2024-03-10");
+
+        let leap = LocalDate::parse("2024-02-29").unwrap();
+        assert_eq!(leap.next().to_string(), "2024-03-01");
+        assert_eq!(leap.minus_days(60).to_string(), "2023-12-31");
+        assert!(LocalDate::parse("2024-02-29") < LocalDate::parse("2024-03-01"));
+        for invalid in ["2023-02-29", "2024-13-01", "2024-1-01", "20240101", "2024-01-01x", ""] {
+            assert!(LocalDate::parse(invalid).is_none(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_system_zoneinfo_when_available() {
+        let Ok(tz) = Timezone::load("America/New_York") else {
+            // No tzdata installed in this environment
+            return;
+        };
+        assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024).0, -4 * 3600);
+        assert_eq!(tz.offset_at(US_FALL_BACK_2024).0, -5 * 3600);
+        // Historical data: 1950-07-01 was EDT too
+        assert_eq!(tz.offset_at(-615_513_600).1, "EDT");
+    }
+
+    const NEW_YORK_FOOTER: &str = "EST5EDT,M3.2.0,M11.1.0";
+
+    /// TZif version 2 data for a New York that starts observing DST in 2024
+    fn new_york_tzif() -> Vec<u8> {
+        let transitions = [(US_SPRING_FORWARD_2024, 1u8), (US_FALL_BACK_2024, 0u8)];
+        let block = |time_size: usize| {
+            let mut block = b"TZif2".to_vec();
+            block.extend_from_slice(&[0; 15]);
+            // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+            for count in [0u32, 0, 0, 2, 2, 8] {
+                block.extend_from_slice(&count.to_be_bytes());
+            }
+            for (at, _) in transitions {
+                match time_size {
+                    8 => block.extend_from_slice(&at.to_be_bytes()),
+                    _ => block.extend_from_slice(&(at as i32).to_be_bytes()),
+                }
+            }
+            block.extend(transitions.iter().map(|&(_, index)| index));
+            for (offset, is_dst, abbr_index) in [(-5 * 3600i32, 0u8, 0u8), (-4 * 3600, 1, 4)] {
+                block.extend_from_slice(&offset.to_be_bytes());
+                block.extend_from_slice(&[is_dst, abbr_index]);
+            }
+            block.extend_from_slice(b"EST\0EDT\0");
+            block
+        };
+
+        let mut data = block(4);
+        data.extend(block(8));
+        data.extend_from_slice(format!("\n{}\n", NEW_YORK_FOOTER).as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_tzif_transitions_and_footer() {
+        let tz = Timezone::from_tzif("America/New_York", &new_york_tzif()).unwrap();
+        assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024 - 1), (-5 * 3600, "EST".to_string()));
+        assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024), (-4 * 3600, "EDT".to_string()));
+        assert_eq!(tz.offset_at(US_FALL_BACK_2024).1, "EST");
+        // 2025-07-01 00:00 UTC is past the last transition, so the footer rule applies
+        assert_eq!(tz.offset_at(1_751_328_000).1, "EDT");
+    }
+
+    #[test]
+    fn test_truncated_tzif_is_rejected() {
+        let data = new_york_tzif();
+        let footer_start = data.len() - NEW_YORK_FOOTER.len() - 2;
+        for len in 0..footer_start {
+            assert!(Timezone::from_tzif("America/New_York", &data[..len]).is_none(), "{} bytes", len);
+        }
+        // A cut footer at worst loses the rule for instants after the last transition
+        for len in footer_start..data.len() {
+            let tz = Timezone::from_tzif("America/New_York", &data[..len]).unwrap();
+            assert_eq!(tz.offset_at(US_SPRING_FORWARD_2024).1, "EDT");
+            tz.format(1_751_328_000);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_tzif_never_panics() {
+        let data = new_york_tzif();
+        // Seeded, so a failure reproduces
+        let mut rng = StdRng::seed_from_u64(2229);
+        for _ in 0..5000 {
+            let mut corrupt = data.clone();
+            for _ in 0..rng.gen_range(1..=4) {
+                let i = rng.gen_range(0..corrupt.len());
+                corrupt[i] = rng.gen();
+            }
+            if let Some(tz) = Timezone::from_tzif("America/New_York", &corrupt) {
+                for at in [-2_000_000_000, 0, US_SPRING_FORWARD_2024, US_FALL_BACK_2024, 4_000_000_000] {
+                    tz.format(at);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_garbage_posix_rules_never_panic() {
+        assert!(PosixRule::parse("EST999999").is_none());
+        assert!(PosixRule::parse("EST5EDT,M3.2.0/99999,M11.1.0").is_none());
+        assert!(PosixRule::parse("EST5:60").is_none());
+
+        const ALPHABET: &[u8] = b"ESTDJM<>0123456789+-,.:/";
+        let mut rng = StdRng::seed_from_u64(2229);
+        for _ in 0..5000 {
+            let len = rng.gen_range(0..32);
+            let rule: String = (0..len).map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char).collect();
+            if let Some(rule) = PosixRule::parse(&rule) {
+                for at in [-2_000_000_000, 0, US_SPRING_FORWARD_2024, 4_000_000_000] {
+                    rule.offset_at(at);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_timezone_names() {
+        for name in ["", "Mars/Olympus_Mons", "../etc/passwd", "/etc/localtime", "Europe/../../x"] {
+            let err = Timezone::load(name).unwrap_err();
+            assert_eq!(err.code(), "invalid_timezone");
+        }
+    }
+
+    #[test]
+    fn test_locale_validation() {
+        assert_eq!(validate_locale("en").unwrap(), "en");
+        assert_eq!(validate_locale("de_DE").unwrap(), "de-DE");
+        assert_eq!(validate_locale("zh-Hant-TW").unwrap(), "zh-Hant-TW");
+        assert_eq!(validate_locale("english").unwrap_err().code(), "invalid_locale");
+        assert!(validate_locale("en--US").is_err());
+    }
+
+    #[test]
+    fn test_room_locale_defaults_to_utc_rendering_off() {
+        let locale = RoomLocale::parse(None, Some("")).unwrap();
+        assert!(locale.timezone.is_none());
+        assert!(locale.locale.is_none());
+        assert!(locale.format_local_ms(1_700_000_000_000).is_none());
+    }
+}
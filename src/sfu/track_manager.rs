@@ -1,16 +1,68 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
 use webrtc::track::track_local::track_local_static_rtp::TrackLocalStaticRTP;
 use webrtc::track::track_remote::TrackRemote;
 
+use super::room::PeerKey;
+
+
+/// What a published track captures, as hinted by the publisher in `MediaReady`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackContent {
+    /// Camera and microphone; assumed when the publisher sends no hint
+    #[default]
+    Camera,
+    Screen,
+}
+
+/// One forwarded track in the order subscribers receive it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackOrderEntry {
+    pub track_id: String,
+    pub source_peer_id: String,
+    /// Stream half of the msid, shared by every track from one source peer
+    pub stream_id: String,
+    pub kind: String,
+    pub content: TrackContent,
+    /// Set when the publisher encrypts its media end to end; the track is
+    /// forwarded as is but cannot be recorded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub e2ee: bool,
+}
+
+/// Sorts tracks by when their source peer joined (in `join_order`), then
+/// camera before screen, video before audio, so every subscriber sees the
+/// same tile and m-line order however the tracks were stored. Tracks from
+/// peers missing from `join_order` go last.
+pub fn order_tracks(mut tracks: Vec<TrackOrderEntry>, join_order: &[String]) -> Vec<TrackOrderEntry> {
+    let rank = |peer_id: &str| join_order.iter().position(|id| id == peer_id).unwrap_or(usize::MAX);
+    tracks.sort_by(|a, b| {
+        rank(&a.source_peer_id)
+            .cmp(&rank(&b.source_peer_id))
+            .then_with(|| a.source_peer_id.cmp(&b.source_peer_id))
+            .then_with(|| a.content.cmp(&b.content))
+            .then_with(|| (a.kind != "video").cmp(&(b.kind != "video")))
+            .then_with(|| a.track_id.cmp(&b.track_id))
+    });
+    tracks
+}
 
 #[derive(Clone)]
 pub struct ForwardedTrack {
     pub id: String,
     pub kind: String,
+    /// Room the track is published in; it is only forwarded within it
+    pub room_id: String,
     pub source_peer_id: String,
+    /// Track ID chosen by the publisher, which content hints refer to
+    pub publisher_track_id: String,
+    /// Whether the publisher reported end-to-end encrypted media, whose
+    /// payloads must not be inspected for keyframes
+    pub e2ee: bool,
     pub remote_track: Arc<TrackRemote>,
     pub local_tracks: HashMap<String, Arc<TrackLocalStaticRTP>>,
 }
@@ -25,17 +77,30 @@ impl ForwardedTrack {
     pub fn ssrc(&self) -> u32 {
         self.remote_track.ssrc()
     }
+
+    pub fn source(&self) -> PeerKey {
+        PeerKey::new(self.room_id.clone(), self.source_peer_id.clone())
+    }
 }
 
 
 pub struct TrackManager {
-    tracks: Arc<RwLock<HashMap<String, ForwardedTrack>>>,
+    /// By (room_id, track_id). Track IDs are built from the publisher's peer ID,
+    /// so a proctor publishing the same camera in two rooms repeats them.
+    tracks: Arc<RwLock<HashMap<(String, String), ForwardedTrack>>>,
+    /// Publisher track ID -> content, per source peer. Hints may arrive before the tracks.
+    content_hints: Arc<RwLock<HashMap<PeerKey, HashMap<String, TrackContent>>>>,
+    /// Source peers publishing end-to-end encrypted media. Reported in `MediaReady`,
+    /// which may arrive before the tracks.
+    e2ee_sources: Arc<RwLock<HashSet<PeerKey>>>,
 }
 
 impl TrackManager {
     pub fn new() -> Self {
         Self {
             tracks: Arc::new(RwLock::new(HashMap::new())),
+            content_hints: Arc::new(RwLock::new(HashMap::new())),
+            e2ee_sources: Arc::new(RwLock::new(HashSet::new())),
         }
     }
 
@@ -43,31 +108,35 @@ impl TrackManager {
     pub async fn add_track(
         &self,
         track_id: String,
-        source_peer_id: String,
+        source: &PeerKey,
         remote_track: Arc<TrackRemote>,
     ) {
         let forwarded_track = ForwardedTrack {
             id: track_id.clone(),
             kind: remote_track.kind().to_string(),
-            source_peer_id,
+            room_id: source.room_id.clone(),
+            source_peer_id: source.peer_id.clone(),
+            publisher_track_id: remote_track.id(),
+            e2ee: self.is_e2ee(source).await,
             remote_track,
             local_tracks: HashMap::new(),
         };
 
         let mut tracks = self.tracks.write().await;
-        tracks.insert(track_id, forwarded_track);
+        tracks.insert((source.room_id.clone(), track_id), forwarded_track);
     }
 
-    /// Create a local track for forwarding to a peer.
+    /// Create a local track for forwarding a track of the target's room to it.
     /// Returns (local_track, is_new_subscriber, is_video, ssrc, source_peer_id)
     pub async fn create_local_track_for_peer(
         &self,
         track_id: &str,
-        target_peer_id: &str,
+        target: &PeerKey,
     ) -> Option<(Arc<TrackLocalStaticRTP>, bool, bool, u32, String)> {
         let mut tracks = self.tracks.write().await;
 
-        if let Some(forwarded_track) = tracks.get_mut(track_id) {
+        if let Some(forwarded_track) = tracks.get_mut(&(target.room_id.clone(), track_id.to_string())) {
+            let target_peer_id = target.peer_id.as_str();
             if forwarded_track.source_peer_id == target_peer_id {
                 return None;
             }
@@ -84,7 +153,7 @@ impl TrackManager {
             let local_track = Arc::new(TrackLocalStaticRTP::new(
                 codec.capability.clone(),
                 track_id.to_string(), // Keep the original track ID which includes source peer ID
-                format!("{}_stream", forwarded_track.source_peer_id),
+                stream_id(&forwarded_track.source_peer_id),
             ));
 
             forwarded_track.local_tracks.insert(target_peer_id.to_string(), local_track.clone());
@@ -95,29 +164,200 @@ impl TrackManager {
     }
 
 
-    pub async fn get_tracks_from_peer(&self, peer_id: &str) -> Vec<String> {
+    pub async fn get_tracks_from_peer(&self, source: &PeerKey) -> Vec<String> {
         let tracks = self.tracks.read().await;
         tracks
             .values()
-            .filter(|track| track.source_peer_id == peer_id)
+            .filter(|track| track.room_id == source.room_id && track.source_peer_id == source.peer_id)
             .map(|track| track.id.clone())
             .collect()
     }
 
 
-    pub async fn remove_peer_tracks(&self, peer_id: &str) {
+    /// Tracks the peer publishes in its room
+    pub async fn count_tracks_for_peer(&self, source: &PeerKey) -> usize {
+        let tracks = self.tracks.read().await;
+        tracks
+            .values()
+            .filter(|track| track.room_id == source.room_id && track.source_peer_id == source.peer_id)
+            .count()
+    }
+
+    /// Drops what the peer published in one room; its tracks in other rooms stay
+    pub async fn remove_peer_tracks(&self, source: &PeerKey) {
+        let mut tracks = self.tracks.write().await;
+        tracks.retain(|_, track| !(track.room_id == source.room_id && track.source_peer_id == source.peer_id));
+        self.content_hints.write().await.remove(source);
+        self.e2ee_sources.write().await.remove(source);
+    }
+
+    /// Stops forwarding tracks of the subscriber's room to it
+    pub async fn remove_subscriber(&self, subscriber: &PeerKey) {
         let mut tracks = self.tracks.write().await;
-        tracks.retain(|_, track| track.source_peer_id != peer_id);
+        for track in tracks.values_mut().filter(|track| track.room_id == subscriber.room_id) {
+            track.local_tracks.remove(&subscriber.peer_id);
+        }
     }
 
-    pub async fn get_track(&self, track_id: &str) -> Option<ForwardedTrack> {
-        let tracks = self.tracks.read().await;
-        tracks.get(track_id).cloned()
+    /// Replaces the content hints for a peer's published tracks
+    pub async fn set_content_hints(&self, source: &PeerKey, hints: HashMap<String, TrackContent>) {
+        self.content_hints.write().await.insert(source.clone(), hints);
     }
 
+    /// Marks every track the peer publishes, now or later, as end-to-end
+    /// encrypted or not. Returns whether that changed anything.
+    pub async fn set_e2ee(&self, source: &PeerKey, e2ee: bool) -> bool {
+        let changed = {
+            let mut sources = self.e2ee_sources.write().await;
+            if e2ee {
+                sources.insert(source.clone())
+            } else {
+                sources.remove(source)
+            }
+        };
+        if changed {
+            let mut tracks = self.tracks.write().await;
+            for track in tracks
+                .values_mut()
+                .filter(|track| track.room_id == source.room_id && track.source_peer_id == source.peer_id)
+            {
+                track.e2ee = e2ee;
+            }
+        }
+        changed
+    }
 
-    pub async fn get_all_track_ids(&self) -> Vec<String> {
+    /// Whether the peer reported end-to-end encrypted media
+    pub async fn is_e2ee(&self, source: &PeerKey) -> bool {
+        self.e2ee_sources.read().await.contains(source)
+    }
+
+    /// Every track published in `room_id` by `source_peer_ids`, unordered
+    pub async fn track_entries(&self, room_id: &str, source_peer_ids: &[String]) -> Vec<TrackOrderEntry> {
         let tracks = self.tracks.read().await;
-        tracks.keys().cloned().collect()
+        let hints = self.content_hints.read().await;
+        tracks
+            .values()
+            .filter(|track| track.room_id == room_id && source_peer_ids.contains(&track.source_peer_id))
+            .map(|track| TrackOrderEntry {
+                track_id: track.id.clone(),
+                source_peer_id: track.source_peer_id.clone(),
+                stream_id: stream_id(&track.source_peer_id),
+                kind: track.kind.clone(),
+                content: hints
+                    .get(&track.source())
+                    .and_then(|peer_hints| peer_hints.get(&track.publisher_track_id))
+                    .copied()
+                    .unwrap_or_default(),
+                e2ee: track.e2ee,
+            })
+            .collect()
+    }
+
+    pub async fn get_track(&self, room_id: &str, track_id: &str) -> Option<ForwardedTrack> {
+        let tracks = self.tracks.read().await;
+        tracks.get(&(room_id.to_string(), track_id.to_string())).cloned()
+    }
+}
+
+/// Stream ID of every local track forwarded from `source_peer_id`
+pub fn stream_id(source_peer_id: &str) -> String {
+    format!("{}_stream", source_peer_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::room::{DisconnectCause, PeerKey, RoomManager};
+    use crate::sfu::timezone::RoomLocale;
+
+    fn entry(source_peer_id: &str, kind: &str, content: TrackContent, n: u32) -> TrackOrderEntry {
+        TrackOrderEntry {
+            track_id: format!("{}_{}_{}", source_peer_id, kind, n),
+            source_peer_id: source_peer_id.to_string(),
+            stream_id: stream_id(source_peer_id),
+            kind: kind.to_string(),
+            content,
+            e2ee: false,
+        }
+    }
+
+    fn room_tracks() -> Vec<TrackOrderEntry> {
+        vec![
+            entry("student_b", "audio", TrackContent::Camera, 1),
+            entry("student_a", "video", TrackContent::Screen, 2),
+            entry("student_b", "video", TrackContent::Camera, 3),
+            entry("student_a", "audio", TrackContent::Camera, 4),
+            entry("student_a", "video", TrackContent::Camera, 5),
+            entry("student_c", "video", TrackContent::Camera, 6),
+        ]
+    }
+
+    #[test]
+    fn test_order_by_join_then_content_then_kind() {
+        let join_order = vec!["student_a".to_string(), "student_b".to_string()];
+        let ordered: Vec<String> = order_tracks(room_tracks(), &join_order)
+            .into_iter()
+            .map(|t| t.track_id)
+            .collect();
+
+        assert_eq!(
+            ordered,
+            vec![
+                "student_a_video_5",
+                "student_a_audio_4",
+                "student_a_video_2",
+                "student_b_video_3",
+                "student_b_audio_1",
+                // Not in the join order, e.g. left while the list was built
+                "student_c_video_6",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_order_stable_across_repeated_joins() {
+        let mut expected = None;
+
+        for attempt in 0..5 {
+            // Same room each time, with tracks stored in a different order
+            let room_manager = RoomManager::new();
+            let room_id = room_manager
+                .create_room("proctor".to_string(), None, RoomLocale::default())
+                .await
+                .unwrap();
+            for student in ["student_a", "student_b", "student_c"] {
+                room_manager.join_room(room_id.clone(), student.to_string(), None).await.unwrap();
+            }
+
+            let mut tracks = room_tracks();
+            let len = tracks.len();
+            tracks.rotate_left(attempt % len);
+            if attempt % 2 == 1 {
+                tracks.reverse();
+            }
+
+            let ordered = order_tracks(tracks, &room_manager.join_order(&room_id).await);
+            match expected {
+                None => expected = Some(ordered),
+                Some(ref first) => assert_eq!(&ordered, first, "attempt {}", attempt),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejoined_peer_moves_to_the_end() {
+        let room_manager = RoomManager::new();
+        let room_id = room_manager
+            .create_room("proctor".to_string(), None, RoomLocale::default())
+            .await
+            .unwrap();
+        room_manager.join_room(room_id.clone(), "student_a".to_string(), None).await.unwrap();
+        room_manager.join_room(room_id.clone(), "student_b".to_string(), None).await.unwrap();
+
+        room_manager.remove_peer(&PeerKey::new(room_id.clone(), "student_a"), DisconnectCause::Left).await;
+        room_manager.join_room(room_id.clone(), "student_a".to_string(), None).await.unwrap();
+
+        assert_eq!(room_manager.join_order(&room_id).await, vec!["proctor", "student_b", "student_a"]);
     }
 }
\ No newline at end of file
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::room::PeerKey;
+
+/// Why moving a student to another room was refused or undone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    /// Source and destination are the same room
+    SameRoom,
+    RoomNotFound(String),
+    /// The student is not in the source room, or not connected to it
+    PeerNotFound(String),
+    /// The target is a proctor; only students move between rooms
+    NotStudent(String),
+    /// The peer is already in the destination room
+    AlreadyInRoom(String),
+    /// The destination's proctor is not connected to approve the move
+    ProctorUnavailable(String),
+    /// No request to move this student to this room is waiting for an answer
+    NoRequest,
+    /// The destination's proctor declined the move
+    Refused,
+    /// A step of the move failed; the student was returned to the source room
+    Failed(String),
+}
+
+impl TransferError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransferError::SameRoom => "invalid_transfer",
+            TransferError::RoomNotFound(_) => "room_not_found",
+            TransferError::PeerNotFound(_) => "peer_not_found",
+            TransferError::NotStudent(_) => "invalid_target",
+            TransferError::AlreadyInRoom(_) => "already_in_room",
+            TransferError::ProctorUnavailable(_) => "proctor_unavailable",
+            TransferError::NoRequest => "transfer_not_found",
+            TransferError::Refused => "transfer_refused",
+            TransferError::Failed(_) => "transfer_failed",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            TransferError::SameRoom => "The student is already in that room".to_string(),
+            TransferError::RoomNotFound(room_id) => format!("Room {} does not exist", room_id),
+            TransferError::PeerNotFound(peer_id) => format!("Student {} is not connected to this room", peer_id),
+            TransferError::NotStudent(peer_id) => format!("{} is a proctor; only students can be transferred", peer_id),
+            TransferError::AlreadyInRoom(room_id) => format!("The student is already in room {}", room_id),
+            TransferError::ProctorUnavailable(room_id) => {
+                format!("The proctor of room {} is not connected to approve the transfer", room_id)
+            }
+            TransferError::NoRequest => "No transfer of this student to this room is awaiting an answer".to_string(),
+            TransferError::Refused => "The destination room's proctor declined the transfer".to_string(),
+            TransferError::Failed(error) => format!("Transfer failed, the student stays in their room: {}", error),
+        }
+    }
+}
+
+/// Details recorded with the on-chain ParticipantLeft of a transferred student
+pub fn left_details(to_room_id: &str) -> String {
+    format!("Transferred to room {}", to_room_id)
+}
+
+/// Details recorded with the on-chain ParticipantJoined of a transferred student
+pub fn joined_details(from_room_id: &str) -> String {
+    format!("Transferred from room {}", from_room_id)
+}
+
+/// Transfers waiting for the destination proctor, and the rooms students were
+/// moved to, so the student's signaling connection can follow them
+#[derive(Default)]
+pub struct Transfers {
+    /// Student in the source room -> destination room awaiting approval
+    pending: Mutex<HashMap<PeerKey, String>>,
+    /// Student's key before a move -> room they were moved to
+    moved: Mutex<HashMap<PeerKey, String>>,
+}
+
+impl Transfers {
+    /// Records a request to move `student` to `to_room_id`, replacing an
+    /// earlier unanswered one
+    pub fn request(&self, student: &PeerKey, to_room_id: &str) {
+        self.pending.lock().unwrap().insert(student.clone(), to_room_id.to_string());
+    }
+
+    /// Takes the request to move `student` to `to_room_id`, false if there is none
+    pub fn take_request(&self, student: &PeerKey, to_room_id: &str) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.get(student).map(String::as_str) != Some(to_room_id) {
+            return false;
+        }
+        pending.remove(student);
+        true
+    }
+
+    /// Records that `student` now lives in `to_room_id`
+    pub fn record_move(&self, student: &PeerKey, to_room_id: &str) {
+        self.moved.lock().unwrap().insert(student.clone(), to_room_id.to_string());
+    }
+
+    /// Forgets a move that was rolled back
+    pub fn undo_move(&self, student: &PeerKey) {
+        self.moved.lock().unwrap().remove(student);
+    }
+
+    /// Room the peer was last moved to from `peer`'s room, following moves
+    /// made one after the other, or `None` if it was never moved
+    pub fn follow(&self, peer: &PeerKey) -> Option<String> {
+        let mut moved = self.moved.lock().unwrap();
+        let mut current = peer.clone();
+        while let Some(room_id) = moved.remove(&current) {
+            current = PeerKey::new(room_id, current.peer_id);
+        }
+        (current != *peer).then_some(current.room_id)
+    }
+
+    /// Drops the unanswered request of a student who left
+    pub fn forget(&self, peer: &PeerKey) {
+        self.pending.lock().unwrap().remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_answered_once_for_its_room() {
+        let transfers = Transfers::default();
+        let student = PeerKey::new("room_a", "student_1");
+        transfers.request(&student, "room_b");
+
+        assert!(!transfers.take_request(&student, "room_c"));
+        assert!(transfers.take_request(&student, "room_b"));
+        assert!(!transfers.take_request(&student, "room_b"));
+
+        transfers.request(&student, "room_b");
+        transfers.forget(&student);
+        assert!(!transfers.take_request(&student, "room_b"));
+    }
+
+    #[test]
+    fn test_follow_chains_moves() {
+        let transfers = Transfers::default();
+        let student = PeerKey::new("room_a", "student_1");
+        assert_eq!(transfers.follow(&student), None);
+
+        transfers.record_move(&student, "room_b");
+        transfers.record_move(&PeerKey::new("room_b", "student_1"), "room_c");
+        assert_eq!(transfers.follow(&student).as_deref(), Some("room_c"));
+        // Followed moves are consumed
+        assert_eq!(transfers.follow(&student), None);
+
+        transfers.record_move(&student, "room_b");
+        transfers.undo_move(&student);
+        assert_eq!(transfers.follow(&student), None);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(TransferError::Refused.code(), "transfer_refused");
+        assert_eq!(TransferError::Failed("disk full".to_string()).code(), "transfer_failed");
+        assert!(TransferError::Failed("disk full".to_string()).message().contains("disk full"));
+    }
+}
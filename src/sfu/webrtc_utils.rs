@@ -1,127 +1,467 @@
-use std::sync::Arc;
-use webrtc::api::interceptor_registry::register_default_interceptors;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+use webrtc::api::interceptor_registry::{configure_nack, configure_twcc_receiver_only};
 use webrtc::api::media_engine::MediaEngine;
 use webrtc::api::setting_engine::SettingEngine;
 use webrtc::api::{APIBuilder, API};
 use webrtc::ice::network_type::NetworkType;
+use webrtc::ice::udp_mux::{UDPMuxDefault, UDPMuxParams};
+use webrtc::ice::udp_network::{EphemeralUDP, UDPNetwork};
+use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
-use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType};
+use webrtc::interceptor::report::receiver::ReceiverReport;
+use webrtc::interceptor::report::sender::SenderReport;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::policy::ice_transport_policy::RTCIceTransportPolicy;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTCRtpHeaderExtensionCapability, RTPCodecType,
+};
 use webrtc::rtp_transceiver::RTCPFeedback;
 
-pub struct WebRTCConfig {
-    pub stun_servers: Vec<String>,
-    pub turn_servers: Vec<TurnServer>,
+use super::rtcp;
+use crate::config::{env, IceTransportPolicy, WebRTCConfig};
+use crate::recording::{RecordingCodecs, RtpCodec};
+
+/// Header extensions that may be offered, by URI, with the media they apply to
+const KNOWN_HEADER_EXTENSIONS: &[(&str, ExtensionMedia)] = &[
+    ("urn:ietf:params:rtp-hdrext:sdes:mid", ExtensionMedia::Both),
+    ("urn:ietf:params:rtp-hdrext:sdes:rtp-stream-id", ExtensionMedia::Video),
+    ("urn:ietf:params:rtp-hdrext:sdes:repaired-rtp-stream-id", ExtensionMedia::Video),
+    ("urn:ietf:params:rtp-hdrext:ssrc-audio-level", ExtensionMedia::Audio),
+    ("urn:ietf:params:rtp-hdrext:toffset", ExtensionMedia::Video),
+    ("urn:3gpp:video-orientation", ExtensionMedia::Video),
+    ("http://www.webrtc.org/experiments/rtp-hdrext/abs-send-time", ExtensionMedia::Both),
+    ("http://www.ietf.org/id/draft-holmer-rmcat-transport-wide-cc-extensions-01", ExtensionMedia::Both),
+];
+
+#[derive(Debug, Clone, Copy)]
+enum ExtensionMedia {
+    Audio,
+    Video,
+    Both,
+}
+
+impl ExtensionMedia {
+    fn kinds(self) -> &'static [RTPCodecType] {
+        match self {
+            ExtensionMedia::Audio => &[RTPCodecType::Audio],
+            ExtensionMedia::Video => &[RTPCodecType::Video],
+            ExtensionMedia::Both => &[RTPCodecType::Audio, RTPCodecType::Video],
+        }
+    }
+}
+
+/// Codecs offered when `WEBRTC_CODECS` is unset. H.264 is there for Safari
+/// clients that offer nothing else.
+const DEFAULT_CODECS: &[&str] = &["vp8", "h264", "opus"];
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum EngineConfigError {
+    #[error("No codecs configured")]
+    NoCodecs,
+
+    #[error("Unknown codec {0}, expected one of vp8, vp9, av1, h264, opus")]
+    UnknownCodec(String),
+
+    #[error("Codec {0} is neither audio/ nor video/")]
+    UnsupportedMimeType(String),
+
+    #[error("Payload type {0} is used by more than one codec")]
+    DuplicatePayloadType(u8),
+
+    #[error("Payload type {0} is above 127")]
+    InvalidPayloadType(u8),
+
+    #[error("Unknown RTP header extension {0}")]
+    UnknownHeaderExtension(String),
+
+    #[error("UDP port range {min}-{max} is empty")]
+    InvalidPortRange { min: u16, max: u16 },
+
+    #[error("Set SFU_UDP_PORT_MIN and SFU_UDP_PORT_MAX together")]
+    PartialPortRange,
+
+    #[error("A UDP port range and a UDP mux port cannot both be set")]
+    PortRangeWithMux,
+
+    #[error("SFU_PUBLIC_IP entry {0} is not an IPv4 address")]
+    InvalidPublicIp(String),
+
+    #[error("Failed to bind UDP mux port {port}: {reason}")]
+    UdpMuxBind { port: u16, reason: String },
+
+    #[error("Failed to configure WebRTC engine: {0}")]
+    Engine(String),
+}
+
+/// One codec the media engine offers and accepts
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CodecConfig {
+    pub mime_type: String,
+    pub clock_rate: u32,
+    pub channels: u16,
+    pub sdp_fmtp_line: String,
+    pub payload_type: u8,
+}
+
+impl CodecConfig {
+    /// Codec by its `WEBRTC_CODECS` name, with its customary payload type
+    pub fn named(name: &str) -> Result<Self, EngineConfigError> {
+        let (mime_type, clock_rate, channels, sdp_fmtp_line, payload_type) = match name.to_ascii_lowercase().as_str() {
+            "vp8" => ("video/VP8", 90000, 0, "", 96),
+            "vp9" => ("video/VP9", 90000, 0, "profile-id=0", 98),
+            "av1" => ("video/AV1", 90000, 0, "", 41),
+            "h264" => (
+                "video/H264",
+                90000,
+                0,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+                102,
+            ),
+            "opus" => ("audio/opus", 48000, 2, "minptime=10;useinbandfec=1", 111),
+            _ => return Err(EngineConfigError::UnknownCodec(name.to_string())),
+        };
+
+        Ok(Self {
+            mime_type: mime_type.to_string(),
+            clock_rate,
+            channels,
+            sdp_fmtp_line: sdp_fmtp_line.to_string(),
+            payload_type,
+        })
+    }
+
+    fn kind(&self) -> Result<RTPCodecType, EngineConfigError> {
+        let mime_type = self.mime_type.to_ascii_lowercase();
+        if mime_type.starts_with("video/") {
+            Ok(RTPCodecType::Video)
+        } else if mime_type.starts_with("audio/") {
+            Ok(RTPCodecType::Audio)
+        } else {
+            Err(EngineConfigError::UnsupportedMimeType(self.mime_type.clone()))
+        }
+    }
 }
 
-pub struct TurnServer {
-    pub urls: Vec<String>,
-    pub username: String,
-    pub credential: String,
+/// RTCP feedback negotiated for video. FIR and PLI are always on, since
+/// recordings and late joiners depend on keyframe requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FeedbackConfig {
+    pub nack: bool,
+    pub remb: bool,
+    pub twcc: bool,
 }
 
-impl Default for WebRTCConfig {
+impl Default for FeedbackConfig {
     fn default() -> Self {
-        let stun_server = std::env::var("STUN_SERVER_URL")
-            .unwrap_or_else(|_| "stun:stun.l.google.com:19302".to_string());
-
-        let mut turn_servers = vec![];
-
-        // Check for optional TURN server configuration
-        if let (Ok(turn_url), Ok(username), Ok(credential)) = (
-            std::env::var("TURN_SERVER_URL"),
-            std::env::var("TURN_USERNAME"),
-            std::env::var("TURN_CREDENTIAL")
-        ) {
-            turn_servers.push(TurnServer {
-                urls: vec![turn_url],
-                username,
-                credential,
-            });
+        Self {
+            nack: true,
+            remb: true,
+            twcc: true,
         }
+    }
+}
 
+/// Everything that shapes a WebRTC API instance. Identical configs share one
+/// instance through `ApiFactory`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WebRtcEngineConfig {
+    /// In order of preference
+    pub codecs: Vec<CodecConfig>,
+    /// URIs from the known header extensions, offered on the media they apply to
+    pub header_extensions: Vec<String>,
+    pub feedback: FeedbackConfig,
+    /// Interval of the receiver and sender report interceptors
+    pub report_interval: Duration,
+    /// Restricts ICE host candidates to this UDP port range
+    pub udp_port_range: Option<(u16, u16)>,
+    /// Serves all ICE traffic from this single UDP port
+    pub udp_mux_port: Option<u16>,
+    /// Advertised in host candidates instead of the local address, behind a 1:1 NAT
+    pub public_ips: Vec<Ipv4Addr>,
+}
+
+impl Default for WebRtcEngineConfig {
+    fn default() -> Self {
         Self {
-            stun_servers: vec![stun_server],
-            turn_servers,
+            codecs: DEFAULT_CODECS
+                .iter()
+                .map(|name| CodecConfig::named(name).expect("default codecs are known"))
+                .collect(),
+            header_extensions: Vec::new(),
+            feedback: FeedbackConfig::default(),
+            report_interval: Duration::from_millis(rtcp::DEFAULT_REPORT_INTERVAL_MS),
+            udp_port_range: None,
+            udp_mux_port: None,
+            public_ips: Vec::new(),
         }
     }
 }
 
-pub fn create_webrtc_api() -> Arc<API> {
-    let mut media_engine = MediaEngine::default();
+impl WebRtcEngineConfig {
+    /// Reads `WEBRTC_CODECS`, `WEBRTC_HEADER_EXTENSIONS`, `WEBRTC_NACK`,
+    /// `WEBRTC_TWCC`, `SFU_UDP_PORT_MIN`/`SFU_UDP_PORT_MAX`,
+    /// `WEBRTC_UDP_MUX_PORT` and `SFU_PUBLIC_IP`; REMB and the report
+    /// interval follow the RTCP settings. The port range and public IP are
+    /// also read under their deprecated `WEBRTC_` names.
+    pub fn from_env() -> Result<Self, EngineConfigError> {
+        let codec_names = env::get_list("WEBRTC_CODECS");
+        let codecs = if codec_names.is_empty() {
+            Self::default().codecs
+        } else {
+            codec_names
+                .iter()
+                .map(|name| CodecConfig::named(name))
+                .collect::<Result<Vec<_>, _>>()?
+        };
 
-    // RTCP feedback mechanisms for video - critical for keyframe recovery
-    let video_rtcp_feedback = vec![
-        RTCPFeedback {
-            typ: "goog-remb".to_string(),
-            parameter: "".to_string(),
-        },
-        RTCPFeedback {
+        let udp_port_range = match (env::get_parsed::<u16>("SFU_UDP_PORT_MIN"), env::get_parsed::<u16>("SFU_UDP_PORT_MAX")) {
+            (Some(min), Some(max)) => Some((min, max)),
+            (None, None) => None,
+            _ => return Err(EngineConfigError::PartialPortRange),
+        };
+
+        let public_ips = env::get_list("SFU_PUBLIC_IP")
+            .into_iter()
+            .map(|ip| ip.parse().map_err(|_| EngineConfigError::InvalidPublicIp(ip)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rtcp_settings = rtcp::RtcpSettings::from_env();
+        let config = Self {
+            codecs,
+            header_extensions: env::get_list("WEBRTC_HEADER_EXTENSIONS"),
+            feedback: FeedbackConfig {
+                nack: env::get_bool("WEBRTC_NACK", true),
+                remb: rtcp_settings.remb_enabled,
+                twcc: env::get_bool("WEBRTC_TWCC", true),
+            },
+            report_interval: rtcp_settings.report_interval,
+            udp_port_range,
+            udp_mux_port: env::get_parsed("WEBRTC_UDP_MUX_PORT"),
+            public_ips,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Codecs a recording should expect: the preferred codec of each kind, with
+    /// the payload type this engine offers it under. Tracks negotiated otherwise
+    /// correct their recording when they arrive.
+    pub fn recording_codecs(&self) -> RecordingCodecs {
+        let preferred = |kind: RTPCodecType| {
+            self.codecs
+                .iter()
+                .find(|codec| codec.kind().ok() == Some(kind))
+                .map(|codec| RtpCodec::from_mime_type(&codec.mime_type, codec.payload_type, codec.clock_rate))
+        };
+
+        let defaults = RecordingCodecs::default();
+        RecordingCodecs {
+            video: preferred(RTPCodecType::Video).unwrap_or(defaults.video),
+            audio: preferred(RTPCodecType::Audio).unwrap_or(defaults.audio),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), EngineConfigError> {
+        if self.codecs.is_empty() {
+            return Err(EngineConfigError::NoCodecs);
+        }
+
+        let mut payload_types = HashSet::new();
+        for codec in &self.codecs {
+            codec.kind()?;
+            if codec.payload_type > 127 {
+                return Err(EngineConfigError::InvalidPayloadType(codec.payload_type));
+            }
+            if !payload_types.insert(codec.payload_type) {
+                return Err(EngineConfigError::DuplicatePayloadType(codec.payload_type));
+            }
+        }
+
+        for uri in &self.header_extensions {
+            if !KNOWN_HEADER_EXTENSIONS.iter().any(|(known, _)| known == uri) {
+                return Err(EngineConfigError::UnknownHeaderExtension(uri.clone()));
+            }
+        }
+
+        if let Some((min, max)) = self.udp_port_range {
+            if min > max || min == 0 {
+                return Err(EngineConfigError::InvalidPortRange { min, max });
+            }
+            if self.udp_mux_port.is_some() {
+                return Err(EngineConfigError::PortRangeWithMux);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn media_engine(&self) -> Result<(MediaEngine, Registry), EngineConfigError> {
+        let engine_error = |e: webrtc::Error| EngineConfigError::Engine(e.to_string());
+        let mut media_engine = MediaEngine::default();
+
+        // RTCP feedback mechanisms for video - critical for keyframe recovery
+        let mut video_rtcp_feedback = Vec::new();
+        if self.feedback.remb {
+            video_rtcp_feedback.push(RTCPFeedback {
+                typ: "goog-remb".to_string(),
+                parameter: "".to_string(),
+            });
+        }
+        video_rtcp_feedback.push(RTCPFeedback {
             typ: "ccm".to_string(),
             parameter: "fir".to_string(),
-        },
-        RTCPFeedback {
-            typ: "nack".to_string(),
-            parameter: "".to_string(),
-        },
-        RTCPFeedback {
+        });
+        if self.feedback.nack {
+            video_rtcp_feedback.push(RTCPFeedback {
+                typ: "nack".to_string(),
+                parameter: "".to_string(),
+            });
+        }
+        video_rtcp_feedback.push(RTCPFeedback {
             typ: "nack".to_string(),
             parameter: "pli".to_string(),
-        },
-    ];
-
-    media_engine
-        .register_codec(
-            RTCRtpCodecParameters {
-                capability: RTCRtpCodecCapability {
-                    mime_type: "video/VP8".to_string(),
-                    clock_rate: 90000,
-                    channels: 0,
-                    sdp_fmtp_line: "".to_string(),
-                    rtcp_feedback: video_rtcp_feedback,
-                },
-                payload_type: 96,
-                ..Default::default()
-            },
-            RTPCodecType::Video,
-        )
-        .expect("Failed to register VP8 codec");
-
-    media_engine
-        .register_codec(
-            RTCRtpCodecParameters {
-                capability: RTCRtpCodecCapability {
-                    mime_type: "audio/opus".to_string(),
-                    clock_rate: 48000,
-                    channels: 2,
-                    sdp_fmtp_line: "minptime=10;useinbandfec=1".to_string(),
-                    rtcp_feedback: vec![],
-                },
-                payload_type: 111,
-                ..Default::default()
-            },
-            RTPCodecType::Audio,
-        )
-        .expect("Failed to register Opus codec");
+        });
 
-    let mut registry = Registry::new();
-    registry = register_default_interceptors(registry, &mut media_engine)
-        .expect("Failed to register default interceptors");
+        for codec in &self.codecs {
+            let kind = codec.kind()?;
+            let rtcp_feedback = match kind {
+                RTPCodecType::Video => video_rtcp_feedback.clone(),
+                _ => vec![],
+            };
+            media_engine
+                .register_codec(
+                    RTCRtpCodecParameters {
+                        capability: RTCRtpCodecCapability {
+                            mime_type: codec.mime_type.clone(),
+                            clock_rate: codec.clock_rate,
+                            channels: codec.channels,
+                            sdp_fmtp_line: codec.sdp_fmtp_line.clone(),
+                            rtcp_feedback,
+                        },
+                        payload_type: codec.payload_type,
+                        ..Default::default()
+                    },
+                    kind,
+                )
+                .map_err(engine_error)?;
+        }
 
-    // Configure SettingEngine to use IPv4 only to avoid IPv6 binding errors
-    let mut setting_engine = SettingEngine::default();
-    setting_engine.set_network_types(vec![NetworkType::Udp4, NetworkType::Tcp4]);
+        for uri in &self.header_extensions {
+            let media = KNOWN_HEADER_EXTENSIONS
+                .iter()
+                .find(|(known, _)| known == uri)
+                .map(|(_, media)| *media)
+                .ok_or_else(|| EngineConfigError::UnknownHeaderExtension(uri.clone()))?;
+            for kind in media.kinds() {
+                media_engine
+                    .register_header_extension(RTCRtpHeaderExtensionCapability { uri: uri.clone() }, *kind, None)
+                    .map_err(engine_error)?;
+            }
+        }
 
-    // Disable mDNS to reduce unnecessary warnings
-    setting_engine.set_ice_multicast_dns_mode(webrtc::ice::mdns::MulticastDnsMode::Disabled);
+        // Same interceptors as webrtc's defaults, but with the receiver report interval
+        // under our control so publishers hear about loss as often as REMB updates go out
+        let mut registry = Registry::new();
+        if self.feedback.nack {
+            registry = configure_nack(registry, &mut media_engine);
+        }
+        registry.add(Box::new(ReceiverReport::builder().with_interval(self.report_interval)));
+        registry.add(Box::new(SenderReport::builder().with_interval(self.report_interval)));
+        if self.feedback.twcc {
+            registry = configure_twcc_receiver_only(registry, &mut media_engine).map_err(engine_error)?;
+        }
+
+        Ok((media_engine, registry))
+    }
 
-    let api = APIBuilder::new()
-        .with_media_engine(media_engine)
-        .with_interceptor_registry(registry)
-        .with_setting_engine(setting_engine)
-        .build();
+    fn setting_engine(&self) -> Result<SettingEngine, EngineConfigError> {
+        // Configure SettingEngine to use IPv4 only to avoid IPv6 binding errors
+        let mut setting_engine = SettingEngine::default();
+        setting_engine.set_network_types(vec![NetworkType::Udp4, NetworkType::Tcp4]);
 
-    Arc::new(api)
+        // Disable mDNS to reduce unnecessary warnings
+        setting_engine.set_ice_multicast_dns_mode(webrtc::ice::mdns::MulticastDnsMode::Disabled);
+
+        if let Some((min, max)) = self.udp_port_range {
+            let ephemeral = EphemeralUDP::new(min, max).map_err(|_| EngineConfigError::InvalidPortRange { min, max })?;
+            setting_engine.set_udp_network(UDPNetwork::Ephemeral(ephemeral));
+        }
+
+        if let Some(port) = self.udp_mux_port {
+            let bind_error = |reason: String| EngineConfigError::UdpMuxBind { port, reason };
+            // The mux runs its read loop on the Tokio runtime
+            let _runtime = tokio::runtime::Handle::try_current().map_err(|e| bind_error(e.to_string()))?;
+            let socket = std::net::UdpSocket::bind(("0.0.0.0", port)).map_err(|e| bind_error(e.to_string()))?;
+            socket.set_nonblocking(true).map_err(|e| bind_error(e.to_string()))?;
+            let socket = tokio::net::UdpSocket::from_std(socket).map_err(|e| bind_error(e.to_string()))?;
+            setting_engine.set_udp_network(UDPNetwork::Muxed(UDPMuxDefault::new(UDPMuxParams::new(socket))));
+        }
+
+        if !self.public_ips.is_empty() {
+            let ips = self.public_ips.iter().map(Ipv4Addr::to_string).collect();
+            setting_engine.set_nat_1to1_ips(ips, RTCIceCandidateType::Host);
+        }
+
+        Ok(setting_engine)
+    }
+}
+
+/// Builds WebRTC API instances, handing out the same instance for identical
+/// configs. A muxed UDP port can only be bound once, and tests that build
+/// many servers should not pay for an engine per server.
+#[derive(Default)]
+pub struct ApiFactory {
+    built: Mutex<HashMap<WebRtcEngineConfig, Arc<API>>>,
+}
+
+impl ApiFactory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validates `config` and returns its API, building it on first use.
+    /// A UDP mux port must be built within a Tokio runtime.
+    pub fn build(&self, config: &WebRtcEngineConfig) -> Result<Arc<API>, EngineConfigError> {
+        let mut built = self.built.lock().unwrap();
+        if let Some(api) = built.get(config) {
+            return Ok(api.clone());
+        }
+
+        config.validate()?;
+        let (media_engine, registry) = config.media_engine()?;
+        let api = Arc::new(
+            APIBuilder::new()
+                .with_media_engine(media_engine)
+                .with_interceptor_registry(registry)
+                .with_setting_engine(config.setting_engine()?)
+                .build(),
+        );
+
+        tracing::info!(
+            codecs = ?config.codecs.iter().map(|c| c.mime_type.as_str()).collect::<Vec<_>>(),
+            header_extensions = config.header_extensions.len(),
+            nack = config.feedback.nack,
+            twcc = config.feedback.twcc,
+            udp_port_range = ?config.udp_port_range,
+            udp_mux_port = ?config.udp_mux_port,
+            public_ips = ?config.public_ips,
+            "Built WebRTC engine"
+        );
+        built.insert(config.clone(), api.clone());
+        Ok(api)
+    }
+}
+
+static API_FACTORY: OnceLock<ApiFactory> = OnceLock::new();
+
+/// Process-wide factory, shared by the server and the ICE self-test
+pub fn api_factory() -> &'static ApiFactory {
+    API_FACTORY.get_or_init(ApiFactory::new)
 }
 
 pub fn get_ice_servers(config: &WebRTCConfig) -> Vec<RTCIceServer> {
@@ -144,4 +484,222 @@ pub fn get_ice_servers(config: &WebRTCConfig) -> Vec<RTCIceServer> {
     }
 
     ice_servers
-}
\ No newline at end of file
+}
+
+/// Peer connection settings from the shared ICE configuration
+pub fn rtc_configuration(config: &WebRTCConfig) -> RTCConfiguration {
+    RTCConfiguration {
+        ice_servers: get_ice_servers(config),
+        ice_transport_policy: match config.ice_transport_policy {
+            IceTransportPolicy::All => RTCIceTransportPolicy::All,
+            IceTransportPolicy::Relay => RTCIceTransportPolicy::Relay,
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_codecs(names: &[&str]) -> WebRtcEngineConfig {
+        WebRtcEngineConfig {
+            codecs: names.iter().map(|name| CodecConfig::named(name).unwrap()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// SDP offered by a throwaway peer connection with one audio and one video transceiver
+    async fn offer_sdp(api: &API) -> String {
+        let peer_connection = api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        peer_connection.add_transceiver_from_kind(RTPCodecType::Video, None).await.unwrap();
+        peer_connection.add_transceiver_from_kind(RTPCodecType::Audio, None).await.unwrap();
+        let offer = peer_connection.create_offer(None).await.unwrap();
+        peer_connection.close().await.unwrap();
+        offer.sdp
+    }
+
+    #[tokio::test]
+    async fn test_configs_offer_their_codecs() {
+        let factory = ApiFactory::new();
+
+        let default_sdp = offer_sdp(&factory.build(&WebRtcEngineConfig::default()).unwrap()).await;
+        assert!(default_sdp.contains("VP8/90000"));
+        assert!(default_sdp.contains("a=rtpmap:102 H264/90000"));
+        assert!(default_sdp.contains("a=fmtp:102 level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"));
+        assert!(default_sdp.contains("opus/48000/2"));
+        assert!(!default_sdp.contains("VP9"));
+        let video_line = default_sdp.lines().find(|line| line.starts_with("m=video")).unwrap();
+        assert!(video_line.ends_with(" 96 102"), "{}", video_line);
+
+        let h264_sdp = offer_sdp(&factory.build(&config_with_codecs(&["h264", "vp9", "opus"])).unwrap()).await;
+        assert!(h264_sdp.contains("a=rtpmap:102 H264/90000"));
+        assert!(h264_sdp.contains("a=rtpmap:98 VP9/90000"));
+        assert!(!h264_sdp.contains("VP8"));
+        // Listed first, so preferred
+        let video_line = h264_sdp.lines().find(|line| line.starts_with("m=video")).unwrap();
+        assert!(video_line.ends_with(" 102 98"), "{}", video_line);
+    }
+
+    /// SDP the SFU's engine answers a video offer from a client engine with
+    async fn answer_sdp(client: &API, sfu: &API) -> String {
+        let offerer = client.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        offerer.add_transceiver_from_kind(RTPCodecType::Video, None).await.unwrap();
+        let offer = offerer.create_offer(None).await.unwrap();
+
+        let answerer = sfu.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        answerer.set_remote_description(offer).await.unwrap();
+        let answer = answerer.create_answer(None).await.unwrap();
+        offerer.close().await.unwrap();
+        answerer.close().await.unwrap();
+        answer.sdp
+    }
+
+    #[tokio::test]
+    async fn test_default_engine_accepts_h264_only_and_vp8_only_clients() {
+        let factory = ApiFactory::new();
+        let sfu = factory.build(&WebRtcEngineConfig::default()).unwrap();
+
+        // Safari offering H.264 only
+        let safari = factory.build(&config_with_codecs(&["h264", "opus"])).unwrap();
+        let answer = answer_sdp(&safari, &sfu).await;
+        let video_line = answer.lines().find(|line| line.starts_with("m=video")).unwrap();
+        assert!(!video_line.starts_with("m=video 0 "), "video rejected: {}", video_line);
+        assert!(answer.contains("a=rtpmap:102 H264/90000"), "{}", answer);
+        assert!(answer.contains("packetization-mode=1"));
+        assert!(!answer.contains("VP8"));
+
+        let vp8_only = factory.build(&config_with_codecs(&["vp8", "opus"])).unwrap();
+        let answer = answer_sdp(&vp8_only, &sfu).await;
+        assert!(answer.contains("a=rtpmap:96 VP8/90000"));
+        assert!(!answer.contains("H264"));
+    }
+
+    #[tokio::test]
+    async fn test_offer_holds_only_configured_codecs() {
+        let factory = ApiFactory::new();
+        let sdp = offer_sdp(&factory.build(&config_with_codecs(&["av1", "vp9", "opus"])).unwrap()).await;
+
+        assert!(sdp.contains("a=rtpmap:41 AV1/90000"), "{}", sdp);
+        assert!(sdp.contains("a=rtpmap:98 VP9/90000"));
+        assert!(sdp.contains("a=rtpmap:111 opus/48000/2"));
+        let video_line = sdp.lines().find(|line| line.starts_with("m=video")).unwrap();
+        assert!(video_line.ends_with(" 41 98"), "{}", video_line);
+        for absent in ["VP8", "H264"] {
+            assert!(!sdp.contains(absent), "{} offered: {}", absent, sdp);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_feedback_and_extensions_shape_the_offer() {
+        let factory = ApiFactory::new();
+        let config = WebRtcEngineConfig {
+            header_extensions: vec!["urn:ietf:params:rtp-hdrext:ssrc-audio-level".to_string()],
+            feedback: FeedbackConfig {
+                nack: false,
+                remb: false,
+                twcc: false,
+            },
+            ..Default::default()
+        };
+        let sdp = offer_sdp(&factory.build(&config).unwrap()).await;
+
+        assert!(sdp.contains("ssrc-audio-level"));
+        assert!(!sdp.contains("goog-remb"));
+        assert!(!sdp.contains("transport-cc"));
+        assert!(!sdp.contains("a=rtcp-fb:96 nack\r\n"));
+        // Keyframe requests survive with every optional feedback off
+        assert!(sdp.contains("a=rtcp-fb:96 nack pli"));
+        assert!(sdp.contains("a=rtcp-fb:96 ccm fir"));
+    }
+
+    #[tokio::test]
+    async fn test_identical_configs_share_an_api() {
+        let factory = ApiFactory::new();
+        let first = factory.build(&WebRtcEngineConfig::default()).unwrap();
+        let second = factory.build(&WebRtcEngineConfig::default()).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let other = factory.build(&config_with_codecs(&["vp9", "opus"])).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_configs() {
+        let mut duplicate = config_with_codecs(&["vp8", "opus"]);
+        duplicate.codecs[1].payload_type = 96;
+        assert_eq!(duplicate.validate(), Err(EngineConfigError::DuplicatePayloadType(96)));
+
+        let unknown_extension = WebRtcEngineConfig {
+            header_extensions: vec!["urn:example:not-an-extension".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            unknown_extension.validate(),
+            Err(EngineConfigError::UnknownHeaderExtension("urn:example:not-an-extension".to_string()))
+        );
+
+        assert_eq!(config_with_codecs(&[]).validate(), Err(EngineConfigError::NoCodecs));
+        assert_eq!(
+            CodecConfig::named("av1"),
+            Err(EngineConfigError::UnknownCodec("av1".to_string()))
+        );
+
+        let reversed_range = WebRtcEngineConfig {
+            udp_port_range: Some((50000, 40000)),
+            ..Default::default()
+        };
+        assert_eq!(
+            reversed_range.validate(),
+            Err(EngineConfigError::InvalidPortRange { min: 50000, max: 40000 })
+        );
+
+        // Invalid configs are never cached
+        assert!(ApiFactory::new().build(&duplicate).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_host_candidates_use_port_range_and_public_ip() {
+        let public_ip = Ipv4Addr::new(203, 0, 113, 7);
+        let config = WebRtcEngineConfig {
+            udp_port_range: Some((52000, 52019)),
+            public_ips: vec![public_ip],
+            ..Default::default()
+        };
+        let api = ApiFactory::new().build(&config).unwrap();
+
+        let peer_connection = api.new_peer_connection(RTCConfiguration::default()).await.unwrap();
+        peer_connection.create_data_channel("candidates", None).await.unwrap();
+        let offer = peer_connection.create_offer(None).await.unwrap();
+        let mut gathering_complete = peer_connection.gathering_complete_promise().await;
+        peer_connection.set_local_description(offer).await.unwrap();
+        let _ = tokio::time::timeout(Duration::from_secs(10), gathering_complete.recv()).await;
+        let sdp = peer_connection.local_description().await.unwrap().sdp;
+        peer_connection.close().await.unwrap();
+
+        // a=candidate:<foundation> <component> <transport> <priority> <address> <port> typ <type>
+        let udp_candidates: Vec<Vec<&str>> = sdp
+            .lines()
+            .filter_map(|line| line.strip_prefix("a=candidate:"))
+            .map(|candidate| candidate.split_whitespace().collect::<Vec<_>>())
+            .filter(|fields| fields[2].eq_ignore_ascii_case("udp"))
+            .collect();
+        assert!(!udp_candidates.is_empty(), "no UDP candidates in {}", sdp);
+        for fields in udp_candidates {
+            let port: u16 = fields[5].parse().unwrap();
+            assert!((52000..=52019).contains(&port), "port {} outside the range", port);
+            assert_eq!(fields[4], public_ip.to_string());
+        }
+    }
+
+    #[test]
+    fn test_recording_codecs_follow_engine_preference() {
+        assert_eq!(WebRtcEngineConfig::default().recording_codecs(), RecordingCodecs::default());
+
+        let mut config = config_with_codecs(&["opus", "h264", "vp8"]);
+        config.codecs[0].payload_type = 109;
+        let codecs = config.recording_codecs();
+        assert_eq!(codecs.video, RtpCodec::from_mime_type("video/H264", 102, 90000));
+        assert_eq!(codecs.audio, RtpCodec::from_mime_type("audio/opus", 109, 48000));
+    }
+}
@@ -1,3 +1,4 @@
+use async_trait::async_trait;
 use std::sync::Arc;
 use std::time::Duration;
 use ethers::prelude::*;
@@ -5,21 +6,24 @@ use ethers::middleware::NonceManagerMiddleware;
 use ethers::providers::{Http, Provider};
 use ethers::signers::{LocalWallet, Signer};
 use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex;
 use tokio::time::timeout;
 
 use super::config::AssetHubConfig;
+use super::recorder::ChainRecorder;
+use crate::chaos::{self, ChaosTarget};
 use crate::error::{Result, SfuError};
 
 /// Role for participants in the proctoring session
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Role {
     Proctor = 0,
     Student = 1,
 }
 
 /// Reason for leaving a room
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LeaveReason {
     Normal = 0,
     Kicked = 1,
@@ -28,7 +32,7 @@ pub enum LeaveReason {
 }
 
 /// ID verification status
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum VerificationStatus {
     Valid = 0,
     Invalid = 1,
@@ -37,7 +41,7 @@ pub enum VerificationStatus {
 }
 
 /// Types of suspicious activity
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SuspiciousActivityType {
     MultipleDevices = 0,
     TabSwitch = 1,
@@ -49,7 +53,7 @@ pub enum SuspiciousActivityType {
 }
 
 /// Reason for closing a room
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RoomCloseReason {
     ProctorLeft = 0,
     SessionCompleted = 1,
@@ -70,12 +74,14 @@ abigen!(
         function recordSuspiciousActivity(string roomId, address participant, uint8 activityType, string details) external
         function recordRecordingStarted(string roomId, address participant) external
         function recordRecordingStopped(string roomId, address participant, uint64 durationSecs, string ipfsCid) external
-        function closeRoom(string roomId, uint8 reason) external
+        function recordRecordingStoppedWithHash(string roomId, address participant, uint64 durationSecs, string ipfsCid, bytes32 fileHash) external
+        function closeRoom(string roomId, uint8 reason, string manifestCid) external
         function createExamResult(string roomId, address participant, uint256 grade, string examName) external returns (uint256)
         function addRecordingToResult(uint256 resultId, string ipfsCid) external
         function addRecordingsToResult(uint256 resultId, string[] ipfsCids) external
         function updateExamResultGrade(uint256 resultId, uint256 newGrade) external
         function markNftMinted(uint256 resultId) external
+        function getRoomManifest(string roomId) external view returns (string)
         function getRoomInfo(string roomId) external view returns (address, string, uint256, uint256, uint32, uint8)
         function getParticipant(string roomId, address participant) external view returns (address, string, uint8, uint256, uint256, uint256)
         function getRoomParticipants(string roomId) external view returns (address[])
@@ -92,6 +98,7 @@ abigen!(
         event ParticipantLeft(string indexed roomId, address indexed participant, uint8 reason, uint256 timestamp)
         event ParticipantKicked(string indexed roomId, address indexed kicked, address indexed proctor, uint256 timestamp)
         event RoomClosed(string indexed roomId, uint8 reason, uint256 timestamp)
+        event RoomManifestRecorded(string indexed roomId, string manifestCid, uint256 timestamp)
         event ExamResultCreated(uint256 indexed resultId, string indexed roomId, address indexed participant, uint256 grade, uint256 timestamp)
         event RecordingAdded(uint256 indexed resultId, string ipfsCid, uint256 timestamp)
         event NftMinted(uint256 indexed resultId, address indexed participant, string indexed roomId, uint256 timestamp)
@@ -105,7 +112,10 @@ pub struct ContractClient {
     contract: ProctoringContract<SignerMiddlewareType>,
     submission_timeout: Duration,
     retry_count: u32,
+    /// Used when a call's gas can't be estimated
     gas_limit: U256,
+    /// Added to each gas estimate, in percent
+    gas_margin_percent: u64,
     /// Mutex to serialize transaction submissions and avoid nonce conflicts
     tx_mutex: Mutex<()>,
     /// RPC URL for debugging
@@ -121,6 +131,7 @@ impl ContractClient {
             timeout_secs = config.submission_timeout_secs,
             retry_count = config.retry_count,
             gas_limit = config.gas_limit,
+            gas_margin_percent = config.gas_margin_percent,
             "Initializing Asset Hub EVM contract client"
         );
 
@@ -166,13 +177,17 @@ impl ContractClient {
             submission_timeout: Duration::from_secs(config.submission_timeout_secs),
             retry_count: config.retry_count,
             gas_limit: U256::from(config.gas_limit),
+            gas_margin_percent: config.gas_margin_percent,
             tx_mutex: Mutex::new(()),
             rpc_url: config.rpc_url,
         })
     }
+}
 
+#[async_trait]
+impl ChainRecorder for ContractClient {
     /// Records a room creation event on-chain
-    pub async fn record_room_created(
+    async fn record_room_created(
         &self,
         room_id: &str,
         proctor: Address,
@@ -189,24 +204,26 @@ impl ContractClient {
                 room_id.to_string(),
                 proctor,
                 proctor_name.unwrap_or("").to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
-    /// Records a participant joining on-chain
-    pub async fn record_participant_joined(
+    /// Records a participant joining on-chain. The contract has no field for
+    /// `details`, so it is only logged
+    async fn record_participant_joined(
         &self,
         room_id: &str,
         participant: Address,
         name: Option<&str>,
         role: Role,
+        details: Option<&str>,
     ) -> Result<()> {
         tracing::debug!(
             room_id = %room_id,
             participant = %participant,
             ?role,
+            ?details,
             "Recording participant join on-chain"
         );
 
@@ -216,23 +233,25 @@ impl ContractClient {
                 participant,
                 name.unwrap_or("").to_string(),
                 role as u8,
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
-    /// Records a participant leaving on-chain
-    pub async fn record_participant_left(
+    /// Records a participant leaving on-chain. As with joins, `details` is
+    /// only logged
+    async fn record_participant_left(
         &self,
         room_id: &str,
         participant: Address,
         reason: LeaveReason,
+        details: Option<&str>,
     ) -> Result<()> {
         tracing::debug!(
             room_id = %room_id,
             participant = %participant,
             ?reason,
+            ?details,
             "Recording participant leave on-chain"
         );
 
@@ -241,14 +260,13 @@ impl ContractClient {
                 room_id.to_string(),
                 participant,
                 reason as u8,
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Records a participant being kicked on-chain
-    pub async fn record_participant_kicked(
+    async fn record_participant_kicked(
         &self,
         room_id: &str,
         proctor: Address,
@@ -268,14 +286,13 @@ impl ContractClient {
                 proctor,
                 kicked,
                 reason.unwrap_or("").to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Records an ID verification result on-chain
-    pub async fn record_id_verification(
+    async fn record_id_verification(
         &self,
         room_id: &str,
         participant: Address,
@@ -295,14 +312,13 @@ impl ContractClient {
                 participant,
                 status as u8,
                 verified_by.to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Records suspicious activity on-chain
-    pub async fn record_suspicious_activity(
+    async fn record_suspicious_activity(
         &self,
         room_id: &str,
         participant: Address,
@@ -322,14 +338,13 @@ impl ContractClient {
                 participant,
                 activity_type as u8,
                 details.unwrap_or("").to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Records recording started on-chain
-    pub async fn record_recording_started(&self, room_id: &str, participant: Address) -> Result<()> {
+    async fn record_recording_started(&self, room_id: &str, participant: Address) -> Result<()> {
         tracing::debug!(
             room_id = %room_id,
             participant = %participant,
@@ -337,63 +352,78 @@ impl ContractClient {
         );
 
         let call = self.contract
-            .record_recording_started(room_id.to_string(), participant)
-            .gas(self.gas_limit);
+            .record_recording_started(room_id.to_string(), participant);
 
         self.send_tx_with_retry(call).await
     }
 
     /// Records recording stopped on-chain
-    pub async fn record_recording_stopped(
+    async fn record_recording_stopped(
         &self,
         room_id: &str,
         participant: Address,
         duration_secs: u64,
         ipfs_cid: Option<&str>,
+        file_hash: Option<&str>,
     ) -> Result<()> {
         tracing::debug!(
             room_id = %room_id,
             participant = %participant,
             duration_secs = duration_secs,
             ?ipfs_cid,
+            ?file_hash,
             "Recording stop event on-chain"
         );
 
+        // Without a hash, the call older contract deployments also have
+        let Some(file_hash) = file_hash else {
+            let call = self.contract
+                .record_recording_stopped(
+                    room_id.to_string(),
+                    participant,
+                    duration_secs,
+                    ipfs_cid.unwrap_or("").to_string(),
+                );
+            return self.send_tx_with_retry(call).await;
+        };
+
         let call = self.contract
-            .record_recording_stopped(
+            .record_recording_stopped_with_hash(
                 room_id.to_string(),
                 participant,
                 duration_secs,
                 ipfs_cid.unwrap_or("").to_string(),
-            )
-            .gas(self.gas_limit);
+                parse_file_hash(file_hash)?,
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Closes a room on-chain
-    pub async fn close_room(&self, room_id: &str, reason: RoomCloseReason) -> Result<()> {
+    /// Closes the room on-chain, pinning the recordings manifest CID when there is one
+    async fn close_room(&self, room_id: &str, reason: RoomCloseReason, manifest_cid: Option<&str>) -> Result<()> {
         tracing::debug!(
             room_id = %room_id,
             ?reason,
+            manifest_cid = ?manifest_cid,
             "Recording room close on-chain"
         );
 
         let call = self.contract
-            .close_room(room_id.to_string(), reason as u8)
-            .gas(self.gas_limit);
+            .close_room(room_id.to_string(), reason as u8, manifest_cid.unwrap_or_default().to_string());
 
         self.send_tx_with_retry(call).await
     }
 
-    /// Creates an exam result for a participant (for NFT generation)
-    pub async fn create_exam_result(
+    /// Creates an exam result for a participant (for NFT generation) and
+    /// returns its ID, read from the `ExamResultCreated` event in the receipt
+    async fn create_exam_result(
         &self,
         room_id: &str,
         participant: Address,
         grade: u64,
         exam_name: &str,
-    ) -> Result<()> {
+    ) -> Result<u64> {
         tracing::debug!(
             room_id = %room_id,
             participant = %participant,
@@ -408,14 +438,14 @@ impl ContractClient {
                 participant,
                 U256::from(grade),
                 exam_name.to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
-        self.send_tx_with_retry_generic(call).await
+        let receipt = self.send_tx_with_retry_generic(call).await?;
+        exam_result_id(&receipt.logs)
     }
 
     /// Adds a recording CID to an existing exam result
-    pub async fn add_recording_to_result(
+    async fn add_recording_to_result(
         &self,
         result_id: u64,
         ipfs_cid: &str,
@@ -430,14 +460,13 @@ impl ContractClient {
             .add_recording_to_result(
                 U256::from(result_id),
                 ipfs_cid.to_string(),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Adds multiple recording CIDs to an existing exam result
-    pub async fn add_recordings_to_result(
+    async fn add_recordings_to_result(
         &self,
         result_id: u64,
         ipfs_cids: Vec<String>,
@@ -452,14 +481,13 @@ impl ContractClient {
             .add_recordings_to_result(
                 U256::from(result_id),
                 ipfs_cids,
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Updates the grade of an exam result
-    pub async fn update_exam_result_grade(
+    async fn update_exam_result_grade(
         &self,
         result_id: u64,
         new_grade: u64,
@@ -474,37 +502,47 @@ impl ContractClient {
             .update_exam_result_grade(
                 U256::from(result_id),
                 U256::from(new_grade),
-            )
-            .gas(self.gas_limit);
+            );
 
         self.send_tx_with_retry(call).await
     }
 
     /// Marks an NFT as minted for an exam result
-    pub async fn mark_nft_minted(&self, result_id: u64) -> Result<()> {
+    async fn mark_nft_minted(&self, result_id: u64) -> Result<()> {
         tracing::debug!(
             result_id = result_id,
             "Marking NFT as minted on-chain"
         );
 
         let call = self.contract
-            .mark_nft_minted(U256::from(result_id))
-            .gas(self.gas_limit);
+            .mark_nft_minted(U256::from(result_id));
 
         self.send_tx_with_retry(call).await
     }
 
+    /// Chain ID reported by the RPC node, which proves it is reachable
+    async fn chain_id(&self) -> Result<u64> {
+        self.contract
+            .client()
+            .get_chainid()
+            .await
+            .map(|id| id.as_u64())
+            .map_err(|e| SfuError::SubstrateConnection(format!("Failed to get chain ID: {}", e)))
+    }
+}
+
+impl ContractClient {
     /// Sends a transaction with retry logic
     async fn send_tx_with_retry(
         &self,
-        call: ContractCall<SignerMiddlewareType, ()>,
+        mut call: ContractCall<SignerMiddlewareType, ()>,
     ) -> Result<()> {
         // Acquire lock for the entire retry loop to ensure transactions are serialized
         let _guard = self.tx_mutex.lock().await;
         let mut last_error = None;
 
         for attempt in 0..self.retry_count {
-            match self.try_send_tx(&call).await {
+            match self.try_send_tx(&mut call).await {
                 Ok(()) => {
                     tracing::debug!("Transaction successful");
                     return Ok(());
@@ -559,20 +597,22 @@ impl ContractClient {
         }))
     }
 
-    /// Sends a transaction with retry logic for calls that return a value
+    /// Sends a transaction with retry logic for calls that return a value.
+    /// The value is not available from a mined transaction, so the receipt is
+    /// returned for reading it from the events the call emitted.
     async fn send_tx_with_retry_generic<T: ethers::abi::Detokenize>(
         &self,
-        call: ContractCall<SignerMiddlewareType, T>,
-    ) -> Result<()> {
+        mut call: ContractCall<SignerMiddlewareType, T>,
+    ) -> Result<TransactionReceipt> {
         // Acquire lock for the entire retry loop to ensure transactions are serialized
         let _guard = self.tx_mutex.lock().await;
         let mut last_error = None;
 
         for attempt in 0..self.retry_count {
-            match self.try_send_tx_generic(&call).await {
-                Ok(()) => {
+            match self.try_send_tx_generic(&mut call).await {
+                Ok(receipt) => {
                     tracing::debug!("Transaction successful");
-                    return Ok(());
+                    return Ok(receipt);
                 }
                 Err(e) => {
                     let error_str = e.to_string();
@@ -627,9 +667,23 @@ impl ContractClient {
     /// Attempts a single transaction
     async fn try_send_tx(
         &self,
-        call: &ContractCall<SignerMiddlewareType, ()>,
+        call: &mut ContractCall<SignerMiddlewareType, ()>,
     ) -> Result<()> {
+        self.try_send_tx_generic(call).await.map(|_| ())
+    }
+
+    /// Attempts a single transaction for calls that return a value. Gas and
+    /// fees are priced again on every attempt, so a retry after a fee spike
+    /// bids the current rate.
+    async fn try_send_tx_generic<T: ethers::abi::Detokenize>(
+        &self,
+        call: &mut ContractCall<SignerMiddlewareType, T>,
+    ) -> Result<TransactionReceipt> {
+        chaos::check(ChaosTarget::Chain, None).await?;
+
         let send_future = async {
+            let estimated_gas = self.price_call(call).await;
+
             let pending_tx = call.send().await
                 .map_err(|e| SfuError::ContractCallFailed(format!("Failed to send tx: {}", e)))?;
 
@@ -645,13 +699,16 @@ impl ContractClient {
                 )));
             }
 
-            tracing::debug!(
+            tracing::info!(
                 tx_hash = ?receipt.transaction_hash,
+                gas_estimated = ?estimated_gas,
+                gas_limit = ?call.tx.gas(),
                 gas_used = ?receipt.gas_used,
+                effective_gas_price = ?receipt.effective_gas_price,
                 "Transaction confirmed"
             );
 
-            Ok::<(), SfuError>(())
+            Ok::<TransactionReceipt, SfuError>(receipt)
         };
 
         timeout(self.submission_timeout, send_future)
@@ -659,39 +716,42 @@ impl ContractClient {
             .map_err(|_| SfuError::Timeout("Transaction timed out".to_string()))?
     }
 
-    /// Attempts a single transaction for calls that return a value
-    async fn try_send_tx_generic<T: ethers::abi::Detokenize>(
+    /// Sets the call's gas limit to its estimate plus the configured margin,
+    /// or to the configured limit when it can't be estimated, and its EIP-1559
+    /// fees from the node's recent fee history. Returns the estimate.
+    async fn price_call<T: ethers::abi::Detokenize>(
         &self,
-        call: &ContractCall<SignerMiddlewareType, T>,
-    ) -> Result<()> {
-        let send_future = async {
-            let pending_tx = call.send().await
-                .map_err(|e| SfuError::ContractCallFailed(format!("Failed to send tx: {}", e)))?;
-
-            let receipt = pending_tx.await
-                .map_err(|e| SfuError::ContractCallFailed(format!("Failed to confirm tx: {}", e)))?
-                .ok_or_else(|| SfuError::ContractCallFailed("No receipt returned".to_string()))?;
-
-            // Check transaction status - status = 1 means success, 0 means revert
-            if receipt.status == Some(ethers::types::U64::from(0)) {
-                return Err(SfuError::ContractCallFailed(format!(
-                    "Transaction reverted: tx_hash={:?}",
-                    receipt.transaction_hash
-                )));
+        call: &mut ContractCall<SignerMiddlewareType, T>,
+    ) -> Option<U256> {
+        let estimated_gas = match call.estimate_gas().await {
+            Ok(gas) => Some(gas),
+            Err(e) => {
+                tracing::warn!(
+                    error = %e,
+                    gas_limit = %self.gas_limit,
+                    "Gas estimation failed, using the configured gas limit"
+                );
+                None
             }
-
-            tracing::debug!(
-                tx_hash = ?receipt.transaction_hash,
-                gas_used = ?receipt.gas_used,
-                "Transaction confirmed"
-            );
-
-            Ok::<(), SfuError>(())
         };
-
-        timeout(self.submission_timeout, send_future)
-            .await
-            .map_err(|_| SfuError::Timeout("Transaction timed out".to_string()))?
+        call.tx.set_gas(gas_limit(estimated_gas, self.gas_margin_percent, self.gas_limit));
+
+        // Left to the signer to fill in if the node can't report fee history
+        match self.contract.client().estimate_eip1559_fees(None).await {
+            Ok((max_fee_per_gas, max_priority_fee_per_gas)) => {
+                if let Some(tx) = call.tx.as_eip1559_mut() {
+                    tx.max_fee_per_gas = Some(max_fee_per_gas);
+                    tx.max_priority_fee_per_gas = Some(max_priority_fee_per_gas);
+                }
+                tracing::debug!(
+                    max_fee_per_gas = %max_fee_per_gas,
+                    max_priority_fee_per_gas = %max_priority_fee_per_gas,
+                    "Transaction fees set from fee history"
+                );
+            }
+            Err(e) => tracing::warn!(error = %e, "Fee estimation failed, leaving fees to the signer"),
+        }
+        estimated_gas
     }
 
     /// Returns the contract address
@@ -700,6 +760,39 @@ impl ContractClient {
     }
 }
 
+/// Gas limit for a call: the estimate plus `margin_percent`, or `fallback`
+/// when there is no estimate
+fn gas_limit(estimated: Option<U256>, margin_percent: u64, fallback: U256) -> U256 {
+    match estimated {
+        Some(gas) => gas.saturating_add(gas * U256::from(margin_percent) / U256::from(100)),
+        None => fallback,
+    }
+}
+
+/// ID of the exam result a `createExamResult` transaction created, from the
+/// `ExamResultCreated` event among its receipt logs
+fn exam_result_id(logs: &[Log]) -> Result<u64> {
+    let event = logs
+        .iter()
+        .find_map(|log| parse_log::<ExamResultCreatedFilter>(log.clone()).ok())
+        .ok_or_else(|| SfuError::ContractCallFailed("No ExamResultCreated event in the receipt".to_string()))?;
+    if event.result_id > U256::from(u64::MAX) {
+        return Err(SfuError::ContractCallFailed(format!(
+            "Exam result ID {} does not fit in 64 bits",
+            event.result_id
+        )));
+    }
+    Ok(event.result_id.as_u64())
+}
+
+/// A hex SHA-256 as the contract's `bytes32`
+fn parse_file_hash(file_hash: &str) -> Result<[u8; 32]> {
+    hex::decode(file_hash)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| SfuError::ContractCallFailed(format!("{} is not a hex SHA-256", file_hash)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -718,6 +811,64 @@ mod tests {
         assert_eq!(LeaveReason::RoomClosed as u8, 3);
     }
 
+    fn exam_result_created_log(result_id: U256) -> Log {
+        let participant = Address::from_low_u64_be(7);
+        let mut result_topic = [0u8; 32];
+        result_id.to_big_endian(&mut result_topic);
+        Log {
+            topics: vec![
+                ExamResultCreatedFilter::signature(),
+                H256::from(result_topic),
+                H256::from(ethers::utils::keccak256("room_1")),
+                H256::from(participant),
+            ],
+            data: ethers::abi::encode(&[
+                ethers::abi::Token::Uint(U256::from(8750)),
+                ethers::abi::Token::Uint(U256::from(1_700_000_000u64)),
+            ])
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_exam_result_id_from_receipt_logs() {
+        // Another event the transaction emitted comes first
+        let recording_added = Log {
+            topics: vec![RecordingAddedFilter::signature(), H256::from_low_u64_be(3)],
+            data: ethers::abi::encode(&[
+                ethers::abi::Token::String("QmTest".to_string()),
+                ethers::abi::Token::Uint(U256::from(1_700_000_000u64)),
+            ])
+            .into(),
+            ..Default::default()
+        };
+        let logs = vec![recording_added, exam_result_created_log(U256::from(42))];
+        assert_eq!(exam_result_id(&logs).unwrap(), 42);
+
+        assert!(matches!(exam_result_id(&logs[..1]), Err(SfuError::ContractCallFailed(_))));
+        assert!(exam_result_id(&[exam_result_created_log(U256::MAX)]).is_err());
+    }
+
+    #[test]
+    fn test_gas_limit_adds_margin_to_estimate() {
+        let fallback = U256::from(3_000_000);
+        assert_eq!(gas_limit(Some(U256::from(100_000)), 20, fallback), U256::from(120_000));
+        assert_eq!(gas_limit(Some(U256::from(100_000)), 0, fallback), U256::from(100_000));
+        // The configured limit only applies when estimation failed, even if an estimate exceeds it
+        assert_eq!(gas_limit(Some(U256::from(4_000_000)), 20, fallback), U256::from(4_800_000));
+        assert_eq!(gas_limit(None, 20, fallback), fallback);
+    }
+
+    #[test]
+    fn test_parse_file_hash() {
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        let bytes = parse_file_hash(hash).unwrap();
+        assert_eq!((bytes[0], bytes[31]), (0x9f, 0x08));
+        assert!(parse_file_hash(&hash[..62]).is_err());
+        assert!(parse_file_hash("not a hash").is_err());
+    }
+
     #[test]
     fn test_suspicious_activity_type_values() {
         assert_eq!(SuspiciousActivityType::MultipleDevices as u8, 0);
@@ -1,4 +1,6 @@
-use std::env;
+use std::path::PathBuf;
+
+use crate::config::env;
 
 /// Default Moonbase Alpha (Moonbeam TestNet) EVM RPC URL
 /// Chain ID: 1287
@@ -11,10 +13,13 @@ pub const DEFAULT_SUBMISSION_TIMEOUT_SECS: u64 = 120;
 /// Default retry count for failed transactions (higher for flaky testnet RPC)
 pub const DEFAULT_RETRY_COUNT: u32 = 5;
 
-/// Default gas limit for contract calls
+/// Default gas limit for contract calls whose gas can't be estimated
 /// Set high to avoid "out of gas" errors on complex string operations
 pub const DEFAULT_GAS_LIMIT: u64 = 3_000_000;
 
+/// Default margin added to each gas estimate, in percent
+pub const DEFAULT_GAS_MARGIN_PERCENT: u64 = 20;
+
 /// Configuration for Asset Hub EVM interaction
 #[derive(Debug, Clone)]
 pub struct AssetHubConfig {
@@ -30,15 +35,19 @@ pub struct AssetHubConfig {
     pub submission_timeout_secs: u64,
     /// Number of retries for failed transactions
     pub retry_count: u32,
-    /// Gas limit for transactions
+    /// Gas limit for transactions whose gas can't be estimated
     pub gas_limit: u64,
+    /// Margin added to each gas estimate, in percent
+    pub gas_margin_percent: u64,
+    /// Journal of queued events, replayed on startup if they were never confirmed
+    pub queue_path: Option<PathBuf>,
 }
 
 impl AssetHubConfig {
     /// Creates configuration from environment variables
     ///
     /// Required environment variables when enabled:
-    /// - `ASSET_HUB_ENABLED`: "true" to enable
+    /// - `ASSET_HUB_ENABLED`: "true" (or "1"/"yes") to enable
     /// - `ASSET_HUB_PRIVATE_KEY`: Private key (hex with 0x prefix)
     /// - `ASSET_HUB_CONTRACT_ADDRESS`: Deployed contract address
     ///
@@ -46,52 +55,45 @@ impl AssetHubConfig {
     /// - `ASSET_HUB_RPC_URL`: RPC URL (default: Paseo Asset Hub / Passet Hub)
     /// - `ASSET_HUB_SUBMISSION_TIMEOUT_SECS`: Timeout in seconds (default: 120)
     /// - `ASSET_HUB_RETRY_COUNT`: Number of retries (default: 3)
-    /// - `ASSET_HUB_GAS_LIMIT`: Gas limit (default: 500000)
+    /// - `ASSET_HUB_GAS_LIMIT`: Gas limit when estimation fails (default: 3000000)
+    /// - `ASSET_HUB_GAS_MARGIN_PERCENT`: Margin added to gas estimates (default: 20)
+    /// - `ASSET_HUB_QUEUE_PATH`: Journal file for queued events (default: none, in memory only)
     pub fn from_env() -> Option<Self> {
-        let enabled = env::var("ASSET_HUB_ENABLED")
-            .map(|v| v.to_lowercase() == "true")
-            .unwrap_or(false);
+        let enabled = env::get_bool("ASSET_HUB_ENABLED", false);
 
         if !enabled {
             return None;
         }
 
-        let private_key = match env::var("ASSET_HUB_PRIVATE_KEY") {
-            Ok(key) if !key.is_empty() => key,
-            _ => {
-                tracing::warn!("ASSET_HUB_ENABLED is true but ASSET_HUB_PRIVATE_KEY is not set");
-                return None;
-            }
+        let Some(private_key) = env::get_string("ASSET_HUB_PRIVATE_KEY") else {
+            tracing::warn!("ASSET_HUB_ENABLED is true but ASSET_HUB_PRIVATE_KEY is not set");
+            return None;
         };
 
-        let contract_address = match env::var("ASSET_HUB_CONTRACT_ADDRESS") {
-            Ok(addr) if !addr.is_empty() => addr,
-            _ => {
-                tracing::warn!(
-                    "ASSET_HUB_ENABLED is true but ASSET_HUB_CONTRACT_ADDRESS is not set"
-                );
-                return None;
-            }
+        let Some(contract_address) = env::get_string("ASSET_HUB_CONTRACT_ADDRESS") else {
+            tracing::warn!(
+                "ASSET_HUB_ENABLED is true but ASSET_HUB_CONTRACT_ADDRESS is not set"
+            );
+            return None;
         };
 
-        let rpc_url = env::var("ASSET_HUB_RPC_URL")
-            .unwrap_or_else(|_| DEFAULT_ASSET_HUB_RPC_URL.to_string());
+        let rpc_url = env::get_string("ASSET_HUB_RPC_URL")
+            .unwrap_or_else(|| DEFAULT_ASSET_HUB_RPC_URL.to_string());
 
-        let submission_timeout_secs = env::var("ASSET_HUB_SUBMISSION_TIMEOUT_SECS")
-            .ok()
-            .and_then(|v| v.parse().ok())
+        let submission_timeout_secs = env::get_parsed("ASSET_HUB_SUBMISSION_TIMEOUT_SECS")
             .unwrap_or(DEFAULT_SUBMISSION_TIMEOUT_SECS);
 
-        let retry_count = env::var("ASSET_HUB_RETRY_COUNT")
-            .ok()
-            .and_then(|v| v.parse().ok())
+        let retry_count = env::get_parsed("ASSET_HUB_RETRY_COUNT")
             .unwrap_or(DEFAULT_RETRY_COUNT);
 
-        let gas_limit = env::var("ASSET_HUB_GAS_LIMIT")
-            .ok()
-            .and_then(|v| v.parse().ok())
+        let gas_limit = env::get_parsed("ASSET_HUB_GAS_LIMIT")
             .unwrap_or(DEFAULT_GAS_LIMIT);
 
+        let gas_margin_percent = env::get_parsed("ASSET_HUB_GAS_MARGIN_PERCENT")
+            .unwrap_or(DEFAULT_GAS_MARGIN_PERCENT);
+
+        let queue_path = env::get_string("ASSET_HUB_QUEUE_PATH").map(PathBuf::from);
+
         Some(Self {
             enabled,
             rpc_url,
@@ -100,6 +102,8 @@ impl AssetHubConfig {
             submission_timeout_secs,
             retry_count,
             gas_limit,
+            gas_margin_percent,
+            queue_path,
         })
     }
 }
@@ -114,11 +118,12 @@ mod tests {
         assert_eq!(DEFAULT_SUBMISSION_TIMEOUT_SECS, 120);
         assert_eq!(DEFAULT_RETRY_COUNT, 5);
         assert_eq!(DEFAULT_GAS_LIMIT, 3_000_000);
+        assert_eq!(DEFAULT_GAS_MARGIN_PERCENT, 20);
     }
 
     #[test]
     fn test_from_env_disabled() {
-        env::remove_var("ASSET_HUB_ENABLED");
+        std::env::remove_var("ASSET_HUB_ENABLED");
         assert!(AssetHubConfig::from_env().is_none());
     }
 }
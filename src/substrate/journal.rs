@@ -0,0 +1,176 @@
+//! Write-ahead log of chain events, so events queued but not yet confirmed
+//! survive a restart. Each event is appended to a JSONL file before it is
+//! submitted and marked done once its transaction confirms; opening the log
+//! returns the events that never were, in the order they were queued.
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::queue::ChainEvent;
+
+/// A line of the log: an event as it was queued, or the confirmation of one
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum JournalLine {
+    Queued { seq: u64, event: ChainEvent },
+    Done { done: u64 },
+}
+
+/// Append-only log of the events an `EventQueue` has not confirmed yet
+pub struct EventJournal {
+    path: PathBuf,
+    file: Mutex<File>,
+    next_seq: Mutex<u64>,
+}
+
+impl EventJournal {
+    /// Opens the log at `path`, creating it and its directory if missing.
+    /// Returns the events that were queued but never marked done, oldest
+    /// first; the file is rewritten to hold only those.
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<(u64, ChainEvent)>)> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let pending = match std::fs::read_to_string(path) {
+            Ok(contents) => unconfirmed(path, &contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        // Compacted through a temporary file so a crash mid-write loses nothing
+        let compacted = path.with_extension("jsonl.tmp");
+        let mut contents = String::new();
+        for (seq, event) in &pending {
+            contents.push_str(&serde_json::to_string(&JournalLine::Queued { seq: *seq, event: event.clone() })?);
+            contents.push('\n');
+        }
+        let mut file = File::create(&compacted)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&compacted, path)?;
+
+        let next_seq = pending.last().map(|(seq, _)| seq + 1).unwrap_or(0);
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+        let journal = Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            next_seq: Mutex::new(next_seq),
+        };
+        Ok((journal, pending))
+    }
+
+    /// Writes `event` to disk ahead of its submission; returns the sequence
+    /// number to mark it done with
+    pub fn append(&self, event: &ChainEvent) -> io::Result<u64> {
+        // Held across the write so lines land in sequence order
+        let mut next_seq = self.next_seq.lock().unwrap();
+        let seq = *next_seq;
+        self.write(&JournalLine::Queued { seq, event: event.clone() }, true)?;
+        *next_seq += 1;
+        Ok(seq)
+    }
+
+    /// Marks the event confirmed on chain, so it is not submitted again
+    pub fn complete(&self, seq: u64) -> io::Result<()> {
+        // A lost confirmation only means the event is submitted twice
+        self.write(&JournalLine::Done { done: seq }, false)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&self, line: &JournalLine, sync: bool) -> io::Result<()> {
+        let mut text = serde_json::to_string(line)?;
+        text.push('\n');
+        let mut file = self.file.lock().unwrap();
+        file.write_all(text.as_bytes())?;
+        if sync {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Queued events in `contents` without a matching confirmation, in sequence
+/// order. Lines that fail to parse, such as one cut short by a crash, are skipped.
+fn unconfirmed(path: &Path, contents: &str) -> Vec<(u64, ChainEvent)> {
+    let mut queued = std::collections::BTreeMap::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(JournalLine::Queued { seq, event }) => {
+                queued.insert(seq, event);
+            }
+            Ok(JournalLine::Done { done }) => {
+                queued.remove(&done);
+            }
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Skipping malformed chain event log line"),
+        }
+    }
+    queued.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::substrate::{Address, Role};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sfu-chain-journal-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join("chain_events.jsonl")
+    }
+
+    fn joined(participant: u64) -> ChainEvent {
+        ChainEvent::ParticipantJoined {
+            room_id: "room_1".to_string(),
+            participant: Address::from_low_u64_be(participant),
+            name: Some("Ada".to_string()),
+            role: Role::Student,
+            details: None,
+        }
+    }
+
+    #[test]
+    fn test_reopen_returns_unconfirmed_events_in_order() {
+        let path = temp_path("reopen");
+        let (journal, pending) = EventJournal::open(&path).unwrap();
+        assert!(pending.is_empty());
+
+        let seqs: Vec<u64> = (1..=3).map(|n| journal.append(&joined(n)).unwrap()).collect();
+        journal.complete(seqs[1]).unwrap();
+        drop(journal);
+        // A line cut short by a crash
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"seq":3,"event":{"Room"#).unwrap();
+
+        let (journal, pending) = EventJournal::open(&path).unwrap();
+        assert_eq!(pending, vec![(seqs[0], joined(1)), (seqs[2], joined(3))]);
+        // Numbering continues after the events still pending
+        assert_eq!(journal.append(&joined(4)).unwrap(), seqs[2] + 1);
+
+        // Compaction kept only what is still pending
+        let lines = std::fs::read_to_string(journal.path()).unwrap().lines().count();
+        assert_eq!(lines, 3);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_all_confirmed_leaves_nothing_to_replay() {
+        let path = temp_path("confirmed");
+        let (journal, _) = EventJournal::open(&path).unwrap();
+        for n in 1..=2 {
+            let seq = journal.append(&joined(n)).unwrap();
+            journal.complete(seq).unwrap();
+        }
+        drop(journal);
+
+        let (_, pending) = EventJournal::open(&path).unwrap();
+        assert!(pending.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
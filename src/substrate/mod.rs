@@ -5,11 +5,13 @@
 //!
 //! # Architecture
 //!
-//! The module consists of three main components:
+//! The module consists of five main components:
 //!
 //! - `config`: Configuration management for blockchain connection
+//! - `recorder`: The `ChainRecorder` trait the queue submits events through
 //! - `client`: Contract client for EVM interaction via ethers
 //! - `queue`: Non-blocking event queue for async submission
+//! - `journal`: Write-ahead log that lets the queue replay unconfirmed events after a restart
 //!
 //! # Wallet-Based Identity
 //!
@@ -21,10 +23,10 @@
 //! ```rust,ignore
 //! use substrate::{AssetHubConfig, ContractClient, EventQueue, ChainEvent, Address};
 //!
-//! // Initialize from environment
-//! if let Some(config) = AssetHubConfig::from_env() {
+//! // Initialize from the configuration Config::from_env assembled
+//! if let Some(config) = config.asset_hub.clone() {
 //!     let client = ContractClient::new(config).await?;
-//!     let queue = EventQueue::new(Arc::new(client));
+//!     let queue = EventQueue::new(Arc::new(client), None, sfu_server.tasks());
 //!
 //!     // Emit events (non-blocking) with wallet addresses
 //!     let proctor_wallet: Address = "0x123...".parse().unwrap();
@@ -38,7 +40,9 @@
 
 mod config;
 mod client;
+mod journal;
 mod queue;
+mod recorder;
 
 pub use config::AssetHubConfig;
 pub use client::{
@@ -49,26 +53,30 @@ pub use client::{
     SuspiciousActivityType,
     RoomCloseReason,
 };
-pub use queue::{EventQueue, ChainEvent};
+pub use queue::{EventQueue, ChainEvent, ExamResultRef};
+pub use recorder::ChainRecorder;
+#[cfg(test)]
+pub use recorder::MockChain;
 
 // Re-export Address type for convenience
 pub use ethers::types::Address;
 
 use std::sync::Arc;
 
-/// Initializes the substrate module from environment configuration
-///
-/// Returns `Some((client, queue))` if blockchain integration is enabled and
-/// configuration is valid, `None` otherwise.
-pub async fn init_from_env() -> Option<(Arc<ContractClient>, EventQueue)> {
-    let config = AssetHubConfig::from_env()?;
+use crate::sfu::TaskSupervisor;
 
+/// Connects to Asset Hub with `config`, from `Config::asset_hub`
+///
+/// Returns `Some((client, queue))` if the client connects, `None` otherwise.
+/// The queue's processor is spawned through `tasks` so it stops with them.
+pub async fn init(config: AssetHubConfig, tasks: &TaskSupervisor) -> Option<(Arc<ContractClient>, EventQueue)> {
     tracing::info!("Initializing Asset Hub EVM blockchain integration");
 
+    let queue_path = config.queue_path.clone();
     match ContractClient::new(config).await {
         Ok(client) => {
             let client = Arc::new(client);
-            let queue = EventQueue::new(client.clone());
+            let queue = EventQueue::new(client.clone(), queue_path.as_deref(), tasks);
             tracing::info!(
                 contract = %client.contract_address(),
                 "Asset Hub integration initialized"
@@ -104,6 +112,8 @@ mod tests {
             submission_timeout_secs: 0,
             retry_count: 0,
             gas_limit: 0,
+            gas_margin_percent: 0,
+            queue_path: None,
         };
     }
 
@@ -1,22 +1,55 @@
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::sleep;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
-use super::client::{
-    ContractClient, LeaveReason, Role, RoomCloseReason, SuspiciousActivityType, VerificationStatus,
-};
+use crate::error::SfuError;
+use crate::health::{self, Heartbeat};
+use crate::metrics;
+use crate::sfu::TaskSupervisor;
 
-/// Delay between dependent transactions to avoid nonce conflicts on Moonbase Alpha
-/// Based on testing, 3 seconds is sufficient to allow each transaction to be
-/// properly confirmed before sending the next one
-const TX_DELAY: Duration = Duration::from_secs(3);
+use super::client::{LeaveReason, Role, RoomCloseReason, SuspiciousActivityType, VerificationStatus};
+use super::journal::EventJournal;
+use super::recorder::ChainRecorder;
+
+/// Events submitted at once. `ContractClient` sends one transaction at a time
+/// anyway; the rest wait for it rather than for the events ahead of them.
+const SUBMISSION_WORKERS: usize = 8;
+
+/// Exam results created by processed `CreateExamResult` events, by (room_id, participant)
+type ExamResults = Arc<Mutex<HashMap<(String, Address), u64>>>;
+
+/// An event on its way to the processor, with its place in the journal if there is one
+struct Queued {
+    seq: Option<u64>,
+    event: ChainEvent,
+}
+
+/// The exam result an event applies to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExamResultRef {
+    Id(u64),
+    /// The result created by a `CreateExamResult` for the participant queued
+    /// before this event; resolved to its ID when the event is processed
+    Pending { room_id: String, participant: Address },
+}
+
+impl From<u64> for ExamResultRef {
+    fn from(result_id: u64) -> Self {
+        ExamResultRef::Id(result_id)
+    }
+}
 
 /// Events that can be queued for blockchain submission
 /// All participant identifiers are wallet addresses for NFT generation support
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ChainEvent {
     RoomCreated {
         room_id: String,
@@ -28,11 +61,14 @@ pub enum ChainEvent {
         participant: Address,
         name: Option<String>,
         role: Role,
+        /// Why the join happened when it isn't an ordinary admission, e.g. a room transfer
+        details: Option<String>,
     },
     ParticipantLeft {
         room_id: String,
         participant: Address,
         reason: LeaveReason,
+        details: Option<String>,
     },
     ParticipantKicked {
         room_id: String,
@@ -61,10 +97,15 @@ pub enum ChainEvent {
         participant: Address,
         duration_secs: u64,
         ipfs_cid: Option<String>,
+        /// Hex SHA-256 of the recorded file
+        #[serde(default)]
+        file_hash: Option<String>,
     },
     RoomClosed {
         room_id: String,
         reason: RoomCloseReason,
+        /// IPFS CID of the room's recordings manifest, if one was uploaded
+        manifest_cid: Option<String>,
     },
     /// Create a new exam result for a participant
     CreateExamResult {
@@ -75,7 +116,7 @@ pub enum ChainEvent {
     },
     /// Add a single recording CID to an exam result
     AddRecordingToResult {
-        result_id: u64,
+        result_id: ExamResultRef,
         ipfs_cid: String,
     },
     /// Add multiple recording CIDs to an exam result
@@ -96,7 +137,7 @@ pub enum ChainEvent {
 
 impl ChainEvent {
     /// Returns the dependency key for this event.
-    /// Events with the same key must be serialized with delays between them.
+    /// Events with the same key are submitted one after another, each once the previous one finished.
     /// Format: "room:<room_id>" for room-level events, "room:<room_id>:participant:<address>" for participant events
     fn dependency_key(&self) -> Option<String> {
         match self {
@@ -133,9 +174,16 @@ impl ChainEvent {
                 Some(format!("room:{}:participant:{:?}", room_id, participant))
             }
             // Result-level events - these depend on the result ID, not room/participant
-            ChainEvent::AddRecordingToResult { result_id, .. } => {
+            ChainEvent::AddRecordingToResult { result_id: ExamResultRef::Id(result_id), .. } => {
                 Some(format!("result:{}", result_id))
             }
+            // Until resolved, a result is known by the participant it is created for
+            ChainEvent::AddRecordingToResult {
+                result_id: ExamResultRef::Pending { room_id, participant },
+                ..
+            } => {
+                Some(format!("room:{}:participant:{:?}", room_id, participant))
+            }
             ChainEvent::AddRecordingsToResult { result_id, .. } => {
                 Some(format!("result:{}", result_id))
             }
@@ -162,6 +210,7 @@ impl ChainEvent {
             ChainEvent::RoomClosed { room_id, .. } => Some(room_id),
             ChainEvent::CreateExamResult { room_id, .. } => Some(room_id),
             // Result events don't have room dependency (they depend on CreateExamResult)
+            ChainEvent::AddRecordingToResult { result_id: ExamResultRef::Pending { room_id, .. }, .. } => Some(room_id),
             ChainEvent::AddRecordingToResult { .. } => None,
             ChainEvent::AddRecordingsToResult { .. } => None,
             ChainEvent::UpdateExamResultGrade { .. } => None,
@@ -170,56 +219,136 @@ impl ChainEvent {
     }
 }
 
-/// Tracks the last transaction time for each dependency key
-struct TransactionTracker {
-    /// Maps dependency key -> last transaction completion time
-    last_tx_times: HashMap<String, Instant>,
-    /// Maps room_id -> whether RoomCreated has completed
-    room_created: HashMap<String, bool>,
+/// What each event has to wait for before it is submitted. Every event gets
+/// a token that is cancelled once its submission finishes, confirmed or not.
+#[derive(Default)]
+struct Dependencies {
+    /// The latest event of each dependency key
+    latest: HashMap<String, CancellationToken>,
+    /// Events of each room that may still be in flight, which its `RoomClosed` waits for
+    rooms: HashMap<String, Vec<CancellationToken>>,
 }
 
-impl TransactionTracker {
-    fn new() -> Self {
-        Self {
-            last_tx_times: HashMap::new(),
-            room_created: HashMap::new(),
-        }
-    }
+impl Dependencies {
+    /// Registers `event` behind the events queued before it. Returns the
+    /// submissions it waits for and the token that marks its own finished.
+    fn register(&mut self, event: &ChainEvent) -> (Vec<CancellationToken>, CancellationToken) {
+        let finished = CancellationToken::new();
+        let mut waits = Vec::new();
 
-    /// Check if we need to wait before processing this event
-    fn needs_delay(&self, event: &ChainEvent) -> Option<Duration> {
-        // First check if this event depends on RoomCreated
         if let Some(room_id) = event.room_dependency() {
-            if !self.room_created.get(room_id).copied().unwrap_or(false) {
-                // Room not yet created, we need to wait for it
-                // Return full delay to allow RoomCreated to complete
-                return Some(TX_DELAY);
+            // Only a RoomCreated queued in this run can be waited for; a room
+            // created before a restart is already on chain
+            if let Some(room) = self.latest.get(&format!("room:{}", room_id)) {
+                waits.push(room.clone());
             }
+            let in_flight = self.rooms.entry(room_id.to_string()).or_default();
+            if matches!(event, ChainEvent::RoomClosed { .. }) {
+                waits.append(in_flight);
+            }
+            in_flight.push(finished.clone());
         }
-
-        // Check if there was a recent transaction for the same dependency key
         if let Some(key) = event.dependency_key() {
-            if let Some(last_time) = self.last_tx_times.get(&key) {
-                let elapsed = last_time.elapsed();
-                if elapsed < TX_DELAY {
-                    return Some(TX_DELAY - elapsed);
+            if let Some(previous) = self.latest.insert(key, finished.clone()) {
+                waits.push(previous);
+            }
+        }
+
+        waits.retain(|previous| !previous.is_cancelled());
+        (waits, finished)
+    }
+
+    /// Forgets events whose submission has finished
+    fn prune(&mut self) {
+        self.latest.retain(|_, finished| !finished.is_cancelled());
+        self.rooms.retain(|_, in_flight| {
+            in_flight.retain(|finished| !finished.is_cancelled());
+            !in_flight.is_empty()
+        });
+    }
+}
+
+/// What a worker needs to submit an event and record the outcome
+#[derive(Clone)]
+struct Submitter {
+    recorder: Arc<dyn ChainRecorder>,
+    exam_results: ExamResults,
+    journal: Option<Arc<EventJournal>>,
+    backlog: Arc<Backlog>,
+    workers: Arc<Semaphore>,
+}
+
+impl Submitter {
+    /// Submits `event` once every submission in `waits` has finished,
+    /// cancelling `finished` when done so the events behind it can go
+    async fn submit(self, seq: Option<u64>, event: ChainEvent, waits: Vec<CancellationToken>, finished: CancellationToken) {
+        // Also cancelled if this panics, so dependents are never stranded
+        let _finished = finished.drop_guard();
+        if !waits.is_empty() {
+            tracing::debug!(waits = waits.len(), event = ?event, "Waiting for dependent transactions");
+            for previous in waits {
+                previous.cancelled().await;
+            }
+        }
+        let _worker = self.workers.acquire().await;
+
+        tracing::info!(event = ?event, "Processing chain event");
+
+        // Dependents are released whether this succeeds or not, so a failed
+        // event can't block the ones behind it
+        match EventQueue::handle_event(self.recorder.as_ref(), &self.exam_results, &event).await {
+            Ok(()) => {
+                metrics::metrics().chain_events_processed_total.inc();
+                tracing::info!("Chain event processed successfully");
+                if let (Some(journal), Some(seq)) = (&self.journal, seq) {
+                    if let Err(e) = journal.complete(seq) {
+                        tracing::warn!(error = %e, seq = seq, "Failed to mark chain event confirmed in the journal");
+                    }
                 }
             }
+            Err(e) => {
+                metrics::metrics().chain_events_failed_total.inc();
+                tracing::error!(error = %e, "Failed to process chain event")
+            }
         }
+        self.backlog.done();
+    }
+}
+
+/// Events queued but not yet submitted, so shutdown can wait for them
+#[derive(Default)]
+struct Backlog {
+    queued: AtomicUsize,
+    drained: Notify,
+}
 
-        None
+impl Backlog {
+    fn add(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
     }
 
-    /// Record that a transaction completed for this event
-    fn record_completion(&mut self, event: &ChainEvent) {
-        // Mark RoomCreated as complete
-        if let ChainEvent::RoomCreated { room_id, .. } = event {
-            self.room_created.insert(room_id.clone(), true);
+    fn done(&self) {
+        if self.queued.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.drained.notify_waiters();
         }
+    }
 
-        // Record the completion time for this dependency key
-        if let Some(key) = event.dependency_key() {
-            self.last_tx_times.insert(key, Instant::now());
+    fn len(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Waits until nothing is queued or `limit` passes; returns what is left
+    async fn wait_drained(&self, limit: Duration) -> usize {
+        let deadline = Instant::now() + limit;
+        loop {
+            // Registered before the check so a completion in between isn't missed
+            let drained = self.drained.notified();
+            if self.len() == 0 {
+                return 0;
+            }
+            if tokio::time::timeout_at(deadline, drained).await.is_err() {
+                return self.len();
+            }
         }
     }
 }
@@ -229,23 +358,86 @@ impl TransactionTracker {
 /// This queue allows the SFU server to emit events without blocking
 /// on blockchain confirmation. Events are processed in the background.
 ///
-/// Delays are only applied between dependent events:
-/// - Events for the same (room, participant) pair are serialized with delays
-/// - Events for different participants can be processed without waiting
+/// Events wait only for the events they depend on, and only until those
+/// finish, confirmed or failed:
+/// - Events for the same (room, participant) pair are submitted in order
+/// - Events for different participants are submitted concurrently
 /// - All participant events wait for RoomCreated to complete first
+/// - RoomClosed waits for every event queued before it in the room
+///
+/// With a journal, every event is written to disk before it is submitted and
+/// marked done once its transaction confirms. Events a previous run queued but
+/// never confirmed, including ones that failed, are submitted again first, in
+/// the order they were queued.
 pub struct EventQueue {
-    sender: mpsc::UnboundedSender<ChainEvent>,
+    sender: mpsc::UnboundedSender<Queued>,
+    /// Also held by the processor; kept here for health checks
+    recorder: Arc<dyn ChainRecorder>,
+    backlog: Arc<Backlog>,
+    /// Set by `close`; later events are dropped. Shared by clones.
+    closed: Arc<AtomicBool>,
+    /// Filled by the processor as exam results are created, and cleared for a room once it closes
+    exam_results: ExamResults,
+    journal: Option<Arc<EventJournal>>,
 }
 
 impl EventQueue {
-    /// Creates a new event queue with a background processor
-    pub fn new(client: Arc<ContractClient>) -> Self {
+    /// Creates a new event queue with a background processor owned by `tasks`.
+    /// With `journal_path`, events left unconfirmed there by a previous run are
+    /// queued ahead of any new ones. A journal that can't be opened is logged
+    /// and the queue runs in memory only.
+    pub fn new(recorder: Arc<dyn ChainRecorder>, journal_path: Option<&Path>, tasks: &TaskSupervisor) -> Self {
         let (sender, receiver) = mpsc::unbounded_channel();
+        let backlog = Arc::new(Backlog::default());
+
+        let journal = journal_path.and_then(|path| match EventJournal::open(path) {
+            Ok((journal, pending)) => {
+                if !pending.is_empty() {
+                    tracing::warn!(
+                        path = %journal.path().display(),
+                        events = pending.len(),
+                        "Replaying chain events a previous run did not confirm"
+                    );
+                }
+                for (seq, event) in pending {
+                    backlog.add();
+                    let _ = sender.send(Queued { seq: Some(seq), event });
+                }
+                Some(Arc::new(journal))
+            }
+            Err(e) => {
+                tracing::error!(
+                    path = %path.display(),
+                    error = %e,
+                    "Failed to open chain event journal, queued events will not survive a restart"
+                );
+                None
+            }
+        });
+
+        // Submissions run on workers, so the processor beats on every tick
+        let heartbeat = health::monitor().register("chain_processor", health::HEARTBEAT_INTERVAL * 2);
+
+        let exam_results = ExamResults::default();
+        let submitter = Submitter {
+            recorder: recorder.clone(),
+            exam_results: exam_results.clone(),
+            journal: journal.clone(),
+            backlog: backlog.clone(),
+            workers: Arc::new(Semaphore::new(SUBMISSION_WORKERS)),
+        };
+        tasks.spawn("chain_processor", move |cancel| {
+            Self::process_events(submitter, receiver, heartbeat, cancel)
+        });
 
-        // Spawn background processor
-        tokio::spawn(Self::process_events(client, receiver));
-
-        Self { sender }
+        Self {
+            sender,
+            recorder,
+            backlog,
+            closed: Arc::new(AtomicBool::new(false)),
+            exam_results,
+            journal,
+        }
     }
 
     /// Queues an event for blockchain submission
@@ -253,54 +445,112 @@ impl EventQueue {
     /// This method is non-blocking and returns immediately.
     /// Events are processed in the background.
     pub fn emit(&self, event: ChainEvent) {
+        if self.closed.load(Ordering::SeqCst) {
+            tracing::warn!(event = ?event, "Chain event queue is closed, dropping event");
+            return;
+        }
         tracing::info!(event = ?event, "Queueing chain event");
-        if let Err(e) = self.sender.send(event) {
-            tracing::error!(error = %e, "Failed to queue chain event");
+        // Still submitted when it can't be written, it just won't survive a restart
+        let seq = self.journal.as_ref().and_then(|journal| match journal.append(&event) {
+            Ok(seq) => Some(seq),
+            Err(e) => {
+                tracing::error!(error = %e, event = ?event, "Failed to write chain event to the journal");
+                None
+            }
+        });
+        self.backlog.add();
+        match self.sender.send(Queued { seq, event }) {
+            Ok(()) => metrics::metrics().chain_events_queued_total.inc(),
+            Err(e) => {
+                self.backlog.done();
+                tracing::error!(error = %e, "Failed to queue chain event");
+            }
         }
     }
 
-    /// Background processor that handles queued events
+    /// Where the queued events are submitted
+    pub fn recorder(&self) -> &Arc<dyn ChainRecorder> {
+        &self.recorder
+    }
+
+    /// ID of the exam result created for the participant in an open room,
+    /// once its `CreateExamResult` has been processed
+    pub fn exam_result_id(&self, room_id: &str, participant: Address) -> Option<u64> {
+        self.exam_results
+            .lock()
+            .unwrap()
+            .get(&(room_id.to_string(), participant))
+            .copied()
+    }
+
+    /// Stops accepting events; those already queued are still submitted
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits until every queued event has been submitted, or until `limit`
+    /// passes. Returns how many were still queued.
+    pub async fn flush(&self, limit: Duration) -> usize {
+        self.backlog.wait_drained(limit).await
+    }
+
+    /// Background processor that hands queued events to workers, each
+    /// behind the events it depends on
     async fn process_events(
-        client: Arc<ContractClient>,
-        mut receiver: mpsc::UnboundedReceiver<ChainEvent>,
+        submitter: Submitter,
+        mut receiver: mpsc::UnboundedReceiver<Queued>,
+        heartbeat: Arc<Heartbeat>,
+        cancel: CancellationToken,
     ) {
         tracing::info!(
-            tx_delay_secs = TX_DELAY.as_secs(),
+            workers = SUBMISSION_WORKERS,
             "Chain event processor started (per-participant tracking enabled)"
         );
 
-        let tracker = Arc::new(RwLock::new(TransactionTracker::new()));
-
-        while let Some(event) = receiver.recv().await {
-            // Check if we need to delay for dependencies
-            let delay = {
-                let tracker_read = tracker.read().await;
-                tracker_read.needs_delay(&event)
+        let mut dependencies = Dependencies::default();
+        let mut submissions = JoinSet::new();
+        let mut tick = tokio::time::interval(health::HEARTBEAT_INTERVAL);
+        let mut draining = false;
+
+        loop {
+            let Queued { seq, event } = tokio::select! {
+                queued = receiver.recv() => match queued {
+                    Some(queued) => queued,
+                    None => break,
+                },
+                Some(submitted) = submissions.join_next() => {
+                    if let Err(e) = submitted {
+                        tracing::error!(error = %e, "Chain event worker failed");
+                    }
+                    continue;
+                }
+                _ = tick.tick() => {
+                    heartbeat.beat();
+                    dependencies.prune();
+                    continue;
+                }
+                // Stop accepting events but submit the ones already queued
+                _ = cancel.cancelled(), if !draining => {
+                    draining = true;
+                    receiver.close();
+                    tracing::info!("Chain event processor draining queued events");
+                    continue;
+                }
             };
 
-            if let Some(delay_duration) = delay {
-                tracing::debug!(
-                    delay_ms = delay_duration.as_millis(),
-                    event = ?event,
-                    "Waiting for dependent transaction"
-                );
-                sleep(delay_duration).await;
-            }
-
-            tracing::info!(event = ?event, "Processing chain event");
-
-            let result = Self::handle_event(&client, &event).await;
-
-            // Record completion regardless of success/failure
-            // This prevents indefinite blocking on failed events
-            {
-                let mut tracker_write = tracker.write().await;
-                tracker_write.record_completion(&event);
-            }
+            let (waits, finished) = dependencies.register(&event);
+            submissions.spawn(submitter.clone().submit(seq, event, waits, finished));
+        }
 
-            match result {
-                Ok(()) => tracing::info!("Chain event processed successfully"),
-                Err(e) => tracing::error!(error = %e, "Failed to process chain event"),
+        // The queue is empty; wait for what is still being submitted
+        loop {
+            tokio::select! {
+                submitted = submissions.join_next() => match submitted {
+                    Some(Err(e)) => tracing::error!(error = %e, "Chain event worker failed"),
+                    Some(Ok(())) => {}
+                    None => break,
+                },
+                _ = tick.tick() => heartbeat.beat(),
             }
         }
 
@@ -309,7 +559,8 @@ impl EventQueue {
 
     /// Handles a single event by calling the appropriate contract method
     async fn handle_event(
-        client: &ContractClient,
+        recorder: &dyn ChainRecorder,
+        exam_results: &ExamResults,
         event: &ChainEvent,
     ) -> crate::error::Result<()> {
         match event {
@@ -318,7 +569,7 @@ impl EventQueue {
                 proctor,
                 proctor_name,
             } => {
-                client
+                recorder
                     .record_room_created(room_id, *proctor, proctor_name.as_deref())
                     .await
             }
@@ -327,18 +578,20 @@ impl EventQueue {
                 participant,
                 name,
                 role,
+                details,
             } => {
-                client
-                    .record_participant_joined(room_id, *participant, name.as_deref(), *role)
+                recorder
+                    .record_participant_joined(room_id, *participant, name.as_deref(), *role, details.as_deref())
                     .await
             }
             ChainEvent::ParticipantLeft {
                 room_id,
                 participant,
                 reason,
+                details,
             } => {
-                client
-                    .record_participant_left(room_id, *participant, *reason)
+                recorder
+                    .record_participant_left(room_id, *participant, *reason, details.as_deref())
                     .await
             }
             ChainEvent::ParticipantKicked {
@@ -347,7 +600,7 @@ impl EventQueue {
                 kicked,
                 reason,
             } => {
-                client
+                recorder
                     .record_participant_kicked(
                         room_id,
                         *proctor,
@@ -362,7 +615,7 @@ impl EventQueue {
                 status,
                 verified_by,
             } => {
-                client
+                recorder
                     .record_id_verification(room_id, *participant, *status, verified_by)
                     .await
             }
@@ -372,25 +625,28 @@ impl EventQueue {
                 activity_type,
                 details,
             } => {
-                client
+                recorder
                     .record_suspicious_activity(room_id, *participant, *activity_type, details.as_deref())
                     .await
             }
             ChainEvent::RecordingStarted { room_id, participant } => {
-                client.record_recording_started(room_id, *participant).await
+                recorder.record_recording_started(room_id, *participant).await
             }
             ChainEvent::RecordingStopped {
                 room_id,
                 participant,
                 duration_secs,
                 ipfs_cid,
+                file_hash,
             } => {
-                client
-                    .record_recording_stopped(room_id, *participant, *duration_secs, ipfs_cid.as_deref())
+                recorder
+                    .record_recording_stopped(room_id, *participant, *duration_secs, ipfs_cid.as_deref(), file_hash.as_deref())
                     .await
             }
-            ChainEvent::RoomClosed { room_id, reason } => {
-                client.close_room(room_id, *reason).await
+            ChainEvent::RoomClosed { room_id, reason, manifest_cid } => {
+                let closed = recorder.close_room(room_id, *reason, manifest_cid.as_deref()).await;
+                exam_results.lock().unwrap().retain(|(result_room, _), _| result_room != room_id);
+                closed
             }
             ChainEvent::CreateExamResult {
                 room_id,
@@ -398,21 +654,38 @@ impl EventQueue {
                 grade,
                 exam_name,
             } => {
-                client
+                let result_id = recorder
                     .create_exam_result(room_id, *participant, *grade, exam_name)
-                    .await
+                    .await?;
+                tracing::info!(room_id = %room_id, participant = ?participant, result_id = result_id, "Exam result created");
+                exam_results.lock().unwrap().insert((room_id.clone(), *participant), result_id);
+                Ok(())
             }
             ChainEvent::AddRecordingToResult { result_id, ipfs_cid } => {
-                client.add_recording_to_result(*result_id, ipfs_cid).await
+                let result_id = match result_id {
+                    ExamResultRef::Id(result_id) => *result_id,
+                    ExamResultRef::Pending { room_id, participant } => exam_results
+                        .lock()
+                        .unwrap()
+                        .get(&(room_id.clone(), *participant))
+                        .copied()
+                        .ok_or_else(|| {
+                            SfuError::ContractCallFailed(format!(
+                                "No exam result was created for {:?} in room {}",
+                                participant, room_id
+                            ))
+                        })?,
+                };
+                recorder.add_recording_to_result(result_id, ipfs_cid).await
             }
             ChainEvent::AddRecordingsToResult { result_id, ipfs_cids } => {
-                client.add_recordings_to_result(*result_id, ipfs_cids.clone()).await
+                recorder.add_recordings_to_result(*result_id, ipfs_cids.clone()).await
             }
             ChainEvent::UpdateExamResultGrade { result_id, new_grade } => {
-                client.update_exam_result_grade(*result_id, *new_grade).await
+                recorder.update_exam_result_grade(*result_id, *new_grade).await
             }
             ChainEvent::MarkNftMinted { result_id } => {
-                client.mark_nft_minted(*result_id).await
+                recorder.mark_nft_minted(*result_id).await
             }
         }
     }
@@ -422,6 +695,11 @@ impl Clone for EventQueue {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
+            recorder: self.recorder.clone(),
+            backlog: self.backlog.clone(),
+            closed: self.closed.clone(),
+            exam_results: self.exam_results.clone(),
+            journal: self.journal.clone(),
         }
     }
 }
@@ -429,6 +707,7 @@ impl Clone for EventQueue {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::substrate::MockChain;
 
     #[test]
     fn test_chain_event_debug() {
@@ -449,6 +728,7 @@ mod tests {
             participant: Address::zero(),
             name: Some("John".to_string()),
             role: Role::Student,
+            details: None,
         };
         let cloned = event.clone();
         match cloned {
@@ -471,6 +751,7 @@ mod tests {
         let room_closed = ChainEvent::RoomClosed {
             room_id: "room_1".to_string(),
             reason: RoomCloseReason::ProctorLeft,
+            manifest_cid: Some("QmManifest".to_string()),
         };
         assert_eq!(room_closed.dependency_key(), Some("room:room_1".to_string()));
     }
@@ -484,6 +765,7 @@ mod tests {
             participant,
             name: None,
             role: Role::Student,
+            details: None,
         };
         let key = joined.dependency_key().unwrap();
         assert!(key.contains("room:room_1"));
@@ -507,6 +789,7 @@ mod tests {
             participant: participant_a,
             name: None,
             role: Role::Student,
+            details: None,
         };
 
         let event_b = ChainEvent::ParticipantJoined {
@@ -514,6 +797,7 @@ mod tests {
             participant: participant_b,
             name: None,
             role: Role::Student,
+            details: None,
         };
 
         // Different participants should have different dependency keys
@@ -534,6 +818,7 @@ mod tests {
             participant: Address::zero(),
             name: None,
             role: Role::Student,
+            details: None,
         };
         assert_eq!(participant_joined.room_dependency(), Some("room_1"));
     }
@@ -554,7 +839,7 @@ mod tests {
     #[test]
     fn test_add_recording_event() {
         let event = ChainEvent::AddRecordingToResult {
-            result_id: 1,
+            result_id: ExamResultRef::Id(1),
             ipfs_cid: "QmTest123".to_string(),
         };
         let debug_str = format!("{:?}", event);
@@ -576,7 +861,7 @@ mod tests {
     #[test]
     fn test_result_level_dependency_keys() {
         let add_recording = ChainEvent::AddRecordingToResult {
-            result_id: 42,
+            result_id: ExamResultRef::Id(42),
             ipfs_cid: "QmTest".to_string(),
         };
         assert_eq!(add_recording.dependency_key(), Some("result:42".to_string()));
@@ -594,7 +879,7 @@ mod tests {
     #[test]
     fn test_result_events_no_room_dependency() {
         let add_recording = ChainEvent::AddRecordingToResult {
-            result_id: 1,
+            result_id: ExamResultRef::Id(1),
             ipfs_cid: "QmTest".to_string(),
         };
         assert!(add_recording.room_dependency().is_none());
@@ -642,11 +927,19 @@ mod tests {
             participant: Address::zero(),
             duration_secs: 3600,
             ipfs_cid: Some("QmRecording123".to_string()),
+            file_hash: Some("9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".to_string()),
         };
         let debug_str = format!("{:?}", event);
         assert!(debug_str.contains("RecordingStopped"));
         assert!(debug_str.contains("3600"));
         assert!(debug_str.contains("QmRecording123"));
+
+        // Journals written before the hash was recorded still replay
+        let journaled = r#"{"RecordingStopped":{"room_id":"room_1","participant":"0x0000000000000000000000000000000000000000","duration_secs":3600,"ipfs_cid":null}}"#;
+        match serde_json::from_str::<ChainEvent>(journaled).unwrap() {
+            ChainEvent::RecordingStopped { file_hash, .. } => assert_eq!(file_hash, None),
+            other => panic!("expected RecordingStopped, got {:?}", other),
+        }
     }
 
     #[test]
@@ -662,89 +955,305 @@ mod tests {
         assert!(debug_str.contains("Cheating detected"));
     }
 
-    #[test]
-    fn test_transaction_tracker_new() {
-        let tracker = TransactionTracker::new();
-        assert!(tracker.last_tx_times.is_empty());
-        assert!(tracker.room_created.is_empty());
+    fn joined(room_id: &str, participant: Address) -> ChainEvent {
+        ChainEvent::ParticipantJoined {
+            room_id: room_id.to_string(),
+            participant,
+            name: None,
+            role: Role::Student,
+            details: None,
+        }
     }
 
-    #[test]
-    fn test_transaction_tracker_needs_delay_for_uncreated_room() {
-        let tracker = TransactionTracker::new();
+    fn room_created(room_id: &str) -> ChainEvent {
+        ChainEvent::RoomCreated {
+            room_id: room_id.to_string(),
+            proctor: Address::zero(),
+            proctor_name: None,
+        }
+    }
 
-        // Participant join should need delay if room not created
-        let event = ChainEvent::ParticipantJoined {
+    #[test]
+    fn test_dependencies_wait_for_room_and_key() {
+        let mut dependencies = Dependencies::default();
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+
+        let (waits, created) = dependencies.register(&room_created("room_1"));
+        assert!(waits.is_empty());
+        // Both joins wait only for the room
+        let (waits, joined_a) = dependencies.register(&joined("room_1", a));
+        assert_eq!(waits.len(), 1);
+        let (waits, joined_b) = dependencies.register(&joined("room_1", b));
+        assert_eq!(waits.len(), 1);
+        // A room nobody created in this run has nothing to wait for
+        let (waits, _) = dependencies.register(&joined("room_2", a));
+        assert!(waits.is_empty());
+
+        created.cancel();
+        // a's next event waits for its join only, now the room exists
+        let (waits, recording_a) = dependencies.register(&ChainEvent::RecordingStarted {
             room_id: "room_1".to_string(),
-            participant: Address::zero(),
-            name: None,
-            role: Role::Student,
-        };
-
-        let delay = tracker.needs_delay(&event);
-        assert!(delay.is_some());
-        assert_eq!(delay.unwrap(), TX_DELAY);
+            participant: a,
+        });
+        assert_eq!(waits.len(), 1);
+        joined_a.cancel();
+        assert!(waits[0].is_cancelled());
+
+        // RoomClosed waits for everything still in flight in the room
+        let (waits, _) = dependencies.register(&ChainEvent::RoomClosed {
+            room_id: "room_1".to_string(),
+            reason: RoomCloseReason::SessionCompleted,
+            manifest_cid: None,
+        });
+        assert_eq!(waits.len(), 2);
+        joined_b.cancel();
+        recording_a.cancel();
+        assert!(waits.iter().all(CancellationToken::is_cancelled));
     }
 
     #[test]
-    fn test_transaction_tracker_no_delay_for_room_created() {
-        let tracker = TransactionTracker::new();
+    fn test_dependencies_forget_finished_events() {
+        let mut dependencies = Dependencies::default();
+        let (_, created) = dependencies.register(&room_created("room_1"));
+        let (_, joined) = dependencies.register(&joined("room_1", Address::zero()));
+        created.cancel();
+        dependencies.prune();
+        assert_eq!(dependencies.latest.len(), 1);
+        assert_eq!(dependencies.rooms["room_1"].len(), 1);
+
+        joined.cancel();
+        dependencies.prune();
+        assert!(dependencies.latest.is_empty());
+        assert!(dependencies.rooms.is_empty());
+    }
 
-        // RoomCreated should not need delay (no room dependency)
-        let event = ChainEvent::RoomCreated {
-            room_id: "room_1".to_string(),
-            proctor: Address::zero(),
-            proctor_name: None,
-        };
+    #[tokio::test]
+    async fn test_backlog_flush_waits_for_queued_events() {
+        let backlog = Arc::new(Backlog::default());
+        assert_eq!(backlog.wait_drained(Duration::from_secs(5)).await, 0);
+
+        backlog.add();
+        backlog.add();
+        // Bounded while events are still queued
+        assert_eq!(backlog.wait_drained(Duration::from_millis(20)).await, 2);
+
+        let processor = backlog.clone();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                processor.done();
+            }
+        });
+        assert_eq!(backlog.wait_drained(Duration::from_secs(5)).await, 0);
+    }
 
-        let delay = tracker.needs_delay(&event);
-        // RoomCreated has no previous tx for its key, so no delay needed
-        assert!(delay.is_none());
+    #[tokio::test(start_paused = true)]
+    async fn test_processor_waits_only_for_dependent_confirmations() {
+        let chain = Arc::new(MockChain::new());
+        let confirmation = Duration::from_millis(500);
+        chain.confirm_after(confirmation);
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), None, &tasks);
+        // Fewer than there are workers, so none queues for one
+        let students: Vec<Address> = (1..=4).map(Address::from_low_u64_be).collect();
+        let recording = |participant| ChainEvent::RecordingStarted { room_id: "room_1".to_string(), participant };
+
+        let started = Instant::now();
+        queue.emit(room_created("room_1"));
+        for &student in &students {
+            queue.emit(joined("room_1", student));
+            queue.emit(recording(student));
+        }
+        // Never created in this run, so it goes straight away
+        queue.emit(joined("room_2", students[0]));
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+
+        let calls: Vec<(Duration, ChainEvent)> = chain
+            .times()
+            .into_iter()
+            .map(|at| at - started)
+            .zip(chain.events())
+            .collect();
+        assert_eq!(calls.len(), 2 + students.len() * 2);
+        let submitted_at = |event: &ChainEvent| calls.iter().find(|(_, call)| call == event).unwrap().0;
+
+        assert_eq!(submitted_at(&room_created("room_1")), Duration::ZERO);
+        assert_eq!(submitted_at(&joined("room_2", students[0])), Duration::ZERO);
+        for &student in &students {
+            // Each goes once the event before it confirms, not after a fixed delay
+            assert_eq!(submitted_at(&joined("room_1", student)), confirmation);
+            assert_eq!(submitted_at(&recording(student)), confirmation * 2);
+        }
+        // Three confirmations deep: the room, each join, then each recording
+        assert_eq!(started.elapsed(), confirmation * 3);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
     }
 
-    #[test]
-    fn test_transaction_tracker_record_completion() {
-        let mut tracker = TransactionTracker::new();
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_event_does_not_block_dependents() {
+        let chain = Arc::new(MockChain::new());
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), None, &tasks);
+        let failed_before = metrics::metrics().chain_events_failed_total.get();
 
-        let room_created = ChainEvent::RoomCreated {
-            room_id: "room_1".to_string(),
-            proctor: Address::zero(),
-            proctor_name: None,
-        };
+        chain.fail_next(1);
+        let events = vec![
+            ChainEvent::RoomCreated {
+                room_id: "room_1".to_string(),
+                proctor: Address::zero(),
+                proctor_name: None,
+            },
+            ChainEvent::ParticipantJoined {
+                room_id: "room_1".to_string(),
+                participant: Address::from_low_u64_be(1),
+                name: None,
+                role: Role::Student,
+                details: None,
+            },
+        ];
+        for event in &events {
+            queue.emit(event.clone());
+        }
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+
+        assert_eq!(chain.events(), events);
+        let times = chain.times();
+        assert_eq!(times[1], times[0]);
+        assert!(metrics::metrics().chain_events_failed_total.get() > failed_before);
 
-        tracker.record_completion(&room_created);
+        // Closed queues drop new events
+        queue.close();
+        queue.emit(events[1].clone());
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+        assert_eq!(chain.events().len(), 2);
 
-        // Room should now be marked as created
-        assert!(tracker.room_created.get("room_1").copied().unwrap_or(false));
-        // Dependency key should be recorded
-        assert!(tracker.last_tx_times.contains_key("room:room_1"));
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
     }
 
-    #[test]
-    fn test_transaction_tracker_no_delay_after_room_created() {
-        let mut tracker = TransactionTracker::new();
+    #[tokio::test(start_paused = true)]
+    async fn test_pending_result_resolves_to_created_id() {
+        let chain = Arc::new(MockChain::new());
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), None, &tasks);
+        let (a, b) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let pending = |participant| ExamResultRef::Pending { room_id: "room_1".to_string(), participant };
 
-        // First, mark room as created
-        let room_created = ChainEvent::RoomCreated {
+        let events = vec![
+            ChainEvent::RoomCreated {
+                room_id: "room_1".to_string(),
+                proctor: Address::zero(),
+                proctor_name: None,
+            },
+            ChainEvent::CreateExamResult {
+                room_id: "room_1".to_string(),
+                participant: a,
+                grade: 9000,
+                exam_name: "Final".to_string(),
+            },
+            ChainEvent::AddRecordingToResult { result_id: pending(a), ipfs_cid: "QmA".to_string() },
+            // No result was created for b, so this never reaches the chain
+            ChainEvent::AddRecordingToResult { result_id: pending(b), ipfs_cid: "QmB".to_string() },
+        ];
+        for event in &events {
+            queue.emit(event.clone());
+        }
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+
+        assert_eq!(
+            chain.events()[1..],
+            [
+                events[1].clone(),
+                ChainEvent::AddRecordingToResult { result_id: ExamResultRef::Id(1), ipfs_cid: "QmA".to_string() },
+            ]
+        );
+        assert_eq!(queue.exam_result_id("room_1", a), Some(1));
+        assert_eq!(queue.exam_result_id("room_1", b), None);
+
+        queue.emit(ChainEvent::RoomClosed {
             room_id: "room_1".to_string(),
-            proctor: Address::zero(),
-            proctor_name: None,
-        };
-        tracker.record_completion(&room_created);
+            reason: RoomCloseReason::SessionCompleted,
+            manifest_cid: None,
+        });
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+        assert_eq!(queue.exam_result_id("room_1", a), None);
+
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
+    }
 
-        // Now a participant join should not need delay for room dependency
-        // (though it might need delay if same participant had recent tx)
-        let participant = Address::from_low_u64_be(999); // Different participant
-        let join_event = ChainEvent::ParticipantJoined {
+    #[tokio::test(start_paused = true)]
+    async fn test_unconfirmed_events_replayed_after_restart() {
+        let dir = std::env::temp_dir().join(format!("sfu-chain-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("chain_events.jsonl");
+        let a = Address::from_low_u64_be(1);
+        let joined = |participant| ChainEvent::ParticipantJoined {
             room_id: "room_1".to_string(),
             participant,
             name: None,
             role: Role::Student,
+            details: None,
         };
+        let events = vec![
+            ChainEvent::RoomCreated {
+                room_id: "room_1".to_string(),
+                proctor: Address::zero(),
+                proctor_name: None,
+            },
+            joined(a),
+            ChainEvent::RecordingStarted { room_id: "room_1".to_string(), participant: a },
+            ChainEvent::RecordingStopped {
+                room_id: "room_1".to_string(),
+                participant: a,
+                duration_secs: 60,
+                ipfs_cid: None,
+                file_hash: None,
+            },
+        ];
 
-        // Since this participant has no previous transaction, no delay needed
-        let delay = tracker.needs_delay(&join_event);
-        assert!(delay.is_none());
+        // The first run confirms only the last, which waited for the others to fail
+        let chain = Arc::new(MockChain::new());
+        chain.fail_next(3);
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), Some(&path), &tasks);
+        for event in &events {
+            queue.emit(event.clone());
+        }
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
+        drop(queue);
+
+        // The next replays the rest in the order they were queued, before anything new
+        let chain = Arc::new(MockChain::new());
+        chain.confirm_after(Duration::from_secs(1));
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), Some(&path), &tasks);
+        let left = ChainEvent::ParticipantLeft {
+            room_id: "room_1".to_string(),
+            participant: a,
+            reason: LeaveReason::Normal,
+            details: None,
+        };
+        queue.emit(left.clone());
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+
+        assert_eq!(chain.events(), [&events[..3], &[left]].concat());
+        let times = chain.times();
+        let offsets: Vec<Duration> = times.iter().map(|at| *at - times[0]).collect();
+        // Each of a's events still waits for the one before it to confirm
+        let seconds = |secs| Duration::from_secs(secs);
+        assert_eq!(offsets, vec![seconds(0), seconds(1), seconds(2), seconds(3)]);
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
+        drop(queue);
+
+        // All confirmed now, so a third run has nothing to replay
+        let chain = Arc::new(MockChain::new());
+        let tasks = TaskSupervisor::new();
+        let queue = EventQueue::new(chain.clone(), Some(&path), &tasks);
+        assert_eq!(queue.flush(Duration::from_secs(60)).await, 0);
+        assert!(chain.events().is_empty());
+        assert!(tasks.shutdown(Duration::from_secs(5)).await.is_clean());
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[test]
@@ -761,11 +1270,13 @@ mod tests {
                 participant: Address::zero(),
                 name: None,
                 role: Role::Student,
+                details: None,
             },
             ChainEvent::ParticipantLeft {
                 room_id: "r1".to_string(),
                 participant: Address::zero(),
                 reason: LeaveReason::Normal,
+                details: None,
             },
             ChainEvent::ParticipantKicked {
                 room_id: "r1".to_string(),
@@ -794,10 +1305,12 @@ mod tests {
                 participant: Address::zero(),
                 duration_secs: 0,
                 ipfs_cid: None,
+                file_hash: None,
             },
             ChainEvent::RoomClosed {
                 room_id: "r1".to_string(),
                 reason: RoomCloseReason::SessionCompleted,
+                manifest_cid: None,
             },
             ChainEvent::CreateExamResult {
                 room_id: "r1".to_string(),
@@ -806,7 +1319,7 @@ mod tests {
                 exam_name: "Test".to_string(),
             },
             ChainEvent::AddRecordingToResult {
-                result_id: 1,
+                result_id: ExamResultRef::Id(1),
                 ipfs_cid: "Qm".to_string(),
             },
             ChainEvent::AddRecordingsToResult {
@@ -0,0 +1,301 @@
+//! The contract calls `EventQueue` submits events through, implemented by
+//! `ContractClient` and, in tests, by `MockChain`
+
+use async_trait::async_trait;
+use ethers::types::Address;
+
+use super::client::{LeaveReason, Role, RoomCloseReason, SuspiciousActivityType, VerificationStatus};
+use crate::error::Result;
+
+#[async_trait]
+pub trait ChainRecorder: Send + Sync {
+    async fn record_room_created(&self, room_id: &str, proctor: Address, proctor_name: Option<&str>) -> Result<()>;
+
+    async fn record_participant_joined(
+        &self,
+        room_id: &str,
+        participant: Address,
+        name: Option<&str>,
+        role: Role,
+        details: Option<&str>,
+    ) -> Result<()>;
+
+    async fn record_participant_left(
+        &self,
+        room_id: &str,
+        participant: Address,
+        reason: LeaveReason,
+        details: Option<&str>,
+    ) -> Result<()>;
+
+    async fn record_participant_kicked(
+        &self,
+        room_id: &str,
+        proctor: Address,
+        kicked: Address,
+        reason: Option<&str>,
+    ) -> Result<()>;
+
+    async fn record_id_verification(
+        &self,
+        room_id: &str,
+        participant: Address,
+        status: VerificationStatus,
+        verified_by: &str,
+    ) -> Result<()>;
+
+    async fn record_suspicious_activity(
+        &self,
+        room_id: &str,
+        participant: Address,
+        activity_type: SuspiciousActivityType,
+        details: Option<&str>,
+    ) -> Result<()>;
+
+    async fn record_recording_started(&self, room_id: &str, participant: Address) -> Result<()>;
+
+    /// `file_hash` is the hex SHA-256 of the recorded file, when it could be read
+    async fn record_recording_stopped(
+        &self,
+        room_id: &str,
+        participant: Address,
+        duration_secs: u64,
+        ipfs_cid: Option<&str>,
+        file_hash: Option<&str>,
+    ) -> Result<()>;
+
+    /// Closes the room, pinning the recordings manifest CID when there is one
+    async fn close_room(&self, room_id: &str, reason: RoomCloseReason, manifest_cid: Option<&str>) -> Result<()>;
+
+    /// Returns the ID of the created result
+    async fn create_exam_result(&self, room_id: &str, participant: Address, grade: u64, exam_name: &str) -> Result<u64>;
+
+    async fn add_recording_to_result(&self, result_id: u64, ipfs_cid: &str) -> Result<()>;
+
+    async fn add_recordings_to_result(&self, result_id: u64, ipfs_cids: Vec<String>) -> Result<()>;
+
+    async fn update_exam_result_grade(&self, result_id: u64, new_grade: u64) -> Result<()>;
+
+    async fn mark_nft_minted(&self, result_id: u64) -> Result<()>;
+
+    /// Chain ID reported by the node, which proves it is reachable
+    async fn chain_id(&self) -> Result<u64>;
+}
+
+#[cfg(test)]
+pub use mock::MockChain;
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use crate::error::SfuError;
+    use crate::substrate::{ChainEvent, ExamResultRef};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    /// Records every call as the `ChainEvent` that produced it, with the time
+    /// it was submitted. Calls succeed unless a failure was requested with
+    /// `fail_next`, and return after the latency set with `confirm_after`.
+    /// Exam results are numbered from 1 in creation order.
+    #[derive(Default)]
+    pub struct MockChain {
+        calls: Mutex<Vec<(Instant, ChainEvent)>>,
+        failures: Mutex<u32>,
+        exam_results: Mutex<u64>,
+        latency: Mutex<Duration>,
+    }
+
+    impl MockChain {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Makes the next `count` calls fail; they are still recorded
+        pub fn fail_next(&self, count: u32) {
+            *self.failures.lock().unwrap() = count;
+        }
+
+        /// Makes every call take `latency` to confirm
+        pub fn confirm_after(&self, latency: Duration) {
+            *self.latency.lock().unwrap() = latency;
+        }
+
+        pub fn events(&self) -> Vec<ChainEvent> {
+            self.calls.lock().unwrap().iter().map(|(_, event)| event.clone()).collect()
+        }
+
+        /// Submission times, in call order
+        pub fn times(&self) -> Vec<Instant> {
+            self.calls.lock().unwrap().iter().map(|(at, _)| *at).collect()
+        }
+
+        async fn record(&self, event: ChainEvent) -> Result<()> {
+            self.calls.lock().unwrap().push((Instant::now(), event));
+            let failed = {
+                let mut failures = self.failures.lock().unwrap();
+                let failed = *failures > 0;
+                *failures = failures.saturating_sub(1);
+                failed
+            };
+            let latency = *self.latency.lock().unwrap();
+            tokio::time::sleep(latency).await;
+            if failed {
+                return Err(SfuError::TransactionFailed("Injected transaction failure".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl ChainRecorder for MockChain {
+        async fn record_room_created(&self, room_id: &str, proctor: Address, proctor_name: Option<&str>) -> Result<()> {
+            self.record(ChainEvent::RoomCreated {
+                room_id: room_id.to_string(),
+                proctor,
+                proctor_name: proctor_name.map(str::to_string),
+            }).await
+        }
+
+        async fn record_participant_joined(
+            &self,
+            room_id: &str,
+            participant: Address,
+            name: Option<&str>,
+            role: Role,
+            details: Option<&str>,
+        ) -> Result<()> {
+            self.record(ChainEvent::ParticipantJoined {
+                room_id: room_id.to_string(),
+                participant,
+                name: name.map(str::to_string),
+                role,
+                details: details.map(str::to_string),
+            }).await
+        }
+
+        async fn record_participant_left(
+            &self,
+            room_id: &str,
+            participant: Address,
+            reason: LeaveReason,
+            details: Option<&str>,
+        ) -> Result<()> {
+            self.record(ChainEvent::ParticipantLeft {
+                room_id: room_id.to_string(),
+                participant,
+                reason,
+                details: details.map(str::to_string),
+            }).await
+        }
+
+        async fn record_participant_kicked(
+            &self,
+            room_id: &str,
+            proctor: Address,
+            kicked: Address,
+            reason: Option<&str>,
+        ) -> Result<()> {
+            self.record(ChainEvent::ParticipantKicked {
+                room_id: room_id.to_string(),
+                proctor,
+                kicked,
+                reason: reason.map(str::to_string),
+            }).await
+        }
+
+        async fn record_id_verification(
+            &self,
+            room_id: &str,
+            participant: Address,
+            status: VerificationStatus,
+            verified_by: &str,
+        ) -> Result<()> {
+            self.record(ChainEvent::IdVerification {
+                room_id: room_id.to_string(),
+                participant,
+                status,
+                verified_by: verified_by.to_string(),
+            }).await
+        }
+
+        async fn record_suspicious_activity(
+            &self,
+            room_id: &str,
+            participant: Address,
+            activity_type: SuspiciousActivityType,
+            details: Option<&str>,
+        ) -> Result<()> {
+            self.record(ChainEvent::SuspiciousActivity {
+                room_id: room_id.to_string(),
+                participant,
+                activity_type,
+                details: details.map(str::to_string),
+            }).await
+        }
+
+        async fn record_recording_started(&self, room_id: &str, participant: Address) -> Result<()> {
+            self.record(ChainEvent::RecordingStarted { room_id: room_id.to_string(), participant }).await
+        }
+
+        async fn record_recording_stopped(
+            &self,
+            room_id: &str,
+            participant: Address,
+            duration_secs: u64,
+            ipfs_cid: Option<&str>,
+            file_hash: Option<&str>,
+        ) -> Result<()> {
+            self.record(ChainEvent::RecordingStopped {
+                room_id: room_id.to_string(),
+                participant,
+                duration_secs,
+                ipfs_cid: ipfs_cid.map(str::to_string),
+                file_hash: file_hash.map(str::to_string),
+            }).await
+        }
+
+        async fn close_room(&self, room_id: &str, reason: RoomCloseReason, manifest_cid: Option<&str>) -> Result<()> {
+            self.record(ChainEvent::RoomClosed {
+                room_id: room_id.to_string(),
+                reason,
+                manifest_cid: manifest_cid.map(str::to_string),
+            }).await
+        }
+
+        async fn create_exam_result(&self, room_id: &str, participant: Address, grade: u64, exam_name: &str) -> Result<u64> {
+            self.record(ChainEvent::CreateExamResult {
+                room_id: room_id.to_string(),
+                participant,
+                grade,
+                exam_name: exam_name.to_string(),
+            }).await?;
+            let mut created = self.exam_results.lock().unwrap();
+            *created += 1;
+            Ok(*created)
+        }
+
+        async fn add_recording_to_result(&self, result_id: u64, ipfs_cid: &str) -> Result<()> {
+            self.record(ChainEvent::AddRecordingToResult {
+                result_id: ExamResultRef::Id(result_id),
+                ipfs_cid: ipfs_cid.to_string(),
+            }).await
+        }
+
+        async fn add_recordings_to_result(&self, result_id: u64, ipfs_cids: Vec<String>) -> Result<()> {
+            self.record(ChainEvent::AddRecordingsToResult { result_id, ipfs_cids }).await
+        }
+
+        async fn update_exam_result_grade(&self, result_id: u64, new_grade: u64) -> Result<()> {
+            self.record(ChainEvent::UpdateExamResultGrade { result_id, new_grade }).await
+        }
+
+        async fn mark_nft_minted(&self, result_id: u64) -> Result<()> {
+            self.record(ChainEvent::MarkNftMinted { result_id }).await
+        }
+
+        async fn chain_id(&self) -> Result<u64> {
+            Ok(1287)
+        }
+    }
+}
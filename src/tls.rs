@@ -0,0 +1,272 @@
+//! TLS for the HTTP and WebSocket listener. With `TLS_CERT_PATH` and
+//! `TLS_KEY_PATH` set the server speaks only HTTPS and WSS, so browsers on an
+//! HTTPS page can reach `/sfu` without a reverse proxy in front.
+//!
+//! Both files are checked at startup: an unreadable file or a key that does
+//! not belong to the certificate stops the server with an error naming the
+//! file, rather than failing every handshake once it is running.
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use openssl::pkey::PKey;
+use openssl::x509::X509;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use warp::{Filter, Reply};
+
+use crate::config::env;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("TLS_CERT_PATH and TLS_KEY_PATH must be set together")]
+    Incomplete,
+
+    #[error("Failed to read {setting} ({}): {source}", .path.display())]
+    Unreadable {
+        setting: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("{} does not contain a PEM certificate: {reason}", .path.display())]
+    InvalidCertificate { path: PathBuf, reason: String },
+
+    #[error("{} does not contain an unencrypted PEM private key: {reason}", .path.display())]
+    InvalidKey { path: PathBuf, reason: String },
+
+    #[error("The private key in {} does not match the certificate in {}", .key.display(), .cert.display())]
+    KeyMismatch { cert: PathBuf, key: PathBuf },
+}
+
+/// Where the certificate and key are; TLS is off while both are unset
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM certificate chain, the server's own certificate first
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key of that certificate
+    pub key_path: Option<PathBuf>,
+}
+
+/// A certificate chain and key that were checked to belong together
+pub struct TlsIdentity {
+    pub cert_pem: Vec<u8>,
+    pub key_pem: Vec<u8>,
+}
+
+impl TlsConfig {
+    pub fn from_env() -> Self {
+        Self {
+            cert_path: env::get_string("TLS_CERT_PATH").map(PathBuf::from),
+            key_path: env::get_string("TLS_KEY_PATH").map(PathBuf::from),
+        }
+    }
+
+    /// Whether TLS was asked for, even if only half configured
+    pub fn enabled(&self) -> bool {
+        self.cert_path.is_some() || self.key_path.is_some()
+    }
+
+    /// Reads and checks both files, `None` when TLS is off
+    pub fn load(&self) -> Result<Option<TlsIdentity>, TlsError> {
+        let (cert_path, key_path) = match (&self.cert_path, &self.key_path) {
+            (None, None) => return Ok(None),
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            _ => return Err(TlsError::Incomplete),
+        };
+        let cert_pem = read("TLS_CERT_PATH", cert_path)?;
+        let key_pem = read("TLS_KEY_PATH", key_path)?;
+
+        let invalid_cert = |reason: String| TlsError::InvalidCertificate {
+            path: cert_path.clone(),
+            reason,
+        };
+        let chain = X509::stack_from_pem(&cert_pem).map_err(|e| invalid_cert(e.to_string()))?;
+        let leaf = chain.first().ok_or_else(|| invalid_cert("no CERTIFICATE block".to_string()))?;
+        let public_key = leaf.public_key().map_err(|e| invalid_cert(e.to_string()))?;
+
+        // An empty passphrase makes an encrypted key fail here rather than
+        // prompting on the terminal; warp cannot use one either
+        let key = PKey::private_key_from_pem_passphrase(&key_pem, b"").map_err(|e| TlsError::InvalidKey {
+            path: key_path.clone(),
+            reason: e.to_string(),
+        })?;
+        if !public_key.public_eq(&key) {
+            return Err(TlsError::KeyMismatch {
+                cert: cert_path.clone(),
+                key: key_path.clone(),
+            });
+        }
+
+        Ok(Some(TlsIdentity { cert_pem, key_pem }))
+    }
+}
+
+fn read(setting: &'static str, path: &Path) -> Result<Vec<u8>, TlsError> {
+    std::fs::read(path).map_err(|source| TlsError::Unreadable {
+        setting,
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Binds `routes` to `addr`, over TLS when `identity` is given, and serves
+/// until `signal` resolves
+pub fn bind<F>(
+    routes: F,
+    addr: impl Into<SocketAddr> + 'static,
+    identity: Option<&TlsIdentity>,
+    signal: impl Future<Output = ()> + Send + 'static,
+) -> Result<(SocketAddr, BoxFuture<'static, ()>), warp::Error>
+where
+    F: Filter<Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let server = warp::serve(routes);
+    match identity {
+        Some(identity) => server
+            .tls()
+            .cert(&identity.cert_pem)
+            .key(&identity.key_pem)
+            .try_bind_with_graceful_shutdown(addr, signal)
+            .map(|(addr, serving)| (addr, serving.boxed())),
+        None => server
+            .try_bind_with_graceful_shutdown(addr, signal)
+            .map(|(addr, serving)| (addr, serving.boxed())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sfu::SfuServer;
+    use futures::{SinkExt, StreamExt};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::Connector;
+
+    struct TestCert {
+        dir: PathBuf,
+        cert_pem: String,
+        config: TlsConfig,
+    }
+
+    /// A self-signed certificate for `localhost` and its key, written to a
+    /// new directory under `name`
+    fn self_signed(name: &str) -> TestCert {
+        let dir = std::env::temp_dir().join(format!("sfu-tls-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_pem = cert.serialize_pem().unwrap();
+        std::fs::write(dir.join("cert.pem"), &cert_pem).unwrap();
+        std::fs::write(dir.join("key.pem"), cert.serialize_private_key_pem()).unwrap();
+        let config = TlsConfig {
+            cert_path: Some(dir.join("cert.pem")),
+            key_path: Some(dir.join("key.pem")),
+        };
+        TestCert { dir, cert_pem, config }
+    }
+
+    #[test]
+    fn test_load_checks_the_files() {
+        assert!(TlsConfig::default().load().unwrap().is_none());
+        assert!(!TlsConfig::default().enabled());
+
+        let TestCert { dir, config, .. } = self_signed("load");
+        assert!(config.load().unwrap().is_some());
+
+        let half = TlsConfig {
+            cert_path: config.cert_path.clone(),
+            key_path: None,
+        };
+        assert!(half.enabled());
+        assert!(matches!(half.load(), Err(TlsError::Incomplete)));
+
+        let missing = TlsConfig {
+            cert_path: Some(dir.join("missing.pem")),
+            key_path: config.key_path.clone(),
+        };
+        let error = missing.load().err().unwrap();
+        assert!(matches!(error, TlsError::Unreadable { setting: "TLS_CERT_PATH", .. }));
+        assert!(error.to_string().contains("missing.pem"), "{}", error);
+
+        std::fs::write(dir.join("garbage.pem"), "not a certificate").unwrap();
+        let garbage = TlsConfig {
+            cert_path: Some(dir.join("garbage.pem")),
+            key_path: config.key_path.clone(),
+        };
+        assert!(matches!(garbage.load(), Err(TlsError::InvalidCertificate { .. })));
+        let garbage_key = TlsConfig {
+            cert_path: config.cert_path.clone(),
+            key_path: Some(dir.join("garbage.pem")),
+        };
+        assert!(matches!(garbage_key.load(), Err(TlsError::InvalidKey { .. })));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_rejects_a_key_from_another_certificate() {
+        let first = self_signed("mismatch-a");
+        let second = self_signed("mismatch-b");
+        let crossed = TlsConfig {
+            cert_path: first.config.cert_path.clone(),
+            key_path: second.config.key_path.clone(),
+        };
+
+        let error = crossed.load().err().unwrap();
+        assert!(matches!(error, TlsError::KeyMismatch { .. }));
+        assert!(error.to_string().contains("does not match"), "{}", error);
+        std::fs::remove_dir_all(&first.dir).ok();
+        std::fs::remove_dir_all(&second.dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_handshake_over_tls() {
+        let TestCert { dir, cert_pem, config } = self_signed("handshake");
+        let identity = config.load().unwrap().unwrap();
+
+        let server = Arc::new(SfuServer::new());
+        let routes = crate::api::sfu_routes::sfu_websocket_route(server.clone());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let (addr, serving) = bind(routes, ([127, 0, 0, 1], 0), Some(&identity), async {
+            stopped.await.ok();
+        })
+        .unwrap();
+        let serving = tokio::spawn(serving);
+
+        // Trusting only the generated certificate, as a browser would a real one
+        let connector = native_tls::TlsConnector::builder()
+            .add_root_certificate(native_tls::Certificate::from_pem(cert_pem.as_bytes()).unwrap())
+            .build()
+            .unwrap();
+        let url = format!("wss://localhost:{}/sfu", addr.port());
+        let (mut ws, response) =
+            tokio_tungstenite::connect_async_tls_with_config(&url, None, false, Some(Connector::NativeTls(connector)))
+                .await
+                .unwrap();
+        assert_eq!(response.status(), 101);
+
+        // The signaling handler is on the other end
+        ws.send(Message::Text("not json".to_string())).await.unwrap();
+        let reply = loop {
+            let frame = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap().unwrap().unwrap();
+            if let Message::Text(text) = frame {
+                break serde_json::from_str::<serde_json::Value>(&text).unwrap();
+            }
+        };
+        assert_eq!(reply["code"], "invalid_message");
+        ws.close(None).await.ok();
+
+        // Plain WebSocket is not served next to it
+        assert!(tokio_tungstenite::connect_async(format!("ws://127.0.0.1:{}/sfu", addr.port())).await.is_err());
+
+        stop.send(()).ok();
+        serving.await.unwrap();
+        assert!(server.shutdown().await.is_clean());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
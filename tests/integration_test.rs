@@ -265,3 +265,50 @@ async fn test_join_invalid_room() {
         }
     }
 }
+
+/// Test that loss on a publisher's uplink shows up in the receive stats
+/// Runs the CLI publisher with every 10th packet dropped and checks /sfu/stats
+#[tokio::test]
+#[ignore] // Requires running server
+async fn test_publisher_loss_reported_in_stats() {
+    let peer_id = format!("loss_test_proctor_{}", std::process::id());
+    let mut publisher = std::process::Command::new(env!("CARGO_BIN_EXE_sfu-cli"))
+        .args(["publish", "--peer-id", &peer_id, "--duration-secs", "10", "--drop-every", "10"])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to start sfu-cli publisher");
+
+    // Leave time for ICE/DTLS and a few report intervals
+    sleep(Duration::from_secs(7)).await;
+
+    let body: serde_json::Value = reqwest::get("http://127.0.0.1:8080/sfu/stats")
+        .await
+        .expect("Cannot reach stats endpoint")
+        .json()
+        .await
+        .unwrap();
+    let _ = publisher.kill();
+    let _ = publisher.wait();
+
+    assert!(body["report_interval_ms"].as_u64().unwrap() > 0);
+
+    let publisher_stats = body["publishers"]
+        .as_array()
+        .and_then(|publishers| publishers.iter().find(|p| p["peer_id"] == peer_id.as_str()))
+        .expect("Publisher should appear in stats");
+    let video = publisher_stats["tracks"]
+        .as_array()
+        .and_then(|tracks| tracks.iter().find(|t| t["kind"] == "video"))
+        .expect("Publisher should have a video track");
+
+    let fraction_lost = video["fraction_lost"].as_f64().unwrap();
+    assert!(
+        (0.05..=0.15).contains(&fraction_lost),
+        "Expected ~10% loss, stats report {}",
+        fraction_lost
+    );
+    assert!(video["packets_lost"].as_i64().unwrap() > 0);
+    if body["remb_enabled"] == true {
+        assert!(video["remb_bitrate_bps"].as_u64().is_some());
+    }
+}
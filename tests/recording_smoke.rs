@@ -0,0 +1,23 @@
+// Recording smoke test
+// Runs the recording-smoke binary against the GStreamer installed on this host.
+// Enable with `cargo test --features recording-smoke --test recording_smoke`.
+
+#![cfg(feature = "recording-smoke")]
+
+use std::process::Command;
+
+/// Records every profile through the real pipeline and probes the files
+#[test]
+fn test_recording_smoke() {
+    let output = Command::new(env!("CARGO_BIN_EXE_recording-smoke"))
+        .args(["--secs", "3"])
+        .output()
+        .expect("recording-smoke should run");
+
+    assert!(
+        output.status.success(),
+        "recording-smoke failed:\n{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}